@@ -0,0 +1,11 @@
+//! Generates the gRPC server from `proto/` when built with `grpc`.
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "grpc")]
+    {
+        tonic_build::configure()
+            .build_client(false)
+            .compile(&["proto/cotai/security/v1/security.proto"], &["proto"])?;
+    }
+    Ok(())
+}
@@ -0,0 +1,270 @@
+/*!
+Generate Module
+The conformance cases, built with fixed keys and clocks and answered by
+the reference implementation
+
+Every case states whether it should pass and, if not, the kind of error;
+generation panics when the reference disagrees, so a behavior change
+cannot slip into the suite unnoticed.
+*/
+
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+use ring::{digest, hmac};
+use serde_json::{json, Value};
+
+use crate::{reference, Case, Suite, SUITE_VERSION};
+
+/// The clock every case is judged at: 2026-01-01T00:00:00Z.
+pub const NOW: i64 = 1_767_225_600;
+
+const DATA_KEY: [u8; 32] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30,
+    31,
+];
+const KEY_ID: &str = "dk-conformance-1";
+const TOKEN_SECRET: &str = "conformance-token-secret";
+const OTHER_SECRET: &str = "some-other-secret";
+const SIGNING_KEY: &[u8] = b"conformance-signing-key";
+const WEBHOOK_SECRETS: [&str; 2] = ["whsec_conformance_1", "whsec_conformance_2"];
+
+fn context_hash(context: &str) -> String {
+    hex::encode(digest::digest(&digest::SHA256, context.as_bytes()).as_ref())
+}
+
+/// An envelope with a fixed nonce, which `Envelope::seal` rightly never
+/// allows outside fixtures.
+fn seal(nonce: &[u8; 12], plaintext: &[u8], context_hash: Option<&str>) -> Value {
+    let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &DATA_KEY).expect("32-byte key"));
+    let mut sealed = plaintext.to_vec();
+    let aad = context_hash.unwrap_or_default().as_bytes();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(*nonce), Aad::from(aad), &mut sealed)
+        .expect("sealing");
+    json!({
+        "encrypted_data": base64::encode(&sealed),
+        "key_id": KEY_ID,
+        "nonce": base64::encode(nonce),
+        "context_hash": context_hash
+    })
+}
+
+fn mint(claims: Value, secret: &str, algorithm: Algorithm) -> String {
+    encode(&Header::new(algorithm), &claims, &EncodingKey::from_secret(secret.as_bytes())).expect("signing")
+}
+
+fn hmac_hex(key: &[u8], message: &[u8]) -> String {
+    hex::encode(hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), message).as_ref())
+}
+
+fn at(offset_secs: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(NOW + offset_secs, 0).expect("in range")
+}
+
+fn webhook_header(secrets: &[&str], timestamp: i64, body: &str) -> String {
+    let mut header = format!("t={}", timestamp);
+    for secret in secrets {
+        header.push_str(",v1=");
+        header.push_str(&hmac_hex(secret.as_bytes(), format!("{}.{}", timestamp, body).as_bytes()));
+    }
+    header
+}
+
+/// A case answered by the reference, which must agree with `intended`:
+/// `Ok` to pass, or `Err` with the error kind.
+fn case(id: &str, kind: &str, description: &str, input: Value, intended: Result<(), &str>) -> Case {
+    let expect = reference::evaluate(kind, &input);
+    match intended {
+        Ok(()) => assert!(expect.ok, "{}: reference failed with {:?}", id, expect.error),
+        Err(error) => assert_eq!(expect.error.as_deref(), Some(error), "{}: reference disagrees", id),
+    }
+    Case {
+        id: id.to_string(),
+        kind: kind.to_string(),
+        description: description.to_string(),
+        input,
+        expect,
+    }
+}
+
+fn envelope_cases() -> Vec<Case> {
+    let data_key = base64::encode(DATA_KEY);
+    let tenant = context_hash("tenant:acme");
+    let plain = seal(b"cotai-nonce1", b"proposta comercial", None);
+    let bound = seal(b"cotai-nonce2", b"{\"bid\":1250000}", Some(&tenant));
+
+    let mut tampered = plain.clone();
+    let mut ciphertext = base64::decode(plain["encrypted_data"].as_str().unwrap_or_default()).unwrap_or_default();
+    ciphertext[0] ^= 1;
+    tampered["encrypted_data"] = json!(base64::encode(&ciphertext));
+    let mut rebound = bound.clone();
+    rebound["context_hash"] = json!(context_hash("tenant:other"));
+    let mut short_nonce = plain.clone();
+    short_nonce["nonce"] = json!(base64::encode(b"short"));
+    let mut bad_hash = bound.clone();
+    bad_hash["context_hash"] = json!("not-a-digest");
+    let mut no_key_id = plain.clone();
+    no_key_id["key_id"] = json!("");
+
+    vec![
+        case("envelope/open/plain", "envelope.open", "Envelope without a context opens to its plaintext",
+            json!({ "envelope": plain, "data_key": data_key }), Ok(())),
+        case("envelope/open/bound", "envelope.open",
+            "The context hash, as its hex text, is the additional authenticated data",
+            json!({ "envelope": bound, "data_key": data_key }), Ok(())),
+        case("envelope/open/tampered", "envelope.open", "A flipped ciphertext bit fails authentication",
+            json!({ "envelope": tampered, "data_key": data_key }), Err("crypto")),
+        case("envelope/open/other-context", "envelope.open", "A different context hash fails authentication",
+            json!({ "envelope": rebound, "data_key": data_key }), Err("crypto")),
+        case("envelope/open/short-key", "envelope.open", "Data keys are exactly 32 bytes",
+            json!({ "envelope": plain, "data_key": base64::encode(&DATA_KEY[..16]) }), Err("crypto")),
+        case("envelope/decode/plain", "envelope.decode", "Structure checks need no key",
+            json!({ "envelope": plain }), Ok(())),
+        case("envelope/decode/short-nonce", "envelope.decode", "Nonces are exactly 12 bytes",
+            json!({ "envelope": short_nonce }), Err("malformed_envelope")),
+        case("envelope/decode/bad-context-hash", "envelope.decode", "Context hashes are SHA-256 hex digests",
+            json!({ "envelope": bad_hash }), Err("malformed_envelope")),
+        case("envelope/decode/empty-key-id", "envelope.decode", "Key ids cannot be empty",
+            json!({ "envelope": no_key_id }), Err("malformed_envelope")),
+    ]
+}
+
+fn token_cases() -> Vec<Case> {
+    let claims = |extra: Value| {
+        let mut claims = json!({
+            "sub": "user-42",
+            "exp": NOW + 3600,
+            "type": "access",
+            "tenant_id": "acme",
+            "roles": ["buyer"],
+            "scope": "tenders:read tenders:write",
+            "jti": "tok-1"
+        });
+        if let (Some(claims), Value::Object(extra)) = (claims.as_object_mut(), extra) {
+            claims.extend(extra);
+        }
+        claims
+    };
+    let input = |token: String, algorithm: &str| {
+        json!({ "token": token, "secret": TOKEN_SECRET, "algorithm": algorithm, "now": NOW })
+    };
+
+    vec![
+        case("token/valid", "token.verify", "Roles, space-separated scopes, tenant and token id",
+            input(mint(claims(json!({})), TOKEN_SECRET, Algorithm::HS256), "HS256"), Ok(())),
+        case("token/role-merged", "token.verify", "A single `role` joins `roles` once",
+            input(mint(claims(json!({ "role": "admin", "roles": ["buyer", "admin"] })), TOKEN_SECRET, Algorithm::HS256), "HS256"),
+            Ok(())),
+        case("token/delegated", "token.verify", "`act` chains become the delegation, current actor first",
+            input(mint(claims(json!({ "act": { "sub": "svc-gateway", "act": { "sub": "svc-portal" } } })), TOKEN_SECRET, Algorithm::HS256), "HS256"),
+            Ok(())),
+        case("token/within-leeway", "token.verify", "Expiry allows 60 seconds of clock skew",
+            input(mint(claims(json!({ "exp": NOW - 30 })), TOKEN_SECRET, Algorithm::HS256), "HS256"), Ok(())),
+        case("token/expired", "token.verify", "Expired past the leeway",
+            input(mint(claims(json!({ "exp": NOW - 3600 })), TOKEN_SECRET, Algorithm::HS256), "HS256"), Err("invalid_token")),
+        case("token/wrong-secret", "token.verify", "Signed with another secret",
+            input(mint(claims(json!({})), OTHER_SECRET, Algorithm::HS256), "HS256"), Err("invalid_token")),
+        case("token/wrong-algorithm", "token.verify", "Only the configured algorithm is accepted",
+            input(mint(claims(json!({})), TOKEN_SECRET, Algorithm::HS384), "HS256"), Err("invalid_token")),
+        case("token/refresh", "token.verify", "Refresh tokens do not identify a caller",
+            input(mint(claims(json!({ "type": "refresh" })), TOKEN_SECRET, Algorithm::HS256), "HS256"), Err("invalid_token")),
+    ]
+}
+
+fn signature_cases() -> Vec<Case> {
+    let key = base64::encode(SIGNING_KEY);
+    let data = "licitacao:2026-001:abertura";
+    let signed = at(-1800);
+    let signature = hmac_hex(SIGNING_KEY, format!("{}{}", data, signed.to_rfc3339()).as_bytes());
+    let input = |data: &str, signature: &str, timestamp: DateTime<Utc>| {
+        json!({
+            "key": key,
+            "data": data,
+            "signature": signature,
+            "timestamp": timestamp.to_rfc3339(),
+            "max_age_secs": 3600,
+            "now": NOW
+        })
+    };
+    let old = at(-2 * 3600);
+    let old_signature = hmac_hex(SIGNING_KEY, format!("{}{}", data, old.to_rfc3339()).as_bytes());
+
+    vec![
+        case("signature/valid", "signature.verify",
+            "HMAC-SHA256 of the data followed by the timestamp as written, `+00:00` and all",
+            input(data, &signature, signed), Ok(())),
+        case("signature/too-old", "signature.verify", "Older than max_age_secs",
+            input(data, &old_signature, old), Err("invalid_signature")),
+        case("signature/other-data", "signature.verify", "Signature over different data",
+            input("licitacao:2026-002:abertura", &signature, signed), Err("invalid_signature")),
+        case("signature/other-time", "signature.verify", "The timestamp is part of what is signed",
+            input(data, &signature, signed + Duration::seconds(1)), Err("invalid_signature")),
+        case("signature/not-hex", "signature.verify", "Signatures are hex",
+            input(data, "zz", signed), Err("invalid_signature")),
+    ]
+}
+
+fn webhook_cases() -> Vec<Case> {
+    let body = "{\"event\":\"incident.opened\",\"id\":\"7d2b\"}";
+    let sent = NOW - 20;
+    let input = |headers: Value, body: &str, secrets: &[&str]| {
+        json!({
+            "secrets": secrets,
+            "headers": headers,
+            "body": body,
+            "tolerance_secs": 300,
+            "now": NOW
+        })
+    };
+    let signed = webhook_header(&WEBHOOK_SECRETS[..1], sent, body);
+    let rotating = webhook_header(&WEBHOOK_SECRETS, sent, body);
+    let stale = webhook_header(&WEBHOOK_SECRETS[..1], NOW - 600, body);
+
+    vec![
+        case("webhook/valid", "webhook.verify", "Signature over `{t}.{body}`, with the delivery id",
+            input(json!([["x-cotai-signature", signed], ["x-cotai-delivery", "d-1"]]), body, &WEBHOOK_SECRETS[..1]),
+            Ok(())),
+        case("webhook/header-case", "webhook.verify", "Header names are case-insensitive",
+            input(json!([["X-Cotai-Signature", signed]]), body, &WEBHOOK_SECRETS[..1]), Ok(())),
+        case("webhook/rotation", "webhook.verify", "Any `v1` matching any secret will do",
+            input(json!([["x-cotai-signature", rotating]]), body, &WEBHOOK_SECRETS[1..]), Ok(())),
+        case("webhook/replay", "webhook.verify", "Replays are marked",
+            input(json!([["x-cotai-signature", signed], ["x-cotai-delivery", "d-1"], ["x-cotai-replay", "1"]]), body, &WEBHOOK_SECRETS[..1]),
+            Ok(())),
+        case("webhook/stale", "webhook.verify", "Timestamped outside the tolerance",
+            input(json!([["x-cotai-signature", stale]]), body, &WEBHOOK_SECRETS[..1]), Err("invalid_signature")),
+        case("webhook/reserialized", "webhook.verify", "The body must be verified exactly as received",
+            input(json!([["x-cotai-signature", signed]]), "{\"event\": \"incident.opened\", \"id\": \"7d2b\"}", &WEBHOOK_SECRETS[..1]),
+            Err("invalid_signature")),
+        case("webhook/unsigned", "webhook.verify", "No signature header",
+            input(json!([["x-cotai-delivery", "d-1"]]), body, &WEBHOOK_SECRETS[..1]), Err("invalid_signature")),
+    ]
+}
+
+pub fn suite() -> Suite {
+    Suite {
+        version: SUITE_VERSION,
+        description: "COTAI edge verification: envelopes, access tokens, signatures and webhook deliveries".to_string(),
+        cases: [envelope_cases(), token_cases(), signature_cases(), webhook_cases()].concat(),
+    }
+}
+
+/// The suite as `generate` prints it, and as `suite.json` holds it.
+pub fn suite_json() -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(&suite()).map(|json| json + "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BUNDLED_SUITE;
+
+    #[test]
+    fn bundled_suite_is_generated_output() {
+        let generated = suite_json().expect("serializable suite");
+        assert!(
+            generated == BUNDLED_SUITE,
+            "suite.json is stale; regenerate it with `cotai-conformance generate > suite.json`"
+        );
+    }
+}
@@ -0,0 +1,118 @@
+/*!
+COTAI Conformance
+Fixtures and a runner checking other implementations against `cotai-verify`
+
+`suite.json` holds cases for the four things an edge verifier does:
+opening envelopes, verifying access tokens, checking `/crypto/sign`
+signatures and checking webhook deliveries. Each case is
+
+```json
+{"id": "token/expired", "kind": "token.verify", "description": "...",
+ "input": {...}, "expect": {"ok": false, "error": "invalid_token"}}
+```
+
+Inputs carry their own `now`, so cases never go stale. Binary values are
+standard base64 and text is used as its UTF-8 bytes. `generate` rebuilds
+the suite from the reference implementation, and `reference::evaluate`
+answers cases with it.
+
+An implementation is checked through an adapter: a command that reads one
+`{"id", "kind", "input"}` object on stdin and writes one outcome on stdout,
+`{"ok": true, "output": {...}}` or `{"ok": false, "error": "<kind>"}`.
+An outcome matches when `ok` agrees, every member of the expected `output`
+is equal, and the error kind is the same (see `VerifyError::kind`). The
+runner binary starts the adapter once per case; see `main.rs`.
+*/
+
+pub mod generate;
+pub mod reference;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const SUITE_VERSION: u32 = 1;
+
+/// The suite shipped with this crate.
+pub const BUNDLED_SUITE: &str = include_str!("../suite.json");
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Suite {
+    pub version: u32,
+    pub description: String,
+    pub cases: Vec<Case>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Case {
+    pub id: String,
+    /// `envelope.open`, `envelope.decode`, `token.verify`,
+    /// `signature.verify` or `webhook.verify`.
+    pub kind: String,
+    pub description: String,
+    pub input: Value,
+    pub expect: Outcome,
+}
+
+/// What an implementation answered, or should have.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Outcome {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Outcome {
+    pub fn success(output: Option<Value>) -> Self {
+        Self { ok: true, output, error: None }
+    }
+
+    pub fn failure(error: &str) -> Self {
+        Self { ok: false, output: None, error: Some(error.to_string()) }
+    }
+
+    /// Why `actual` does not meet this expectation, if it does not.
+    /// `error_kinds: false` accepts any error for an expected failure.
+    pub fn mismatch(&self, actual: &Outcome, error_kinds: bool) -> Option<String> {
+        if self.ok != actual.ok {
+            return Some(match (&actual.error, self.ok) {
+                (Some(error), true) => format!("expected success, got error '{}'", error),
+                _ => "expected failure, got success".to_string(),
+            });
+        }
+        if !self.ok {
+            return match (&self.error, &actual.error) {
+                (Some(expected), Some(got)) if error_kinds && expected != got => {
+                    Some(format!("expected error '{}', got '{}'", expected, got))
+                }
+                (Some(expected), None) if error_kinds => Some(format!("expected error '{}', got none", expected)),
+                _ => None,
+            };
+        }
+        let Some(Value::Object(expected)) = &self.output else {
+            return None;
+        };
+        let got = actual.output.as_ref().and_then(Value::as_object);
+        expected.iter().find_map(|(name, value)| {
+            let found = got.and_then(|got| got.get(name));
+            (found != Some(value)).then(|| {
+                format!(
+                    "output.{}: expected {}, got {}",
+                    name,
+                    value,
+                    found.map_or("nothing".to_string(), Value::to_string)
+                )
+            })
+        })
+    }
+}
+
+/// Read a suite, refusing versions this runner does not know.
+pub fn parse_suite(json: &str) -> Result<Suite, String> {
+    let suite: Suite = serde_json::from_str(json).map_err(|e| format!("invalid suite: {}", e))?;
+    if suite.version != SUITE_VERSION {
+        return Err(format!("suite version {} is not supported (expected {})", suite.version, SUITE_VERSION));
+    }
+    Ok(suite)
+}
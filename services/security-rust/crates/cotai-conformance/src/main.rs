@@ -0,0 +1,161 @@
+/*!
+Conformance runner
+
+```text
+cotai-conformance run [--suite FILE] [--only PREFIX] [--any-error] -- COMMAND [ARGS...]
+cotai-conformance generate
+cotai-conformance adapter
+```
+
+`run` starts `COMMAND` once per case with the case on stdin (see the crate
+docs), prints a line per case and exits non-zero if any failed. Without
+`--suite` it uses the bundled suite; `--only` keeps cases whose id starts
+with a prefix, and `--any-error` accepts any error kind where a failure is
+expected, for implementations that do not tell them apart.
+
+`generate` prints the suite as the reference implementation answers it;
+`suite.json` is its output. `adapter` is the reference implementation as
+an adapter, so `cotai-conformance run -- cotai-conformance adapter` checks
+the suite against this crate.
+*/
+
+use std::io::{Read, Write};
+use std::process::{exit, Command, Stdio};
+
+use cotai_conformance::{generate, parse_suite, reference, Case, Outcome, BUNDLED_SUITE};
+use serde_json::Value;
+
+fn usage() -> ! {
+    eprintln!("usage: cotai-conformance run [--suite FILE] [--only PREFIX] [--any-error] -- COMMAND [ARGS...]");
+    eprintln!("       cotai-conformance generate");
+    eprintln!("       cotai-conformance adapter");
+    exit(2);
+}
+
+/// Run the adapter on one case, returning its outcome.
+fn attempt(command: &[String], case: &Case) -> Result<Outcome, String> {
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|e| format!("cannot start {}: {}", command[0], e))?;
+    let request = serde_json::json!({ "id": case.id, "kind": case.kind, "input": case.input });
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(request.to_string().as_bytes()).map_err(|e| format!("cannot write the case: {}", e))?;
+    }
+    let output = child.wait_with_output().map_err(|e| format!("adapter failed: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("adapter exited with {}", output.status));
+    }
+    serde_json::from_slice(&output.stdout).map_err(|e| format!("adapter answered something other than an outcome: {}", e))
+}
+
+fn run(args: &[String]) -> i32 {
+    let mut suite_path = None;
+    let mut only = None;
+    let mut error_kinds = true;
+    let mut rest = args.iter();
+    let mut command = Vec::new();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--suite" => suite_path = Some(rest.next().cloned().unwrap_or_else(|| usage())),
+            "--only" => only = Some(rest.next().cloned().unwrap_or_else(|| usage())),
+            "--any-error" => error_kinds = false,
+            "--" => {
+                command = rest.by_ref().cloned().collect();
+                break;
+            }
+            _ => usage(),
+        }
+    }
+    if command.is_empty() {
+        usage();
+    }
+
+    let json = match &suite_path {
+        Some(path) => match std::fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) => {
+                eprintln!("cannot read {}: {}", path, e);
+                return 2;
+            }
+        },
+        None => BUNDLED_SUITE.to_string(),
+    };
+    let suite = match parse_suite(&json) {
+        Ok(suite) => suite,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+
+    let (mut passed, mut failed) = (0, 0);
+    let selected = suite.cases.iter().filter(|case| match &only {
+        Some(prefix) => case.id.starts_with(prefix.as_str()),
+        None => true,
+    });
+    for case in selected {
+        let problem = match attempt(&command, case) {
+            Ok(actual) => case.expect.mismatch(&actual, error_kinds),
+            Err(e) => Some(e),
+        };
+        match problem {
+            None => {
+                passed += 1;
+                println!("ok    {}", case.id);
+            }
+            Some(problem) => {
+                failed += 1;
+                println!("FAIL  {}: {}", case.id, problem);
+            }
+        }
+    }
+    println!("{} passed, {} failed", passed, failed);
+    if failed > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+fn adapter() -> i32 {
+    let mut input = String::new();
+    if let Err(e) = std::io::stdin().read_to_string(&mut input) {
+        eprintln!("cannot read the case: {}", e);
+        return 1;
+    }
+    let request: Value = match serde_json::from_str(&input) {
+        Ok(request) => request,
+        Err(e) => {
+            eprintln!("invalid case: {}", e);
+            return 1;
+        }
+    };
+    let kind = request.get("kind").and_then(Value::as_str).unwrap_or_default();
+    let outcome = reference::evaluate(kind, request.get("input").unwrap_or(&Value::Null));
+    println!("{}", serde_json::to_string(&outcome).unwrap_or_default());
+    0
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let code = match args.first().map(String::as_str) {
+        Some("run") => run(&args[1..]),
+        Some("generate") => match generate::suite_json() {
+            Ok(json) => {
+                print!("{}", json);
+                0
+            }
+            Err(e) => {
+                eprintln!("cannot write the suite: {}", e);
+                1
+            }
+        },
+        Some("adapter") => adapter(),
+        _ => usage(),
+    };
+    exit(code);
+}
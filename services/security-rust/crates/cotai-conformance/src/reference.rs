@@ -0,0 +1,110 @@
+/*!
+Reference Module
+Answers conformance cases with `cotai-verify`
+*/
+
+use chrono::{DateTime, Duration, Utc};
+use cotai_verify::envelope::Envelope;
+use cotai_verify::{signature, webhook, TokenVerifier, VerifyError};
+use serde_json::Value;
+
+use crate::Outcome;
+
+fn field<'a>(input: &'a Value, name: &str) -> Result<&'a Value, VerifyError> {
+    input.get(name).ok_or_else(|| VerifyError::Config(format!("input has no '{}'", name)))
+}
+
+fn text<'a>(input: &'a Value, name: &str) -> Result<&'a str, VerifyError> {
+    field(input, name)?.as_str().ok_or_else(|| VerifyError::Config(format!("'{}' must be a string", name)))
+}
+
+fn number(input: &Value, name: &str) -> Result<i64, VerifyError> {
+    field(input, name)?.as_i64().ok_or_else(|| VerifyError::Config(format!("'{}' must be an integer", name)))
+}
+
+fn bytes(input: &Value, name: &str) -> Result<Vec<u8>, VerifyError> {
+    base64::decode(text(input, name)?).map_err(|_| VerifyError::Config(format!("'{}' must be base64", name)))
+}
+
+fn envelope(input: &Value) -> Result<Envelope, VerifyError> {
+    serde_json::from_value(field(input, "envelope")?.clone())
+        .map_err(|e| VerifyError::MalformedEnvelope(e.to_string()))
+}
+
+fn timestamp(input: &Value, name: &str) -> Result<DateTime<Utc>, VerifyError> {
+    DateTime::parse_from_rfc3339(text(input, name)?)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|_| VerifyError::Config(format!("'{}' must be RFC 3339", name)))
+}
+
+fn answer(kind: &str, input: &Value) -> Result<Option<Value>, VerifyError> {
+    match kind {
+        "envelope.open" => {
+            let plaintext = envelope(input)?.open(&bytes(input, "data_key")?)?;
+            Ok(Some(serde_json::json!({ "plaintext": base64::encode(plaintext) })))
+        }
+        "envelope.decode" => {
+            let decoded = envelope(input)?.decode()?;
+            Ok(Some(serde_json::json!({
+                "key_id": decoded.key_id,
+                "aad": String::from_utf8_lossy(&decoded.aad)
+            })))
+        }
+        "token.verify" => {
+            let verifier = TokenVerifier::with_secret(text(input, "secret")?.as_bytes(), text(input, "algorithm")?)?;
+            let principal = verifier.verify_at(text(input, "token")?, number(input, "now")?)?;
+            Ok(Some(serde_json::to_value(principal).map_err(|e| VerifyError::Config(e.to_string()))?))
+        }
+        "signature.verify" => {
+            let valid = signature::verify_at(
+                &bytes(input, "key")?,
+                text(input, "data")?,
+                text(input, "signature")?,
+                timestamp(input, "timestamp")?,
+                Duration::seconds(number(input, "max_age_secs")?),
+                DateTime::from_timestamp(number(input, "now")?, 0)
+                    .ok_or_else(|| VerifyError::Config("'now' is out of range".to_string()))?,
+            );
+            if !valid {
+                return Err(VerifyError::InvalidSignature("signature does not verify".to_string()));
+            }
+            Ok(None)
+        }
+        "webhook.verify" => {
+            let secrets: Vec<&str> = field(input, "secrets")?
+                .as_array()
+                .map(|secrets| secrets.iter().filter_map(Value::as_str).collect())
+                .unwrap_or_default();
+            let headers: Vec<(&str, &str)> = field(input, "headers")?
+                .as_array()
+                .map(|headers| {
+                    headers
+                        .iter()
+                        .filter_map(|pair| Some((pair.get(0)?.as_str()?, pair.get(1)?.as_str()?)))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let delivery = webhook::verify_delivery_at(
+                &secrets,
+                headers,
+                text(input, "body")?.as_bytes(),
+                number(input, "tolerance_secs")?,
+                number(input, "now")?,
+            )?;
+            Ok(Some(serde_json::json!({
+                "timestamp": delivery.timestamp,
+                "id": delivery.id,
+                "replay": delivery.replay
+            })))
+        }
+        other => Err(VerifyError::Config(format!("unknown kind '{}'", other))),
+    }
+}
+
+/// The reference implementation's outcome for a case.
+pub fn evaluate(kind: &str, input: &Value) -> Outcome {
+    match answer(kind, input) {
+        Ok(output) => Outcome::success(output),
+        Err(e) => Outcome::failure(e.kind()),
+    }
+}
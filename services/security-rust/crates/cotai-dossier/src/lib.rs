@@ -0,0 +1,560 @@
+/*!
+COTAI Dossier
+Offline verification of sealed tender dossiers
+
+A control body receives a dossier as one JSON archive (see the service's
+`dossiers` module for the format) and checks it with its RSA private key
+and the JWK Set COTAI publishes at `/.well-known/jwks.json`:
+
+- the archive signature, before anything is decrypted
+- that the archive is sealed to this key, and decrypts under it
+- the index hash and signature, and every section against the index
+- the records inside: audit event chain links, checkpoint signatures,
+  submission receipt chains and signatures, quorum officer signatures and
+  attestations
+
+Signatures by keys the key set no longer lists are skipped, not failed, as
+long-lived records outlast key rotation.
+*/
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+use ring::digest::{digest, SHA256};
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED, ED25519};
+use rsa::pkcs8::EncodePublicKey;
+use rsa::{Oaep, RsaPrivateKey};
+use serde::Deserialize;
+use serde_json::Value;
+
+pub const FORMAT: &str = "cotai-dossier/1";
+pub const SEALED_FORMAT: &str = "cotai-dossier-sealed/1";
+
+/// What the first receipt of a call names as its predecessor.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+// Domains the service signs each record for (see its `signing` module);
+// the signed message is `cotai-signature:<domain>\0` and the record.
+const AUDIT_CHECKPOINT_DOMAIN: &str = "audit-checkpoint";
+const SUBMISSION_RECEIPT_DOMAIN: &str = "submission-receipt";
+const QUORUM_ATTESTATION_DOMAIN: &str = "quorum-attestation";
+const DOSSIER_INDEX_DOMAIN: &str = "dossier-index";
+const DOSSIER_ARCHIVE_DOMAIN: &str = "dossier-archive";
+
+fn sha256(data: &[u8]) -> String {
+    hex::encode(digest(&SHA256, data))
+}
+
+fn micros(value: &DateTime<Utc>) -> String {
+    value.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Failed(String),
+    /// Could not be checked, e.g. the key is no longer published.
+    Skipped(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+    pub warnings: Vec<String>,
+    /// Each section's JSON, once decrypted.
+    pub sections: BTreeMap<String, String>,
+}
+
+impl Report {
+    fn record(&mut self, name: impl Into<String>, status: Status) {
+        self.checks.push(Check { name: name.into(), status });
+    }
+
+    fn expect(&mut self, name: impl Into<String>, ok: bool, problem: &str) {
+        let status = if ok { Status::Ok } else { Status::Failed(problem.to_string()) };
+        self.record(name, status);
+    }
+
+    pub fn failed(&self) -> bool {
+        self.checks.iter().any(|check| matches!(check.status, Status::Failed(_)))
+    }
+}
+
+/// Public signing keys by key id.
+pub struct Keys(HashMap<String, (String, Vec<u8>)>);
+
+impl Keys {
+    /// Ed25519 and P-256 keys of a JWK Set; other keys are ignored.
+    pub fn from_jwks(jwks: &Value) -> Result<Self, String> {
+        let decode = |key: &Value, name: &str| {
+            key.get(name)
+                .and_then(Value::as_str)
+                .and_then(|value| base64::decode_config(value, base64::URL_SAFE_NO_PAD).ok())
+        };
+        let entries = jwks.get("keys").and_then(Value::as_array).ok_or("not a JWK Set")?;
+        let mut keys = HashMap::new();
+        for key in entries {
+            let Some(kid) = key.get("kid").and_then(Value::as_str) else {
+                continue;
+            };
+            let public_key = match key.get("crv").and_then(Value::as_str) {
+                Some("Ed25519") => decode(key, "x").map(|x| ("EdDSA", x)),
+                Some("P-256") => decode(key, "x").zip(decode(key, "y")).map(|(x, y)| {
+                    // Uncompressed point: 0x04 || X || Y
+                    let mut point = vec![4];
+                    point.extend(x);
+                    point.extend(y);
+                    ("ES256", point)
+                }),
+                _ => None,
+            };
+            if let Some((algorithm, public_key)) = public_key {
+                keys.insert(kid.to_string(), (algorithm.to_string(), public_key));
+            }
+        }
+        Ok(Self(keys))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Check a hex signature by the service's `key_id` key over `message`
+    /// signed for `domain`.
+    pub fn verify(&self, algorithm: &str, key_id: &str, domain: &str, message: &str, signature: &str) -> Status {
+        let Some((key_algorithm, public_key)) = self.0.get(key_id) else {
+            return Status::Skipped(format!("key {} is not in the key set", key_id));
+        };
+        if key_algorithm != algorithm {
+            return Status::Failed(format!("key {} is not an {} key", key_id, algorithm));
+        }
+        let Ok(signature) = hex::decode(signature) else {
+            return Status::Failed("the signature is not hex".to_string());
+        };
+        let message = [b"cotai-signature:", domain.as_bytes(), b"\0", message.as_bytes()].concat();
+        let verified = match algorithm {
+            "EdDSA" => UnparsedPublicKey::new(&ED25519, public_key).verify(&message, &signature),
+            _ => UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, public_key).verify(&message, &signature),
+        };
+        match verified {
+            Ok(()) => Status::Ok,
+            Err(_) => Status::Failed("the signature does not verify".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Sealed {
+    format: String,
+    dossier_id: String,
+    recipient: String,
+    recipient_key_sha256: String,
+    wrapped_key: String,
+    nonce: String,
+    ciphertext: String,
+    ciphertext_sha256: String,
+    algorithm: String,
+    key_id: String,
+    signature: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Contents {
+    format: String,
+    dossier_id: String,
+    index: String,
+    index_sha256: String,
+    algorithm: String,
+    key_id: String,
+    signature: String,
+    sections: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SectionEntry {
+    sha256: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Index {
+    dossier_id: String,
+    sections: BTreeMap<String, SectionEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Event {
+    id: String,
+    occurred_at: DateTime<Utc>,
+    tenant_id: Option<String>,
+    actor: String,
+    actor_ip: Option<String>,
+    action: String,
+    resource: String,
+    outcome: String,
+    payload: Value,
+    source: String,
+    chain_index: Option<i64>,
+    prev_hash: Option<String>,
+    chain_hash: Option<String>,
+}
+
+impl Event {
+    /// The service's chain link from `prev_hash` to this event.
+    fn link(&self, prev_hash: &str) -> String {
+        let record = serde_json::json!([
+            self.id,
+            micros(&self.occurred_at),
+            self.tenant_id,
+            self.actor,
+            self.actor_ip,
+            self.action,
+            self.resource,
+            self.outcome,
+            self.payload,
+            self.source
+        ]);
+        sha256(format!("{}\n{}", prev_hash, record).as_bytes())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Checkpoint {
+    chain_index: i64,
+    chain_hash: String,
+    algorithm: String,
+    key_id: String,
+    signature: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuditChain {
+    checkpoints: Vec<Checkpoint>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Receipt {
+    id: String,
+    call_id: String,
+    sequence: i64,
+    outcome: String,
+    package_sha256: String,
+    submitted_by: String,
+    received_at: DateTime<Utc>,
+    deadline: DateTime<Utc>,
+    previous_sha256: String,
+    receipt_sha256: String,
+    algorithm: String,
+    key_id: String,
+    signature: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubmissionRecord {
+    id: String,
+    receipts: Vec<Receipt>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OfficerSignature {
+    officer: String,
+    public_key: String,
+    signature: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct QuorumRecord {
+    id: String,
+    tenant_id: Option<String>,
+    reference: String,
+    document_sha256: String,
+    created_at: DateTime<Utc>,
+    attestation: Option<String>,
+    attestation_sha256: Option<String>,
+    algorithm: Option<String>,
+    key_id: Option<String>,
+    signature: Option<String>,
+    signatures: Vec<OfficerSignature>,
+}
+
+fn parse<T: for<'de> Deserialize<'de>>(report: &mut Report, name: &str) -> Option<T> {
+    let document = report.sections.get(name)?;
+    match serde_json::from_str(document) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            report.record(format!("{} section", name), Status::Failed(format!("unreadable: {}", e)));
+            None
+        }
+    }
+}
+
+/// Check a sealed archive. Without `jwks`, signatures are checked against
+/// the keys inside the dossier, which only shows it is self-consistent.
+pub fn verify(archive: &[u8], jwks: Option<&Value>, key: &RsaPrivateKey) -> Report {
+    let mut report = Report::default();
+    let sealed: Sealed = match serde_json::from_slice(archive) {
+        Ok(sealed) => sealed,
+        Err(e) => {
+            report.record("archive", Status::Failed(format!("not a sealed dossier: {}", e)));
+            return report;
+        }
+    };
+    report.expect("archive format", sealed.format == SEALED_FORMAT, &format!("unknown format {}", sealed.format));
+
+    let ciphertext = base64::decode(&sealed.ciphertext).unwrap_or_default();
+    report.expect("archive hash", sha256(&ciphertext) == sealed.ciphertext_sha256, "the ciphertext does not match its hash");
+    let own_key = key.to_public_key().to_public_key_der().map(|der| sha256(der.as_bytes())).unwrap_or_default();
+    if own_key != sealed.recipient_key_sha256 {
+        report.record(
+            "recipient key",
+            Status::Failed(format!("sealed to {} with another key ({})", sealed.recipient, sealed.recipient_key_sha256)),
+        );
+        return report;
+    }
+    report.record("recipient key", Status::Ok);
+
+    let given = match jwks.map(Keys::from_jwks).transpose() {
+        Ok(keys) => keys,
+        Err(e) => {
+            report.record("key set", Status::Failed(e));
+            return report;
+        }
+    };
+    let sealed_message = format!(
+        "cotai-dossier-sealed:{}:{}:{}",
+        sealed.dossier_id, sealed.recipient_key_sha256, sealed.ciphertext_sha256
+    );
+    if let Some(keys) = &given {
+        let status = keys.verify(&sealed.algorithm, &sealed.key_id, DOSSIER_ARCHIVE_DOMAIN, &sealed_message, &sealed.signature);
+        let signed = status == Status::Ok;
+        report.record("archive signature", status);
+        if !signed {
+            return report;
+        }
+    }
+
+    let plaintext = base64::decode(&sealed.wrapped_key)
+        .ok()
+        .and_then(|wrapped| key.decrypt(Oaep::new::<sha2::Sha256>(), &wrapped).ok())
+        .zip(base64::decode(&sealed.nonce).ok())
+        .and_then(|(data_key, nonce)| {
+            let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &data_key).ok()?);
+            let nonce = Nonce::try_assume_unique_for_key(&nonce).ok()?;
+            let aad = format!("cotai-dossier:{}:{}", sealed.dossier_id, sealed.recipient_key_sha256);
+            let mut buffer = ciphertext.clone();
+            let plaintext = key.open_in_place(nonce, Aad::from(aad.as_bytes()), &mut buffer).ok()?.to_vec();
+            Some(plaintext)
+        });
+    let Some(plaintext) = plaintext else {
+        report.record("decryption", Status::Failed("the archive does not decrypt under this key".to_string()));
+        return report;
+    };
+    report.record("decryption", Status::Ok);
+
+    let contents: Contents = match serde_json::from_slice(&plaintext) {
+        Ok(contents) => contents,
+        Err(e) => {
+            report.record("dossier", Status::Failed(format!("unreadable: {}", e)));
+            return report;
+        }
+    };
+    report.expect("dossier format", contents.format == FORMAT, &format!("unknown format {}", contents.format));
+    report.expect("dossier id", contents.dossier_id == sealed.dossier_id, "the dossier inside is another one");
+    report.sections = contents.sections;
+
+    let keys = match given {
+        Some(keys) => keys,
+        None => {
+            report.warnings.push(
+                "No --jwks given: signatures are checked against the keys inside the dossier, which shows it is \
+                 self-consistent but not that COTAI signed it"
+                    .to_string(),
+            );
+            let inside = report
+                .sections
+                .get("keys")
+                .and_then(|document| serde_json::from_str::<Value>(document).ok())
+                .ok_or_else(|| "the dossier holds no keys".to_string())
+                .and_then(|jwks| Keys::from_jwks(&jwks));
+            match inside {
+                Ok(keys) => {
+                    let status = keys.verify(&sealed.algorithm, &sealed.key_id, DOSSIER_ARCHIVE_DOMAIN, &sealed_message, &sealed.signature);
+                    report.record("archive signature", status);
+                    keys
+                }
+                Err(e) => {
+                    report.record("key set", Status::Failed(e));
+                    return report;
+                }
+            }
+        }
+    };
+
+    report.expect("index hash", sha256(contents.index.as_bytes()) == contents.index_sha256, "the index does not match its hash");
+    let status = keys.verify(
+        &contents.algorithm,
+        &contents.key_id,
+        DOSSIER_INDEX_DOMAIN,
+        &format!("cotai-dossier:{}:{}", contents.dossier_id, contents.index_sha256),
+        &contents.signature,
+    );
+    report.record("index signature", status);
+    let index: Index = match serde_json::from_str(&contents.index) {
+        Ok(index) => index,
+        Err(e) => {
+            report.record("index", Status::Failed(format!("unreadable: {}", e)));
+            return report;
+        }
+    };
+    report.expect("index dossier id", index.dossier_id == contents.dossier_id, "the index is another dossier's");
+    for (name, entry) in &index.sections {
+        let status = match report.sections.get(name) {
+            Some(document) if sha256(document.as_bytes()) == entry.sha256 => Status::Ok,
+            Some(_) => Status::Failed("does not match the index".to_string()),
+            None => Status::Failed("missing".to_string()),
+        };
+        report.record(format!("{} section", name), status);
+    }
+    let unlisted: Vec<String> = report.sections.keys().filter(|name| !index.sections.contains_key(*name)).cloned().collect();
+    for name in unlisted {
+        report.record(format!("{} section", name), Status::Failed("not in the index".to_string()));
+    }
+
+    check_events(&mut report, &keys);
+    check_submissions(&mut report, &keys);
+    check_quorum(&mut report, &keys);
+    report
+}
+
+fn check_events(report: &mut Report, keys: &Keys) {
+    let Some(mut events) = parse::<Vec<Event>>(report, "audit_events") else {
+        return;
+    };
+    events.retain(|event| event.chain_index.is_some());
+    events.sort_by_key(|event| event.chain_index);
+    let mut hashes = HashMap::new();
+    let mut previous: Option<(i64, String)> = None;
+    for event in &events {
+        let (Some(index), Some(prev_hash), Some(chain_hash)) = (event.chain_index, &event.prev_hash, &event.chain_hash) else {
+            continue;
+        };
+        let mut status = if event.link(prev_hash) == *chain_hash {
+            Status::Ok
+        } else {
+            Status::Failed("the event does not hash to its chain hash".to_string())
+        };
+        if let Some((previous_index, previous_hash)) = &previous {
+            if *previous_index == index - 1 && previous_hash != prev_hash {
+                status = Status::Failed(format!("does not link to event {}", previous_index));
+            }
+        }
+        report.record(format!("audit event {}", index), status);
+        hashes.insert(index, chain_hash.clone());
+        previous = Some((index, chain_hash.clone()));
+    }
+
+    let Some(chain) = parse::<AuditChain>(report, "audit_chain") else {
+        return;
+    };
+    for checkpoint in &chain.checkpoints {
+        let message = format!(
+            "cotai-audit-checkpoint:{}:{}:{}",
+            checkpoint.chain_index,
+            checkpoint.chain_hash,
+            micros(&checkpoint.created_at)
+        );
+        let mut status = keys.verify(&checkpoint.algorithm, &checkpoint.key_id, AUDIT_CHECKPOINT_DOMAIN, &message, &checkpoint.signature);
+        if hashes.get(&checkpoint.chain_index).is_some_and(|hash| *hash != checkpoint.chain_hash) {
+            status = Status::Failed("the event at this index has another chain hash".to_string());
+        }
+        report.record(format!("audit checkpoint {}", checkpoint.chain_index), status);
+    }
+}
+
+fn check_submissions(report: &mut Report, keys: &Keys) {
+    let Some(calls) = parse::<Vec<SubmissionRecord>>(report, "submissions") else {
+        return;
+    };
+    for call in calls {
+        let mut previous = GENESIS.to_string();
+        for (position, receipt) in call.receipts.iter().enumerate() {
+            let message = format!(
+                "cotai-submission:{}:{}:{}:{}:{}:{}:{}:{}",
+                receipt.call_id,
+                receipt.sequence,
+                receipt.outcome,
+                receipt.package_sha256,
+                receipt.submitted_by,
+                micros(&receipt.received_at),
+                micros(&receipt.deadline),
+                receipt.previous_sha256
+            );
+            let status = if receipt.call_id != call.id || receipt.sequence != position as i64 + 1 {
+                Status::Failed("out of sequence".to_string())
+            } else if receipt.previous_sha256 != previous {
+                Status::Failed("does not chain to the receipt before it".to_string())
+            } else if sha256(message.as_bytes()) != receipt.receipt_sha256 {
+                Status::Failed("does not match its hash".to_string())
+            } else {
+                keys.verify(&receipt.algorithm, &receipt.key_id, SUBMISSION_RECEIPT_DOMAIN, &message, &receipt.signature)
+            };
+            report.record(format!("submission receipt {}", receipt.id), status);
+            previous = receipt.receipt_sha256.clone();
+        }
+    }
+}
+
+fn check_quorum(report: &mut Report, keys: &Keys) {
+    let Some(publications) = parse::<Vec<QuorumRecord>>(report, "quorum") else {
+        return;
+    };
+    for publication in publications {
+        let message = format!(
+            "cotai-quorum:{}:{}:{}:{}:{}",
+            publication.id,
+            publication.tenant_id.as_deref().unwrap_or(""),
+            publication.reference,
+            publication.document_sha256,
+            micros(&publication.created_at)
+        );
+        for signature in &publication.signatures {
+            let decode = |value: &str| base64::decode_config(value, base64::URL_SAFE_NO_PAD).ok();
+            let verified = decode(&signature.public_key).zip(decode(&signature.signature)).is_some_and(|(key, signature)| {
+                UnparsedPublicKey::new(&ED25519, &key).verify(message.as_bytes(), &signature).is_ok()
+            });
+            report.expect(
+                format!("quorum {} signature by {}", publication.id, signature.officer),
+                verified,
+                "the officer signature does not verify",
+            );
+        }
+
+        let (Some(attestation), Some(attestation_sha256), Some(algorithm), Some(key_id), Some(signature)) = (
+            &publication.attestation,
+            &publication.attestation_sha256,
+            &publication.algorithm,
+            &publication.key_id,
+            &publication.signature,
+        ) else {
+            continue;
+        };
+        let status = if sha256(attestation.as_bytes()) != *attestation_sha256 {
+            Status::Failed("the attestation does not match its hash".to_string())
+        } else {
+            let message = format!("cotai-quorum-attestation:{}:{}", publication.id, attestation_sha256);
+            keys.verify(algorithm, key_id, QUORUM_ATTESTATION_DOMAIN, &message, signature)
+        };
+        report.record(format!("quorum {} attestation", publication.id), status);
+    }
+}
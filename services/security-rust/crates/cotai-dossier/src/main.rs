@@ -0,0 +1,101 @@
+/*!
+Dossier verifier
+
+```text
+cotai-dossier verify ARCHIVE --key RECIPIENT.pem [--jwks FILE] [--out DIR]
+```
+
+`verify` opens a sealed dossier with the recipient's RSA private key
+(PKCS#8 PEM), prints a line per check and exits non-zero if any failed.
+`--jwks` is COTAI's published JWK Set, saved beforehand; without it the
+dossier is only checked against its own keys, with a warning. `--out`
+writes each section to `DIR/<section>.json` once the archive is opened.
+*/
+
+use std::process::exit;
+
+use cotai_dossier::{verify, Status};
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::RsaPrivateKey;
+use serde_json::Value;
+
+fn usage() -> ! {
+    eprintln!("usage: cotai-dossier verify ARCHIVE --key RECIPIENT.pem [--jwks FILE] [--out DIR]");
+    exit(2);
+}
+
+fn read(path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e))
+}
+
+fn run(args: &[String]) -> Result<i32, String> {
+    let mut archive_path = None;
+    let mut key_path = None;
+    let mut jwks_path = None;
+    let mut out = None;
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--key" => key_path = Some(rest.next().cloned().unwrap_or_else(|| usage())),
+            "--jwks" => jwks_path = Some(rest.next().cloned().unwrap_or_else(|| usage())),
+            "--out" => out = Some(rest.next().cloned().unwrap_or_else(|| usage())),
+            _ if archive_path.is_none() && !arg.starts_with("--") => archive_path = Some(arg.clone()),
+            _ => usage(),
+        }
+    }
+    let (Some(archive_path), Some(key_path)) = (archive_path, key_path) else {
+        usage();
+    };
+
+    let archive = read(&archive_path)?;
+    let pem = String::from_utf8(read(&key_path)?).map_err(|_| format!("{} is not a PEM file", key_path))?;
+    let key = RsaPrivateKey::from_pkcs8_pem(&pem).map_err(|e| format!("{} is not an RSA PKCS#8 key: {}", key_path, e))?;
+    let jwks = match &jwks_path {
+        Some(path) => Some(serde_json::from_slice::<Value>(&read(path)?).map_err(|e| format!("{} is not JSON: {}", path, e))?),
+        None => None,
+    };
+
+    let report = verify(&archive, jwks.as_ref(), &key);
+    for warning in &report.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    let (mut passed, mut skipped, mut failed) = (0, 0, 0);
+    for check in &report.checks {
+        match &check.status {
+            Status::Ok => {
+                passed += 1;
+                println!("ok    {}", check.name);
+            }
+            Status::Skipped(reason) => {
+                skipped += 1;
+                println!("skip  {}: {}", check.name, reason);
+            }
+            Status::Failed(problem) => {
+                failed += 1;
+                println!("FAIL  {}: {}", check.name, problem);
+            }
+        }
+    }
+    println!("{} passed, {} skipped, {} failed", passed, skipped, failed);
+
+    if let Some(dir) = out.filter(|_| !report.sections.is_empty()) {
+        std::fs::create_dir_all(&dir).map_err(|e| format!("cannot create {}: {}", dir, e))?;
+        for (name, document) in &report.sections {
+            let path = std::path::Path::new(&dir).join(format!("{}.json", name));
+            std::fs::write(&path, document).map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+        }
+    }
+    Ok(if report.failed() { 1 } else { 0 })
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let code = match args.first().map(String::as_str) {
+        Some("verify") => run(&args[1..]).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            2
+        }),
+        _ => usage(),
+    };
+    exit(code);
+}
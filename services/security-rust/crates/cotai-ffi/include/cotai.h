@@ -0,0 +1,53 @@
+/*
+ * COTAI FFI — C ABI for envelope encryption and token verification.
+ * Keep in sync with crates/cotai-ffi/src/lib.rs; bump COTAI_ABI_VERSION on change.
+ */
+
+#ifndef COTAI_H
+#define COTAI_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define COTAI_ABI_VERSION 1
+
+#define COTAI_OK                 0
+#define COTAI_INVALID_ARGUMENT   1
+#define COTAI_INVALID_TOKEN      2
+#define COTAI_MALFORMED_ENVELOPE 3
+#define COTAI_CRYPTO_FAILURE     4
+#define COTAI_INTERNAL           5
+
+typedef struct CotaiVerifier CotaiVerifier;
+
+uint32_t cotai_abi_version(void);
+
+/* Message for the last failure on this thread, or NULL. Do not free. */
+const char *cotai_last_error(void);
+
+int32_t cotai_envelope_seal(const uint8_t *data_key, size_t data_key_len,
+                            const char *key_id,
+                            const uint8_t *plaintext, size_t plaintext_len,
+                            const char *context_hash, /* nullable */
+                            char **out_json);
+
+int32_t cotai_envelope_open(const uint8_t *data_key, size_t data_key_len,
+                            const char *envelope_json,
+                            uint8_t **out_ptr, size_t *out_len);
+
+/* Returns NULL on failure. */
+CotaiVerifier *cotai_verifier_new_secret(const uint8_t *secret, size_t secret_len,
+                                         const char *algorithm);
+
+/* algorithms: comma-separated, e.g. "RS256,ES256". Returns NULL on failure. */
+CotaiVerifier *cotai_verifier_new_jwks(const char *jwks_json, const char *algorithms);
+
+int32_t cotai_verifier_verify(const CotaiVerifier *verifier,
+                              const char *token,
+                              char **out_principal_json);
+
+void cotai_verifier_free(CotaiVerifier *verifier);
+void cotai_string_free(char *value);
+void cotai_bytes_free(uint8_t *ptr, size_t len);
+
+#endif /* COTAI_H */
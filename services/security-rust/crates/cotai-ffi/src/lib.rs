@@ -0,0 +1,286 @@
+/*!
+COTAI FFI
+Stable C ABI for envelope encryption and token verification
+
+For components that cannot afford an HTTP round trip per call, such as the
+PHP integration through `FFI::cdef`. The declarations live in
+`include/cotai.h`; `COTAI_ABI_VERSION` changes whenever they do.
+
+Conventions:
+- every function returns a `COTAI_*` status code, `0` on success;
+- on failure `cotai_last_error` describes the problem for the calling thread;
+- strings are NUL-terminated UTF-8; returned strings and buffers are owned
+  by the caller and released with `cotai_string_free` / `cotai_bytes_free`;
+- panics never cross the boundary and are reported as `COTAI_INTERNAL`.
+*/
+
+use cotai_verify::{Envelope, JwksCache, TokenVerifier, VerifyError};
+use jsonwebtoken::Algorithm;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::sync::Arc;
+
+pub const COTAI_ABI_VERSION: u32 = 1;
+
+pub const COTAI_OK: i32 = 0;
+pub const COTAI_INVALID_ARGUMENT: i32 = 1;
+pub const COTAI_INVALID_TOKEN: i32 = 2;
+pub const COTAI_MALFORMED_ENVELOPE: i32 = 3;
+pub const COTAI_CRYPTO_FAILURE: i32 = 4;
+pub const COTAI_INTERNAL: i32 = 5;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Opaque verifier handle.
+pub struct CotaiVerifier(TokenVerifier);
+
+fn set_error(message: &str) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn status_for(e: &VerifyError) -> i32 {
+    match e {
+        VerifyError::InvalidToken(_) | VerifyError::UnknownKey(_) | VerifyError::InvalidSignature(_) => {
+            COTAI_INVALID_TOKEN
+        }
+        VerifyError::MalformedEnvelope(_) => COTAI_MALFORMED_ENVELOPE,
+        VerifyError::Crypto(_) => COTAI_CRYPTO_FAILURE,
+        VerifyError::Config(_) | VerifyError::KeySetUnavailable(_) => COTAI_INVALID_ARGUMENT,
+    }
+}
+
+/// Run `body`, translating errors and panics into status codes.
+fn guarded(body: impl FnOnce() -> Result<(), (i32, String)>) -> i32 {
+    match catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => COTAI_OK,
+        Ok(Err((status, message))) => {
+            set_error(&message);
+            status
+        }
+        Err(_) => {
+            set_error("Internal error");
+            COTAI_INTERNAL
+        }
+    }
+}
+
+fn verify_error(e: VerifyError) -> (i32, String) {
+    (status_for(&e), e.to_string())
+}
+
+fn invalid(message: &str) -> (i32, String) {
+    (COTAI_INVALID_ARGUMENT, message.to_string())
+}
+
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, (i32, String)> {
+    if ptr.is_null() {
+        return Err(invalid(&format!("{} is null", name)));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| invalid(&format!("{} is not UTF-8", name)))
+}
+
+unsafe fn opt_str_arg<'a>(ptr: *const c_char, name: &str) -> Result<Option<&'a str>, (i32, String)> {
+    if ptr.is_null() {
+        Ok(None)
+    } else {
+        str_arg(ptr, name).map(Some)
+    }
+}
+
+unsafe fn bytes_arg<'a>(ptr: *const u8, len: usize, name: &str) -> Result<&'a [u8], (i32, String)> {
+    match (ptr.is_null(), len) {
+        (_, 0) => Ok(&[]),
+        (true, _) => Err(invalid(&format!("{} is null", name))),
+        (false, len) => Ok(std::slice::from_raw_parts(ptr, len)),
+    }
+}
+
+fn into_c_string(value: String) -> Result<*mut c_char, (i32, String)> {
+    CString::new(value)
+        .map(CString::into_raw)
+        .map_err(|_| (COTAI_INTERNAL, "Output contains NUL".to_string()))
+}
+
+#[no_mangle]
+pub extern "C" fn cotai_abi_version() -> u32 {
+    COTAI_ABI_VERSION
+}
+
+/// Message for the last failure on this thread, or null. Valid until the
+/// next call into this library from the same thread; do not free it.
+#[no_mangle]
+pub extern "C" fn cotai_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// Encrypt `plaintext` under a 32-byte data key into envelope JSON.
+///
+/// # Safety
+/// `data_key` and `plaintext` must point to `*_len` readable bytes, `key_id`
+/// to a NUL-terminated string, `context_hash` to one or be null, and
+/// `out_json` to writable storage for a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn cotai_envelope_seal(
+    data_key: *const u8,
+    data_key_len: usize,
+    key_id: *const c_char,
+    plaintext: *const u8,
+    plaintext_len: usize,
+    context_hash: *const c_char,
+    out_json: *mut *mut c_char,
+) -> i32 {
+    guarded(|| {
+        if out_json.is_null() {
+            return Err(invalid("out_json is null"));
+        }
+        let key = bytes_arg(data_key, data_key_len, "data_key")?;
+        let key_id = str_arg(key_id, "key_id")?;
+        let plaintext = bytes_arg(plaintext, plaintext_len, "plaintext")?;
+        let context_hash = opt_str_arg(context_hash, "context_hash")?;
+
+        let envelope = Envelope::seal(key, key_id, plaintext, context_hash).map_err(verify_error)?;
+        let json = serde_json::to_string(&envelope).map_err(|e| (COTAI_INTERNAL, e.to_string()))?;
+        *out_json = into_c_string(json)?;
+        Ok(())
+    })
+}
+
+/// Decrypt envelope JSON with a 32-byte data key.
+///
+/// # Safety
+/// `data_key` must point to `data_key_len` readable bytes, `envelope_json`
+/// to a NUL-terminated string, and `out_ptr`/`out_len` to writable storage.
+#[no_mangle]
+pub unsafe extern "C" fn cotai_envelope_open(
+    data_key: *const u8,
+    data_key_len: usize,
+    envelope_json: *const c_char,
+    out_ptr: *mut *mut u8,
+    out_len: *mut usize,
+) -> i32 {
+    guarded(|| {
+        if out_ptr.is_null() || out_len.is_null() {
+            return Err(invalid("out_ptr and out_len are required"));
+        }
+        let key = bytes_arg(data_key, data_key_len, "data_key")?;
+        let json = str_arg(envelope_json, "envelope_json")?;
+
+        let plaintext = Envelope::parse(json)
+            .and_then(|envelope| envelope.open(key))
+            .map_err(verify_error)?;
+
+        let boxed = plaintext.into_boxed_slice();
+        *out_len = boxed.len();
+        *out_ptr = Box::into_raw(boxed) as *mut u8;
+        Ok(())
+    })
+}
+
+/// Create a verifier for tokens signed with a shared secret (`HS256` etc.).
+/// Returns null on failure; see `cotai_last_error`.
+///
+/// # Safety
+/// `secret` must point to `secret_len` readable bytes and `algorithm` to a
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cotai_verifier_new_secret(
+    secret: *const u8,
+    secret_len: usize,
+    algorithm: *const c_char,
+) -> *mut CotaiVerifier {
+    let mut verifier = ptr::null_mut();
+    guarded(|| {
+        let secret = bytes_arg(secret, secret_len, "secret")?;
+        let algorithm = str_arg(algorithm, "algorithm")?;
+        let inner = TokenVerifier::with_secret(secret, algorithm).map_err(verify_error)?;
+        verifier = Box::into_raw(Box::new(CotaiVerifier(inner)));
+        Ok(())
+    });
+    verifier
+}
+
+/// Create a verifier for asymmetric tokens checked against a JWKS document.
+/// `algorithms` is a comma-separated list such as `"RS256,ES256"`. The key
+/// set is fixed for the handle's lifetime; create a new one to rotate.
+/// Returns null on failure; see `cotai_last_error`.
+///
+/// # Safety
+/// `jwks_json` and `algorithms` must be NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn cotai_verifier_new_jwks(
+    jwks_json: *const c_char,
+    algorithms: *const c_char,
+) -> *mut CotaiVerifier {
+    let mut verifier = ptr::null_mut();
+    guarded(|| {
+        let jwks = JwksCache::from_json(str_arg(jwks_json, "jwks_json")?).map_err(verify_error)?;
+        let algorithms = str_arg(algorithms, "algorithms")?
+            .split(',')
+            .map(|a| a.trim().parse::<Algorithm>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid("Unsupported JWT algorithm"))?;
+        let inner = TokenVerifier::with_jwks(Arc::new(jwks), &algorithms).map_err(verify_error)?;
+        verifier = Box::into_raw(Box::new(CotaiVerifier(inner)));
+        Ok(())
+    });
+    verifier
+}
+
+/// Verify a bearer token and write the caller's principal as JSON.
+///
+/// # Safety
+/// `verifier` must come from `cotai_verifier_new_*` and not be freed,
+/// `token` must be a NUL-terminated string and `out_principal_json`
+/// writable storage for a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn cotai_verifier_verify(
+    verifier: *const CotaiVerifier,
+    token: *const c_char,
+    out_principal_json: *mut *mut c_char,
+) -> i32 {
+    guarded(|| {
+        if verifier.is_null() || out_principal_json.is_null() {
+            return Err(invalid("verifier and out_principal_json are required"));
+        }
+        let token = str_arg(token, "token")?;
+
+        let principal = (*verifier).0.verify(token).map_err(verify_error)?;
+        let json = serde_json::to_string(&principal).map_err(|e| (COTAI_INTERNAL, e.to_string()))?;
+        *out_principal_json = into_c_string(json)?;
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `verifier` must come from `cotai_verifier_new_*` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn cotai_verifier_free(verifier: *mut CotaiVerifier) {
+    if !verifier.is_null() {
+        drop(Box::from_raw(verifier));
+    }
+}
+
+/// # Safety
+/// `value` must be a string returned by this library, freed at most once.
+#[no_mangle]
+pub unsafe extern "C" fn cotai_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
+
+/// # Safety
+/// `ptr`/`len` must be a buffer returned by `cotai_envelope_open`, freed at most once.
+#[no_mangle]
+pub unsafe extern "C" fn cotai_bytes_free(ptr: *mut u8, len: usize) {
+    if !ptr.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)));
+    }
+}
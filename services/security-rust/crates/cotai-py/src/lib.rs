@@ -0,0 +1,80 @@
+/*!
+COTAI Python
+Validation rules and PII detection for data pipelines
+
+Build with `maturin build --release -m crates/cotai-py/Cargo.toml` and
+install the wheel; then `import cotai_py`. The module wraps
+cotai-validation directly, so pipelines and the security service agree on
+what a valid CPF is and on what counts as PII.
+*/
+
+use cotai_validation::{pii, Rule};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+fn parse_rule(rule: &str) -> PyResult<Rule> {
+    rule.parse().map_err(PyValueError::new_err)
+}
+
+/// Python indexes strings by code point, the detector by byte.
+fn char_offset(text: &str, byte_offset: usize) -> usize {
+    text[..byte_offset].chars().count()
+}
+
+/// Names of the rules `validate` accepts.
+#[pyfunction]
+fn rules() -> Vec<&'static str> {
+    cotai_validation::RULES.iter().map(Rule::as_str).collect()
+}
+
+/// None when `value` is valid under `rule`, otherwise the reason.
+/// Raises ValueError for an unknown rule.
+#[pyfunction]
+fn validate(rule: &str, value: &str) -> PyResult<Option<&'static str>> {
+    let rule = parse_rule(rule)?;
+    Ok(cotai_validation::validate(rule, value).err().map(|issue| issue.message))
+}
+
+#[pyfunction]
+fn is_valid(rule: &str, value: &str) -> PyResult<bool> {
+    let rule = parse_rule(rule)?;
+    Ok(cotai_validation::validate(rule, value).is_ok())
+}
+
+/// `(rule, start, end, value)` for each PII match, with `text[start:end] == value`.
+#[pyfunction]
+fn detect_pii(text: &str) -> Vec<(&'static str, usize, usize, String)> {
+    pii::detect(text)
+        .into_iter()
+        .map(|finding| {
+            (
+                finding.rule.as_str(),
+                char_offset(text, finding.start),
+                char_offset(text, finding.end),
+                text[finding.start..finding.end].to_string(),
+            )
+        })
+        .collect()
+}
+
+#[pyfunction]
+fn contains_pii(text: &str) -> bool {
+    pii::contains_pii(text)
+}
+
+/// `text` with every PII match replaced by `[REDACTED:<rule>]`.
+#[pyfunction]
+fn redact_pii(text: &str) -> String {
+    pii::redact(text)
+}
+
+#[pymodule]
+fn cotai_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(rules, m)?)?;
+    m.add_function(wrap_pyfunction!(validate, m)?)?;
+    m.add_function(wrap_pyfunction!(is_valid, m)?)?;
+    m.add_function(wrap_pyfunction!(detect_pii, m)?)?;
+    m.add_function(wrap_pyfunction!(contains_pii, m)?)?;
+    m.add_function(wrap_pyfunction!(redact_pii, m)?)?;
+    Ok(())
+}
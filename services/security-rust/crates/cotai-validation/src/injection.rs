@@ -0,0 +1,310 @@
+/*!
+Injection Detection
+Flags SQL injection, cross-site scripting and path traversal patterns in untrusted strings
+
+This is a screen, not a sanitizer: callers still bind SQL parameters and
+escape output. Before matching, input is percent-decoded twice (to catch
+double encoding), has HTML character references decoded and is
+lowercased; SQL comments and whitespace are collapsed for the SQL checks,
+so `UNION/**/SELECT` and `%252e%252e%252f` are both caught.
+*/
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::FieldError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Threat {
+    SqlInjection,
+    Xss,
+    PathTraversal,
+}
+
+impl Threat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Threat::SqlInjection => "sql_injection",
+            Threat::Xss => "xss",
+            Threat::PathTraversal => "path_traversal",
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Threat::SqlInjection => "SQL injection",
+            Threat::Xss => "cross-site scripting",
+            Threat::PathTraversal => "path traversal",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Detection {
+    pub threat: Threat,
+    /// What matched, e.g. `union select`.
+    pub pattern: &'static str,
+}
+
+const SQL_PATTERNS: &[&str] = &[
+    "union select",
+    "union all select",
+    "select * from",
+    "insert into",
+    "delete from",
+    "drop table",
+    "drop database",
+    "truncate table",
+    "alter table",
+    "xp_cmdshell",
+    "exec(",
+    "information_schema",
+    "pg_catalog",
+    "pg_sleep(",
+    "sleep(",
+    "benchmark(",
+    "waitfor delay",
+    "load_file(",
+    "into outfile",
+    "'--",
+    "' --",
+    "'#",
+    "';",
+    "' ;",
+];
+
+const XSS_PATTERNS: &[&str] = &[
+    "<script",
+    "</script",
+    "javascript:",
+    "vbscript:",
+    "livescript:",
+    "data:text/html",
+    "<iframe",
+    "<frame",
+    "<object",
+    "<embed",
+    "<applet",
+    "<base",
+    "<meta",
+    "<svg",
+    "<math",
+    "srcdoc=",
+    "expression(",
+    "document.cookie",
+    "document.domain",
+];
+
+const PATH_PATTERNS: &[&str] = &[
+    "../",
+    "/..",
+    "/etc/passwd",
+    "/etc/shadow",
+    "/proc/self",
+    "c:/windows",
+    "file://",
+    "\0",
+];
+
+/// Overlong UTF-8 encodings of `.` and `/`, matched before decoding.
+const OVERLONG_PATTERNS: &[&str] = &["%c0%ae", "%c0%af", "%e0%80%ae", "%c1%9c"];
+
+/// Every threat found in `text`, at most one detection per threat.
+pub fn detect(text: &str) -> Vec<Detection> {
+    let lowered = text.to_lowercase();
+    let decoded = decode_entities(&percent_decode(&percent_decode(&lowered))).to_lowercase();
+
+    let mut detections = Vec::new();
+    if let Some(pattern) = sql(&decoded) {
+        detections.push(Detection { threat: Threat::SqlInjection, pattern });
+    }
+    if let Some(pattern) = xss(&decoded) {
+        detections.push(Detection { threat: Threat::Xss, pattern });
+    }
+    let path = decoded.replace('\\', "/");
+    let traversal = OVERLONG_PATTERNS.iter().find(|p| lowered.contains(*p))
+        .or_else(|| PATH_PATTERNS.iter().find(|p| path.contains(*p)));
+    if let Some(pattern) = traversal.copied().or_else(|| (path.trim() == "..").then_some("..")) {
+        detections.push(Detection { threat: Threat::PathTraversal, pattern });
+    }
+    detections
+}
+
+pub fn is_suspicious(text: &str) -> bool {
+    !detect(text).is_empty()
+}
+
+/// Detections in every string of `value`, object keys included, as field
+/// errors keyed by path (`$.buyer.name`, `$.items[0]`).
+pub fn scan(value: &Value) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    scan_at(value, "$".to_string(), &mut errors);
+    errors
+}
+
+fn scan_at(value: &Value, path: String, errors: &mut Vec<FieldError>) {
+    match value {
+        Value::String(text) => errors.extend(check(&path, text)),
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                scan_at(item, crate::index_path(&path, i), errors);
+            }
+        }
+        Value::Object(fields) => {
+            for (key, item) in fields {
+                let child = crate::field_path(&path, key);
+                errors.extend(check(&child, key));
+                scan_at(item, child, errors);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Detections in `text` as field errors for `field`.
+pub fn check(field: &str, text: &str) -> Vec<FieldError> {
+    detect(text)
+        .into_iter()
+        .map(|detection| FieldError {
+            field: field.to_string(),
+            code: detection.threat.as_str().to_string(),
+            message: format!("Value looks like {} ({})", detection.threat.describe(), detection.pattern),
+        })
+        .collect()
+}
+
+fn sql(decoded: &str) -> Option<&'static str> {
+    let (normalized, commented) = strip_sql_comments(decoded);
+    if let Some(pattern) = SQL_PATTERNS.iter().find(|p| normalized.contains(*p)) {
+        return Some(*pattern);
+    }
+    if tautology(&normalized) {
+        return Some("or tautology");
+    }
+    // A comment is only telling next to a quote or keyword; bare "/*" is common prose
+    (commented && (normalized.contains('\'') || normalized.contains(" select ") || normalized.contains(" or ")))
+        .then_some("comment")
+}
+
+/// `text` with `/* */` comments replaced by a space, whitespace collapsed and
+/// spaces around `=` dropped; the flag says whether a comment was removed.
+fn strip_sql_comments(text: &str) -> (String, bool) {
+    let mut out = String::with_capacity(text.len());
+    let mut commented = false;
+    let mut rest = text;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        out.push(' ');
+        commented = true;
+        rest = match rest[start + 2..].find("*/") {
+            Some(end) => &rest[start + 2 + end + 2..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+
+    let collapsed = out.split_whitespace().collect::<Vec<_>>().join(" ");
+    (collapsed.replace(" =", "=").replace("= ", "="), commented)
+}
+
+/// `or x=x` with both sides equal once quotes are stripped, e.g.
+/// `' or '1'='1` or `or 1=1--`.
+fn tautology(normalized: &str) -> bool {
+    normalized.match_indices("or ").any(|(i, _)| {
+        let preceded = i == 0 || matches!(normalized.as_bytes()[i - 1], b' ' | b'\'' | b'"' | b')');
+        let operand = normalized[i + 3..].split(' ').next().unwrap_or("");
+        let Some((left, right)) = operand.split_once('=') else {
+            return false;
+        };
+        let trim = |side: &str| side.trim_matches(|c: char| !c.is_ascii_alphanumeric()).to_string();
+        preceded && !trim(left).is_empty() && trim(left) == trim(right)
+    })
+}
+
+fn xss(decoded: &str) -> Option<&'static str> {
+    // Browsers skip tabs and newlines inside URL schemes ("java\tscript:")
+    let compact: String = decoded.chars().filter(|c| !matches!(c, '\t' | '\n' | '\r' | '\0')).collect();
+    if let Some(pattern) = XSS_PATTERNS.iter().find(|p| compact.contains(*p)) {
+        return Some(*pattern);
+    }
+    (compact.contains('<') && event_handler(&compact)).then_some("event handler")
+}
+
+/// An `on<event>=` attribute, as in `<img src=x onerror=alert(1)>`.
+fn event_handler(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    text.match_indices("on").any(|(i, _)| {
+        let boundary = i > 0 && matches!(bytes[i - 1], b' ' | b'/' | b'"' | b'\'');
+        let name_end = bytes[i + 2..].iter().position(|b| !b.is_ascii_lowercase()).map_or(bytes.len(), |n| i + 2 + n);
+        let after = text[name_end..].trim_start();
+        boundary && name_end > i + 2 && after.starts_with('=')
+    })
+}
+
+/// One round of `%XX` decoding; malformed escapes are kept as they are.
+fn percent_decode(text: &str) -> String {
+    if !text.contains('%') {
+        return text.to_string();
+    }
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = |b: u8| (b as char).to_digit(16);
+        match (bytes[i], bytes.get(i + 1).copied().and_then(hex), bytes.get(i + 2).copied().and_then(hex)) {
+            (b'%', Some(high), Some(low)) => {
+                out.push((high * 16 + low) as u8);
+                i += 3;
+            }
+            (b, _, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Decode numeric references and the few named ones that matter for markup.
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest[1..].find(|c: char| !c.is_ascii_alphanumeric() && c != '#').map_or(rest.len(), |n| n + 1);
+        let decoded = match &rest[1..end] {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "amp" => Some('&'),
+            "colon" => Some(':'),
+            "tab" => Some('\t'),
+            "newline" => Some('\n'),
+            name => name.strip_prefix('#').and_then(|number| {
+                match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => number.parse().ok(),
+                }
+                .and_then(char::from_u32)
+            }),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = rest[end..].strip_prefix(';').unwrap_or(&rest[end..]);
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,269 @@
+/*!
+COTAI Validation
+Field validation rules, JSON schema checks, PII and injection detection shared by the security service, the gateway, the frontend and data pipelines
+
+Formatting characters are accepted where Brazilian documents commonly carry
+them (`123.456.789-09`, `12.345.678/0001-95`); anything else is rejected.
+*/
+
+use serde::Serialize;
+use std::str::FromStr;
+
+pub mod injection;
+pub mod pii;
+pub mod schema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    Cpf,
+    Cnpj,
+    Cep,
+    Phone,
+    Email,
+}
+
+pub const RULES: &[Rule] = &[Rule::Cpf, Rule::Cnpj, Rule::Cep, Rule::Phone, Rule::Email];
+
+impl Rule {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Rule::Cpf => "cpf",
+            Rule::Cnpj => "cnpj",
+            Rule::Cep => "cep",
+            Rule::Phone => "phone",
+            Rule::Email => "email",
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RULES
+            .iter()
+            .copied()
+            .find(|rule| rule.as_str() == s)
+            .ok_or_else(|| format!("Unknown validation rule '{}'", s))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Issue {
+    pub rule: Rule,
+    pub message: &'static str,
+}
+
+/// One problem with one field of a payload, in the shape services show
+/// their callers. `field` is a path such as `$.buyer.cnpj` or `$.items[0]`;
+/// `code` is the rule, schema keyword or threat that failed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub code: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn from_issue(field: &str, issue: Issue) -> Self {
+        Self { field: field.to_string(), code: issue.rule.as_str().to_string(), message: issue.message.to_string() }
+    }
+}
+
+/// Path of `key` under `parent`, quoted when it is not a plain identifier.
+pub fn field_path(parent: &str, key: &str) -> String {
+    let plain = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if plain {
+        format!("{}.{}", parent, key)
+    } else {
+        format!("{}['{}']", parent, key.replace('\\', "\\\\").replace('\'', "\\'"))
+    }
+}
+
+pub fn index_path(parent: &str, index: usize) -> String {
+    format!("{}[{}]", parent, index)
+}
+
+pub fn validate(rule: Rule, value: &str) -> Result<(), Issue> {
+    let result = match rule {
+        Rule::Cpf => check_cpf(value),
+        Rule::Cnpj => check_cnpj(value),
+        Rule::Cep => check_cep(value),
+        Rule::Phone => check_phone(value),
+        Rule::Email => check_email(value),
+    };
+    result.map_err(|message| Issue { rule, message })
+}
+
+/// Digits of `value`, allowing only the given separators in between.
+fn digits(value: &str, separators: &[char]) -> Option<Vec<u32>> {
+    value
+        .trim()
+        .chars()
+        .filter(|c| !separators.contains(c))
+        .map(|c| c.to_digit(10))
+        .collect()
+}
+
+fn mod11_digit(digits: &[u32], weights: &[u32]) -> u32 {
+    let sum: u32 = digits.iter().zip(weights).map(|(d, w)| d * w).sum();
+    match sum % 11 {
+        0 | 1 => 0,
+        r => 11 - r,
+    }
+}
+
+fn check_cpf(value: &str) -> Result<(), &'static str> {
+    let d = digits(value, &['.', '-']).ok_or("CPF may only contain digits, '.' and '-'")?;
+    if d.len() != 11 {
+        return Err("CPF must have 11 digits");
+    }
+    if d.iter().all(|x| *x == d[0]) {
+        return Err("CPF with all digits equal is invalid");
+    }
+    let first = mod11_digit(&d[..9], &[10, 9, 8, 7, 6, 5, 4, 3, 2]);
+    let second = mod11_digit(&d[..10], &[11, 10, 9, 8, 7, 6, 5, 4, 3, 2]);
+    if d[9] != first || d[10] != second {
+        return Err("CPF check digits do not match");
+    }
+    Ok(())
+}
+
+fn check_cnpj(value: &str) -> Result<(), &'static str> {
+    let d = digits(value, &['.', '-', '/']).ok_or("CNPJ may only contain digits, '.', '/' and '-'")?;
+    if d.len() != 14 {
+        return Err("CNPJ must have 14 digits");
+    }
+    if d.iter().all(|x| *x == d[0]) {
+        return Err("CNPJ with all digits equal is invalid");
+    }
+    let first = mod11_digit(&d[..12], &[5, 4, 3, 2, 9, 8, 7, 6, 5, 4, 3, 2]);
+    let second = mod11_digit(&d[..13], &[6, 5, 4, 3, 2, 9, 8, 7, 6, 5, 4, 3, 2]);
+    if d[12] != first || d[13] != second {
+        return Err("CNPJ check digits do not match");
+    }
+    Ok(())
+}
+
+fn check_cep(value: &str) -> Result<(), &'static str> {
+    let d = digits(value, &['-', '.']).ok_or("CEP may only contain digits, '.' and '-'")?;
+    if d.len() != 8 {
+        return Err("CEP must have 8 digits");
+    }
+    Ok(())
+}
+
+/// Brazilian numbers with area code, optionally prefixed by +55.
+fn check_phone(value: &str) -> Result<(), &'static str> {
+    let trimmed = value.trim();
+    let national = trimmed.strip_prefix("+55").unwrap_or(trimmed);
+    let d = digits(national, &[' ', '-', '(', ')']).ok_or("Phone numbers may only contain digits, spaces, '()', '-' and a leading +55")?;
+    if d.len() != 10 && d.len() != 11 {
+        return Err("Phone numbers need an area code and 8 or 9 digits");
+    }
+    if d[0] == 0 || d[1] == 0 {
+        return Err("Area code is invalid");
+    }
+    if d.len() == 11 && d[2] != 9 {
+        return Err("Mobile numbers must start with 9");
+    }
+    Ok(())
+}
+
+/// Structural check only; deliverability is the mailer's concern.
+fn check_email(value: &str) -> Result<(), &'static str> {
+    let value = value.trim();
+    if value.len() > 254 || value.chars().any(char::is_whitespace) {
+        return Err("E-mail address is too long or contains whitespace");
+    }
+    let (local, domain) = value.split_once('@').ok_or("E-mail address needs an '@'")?;
+    if local.is_empty() || local.len() > 64 || domain.contains('@') {
+        return Err("E-mail local part is invalid");
+    }
+    let labels: Vec<&str> = domain.split('.').collect();
+    if labels.len() < 2 || labels.iter().any(|l| l.is_empty() || l.starts_with('-') || l.ends_with('-')) {
+        return Err("E-mail domain is invalid");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(rule: Rule, valid: &[&str], invalid: &[&str]) {
+        for value in valid {
+            assert_eq!(validate(rule, value), Ok(()), "{} should be a valid {}", value, rule.as_str());
+        }
+        for value in invalid {
+            assert!(validate(rule, value).is_err(), "{} should not be a valid {}", value, rule.as_str());
+        }
+    }
+
+    #[test]
+    fn cpf_check_digits() {
+        check(
+            Rule::Cpf,
+            &["529.982.247-25", "52998224725", "111.444.777-35", " 111.444.777-35 "],
+            &["529.982.247-26", "52998224724", "111.111.111-11", "5299822472", "529.982.247/25", "529 982 247 25"],
+        );
+        assert_eq!(
+            validate(Rule::Cpf, "529.982.247-26"),
+            Err(Issue { rule: Rule::Cpf, message: "CPF check digits do not match" })
+        );
+    }
+
+    #[test]
+    fn cnpj_check_digits() {
+        check(
+            Rule::Cnpj,
+            &["11.222.333/0001-81", "11222333000181", "11.444.777/0001-61"],
+            &["11.222.333/0001-82", "11222333000180", "00.000.000/0000-00", "11.222.333/0001-8", "11.222.333|0001-81"],
+        );
+        assert_eq!(
+            validate(Rule::Cnpj, "11.222.333/0001-8"),
+            Err(Issue { rule: Rule::Cnpj, message: "CNPJ must have 14 digits" })
+        );
+    }
+
+    #[test]
+    fn cep_has_eight_digits() {
+        check(Rule::Cep, &["01310-100", "01310100", "01.310-100"], &["1310-100", "01310-1000", "01310-10a", "01310 100"]);
+    }
+
+    #[test]
+    fn phones_need_an_area_code() {
+        check(
+            Rule::Phone,
+            &["(11) 98765-4321", "11987654321", "+55 11 98765-4321", "(11) 3265-4321", "1132654321"],
+            &["(11) 88765-4321", "(01) 98765-4321", "98765-4321", "+1 415 555 0100", "(11) 98765-432a"],
+        );
+    }
+
+    #[test]
+    fn emails_are_checked_for_shape_only() {
+        check(
+            Rule::Email,
+            &["ana@example.com", "ana.silva+cotai@mail.example.com.br"],
+            &["ana@example", "ana@@example.com", "@example.com", "ana silva@example.com", "ana@-example.com"],
+        );
+    }
+
+    #[test]
+    fn rules_round_trip_through_their_names() {
+        for rule in RULES {
+            assert_eq!(rule.as_str().parse::<Rule>(), Ok(*rule));
+        }
+        assert!("rg".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn field_paths_quote_odd_keys() {
+        assert_eq!(field_path("$", "buyer"), "$.buyer");
+        assert_eq!(field_path("$.buyer", "tax-id"), "$.buyer.tax-id");
+        assert_eq!(field_path("$", "razão social"), "$['razão social']");
+        assert_eq!(field_path("$", "it's"), "$['it\\'s']");
+        assert_eq!(index_path("$.items", 0), "$.items[0]");
+    }
+}
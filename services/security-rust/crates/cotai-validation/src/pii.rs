@@ -0,0 +1,228 @@
+/*!
+PII Detection
+Finds CPF, CNPJ, phone numbers, CEPs and e-mail addresses in free text
+
+A candidate is only reported when it passes the same rule `validate`
+applies, so a number shaped like a CPF but with wrong check digits is not
+PII. Offsets are byte offsets into the input.
+*/
+
+use serde::Serialize;
+
+use crate::{validate, Rule};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub rule: Rule,
+    pub start: usize,
+    pub end: usize,
+}
+
+const EMAIL_DELIMITERS: &[char] = &[',', ';', '<', '>', '(', ')', '[', ']', '"', '\''];
+
+/// All PII in `text`, ordered by position and never overlapping.
+pub fn detect(text: &str) -> Vec<Finding> {
+    let mut findings = emails(text);
+    for finding in numbers(text) {
+        if !findings.iter().any(|f| f.start < finding.end && finding.start < f.end) {
+            findings.push(finding);
+        }
+    }
+    findings.sort_by_key(|f| f.start);
+    findings
+}
+
+pub fn contains_pii(text: &str) -> bool {
+    !detect(text).is_empty()
+}
+
+/// `text` with every finding replaced by `[REDACTED:<rule>]`.
+pub fn redact(text: &str) -> String {
+    redact_findings(text, &detect(text))
+}
+
+/// Redacts only `findings`, which must come from `detect(text)` in order.
+pub fn redact_findings(text: &str, findings: &[Finding]) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut last = 0;
+    for finding in findings {
+        redacted.push_str(&text[last..finding.start]);
+        redacted.push_str("[REDACTED:");
+        redacted.push_str(finding.rule.as_str());
+        redacted.push(']');
+        last = finding.end;
+    }
+    redacted.push_str(&text[last..]);
+    redacted
+}
+
+fn emails(text: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        let delimiter = c.is_whitespace() || EMAIL_DELIMITERS.contains(&c);
+        match (delimiter, start) {
+            (false, None) => start = Some(i),
+            (true, Some(s)) => {
+                let token = text[s..i].trim_end_matches(['.', ':', '!', '?']);
+                if token.contains('@') && validate(Rule::Email, token).is_ok() {
+                    findings.push(Finding { rule: Rule::Email, start: s, end: s + token.len() });
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    findings
+}
+
+/// Maximal runs of digits and document/phone punctuation, split into
+/// space-separated pieces. Spans of pieces are tried longest first so
+/// "(11) 98765-4321" is one phone while "CPF 123.456.789-09 12" still
+/// yields the CPF.
+fn numbers(text: &str) -> Vec<Finding> {
+    let bytes = text.as_bytes();
+    let mut findings = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let starts = matches!(bytes[i], b'0'..=b'9' | b'(' | b'+')
+            && (i == 0 || !bytes[i - 1].is_ascii_alphanumeric());
+        if !starts {
+            i += 1;
+            continue;
+        }
+
+        let mut pieces: Vec<(usize, usize)> = Vec::new();
+        let mut piece_start = i;
+        let mut last_digit = None;
+        let mut j = i;
+        while j < bytes.len() {
+            match bytes[j] {
+                b'0'..=b'9' => last_digit = Some(j + 1),
+                b'.' | b'-' | b'/' | b'(' | b')' | b'+' => {}
+                b' ' if j + 1 < bytes.len() && matches!(bytes[j + 1], b'0'..=b'9' | b'(') => {
+                    if let Some(end) = last_digit.take() {
+                        pieces.push((piece_start, end));
+                    }
+                    piece_start = j + 1;
+                }
+                _ => break,
+            }
+            j += 1;
+        }
+        if let Some(end) = last_digit {
+            pieces.push((piece_start, end));
+        }
+        // Digits glued to letters ("abc123", "123abc") are identifiers, not PII
+        let glued = j < bytes.len() && bytes[j].is_ascii_alphabetic() && last_digit == Some(j);
+        if glued {
+            pieces.pop();
+        }
+
+        let mut p = 0;
+        while p < pieces.len() {
+            let found = (p..pieces.len()).rev().find_map(|q| {
+                let (start, end) = (pieces[p].0, pieces[q].1);
+                classify(&text[start..end]).map(|rule| Finding { rule, start, end })
+            });
+            match found {
+                Some(finding) => {
+                    p = pieces.iter().position(|piece| piece.1 == finding.end).unwrap_or(p) + 1;
+                    findings.push(finding);
+                }
+                None => p += 1,
+            }
+        }
+        i = j.max(i + 1);
+    }
+    findings
+}
+
+fn classify(candidate: &str) -> Option<Rule> {
+    let digit_count = candidate.bytes().filter(u8::is_ascii_digit).count();
+    let formatted = candidate.bytes().any(|b| !b.is_ascii_digit());
+    let is = |rule| validate(rule, candidate).is_ok();
+
+    match digit_count {
+        11 if is(Rule::Cpf) => Some(Rule::Cpf),
+        14 if is(Rule::Cnpj) => Some(Rule::Cnpj),
+        // Bare 10-digit numbers are too often timestamps or ids to call them phones
+        10 if formatted && is(Rule::Phone) => Some(Rule::Phone),
+        11 if is(Rule::Phone) => Some(Rule::Phone),
+        12 | 13 if candidate.starts_with("+55") && is(Rule::Phone) => Some(Rule::Phone),
+        // Likewise only the 00000-000 form counts as a CEP
+        8 if candidate.len() == 9 && candidate.as_bytes()[5] == b'-' && is(Rule::Cep) => Some(Rule::Cep),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The rule and text of each finding.
+    fn found(text: &str) -> Vec<(Rule, &str)> {
+        detect(text).into_iter().map(|f| (f.rule, &text[f.start..f.end])).collect()
+    }
+
+    #[test]
+    fn documents_are_found_formatted_or_not() {
+        let text = "CPF 529.982.247-25, CNPJ 11222333000181 e CEP 01310-100.";
+        assert_eq!(
+            found(text),
+            vec![(Rule::Cpf, "529.982.247-25"), (Rule::Cnpj, "11222333000181"), (Rule::Cep, "01310-100")]
+        );
+        assert_eq!(found("cpf 52998224725"), vec![(Rule::Cpf, "52998224725")]);
+        assert_eq!(found("cnpj 11.222.333/0001-81"), vec![(Rule::Cnpj, "11.222.333/0001-81")]);
+    }
+
+    #[test]
+    fn spans_are_byte_offsets() {
+        let text = "Olá, CPF 529.982.247-25";
+        let findings = detect(text);
+        assert_eq!(findings, vec![Finding { rule: Rule::Cpf, start: 10, end: 24 }]);
+        assert_eq!(&text[10..24], "529.982.247-25");
+    }
+
+    #[test]
+    fn wrong_check_digits_are_not_pii() {
+        assert!(detect("CPF 529.982.247-26 e CNPJ 11.222.333/0001-82").is_empty());
+        assert!(!contains_pii("pedido 11222333000182"));
+    }
+
+    #[test]
+    fn phones_span_their_spaces() {
+        assert_eq!(found("ligue (11) 98765-4321 hoje"), vec![(Rule::Phone, "(11) 98765-4321")]);
+        assert_eq!(found("ou +55 11 98765-4321"), vec![(Rule::Phone, "+55 11 98765-4321")]);
+        // A trailing number does not stop the CPF before it being found
+        assert_eq!(found("CPF 123.456.789-09 12"), vec![(Rule::Cpf, "123.456.789-09")]);
+    }
+
+    #[test]
+    fn ambiguous_numbers_are_left_alone() {
+        // Bare ten digits and eight digits are too often ids or timestamps
+        assert!(detect("ts 1132654321 id 01310100").is_empty());
+        // Digits glued to letters are identifiers
+        assert!(detect("pedido52998224725 e 52998224725abc").is_empty());
+    }
+
+    #[test]
+    fn overlapping_spans_keep_the_email() {
+        let text = "escreva para 52998224725@example.com ou ana@example.com";
+        assert_eq!(
+            found(text),
+            vec![(Rule::Email, "52998224725@example.com"), (Rule::Email, "ana@example.com")]
+        );
+        let findings = detect(text);
+        assert!(findings.windows(2).all(|pair| pair[0].end <= pair[1].start));
+    }
+
+    #[test]
+    fn redaction_replaces_each_span() {
+        assert_eq!(
+            redact("CPF 529.982.247-25, fone (11) 98765-4321, email ana@example.com."),
+            "CPF [REDACTED:cpf], fone [REDACTED:phone], email [REDACTED:email]."
+        );
+        assert_eq!(redact("nada aqui"), "nada aqui");
+    }
+}
@@ -0,0 +1,351 @@
+/*!
+JSON Schema
+Validates JSON payloads against a subset of JSON Schema, reporting every failing field
+
+Supported keywords: `type`, `enum`, `const`, `properties`, `required`,
+`additionalProperties`, `minProperties`, `maxProperties`, `items`,
+`minItems`, `maxItems`, `uniqueItems`, `minLength`, `maxLength`,
+`minimum`, `maximum`, `exclusiveMinimum`, `exclusiveMaximum` and
+`format`, whose values are the validation rules (`cpf`, `cnpj`, `cep`,
+`phone`, `email`) plus `uuid`. Annotations (`title`, `description`, ...)
+are ignored; any other keyword fails compilation rather than being
+silently skipped, so a schema never looks stricter than it is.
+*/
+
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+use crate::{field_path, index_path, validate as validate_rule, FieldError, Rule};
+
+/// Nesting beyond this is rejected when compiling.
+pub const MAX_DEPTH: usize = 32;
+
+const ANNOTATIONS: &[&str] = &["$schema", "$id", "$comment", "title", "description", "default", "examples", "deprecated"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Null,
+    Boolean,
+    Integer,
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+impl Type {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "null" => Type::Null,
+            "boolean" => Type::Boolean,
+            "integer" => Type::Integer,
+            "number" => Type::Number,
+            "string" => Type::String,
+            "array" => Type::Array,
+            "object" => Type::Object,
+            _ => return None,
+        })
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Type::Null => "null",
+            Type::Boolean => "boolean",
+            Type::Integer => "integer",
+            Type::Number => "number",
+            Type::String => "string",
+            Type::Array => "array",
+            Type::Object => "object",
+        }
+    }
+
+    fn matches(&self, value: &Value) -> bool {
+        match self {
+            Type::Null => value.is_null(),
+            Type::Boolean => value.is_boolean(),
+            Type::Integer => value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0),
+            Type::Number => value.is_number(),
+            Type::String => value.is_string(),
+            Type::Array => value.is_array(),
+            Type::Object => value.is_object(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Rule(Rule),
+    Uuid,
+}
+
+#[derive(Debug, Clone)]
+enum Additional {
+    Allowed,
+    Forbidden,
+    Schema(Box<Schema>),
+}
+
+/// A compiled schema.
+#[derive(Debug, Clone)]
+pub struct Schema {
+    types: Option<Vec<Type>>,
+    enumeration: Option<Vec<Value>>,
+    constant: Option<Value>,
+    properties: BTreeMap<String, Schema>,
+    required: Vec<String>,
+    additional: Additional,
+    min_properties: Option<usize>,
+    max_properties: Option<usize>,
+    items: Option<Box<Schema>>,
+    min_items: Option<usize>,
+    max_items: Option<usize>,
+    unique_items: bool,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+    exclusive_minimum: Option<f64>,
+    exclusive_maximum: Option<f64>,
+    format: Option<Format>,
+}
+
+impl Schema {
+    /// Compile `schema`; the error names the offending keyword and where it is.
+    pub fn compile(schema: &Value) -> Result<Self, String> {
+        compile_at(schema, "#", 0)
+    }
+
+    /// Every problem with `data`, by field path (`$.buyer.cnpj`,
+    /// `$.items[2]`); empty when it is valid.
+    pub fn validate(&self, data: &Value) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        self.check(data, "$", &mut errors);
+        errors
+    }
+
+    fn check(&self, value: &Value, path: &str, errors: &mut Vec<FieldError>) {
+        let mut fail = |code: &str, message: String| {
+            errors.push(FieldError { field: path.to_string(), code: code.to_string(), message });
+        };
+
+        if let Some(types) = &self.types {
+            if !types.iter().any(|t| t.matches(value)) {
+                let names: Vec<&str> = types.iter().map(Type::as_str).collect();
+                fail("type", format!("Expected {}", names.join(" or ")));
+                return;
+            }
+        }
+        if let Some(allowed) = &self.enumeration {
+            if !allowed.contains(value) {
+                fail("enum", "Value is not one of the allowed values".to_string());
+            }
+        }
+        if let Some(constant) = &self.constant {
+            if constant != value {
+                fail("const", format!("Value must be {}", constant));
+            }
+        }
+
+        match value {
+            Value::String(text) => self.check_string(text, fail),
+            Value::Number(number) => {
+                if let Some(n) = number.as_f64() {
+                    self.check_number(n, fail);
+                }
+            }
+            Value::Array(items) => {
+                self.check_array(items, fail);
+                if let Some(schema) = &self.items {
+                    for (i, item) in items.iter().enumerate() {
+                        schema.check(item, &index_path(path, i), errors);
+                    }
+                }
+            }
+            Value::Object(fields) => {
+                self.check_object(fields, fail);
+                let additional = match &self.additional {
+                    Additional::Schema(schema) => Some(&**schema),
+                    _ => None,
+                };
+                for (key, item) in fields {
+                    let child = field_path(path, key);
+                    match self.properties.get(key).or(additional) {
+                        Some(schema) => schema.check(item, &child, errors),
+                        None if matches!(self.additional, Additional::Forbidden) => errors.push(FieldError {
+                            field: child,
+                            code: "additionalProperties".to_string(),
+                            message: "Field is not allowed".to_string(),
+                        }),
+                        None => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_string(&self, text: &str, mut fail: impl FnMut(&str, String)) {
+        let length = text.chars().count();
+        if let Some(min) = self.min_length.filter(|min| length < *min) {
+            fail("minLength", format!("Must be at least {} characters", min));
+        }
+        if let Some(max) = self.max_length.filter(|max| length > *max) {
+            fail("maxLength", format!("Must be at most {} characters", max));
+        }
+        match self.format {
+            Some(Format::Rule(rule)) => {
+                if let Err(issue) = validate_rule(rule, text) {
+                    fail(rule.as_str(), issue.message.to_string());
+                }
+            }
+            Some(Format::Uuid) if !is_uuid(text) => fail("uuid", "Must be a UUID".to_string()),
+            _ => {}
+        }
+    }
+
+    fn check_number(&self, n: f64, mut fail: impl FnMut(&str, String)) {
+        if let Some(min) = self.minimum.filter(|min| n < *min) {
+            fail("minimum", format!("Must be at least {}", min));
+        }
+        if let Some(max) = self.maximum.filter(|max| n > *max) {
+            fail("maximum", format!("Must be at most {}", max));
+        }
+        if let Some(min) = self.exclusive_minimum.filter(|min| n <= *min) {
+            fail("exclusiveMinimum", format!("Must be greater than {}", min));
+        }
+        if let Some(max) = self.exclusive_maximum.filter(|max| n >= *max) {
+            fail("exclusiveMaximum", format!("Must be less than {}", max));
+        }
+    }
+
+    fn check_array(&self, items: &[Value], mut fail: impl FnMut(&str, String)) {
+        if let Some(min) = self.min_items.filter(|min| items.len() < *min) {
+            fail("minItems", format!("Must have at least {} items", min));
+        }
+        if let Some(max) = self.max_items.filter(|max| items.len() > *max) {
+            fail("maxItems", format!("Must have at most {} items", max));
+        }
+        if self.unique_items && items.iter().enumerate().any(|(i, item)| items[..i].contains(item)) {
+            fail("uniqueItems", "Items must be unique".to_string());
+        }
+    }
+
+    fn check_object(&self, fields: &Map<String, Value>, mut fail: impl FnMut(&str, String)) {
+        for name in self.required.iter().filter(|name| !fields.contains_key(*name)) {
+            fail("required", format!("Field '{}' is required", name));
+        }
+        if let Some(min) = self.min_properties.filter(|min| fields.len() < *min) {
+            fail("minProperties", format!("Must have at least {} fields", min));
+        }
+        if let Some(max) = self.max_properties.filter(|max| fields.len() > *max) {
+            fail("maxProperties", format!("Must have at most {} fields", max));
+        }
+    }
+}
+
+fn compile_at(schema: &Value, at: &str, depth: usize) -> Result<Schema, String> {
+    if depth > MAX_DEPTH {
+        return Err(format!("{}: schema is nested more than {} levels", at, MAX_DEPTH));
+    }
+    let empty = Map::new();
+    let keywords = match schema {
+        Value::Object(keywords) => keywords,
+        // `true` accepts anything; `false` has no use in a request schema
+        Value::Bool(true) => &empty,
+        _ => return Err(format!("{}: schema must be an object", at)),
+    };
+
+    let mut compiled = Schema {
+        types: None,
+        enumeration: None,
+        constant: None,
+        properties: BTreeMap::new(),
+        required: Vec::new(),
+        additional: Additional::Allowed,
+        min_properties: None,
+        max_properties: None,
+        items: None,
+        min_items: None,
+        max_items: None,
+        unique_items: false,
+        min_length: None,
+        max_length: None,
+        minimum: None,
+        maximum: None,
+        exclusive_minimum: None,
+        exclusive_maximum: None,
+        format: None,
+    };
+
+    for (keyword, value) in keywords {
+        let here = format!("{}/{}", at, keyword);
+        let invalid = || format!("{}: invalid value", here);
+        let count = || value.as_u64().map(|n| n as usize).ok_or_else(invalid);
+        let number = || value.as_f64().ok_or_else(invalid);
+
+        match keyword.as_str() {
+            "type" => {
+                let names: Vec<&Value> = match value {
+                    Value::Array(names) => names.iter().collect(),
+                    name => vec![name],
+                };
+                let types = names
+                    .into_iter()
+                    .map(|name| name.as_str().and_then(Type::parse).ok_or_else(|| format!("{}: unknown type {}", here, name)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                compiled.types = Some(types);
+            }
+            "enum" => compiled.enumeration = Some(value.as_array().cloned().ok_or_else(invalid)?),
+            "const" => compiled.constant = Some(value.clone()),
+            "properties" => {
+                for (name, property) in value.as_object().ok_or_else(invalid)? {
+                    let property = compile_at(property, &format!("{}/{}", here, name), depth + 1)?;
+                    compiled.properties.insert(name.clone(), property);
+                }
+            }
+            "required" => {
+                compiled.required = value
+                    .as_array()
+                    .and_then(|names| names.iter().map(|name| name.as_str().map(str::to_string)).collect())
+                    .ok_or_else(invalid)?;
+            }
+            "additionalProperties" => {
+                compiled.additional = match value {
+                    Value::Bool(true) => Additional::Allowed,
+                    Value::Bool(false) => Additional::Forbidden,
+                    schema => Additional::Schema(Box::new(compile_at(schema, &here, depth + 1)?)),
+                };
+            }
+            "minProperties" => compiled.min_properties = Some(count()?),
+            "maxProperties" => compiled.max_properties = Some(count()?),
+            "items" => compiled.items = Some(Box::new(compile_at(value, &here, depth + 1)?)),
+            "minItems" => compiled.min_items = Some(count()?),
+            "maxItems" => compiled.max_items = Some(count()?),
+            "uniqueItems" => compiled.unique_items = value.as_bool().ok_or_else(invalid)?,
+            "minLength" => compiled.min_length = Some(count()?),
+            "maxLength" => compiled.max_length = Some(count()?),
+            "minimum" => compiled.minimum = Some(number()?),
+            "maximum" => compiled.maximum = Some(number()?),
+            "exclusiveMinimum" => compiled.exclusive_minimum = Some(number()?),
+            "exclusiveMaximum" => compiled.exclusive_maximum = Some(number()?),
+            "format" => {
+                let name = value.as_str().ok_or_else(invalid)?;
+                compiled.format = Some(match name {
+                    "uuid" => Format::Uuid,
+                    name => Format::Rule(name.parse().map_err(|_| format!("{}: unsupported format '{}'", here, name))?),
+                });
+            }
+            keyword if ANNOTATIONS.contains(&keyword) => {}
+            keyword => return Err(format!("{}: unsupported keyword '{}'", at, keyword)),
+        }
+    }
+    Ok(compiled)
+}
+
+fn is_uuid(text: &str) -> bool {
+    let groups: Vec<&str> = text.split('-').collect();
+    groups.len() == 5
+        && groups.iter().zip([8, 4, 4, 4, 12]).all(|(group, len)| {
+            group.len() == len && group.bytes().all(|b| b.is_ascii_hexdigit())
+        })
+}
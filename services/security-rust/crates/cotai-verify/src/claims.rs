@@ -0,0 +1,103 @@
+/*!
+Claims Module
+Access token claims and the caller identity derived from them
+*/
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::VerifyError;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub exp: i64,
+    #[serde(rename = "type")]
+    pub token_type: Option<String>,
+    pub role: Option<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    pub tenant_id: Option<String>,
+    #[serde(default)]
+    pub scope: Option<String>,
+    /// Token id, present on tokens the security service issues so they can be revoked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    /// Who the token was delegated to (RFC 8693 `act`), on exchanged tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+}
+
+/// An actor in a delegation chain. `act` is the actor that delegated
+/// before this one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Actor {
+    pub sub: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Box<Actor>>,
+}
+
+impl Actor {
+    /// Subjects of the chain, the current actor first.
+    pub fn chain(&self) -> Vec<String> {
+        let mut chain = vec![self.sub.clone()];
+        let mut next = self.act.as_deref();
+        while let Some(actor) = next {
+            chain.push(actor.sub.clone());
+            next = actor.act.as_deref();
+        }
+        chain
+    }
+}
+
+/// Authenticated caller resolved from a bearer token.
+#[derive(Debug, Clone, Serialize)]
+pub struct Principal {
+    pub subject: String,
+    pub tenant_id: Option<String>,
+    pub roles: Vec<String>,
+    pub scopes: Vec<String>,
+    /// The token's `jti`, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_id: Option<String>,
+    /// Actors acting for `subject`, the current actor first; empty unless
+    /// the token came from a token exchange.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub delegation: Vec<String>,
+}
+
+impl Principal {
+    pub fn has_any_role(&self, roles: &[String]) -> bool {
+        self.roles.iter().any(|r| roles.contains(r))
+    }
+}
+
+impl TryFrom<Claims> for Principal {
+    type Error = VerifyError;
+
+    /// Only access tokens identify a caller; refresh and other token types are rejected.
+    fn try_from(claims: Claims) -> Result<Self, Self::Error> {
+        if claims.token_type.as_deref().unwrap_or("access") != "access" {
+            return Err(VerifyError::InvalidToken("Not an access token".to_string()));
+        }
+
+        let mut roles = claims.roles;
+        if let Some(role) = claims.role {
+            if !roles.contains(&role) {
+                roles.push(role);
+            }
+        }
+
+        let scopes = claims.scope
+            .map(|s| s.split_whitespace().map(String::from).collect())
+            .unwrap_or_default();
+
+        Ok(Principal {
+            subject: claims.sub,
+            tenant_id: claims.tenant_id,
+            roles,
+            scopes,
+            token_id: claims.jti,
+            delegation: claims.act.map(|act| act.chain()).unwrap_or_default(),
+        })
+    }
+}
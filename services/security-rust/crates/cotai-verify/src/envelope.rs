@@ -0,0 +1,121 @@
+/*!
+Envelope Module
+Parsing of the encrypted payloads produced by the security service
+*/
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::VerifyError;
+
+#[cfg(feature = "verify")]
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+#[cfg(feature = "verify")]
+use ring::rand::{SecureRandom, SystemRandom};
+
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// The JSON returned by `/crypto/encrypt` and accepted by `/crypto/decrypt`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    pub encrypted_data: String,
+    pub key_id: String,
+    pub nonce: String,
+    pub context_hash: Option<String>,
+}
+
+/// An envelope with its fields decoded and checked.
+#[derive(Debug, Clone)]
+pub struct DecodedEnvelope {
+    pub key_id: String,
+    pub nonce: [u8; NONCE_LEN],
+    /// Ciphertext with the AES-GCM tag appended.
+    pub ciphertext: Vec<u8>,
+    /// Additional authenticated data: the context hash, if any.
+    pub aad: Vec<u8>,
+}
+
+impl Envelope {
+    pub fn parse(json: &str) -> Result<Self, VerifyError> {
+        serde_json::from_str(json).map_err(|e| VerifyError::MalformedEnvelope(e.to_string()))
+    }
+
+    /// Check the structure without any key: encodings, nonce size and tag presence.
+    pub fn decode(&self) -> Result<DecodedEnvelope, VerifyError> {
+        if self.key_id.is_empty() {
+            return Err(VerifyError::MalformedEnvelope("key_id is empty".to_string()));
+        }
+
+        let nonce: [u8; NONCE_LEN] = base64::decode(&self.nonce)
+            .map_err(|_| VerifyError::MalformedEnvelope("nonce is not base64".to_string()))?
+            .try_into()
+            .map_err(|_| VerifyError::MalformedEnvelope(format!("nonce must be {} bytes", NONCE_LEN)))?;
+
+        let ciphertext = base64::decode(&self.encrypted_data)
+            .map_err(|_| VerifyError::MalformedEnvelope("encrypted_data is not base64".to_string()))?;
+        if ciphertext.len() < TAG_LEN {
+            return Err(VerifyError::MalformedEnvelope("encrypted_data is shorter than the tag".to_string()));
+        }
+
+        if let Some(hash) = &self.context_hash {
+            if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(VerifyError::MalformedEnvelope("context_hash must be a SHA-256 hex digest".to_string()));
+            }
+        }
+
+        Ok(DecodedEnvelope {
+            key_id: self.key_id.clone(),
+            nonce,
+            ciphertext,
+            aad: self.context_hash.clone().unwrap_or_default().into_bytes(),
+        })
+    }
+}
+
+#[cfg(feature = "verify")]
+fn aead_key(data_key: &[u8]) -> Result<LessSafeKey, VerifyError> {
+    UnboundKey::new(&AES_256_GCM, data_key)
+        .map(LessSafeKey::new)
+        .map_err(|_| VerifyError::Crypto("Data key must be 32 bytes".to_string()))
+}
+
+/// Envelope encryption with a caller-held data key, producing exactly what
+/// `/crypto/encrypt` would for the same key.
+#[cfg(feature = "verify")]
+impl Envelope {
+    pub fn seal(
+        data_key: &[u8],
+        key_id: &str,
+        plaintext: &[u8],
+        context_hash: Option<&str>,
+    ) -> Result<Self, VerifyError> {
+        let key = aead_key(data_key)?;
+
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| VerifyError::Crypto("Failed to generate nonce".to_string()))?;
+
+        let mut sealed = plaintext.to_vec();
+        let aad = context_hash.unwrap_or_default().as_bytes();
+        key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(aad), &mut sealed)
+            .map_err(|_| VerifyError::Crypto("Encryption failed".to_string()))?;
+
+        Ok(Envelope {
+            encrypted_data: base64::encode(&sealed),
+            key_id: key_id.to_string(),
+            nonce: base64::encode(nonce),
+            context_hash: context_hash.map(str::to_string),
+        })
+    }
+
+    pub fn open(&self, data_key: &[u8]) -> Result<Vec<u8>, VerifyError> {
+        let key = aead_key(data_key)?;
+        let DecodedEnvelope { nonce, mut ciphertext, aad, .. } = self.decode()?;
+
+        let plaintext = key
+            .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::from(aad), &mut ciphertext)
+            .map_err(|_| VerifyError::Crypto("Decryption failed".to_string()))?;
+        Ok(plaintext.to_vec())
+    }
+}
@@ -0,0 +1,46 @@
+/*!
+Error Types
+Verification failures shared by all modules
+*/
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum VerifyError {
+    #[error("Invalid token: {0}")]
+    InvalidToken(String),
+
+    #[error("Unknown signing key: {0}")]
+    UnknownKey(String),
+
+    #[error("Key set unavailable: {0}")]
+    KeySetUnavailable(String),
+
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+
+    #[error("Malformed envelope: {0}")]
+    MalformedEnvelope(String),
+
+    #[error("Crypto error: {0}")]
+    Crypto(String),
+
+    #[error("Configuration error: {0}")]
+    Config(String),
+}
+
+impl VerifyError {
+    /// A stable name for the kind of failure, as used by the conformance
+    /// suite.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            VerifyError::InvalidToken(_) => "invalid_token",
+            VerifyError::UnknownKey(_) => "unknown_key",
+            VerifyError::KeySetUnavailable(_) => "key_set_unavailable",
+            VerifyError::InvalidSignature(_) => "invalid_signature",
+            VerifyError::MalformedEnvelope(_) => "malformed_envelope",
+            VerifyError::Crypto(_) => "crypto",
+            VerifyError::Config(_) => "config",
+        }
+    }
+}
@@ -0,0 +1,105 @@
+/*!
+JWKS Module
+Cached JSON Web Key Set for verifying asymmetrically signed tokens
+*/
+
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::DecodingKey;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use crate::error::VerifyError;
+
+struct State {
+    keys: HashMap<String, DecodingKey>,
+    fetched_at: Option<Instant>,
+}
+
+/// Keys by `kid`. Lookups never block on the network; callers refresh
+/// when `is_stale` says so or when a token names an unknown key.
+pub struct JwksCache {
+    url: Option<String>,
+    ttl: Duration,
+    /// Floor between fetches, so tokens with made-up key ids cannot drive traffic to the issuer.
+    min_refresh: Duration,
+    state: RwLock<State>,
+}
+
+impl JwksCache {
+    pub fn new(url: impl Into<String>, ttl: Duration) -> Self {
+        Self {
+            url: Some(url.into()),
+            ttl,
+            min_refresh: Duration::from_secs(10),
+            state: RwLock::new(State { keys: HashMap::new(), fetched_at: None }),
+        }
+    }
+
+    /// A fixed key set, for deployments that distribute the JWKS themselves.
+    pub fn from_json(json: &str) -> Result<Self, VerifyError> {
+        let cache = Self {
+            url: None,
+            ttl: Duration::MAX,
+            min_refresh: Duration::MAX,
+            state: RwLock::new(State { keys: HashMap::new(), fetched_at: None }),
+        };
+        cache.load_json(json)?;
+        Ok(cache)
+    }
+
+    /// Replace the cached keys with the given JWKS document. Keys without a `kid`
+    /// cannot be selected by a token header and are skipped.
+    pub fn load_json(&self, json: &str) -> Result<usize, VerifyError> {
+        let set: JwkSet = serde_json::from_str(json)
+            .map_err(|e| VerifyError::KeySetUnavailable(format!("Invalid JWKS: {}", e)))?;
+
+        let keys: HashMap<String, DecodingKey> = set.keys
+            .iter()
+            .filter_map(|jwk| {
+                let kid = jwk.common.key_id.clone()?;
+                DecodingKey::from_jwk(jwk).ok().map(|key| (kid, key))
+            })
+            .collect();
+
+        let count = keys.len();
+        let mut state = self.state.write().unwrap();
+        state.keys = keys;
+        state.fetched_at = Some(Instant::now());
+        Ok(count)
+    }
+
+    pub fn key(&self, kid: &str) -> Option<DecodingKey> {
+        self.state.read().unwrap().keys.get(kid).cloned()
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.url.is_some() && self.age().is_none_or(|age| age >= self.ttl)
+    }
+
+    /// Whether a refresh is allowed now; always true before the first fetch.
+    pub fn may_refresh(&self) -> bool {
+        self.url.is_some() && self.age().is_none_or(|age| age >= self.min_refresh)
+    }
+
+    fn age(&self) -> Option<Duration> {
+        self.state.read().unwrap().fetched_at.map(|at| at.elapsed())
+    }
+
+    #[cfg(feature = "fetch")]
+    pub async fn refresh(&self, client: &reqwest::Client) -> Result<usize, VerifyError> {
+        let url = self.url.as_deref()
+            .ok_or_else(|| VerifyError::Config("This key set has no URL to fetch from".to_string()))?;
+
+        let body = client.get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| VerifyError::KeySetUnavailable(e.to_string()))?
+            .text()
+            .await
+            .map_err(|e| VerifyError::KeySetUnavailable(e.to_string()))?;
+
+        self.load_json(&body)
+    }
+}
@@ -0,0 +1,31 @@
+/*!
+COTAI Verify
+Stateless verification of COTAI access tokens, crypto envelopes, signatures
+and webhook deliveries
+
+Gateways embed this crate to verify requests locally and only call the
+security service for issuance and revocation checks. Nothing here keeps
+state besides an optional JWKS cache, so any replica can answer any request.
+*/
+
+pub mod claims;
+pub mod envelope;
+pub mod error;
+#[cfg(feature = "verify")]
+pub mod jwks;
+#[cfg(feature = "verify")]
+pub mod signature;
+#[cfg(feature = "verify")]
+pub mod token;
+#[cfg(feature = "verify")]
+pub mod webhook;
+
+pub use claims::{Actor, Claims, Principal};
+pub use envelope::Envelope;
+pub use error::VerifyError;
+#[cfg(feature = "verify")]
+pub use jwks::JwksCache;
+#[cfg(feature = "verify")]
+pub use token::{KeySource, TokenVerifier};
+#[cfg(feature = "verify")]
+pub use webhook::{verify_delivery, verify_delivery_at};
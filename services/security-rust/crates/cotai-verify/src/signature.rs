@@ -0,0 +1,45 @@
+/*!
+Signature Module
+Verification of the timestamped HMAC signatures issued by `/crypto/sign`
+*/
+
+use chrono::{DateTime, Duration, Utc};
+use ring::hmac;
+
+/// How old a signature may be before it is refused, matching the service.
+pub const DEFAULT_MAX_AGE_SECS: i64 = 3600;
+
+/// Check an HMAC-SHA256 signature over `data` followed by the RFC 3339 timestamp.
+/// The comparison is constant-time. Pass `Duration::seconds(DEFAULT_MAX_AGE_SECS)`
+/// unless the signer agreed on something else.
+pub fn verify(
+    key: &[u8],
+    data: &str,
+    signature_hex: &str,
+    timestamp: DateTime<Utc>,
+    max_age: Duration,
+) -> bool {
+    verify_at(key, data, signature_hex, timestamp, max_age, Utc::now())
+}
+
+/// Like `verify`, but judges age against `now` instead of the system clock.
+pub fn verify_at(
+    key: &[u8],
+    data: &str,
+    signature_hex: &str,
+    timestamp: DateTime<Utc>,
+    max_age: Duration,
+    now: DateTime<Utc>,
+) -> bool {
+    if now.signed_duration_since(timestamp) > max_age {
+        return false;
+    }
+    let Ok(signature) = hex::decode(signature_hex) else {
+        return false;
+    };
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    let mut message = data.as_bytes().to_vec();
+    message.extend_from_slice(timestamp.to_rfc3339().as_bytes());
+    hmac::verify(&key, &message, &signature).is_ok()
+}
@@ -0,0 +1,119 @@
+/*!
+Token Module
+Access token verification against a shared secret or a JWKS
+*/
+
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use std::sync::Arc;
+
+use crate::claims::{Claims, Principal};
+use crate::error::VerifyError;
+use crate::jwks::JwksCache;
+
+pub enum KeySource {
+    /// HMAC secret shared with the issuer (HS256/384/512).
+    Secret(DecodingKey),
+    /// Asymmetric keys selected by the token's `kid`.
+    Jwks(Arc<JwksCache>),
+}
+
+pub struct TokenVerifier {
+    source: KeySource,
+    validation: Validation,
+}
+
+impl TokenVerifier {
+    pub fn with_secret(secret: &[u8], algorithm: &str) -> Result<Self, VerifyError> {
+        let algorithm: Algorithm = algorithm.parse()
+            .map_err(|_| VerifyError::Config("Unsupported JWT algorithm".to_string()))?;
+
+        Ok(Self {
+            source: KeySource::Secret(DecodingKey::from_secret(secret)),
+            validation: Validation::new(algorithm),
+        })
+    }
+
+    /// `algorithms` must be asymmetric; a JWKS never vouches for HMAC tokens.
+    pub fn with_jwks(jwks: Arc<JwksCache>, algorithms: &[Algorithm]) -> Result<Self, VerifyError> {
+        let first = *algorithms.first()
+            .ok_or_else(|| VerifyError::Config("At least one algorithm is required".to_string()))?;
+        if algorithms.iter().any(|a| matches!(a, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512)) {
+            return Err(VerifyError::Config("HMAC algorithms cannot be used with a JWKS".to_string()));
+        }
+
+        let mut validation = Validation::new(first);
+        validation.algorithms = algorithms.to_vec();
+        Ok(Self {
+            source: KeySource::Jwks(jwks),
+            validation,
+        })
+    }
+
+    /// Require the given issuer and audience, as the service does behind a shared gateway.
+    pub fn expect(mut self, issuer: Option<&str>, audience: Option<&str>) -> Self {
+        if let Some(issuer) = issuer {
+            self.validation.set_issuer(&[issuer]);
+        }
+        if let Some(audience) = audience {
+            self.validation.set_audience(&[audience]);
+        }
+        self
+    }
+
+    /// Accept tokens meant for any audience, for issuers reporting on
+    /// tokens they minted for other services.
+    pub fn any_audience(mut self) -> Self {
+        self.validation.validate_aud = false;
+        self
+    }
+
+    /// Verify with the keys at hand. Never touches the network.
+    pub fn verify(&self, token: &str) -> Result<Principal, VerifyError> {
+        let data = decode::<Claims>(token, &self.key_for(token)?, &self.validation)
+            .map_err(|e| VerifyError::InvalidToken(e.to_string()))?;
+        Principal::try_from(data.claims)
+    }
+
+    /// Like `verify`, but judges expiry against `now` (Unix seconds) instead
+    /// of the system clock, for callers with an injected time source.
+    pub fn verify_at(&self, token: &str, now: i64) -> Result<Principal, VerifyError> {
+        let mut validation = self.validation.clone();
+        validation.validate_exp = false;
+
+        let data = decode::<Claims>(token, &self.key_for(token)?, &validation)
+            .map_err(|e| VerifyError::InvalidToken(e.to_string()))?;
+        if data.claims.exp.saturating_add(validation.leeway as i64) < now {
+            return Err(VerifyError::InvalidToken("ExpiredSignature".to_string()));
+        }
+        Principal::try_from(data.claims)
+    }
+
+    fn key_for(&self, token: &str) -> Result<DecodingKey, VerifyError> {
+        match &self.source {
+            KeySource::Secret(key) => Ok(key.clone()),
+            KeySource::Jwks(jwks) => {
+                let kid = key_id(token)?;
+                jwks.key(&kid).ok_or(VerifyError::UnknownKey(kid))
+            }
+        }
+    }
+
+    /// Like `verify`, but refreshes a stale JWKS or one missing the token's key first.
+    #[cfg(feature = "fetch")]
+    pub async fn verify_fetching(&self, token: &str, client: &reqwest::Client) -> Result<Principal, VerifyError> {
+        if let KeySource::Jwks(jwks) = &self.source {
+            let kid = key_id(token)?;
+            if (jwks.is_stale() || jwks.key(&kid).is_none()) && jwks.may_refresh() {
+                jwks.refresh(client).await?;
+            }
+        }
+        self.verify(token)
+    }
+}
+
+fn key_id(token: &str) -> Result<String, VerifyError> {
+    decode_header(token)
+        .map_err(|e| VerifyError::InvalidToken(e.to_string()))?
+        .kid
+        .ok_or_else(|| VerifyError::InvalidToken("Token header has no kid".to_string()))
+}
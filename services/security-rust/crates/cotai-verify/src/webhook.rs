@@ -0,0 +1,110 @@
+/*!
+Webhook Module
+Verification of webhook deliveries signed by the security service
+
+SOAR pushes, mailbox notifications and payloads signed through
+`/crypto/webhook/sign` carry
+
+```text
+X-Cotai-Signature: t=1718000000,v1=5257a869...
+```
+
+where each `v1` is the hex HMAC-SHA256 of `{t}.{body}` under the shared
+secret, used as its UTF-8 bytes as given, never decoded. Verify the body
+exactly as received, before parsing it: any re-serialization changes the
+bytes signed.
+*/
+
+use chrono::Utc;
+use ring::hmac;
+
+use crate::error::VerifyError;
+
+pub const SIGNATURE_HEADER: &str = "x-cotai-signature";
+
+/// Id of a SOAR delivery, the same on every attempt and replay.
+pub const DELIVERY_HEADER: &str = "x-cotai-delivery";
+
+/// Set on deliveries resent by hand from the admin API.
+pub const REPLAY_HEADER: &str = "x-cotai-replay";
+
+/// How far a delivery's timestamp may be from now, matching the service.
+pub const DEFAULT_TOLERANCE_SECS: i64 = 300;
+
+/// A delivery whose signature checked out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    /// The signature's timestamp, in Unix seconds.
+    pub timestamp: i64,
+    pub id: Option<String>,
+    pub replay: bool,
+}
+
+/// Check a delivery given its headers, as (name, value) pairs in any case,
+/// and its raw body against any of `secrets`, so a secret can be rotated
+/// without dropping deliveries.
+pub fn verify_delivery<'a, S: AsRef<str>>(
+    secrets: &[S],
+    headers: impl IntoIterator<Item = (&'a str, &'a str)>,
+    body: &[u8],
+    tolerance_secs: i64,
+) -> Result<Delivery, VerifyError> {
+    verify_delivery_at(secrets, headers, body, tolerance_secs, Utc::now().timestamp())
+}
+
+/// Like `verify_delivery`, but judges the timestamp against `now` (Unix
+/// seconds) instead of the system clock.
+pub fn verify_delivery_at<'a, S: AsRef<str>>(
+    secrets: &[S],
+    headers: impl IntoIterator<Item = (&'a str, &'a str)>,
+    body: &[u8],
+    tolerance_secs: i64,
+    now: i64,
+) -> Result<Delivery, VerifyError> {
+    let mut signature_header = None;
+    let mut id = None;
+    let mut replay = false;
+    for (name, value) in headers {
+        if name.eq_ignore_ascii_case(SIGNATURE_HEADER) {
+            signature_header = Some(value);
+        } else if name.eq_ignore_ascii_case(DELIVERY_HEADER) {
+            id = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case(REPLAY_HEADER) {
+            replay = value.trim() == "1";
+        }
+    }
+    let header = signature_header
+        .ok_or_else(|| VerifyError::InvalidSignature(format!("missing {} header", SIGNATURE_HEADER)))?;
+
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.trim().parse::<i64>().ok(),
+            Some(("v1", value)) => signatures.extend(hex::decode(value.trim()).ok()),
+            _ => {}
+        }
+    }
+    let timestamp = timestamp.ok_or_else(|| VerifyError::InvalidSignature("no t= timestamp".to_string()))?;
+    if signatures.is_empty() {
+        return Err(VerifyError::InvalidSignature("no v1= signature".to_string()));
+    }
+    let age = now - timestamp;
+    if age.abs() > tolerance_secs {
+        return Err(VerifyError::InvalidSignature(format!(
+            "timestamped {}s from now, over the {}s tolerance",
+            age, tolerance_secs
+        )));
+    }
+
+    let mut message = format!("{}.", timestamp).into_bytes();
+    message.extend_from_slice(body);
+    let matched = secrets.iter().any(|secret| {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_ref().as_bytes());
+        signatures.iter().any(|signature| hmac::verify(&key, &message, signature).is_ok())
+    });
+    if !matched {
+        return Err(VerifyError::InvalidSignature("no v1 signature matches".to_string()));
+    }
+    Ok(Delivery { timestamp, id, replay })
+}
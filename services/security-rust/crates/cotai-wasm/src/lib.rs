@@ -0,0 +1,62 @@
+/*!
+COTAI WASM
+Validation rules and envelope parsing for the API gateway and the browser
+
+Build with `wasm-pack build crates/cotai-wasm --target nodejs` for the
+gateway or `--target web` for the frontend. Both call the same Rust code
+the security service runs, so their answers cannot drift.
+*/
+
+use cotai_validation::Rule;
+use cotai_verify::Envelope;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Outcome {
+    valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ParsedEnvelope {
+    key_id: String,
+    ciphertext_len: usize,
+    has_context: bool,
+}
+
+/// Names of the rules `validate` accepts.
+#[wasm_bindgen]
+pub fn rules() -> Vec<JsValue> {
+    cotai_validation::RULES.iter().map(|rule| JsValue::from_str(rule.as_str())).collect()
+}
+
+/// `{ valid, message? }` for `value` under the named rule; throws on an unknown rule.
+#[wasm_bindgen]
+pub fn validate(rule: &str, value: &str) -> Result<JsValue, JsError> {
+    let rule: Rule = rule.parse().map_err(|e: String| JsError::new(&e))?;
+    let outcome = match cotai_validation::validate(rule, value) {
+        Ok(()) => Outcome { valid: true, message: None },
+        Err(issue) => Outcome { valid: false, message: Some(issue.message) },
+    };
+    serde_wasm_bindgen::to_value(&outcome).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Structurally check an encryption envelope without any key material;
+/// throws with the reason when it is malformed.
+#[wasm_bindgen(js_name = parseEnvelope)]
+pub fn parse_envelope(json: &str) -> Result<JsValue, JsError> {
+    let decoded = Envelope::parse(json)
+        .and_then(|envelope| envelope.decode())
+        .map_err(|e| JsError::new(&e.to_string()))?;
+
+    let parsed = ParsedEnvelope {
+        key_id: decoded.key_id,
+        ciphertext_len: decoded.ciphertext.len(),
+        has_context: !decoded.aad.is_empty(),
+    };
+    serde_wasm_bindgen::to_value(&parsed).map_err(|e| JsError::new(&e.to_string()))
+}
@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS audit_saved_searches (
+    id UUID PRIMARY KEY,
+    owner TEXT NOT NULL,
+    owner_tenant_id TEXT,
+    owner_roles TEXT[] NOT NULL DEFAULT '{}',
+    name TEXT NOT NULL,
+    query JSONB NOT NULL,
+    schedule TEXT,
+    sinks TEXT[] NOT NULL DEFAULT '{}',
+    shared_with TEXT[] NOT NULL DEFAULT '{}',
+    shared_with_tenant BOOLEAN NOT NULL DEFAULT FALSE,
+    next_run_at TIMESTAMPTZ,
+    last_run_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (owner, name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_saved_searches_due ON audit_saved_searches (next_run_at)
+    WHERE schedule IS NOT NULL;
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS dead_letters (
+    id UUID PRIMARY KEY,
+    subsystem TEXT NOT NULL,
+    target TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    failure_reason TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 1,
+    status TEXT NOT NULL DEFAULT 'pending',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_dead_letters_pending ON dead_letters (subsystem, created_at)
+    WHERE status = 'pending';
@@ -0,0 +1,6 @@
+ALTER TABLE audit_events ADD COLUMN IF NOT EXISTS source TEXT NOT NULL DEFAULT 'live';
+ALTER TABLE audit_events ADD COLUMN IF NOT EXISTS legacy_id TEXT;
+ALTER TABLE audit_events ADD COLUMN IF NOT EXISTS imported_at TIMESTAMPTZ;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_audit_events_legacy_id ON audit_events (legacy_id)
+    WHERE legacy_id IS NOT NULL;
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS audit_exports (
+    id UUID PRIMARY KEY,
+    requested_by TEXT NOT NULL,
+    view TEXT NOT NULL,
+    format TEXT NOT NULL,
+    query JSONB NOT NULL,
+    status TEXT NOT NULL,
+    object_key TEXT,
+    row_count BIGINT,
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_exports_requested_by ON audit_exports (requested_by, created_at DESC);
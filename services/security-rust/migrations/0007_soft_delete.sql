@@ -0,0 +1,9 @@
+ALTER TABLE audit_saved_searches ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+
+-- Names only need to be unique among live searches; deleted ones may be restored later.
+ALTER TABLE audit_saved_searches DROP CONSTRAINT IF EXISTS audit_saved_searches_owner_name_key;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_audit_saved_searches_owner_name ON audit_saved_searches (owner, name)
+    WHERE deleted_at IS NULL;
+
+CREATE INDEX IF NOT EXISTS idx_audit_saved_searches_deleted ON audit_saved_searches (deleted_at)
+    WHERE deleted_at IS NOT NULL;
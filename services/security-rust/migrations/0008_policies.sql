@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS policies (
+    id UUID PRIMARY KEY,
+    tenant_id TEXT,
+    name TEXT NOT NULL,
+    description TEXT,
+    document JSONB NOT NULL,
+    version BIGINT NOT NULL DEFAULT 1,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_by TEXT NOT NULL,
+    deleted_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_policies_tenant_name ON policies (COALESCE(tenant_id, ''), name)
+    WHERE deleted_at IS NULL;
+
+CREATE INDEX IF NOT EXISTS idx_policies_deleted ON policies (deleted_at)
+    WHERE deleted_at IS NOT NULL;
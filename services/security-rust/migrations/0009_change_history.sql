@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS change_history (
+    id UUID PRIMARY KEY,
+    resource_type TEXT NOT NULL,
+    resource_id TEXT NOT NULL,
+    version BIGINT,
+    action TEXT NOT NULL,
+    author TEXT NOT NULL,
+    tenant_id TEXT,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    before JSONB,
+    after JSONB,
+    diff JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_change_history_resource ON change_history (resource_type, resource_id, changed_at DESC);
+CREATE INDEX IF NOT EXISTS idx_change_history_changed_at ON change_history (changed_at DESC);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS experiments (
+    key TEXT PRIMARY KEY,
+    description TEXT,
+    status TEXT NOT NULL DEFAULT 'draft',
+    variants JSONB NOT NULL,
+    salt TEXT NOT NULL,
+    version BIGINT NOT NULL DEFAULT 1,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_by TEXT NOT NULL
+);
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS maintenance_windows (
+    id UUID PRIMARY KEY,
+    reason TEXT NOT NULL,
+    starts_at TIMESTAMPTZ NOT NULL,
+    ends_at TIMESTAMPTZ NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    cancelled_at TIMESTAMPTZ,
+    cancelled_by TEXT,
+    CHECK (ends_at > starts_at)
+);
+
+CREATE INDEX IF NOT EXISTS idx_maintenance_windows_pending
+    ON maintenance_windows (ends_at) WHERE cancelled_at IS NULL;
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS tenant_settings (
+    tenant_id TEXT PRIMARY KEY,
+    overrides JSONB NOT NULL DEFAULT '{}',
+    version BIGINT NOT NULL DEFAULT 1,
+    updated_by TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,39 @@
+CREATE TABLE IF NOT EXISTS correlation_rules (
+    name TEXT PRIMARY KEY,
+    definition JSONB NOT NULL,
+    version BIGINT NOT NULL DEFAULT 1,
+    updated_by TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Single row: how far the engine has read the audit stream, and the
+-- windowed per-entity state it carries between batches.
+CREATE TABLE IF NOT EXISTS correlation_cursor (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    processed_at TIMESTAMPTZ NOT NULL,
+    processed_id UUID NOT NULL,
+    state JSONB NOT NULL DEFAULT '{}',
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Start from now rather than replaying the whole history
+INSERT INTO correlation_cursor (processed_at, processed_id)
+VALUES (NOW(), '00000000-0000-0000-0000-000000000000')
+ON CONFLICT (id) DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS security_incidents (
+    id UUID PRIMARY KEY,
+    rule TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    tenant_id TEXT,
+    entity_type TEXT NOT NULL,
+    entity TEXT NOT NULL,
+    event_ids UUID[] NOT NULL,
+    first_seen TIMESTAMPTZ NOT NULL,
+    last_seen TIMESTAMPTZ NOT NULL,
+    status TEXT NOT NULL DEFAULT 'open',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_security_incidents_created ON security_incidents (created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_security_incidents_entity ON security_incidents (entity_type, entity);
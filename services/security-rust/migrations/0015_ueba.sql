@@ -0,0 +1,30 @@
+-- One profile per actor, relearned daily from the audit history before
+-- `learned_for`.
+CREATE TABLE IF NOT EXISTS user_baselines (
+    actor TEXT PRIMARY KEY,
+    profile JSONB NOT NULL,
+    learned_for DATE NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Latest score per actor per UTC day; the rows over time are the trend.
+CREATE TABLE IF NOT EXISTS user_risk_scores (
+    actor TEXT NOT NULL,
+    day DATE NOT NULL,
+    score DOUBLE PRECISION NOT NULL,
+    factors JSONB NOT NULL,
+    events BIGINT NOT NULL,
+    scored_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (actor, day)
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_risk_scores_day ON user_risk_scores (day, score DESC);
+
+-- Single row locked by whichever replica runs the scoring pass.
+CREATE TABLE IF NOT EXISTS ueba_state (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    learned_for DATE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO ueba_state (learned_for) VALUES (NULL) ON CONFLICT (id) DO NOTHING;
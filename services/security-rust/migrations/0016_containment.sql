@@ -0,0 +1,30 @@
+CREATE TABLE IF NOT EXISTS playbooks (
+    name TEXT PRIMARY KEY,
+    definition JSONB NOT NULL,
+    version BIGINT NOT NULL DEFAULT 1,
+    updated_by TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- One row per action a playbook took, or proposes while awaiting approval.
+CREATE TABLE IF NOT EXISTS containment_actions (
+    id UUID PRIMARY KEY,
+    playbook TEXT NOT NULL,
+    incident_id UUID NOT NULL REFERENCES security_incidents (id),
+    action TEXT NOT NULL,
+    target_type TEXT NOT NULL,
+    target TEXT NOT NULL,
+    tenant_id TEXT,
+    status TEXT NOT NULL,
+    duration_secs BIGINT NOT NULL,
+    expires_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    decided_by TEXT,
+    decided_at TIMESTAMPTZ,
+    rolled_back_by TEXT,
+    rolled_back_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_containment_actions_created ON containment_actions (created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_containment_actions_open ON containment_actions (action, target_type, target)
+    WHERE status IN ('pending_approval', 'active');
@@ -0,0 +1,30 @@
+ALTER TABLE security_incidents
+    ADD COLUMN IF NOT EXISTS enrichment JSONB NOT NULL DEFAULT '{}',
+    ADD COLUMN IF NOT EXISTS external_refs JSONB NOT NULL DEFAULT '{}',
+    ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+-- How incidents and callbacks translate to and from each SOAR's schema.
+CREATE TABLE IF NOT EXISTS soar_mappings (
+    integration TEXT PRIMARY KEY,
+    definition JSONB NOT NULL,
+    version BIGINT NOT NULL DEFAULT 1,
+    updated_by TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Outbound queue, written in the transaction that changes the incident.
+CREATE TABLE IF NOT EXISTS soar_deliveries (
+    id UUID PRIMARY KEY,
+    integration TEXT NOT NULL,
+    incident_id UUID NOT NULL REFERENCES security_incidents (id),
+    event TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_soar_deliveries_due ON soar_deliveries (next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_soar_deliveries_created ON soar_deliveries (created_at DESC);
@@ -0,0 +1,9 @@
+-- SHA-256 of each export object as written, re-checked by the integrity
+-- monitor. `integrity` is `unverified`, `ok`, `mismatch` or `missing`.
+ALTER TABLE audit_exports ADD COLUMN IF NOT EXISTS sha256 TEXT;
+ALTER TABLE audit_exports ADD COLUMN IF NOT EXISTS integrity TEXT NOT NULL DEFAULT 'unverified';
+ALTER TABLE audit_exports ADD COLUMN IF NOT EXISTS verified_at TIMESTAMPTZ;
+ALTER TABLE audit_exports ADD COLUMN IF NOT EXISTS integrity_error TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_audit_exports_verified_at ON audit_exports (verified_at NULLS FIRST)
+    WHERE sha256 IS NOT NULL;
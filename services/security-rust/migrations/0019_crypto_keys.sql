@@ -0,0 +1,25 @@
+-- Data keys used by /crypto/encrypt and /crypto/decrypt, so ciphertexts
+-- stay decryptable across restarts and deploys.
+--
+--   key_id      id handed out with every ciphertext
+--   algorithm   always AES-256-GCM for now
+--   nonce       base64 96-bit nonce used to seal the key
+--   sealed_key  base64 key material sealed under the master key with
+--               AES-256-GCM, key_id bound as AAD; never stored in the clear
+--   source      `generated` by rotation, or `imported` from the local key
+--               cache for keys created before this table existed
+--   created_at  when the key was generated; the newest key encrypts
+--
+-- Keys created before this table existed survive only if the local key
+-- cache (CRYPTO_KEY_CACHE_DIR) still holds them. They are imported on the
+-- next start with their original ids. Keys that were only ever in memory
+-- cannot be recovered.
+CREATE TABLE IF NOT EXISTS crypto_keys (
+    key_id TEXT PRIMARY KEY,
+    algorithm TEXT NOT NULL DEFAULT 'AES-256-GCM',
+    nonce TEXT NOT NULL,
+    sealed_key TEXT NOT NULL,
+    source TEXT NOT NULL DEFAULT 'generated',
+    created_at TIMESTAMPTZ NOT NULL,
+    stored_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
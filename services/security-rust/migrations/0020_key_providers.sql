@@ -0,0 +1,10 @@
+-- Data keys are wrapped by a pluggable provider (CRYPTO_KEY_PROVIDER).
+-- `wrapped_key` is that provider's opaque output and `provider` names it,
+-- so a key is always unwrapped by the backend that wrapped it.
+ALTER TABLE crypto_keys ADD COLUMN provider TEXT NOT NULL DEFAULT 'local';
+
+-- Keys sealed under the master key kept their nonce apart; the local
+-- provider's format is `<nonce>:<sealed key>`
+UPDATE crypto_keys SET sealed_key = nonce || ':' || sealed_key;
+ALTER TABLE crypto_keys DROP COLUMN nonce;
+ALTER TABLE crypto_keys RENAME COLUMN sealed_key TO wrapped_key;
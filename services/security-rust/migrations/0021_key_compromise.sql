@@ -0,0 +1,32 @@
+-- `active` keys encrypt and decrypt, `compromised` keys only decrypt so
+-- their ciphertexts can be re-encrypted, `retired` keys do neither.
+ALTER TABLE crypto_keys ADD COLUMN state TEXT NOT NULL DEFAULT 'active';
+ALTER TABLE crypto_keys ADD COLUMN state_changed_at TIMESTAMPTZ;
+
+-- Operations per key per UTC day, the inventory behind a compromise report.
+CREATE TABLE IF NOT EXISTS crypto_key_usage (
+    key_id TEXT NOT NULL,
+    day DATE NOT NULL,
+    operation TEXT NOT NULL,
+    count BIGINT NOT NULL,
+    first_at TIMESTAMPTZ NOT NULL,
+    last_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (key_id, day, operation)
+);
+
+-- One re-encryption job per compromised key, tracked by an incident.
+CREATE TABLE IF NOT EXISTS key_compromises (
+    id UUID PRIMARY KEY,
+    key_id TEXT NOT NULL UNIQUE REFERENCES crypto_keys (key_id),
+    reason TEXT NOT NULL,
+    reported_by TEXT NOT NULL,
+    incident_id UUID REFERENCES security_incidents (id),
+    replacement_key_id TEXT,
+    status TEXT NOT NULL DEFAULT 'reencrypting',
+    rewrapped BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_by TEXT,
+    completed_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_key_compromises_created_at ON key_compromises (created_at DESC);
@@ -0,0 +1,7 @@
+-- Keys superseded by a rotation are `decrypt_only`. Only the newest active
+-- key stays active; the rest predate the state.
+UPDATE crypto_keys SET state = 'decrypt_only', state_changed_at = NOW()
+WHERE state = 'active'
+  AND key_id <> (
+      SELECT key_id FROM crypto_keys WHERE state = 'active' ORDER BY created_at DESC LIMIT 1
+  );
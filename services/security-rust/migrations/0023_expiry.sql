@@ -0,0 +1,29 @@
+-- Artifacts whose expiry is tracked but which live outside this service,
+-- e.g. ingress certificates, SAML IdP certificates and partner webhook
+-- secrets.
+CREATE TABLE IF NOT EXISTS managed_artifacts (
+    id UUID PRIMARY KEY,
+    kind TEXT NOT NULL,
+    name TEXT NOT NULL,
+    tenant_id TEXT,
+    expires_at TIMESTAMPTZ NOT NULL,
+    fingerprint TEXT,
+    notes TEXT,
+    registered_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (kind, name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_managed_artifacts_expires_at ON managed_artifacts (expires_at);
+
+-- Expiry alerts already sent, one per artifact, expiry and lead time, so
+-- replicas do not repeat them and a renewal starts a fresh cycle.
+CREATE TABLE IF NOT EXISTS expiry_alerts (
+    kind TEXT NOT NULL,
+    name TEXT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    lead_days INTEGER NOT NULL,
+    alerted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (kind, name, expires_at, lead_days)
+);
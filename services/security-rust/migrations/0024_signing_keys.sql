@@ -0,0 +1,14 @@
+-- Asymmetric signing keys. The private half is wrapped by the key
+-- provider like data keys are; the public half is published as JWKS.
+CREATE TABLE IF NOT EXISTS signing_keys (
+    key_id TEXT PRIMARY KEY,
+    -- JOSE name: EdDSA (Ed25519) or ES256 (ECDSA P-256)
+    algorithm TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    -- Ed25519 seed or P-256 PKCS#8 document, wrapped
+    wrapped_key TEXT NOT NULL,
+    -- base64url: raw Ed25519 key or uncompressed P-256 point
+    public_key TEXT NOT NULL,
+    state TEXT NOT NULL DEFAULT 'active',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
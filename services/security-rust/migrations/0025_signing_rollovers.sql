@@ -0,0 +1,25 @@
+-- Signing keys move pending -> active -> verify_only -> retired. Pending
+-- and verify_only keys are published but do not sign; retired keys are
+-- unpublished.
+
+-- One rollover per algorithm at a time: publish the new key, switch
+-- signing to it once verifiers have fetched it, retire the old key once
+-- tokens it signed have expired.
+CREATE TABLE IF NOT EXISTS signing_rollovers (
+    id UUID PRIMARY KEY,
+    algorithm TEXT NOT NULL,
+    old_key_id TEXT REFERENCES signing_keys (key_id),
+    new_key_id TEXT NOT NULL REFERENCES signing_keys (key_id),
+    -- publishing, retiring or completed
+    phase TEXT NOT NULL DEFAULT 'publishing',
+    started_by TEXT NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL,
+    activate_at TIMESTAMPTZ NOT NULL,
+    activated_at TIMESTAMPTZ,
+    retire_at TIMESTAMPTZ,
+    completed_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_signing_rollovers_in_progress
+    ON signing_rollovers (algorithm) WHERE phase <> 'completed';
+CREATE INDEX IF NOT EXISTS idx_signing_rollovers_started_at ON signing_rollovers (started_at DESC);
@@ -0,0 +1,57 @@
+-- Non-human callers. They authenticate with a JWT assertion signed by a
+-- registered key (private_key_jwt) and get access tokens limited to their
+-- scopes.
+CREATE TABLE IF NOT EXISTS service_accounts (
+    id UUID PRIMARY KEY,
+    tenant_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    scopes TEXT[] NOT NULL DEFAULT '{}',
+    -- active or disabled
+    status TEXT NOT NULL DEFAULT 'active',
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ,
+    UNIQUE (tenant_id, name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_service_accounts_created_at ON service_accounts (created_at DESC);
+
+-- Public keys only; the private half never reaches this service. Several
+-- active keys per account let clients rotate without downtime.
+CREATE TABLE IF NOT EXISTS service_account_keys (
+    key_id TEXT PRIMARY KEY,
+    account_id UUID NOT NULL REFERENCES service_accounts (id) ON DELETE CASCADE,
+    algorithm TEXT NOT NULL,
+    public_key JSONB NOT NULL,
+    -- active or revoked
+    status TEXT NOT NULL DEFAULT 'active',
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_service_account_keys_account ON service_account_keys (account_id);
+
+-- Assertion ids seen until the assertion expires, so none is replayed.
+CREATE TABLE IF NOT EXISTS service_account_assertions (
+    account_id UUID NOT NULL REFERENCES service_accounts (id) ON DELETE CASCADE,
+    jti TEXT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (account_id, jti)
+);
+
+-- Token requests per account, key, UTC day and outcome.
+CREATE TABLE IF NOT EXISTS service_account_usage (
+    account_id UUID NOT NULL REFERENCES service_accounts (id) ON DELETE CASCADE,
+    day DATE NOT NULL,
+    key_id TEXT NOT NULL,
+    -- issued, or why the request was rejected
+    outcome TEXT NOT NULL,
+    count BIGINT NOT NULL,
+    last_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (account_id, day, key_id, outcome)
+);
@@ -0,0 +1,35 @@
+-- Refresh tokens are opaque and stored hashed. Each use rotates the token
+-- within its family; presenting a used one again revokes the family.
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+    id UUID PRIMARY KEY,
+    family_id UUID NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    subject TEXT NOT NULL,
+    tenant_id TEXT,
+    roles TEXT[] NOT NULL DEFAULT '{}',
+    scope TEXT NOT NULL DEFAULT '',
+    -- The access token issued alongside, revoked with the family
+    access_token_id TEXT NOT NULL,
+    access_expires_at TIMESTAMPTZ NOT NULL,
+    issued_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ,
+    revoked_reason TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_family ON refresh_tokens (family_id);
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_expires_at ON refresh_tokens (expires_at);
+
+-- Access tokens revoked before expiry, by jti. Rows are dropped once the
+-- token would have expired anyway.
+CREATE TABLE IF NOT EXISTS revoked_tokens (
+    jti TEXT PRIMARY KEY,
+    subject TEXT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    reason TEXT NOT NULL,
+    revoked_by TEXT NOT NULL,
+    revoked_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_revoked_tokens_expires_at ON revoked_tokens (expires_at);
@@ -0,0 +1,35 @@
+-- Third-party applications users can authorize, and what each user has
+-- granted them.
+CREATE TABLE IF NOT EXISTS oauth_clients (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT NOT NULL DEFAULT '',
+    redirect_uris TEXT[] NOT NULL DEFAULT '{}',
+    -- Scopes the client may ask users for
+    scopes TEXT[] NOT NULL DEFAULT '{}',
+    status TEXT NOT NULL DEFAULT 'active',
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS consent_grants (
+    id UUID PRIMARY KEY,
+    client_id UUID NOT NULL REFERENCES oauth_clients (id),
+    subject TEXT NOT NULL,
+    tenant_id TEXT,
+    scopes TEXT[] NOT NULL,
+    granted_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ,
+    revoked_by TEXT
+);
+
+-- One live grant per user and client; granting more scopes extends it
+CREATE UNIQUE INDEX IF NOT EXISTS idx_consent_grants_live
+    ON consent_grants (client_id, subject) WHERE revoked_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_consent_grants_subject ON consent_grants (subject, granted_at);
+
+-- Refresh tokens issued to a client stop working when consent is revoked
+ALTER TABLE refresh_tokens ADD COLUMN IF NOT EXISTS client_id UUID;
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_client ON refresh_tokens (client_id, subject) WHERE client_id IS NOT NULL;
@@ -0,0 +1,24 @@
+-- Credentials the service presents to SIEMs, SMS gateways, TSAs and other
+-- outbound integrations, encrypted with the data keys.
+CREATE TABLE IF NOT EXISTS outbound_credentials (
+    name TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    ciphertext TEXT NOT NULL,
+    key_id TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    context_hash TEXT,
+    version INTEGER NOT NULL DEFAULT 1,
+    rotator TEXT,
+    rotation_url TEXT,
+    rotation_interval_secs BIGINT,
+    next_rotation_at TIMESTAMPTZ,
+    rotated_at TIMESTAMPTZ,
+    last_error TEXT,
+    expires_at TIMESTAMPTZ,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_outbound_credentials_rotation
+    ON outbound_credentials (next_rotation_at) WHERE next_rotation_at IS NOT NULL;
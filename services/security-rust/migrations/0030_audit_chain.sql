@@ -0,0 +1,36 @@
+-- Hash chain over audit events. Events are linked in the order they are
+-- chained, which can differ from seq when writers commit out of order.
+ALTER TABLE audit_events ADD COLUMN IF NOT EXISTS seq BIGSERIAL;
+ALTER TABLE audit_events ADD COLUMN IF NOT EXISTS chain_index BIGINT;
+ALTER TABLE audit_events ADD COLUMN IF NOT EXISTS prev_hash TEXT;
+ALTER TABLE audit_events ADD COLUMN IF NOT EXISTS chain_hash TEXT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_audit_events_chain_index
+    ON audit_events (chain_index) WHERE chain_index IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_audit_events_unchained
+    ON audit_events (seq) WHERE chain_index IS NULL;
+
+-- Single row holding the end of the chain; locking it serializes chaining
+-- across replicas
+CREATE TABLE IF NOT EXISTS audit_chain_head (
+    singleton BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (singleton),
+    chain_index BIGINT NOT NULL DEFAULT 0,
+    chain_hash TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO audit_chain_head (singleton, chain_index, chain_hash)
+VALUES (TRUE, 0, repeat('0', 64))
+ON CONFLICT (singleton) DO NOTHING;
+
+-- Signed statements of the chain's head, so a rewritten chain cannot be
+-- passed off as the original
+CREATE TABLE IF NOT EXISTS audit_checkpoints (
+    id UUID PRIMARY KEY,
+    chain_index BIGINT NOT NULL UNIQUE,
+    chain_hash TEXT NOT NULL,
+    algorithm TEXT NOT NULL,
+    key_id TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
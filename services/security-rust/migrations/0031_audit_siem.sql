@@ -0,0 +1,10 @@
+-- How far each SIEM sink has been sent the audit chain. A new sink starts
+-- at the chain head; moving its cursor back replays history.
+CREATE TABLE IF NOT EXISTS audit_siem_cursors (
+    sink TEXT PRIMARY KEY,
+    chain_index BIGINT NOT NULL,
+    failures INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    last_success_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
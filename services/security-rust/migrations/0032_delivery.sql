@@ -0,0 +1,44 @@
+-- Attempts to deliver email, SMS and WhatsApp messages, one row per
+-- provider tried. Recipients and bodies are not kept.
+CREATE TABLE IF NOT EXISTS delivery_messages (
+    id UUID PRIMARY KEY,
+    provider TEXT NOT NULL,
+    channel TEXT NOT NULL,
+    purpose TEXT NOT NULL,
+    reference UUID,
+    tenant_id TEXT,
+    status TEXT NOT NULL,
+    provider_message_id TEXT,
+    cost DOUBLE PRECISION NOT NULL DEFAULT 0,
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_delivery_messages_provider
+    ON delivery_messages (provider, created_at);
+CREATE INDEX IF NOT EXISTS idx_delivery_messages_provider_id
+    ON delivery_messages (provider, provider_message_id) WHERE provider_message_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_delivery_messages_reference
+    ON delivery_messages (reference) WHERE reference IS NOT NULL;
+
+-- One-time code challenges. Only hashes of the code and destination are
+-- kept; message_id is the latest delivery of the code.
+CREATE TABLE IF NOT EXISTS otp_challenges (
+    id UUID PRIMARY KEY,
+    subject TEXT NOT NULL,
+    tenant_id TEXT,
+    channel TEXT NOT NULL,
+    destination_hash TEXT NOT NULL,
+    destination_hint TEXT NOT NULL,
+    code_hash TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    resends INTEGER NOT NULL DEFAULT 0,
+    message_id UUID REFERENCES delivery_messages (id),
+    expires_at TIMESTAMPTZ NOT NULL,
+    verified_at TIMESTAMPTZ,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_otp_challenges_expires ON otp_challenges (expires_at);
@@ -0,0 +1,19 @@
+-- Where retention has cut the hash chain: the last removed event and its
+-- hash, which verification starts from.
+ALTER TABLE audit_chain_head ADD COLUMN IF NOT EXISTS pruned_index BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE audit_chain_head ADD COLUMN IF NOT EXISTS pruned_hash TEXT NOT NULL DEFAULT repeat('0', 64);
+
+-- Chain ranges removed by retention, with the export holding them when
+-- archived
+CREATE TABLE IF NOT EXISTS audit_archives (
+    id UUID PRIMARY KEY,
+    first_index BIGINT NOT NULL,
+    last_index BIGINT NOT NULL,
+    oldest_occurred_at TIMESTAMPTZ NOT NULL,
+    newest_occurred_at TIMESTAMPTZ NOT NULL,
+    row_count BIGINT NOT NULL,
+    export_id UUID REFERENCES audit_exports (id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_archives_range ON audit_archives (first_index);
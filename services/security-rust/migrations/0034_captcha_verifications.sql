@@ -0,0 +1,21 @@
+-- Results of CAPTCHA token checks, kept for a week to answer signal
+-- queries. Tokens are not kept.
+CREATE TABLE IF NOT EXISTS captcha_verifications (
+    id UUID PRIMARY KEY,
+    provider TEXT NOT NULL,
+    success BOOLEAN NOT NULL,
+    score DOUBLE PRECISION,
+    action TEXT,
+    hostname TEXT,
+    error_codes TEXT[] NOT NULL DEFAULT '{}',
+    remote_ip TEXT,
+    subject TEXT,
+    via TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_captcha_verifications_created ON captcha_verifications (created_at);
+CREATE INDEX IF NOT EXISTS idx_captcha_verifications_ip
+    ON captcha_verifications (remote_ip, created_at) WHERE remote_ip IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_captcha_verifications_subject
+    ON captcha_verifications (subject, created_at) WHERE subject IS NOT NULL;
@@ -0,0 +1,42 @@
+-- Notary ledger: an append-only Merkle tree (RFC 6962 hashing) over
+-- registered document hashes
+
+-- Single row holding the ledger's size; locking it serializes appends
+-- across replicas
+CREATE TABLE IF NOT EXISTS notary_head (
+    singleton BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (singleton),
+    tree_size BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO notary_head (singleton, tree_size)
+VALUES (TRUE, 0)
+ON CONFLICT (singleton) DO NOTHING;
+
+-- Roots of complete subtrees: node (level, idx) covers leaves
+-- [idx * 2^level, (idx + 1) * 2^level)
+CREATE TABLE IF NOT EXISTS notary_nodes (
+    level INTEGER NOT NULL,
+    idx BIGINT NOT NULL,
+    hash TEXT NOT NULL,
+    PRIMARY KEY (level, idx)
+);
+
+-- Registered documents, each with the signed tree head it was included in
+CREATE TABLE IF NOT EXISTS notary_entries (
+    id UUID PRIMARY KEY,
+    leaf_index BIGINT NOT NULL UNIQUE,
+    document_sha256 TEXT NOT NULL,
+    label TEXT,
+    tenant_id TEXT,
+    registered_by TEXT NOT NULL,
+    registered_at TIMESTAMPTZ NOT NULL,
+    leaf_hash TEXT NOT NULL,
+    tree_size BIGINT NOT NULL,
+    root_hash TEXT NOT NULL,
+    algorithm TEXT NOT NULL,
+    key_id TEXT NOT NULL,
+    signature TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_notary_entries_document ON notary_entries (document_sha256);
@@ -0,0 +1,35 @@
+-- Digital seal queue: official PDFs waiting for, or carrying, the
+-- institutional PAdES seal
+CREATE TABLE IF NOT EXISTS seal_jobs (
+    id UUID PRIMARY KEY,
+    tenant_id TEXT,
+    -- What the document is to the submitter, e.g. the award id
+    reference TEXT NOT NULL,
+    label TEXT,
+    submitted_by TEXT NOT NULL,
+    original_sha256 TEXT NOT NULL,
+    original_bytes BIGINT NOT NULL,
+    original_key TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_error TEXT,
+    -- Verification record, filled in once sealed
+    sealed_sha256 TEXT,
+    sealed_bytes BIGINT,
+    sealed_key TEXT,
+    certificate_sha256 TEXT,
+    certificate_subject TEXT,
+    signing_time TIMESTAMPTZ,
+    timestamp_time TIMESTAMPTZ,
+    timestamp_authority TEXT,
+    notary_entry_id UUID,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    sealed_at TIMESTAMPTZ
+);
+
+-- Resubmitting a document returns its job unless that job failed
+CREATE UNIQUE INDEX IF NOT EXISTS idx_seal_jobs_original
+    ON seal_jobs (COALESCE(tenant_id, ''), original_sha256) WHERE status <> 'failed';
+CREATE INDEX IF NOT EXISTS idx_seal_jobs_due ON seal_jobs (next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_seal_jobs_sealed ON seal_jobs (sealed_sha256) WHERE sealed_sha256 IS NOT NULL;
@@ -0,0 +1,25 @@
+-- Brute-force and abuse detections, and the lockouts they impose
+CREATE TABLE IF NOT EXISTS threat_detections (
+    id UUID PRIMARY KEY,
+    -- auth_failure or rate_limited
+    signal TEXT NOT NULL,
+    -- actor or actor_ip
+    entity_type TEXT NOT NULL,
+    entity TEXT NOT NULL,
+    tenant_id TEXT,
+    -- Signals counted past the threshold while active
+    hits BIGINT NOT NULL DEFAULT 1,
+    first_seen TIMESTAMPTZ NOT NULL,
+    last_seen TIMESTAMPTZ NOT NULL,
+    locked_until TIMESTAMPTZ,
+    -- active, reset or expired
+    status TEXT NOT NULL DEFAULT 'active',
+    reset_by TEXT,
+    reset_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- One active detection per signal and entity
+CREATE UNIQUE INDEX IF NOT EXISTS idx_threat_detections_active
+    ON threat_detections (signal, entity_type, entity) WHERE status = 'active';
+CREATE INDEX IF NOT EXISTS idx_threat_detections_created ON threat_detections (created_at);
@@ -0,0 +1,28 @@
+-- Long-term validation: what each seal embeds for validation decades later,
+-- and the history of revalidating it
+ALTER TABLE seal_jobs ADD COLUMN IF NOT EXISTS level TEXT;
+-- SHA-256 of each OCSP response, CRL and certificate embedded in the DSS
+ALTER TABLE seal_jobs ADD COLUMN IF NOT EXISTS validation_material JSONB;
+ALTER TABLE seal_jobs ADD COLUMN IF NOT EXISTS archive_timestamp_time TIMESTAMPTZ;
+ALTER TABLE seal_jobs ADD COLUMN IF NOT EXISTS archive_timestamp_authority TEXT;
+
+CREATE TABLE IF NOT EXISTS seal_validations (
+    id UUID PRIMARY KEY,
+    job_id UUID NOT NULL REFERENCES seal_jobs (id),
+    validated_at TIMESTAMPTZ NOT NULL,
+    validated_by TEXT NOT NULL,
+    -- The stored file still hashes to the recorded sealed_sha256
+    document_intact BOOLEAN NOT NULL,
+    -- The notary still proves the sealed hash
+    notarized BOOLEAN NOT NULL,
+    -- ETSI EN 319 102-1 indication from the validator
+    indication TEXT,
+    sub_indication TEXT,
+    best_signature_time TIMESTAMPTZ,
+    -- When the newest timestamp's certificate expires
+    valid_until TIMESTAMPTZ,
+    verifiable BOOLEAN NOT NULL,
+    report JSONB NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX IF NOT EXISTS idx_seal_validations_job ON seal_validations (job_id, validated_at);
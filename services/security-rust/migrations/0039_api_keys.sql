@@ -0,0 +1,23 @@
+-- Opaque API keys for machine callers. Only a hash of the secret is kept.
+CREATE TABLE IF NOT EXISTS api_keys (
+    id UUID PRIMARY KEY,
+    tenant_id TEXT,
+    name TEXT NOT NULL,
+    -- SHA-256 of the secret part of the key
+    secret_hash TEXT NOT NULL,
+    scopes TEXT[] NOT NULL DEFAULT '{}',
+    -- active or revoked
+    status TEXT NOT NULL DEFAULT 'active',
+    expires_at TIMESTAMPTZ NOT NULL,
+    -- The key this one was rotated from, and the one that replaced it
+    rotated_from UUID REFERENCES api_keys (id),
+    replaced_by UUID REFERENCES api_keys (id),
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ,
+    revoked_by TEXT,
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_api_keys_tenant ON api_keys (tenant_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_api_keys_active ON api_keys (expires_at) WHERE status = 'active';
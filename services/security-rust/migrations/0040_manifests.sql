@@ -0,0 +1,44 @@
+-- Signed file manifests of multi-file packages (tender packages), one
+-- version per re-issue of a package
+CREATE TABLE IF NOT EXISTS manifests (
+    id UUID PRIMARY KEY,
+    tenant_id TEXT,
+    -- What the package is to the creator, e.g. the tender id
+    package TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    label TEXT,
+    -- [{name, sha256, size}] sorted by name
+    files JSONB NOT NULL,
+    file_count INTEGER NOT NULL,
+    total_bytes BIGINT NOT NULL,
+    manifest_sha256 TEXT NOT NULL,
+    algorithm TEXT NOT NULL,
+    key_id TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_manifests_version
+    ON manifests (COALESCE(tenant_id, ''), package, version);
+CREATE INDEX IF NOT EXISTS idx_manifests_created ON manifests (created_at DESC);
+
+-- Every check of a package, or of another manifest, against a manifest
+CREATE TABLE IF NOT EXISTS manifest_comparisons (
+    id UUID PRIMARY KEY,
+    manifest_id UUID NOT NULL REFERENCES manifests (id),
+    -- Set when compared with another manifest rather than presented files
+    against_manifest_id UUID REFERENCES manifests (id),
+    tenant_id TEXT,
+    matches BOOLEAN NOT NULL,
+    signature_valid BOOLEAN,
+    added JSONB NOT NULL,
+    removed JSONB NOT NULL,
+    modified JSONB NOT NULL,
+    unchanged INTEGER NOT NULL,
+    compared_by TEXT NOT NULL,
+    compared_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_manifest_comparisons_manifest
+    ON manifest_comparisons (manifest_id, compared_at DESC);
@@ -0,0 +1,41 @@
+-- Evidence files flagged in disputes; the files stay where they are, only
+-- their hashes and custody are tracked
+CREATE TABLE IF NOT EXISTS custody_evidence (
+    id UUID PRIMARY KEY,
+    tenant_id TEXT,
+    -- The dispute or proceeding, e.g. the appeal number
+    case_reference TEXT NOT NULL,
+    name TEXT NOT NULL,
+    sha256 TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    reason TEXT NOT NULL,
+    custodian TEXT NOT NULL,
+    -- 'intact' until a presented copy fails to match
+    integrity TEXT NOT NULL DEFAULT 'intact',
+    event_count INTEGER NOT NULL DEFAULT 0,
+    head_hash TEXT NOT NULL,
+    flagged_by TEXT NOT NULL,
+    flagged_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_custody_evidence_case ON custody_evidence (tenant_id, case_reference);
+
+-- Hash-linked custody log, one chain per evidence file
+CREATE TABLE IF NOT EXISTS custody_events (
+    id UUID PRIMARY KEY,
+    evidence_id UUID NOT NULL REFERENCES custody_evidence (id),
+    seq INTEGER NOT NULL,
+    -- flag, access, transfer or export
+    kind TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    actor_ip TEXT,
+    purpose TEXT NOT NULL,
+    presented_sha256 TEXT NOT NULL,
+    hash_verified BOOLEAN NOT NULL,
+    -- New custodian of a transfer, destination of an export
+    counterparty TEXT,
+    recorded_at TIMESTAMPTZ NOT NULL,
+    prev_hash TEXT NOT NULL,
+    entry_hash TEXT NOT NULL,
+    UNIQUE (evidence_id, seq)
+);
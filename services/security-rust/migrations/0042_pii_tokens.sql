@@ -0,0 +1,26 @@
+-- Tokenization vault: opaque or format-preserving tokens standing in for
+-- CPF/CNPJ and bank account data, with the values encrypted under the
+-- crypto service's data keys
+CREATE TABLE IF NOT EXISTS pii_tokens (
+    id UUID PRIMARY KEY,
+    tenant_id TEXT,
+    -- cpf, cnpj or bank_account
+    data_type TEXT NOT NULL,
+    format_preserving BOOLEAN NOT NULL,
+    token TEXT NOT NULL,
+    -- Keyed hash of the normalized value, so a value always gets its token back
+    value_index TEXT NOT NULL,
+    encrypted_value TEXT NOT NULL,
+    key_id TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    context_hash TEXT,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    detokenize_count BIGINT NOT NULL DEFAULT 0,
+    last_detokenized_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_pii_tokens_token
+    ON pii_tokens (COALESCE(tenant_id, ''), token);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_pii_tokens_value
+    ON pii_tokens (COALESCE(tenant_id, ''), data_type, format_preserving, value_index);
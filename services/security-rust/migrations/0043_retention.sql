@@ -0,0 +1,22 @@
+-- Legal holds exempting data from the retention engine until released
+CREATE TABLE IF NOT EXISTS legal_holds (
+    id UUID PRIMARY KEY,
+    -- A retention data type, or every type when null
+    data_type TEXT,
+    -- Holds one tenant's rows, or every tenant's when null
+    tenant_id TEXT,
+    -- The proceeding or request the data is held for
+    case_reference TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    released_by TEXT,
+    released_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_legal_holds_active ON legal_holds (data_type) WHERE released_at IS NULL;
+
+-- Columns retention ages rows by
+CREATE INDEX IF NOT EXISTS idx_delivery_messages_created ON delivery_messages (created_at);
+CREATE INDEX IF NOT EXISTS idx_security_incidents_last_seen ON security_incidents (last_seen);
+CREATE INDEX IF NOT EXISTS idx_threat_detections_last_seen ON threat_detections (last_seen);
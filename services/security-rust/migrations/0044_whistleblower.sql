@@ -0,0 +1,54 @@
+-- Whistleblower reports, encrypted by the reporter to the channel's public
+-- key. No column records who sent a report or from where, and times are
+-- stored at the configured precision only.
+CREATE TABLE IF NOT EXISTS whistleblower_reports (
+    id UUID PRIMARY KEY,
+    -- The organization the report concerns, if the reporter named one
+    tenant_id TEXT,
+    -- SHA-256 of the public key the report was encrypted to
+    key_fingerprint TEXT NOT NULL,
+    ciphertext TEXT NOT NULL,
+    -- Reporter-supplied fields kept by WHISTLEBLOWER_METADATA_FIELDS
+    metadata JSONB NOT NULL DEFAULT '{}',
+    -- Public key of the reporter's for encrypting replies, if given
+    reply_key TEXT,
+    -- Keyed hash of the follow-up token
+    followup_hash TEXT NOT NULL UNIQUE,
+    -- received, in_review or closed
+    status TEXT NOT NULL DEFAULT 'received',
+    received_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_whistleblower_reports_received ON whistleblower_reports (tenant_id, received_at);
+
+-- Two-way follow-up between the reporter and handlers
+CREATE TABLE IF NOT EXISTS whistleblower_messages (
+    id UUID PRIMARY KEY,
+    report_id UUID NOT NULL REFERENCES whistleblower_reports (id),
+    -- reporter or handler
+    author TEXT NOT NULL,
+    -- The handler's subject; never set for the reporter
+    handler TEXT,
+    ciphertext TEXT NOT NULL,
+    sent_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_whistleblower_messages_report ON whistleblower_messages (report_id, sent_at);
+
+-- Handlers' requests to read a report, approved by another handler
+CREATE TABLE IF NOT EXISTS whistleblower_access (
+    id UUID PRIMARY KEY,
+    report_id UUID NOT NULL REFERENCES whistleblower_reports (id),
+    requested_by TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    -- pending_approval, approved or rejected
+    status TEXT NOT NULL DEFAULT 'pending_approval',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    decided_by TEXT,
+    decided_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_whistleblower_access_report ON whistleblower_access (report_id, requested_by);
+CREATE INDEX IF NOT EXISTS idx_whistleblower_access_pending ON whistleblower_access (created_at)
+    WHERE status = 'pending_approval';
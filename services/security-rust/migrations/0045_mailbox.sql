@@ -0,0 +1,45 @@
+-- Store-and-forward messages between service identities, encrypted at rest
+-- under the crypto service's data keys
+CREATE TABLE IF NOT EXISTS mailbox_messages (
+    id UUID PRIMARY KEY,
+    tenant_id TEXT,
+    sender TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    -- Free-form label the recipient can route on, e.g. bid_opening_key
+    subject_line TEXT NOT NULL,
+    -- Cleared once the message is acknowledged or expires
+    encrypted_body TEXT,
+    key_id TEXT,
+    nonce TEXT,
+    context_hash TEXT,
+    size INTEGER NOT NULL,
+    -- pending, acknowledged or expired
+    status TEXT NOT NULL DEFAULT 'pending',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    read_at TIMESTAMPTZ,
+    acknowledged_at TIMESTAMPTZ,
+    -- Webhook notification: none, pending, sent or failed
+    notify_status TEXT NOT NULL DEFAULT 'none',
+    notify_attempts INTEGER NOT NULL DEFAULT 0,
+    notify_next_at TIMESTAMPTZ,
+    notify_error TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_mailbox_messages_recipient ON mailbox_messages (recipient, created_at)
+    WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_mailbox_messages_expires ON mailbox_messages (expires_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_mailbox_messages_notify ON mailbox_messages (notify_next_at)
+    WHERE notify_status = 'pending';
+
+-- Where recipients want to be told about new messages
+CREATE TABLE IF NOT EXISTS mailbox_webhooks (
+    subject TEXT PRIMARY KEY,
+    url TEXT NOT NULL,
+    -- Signing secret, encrypted like message bodies
+    encrypted_secret TEXT NOT NULL,
+    key_id TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    context_hash TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,24 @@
+-- Login sessions, one per refresh token family (the id is the family id)
+CREATE TABLE IF NOT EXISTS sessions (
+    id UUID PRIMARY KEY,
+    subject TEXT NOT NULL,
+    tenant_id TEXT,
+    client_id UUID,
+    -- Where the session started, as reported by the login flow
+    ip TEXT,
+    user_agent TEXT,
+    -- Updated on every refresh
+    last_ip TEXT,
+    last_seen_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    -- Absolute end, however active the session is
+    expires_at TIMESTAMPTZ NOT NULL,
+    ended_at TIMESTAMPTZ,
+    -- revoked, reuse_detected, consent_revoked, idle_timeout or expired
+    end_reason TEXT,
+    ended_by TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_sessions_subject ON sessions (subject, last_seen_at) WHERE ended_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_sessions_expires_at ON sessions (expires_at) WHERE ended_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_sessions_last_seen_at ON sessions (last_seen_at) WHERE ended_at IS NULL;
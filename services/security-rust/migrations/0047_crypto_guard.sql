@@ -0,0 +1,35 @@
+-- Plaintext reads per caller and counting window, summed across instances
+CREATE TABLE IF NOT EXISTS crypto_read_volume (
+    caller TEXT NOT NULL,
+    window_start TIMESTAMPTZ NOT NULL,
+    tenant_id TEXT,
+    operations BIGINT NOT NULL,
+    PRIMARY KEY (caller, window_start)
+);
+
+CREATE INDEX IF NOT EXISTS idx_crypto_read_volume_window ON crypto_read_volume (window_start);
+
+-- Callers paused for reading far over their baseline, until an admin
+-- releases them
+CREATE TABLE IF NOT EXISTS crypto_holds (
+    id UUID PRIMARY KEY,
+    caller TEXT NOT NULL,
+    tenant_id TEXT,
+    window_start TIMESTAMPTZ NOT NULL,
+    -- Reads in the window when the guard tripped, and the limit passed
+    observed BIGINT NOT NULL,
+    threshold BIGINT NOT NULL,
+    baseline BIGINT NOT NULL,
+    -- Whether the baseline was learned or the configured minimum
+    learned BOOLEAN NOT NULL,
+    incident_id UUID,
+    -- held or released
+    status TEXT NOT NULL DEFAULT 'held',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    released_by TEXT,
+    released_at TIMESTAMPTZ,
+    note TEXT
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_crypto_holds_held ON crypto_holds (caller) WHERE status = 'held';
+CREATE INDEX IF NOT EXISTS idx_crypto_holds_created_at ON crypto_holds (created_at);
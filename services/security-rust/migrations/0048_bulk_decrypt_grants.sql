@@ -0,0 +1,27 @@
+-- Approved allowances for decrypting more records in one request than
+-- CRYPTO_BULK_DECRYPT_THRESHOLD
+CREATE TABLE IF NOT EXISTS bulk_decrypt_grants (
+    id UUID PRIMARY KEY,
+    -- SHA-256 of the grant token, which is only shown to the requester
+    token_hash TEXT NOT NULL UNIQUE,
+    requested_by TEXT NOT NULL,
+    tenant_id TEXT,
+    purpose TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    -- Records the grant covers, and those decrypted under it so far
+    records BIGINT NOT NULL,
+    records_used BIGINT NOT NULL DEFAULT 0,
+    -- pending_approval, approved or rejected
+    status TEXT NOT NULL DEFAULT 'pending_approval',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    decided_by TEXT,
+    decided_at TIMESTAMPTZ,
+    -- Audit event of the approval, named by every use of the grant
+    approval_event_id UUID,
+    -- When a pending request lapses, or an approved grant stops working
+    expires_at TIMESTAMPTZ NOT NULL,
+    last_used_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_bulk_decrypt_grants_status ON bulk_decrypt_grants (status, created_at);
+CREATE INDEX IF NOT EXISTS idx_bulk_decrypt_grants_requested_by ON bulk_decrypt_grants (requested_by);
@@ -0,0 +1,18 @@
+-- Sandboxed WebAssembly plugins, a row per uploaded version
+CREATE TABLE IF NOT EXISTS plugins (
+    name TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    -- validation or detection, the same for every version of a name
+    kind TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    module BYTEA NOT NULL,
+    -- Hex SHA-256 of module
+    sha256 TEXT NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT FALSE,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (name, version)
+);
+
+-- At most one active version per plugin
+CREATE UNIQUE INDEX IF NOT EXISTS idx_plugins_active ON plugins (name) WHERE active;
@@ -0,0 +1,27 @@
+-- Sanitized copies of requests the pipeline rejected or flagged, encrypted
+-- at rest under the crypto service's data keys
+CREATE TABLE IF NOT EXISTS forensic_captures (
+    id UUID PRIMARY KEY,
+    tenant_id TEXT,
+    -- Pipeline stage that rejected or flagged the request, e.g. rate_limit
+    stage TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    -- Status answered when the stage rejected the request
+    status INTEGER,
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    client_ip TEXT,
+    subject TEXT,
+    body_bytes INTEGER NOT NULL,
+    body_truncated BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Headers, query, body and decision context as JSON
+    encrypted_capture TEXT NOT NULL,
+    key_id TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    context_hash TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_forensic_captures_created ON forensic_captures (created_at);
+CREATE INDEX IF NOT EXISTS idx_forensic_captures_client_ip ON forensic_captures (client_ip, created_at);
+CREATE INDEX IF NOT EXISTS idx_forensic_captures_stage ON forensic_captures (stage, created_at);
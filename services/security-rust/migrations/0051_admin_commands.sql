@@ -0,0 +1,31 @@
+-- Mutating admin requests, recorded for review and replay
+CREATE TABLE IF NOT EXISTS admin_commands (
+    id UUID PRIMARY KEY,
+    -- Recording order across replicas; replay follows it
+    sequence BIGSERIAL NOT NULL,
+    -- Token id (jti) of the login the command was sent under
+    session TEXT,
+    actor TEXT NOT NULL,
+    tenant_id TEXT,
+    actor_ip TEXT,
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    query TEXT NOT NULL DEFAULT '',
+    route TEXT,
+    -- Redacted JSON body; none, json, redacted, truncated or omitted
+    body JSONB,
+    body_state TEXT NOT NULL,
+    status INTEGER NOT NULL,
+    result JSONB,
+    started_at TIMESTAMPTZ NOT NULL,
+    duration_ms BIGINT NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_admin_commands_sequence ON admin_commands (sequence);
+CREATE INDEX IF NOT EXISTS idx_admin_commands_started ON admin_commands (started_at);
+CREATE INDEX IF NOT EXISTS idx_admin_commands_session ON admin_commands (session, started_at);
+CREATE INDEX IF NOT EXISTS idx_admin_commands_actor ON admin_commands (actor, started_at);
+
+-- Changes made while serving a command
+ALTER TABLE change_history ADD COLUMN IF NOT EXISTS command_id UUID;
+CREATE INDEX IF NOT EXISTS idx_change_history_command ON change_history (command_id) WHERE command_id IS NOT NULL;
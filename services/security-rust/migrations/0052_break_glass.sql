@@ -0,0 +1,47 @@
+-- Sealed emergency credentials and their use
+CREATE TABLE IF NOT EXISTS break_glass_accounts (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    holder TEXT NOT NULL,
+    channel TEXT NOT NULL,
+    -- Where one-time codes go, encrypted under the crypto service's data keys
+    encrypted_destination TEXT NOT NULL,
+    key_id TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    context_hash TEXT,
+    destination_hint TEXT NOT NULL,
+    -- Argon2 hash of the unspent secret; NULL once it is used
+    secret_hash TEXT,
+    sealed_at TIMESTAMPTZ,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    disabled_at TIMESTAMPTZ
+);
+
+CREATE TABLE IF NOT EXISTS break_glass_activations (
+    id UUID PRIMARY KEY,
+    account_id UUID NOT NULL REFERENCES break_glass_accounts (id),
+    -- pending_mfa, active, ended, expired or failed
+    status TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    client_ip TEXT,
+    challenge_id UUID,
+    -- Incident standing as the post-use review record
+    incident_id UUID,
+    -- jti of every access token issued
+    token_ids TEXT[] NOT NULL DEFAULT '{}',
+    started_at TIMESTAMPTZ NOT NULL,
+    activated_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ,
+    ended_at TIMESTAMPTZ,
+    ended_by TEXT,
+    reviewed_at TIMESTAMPTZ,
+    reviewed_by TEXT,
+    review_outcome TEXT,
+    review_notes TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_break_glass_activations_account ON break_glass_activations (account_id, started_at);
+CREATE INDEX IF NOT EXISTS idx_break_glass_activations_status ON break_glass_activations (status, expires_at);
+CREATE INDEX IF NOT EXISTS idx_break_glass_activations_unreviewed ON break_glass_activations (started_at)
+    WHERE incident_id IS NOT NULL AND reviewed_at IS NULL;
@@ -0,0 +1,25 @@
+-- Just-in-time role elevation requests and grants
+CREATE TABLE IF NOT EXISTS elevations (
+    id UUID PRIMARY KEY,
+    subject TEXT NOT NULL,
+    tenant_id TEXT,
+    role TEXT NOT NULL,
+    justification TEXT NOT NULL,
+    duration_secs BIGINT NOT NULL,
+    -- pending, active, denied, lapsed, cancelled, revoked or expired
+    status TEXT NOT NULL,
+    -- auto or manual
+    decision TEXT,
+    decided_by TEXT,
+    decided_at TIMESTAMPTZ,
+    decision_reason TEXT,
+    requested_at TIMESTAMPTZ NOT NULL,
+    starts_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ,
+    ended_at TIMESTAMPTZ,
+    ended_by TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_elevations_subject ON elevations (subject, requested_at);
+CREATE INDEX IF NOT EXISTS idx_elevations_requested ON elevations (requested_at);
+CREATE INDEX IF NOT EXISTS idx_elevations_open ON elevations (status, expires_at) WHERE status IN ('pending', 'active');
@@ -0,0 +1,14 @@
+-- Actions subjects performed on resources, as reported through
+-- `/authz/check` with `record`, checked against separation of duties rules.
+CREATE TABLE IF NOT EXISTS sod_duties (
+    id UUID PRIMARY KEY,
+    subject TEXT NOT NULL,
+    tenant_id TEXT,
+    action TEXT NOT NULL,
+    resource TEXT NOT NULL,
+    performed_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (subject, action, resource)
+);
+
+CREATE INDEX IF NOT EXISTS idx_sod_duties_resource ON sod_duties (subject, resource);
+CREATE INDEX IF NOT EXISTS idx_sod_duties_tenant ON sod_duties (tenant_id, subject);
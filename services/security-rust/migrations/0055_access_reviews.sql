@@ -0,0 +1,56 @@
+-- Who reviews each subject's access in recertification campaigns.
+CREATE TABLE IF NOT EXISTS access_review_managers (
+    subject TEXT PRIMARY KEY,
+    manager TEXT NOT NULL,
+    updated_by TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_access_review_managers_manager ON access_review_managers (manager);
+
+CREATE TABLE IF NOT EXISTS access_review_campaigns (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL,
+    -- Only this tenant's access, or everyone's
+    tenant_id TEXT,
+    -- open or closed
+    status TEXT NOT NULL,
+    scheduled BOOLEAN NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    deadline TIMESTAMPTZ NOT NULL,
+    closed_at TIMESTAMPTZ,
+    -- Counts of outcomes, and SHA-256 of the archived items, once closed
+    summary JSONB,
+    archive_digest TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_access_review_campaigns_created ON access_review_campaigns (created_at);
+CREATE INDEX IF NOT EXISTS idx_access_review_campaigns_open ON access_review_campaigns (deadline) WHERE status = 'open';
+
+-- One piece of access to keep or revoke: a role, API key, elevation or
+-- consent grant.
+CREATE TABLE IF NOT EXISTS access_review_items (
+    id UUID PRIMARY KEY,
+    campaign_id UUID NOT NULL REFERENCES access_review_campaigns (id),
+    subject TEXT NOT NULL,
+    tenant_id TEXT,
+    -- role, api_key, elevation or consent
+    kind TEXT NOT NULL,
+    -- The role, or the id of the key, elevation or client
+    target TEXT NOT NULL,
+    detail JSONB NOT NULL,
+    -- The subject's manager; NULL for ACCESS_REVIEW_FALLBACK_ROLES
+    reviewer TEXT,
+    -- keep or revoke
+    decision TEXT,
+    decided_by TEXT,
+    decided_at TIMESTAMPTZ,
+    comment TEXT,
+    -- kept, revoked, already_gone or revoke_failed
+    outcome TEXT,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_access_review_items_campaign ON access_review_items (campaign_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_access_review_items_reviewer ON access_review_items (reviewer, created_at) WHERE decision IS NULL;
@@ -0,0 +1,28 @@
+-- Organization chart per tenant: units in a tree, and the unit and
+-- manager of each subject. Units refer to each other by key so whole
+-- charts can be synced in any order.
+CREATE TABLE IF NOT EXISTS org_units (
+    id UUID PRIMARY KEY,
+    tenant_id TEXT,
+    key TEXT NOT NULL,
+    name TEXT NOT NULL,
+    -- department or unit
+    kind TEXT NOT NULL,
+    parent TEXT,
+    manager TEXT,
+    updated_by TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_org_units_key ON org_units ((COALESCE(tenant_id, '')), key);
+
+CREATE TABLE IF NOT EXISTS org_members (
+    subject TEXT PRIMARY KEY,
+    tenant_id TEXT,
+    unit TEXT NOT NULL,
+    manager TEXT,
+    updated_by TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_org_members_tenant ON org_members (tenant_id, unit);
@@ -0,0 +1,15 @@
+-- Resources pushed by the services owning them, with the attributes
+-- authorization trusts over what callers say.
+CREATE TABLE IF NOT EXISTS registered_resources (
+    id TEXT NOT NULL,
+    tenant_id TEXT,
+    -- the id up to its first ':'
+    resource_type TEXT NOT NULL,
+    attributes JSONB NOT NULL,
+    version BIGINT NOT NULL,
+    updated_by TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_registered_resources_id ON registered_resources ((COALESCE(tenant_id, '')), id);
+CREATE INDEX IF NOT EXISTS idx_registered_resources_type ON registered_resources (tenant_id, resource_type);
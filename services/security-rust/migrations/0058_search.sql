@@ -0,0 +1,15 @@
+-- Indexes for admin search: trigram indexes on what is matched by prefix
+-- and similarity, full-text ones on descriptions.
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS idx_org_members_subject_trgm ON org_members USING GIN (lower(subject) gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS idx_api_keys_name_trgm ON api_keys USING GIN (lower(name) gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS idx_service_accounts_name_trgm ON service_accounts USING GIN (lower(name) gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS idx_service_accounts_description_fts ON service_accounts USING GIN (to_tsvector('simple', description));
+CREATE INDEX IF NOT EXISTS idx_oauth_clients_name_trgm ON oauth_clients USING GIN (lower(name) gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS idx_oauth_clients_description_fts ON oauth_clients USING GIN (to_tsvector('simple', description));
+CREATE INDEX IF NOT EXISTS idx_policies_name_trgm ON policies USING GIN (lower(name) gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS idx_policies_description_fts ON policies USING GIN (to_tsvector('simple', COALESCE(description, '')));
+CREATE INDEX IF NOT EXISTS idx_security_incidents_rule_trgm ON security_incidents USING GIN (lower(rule) gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS idx_security_incidents_entity_trgm ON security_incidents USING GIN (lower(entity) gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS idx_crypto_keys_key_id_trgm ON crypto_keys USING GIN (lower(key_id) gin_trgm_ops);
@@ -0,0 +1,15 @@
+-- Configuration bundles applied from the platform pipeline. A bundle is
+-- applied only over an older version, so the newest one recorded is what
+-- the policies, rules and flags came from.
+CREATE TABLE IF NOT EXISTS config_bundles (
+    version BIGINT PRIMARY KEY,
+    bundle_id TEXT NOT NULL,
+    signer TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    payload_sha256 TEXT NOT NULL,
+    policies INTEGER NOT NULL,
+    correlation_rules INTEGER NOT NULL,
+    flags INTEGER NOT NULL,
+    applied_by TEXT NOT NULL,
+    applied_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
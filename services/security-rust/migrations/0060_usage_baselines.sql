@@ -0,0 +1,54 @@
+-- Requests per endpoint, tenant ('' for none) and UTC hour, added to by
+-- every replica.
+CREATE TABLE IF NOT EXISTS endpoint_usage (
+    tenant_id TEXT NOT NULL,
+    route TEXT NOT NULL,
+    hour TIMESTAMPTZ NOT NULL,
+    requests BIGINT NOT NULL,
+    PRIMARY KEY (tenant_id, route, hour)
+);
+
+CREATE INDEX IF NOT EXISTS idx_endpoint_usage_hour ON endpoint_usage (hour);
+
+-- One hour-of-week profile per endpoint and tenant, relearned daily.
+CREATE TABLE IF NOT EXISTS usage_baselines (
+    tenant_id TEXT NOT NULL,
+    route TEXT NOT NULL,
+    profile JSONB NOT NULL,
+    learned_for DATE NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (tenant_id, route)
+);
+
+-- Thresholds set by hand, in place of or on top of the learned ones.
+CREATE TABLE IF NOT EXISTS usage_overrides (
+    tenant_id TEXT NOT NULL,
+    route TEXT NOT NULL,
+    max_requests BIGINT,
+    sigma DOUBLE PRECISION,
+    reason TEXT NOT NULL,
+    updated_by TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (tenant_id, route)
+);
+
+-- Hours that went over their threshold; one incident each.
+CREATE TABLE IF NOT EXISTS usage_anomalies (
+    tenant_id TEXT NOT NULL,
+    route TEXT NOT NULL,
+    hour TIMESTAMPTZ NOT NULL,
+    requests BIGINT NOT NULL,
+    threshold BIGINT NOT NULL,
+    incident_id UUID,
+    detected_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (tenant_id, route, hour)
+);
+
+-- Single row locked by whichever replica runs the learning pass.
+CREATE TABLE IF NOT EXISTS usage_state (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    learned_for DATE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO usage_state (learned_for) VALUES (NULL) ON CONFLICT (id) DO NOTHING;
@@ -0,0 +1,14 @@
+-- Operations per data key, caller and UTC day, added to by every replica;
+-- the per-caller side of crypto_key_usage, kept for a limited time.
+CREATE TABLE IF NOT EXISTS crypto_key_callers (
+    key_id TEXT NOT NULL,
+    day DATE NOT NULL,
+    caller TEXT NOT NULL,
+    count BIGINT NOT NULL,
+    last_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (key_id, day, caller)
+);
+
+CREATE INDEX IF NOT EXISTS idx_crypto_key_callers_day ON crypto_key_callers (day);
+
+CREATE INDEX IF NOT EXISTS idx_crypto_key_usage_day ON crypto_key_usage (day);
@@ -0,0 +1,43 @@
+-- Last login per subject, kept after its sessions are purged.
+CREATE TABLE IF NOT EXISTS subject_logins (
+    subject TEXT PRIMARY KEY,
+    tenant_id TEXT,
+    last_login_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_subject_logins_last_login_at ON subject_logins (last_login_at);
+
+INSERT INTO subject_logins (subject, tenant_id, last_login_at)
+SELECT DISTINCT ON (subject) subject, tenant_id, created_at FROM sessions ORDER BY subject, created_at DESC
+ON CONFLICT (subject) DO NOTHING;
+
+-- Credentials and accounts found dormant, once per period of inactivity.
+CREATE TABLE IF NOT EXISTS dormancy_findings (
+    id UUID PRIMARY KEY,
+    -- api_key, user or session
+    kind TEXT NOT NULL,
+    target TEXT NOT NULL,
+    subject TEXT,
+    tenant_id TEXT,
+    last_active_at TIMESTAMPTZ NOT NULL,
+    -- notify, disable or delete
+    action TEXT NOT NULL,
+    -- flagged, disabled, deleted or failed
+    status TEXT NOT NULL,
+    error TEXT,
+    detected_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (kind, target, last_active_at)
+);
+
+CREATE INDEX IF NOT EXISTS idx_dormancy_findings_detected_at ON dormancy_findings (detected_at);
+
+-- Targets the job leaves alone, until an optional expiry.
+CREATE TABLE IF NOT EXISTS dormancy_exemptions (
+    kind TEXT NOT NULL,
+    target TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ,
+    PRIMARY KEY (kind, target)
+);
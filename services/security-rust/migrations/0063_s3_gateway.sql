@@ -0,0 +1,18 @@
+-- Access keys for the S3 gateway. The secret signs requests (SigV4), so it
+-- is kept encrypted rather than hashed.
+CREATE TABLE IF NOT EXISTS s3_gateway_credentials (
+    access_key_id TEXT PRIMARY KEY,
+    tenant_id TEXT NOT NULL,
+    description TEXT NOT NULL,
+    -- read, write or read-write
+    access TEXT NOT NULL,
+    secret_key_id TEXT NOT NULL,
+    secret_nonce BYTEA NOT NULL,
+    secret_ciphertext BYTEA NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    last_used_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_s3_gateway_credentials_tenant_id ON s3_gateway_credentials (tenant_id);
@@ -0,0 +1,22 @@
+-- DKIM signing keys for outgoing email. The private key is encrypted by
+-- the crypto service; the public key is what the DNS record publishes.
+CREATE TABLE IF NOT EXISTS dkim_keys (
+    selector TEXT PRIMARY KEY,
+    domain TEXT NOT NULL,
+    -- rsa-sha256 or ed25519-sha256
+    algorithm TEXT NOT NULL,
+    -- base64 p= value: SubjectPublicKeyInfo for RSA, the raw key for Ed25519
+    public_key TEXT NOT NULL,
+    secret_key_id TEXT NOT NULL,
+    secret_nonce BYTEA NOT NULL,
+    secret_ciphertext BYTEA NOT NULL,
+    -- pending, active, verify_only or retired
+    state TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    activate_at TIMESTAMPTZ NOT NULL,
+    retire_at TIMESTAMPTZ,
+    retired_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_dkim_keys_domain_state ON dkim_keys (domain, algorithm, state);
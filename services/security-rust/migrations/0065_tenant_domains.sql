@@ -0,0 +1,25 @@
+-- Domains tenants have claimed, and proof of their ownership by DNS TXT
+-- challenge.
+CREATE TABLE IF NOT EXISTS tenant_domains (
+    id UUID PRIMARY KEY,
+    tenant_id TEXT NOT NULL,
+    domain TEXT NOT NULL,
+    -- hex token the TXT record must hold
+    token TEXT NOT NULL,
+    -- pending, verified, lapsed or expired
+    status TEXT NOT NULL,
+    -- whether the last passing answer was DNSSEC-validated
+    dnssec BOOLEAN NOT NULL DEFAULT FALSE,
+    -- failed re-checks in a row
+    failures INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    verified_at TIMESTAMPTZ,
+    last_checked_at TIMESTAMPTZ,
+    next_check_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (tenant_id, domain)
+);
+
+CREATE INDEX IF NOT EXISTS idx_tenant_domains_due ON tenant_domains (next_check_at) WHERE status IN ('pending', 'verified', 'lapsed');
@@ -0,0 +1,59 @@
+-- SSH certificate authority: CA keys, operators' MFA destinations and the
+-- certificates issued
+CREATE TABLE IF NOT EXISTS ssh_ca_keys (
+    id UUID PRIMARY KEY,
+    -- user or host
+    kind TEXT NOT NULL,
+    -- OpenSSH public key line
+    public_key TEXT NOT NULL,
+    -- Ed25519 seed, encrypted by the crypto service
+    secret_key_id TEXT NOT NULL,
+    secret_nonce BYTEA NOT NULL,
+    secret_ciphertext BYTEA NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    -- Set when replaced: the key is trusted until then
+    retire_at TIMESTAMPTZ
+);
+
+-- One active key per CA
+CREATE UNIQUE INDEX IF NOT EXISTS idx_ssh_ca_keys_active ON ssh_ca_keys (kind) WHERE retire_at IS NULL;
+
+CREATE TABLE IF NOT EXISTS ssh_operators (
+    subject TEXT PRIMARY KEY,
+    channel TEXT NOT NULL,
+    -- Where one-time codes go, encrypted under the crypto service's data keys
+    encrypted_destination TEXT NOT NULL,
+    key_id TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    context_hash TEXT,
+    destination_hint TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE SEQUENCE IF NOT EXISTS ssh_certificate_serials;
+
+CREATE TABLE IF NOT EXISTS ssh_certificates (
+    id UUID PRIMARY KEY,
+    -- Assigned at issuance
+    serial BIGINT UNIQUE,
+    kind TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    principals TEXT[] NOT NULL,
+    public_key TEXT NOT NULL,
+    fingerprint TEXT NOT NULL,
+    ttl_secs BIGINT NOT NULL,
+    -- pending_mfa, issued or failed
+    status TEXT NOT NULL,
+    reason TEXT,
+    client_ip TEXT,
+    challenge_id UUID,
+    ca_key_id UUID REFERENCES ssh_ca_keys (id),
+    requested_at TIMESTAMPTZ NOT NULL,
+    valid_after TIMESTAMPTZ,
+    valid_before TIMESTAMPTZ,
+    issued_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_ssh_certificates_subject ON ssh_certificates (subject, requested_at DESC);
@@ -0,0 +1,30 @@
+-- Encrypted backups of keys and policies, and the restore drills run on them
+CREATE TABLE IF NOT EXISTS backups (
+    backup_id UUID PRIMARY KEY,
+    object_key TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    -- SHA-256 of the payload before encryption
+    payload_sha256 TEXT NOT NULL,
+    crypto_keys INTEGER NOT NULL,
+    signing_keys INTEGER NOT NULL,
+    policies INTEGER NOT NULL,
+    bytes BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_backups_created_at ON backups (created_at DESC);
+
+CREATE TABLE IF NOT EXISTS backup_drills (
+    id UUID PRIMARY KEY,
+    -- Unset when no backup could be fetched
+    backup_id UUID,
+    object_key TEXT,
+    backup_created_at TIMESTAMPTZ,
+    passed BOOLEAN NOT NULL,
+    -- [{name, passed, detail}]
+    checks JSONB NOT NULL,
+    started_by TEXT NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL,
+    finished_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_backup_drills_started_at ON backup_drills (started_at DESC);
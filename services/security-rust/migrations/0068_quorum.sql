@@ -0,0 +1,52 @@
+-- Quorum signing of published results: officers' keys, publications and
+-- the signatures collected for them
+CREATE TABLE IF NOT EXISTS quorum_officers (
+    id UUID PRIMARY KEY,
+    tenant_id TEXT,
+    subject TEXT NOT NULL,
+    -- Ed25519, base64url
+    public_key TEXT NOT NULL,
+    registered_by TEXT NOT NULL,
+    registered_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_quorum_officers_active
+    ON quorum_officers (COALESCE(tenant_id, ''), subject) WHERE revoked_at IS NULL;
+
+CREATE TABLE IF NOT EXISTS quorum_publications (
+    id UUID PRIMARY KEY,
+    tenant_id TEXT,
+    reference TEXT NOT NULL,
+    document_sha256 TEXT NOT NULL,
+    label TEXT,
+    threshold INTEGER NOT NULL,
+    -- [{subject, public_key}] of the officers when it opened
+    officers JSONB NOT NULL,
+    -- collecting, published or lapsed
+    status TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    published_at TIMESTAMPTZ,
+    -- Kept as text: the exact bytes hashed and signed
+    attestation TEXT,
+    attestation_sha256 TEXT,
+    algorithm TEXT,
+    key_id TEXT,
+    signature TEXT,
+    notary_entry_id UUID
+);
+
+CREATE INDEX IF NOT EXISTS idx_quorum_publications_tenant ON quorum_publications (tenant_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_quorum_publications_collecting ON quorum_publications (expires_at)
+    WHERE status = 'collecting';
+
+CREATE TABLE IF NOT EXISTS quorum_signatures (
+    publication_id UUID NOT NULL REFERENCES quorum_publications (id),
+    officer TEXT NOT NULL,
+    public_key TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    signed_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (publication_id, officer)
+);
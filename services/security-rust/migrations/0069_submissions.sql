@@ -0,0 +1,32 @@
+-- Deadline-enforced bid submissions: calls for bids and the chained,
+-- signed receipts issued for every submission
+CREATE TABLE IF NOT EXISTS submission_calls (
+    id UUID PRIMARY KEY,
+    tenant_id TEXT,
+    reference TEXT NOT NULL,
+    deadline TIMESTAMPTZ NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    -- Receipts issued so far; also the last sequence number
+    receipts BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS submission_receipts (
+    id UUID PRIMARY KEY,
+    call_id UUID NOT NULL REFERENCES submission_calls (id),
+    tenant_id TEXT,
+    sequence BIGINT NOT NULL,
+    -- accepted or refused
+    outcome TEXT NOT NULL,
+    package_sha256 TEXT NOT NULL,
+    label TEXT,
+    submitted_by TEXT NOT NULL,
+    received_at TIMESTAMPTZ NOT NULL,
+    deadline TIMESTAMPTZ NOT NULL,
+    previous_sha256 TEXT NOT NULL,
+    receipt_sha256 TEXT NOT NULL,
+    algorithm TEXT NOT NULL,
+    key_id TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    UNIQUE (call_id, sequence)
+);
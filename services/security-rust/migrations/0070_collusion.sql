@@ -0,0 +1,17 @@
+-- Duplicate-bidder screening: each analysis with its scored pairs and the
+-- evidence behind them, which holds blind-index references and vault
+-- tokens only
+CREATE TABLE IF NOT EXISTS collusion_analyses (
+    id UUID PRIMARY KEY,
+    tenant_id TEXT,
+    tender TEXT NOT NULL,
+    bid_count INTEGER NOT NULL,
+    identifier_count INTEGER NOT NULL,
+    flagged INTEGER NOT NULL,
+    -- {pairs: [{bids, score, flagged, shared, evidence}], evidence: [{id, kind, bids, tokens}]}
+    pairs JSONB NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_collusion_analyses_tender ON collusion_analyses (tenant_id, tender, created_at DESC);
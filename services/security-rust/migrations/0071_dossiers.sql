@@ -0,0 +1,35 @@
+-- Security dossiers exported to control bodies, and the RSA keys they are
+-- sealed to
+CREATE TABLE IF NOT EXISTS dossier_recipients (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL,
+    -- SubjectPublicKeyInfo PEM
+    public_key TEXT NOT NULL,
+    key_sha256 TEXT NOT NULL,
+    key_bits INTEGER NOT NULL,
+    registered_by TEXT NOT NULL,
+    registered_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_dossier_recipients_name ON dossier_recipients (name) WHERE revoked_at IS NULL;
+
+CREATE TABLE IF NOT EXISTS dossiers (
+    id UUID PRIMARY KEY,
+    tenant_id TEXT,
+    reference TEXT NOT NULL,
+    recipient_id UUID NOT NULL REFERENCES dossier_recipients (id),
+    -- pending, running, completed or failed
+    status TEXT NOT NULL,
+    object_key TEXT,
+    -- {name: {sha256, bytes, count}}
+    sections JSONB,
+    index_sha256 TEXT,
+    archive_sha256 TEXT,
+    error TEXT,
+    requested_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    completed_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_dossiers_reference ON dossiers (tenant_id, reference, created_at DESC);
@@ -0,0 +1,25 @@
+-- Tenants pinned to a region, and where each of a tenant's stored objects
+-- was written
+CREATE TABLE IF NOT EXISTS residency_policies (
+    tenant_id TEXT PRIMARY KEY,
+    region TEXT NOT NULL,
+    updated_by TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS residency_placements (
+    id UUID PRIMARY KEY,
+    tenant_id TEXT NOT NULL,
+    -- audit_export, audit_archive, dossier or seal
+    kind TEXT NOT NULL,
+    resource TEXT NOT NULL,
+    region TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_residency_placements_tenant ON residency_placements (tenant_id, kind, region);
+
+-- Region of the bucket holding each object; NULL is the home region
+ALTER TABLE audit_exports ADD COLUMN IF NOT EXISTS region TEXT;
+ALTER TABLE dossiers ADD COLUMN IF NOT EXISTS region TEXT;
+ALTER TABLE seal_jobs ADD COLUMN IF NOT EXISTS region TEXT;
@@ -0,0 +1,36 @@
+-- Retired data keys moved to the cold tier. Their crypto_keys row stays,
+-- in state `archived` and without key material, so ids are never reused.
+CREATE TABLE IF NOT EXISTS key_archives (
+    key_id TEXT PRIMARY KEY REFERENCES crypto_keys (key_id),
+    object_key TEXT NOT NULL,
+    sha256 TEXT NOT NULL,
+    -- Key check value, compared when the key is restored
+    check_value TEXT NOT NULL,
+    archived_by TEXT NOT NULL,
+    archived_at TIMESTAMPTZ NOT NULL,
+    restored_at TIMESTAMPTZ
+);
+
+-- Requests to bring an archived key back, approved by a second admin and
+-- delayed before they run
+CREATE TABLE IF NOT EXISTS key_restores (
+    id UUID PRIMARY KEY,
+    key_id TEXT NOT NULL REFERENCES key_archives (key_id),
+    reason TEXT NOT NULL,
+    requested_by TEXT NOT NULL,
+    requested_at TIMESTAMPTZ NOT NULL,
+    -- requested, approved, restored, closed, cancelled or failed
+    status TEXT NOT NULL DEFAULT 'requested',
+    approved_by TEXT,
+    approved_at TIMESTAMPTZ,
+    available_at TIMESTAMPTZ,
+    restored_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ,
+    ended_by TEXT,
+    ended_at TIMESTAMPTZ,
+    error TEXT
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_key_restores_open ON key_restores (key_id)
+    WHERE status IN ('requested', 'approved', 'restored');
+CREATE INDEX IF NOT EXISTS idx_key_restores_requested_at ON key_restores (requested_at DESC);
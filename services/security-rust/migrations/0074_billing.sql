@@ -0,0 +1,31 @@
+-- Cost units charged to each tenant, per day and meter. Replicas add their
+-- counts as they flush, so a row grows through its day.
+CREATE TABLE IF NOT EXISTS billing_usage (
+    tenant_id TEXT NOT NULL,
+    day DATE NOT NULL,
+    -- aead_kib, signature_ops, hash_ops or password_hash_ops
+    meter TEXT NOT NULL,
+    requests BIGINT NOT NULL DEFAULT 0,
+    quantity BIGINT NOT NULL DEFAULT 0,
+    units BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (tenant_id, day, meter)
+);
+
+CREATE INDEX IF NOT EXISTS idx_billing_usage_day ON billing_usage (day);
+
+-- One export per closed day; the row is claimed before the export runs so
+-- only one replica writes it
+CREATE TABLE IF NOT EXISTS billing_exports (
+    day DATE PRIMARY KEY,
+    -- running, completed or failed
+    status TEXT NOT NULL DEFAULT 'running',
+    objects TEXT[] NOT NULL DEFAULT '{}',
+    row_count BIGINT,
+    total_units BIGINT,
+    -- sent, failed, or NULL without BILLING_WEBHOOK_URL
+    webhook_status TEXT,
+    error TEXT,
+    requested_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    completed_at TIMESTAMPTZ
+);
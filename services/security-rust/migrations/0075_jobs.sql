@@ -0,0 +1,26 @@
+-- Slow operations answered with 202 and run on; see `jobs`
+CREATE TABLE IF NOT EXISTS async_jobs (
+    id UUID PRIMARY KEY,
+    kind TEXT NOT NULL,
+    owner TEXT NOT NULL,
+    tenant_id TEXT,
+    -- running, succeeded, failed or expired
+    status TEXT NOT NULL DEFAULT 'running',
+    -- HTTP status the operation answered with
+    status_code INTEGER,
+    result JSONB,
+    -- Headers the operation answered with, such as audit receipts
+    headers JSONB NOT NULL DEFAULT '{}',
+    error TEXT,
+    callback_url TEXT,
+    -- pending, sent or failed; NULL without a callback
+    callback_status TEXT,
+    callback_attempts INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL,
+    completed_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_async_jobs_running ON async_jobs (created_at) WHERE status = 'running';
+CREATE INDEX IF NOT EXISTS idx_async_jobs_expires_at ON async_jobs (expires_at);
+CREATE INDEX IF NOT EXISTS idx_async_jobs_callbacks ON async_jobs (completed_at) WHERE callback_status = 'pending';
@@ -0,0 +1,15 @@
+-- Noised aggregates released outside the SOC, per tenant, for the privacy
+-- budget; see `privacy`
+CREATE TABLE IF NOT EXISTS dp_releases (
+    -- Empty for events without a tenant
+    tenant_id TEXT NOT NULL,
+    -- Report and bucketed window
+    question TEXT NOT NULL,
+    since TIMESTAMPTZ NOT NULL,
+    until TIMESTAMPTZ NOT NULL,
+    epsilon DOUBLE PRECISION NOT NULL,
+    released_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (tenant_id, question)
+);
+
+CREATE INDEX IF NOT EXISTS idx_dp_releases_window ON dp_releases (tenant_id, since, until);
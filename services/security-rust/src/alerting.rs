@@ -0,0 +1,178 @@
+/*!
+Alerting Module
+Delivery of security notifications to configured sinks
+
+Webhook sinks are called with their `alerting.<sink>` outbound credential
+when one is stored (see `credentials`).
+*/
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::config::Config;
+use crate::credentials::CredentialCache;
+use crate::deadline;
+use crate::dlq::{DeadLetterQueue, DeliverySubsystem};
+use crate::errors::SecurityError;
+
+/// Ordered from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    pub source: String,
+    pub severity: Severity,
+    pub title: String,
+    pub details: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Alert {
+    pub fn new(
+        source: &str,
+        severity: Severity,
+        title: impl Into<String>,
+        details: serde_json::Value,
+        created_at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            source: source.to_string(),
+            severity,
+            title: title.into(),
+            details,
+            created_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum AlertSink {
+    /// Writes the alert to the service log; always available.
+    Log { name: String },
+    /// POSTs the alert as JSON to an HTTP endpoint (chat hooks, SOAR, paging).
+    Webhook { name: String, url: String },
+}
+
+impl AlertSink {
+    pub fn name(&self) -> &str {
+        match self {
+            AlertSink::Log { name } => name,
+            AlertSink::Webhook { name, .. } => name,
+        }
+    }
+}
+
+pub struct AlertingService {
+    sinks: Vec<AlertSink>,
+    client: reqwest::Client,
+    dead_letters: DeadLetterQueue,
+    credentials: Arc<CredentialCache>,
+}
+
+impl AlertingService {
+    pub fn new(
+        config: &Config,
+        dead_letters: DeadLetterQueue,
+        credentials: Arc<CredentialCache>,
+    ) -> Result<Self, SecurityError> {
+        let mut sinks = vec![AlertSink::Log { name: "log".to_string() }];
+        for (name, url) in &config.alerting.webhook_sinks {
+            sinks.push(AlertSink::Webhook {
+                name: name.clone(),
+                url: url.clone(),
+            });
+        }
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.alerting.timeout_secs))
+            .build()
+            .map_err(|e| SecurityError::ConfigError(format!("Alerting client: {}", e)))?;
+
+        info!("Alerting service initialized with {} sinks", sinks.len());
+        Ok(Self {
+            sinks,
+            client,
+            dead_letters,
+            credentials,
+        })
+    }
+
+    pub fn sink_names(&self) -> Vec<String> {
+        self.sinks.iter().map(|s| s.name().to_string()).collect()
+    }
+
+    /// Deliver to the named sinks, or to every sink when `targets` is empty.
+    /// Returns the names of sinks that failed.
+    pub async fn send(&self, alert: &Alert, targets: &[String]) -> Vec<String> {
+        let mut failed = Vec::new();
+
+        for sink in &self.sinks {
+            if !targets.is_empty() && !targets.iter().any(|t| t == sink.name()) {
+                continue;
+            }
+            if let Err(e) = self.deliver(sink, alert).await {
+                error!("Alert delivery to sink '{}' failed: {:?}", sink.name(), e);
+                self.dead_letter(sink, alert, &e).await;
+                failed.push(sink.name().to_string());
+            }
+        }
+
+        for target in targets {
+            if !self.sinks.iter().any(|s| s.name() == target) {
+                warn!("Alert addressed to unknown sink '{}'", target);
+            }
+        }
+
+        failed
+    }
+
+    async fn dead_letter(&self, sink: &AlertSink, alert: &Alert, error: &SecurityError) {
+        if let AlertSink::Webhook { url, .. } = sink {
+            let payload = match serde_json::to_value(alert) {
+                Ok(payload) => payload,
+                Err(_) => return,
+            };
+            if let Err(e) = self.dead_letters.push(DeliverySubsystem::Webhook, url, &payload, &error.to_string()).await {
+                error!("Failed to dead-letter alert for sink '{}': {:?}", sink.name(), e);
+            }
+        }
+    }
+
+    async fn deliver(&self, sink: &AlertSink, alert: &Alert) -> Result<(), SecurityError> {
+        match sink {
+            AlertSink::Log { .. } => {
+                warn!(
+                    source = %alert.source,
+                    severity = ?alert.severity,
+                    "ALERT: {}",
+                    alert.title
+                );
+                Ok(())
+            }
+            AlertSink::Webhook { name, url } => {
+                let request = self.credentials.apply(&format!("alerting.{}", name), self.client.post(url));
+                let response = deadline::outbound(request)
+                    .json(alert)
+                    .send()
+                    .await
+                    .map_err(|e| SecurityError::AlertError(e.to_string()))?;
+
+                if !response.status().is_success() {
+                    return Err(SecurityError::AlertError(format!("Sink returned {}", response.status())));
+                }
+                Ok(())
+            }
+        }
+    }
+}
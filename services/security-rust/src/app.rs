@@ -0,0 +1,888 @@
+/*!
+Application Module
+Service assembly shared by the standalone binary and embedding hosts
+
+Embedding inside an existing Actix service:
+
+```ignore
+let security = SecurityServiceBuilder::new(Config::from_env()?).build().await?;
+HttpServer::new(move || {
+    App::new()
+        .configure(|cfg| security.configure(cfg))
+        .service(my_routes())
+})
+```
+
+The security endpoints keep their own middleware pipeline (see
+`pipeline`), request deadlines (see `deadline`) and request metrics (see
+`monitoring`) under `/api/v1`; CORS, logging and health routes are left to
+the host (`health_check`, `readiness_check`, `crypto::jwks_handler` and
+`monitoring::metrics_handler` can be mounted anywhere).
+
+Everything the service would otherwise reach for on its own can be
+injected, which is what integration tests and the testkit rely on:
+
+- `storage`: a shared connection pool, already migrated. Default: one
+  pool from `DATABASE_URL`, retried while the database comes up.
+- `clock`: time source for expiry and rotation. Default: `SystemClock`.
+- `random`: randomness for keys and nonces, health tested whatever it is
+  (see `random`). Default: `SystemRandomSource`.
+- `key_provider`: backend wrapping data keys. Default: the one named by
+  `CRYPTO_KEY_PROVIDER` (see `key_provider`).
+- `secret_resolver`: backends for `scheme://` references in secret config
+  fields. Default: Vault and KMS when configured (see `secrets`).
+- `credential_rotator`: rotators for outbound credentials. Default:
+  `oauth2` and `http` (see `credentials`).
+*/
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::middleware::{from_fn, Compress, Condition, Logger, Next};
+use actix_web::{web, App, Error};
+use actix_cors::Cors;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use crate::alerting::AlertingService;
+use crate::audit::{self, siem::SiemExporter, AuditService};
+use crate::auth::access_reviews::{self, AccessReviewService};
+use crate::auth::dormancy::{self, DormancyService};
+use crate::s3_gateway::{self, S3Gateway};
+use crate::email::{self, EmailService};
+use crate::domains::{self, DomainVerificationService};
+use crate::auth::api_keys::{self, ApiKeyRing, ApiKeyService};
+use crate::auth::captcha::CaptchaService;
+use crate::auth::password::PasswordService;
+use crate::auth::consent::ConsentService;
+use crate::auth::break_glass::{self, BreakGlassService};
+use crate::auth::ssh_ca::{self, SshCaService};
+use crate::auth::elevation::{self, ElevationGrants, ElevationService};
+use crate::auth::otp::OtpService;
+use crate::auth::service_accounts::{self, ServiceAccountService};
+use crate::auth::sessions::{self, SessionService};
+use crate::auth::tokens::{self, RevocationList, TokenService};
+use crate::auth::{self, AuthService};
+use crate::changes::{self, ChangeHistory};
+use crate::clock::{Clock, SystemClock};
+use crate::config::{Config, LiveConfig};
+use crate::containment::{self, ContainmentService, Denylist};
+use crate::credentials::{self, CredentialCache, CredentialRotator, CredentialRotators, OutboundCredentials};
+use crate::crypto::bulk::BulkDecryption;
+use crate::crypto::webhook::WebhookSigner;
+use crate::crypto::guard::{self, ReadGuard};
+use crate::crypto::tokenization::TokenVault;
+use crate::crypto::{self, CryptoService};
+use crate::deadline;
+use crate::degraded::{self, DependencyMonitor};
+use crate::delivery::{self, DeliveryService};
+use crate::detection::{self, CorrelationEngine, IncidentService, UebaService, UsageBaselines};
+use crate::dlq::{self, DeadLetterQueue};
+use crate::errors::SecurityError;
+use crate::events::{self, EventBus, EventPublisher};
+use crate::experiments::{self, ExperimentService};
+use crate::expiry::{self, ExpiryRegistry, ExpirySourceFn, ExpiryService};
+use crate::flags::{self, FeatureFlags};
+use crate::health::{CheckFn, Criticality, HealthRegistry};
+use crate::hooks::RequestHooks;
+use crate::key_compromise::{self, KeyCompromiseService};
+use crate::key_provider::{self, KeyProvider};
+use crate::maintenance::{self, MaintenanceService};
+use crate::authz::{self, AuthzService};
+use crate::custody::{self, CustodyService};
+use crate::retention::{self, RetentionService};
+use crate::whistleblower::{self, WhistleblowerService};
+use crate::mailbox::{self, MailboxService};
+use crate::commands::{self, CommandLog};
+use crate::forensics::{self, ForensicStore};
+use crate::manifests::{self, ManifestService};
+use crate::quorum::{self, QuorumService};
+use crate::submissions::{self, SubmissionService};
+use crate::collusion::{self, CollusionService};
+use crate::dossiers::{self, DossierService};
+use crate::residency::{self, ResidencyService};
+use crate::key_archive::{self, KeyArchiveService};
+use crate::billing::{self, BillingService};
+use crate::jobs::{self, JobService};
+use crate::notary::{self, NotaryService};
+use crate::org::{self, OrgService};
+use crate::registry::{self, ResourceRegistry};
+use crate::search::{self, SearchService};
+use crate::i18n::I18n;
+use crate::privacy::NoiseLayer;
+use crate::problem;
+use crate::echo;
+use crate::status_page::{self, StatusPage};
+use crate::backups::{self, BackupService};
+use crate::bundles::{self, ConfigBundles};
+use crate::monitoring::threats::{self, ThreatEngine};
+use crate::monitoring::{self, MetricsService};
+use crate::network::ClientIps;
+use crate::pipeline::Pipeline;
+use crate::plugins::{self, PluginHost};
+use crate::policies::{self, PolicyService};
+use crate::random::{self, HealthTestedSource, RandomSource, SystemRandomSource};
+use crate::seal::{self, SealService};
+use crate::sod::{self, SodService};
+use crate::secrets::{SecretResolver, SecretResolvers};
+use crate::soar::{self, SoarService};
+use crate::rate_limiting::RateLimiter;
+use crate::startup::{self, StartupReport};
+use crate::storage::{self, Storage};
+use crate::tenant_settings::{self, TenantSettingsService};
+use crate::{bulk, expr, soft_delete, validation, AppState};
+
+pub struct SecurityServiceBuilder {
+    config: Config,
+    background_jobs: bool,
+    storage: Option<Storage>,
+    clock: Arc<dyn Clock>,
+    random: Arc<dyn RandomSource>,
+    key_provider: Option<Box<dyn KeyProvider>>,
+    secret_resolvers: SecretResolvers,
+    credential_rotators: CredentialRotators,
+    health: HealthRegistry,
+    expiry_sources: ExpiryRegistry,
+}
+
+impl SecurityServiceBuilder {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            background_jobs: true,
+            storage: None,
+            clock: Arc::new(SystemClock),
+            random: Arc::new(SystemRandomSource::default()),
+            key_provider: None,
+            secret_resolvers: SecretResolvers::default(),
+            credential_rotators: CredentialRotators::default(),
+            health: HealthRegistry::default(),
+            expiry_sources: ExpiryRegistry::default(),
+        }
+    }
+
+    pub fn storage(mut self, storage: Storage) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn random(mut self, random: Arc<dyn RandomSource>) -> Self {
+        self.random = random;
+        self
+    }
+
+    pub fn key_provider(mut self, provider: Box<dyn KeyProvider>) -> Self {
+        self.key_provider = Some(provider);
+        self
+    }
+
+    /// Resolver for `scheme://` references in secret config fields.
+    /// Reusing `vault` or `kms` replaces the built-in backend.
+    pub fn secret_resolver(mut self, scheme: &str, resolver: Arc<dyn SecretResolver>) -> Self {
+        self.secret_resolvers.register(scheme, resolver);
+        self
+    }
+
+    /// Rotator for outbound credentials that name it. Reusing `oauth2` or
+    /// `http` replaces the built-in one.
+    pub fn credential_rotator(mut self, name: &str, rotator: Arc<dyn CredentialRotator>) -> Self {
+        self.credential_rotators.register(name, rotator);
+        self
+    }
+
+    /// Extra readiness check, e.g. for a host's own dependency. Reusing a
+    /// built-in name replaces that check.
+    pub fn health_check(mut self, name: &'static str, criticality: Criticality, check: CheckFn) -> Self {
+        self.health.register(name, criticality, check);
+        self
+    }
+
+    /// Extra source of expiring artifacts for `/admin/expiry`. Reusing a
+    /// built-in name replaces that source.
+    pub fn expiry_source(mut self, name: &'static str, source: ExpirySourceFn) -> Self {
+        self.expiry_sources.register(name, source);
+        self
+    }
+
+    /// Skip refresh loops, schedulers and probes. Only for hosts that run
+    /// another instance with them enabled against the same storage.
+    pub fn background_jobs(mut self, enabled: bool) -> Self {
+        self.background_jobs = enabled;
+        self
+    }
+
+    /// Initialize every service, dependencies first, on one shared storage
+    /// pool. Components retry while the database comes up instead of
+    /// failing immediately.
+    pub async fn build(self) -> Result<SecurityService, SecurityError> {
+        let mut config = self.config;
+
+        // Before touching any dependency: a bad pipeline must not reach traffic
+        let pipeline = Pipeline::from_config(&config).map_err(|e| failed("middleware pipeline", e))?;
+        info!("Middleware pipeline: {}", pipeline.stages().join(" > "));
+
+        // Every component below reads secrets from config
+        let mut resolvers = SecretResolvers::from_config(&config.secrets, self.clock.clone()).map_err(|e| failed("secrets", e))?;
+        resolvers.extend(self.secret_resolvers);
+        resolvers.resolve_config(&mut config).await.map_err(|e| failed("secrets", e))?;
+        config.validate().map_err(|e| failed("configuration", e))?;
+
+        // Keys and nonces are drawn from here on; a failing source stops startup
+        let random_health = Arc::new(HealthTestedSource::new(&config.rng, self.random, self.clock.clone()).map_err(|e| failed("random source", e))?);
+        let random: Arc<dyn RandomSource> = random_health.clone();
+
+        let retry = &config.startup;
+        let report = StartupReport::default();
+
+        let storage = match self.storage {
+            Some(storage) => storage,
+            None => startup::init(retry, &report, "storage", || Storage::new(&config)).await
+                .map_err(|e| failed("storage", e))?,
+        };
+        let key_provider: Arc<dyn KeyProvider> = match self.key_provider {
+            Some(provider) => Arc::from(provider),
+            None => key_provider::from_config(&config, random.clone(), self.clock.clone()).map_err(|e| failed("key provider", e))?,
+        };
+
+        let crypto_service = startup::init(retry, &report, "crypto", || {
+            CryptoService::new(&config, key_provider.clone(), storage.clone(), self.clock.clone(), random.clone())
+        }).await
+            .map_err(|e| failed("crypto", e))?;
+
+        // Shared so containment holds take effect at authentication
+        let denylist = Arc::new(Denylist::default());
+        // Shared so revocations take effect at authentication
+        let revocations = Arc::new(RevocationList::default());
+        // Shared so minted and revoked API keys take effect at authentication
+        let api_key_ring = Arc::new(ApiKeyRing::default());
+        // Shared so elevations take effect at authentication
+        let elevation_grants = Arc::new(ElevationGrants::new(&config.elevation));
+        // Shared so stored and rotated credentials reach outbound calls
+        let credential_cache = Arc::new(CredentialCache::default());
+
+        let jwks = crypto_service.signing_keys().jwks();
+        let auth_service = startup::init(retry, &report, "auth", || {
+            AuthService::new(
+                &config,
+                self.clock.clone(),
+                denylist.clone(),
+                revocations.clone(),
+                api_key_ring.clone(),
+                elevation_grants.clone(),
+                &jwks,
+            )
+        }).await
+            .map_err(|e| failed("auth", e))?;
+
+        let tokens = startup::init(retry, &report, "tokens", || {
+            TokenService::new(&config, storage.clone(), self.clock.clone(), random.clone(), revocations.clone())
+        }).await
+            .map_err(|e| failed("token service", e))?;
+
+        let sessions = startup::init(retry, &report, "sessions", || SessionService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("session service", e))?;
+
+        let metrics_service = startup::init(retry, &report, "metrics", || MetricsService::new(&config)).await
+            .map_err(|e| failed("metrics", e))?;
+
+        let threats = startup::init(retry, &report, "threats", || ThreatEngine::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("threat engine", e))?;
+
+        let rate_limiter = RateLimiter::new(&config, self.clock.clone())
+            .map_err(|e| failed("rate limiter", e))?;
+
+        let event_publisher = EventPublisher::new(&config)
+            .map_err(|e| failed("event publisher", e))?;
+
+        let audit_service = startup::init(retry, &report, "audit", || AuditService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("audit", e))?;
+
+        let dead_letters = startup::init(retry, &report, "dlq", || DeadLetterQueue::new(&config, storage.clone(), event_publisher.clone())).await
+            .map_err(|e| failed("dead-letter queue", e))?;
+
+        let event_bus = startup::init(retry, &report, "events", || EventBus::new(&config, storage.clone(), event_publisher.clone())).await
+            .map_err(|e| failed("event bus", e))?;
+
+        let alerting_service = AlertingService::new(&config, dead_letters.clone(), credential_cache.clone())
+            .map_err(|e| failed("alerting", e))?;
+
+        let policy_service = startup::init(retry, &report, "policies", || PolicyService::new(storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("policy service", e))?;
+
+        let change_history = startup::init(retry, &report, "changes", || ChangeHistory::new(storage.clone())).await
+            .map_err(|e| failed("change history", e))?;
+
+        let feature_flags = startup::init(retry, &report, "feature_flags", || FeatureFlags::new(storage.clone())).await
+            .map_err(|e| failed("feature flags", e))?;
+
+        let experiments = startup::init(retry, &report, "experiments", || ExperimentService::new(storage.clone())).await
+            .map_err(|e| failed("experiment service", e))?;
+
+        let maintenance = startup::init(retry, &report, "maintenance", || MaintenanceService::new(storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("maintenance service", e))?;
+
+        let tenant_settings = startup::init(retry, &report, "tenant_settings", || TenantSettingsService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("tenant settings", e))?;
+
+        let incidents = startup::init(retry, &report, "incidents", || IncidentService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("incident service", e))?;
+
+        let correlation = startup::init(retry, &report, "correlation", || CorrelationEngine::new(&config, storage.clone())).await
+            .map_err(|e| failed("correlation engine", e))?;
+
+        let ueba = startup::init(retry, &report, "ueba", || UebaService::new(&config, storage.clone())).await
+            .map_err(|e| failed("UEBA service", e))?;
+
+        let usage = startup::init(retry, &report, "usage", || UsageBaselines::new(&config, storage.clone())).await
+            .map_err(|e| failed("usage baselines", e))?;
+
+        let containment = startup::init(retry, &report, "containment", || ContainmentService::new(&config, storage.clone(), denylist.clone(), self.clock.clone())).await
+            .map_err(|e| failed("containment service", e))?;
+
+        let soar = startup::init(retry, &report, "soar", || SoarService::new(&config, storage.clone(), self.clock.clone(), credential_cache.clone())).await
+            .map_err(|e| failed("SOAR service", e))?;
+
+        let expiry = startup::init(retry, &report, "expiry", || ExpiryService::new(&config, storage.clone())).await
+            .map_err(|e| failed("expiry service", e))?;
+
+        let key_compromises = startup::init(retry, &report, "key_compromises", || KeyCompromiseService::new(storage.clone())).await
+            .map_err(|e| failed("key compromise service", e))?;
+
+        let api_keys = startup::init(retry, &report, "api_keys", || ApiKeyService::new(&config, storage.clone(), self.clock.clone(), api_key_ring.clone())).await
+            .map_err(|e| failed("API key service", e))?;
+
+        let service_accounts = startup::init(retry, &report, "service_accounts", || ServiceAccountService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("service account service", e))?;
+
+        let consents = startup::init(retry, &report, "consents", || ConsentService::new(storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("consent service", e))?;
+
+        let mut rotators = CredentialRotators::built_in();
+        rotators.extend(self.credential_rotators);
+        let credentials = startup::init(retry, &report, "outbound_credentials", || {
+            OutboundCredentials::new(&config, storage.clone(), self.clock.clone(), rotators.clone(), credential_cache.clone())
+        }).await
+            .map_err(|e| failed("outbound credentials", e))?;
+
+        let siem = startup::init(retry, &report, "siem", || SiemExporter::new(&config, storage.clone(), credential_cache.clone())).await
+            .map_err(|e| failed("SIEM exporter", e))?;
+
+        let delivery = startup::init(retry, &report, "delivery", || DeliveryService::new(&config, storage.clone(), self.clock.clone(), credential_cache.clone())).await
+            .map_err(|e| failed("delivery service", e))?;
+
+        let otp = startup::init(retry, &report, "otp", || OtpService::new(&config, storage.clone(), self.clock.clone(), random.clone())).await
+            .map_err(|e| failed("OTP service", e))?;
+
+        let passwords = startup::init(retry, &report, "passwords", || PasswordService::new(&config, self.clock.clone())).await
+            .map_err(|e| failed("password service", e))?;
+
+        let captcha = startup::init(retry, &report, "captcha", || CaptchaService::new(&config, storage.clone(), self.clock.clone(), credential_cache.clone())).await
+            .map_err(|e| failed("CAPTCHA service", e))?;
+
+        let notary = startup::init(retry, &report, "notary", || NotaryService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("notary service", e))?;
+
+        let seal = startup::init(retry, &report, "seal", || SealService::new(&config, storage.clone(), self.clock.clone(), credential_cache.clone())).await
+            .map_err(|e| failed("seal service", e))?;
+
+        let manifests = startup::init(retry, &report, "manifests", || ManifestService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("manifest service", e))?;
+        let quorum = startup::init(retry, &report, "quorum", || QuorumService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("quorum service", e))?;
+        let submissions = startup::init(retry, &report, "submissions", || SubmissionService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("submission service", e))?;
+        let collusion = startup::init(retry, &report, "collusion", || CollusionService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("collusion screening", e))?;
+        let dossiers = startup::init(retry, &report, "dossiers", || DossierService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("dossier service", e))?;
+        let residency = startup::init(retry, &report, "residency", || ResidencyService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("residency service", e))?;
+        let key_archive = startup::init(retry, &report, "key_archive", || KeyArchiveService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("key archive service", e))?;
+        let billing = startup::init(retry, &report, "billing", || BillingService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("billing service", e))?;
+        let jobs = startup::init(retry, &report, "jobs", || JobService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("job service", e))?;
+
+        let custody = startup::init(retry, &report, "custody", || CustodyService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("custody service", e))?;
+
+        let authz = startup::init(retry, &report, "authz", || AuthzService::new(&config)).await
+            .map_err(|e| failed("authz service", e))?;
+
+        let sod = startup::init(retry, &report, "sod", || SodService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("separation of duties", e))?;
+
+        let org = startup::init(retry, &report, "org", || OrgService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("org service", e))?;
+
+        let resource_registry = startup::init(retry, &report, "resource_registry", || ResourceRegistry::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("resource registry", e))?;
+
+        let search = startup::init(retry, &report, "search", || SearchService::new(&config, storage.clone())).await
+            .map_err(|e| failed("admin search", e))?;
+
+        let status_page = startup::init(retry, &report, "status_page", || StatusPage::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("status page", e))?;
+
+        let bundles = startup::init(retry, &report, "bundles", || ConfigBundles::new(&config, key_provider.clone(), storage.clone())).await
+            .map_err(|e| failed("configuration bundles", e))?;
+
+        let client_ips = ClientIps::new(&config).map_err(|e| failed("client addresses", e))?;
+
+        let i18n = I18n::new(&config).map_err(|e| failed("message catalogs", e))?;
+
+        let noise = NoiseLayer::new(&config);
+
+        let request_hooks = startup::init(retry, &report, "request_hooks", || RequestHooks::new(&config)).await
+            .map_err(|e| failed("request hooks", e))?;
+
+        let token_vault = startup::init(retry, &report, "token_vault", || TokenVault::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("token vault", e))?;
+
+        let crypto_guard = startup::init(retry, &report, "crypto_guard", || ReadGuard::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("read volume guard", e))?;
+
+        let bulk_decrypt = startup::init(retry, &report, "bulk_decrypt", || BulkDecryption::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("bulk decryption", e))?;
+
+        let webhooks = WebhookSigner::new(&config, self.clock.clone(), credential_cache.clone());
+
+        let plugins = PluginHost::new(&config, storage.clone(), self.clock.clone())
+            .map_err(|e| failed("plugin host", e))?;
+
+        let retention = startup::init(retry, &report, "retention", || RetentionService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("retention service", e))?;
+
+        let whistleblower = startup::init(retry, &report, "whistleblower", || WhistleblowerService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("whistleblower service", e))?;
+
+        let mailbox = startup::init(retry, &report, "mailbox", || MailboxService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("mailbox service", e))?;
+
+        let forensics = startup::init(retry, &report, "forensics", || ForensicStore::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("forensic store", e))?;
+
+        let break_glass = startup::init(retry, &report, "break_glass", || BreakGlassService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("break-glass service", e))?;
+        let ssh_ca = startup::init(retry, &report, "ssh_ca", || SshCaService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("SSH CA", e))?;
+
+        let elevations = startup::init(retry, &report, "elevations", || {
+            ElevationService::new(&config, storage.clone(), self.clock.clone(), elevation_grants.clone())
+        }).await
+            .map_err(|e| failed("elevation service", e))?;
+
+        let access_reviews = startup::init(retry, &report, "access_reviews", || AccessReviewService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("access review service", e))?;
+
+        let dormancy = startup::init(retry, &report, "dormancy", || DormancyService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("dormancy service", e))?;
+        let s3_gateway = startup::init(retry, &report, "s3_gateway", || S3Gateway::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("S3 gateway", e))?;
+        let email = startup::init(retry, &report, "email", || EmailService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("email authentication", e))?;
+        let domains = startup::init(retry, &report, "domains", || DomainVerificationService::new(&config, storage.clone(), self.clock.clone())).await
+            .map_err(|e| failed("domain verification", e))?;
+        let backups = startup::init(retry, &report, "backups", || {
+            BackupService::new(&config, key_provider.clone(), storage.clone(), self.clock.clone())
+        }).await
+            .map_err(|e| failed("backups", e))?;
+
+        let command_log = startup::init(retry, &report, "commands", || CommandLog::new(&config, storage.clone())).await
+            .map_err(|e| failed("command log", e))?;
+
+        // Built-in checks first so host-registered ones can replace them
+        let mut health = HealthRegistry::default();
+        storage::register_health_checks(&mut health);
+        crypto::register_health_checks(&mut health);
+        random::register_health_checks(&mut health);
+        auth::register_health_checks(&mut health);
+        audit::register_health_checks(&mut health);
+        events::register_health_checks(&mut health);
+        delivery::register_health_checks(&mut health);
+        health.extend(self.health);
+
+        let mut expiry_sources = ExpiryRegistry::default();
+        expiry::register_expiry_sources(&mut expiry_sources);
+        service_accounts::register_expiry_sources(&mut expiry_sources);
+        api_keys::register_expiry_sources(&mut expiry_sources);
+        credentials::register_expiry_sources(&mut expiry_sources);
+        expiry_sources.extend(self.expiry_sources);
+
+        // Caches can serve empty until their refresh loops catch up
+        startup::warm(&report, "feature_flags", feature_flags.refresh()).await;
+        startup::warm(&report, "experiments", experiments.refresh()).await;
+        startup::warm(&report, "maintenance", maintenance.refresh()).await;
+        startup::warm(&report, "containment", containment.refresh()).await;
+        startup::warm(&report, "crypto_guard", crypto_guard.refresh()).await;
+        startup::warm(&report, "threats", threats.refresh()).await;
+        startup::warm(&report, "token_revocations", tokens.refresh_revocations()).await;
+        startup::warm(&report, "api_keys", api_keys.refresh()).await;
+        startup::warm(&report, "outbound_credentials", credentials.refresh(&crypto_service)).await;
+        startup::warm(&report, "plugins", plugins.refresh()).await;
+
+        let live_config = LiveConfig::new(config.clone());
+        let state = web::Data::new(AppState {
+            config,
+            live_config,
+            storage,
+            clock: self.clock,
+            random,
+            random_health,
+            crypto_service,
+            auth_service,
+            tokens,
+            sessions,
+            audit_service,
+            metrics_service,
+            threats,
+            rate_limiter,
+            alerting_service,
+            dead_letters,
+            event_bus,
+            policy_service,
+            change_history,
+            feature_flags,
+            experiments,
+            maintenance,
+            tenant_settings,
+            incidents,
+            correlation,
+            ueba,
+            usage,
+            containment,
+            soar,
+            key_compromises,
+            service_accounts,
+            api_keys,
+            consents,
+            otp,
+            captcha,
+            passwords,
+            notary,
+            seal,
+            manifests,
+            quorum,
+            submissions,
+            collusion,
+            dossiers,
+            residency,
+            key_archive,
+            billing,
+            jobs,
+            custody,
+            authz,
+            sod,
+            org,
+            resource_registry,
+            search,
+            i18n,
+            status_page,
+            bundles,
+            token_vault,
+            crypto_guard,
+            bulk_decrypt,
+            webhooks,
+            plugins,
+            request_hooks,
+            client_ips,
+            noise,
+            retention,
+            whistleblower,
+            mailbox,
+            forensics,
+            command_log,
+            break_glass,
+            ssh_ca,
+            elevations,
+            access_reviews,
+            dormancy,
+            s3_gateway,
+            email,
+            domains,
+            backups,
+            credentials,
+            siem,
+            delivery,
+            expiry,
+            expiry_sources,
+            startup: report,
+            health,
+            dependencies: DependencyMonitor::default(),
+        });
+
+        if self.background_jobs {
+            spawn_background_jobs(&state);
+        }
+
+        info!("Security service assembled");
+        Ok(SecurityService { state, pipeline: Arc::new(pipeline) })
+    }
+}
+
+fn failed(component: &str, e: SecurityError) -> SecurityError {
+    error!("Failed to initialize {}: {}", component, e);
+    e
+}
+
+fn spawn_background_jobs(state: &web::Data<AppState>) {
+    tokio::spawn(audit::saved_searches::run_scheduler(state.clone()));
+    tokio::spawn(audit::integrity::run_monitor(state.clone()));
+    tokio::spawn(audit::chain::run_chain(state.clone()));
+    tokio::spawn(audit::siem::run_export(state.clone()));
+    tokio::spawn(audit::retention::run_retention(state.clone()));
+    tokio::spawn(events::run_relay(state.clone()));
+    tokio::spawn(soft_delete::run_purge(state.clone()));
+    tokio::spawn(retention::run_enforcement(state.clone()));
+    tokio::spawn(mailbox::run_delivery(state.clone()));
+    tokio::spawn(forensics::run_writer(state.clone()));
+    tokio::spawn(flags::run_refresh(state.clone()));
+    tokio::spawn(bundles::run_watch(state.clone()));
+    tokio::spawn(experiments::run_refresh(state.clone()));
+    tokio::spawn(maintenance::run_refresh(state.clone()));
+    tokio::spawn(degraded::run_probe(state.clone()));
+    tokio::spawn(detection::correlation::run_engine(state.clone()));
+    tokio::spawn(detection::ueba::run_scoring(state.clone()));
+    tokio::spawn(detection::usage::run_scoring(state.clone()));
+    tokio::spawn(containment::run_refresh(state.clone()));
+    tokio::spawn(guard::run_sync(state.clone()));
+    tokio::spawn(tokens::run_revocation_refresh(state.clone()));
+    tokio::spawn(break_glass::run_expiry(state.clone()));
+    tokio::spawn(ssh_ca::run_setup(state.clone()));
+    tokio::spawn(elevation::run_refresh(state.clone()));
+    tokio::spawn(access_reviews::run_campaigns(state.clone()));
+    tokio::spawn(dormancy::run_scans(state.clone()));
+    tokio::spawn(email::run_rotation(state.clone()));
+    tokio::spawn(domains::run_checks(state.clone()));
+    tokio::spawn(backups::run_backups(state.clone()));
+    tokio::spawn(backups::run_drills(state.clone()));
+    tokio::spawn(sessions::run_expiry(state.clone()));
+    tokio::spawn(api_keys::run_refresh(state.clone()));
+    tokio::spawn(plugins::run_refresh(state.clone()));
+    tokio::spawn(credentials::run_rotation(state.clone()));
+    tokio::spawn(soar::run_delivery(state.clone()));
+    tokio::spawn(crypto::run_key_maintenance(state.clone()));
+    tokio::spawn(random::run_monitor(state.clone()));
+    tokio::spawn(expiry::run_scan(state.clone()));
+    tokio::spawn(seal::run_queue(state.clone()));
+    tokio::spawn(key_archive::run_archival(state.clone()));
+    tokio::spawn(billing::run_billing(state.clone()));
+    tokio::spawn(jobs::run_jobs(state.clone()));
+    tokio::spawn(threats::run_engine(state.clone()));
+}
+
+/// Write out what is still queued once the listeners have stopped: audit
+/// events buffered while storage was down, the SIEM export backlog, data
+/// key usage counts, endpoint request counts and billing totals. Gives up after `SERVER_SHUTDOWN_TIMEOUT_SECS`.
+pub async fn drain(state: &AppState) {
+    let work = async {
+        match state.audit_service.flush_buffer().await {
+            Ok(0) => {}
+            Ok(flushed) => info!("Wrote {} buffered audit events", flushed),
+            Err(e) => error!(
+                "Failed to write buffered audit events, {} lost: {:?}",
+                state.audit_service.buffered(),
+                e
+            ),
+        }
+        state.siem.drain().await;
+        if let Err(e) = state.crypto_service.flush_usage().await {
+            warn!("Failed to record data key usage: {:?}", e);
+        }
+        if let Err(e) = state.usage.flush().await {
+            warn!("Failed to record endpoint usage: {:?}", e);
+        }
+        if let Err(e) = state.billing.flush().await {
+            warn!("Failed to record billing usage: {:?}", e);
+        }
+    };
+    let timeout = Duration::from_secs(state.config.server.shutdown_timeout_secs);
+    if tokio::time::timeout(timeout, work).await.is_err() {
+        error!("Gave up draining after {}s", timeout.as_secs());
+    }
+}
+
+/// A fully initialized service. Cheap to clone into each worker's app factory.
+#[derive(Clone)]
+pub struct SecurityService {
+    state: web::Data<AppState>,
+    pipeline: Arc<Pipeline>,
+}
+
+impl SecurityService {
+    pub fn state(&self) -> web::Data<AppState> {
+        self.state.clone()
+    }
+
+    /// Register the `/api/v1` scope, with its own state and middleware,
+    /// on a host app.
+    pub fn configure(&self, cfg: &mut web::ServiceConfig) {
+        let state = self.state.clone();
+        let pipeline = self.pipeline.clone();
+        let compress = self.state.config.server.compression;
+        let metrics_state = self.state.clone();
+        let command_state = self.state.clone();
+        let problem_state = self.state.clone();
+        let billing_state = self.state.clone();
+
+        cfg.service(
+            web::scope("/api/v1")
+                .app_data(self.state.clone())
+                // Innermost, so only requests the pipeline let through are
+                // recorded, with the response their handler gave
+                .wrap(from_fn(move |req: ServiceRequest, next: Next<BoxBody>| {
+                    commands::record(command_state.clone(), req, next)
+                }))
+                .wrap(from_fn(move |mut req: ServiceRequest, next: Next<BoxBody>| {
+                    let pipeline = pipeline.clone();
+                    let state = state.clone();
+                    async move {
+                        // Stages may await (CAPTCHA checks), so this runs as
+                        // a future rather than deciding up front
+                        if let Some((ran, response)) = pipeline.before(&state, &mut req).await {
+                            return Ok(pipeline.after(ran, req.into_response(response)));
+                        }
+                        let budget = match deadline::budget(req.headers(), state.clock.now(), &state.config.deadline) {
+                            Ok(Some(budget)) if budget.is_zero() => {
+                                return Ok(pipeline.after(pipeline.len(), req.into_response(deadline::exceeded())));
+                            }
+                            Ok(budget) => budget,
+                            Err(message) => {
+                                let response = deadline::rejected(message);
+                                return Ok(pipeline.after(pipeline.len(), req.into_response(response)));
+                            }
+                        };
+
+                        let request = req.request().clone();
+                        let call = next.call(req);
+                        let res = match budget {
+                            None => call.await?,
+                            Some(budget) => match deadline::run(budget, call).await {
+                                Some(res) => res?,
+                                None => ServiceResponse::new(request, deadline::exceeded()),
+                            },
+                        };
+                        Ok::<_, Error>(pipeline.after(pipeline.len(), res))
+                    }
+                }))
+                // Charges whatever the handler did, even past its deadline
+                .wrap(from_fn(move |req: ServiceRequest, next: Next<BoxBody>| {
+                    billing::meter(billing_state.clone(), req, next)
+                }))
+                // Outside the pipeline, so its rejections are rewritten too
+                .wrap(from_fn(move |req: ServiceRequest, next: Next<BoxBody>| {
+                    problem::render(problem_state.clone(), req, next)
+                }))
+                .wrap(Condition::new(compress, Compress::default()))
+                // Outermost, so early rejections and timeouts are counted too
+                .wrap(from_fn(move |req: ServiceRequest, next: Next<_>| {
+                    monitoring::track(metrics_state.clone(), req, next)
+                }))
+                .configure(crypto::configure_routes)
+                .configure(auth::configure_routes)
+                .configure(audit::configure_routes)
+                .configure(monitoring::configure_routes)
+                .configure(dlq::configure_routes)
+                .configure(bulk::configure_routes)
+                .configure(policies::configure_routes)
+                .configure(changes::configure_routes)
+                .configure(flags::configure_routes)
+                .configure(experiments::configure_routes)
+                .configure(maintenance::configure_routes)
+                .configure(tenant_settings::configure_routes)
+                .configure(detection::configure_routes)
+                .configure(containment::configure_routes)
+                .configure(soar::configure_routes)
+                .configure(key_archive::configure_routes)
+                .configure(billing::configure_routes)
+                .configure(jobs::configure_routes)
+                .configure(key_compromise::configure_routes)
+                .configure(expiry::configure_routes)
+                .configure(credentials::configure_routes)
+                .configure(delivery::configure_routes)
+                .configure(notary::configure_routes)
+                .configure(seal::configure_routes)
+                .configure(manifests::configure_routes)
+                .configure(quorum::configure_routes)
+                .configure(submissions::configure_routes)
+                .configure(collusion::configure_routes)
+                .configure(dossiers::configure_routes)
+                .configure(residency::configure_routes)
+                .configure(custody::configure_routes)
+                .configure(authz::configure_routes)
+                .configure(sod::configure_routes)
+                .configure(org::configure_routes)
+                .configure(registry::configure_routes)
+                .configure(search::configure_routes)
+                .configure(echo::configure_routes)
+                .configure(bundles::configure_routes)
+                .configure(expr::configure_routes)
+                .configure(plugins::configure_routes)
+                .configure(retention::configure_routes)
+                .configure(s3_gateway::configure_routes)
+                .configure(email::configure_routes)
+                .configure(domains::configure_routes)
+                .configure(backups::configure_routes)
+                .configure(whistleblower::configure_routes)
+                .configure(mailbox::configure_routes)
+                .configure(forensics::configure_routes)
+                .configure(commands::configure_routes)
+                .configure(validation::configure_routes),
+        );
+    }
+
+    /// The standalone app: the API plus CORS, request logging, the health
+    /// routes and `/metrics`.
+    pub fn build_app(
+        &self,
+    ) -> App<
+        impl ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<impl MessageBody>,
+            Error = Error,
+            InitError = (),
+        >,
+    > {
+        let middleware = &self.state.config.middleware;
+        let live = self.state.clone();
+
+        App::new()
+            .app_data(self.state.clone())
+            // Whistleblower requests would tie reporters to their addresses
+            .wrap(Condition::new(
+                middleware.request_log,
+                Logger::default().exclude_regex(format!("^{}", whistleblower::PATH_PREFIX)),
+            ))
+            .wrap(Condition::new(
+                middleware.cors,
+                Cors::default()
+                    // Reloadable, so looked up on every request
+                    .allowed_origin_fn(move |origin, _req_head| {
+                        let origins = &live.live_config.current().middleware.cors_origins;
+                        if origins.is_empty() {
+                            return origin.as_bytes().starts_with(b"https://");
+                        }
+                        origins.iter().any(|allowed| origin.as_bytes() == allowed.as_bytes())
+                    })
+                    .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
+                    .allowed_headers(vec!["Authorization", "Content-Type", "If-Match", whistleblower::FOLLOWUP_HEADER])
+                    // Browsers hide response headers not listed, and
+                    // conditional updates need the entity tag
+                    .expose_headers(vec!["ETag"])
+                    .max_age(3600),
+            ))
+            .route("/health", web::get().to(crate::health_check))
+            .route("/ready", web::get().to(crate::readiness_check))
+            .configure(status_page::configure_routes)
+            .route("/.well-known/jwks.json", web::get().to(crypto::jwks_handler))
+            .route("/metrics", web::get().to(monitoring::metrics_handler))
+            .configure(|cfg| self.configure(cfg))
+    }
+}
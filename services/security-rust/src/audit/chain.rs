@@ -0,0 +1,472 @@
+/*!
+Audit Chain
+Hash chain and signed checkpoints over audit events
+
+Events are written without coordination and linked shortly after, every
+`AUDIT_CHAIN_INTERVAL_SECS`, in the order they are picked up: each gets the
+next `chain_index`, the previous event's hash, and
+`chain_hash = SHA-256(prev_hash "\n" record)` over its stored fields.
+Events that existed before the chain are linked the same way, oldest
+first. Replicas take turns through a lock on the chain head.
+
+Once new events are linked and `AUDIT_CHECKPOINT_INTERVAL_SECS` has passed,
+the head is signed with the `AUDIT_CHECKPOINT_ALGORITHM` signing key. A
+checkpoint pins everything before it: rewriting the chain would need the
+private key.
+
+`GET /audit/verify` walks the chain, optionally from `from` to `to`, and
+reports the first break:
+
+- `missing`: an index is absent, i.e. an event was deleted or the chain
+  stops short of a checkpoint
+- `relinked`: the stored previous hash is not the previous event's
+- `modified`: the event no longer hashes to its stored hash
+- `checkpoint_mismatch`: the chain does not reach a checkpoint's hash
+- `checkpoint_forged`: a checkpoint's signature does not verify
+
+Checkpoints signed by keys that have since been retired cannot be checked
+and are counted as unverifiable rather than failed.
+Events removed by retention (see `retention`) do not count as missing:
+the walk starts from the hash the chain was cut at.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, SecondsFormat, SubsecRound, Utc};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::alerting::{Alert, Severity};
+use crate::auth::auth_error_response;
+use crate::crypto::{CryptoService, VerifyRequest};
+use crate::errors::SecurityError;
+use crate::signing::{Algorithm, AUDIT_CHECKPOINT_DOMAIN};
+use super::{AuditEvent, AuditService, NewAuditEvent, EVENT_COLUMNS};
+
+/// Previous hash of the first event.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Who chain breaks are audited as.
+const SYSTEM_ACTOR: &str = "system:audit_chain";
+
+pub(crate) const CHECKPOINT_COLUMNS: &str = "id, chain_index, chain_hash, algorithm, key_id, signature, created_at";
+
+/// Events read per query while verifying.
+const VERIFY_PAGE_SIZE: i64 = 5000;
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Checkpoint {
+    pub id: Uuid,
+    pub chain_index: i64,
+    pub chain_hash: String,
+    pub algorithm: String,
+    pub key_id: String,
+    pub signature: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Checkpoint {
+    /// The signed statement.
+    fn message(chain_index: i64, chain_hash: &str, created_at: DateTime<Utc>) -> String {
+        format!(
+            "cotai-audit-checkpoint:{}:{}:{}",
+            chain_index,
+            chain_hash,
+            created_at.to_rfc3339_opts(SecondsFormat::Micros, true)
+        )
+    }
+}
+
+/// An event with its chain fields, as verified and archived.
+#[derive(Serialize, FromRow)]
+pub(super) struct ChainedEvent {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub(super) event: AuditEvent,
+    pub(super) chain_index: i64,
+    pub(super) prev_hash: String,
+    pub(super) chain_hash: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct VerifyQuery {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainBreak {
+    pub chain_index: i64,
+    pub event_id: Option<Uuid>,
+    pub reason: &'static str,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CheckpointSummary {
+    pub verified: usize,
+    pub unverifiable: usize,
+    pub latest: Option<Checkpoint>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerifyReport {
+    pub intact: bool,
+    pub from: i64,
+    pub to: i64,
+    pub verified_events: i64,
+    /// Index of the last linked event.
+    pub head: i64,
+    /// Index of the last event removed by retention; the walk starts after it.
+    pub pruned_through: i64,
+    /// Events recorded but not linked yet.
+    pub unchained_events: i64,
+    pub first_tampered: Option<ChainBreak>,
+    pub checkpoints: CheckpointSummary,
+}
+
+/// Hash linking `event` to the event before it.
+pub fn link(prev_hash: &str, event: &AuditEvent) -> String {
+    let record = serde_json::json!([
+        event.id,
+        event.occurred_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+        event.tenant_id,
+        event.actor,
+        event.actor_ip,
+        event.action,
+        event.resource,
+        event.outcome,
+        event.payload,
+        event.source
+    ]);
+
+    let mut context = digest::Context::new(&digest::SHA256);
+    context.update(prev_hash.as_bytes());
+    context.update(b"\n");
+    context.update(record.to_string().as_bytes());
+    hex::encode(context.finish().as_ref())
+}
+
+fn broken(report: &mut VerifyReport, chain_index: i64, event_id: Option<Uuid>, reason: &'static str) {
+    report.intact = false;
+    report.first_tampered = Some(ChainBreak { chain_index, event_id, reason });
+}
+
+/// Whether a checkpoint's signature holds; `None` when its key is gone.
+fn checkpoint_signed(crypto: &CryptoService, checkpoint: &Checkpoint) -> Option<bool> {
+    let algorithm = Algorithm::parse(&checkpoint.algorithm)?;
+    let usable = crypto.signing_keys().metadata().iter()
+        .any(|key| key.key_id == checkpoint.key_id && key.state != "retired");
+    if !usable {
+        return None;
+    }
+
+    crypto.verify(AUDIT_CHECKPOINT_DOMAIN, &VerifyRequest {
+        data: Checkpoint::message(checkpoint.chain_index, &checkpoint.chain_hash, checkpoint.created_at),
+        signature: checkpoint.signature.clone(),
+        algorithm: Some(algorithm),
+        key_id: Some(checkpoint.key_id.clone()),
+        timestamp: None,
+    })
+    .ok()
+}
+
+impl AuditService {
+    /// Link the next batch of unchained events, and sign a checkpoint when
+    /// one is due. Returns how many events were linked; 0 also when another
+    /// replica holds the chain.
+    pub async fn extend_chain(&self, crypto: &CryptoService) -> Result<usize, SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let Some((mut chain_index, mut chain_hash)) = sqlx::query_as::<_, (i64, String)>(
+            "SELECT chain_index, chain_hash FROM audit_chain_head FOR UPDATE SKIP LOCKED",
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(0);
+        };
+
+        let events = sqlx::query_as::<_, AuditEvent>(&format!(
+            "SELECT {} FROM audit_events WHERE chain_index IS NULL ORDER BY seq LIMIT $1",
+            EVENT_COLUMNS
+        ))
+        .bind(self.config.chain_batch_size)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        if !events.is_empty() {
+            let mut ids = Vec::with_capacity(events.len());
+            let mut indexes = Vec::with_capacity(events.len());
+            let mut prev_hashes = Vec::with_capacity(events.len());
+            let mut hashes = Vec::with_capacity(events.len());
+            for event in &events {
+                chain_index += 1;
+                let next = link(&chain_hash, event);
+                ids.push(event.id);
+                indexes.push(chain_index);
+                prev_hashes.push(std::mem::replace(&mut chain_hash, next.clone()));
+                hashes.push(next);
+            }
+
+            sqlx::query(
+                "UPDATE audit_events e SET chain_index = v.chain_index, prev_hash = v.prev_hash, chain_hash = v.chain_hash \
+                 FROM UNNEST($1::uuid[], $2::bigint[], $3::text[], $4::text[]) AS v(id, chain_index, prev_hash, chain_hash) \
+                 WHERE e.id = v.id",
+            )
+            .bind(&ids)
+            .bind(&indexes)
+            .bind(&prev_hashes)
+            .bind(&hashes)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query("UPDATE audit_chain_head SET chain_index = $1, chain_hash = $2, updated_at = NOW()")
+                .bind(chain_index)
+                .bind(&chain_hash)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let (checkpointed, last_at): (Option<i64>, Option<DateTime<Utc>>) = sqlx::query_as(
+            "SELECT MAX(chain_index), MAX(created_at) FROM audit_checkpoints",
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        // Stored timestamps keep microseconds, and the signature covers it
+        let now = self.clock.now().trunc_subsecs(6);
+        let due = last_at.is_none_or(|at| now - at >= Duration::seconds(self.config.checkpoint_interval_secs));
+        if chain_index > checkpointed.unwrap_or(0) && due {
+            let algorithm = Algorithm::parse(&self.config.checkpoint_algorithm)
+                .ok_or_else(|| SecurityError::ConfigError("Invalid AUDIT_CHECKPOINT_ALGORITHM".to_string()))?;
+            let signed = crypto.generate_signature(AUDIT_CHECKPOINT_DOMAIN, &Checkpoint::message(chain_index, &chain_hash, now), None, algorithm)?;
+            sqlx::query(
+                "INSERT INTO audit_checkpoints (id, chain_index, chain_hash, algorithm, key_id, signature, created_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            )
+            .bind(Uuid::new_v4())
+            .bind(chain_index)
+            .bind(&chain_hash)
+            .bind(algorithm.as_str())
+            .bind(&signed.key_id)
+            .bind(&signed.signature)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(events.len())
+    }
+
+    /// Walk the chain from `from` to `to`, both defaulting to its ends, and
+    /// stop at the first break.
+    pub async fn verify_chain(&self, crypto: &CryptoService, query: &VerifyQuery) -> Result<VerifyReport, SecurityError> {
+        let pool = self.storage.pool();
+        let (head, pruned_index, pruned_hash): (i64, i64, String) =
+            sqlx::query_as("SELECT chain_index, pruned_index, pruned_hash FROM audit_chain_head")
+                .fetch_one(pool)
+                .await?;
+        let (unchained_events,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM audit_events WHERE chain_index IS NULL")
+            .fetch_one(pool)
+            .await?;
+        let from = query.from.unwrap_or(1).max(pruned_index + 1);
+        let to = query.to.unwrap_or(head);
+        if to < from - 1 {
+            return Err(SecurityError::ValidationError("to must not be before from".to_string()));
+        }
+
+        let checkpoints: HashMap<i64, Checkpoint> = sqlx::query_as::<_, Checkpoint>(&format!(
+            "SELECT {} FROM audit_checkpoints WHERE chain_index >= $1 ORDER BY chain_index",
+            CHECKPOINT_COLUMNS
+        ))
+        .bind(from)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|checkpoint| (checkpoint.chain_index, checkpoint))
+        .collect();
+
+        let mut report = VerifyReport {
+            intact: true,
+            from,
+            to,
+            verified_events: 0,
+            head,
+            pruned_through: pruned_index,
+            unchained_events,
+            first_tampered: None,
+            checkpoints: CheckpointSummary::default(),
+        };
+        // A partial walk trusts the stored hash it starts from; so does a
+        // walk from where retention cut the chain, until the next checkpoint
+        let mut prev = if from == pruned_index + 1 {
+            pruned_hash
+        } else {
+            let stored: Option<(String,)> = sqlx::query_as("SELECT chain_hash FROM audit_events WHERE chain_index = $1")
+                .bind(from - 1)
+                .fetch_optional(pool)
+                .await?;
+            match stored {
+                Some((hash,)) => hash,
+                None => {
+                    broken(&mut report, from - 1, None, "missing");
+                    return Ok(report);
+                }
+            }
+        };
+
+        let mut expected = from;
+        while expected <= to {
+            let page = sqlx::query_as::<_, ChainedEvent>(&format!(
+                "SELECT {}, chain_index, prev_hash, chain_hash FROM audit_events \
+                 WHERE chain_index >= $1 AND chain_index <= $2 ORDER BY chain_index LIMIT $3",
+                EVENT_COLUMNS
+            ))
+            .bind(expected)
+            .bind(to)
+            .bind(VERIFY_PAGE_SIZE)
+            .fetch_all(pool)
+            .await?;
+            if page.is_empty() {
+                break;
+            }
+
+            for row in page {
+                let event_id = Some(row.event.id);
+                if row.chain_index != expected {
+                    broken(&mut report, expected, None, "missing");
+                    return Ok(report);
+                }
+                if row.prev_hash != prev {
+                    broken(&mut report, expected, event_id, "relinked");
+                    return Ok(report);
+                }
+                if link(&prev, &row.event) != row.chain_hash {
+                    broken(&mut report, expected, event_id, "modified");
+                    return Ok(report);
+                }
+                if let Some(checkpoint) = checkpoints.get(&expected) {
+                    if checkpoint.chain_hash != row.chain_hash {
+                        broken(&mut report, expected, event_id, "checkpoint_mismatch");
+                        return Ok(report);
+                    }
+                    match checkpoint_signed(crypto, checkpoint) {
+                        Some(true) => report.checkpoints.verified += 1,
+                        Some(false) => {
+                            broken(&mut report, expected, event_id, "checkpoint_forged");
+                            return Ok(report);
+                        }
+                        None => report.checkpoints.unverifiable += 1,
+                    }
+                    report.checkpoints.latest = Some(checkpoint.clone());
+                }
+
+                prev = row.chain_hash;
+                report.verified_events += 1;
+                expected += 1;
+            }
+        }
+
+        if expected <= to {
+            broken(&mut report, expected, None, "missing");
+            return Ok(report);
+        }
+        // A checkpoint past the head means linked events were cut off
+        if query.to.is_none() && checkpoints.keys().any(|index| *index > head) {
+            broken(&mut report, head + 1, None, "missing");
+        }
+        Ok(report)
+    }
+}
+
+/// Background loop linking new events into the chain.
+pub async fn run_chain(state: web::Data<crate::AppState>) {
+    let batch_size = state.config.audit.chain_batch_size as usize;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(state.config.audit.chain_interval_secs));
+
+    loop {
+        interval.tick().await;
+        // Keep going while full batches show a backlog
+        loop {
+            match state.audit_service.extend_chain(&state.crypto_service).await {
+                Ok(linked) if linked >= batch_size => continue,
+                Ok(_) => break,
+                Err(e) => {
+                    error!("Audit chain pass failed: {:?}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+async fn report_break(state: &crate::AppState, actor: &str, chain_break: &ChainBreak) {
+    error!(
+        "Audit chain broken at {} ({}), event {:?}",
+        chain_break.chain_index, chain_break.reason, chain_break.event_id
+    );
+    let details = serde_json::json!({
+        "chain_index": chain_break.chain_index,
+        "event_id": chain_break.event_id,
+        "reason": chain_break.reason,
+        "verified_by": actor
+    });
+
+    let alert = Alert::new(
+        "audit_chain",
+        Severity::Critical,
+        format!("Audit log tampering detected at chain index {}", chain_break.chain_index),
+        details.clone(),
+        state.clock.now(),
+    );
+    state.alerting_service.send(&alert, &[]).await;
+
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: None,
+        actor: SYSTEM_ACTOR.to_string(),
+        actor_ip: None,
+        action: "audit.chain.break".to_string(),
+        resource: format!("audit_chain:{}", chain_break.chain_index),
+        outcome: "failure".to_string(),
+        payload: details,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit chain break at {}: {:?}", chain_break.chain_index, e);
+    }
+}
+
+// HTTP handlers
+
+pub async fn verify_handler(
+    req: HttpRequest,
+    query: web::Query<VerifyQuery>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.audit_service.verify_chain(&state.crypto_service, &query).await {
+        Ok(report) => {
+            if let Some(chain_break) = &report.first_tampered {
+                report_break(&state, &principal.subject, chain_break).await;
+            }
+            Ok(HttpResponse::Ok().json(report))
+        }
+        Err(SecurityError::ValidationError(msg)) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        }))),
+        Err(e) => {
+            error!("Audit chain verification failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Audit chain verification failed"
+            })))
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/verify", web::get().to(verify_handler));
+}
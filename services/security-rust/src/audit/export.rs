@@ -0,0 +1,421 @@
+/*!
+Audit Export
+Background export of filtered audit data to object storage as NDJSON (gzip) or Parquet
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use arrow::array::{ArrayRef, BooleanArray, StringArray, TimestampMicrosecondArray};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use async_compression::tokio::write::GzipEncoder;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use object_store::aws::AmazonS3Builder;
+use object_store::buffered::BufWriter;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use parquet::arrow::async_writer::{AsyncArrowWriter, ParquetObjectWriter};
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::config::AuditConfig;
+use crate::errors::SecurityError;
+use crate::residency;
+use super::visibility::{AuditView, ProjectedAuditEvent};
+use super::filter::Filter;
+use super::{AuditEvent, AuditQuery, AuditService};
+
+const PARQUET_BATCH_ROWS: usize = 8192;
+
+pub(crate) const EXPORT_COLUMNS: &str = "id, requested_by, view, format, status, object_key, row_count, error, \
+    sha256, integrity, verified_at, region, created_at, completed_at";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Ndjson,
+    Parquet,
+}
+
+impl ExportFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "ndjson.gz",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportRequest {
+    pub format: ExportFormat,
+    #[serde(default)]
+    pub query: AuditQuery,
+    /// Region to write to; a pinned tenant's by default, see `residency`.
+    pub region: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ExportJob {
+    pub id: Uuid,
+    pub requested_by: String,
+    pub view: String,
+    pub format: String,
+    pub status: String,
+    pub object_key: Option<String>,
+    pub row_count: Option<i64>,
+    pub error: Option<String>,
+    /// Hex SHA-256 of the object as written.
+    pub sha256: Option<String>,
+    /// Outcome of the last integrity check, see `integrity`.
+    pub integrity: String,
+    pub verified_at: Option<DateTime<Utc>>,
+    /// Region of the bucket holding the object; `None` is the home region.
+    pub region: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// Object store receiving exports; `None` when no export bucket is configured.
+pub fn build_store(config: &AuditConfig) -> Result<Option<Arc<dyn ObjectStore>>, SecurityError> {
+    match &config.export_bucket {
+        Some(bucket) => {
+            let store = AmazonS3Builder::from_env()
+                .with_bucket_name(bucket)
+                .build()
+                .map_err(|e| SecurityError::ConfigError(format!("Export store: {}", e)))?;
+            Ok(Some(Arc::new(store)))
+        }
+        None => Ok(None),
+    }
+}
+
+impl AuditService {
+    /// The export store of `region`; `None` is the home region's.
+    pub(crate) fn store_in(&self, region: Option<&str>) -> Result<&Arc<dyn ObjectStore>, SecurityError> {
+        residency::store_in(&self.regional_stores, self.export_store.as_ref(), region)
+    }
+}
+
+fn export_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("occurred_at", DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())), false),
+        Field::new("tenant_id", DataType::Utf8, true),
+        Field::new("actor", DataType::Utf8, false),
+        Field::new("actor_ip", DataType::Utf8, true),
+        Field::new("action", DataType::Utf8, false),
+        Field::new("resource", DataType::Utf8, false),
+        Field::new("outcome", DataType::Utf8, false),
+        Field::new("payload", DataType::Utf8, false),
+        Field::new("source", DataType::Utf8, false),
+        Field::new("redacted", DataType::Boolean, false),
+    ]))
+}
+
+fn to_record_batch(schema: &SchemaRef, rows: &[ProjectedAuditEvent]) -> Result<RecordBatch, SecurityError> {
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.id.to_string()))),
+        Arc::new(
+            TimestampMicrosecondArray::from_iter_values(rows.iter().map(|r| r.occurred_at.timestamp_micros()))
+                .with_timezone("UTC"),
+        ),
+        Arc::new(StringArray::from_iter(rows.iter().map(|r| r.tenant_id.as_deref()))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.actor.as_str()))),
+        Arc::new(StringArray::from_iter(rows.iter().map(|r| r.actor_ip.as_deref()))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.action.as_str()))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.resource.as_str()))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.outcome.as_str()))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.payload.to_string()))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.source.as_str()))),
+        Arc::new(BooleanArray::from(rows.iter().map(|r| r.redacted).collect::<Vec<_>>())),
+    ];
+
+    RecordBatch::try_new(schema.clone(), columns)
+        .map_err(|e| SecurityError::AuditError(format!("Arrow batch: {}", e)))
+}
+
+fn export_error(e: impl std::fmt::Display) -> SecurityError {
+    SecurityError::AuditError(format!("Export failed: {}", e))
+}
+
+impl AuditService {
+    pub async fn create_export(
+        &self,
+        requested_by: &str,
+        view: &AuditView,
+        format: ExportFormat,
+        query: &AuditQuery,
+        region: Option<&str>,
+    ) -> Result<ExportJob, SecurityError> {
+        if self.export_store.is_none() {
+            return Err(SecurityError::ConfigError("Audit export storage is not configured".to_string()));
+        }
+        self.store_in(region)?;
+
+        // Reject bad expressions now rather than in the background job
+        if let Some(expression) = &query.filter {
+            let mut restricted = query.clone();
+            view.restrict(&mut restricted);
+            Filter::parse(expression, self.config.filter_max_cost)?.ensure_allowed(&restricted.denied_fields)?;
+        }
+
+        let job = sqlx::query_as::<_, ExportJob>(&format!(
+            "INSERT INTO audit_exports (id, requested_by, view, format, query, status, region) \
+             VALUES ($1, $2, $3, $4, $5, 'pending', $6) \
+             RETURNING {}",
+            EXPORT_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(requested_by)
+        .bind(view.name())
+        .bind(format.as_str())
+        .bind(sqlx::types::Json(query))
+        .bind(region)
+        .fetch_one(self.storage.pool())
+        .await?;
+
+        Ok(job)
+    }
+
+    pub async fn get_export(&self, id: Uuid, requested_by: &str) -> Result<ExportJob, SecurityError> {
+        sqlx::query_as::<_, ExportJob>(&format!(
+            "SELECT {} FROM audit_exports WHERE id = $1 AND requested_by = $2",
+            EXPORT_COLUMNS
+        ))
+        .bind(id)
+        .bind(requested_by)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("Export not found".to_string()))
+    }
+
+    /// Run an export job to completion, recording its outcome on the job row.
+    pub async fn run_export(
+        &self,
+        job_id: Uuid,
+        view: AuditView,
+        format: ExportFormat,
+        mut query: AuditQuery,
+        region: Option<String>,
+    ) {
+        view.restrict(&mut query);
+
+        let _ = sqlx::query("UPDATE audit_exports SET status = 'running' WHERE id = $1")
+            .bind(job_id)
+            .execute(self.storage.pool())
+            .await;
+
+        let key = Path::from(format!(
+            "{}/{}/{}.{}",
+            self.config.export_prefix,
+            self.clock.now().format("%Y/%m/%d"),
+            job_id,
+            format.extension()
+        ));
+
+        let region = region.as_deref();
+        let written = match format {
+            ExportFormat::Ndjson => self.write_ndjson(region, &key, &view, &query).await,
+            ExportFormat::Parquet => self.write_parquet(region, &key, &view, &query).await,
+        };
+        // Read back what the store holds so later checks compare against it
+        let result = match written {
+            Ok(rows) => self.checksum(region, &key).await.map(|sha256| (rows, sha256)),
+            Err(e) => Err(e),
+        };
+
+        let update = match result {
+            Ok((rows, sha256)) => {
+                info!("Audit export {} completed: {} rows to {}", job_id, rows, key);
+                sqlx::query(
+                    "UPDATE audit_exports SET status = 'completed', object_key = $2, row_count = $3, sha256 = $4, \
+                     completed_at = NOW() WHERE id = $1",
+                )
+                .bind(job_id)
+                .bind(key.to_string())
+                .bind(rows as i64)
+                .bind(sha256)
+                .execute(self.storage.pool())
+                .await
+            }
+            Err(e) => {
+                error!("Audit export {} failed: {:?}", job_id, e);
+                sqlx::query("UPDATE audit_exports SET status = 'failed', error = $2, completed_at = NOW() WHERE id = $1")
+                    .bind(job_id)
+                    .bind(e.to_string())
+                    .execute(self.storage.pool())
+                    .await
+            }
+        };
+
+        if let Err(e) = update {
+            warn!("Could not update export job {}: {:?}", job_id, e);
+        }
+    }
+
+    async fn write_ndjson(&self, region: Option<&str>, key: &Path, view: &AuditView, query: &AuditQuery) -> Result<u64, SecurityError> {
+        let store = self.store_in(region)?.clone();
+        let mut encoder = GzipEncoder::new(BufWriter::new(store, key.clone()));
+
+        let mut builder = self.filtered_query(query)?;
+        builder.push(" ORDER BY occurred_at, id");
+        let mut rows = builder.build_query_as::<AuditEvent>().fetch(self.storage.pool());
+
+        let mut count = 0u64;
+        let mut line = Vec::with_capacity(1024);
+        while let Some(event) = rows.try_next().await? {
+            line.clear();
+            serde_json::to_writer(&mut line, &view.project(event, &self.pseudonymizer)).map_err(export_error)?;
+            line.push(b'\n');
+            if let Err(e) = encoder.write_all(&line).await {
+                let _ = encoder.get_mut().abort().await;
+                return Err(export_error(e));
+            }
+            count += 1;
+        }
+
+        encoder.shutdown().await.map_err(export_error)?;
+        Ok(count)
+    }
+
+    async fn write_parquet(&self, region: Option<&str>, key: &Path, view: &AuditView, query: &AuditQuery) -> Result<u64, SecurityError> {
+        let store = self.store_in(region)?.clone();
+        let schema = export_schema();
+        let props = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .build();
+        let mut writer = AsyncArrowWriter::try_new(ParquetObjectWriter::new(store, key.clone()), schema.clone(), Some(props))
+            .map_err(export_error)?;
+
+        let mut builder = self.filtered_query(query)?;
+        builder.push(" ORDER BY occurred_at, id");
+        let mut rows = builder.build_query_as::<AuditEvent>().fetch(self.storage.pool());
+
+        let mut count = 0u64;
+        let mut pending = Vec::with_capacity(PARQUET_BATCH_ROWS);
+        while let Some(event) = rows.try_next().await? {
+            pending.push(view.project(event, &self.pseudonymizer));
+            if pending.len() == PARQUET_BATCH_ROWS {
+                writer.write(&to_record_batch(&schema, &pending)?).await.map_err(export_error)?;
+                count += pending.len() as u64;
+                pending.clear();
+            }
+        }
+        if !pending.is_empty() {
+            writer.write(&to_record_batch(&schema, &pending)?).await.map_err(export_error)?;
+            count += pending.len() as u64;
+        }
+
+        writer.close().await.map_err(export_error)?;
+        Ok(count)
+    }
+}
+
+// HTTP handlers
+
+pub async fn create_export_handler(
+    req: HttpRequest,
+    request: web::Json<ExportRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(crate::auth::auth_error_response(&e)),
+    };
+    let view = match AuditView::for_principal(&principal, &state.audit_service.config) {
+        Ok(view) => view,
+        Err(e) => return Ok(crate::auth::auth_error_response(&e)),
+    };
+
+    let request = request.into_inner();
+    // Whose data this is decides where it may be written
+    let mut scope = request.query.clone();
+    view.restrict(&mut scope);
+    let created = async {
+        let destination = match &request.region {
+            Some(region) => Some(region.trim().to_string()),
+            None => state.residency.pinned(scope.tenant_id.as_deref()).await?,
+        };
+        state.residency.check_export(scope.tenant_id.as_deref(), destination.as_deref()).await?;
+        let region = destination.and_then(|region| state.residency.regional(region));
+        let job = state.audit_service
+            .create_export(&principal.subject, &view, request.format, &request.query, region.as_deref())
+            .await?;
+        Ok::<_, SecurityError>((job, region))
+    }
+    .await;
+
+    match created {
+        Ok((job, region)) => {
+            state.residency
+                .record(scope.tenant_id.as_deref(), "audit_export", &format!("audit_export:{}", job.id), region.as_deref())
+                .await;
+            let worker_state = state.clone();
+            let job_id = job.id;
+            tokio::spawn(async move {
+                worker_state.audit_service
+                    .run_export(job_id, view, request.format, request.query, region)
+                    .await;
+            });
+            Ok(HttpResponse::Accepted().json(job))
+        }
+        Err(SecurityError::ConfigError(msg)) => Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": msg
+        }))),
+        Err(SecurityError::ValidationError(msg)) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        }))),
+        Err(SecurityError::AccessDenied(msg)) => Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg
+        }))),
+        Err(e) => {
+            error!("Audit export creation failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Audit export failed"
+            })))
+        }
+    }
+}
+
+pub async fn get_export_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(crate::auth::auth_error_response(&e)),
+    };
+
+    match state.audit_service.get_export(path.into_inner(), &principal.subject).await {
+        Ok(job) => Ok(HttpResponse::Ok().json(job)),
+        Err(SecurityError::NotFound(msg)) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        }))),
+        Err(e) => {
+            error!("Audit export lookup failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Audit export lookup failed"
+            })))
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/exports", web::post().to(create_export_handler))
+        .route("/exports/{id}", web::get().to(get_export_handler));
+}
@@ -0,0 +1,750 @@
+/*!
+Audit Filter Language
+OData-style `$filter` expressions parsed, validated, and compiled to parameterized SQL
+
+`Filter::matches` evaluates the same expression against a single event in
+memory, with the SQL semantics (NULL comparisons are unknown, string
+functions ignore case), for consumers of the live event stream.
+*/
+
+use chrono::{DateTime, Utc};
+use sqlx::{Postgres, QueryBuilder};
+
+use super::AuditEvent;
+use crate::errors::SecurityError;
+
+const MAX_FILTER_LENGTH: usize = 2000;
+const MAX_DEPTH: usize = 10;
+const MAX_IN_VALUES: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    DateTime(DateTime<Utc>),
+    LParen,
+    RParen,
+    Comma,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl CmpOp {
+    fn sql(&self) -> &'static str {
+        match self {
+            CmpOp::Eq => " = ",
+            CmpOp::Ne => " <> ",
+            CmpOp::Gt => " > ",
+            CmpOp::Ge => " >= ",
+            CmpOp::Lt => " < ",
+            CmpOp::Le => " <= ",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StrFunc {
+    Contains,
+    StartsWith,
+    EndsWith,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Str(String),
+    Num(f64),
+    Bool(bool),
+    Null,
+    DateTime(DateTime<Utc>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Field {
+    /// A top-level audit_events column.
+    Column(&'static str),
+    /// A path into the JSON payload, e.g. `payload/request/method`.
+    Payload(Vec<String>),
+}
+
+impl Field {
+    fn name(&self) -> &str {
+        match self {
+            Field::Column(name) => name,
+            Field::Payload(_) => "payload",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare { field: Field, op: CmpOp, value: Literal },
+    In { field: Field, values: Vec<Literal> },
+    Func { func: StrFunc, field: Field, value: String },
+}
+
+/// A parsed filter with its computed evaluation cost.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    pub expr: Expr,
+    pub cost: u32,
+}
+
+const COLUMNS: &[&str] = &[
+    "tenant_id", "actor", "actor_ip", "action", "resource", "outcome", "source", "occurred_at",
+];
+
+fn invalid(msg: impl Into<String>) -> SecurityError {
+    SecurityError::ValidationError(format!("Invalid $filter: {}", msg.into()))
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, SecurityError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ' ' | '\t' | '\n' | '\r' => i += 1,
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            '\'' => {
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some('\'') if chars.get(i + 1) == Some(&'\'') => {
+                            value.push('\'');
+                            i += 2;
+                        }
+                        Some('\'') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(ch) => {
+                            value.push(*ch);
+                            i += 1;
+                        }
+                        None => return Err(invalid("unterminated string literal")),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || ":.-+".contains(chars[i])) {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                if let Ok(num) = text.parse::<f64>() {
+                    tokens.push(Token::Num(num));
+                } else if let Ok(dt) = DateTime::parse_from_rfc3339(&text) {
+                    tokens.push(Token::DateTime(dt.with_timezone(&Utc)));
+                } else {
+                    return Err(invalid(format!("bad literal '{}'", text)));
+                }
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '/') {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            }
+            other => return Err(invalid(format!("unexpected character '{}'", other))),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(id)) if id.eq_ignore_ascii_case(keyword))
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), SecurityError> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            other => Err(invalid(format!("expected {:?}, found {:?}", expected, other))),
+        }
+    }
+
+    fn enter(&mut self) -> Result<(), SecurityError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(invalid("expression nested too deeply"));
+        }
+        Ok(())
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, SecurityError> {
+        let mut left = self.parse_and()?;
+        while self.peek_keyword("or") {
+            self.pos += 1;
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, SecurityError> {
+        let mut left = self.parse_unary()?;
+        while self.peek_keyword("and") {
+            self.pos += 1;
+            let right = self.parse_unary()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, SecurityError> {
+        if self.peek_keyword("not") {
+            self.pos += 1;
+            self.enter()?;
+            let inner = self.parse_unary()?;
+            self.depth -= 1;
+            return Ok(Expr::Not(Box::new(inner)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, SecurityError> {
+        match self.next() {
+            Some(Token::LParen) => {
+                self.enter()?;
+                let expr = self.parse_or()?;
+                self.expect(Token::RParen)?;
+                self.depth -= 1;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => {
+                let func = match name.to_ascii_lowercase().as_str() {
+                    "contains" => Some(StrFunc::Contains),
+                    "startswith" => Some(StrFunc::StartsWith),
+                    "endswith" => Some(StrFunc::EndsWith),
+                    _ => None,
+                };
+                if let Some(func) = func {
+                    self.expect(Token::LParen)?;
+                    let field = match self.next() {
+                        Some(Token::Ident(field)) => parse_field(&field)?,
+                        other => return Err(invalid(format!("expected field, found {:?}", other))),
+                    };
+                    self.expect(Token::Comma)?;
+                    let value = match self.next() {
+                        Some(Token::Str(value)) => value,
+                        other => return Err(invalid(format!("expected string, found {:?}", other))),
+                    };
+                    self.expect(Token::RParen)?;
+                    return Ok(Expr::Func { func, field, value });
+                }
+
+                let field = parse_field(&name)?;
+                let op = match self.next() {
+                    Some(Token::Ident(op)) => op.to_ascii_lowercase(),
+                    other => return Err(invalid(format!("expected operator, found {:?}", other))),
+                };
+
+                if op == "in" {
+                    self.expect(Token::LParen)?;
+                    let mut values = vec![self.parse_literal()?];
+                    while self.peek() == Some(&Token::Comma) {
+                        self.pos += 1;
+                        values.push(self.parse_literal()?);
+                    }
+                    self.expect(Token::RParen)?;
+                    if values.len() > MAX_IN_VALUES {
+                        return Err(invalid(format!("'in' accepts at most {} values", MAX_IN_VALUES)));
+                    }
+                    return Ok(Expr::In { field, values });
+                }
+
+                let op = match op.as_str() {
+                    "eq" => CmpOp::Eq,
+                    "ne" => CmpOp::Ne,
+                    "gt" => CmpOp::Gt,
+                    "ge" => CmpOp::Ge,
+                    "lt" => CmpOp::Lt,
+                    "le" => CmpOp::Le,
+                    other => return Err(invalid(format!("unknown operator '{}'", other))),
+                };
+                let value = self.parse_literal()?;
+                Ok(Expr::Compare { field, op, value })
+            }
+            other => Err(invalid(format!("unexpected token {:?}", other))),
+        }
+    }
+
+    fn parse_literal(&mut self) -> Result<Literal, SecurityError> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(Literal::Str(s)),
+            Some(Token::Num(n)) => Ok(Literal::Num(n)),
+            Some(Token::DateTime(dt)) => Ok(Literal::DateTime(dt)),
+            Some(Token::Ident(id)) => match id.to_ascii_lowercase().as_str() {
+                "true" => Ok(Literal::Bool(true)),
+                "false" => Ok(Literal::Bool(false)),
+                "null" => Ok(Literal::Null),
+                _ => Err(invalid(format!("expected literal, found '{}'", id))),
+            },
+            other => Err(invalid(format!("expected literal, found {:?}", other))),
+        }
+    }
+}
+
+fn parse_field(name: &str) -> Result<Field, SecurityError> {
+    if let Some(path) = name.strip_prefix("payload/") {
+        let segments: Vec<String> = path.split('/').map(String::from).collect();
+        if segments.is_empty() || segments.len() > 5 || segments.iter().any(|s| s.is_empty()) {
+            return Err(invalid(format!("bad payload path '{}'", name)));
+        }
+        return Ok(Field::Payload(segments));
+    }
+
+    COLUMNS.iter()
+        .find(|c| **c == name)
+        .map(|c| Field::Column(c))
+        .ok_or_else(|| invalid(format!("unknown field '{}'", name)))
+}
+
+fn field_cost(field: &Field) -> u32 {
+    match field {
+        Field::Column(_) => 0,
+        // JSON extraction cannot use the column indexes
+        Field::Payload(_) => 2,
+    }
+}
+
+fn cost(expr: &Expr) -> u32 {
+    match expr {
+        Expr::And(l, r) | Expr::Or(l, r) => 1 + cost(l) + cost(r),
+        Expr::Not(inner) => 1 + cost(inner),
+        Expr::Compare { field, .. } => 1 + field_cost(field),
+        Expr::In { field, values } => 1 + field_cost(field) + values.len() as u32 / 10,
+        Expr::Func { func, field, .. } => {
+            let base = match func {
+                StrFunc::StartsWith => 2,
+                StrFunc::Contains | StrFunc::EndsWith => 5,
+            };
+            base + field_cost(field)
+        }
+    }
+}
+
+fn validate_types(expr: &Expr) -> Result<(), SecurityError> {
+    match expr {
+        Expr::And(l, r) | Expr::Or(l, r) => {
+            validate_types(l)?;
+            validate_types(r)
+        }
+        Expr::Not(inner) => validate_types(inner),
+        Expr::Compare { field: Field::Column("occurred_at"), value, .. } => match value {
+            Literal::DateTime(_) => Ok(()),
+            _ => Err(invalid("occurred_at must be compared to a datetime")),
+        },
+        Expr::Compare { field: Field::Column(_), op, value } => match value {
+            Literal::Str(_) => Ok(()),
+            Literal::Null if matches!(op, CmpOp::Eq | CmpOp::Ne) => Ok(()),
+            _ => Err(invalid("columns compare to strings (or eq/ne null)")),
+        },
+        Expr::Compare { field: Field::Payload(_), op, value } => match value {
+            Literal::Null if !matches!(op, CmpOp::Eq | CmpOp::Ne) => Err(invalid("null only supports eq/ne")),
+            Literal::DateTime(_) => Err(invalid("payload fields compare to strings, numbers or booleans")),
+            _ => Ok(()),
+        },
+        Expr::In { field, values } => {
+            if *field == Field::Column("occurred_at") {
+                return Err(invalid("'in' is not supported on occurred_at"));
+            }
+            if values.iter().all(|v| matches!(v, Literal::Str(_))) {
+                Ok(())
+            } else {
+                Err(invalid("'in' accepts string values only"))
+            }
+        }
+        Expr::Func { field, .. } => {
+            if *field == Field::Column("occurred_at") {
+                return Err(invalid("string functions are not supported on occurred_at"));
+            }
+            Ok(())
+        }
+    }
+}
+
+fn referenced_fields<'a>(expr: &'a Expr, out: &mut Vec<&'a str>) {
+    match expr {
+        Expr::And(l, r) | Expr::Or(l, r) => {
+            referenced_fields(l, out);
+            referenced_fields(r, out);
+        }
+        Expr::Not(inner) => referenced_fields(inner, out),
+        Expr::Compare { field, .. } | Expr::In { field, .. } | Expr::Func { field, .. } => out.push(field.name()),
+    }
+}
+
+impl Filter {
+    pub fn parse(input: &str, max_cost: u32) -> Result<Self, SecurityError> {
+        if input.len() > MAX_FILTER_LENGTH {
+            return Err(invalid("expression too long"));
+        }
+
+        let tokens = tokenize(input)?;
+        let mut parser = Parser { tokens, pos: 0, depth: 0 };
+        let expr = parser.parse_or()?;
+        if parser.pos != parser.tokens.len() {
+            return Err(invalid("trailing input"));
+        }
+
+        validate_types(&expr)?;
+        let cost = cost(&expr);
+        if cost > max_cost {
+            return Err(invalid(format!("expression cost {} exceeds limit {}", cost, max_cost)));
+        }
+
+        Ok(Self { expr, cost })
+    }
+
+    /// Reject filters over fields the caller's view hides.
+    pub fn ensure_allowed(&self, denied: &[&str]) -> Result<(), SecurityError> {
+        let mut fields = Vec::new();
+        referenced_fields(&self.expr, &mut fields);
+        match fields.into_iter().find(|f| denied.contains(f)) {
+            Some(field) => Err(SecurityError::AccessDenied(format!("Filtering on '{}' is not permitted", field))),
+            None => Ok(()),
+        }
+    }
+
+    pub fn push_sql(&self, builder: &mut QueryBuilder<'static, Postgres>) {
+        builder.push("(");
+        push_expr(&self.expr, builder);
+        builder.push(")");
+    }
+
+    /// Whether `event` is one the compiled SQL would select.
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        eval(&self.expr, event) == Some(true)
+    }
+}
+
+enum Value {
+    Text(String),
+    Time(DateTime<Utc>),
+}
+
+/// The value SQL would see for `field`; `None` is NULL.
+fn field_value(field: &Field, event: &AuditEvent) -> Option<Value> {
+    let text = |s: &str| Some(Value::Text(s.to_string()));
+    match field {
+        Field::Column("occurred_at") => Some(Value::Time(event.occurred_at)),
+        Field::Column("tenant_id") => event.tenant_id.as_deref().and_then(text),
+        Field::Column("actor") => text(&event.actor),
+        Field::Column("actor_ip") => event.actor_ip.as_deref().and_then(text),
+        Field::Column("action") => text(&event.action),
+        Field::Column("resource") => text(&event.resource),
+        Field::Column("outcome") => text(&event.outcome),
+        Field::Column("source") => text(&event.source),
+        Field::Column(_) => None,
+        // Mirrors `payload #>> path`
+        Field::Payload(path) => {
+            let value = path.iter().try_fold(&event.payload, |value, key| match value {
+                serde_json::Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+                _ => value.get(key),
+            })?;
+            match value {
+                serde_json::Value::Null => None,
+                serde_json::Value::String(s) => text(s),
+                other => Some(Value::Text(other.to_string())),
+            }
+        }
+    }
+}
+
+fn compare<T: PartialOrd>(left: T, op: CmpOp, right: T) -> bool {
+    match op {
+        CmpOp::Eq => left == right,
+        CmpOp::Ne => left != right,
+        CmpOp::Gt => left > right,
+        CmpOp::Ge => left >= right,
+        CmpOp::Lt => left < right,
+        CmpOp::Le => left <= right,
+    }
+}
+
+/// Three-valued evaluation; `None` is SQL's unknown.
+fn eval(expr: &Expr, event: &AuditEvent) -> Option<bool> {
+    match expr {
+        Expr::And(l, r) => match (eval(l, event), eval(r, event)) {
+            (Some(false), _) | (_, Some(false)) => Some(false),
+            (Some(true), Some(true)) => Some(true),
+            _ => None,
+        },
+        Expr::Or(l, r) => match (eval(l, event), eval(r, event)) {
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            (Some(false), Some(false)) => Some(false),
+            _ => None,
+        },
+        Expr::Not(inner) => eval(inner, event).map(|b| !b),
+        Expr::Compare { field, op, value } => {
+            let actual = field_value(field, event);
+            match (actual, value) {
+                (actual, Literal::Null) => Some(actual.is_none() == (*op == CmpOp::Eq)),
+                (None, _) => None,
+                (Some(Value::Text(a)), Literal::Str(s)) => Some(compare(a.as_str(), *op, s.as_str())),
+                (Some(Value::Text(a)), Literal::Num(n)) => a.trim().parse::<f64>().ok().map(|a| compare(a, *op, *n)),
+                (Some(Value::Text(a)), Literal::Bool(b)) => Some(compare(a.as_str(), *op, if *b { "true" } else { "false" })),
+                (Some(Value::Time(a)), Literal::DateTime(dt)) => Some(compare(a, *op, *dt)),
+                _ => None,
+            }
+        }
+        Expr::In { field, values } => match field_value(field, event)? {
+            Value::Text(a) => Some(values.iter().any(|v| matches!(v, Literal::Str(s) if *s == a))),
+            Value::Time(_) => None,
+        },
+        Expr::Func { func, field, value } => match field_value(field, event)? {
+            Value::Text(a) => {
+                let (a, value) = (a.to_lowercase(), value.to_lowercase());
+                Some(match func {
+                    StrFunc::Contains => a.contains(&value),
+                    StrFunc::StartsWith => a.starts_with(&value),
+                    StrFunc::EndsWith => a.ends_with(&value),
+                })
+            }
+            Value::Time(_) => None,
+        },
+    }
+}
+
+fn push_field(field: &Field, numeric: bool, builder: &mut QueryBuilder<'static, Postgres>) {
+    match field {
+        Field::Column(name) => {
+            builder.push(*name);
+        }
+        Field::Payload(path) => {
+            builder.push(if numeric { "(payload #>> " } else { "payload #>> " });
+            builder.push_bind(path.clone());
+            if numeric {
+                builder.push(")::numeric");
+            }
+        }
+    }
+}
+
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+fn push_expr(expr: &Expr, builder: &mut QueryBuilder<'static, Postgres>) {
+    match expr {
+        Expr::And(l, r) | Expr::Or(l, r) => {
+            let joiner = if matches!(expr, Expr::And(..)) { " AND " } else { " OR " };
+            builder.push("(");
+            push_expr(l, builder);
+            builder.push(joiner);
+            push_expr(r, builder);
+            builder.push(")");
+        }
+        Expr::Not(inner) => {
+            builder.push("NOT (");
+            push_expr(inner, builder);
+            builder.push(")");
+        }
+        Expr::Compare { field, op, value } => match value {
+            Literal::Null => {
+                push_field(field, false, builder);
+                builder.push(if *op == CmpOp::Eq { " IS NULL" } else { " IS NOT NULL" });
+            }
+            Literal::Str(s) => {
+                push_field(field, false, builder);
+                builder.push(op.sql()).push_bind(s.clone());
+            }
+            Literal::Num(n) => {
+                push_field(field, true, builder);
+                builder.push(op.sql()).push_bind(*n).push("::numeric");
+            }
+            Literal::Bool(b) => {
+                push_field(field, false, builder);
+                builder.push(op.sql()).push_bind(b.to_string());
+            }
+            Literal::DateTime(dt) => {
+                push_field(field, false, builder);
+                builder.push(op.sql()).push_bind(*dt);
+            }
+        },
+        Expr::In { field, values } => {
+            let values: Vec<String> = values
+                .iter()
+                .filter_map(|v| match v {
+                    Literal::Str(s) => Some(s.clone()),
+                    _ => None,
+                })
+                .collect();
+            push_field(field, false, builder);
+            builder.push(" = ANY(").push_bind(values).push(")");
+        }
+        Expr::Func { func, field, value } => {
+            let pattern = match func {
+                StrFunc::Contains => format!("%{}%", escape_like(value)),
+                StrFunc::StartsWith => format!("{}%", escape_like(value)),
+                StrFunc::EndsWith => format!("%{}", escape_like(value)),
+            };
+            push_field(field, false, builder);
+            builder.push(" ILIKE ").push_bind(pattern);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn event() -> AuditEvent {
+        AuditEvent {
+            id: Uuid::nil(),
+            occurred_at: Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap(),
+            tenant_id: Some("t1".to_string()),
+            actor: "ana@example.com".to_string(),
+            actor_ip: None,
+            action: "auth.login_failed".to_string(),
+            resource: "session".to_string(),
+            outcome: "failure".to_string(),
+            payload: json!({ "request": { "method": "POST", "attempts": 5, "tags": ["sso", "mfa"] }, "mfa": true, "note": null }),
+            source: "live".to_string(),
+        }
+    }
+
+    fn matches(filter: &str) -> bool {
+        Filter::parse(filter, 100).unwrap().matches(&event())
+    }
+
+    fn refused(filter: &str) -> String {
+        Filter::parse(filter, 100).unwrap_err().to_string()
+    }
+
+    fn sql(filter: &str) -> String {
+        let mut builder = QueryBuilder::new("");
+        Filter::parse(filter, 100).unwrap().push_sql(&mut builder);
+        builder.sql().to_string()
+    }
+
+    #[test]
+    fn filters_compile_to_parameterized_sql() {
+        assert_eq!(
+            sql("action eq 'auth.login_failed' and (outcome ne 'success' or not actor_ip eq null)"),
+            "((action = $1 AND (outcome <> $2 OR NOT (actor_ip IS NULL))))"
+        );
+        assert_eq!(sql("payload/request/attempts ge 3"), "((payload #>> $1)::numeric >= $2::numeric)");
+        assert_eq!(sql("actor in ('a', 'b')"), "(actor = ANY($1))");
+        assert_eq!(sql("contains(resource, '50%_off')"), "(resource ILIKE $1)");
+        assert_eq!(sql("occurred_at lt 2026-03-02T00:00:00Z"), "(occurred_at < $1)");
+    }
+
+    #[test]
+    fn like_patterns_are_escaped() {
+        assert_eq!(escape_like(r"50%_off\"), r"50\%\_off\\");
+    }
+
+    #[test]
+    fn filters_match_like_the_sql() {
+        assert!(matches("action eq 'auth.login_failed' and outcome eq 'failure'"));
+        assert!(matches("startswith(action, 'AUTH.') and endswith(actor, '@EXAMPLE.COM')"));
+        assert!(!matches("action eq 'AUTH.LOGIN_FAILED'"));
+        assert!(matches("tenant_id in ('t1', 't2')"));
+        assert!(matches("occurred_at ge 2026-03-01T00:00:00Z and occurred_at lt 2026-03-02T00:00:00Z"));
+        assert!(matches("payload/request/method eq 'POST' and payload/request/attempts gt 4.5"));
+        assert!(matches("payload/request/tags/1 eq 'mfa' and payload/mfa eq true"));
+        assert!(matches("payload/note eq null and payload/missing eq null and actor_ip eq null"));
+        // Keywords and operators ignore case; field names do not
+        assert!(matches("outcome EQ 'failure' AND NOT source Eq 'imported'"));
+    }
+
+    #[test]
+    fn null_comparisons_are_unknown() {
+        // Unknown is neither true nor, negated, true
+        assert!(!matches("actor_ip eq '192.0.2.1'"));
+        assert!(!matches("not actor_ip eq '192.0.2.1'"));
+        assert!(!matches("contains(payload/missing, 'x')"));
+        assert!(matches("actor_ip eq '192.0.2.1' or outcome eq 'failure'"));
+        assert!(!matches("actor_ip eq '192.0.2.1' and outcome eq 'failure'"));
+        assert!(!matches("payload/request/method gt 1"));
+    }
+
+    #[test]
+    fn malformed_filters_are_refused() {
+        assert!(refused("actor eq 'open").contains("unterminated string literal"));
+        assert!(refused("actor like 'a'").contains("unknown operator 'like'"));
+        assert!(refused("password eq 'x'").contains("unknown field 'password'"));
+        assert!(refused("ACTOR eq 'x'").contains("unknown field 'ACTOR'"));
+        assert!(refused("payload//x eq 1").contains("bad payload path"));
+        assert!(refused("payload/a/b/c/d/e/f eq 1").contains("bad payload path"));
+        assert!(refused("actor eq 'a' actor").contains("trailing input"));
+        assert!(refused("actor eq 'a' ; drop").contains("unexpected character ';'"));
+        assert!(refused("occurred_at gt 'yesterday'").contains("occurred_at must be compared to a datetime"));
+        assert!(refused("actor gt 5").contains("columns compare to strings"));
+        assert!(refused("payload/x lt null").contains("null only supports eq/ne"));
+        assert!(refused("actor in ('a', 1)").contains("'in' accepts string values only"));
+        assert!(refused("contains(occurred_at, '2026')").contains("not supported on occurred_at"));
+        assert!(refused(&format!("{}actor eq 'a'{}", "(".repeat(MAX_DEPTH + 1), ")".repeat(MAX_DEPTH + 1)))
+            .contains("nested too deeply"));
+        assert!(refused(&format!("actor eq '{}'", "a".repeat(MAX_FILTER_LENGTH))).contains("too long"));
+        let many = vec!["'a'"; MAX_IN_VALUES + 1].join(", ");
+        assert!(refused(&format!("actor in ({})", many)).contains("at most 100 values"));
+    }
+
+    #[test]
+    fn costs_are_limited() {
+        assert_eq!(Filter::parse("actor eq 'a'", 100).unwrap().cost, 1);
+        assert_eq!(Filter::parse("payload/x eq 1 and contains(actor, 'a')", 100).unwrap().cost, 9);
+        assert!(Filter::parse("contains(actor, 'a') or contains(resource, 'b')", 10)
+            .unwrap_err()
+            .to_string()
+            .contains("expression cost 11 exceeds limit 10"));
+    }
+
+    #[test]
+    fn hidden_fields_cannot_be_filtered_on() {
+        let filter = Filter::parse("action eq 'x' or payload/ip eq '192.0.2.1'", 100).unwrap();
+        assert!(filter.ensure_allowed(&["actor_ip"]).is_ok());
+        assert!(matches!(filter.ensure_allowed(&["payload"]), Err(SecurityError::AccessDenied(_))));
+    }
+}
@@ -0,0 +1,385 @@
+/*!
+Legacy Audit Import
+Streamed NDJSON/CSV backfill of audit records from the legacy Python service
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::{Postgres, QueryBuilder};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::auth::auth_error_response;
+use crate::errors::SecurityError;
+use super::{AuditService, NewAuditEvent};
+
+const MAX_RECORD_BYTES: usize = 1024 * 1024;
+
+/// Row shape of the legacy `audit_logs` table.
+#[derive(Debug, Deserialize)]
+pub struct LegacyAuditRecord {
+    pub id: String,
+    pub user_id: Option<String>,
+    pub action: String,
+    pub resource_type: Option<String>,
+    pub resource_id: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_path: Option<String>,
+    pub request_method: Option<String>,
+    pub details: Option<Value>,
+    pub status: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    pub duration_ms: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Ndjson,
+    Csv,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportParams {
+    pub format: ImportFormat,
+    /// Tenant the legacy records belong to; the legacy schema has no tenant column.
+    pub tenant_id: Option<String>,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportError {
+    pub record: usize,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub received: usize,
+    pub imported: u64,
+    pub duplicates: u64,
+    pub rejected: usize,
+    pub errors: Vec<ImportError>,
+    pub truncated_errors: bool,
+}
+
+struct MappedRecord {
+    legacy_id: String,
+    occurred_at: DateTime<Utc>,
+    event: NewAuditEvent,
+}
+
+impl LegacyAuditRecord {
+    fn into_mapped(self, tenant_id: &Option<String>, now: DateTime<Utc>) -> Result<MappedRecord, String> {
+        if self.id.trim().is_empty() {
+            return Err("missing id".to_string());
+        }
+        if self.action.trim().is_empty() {
+            return Err("missing action".to_string());
+        }
+        if self.timestamp > now {
+            return Err("timestamp is in the future".to_string());
+        }
+
+        let outcome = match self.status.as_deref().unwrap_or("SUCCESS").to_uppercase().as_str() {
+            "SUCCESS" => "success",
+            "FAILED" => "failure",
+            "ERROR" => "error",
+            other => return Err(format!("unknown status '{}'", other)),
+        };
+
+        let resource = match (self.resource_type, self.resource_id) {
+            (Some(kind), Some(id)) => format!("{}:{}", kind.to_lowercase(), id),
+            (Some(kind), None) => kind.to_lowercase(),
+            (None, Some(id)) => id,
+            (None, None) => "unknown".to_string(),
+        };
+
+        let payload = serde_json::json!({
+            "details": self.details,
+            "request": {
+                "path": self.request_path,
+                "method": self.request_method,
+                "user_agent": self.user_agent,
+                "duration_ms": self.duration_ms,
+            },
+            "legacy_id": self.id,
+        });
+
+        Ok(MappedRecord {
+            legacy_id: self.id,
+            occurred_at: self.timestamp,
+            event: NewAuditEvent {
+                tenant_id: tenant_id.clone(),
+                actor: self.user_id.unwrap_or_else(|| "anonymous".to_string()),
+                actor_ip: self.ip_address,
+                action: self.action.to_lowercase(),
+                resource,
+                outcome: outcome.to_string(),
+                payload,
+            },
+        })
+    }
+}
+
+/// Splits a byte stream into records. In CSV mode newlines inside quoted
+/// fields do not terminate a record.
+struct RecordSplitter {
+    buf: Vec<u8>,
+    scanned: usize,
+    in_quotes: bool,
+    quote_aware: bool,
+}
+
+impl RecordSplitter {
+    fn new(quote_aware: bool) -> Self {
+        Self {
+            buf: Vec::new(),
+            scanned: 0,
+            in_quotes: false,
+            quote_aware,
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+        self.buf.extend_from_slice(chunk);
+        let mut records = Vec::new();
+        let mut start = 0;
+
+        let mut i = self.scanned;
+        while i < self.buf.len() {
+            match self.buf[i] {
+                b'"' if self.quote_aware => self.in_quotes = !self.in_quotes,
+                b'\n' if !self.in_quotes => {
+                    records.push(self.buf[start..i].to_vec());
+                    start = i + 1;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+
+        self.buf.drain(..start);
+        self.scanned = self.buf.len();
+        if self.buf.len() > MAX_RECORD_BYTES {
+            return Err("record exceeds maximum size".to_string());
+        }
+        Ok(records)
+    }
+
+    fn finish(self) -> Option<Vec<u8>> {
+        if self.buf.iter().all(|b| b.is_ascii_whitespace()) {
+            None
+        } else {
+            Some(self.buf)
+        }
+    }
+}
+
+fn parse_ndjson(line: &[u8]) -> Result<LegacyAuditRecord, String> {
+    serde_json::from_slice(line).map_err(|e| format!("invalid JSON: {}", e))
+}
+
+fn parse_csv(line: &[u8], headers: &csv::StringRecord) -> Result<LegacyAuditRecord, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(line);
+    let record = reader.records()
+        .next()
+        .ok_or_else(|| "empty record".to_string())?
+        .map_err(|e| format!("invalid CSV: {}", e))?;
+
+    let mut map = Map::new();
+    for (name, value) in headers.iter().zip(record.iter()) {
+        let value = if value.is_empty() {
+            Value::Null
+        } else {
+            match name {
+                "details" => serde_json::from_str(value).map_err(|_| "details is not valid JSON".to_string())?,
+                "duration_ms" => value.parse::<i64>()
+                    .map(Value::from)
+                    .map_err(|_| "duration_ms is not an integer".to_string())?,
+                _ => Value::String(value.to_string()),
+            }
+        };
+        map.insert(name.to_string(), value);
+    }
+
+    serde_json::from_value(Value::Object(map)).map_err(|e| format!("invalid record: {}", e))
+}
+
+impl AuditService {
+    /// Insert a batch of imported records, skipping ones already imported.
+    async fn insert_imported(&self, batch: &[MappedRecord]) -> Result<u64, SecurityError> {
+        if batch.is_empty() {
+            return Ok(0);
+        }
+
+        let imported_at = self.clock.now();
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "INSERT INTO audit_events \
+             (id, occurred_at, tenant_id, actor, actor_ip, action, resource, outcome, payload, source, legacy_id, imported_at) ",
+        );
+        builder.push_values(batch, |mut row, record| {
+            row.push_bind(Uuid::new_v4())
+                .push_bind(record.occurred_at)
+                .push_bind(&record.event.tenant_id)
+                .push_bind(&record.event.actor)
+                .push_bind(&record.event.actor_ip)
+                .push_bind(&record.event.action)
+                .push_bind(&record.event.resource)
+                .push_bind(&record.event.outcome)
+                .push_bind(&record.event.payload)
+                .push_bind("imported")
+                .push_bind(&record.legacy_id)
+                .push_bind(imported_at);
+        });
+        builder.push(" ON CONFLICT (legacy_id) WHERE legacy_id IS NOT NULL DO NOTHING");
+
+        let result = builder.build().execute(self.storage.pool()).await?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn import_legacy(
+        &self,
+        params: &ImportParams,
+        mut body: web::Payload,
+    ) -> Result<ImportReport, SecurityError> {
+        let mut report = ImportReport::default();
+        let mut splitter = RecordSplitter::new(params.format == ImportFormat::Csv);
+        let mut headers: Option<csv::StringRecord> = None;
+        let mut batch: Vec<MappedRecord> = Vec::with_capacity(self.config.import_batch_size);
+        let mut index = 0;
+
+        loop {
+            let (records, done) = match body.next().await {
+                Some(chunk) => {
+                    let chunk = chunk.map_err(|e| SecurityError::ValidationError(format!("Body read failed: {}", e)))?;
+                    let records = splitter.push(&chunk).map_err(SecurityError::ValidationError)?;
+                    (records, false)
+                }
+                None => {
+                    let rest = std::mem::replace(&mut splitter, RecordSplitter::new(false)).finish();
+                    (rest.into_iter().collect(), true)
+                }
+            };
+
+            for raw in records {
+                let raw = raw.strip_suffix(b"\r").unwrap_or(&raw).to_vec();
+                if raw.iter().all(|b| b.is_ascii_whitespace()) {
+                    continue;
+                }
+
+                if params.format == ImportFormat::Csv && headers.is_none() {
+                    let mut reader = csv::ReaderBuilder::new().has_headers(false).from_reader(raw.as_slice());
+                    let header = reader.records()
+                        .next()
+                        .and_then(|r| r.ok())
+                        .ok_or_else(|| SecurityError::ValidationError("Missing CSV header".to_string()))?;
+                    headers = Some(header);
+                    continue;
+                }
+
+                index += 1;
+                report.received += 1;
+
+                let parsed = match (&params.format, &headers) {
+                    (ImportFormat::Ndjson, _) => parse_ndjson(&raw),
+                    (ImportFormat::Csv, Some(headers)) => parse_csv(&raw, headers),
+                    (ImportFormat::Csv, None) => unreachable!("header is read before data records"),
+                };
+
+                match parsed.and_then(|record| record.into_mapped(&params.tenant_id, self.clock.now())) {
+                    Ok(mapped) => batch.push(mapped),
+                    Err(reason) => {
+                        report.rejected += 1;
+                        if report.errors.len() < self.config.import_max_errors {
+                            report.errors.push(ImportError { record: index, reason });
+                        } else {
+                            report.truncated_errors = true;
+                        }
+                    }
+                }
+
+                if batch.len() >= self.config.import_batch_size {
+                    if !params.dry_run {
+                        let inserted = self.insert_imported(&batch).await?;
+                        report.imported += inserted;
+                        report.duplicates += batch.len() as u64 - inserted;
+                    }
+                    batch.clear();
+                }
+            }
+
+            if done {
+                break;
+            }
+        }
+
+        if !params.dry_run {
+            let inserted = self.insert_imported(&batch).await?;
+            report.imported += inserted;
+            report.duplicates += batch.len() as u64 - inserted;
+        }
+
+        Ok(report)
+    }
+}
+
+// HTTP handlers
+
+pub async fn import_handler(
+    req: HttpRequest,
+    params: web::Query<ImportParams>,
+    body: web::Payload,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let service = &state.audit_service;
+    match service.import_legacy(&params, body).await {
+        Ok(report) => {
+            info!(
+                "Legacy audit import by {}: {} imported, {} duplicates, {} rejected",
+                principal.subject, report.imported, report.duplicates, report.rejected
+            );
+            if !params.dry_run {
+                let _ = service.record(NewAuditEvent {
+                    tenant_id: params.tenant_id.clone(),
+                    actor: principal.subject.clone(),
+                    actor_ip: None,
+                    action: "audit.import".to_string(),
+                    resource: "audit_events".to_string(),
+                    outcome: "success".to_string(),
+                    payload: serde_json::json!({
+                        "imported": report.imported,
+                        "duplicates": report.duplicates,
+                        "rejected": report.rejected,
+                    }),
+                }).await;
+            }
+            Ok(HttpResponse::Ok().json(report))
+        }
+        Err(SecurityError::ValidationError(msg)) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        }))),
+        Err(e) => {
+            error!("Legacy audit import failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Audit import failed"
+            })))
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/import", web::post().to(import_handler));
+}
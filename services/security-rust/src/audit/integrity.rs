@@ -0,0 +1,232 @@
+/*!
+Audit Integrity
+Scheduled verification of exported audit archives
+
+Every completed export records the SHA-256 of its object. The monitor
+re-reads a sample of exports each pass, least recently verified first, so
+the whole archive is covered over time. A changed or vanished object is
+flagged on its job row and raises a critical alert straight away; flagged
+exports drop out of sampling until someone investigates them.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use futures::TryStreamExt;
+use object_store::path::Path;
+use ring::digest;
+use serde::Serialize;
+use sqlx::FromRow;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::alerting::{Alert, Severity};
+use crate::auth::auth_error_response;
+use crate::errors::SecurityError;
+use super::export::{ExportJob, EXPORT_COLUMNS};
+use super::{AuditService, NewAuditEvent};
+
+/// Who violations are audited as.
+const SYSTEM_ACTOR: &str = "system:audit_integrity";
+
+/// An export whose stored object no longer matches its checksum.
+#[derive(Debug, Clone, Serialize)]
+pub struct Violation {
+    pub export_id: Uuid,
+    pub object_key: String,
+    /// `mismatch` or `missing`.
+    pub integrity: &'static str,
+    pub expected: String,
+    pub actual: Option<String>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct IntegrityCount {
+    pub integrity: String,
+    pub exports: i64,
+}
+
+#[derive(FromRow)]
+struct Sample {
+    id: Uuid,
+    object_key: String,
+    sha256: String,
+    region: Option<String>,
+}
+
+fn integrity_error(e: impl std::fmt::Display) -> SecurityError {
+    SecurityError::AuditError(format!("Integrity check failed: {}", e))
+}
+
+impl AuditService {
+    /// Hex SHA-256 of a stored export object, streamed from the store.
+    pub(crate) async fn checksum(&self, region: Option<&str>, key: &Path) -> Result<String, SecurityError> {
+        let store = self.store_in(region).map_err(integrity_error)?;
+        let mut stream = store.get(key).await.map_err(integrity_error)?.into_stream();
+
+        let mut context = digest::Context::new(&digest::SHA256);
+        while let Some(chunk) = stream.try_next().await.map_err(integrity_error)? {
+            context.update(&chunk);
+        }
+        Ok(hex::encode(context.finish().as_ref()))
+    }
+
+    /// Re-check a sample of exports, returning the ones that failed.
+    pub async fn verify_sample(&self) -> Result<Vec<Violation>, SecurityError> {
+        if self.export_store.is_none() {
+            return Ok(Vec::new());
+        }
+
+        let mut tx = self.storage.begin().await?;
+        let samples = sqlx::query_as::<_, Sample>(
+            "SELECT id, object_key, sha256, region FROM audit_exports \
+             WHERE sha256 IS NOT NULL AND object_key IS NOT NULL AND integrity IN ('unverified', 'ok') \
+             ORDER BY verified_at NULLS FIRST LIMIT $1 FOR UPDATE SKIP LOCKED",
+        )
+        .bind(self.config.integrity_sample_size)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut violations = Vec::new();
+        for sample in samples {
+            let key = Path::from(sample.object_key.as_str());
+            let region = sample.region.as_deref();
+            let store = match self.store_in(region) {
+                Ok(store) => store.clone(),
+                Err(e) => {
+                    warn!("Could not verify audit export {}: {}", sample.id, e);
+                    continue;
+                }
+            };
+            let (integrity, actual) = match self.checksum(region, &key).await {
+                Ok(actual) if actual == sample.sha256 => ("ok", Some(actual)),
+                Ok(actual) => ("mismatch", Some(actual)),
+                Err(_) if matches!(store.head(&key).await, Err(object_store::Error::NotFound { .. })) => ("missing", None),
+                Err(e) => {
+                    // Store trouble says nothing about the object; try again next pass
+                    warn!("Could not verify audit export {}: {}", sample.id, e);
+                    sqlx::query("UPDATE audit_exports SET integrity_error = $2 WHERE id = $1")
+                        .bind(sample.id)
+                        .bind(e.to_string())
+                        .execute(&mut *tx)
+                        .await?;
+                    continue;
+                }
+            };
+
+            sqlx::query(
+                "UPDATE audit_exports SET integrity = $2, verified_at = NOW(), integrity_error = NULL WHERE id = $1",
+            )
+            .bind(sample.id)
+            .bind(integrity)
+            .execute(&mut *tx)
+            .await?;
+
+            if integrity != "ok" {
+                violations.push(Violation {
+                    export_id: sample.id,
+                    object_key: sample.object_key,
+                    integrity,
+                    expected: sample.sha256,
+                    actual,
+                });
+            }
+        }
+        tx.commit().await?;
+
+        Ok(violations)
+    }
+
+    pub async fn integrity_summary(&self) -> Result<(Vec<IntegrityCount>, Vec<ExportJob>), SecurityError> {
+        let counts = sqlx::query_as::<_, IntegrityCount>(
+            "SELECT integrity, COUNT(*) AS exports FROM audit_exports WHERE sha256 IS NOT NULL \
+             GROUP BY integrity ORDER BY integrity",
+        )
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        let flagged = sqlx::query_as::<_, ExportJob>(&format!(
+            "SELECT {} FROM audit_exports WHERE integrity IN ('mismatch', 'missing') ORDER BY verified_at DESC",
+            EXPORT_COLUMNS
+        ))
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        Ok((counts, flagged))
+    }
+}
+
+/// Alert and audit each violation as soon as it is found.
+async fn report(state: &crate::AppState, violation: &Violation) {
+    error!(
+        "Audit export {} failed integrity check: {} ({})",
+        violation.export_id, violation.integrity, violation.object_key
+    );
+    let details = serde_json::to_value(violation).unwrap_or_default();
+
+    let alert = Alert::new(
+        "audit_integrity",
+        Severity::Critical,
+        format!("Audit archive {}: {}", violation.integrity, violation.object_key),
+        details.clone(),
+        state.clock.now(),
+    );
+    state.alerting_service.send(&alert, &[]).await;
+
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: None,
+        actor: SYSTEM_ACTOR.to_string(),
+        actor_ip: None,
+        action: "audit.integrity_violation".to_string(),
+        resource: format!("audit_export:{}", violation.export_id),
+        outcome: "failure".to_string(),
+        payload: details,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit integrity violation on {}: {:?}", violation.export_id, e);
+    }
+}
+
+/// Background loop verifying archive samples.
+pub async fn run_monitor(state: web::Data<crate::AppState>) {
+    let interval_secs = state.config.audit.integrity_interval_secs;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+
+    loop {
+        interval.tick().await;
+        match state.audit_service.verify_sample().await {
+            Ok(violations) => {
+                for violation in &violations {
+                    report(&state, violation).await;
+                }
+            }
+            Err(e) => error!("Audit integrity pass failed: {:?}", e),
+        }
+    }
+}
+
+// HTTP handlers
+
+pub async fn integrity_handler(
+    req: HttpRequest,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.audit_service.integrity_summary().await {
+        Ok((counts, flagged)) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "counts": counts,
+            "flagged": flagged
+        }))),
+        Err(e) => {
+            error!("Audit integrity lookup failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Audit integrity lookup failed"
+            })))
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/integrity", web::get().to(integrity_handler));
+}
@@ -0,0 +1,387 @@
+/*!
+Audit Module
+Security event recording and role-aware audit queries
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use object_store::ObjectStore;
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::auth::Principal;
+use crate::clock::Clock;
+use crate::config::{AuditConfig, Config};
+use crate::errors::SecurityError;
+use crate::health::{CheckFuture, Criticality, HealthRegistry};
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::Storage;
+
+pub mod chain;
+pub mod export;
+pub mod filter;
+pub mod import;
+pub mod integrity;
+pub mod receipts;
+pub mod retention;
+pub mod saved_searches;
+pub mod siem;
+pub mod stats;
+pub mod visibility;
+
+use filter::Filter;
+use visibility::{AuditView, ProjectedAuditEvent, Pseudonymizer};
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AuditEvent {
+    pub id: Uuid,
+    pub occurred_at: DateTime<Utc>,
+    pub tenant_id: Option<String>,
+    pub actor: String,
+    pub actor_ip: Option<String>,
+    pub action: String,
+    pub resource: String,
+    pub outcome: String,
+    pub payload: serde_json::Value,
+    /// `live` for events recorded by this service, `imported` for backfilled legacy records.
+    pub source: String,
+}
+
+pub(crate) const EVENT_COLUMNS: &str =
+    "id, occurred_at, tenant_id, actor, actor_ip, action, resource, outcome, payload, source";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewAuditEvent {
+    pub tenant_id: Option<String>,
+    pub actor: String,
+    pub actor_ip: Option<String>,
+    pub action: String,
+    pub resource: String,
+    pub outcome: String,
+    #[serde(default)]
+    pub payload: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditQuery {
+    pub tenant_id: Option<String>,
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub resource: Option<String>,
+    pub outcome: Option<String>,
+    pub source: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+    /// OData-style filter expression, see `filter` for the grammar.
+    #[serde(rename = "$filter")]
+    pub filter: Option<String>,
+    /// Fields the caller's view may not filter on; set by `AuditView::restrict`.
+    #[serde(skip)]
+    pub(crate) denied_fields: Vec<&'static str>,
+}
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "occurred_at", column: "occurred_at", kind: KeyKind::Timestamp },
+];
+
+pub struct AuditService {
+    storage: Storage,
+    config: AuditConfig,
+    pseudonymizer: Pseudonymizer,
+    export_store: Option<Arc<dyn ObjectStore>>,
+    /// Export stores of the tenants pinned elsewhere, by region.
+    regional_stores: HashMap<String, Arc<dyn ObjectStore>>,
+    /// Events accepted while storage was unreachable, oldest first.
+    buffer: Mutex<VecDeque<(Uuid, DateTime<Utc>, NewAuditEvent)>>,
+    /// Failed outcomes as they are recorded, for `monitoring::threats`.
+    failures: broadcast::Sender<NewAuditEvent>,
+    clock: Arc<dyn Clock>,
+}
+
+impl AuditService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let pseudonymizer = Pseudonymizer::new(config.audit.pseudonymization_key.as_bytes());
+        let export_store = export::build_store(&config.audit)?;
+        let regional_stores = crate::residency::build_stores(config)?;
+
+        info!("Audit service initialized successfully");
+        Ok(Self {
+            storage,
+            config: config.audit.clone(),
+            pseudonymizer,
+            export_store,
+            regional_stores,
+            buffer: Mutex::new(VecDeque::new()),
+            failures: broadcast::channel(config.threats.queue_size).0,
+            clock,
+        })
+    }
+
+    pub async fn is_ready(&self) -> bool {
+        self.storage.is_ready().await
+    }
+
+    /// Record an event. When storage is down the event is buffered in memory,
+    /// keeping its original timestamp, and written by `flush_buffer` later.
+    pub async fn record(&self, event: NewAuditEvent) -> Result<Uuid, SecurityError> {
+        let id = Uuid::new_v4();
+        let occurred_at = self.clock.now();
+        if event.outcome == "failure" && self.failures.receiver_count() > 0 {
+            let _ = self.failures.send(event.clone());
+        }
+
+        match self.insert(id, occurred_at, &event).await {
+            Ok(()) => Ok(id),
+            Err(SecurityError::StorageError(e)) => {
+                let mut buffer = self.buffer.lock().unwrap();
+                if buffer.len() >= self.config.buffer_max_events {
+                    return Err(SecurityError::AuditError(format!("Audit buffer full and storage unavailable: {}", e)));
+                }
+                warn!("Audit storage unavailable, buffering event {}: {}", id, e);
+                buffer.push_back((id, occurred_at, event));
+                Ok(id)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn insert(&self, id: Uuid, occurred_at: DateTime<Utc>, event: &NewAuditEvent) -> Result<(), SecurityError> {
+        sqlx::query(
+            "INSERT INTO audit_events (id, occurred_at, tenant_id, actor, actor_ip, action, resource, outcome, payload) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) ON CONFLICT (id) DO NOTHING",
+        )
+        .bind(id)
+        .bind(occurred_at)
+        .bind(&event.tenant_id)
+        .bind(&event.actor)
+        .bind(&event.actor_ip)
+        .bind(&event.action)
+        .bind(&event.resource)
+        .bind(&event.outcome)
+        .bind(&event.payload)
+        .execute(self.storage.pool())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Follow events recorded with a `failure` outcome from now on.
+    pub fn subscribe_failures(&self) -> broadcast::Receiver<NewAuditEvent> {
+        self.failures.subscribe()
+    }
+
+    pub fn buffered(&self) -> usize {
+        self.buffer.lock().unwrap().len()
+    }
+
+    /// Write buffered events in order, stopping at the first failure.
+    pub async fn flush_buffer(&self) -> Result<usize, SecurityError> {
+        let mut flushed = 0;
+        loop {
+            let Some((id, occurred_at, event)) = self.buffer.lock().unwrap().pop_front() else {
+                return Ok(flushed);
+            };
+            if let Err(e) = self.insert(id, occurred_at, &event).await {
+                self.buffer.lock().unwrap().push_front((id, occurred_at, event));
+                return Err(e);
+            }
+            flushed += 1;
+        }
+    }
+
+    pub async fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEvent>, SecurityError> {
+        let limit = query.limit
+            .unwrap_or(100)
+            .clamp(1, self.config.max_query_limit);
+
+        let mut builder = self.filtered_query(query)?;
+        builder.push(" ORDER BY occurred_at DESC LIMIT ").push_bind(limit);
+
+        let events = builder
+            .build_query_as::<AuditEvent>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(events)
+    }
+
+    pub async fn query_page(
+        &self,
+        query: &AuditQuery,
+        page: &PageRequest,
+    ) -> Result<Page<AuditEvent>, SecurityError> {
+        let mut builder = self.filtered_query(query)?;
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let events = builder
+            .build_query_as::<AuditEvent>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(events, |event, _| (SortKey::Timestamp(event.occurred_at), event.id)))
+    }
+
+    /// Query through the principal's view, returning projected records.
+    pub async fn query_visible(
+        &self,
+        principal: &Principal,
+        mut query: AuditQuery,
+    ) -> Result<Vec<ProjectedAuditEvent>, SecurityError> {
+        let view = AuditView::for_principal(principal, &self.config)?;
+        view.restrict(&mut query);
+
+        let events = self.query(&query).await?;
+        Ok(events
+            .into_iter()
+            .map(|event| view.project(event, &self.pseudonymizer))
+            .collect())
+    }
+
+    /// `SELECT ... WHERE` with every filter of `query` applied; callers add ordering and limits.
+    pub(crate) fn filtered_query(&self, query: &AuditQuery) -> Result<QueryBuilder<'static, Postgres>, SecurityError> {
+        let mut builder = base_filtered_query(query);
+
+        if let Some(expression) = &query.filter {
+            let filter = Filter::parse(expression, self.config.filter_max_cost)?;
+            filter.ensure_allowed(&query.denied_fields)?;
+            builder.push(" AND ");
+            filter.push_sql(&mut builder);
+        }
+
+        Ok(builder)
+    }
+}
+
+fn base_filtered_query(query: &AuditQuery) -> QueryBuilder<'static, Postgres> {
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+        "SELECT {} FROM audit_events WHERE 1 = 1",
+        EVENT_COLUMNS
+    ));
+
+    if let Some(tenant_id) = &query.tenant_id {
+        builder.push(" AND tenant_id = ").push_bind(tenant_id.clone());
+    }
+    if let Some(actor) = &query.actor {
+        builder.push(" AND actor = ").push_bind(actor.clone());
+    }
+    if let Some(action) = &query.action {
+        builder.push(" AND action = ").push_bind(action.clone());
+    }
+    if let Some(resource) = &query.resource {
+        builder.push(" AND resource = ").push_bind(resource.clone());
+    }
+    if let Some(outcome) = &query.outcome {
+        builder.push(" AND outcome = ").push_bind(outcome.clone());
+    }
+    if let Some(source) = &query.source {
+        builder.push(" AND source = ").push_bind(source.clone());
+    }
+    if let Some(since) = query.since {
+        builder.push(" AND occurred_at >= ").push_bind(since);
+    }
+    if let Some(until) = query.until {
+        builder.push(" AND occurred_at < ").push_bind(until);
+    }
+
+    builder
+}
+
+// HTTP handlers
+
+pub async fn events_handler(
+    req: HttpRequest,
+    query: web::Query<AuditQuery>,
+    page: web::Query<PageParams>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => {
+            warn!("Audit query rejected: {:?}", e);
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Authentication required"
+            })));
+        }
+    };
+
+    let service = &state.audit_service;
+    let view = match AuditView::for_principal(&principal, &service.config) {
+        Ok(view) => view,
+        Err(e) => {
+            warn!("Audit query denied for {}: {:?}", principal.subject, e);
+            return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "Insufficient privileges for audit data"
+            })));
+        }
+    };
+
+    let mut query = query.into_inner();
+    view.restrict(&mut query);
+
+    let result = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => service.query_page(&query, &page).await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(page) => {
+            let page = page.map(|event| view.project(event, &service.pseudonymizer));
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "events": page.items,
+                "page": page.info,
+                "view": view.name()
+            })))
+        }
+        Err(SecurityError::ValidationError(msg)) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        }))),
+        Err(SecurityError::AccessDenied(msg)) => Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg
+        }))),
+        Err(e) => {
+            error!("Audit query failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Audit query failed"
+            })))
+        }
+    }
+}
+
+/// Recording survives a storage outage by buffering, so a backlog degrades
+/// rather than fails readiness.
+fn buffer_drained(state: &crate::AppState) -> CheckFuture<'_> {
+    Box::pin(async move {
+        match state.audit_service.buffered() {
+            0 => Ok(()),
+            n => Err(format!("{} events buffered awaiting storage", n)),
+        }
+    })
+}
+
+pub fn register_health_checks(registry: &mut HealthRegistry) {
+    registry.register("audit", Criticality::NonCritical, buffer_drained);
+    registry.register("siem", Criticality::NonCritical, siem::sinks_healthy);
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/audit")
+            .route("/events", web::get().to(events_handler))
+            .configure(saved_searches::configure_routes)
+            .configure(import::configure_routes)
+            .configure(export::configure_routes)
+            .configure(integrity::configure_routes)
+            .configure(chain::configure_routes)
+            .configure(siem::configure_routes)
+            .configure(retention::configure_routes)
+            .configure(receipts::configure_routes)
+            .configure(stats::configure_routes)
+    );
+}
@@ -0,0 +1,198 @@
+/*!
+Audit Receipts
+Signed proof, for the caller, that a sensitive operation was recorded
+
+When an audited action is one of `AUDIT_RECEIPT_ACTIONS` the caller gets
+back, in `X-Audit-Receipt`, a compact JWT signed with the
+`AUDIT_RECEIPT_ALGORITHM` key:
+
+- `iss`: the token issuer
+- `sub`: the actor
+- `op`: the audit action
+- `res`: SHA-256 of the audited resource
+- `iat`: when the receipt was issued
+- `jti`: the audit event id
+
+The caller keeps it and can later show that the operation happened, as
+that actor, on that resource. It verifies offline against
+`/.well-known/jwks.json` like any token, or with
+`POST /audit/receipts/verify`, which also checks it still matches the
+recorded event. The endpoint checks signatures only while the signing
+key is published; once it is retired, older receipts are checked offline
+against a saved copy of its public key.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::auth::auth_error_response;
+use crate::errors::SecurityError;
+use crate::signing::Algorithm;
+use crate::AppState;
+use super::{AuditEvent, AuditService, NewAuditEvent, EVENT_COLUMNS};
+
+/// Response header carrying the receipt.
+pub const RECEIPT_HEADER: &str = "X-Audit-Receipt";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Receipt {
+    pub iss: String,
+    pub sub: String,
+    pub op: String,
+    pub res: String,
+    pub iat: i64,
+    pub jti: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyReceiptRequest {
+    pub receipt: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReceiptVerification {
+    /// Signature and recorded event both check out.
+    pub valid: bool,
+    pub signature_valid: bool,
+    /// Whether the event is in the audit trail and matches; `None` when
+    /// the signature failed and it was not looked up.
+    pub matches_event: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub claims: Option<Receipt>,
+}
+
+impl AuditService {
+    async fn event(&self, id: Uuid) -> Result<Option<AuditEvent>, SecurityError> {
+        let event = sqlx::query_as::<_, AuditEvent>(&format!("SELECT {} FROM audit_events WHERE id = $1", EVENT_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?;
+        Ok(event)
+    }
+}
+
+fn resource_hash(resource: &str) -> String {
+    hex::encode(digest::digest(&digest::SHA256, resource.as_bytes()))
+}
+
+fn decode_part(part: &str) -> Option<Vec<u8>> {
+    base64::decode_config(part, base64::URL_SAFE_NO_PAD).ok()
+}
+
+/// Record `event`, signing a receipt for it when its action calls for one.
+pub async fn record(state: &AppState, event: NewAuditEvent) -> Result<(Uuid, Option<String>), SecurityError> {
+    let wanted = state.config.audit.receipt_actions.contains(&event.action);
+    let id = state.audit_service.record(event.clone()).await?;
+    if !wanted {
+        return Ok((id, None));
+    }
+
+    let algorithm = Algorithm::parse(&state.config.audit.receipt_algorithm)
+        .ok_or_else(|| SecurityError::ConfigError("Invalid AUDIT_RECEIPT_ALGORITHM".to_string()))?;
+    let receipt = state.crypto_service.signing_keys().sign_jwt(algorithm, &Receipt {
+        iss: state.config.auth.token_issuer.clone(),
+        sub: event.actor,
+        op: event.action,
+        res: resource_hash(&event.resource),
+        iat: state.clock.now().timestamp(),
+        jti: id,
+    })?;
+    Ok((id, Some(receipt)))
+}
+
+/// `record` for handlers, which answer even when auditing fails.
+pub async fn record_or_warn(state: &AppState, event: NewAuditEvent) -> Option<String> {
+    let action = event.action.clone();
+    match record(state, event).await {
+        Ok((_, receipt)) => receipt,
+        Err(e) => {
+            warn!("Failed to audit {} with receipt: {:?}", action, e);
+            None
+        }
+    }
+}
+
+/// Check a receipt's signature, then that the event it names was recorded
+/// as it says.
+pub async fn verify(state: &AppState, receipt: &str) -> Result<ReceiptVerification, SecurityError> {
+    let rejected = |reason| ReceiptVerification {
+        valid: false,
+        signature_valid: false,
+        matches_event: None,
+        reason: Some(reason),
+        claims: None,
+    };
+
+    let parts: Vec<&str> = receipt.trim().split('.').collect();
+    let [header, payload, signature] = parts[..] else {
+        return Ok(rejected("malformed"));
+    };
+    let header: serde_json::Value = match decode_part(header).and_then(|h| serde_json::from_slice(&h).ok()) {
+        Some(header) => header,
+        None => return Ok(rejected("malformed")),
+    };
+    let (Some(algorithm), Some(key_id), Some(signature), Some(claims)) = (
+        header["alg"].as_str().and_then(Algorithm::parse).filter(|a| *a != Algorithm::Hs256),
+        header["kid"].as_str(),
+        decode_part(signature),
+        decode_part(payload).and_then(|p| serde_json::from_slice::<Receipt>(&p).ok()),
+    ) else {
+        return Ok(rejected("malformed"));
+    };
+
+    let signing_input = format!("{}.{}", parts[0], parts[1]);
+    let signature_valid = match state.crypto_service.signing_keys().verify_jwt_signature(algorithm, key_id, &signing_input, &signature) {
+        Ok(valid) => valid,
+        Err(SecurityError::NotFound(_)) => false,
+        Err(e) => return Err(e),
+    };
+    if !signature_valid {
+        return Ok(ReceiptVerification { claims: Some(claims), ..rejected("bad_signature") });
+    }
+
+    let reason = match state.audit_service.event(claims.jti).await? {
+        None => Some("event_not_found"),
+        Some(event) if event.actor != claims.sub || event.action != claims.op
+            || resource_hash(&event.resource) != claims.res => Some("event_mismatch"),
+        Some(_) => None,
+    };
+
+    Ok(ReceiptVerification {
+        valid: reason.is_none(),
+        signature_valid,
+        matches_event: Some(reason.is_none()),
+        reason,
+        claims: Some(claims),
+    })
+}
+
+// HTTP handlers
+
+pub async fn verify_handler(
+    req: HttpRequest,
+    request: web::Json<VerifyReceiptRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authenticate(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match verify(&state, &request.receipt).await {
+        Ok(verification) => Ok(HttpResponse::Ok().json(verification)),
+        Err(e) => {
+            error!("Receipt verification failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Receipt verification failed"
+            })))
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/receipts/verify", web::post().to(verify_handler));
+}
@@ -0,0 +1,341 @@
+/*!
+Audit Retention
+Ageing old events out of the database, archived first
+
+With `AUDIT_RETENTION_DAYS` set, every `AUDIT_RETENTION_INTERVAL_SECS` the
+oldest events are removed in chain order (see `chain`), up to the latest
+checkpoint signed more than that many days ago. Retention only ever cuts a
+prefix of the chain, so what remains still verifies: the last removed
+event's hash is kept on the chain head and `GET /audit/verify` starts from
+it. Events some SIEM sink (see `siem`) has yet to receive are kept.
+
+With `AUDIT_RETENTION_ARCHIVE` (the default) each batch of up to
+`AUDIT_RETENTION_BATCH_SIZE` events is first written to the export bucket
+as gzipped NDJSON, chain fields included, under `<prefix>/archive/`. The
+archive is recorded as a completed export, so the integrity monitor keeps
+checking it and the batch can be verified offline against its neighbours
+and the checkpoints. Without it, events are deleted outright.
+
+Events of tenants pinned to another region (see `residency`) go to an
+archive of their own in that region's bucket, named after the batch with
+the region appended, so a batch may span several archives.
+
+`GET /audit/retention` shows the policy, where the chain was cut and the
+archives written.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use async_compression::tokio::write::GzipEncoder;
+use chrono::{DateTime, Duration, Utc};
+use object_store::buffered::BufWriter;
+use object_store::path::Path;
+use serde::Serialize;
+use sqlx::FromRow;
+use std::collections::{BTreeMap, HashMap};
+use tokio::io::AsyncWriteExt;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::auth::auth_error_response;
+use crate::errors::SecurityError;
+use super::chain::ChainedEvent;
+use super::{AuditService, EVENT_COLUMNS};
+
+/// Who archives are exported as.
+const SYSTEM_ACTOR: &str = "system:audit_retention";
+
+const ARCHIVE_COLUMNS: &str = "a.id, a.first_index, a.last_index, a.oldest_occurred_at, a.newest_occurred_at, \
+    a.row_count, a.export_id, e.object_key, e.sha256, e.integrity, a.created_at";
+
+/// Archives listed by `GET /audit/retention`.
+const RECENT_ARCHIVES: i64 = 50;
+
+/// A chain range removed by retention.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Archive {
+    pub id: Uuid,
+    pub first_index: i64,
+    pub last_index: i64,
+    pub oldest_occurred_at: DateTime<Utc>,
+    pub newest_occurred_at: DateTime<Utc>,
+    pub row_count: i64,
+    /// Export holding the events; none when deleted without archiving.
+    pub export_id: Option<Uuid>,
+    pub object_key: Option<String>,
+    pub sha256: Option<String>,
+    pub integrity: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RetentionStatus {
+    pub retention_days: i64,
+    pub archive: bool,
+    /// Index of the last removed event.
+    pub pruned_through: i64,
+    /// Latest index retention may currently remove up to.
+    pub eligible_through: i64,
+    pub archives: Vec<Archive>,
+}
+
+fn archive_error(e: impl std::fmt::Display) -> SecurityError {
+    SecurityError::AuditError(format!("Archive failed: {}", e))
+}
+
+impl AuditService {
+    /// Last chain index retention may remove at `now`: the newest checkpoint
+    /// old enough, short of anything a SIEM sink has not been sent.
+    async fn retention_limit(&self, now: DateTime<Utc>) -> Result<i64, SecurityError> {
+        if self.config.retention_days == 0 {
+            return Ok(0);
+        }
+        let pool = self.storage.pool();
+        let (checkpointed,): (Option<i64>,) =
+            sqlx::query_as("SELECT MAX(chain_index) FROM audit_checkpoints WHERE created_at < $1")
+                .bind(now - Duration::days(self.config.retention_days))
+                .fetch_one(pool)
+                .await?;
+
+        let sinks: Vec<String> = self.config.siem_sinks.iter().map(|(name, _)| name.clone()).collect();
+        let (unsent,): (Option<i64>,) =
+            sqlx::query_as("SELECT MIN(chain_index) FROM audit_siem_cursors WHERE sink = ANY($1)")
+                .bind(&sinks)
+                .fetch_one(pool)
+                .await?;
+
+        let limit = checkpointed.unwrap_or(0);
+        Ok(unsent.map_or(limit, |cursor| limit.min(cursor)))
+    }
+
+    async fn write_archive(&self, region: Option<&str>, key: &Path, events: &[&ChainedEvent]) -> Result<(), SecurityError> {
+        let store = self.store_in(region).map_err(archive_error)?.clone();
+        let mut encoder = GzipEncoder::new(BufWriter::new(store, key.clone()));
+
+        let mut line = Vec::with_capacity(1024);
+        for event in events {
+            line.clear();
+            serde_json::to_writer(&mut line, event).map_err(archive_error)?;
+            line.push(b'\n');
+            if let Err(e) = encoder.write_all(&line).await {
+                let _ = encoder.get_mut().abort().await;
+                return Err(archive_error(e));
+            }
+        }
+
+        encoder.shutdown().await.map_err(archive_error)
+    }
+
+    /// Remove the next batch of expired events, archiving them first when
+    /// configured. Returns how many were removed.
+    pub async fn apply_retention(&self, now: DateTime<Utc>) -> Result<usize, SecurityError> {
+        let limit = self.retention_limit(now).await?;
+        let pool = self.storage.pool();
+        let (pruned,): (i64,) = sqlx::query_as("SELECT pruned_index FROM audit_chain_head")
+            .fetch_one(pool)
+            .await?;
+        if limit <= pruned {
+            return Ok(0);
+        }
+
+        let last = limit.min(pruned + self.config.retention_batch_size);
+        let events = sqlx::query_as::<_, ChainedEvent>(&format!(
+            "SELECT {}, chain_index, prev_hash, chain_hash FROM audit_events \
+             WHERE chain_index > $1 AND chain_index <= $2 ORDER BY chain_index",
+            EVENT_COLUMNS
+        ))
+        .bind(pruned)
+        .bind(last)
+        .fetch_all(pool)
+        .await?;
+        // A gap means the chain is already broken; keep the evidence
+        if events.len() as i64 != last - pruned {
+            return Err(SecurityError::AuditError(format!(
+                "Chain has gaps between {} and {}; retention stopped, see /audit/verify",
+                pruned + 1,
+                last
+            )));
+        }
+        let (Some(oldest), Some(newest), Some(last_hash)) = (
+            events.iter().map(|e| e.event.occurred_at).min(),
+            events.iter().map(|e| e.event.occurred_at).max(),
+            events.last().map(|e| e.chain_hash.clone()),
+        ) else {
+            return Ok(0);
+        };
+
+        // Written before taking the lock; a replica racing us writes the
+        // same objects under the same keys
+        let mut archived = Vec::new();
+        if self.config.retention_archive {
+            let regions: Vec<String> = self.regional_stores.keys().cloned().collect();
+            let pinned: HashMap<String, String> =
+                sqlx::query_as("SELECT tenant_id, region FROM residency_policies WHERE region = ANY($1)")
+                    .bind(&regions)
+                    .fetch_all(pool)
+                    .await?
+                    .into_iter()
+                    .collect();
+            let mut parts: BTreeMap<Option<String>, Vec<&ChainedEvent>> = BTreeMap::new();
+            for event in &events {
+                let region = event.event.tenant_id.as_ref().and_then(|tenant| pinned.get(tenant)).cloned();
+                parts.entry(region).or_default().push(event);
+            }
+            for (region, part) in parts {
+                let suffix = region.as_ref().map(|region| format!(".{}", region)).unwrap_or_default();
+                let key = Path::from(format!(
+                    "{}/archive/{:020}-{:020}{}.ndjson.gz",
+                    self.config.export_prefix,
+                    pruned + 1,
+                    last,
+                    suffix
+                ));
+                self.write_archive(region.as_deref(), &key, &part).await?;
+                let sha256 = self.checksum(region.as_deref(), &key).await?;
+                let mut tenants: Vec<String> = part.iter().filter_map(|event| event.event.tenant_id.clone()).collect();
+                tenants.sort();
+                tenants.dedup();
+                archived.push((region, key, sha256, part.len(), tenants));
+            }
+        }
+
+        let mut tx = self.storage.begin().await?;
+        let (current,): (i64,) = sqlx::query_as("SELECT pruned_index FROM audit_chain_head FOR UPDATE")
+            .fetch_one(&mut *tx)
+            .await?;
+        if current != pruned {
+            return Ok(0);
+        }
+
+        // The archive is the home region's part, or the first when there is none
+        let mut export_id = None;
+        for (region, key, sha256, rows, tenants) in archived {
+            let id = Uuid::new_v4();
+            sqlx::query(
+                "INSERT INTO audit_exports (id, requested_by, view, format, query, status, object_key, row_count, \
+                 sha256, region, completed_at) VALUES ($1, $2, 'archive', 'ndjson', $3, 'completed', $4, $5, $6, $7, NOW())",
+            )
+            .bind(id)
+            .bind(SYSTEM_ACTOR)
+            .bind(serde_json::json!({ "from": pruned + 1, "to": last, "region": region }))
+            .bind(key.to_string())
+            .bind(rows as i64)
+            .bind(sha256)
+            .bind(&region)
+            .execute(&mut *tx)
+            .await?;
+            for tenant in tenants.iter().filter(|_| region.is_some()) {
+                sqlx::query(
+                    "INSERT INTO residency_placements (id, tenant_id, kind, resource, region, created_at) \
+                     VALUES ($1, $2, 'audit_archive', $3, $4, NOW())",
+                )
+                .bind(Uuid::new_v4())
+                .bind(tenant)
+                .bind(format!("audit_export:{}", id))
+                .bind(&region)
+                .execute(&mut *tx)
+                .await?;
+            }
+            if export_id.is_none() || region.is_none() {
+                export_id = Some(id);
+            }
+        }
+
+        sqlx::query("DELETE FROM audit_events WHERE chain_index > $1 AND chain_index <= $2")
+            .bind(pruned)
+            .bind(last)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT INTO audit_archives (id, first_index, last_index, oldest_occurred_at, newest_occurred_at, \
+             row_count, export_id) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(Uuid::new_v4())
+        .bind(pruned + 1)
+        .bind(last)
+        .bind(oldest)
+        .bind(newest)
+        .bind(events.len() as i64)
+        .bind(export_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE audit_chain_head SET pruned_index = $1, pruned_hash = $2")
+            .bind(last)
+            .bind(last_hash)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        info!("Audit retention removed events {} to {}", pruned + 1, last);
+        Ok(events.len())
+    }
+
+    pub async fn retention_status(&self) -> Result<RetentionStatus, SecurityError> {
+        let pool = self.storage.pool();
+        let (pruned_through,): (i64,) = sqlx::query_as("SELECT pruned_index FROM audit_chain_head")
+            .fetch_one(pool)
+            .await?;
+        let archives = sqlx::query_as::<_, Archive>(&format!(
+            "SELECT {} FROM audit_archives a LEFT JOIN audit_exports e ON e.id = a.export_id \
+             ORDER BY a.first_index DESC LIMIT $1",
+            ARCHIVE_COLUMNS
+        ))
+        .bind(RECENT_ARCHIVES)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(RetentionStatus {
+            retention_days: self.config.retention_days,
+            archive: self.config.retention_archive,
+            pruned_through,
+            eligible_through: self.retention_limit(self.clock.now()).await?.max(pruned_through),
+            archives,
+        })
+    }
+}
+
+/// Background loop removing expired events.
+pub async fn run_retention(state: web::Data<crate::AppState>) {
+    if state.config.audit.retention_days == 0 {
+        return;
+    }
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        state.config.audit.retention_interval_secs,
+    ));
+
+    loop {
+        interval.tick().await;
+        // Work through the backlog a batch at a time
+        loop {
+            match state.audit_service.apply_retention(state.clock.now()).await {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Audit retention pass failed: {:?}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// HTTP handlers
+
+pub async fn status_handler(req: HttpRequest, state: web::Data<crate::AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.audit_service.retention_status().await {
+        Ok(status) => Ok(HttpResponse::Ok().json(status)),
+        Err(e) => {
+            error!("Audit retention lookup failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Audit retention lookup failed"
+            })))
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/retention", web::get().to(status_handler));
+}
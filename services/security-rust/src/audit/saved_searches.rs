@@ -0,0 +1,567 @@
+/*!
+Saved Audit Searches
+Per-user saved filters, sharing, and scheduled digest delivery
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::alerting::{Alert, Severity};
+use crate::auth::Principal;
+use crate::errors::SecurityError;
+use crate::events::{self, DomainEvent};
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::soft_delete;
+use super::visibility::{AuditView, ProjectedAuditEvent};
+use super::{AuditQuery, AuditService};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Schedule {
+    Hourly,
+    Daily,
+    Weekly,
+}
+
+impl Schedule {
+    pub fn period(&self) -> Duration {
+        match self {
+            Schedule::Hourly => Duration::hours(1),
+            Schedule::Daily => Duration::days(1),
+            Schedule::Weekly => Duration::weeks(1),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Schedule::Hourly => "hourly",
+            Schedule::Daily => "daily",
+            Schedule::Weekly => "weekly",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "hourly" => Some(Schedule::Hourly),
+            "daily" => Some(Schedule::Daily),
+            "weekly" => Some(Schedule::Weekly),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SavedSearchRequest {
+    pub name: String,
+    pub query: AuditQuery,
+    pub schedule: Option<Schedule>,
+    #[serde(default)]
+    pub sinks: Vec<String>,
+    #[serde(default)]
+    pub shared_with: Vec<String>,
+    #[serde(default)]
+    pub shared_with_tenant: bool,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct SavedSearch {
+    pub id: Uuid,
+    pub owner: String,
+    pub owner_tenant_id: Option<String>,
+    #[serde(skip_serializing)]
+    pub owner_roles: Vec<String>,
+    pub name: String,
+    pub query: sqlx::types::Json<AuditQuery>,
+    pub schedule: Option<String>,
+    pub sinks: Vec<String>,
+    pub shared_with: Vec<String>,
+    pub shared_with_tenant: bool,
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl SavedSearch {
+    fn owner_principal(&self) -> Principal {
+        Principal {
+            subject: self.owner.clone(),
+            tenant_id: self.owner_tenant_id.clone(),
+            roles: self.owner_roles.clone(),
+            scopes: Vec::new(),
+            token_id: None,
+            delegation: Vec::new(),
+        }
+    }
+
+    fn visible_to(&self, principal: &Principal) -> bool {
+        self.owner == principal.subject
+            || self.shared_with.contains(&principal.subject)
+            || (self.shared_with_tenant
+                && self.owner_tenant_id.is_some()
+                && self.owner_tenant_id == principal.tenant_id)
+    }
+}
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "name", column: "name", kind: KeyKind::Text },
+    SortField { name: "created_at", column: "created_at", kind: KeyKind::Timestamp },
+];
+
+const SELECT_COLUMNS: &str = "id, owner, owner_tenant_id, owner_roles, name, query, schedule, sinks, \
+    shared_with, shared_with_tenant, next_run_at, last_run_at, created_at, deleted_at";
+
+impl AuditService {
+    pub async fn create_saved_search(
+        &self,
+        owner: &Principal,
+        request: SavedSearchRequest,
+    ) -> Result<SavedSearch, SecurityError> {
+        if request.name.trim().is_empty() {
+            return Err(SecurityError::ValidationError("Search name is required".to_string()));
+        }
+
+        let next_run_at = request.schedule.map(|s| self.clock.now() + s.period());
+
+        let mut tx = self.storage.begin().await?;
+
+        let search = sqlx::query_as::<_, SavedSearch>(&format!(
+            "INSERT INTO audit_saved_searches \
+             (id, owner, owner_tenant_id, owner_roles, name, query, schedule, sinks, shared_with, shared_with_tenant, next_run_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(&owner.subject)
+        .bind(&owner.tenant_id)
+        .bind(&owner.roles)
+        .bind(request.name.trim())
+        .bind(sqlx::types::Json(&request.query))
+        .bind(request.schedule.map(|s| s.as_str()))
+        .bind(&request.sinks)
+        .bind(&request.shared_with)
+        .bind(request.shared_with_tenant)
+        .bind(next_run_at)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let event = DomainEvent::new(
+            "audit.saved_search.created",
+            "audit_saved_search",
+            search.id,
+            search.owner_tenant_id.clone(),
+            serde_json::json!({
+                "owner": search.owner,
+                "name": search.name,
+                "schedule": search.schedule,
+            }),
+            self.clock.now(),
+        );
+        events::enqueue(&mut tx, &event).await?;
+        tx.commit().await?;
+
+        Ok(search)
+    }
+
+    pub async fn list_saved_searches(
+        &self,
+        principal: &Principal,
+        page: &PageRequest,
+    ) -> Result<Page<SavedSearch>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM audit_saved_searches WHERE deleted_at IS NULL AND (owner = ",
+            SELECT_COLUMNS
+        ));
+        builder.push_bind(principal.subject.clone())
+            .push(" OR ")
+            .push_bind(principal.subject.clone())
+            .push(" = ANY(shared_with) OR (shared_with_tenant AND owner_tenant_id IS NOT NULL AND owner_tenant_id = ")
+            .push_bind(principal.tenant_id.clone())
+            .push("))");
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let searches = builder
+            .build_query_as::<SavedSearch>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(searches, |search, sort| {
+            let key = match sort {
+                "created_at" => SortKey::Timestamp(search.created_at),
+                _ => SortKey::Text(search.name.clone()),
+            };
+            (key, search.id)
+        }))
+    }
+
+    pub async fn get_saved_search(&self, principal: &Principal, id: Uuid) -> Result<SavedSearch, SecurityError> {
+        let search = sqlx::query_as::<_, SavedSearch>(&format!(
+            "SELECT {} FROM audit_saved_searches WHERE id = $1 AND deleted_at IS NULL",
+            SELECT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("Saved search not found".to_string()))?;
+
+        if !search.visible_to(principal) {
+            // Do not disclose the existence of other users' searches
+            return Err(SecurityError::NotFound("Saved search not found".to_string()));
+        }
+
+        Ok(search)
+    }
+
+    /// Soft-delete; the search stays restorable until purged.
+    pub async fn delete_saved_search(&self, principal: &Principal, id: Uuid) -> Result<(), SecurityError> {
+        let result = sqlx::query(
+            "UPDATE audit_saved_searches SET deleted_at = NOW() WHERE id = $1 AND owner = $2 AND deleted_at IS NULL",
+        )
+        .bind(id)
+        .bind(&principal.subject)
+        .execute(self.storage.pool())
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(SecurityError::NotFound("Saved search not found".to_string()));
+        }
+        Ok(())
+    }
+
+    /// The caller's deleted searches that can still be restored.
+    pub async fn deleted_saved_searches(
+        &self,
+        principal: &Principal,
+        restorable_since: DateTime<Utc>,
+    ) -> Result<Vec<SavedSearch>, SecurityError> {
+        let searches = sqlx::query_as::<_, SavedSearch>(&format!(
+            "SELECT {} FROM audit_saved_searches \
+             WHERE owner = $1 AND deleted_at >= $2 ORDER BY deleted_at DESC",
+            SELECT_COLUMNS
+        ))
+        .bind(&principal.subject)
+        .bind(restorable_since)
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        Ok(searches)
+    }
+
+    pub async fn restore_saved_search(
+        &self,
+        principal: &Principal,
+        id: Uuid,
+        restorable_since: DateTime<Utc>,
+    ) -> Result<SavedSearch, SecurityError> {
+        let restored = sqlx::query_as::<_, SavedSearch>(&format!(
+            "UPDATE audit_saved_searches SET deleted_at = NULL \
+             WHERE id = $1 AND owner = $2 AND deleted_at >= $3 RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(id)
+        .bind(&principal.subject)
+        .bind(restorable_since)
+        .fetch_optional(self.storage.pool())
+        .await;
+
+        match restored {
+            Ok(Some(search)) => Ok(search),
+            Ok(None) => Err(SecurityError::NotFound("No restorable saved search".to_string())),
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Err(SecurityError::ValidationError(
+                "A saved search with this name already exists".to_string(),
+            )),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Hard-delete searches soft-deleted before `cutoff`.
+    pub async fn purge_deleted_saved_searches(&self, cutoff: DateTime<Utc>) -> Result<u64, SecurityError> {
+        let result = sqlx::query("DELETE FROM audit_saved_searches WHERE deleted_at < $1")
+            .bind(cutoff)
+            .execute(self.storage.pool())
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Execute a saved search with the view of whoever is running it.
+    pub async fn run_saved_search(
+        &self,
+        search: &SavedSearch,
+        view: &AuditView,
+        window: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> Result<Vec<ProjectedAuditEvent>, SecurityError> {
+        let mut query = search.query.0.clone();
+        if let Some((since, until)) = window {
+            query.since = Some(since);
+            query.until = Some(until);
+        }
+        view.restrict(&mut query);
+
+        let events = self.query(&query).await?;
+        Ok(events
+            .into_iter()
+            .map(|event| view.project(event, &self.pseudonymizer))
+            .collect())
+    }
+
+    async fn due_saved_searches(&self, now: DateTime<Utc>) -> Result<Vec<SavedSearch>, SecurityError> {
+        let searches = sqlx::query_as::<_, SavedSearch>(&format!(
+            "SELECT {} FROM audit_saved_searches \
+             WHERE schedule IS NOT NULL AND deleted_at IS NULL AND next_run_at <= $1 ORDER BY next_run_at",
+            SELECT_COLUMNS
+        ))
+        .bind(now)
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        Ok(searches)
+    }
+
+    async fn mark_saved_search_run(
+        &self,
+        id: Uuid,
+        ran_at: DateTime<Utc>,
+        next_run_at: DateTime<Utc>,
+    ) -> Result<(), SecurityError> {
+        sqlx::query("UPDATE audit_saved_searches SET last_run_at = $2, next_run_at = $3 WHERE id = $1")
+            .bind(id)
+            .bind(ran_at)
+            .bind(next_run_at)
+            .execute(self.storage.pool())
+            .await?;
+        Ok(())
+    }
+}
+
+/// Background loop delivering scheduled searches as digests through the alerting sinks.
+pub async fn run_scheduler(state: web::Data<crate::AppState>) {
+    let interval_secs = state.audit_service.config.scheduler_interval_secs;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = run_due_searches(&state).await {
+            error!("Scheduled audit searches failed: {:?}", e);
+        }
+    }
+}
+
+async fn run_due_searches(state: &crate::AppState) -> Result<(), SecurityError> {
+    let service = &state.audit_service;
+    let now = state.clock.now();
+
+    for search in service.due_saved_searches(now).await? {
+        let schedule = match search.schedule.as_deref().and_then(Schedule::parse) {
+            Some(schedule) => schedule,
+            None => continue,
+        };
+
+        // Scheduled runs see exactly what the owner could see interactively
+        let view = match AuditView::for_principal(&search.owner_principal(), &service.config) {
+            Ok(view) => view,
+            Err(e) => {
+                warn!("Skipping saved search {}: owner lost audit access ({:?})", search.id, e);
+                service.mark_saved_search_run(search.id, now, now + schedule.period()).await?;
+                continue;
+            }
+        };
+
+        let since = search.last_run_at.unwrap_or(now - schedule.period());
+        let events = service.run_saved_search(&search, &view, Some((since, now))).await?;
+
+        if !events.is_empty() {
+            let total = events.len();
+            let sample: Vec<_> = events.into_iter().take(service.config.digest_max_events).collect();
+            let alert = Alert::new(
+                "audit.saved_search",
+                Severity::Info,
+                format!("Audit digest '{}': {} matching events", search.name, total),
+                serde_json::json!({
+                    "search_id": search.id,
+                    "owner": search.owner,
+                    "window": { "since": since, "until": now },
+                    "total": total,
+                    "events": sample,
+                }),
+                now,
+            );
+            let failed = state.alerting_service.send(&alert, &search.sinks).await;
+            if !failed.is_empty() {
+                warn!("Digest for saved search {} not delivered to {:?}", search.id, failed);
+            }
+        }
+
+        service.mark_saved_search_run(search.id, now, now + schedule.period()).await?;
+        info!("Saved search {} executed", search.id);
+    }
+
+    Ok(())
+}
+
+// HTTP handlers
+
+#[allow(clippy::result_large_err)]
+fn authorize(req: &HttpRequest, state: &crate::AppState) -> Result<(Principal, AuditView), HttpResponse> {
+    let principal = state.auth_service.authenticate(req).map_err(|e| {
+        warn!("Saved search request rejected: {:?}", e);
+        HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Authentication required"
+        }))
+    })?;
+
+    let view = AuditView::for_principal(&principal, &state.audit_service.config).map_err(|_| {
+        HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Insufficient privileges for audit data"
+        }))
+    })?;
+
+    Ok((principal, view))
+}
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Saved search operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Saved search operation failed"
+            }))
+        }
+    }
+}
+
+pub async fn create_search_handler(
+    req: HttpRequest,
+    request: web::Json<SavedSearchRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let (principal, _) = match authorize(&req, &state) {
+        Ok(auth) => auth,
+        Err(response) => return Ok(response),
+    };
+
+    match state.audit_service.create_saved_search(&principal, request.into_inner()).await {
+        Ok(search) => Ok(HttpResponse::Created().json(search)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_searches_handler(
+    req: HttpRequest,
+    page: web::Query<PageParams>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let (principal, _) = match authorize(&req, &state) {
+        Ok(auth) => auth,
+        Err(response) => return Ok(response),
+    };
+
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Asc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.audit_service.list_saved_searches(&principal, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "searches": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn run_search_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let (principal, view) = match authorize(&req, &state) {
+        Ok(auth) => auth,
+        Err(response) => return Ok(response),
+    };
+
+    let service = &state.audit_service;
+    let result = match service.get_saved_search(&principal, path.into_inner()).await {
+        Ok(search) => service.run_saved_search(&search, &view, None).await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(events) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "events": events,
+            "view": view.name()
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn delete_search_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let (principal, _) = match authorize(&req, &state) {
+        Ok(auth) => auth,
+        Err(response) => return Ok(response),
+    };
+
+    match state.audit_service.delete_saved_search(&principal, path.into_inner()).await {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn deleted_searches_handler(
+    req: HttpRequest,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let (principal, _) = match authorize(&req, &state) {
+        Ok(auth) => auth,
+        Err(response) => return Ok(response),
+    };
+
+    let since = soft_delete::restorable_since(&state.config, state.clock.now());
+    match state.audit_service.deleted_saved_searches(&principal, since).await {
+        Ok(searches) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "searches": searches
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn restore_search_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let (principal, _) = match authorize(&req, &state) {
+        Ok(auth) => auth,
+        Err(response) => return Ok(response),
+    };
+
+    let since = soft_delete::restorable_since(&state.config, state.clock.now());
+    match state.audit_service.restore_saved_search(&principal, path.into_inner(), since).await {
+        Ok(search) => Ok(HttpResponse::Ok().json(search)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/searches", web::post().to(create_search_handler))
+        .route("/searches", web::get().to(list_searches_handler))
+        .route("/searches/deleted", web::get().to(deleted_searches_handler))
+        .route("/searches/{id}/run", web::post().to(run_search_handler))
+        .route("/searches/{id}/restore", web::post().to(restore_search_handler))
+        .route("/searches/{id}", web::delete().to(delete_search_handler));
+}
@@ -0,0 +1,715 @@
+/*!
+SIEM Export
+Streams the audit trail to SIEMs as it is written
+
+Each sink in `AUDIT_SIEM_SINKS` (`name=url`) follows the hash chain (see
+`chain`) with its own cursor, so it receives every event exactly in chain
+order, with `chain_index` and `chain_hash` for checking on the far side:
+
+- `syslog+udp://host:514`, `syslog+tcp://host:514`, `syslog+tls://host:6514`:
+  RFC 5424 messages carrying CEF, octet-counted on TCP and TLS
+- `splunk+https://host:8088[/path]`: Splunk HTTP Event Collector, by default
+  at `/services/collector/event`. The HEC token is the `siem.<name>`
+  outbound credential (see `credentials`), typically a `header` credential
+  for `Authorization: Splunk <token>`.
+- `kafka://broker:9092[;broker:9092...]/topic`: one JSON message per event
+  keyed by event id. Needs the `kafka` feature.
+
+Every `AUDIT_SIEM_INTERVAL_MS` each sink is sent up to
+`AUDIT_SIEM_BATCH_SIZE` events past its cursor, more while full batches
+show a backlog. Only one batch per sink is in flight and the cursor moves
+only once the sink has taken it, so a slow or down SIEM falls behind
+instead of losing events: the audit table is the buffer. Failures back off
+exponentially up to `AUDIT_SIEM_MAX_BACKOFF_SECS`. Cursors are shared, so
+replicas take turns per sink.
+
+A new sink starts at the chain head; moving its cursor back with
+`PUT /audit/siem/{sink}/cursor` replays history. `/ready` reports sinks that
+are failing or more than `AUDIT_SIEM_MAX_LAG` events behind, and
+`GET /audit/siem` shows every sink's position.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::future::{join_all, BoxFuture};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tracing::{error, info, warn};
+
+use crate::audit::NewAuditEvent;
+use crate::auth::auth_error_response;
+use crate::config::{AuditConfig, Config};
+use crate::credentials::CredentialCache;
+use crate::errors::SecurityError;
+use crate::health::CheckFuture;
+use crate::storage::Storage;
+use super::{AuditEvent, EVENT_COLUMNS};
+
+const CURSOR_COLUMNS: &str = "sink, chain_index, failures, last_error, last_success_at, updated_at";
+
+/// CEF header fields.
+const CEF_VENDOR: &str = "COTAI";
+const CEF_PRODUCT: &str = "cotai-security";
+const CEF_VERSION: &str = "1.0";
+
+/// authpriv, for security and authorization messages.
+const SYSLOG_FACILITY: u8 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Udp,
+    Tcp,
+    Tls,
+}
+
+/// Where a sink sends, parsed from its `AUDIT_SIEM_SINKS` url.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Syslog { transport: Transport, host: String, port: u16 },
+    Splunk { url: String },
+    Kafka { brokers: String, topic: String },
+}
+
+impl Target {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let (scheme, rest) = url.split_once("://").ok_or("must be a url")?;
+        match scheme {
+            "syslog+udp" | "syslog+tcp" | "syslog+tls" => {
+                let transport = match scheme {
+                    "syslog+udp" => Transport::Udp,
+                    "syslog+tcp" => Transport::Tcp,
+                    _ => Transport::Tls,
+                };
+                let parsed = reqwest::Url::parse(url).map_err(|e| e.to_string())?;
+                let host = parsed.host_str().filter(|host| !host.is_empty()).ok_or("needs a host")?;
+                let default_port = if transport == Transport::Tls { 6514 } else { 514 };
+                Ok(Target::Syslog {
+                    transport,
+                    host: host.to_string(),
+                    port: parsed.port().unwrap_or(default_port),
+                })
+            }
+            "splunk+https" | "splunk+http" => {
+                let scheme = scheme.trim_start_matches("splunk+");
+                let mut parsed = reqwest::Url::parse(&format!("{}://{}", scheme, rest)).map_err(|e| e.to_string())?;
+                if parsed.path() == "/" {
+                    parsed.set_path("/services/collector/event");
+                }
+                Ok(Target::Splunk { url: parsed.to_string() })
+            }
+            "kafka" => {
+                if !cfg!(feature = "kafka") {
+                    return Err("kafka sinks need the kafka feature".to_string());
+                }
+                let (brokers, topic) = rest.split_once('/').ok_or("needs a topic")?;
+                if brokers.is_empty() || topic.is_empty() || topic.contains('/') {
+                    return Err("must be kafka://broker[;broker...]/topic".to_string());
+                }
+                Ok(Target::Kafka { brokers: brokers.replace(';', ","), topic: topic.to_string() })
+            }
+            other => Err(format!("unsupported scheme '{}'", other)),
+        }
+    }
+}
+
+/// An audit event with its place in the chain.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct SiemEvent {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub event: AuditEvent,
+    pub chain_index: i64,
+    pub chain_hash: String,
+}
+
+pub trait SiemSink: Send + Sync {
+    /// Deliver a batch, all or nothing as far as the cursor is concerned:
+    /// a failed batch is sent again whole.
+    fn send<'a>(&'a self, events: &'a [SiemEvent]) -> BoxFuture<'a, Result<(), SecurityError>>;
+}
+
+fn sink_error(e: impl std::fmt::Display) -> SecurityError {
+    SecurityError::DeliveryError(e.to_string())
+}
+
+/// Escape a CEF header field.
+fn cef_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+/// Escape a CEF extension value.
+fn cef_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+}
+
+/// The event as a CEF record.
+pub fn cef(event: &SiemEvent) -> String {
+    let audit = &event.event;
+    let severity = if audit.outcome == "success" { 3 } else { 7 };
+    let mut extension = vec![
+        format!("rt={}", audit.occurred_at.timestamp_millis()),
+        format!("externalId={}", audit.id),
+        format!("suser={}", cef_value(&audit.actor)),
+        format!("outcome={}", cef_value(&audit.outcome)),
+        format!("act={}", cef_value(&audit.action)),
+        format!("cs1Label=resource cs1={}", cef_value(&audit.resource)),
+        format!("cn1Label=chainIndex cn1={}", event.chain_index),
+        format!("cs3Label=chainHash cs3={}", event.chain_hash),
+    ];
+    if let Some(ip) = &audit.actor_ip {
+        extension.push(format!("src={}", cef_value(ip)));
+    }
+    if let Some(tenant_id) = &audit.tenant_id {
+        extension.push(format!("cs2Label=tenant cs2={}", cef_value(tenant_id)));
+    }
+    if !audit.payload.is_null() {
+        extension.push(format!("msg={}", cef_value(&audit.payload.to_string())));
+    }
+
+    format!(
+        "CEF:0|{}|{}|{}|{}|{}|{}|{}",
+        CEF_VENDOR,
+        CEF_PRODUCT,
+        CEF_VERSION,
+        cef_header(&audit.action),
+        cef_header(&audit.action),
+        severity,
+        extension.join(" ")
+    )
+}
+
+/// The event as an RFC 5424 message with a CEF body.
+fn syslog_message(event: &SiemEvent, hostname: &str) -> String {
+    // warning for failures, informational otherwise
+    let severity = if event.event.outcome == "success" { 6 } else { 4 };
+    format!(
+        "<{}>1 {} {} {} - audit - {}",
+        SYSLOG_FACILITY * 8 + severity,
+        event.event.occurred_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        hostname,
+        CEF_PRODUCT,
+        cef(event)
+    )
+}
+
+type Connection = Box<dyn AsyncWrite + Send + Unpin>;
+
+struct SyslogSink {
+    transport: Transport,
+    host: String,
+    port: u16,
+    hostname: String,
+    /// Kept open between batches; dropped on error to reconnect.
+    connection: tokio::sync::Mutex<Option<Connection>>,
+}
+
+impl SyslogSink {
+    async fn connect(&self) -> Result<Connection, SecurityError> {
+        let tcp = tokio::net::TcpStream::connect((self.host.as_str(), self.port)).await.map_err(sink_error)?;
+        if self.transport == Transport::Tcp {
+            return Ok(Box::new(tcp));
+        }
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+            rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+                anchor.subject,
+                anchor.spki,
+                anchor.name_constraints,
+            )
+        }));
+        let config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let server_name = rustls::ServerName::try_from(self.host.as_str()).map_err(sink_error)?;
+        let tls = tokio_rustls::TlsConnector::from(Arc::new(config))
+            .connect(server_name, tcp)
+            .await
+            .map_err(sink_error)?;
+        Ok(Box::new(tls))
+    }
+
+    async fn send_stream(&self, messages: Vec<String>) -> Result<(), SecurityError> {
+        let mut connection = self.connection.lock().await;
+        if connection.is_none() {
+            *connection = Some(self.connect().await?);
+        }
+        let Some(stream) = connection.as_mut() else {
+            return Err(sink_error("not connected"));
+        };
+
+        // RFC 6587 octet counting
+        let mut framed = Vec::new();
+        for message in messages {
+            framed.extend_from_slice(format!("{} {}", message.len(), message).as_bytes());
+        }
+        let written = async {
+            stream.write_all(&framed).await?;
+            stream.flush().await
+        }
+        .await;
+        if let Err(e) = written {
+            *connection = None;
+            return Err(sink_error(e));
+        }
+        Ok(())
+    }
+
+    async fn send_datagrams(&self, messages: Vec<String>) -> Result<(), SecurityError> {
+        let socket = tokio::net::UdpSocket::bind("0.0.0.0:0").await.map_err(sink_error)?;
+        socket.connect((self.host.as_str(), self.port)).await.map_err(sink_error)?;
+        for message in messages {
+            socket.send(message.as_bytes()).await.map_err(sink_error)?;
+        }
+        Ok(())
+    }
+}
+
+impl SiemSink for SyslogSink {
+    fn send<'a>(&'a self, events: &'a [SiemEvent]) -> BoxFuture<'a, Result<(), SecurityError>> {
+        Box::pin(async move {
+            let messages = events.iter().map(|event| syslog_message(event, &self.hostname)).collect();
+            match self.transport {
+                Transport::Udp => self.send_datagrams(messages).await,
+                Transport::Tcp | Transport::Tls => self.send_stream(messages).await,
+            }
+        })
+    }
+}
+
+struct SplunkSink {
+    url: String,
+    /// Outbound credential holding the HEC token.
+    credential: String,
+    hostname: String,
+    client: reqwest::Client,
+    credentials: Arc<CredentialCache>,
+}
+
+impl SiemSink for SplunkSink {
+    fn send<'a>(&'a self, events: &'a [SiemEvent]) -> BoxFuture<'a, Result<(), SecurityError>> {
+        Box::pin(async move {
+            if self.credentials.get(&self.credential).is_none() {
+                return Err(sink_error(format!("No outbound credential '{}' for the HEC token", self.credential)));
+            }
+
+            // HEC takes concatenated event objects in one request
+            let mut body = String::new();
+            for event in events {
+                let record = serde_json::json!({
+                    "time": event.event.occurred_at.timestamp_millis() as f64 / 1000.0,
+                    "host": self.hostname,
+                    "source": CEF_PRODUCT,
+                    "sourcetype": "cotai:audit",
+                    "event": event
+                });
+                body.push_str(&record.to_string());
+                body.push('\n');
+            }
+
+            let request = self.client.post(&self.url).header("Content-Type", "application/json").body(body);
+            let response = self.credentials.apply(&self.credential, request)
+                .send()
+                .await
+                .map_err(sink_error)?;
+            if !response.status().is_success() {
+                return Err(sink_error(format!("HEC returned {}", response.status())));
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(feature = "kafka")]
+struct KafkaSink {
+    topic: String,
+    timeout: Duration,
+    producer: rdkafka::producer::FutureProducer,
+}
+
+#[cfg(feature = "kafka")]
+impl KafkaSink {
+    fn new(brokers: &str, topic: &str, timeout: Duration) -> Result<Self, SecurityError> {
+        let producer = rdkafka::ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("message.timeout.ms", timeout.as_millis().to_string())
+            .set("enable.idempotence", "true")
+            .create()
+            .map_err(|e| SecurityError::ConfigError(format!("Kafka producer: {}", e)))?;
+        Ok(Self { topic: topic.to_string(), timeout, producer })
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl SiemSink for KafkaSink {
+    fn send<'a>(&'a self, events: &'a [SiemEvent]) -> BoxFuture<'a, Result<(), SecurityError>> {
+        Box::pin(async move {
+            let mut payloads = Vec::with_capacity(events.len());
+            for event in events {
+                payloads.push((event.event.id.to_string(), serde_json::to_string(event).map_err(sink_error)?));
+            }
+
+            let deliveries = payloads.iter().map(|(key, payload)| {
+                self.producer.send(
+                    rdkafka::producer::FutureRecord::to(&self.topic).key(key).payload(payload),
+                    rdkafka::util::Timeout::After(self.timeout),
+                )
+            });
+            for delivery in join_all(deliveries).await {
+                delivery.map_err(|(e, _)| sink_error(e))?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// A sink's position in the chain, shared by replicas.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct SinkCursor {
+    pub sink: String,
+    pub chain_index: i64,
+    /// Consecutive failed batches.
+    pub failures: i32,
+    pub last_error: Option<String>,
+    pub last_success_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SinkStatus {
+    #[serde(flatten)]
+    pub cursor: SinkCursor,
+    /// Events linked but not yet sent.
+    pub lag: i64,
+    pub healthy: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CursorRequest {
+    pub chain_index: i64,
+}
+
+pub struct SiemExporter {
+    storage: Storage,
+    config: AuditConfig,
+    sinks: Vec<(String, Arc<dyn SiemSink>)>,
+    /// When each sink may be tried again after a failure.
+    retry_at: Mutex<HashMap<String, Instant>>,
+}
+
+impl SiemExporter {
+    pub async fn new(config: &Config, storage: Storage, credentials: Arc<CredentialCache>) -> Result<Self, SecurityError> {
+        let audit = &config.audit;
+        let timeout = Duration::from_secs(audit.siem_timeout_secs);
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| SecurityError::ConfigError(format!("SIEM client: {}", e)))?;
+
+        let mut sinks: Vec<(String, Arc<dyn SiemSink>)> = Vec::new();
+        for (name, url) in &audit.siem_sinks {
+            let target = Target::parse(url)
+                .map_err(|e| SecurityError::ConfigError(format!("SIEM sink '{}': {}", name, e)))?;
+            let sink: Arc<dyn SiemSink> = match target {
+                Target::Syslog { transport, host, port } => Arc::new(SyslogSink {
+                    transport,
+                    host,
+                    port,
+                    hostname: audit.siem_hostname.clone(),
+                    connection: tokio::sync::Mutex::new(None),
+                }),
+                Target::Splunk { url } => Arc::new(SplunkSink {
+                    url,
+                    credential: format!("siem.{}", name),
+                    hostname: audit.siem_hostname.clone(),
+                    client: client.clone(),
+                    credentials: credentials.clone(),
+                }),
+                #[cfg(feature = "kafka")]
+                Target::Kafka { brokers, topic } => Arc::new(KafkaSink::new(&brokers, &topic, timeout)?),
+                #[cfg(not(feature = "kafka"))]
+                Target::Kafka { .. } => {
+                    return Err(SecurityError::ConfigError("Kafka sinks need the kafka feature".to_string()));
+                }
+            };
+            sinks.push((name.clone(), sink));
+        }
+
+        info!("SIEM exporter initialized with {} sinks", sinks.len());
+        Ok(Self {
+            storage,
+            config: audit.clone(),
+            sinks,
+            retry_at: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn sink_names(&self) -> Vec<String> {
+        self.sinks.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Send one batch to every sink that is not backing off. Returns the
+    /// largest batch sent, so callers can tell when there is a backlog.
+    pub async fn export(&self) -> usize {
+        let results = join_all(self.sinks.iter().map(|(name, sink)| async move {
+            let result = self.export_sink(name, sink.as_ref()).await;
+            (name, result)
+        }))
+        .await;
+
+        let mut largest = 0;
+        for (name, result) in results {
+            match result {
+                Ok(sent) => largest = largest.max(sent),
+                Err(e) => warn!("SIEM export to '{}' failed: {}", name, e),
+            }
+        }
+        largest
+    }
+
+    /// Export until a batch comes back short, i.e. the backlog is sent or a
+    /// sink is failing.
+    pub async fn drain(&self) {
+        let batch_size = self.config.siem_batch_size as usize;
+        while !self.sinks.is_empty() && self.export().await >= batch_size {}
+    }
+
+    async fn export_sink(&self, name: &str, sink: &dyn SiemSink) -> Result<usize, SecurityError> {
+        let now = Instant::now();
+        if self.retry_at.lock().unwrap().get(name).is_some_and(|at| *at > now) {
+            return Ok(0);
+        }
+
+        sqlx::query(
+            "INSERT INTO audit_siem_cursors (sink, chain_index) \
+             SELECT $1, chain_index FROM audit_chain_head ON CONFLICT (sink) DO NOTHING",
+        )
+        .bind(name)
+        .execute(self.storage.pool())
+        .await?;
+
+        let mut tx = self.storage.begin().await?;
+        let Some((cursor, failures)) = sqlx::query_as::<_, (i64, i32)>(
+            "SELECT chain_index, failures FROM audit_siem_cursors WHERE sink = $1 FOR UPDATE SKIP LOCKED",
+        )
+        .bind(name)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            // Another replica is sending this sink's batch
+            return Ok(0);
+        };
+
+        let events = sqlx::query_as::<_, SiemEvent>(&format!(
+            "SELECT {}, chain_index, chain_hash FROM audit_events \
+             WHERE chain_index > $1 ORDER BY chain_index LIMIT $2",
+            EVENT_COLUMNS
+        ))
+        .bind(cursor)
+        .bind(self.config.siem_batch_size)
+        .fetch_all(&mut *tx)
+        .await?;
+        let Some(last) = events.last().map(|event| event.chain_index) else {
+            return Ok(0);
+        };
+
+        let timeout = Duration::from_secs(self.config.siem_timeout_secs);
+        let sent = match tokio::time::timeout(timeout, sink.send(&events)).await {
+            Ok(result) => result,
+            Err(_) => Err(sink_error("timed out")),
+        };
+
+        match sent {
+            Ok(()) => {
+                sqlx::query(
+                    "UPDATE audit_siem_cursors SET chain_index = $2, failures = 0, last_error = NULL, \
+                     last_success_at = NOW(), updated_at = NOW() WHERE sink = $1",
+                )
+                .bind(name)
+                .bind(last)
+                .execute(&mut *tx)
+                .await?;
+                tx.commit().await?;
+                self.retry_at.lock().unwrap().remove(name);
+                Ok(events.len())
+            }
+            Err(e) => {
+                sqlx::query(
+                    "UPDATE audit_siem_cursors SET failures = failures + 1, last_error = $2, updated_at = NOW() \
+                     WHERE sink = $1",
+                )
+                .bind(name)
+                .bind(e.to_string())
+                .execute(&mut *tx)
+                .await?;
+                tx.commit().await?;
+
+                let backoff = (1u64 << (failures + 1).clamp(0, 16) as u32).min(self.config.siem_max_backoff_secs);
+                self.retry_at.lock().unwrap().insert(name.to_string(), now + Duration::from_secs(backoff));
+                Err(e)
+            }
+        }
+    }
+
+    /// Every configured sink's position and health.
+    pub async fn status(&self) -> Result<Vec<SinkStatus>, SecurityError> {
+        let (head,): (i64,) = sqlx::query_as("SELECT chain_index FROM audit_chain_head")
+            .fetch_one(self.storage.pool())
+            .await?;
+        let names = self.sink_names();
+        let cursors = sqlx::query_as::<_, SinkCursor>(&format!(
+            "SELECT {} FROM audit_siem_cursors WHERE sink = ANY($1) ORDER BY sink",
+            CURSOR_COLUMNS
+        ))
+        .bind(&names)
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        Ok(cursors
+            .into_iter()
+            .map(|cursor| {
+                let lag = (head - cursor.chain_index).max(0);
+                let healthy = cursor.failures == 0 && lag <= self.config.siem_max_lag;
+                SinkStatus { cursor, lag, healthy }
+            })
+            .collect())
+    }
+
+    /// Move a sink's cursor, to replay from `chain_index` onwards or to
+    /// skip ahead.
+    pub async fn set_cursor(&self, name: &str, chain_index: i64) -> Result<SinkCursor, SecurityError> {
+        if !self.sinks.iter().any(|(sink, _)| sink == name) {
+            return Err(SecurityError::NotFound(format!("Unknown SIEM sink '{}'", name)));
+        }
+        if chain_index < 0 {
+            return Err(SecurityError::ValidationError("chain_index must not be negative".to_string()));
+        }
+
+        let cursor = sqlx::query_as::<_, SinkCursor>(&format!(
+            "INSERT INTO audit_siem_cursors (sink, chain_index) VALUES ($1, $2) \
+             ON CONFLICT (sink) DO UPDATE SET chain_index = $2, failures = 0, last_error = NULL, updated_at = NOW() \
+             RETURNING {}",
+            CURSOR_COLUMNS
+        ))
+        .bind(name)
+        .bind(chain_index)
+        .fetch_one(self.storage.pool())
+        .await?;
+        self.retry_at.lock().unwrap().remove(name);
+        Ok(cursor)
+    }
+}
+
+/// Background loop streaming the chain to every sink.
+pub async fn run_export(state: web::Data<crate::AppState>) {
+    if state.siem.sinks.is_empty() {
+        return;
+    }
+    let batch_size = state.config.audit.siem_batch_size as usize;
+    let mut interval = tokio::time::interval(Duration::from_millis(state.config.audit.siem_interval_ms));
+
+    loop {
+        interval.tick().await;
+        // Keep going while full batches show a backlog
+        while state.siem.export().await >= batch_size {}
+    }
+}
+
+pub(super) fn sinks_healthy(state: &crate::AppState) -> CheckFuture<'_> {
+    Box::pin(async move {
+        if state.siem.sinks.is_empty() {
+            return Ok(());
+        }
+        let status = state.siem.status().await.map_err(|e| e.to_string())?;
+        let unhealthy: Vec<String> = status
+            .iter()
+            .filter(|sink| !sink.healthy)
+            .map(|sink| match &sink.cursor.last_error {
+                Some(e) => format!("{}: {} behind, failing: {}", sink.cursor.sink, sink.lag, e),
+                None => format!("{}: {} behind", sink.cursor.sink, sink.lag),
+            })
+            .collect();
+        if unhealthy.is_empty() {
+            Ok(())
+        } else {
+            Err(unhealthy.join("; "))
+        }
+    })
+}
+
+// HTTP handlers
+
+pub async fn status_handler(req: HttpRequest, state: web::Data<crate::AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.siem.status().await {
+        Ok(sinks) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "sinks": sinks
+        }))),
+        Err(e) => {
+            error!("SIEM status lookup failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "SIEM status lookup failed"
+            })))
+        }
+    }
+}
+
+pub async fn set_cursor_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    request: web::Json<CursorRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let sink = path.into_inner();
+    match state.siem.set_cursor(&sink, request.chain_index).await {
+        Ok(cursor) => {
+            let recorded = state.audit_service.record(NewAuditEvent {
+                tenant_id: None,
+                actor: principal.subject.clone(),
+                actor_ip: None,
+                action: "audit.siem.cursor".to_string(),
+                resource: format!("siem_sink:{}", sink),
+                outcome: "success".to_string(),
+                payload: serde_json::json!({ "chain_index": request.chain_index }),
+            }).await;
+            if let Err(e) = recorded {
+                warn!("Failed to audit SIEM cursor change for '{}': {:?}", sink, e);
+            }
+            Ok(HttpResponse::Ok().json(cursor))
+        }
+        Err(SecurityError::NotFound(msg)) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        }))),
+        Err(SecurityError::ValidationError(msg)) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        }))),
+        Err(e) => {
+            error!("SIEM cursor update failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "SIEM cursor update failed"
+            })))
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/siem", web::get().to(status_handler))
+        .route("/siem/{sink}/cursor", web::put().to(set_cursor_handler));
+}
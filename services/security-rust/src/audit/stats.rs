@@ -0,0 +1,198 @@
+/*!
+Audit Statistics
+Per-tenant authentication and decryption volumes, noised outside the SOC
+
+`GET /audit/stats` counts, per tenant over a window (or a `period` holding
+`date`, as for the purpose report), authentication attempts and failures
+(audit actions under `THREAT_AUTH_ACTIONS`) and successful plaintext reads
+(see `crypto::purpose`), with the failure rate.
+
+SOC callers see exact counts. Everyone else with audit access (tenant
+admins for their own tenant only) sees them through `privacy::NoiseLayer`:
+the window is widened to whole days, counts are noised, small ones withheld
+as `null`, and the response says so under `privacy`. A new window spends
+privacy budget, and is refused with 429 once the budget is spent.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use tracing::{error, warn};
+
+use crate::audit::visibility::AuditView;
+use crate::auth::auth_error_response;
+use crate::crypto::purpose;
+use crate::privacy::{self, NoiseLayer, Released};
+use crate::timezone::{self, Period};
+use crate::AppState;
+
+const DEFAULT_DAYS: i64 = 30;
+const MAX_DAYS: i64 = 366;
+/// Released counts one event can move: an authentication moves attempts and failures.
+const RELEASED_PER_EVENT: u32 = 2;
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Ignored for tenant admins, who only see their own tenant.
+    pub tenant_id: Option<String>,
+    pub period: Option<Period>,
+    pub date: Option<NaiveDate>,
+}
+
+#[derive(Debug, FromRow)]
+struct TenantCounts {
+    tenant_id: Option<String>,
+    auth_attempts: i64,
+    auth_failures: i64,
+    decrypts: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TenantStats {
+    pub tenant_id: Option<String>,
+    pub auth_attempts: Released,
+    pub auth_failures: Released,
+    pub auth_failure_rate: Option<f64>,
+    pub decrypts: Released,
+}
+
+impl TenantStats {
+    fn exact(counts: TenantCounts) -> Self {
+        let rate = NoiseLayer::rate(Some(counts.auth_failures), Some(counts.auth_attempts));
+        Self {
+            tenant_id: counts.tenant_id,
+            auth_attempts: Some(counts.auth_attempts),
+            auth_failures: Some(counts.auth_failures),
+            auth_failure_rate: rate,
+            decrypts: Some(counts.decrypts),
+        }
+    }
+
+    fn noised(counts: TenantCounts, noise: &NoiseLayer, window: &str) -> Self {
+        let cell = |name: &str| {
+            format!("audit.stats.{}:{}:{}", name, counts.tenant_id.as_deref().unwrap_or_default(), window)
+        };
+        let auth_attempts = noise.count(&cell("auth_attempts"), counts.auth_attempts);
+        let auth_failures = noise.count(&cell("auth_failures"), counts.auth_failures);
+        Self {
+            auth_attempts,
+            auth_failures,
+            auth_failure_rate: NoiseLayer::rate(auth_failures, auth_attempts),
+            decrypts: noise.count(&cell("decrypts"), counts.decrypts),
+            tenant_id: counts.tenant_id,
+        }
+    }
+}
+
+pub async fn stats_handler(
+    req: HttpRequest,
+    query: web::Query<StatsQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let view = match AuditView::for_principal(&principal, &state.config.audit) {
+        Ok(view) => view,
+        Err(e) => {
+            warn!("Audit statistics denied for {}: {:?}", principal.subject, e);
+            return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "Insufficient privileges for audit data"
+            })));
+        }
+    };
+
+    let query = query.into_inner();
+    let tenant_id = match &view {
+        AuditView::Tenant { tenant_id } => Some(tenant_id.clone()),
+        _ => query.tenant_id,
+    };
+    let zone = state.tenant_settings.effective(tenant_id.as_deref()).await.zone();
+    let (since, until) = match query.period {
+        Some(period) => {
+            let date = query.date.unwrap_or_else(|| timezone::local_date(zone, state.clock.now()));
+            timezone::period_bounds(zone, period, date)
+        }
+        None => {
+            let until = query.until.unwrap_or_else(|| state.clock.now());
+            (query.since.unwrap_or(until - Duration::days(DEFAULT_DAYS)), until)
+        }
+    };
+    if since >= until || until - since > Duration::days(MAX_DAYS) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("since must be before until, at most {} days apart", MAX_DAYS)
+        })));
+    }
+    let exact = view == AuditView::Full;
+    let (since, until) = match exact {
+        true => (since, until),
+        false => privacy::bucket(zone, since, until),
+    };
+
+    let auth_patterns: Vec<String> = state.config.threats.auth_actions.iter().map(|prefix| format!("{}%", prefix)).collect();
+    let counts = sqlx::query_as::<_, TenantCounts>(
+        "SELECT tenant_id, \
+         COUNT(*) FILTER (WHERE action LIKE ANY($3)) AS auth_attempts, \
+         COUNT(*) FILTER (WHERE action LIKE ANY($3) AND outcome <> 'success') AS auth_failures, \
+         COUNT(*) FILTER (WHERE action = ANY($4) AND outcome = 'success') AS decrypts \
+         FROM audit_events WHERE occurred_at >= $1 AND occurred_at < $2 \
+         AND ($5::text IS NULL OR tenant_id = $5) GROUP BY 1 ORDER BY 1 NULLS FIRST",
+    )
+    .bind(since)
+    .bind(until)
+    .bind(&auth_patterns)
+    .bind(purpose::ACTIONS)
+    .bind(&tenant_id)
+    .fetch_all(state.storage.pool())
+    .await;
+    let counts = match counts {
+        Ok(counts) => counts,
+        Err(e) => {
+            error!("Audit statistics failed: {:?}", e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Audit statistics failed"
+            })));
+        }
+    };
+
+    if !exact {
+        let mut tenants: Vec<String> = match &tenant_id {
+            Some(tenant_id) => vec![tenant_id.clone()],
+            None => counts.iter().map(|count| count.tenant_id.clone().unwrap_or_default()).collect(),
+        };
+        tenants.dedup();
+        match state.noise.spend(&state.storage, "audit.stats", &tenants, RELEASED_PER_EVENT, (since, until), state.clock.now()).await {
+            Ok(true) => {}
+            Ok(false) => return Ok(privacy::budget_spent()),
+            Err(e) => {
+                error!("Audit statistics budget check failed: {:?}", e);
+                return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": "Audit statistics failed"
+                })));
+            }
+        }
+    }
+    let window = format!("{}:{}", since.timestamp(), until.timestamp());
+    let stats: Vec<TenantStats> = counts
+        .into_iter()
+        .map(|counts| match exact {
+            true => TenantStats::exact(counts),
+            false => TenantStats::noised(counts, &state.noise, &window),
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "since": since,
+        "until": until,
+        "tenant_id": tenant_id,
+        "privacy": (!exact).then(|| state.noise.disclosure()),
+        "stats": stats
+    })))
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/stats", web::get().to(stats_handler));
+}
@@ -0,0 +1,174 @@
+/*!
+Audit Visibility
+Role-based projection of audit records applied at query time
+*/
+
+use ring::hmac;
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+use crate::auth::Principal;
+use crate::config::AuditConfig;
+use crate::errors::SecurityError;
+use super::{AuditEvent, AuditQuery};
+
+const REDACTED: &str = "[REDACTED]";
+const AUDIT_READ_SCOPE: &str = "audit:read";
+
+/// Keyed, stable pseudonyms so tenant admins can correlate actors without learning identities.
+pub struct Pseudonymizer {
+    key: hmac::Key,
+}
+
+impl Pseudonymizer {
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret),
+        }
+    }
+
+    pub fn pseudonymize(&self, value: &str) -> String {
+        let tag = hmac::sign(&self.key, value.as_bytes());
+        format!("psn_{}", hex::encode(&tag.as_ref()[..12]))
+    }
+}
+
+/// The audit view a caller is entitled to. Every audit read path must go
+/// through `restrict` and `project`; handlers never shape records themselves.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuditView {
+    /// SOC analysts: complete records across all tenants.
+    Full,
+    /// Tenant admins: own tenant only, actors pseudonymized, no IPs.
+    Tenant { tenant_id: String },
+    /// Support staff: payload values redacted, no IPs.
+    Support,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectedAuditEvent {
+    pub id: Uuid,
+    pub occurred_at: DateTime<Utc>,
+    pub tenant_id: Option<String>,
+    pub actor: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actor_ip: Option<String>,
+    pub action: String,
+    pub resource: String,
+    pub outcome: String,
+    pub payload: Value,
+    pub source: String,
+    pub redacted: bool,
+}
+
+impl AuditView {
+    pub fn for_principal(principal: &Principal, config: &AuditConfig) -> Result<Self, SecurityError> {
+        if principal.has_any_role(&config.soc_roles) {
+            return Ok(AuditView::Full);
+        }
+
+        if principal.has_any_role(&config.tenant_admin_roles) {
+            let tenant_id = principal.tenant_id.clone()
+                .ok_or_else(|| SecurityError::AccessDenied("Tenant admin token without tenant".to_string()))?;
+            return Ok(AuditView::Tenant { tenant_id });
+        }
+
+        if principal.has_any_role(&config.support_roles) {
+            return Ok(AuditView::Support);
+        }
+
+        // Machine callers (API keys) read with a scope instead of a role
+        if principal.roles.is_empty() && principal.scopes.iter().any(|s| s == AUDIT_READ_SCOPE) {
+            return Ok(match principal.tenant_id.clone() {
+                Some(tenant_id) => AuditView::Tenant { tenant_id },
+                None => AuditView::Support,
+            });
+        }
+
+        Err(SecurityError::AccessDenied("No audit role".to_string()))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuditView::Full => "full",
+            AuditView::Tenant { .. } => "tenant",
+            AuditView::Support => "support",
+        }
+    }
+
+    /// Narrow a caller-supplied query to what this view may see.
+    pub fn restrict(&self, query: &mut AuditQuery) {
+        match self {
+            AuditView::Full => {}
+            AuditView::Tenant { tenant_id } => {
+                query.tenant_id = Some(tenant_id.clone());
+                // Actors are pseudonymized for this view; filtering by real identity
+                // would allow probing who performed an action.
+                query.actor = None;
+                query.denied_fields = vec!["actor", "actor_ip"];
+            }
+            AuditView::Support => {
+                // Same reasoning for redacted payload values and hidden IPs
+                query.denied_fields = vec!["payload", "actor_ip"];
+            }
+        }
+    }
+
+    pub fn project(&self, event: AuditEvent, pseudonymizer: &Pseudonymizer) -> ProjectedAuditEvent {
+        match self {
+            AuditView::Full => ProjectedAuditEvent {
+                id: event.id,
+                occurred_at: event.occurred_at,
+                tenant_id: event.tenant_id,
+                actor: event.actor,
+                actor_ip: event.actor_ip,
+                action: event.action,
+                resource: event.resource,
+                outcome: event.outcome,
+                payload: event.payload,
+                source: event.source,
+                redacted: false,
+            },
+            AuditView::Tenant { .. } => ProjectedAuditEvent {
+                id: event.id,
+                occurred_at: event.occurred_at,
+                tenant_id: event.tenant_id,
+                actor: pseudonymizer.pseudonymize(&event.actor),
+                actor_ip: None,
+                action: event.action,
+                resource: event.resource,
+                outcome: event.outcome,
+                payload: event.payload,
+                source: event.source,
+                redacted: false,
+            },
+            AuditView::Support => ProjectedAuditEvent {
+                id: event.id,
+                occurred_at: event.occurred_at,
+                tenant_id: event.tenant_id,
+                actor: event.actor,
+                actor_ip: None,
+                action: event.action,
+                resource: event.resource,
+                outcome: event.outcome,
+                payload: redact_values(event.payload),
+                source: event.source,
+                redacted: true,
+            },
+        }
+    }
+}
+
+/// Keep the payload shape (keys, array lengths) but blank every scalar value.
+fn redact_values(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter().map(|(k, v)| (k, redact_values(v))).collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(redact_values).collect()),
+        Value::Null => Value::Null,
+        _ => Value::String(REDACTED.to_string()),
+    }
+}
@@ -0,0 +1,937 @@
+/*!
+Access Reviews
+Periodic recertification of who holds what
+
+A campaign lists the access each subject holds when it starts, one item
+per piece:
+
+- `role`: a role carried by the subject's live refresh tokens
+- `api_key`: an active API key, under the subject that created it
+- `elevation`: an active just-in-time grant (see `elevation`)
+- `consent`: a third-party application the subject authorized (see `consent`)
+
+Campaigns start every `ACCESS_REVIEW_INTERVAL_DAYS`, or when an
+`ACCESS_REVIEW_ADMIN_ROLES` holder posts to
+`/admin/access-reviews/campaigns`, optionally for one tenant. Each item is
+routed to the subject's manager, as assigned under
+`/admin/access-reviews/managers/{subject}`, or to holders of
+`ACCESS_REVIEW_FALLBACK_ROLES` for subjects without one. Reviewers find
+what awaits them at `GET /auth/access-reviews` and decide with
+`POST /auth/access-reviews/{id}`, `{"decision": "keep" | "revoke"}`; nobody
+reviews their own access. Revoking takes effect at once: role holders lose
+the refresh token families carrying the role, keys and elevations are
+revoked and consent is withdrawn with its tokens.
+
+At the deadline, `ACCESS_REVIEW_DEADLINE_DAYS` after the start, items
+nobody decided are revoked (kept, marked `unanswered`, without
+`ACCESS_REVIEW_REVOKE_UNANSWERED`) and the campaign closes. Closed
+campaigns keep their items unchanged, with a summary of outcomes and a
+SHA-256 digest of the items; `GET /admin/access-reviews/campaigns/{id}/archive`
+hands auditors (`ACCESS_REVIEW_AUDITOR_ROLES`) the whole record and checks
+it against the digest. Starts, decisions, revocations and closes are
+audited.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::NewAuditEvent;
+use crate::auth::sessions::SYSTEM_ACTOR;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{AccessReviewConfig, Config};
+use crate::errors::SecurityError;
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::Storage;
+use crate::AppState;
+
+pub const DECISIONS: &[&str] = &["keep", "revoke"];
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "created_at", column: "created_at", kind: KeyKind::Timestamp },
+];
+
+const CAMPAIGN_COLUMNS: &str = "id, name, tenant_id, status, scheduled, created_by, created_at, deadline, \
+    closed_at, summary, archive_digest";
+
+const ITEM_COLUMNS: &str = "id, campaign_id, subject, tenant_id, kind, target, detail, reviewer, decision, \
+    decided_by, decided_at, comment, outcome, created_at";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Campaign {
+    pub id: Uuid,
+    pub name: String,
+    pub tenant_id: Option<String>,
+    /// `open` or `closed`.
+    pub status: String,
+    pub scheduled: bool,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub deadline: DateTime<Utc>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub summary: Option<Value>,
+    pub archive_digest: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Item {
+    pub id: Uuid,
+    pub campaign_id: Uuid,
+    pub subject: String,
+    pub tenant_id: Option<String>,
+    /// `role`, `api_key`, `elevation` or `consent`.
+    pub kind: String,
+    /// The role, or the id of the key, elevation or client.
+    pub target: String,
+    pub detail: Value,
+    /// `None` for `ACCESS_REVIEW_FALLBACK_ROLES`.
+    pub reviewer: Option<String>,
+    /// `keep` or `revoke`, once decided.
+    pub decision: Option<String>,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub comment: Option<String>,
+    /// `kept`, `revoked`, `already_gone`, `revoke_failed` or `unanswered`.
+    pub outcome: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Access held when a campaign starts.
+#[derive(Debug, FromRow)]
+struct Holding {
+    subject: String,
+    tenant_id: Option<String>,
+    kind: String,
+    target: String,
+    detail: Value,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Manager {
+    pub subject: String,
+    pub manager: String,
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CampaignRequest {
+    pub name: String,
+    pub tenant_id: Option<String>,
+    /// Defaults to `ACCESS_REVIEW_DEADLINE_DAYS`.
+    pub deadline_days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DecisionRequest {
+    pub decision: String,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ManagerRequest {
+    pub manager: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CampaignFilter {
+    pub status: Option<String>,
+    pub tenant_id: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ItemFilter {
+    pub subject: Option<String>,
+    pub reviewer: Option<String>,
+    pub kind: Option<String>,
+    /// Only items nobody has decided.
+    #[serde(default)]
+    pub pending: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ManagerFilter {
+    pub manager: Option<String>,
+}
+
+fn archive_digest(items: &[Item]) -> String {
+    let archived = serde_json::to_string(items).unwrap_or_default();
+    hex::encode(digest(&SHA256, archived.as_bytes()))
+}
+
+pub struct AccessReviewService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: AccessReviewConfig,
+}
+
+impl AccessReviewService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        info!(
+            "Access review service initialized (every {} days, {} days to decide)",
+            config.access_review.interval_days, config.access_review.deadline_days
+        );
+        Ok(Self { storage, clock, config: config.access_review.clone() })
+    }
+
+    /// Start a campaign over the access held now. Scheduled campaigns are
+    /// skipped, returning `None`, when another replica already started one
+    /// this interval.
+    pub async fn start(
+        &self,
+        name: &str,
+        tenant_id: Option<&str>,
+        deadline_days: i64,
+        actor: &str,
+        scheduled: bool,
+    ) -> Result<Option<(Campaign, usize)>, SecurityError> {
+        let now = self.clock.now();
+        let mut tx = self.storage.begin().await?;
+        let campaign = sqlx::query_as::<_, Campaign>(&format!(
+            "INSERT INTO access_review_campaigns \
+             (id, name, tenant_id, status, scheduled, created_by, created_at, deadline) \
+             SELECT $1, $2, $3, 'open', $4, $5, $6, $7 \
+             WHERE NOT $4 OR NOT EXISTS (SELECT 1 FROM access_review_campaigns WHERE scheduled AND created_at > $8) \
+             RETURNING {}",
+            CAMPAIGN_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(name)
+        .bind(tenant_id)
+        .bind(scheduled)
+        .bind(actor)
+        .bind(now)
+        .bind(now + Duration::days(deadline_days))
+        .bind(now - Duration::days(self.config.interval_days))
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(campaign) = campaign else {
+            return Ok(None);
+        };
+
+        let holdings = sqlx::query_as::<_, Holding>(
+            "SELECT subject, tenant_id, 'role' AS kind, role AS target, \
+             jsonb_build_object('refresh_tokens', COUNT(*)) AS detail \
+             FROM (SELECT subject, tenant_id, UNNEST(roles) AS role FROM refresh_tokens \
+                   WHERE used_at IS NULL AND revoked_at IS NULL AND expires_at > $1) held \
+             WHERE $2::TEXT IS NULL OR tenant_id = $2 GROUP BY subject, tenant_id, role \
+             UNION ALL \
+             SELECT created_by, tenant_id, 'api_key', id::TEXT, jsonb_build_object('name', name, 'scopes', scopes, \
+             'expires_at', expires_at, 'last_used_at', last_used_at) FROM api_keys \
+             WHERE status = 'active' AND expires_at > $1 AND ($2::TEXT IS NULL OR tenant_id = $2) \
+             UNION ALL \
+             SELECT subject, tenant_id, 'elevation', id::TEXT, jsonb_build_object('role', role, \
+             'justification', justification, 'expires_at', expires_at) FROM elevations \
+             WHERE status = 'active' AND expires_at > $1 AND ($2::TEXT IS NULL OR tenant_id = $2) \
+             UNION ALL \
+             SELECT g.subject, g.tenant_id, 'consent', g.client_id::TEXT, jsonb_build_object('client', c.name, \
+             'scopes', g.scopes, 'granted_at', g.granted_at) \
+             FROM consent_grants g JOIN oauth_clients c ON c.id = g.client_id \
+             WHERE g.revoked_at IS NULL AND ($2::TEXT IS NULL OR g.tenant_id = $2) \
+             ORDER BY 1, 3, 4",
+        )
+        .bind(now)
+        .bind(tenant_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for holding in &holdings {
+            sqlx::query(
+                "INSERT INTO access_review_items \
+                 (id, campaign_id, subject, tenant_id, kind, target, detail, reviewer, created_at) \
+                 SELECT $1, $2, $3, $4, $5, $6, $7, \
+                 (SELECT manager FROM access_review_managers WHERE subject = $3), $8",
+            )
+            .bind(Uuid::new_v4())
+            .bind(campaign.id)
+            .bind(&holding.subject)
+            .bind(&holding.tenant_id)
+            .bind(&holding.kind)
+            .bind(&holding.target)
+            .bind(&holding.detail)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        info!("{} started access review {} with {} items", actor, campaign.id, holdings.len());
+        Ok(Some((campaign, holdings.len())))
+    }
+
+    pub async fn list(&self, filter: &CampaignFilter, page: &PageRequest) -> Result<Page<Campaign>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM access_review_campaigns WHERE 1 = 1",
+            CAMPAIGN_COLUMNS
+        ));
+        if let Some(status) = &filter.status {
+            builder.push(" AND status = ").push_bind(status.clone());
+        }
+        if let Some(tenant_id) = &filter.tenant_id {
+            builder.push(" AND tenant_id = ").push_bind(tenant_id.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let campaigns = builder
+            .build_query_as::<Campaign>()
+            .fetch_all(self.storage.pool())
+            .await?;
+        Ok(page.page(campaigns, |campaign, _| (SortKey::Timestamp(campaign.created_at), campaign.id)))
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<Campaign, SecurityError> {
+        sqlx::query_as::<_, Campaign>(&format!("SELECT {} FROM access_review_campaigns WHERE id = $1", CAMPAIGN_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound("Access review not found".to_string()))
+    }
+
+    /// Items of a campaign by decision and outcome.
+    pub async fn progress(&self, id: Uuid) -> Result<Value, SecurityError> {
+        let counts: Vec<(Option<String>, Option<String>, i64)> = sqlx::query_as(
+            "SELECT decision, outcome, COUNT(*) FROM access_review_items WHERE campaign_id = $1 \
+             GROUP BY decision, outcome ORDER BY decision, outcome",
+        )
+        .bind(id)
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        let total: i64 = counts.iter().map(|(_, _, count)| count).sum();
+        let pending: i64 = counts.iter().filter(|(decision, outcome, _)| decision.is_none() && outcome.is_none())
+            .map(|(_, _, count)| count)
+            .sum();
+        let mut outcomes = serde_json::Map::new();
+        for (_, outcome, count) in counts {
+            if let Some(outcome) = outcome {
+                let seen = outcomes.get(&outcome).and_then(Value::as_i64).unwrap_or(0);
+                outcomes.insert(outcome, (seen + count).into());
+            }
+        }
+        Ok(serde_json::json!({ "total": total, "pending": pending, "outcomes": outcomes }))
+    }
+
+    pub async fn items(&self, campaign_id: Uuid, filter: &ItemFilter, page: &PageRequest) -> Result<Page<Item>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM access_review_items WHERE campaign_id = ",
+            ITEM_COLUMNS
+        ));
+        builder.push_bind(campaign_id);
+        if let Some(subject) = &filter.subject {
+            builder.push(" AND subject = ").push_bind(subject.clone());
+        }
+        if let Some(reviewer) = &filter.reviewer {
+            builder.push(" AND reviewer = ").push_bind(reviewer.clone());
+        }
+        if let Some(kind) = &filter.kind {
+            builder.push(" AND kind = ").push_bind(kind.clone());
+        }
+        if filter.pending {
+            builder.push(" AND decision IS NULL AND outcome IS NULL");
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let items = builder
+            .build_query_as::<Item>()
+            .fetch_all(self.storage.pool())
+            .await?;
+        Ok(page.page(items, |item, _| (SortKey::Timestamp(item.created_at), item.id)))
+    }
+
+    /// Undecided items of open campaigns `reviewer` may decide.
+    pub async fn assigned(&self, reviewer: &Principal, page: &PageRequest) -> Result<Page<Item>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM access_review_items WHERE decision IS NULL AND outcome IS NULL \
+             AND campaign_id IN (SELECT id FROM access_review_campaigns WHERE status = 'open') AND subject <> ",
+            ITEM_COLUMNS
+        ));
+        builder.push_bind(reviewer.subject.clone());
+        builder.push(" AND (reviewer = ").push_bind(reviewer.subject.clone());
+        if reviewer.has_any_role(&self.config.fallback_roles) {
+            builder.push(" OR reviewer IS NULL");
+        }
+        builder.push(")");
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let items = builder
+            .build_query_as::<Item>()
+            .fetch_all(self.storage.pool())
+            .await?;
+        Ok(page.page(items, |item, _| (SortKey::Timestamp(item.created_at), item.id)))
+    }
+
+    async fn item(&self, id: Uuid) -> Result<Item, SecurityError> {
+        sqlx::query_as::<_, Item>(&format!("SELECT {} FROM access_review_items WHERE id = $1", ITEM_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound("Access review item not found".to_string()))
+    }
+
+    /// Keep or revoke one item as its reviewer, revoking at once.
+    pub async fn decide(
+        &self,
+        state: &AppState,
+        id: Uuid,
+        reviewer: &Principal,
+        request: &DecisionRequest,
+    ) -> Result<Item, SecurityError> {
+        let decision = request.decision.trim();
+        if !DECISIONS.contains(&decision) {
+            return Err(SecurityError::ValidationError("decision must be keep or revoke".to_string()));
+        }
+        let comment = request.comment.as_deref().map(str::trim).filter(|c| !c.is_empty());
+        if comment.is_some_and(|c| c.len() > 1000) {
+            return Err(SecurityError::ValidationError("comment must be at most 1000 characters".to_string()));
+        }
+
+        let item = self.item(id).await?;
+        let assigned = match &item.reviewer {
+            Some(manager) => *manager == reviewer.subject,
+            None => reviewer.has_any_role(&self.config.fallback_roles),
+        };
+        if !assigned || item.subject == reviewer.subject {
+            return Err(SecurityError::AccessDenied(format!("{} does not review this item", reviewer.subject)));
+        }
+
+        let now = self.clock.now();
+        let decided = sqlx::query_as::<_, Item>(&format!(
+            "UPDATE access_review_items SET decision = $2, decided_by = $3, decided_at = $4, comment = $5, \
+             outcome = CASE WHEN $2 = 'keep' THEN 'kept' END \
+             WHERE id = $1 AND decision IS NULL AND outcome IS NULL AND campaign_id IN \
+             (SELECT id FROM access_review_campaigns WHERE status = 'open' AND deadline > $4) RETURNING {}",
+            ITEM_COLUMNS
+        ))
+        .bind(id)
+        .bind(decision)
+        .bind(&reviewer.subject)
+        .bind(now)
+        .bind(comment)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::Conflict("Item is already decided or its review has closed".to_string()))?;
+
+        if decision == "revoke" {
+            return self.enforce(state, decided, &reviewer.subject).await;
+        }
+        Ok(decided)
+    }
+
+    /// Revoke what an item grants and record how that went.
+    async fn enforce(&self, state: &AppState, item: Item, actor: &str) -> Result<Item, SecurityError> {
+        let outcome = match revoke(state, &item, actor).await {
+            Ok(true) => "revoked",
+            Ok(false) => "already_gone",
+            Err(e) => {
+                error!("Failed to revoke {} {} of {} for access review: {:?}", item.kind, item.target, item.subject, e);
+                "revoke_failed"
+            }
+        };
+        Ok(sqlx::query_as::<_, Item>(&format!(
+            "UPDATE access_review_items SET outcome = $2 WHERE id = $1 RETURNING {}",
+            ITEM_COLUMNS
+        ))
+        .bind(item.id)
+        .bind(outcome)
+        .fetch_one(self.storage.pool())
+        .await?)
+    }
+
+    /// Open campaigns past their deadline.
+    pub async fn due(&self) -> Result<Vec<Campaign>, SecurityError> {
+        Ok(sqlx::query_as::<_, Campaign>(&format!(
+            "SELECT {} FROM access_review_campaigns WHERE status = 'open' AND deadline <= $1",
+            CAMPAIGN_COLUMNS
+        ))
+        .bind(self.clock.now())
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    /// Handle the items nobody decided, then close the campaign with its
+    /// summary and archive digest. Returns the items handled and the
+    /// closed campaign, `None` if another replica closed it first.
+    pub async fn close(&self, state: &AppState, campaign: &Campaign) -> Result<(Vec<Item>, Option<Campaign>), SecurityError> {
+        let now = self.clock.now();
+        // Unanswered items are revoked below, or marked and kept
+        let set = if self.config.revoke_unanswered {
+            "decision = 'revoke', comment = 'No decision by the deadline'"
+        } else {
+            "outcome = 'unanswered'"
+        };
+        let unanswered = sqlx::query_as::<_, Item>(&format!(
+            "UPDATE access_review_items SET {}, decided_by = $2, decided_at = $3 \
+             WHERE campaign_id = $1 AND decision IS NULL AND outcome IS NULL RETURNING {}",
+            set, ITEM_COLUMNS
+        ))
+        .bind(campaign.id)
+        .bind(SYSTEM_ACTOR)
+        .bind(now)
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        let mut handled = Vec::with_capacity(unanswered.len());
+        for item in unanswered {
+            handled.push(if item.decision.is_some() { self.enforce(state, item, SYSTEM_ACTOR).await? } else { item });
+        }
+
+        let items = sqlx::query_as::<_, Item>(&format!(
+            "SELECT {} FROM access_review_items WHERE campaign_id = $1 ORDER BY created_at, id",
+            ITEM_COLUMNS
+        ))
+        .bind(campaign.id)
+        .fetch_all(self.storage.pool())
+        .await?;
+        let summary = self.progress(campaign.id).await?;
+
+        let closed = sqlx::query_as::<_, Campaign>(&format!(
+            "UPDATE access_review_campaigns SET status = 'closed', closed_at = $2, summary = $3, archive_digest = $4 \
+             WHERE id = $1 AND status = 'open' RETURNING {}",
+            CAMPAIGN_COLUMNS
+        ))
+        .bind(campaign.id)
+        .bind(now)
+        .bind(&summary)
+        .bind(archive_digest(&items))
+        .fetch_optional(self.storage.pool())
+        .await?;
+        Ok((handled, closed))
+    }
+
+    /// A closed campaign with all its items, and whether they still match
+    /// the digest taken at close.
+    pub async fn archive(&self, id: Uuid) -> Result<Value, SecurityError> {
+        let campaign = self.get(id).await?;
+        let Some(digest) = campaign.archive_digest.clone() else {
+            return Err(SecurityError::Conflict("Access review is still open".to_string()));
+        };
+        let items = sqlx::query_as::<_, Item>(&format!(
+            "SELECT {} FROM access_review_items WHERE campaign_id = $1 ORDER BY created_at, id",
+            ITEM_COLUMNS
+        ))
+        .bind(id)
+        .fetch_all(self.storage.pool())
+        .await?;
+        let verified = archive_digest(&items) == digest;
+        Ok(serde_json::json!({
+            "campaign": campaign,
+            "items": items,
+            "verified": verified
+        }))
+    }
+
+    pub async fn managers(&self, filter: &ManagerFilter) -> Result<Vec<Manager>, SecurityError> {
+        Ok(sqlx::query_as::<_, Manager>(
+            "SELECT subject, manager, updated_by, updated_at FROM access_review_managers \
+             WHERE $1::TEXT IS NULL OR manager = $1 ORDER BY subject",
+        )
+        .bind(&filter.manager)
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    pub async fn set_manager(&self, subject: &str, manager: &str, actor: &str) -> Result<Manager, SecurityError> {
+        let manager = manager.trim();
+        if subject.trim().is_empty() || manager.is_empty() {
+            return Err(SecurityError::ValidationError("subject and manager are required".to_string()));
+        }
+        if manager == subject {
+            return Err(SecurityError::ValidationError("Subjects cannot manage themselves".to_string()));
+        }
+        Ok(sqlx::query_as::<_, Manager>(
+            "INSERT INTO access_review_managers (subject, manager, updated_by, updated_at) VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (subject) DO UPDATE SET manager = $2, updated_by = $3, updated_at = $4 \
+             RETURNING subject, manager, updated_by, updated_at",
+        )
+        .bind(subject)
+        .bind(manager)
+        .bind(actor)
+        .bind(self.clock.now())
+        .fetch_one(self.storage.pool())
+        .await?)
+    }
+
+    pub async fn remove_manager(&self, subject: &str) -> Result<(), SecurityError> {
+        let removed = sqlx::query("DELETE FROM access_review_managers WHERE subject = $1")
+            .bind(subject)
+            .execute(self.storage.pool())
+            .await?;
+        if removed.rows_affected() == 0 {
+            return Err(SecurityError::NotFound(format!("{} has no manager", subject)));
+        }
+        Ok(())
+    }
+}
+
+/// Take away what `item` grants. `Ok(false)` when it was already gone.
+async fn revoke(state: &AppState, item: &Item, actor: &str) -> Result<bool, SecurityError> {
+    let id = || Uuid::parse_str(&item.target).map_err(|_| SecurityError::ValidationError(format!("Bad target {}", item.target)));
+    let revoked = match item.kind.as_str() {
+        "role" => {
+            let families = state.tokens.revoke_role(&item.subject, item.tenant_id.as_deref(), &item.target, "access_review", actor).await?;
+            Ok(families > 0)
+        }
+        "api_key" => state.api_keys.revoke(actor, id()?).await.map(|_| true),
+        "elevation" => state.elevations.end(id()?, actor, "revoked").await.map(|_| true),
+        "consent" => {
+            let client_id = id()?;
+            let withdrawn = state.consents.revoke(&item.subject, client_id, actor).await;
+            if withdrawn.is_ok() {
+                state.tokens.revoke_client(&item.subject, client_id, actor).await?;
+            }
+            withdrawn.map(|_| true)
+        }
+        kind => return Err(SecurityError::ValidationError(format!("Unknown item kind {}", kind))),
+    };
+    match revoked {
+        Err(SecurityError::NotFound(_)) | Err(SecurityError::Conflict(_)) => Ok(false),
+        revoked => revoked,
+    }
+}
+
+async fn audit(state: &AppState, actor: &str, actor_ip: Option<String>, action: &str, resource: String, tenant_id: Option<String>, payload: Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id,
+        actor: actor.to_string(),
+        actor_ip,
+        action: action.to_string(),
+        resource: resource.clone(),
+        outcome: "success".to_string(),
+        payload,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} of {}: {:?}", action, resource, e);
+    }
+}
+
+async fn audit_item(state: &AppState, actor: &str, actor_ip: Option<String>, item: &Item) {
+    audit(state, actor, actor_ip, "access_review.decide", format!("access_review_item:{}", item.id), item.tenant_id.clone(), serde_json::json!({
+        "campaign_id": item.campaign_id,
+        "subject": item.subject,
+        "kind": item.kind,
+        "target": item.target,
+        "decision": item.decision,
+        "comment": item.comment,
+        "outcome": item.outcome
+    })).await;
+}
+
+async fn audit_campaign(state: &AppState, actor: &str, actor_ip: Option<String>, action: &str, campaign: &Campaign, payload: Value) {
+    audit(state, actor, actor_ip, action, format!("access_review:{}", campaign.id), campaign.tenant_id.clone(), payload).await;
+}
+
+/// Start scheduled campaigns and close those past their deadline.
+pub async fn run_campaigns(state: web::Data<AppState>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(300));
+    loop {
+        interval.tick().await;
+        let config = &state.config.access_review;
+        if config.interval_days > 0 {
+            let name = format!("Scheduled review {}", state.clock.now().format("%Y-%m-%d"));
+            match state.access_reviews.start(&name, None, config.deadline_days, SYSTEM_ACTOR, true).await {
+                Ok(Some((campaign, items))) => {
+                    audit_campaign(&state, SYSTEM_ACTOR, None, "access_review.start", &campaign, serde_json::json!({
+                        "name": campaign.name,
+                        "deadline": campaign.deadline,
+                        "items": items
+                    })).await;
+                }
+                Ok(None) => {}
+                Err(e) => error!("Failed to start scheduled access review: {:?}", e),
+            }
+        }
+
+        let due = match state.access_reviews.due().await {
+            Ok(due) => due,
+            Err(e) => {
+                warn!("Failed to find due access reviews: {:?}", e);
+                continue;
+            }
+        };
+        for campaign in due {
+            match state.access_reviews.close(&state, &campaign).await {
+                Ok((handled, closed)) => {
+                    for item in &handled {
+                        audit_item(&state, SYSTEM_ACTOR, None, item).await;
+                    }
+                    if let Some(closed) = closed {
+                        audit_campaign(&state, SYSTEM_ACTOR, None, "access_review.close", &closed, serde_json::json!({
+                            "summary": closed.summary,
+                            "archive_digest": closed.archive_digest
+                        })).await;
+                    }
+                }
+                Err(e) => error!("Failed to close access review {}: {:?}", campaign.id, e),
+            }
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::AccessDenied(msg) => HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Access review operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Access review operation failed"
+            }))
+        }
+    }
+}
+
+fn authorize_admin(state: &AppState, req: &HttpRequest) -> Result<Principal, SecurityError> {
+    state.auth_service.authorize_roles(req, &state.config.access_review.admin_roles)
+}
+
+/// Admins and auditors, for reading.
+fn authorize_reader(state: &AppState, req: &HttpRequest) -> Result<Principal, SecurityError> {
+    let config = &state.config.access_review;
+    let roles: Vec<String> = config.admin_roles.iter().chain(&config.auditor_roles).cloned().collect();
+    state.auth_service.authorize_roles(req, &roles)
+}
+
+pub async fn assigned_handler(
+    req: HttpRequest,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Asc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.access_reviews.assigned(&principal, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "items": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn decide_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<DecisionRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    match state.access_reviews.decide(&state, path.into_inner(), &principal, &request).await {
+        Ok(item) => {
+            audit_item(&state, &principal.subject, client_ip(&req), &item).await;
+            Ok(HttpResponse::Ok().json(item))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn start_handler(
+    req: HttpRequest,
+    request: web::Json<CampaignRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_admin(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let name = request.name.trim();
+    let deadline_days = request.deadline_days.unwrap_or(state.config.access_review.deadline_days);
+    if name.is_empty() || name.len() > 200 {
+        return Ok(error_response(SecurityError::ValidationError("name must be 1-200 characters".to_string())));
+    }
+    if !(1..=365).contains(&deadline_days) {
+        return Ok(error_response(SecurityError::ValidationError("deadline_days must be 1-365".to_string())));
+    }
+
+    match state.access_reviews.start(name, request.tenant_id.as_deref(), deadline_days, &principal.subject, false).await {
+        Ok(Some((campaign, items))) => {
+            audit_campaign(&state, &principal.subject, client_ip(&req), "access_review.start", &campaign, serde_json::json!({
+                "name": campaign.name,
+                "deadline": campaign.deadline,
+                "items": items
+            })).await;
+            Ok(HttpResponse::Created().json(serde_json::json!({
+                "campaign": campaign,
+                "items": items
+            })))
+        }
+        Ok(None) => Ok(error_response(SecurityError::Conflict("Access review was not started".to_string()))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_handler(
+    req: HttpRequest,
+    filter: web::Query<CampaignFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = authorize_reader(&state, &req) {
+        return Ok(auth_error_response(&e));
+    }
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.access_reviews.list(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "campaigns": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = authorize_reader(&state, &req) {
+        return Ok(auth_error_response(&e));
+    }
+    let id = path.into_inner();
+    let campaign = match state.access_reviews.get(id).await {
+        Ok(campaign) => campaign,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.access_reviews.progress(id).await {
+        Ok(progress) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "campaign": campaign,
+            "progress": progress
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn items_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    filter: web::Query<ItemFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = authorize_reader(&state, &req) {
+        return Ok(auth_error_response(&e));
+    }
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Asc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.access_reviews.items(path.into_inner(), &filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "items": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn archive_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match authorize_reader(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let id = path.into_inner();
+    match state.access_reviews.archive(id).await {
+        Ok(archive) => {
+            let tenant_id = archive["campaign"]["tenant_id"].as_str().map(str::to_string);
+            audit(&state, &principal.subject, client_ip(&req), "access_review.archive.export", format!("access_review:{}", id),
+                tenant_id, serde_json::json!({ "verified": archive["verified"] })).await;
+            Ok(HttpResponse::Ok().json(archive))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn managers_handler(
+    req: HttpRequest,
+    filter: web::Query<ManagerFilter>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = authorize_admin(&state, &req) {
+        return Ok(auth_error_response(&e));
+    }
+    match state.access_reviews.managers(&filter).await {
+        Ok(managers) => Ok(HttpResponse::Ok().json(serde_json::json!({ "managers": managers }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn set_manager_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    request: web::Json<ManagerRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_admin(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let subject = path.into_inner();
+    match state.access_reviews.set_manager(&subject, &request.manager, &principal.subject).await {
+        Ok(manager) => {
+            audit(&state, &principal.subject, client_ip(&req), "access_review.manager.set", format!("subject:{}", subject),
+                principal.tenant_id.clone(), serde_json::json!({ "manager": manager.manager })).await;
+            Ok(HttpResponse::Ok().json(manager))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn remove_manager_handler(req: HttpRequest, path: web::Path<String>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match authorize_admin(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let subject = path.into_inner();
+    match state.access_reviews.remove_manager(&subject).await {
+        Ok(()) => {
+            audit(&state, &principal.subject, client_ip(&req), "access_review.manager.remove", format!("subject:{}", subject),
+                principal.tenant_id.clone(), serde_json::json!({})).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+/// The `/auth/access-reviews` routes are registered by `auth::configure_routes`.
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/access-reviews")
+            .route("/campaigns", web::post().to(start_handler))
+            .route("/campaigns", web::get().to(list_handler))
+            .route("/campaigns/{id}", web::get().to(get_handler))
+            .route("/campaigns/{id}/items", web::get().to(items_handler))
+            .route("/campaigns/{id}/archive", web::get().to(archive_handler))
+            .route("/managers", web::get().to(managers_handler))
+            .route("/managers/{subject}", web::put().to(set_manager_handler))
+            .route("/managers/{subject}", web::delete().to(remove_manager_handler))
+    );
+}
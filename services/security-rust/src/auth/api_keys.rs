@@ -0,0 +1,811 @@
+/*!
+API Keys
+Opaque keys for machine callers that cannot run the assertion flow
+
+Other COTAI services call this one either with a service account token
+(see `service_accounts`) or, where signing assertions is impractical, with
+an API key in the `X-API-Key` header:
+
+```text
+cotai_<key id>_<secret>
+```
+
+Admins mint keys under `/admin/api-keys` for a tenant, or for none, with
+scopes from `API_KEY_SCOPES` and a lifetime of at most
+`API_KEY_MAX_LIFETIME_DAYS`. The key is shown once, at creation; only the
+SHA-256 of its secret, computed by `CryptoService`, is stored. A key
+authenticates as `api_key:<id>` with its tenant and scopes and no roles, so
+it passes `authorize_scope` checks and the `scopes` pipeline stage but
+never admin checks.
+
+Rotating a key mints a replacement with the same name, tenant and scopes,
+and cuts the old key's lifetime to `API_KEY_ROTATION_OVERLAP_SECS` (or the
+overlap asked for, up to `API_KEY_MAX_ROTATION_OVERLAP_SECS`), so callers
+switch over without an outage. Revoking takes effect at once on the replica
+that handled it and within `API_KEY_REFRESH_INTERVAL_SECS` on the others.
+Keys are tracked by `expiry` until they are rotated or revoked.
+
+The `scopes` pipeline stage holds every caller but admins to the scope
+`MIDDLEWARE_ROUTE_SCOPES` names for a route, so a key minted for
+`crypto:hash` cannot decrypt, and answers `401` to requests without
+credentials.
+*/
+
+use actix_web::dev::ServiceRequest;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::NewAuditEvent;
+use crate::auth::service_accounts::validate_scopes;
+use crate::auth::{auth_error_response, client_ip, AuthService, Principal};
+use crate::clock::Clock;
+use crate::config::{ApiKeyConfig, Config};
+use crate::crypto::CryptoService;
+use crate::errors::SecurityError;
+use crate::expiry::{Expiring, ExpiryFuture, ExpiryRegistry};
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::Storage;
+use crate::AppState;
+
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+const KEY_PREFIX: &str = "cotai_";
+
+/// Subject prefix of API key principals.
+const SUBJECT_PREFIX: &str = "api_key:";
+
+const SECRET_BYTES: usize = 32;
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "created_at", column: "created_at", kind: KeyKind::Timestamp },
+    SortField { name: "name", column: "name", kind: KeyKind::Text },
+];
+
+const KEY_COLUMNS: &str = "id, tenant_id, name, scopes, status, expires_at, rotated_from, replaced_by, \
+    created_by, created_at, last_used_at, revoked_by, revoked_at";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub tenant_id: Option<String>,
+    pub name: String,
+    pub scopes: Vec<String>,
+    /// `active` or `revoked`.
+    pub status: String,
+    pub expires_at: DateTime<Utc>,
+    pub rotated_from: Option<Uuid>,
+    pub replaced_by: Option<Uuid>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_by: Option<String>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// A key as handed out, with its secret. Only ever returned once.
+#[derive(Debug, Serialize)]
+pub struct MintedKey {
+    pub key: String,
+    pub api_key: ApiKey,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateKeyRequest {
+    pub tenant_id: Option<String>,
+    pub name: String,
+    pub scopes: Vec<String>,
+    /// Defaults to `API_KEY_MAX_LIFETIME_DAYS`.
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RotateKeyRequest {
+    /// How long the old key keeps working; defaults to
+    /// `API_KEY_ROTATION_OVERLAP_SECS`.
+    pub overlap_secs: Option<i64>,
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeyFilter {
+    pub tenant_id: Option<String>,
+    pub status: Option<String>,
+}
+
+/// What authentication needs of an active key.
+#[derive(Debug, Clone, FromRow)]
+struct ActiveKey {
+    id: Uuid,
+    tenant_id: Option<String>,
+    secret_hash: String,
+    scopes: Vec<String>,
+    expires_at: DateTime<Utc>,
+}
+
+/// Active keys, checked on every authentication that presents one.
+#[derive(Default)]
+pub struct ApiKeyRing {
+    keys: RwLock<HashMap<Uuid, ActiveKey>>,
+    /// Last use per key since the previous refresh.
+    used: Mutex<HashMap<Uuid, DateTime<Utc>>>,
+}
+
+fn secret_hash(secret: &str) -> String {
+    // Same digest as `CryptoService::compute_hash` without a salt
+    hex::encode(digest(&SHA256, secret.as_bytes()))
+}
+
+/// Split a presented key into its id and secret.
+fn parse_key(key: &str) -> Option<(Uuid, &str)> {
+    let (id, secret) = key.strip_prefix(KEY_PREFIX)?.split_once('_')?;
+    let id = Uuid::try_parse(id).ok()?;
+    (!secret.is_empty()).then_some((id, secret))
+}
+
+impl ApiKeyRing {
+    /// The principal a presented key stands for.
+    pub fn verify(&self, key: &str, now: DateTime<Utc>) -> Result<Principal, SecurityError> {
+        let invalid = || SecurityError::AuthError("Invalid API key".to_string());
+        let (id, secret) = parse_key(key).ok_or_else(invalid)?;
+        let keys = self.keys.read().unwrap_or_else(|e| e.into_inner());
+        let active = keys.get(&id).filter(|k| k.expires_at > now).ok_or_else(invalid)?;
+        if secret_hash(secret) != active.secret_hash {
+            return Err(invalid());
+        }
+        self.used.lock().unwrap_or_else(|e| e.into_inner()).insert(id, now);
+
+        Ok(Principal {
+            subject: format!("{}{}", SUBJECT_PREFIX, id),
+            tenant_id: active.tenant_id.clone(),
+            roles: Vec::new(),
+            scopes: active.scopes.clone(),
+            token_id: None,
+            delegation: Vec::new(),
+        })
+    }
+
+    fn insert(&self, key: ActiveKey) {
+        self.keys.write().unwrap_or_else(|e| e.into_inner()).insert(key.id, key);
+    }
+
+    fn remove(&self, id: Uuid) {
+        self.keys.write().unwrap_or_else(|e| e.into_inner()).remove(&id);
+    }
+
+    fn set_expiry(&self, id: Uuid, expires_at: DateTime<Utc>) {
+        if let Some(key) = self.keys.write().unwrap_or_else(|e| e.into_inner()).get_mut(&id) {
+            key.expires_at = expires_at;
+        }
+    }
+
+    fn replace(&self, active: Vec<ActiveKey>) {
+        *self.keys.write().unwrap_or_else(|e| e.into_inner()) = active.into_iter().map(|k| (k.id, k)).collect();
+    }
+
+    fn take_used(&self) -> HashMap<Uuid, DateTime<Utc>> {
+        std::mem::take(&mut *self.used.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+pub struct ApiKeyService {
+    storage: Storage,
+    config: ApiKeyConfig,
+    clock: Arc<dyn Clock>,
+    ring: Arc<ApiKeyRing>,
+}
+
+impl ApiKeyService {
+    pub async fn new(
+        config: &Config,
+        storage: Storage,
+        clock: Arc<dyn Clock>,
+        ring: Arc<ApiKeyRing>,
+    ) -> Result<Self, SecurityError> {
+
+        info!("API key service initialized successfully");
+        Ok(Self {
+            storage,
+            config: config.api_keys.clone(),
+            clock,
+            ring,
+        })
+    }
+
+    fn validate_scopes(&self, scopes: &[String]) -> Result<(), SecurityError> {
+        validate_scopes(scopes)?;
+        if scopes.is_empty() {
+            return Err(SecurityError::ValidationError("A key needs at least one scope".to_string()));
+        }
+        let allowed: HashSet<&str> = self.config.scopes.iter().map(String::as_str).collect();
+        if let Some(scope) = scopes.iter().find(|scope| !allowed.contains(scope.as_str())) {
+            return Err(SecurityError::ValidationError(format!(
+                "Scope {} cannot be given to API keys (allowed: {})",
+                scope,
+                self.config.scopes.join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    fn expiry(&self, expires_in_days: Option<i64>, now: DateTime<Utc>) -> Result<DateTime<Utc>, SecurityError> {
+        let days = expires_in_days.unwrap_or(self.config.max_lifetime_days);
+        if days <= 0 || days > self.config.max_lifetime_days {
+            return Err(SecurityError::ValidationError(format!(
+                "expires_in_days must be 1-{}",
+                self.config.max_lifetime_days
+            )));
+        }
+        Ok(now + Duration::days(days))
+    }
+
+    /// Insert a key with a fresh secret and add it to the ring.
+    async fn insert(
+        &self,
+        tx: &mut sqlx::Transaction<'static, Postgres>,
+        crypto: &CryptoService,
+        fields: (&Option<String>, &str, &[String]),
+        expires_at: DateTime<Utc>,
+        rotated_from: Option<Uuid>,
+        principal: &Principal,
+    ) -> Result<(MintedKey, ActiveKey), SecurityError> {
+        let (tenant_id, name, scopes) = fields;
+        let id = Uuid::new_v4();
+        let secret = base64::encode_config(crypto.secure_random(SECRET_BYTES).await?, base64::URL_SAFE_NO_PAD);
+        let hash = crypto.compute_hash(&secret, None)?;
+
+        let api_key = sqlx::query_as::<_, ApiKey>(&format!(
+            "INSERT INTO api_keys (id, tenant_id, name, secret_hash, scopes, expires_at, rotated_from, created_by, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING {}",
+            KEY_COLUMNS
+        ))
+        .bind(id)
+        .bind(tenant_id)
+        .bind(name)
+        .bind(&hash)
+        .bind(scopes)
+        .bind(expires_at)
+        .bind(rotated_from)
+        .bind(&principal.subject)
+        .bind(self.clock.now())
+        .fetch_one(&mut **tx)
+        .await?;
+
+        let active = ActiveKey {
+            id,
+            tenant_id: tenant_id.clone(),
+            secret_hash: hash,
+            scopes: scopes.to_vec(),
+            expires_at,
+        };
+        let key = format!("{}{}_{}", KEY_PREFIX, id.simple(), secret);
+        Ok((MintedKey { key, api_key }, active))
+    }
+
+    pub async fn create(
+        &self,
+        crypto: &CryptoService,
+        principal: &Principal,
+        request: CreateKeyRequest,
+    ) -> Result<MintedKey, SecurityError> {
+        let name = request.name.trim();
+        if name.is_empty() || name.len() > 100 {
+            return Err(SecurityError::ValidationError("name must be 1-100 characters".to_string()));
+        }
+        if request.tenant_id.as_deref().is_some_and(|t| t.trim().is_empty()) {
+            return Err(SecurityError::ValidationError("tenant_id must not be empty".to_string()));
+        }
+        self.validate_scopes(&request.scopes)?;
+        let expires_at = self.expiry(request.expires_in_days, self.clock.now())?;
+
+        let mut tx = self.storage.begin().await?;
+        let (minted, active) = self
+            .insert(&mut tx, crypto, (&request.tenant_id, name, &request.scopes), expires_at, None, principal)
+            .await?;
+        tx.commit().await?;
+        self.ring.insert(active);
+
+        info!("{} created API key {} ({})", principal.subject, minted.api_key.id, name);
+        Ok(minted)
+    }
+
+    pub async fn list(&self, filter: &KeyFilter, page: &PageRequest) -> Result<Page<ApiKey>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM api_keys WHERE 1 = 1",
+            KEY_COLUMNS
+        ));
+        if let Some(tenant_id) = &filter.tenant_id {
+            builder.push(" AND tenant_id = ").push_bind(tenant_id.clone());
+        }
+        if let Some(status) = &filter.status {
+            builder.push(" AND status = ").push_bind(status.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let keys = builder
+            .build_query_as::<ApiKey>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(keys, |key, field| match field {
+            "name" => (SortKey::Text(key.name.clone()), key.id),
+            _ => (SortKey::Timestamp(key.created_at), key.id),
+        }))
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<ApiKey, SecurityError> {
+        sqlx::query_as::<_, ApiKey>(&format!("SELECT {} FROM api_keys WHERE id = $1", KEY_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound("API key not found".to_string()))
+    }
+
+    /// Mint a replacement and let the old key run out after the overlap.
+    /// Returns the replacement and the old key as updated.
+    pub async fn rotate(
+        &self,
+        crypto: &CryptoService,
+        principal: &Principal,
+        id: Uuid,
+        request: RotateKeyRequest,
+    ) -> Result<(MintedKey, ApiKey), SecurityError> {
+        let overlap = request.overlap_secs.unwrap_or(self.config.rotation_overlap_secs);
+        if !(0..=self.config.max_rotation_overlap_secs).contains(&overlap) {
+            return Err(SecurityError::ValidationError(format!(
+                "overlap_secs must be 0-{}",
+                self.config.max_rotation_overlap_secs
+            )));
+        }
+        let now = self.clock.now();
+        let expires_at = self.expiry(request.expires_in_days, now)?;
+
+        let mut tx = self.storage.begin().await?;
+        let old = sqlx::query_as::<_, ApiKey>(&format!(
+            "SELECT {} FROM api_keys WHERE id = $1 FOR UPDATE",
+            KEY_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("API key not found".to_string()))?;
+        if old.status != "active" || old.expires_at <= now {
+            return Err(SecurityError::Conflict(format!("API key {} is no longer active", id)));
+        }
+        if let Some(replacement) = old.replaced_by {
+            return Err(SecurityError::Conflict(format!("API key {} was already rotated to {}", id, replacement)));
+        }
+
+        let (minted, active) = self
+            .insert(&mut tx, crypto, (&old.tenant_id, &old.name, &old.scopes), expires_at, Some(old.id), principal)
+            .await?;
+        let old_expires_at = old.expires_at.min(now + Duration::seconds(overlap));
+        let old = sqlx::query_as::<_, ApiKey>(&format!(
+            "UPDATE api_keys SET expires_at = $2, replaced_by = $3 WHERE id = $1 RETURNING {}",
+            KEY_COLUMNS
+        ))
+        .bind(id)
+        .bind(old_expires_at)
+        .bind(minted.api_key.id)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.ring.insert(active);
+        self.ring.set_expiry(id, old_expires_at);
+        info!("{} rotated API key {} to {}", principal.subject, id, minted.api_key.id);
+        Ok((minted, old))
+    }
+
+    pub async fn revoke(&self, actor: &str, id: Uuid) -> Result<ApiKey, SecurityError> {
+        let revoked = sqlx::query_as::<_, ApiKey>(&format!(
+            "UPDATE api_keys SET status = 'revoked', revoked_by = $2, revoked_at = $3 \
+             WHERE id = $1 AND status = 'active' RETURNING {}",
+            KEY_COLUMNS
+        ))
+        .bind(id)
+        .bind(actor)
+        .bind(self.clock.now())
+        .fetch_optional(self.storage.pool())
+        .await?;
+
+        let key = match revoked {
+            Some(key) => key,
+            None => {
+                let key = self.get(id).await?;
+                return Err(SecurityError::Conflict(format!("API key {} is already {}", key.id, key.status)));
+            }
+        };
+        self.ring.remove(id);
+        info!("{} revoked API key {}", actor, id);
+        Ok(key)
+    }
+
+    /// Remove a key for good, revoking it first if it is still active.
+    pub async fn delete(&self, actor: &str, id: Uuid) -> Result<ApiKey, SecurityError> {
+        let key = match self.revoke(actor, id).await {
+            Ok(key) => key,
+            Err(SecurityError::Conflict(_)) => self.get(id).await?,
+            Err(e) => return Err(e),
+        };
+
+        let mut tx = self.storage.begin().await?;
+        sqlx::query("UPDATE api_keys SET rotated_from = NULL WHERE rotated_from = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE api_keys SET replaced_by = NULL WHERE replaced_by = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM api_keys WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        info!("{} deleted API key {}", actor, id);
+        Ok(key)
+    }
+
+    /// Record when keys were last used and reload the active ones.
+    pub async fn refresh(&self) -> Result<(), SecurityError> {
+        for (id, used_at) in self.ring.take_used() {
+            sqlx::query("UPDATE api_keys SET last_used_at = GREATEST(last_used_at, $2) WHERE id = $1")
+                .bind(id)
+                .bind(used_at)
+                .execute(self.storage.pool())
+                .await?;
+        }
+
+        let active = sqlx::query_as::<_, ActiveKey>(
+            "SELECT id, tenant_id, secret_hash, scopes, expires_at FROM api_keys \
+             WHERE status = 'active' AND expires_at > $1",
+        )
+        .bind(self.clock.now())
+        .fetch_all(self.storage.pool())
+        .await?;
+        self.ring.replace(active);
+        Ok(())
+    }
+
+    /// Active keys not yet rotated, for the expiry scan.
+    async fn expiring_keys(&self) -> Result<Vec<Expiring>, SecurityError> {
+        let rows: Vec<(Uuid, String, Option<String>, DateTime<Utc>)> = sqlx::query_as(
+            "SELECT id, name, tenant_id, expires_at FROM api_keys \
+             WHERE status = 'active' AND replaced_by IS NULL",
+        )
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(id, name, tenant_id, expires_at)| Expiring {
+                source: "api_keys",
+                kind: "api_key".to_string(),
+                name: format!("{}/{}", name, id),
+                tenant_id,
+                expires_at,
+                fingerprint: None,
+            })
+            .collect())
+    }
+}
+
+fn expiring_keys(state: &AppState) -> ExpiryFuture<'_> {
+    Box::pin(state.api_keys.expiring_keys())
+}
+
+pub fn register_expiry_sources(registry: &mut ExpiryRegistry) {
+    registry.register("api_keys", expiring_keys);
+}
+
+/// Background loop keeping the ring in step with other replicas.
+pub async fn run_refresh(state: web::Data<AppState>) {
+    let interval_secs = state.config.api_keys.refresh_interval_secs;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+
+    loop {
+        interval.tick().await;
+        match state.api_keys.refresh().await {
+            Ok(()) => state.startup.recovered("api_keys"),
+            Err(e) => error!("API key refresh failed: {:?}", e),
+        }
+    }
+}
+
+/// The `scopes` pipeline stage.
+pub fn rejection(state: &AppState, req: &ServiceRequest) -> Option<HttpResponse> {
+    scope_rejection(&state.auth_service, &state.config.middleware.route_scopes, req.request())
+}
+
+/// The answer to a request for a route in `route_scopes` whose caller
+/// lacks the route's scope; the longest matching prefix decides.
+fn scope_rejection(auth: &AuthService, route_scopes: &[(String, String)], req: &HttpRequest) -> Option<HttpResponse> {
+    let path = req.path();
+    let (_, scope) = route_scopes
+        .iter()
+        .filter(|(prefix, _)| path.starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len())?;
+    match auth.authorize_scope(req, scope) {
+        Ok(_) => None,
+        Err(e) => {
+            info!("Rejected {} needing scope {}: {}", path, scope, e);
+            Some(auth_error_response(&e))
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("API key operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "API key operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit(state: &AppState, req: &HttpRequest, principal: &Principal, action: &str, key: &ApiKey, payload: serde_json::Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: key.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(req),
+        action: action.to_string(),
+        resource: format!("api_key:{}", key.id),
+        outcome: "success".to_string(),
+        payload,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} on API key {}: {:?}", action, key.id, e);
+    }
+}
+
+pub async fn create_handler(
+    req: HttpRequest,
+    request: web::Json<CreateKeyRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.api_keys.create(&state.crypto_service, &principal, request.into_inner()).await {
+        Ok(minted) => {
+            let key = &minted.api_key;
+            audit(&state, &req, &principal, "auth.api_key.create", key, serde_json::json!({
+                "name": key.name,
+                "scopes": key.scopes,
+                "expires_at": key.expires_at
+            })).await;
+            Ok(HttpResponse::Created()
+                .insert_header(("Cache-Control", "no-store"))
+                .json(minted))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_handler(
+    req: HttpRequest,
+    filter: web::Query<KeyFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.api_keys.list(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "keys": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.api_keys.get(path.into_inner()).await {
+        Ok(key) => Ok(HttpResponse::Ok().json(key)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn rotate_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: Option<web::Json<RotateKeyRequest>>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let request = request.map(|r| r.into_inner()).unwrap_or_default();
+    match state.api_keys.rotate(&state.crypto_service, &principal, path.into_inner(), request).await {
+        Ok((minted, old)) => {
+            audit(&state, &req, &principal, "auth.api_key.rotate", &old, serde_json::json!({
+                "replaced_by": minted.api_key.id,
+                "old_expires_at": old.expires_at,
+                "expires_at": minted.api_key.expires_at
+            })).await;
+            Ok(HttpResponse::Created()
+                .insert_header(("Cache-Control", "no-store"))
+                .json(serde_json::json!({
+                    "key": minted.key,
+                    "api_key": minted.api_key,
+                    "previous": old
+                })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn revoke_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.api_keys.revoke(&principal.subject, path.into_inner()).await {
+        Ok(key) => {
+            audit(&state, &req, &principal, "auth.api_key.revoke", &key, serde_json::json!({
+                "name": key.name
+            })).await;
+            Ok(HttpResponse::Ok().json(key))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/api-keys")
+            .route("", web::post().to(create_handler))
+            .route("", web::get().to(list_handler))
+            .route("/{id}", web::get().to(get_handler))
+            .route("/{id}", web::delete().to(revoke_handler))
+            .route("/{id}/rotate", web::post().to(rotate_handler)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::elevation::ElevationGrants;
+    use crate::auth::tokens::RevocationList;
+    use crate::clock::ManualClock;
+    use crate::containment::Denylist;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+    use chrono::TimeZone;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    struct Fixture {
+        auth: AuthService,
+        config: Config,
+        clock: Arc<ManualClock>,
+        routes: Vec<(String, String)>,
+    }
+
+    impl Fixture {
+        async fn new() -> Self {
+            let config = Config::for_tests(&[]);
+            let clock = Arc::new(ManualClock::new(Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap()));
+            let auth = AuthService::new(
+                &config,
+                clock.clone(),
+                Arc::new(Denylist::default()),
+                Arc::new(RevocationList::default()),
+                Arc::new(ApiKeyRing::default()),
+                Arc::new(ElevationGrants::new(&config.elevation)),
+                &serde_json::json!({ "keys": [] }),
+            )
+            .await
+            .expect("auth service");
+            let routes = [("/api/v1/crypto", "crypto:use"), ("/api/v1/crypto/decrypt", "crypto:decrypt")]
+                .into_iter()
+                .map(|(prefix, scope)| (prefix.to_string(), scope.to_string()))
+                .collect();
+            Self { auth, config, clock, routes }
+        }
+
+        /// A token for `alice` valid for five minutes from now.
+        fn token(&self, roles: &[&str], scope: &str) -> String {
+            let claims = serde_json::json!({
+                "sub": "alice",
+                "exp": self.clock.now().timestamp() + 300,
+                "roles": roles,
+                "scope": scope
+            });
+            encode(&Header::default(), &claims, &EncodingKey::from_secret(self.config.auth.jwt_secret.as_bytes()))
+                .expect("signed token")
+        }
+
+        /// The status the stage answers with, or `None` if it lets the request through.
+        fn answer(&self, path: &str, token: Option<&str>) -> Option<StatusCode> {
+            let mut req = TestRequest::get().uri(path);
+            if let Some(token) = token {
+                req = req.insert_header(("Authorization", format!("Bearer {}", token)));
+            }
+            scope_rejection(&self.auth, &self.routes, &req.to_http_request()).map(|res| res.status())
+        }
+    }
+
+    #[actix_web::test]
+    async fn scoped_routes_need_credentials() {
+        let fixture = Fixture::new().await;
+        assert_eq!(fixture.answer("/api/v1/crypto/encrypt", None), Some(StatusCode::UNAUTHORIZED));
+        assert_eq!(fixture.answer("/api/v1/crypto/encrypt", Some("not-a-token")), Some(StatusCode::UNAUTHORIZED));
+        assert_eq!(fixture.answer("/api/v1/health", None), None);
+    }
+
+    #[actix_web::test]
+    async fn the_longest_prefix_names_the_scope() {
+        let fixture = Fixture::new().await;
+        let token = fixture.token(&[], "crypto:use");
+        assert_eq!(fixture.answer("/api/v1/crypto/encrypt", Some(&token)), None);
+        assert_eq!(fixture.answer("/api/v1/crypto/decrypt", Some(&token)), Some(StatusCode::FORBIDDEN));
+
+        let token = fixture.token(&[], "crypto:use crypto:decrypt");
+        assert_eq!(fixture.answer("/api/v1/crypto/decrypt", Some(&token)), None);
+    }
+
+    #[actix_web::test]
+    async fn only_admin_roles_are_exempt() {
+        let fixture = Fixture::new().await;
+        let admin = fixture.token(&["super_admin"], "");
+        assert_eq!(fixture.answer("/api/v1/crypto/decrypt", Some(&admin)), None);
+
+        let other = fixture.token(&["admin", "analyst"], "");
+        assert_eq!(fixture.answer("/api/v1/crypto/decrypt", Some(&other)), Some(StatusCode::FORBIDDEN));
+    }
+
+    #[actix_web::test]
+    async fn expired_tokens_are_unauthenticated() {
+        let fixture = Fixture::new().await;
+        let token = fixture.token(&[], "crypto:use");
+        fixture.clock.advance(Duration::minutes(10));
+        assert_eq!(fixture.answer("/api/v1/crypto/encrypt", Some(&token)), Some(StatusCode::UNAUTHORIZED));
+    }
+}
@@ -0,0 +1,1088 @@
+/*!
+Break-Glass Access
+Sealed emergency credentials for when the usual way in is down
+
+Holders of `BREAK_GLASS_ADMIN_ROLES` provision accounts under
+`/admin/break-glass/accounts`, each with a name, the person or team holding
+it and where its one-time codes go. The account's secret is shown once, at
+creation or resealing, to be printed and sealed away; only its Argon2 hash
+is kept.
+
+Using one takes two steps, neither needing a bearer token:
+
+- `POST /auth/break-glass/activate` with `{account, secret, reason}` breaks
+  the seal: the secret is spent whatever happens next, the security team
+  is paged through `BREAK_GLASS_ALERT_SINKS` (every sink when unset), and a
+  one-time code goes to the account's destination (see `otp`)
+- `POST /auth/break-glass/activations/{id}/verify` with `{code}` completes
+  the activation and returns an access token for `break_glass:<name>` with
+  `BREAK_GLASS_ROLES`
+
+The activation lasts `BREAK_GLASS_TTL_SECS`. Its tokens never outlive it,
+and the holder renews them with `POST .../{id}/renew` while it does; `POST
+.../{id}/end` ends it early and revokes them. Expired activations are ended
+by a sweep.
+
+Each completed activation opens a critical `break_glass_activation`
+incident (see `detection`) that stands as its post-use review record; the
+command log (see `commands`) holds what was done, under the tokens' ids,
+which the incident lists. Holders of `BREAK_GLASS_REVIEW_ROLES` close it
+with `POST /admin/break-glass/activations/{id}/review` once the activation
+has ended, and an account cannot be resealed while an activation of it
+awaits review. Wrong secrets, failed codes, activations, renewals, ends and
+reviews are all audited.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::alerting::{Alert, Severity};
+use crate::audit::NewAuditEvent;
+use crate::auth::otp::{self, StartRequest, Verification};
+use crate::auth::sessions::SYSTEM_ACTOR;
+use crate::auth::tokens::{IssueRequest, IssuedTokens};
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{BreakGlassConfig, Config};
+use crate::crypto::{CryptoService, DecryptionRequest, EncryptionRequest};
+use crate::delivery::Channel;
+use crate::detection::{self, IncidentUpdate, NewIncident};
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+use crate::AppState;
+
+const SECRET_PREFIX: &str = "bg_";
+const SECRET_BYTES: usize = 32;
+
+/// Subject prefix of break-glass principals.
+const SUBJECT_PREFIX: &str = "break_glass:";
+
+const ACCOUNT_COLUMNS: &str = "id, name, holder, channel, destination_hint, secret_hash IS NOT NULL AS sealed, \
+    sealed_at, created_by, created_at, disabled_at";
+
+const ACTIVATION_COLUMNS: &str = "a.id, a.account_id, b.name AS account, a.status, a.reason, a.client_ip, \
+    a.challenge_id, a.incident_id, a.token_ids, a.started_at, a.activated_at, a.expires_at, a.ended_at, a.ended_by, \
+    a.reviewed_at, a.reviewed_by, a.review_outcome, a.review_notes";
+
+const REVIEW_OUTCOMES: &[&str] = &["justified", "unjustified"];
+
+/// Whether `subject` is a break-glass principal.
+pub fn is_break_glass(subject: &str) -> bool {
+    subject.starts_with(SUBJECT_PREFIX)
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Account {
+    pub id: Uuid,
+    pub name: String,
+    /// Who keeps the sealed secret.
+    pub holder: String,
+    pub channel: String,
+    /// Masked destination of its one-time codes.
+    pub destination_hint: String,
+    /// Whether an unspent secret exists.
+    pub sealed: bool,
+    pub sealed_at: Option<DateTime<Utc>>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub disabled_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Activation {
+    pub id: Uuid,
+    pub account_id: Uuid,
+    pub account: String,
+    /// `pending_mfa`, `active`, `ended`, `expired` or `failed`.
+    pub status: String,
+    pub reason: String,
+    pub client_ip: Option<String>,
+    pub challenge_id: Option<Uuid>,
+    /// The incident standing as the review record.
+    pub incident_id: Option<Uuid>,
+    /// `jti` of every token issued, as the command log records them.
+    pub token_ids: Vec<String>,
+    pub started_at: DateTime<Utc>,
+    pub activated_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub ended_by: Option<String>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+    pub reviewed_by: Option<String>,
+    pub review_outcome: Option<String>,
+    pub review_notes: Option<String>,
+}
+
+impl Activation {
+    fn subject(&self) -> String {
+        format!("{}{}", SUBJECT_PREFIX, self.account)
+    }
+
+    fn awaits_review(&self) -> bool {
+        self.incident_id.is_some() && self.reviewed_at.is_none()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAccountRequest {
+    pub name: String,
+    pub holder: String,
+    pub channel: Channel,
+    pub destination: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ActivateRequest {
+    pub account: String,
+    pub secret: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewRequest {
+    pub outcome: String,
+    pub notes: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ActivationFilter {
+    pub status: Option<String>,
+    pub account: Option<String>,
+    /// Only activations whose review is outstanding.
+    #[serde(default)]
+    pub unreviewed: bool,
+}
+
+/// Outcome of checking an activation's code.
+pub enum Completion {
+    Activated { activation: Activation, tokens: Box<IssuedTokens> },
+    Rejected { reason: &'static str, attempts_remaining: i32, activation: Activation },
+}
+
+fn destination_context(id: Uuid) -> HashMap<String, String> {
+    HashMap::from([
+        ("purpose".to_string(), "break_glass_destination".to_string()),
+        ("account_id".to_string(), id.to_string()),
+    ])
+}
+
+#[derive(FromRow)]
+struct SealedAccount {
+    id: Uuid,
+    name: String,
+    channel: String,
+    secret_hash: Option<String>,
+    encrypted_destination: String,
+    key_id: String,
+    nonce: String,
+    context_hash: Option<String>,
+}
+
+pub struct BreakGlassService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: BreakGlassConfig,
+}
+
+impl BreakGlassService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        info!("Break-glass service initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            config: config.break_glass.clone(),
+        })
+    }
+
+    fn not_found(what: &str, id: Uuid) -> SecurityError {
+        SecurityError::NotFound(format!("Break-glass {} {} not found", what, id))
+    }
+
+    async fn fresh_secret(crypto: &CryptoService) -> Result<(String, String), SecurityError> {
+        let secret = format!(
+            "{}{}",
+            SECRET_PREFIX,
+            base64::encode_config(crypto.secure_random(SECRET_BYTES).await?, base64::URL_SAFE_NO_PAD)
+        );
+        let hash = crypto.hash_password(&secret)?;
+        Ok((secret, hash))
+    }
+
+    pub async fn accounts(&self) -> Result<Vec<Account>, SecurityError> {
+        Ok(sqlx::query_as::<_, Account>(&format!(
+            "SELECT {} FROM break_glass_accounts ORDER BY name",
+            ACCOUNT_COLUMNS
+        ))
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    /// Provision an account, returning it with its secret.
+    pub async fn create_account(
+        &self,
+        crypto: &CryptoService,
+        request: &CreateAccountRequest,
+        created_by: &str,
+    ) -> Result<(Account, String), SecurityError> {
+        let name = request.name.trim();
+        if name.is_empty() || name.len() > 100 || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(SecurityError::ValidationError(
+                "name must be 1-100 letters, digits, '-' or '_'".to_string(),
+            ));
+        }
+        if request.holder.trim().is_empty() {
+            return Err(SecurityError::ValidationError("holder is required".to_string()));
+        }
+        let destination = otp::normalize(request.channel, &request.destination)?;
+
+        let id = Uuid::new_v4();
+        let sealed = crypto.encrypt_data(EncryptionRequest {
+            data: destination.clone(),
+            key_id: None,
+            context: Some(destination_context(id)),
+        }).await?;
+        let (secret, hash) = Self::fresh_secret(crypto).await?;
+        let now = self.clock.now();
+
+        let account = sqlx::query_as::<_, Account>(&format!(
+            "INSERT INTO break_glass_accounts (id, name, holder, channel, encrypted_destination, key_id, nonce, \
+             context_hash, destination_hint, secret_hash, sealed_at, created_by, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $11) \
+             ON CONFLICT (name) DO NOTHING RETURNING {}",
+            ACCOUNT_COLUMNS
+        ))
+        .bind(id)
+        .bind(name)
+        .bind(request.holder.trim())
+        .bind(request.channel.as_str())
+        .bind(&sealed.encrypted_data)
+        .bind(&sealed.key_id)
+        .bind(&sealed.nonce)
+        .bind(&sealed.context_hash)
+        .bind(otp::hint(request.channel, &destination))
+        .bind(&hash)
+        .bind(now)
+        .bind(created_by)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::Conflict(format!("Break-glass account '{}' already exists", name)))?;
+        Ok((account, secret))
+    }
+
+    /// Issue a new secret, replacing any unspent one. Refused while an
+    /// activation of the account awaits review.
+    pub async fn reseal(&self, crypto: &CryptoService, id: Uuid) -> Result<(Account, String), SecurityError> {
+        let unreviewed: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM break_glass_activations \
+             WHERE account_id = $1 AND incident_id IS NOT NULL AND reviewed_at IS NULL)",
+        )
+        .bind(id)
+        .fetch_one(self.storage.pool())
+        .await?;
+        if unreviewed {
+            return Err(SecurityError::Conflict(
+                "An activation of this account awaits review".to_string(),
+            ));
+        }
+
+        let (secret, hash) = Self::fresh_secret(crypto).await?;
+        let account = sqlx::query_as::<_, Account>(&format!(
+            "UPDATE break_glass_accounts SET secret_hash = $2, sealed_at = $3 \
+             WHERE id = $1 AND disabled_at IS NULL RETURNING {}",
+            ACCOUNT_COLUMNS
+        ))
+        .bind(id)
+        .bind(&hash)
+        .bind(self.clock.now())
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| Self::not_found("account", id))?;
+        Ok((account, secret))
+    }
+
+    /// Disable an account and spend its secret.
+    pub async fn disable(&self, id: Uuid) -> Result<Account, SecurityError> {
+        sqlx::query_as::<_, Account>(&format!(
+            "UPDATE break_glass_accounts SET secret_hash = NULL, sealed_at = NULL, \
+             disabled_at = COALESCE(disabled_at, $2) WHERE id = $1 RETURNING {}",
+            ACCOUNT_COLUMNS
+        ))
+        .bind(id)
+        .bind(self.clock.now())
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| Self::not_found("account", id))
+    }
+
+    pub async fn activations(&self, filter: &ActivationFilter) -> Result<Vec<Activation>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM break_glass_activations a JOIN break_glass_accounts b ON b.id = a.account_id WHERE 1 = 1",
+            ACTIVATION_COLUMNS
+        ));
+        if let Some(status) = &filter.status {
+            builder.push(" AND a.status = ").push_bind(status.clone());
+        }
+        if let Some(account) = &filter.account {
+            builder.push(" AND b.name = ").push_bind(account.clone());
+        }
+        if filter.unreviewed {
+            builder.push(" AND a.incident_id IS NOT NULL AND a.reviewed_at IS NULL");
+        }
+        builder.push(" ORDER BY a.started_at DESC LIMIT 500");
+        Ok(builder
+            .build_query_as::<Activation>()
+            .fetch_all(self.storage.pool())
+            .await?)
+    }
+
+    pub async fn activation(&self, id: Uuid) -> Result<Activation, SecurityError> {
+        sqlx::query_as::<_, Activation>(&format!(
+            "SELECT {} FROM break_glass_activations a JOIN break_glass_accounts b ON b.id = a.account_id WHERE a.id = $1",
+            ACTIVATION_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| Self::not_found("activation", id))
+    }
+
+    /// Check a secret and spend it, starting an activation and sending its
+    /// code. Unknown accounts and wrong secrets are told apart only in the
+    /// audit trail.
+    pub async fn activate(
+        &self,
+        state: &AppState,
+        request: &ActivateRequest,
+        ip: Option<String>,
+    ) -> Result<(Activation, otp::Challenge), SecurityError> {
+        let reason = request.reason.trim();
+        if reason.is_empty() {
+            return Err(SecurityError::ValidationError("reason is required".to_string()));
+        }
+        let invalid = || SecurityError::AuthError("Invalid break-glass credential".to_string());
+        let account = sqlx::query_as::<_, SealedAccount>(
+            "SELECT id, name, channel, secret_hash, encrypted_destination, key_id, nonce, context_hash \
+             FROM break_glass_accounts WHERE name = $1 AND disabled_at IS NULL",
+        )
+        .bind(request.account.trim())
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(invalid)?;
+        let hash = account.secret_hash.clone().ok_or_else(invalid)?;
+        if !state.crypto_service.verify_hash(request.secret.trim(), &hash)? {
+            return Err(invalid());
+        }
+
+        // The seal is broken now, whether or not the code follows
+        let spent = sqlx::query(
+            "UPDATE break_glass_accounts SET secret_hash = NULL, sealed_at = NULL WHERE id = $1 AND secret_hash = $2",
+        )
+        .bind(account.id)
+        .bind(&hash)
+        .execute(self.storage.pool())
+        .await?;
+        if spent.rows_affected() == 0 {
+            return Err(invalid());
+        }
+
+        let id = Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO break_glass_activations (id, account_id, status, reason, client_ip, started_at) \
+             VALUES ($1, $2, 'pending_mfa', $3, $4, $5)",
+        )
+        .bind(id)
+        .bind(account.id)
+        .bind(reason)
+        .bind(&ip)
+        .bind(self.clock.now())
+        .execute(self.storage.pool())
+        .await?;
+
+        if state.crypto_service.context_hash(Some(&destination_context(account.id)))? != account.context_hash {
+            return Err(SecurityError::CryptoError("Destination binding mismatch".to_string()));
+        }
+        let destination = state.crypto_service.decrypt_data(DecryptionRequest {
+            encrypted_data: account.encrypted_destination,
+            key_id: account.key_id,
+            nonce: account.nonce,
+            context_hash: account.context_hash,
+        }).await?;
+        let channel = Channel::parse(&account.channel)
+            .ok_or_else(|| SecurityError::ConfigError(format!("Unknown channel '{}'", account.channel)))?;
+        let locale = &state.config.tenant_settings.locale;
+        let challenge = state.otp.start(&state.delivery, &state.i18n, locale, &StartRequest {
+            subject: format!("{}{}", SUBJECT_PREFIX, account.name),
+            tenant_id: None,
+            channel,
+            destination,
+            locale: None,
+        }, SYSTEM_ACTOR).await?;
+
+        sqlx::query("UPDATE break_glass_activations SET challenge_id = $2 WHERE id = $1")
+            .bind(id)
+            .bind(challenge.id)
+            .execute(self.storage.pool())
+            .await?;
+        Ok((self.activation(id).await?, challenge))
+    }
+
+    async fn issue(&self, state: &AppState, activation: &Activation, ip: Option<String>) -> Result<IssuedTokens, SecurityError> {
+        let expires_at = activation.expires_at.unwrap_or_else(|| self.clock.now());
+        let tokens = state.tokens.issue(state, &IssueRequest {
+            subject: activation.subject(),
+            roles: self.config.roles.clone(),
+            not_after: Some(expires_at.timestamp()),
+            client_ip: ip,
+            ..IssueRequest::default()
+        }).await?;
+        if let Some(claims) = &tokens.claims {
+            sqlx::query("UPDATE break_glass_activations SET token_ids = array_append(token_ids, $2) WHERE id = $1")
+                .bind(activation.id)
+                .bind(&claims.jti)
+                .execute(self.storage.pool())
+                .await?;
+        }
+        Ok(tokens)
+    }
+
+    /// Check the activation's code; a good one completes it, issues its
+    /// first token and opens its review incident.
+    pub async fn verify(
+        &self,
+        state: &AppState,
+        id: Uuid,
+        code: &str,
+        ip: Option<String>,
+    ) -> Result<Completion, SecurityError> {
+        let activation = self.activation(id).await?;
+        let Some(challenge_id) = activation.challenge_id.filter(|_| activation.status == "pending_mfa") else {
+            return Err(SecurityError::Conflict(format!("Activation is {}", activation.status)));
+        };
+
+        let challenge = match state.otp.verify(challenge_id, code).await? {
+            Verification::Verified(challenge) => challenge,
+            Verification::Rejected { reason, challenge } => {
+                let attempts_remaining = state.otp.attempts_remaining(&challenge);
+                // A locked or expired challenge cannot be retried, and the
+                // secret is spent
+                let activation = if matches!(reason, "locked" | "expired") || attempts_remaining == 0 {
+                    self.finish(id, "pending_mfa", "failed", SYSTEM_ACTOR).await?.unwrap_or(activation)
+                } else {
+                    activation
+                };
+                return Ok(Completion::Rejected { reason, attempts_remaining, activation });
+            }
+        };
+
+        let now = self.clock.now();
+        let completed = sqlx::query(
+            "UPDATE break_glass_activations SET status = 'active', activated_at = $2, expires_at = $3 \
+             WHERE id = $1 AND status = 'pending_mfa'",
+        )
+        .bind(id)
+        .bind(now)
+        .bind(now + Duration::seconds(self.config.ttl_secs))
+        .execute(self.storage.pool())
+        .await?;
+        if completed.rows_affected() == 0 {
+            return Err(SecurityError::Conflict("Activation is no longer pending".to_string()));
+        }
+        let activation = self.activation(id).await?;
+        let tokens = self.issue(state, &activation, ip).await?;
+
+        let event_ids = audit(state, &activation.subject(), None, "break_glass.activate", &activation, "success", serde_json::json!({
+            "reason": activation.reason,
+            "challenge_id": challenge.id,
+            "expires_at": activation.expires_at
+        })).await.into_iter().collect();
+        let mut tx = self.storage.begin().await?;
+        let opened = detection::open(state, &mut tx, NewIncident {
+            rule: "break_glass_activation".to_string(),
+            severity: Severity::Critical,
+            tenant_id: None,
+            entity_type: "account",
+            entity: activation.account.clone(),
+            event_ids,
+            first_seen: activation.started_at,
+            last_seen: now,
+        }).await?;
+        sqlx::query("UPDATE break_glass_activations SET incident_id = $2 WHERE id = $1")
+            .bind(id)
+            .bind(opened.incident.id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        let incident_id = opened.incident.id;
+        detection::announce(state, &[opened]).await;
+        self.enrich(state, incident_id, &self.activation(id).await?).await;
+
+        Ok(Completion::Activated { activation: self.activation(id).await?, tokens: Box::new(tokens) })
+    }
+
+    /// Keep the review incident's copy of the activation current.
+    async fn enrich(&self, state: &AppState, incident_id: Uuid, activation: &Activation) {
+        let mut enrichment = serde_json::Map::new();
+        enrichment.insert("break_glass".to_string(), serde_json::json!({
+            "activation_id": activation.id,
+            "account": activation.account,
+            "reason": activation.reason,
+            "status": activation.status,
+            "client_ip": activation.client_ip,
+            "activated_at": activation.activated_at,
+            "expires_at": activation.expires_at,
+            "ended_at": activation.ended_at,
+            // Command log sessions to review
+            "sessions": activation.token_ids,
+            "review_outcome": activation.review_outcome,
+            "reviewed_by": activation.reviewed_by
+        }));
+        let update = IncidentUpdate { enrichment: Some(enrichment), ..IncidentUpdate::default() };
+        if let Err(e) = state.incidents.update(incident_id, update, None).await {
+            warn!("Failed to update break-glass incident {}: {:?}", incident_id, e);
+        }
+    }
+
+    /// A fresh token for a live activation, presented with one of its own.
+    pub async fn renew(&self, state: &AppState, id: Uuid, principal: &Principal, ip: Option<String>) -> Result<IssuedTokens, SecurityError> {
+        let activation = self.activation(id).await?;
+        let own = principal.token_id.as_ref().is_some_and(|jti| activation.token_ids.contains(jti));
+        if !own {
+            return Err(SecurityError::AccessDenied("Token is not from this activation".to_string()));
+        }
+        let live = activation.status == "active" && activation.expires_at.is_some_and(|at| at > self.clock.now());
+        if !live {
+            return Err(SecurityError::Conflict("Activation has ended".to_string()));
+        }
+        self.issue(state, &activation, ip).await
+    }
+
+    /// Move an activation from `from` to `to`, returning it if it was there.
+    async fn finish(&self, id: Uuid, from: &str, to: &str, actor: &str) -> Result<Option<Activation>, SecurityError> {
+        let moved = sqlx::query(
+            "UPDATE break_glass_activations SET status = $3, ended_at = $4, ended_by = $5 WHERE id = $1 AND status = $2",
+        )
+        .bind(id)
+        .bind(from)
+        .bind(to)
+        .bind(self.clock.now())
+        .bind(actor)
+        .execute(self.storage.pool())
+        .await?;
+        if moved.rows_affected() == 0 {
+            return Ok(None);
+        }
+        Ok(Some(self.activation(id).await?))
+    }
+
+    /// End an activation and revoke its tokens.
+    pub async fn end(&self, state: &AppState, id: Uuid, status: &str, actor: &str) -> Result<Activation, SecurityError> {
+        let Some(activation) = self.finish(id, "active", status, actor).await? else {
+            let activation = self.activation(id).await?;
+            return Err(SecurityError::Conflict(format!("Activation is {}", activation.status)));
+        };
+        let expires_at = activation.expires_at.unwrap_or_else(|| self.clock.now());
+        let issued: Vec<_> = activation.token_ids.iter().map(|jti| (jti.clone(), expires_at)).collect();
+        state.tokens.revoke_access(&issued, &activation.subject(), "break_glass_ended", actor).await?;
+        if let Some(incident_id) = activation.incident_id {
+            self.enrich(state, incident_id, &activation).await;
+        }
+        Ok(activation)
+    }
+
+    /// Record the post-use review and resolve its incident.
+    pub async fn review(
+        &self,
+        state: &AppState,
+        id: Uuid,
+        request: &ReviewRequest,
+        reviewer: &str,
+    ) -> Result<Activation, SecurityError> {
+        if !REVIEW_OUTCOMES.contains(&request.outcome.as_str()) {
+            return Err(SecurityError::ValidationError(format!(
+                "outcome must be one of {}",
+                REVIEW_OUTCOMES.join(", ")
+            )));
+        }
+        if request.notes.trim().is_empty() {
+            return Err(SecurityError::ValidationError("notes are required".to_string()));
+        }
+        let activation = self.activation(id).await?;
+        if reviewer == activation.subject() {
+            return Err(SecurityError::AccessDenied("An activation cannot review itself".to_string()));
+        }
+        if !activation.awaits_review() {
+            return Err(SecurityError::Conflict("Activation has no review outstanding".to_string()));
+        }
+        if activation.status == "active" {
+            return Err(SecurityError::Conflict("Activation has not ended".to_string()));
+        }
+
+        sqlx::query(
+            "UPDATE break_glass_activations SET reviewed_at = $2, reviewed_by = $3, review_outcome = $4, \
+             review_notes = $5 WHERE id = $1 AND reviewed_at IS NULL",
+        )
+        .bind(id)
+        .bind(self.clock.now())
+        .bind(reviewer)
+        .bind(&request.outcome)
+        .bind(request.notes.trim())
+        .execute(self.storage.pool())
+        .await?;
+        let activation = self.activation(id).await?;
+
+        if let Some(incident_id) = activation.incident_id {
+            let mut enrichment = serde_json::Map::new();
+            enrichment.insert("break_glass_review".to_string(), serde_json::json!({
+                "outcome": activation.review_outcome,
+                "notes": activation.review_notes,
+                "reviewed_by": activation.reviewed_by,
+                "reviewed_at": activation.reviewed_at
+            }));
+            state.incidents.update(incident_id, IncidentUpdate {
+                status: Some("resolved".to_string()),
+                enrichment: Some(enrichment),
+                ..IncidentUpdate::default()
+            }, None).await?;
+        }
+        Ok(activation)
+    }
+
+    /// Activations past their expiry, and those whose code can no longer
+    /// arrive.
+    async fn due(&self) -> Result<Vec<(Uuid, &'static str, &'static str)>, SecurityError> {
+        let now = self.clock.now();
+        let expired: Vec<Uuid> = sqlx::query_scalar(
+            "SELECT id FROM break_glass_activations WHERE status = 'active' AND expires_at <= $1",
+        )
+        .bind(now)
+        .fetch_all(self.storage.pool())
+        .await?;
+        let abandoned: Vec<Uuid> = sqlx::query_scalar(
+            "SELECT a.id FROM break_glass_activations a LEFT JOIN otp_challenges c ON c.id = a.challenge_id \
+             WHERE a.status = 'pending_mfa' AND (c.id IS NULL OR c.expires_at <= $1) AND a.started_at <= $2",
+        )
+        .bind(now)
+        .bind(now - Duration::minutes(1))
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(expired.into_iter().map(|id| (id, "active", "expired"))
+            .chain(abandoned.into_iter().map(|id| (id, "pending_mfa", "failed")))
+            .collect())
+    }
+}
+
+/// Page the security team.
+async fn page(state: &AppState, severity: Severity, title: String, details: serde_json::Value) {
+    let alert = Alert::new("break_glass", severity, title, details, state.clock.now());
+    let failed = state.alerting_service.send(&alert, &state.config.break_glass.alert_sinks).await;
+    if !failed.is_empty() {
+        error!("Break-glass page not delivered to {}", failed.join(", "));
+    }
+}
+
+async fn audit(
+    state: &AppState,
+    actor: &str,
+    actor_ip: Option<String>,
+    action: &str,
+    activation: &Activation,
+    outcome: &str,
+    payload: serde_json::Value,
+) -> Option<Uuid> {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: None,
+        actor: actor.to_string(),
+        actor_ip,
+        action: action.to_string(),
+        resource: format!("break_glass_activation:{}", activation.id),
+        outcome: outcome.to_string(),
+        payload,
+    }).await;
+    match recorded {
+        Ok(id) => Some(id),
+        Err(e) => {
+            warn!("Failed to audit {} of break-glass activation {}: {:?}", action, activation.id, e);
+            None
+        }
+    }
+}
+
+/// Account managers, who may not be break-glass principals themselves.
+fn authorize_manager(state: &AppState, req: &HttpRequest) -> Result<Principal, SecurityError> {
+    let principal = state.auth_service.authorize_roles(req, &state.config.break_glass.admin_roles)?;
+    if is_break_glass(&principal.subject) {
+        return Err(SecurityError::AccessDenied("Break-glass accounts cannot manage accounts".to_string()));
+    }
+    Ok(principal)
+}
+
+async fn audit_account(state: &AppState, req: &HttpRequest, principal: &Principal, action: &str, account: &Account) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: None,
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(req),
+        action: action.to_string(),
+        resource: format!("break_glass_account:{}", account.id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "name": account.name,
+            "holder": account.holder,
+            "channel": account.channel
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} of break-glass account {}: {:?}", action, account.id, e);
+    }
+}
+
+/// End expired activations and give up on abandoned ones.
+pub async fn run_expiry(state: web::Data<AppState>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
+    loop {
+        interval.tick().await;
+        let due = match state.break_glass.due().await {
+            Ok(due) => due,
+            Err(e) => {
+                warn!("Failed to find due break-glass activations: {:?}", e);
+                continue;
+            }
+        };
+        for (id, from, to) in due {
+            let ended = if from == "active" {
+                state.break_glass.end(&state, id, to, SYSTEM_ACTOR).await.map(Some)
+            } else {
+                state.break_glass.finish(id, from, to, SYSTEM_ACTOR).await
+            };
+            match ended {
+                Ok(Some(activation)) => {
+                    audit(&state, SYSTEM_ACTOR, None, "break_glass.expire", &activation, "success", serde_json::json!({
+                        "status": activation.status
+                    })).await;
+                    if activation.awaits_review() {
+                        page(&state, Severity::High, format!("Break-glass activation of {} expired; review due", activation.account),
+                            serde_json::to_value(&activation).unwrap_or_default()).await;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => error!("Failed to end break-glass activation {}: {:?}", id, e),
+            }
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::AuthError(msg) => HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::AccessDenied(msg) => HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Break-glass operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Break-glass operation failed"
+            }))
+        }
+    }
+}
+
+fn token_response(activation: &Activation, tokens: &IssuedTokens) -> serde_json::Value {
+    serde_json::json!({
+        "activation": activation,
+        "access_token": tokens.access_token,
+        "token_type": tokens.token_type,
+        "expires_in": tokens.expires_in
+    })
+}
+
+pub async fn activate_handler(
+    req: HttpRequest,
+    request: web::Json<ActivateRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let ip = client_ip(&req);
+    match state.break_glass.activate(&state, &request, ip.clone()).await {
+        Ok((activation, challenge)) => {
+            audit(&state, &activation.subject(), ip.clone(), "break_glass.unseal", &activation, "success", serde_json::json!({
+                "reason": activation.reason,
+                "challenge_id": challenge.id
+            })).await;
+            page(&state, Severity::Critical, format!("Break-glass credential {} unsealed", activation.account), serde_json::json!({
+                "activation_id": activation.id,
+                "reason": activation.reason,
+                "client_ip": ip
+            })).await;
+            Ok(HttpResponse::Accepted().json(serde_json::json!({
+                "activation": activation,
+                "challenge": {
+                    "id": challenge.id,
+                    "channel": challenge.channel,
+                    "destination_hint": challenge.destination_hint,
+                    "expires_at": challenge.expires_at
+                }
+            })))
+        }
+        Err(SecurityError::AuthError(msg)) => {
+            let recorded = state.audit_service.record(NewAuditEvent {
+                tenant_id: None,
+                actor: format!("{}{}", SUBJECT_PREFIX, request.account.trim()),
+                actor_ip: ip.clone(),
+                action: "break_glass.unseal".to_string(),
+                resource: format!("break_glass_account:{}", request.account.trim()),
+                outcome: "failure".to_string(),
+                payload: serde_json::json!({ "reason": request.reason }),
+            }).await;
+            if let Err(e) = recorded {
+                warn!("Failed to audit failed break-glass unseal: {:?}", e);
+            }
+            page(&state, Severity::High, format!("Failed break-glass unseal of {}", request.account.trim()), serde_json::json!({
+                "client_ip": ip
+            })).await;
+            Ok(error_response(SecurityError::AuthError(msg)))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn verify_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<VerifyRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let ip = client_ip(&req);
+    match state.break_glass.verify(&state, path.into_inner(), &request.code, ip.clone()).await {
+        Ok(Completion::Activated { activation, tokens }) => Ok(HttpResponse::Ok()
+            .insert_header(("Cache-Control", "no-store"))
+            .json(token_response(&activation, &tokens))),
+        Ok(Completion::Rejected { reason, attempts_remaining, activation }) => {
+            audit(&state, &activation.subject(), ip, "break_glass.verify", &activation, "failure", serde_json::json!({
+                "reason": reason,
+                "attempts_remaining": attempts_remaining
+            })).await;
+            if activation.status == "failed" {
+                page(&state, Severity::High, format!("Break-glass activation of {} failed its code", activation.account),
+                    serde_json::to_value(&activation).unwrap_or_default()).await;
+            }
+            Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": reason,
+                "attempts_remaining": attempts_remaining,
+                "activation": activation
+            })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn renew_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let id = path.into_inner();
+    match state.break_glass.renew(&state, id, &principal, client_ip(&req)).await {
+        Ok(tokens) => {
+            let activation = match state.break_glass.activation(id).await {
+                Ok(activation) => activation,
+                Err(e) => return Ok(error_response(e)),
+            };
+            audit(&state, &principal.subject, client_ip(&req), "break_glass.renew", &activation, "success", serde_json::Value::Null).await;
+            Ok(HttpResponse::Ok()
+                .insert_header(("Cache-Control", "no-store"))
+                .json(token_response(&activation, &tokens)))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn end_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let id = path.into_inner();
+    let activation = match state.break_glass.activation(id).await {
+        Ok(activation) => activation,
+        Err(e) => return Ok(error_response(e)),
+    };
+    // The activation's own tokens, or an admin's
+    let own = principal.token_id.as_ref().is_some_and(|jti| activation.token_ids.contains(jti));
+    if !own && !principal.has_any_role(&state.config.break_glass.admin_roles) {
+        return Ok(auth_error_response(&SecurityError::AccessDenied("Not this activation".to_string())));
+    }
+
+    match state.break_glass.end(&state, id, "ended", &principal.subject).await {
+        Ok(activation) => {
+            audit(&state, &principal.subject, client_ip(&req), "break_glass.end", &activation, "success", serde_json::json!({
+                "revoked_tokens": activation.token_ids.len()
+            })).await;
+            page(&state, Severity::Medium, format!("Break-glass activation of {} ended; review due", activation.account),
+                serde_json::to_value(&activation).unwrap_or_default()).await;
+            Ok(HttpResponse::Ok().json(activation))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_accounts_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = authorize_manager(&state, &req) {
+        return Ok(auth_error_response(&e));
+    }
+    match state.break_glass.accounts().await {
+        Ok(accounts) => Ok(HttpResponse::Ok().json(serde_json::json!({ "accounts": accounts }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn create_account_handler(
+    req: HttpRequest,
+    request: web::Json<CreateAccountRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_manager(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    match state.break_glass.create_account(&state.crypto_service, &request, &principal.subject).await {
+        Ok((account, secret)) => {
+            audit_account(&state, &req, &principal, "break_glass.account.create", &account).await;
+            Ok(HttpResponse::Created()
+                .insert_header(("Cache-Control", "no-store"))
+                .json(serde_json::json!({ "account": account, "secret": secret })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn reseal_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_manager(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    match state.break_glass.reseal(&state.crypto_service, path.into_inner()).await {
+        Ok((account, secret)) => {
+            audit_account(&state, &req, &principal, "break_glass.account.reseal", &account).await;
+            Ok(HttpResponse::Ok()
+                .insert_header(("Cache-Control", "no-store"))
+                .json(serde_json::json!({ "account": account, "secret": secret })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn disable_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_manager(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    match state.break_glass.disable(path.into_inner()).await {
+        Ok(account) => {
+            audit_account(&state, &req, &principal, "break_glass.account.disable", &account).await;
+            Ok(HttpResponse::Ok().json(account))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_activations_handler(
+    req: HttpRequest,
+    filter: web::Query<ActivationFilter>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_roles(&req, &state.config.break_glass.review_roles) {
+        return Ok(auth_error_response(&e));
+    }
+    match state.break_glass.activations(&filter).await {
+        Ok(activations) => Ok(HttpResponse::Ok().json(serde_json::json!({ "activations": activations }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_activation_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_roles(&req, &state.config.break_glass.review_roles) {
+        return Ok(auth_error_response(&e));
+    }
+    match state.break_glass.activation(path.into_inner()).await {
+        Ok(activation) => Ok(HttpResponse::Ok().json(activation)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn review_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<ReviewRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_roles(&req, &state.config.break_glass.review_roles) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    match state.break_glass.review(&state, path.into_inner(), &request, &principal.subject).await {
+        Ok(activation) => {
+            audit(&state, &principal.subject, client_ip(&req), "break_glass.review", &activation, "success", serde_json::json!({
+                "outcome": activation.review_outcome,
+                "incident_id": activation.incident_id
+            })).await;
+            Ok(HttpResponse::Ok().json(activation))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+/// The `/auth/break-glass` routes are registered by `auth::configure_routes`.
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/break-glass")
+            .route("/accounts", web::get().to(list_accounts_handler))
+            .route("/accounts", web::post().to(create_account_handler))
+            .route("/accounts/{id}/reseal", web::post().to(reseal_handler))
+            .route("/accounts/{id}", web::delete().to(disable_handler))
+            .route("/activations", web::get().to(list_activations_handler))
+            .route("/activations/{id}", web::get().to(get_activation_handler))
+            .route("/activations/{id}/review", web::post().to(review_handler))
+    );
+}
@@ -0,0 +1,449 @@
+/*!
+CAPTCHA Verification
+Server-side checks of hCaptcha, reCAPTCHA and Turnstile tokens
+
+Frontends hand the token their widget produced to this service instead of
+every service holding the provider's secret. `CAPTCHA_PROVIDER` picks the
+provider; its site secret is the `captcha` credential (a `bearer`
+credential, see `credentials`), or else `CAPTCHA_SECRET`.
+
+- `POST /auth/captcha/verify` with `{token, action, subject}` checks a
+  token and returns the result. It needs no authentication, so a frontend
+  can call it directly.
+- The `captcha` pipeline stage (see `pipeline`) requires a passing token
+  in `X-Captcha-Token` on mutating requests under its scopes, e.g.
+  `captcha@/api/v1/auth/otp`.
+
+A token passes when the provider accepts it, its score (reCAPTCHA v3)
+reaches `CAPTCHA_MIN_SCORE`, it was solved on one of `CAPTCHA_HOSTNAMES`
+and, when the caller names one, for the expected action. An unreachable
+provider fails the check unless `CAPTCHA_FAIL_OPEN` is set.
+
+Every result is stored with the caller's address and subject and audited
+as `captcha.verify`, so UEBA scores CAPTCHA failures like other activity.
+`GET /auth/captcha/signals?ip=..&subject=..`, for holders of
+`AUTH_ISSUE_SCOPE`, sums up recent results with the subject's UEBA risk and
+says whether the next attempt should be challenged, which the login flow
+uses for throttling.
+*/
+
+use actix_web::dev::ServiceRequest;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip};
+use crate::clock::Clock;
+use crate::config::{CaptchaConfig, Config};
+use crate::credentials::CredentialCache;
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+use crate::AppState;
+
+/// Header the `captcha` pipeline stage reads the token from.
+pub const TOKEN_HEADER: &str = "X-Captcha-Token";
+
+const CREDENTIAL: &str = "captcha";
+
+const VERIFICATION_COLUMNS: &str =
+    "id, provider, success, score, action, hostname, error_codes, remote_ip, subject, via, created_at";
+
+/// How long results are kept for signals.
+const RETENTION_DAYS: i64 = 7;
+
+/// Error code recorded when the provider could not be asked.
+const UNAVAILABLE: &str = "provider-unavailable";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    Hcaptcha,
+    Recaptcha,
+    Turnstile,
+}
+
+impl Provider {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "hcaptcha" => Some(Provider::Hcaptcha),
+            "recaptcha" => Some(Provider::Recaptcha),
+            "turnstile" => Some(Provider::Turnstile),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Provider::Hcaptcha => "hcaptcha",
+            Provider::Recaptcha => "recaptcha",
+            Provider::Turnstile => "turnstile",
+        }
+    }
+
+    fn verify_url(&self) -> &'static str {
+        match self {
+            Provider::Hcaptcha => "https://api.hcaptcha.com/siteverify",
+            Provider::Recaptcha => "https://www.google.com/recaptcha/api/siteverify",
+            Provider::Turnstile => "https://challenges.cloudflare.com/turnstile/v0/siteverify",
+        }
+    }
+}
+
+/// The siteverify answer; the three providers share this shape.
+#[derive(Debug, Deserialize)]
+struct SiteVerify {
+    success: bool,
+    score: Option<f64>,
+    action: Option<String>,
+    hostname: Option<String>,
+    #[serde(rename = "error-codes", default)]
+    error_codes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Verification {
+    pub id: Uuid,
+    pub provider: String,
+    /// Whether the token passed every check, not only the provider's.
+    pub success: bool,
+    pub score: Option<f64>,
+    pub action: Option<String>,
+    pub hostname: Option<String>,
+    /// Provider error codes, plus `score-too-low`, `hostname-mismatch`,
+    /// `action-mismatch` or `provider-unavailable` from our own checks.
+    pub error_codes: Vec<String>,
+    pub remote_ip: Option<String>,
+    pub subject: Option<String>,
+    /// `endpoint` or `pipeline`.
+    pub via: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyRequest {
+    pub token: String,
+    /// Action the token must have been issued for, where the provider
+    /// reports one.
+    pub action: Option<String>,
+    /// Who is attempting, e.g. the login name; kept for signals.
+    pub subject: Option<String>,
+}
+
+/// What a check is about, besides the token.
+pub struct Attempt<'a> {
+    pub action: Option<&'a str>,
+    pub subject: Option<&'a str>,
+    pub remote_ip: Option<&'a str>,
+    pub via: &'static str,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SignalsQuery {
+    pub ip: Option<String>,
+    pub subject: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Signals {
+    pub window_secs: i64,
+    pub failures: i64,
+    pub successes: i64,
+    pub last_failure_at: Option<DateTime<Utc>>,
+    /// The subject's UEBA score today, when it has one.
+    pub risk_score: Option<f64>,
+    /// Whether the next attempt should be challenged.
+    pub challenge: bool,
+}
+
+pub struct CaptchaService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: CaptchaConfig,
+    provider: Option<Provider>,
+    client: reqwest::Client,
+    credentials: Arc<CredentialCache>,
+}
+
+impl CaptchaService {
+    pub async fn new(
+        config: &Config,
+        storage: Storage,
+        clock: Arc<dyn Clock>,
+        credentials: Arc<CredentialCache>,
+    ) -> Result<Self, SecurityError> {
+        let provider = match &config.captcha.provider {
+            Some(name) => Some(Provider::parse(name)
+                .ok_or_else(|| SecurityError::ConfigError(format!("Unknown CAPTCHA provider '{}'", name)))?),
+            None => None,
+        };
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(config.captcha.timeout_secs))
+            .build()
+            .map_err(|e| SecurityError::ConfigError(format!("CAPTCHA client: {}", e)))?;
+
+        info!("CAPTCHA service initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            config: config.captcha.clone(),
+            provider,
+            client,
+            credentials,
+        })
+    }
+
+    fn secret(&self) -> Option<String> {
+        self.credentials.bearer_token(CREDENTIAL).or_else(|| self.config.secret.clone())
+    }
+
+    async fn site_verify(&self, provider: Provider, token: &str, remote_ip: Option<&str>) -> Result<SiteVerify, SecurityError> {
+        let secret = self.secret()
+            .ok_or_else(|| SecurityError::ConfigError("No CAPTCHA secret is configured".to_string()))?;
+        let url = self.config.verify_url.as_deref().unwrap_or(provider.verify_url());
+        let mut form = vec![("secret", secret.as_str()), ("response", token)];
+        if let Some(ip) = remote_ip {
+            form.push(("remoteip", ip));
+        }
+
+        let response = self.client.post(url).form(&form).send().await
+            .map_err(|e| SecurityError::DeliveryError(format!("CAPTCHA provider unreachable: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(SecurityError::DeliveryError(format!("CAPTCHA provider returned {}", response.status())));
+        }
+        response.json().await
+            .map_err(|e| SecurityError::DeliveryError(format!("CAPTCHA provider response: {}", e)))
+    }
+
+    /// Check a token and store the result.
+    pub async fn verify(&self, token: &str, attempt: &Attempt<'_>) -> Result<Verification, SecurityError> {
+        let provider = self.provider
+            .ok_or_else(|| SecurityError::ValidationError("CAPTCHA verification is not configured".to_string()))?;
+        if token.trim().is_empty() {
+            return Err(SecurityError::ValidationError("token is required".to_string()));
+        }
+
+        let (success, answer) = match self.site_verify(provider, token.trim(), attempt.remote_ip).await {
+            Ok(answer) => {
+                let mut failed = Vec::new();
+                if answer.score.is_some_and(|score| score < self.config.min_score) {
+                    failed.push("score-too-low".to_string());
+                }
+                let host_allowed = self.config.hostnames.is_empty()
+                    || answer.hostname.as_ref().is_some_and(|host| self.config.hostnames.contains(host));
+                if !host_allowed {
+                    failed.push("hostname-mismatch".to_string());
+                }
+                if let (Some(expected), Some(action)) = (attempt.action, &answer.action) {
+                    if expected != action {
+                        failed.push("action-mismatch".to_string());
+                    }
+                }
+                let success = answer.success && failed.is_empty();
+                let mut error_codes = answer.error_codes.clone();
+                error_codes.extend(failed);
+                (success, SiteVerify { error_codes, ..answer })
+            }
+            Err(SecurityError::DeliveryError(e)) => {
+                warn!("CAPTCHA verification unavailable: {}", e);
+                (self.config.fail_open, SiteVerify {
+                    success: false,
+                    score: None,
+                    action: None,
+                    hostname: None,
+                    error_codes: vec![UNAVAILABLE.to_string()],
+                })
+            }
+            Err(e) => return Err(e),
+        };
+
+        let now = self.clock.now();
+        sqlx::query("DELETE FROM captcha_verifications WHERE created_at < $1")
+            .bind(now - Duration::days(RETENTION_DAYS))
+            .execute(self.storage.pool())
+            .await?;
+        let verification = sqlx::query_as::<_, Verification>(&format!(
+            "INSERT INTO captcha_verifications (id, provider, success, score, action, hostname, error_codes, \
+             remote_ip, subject, via, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) \
+             RETURNING {}",
+            VERIFICATION_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(provider.as_str())
+        .bind(success)
+        .bind(answer.score)
+        .bind(&answer.action)
+        .bind(&answer.hostname)
+        .bind(&answer.error_codes)
+        .bind(attempt.remote_ip)
+        .bind(attempt.subject)
+        .bind(attempt.via)
+        .bind(now)
+        .fetch_one(self.storage.pool())
+        .await?;
+
+        Ok(verification)
+    }
+
+    /// Recent results for an address or subject.
+    pub async fn signals(&self, ueba: &crate::detection::UebaService, query: &SignalsQuery) -> Result<Signals, SecurityError> {
+        if query.ip.is_none() && query.subject.is_none() {
+            return Err(SecurityError::ValidationError("ip or subject is required".to_string()));
+        }
+        let now = self.clock.now();
+        let (failures, successes, last_failure_at): (i64, i64, Option<DateTime<Utc>>) = sqlx::query_as(
+            "SELECT COUNT(*) FILTER (WHERE NOT success), COUNT(*) FILTER (WHERE success), \
+             MAX(created_at) FILTER (WHERE NOT success) FROM captcha_verifications \
+             WHERE created_at >= $1 AND (remote_ip = $2 OR subject = $3)",
+        )
+        .bind(now - Duration::seconds(self.config.failure_window_secs))
+        .bind(&query.ip)
+        .bind(&query.subject)
+        .fetch_one(self.storage.pool())
+        .await?;
+
+        let risk_score = match &query.subject {
+            Some(subject) => ueba.current_risk(subject, ueba.today(now)).await?.map(|risk| risk.score),
+            None => None,
+        };
+
+        Ok(Signals {
+            window_secs: self.config.failure_window_secs,
+            failures,
+            successes,
+            last_failure_at,
+            risk_score,
+            challenge: failures >= self.config.failure_threshold
+                || risk_score.is_some_and(|score| score >= self.config.risk_threshold),
+        })
+    }
+}
+
+/// Verify a token, auditing and counting the result.
+pub async fn check(state: &AppState, token: &str, attempt: &Attempt<'_>) -> Result<Verification, SecurityError> {
+    let verification = state.captcha.verify(token, attempt).await?;
+    let outcome = if verification.success { "success" } else { "failure" };
+    state.metrics_service.increment(
+        "cotai_captcha_verifications_total",
+        &[("provider", verification.provider.as_str()), ("outcome", outcome)],
+    );
+
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: None,
+        actor: verification.subject.clone().unwrap_or_else(|| "anonymous".to_string()),
+        actor_ip: verification.remote_ip.clone(),
+        action: "captcha.verify".to_string(),
+        resource: format!("captcha_verification:{}", verification.id),
+        outcome: outcome.to_string(),
+        payload: serde_json::json!({
+            "provider": verification.provider,
+            "score": verification.score,
+            "action": verification.action,
+            "hostname": verification.hostname,
+            "error_codes": verification.error_codes,
+            "via": verification.via
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit CAPTCHA verification {}: {:?}", verification.id, e);
+    }
+    Ok(verification)
+}
+
+/// The `captcha` pipeline stage: the response to answer with instead of
+/// running a mutating request without a passing token.
+pub async fn rejection(state: &AppState, req: &ServiceRequest) -> Option<HttpResponse> {
+    if req.method().is_safe() {
+        return None;
+    }
+    let Some(token) = req.headers().get(TOKEN_HEADER).and_then(|v| v.to_str().ok()) else {
+        return Some(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": format!("A CAPTCHA token is required in {}", TOKEN_HEADER),
+            "code": "captcha_required"
+        })));
+    };
+
+    let http = req.request();
+    let subject = state.auth_service.authenticate(http).ok().map(|principal| principal.subject);
+    let remote_ip = client_ip(http);
+    let attempt = Attempt {
+        action: None,
+        subject: subject.as_deref(),
+        remote_ip: remote_ip.as_deref(),
+        via: "pipeline",
+    };
+
+    match check(state, token, &attempt).await {
+        Ok(verification) if verification.success => None,
+        Ok(verification) => Some(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "CAPTCHA verification failed",
+            "code": "captcha_failed",
+            "error_codes": verification.error_codes
+        }))),
+        Err(SecurityError::ValidationError(msg)) => Some(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg,
+            "code": "captcha_required"
+        }))),
+        Err(e) => {
+            error!("CAPTCHA check failed: {:?}", e);
+            Some(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "error": "CAPTCHA verification is unavailable",
+                "code": "captcha_unavailable"
+            })))
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("CAPTCHA operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "CAPTCHA operation failed"
+            }))
+        }
+    }
+}
+
+pub async fn verify_handler(
+    req: HttpRequest,
+    request: web::Json<VerifyRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let remote_ip = client_ip(&req);
+    let attempt = Attempt {
+        action: request.action.as_deref(),
+        subject: request.subject.as_deref(),
+        remote_ip: remote_ip.as_deref(),
+        via: "endpoint",
+    };
+
+    match check(&state, &request.token, &attempt).await {
+        Ok(verification) => Ok(HttpResponse::Ok().json(verification)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn signals_handler(
+    req: HttpRequest,
+    query: web::Query<SignalsQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = authorize_scope(&state, &req, &state.config.auth.issue_scope) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.captcha.signals(&state.ueba, &query).await {
+        Ok(signals) => Ok(HttpResponse::Ok().json(signals)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
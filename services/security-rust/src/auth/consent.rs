@@ -0,0 +1,752 @@
+/*!
+Consent Module
+Third-party applications and the scopes users grant them
+
+Admins register third-party applications (bid-monitoring tools and the
+like) as OAuth clients under `/admin/oauth-clients`, with the scopes each
+may ask for and its redirect URIs. The platform's consent screen then
+works against the user's own token:
+
+- `GET /auth/consent?client_id=..&scope=..&redirect_uri=..` describes the
+  client and which requested scopes the user has not granted yet, with the
+  screen's texts and scope descriptions in the user's locale (see `i18n`)
+- `POST /auth/consent` records a grant; granting again adds scopes
+- `GET /auth/consents` and `DELETE /auth/consents/{client_id}` list and
+  revoke the user's grants
+
+Tokens minted for a client through `POST /auth/tokens` (with `client_id`)
+are refused unless the user's grant covers every scope, and carry the
+`client_id` claim. Refresh tokens are checked again on each use, and
+revoking a grant revokes the refresh token families issued to the client
+for that user, with their access tokens. Admins see every grant at
+`/admin/consents`.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::NewAuditEvent;
+use crate::auth::service_accounts::validate_scopes;
+use crate::auth::{auth_error_response, Principal};
+use crate::clock::Clock;
+use crate::errors::SecurityError;
+use crate::i18n::{self, I18n};
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::Storage;
+use crate::AppState;
+
+const CLIENT_SORT_FIELDS: &[SortField] = &[
+    SortField { name: "created_at", column: "created_at", kind: KeyKind::Timestamp },
+    SortField { name: "name", column: "name", kind: KeyKind::Text },
+];
+
+const GRANT_SORT_FIELDS: &[SortField] = &[
+    SortField { name: "granted_at", column: "granted_at", kind: KeyKind::Timestamp },
+];
+
+const CLIENT_COLUMNS: &str = "id, name, description, redirect_uris, scopes, status, created_by, created_at, updated_at";
+
+const GRANT_COLUMNS: &str = "id, client_id, subject, tenant_id, scopes, granted_at, updated_at, revoked_at, revoked_by";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct OAuthClient {
+    pub id: Uuid,
+    pub name: String,
+    pub description: String,
+    pub redirect_uris: Vec<String>,
+    /// Scopes the client may ask users for.
+    pub scopes: Vec<String>,
+    /// `active` or `disabled`.
+    pub status: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ConsentGrant {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub subject: String,
+    pub tenant_id: Option<String>,
+    pub scopes: Vec<String>,
+    pub granted_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub revoked_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateClientRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateClientRequest {
+    pub description: Option<String>,
+    pub redirect_uris: Option<Vec<String>>,
+    pub scopes: Option<Vec<String>>,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ClientFilter {
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct GrantFilter {
+    pub subject: Option<String>,
+    pub client_id: Option<Uuid>,
+    /// Include revoked grants.
+    #[serde(default)]
+    pub all: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConsentQuery {
+    pub client_id: Uuid,
+    pub scope: Option<String>,
+    pub redirect_uri: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GrantRequest {
+    pub client_id: Uuid,
+    pub scopes: Vec<String>,
+}
+
+/// What the consent screen shows.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsentScreen {
+    pub client_id: Uuid,
+    pub name: String,
+    pub description: String,
+    pub requested: Vec<String>,
+    pub granted: Vec<String>,
+    /// Requested scopes the user still has to approve.
+    pub missing: Vec<String>,
+    pub consent_required: bool,
+    /// Filled in for the user's locale.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub texts: Option<ConsentTexts>,
+}
+
+/// What the consent screen says, in one locale.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsentTexts {
+    pub locale: String,
+    pub prompt: String,
+    /// Heading of the scopes still to approve.
+    pub requested: String,
+    /// Heading of the scopes approved before.
+    pub granted: String,
+    /// Description of each requested scope, the scope itself when the
+    /// catalog has none.
+    pub scopes: BTreeMap<String, String>,
+}
+
+impl ConsentTexts {
+    pub fn new(i18n: &I18n, locale: &str, screen: &ConsentScreen) -> Self {
+        let client: &dyn std::fmt::Display = &screen.name;
+        Self {
+            locale: locale.to_string(),
+            prompt: i18n.text(locale, "consent.prompt", &[("client", client)]),
+            requested: i18n.text(locale, "consent.requested", &[("client", client)]),
+            granted: i18n.text(locale, "consent.granted", &[("client", client)]),
+            scopes: screen
+                .requested
+                .iter()
+                .map(|scope| {
+                    let description = i18n.translation(locale, &format!("scope.{}", scope));
+                    (scope.clone(), description.unwrap_or_else(|| scope.clone()))
+                })
+                .collect(),
+        }
+    }
+}
+
+fn validate_redirect_uris(uris: &[String]) -> Result<(), SecurityError> {
+    // Plain HTTP only for tools running on the user's own machine
+    let valid = |uri: &String| {
+        uri.starts_with("https://") || uri.starts_with("http://localhost") || uri.starts_with("http://127.0.0.1")
+    };
+    if let Some(uri) = uris.iter().find(|uri| !valid(uri) || uri.contains('#')) {
+        return Err(SecurityError::ValidationError(format!(
+            "Invalid redirect URI {:?}: must be https (or http on localhost) without a fragment",
+            uri
+        )));
+    }
+    Ok(())
+}
+
+fn validate_status(status: Option<&str>) -> Result<(), SecurityError> {
+    match status {
+        None | Some("active") | Some("disabled") => Ok(()),
+        Some(other) => Err(SecurityError::ValidationError(format!("Unknown status '{}'", other))),
+    }
+}
+
+pub struct ConsentService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+}
+
+impl ConsentService {
+    pub async fn new(storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+
+        info!("Consent service initialized successfully");
+        Ok(Self { storage, clock })
+    }
+
+    pub async fn create_client(&self, principal: &Principal, request: CreateClientRequest) -> Result<OAuthClient, SecurityError> {
+        let name = request.name.trim();
+        if name.is_empty() || name.len() > 100 {
+            return Err(SecurityError::ValidationError("name must be 1-100 characters".to_string()));
+        }
+        if request.scopes.is_empty() {
+            return Err(SecurityError::ValidationError("A client needs at least one scope".to_string()));
+        }
+        validate_scopes(&request.scopes)?;
+        validate_redirect_uris(&request.redirect_uris)?;
+
+        let client = sqlx::query_as::<_, OAuthClient>(&format!(
+            "INSERT INTO oauth_clients (id, name, description, redirect_uris, scopes, created_by) \
+             VALUES ($1, $2, $3, $4, $5, $6) RETURNING {}",
+            CLIENT_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(name)
+        .bind(&request.description)
+        .bind(&request.redirect_uris)
+        .bind(&request.scopes)
+        .bind(&principal.subject)
+        .fetch_one(self.storage.pool())
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                SecurityError::Conflict(format!("A client named {} already exists", name))
+            }
+            e => e.into(),
+        })?;
+
+        info!("{} registered OAuth client {} ({})", principal.subject, client.id, client.name);
+        Ok(client)
+    }
+
+    pub async fn list_clients(&self, filter: &ClientFilter, page: &PageRequest) -> Result<Page<OAuthClient>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM oauth_clients WHERE 1 = 1",
+            CLIENT_COLUMNS
+        ));
+        if let Some(status) = &filter.status {
+            builder.push(" AND status = ").push_bind(status.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let clients = builder
+            .build_query_as::<OAuthClient>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(clients, |client, field| match field {
+            "name" => (SortKey::Text(client.name.clone()), client.id),
+            _ => (SortKey::Timestamp(client.created_at), client.id),
+        }))
+    }
+
+    pub async fn get_client(&self, id: Uuid) -> Result<OAuthClient, SecurityError> {
+        sqlx::query_as::<_, OAuthClient>(&format!(
+            "SELECT {} FROM oauth_clients WHERE id = $1",
+            CLIENT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("OAuth client not found".to_string()))
+    }
+
+    /// Narrowing a client's scopes does not touch existing grants; tokens
+    /// stop carrying the dropped scopes at the next issue or refresh.
+    pub async fn update_client(&self, id: Uuid, request: UpdateClientRequest) -> Result<OAuthClient, SecurityError> {
+        validate_status(request.status.as_deref())?;
+        if let Some(scopes) = &request.scopes {
+            if scopes.is_empty() {
+                return Err(SecurityError::ValidationError("A client needs at least one scope".to_string()));
+            }
+            validate_scopes(scopes)?;
+        }
+        if let Some(uris) = &request.redirect_uris {
+            validate_redirect_uris(uris)?;
+        }
+
+        sqlx::query_as::<_, OAuthClient>(&format!(
+            "UPDATE oauth_clients SET description = COALESCE($2, description), \
+             redirect_uris = COALESCE($3, redirect_uris), scopes = COALESCE($4, scopes), \
+             status = COALESCE($5, status), updated_at = NOW() WHERE id = $1 RETURNING {}",
+            CLIENT_COLUMNS
+        ))
+        .bind(id)
+        .bind(&request.description)
+        .bind(&request.redirect_uris)
+        .bind(&request.scopes)
+        .bind(&request.status)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("OAuth client not found".to_string()))
+    }
+
+    async fn live_grant(&self, client_id: Uuid, subject: &str) -> Result<Option<ConsentGrant>, SecurityError> {
+        let grant = sqlx::query_as::<_, ConsentGrant>(&format!(
+            "SELECT {} FROM consent_grants WHERE client_id = $1 AND subject = $2 AND revoked_at IS NULL",
+            GRANT_COLUMNS
+        ))
+        .bind(client_id)
+        .bind(subject)
+        .fetch_optional(self.storage.pool())
+        .await?;
+        Ok(grant)
+    }
+
+    /// The consent screen for `principal`. Scopes default to all the
+    /// client may ask for.
+    pub async fn screen(&self, principal: &Principal, query: &ConsentQuery) -> Result<ConsentScreen, SecurityError> {
+        let client = self.get_client(query.client_id).await?;
+        if client.status != "active" {
+            return Err(SecurityError::ValidationError("This application is disabled".to_string()));
+        }
+        if let Some(uri) = &query.redirect_uri {
+            if !client.redirect_uris.contains(uri) {
+                return Err(SecurityError::ValidationError("redirect_uri is not registered for this application".to_string()));
+            }
+        }
+
+        let requested: Vec<String> = match query.scope.as_deref() {
+            Some(scope) => scope.split_whitespace().map(String::from).collect(),
+            None => client.scopes.clone(),
+        };
+        if let Some(scope) = requested.iter().find(|scope| !client.scopes.contains(scope)) {
+            return Err(SecurityError::ValidationError(format!("This application may not request '{}'", scope)));
+        }
+
+        let granted = self.live_grant(client.id, &principal.subject).await?
+            .map(|grant| grant.scopes)
+            .unwrap_or_default();
+        let missing: Vec<String> = requested.iter().filter(|scope| !granted.contains(scope)).cloned().collect();
+        Ok(ConsentScreen {
+            client_id: client.id,
+            name: client.name,
+            description: client.description,
+            consent_required: !missing.is_empty(),
+            requested,
+            granted,
+            missing,
+            texts: None,
+        })
+    }
+
+    /// Record `principal`'s consent, adding to any live grant.
+    pub async fn grant(&self, principal: &Principal, request: &GrantRequest) -> Result<ConsentGrant, SecurityError> {
+        if request.scopes.is_empty() {
+            return Err(SecurityError::ValidationError("scopes must not be empty".to_string()));
+        }
+        let client = self.get_client(request.client_id).await?;
+        if client.status != "active" {
+            return Err(SecurityError::ValidationError("This application is disabled".to_string()));
+        }
+        if let Some(scope) = request.scopes.iter().find(|scope| !client.scopes.contains(scope)) {
+            return Err(SecurityError::ValidationError(format!("This application may not request '{}'", scope)));
+        }
+
+        let now = self.clock.now();
+        let grant = sqlx::query_as::<_, ConsentGrant>(&format!(
+            "INSERT INTO consent_grants (id, client_id, subject, tenant_id, scopes, granted_at, updated_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $6) \
+             ON CONFLICT (client_id, subject) WHERE revoked_at IS NULL DO UPDATE SET \
+             scopes = ARRAY(SELECT DISTINCT unnest(consent_grants.scopes || EXCLUDED.scopes) ORDER BY 1), \
+             updated_at = EXCLUDED.updated_at \
+             RETURNING {}",
+            GRANT_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(client.id)
+        .bind(&principal.subject)
+        .bind(&principal.tenant_id)
+        .bind(&request.scopes)
+        .bind(now)
+        .fetch_one(self.storage.pool())
+        .await?;
+
+        info!("{} granted {} to OAuth client {}", principal.subject, request.scopes.join(" "), client.id);
+        Ok(grant)
+    }
+
+    pub async fn grants(&self, filter: &GrantFilter, page: &PageRequest) -> Result<Page<ConsentGrant>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM consent_grants WHERE 1 = 1",
+            GRANT_COLUMNS
+        ));
+        if let Some(subject) = &filter.subject {
+            builder.push(" AND subject = ").push_bind(subject.clone());
+        }
+        if let Some(client_id) = filter.client_id {
+            builder.push(" AND client_id = ").push_bind(client_id);
+        }
+        if !filter.all {
+            builder.push(" AND revoked_at IS NULL");
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let grants = builder
+            .build_query_as::<ConsentGrant>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(grants, |grant, _| (SortKey::Timestamp(grant.granted_at), grant.id)))
+    }
+
+    /// Withdraw `subject`'s consent for a client. Tokens are revoked by
+    /// the caller, see `TokenService::revoke_client`.
+    pub async fn revoke(&self, subject: &str, client_id: Uuid, actor: &str) -> Result<ConsentGrant, SecurityError> {
+        sqlx::query_as::<_, ConsentGrant>(&format!(
+            "UPDATE consent_grants SET revoked_at = $3, revoked_by = $4 \
+             WHERE client_id = $1 AND subject = $2 AND revoked_at IS NULL RETURNING {}",
+            GRANT_COLUMNS
+        ))
+        .bind(client_id)
+        .bind(subject)
+        .bind(self.clock.now())
+        .bind(actor)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("No consent granted to this application".to_string()))
+    }
+
+    /// Refuse tokens for `client_id` that `subject` has not consented to.
+    pub async fn check(&self, client_id: Uuid, subject: &str, scopes: &[String]) -> Result<(), SecurityError> {
+        let client = self.get_client(client_id).await.map_err(|e| match e {
+            SecurityError::NotFound(_) => SecurityError::AccessDenied(format!("Unknown client {}", client_id)),
+            e => e,
+        })?;
+        if client.status != "active" {
+            return Err(SecurityError::AccessDenied(format!("Client {} is disabled", client_id)));
+        }
+        if let Some(scope) = scopes.iter().find(|scope| !client.scopes.contains(scope)) {
+            return Err(SecurityError::AccessDenied(format!("Client {} may not hold '{}'", client_id, scope)));
+        }
+
+        let granted = self.live_grant(client_id, subject).await?
+            .map(|grant| grant.scopes)
+            .unwrap_or_default();
+        let missing: Vec<&str> = scopes.iter().filter(|scope| !granted.contains(scope)).map(String::as_str).collect();
+        if !missing.is_empty() {
+            return Err(SecurityError::AccessDenied(format!(
+                "consent_required: {} has not granted {} to client {}",
+                subject, missing.join(" "), client_id
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Consent operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Consent operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit(state: &AppState, principal: &Principal, action: &str, resource: String, payload: serde_json::Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: principal.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: None,
+        action: action.to_string(),
+        resource: resource.clone(),
+        outcome: "success".to_string(),
+        payload,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} on {}: {:?}", action, resource, e);
+    }
+}
+
+/// Revoke a grant and the tokens issued under it.
+async fn revoke_grant(state: &AppState, principal: &Principal, subject: &str, client_id: Uuid) -> HttpResponse {
+    let grant = match state.consents.revoke(subject, client_id, &principal.subject).await {
+        Ok(grant) => grant,
+        Err(e) => return error_response(e),
+    };
+    let revoked = match state.tokens.revoke_client(subject, client_id, &principal.subject).await {
+        Ok(revoked) => revoked,
+        Err(e) => {
+            error!("Consent for client {} revoked but its tokens for {} were not: {:?}", client_id, subject, e);
+            return error_response(e);
+        }
+    };
+
+    audit(state, principal, "auth.consent.revoke", format!("oauth_client:{}", client_id), serde_json::json!({
+        "subject": subject,
+        "scopes": grant.scopes,
+        "revoked_access_tokens": revoked
+    })).await;
+    HttpResponse::Ok().json(grant)
+}
+
+// HTTP handlers
+
+pub async fn screen_handler(
+    req: HttpRequest,
+    query: web::Query<ConsentQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.consents.screen(&principal, &query).await {
+        Ok(mut screen) => {
+            let locale = state.i18n.locale(&state, &req, principal.tenant_id.as_deref()).await;
+            screen.texts = Some(ConsentTexts::new(&state.i18n, &locale, &screen));
+            Ok(i18n::content_language(HttpResponse::Ok().json(screen), &locale))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn grant_handler(
+    req: HttpRequest,
+    request: web::Json<GrantRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    // Consent is the user's own decision; a delegated token cannot give it
+    if !principal.delegation.is_empty() {
+        let e = SecurityError::AccessDenied("Consent cannot be given with a delegated token".to_string());
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.consents.grant(&principal, &request).await {
+        Ok(grant) => {
+            audit(&state, &principal, "auth.consent.grant", format!("oauth_client:{}", grant.client_id), serde_json::json!({
+                "requested": request.scopes,
+                "scopes": grant.scopes
+            })).await;
+            Ok(HttpResponse::Ok().json(grant))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_own_handler(
+    req: HttpRequest,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let page = match page.resolve(GRANT_SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let filter = GrantFilter { subject: Some(principal.subject.clone()), ..Default::default() };
+
+    match state.consents.grants(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "grants": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn revoke_own_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let subject = principal.subject.clone();
+    Ok(revoke_grant(&state, &principal, &subject, path.into_inner()).await)
+}
+
+pub async fn create_client_handler(
+    req: HttpRequest,
+    request: web::Json<CreateClientRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.consents.create_client(&principal, request.into_inner()).await {
+        Ok(client) => {
+            audit(&state, &principal, "auth.oauth_client.create", format!("oauth_client:{}", client.id), serde_json::json!({
+                "name": client.name,
+                "scopes": client.scopes,
+                "redirect_uris": client.redirect_uris
+            })).await;
+            Ok(HttpResponse::Created().json(client))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_clients_handler(
+    req: HttpRequest,
+    filter: web::Query<ClientFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let page = match page.resolve(CLIENT_SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.consents.list_clients(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "clients": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_client_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.consents.get_client(path.into_inner()).await {
+        Ok(client) => Ok(HttpResponse::Ok().json(client)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn update_client_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<UpdateClientRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let request = request.into_inner();
+    let changes = serde_json::json!({
+        "description": request.description,
+        "redirect_uris": request.redirect_uris,
+        "scopes": request.scopes,
+        "status": request.status
+    });
+    match state.consents.update_client(path.into_inner(), request).await {
+        Ok(client) => {
+            audit(&state, &principal, "auth.oauth_client.update", format!("oauth_client:{}", client.id), changes).await;
+            Ok(HttpResponse::Ok().json(client))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_grants_handler(
+    req: HttpRequest,
+    filter: web::Query<GrantFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let page = match page.resolve(GRANT_SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.consents.grants(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "grants": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn revoke_grant_handler(
+    req: HttpRequest,
+    path: web::Path<(Uuid, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let (client_id, subject) = path.into_inner();
+    Ok(revoke_grant(&state, &principal, &subject, client_id).await)
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/oauth-clients")
+            .route("", web::post().to(create_client_handler))
+            .route("", web::get().to(list_clients_handler))
+            .route("/{id}", web::get().to(get_client_handler))
+            .route("/{id}", web::patch().to(update_client_handler))
+    )
+    .service(
+        web::scope("/admin/consents")
+            .route("", web::get().to(list_grants_handler))
+            .route("/{client_id}/{subject}", web::delete().to(revoke_grant_handler))
+    );
+}
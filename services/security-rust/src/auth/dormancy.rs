@@ -0,0 +1,613 @@
+/*!
+Dormancy
+Stale API keys, dormant accounts and long-idle sessions, found and acted on
+
+Every `DORMANCY_INTERVAL_SECS` a pass looks for:
+
+- `api_key`: active API keys unused (or, never used, created) more than
+  `DORMANCY_API_KEY_DAYS` ago
+- `user`: subjects whose last login, recorded when a session starts, is
+  more than `DORMANCY_USER_DAYS` old
+- `session`: open sessions not refreshed for `DORMANCY_SESSION_IDLE_DAYS`,
+  for deployments whose `AUTH_SESSION_IDLE_TIMEOUT_SECS` is off or longer
+
+A kind whose days are 0 is not looked for. Each finding is recorded once
+per period of inactivity, published as `dormancy.detected` for the services
+owning the accounts, audited, and handled by the kind's action
+(`DORMANCY_<KIND>_ACTION`):
+
+| action    | `api_key`           | `user`                          | `session` |
+|-----------|---------------------|---------------------------------|-----------|
+| `notify`  | flagged             | flagged                         | flagged   |
+| `disable` | revoked             | sessions ended                  | ended     |
+| `delete`  | revoked and removed | sessions ended, login forgotten | ended     |
+
+User accounts themselves belong to the identity service, which disables or
+deletes them on the event. A pass that finds anything sends one alert
+listing it. Subjects in `DORMANCY_EXEMPT_SUBJECTS`, key ids in
+`DORMANCY_EXEMPT_API_KEYS` and targets exempted under
+`/admin/dormancy/exemptions/{kind}/{target}` are skipped, and so are an
+exempt user's sessions and the keys they created. Admins list findings at
+`GET /admin/dormancy/findings` and run a pass at once with
+`POST /admin/dormancy/run`.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::alerting::{Alert, Severity};
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, DormancyConfig};
+use crate::errors::SecurityError;
+use crate::events::{self, DomainEvent};
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::Storage;
+use crate::AppState;
+
+pub const SYSTEM_ACTOR: &str = "system:dormancy";
+
+pub const KINDS: &[&str] = &["api_key", "user", "session"];
+
+pub const ACTIONS: &[&str] = &["notify", "disable", "delete"];
+
+/// Candidates looked at per kind and pass.
+const PASS_BATCH: i64 = 500;
+
+/// Findings listed in a pass alert; the rest are counted.
+const ALERT_MAX_FINDINGS: usize = 50;
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "detected_at", column: "detected_at", kind: KeyKind::Timestamp },
+];
+
+const FINDING_COLUMNS: &str = "id, kind, target, subject, tenant_id, last_active_at, action, status, error, detected_at";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Finding {
+    pub id: Uuid,
+    pub kind: String,
+    /// The key or session id, or the subject for `user`.
+    pub target: String,
+    pub subject: Option<String>,
+    pub tenant_id: Option<String>,
+    pub last_active_at: DateTime<Utc>,
+    pub action: String,
+    /// `flagged`, `disabled`, `deleted` or `failed`.
+    pub status: String,
+    pub error: Option<String>,
+    pub detected_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Exemption {
+    pub kind: String,
+    pub target: String,
+    pub reason: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExemptionRequest {
+    pub reason: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FindingFilter {
+    pub kind: Option<String>,
+    pub status: Option<String>,
+    pub tenant_id: Option<String>,
+}
+
+/// Something found inactive, before it is recorded.
+#[derive(Debug, FromRow)]
+struct Candidate {
+    target: String,
+    subject: Option<String>,
+    tenant_id: Option<String>,
+    last_active_at: DateTime<Utc>,
+}
+
+pub struct DormancyService {
+    storage: Storage,
+    config: DormancyConfig,
+    clock: Arc<dyn Clock>,
+}
+
+impl DormancyService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        info!("Dormancy service initialized successfully");
+        Ok(Self {
+            storage,
+            config: config.dormancy.clone(),
+            clock,
+        })
+    }
+
+    /// Days and action configured for `kind`.
+    fn policy(&self, kind: &str) -> (i64, &str) {
+        match kind {
+            "api_key" => (self.config.api_key_days, self.config.api_key_action.as_str()),
+            "user" => (self.config.user_days, self.config.user_action.as_str()),
+            _ => (self.config.session_idle_days, self.config.session_action.as_str()),
+        }
+    }
+
+    async fn candidates(&self, kind: &str, cutoff: DateTime<Utc>) -> Result<Vec<Candidate>, SecurityError> {
+        let now = self.clock.now();
+        let candidates = match kind {
+            "api_key" => sqlx::query_as::<_, Candidate>(
+                "SELECT id::text AS target, created_by AS subject, tenant_id, \
+                 COALESCE(last_used_at, created_at) AS last_active_at FROM api_keys \
+                 WHERE status = 'active' AND expires_at > $3 AND COALESCE(last_used_at, created_at) < $1 \
+                 ORDER BY 4 LIMIT $2",
+            )
+            .bind(cutoff)
+            .bind(PASS_BATCH)
+            .bind(now),
+            "user" => sqlx::query_as::<_, Candidate>(
+                "SELECT subject AS target, subject, tenant_id, last_login_at AS last_active_at \
+                 FROM subject_logins WHERE last_login_at < $1 ORDER BY 4 LIMIT $2",
+            )
+            .bind(cutoff)
+            .bind(PASS_BATCH),
+            _ => sqlx::query_as::<_, Candidate>(
+                "SELECT id::text AS target, subject, tenant_id, last_seen_at AS last_active_at FROM sessions \
+                 WHERE ended_at IS NULL AND expires_at > $3 AND last_seen_at < $1 ORDER BY 4 LIMIT $2",
+            )
+            .bind(cutoff)
+            .bind(PASS_BATCH)
+            .bind(now),
+        };
+        Ok(candidates.fetch_all(self.storage.pool()).await?)
+    }
+
+    /// Exempted `(kind, target)` pairs, configured and unexpired.
+    async fn exempted(&self) -> Result<HashSet<(String, String)>, SecurityError> {
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            "SELECT kind, target FROM dormancy_exemptions WHERE expires_at IS NULL OR expires_at > $1",
+        )
+        .bind(self.clock.now())
+        .fetch_all(self.storage.pool())
+        .await?;
+        let mut exempted: HashSet<(String, String)> = rows.into_iter().collect();
+        for subject in &self.config.exempt_subjects {
+            exempted.insert(("user".to_string(), subject.clone()));
+        }
+        for id in &self.config.exempt_api_keys {
+            exempted.insert(("api_key".to_string(), id.clone()));
+        }
+        Ok(exempted)
+    }
+
+    fn is_exempt(&self, exempted: &HashSet<(String, String)>, kind: &str, candidate: &Candidate) -> bool {
+        let exempt = |kind: &str, target: &str| exempted.contains(&(kind.to_string(), target.to_string()));
+        // Exempt users keep their sessions and the keys they created
+        exempt(kind, &candidate.target)
+            || candidate.subject.as_deref().is_some_and(|subject| exempt("user", subject))
+    }
+
+    /// Record `candidate` and publish it, unless this period of inactivity
+    /// was already found.
+    async fn claim(&self, kind: &str, action: &str, candidate: &Candidate) -> Result<Option<Finding>, SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let finding = sqlx::query_as::<_, Finding>(&format!(
+            "INSERT INTO dormancy_findings (id, kind, target, subject, tenant_id, last_active_at, action, status, \
+             detected_at) VALUES ($1, $2, $3, $4, $5, $6, $7, 'flagged', $8) \
+             ON CONFLICT (kind, target, last_active_at) DO NOTHING RETURNING {}",
+            FINDING_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(kind)
+        .bind(&candidate.target)
+        .bind(&candidate.subject)
+        .bind(&candidate.tenant_id)
+        .bind(candidate.last_active_at)
+        .bind(action)
+        .bind(self.clock.now())
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(finding) = finding else {
+            return Ok(None);
+        };
+
+        let event = DomainEvent::new(
+            "dormancy.detected",
+            kind,
+            &finding.target,
+            finding.tenant_id.clone(),
+            serde_json::json!({
+                "finding_id": finding.id,
+                "subject": finding.subject,
+                "last_active_at": finding.last_active_at,
+                "action": finding.action
+            }),
+            self.clock.now(),
+        );
+        events::enqueue(&mut tx, &event).await?;
+        tx.commit().await?;
+        Ok(Some(finding))
+    }
+
+    async fn settle(&self, finding: &mut Finding, outcome: Result<&str, String>) -> Result<(), SecurityError> {
+        let (status, error) = match outcome {
+            Ok(status) => (status.to_string(), None),
+            Err(error) => ("failed".to_string(), Some(error)),
+        };
+        sqlx::query("UPDATE dormancy_findings SET status = $2, error = $3 WHERE id = $1")
+            .bind(finding.id)
+            .bind(&status)
+            .bind(&error)
+            .execute(self.storage.pool())
+            .await?;
+        finding.status = status;
+        finding.error = error;
+        Ok(())
+    }
+
+    /// Forget `subject`'s last login, so a deleted account is not found again.
+    async fn forget_login(&self, subject: &str) -> Result<(), SecurityError> {
+        sqlx::query("DELETE FROM subject_logins WHERE subject = $1")
+            .bind(subject)
+            .execute(self.storage.pool())
+            .await?;
+        Ok(())
+    }
+
+    /// One pass over every kind. Returns what was newly found.
+    pub async fn run_pass(&self, state: &AppState) -> Result<Vec<Finding>, SecurityError> {
+        let now = self.clock.now();
+        let exempted = self.exempted().await?;
+        let mut found = Vec::new();
+        for kind in KINDS {
+            let (days, action) = self.policy(kind);
+            if days == 0 {
+                continue;
+            }
+            for candidate in self.candidates(kind, now - Duration::days(days)).await? {
+                if self.is_exempt(&exempted, kind, &candidate) {
+                    continue;
+                }
+                let Some(mut finding) = self.claim(kind, action, &candidate).await? else {
+                    continue;
+                };
+                let outcome = act(state, &finding).await.map_err(|e| format!("{}", e));
+                if let Err(e) = &outcome {
+                    error!("Dormancy action {} on {} {} failed: {}", finding.action, kind, finding.target, e);
+                }
+                self.settle(&mut finding, outcome).await?;
+                audit_finding(state, &finding).await;
+                found.push(finding);
+            }
+        }
+        Ok(found)
+    }
+
+    pub async fn list(&self, filter: &FindingFilter, page: &PageRequest) -> Result<Page<Finding>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM dormancy_findings WHERE 1 = 1",
+            FINDING_COLUMNS
+        ));
+        if let Some(kind) = &filter.kind {
+            builder.push(" AND kind = ").push_bind(kind.clone());
+        }
+        if let Some(status) = &filter.status {
+            builder.push(" AND status = ").push_bind(status.clone());
+        }
+        if let Some(tenant_id) = &filter.tenant_id {
+            builder.push(" AND tenant_id = ").push_bind(tenant_id.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let findings = builder
+            .build_query_as::<Finding>()
+            .fetch_all(self.storage.pool())
+            .await?;
+        Ok(page.page(findings, |finding, _| (SortKey::Timestamp(finding.detected_at), finding.id)))
+    }
+
+    pub async fn exemptions(&self) -> Result<Vec<Exemption>, SecurityError> {
+        Ok(sqlx::query_as::<_, Exemption>(
+            "SELECT kind, target, reason, created_by, created_at, expires_at FROM dormancy_exemptions \
+             ORDER BY kind, target",
+        )
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    pub async fn exempt(
+        &self,
+        actor: &Principal,
+        kind: &str,
+        target: &str,
+        request: ExemptionRequest,
+    ) -> Result<Exemption, SecurityError> {
+        if !KINDS.contains(&kind) {
+            return Err(SecurityError::ValidationError(format!(
+                "Unknown kind {}; expected one of {}",
+                kind,
+                KINDS.join(", ")
+            )));
+        }
+        if request.reason.trim().is_empty() {
+            return Err(SecurityError::ValidationError("An exemption needs a reason".to_string()));
+        }
+        let now = self.clock.now();
+        if request.expires_at.is_some_and(|at| at <= now) {
+            return Err(SecurityError::ValidationError("expires_at must be in the future".to_string()));
+        }
+
+        Ok(sqlx::query_as::<_, Exemption>(
+            "INSERT INTO dormancy_exemptions (kind, target, reason, created_by, created_at, expires_at) \
+             VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (kind, target) DO UPDATE SET \
+             reason = EXCLUDED.reason, created_by = EXCLUDED.created_by, created_at = EXCLUDED.created_at, \
+             expires_at = EXCLUDED.expires_at \
+             RETURNING kind, target, reason, created_by, created_at, expires_at",
+        )
+        .bind(kind)
+        .bind(target)
+        .bind(request.reason.trim())
+        .bind(&actor.subject)
+        .bind(now)
+        .bind(request.expires_at)
+        .fetch_one(self.storage.pool())
+        .await?)
+    }
+
+    pub async fn unexempt(&self, kind: &str, target: &str) -> Result<(), SecurityError> {
+        let removed = sqlx::query("DELETE FROM dormancy_exemptions WHERE kind = $1 AND target = $2")
+            .bind(kind)
+            .bind(target)
+            .execute(self.storage.pool())
+            .await?
+            .rows_affected();
+        if removed == 0 {
+            return Err(SecurityError::NotFound(format!("No exemption for {} {}", kind, target)));
+        }
+        Ok(())
+    }
+}
+
+/// Carry out `finding`'s action, returning the status it ends in.
+async fn act(state: &AppState, finding: &Finding) -> Result<&'static str, SecurityError> {
+    let reason = "dormant";
+    match (finding.kind.as_str(), finding.action.as_str()) {
+        (_, "notify") => Ok("flagged"),
+        ("api_key", action) => {
+            let id = Uuid::try_parse(&finding.target)
+                .map_err(|_| SecurityError::ValidationError(format!("Bad API key id {}", finding.target)))?;
+            if action == "delete" {
+                state.api_keys.delete(SYSTEM_ACTOR, id).await?;
+                Ok("deleted")
+            } else {
+                state.api_keys.revoke(SYSTEM_ACTOR, id).await?;
+                Ok("disabled")
+            }
+        }
+        ("user", action) => {
+            // The account itself belongs to the identity service, which
+            // acts on `dormancy.detected`
+            state.tokens.end_sessions(&finding.target, None, reason, SYSTEM_ACTOR).await?;
+            if action == "delete" {
+                state.dormancy.forget_login(&finding.target).await?;
+                Ok("deleted")
+            } else {
+                Ok("disabled")
+            }
+        }
+        (_, action) => {
+            let id = Uuid::try_parse(&finding.target)
+                .map_err(|_| SecurityError::ValidationError(format!("Bad session id {}", finding.target)))?;
+            state.tokens.end_session(id, reason, SYSTEM_ACTOR).await?;
+            Ok(if action == "delete" { "deleted" } else { "disabled" })
+        }
+    }
+}
+
+async fn audit_finding(state: &AppState, finding: &Finding) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: finding.tenant_id.clone(),
+        actor: SYSTEM_ACTOR.to_string(),
+        actor_ip: None,
+        action: format!("dormancy.{}.{}", finding.kind, finding.action),
+        resource: format!("{}:{}", finding.kind, finding.target),
+        outcome: if finding.status == "failed" { "failure" } else { "success" }.to_string(),
+        payload: serde_json::json!({
+            "finding_id": finding.id,
+            "subject": finding.subject,
+            "last_active_at": finding.last_active_at,
+            "status": finding.status,
+            "error": finding.error
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit dormancy finding {}: {:?}", finding.id, e);
+    }
+}
+
+/// One alert for everything a pass found.
+async fn alert(state: &AppState, found: &[Finding]) {
+    if found.is_empty() {
+        return;
+    }
+    let failed = found.iter().filter(|finding| finding.status == "failed").count();
+    let severity = if failed > 0 { Severity::Medium } else { Severity::Low };
+    let title = format!("{} dormant credentials or accounts found", found.len());
+    let details = serde_json::json!({
+        "found": found.len(),
+        "failed": failed,
+        "findings": found.iter().take(ALERT_MAX_FINDINGS).collect::<Vec<_>>()
+    });
+    state.alerting_service.send(&Alert::new("dormancy", severity, title, details, state.clock.now()), &[]).await;
+}
+
+pub async fn run_scans(state: web::Data<AppState>) {
+    if !state.config.dormancy.enabled {
+        return;
+    }
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(state.config.dormancy.interval_secs));
+    loop {
+        interval.tick().await;
+        match state.dormancy.run_pass(&state).await {
+            Ok(found) => alert(&state, &found).await,
+            Err(e) => error!("Dormancy pass failed: {:?}", e),
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Dormancy operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Dormancy operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit_exemption(state: &AppState, req: &HttpRequest, principal: &Principal, action: &str, kind: &str, target: &str, payload: serde_json::Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: principal.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(req),
+        action: action.to_string(),
+        resource: format!("{}:{}", kind, target),
+        outcome: "success".to_string(),
+        payload,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} for {} {}: {:?}", action, kind, target, e);
+    }
+}
+
+pub async fn list_findings_handler(
+    req: HttpRequest,
+    filter: web::Query<FindingFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.dormancy.list(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "findings": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn run_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.dormancy.run_pass(&state).await {
+        Ok(found) => {
+            info!("{} ran a dormancy pass, {} found", principal.subject, found.len());
+            alert(&state, &found).await;
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "found": found
+            })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_exemptions_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.dormancy.exemptions().await {
+        Ok(exemptions) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "exemptions": exemptions,
+            "exempt_subjects": state.config.dormancy.exempt_subjects,
+            "exempt_api_keys": state.config.dormancy.exempt_api_keys
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn put_exemption_handler(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    request: web::Json<ExemptionRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let (kind, target) = path.into_inner();
+    match state.dormancy.exempt(&principal, &kind, &target, request.into_inner()).await {
+        Ok(exemption) => {
+            audit_exemption(&state, &req, &principal, "dormancy.exempt", &kind, &target, serde_json::json!({
+                "reason": exemption.reason,
+                "expires_at": exemption.expires_at
+            })).await;
+            Ok(HttpResponse::Ok().json(exemption))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn delete_exemption_handler(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let (kind, target) = path.into_inner();
+    match state.dormancy.unexempt(&kind, &target).await {
+        Ok(()) => {
+            audit_exemption(&state, &req, &principal, "dormancy.unexempt", &kind, &target, serde_json::json!({})).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/dormancy")
+            .route("/findings", web::get().to(list_findings_handler))
+            .route("/run", web::post().to(run_handler))
+            .route("/exemptions", web::get().to(list_exemptions_handler))
+            .route("/exemptions/{kind}/{target}", web::put().to(put_exemption_handler))
+            .route("/exemptions/{kind}/{target}", web::delete().to(delete_exemption_handler)),
+    );
+}
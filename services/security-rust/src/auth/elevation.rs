@@ -0,0 +1,765 @@
+/*!
+Just-in-Time Elevation
+Privileged roles held for a stated purpose and a bounded time
+
+`ELEVATION_ROLES` names the roles handed out this way and how a request
+for each is decided, e.g. `security_admin=auto,super_admin=manual`. A
+caller holding `eligible:<role>` asks for one:
+
+- `POST /auth/elevations` with `{role, duration_secs, justification}`;
+  `auto` roles are granted at once, `manual` ones wait for a holder of
+  `ELEVATION_APPROVER_ROLES` other than the requester to approve or deny
+  them under `/admin/elevations/{id}`. Requests not decided within
+  `ELEVATION_PENDING_TTL_SECS` lapse. Neither a request nor an approval
+  may leave the caller holding roles separation of duties forbids
+  together (see `sod`).
+- `GET /auth/elevations` lists the caller's own requests and
+  `DELETE /auth/elevations/{id}` withdraws one or gives a grant up early.
+
+A grant lasts `duration_secs`, at most `ELEVATION_MAX_DURATION_SECS`
+(`ELEVATION_DEFAULT_DURATION_SECS` when not given). While it lasts the role
+is added to the caller's principal at authentication, whatever token they
+present, for the tenant they asked from; it stops at expiry without
+anything having to run. With `ELEVATION_STRIP_STANDING` the roles are also
+taken out of tokens that carry them, so they are held only by grant;
+break-glass principals (see `break_glass`) and `ELEVATION_EXEMPT_SUBJECTS`
+keep theirs. Grants and revocations take effect at once on the replica
+that made them and within `AUTH_REVOCATION_REFRESH_SECS` elsewhere.
+
+Approvers revoke grants with `POST /admin/elevations/{id}/revoke`, and
+`GET /admin/elevations/report` summarises how often each user elevated, to
+which roles and for how long, between `since` and `until` or over a local
+`period` (`day`, `week` or `month`) holding `date`, today by default, in
+the tenant's time zone (see `timezone`). Every request, decision, revocation and
+expiry is audited.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::NewAuditEvent;
+use crate::auth::break_glass;
+use crate::auth::sessions::SYSTEM_ACTOR;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, ElevationConfig};
+use crate::errors::SecurityError;
+use crate::jobs::{self, Output};
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::Storage;
+use crate::timezone::{self, Period};
+use crate::AppState;
+
+/// Role prefix making its holder eligible to request the rest.
+pub const ELIGIBLE_PREFIX: &str = "eligible:";
+
+pub const POLICIES: &[&str] = &["auto", "manual"];
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "requested_at", column: "requested_at", kind: KeyKind::Timestamp },
+];
+
+const ELEVATION_COLUMNS: &str = "id, subject, tenant_id, role, justification, duration_secs, status, decision, \
+    decided_by, decided_at, decision_reason, requested_at, starts_at, expires_at, ended_at, ended_by";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Elevation {
+    pub id: Uuid,
+    pub subject: String,
+    pub tenant_id: Option<String>,
+    pub role: String,
+    pub justification: String,
+    pub duration_secs: i64,
+    /// `pending`, `active`, `denied`, `lapsed`, `cancelled`, `revoked` or
+    /// `expired`.
+    pub status: String,
+    /// `auto` or `manual`, once decided.
+    pub decision: Option<String>,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub decision_reason: Option<String>,
+    pub requested_at: DateTime<Utc>,
+    pub starts_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub ended_by: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ElevationRequest {
+    pub role: String,
+    pub duration_secs: Option<i64>,
+    pub justification: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DecisionRequest {
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ElevationFilter {
+    pub status: Option<String>,
+    pub subject: Option<String>,
+    pub role: Option<String>,
+    pub tenant_id: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReportQuery {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub tenant_id: Option<String>,
+    /// Sets `since` and `until` to this local period.
+    pub period: Option<Period>,
+    pub date: Option<NaiveDate>,
+}
+
+/// Elevations of one user over a report's period.
+#[derive(Debug, Serialize, FromRow)]
+pub struct ElevationSummary {
+    pub subject: String,
+    pub requests: i64,
+    pub granted: i64,
+    pub denied: i64,
+    pub revoked: i64,
+    pub roles: Vec<String>,
+    /// Time spent elevated, in seconds.
+    pub elevated_secs: i64,
+    pub last_requested_at: DateTime<Utc>,
+}
+
+/// A grant as authentication needs it.
+#[derive(Debug, Clone, FromRow)]
+struct Grant {
+    id: Uuid,
+    subject: String,
+    tenant_id: Option<String>,
+    role: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Active grants, applied on every authentication.
+pub struct ElevationGrants {
+    /// Roles held only by grant when standing ones are stripped.
+    stripped: Vec<String>,
+    exempt: Vec<String>,
+    grants: RwLock<HashMap<String, Vec<Grant>>>,
+}
+
+impl ElevationGrants {
+    pub fn new(config: &ElevationConfig) -> Self {
+        let stripped = if config.strip_standing {
+            config.policies.iter().map(|(role, _)| role.clone()).collect()
+        } else {
+            Vec::new()
+        };
+        Self {
+            stripped,
+            exempt: config.exempt_subjects.clone(),
+            grants: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Take stripped roles out of `principal` and add those it was granted.
+    pub fn apply(&self, principal: &mut Principal, now: DateTime<Utc>) {
+        let exempt = break_glass::is_break_glass(&principal.subject) || self.exempt.contains(&principal.subject);
+        if !exempt && !self.stripped.is_empty() {
+            principal.roles.retain(|role| !self.stripped.contains(role));
+        }
+        let grants = self.grants.read().unwrap_or_else(|e| e.into_inner());
+        for grant in grants.get(&principal.subject).into_iter().flatten() {
+            let applies = grant.expires_at > now && (grant.tenant_id.is_none() || grant.tenant_id == principal.tenant_id);
+            if applies && !principal.roles.contains(&grant.role) {
+                principal.roles.push(grant.role.clone());
+            }
+        }
+    }
+
+    /// Roles `subject` holds by grant in `tenant_id`.
+    pub fn granted(&self, subject: &str, tenant_id: Option<&str>, now: DateTime<Utc>) -> Vec<String> {
+        let grants = self.grants.read().unwrap_or_else(|e| e.into_inner());
+        grants.get(subject).into_iter().flatten()
+            .filter(|grant| grant.expires_at > now && (grant.tenant_id.is_none() || grant.tenant_id.as_deref() == tenant_id))
+            .map(|grant| grant.role.clone())
+            .collect()
+    }
+
+    fn insert(&self, grant: Grant) {
+        self.grants.write().unwrap_or_else(|e| e.into_inner())
+            .entry(grant.subject.clone())
+            .or_default()
+            .push(grant);
+    }
+
+    fn remove(&self, subject: &str, id: Uuid) {
+        if let Some(grants) = self.grants.write().unwrap_or_else(|e| e.into_inner()).get_mut(subject) {
+            grants.retain(|grant| grant.id != id);
+        }
+    }
+
+    fn replace(&self, active: Vec<Grant>) {
+        let mut grants: HashMap<String, Vec<Grant>> = HashMap::new();
+        for grant in active {
+            grants.entry(grant.subject.clone()).or_default().push(grant);
+        }
+        *self.grants.write().unwrap_or_else(|e| e.into_inner()) = grants;
+    }
+}
+
+pub struct ElevationService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: ElevationConfig,
+    grants: Arc<ElevationGrants>,
+}
+
+impl ElevationService {
+    pub async fn new(
+        config: &Config,
+        storage: Storage,
+        clock: Arc<dyn Clock>,
+        grants: Arc<ElevationGrants>,
+    ) -> Result<Self, SecurityError> {
+        info!("Elevation service initialized with {} elevatable roles", config.elevation.policies.len());
+        Ok(Self {
+            storage,
+            clock,
+            config: config.elevation.clone(),
+            grants,
+        })
+    }
+
+    fn policy(&self, role: &str) -> Option<&str> {
+        self.config.policies.iter().find(|(name, _)| name == role).map(|(_, policy)| policy.as_str())
+    }
+
+    fn grant_of(elevation: &Elevation) -> Option<Grant> {
+        Some(Grant {
+            id: elevation.id,
+            subject: elevation.subject.clone(),
+            tenant_id: elevation.tenant_id.clone(),
+            role: elevation.role.clone(),
+            expires_at: elevation.expires_at?,
+        })
+    }
+
+    /// Ask for `request.role`, which must not break separation of duties
+    /// with the roles `principal` already holds.
+    pub async fn request(
+        &self,
+        state: &AppState,
+        principal: &Principal,
+        request: &ElevationRequest,
+    ) -> Result<Elevation, SecurityError> {
+        let role = request.role.trim();
+        let Some(policy) = self.policy(role) else {
+            return Err(SecurityError::ValidationError(format!("{} cannot be requested", role)));
+        };
+        if !principal.roles.iter().any(|r| r.strip_prefix(ELIGIBLE_PREFIX) == Some(role)) {
+            return Err(SecurityError::AccessDenied(format!("{} is not eligible for {}", principal.subject, role)));
+        }
+        let adding = [role.to_string()];
+        state.sod.check_assignment(state, principal.tenant_id.as_deref(), &principal.subject, &principal.roles, &adding).await?;
+        let justification = request.justification.trim();
+        if justification.is_empty() || justification.len() > 1000 {
+            return Err(SecurityError::ValidationError("justification must be 1-1000 characters".to_string()));
+        }
+        let duration_secs = request.duration_secs.unwrap_or(self.config.default_duration_secs);
+        if !(1..=self.config.max_duration_secs).contains(&duration_secs) {
+            return Err(SecurityError::ValidationError(format!(
+                "duration_secs must be 1-{}",
+                self.config.max_duration_secs
+            )));
+        }
+
+        let now = self.clock.now();
+        let auto = policy == "auto";
+        let (status, decision, starts_at, expires_at) = if auto {
+            ("active", Some("auto"), Some(now), Some(now + Duration::seconds(duration_secs)))
+        } else {
+            ("pending", None, None, None)
+        };
+        // One open request or grant per role
+        let elevation = sqlx::query_as::<_, Elevation>(&format!(
+            "INSERT INTO elevations (id, subject, tenant_id, role, justification, duration_secs, status, decision, \
+             decided_by, decided_at, requested_at, starts_at, expires_at) \
+             SELECT $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13 \
+             WHERE NOT EXISTS (SELECT 1 FROM elevations WHERE subject = $2 AND role = $4 \
+             AND (status = 'pending' OR (status = 'active' AND expires_at > $11))) \
+             RETURNING {}",
+            ELEVATION_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(&principal.subject)
+        .bind(&principal.tenant_id)
+        .bind(role)
+        .bind(justification)
+        .bind(duration_secs)
+        .bind(status)
+        .bind(decision)
+        .bind(auto.then_some(SYSTEM_ACTOR))
+        .bind(auto.then_some(now))
+        .bind(now)
+        .bind(starts_at)
+        .bind(expires_at)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::Conflict(format!("A request for {} is already open", role)))?;
+
+        if let Some(grant) = Self::grant_of(&elevation).filter(|_| auto) {
+            self.grants.insert(grant);
+        }
+        Ok(elevation)
+    }
+
+    pub async fn list(&self, filter: &ElevationFilter, page: &PageRequest) -> Result<Page<Elevation>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM elevations WHERE 1 = 1",
+            ELEVATION_COLUMNS
+        ));
+        if let Some(status) = &filter.status {
+            builder.push(" AND status = ").push_bind(status.clone());
+        }
+        if let Some(subject) = &filter.subject {
+            builder.push(" AND subject = ").push_bind(subject.clone());
+        }
+        if let Some(role) = &filter.role {
+            builder.push(" AND role = ").push_bind(role.clone());
+        }
+        if let Some(tenant_id) = &filter.tenant_id {
+            builder.push(" AND tenant_id = ").push_bind(tenant_id.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let elevations = builder
+            .build_query_as::<Elevation>()
+            .fetch_all(self.storage.pool())
+            .await?;
+        Ok(page.page(elevations, |elevation, _| (SortKey::Timestamp(elevation.requested_at), elevation.id)))
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<Elevation, SecurityError> {
+        sqlx::query_as::<_, Elevation>(&format!("SELECT {} FROM elevations WHERE id = $1", ELEVATION_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound(format!("Elevation {} not found", id)))
+    }
+
+    /// Approve or deny a pending request. Requesters cannot decide their own,
+    /// and approvals cannot break separation of duties with the subject's
+    /// other grants.
+    pub async fn decide(
+        &self,
+        state: &AppState,
+        id: Uuid,
+        approver: &str,
+        approve: bool,
+        reason: Option<&str>,
+    ) -> Result<Elevation, SecurityError> {
+        let pending = self.get(id).await?;
+        if pending.subject == approver {
+            return Err(SecurityError::AccessDenied("Requests cannot be decided by their requester".to_string()));
+        }
+        if approve {
+            let granted = self.grants.granted(&pending.subject, pending.tenant_id.as_deref(), self.clock.now());
+            let role = [pending.role.clone()];
+            state.sod.check_assignment(state, pending.tenant_id.as_deref(), &pending.subject, &granted, &role).await?;
+        }
+        let now = self.clock.now();
+        let elevation = sqlx::query_as::<_, Elevation>(&format!(
+            "UPDATE elevations SET status = $2, decision = 'manual', decided_by = $3, decided_at = $4, \
+             decision_reason = $5, starts_at = CASE WHEN $6 THEN $4 END, \
+             expires_at = CASE WHEN $6 THEN $4 + duration_secs * INTERVAL '1 second' END \
+             WHERE id = $1 AND status = 'pending' RETURNING {}",
+            ELEVATION_COLUMNS
+        ))
+        .bind(id)
+        .bind(if approve { "active" } else { "denied" })
+        .bind(approver)
+        .bind(now)
+        .bind(reason.map(str::trim).filter(|r| !r.is_empty()))
+        .bind(approve)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::Conflict(format!("Elevation is {}", pending.status)))?;
+
+        if let Some(grant) = Self::grant_of(&elevation).filter(|_| approve) {
+            self.grants.insert(grant);
+        }
+        Ok(elevation)
+    }
+
+    /// Withdraw a pending request or end a grant early.
+    pub async fn end(&self, id: Uuid, actor: &str, status: &str) -> Result<Elevation, SecurityError> {
+        let elevation = sqlx::query_as::<_, Elevation>(&format!(
+            "UPDATE elevations SET status = CASE WHEN status = 'pending' THEN 'cancelled' ELSE $2 END, \
+             ended_at = $3, ended_by = $4 \
+             WHERE id = $1 AND (status = 'pending' OR (status = 'active' AND expires_at > $3)) RETURNING {}",
+            ELEVATION_COLUMNS
+        ))
+        .bind(id)
+        .bind(status)
+        .bind(self.clock.now())
+        .bind(actor)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::Conflict("Elevation is neither pending nor active".to_string()))?;
+        self.grants.remove(&elevation.subject, elevation.id);
+        Ok(elevation)
+    }
+
+    /// Close grants past their expiry and requests left undecided, then
+    /// reload the active grants. Returns what was closed.
+    pub async fn refresh(&self) -> Result<Vec<Elevation>, SecurityError> {
+        let now = self.clock.now();
+        let mut closed = sqlx::query_as::<_, Elevation>(&format!(
+            "UPDATE elevations SET status = 'expired', ended_at = expires_at, ended_by = $2 \
+             WHERE status = 'active' AND expires_at <= $1 RETURNING {}",
+            ELEVATION_COLUMNS
+        ))
+        .bind(now)
+        .bind(SYSTEM_ACTOR)
+        .fetch_all(self.storage.pool())
+        .await?;
+        closed.extend(sqlx::query_as::<_, Elevation>(&format!(
+            "UPDATE elevations SET status = 'lapsed', ended_at = $1, ended_by = $3 \
+             WHERE status = 'pending' AND requested_at <= $2 RETURNING {}",
+            ELEVATION_COLUMNS
+        ))
+        .bind(now)
+        .bind(now - Duration::seconds(self.config.pending_ttl_secs))
+        .bind(SYSTEM_ACTOR)
+        .fetch_all(self.storage.pool())
+        .await?);
+
+        let active = sqlx::query_as::<_, Grant>(
+            "SELECT id, subject, tenant_id, role, expires_at FROM elevations WHERE status = 'active' AND expires_at > $1",
+        )
+        .bind(now)
+        .fetch_all(self.storage.pool())
+        .await?;
+        self.grants.replace(active);
+        Ok(closed)
+    }
+
+    /// Elevation frequency and time elevated per user, most frequent first.
+    pub async fn report(&self, query: &ReportQuery) -> Result<Vec<ElevationSummary>, SecurityError> {
+        let now = self.clock.now();
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "SELECT subject, COUNT(*) AS requests, \
+             COUNT(*) FILTER (WHERE starts_at IS NOT NULL) AS granted, \
+             COUNT(*) FILTER (WHERE status = 'denied') AS denied, \
+             COUNT(*) FILTER (WHERE status = 'revoked') AS revoked, \
+             ARRAY_AGG(DISTINCT role) AS roles, \
+             COALESCE(SUM(EXTRACT(EPOCH FROM (LEAST(COALESCE(ended_at, expires_at), ",
+        );
+        builder.push_bind(now);
+        builder.push(") - starts_at))) FILTER (WHERE starts_at IS NOT NULL), 0)::BIGINT AS elevated_secs, \
+             MAX(requested_at) AS last_requested_at FROM elevations WHERE 1 = 1");
+        if let Some(since) = query.since {
+            builder.push(" AND requested_at >= ").push_bind(since);
+        }
+        if let Some(until) = query.until {
+            builder.push(" AND requested_at < ").push_bind(until);
+        }
+        if let Some(tenant_id) = &query.tenant_id {
+            builder.push(" AND tenant_id = ").push_bind(tenant_id.clone());
+        }
+        builder.push(" GROUP BY subject ORDER BY requests DESC, subject");
+
+        Ok(builder
+            .build_query_as::<ElevationSummary>()
+            .fetch_all(self.storage.pool())
+            .await?)
+    }
+}
+
+async fn audit(state: &AppState, actor: &str, actor_ip: Option<String>, action: &str, elevation: &Elevation) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: elevation.tenant_id.clone(),
+        actor: actor.to_string(),
+        actor_ip,
+        action: action.to_string(),
+        resource: format!("elevation:{}", elevation.id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "subject": elevation.subject,
+            "role": elevation.role,
+            "status": elevation.status,
+            "justification": elevation.justification,
+            "duration_secs": elevation.duration_secs,
+            "decision": elevation.decision,
+            "decision_reason": elevation.decision_reason,
+            "expires_at": elevation.expires_at
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} of elevation {}: {:?}", action, elevation.id, e);
+    }
+}
+
+/// Expire grants and keep them in step with other replicas.
+pub async fn run_refresh(state: web::Data<AppState>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        state.config.auth.revocation_refresh_secs,
+    ));
+    loop {
+        interval.tick().await;
+        match state.elevations.refresh().await {
+            Ok(closed) => {
+                state.startup.recovered("elevations");
+                for elevation in &closed {
+                    let action = if elevation.status == "expired" { "elevation.expire" } else { "elevation.lapse" };
+                    audit(&state, SYSTEM_ACTOR, None, action, elevation).await;
+                }
+            }
+            Err(e) => error!("Elevation refresh failed: {:?}", e),
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::AccessDenied(msg) => HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Elevation operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Elevation operation failed"
+            }))
+        }
+    }
+}
+
+/// Approvers, and admins for reading and revoking.
+fn authorize_reviewer(state: &AppState, req: &HttpRequest, admins_too: bool) -> Result<Principal, SecurityError> {
+    let principal = state.auth_service.authenticate(req)?;
+    let allowed = principal.has_any_role(&state.config.elevation.approver_roles)
+        || (admins_too && principal.has_any_role(&state.config.auth.admin_roles));
+    if !allowed {
+        return Err(SecurityError::AccessDenied(format!("{} lacks a required role", principal.subject)));
+    }
+    Ok(principal)
+}
+
+pub async fn request_handler(
+    req: HttpRequest,
+    request: web::Json<ElevationRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    match state.elevations.request(&state, &principal, &request).await {
+        Ok(elevation) => {
+            let action = if elevation.status == "active" { "elevation.grant" } else { "elevation.request" };
+            audit(&state, &principal.subject, client_ip(&req), action, &elevation).await;
+            Ok(HttpResponse::Created().json(elevation))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_own_handler(
+    req: HttpRequest,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let filter = ElevationFilter { subject: Some(principal.subject), ..ElevationFilter::default() };
+    match state.elevations.list(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "elevations": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn end_own_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let id = path.into_inner();
+    match state.elevations.get(id).await {
+        Ok(elevation) if elevation.subject == principal.subject => {}
+        Ok(_) => return Ok(error_response(SecurityError::NotFound(format!("Elevation {} not found", id)))),
+        Err(e) => return Ok(error_response(e)),
+    }
+    match state.elevations.end(id, &principal.subject, "cancelled").await {
+        Ok(elevation) => {
+            audit(&state, &principal.subject, client_ip(&req), "elevation.cancel", &elevation).await;
+            Ok(HttpResponse::Ok().json(elevation))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_handler(
+    req: HttpRequest,
+    filter: web::Query<ElevationFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = authorize_reviewer(&state, &req, true) {
+        return Ok(auth_error_response(&e));
+    }
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.elevations.list(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "elevations": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = authorize_reviewer(&state, &req, true) {
+        return Ok(auth_error_response(&e));
+    }
+    match state.elevations.get(path.into_inner()).await {
+        Ok(elevation) => Ok(HttpResponse::Ok().json(elevation)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+async fn decide(
+    req: HttpRequest,
+    id: Uuid,
+    request: DecisionRequest,
+    state: web::Data<AppState>,
+    approve: bool,
+) -> Result<HttpResponse> {
+    let principal = match authorize_reviewer(&state, &req, false) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    match state.elevations.decide(&state, id, &principal.subject, approve, request.reason.as_deref()).await {
+        Ok(elevation) => {
+            let action = if approve { "elevation.approve" } else { "elevation.deny" };
+            audit(&state, &principal.subject, client_ip(&req), action, &elevation).await;
+            Ok(HttpResponse::Ok().json(elevation))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn approve_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: Option<web::Json<DecisionRequest>>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.map(web::Json::into_inner).unwrap_or_default();
+    decide(req, path.into_inner(), request, state, true).await
+}
+
+pub async fn deny_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: Option<web::Json<DecisionRequest>>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.map(web::Json::into_inner).unwrap_or_default();
+    decide(req, path.into_inner(), request, state, false).await
+}
+
+pub async fn revoke_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match authorize_reviewer(&state, &req, true) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    match state.elevations.end(path.into_inner(), &principal.subject, "revoked").await {
+        Ok(elevation) => {
+            audit(&state, &principal.subject, client_ip(&req), "elevation.revoke", &elevation).await;
+            Ok(HttpResponse::Ok().json(elevation))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn report_handler(
+    req: HttpRequest,
+    query: web::Query<ReportQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_reviewer(&state, &req, true) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let mut query = query.into_inner();
+    let mut time_zone = None;
+    if let Some(period) = query.period {
+        let settings = state.tenant_settings.effective(query.tenant_id.as_deref()).await;
+        let zone = settings.zone();
+        let date = query.date.unwrap_or_else(|| timezone::local_date(zone, state.clock.now()));
+        let (since, until) = timezone::period_bounds(zone, period, date);
+        query.since = Some(since);
+        query.until = Some(until);
+        time_zone = Some(settings.time_zone);
+    }
+    let work_state = state.clone();
+    Ok(jobs::respond(&state, &req, &principal, "elevation.report", async move {
+        let users = work_state.elevations.report(&query).await?;
+        Ok(Output::json(serde_json::json!({
+            "since": query.since,
+            "until": query.until,
+            "period": query.period,
+            "time_zone": time_zone,
+            "users": users
+        })))
+    })
+    .await)
+}
+
+/// The `/auth/elevations` routes are registered by `auth::configure_routes`.
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/elevations")
+            .route("", web::get().to(list_handler))
+            .route("/report", web::get().to(report_handler))
+            .route("/{id}", web::get().to(get_handler))
+            .route("/{id}/approve", web::post().to(approve_handler))
+            .route("/{id}/deny", web::post().to(deny_handler))
+            .route("/{id}/revoke", web::post().to(revoke_handler))
+    );
+}
@@ -0,0 +1,305 @@
+/*!
+Token Exchange
+RFC 8693 delegation between services
+
+A service holding a user's access token trades it at `POST /auth/token`
+for a narrower token to call another service on the user's behalf:
+
+- `grant_type=urn:ietf:params:oauth:grant-type:token-exchange`
+- `subject_token`: the user's token, with `subject_token_type` set to
+  `urn:ietf:params:oauth:token-type:access_token` (or `...:jwt`)
+- `actor_token`: the calling service's own token, typed the same way
+- `audience`: the service the new token is for
+- `scope`, optional: what to delegate
+
+Which exchanges are allowed is set by the `token_exchange` policy (see
+`policies`), global or per tenant. The policies of the subject's tenant
+and the global one apply together:
+
+```json
+{"rules": [{"actor": "service_account:...", "audiences": ["billing"],
+            "scopes": ["invoices:read"], "max_depth": 1}]}
+```
+
+An exchange needs a rule naming the actor and the audience. The new token
+keeps the subject, tenant and non-admin roles. Its scopes are the requested
+ones, or if none are requested every subject scope the rules allow. It
+carries `aud` and an `act` claim putting the actor in front of the subject
+token's own chain. `max_depth` caps that chain, so a delegated token can
+only be exchanged onward where a rule allows the extra hop. The token
+expires no later than the subject token and comes without a refresh token.
+Both grants and refusals are audited with the chain.
+*/
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse};
+use cotai_verify::Actor;
+use serde::Deserialize;
+use tracing::{error, warn};
+
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::{self, oauth_error, IssueRequest, IssuedTokens, TokenRequest};
+use crate::auth::{client_ip, Principal};
+use crate::errors::SecurityError;
+use crate::AppState;
+
+pub const GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+pub const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+pub const JWT_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:jwt";
+
+/// Name of the policy document holding the exchange rules.
+pub const POLICY_NAME: &str = "token_exchange";
+
+/// Longest delegation chain a rule may allow.
+const MAX_DEPTH: usize = 5;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExchangePolicy {
+    pub rules: Vec<ExchangeRule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExchangeRule {
+    /// Subject of the actor token, e.g. `service_account:<id>`.
+    pub actor: String,
+    pub audiences: Vec<String>,
+    /// Scopes that may be delegated; none if empty.
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// Longest chain of actors the new token may carry.
+    #[serde(default = "default_max_depth")]
+    pub max_depth: usize,
+}
+
+fn default_max_depth() -> usize {
+    1
+}
+
+/// Check a `token_exchange` policy document before it is stored.
+pub fn validate_policy(document: &serde_json::Value) -> Result<(), SecurityError> {
+    let policy: ExchangePolicy = serde_json::from_value(document.clone())
+        .map_err(|e| SecurityError::ValidationError(format!("Invalid token exchange policy: {}", e)))?;
+
+    let mut problems = Vec::new();
+    for (i, rule) in policy.rules.iter().enumerate() {
+        if rule.actor.trim().is_empty() {
+            problems.push(format!("rule {}: actor is required", i + 1));
+        }
+        if rule.audiences.is_empty() || rule.audiences.iter().any(|a| a.trim().is_empty()) {
+            problems.push(format!("rule {}: audiences must be non-empty names", i + 1));
+        }
+        if rule.max_depth == 0 || rule.max_depth > MAX_DEPTH {
+            problems.push(format!("rule {}: max_depth must be 1-{}", i + 1, MAX_DEPTH));
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(SecurityError::ValidationError(problems.join("; ")));
+    }
+    Ok(())
+}
+
+/// Why an exchange was refused, as an RFC 8693 section 2.2.2 error.
+struct Refusal {
+    error: &'static str,
+    description: String,
+}
+
+fn refuse(error: &'static str, description: impl Into<String>) -> Refusal {
+    Refusal { error, description: description.into() }
+}
+
+struct Exchanged {
+    issued: IssuedTokens,
+    subject: Principal,
+    actor: Principal,
+    chain: Vec<String>,
+}
+
+/// Rules of the policies that apply to `tenant_id` for this actor and
+/// audience.
+async fn matching_rules(
+    state: &AppState,
+    tenant_id: Option<&str>,
+    actor: &str,
+    audience: &str,
+) -> Result<(bool, Vec<ExchangeRule>), SecurityError> {
+    let mut known_actor = false;
+    let mut rules = Vec::new();
+    for policy in state.policy_service.manifest(tenant_id).await? {
+        if policy.name != POLICY_NAME {
+            continue;
+        }
+        let document: ExchangePolicy = match serde_json::from_value(policy.document) {
+            Ok(document) => document,
+            Err(e) => {
+                warn!("Skipping unreadable token exchange policy {}: {}", policy.id, e);
+                continue;
+            }
+        };
+        for rule in document.rules.into_iter().filter(|rule| rule.actor == actor) {
+            known_actor = true;
+            if rule.audiences.iter().any(|a| a == audience) {
+                rules.push(rule);
+            }
+        }
+    }
+    Ok((known_actor, rules))
+}
+
+fn check_type(token_type: Option<&str>, param: &str) -> Result<(), Refusal> {
+    match token_type {
+        Some(ACCESS_TOKEN_TYPE) | Some(JWT_TOKEN_TYPE) => Ok(()),
+        Some(other) => Err(refuse("invalid_request", format!("Unsupported {} '{}'", param, other))),
+        None => Err(refuse("invalid_request", format!("{} is required", param))),
+    }
+}
+
+async fn exchange(state: &AppState, form: &TokenRequest) -> Result<Exchanged, Refusal> {
+    if let Some(requested) = form.requested_token_type.as_deref() {
+        if requested != ACCESS_TOKEN_TYPE {
+            return Err(refuse("invalid_request", "Only access tokens can be requested"));
+        }
+    }
+    let (Some(subject_token), Some(actor_token)) = (form.subject_token.as_deref(), form.actor_token.as_deref()) else {
+        return Err(refuse("invalid_request", "subject_token and actor_token are required"));
+    };
+    check_type(form.subject_token_type.as_deref(), "subject_token_type")?;
+    check_type(form.actor_token_type.as_deref(), "actor_token_type")?;
+    let audience = form.audience.as_deref()
+        .filter(|a| !a.trim().is_empty())
+        .ok_or_else(|| refuse("invalid_request", "audience is required"))?;
+
+    let actor = state.auth_service.verify_token(actor_token)
+        .map_err(|e| refuse("invalid_client", format!("Actor token rejected: {}", e)))?;
+    if !actor.delegation.is_empty() {
+        return Err(refuse("invalid_client", "The actor token must be the caller's own, not a delegated one"));
+    }
+    // Delegated tokens are meant for other audiences, so the subject token
+    // may name any; the policy decides whether it can go further
+    let subject = state.auth_service.verify_issued(subject_token)
+        .map_err(|e| refuse("invalid_grant", format!("Subject token rejected: {}", e)))?;
+    if actor.tenant_id.is_some() && actor.tenant_id != subject.tenant_id {
+        return Err(refuse("unauthorized_client", "The actor cannot act for another tenant's subject"));
+    }
+
+    let (known_actor, rules) = matching_rules(state, subject.tenant_id.as_deref(), &actor.subject, audience)
+        .await
+        .map_err(|e| {
+            error!("Failed to load token exchange policy: {:?}", e);
+            refuse("server_error", "Token exchange policy unavailable")
+        })?;
+    if !known_actor {
+        return Err(refuse("unauthorized_client", format!("{} may not exchange tokens", actor.subject)));
+    }
+    if rules.is_empty() {
+        return Err(refuse("invalid_target", format!("{} may not exchange tokens for {}", actor.subject, audience)));
+    }
+
+    let mut chain = vec![actor.subject.clone()];
+    chain.extend(subject.delegation.iter().cloned());
+    let max_depth = rules.iter().map(|rule| rule.max_depth).max().unwrap_or(1);
+    if chain.len() > max_depth {
+        return Err(refuse("invalid_grant", format!("Delegation chain of {} exceeds {}", chain.len(), max_depth)));
+    }
+
+    let delegable: Vec<String> = subject.scopes.iter()
+        .filter(|scope| rules.iter().any(|rule| rule.scopes.contains(scope)))
+        .cloned()
+        .collect();
+    let scopes = match form.scope.as_deref() {
+        Some(requested) => {
+            let requested: Vec<String> = requested.split_whitespace().map(String::from).collect();
+            if let Some(extra) = requested.iter().find(|scope| !delegable.contains(scope)) {
+                return Err(refuse("invalid_scope", format!("Scope '{}' cannot be delegated", extra)));
+            }
+            requested
+        }
+        None => delegable,
+    };
+
+    let claims = tokens::payload(subject_token).unwrap_or_default();
+    let not_after = claims.get("exp").and_then(|exp| exp.as_i64());
+    let prior: Option<Actor> = claims.get("act").and_then(|act| serde_json::from_value(act.clone()).ok());
+    let admin_roles = &state.config.auth.admin_roles;
+    let request = IssueRequest {
+        subject: subject.subject.clone(),
+        tenant_id: subject.tenant_id.clone(),
+        roles: subject.roles.iter().filter(|role| !admin_roles.contains(role)).cloned().collect(),
+        scopes,
+        refresh: false,
+        client_id: None,
+        audience: Some(audience.to_string()),
+        act: Some(Actor { sub: actor.subject.clone(), act: prior.map(Box::new) }),
+        not_after,
+        client_ip: None,
+        user_agent: None,
+    };
+
+    let mut issued = state.tokens.issue(state, &request).await.map_err(|e| {
+        error!("Failed to issue exchanged token for {}: {:?}", subject.subject, e);
+        refuse("server_error", "Token issuance failed")
+    })?;
+    issued.issued_token_type = Some(ACCESS_TOKEN_TYPE);
+    Ok(Exchanged { issued, subject, actor, chain })
+}
+
+/// The token exchange grant of `POST /auth/token`.
+pub(super) async fn token_exchange(req: &HttpRequest, form: TokenRequest, state: &AppState) -> HttpResponse {
+    let ip = client_ip(req);
+    match exchange(state, &form).await {
+        Ok(Exchanged { issued, subject, actor, chain }) => {
+            let jti = issued.claims.as_ref().map(|claims| claims.jti.clone()).unwrap_or_default();
+            let recorded = state.audit_service.record(NewAuditEvent {
+                tenant_id: subject.tenant_id.clone(),
+                actor: actor.subject.clone(),
+                actor_ip: ip,
+                action: "auth.token.exchange".to_string(),
+                resource: format!("token:{}", jti),
+                outcome: "success".to_string(),
+                payload: serde_json::json!({
+                    "subject": subject.subject,
+                    "subject_token_id": subject.token_id,
+                    "audience": form.audience,
+                    "scope": issued.scope,
+                    "delegation": chain
+                }),
+            }).await;
+            if let Err(e) = recorded {
+                warn!("Failed to audit token exchange by {}: {:?}", actor.subject, e);
+            }
+
+            HttpResponse::Ok()
+                .insert_header(("Cache-Control", "no-store"))
+                .json(issued)
+        }
+        Err(refusal) => {
+            warn!("Token exchange from {:?} refused: {}", ip, refusal.description);
+            let recorded = state.audit_service.record(NewAuditEvent {
+                tenant_id: None,
+                actor: form.client_id.clone().unwrap_or_else(|| "unknown".to_string()),
+                actor_ip: ip,
+                action: "auth.token.exchange".to_string(),
+                resource: "token".to_string(),
+                outcome: "failure".to_string(),
+                payload: serde_json::json!({
+                    "error": refusal.error,
+                    "reason": refusal.description,
+                    "audience": form.audience
+                }),
+            }).await;
+            if let Err(e) = recorded {
+                warn!("Failed to audit refused token exchange: {:?}", e);
+            }
+
+            let status = match refusal.error {
+                "invalid_client" => StatusCode::UNAUTHORIZED,
+                "server_error" => StatusCode::INTERNAL_SERVER_ERROR,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            oauth_error(status, refusal.error, &refusal.description)
+        }
+    }
+}
@@ -0,0 +1,91 @@
+/*!
+Access Labels
+Label policies a caller's token must satisfy, used to bind ciphertext to who may open it
+
+A policy admits a caller when every label of at least one clause holds:
+
+```json
+{"any_of": [["tenant:t1", "role:pregoeiro", "clearance:confidential"],
+            ["role:auditor", "clearance:secret"]]}
+```
+
+Labels read the caller's token only: `role:<role>`, `scope:<scope>`,
+`tenant:<tenant id>`, `subject:<subject>` and `clearance:<level>`. A
+caller holds a clearance through a `clearance:<level>` role, which also
+covers every level below it in `AUTH_CLEARANCE_LEVELS`. Admin roles count
+only as the roles they are.
+*/
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::SecurityError;
+use super::Principal;
+
+/// Limits keeping policies, which travel inside every envelope, small.
+const MAX_CLAUSES: usize = 16;
+const MAX_LABELS: usize = 16;
+
+const KINDS: &[&str] = &["role", "scope", "tenant", "subject", "clearance"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LabelPolicy {
+    pub any_of: Vec<Vec<String>>,
+}
+
+impl LabelPolicy {
+    /// Check the policy is well formed and put it in canonical form: labels
+    /// sorted and deduplicated within clauses, clauses sorted. Equivalent
+    /// policies then serialize identically.
+    pub fn normalize(mut self, levels: &[String]) -> Result<Self, SecurityError> {
+        let invalid = |msg: String| SecurityError::ValidationError(msg);
+        if self.any_of.is_empty() || self.any_of.len() > MAX_CLAUSES {
+            return Err(invalid(format!("labels need between 1 and {} clauses", MAX_CLAUSES)));
+        }
+        for clause in &mut self.any_of {
+            if clause.is_empty() || clause.len() > MAX_LABELS {
+                return Err(invalid(format!("each clause needs between 1 and {} labels", MAX_LABELS)));
+            }
+            for label in clause.iter() {
+                let (kind, value) = label.split_once(':')
+                    .filter(|(kind, value)| KINDS.contains(kind) && !value.is_empty())
+                    .ok_or_else(|| invalid(format!("'{}' is not a label; use one of {}:<value>", label, KINDS.join(", "))))?;
+                if kind == "clearance" && !levels.iter().any(|level| level == value) {
+                    return Err(invalid(format!("'{}' is not a clearance level", value)));
+                }
+            }
+            clause.sort();
+            clause.dedup();
+        }
+        self.any_of.sort();
+        self.any_of.dedup();
+        Ok(self)
+    }
+
+    pub fn admits(&self, principal: &Principal, levels: &[String]) -> bool {
+        let held = clearance(principal, levels);
+        self.any_of.iter().any(|clause| clause.iter().all(|label| holds(principal, label, held, levels)))
+    }
+}
+
+/// Rank of the highest clearance `principal` holds.
+fn clearance(principal: &Principal, levels: &[String]) -> Option<usize> {
+    principal.roles.iter()
+        .filter_map(|role| role.strip_prefix("clearance:"))
+        .filter_map(|level| levels.iter().position(|l| l == level))
+        .max()
+}
+
+fn holds(principal: &Principal, label: &str, held: Option<usize>, levels: &[String]) -> bool {
+    match label.split_once(':') {
+        Some(("role", role)) => principal.roles.iter().any(|r| r == role),
+        Some(("scope", scope)) => principal.scopes.iter().any(|s| s == scope),
+        Some(("tenant", tenant)) => principal.tenant_id.as_deref() == Some(tenant),
+        Some(("subject", subject)) => principal.subject == subject,
+        Some(("clearance", level)) => match (levels.iter().position(|l| l == level), held) {
+            (Some(needed), Some(held)) => held >= needed,
+            _ => false,
+        },
+        _ => false,
+    }
+}
@@ -0,0 +1,350 @@
+/*!
+Authentication Module
+Bearer token verification and caller identity resolution
+
+Two kinds of token are accepted. HMAC tokens under `SECRET_KEY` come from
+the platform's identity provider. Tokens issued here (see `tokens`, and
+`service_accounts` for machine callers) are signed with the service's
+asymmetric keys and checked against its own JWKS, reloaded on the key
+maintenance loop, and against the revocation list. Callers that cannot
+hold a token present an API key (see `api_keys`) in `X-API-Key` instead.
+Refresh tokens belong to server-side sessions (see `sessions`) that can be
+listed and revoked. Passwords themselves are the identity provider's, but
+their policy is checked here (see `password`). Sealed break-glass
+credentials open temporary superuser access when all else fails (see
+`break_glass`), and privileged roles can be held just in time rather than
+standing (see `elevation`). Who holds what is recertified periodically
+by the subjects' managers (see `access_reviews`). Ops access to hosts is by
+short-lived SSH certificates rather than standing keys (see `ssh_ca`).
+*/
+
+pub mod access_reviews;
+pub mod api_keys;
+pub mod break_glass;
+pub mod captcha;
+pub mod consent;
+pub mod dormancy;
+pub mod elevation;
+pub mod exchange;
+pub mod labels;
+pub mod otp;
+pub mod password;
+pub mod service_accounts;
+pub mod sessions;
+pub mod ssh_ca;
+pub mod tokens;
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use cotai_verify::{JwksCache, TokenVerifier};
+use jsonwebtoken::Algorithm;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::containment::Denylist;
+use crate::errors::SecurityError;
+use crate::health::{CheckFuture, Criticality, HealthRegistry};
+use crate::network;
+use crate::AppState;
+use api_keys::{ApiKeyRing, API_KEY_HEADER};
+use elevation::ElevationGrants;
+use labels::LabelPolicy;
+use tokens::RevocationList;
+
+/// Identity comes from the verification library gateways use,
+/// so the service and the edge can never disagree about a token.
+pub use cotai_verify::Principal;
+
+pub struct AuthService {
+    verifier: TokenVerifier,
+    /// Tokens this service issued, by the keys in `signing_keys`. One per
+    /// algorithm, since a verifier only accepts a single key family.
+    issued: HashMap<Algorithm, TokenVerifier>,
+    /// The same, but accepting tokens exchanged for other audiences.
+    issued_any_audience: HashMap<Algorithm, TokenVerifier>,
+    signing_keys: Arc<JwksCache>,
+    admin_roles: Vec<String>,
+    clearance_levels: Vec<String>,
+    clock: Arc<dyn Clock>,
+    denylist: Arc<Denylist>,
+    revocations: Arc<RevocationList>,
+    api_keys: Arc<ApiKeyRing>,
+    elevations: Arc<ElevationGrants>,
+}
+
+impl AuthService {
+    pub async fn new(
+        config: &Config,
+        clock: Arc<dyn Clock>,
+        denylist: Arc<Denylist>,
+        revocations: Arc<RevocationList>,
+        api_keys: Arc<ApiKeyRing>,
+        elevations: Arc<ElevationGrants>,
+        jwks: &serde_json::Value,
+    ) -> Result<Self, SecurityError> {
+        let verifier = TokenVerifier::with_secret(config.auth.jwt_secret.as_bytes(), &config.auth.jwt_algorithm)
+            .map_err(|e| SecurityError::ConfigError(e.to_string()))?;
+        let signing_keys = Arc::new(JwksCache::from_json(&jwks.to_string())
+            .map_err(|e| SecurityError::ConfigError(e.to_string()))?);
+        let issuer = Some(config.auth.token_issuer.as_str());
+        let mut issued = HashMap::new();
+        let mut issued_any_audience = HashMap::new();
+        for algorithm in [Algorithm::EdDSA, Algorithm::ES256] {
+            let verifier = || TokenVerifier::with_jwks(signing_keys.clone(), &[algorithm])
+                .map_err(|e| SecurityError::ConfigError(e.to_string()));
+            // Exchanged tokens name their audience; only ours are accepted here
+            issued.insert(algorithm, verifier()?.expect(issuer, issuer));
+            issued_any_audience.insert(algorithm, verifier()?.expect(issuer, None).any_audience());
+        }
+
+        info!("Auth service initialized successfully");
+        Ok(Self {
+            verifier,
+            issued,
+            issued_any_audience,
+            signing_keys,
+            admin_roles: config.auth.admin_roles.clone(),
+            clearance_levels: config.auth.clearance_levels.clone(),
+            clock,
+            denylist,
+            revocations,
+            api_keys,
+            elevations,
+        })
+    }
+
+    /// Replace the keys issued tokens are checked against.
+    pub fn load_signing_keys(&self, jwks: &serde_json::Value) -> Result<(), SecurityError> {
+        self.signing_keys.load_json(&jwks.to_string())
+            .map_err(|e| SecurityError::CryptoError(e.to_string()))?;
+        Ok(())
+    }
+
+    pub async fn is_ready(&self) -> bool {
+        true
+    }
+
+    pub fn verify_token(&self, token: &str) -> Result<Principal, SecurityError> {
+        self.verify(token, None)
+    }
+
+    /// Verify a token this service issued, whatever its audience, for
+    /// introspection and revocation.
+    pub fn verify_issued(&self, token: &str) -> Result<Principal, SecurityError> {
+        self.verify_with(&self.issued_any_audience, token, None)
+    }
+
+    fn verify(&self, token: &str, ip: Option<&str>) -> Result<Principal, SecurityError> {
+        self.verify_with(&self.issued, token, ip)
+    }
+
+    /// Verify the token with the verifier its algorithm calls for, then
+    /// reject revoked tokens and callers held by containment and settle
+    /// elevated roles (see `elevation`).
+    fn verify_with(
+        &self,
+        issued: &HashMap<Algorithm, TokenVerifier>,
+        token: &str,
+        ip: Option<&str>,
+    ) -> Result<Principal, SecurityError> {
+        let now = self.clock.now();
+        let header = jsonwebtoken::decode_header(token)
+            .map_err(|e| SecurityError::AuthError(e.to_string()))?;
+        let verifier = issued.get(&header.alg).unwrap_or(&self.verifier);
+        let mut principal = verifier
+            .verify_at(token, now.timestamp())
+            .map_err(|e| SecurityError::AuthError(e.to_string()))?;
+        if principal.token_id.as_deref().is_some_and(|jti| self.revocations.contains(jti)) {
+            return Err(SecurityError::AuthError("Token revoked".to_string()));
+        }
+        self.denylist.check(&principal, ip, now)?;
+        self.elevations.apply(&mut principal, now);
+        Ok(principal)
+    }
+
+    /// Reject a caller held by containment, for grants made without a
+    /// bearer token.
+    pub fn check_containment(&self, principal: &Principal, ip: Option<&str>) -> Result<(), SecurityError> {
+        self.denylist.check(principal, ip, self.clock.now())
+    }
+
+    pub fn authenticate(&self, req: &HttpRequest) -> Result<Principal, SecurityError> {
+        let api_key = match req.headers().get(API_KEY_HEADER) {
+            Some(key) => Some(key.to_str()
+                .map_err(|_| SecurityError::AuthError("Invalid API key".to_string()))?),
+            None => None,
+        };
+        let authorization = req.headers()
+            .get("Authorization")
+            .and_then(|h| h.to_str().ok());
+
+        self.authenticate_credentials(api_key, authorization, client_ip(req).as_deref())
+    }
+
+    /// `authenticate` for calls that are not HTTP requests, such as gRPC: an
+    /// API key if given, else the `Authorization` value.
+    pub fn authenticate_credentials(
+        &self,
+        api_key: Option<&str>,
+        authorization: Option<&str>,
+        ip: Option<&str>,
+    ) -> Result<Principal, SecurityError> {
+        if let Some(key) = api_key {
+            let now = self.clock.now();
+            let principal = self.api_keys.verify(key, now)?;
+            self.denylist.check(&principal, ip, now)?;
+            return Ok(principal);
+        }
+
+        let header = authorization
+            .ok_or_else(|| SecurityError::AuthError("Missing Authorization header".to_string()))?;
+
+        let token = header.strip_prefix("Bearer ")
+            .ok_or_else(|| SecurityError::AuthError("Expected bearer token".to_string()))?;
+
+        self.verify(token, ip)
+    }
+
+    /// Authenticate and require one of the given roles.
+    pub fn authorize_roles(&self, req: &HttpRequest, roles: &[String]) -> Result<Principal, SecurityError> {
+        let principal = self.authenticate(req)?;
+        if !principal.has_any_role(roles) {
+            return Err(SecurityError::AccessDenied(format!(
+                "{} lacks a required role",
+                principal.subject
+            )));
+        }
+        Ok(principal)
+    }
+
+    pub fn authorize_admin(&self, req: &HttpRequest) -> Result<Principal, SecurityError> {
+        self.authorize_roles(req, &self.admin_roles)
+    }
+
+    /// Authenticate and require `scope`; admins pass without it.
+    pub fn authorize_scope(&self, req: &HttpRequest, scope: &str) -> Result<Principal, SecurityError> {
+        let principal = self.authenticate(req)?;
+        if principal.scopes.iter().any(|s| s == scope) || principal.has_any_role(&self.admin_roles) {
+            Ok(principal)
+        } else {
+            Err(SecurityError::AccessDenied(format!("{} lacks scope {}", principal.subject, scope)))
+        }
+    }
+
+    /// Check a label policy against `principal`; admin roles confer no
+    /// exemption.
+    pub fn check_labels(&self, principal: &Principal, policy: &LabelPolicy) -> Result<(), SecurityError> {
+        if !policy.admits(principal, &self.clearance_levels) {
+            return Err(SecurityError::AccessDenied(format!(
+                "{} does not satisfy the access labels",
+                principal.subject
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn clearance_levels(&self) -> &[String] {
+        &self.clearance_levels
+    }
+}
+
+/// The caller's address behind the trusted proxies, without a port; see
+/// `network`.
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    let ip = match req.app_data::<web::Data<AppState>>() {
+        Some(state) => state.client_ips.resolve(req)?,
+        None => network::normalize(req.peer_addr()?.ip()),
+    };
+    Some(ip.to_string())
+}
+
+/// Map an authentication/authorization failure to the matching HTTP response.
+pub fn auth_error_response(e: &SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::AccessDenied(_) => HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Insufficient privileges"
+        })),
+        _ => HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Authentication required"
+        })),
+    }
+}
+
+// HTTP handlers
+
+pub async fn verify_handler(
+    req: HttpRequest,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    match state.auth_service.authenticate(&req) {
+        Ok(principal) => Ok(HttpResponse::Ok().json(principal)),
+        Err(e) => {
+            warn!("Token verification failed: {:?}", e);
+            Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Invalid token"
+            })))
+        }
+    }
+}
+
+fn verifier_ready(state: &crate::AppState) -> CheckFuture<'_> {
+    Box::pin(async move {
+        if state.auth_service.is_ready().await {
+            Ok(())
+        } else {
+            Err("Token verifier is not ready".to_string())
+        }
+    })
+}
+
+pub fn register_health_checks(registry: &mut HealthRegistry) {
+    registry.register("auth", Criticality::Critical, verifier_ready);
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/auth")
+            .route("/verify", web::get().to(verify_handler))
+            .route("/token", web::post().to(tokens::token_handler))
+            .route("/tokens", web::post().to(tokens::issue_handler))
+            .route("/revoke", web::post().to(tokens::revoke_handler))
+            .route("/introspect", web::post().to(tokens::introspect_handler))
+            .route("/consent", web::get().to(consent::screen_handler))
+            .route("/consent", web::post().to(consent::grant_handler))
+            .route("/consents", web::get().to(consent::list_own_handler))
+            .route("/consents/{client_id}", web::delete().to(consent::revoke_own_handler))
+            .route("/sessions", web::get().to(sessions::list_own_handler))
+            .route("/sessions", web::delete().to(sessions::revoke_all_own_handler))
+            .route("/sessions/{id}", web::delete().to(sessions::revoke_own_handler))
+            .route("/otp", web::post().to(otp::start_handler))
+            .route("/otp/{id}", web::get().to(otp::get_handler))
+            .route("/otp/{id}/verify", web::post().to(otp::verify_handler))
+            .route("/otp/{id}/resend", web::post().to(otp::resend_handler))
+            .route("/captcha/verify", web::post().to(captcha::verify_handler))
+            .route("/captcha/signals", web::get().to(captcha::signals_handler))
+            .route("/password/check", web::post().to(password::check_handler))
+            .route("/elevations", web::post().to(elevation::request_handler))
+            .route("/elevations", web::get().to(elevation::list_own_handler))
+            .route("/elevations/{id}", web::delete().to(elevation::end_own_handler))
+            .route("/break-glass/activate", web::post().to(break_glass::activate_handler))
+            .route("/break-glass/activations/{id}/verify", web::post().to(break_glass::verify_handler))
+            .route("/break-glass/activations/{id}/renew", web::post().to(break_glass::renew_handler))
+            .route("/break-glass/activations/{id}/end", web::post().to(break_glass::end_handler))
+            .route("/ssh/trust", web::get().to(ssh_ca::trust_handler))
+            .route("/ssh/certificates", web::post().to(ssh_ca::request_handler))
+            .route("/ssh/certificates/{id}/verify", web::post().to(ssh_ca::verify_handler))
+            .route("/access-reviews", web::get().to(access_reviews::assigned_handler))
+            .route("/access-reviews/{id}", web::post().to(access_reviews::decide_handler))
+    );
+    service_accounts::configure_routes(cfg);
+    api_keys::configure_routes(cfg);
+    consent::configure_routes(cfg);
+    sessions::configure_routes(cfg);
+    break_glass::configure_routes(cfg);
+    elevation::configure_routes(cfg);
+    access_reviews::configure_routes(cfg);
+    dormancy::configure_routes(cfg);
+    ssh_ca::configure_routes(cfg);
+}
@@ -0,0 +1,521 @@
+/*!
+One-Time Codes
+Codes sent by email, SMS or WhatsApp to confirm a login
+
+The platform's login flow, holding `AUTH_ISSUE_SCOPE`, drives a challenge:
+
+- `POST /auth/otp` with `{subject, tenant_id, channel, destination}` sends
+  a fresh `AUTH_OTP_LENGTH`-digit code through the channel's providers
+  (see `delivery`) and returns the challenge
+- `POST /auth/otp/{id}/verify` with `{code}` checks it
+- `GET /auth/otp/{id}` shows the challenge with the status of its latest
+  delivery
+- `POST /auth/otp/{id}/resend` with the same `destination` sends a new
+  code, trying providers that reported the earlier ones undelivered last
+
+A code is valid for `AUTH_OTP_TTL_SECS` and a challenge is locked after
+`AUTH_OTP_MAX_ATTEMPTS` wrong codes, counted across resends. Only hashes of
+the code and destination are stored. A rejected code comes back with the
+delivery status, so when the provider has reported the code undelivered
+the login flow can resend rather than wait. Starts, resends and checks are
+audited.
+
+The message is in the `locale` given with the start or resend, else the
+request's (see `i18n`).
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip};
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::delivery::{Channel, DeliveryService, Outgoing};
+use crate::errors::SecurityError;
+use crate::i18n::I18n;
+use crate::random::{self, RandomSource};
+use crate::storage::Storage;
+use crate::AppState;
+
+const CHALLENGE_COLUMNS: &str = "c.id, c.subject, c.tenant_id, c.channel, c.destination_hint, c.attempts, \
+    c.resends, c.expires_at, c.verified_at, c.created_at, m.provider, m.status AS delivery_status, \
+    m.error AS delivery_error";
+
+/// New codes a challenge may be sent after the first.
+const MAX_RESENDS: i32 = 3;
+
+/// How long expired challenges are kept for lookups.
+const RETENTION_DAYS: i64 = 1;
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Challenge {
+    pub id: Uuid,
+    pub subject: String,
+    pub tenant_id: Option<String>,
+    pub channel: String,
+    /// Masked destination, e.g. `j***@example.com` or `+*******4321`.
+    pub destination_hint: String,
+    pub attempts: i32,
+    pub resends: i32,
+    pub expires_at: DateTime<Utc>,
+    pub verified_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    /// Provider of the latest code.
+    pub provider: Option<String>,
+    /// `sending`, `sent`, `delivered` or `failed`, as last reported.
+    pub delivery_status: Option<String>,
+    pub delivery_error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartRequest {
+    pub subject: String,
+    pub tenant_id: Option<String>,
+    pub channel: Channel,
+    pub destination: String,
+    /// The user's locale, when the login flow knows it.
+    #[serde(default)]
+    pub locale: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResendRequest {
+    pub destination: String,
+    #[serde(default)]
+    pub locale: Option<String>,
+}
+
+/// Outcome of checking a code.
+pub enum Verification {
+    Verified(Challenge),
+    Rejected { reason: &'static str, challenge: Challenge },
+}
+
+fn sha256(value: &str) -> String {
+    hex::encode(digest::digest(&digest::SHA256, value.as_bytes()))
+}
+
+fn code_hash(id: Uuid, code: &str) -> String {
+    sha256(&format!("{}:{}", id, code))
+}
+
+/// Normalize a destination for its channel, rejecting malformed ones.
+pub(crate) fn normalize(channel: Channel, destination: &str) -> Result<String, SecurityError> {
+    let destination = destination.trim();
+    match channel {
+        Channel::Email => {
+            let valid = destination.split_once('@')
+                .is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.') && !domain.contains('@'));
+            if !valid {
+                return Err(SecurityError::ValidationError("destination must be an email address".to_string()));
+            }
+            Ok(destination.to_lowercase())
+        }
+        Channel::Sms | Channel::Whatsapp => {
+            let digits = destination.strip_prefix('+').unwrap_or_default();
+            if !(8..=15).contains(&digits.len()) || !digits.chars().all(|c| c.is_ascii_digit()) {
+                return Err(SecurityError::ValidationError("destination must be an E.164 phone number".to_string()));
+            }
+            Ok(destination.to_string())
+        }
+    }
+}
+
+pub(crate) fn hint(channel: Channel, destination: &str) -> String {
+    match channel {
+        Channel::Email => match destination.split_once('@') {
+            Some((local, domain)) => format!("{}***@{}", local.chars().next().unwrap_or('*'), domain),
+            None => "***".to_string(),
+        },
+        Channel::Sms | Channel::Whatsapp => {
+            let visible = destination.len().saturating_sub(4);
+            format!("+{}{}", "*".repeat(visible.saturating_sub(1)), &destination[visible..])
+        }
+    }
+}
+
+pub struct OtpService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    rng: Arc<dyn RandomSource>,
+    ttl: Duration,
+    length: u32,
+    max_attempts: i32,
+}
+
+impl OtpService {
+    pub async fn new(
+        config: &Config,
+        storage: Storage,
+        clock: Arc<dyn Clock>,
+        rng: Arc<dyn RandomSource>,
+    ) -> Result<Self, SecurityError> {
+        info!("OTP service initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            rng,
+            ttl: Duration::seconds(config.auth.otp_ttl_secs),
+            length: config.auth.otp_length,
+            max_attempts: config.auth.otp_max_attempts,
+        })
+    }
+
+    fn code(&self) -> Result<String, SecurityError> {
+        let mut bytes = [0u8; 8];
+        self.rng.fill(&mut bytes)?;
+        let code = u64::from_be_bytes(bytes) % 10u64.pow(self.length);
+        Ok(format!("{:0width$}", code, width = self.length as usize))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn deliver(
+        &self,
+        delivery: &DeliveryService,
+        i18n: &I18n,
+        locale: &str,
+        id: Uuid,
+        channel: Channel,
+        destination: &str,
+        tenant_id: Option<&str>,
+        avoid: &[String],
+    ) -> Result<(String, Uuid), SecurityError> {
+        let code = self.code()?;
+        let minutes = (self.ttl.num_seconds() + 59) / 60;
+        let body = i18n.text(locale, "otp.body", &[("code", &code), ("minutes", &minutes)]);
+        let subject = i18n.text(locale, "otp.subject", &[]);
+        let message = delivery.send(&Outgoing {
+            channel,
+            to: destination,
+            subject: &subject,
+            body: &body,
+            purpose: "otp",
+            reference: Some(id),
+            tenant_id,
+        }, avoid).await?;
+        Ok((code_hash(id, &code), message.id))
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<Challenge, SecurityError> {
+        sqlx::query_as::<_, Challenge>(&format!(
+            "SELECT {} FROM otp_challenges c LEFT JOIN delivery_messages m ON m.id = c.message_id WHERE c.id = $1",
+            CHALLENGE_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("OTP challenge {} not found", id)))
+    }
+
+    /// Send a code and open a challenge for it.
+    pub async fn start(
+        &self,
+        delivery: &DeliveryService,
+        i18n: &I18n,
+        locale: &str,
+        request: &StartRequest,
+        created_by: &str,
+    ) -> Result<Challenge, SecurityError> {
+        if request.subject.trim().is_empty() {
+            return Err(SecurityError::ValidationError("subject is required".to_string()));
+        }
+        let destination = normalize(request.channel, &request.destination)?;
+
+        sqlx::query("DELETE FROM otp_challenges WHERE expires_at < $1")
+            .bind(self.clock.now() - Duration::days(RETENTION_DAYS))
+            .execute(self.storage.pool())
+            .await?;
+
+        let id = random::uuid_v4(self.rng.as_ref())?;
+        let (code_hash, message_id) = self
+            .deliver(delivery, i18n, locale, id, request.channel, &destination, request.tenant_id.as_deref(), &[])
+            .await?;
+        let now = self.clock.now();
+        sqlx::query(
+            "INSERT INTO otp_challenges (id, subject, tenant_id, channel, destination_hash, destination_hint, \
+             code_hash, message_id, expires_at, created_by, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+        )
+        .bind(id)
+        .bind(&request.subject)
+        .bind(&request.tenant_id)
+        .bind(request.channel.as_str())
+        .bind(sha256(&destination))
+        .bind(hint(request.channel, &destination))
+        .bind(code_hash)
+        .bind(message_id)
+        .bind(now + self.ttl)
+        .bind(created_by)
+        .bind(now)
+        .execute(self.storage.pool())
+        .await?;
+
+        self.get(id).await
+    }
+
+    /// Check a code. Wrong codes count against the challenge; the last
+    /// allowed one locks it.
+    pub async fn verify(&self, id: Uuid, code: &str) -> Result<Verification, SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let (stored_hash, attempts, expires_at, verified_at): (String, i32, DateTime<Utc>, Option<DateTime<Utc>>) =
+            sqlx::query_as(
+                "SELECT code_hash, attempts, expires_at, verified_at FROM otp_challenges WHERE id = $1 FOR UPDATE",
+            )
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| SecurityError::NotFound(format!("OTP challenge {} not found", id)))?;
+
+        let now = self.clock.now();
+        let reason = if verified_at.is_some() {
+            Some("already_verified")
+        } else if attempts >= self.max_attempts {
+            Some("locked")
+        } else if expires_at <= now {
+            Some("expired")
+        } else if code_hash(id, code.trim()) != stored_hash {
+            sqlx::query("UPDATE otp_challenges SET attempts = attempts + 1 WHERE id = $1")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            Some("invalid_code")
+        } else {
+            sqlx::query("UPDATE otp_challenges SET verified_at = $2 WHERE id = $1")
+                .bind(id)
+                .bind(now)
+                .execute(&mut *tx)
+                .await?;
+            None
+        };
+        tx.commit().await?;
+
+        let challenge = self.get(id).await?;
+        Ok(match reason {
+            Some(reason) => Verification::Rejected { reason, challenge },
+            None => Verification::Verified(challenge),
+        })
+    }
+
+    /// Send a new code to the challenge's destination, preferring providers
+    /// other than those that failed to deliver earlier codes.
+    pub async fn resend(
+        &self,
+        delivery: &DeliveryService,
+        i18n: &I18n,
+        locale: &str,
+        id: Uuid,
+        destination: &str,
+    ) -> Result<Challenge, SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let (channel, destination_hash, tenant_id, attempts, resends, verified_at):
+            (String, String, Option<String>, i32, i32, Option<DateTime<Utc>>) = sqlx::query_as(
+                "SELECT channel, destination_hash, tenant_id, attempts, resends, verified_at \
+                 FROM otp_challenges WHERE id = $1 FOR UPDATE",
+            )
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| SecurityError::NotFound(format!("OTP challenge {} not found", id)))?;
+
+        let channel = Channel::parse(&channel)
+            .ok_or_else(|| SecurityError::StorageError(format!("Unknown channel '{}'", channel)))?;
+        let destination = normalize(channel, destination)?;
+        if sha256(&destination) != destination_hash {
+            return Err(SecurityError::ValidationError("destination does not match the challenge".to_string()));
+        }
+        if verified_at.is_some() {
+            return Err(SecurityError::Conflict("Challenge already verified".to_string()));
+        }
+        if attempts >= self.max_attempts {
+            return Err(SecurityError::Conflict("Challenge is locked".to_string()));
+        }
+        if resends >= MAX_RESENDS {
+            return Err(SecurityError::Conflict(format!("Challenge was already resent {} times", MAX_RESENDS)));
+        }
+
+        let failed: Vec<String> = sqlx::query_scalar(
+            "SELECT DISTINCT provider FROM delivery_messages WHERE reference = $1 AND status = 'failed'",
+        )
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await?;
+        let (code_hash, message_id) = self
+            .deliver(delivery, i18n, locale, id, channel, &destination, tenant_id.as_deref(), &failed)
+            .await?;
+
+        sqlx::query(
+            "UPDATE otp_challenges SET code_hash = $2, message_id = $3, expires_at = $4, resends = resends + 1 \
+             WHERE id = $1",
+        )
+        .bind(id)
+        .bind(code_hash)
+        .bind(message_id)
+        .bind(self.clock.now() + self.ttl)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.get(id).await
+    }
+
+    pub fn attempts_remaining(&self, challenge: &Challenge) -> i32 {
+        (self.max_attempts - challenge.attempts).max(0)
+    }
+}
+
+async fn audit(state: &AppState, req: &HttpRequest, actor: &str, action: &str, challenge: &Challenge, outcome: &str, detail: serde_json::Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: challenge.tenant_id.clone(),
+        actor: actor.to_string(),
+        actor_ip: client_ip(req),
+        action: action.to_string(),
+        resource: format!("otp_challenge:{}", challenge.id),
+        outcome: outcome.to_string(),
+        payload: serde_json::json!({
+            "subject": challenge.subject,
+            "channel": challenge.channel,
+            "provider": challenge.provider,
+            "detail": detail
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} for {}: {:?}", action, challenge.id, e);
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::DeliveryError(msg) => HttpResponse::BadGateway().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("OTP operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "OTP operation failed"
+            }))
+        }
+    }
+}
+
+pub async fn start_handler(
+    req: HttpRequest,
+    request: web::Json<StartRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.auth.issue_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let locale = match request.locale.as_deref().and_then(|l| state.i18n.supported(l)) {
+        Some(locale) => locale,
+        None => state.i18n.locale(&state, &req, request.tenant_id.as_deref()).await,
+    };
+    match state.otp.start(&state.delivery, &state.i18n, &locale, &request, &principal.subject).await {
+        Ok(challenge) => {
+            audit(&state, &req, &principal.subject, "auth.otp.start", &challenge, "success", serde_json::Value::Null).await;
+            Ok(HttpResponse::Created().json(challenge))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = authorize_scope(&state, &req, &state.config.auth.issue_scope) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.otp.get(path.into_inner()).await {
+        Ok(challenge) => Ok(HttpResponse::Ok().json(challenge)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn verify_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<VerifyRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.auth.issue_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.otp.verify(path.into_inner(), &request.code).await {
+        Ok(Verification::Verified(challenge)) => {
+            audit(&state, &req, &principal.subject, "auth.otp.verify", &challenge, "success", serde_json::Value::Null).await;
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "verified": true,
+                "challenge": challenge
+            })))
+        }
+        Ok(Verification::Rejected { reason, challenge }) => {
+            audit(&state, &req, &principal.subject, "auth.otp.verify", &challenge, "failure", serde_json::json!(reason)).await;
+            Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "verified": false,
+                "error": reason,
+                "attempts_remaining": state.otp.attempts_remaining(&challenge),
+                "delivery_status": challenge.delivery_status,
+                "challenge": challenge
+            })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn resend_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<ResendRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.auth.issue_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let id = path.into_inner();
+    let locale = match request.locale.as_deref().and_then(|l| state.i18n.supported(l)) {
+        Some(locale) => locale,
+        None => {
+            let tenant_id = state.otp.get(id).await.ok().and_then(|challenge| challenge.tenant_id);
+            state.i18n.locale(&state, &req, tenant_id.as_deref()).await
+        }
+    };
+    match state.otp.resend(&state.delivery, &state.i18n, &locale, id, &request.destination).await {
+        Ok(challenge) => {
+            audit(&state, &req, &principal.subject, "auth.otp.resend", &challenge, "success", serde_json::Value::Null).await;
+            Ok(HttpResponse::Ok().json(challenge))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
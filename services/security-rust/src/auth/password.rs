@@ -0,0 +1,595 @@
+/*!
+Password Policy
+Strength scoring, policy checks and breached-password screening
+
+`POST /auth/password/check` with `{password, user_inputs}` returns a
+strength report; it needs no authentication, so registration and
+password-change pages can call it as the user types. Flows that set a
+password call `enforce`, which refuses one the report does not accept.
+
+A password is acceptable when it:
+
+- is `PASSWORD_MIN_LENGTH` to `PASSWORD_MAX_LENGTH` characters long
+- mixes `PASSWORD_MIN_CHARACTER_CLASSES` of lowercase, uppercase, digits
+  and symbols
+- contains none of `PASSWORD_BANNED_WORDS` (and `PASSWORD_BANNED_WORDS_FILE`)
+  nor the user's own details from `user_inputs` (name, e-mail, CNPJ),
+  ignoring case and substitutions such as `@` for `a`
+- scores at least `PASSWORD_MIN_SCORE`
+- with `PASSWORD_BREACH_CHECK`, is not in the Have I Been Pwned corpus
+
+The score estimates guesses the way zxcvbn does: the password is split into
+dictionary words, repeats, sequences, keyboard rows and years, each costing
+few guesses, and the characters left over are brute-forced. Under 10^3
+guesses scores 0, then 10^6, 10^8 and 10^10 mark scores 1 to 4.
+
+The breach check sends only the first five hex digits of the password's
+SHA-1 to the k-anonymity range API, with response padding on, and compares
+the rest locally. Fetched ranges are cached for
+`PASSWORD_BREACH_CACHE_SECS`. When the API cannot be reached the report
+says so, and the password passes unless `PASSWORD_BREACH_FAIL_OPEN` is off.
+Passwords are never stored, logged or audited.
+*/
+
+use actix_web::{web, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+use crate::clock::Clock;
+use crate::config::{Config, PasswordConfig};
+use crate::crypto::algorithms;
+use crate::errors::SecurityError;
+use crate::AppState;
+
+/// Most `user_inputs` in one check.
+const MAX_USER_INPUTS: usize = 20;
+/// Shortest word or user detail matched inside a password.
+const MIN_WORD_CHARS: usize = 4;
+
+/// Passwords common enough to be among the first guesses, in English and
+/// Portuguese.
+const COMMON_WORDS: &[&str] = &[
+    "password", "passw0rd", "senha", "123456", "12345678", "qwerty", "abc123", "admin", "administrador",
+    "welcome", "bemvindo", "letmein", "iloveyou", "teamo", "dragon", "monkey", "football", "futebol",
+    "master", "sunshine", "princess", "princesa", "changeme", "mudar", "trocar", "teste", "segredo",
+    "secret", "login", "acesso", "brasil", "flamengo", "corinthians", "palmeiras", "santos", "gremio",
+    "vasco", "cruzeiro", "amor", "familia", "jesus", "deus", "gabriel", "lucas", "mateus", "maria",
+    "usuario", "default", "padrao", "empresa",
+];
+
+const KEYBOARD_ROWS: &[&str] = &["qwertyuiop", "asdfghjkl", "zxcvbnm", "1234567890", "qwertzuiop", "azertyuiop"];
+
+/// Look-alike characters, undone before matching words.
+fn unleet(c: char) -> char {
+    match c {
+        '4' | '@' => 'a',
+        '8' => 'b',
+        '3' => 'e',
+        '9' => 'g',
+        '1' | '!' | '|' => 'i',
+        '0' => 'o',
+        '5' | '$' => 's',
+        '7' | '+' => 't',
+        '2' => 'z',
+        c => c,
+    }
+}
+
+fn normalize(word: &str) -> Vec<char> {
+    word.chars().map(|c| unleet(c.to_ascii_lowercase())).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Class {
+    Lowercase,
+    Uppercase,
+    Digit,
+    Symbol,
+}
+
+impl Class {
+    fn of(c: char) -> Self {
+        if c.is_ascii_digit() {
+            Class::Digit
+        } else if c.is_lowercase() {
+            Class::Lowercase
+        } else if c.is_uppercase() {
+            Class::Uppercase
+        } else {
+            Class::Symbol
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Class::Lowercase => "lowercase",
+            Class::Uppercase => "uppercase",
+            Class::Digit => "digit",
+            Class::Symbol => "symbol",
+        }
+    }
+
+    /// Characters a brute-force attacker tries for the class.
+    fn pool(&self) -> f64 {
+        match self {
+            Class::Lowercase | Class::Uppercase => 26.0,
+            Class::Digit => 10.0,
+            Class::Symbol => 33.0,
+        }
+    }
+}
+
+fn pool_of(chars: &[char]) -> f64 {
+    let classes: BTreeSet<Class> = chars.iter().map(|c| Class::of(*c)).collect();
+    classes.iter().map(Class::pool).sum()
+}
+
+fn find(haystack: &[char], needle: &[char], from: usize) -> Option<usize> {
+    if needle.is_empty() || haystack.len() < needle.len() {
+        return None;
+    }
+    (from..=haystack.len() - needle.len()).find(|&i| haystack[i..i + needle.len()] == *needle)
+}
+
+/// Guesses needed, in log10, with what made them few.
+struct Estimate {
+    guesses_log10: f64,
+    warnings: BTreeSet<&'static str>,
+}
+
+/// A password split into cheap patterns and a brute-forced rest.
+struct Estimator {
+    original: Vec<char>,
+    lower: Vec<char>,
+    unleeted: Vec<char>,
+    covered: Vec<bool>,
+    guesses_log10: f64,
+    segments: usize,
+    warnings: BTreeSet<&'static str>,
+}
+
+impl Estimator {
+    fn new(password: &str) -> Self {
+        let original: Vec<char> = password.chars().collect();
+        let lower: Vec<char> = original.iter().map(|c| c.to_ascii_lowercase()).collect();
+        let unleeted = lower.iter().map(|c| unleet(*c)).collect();
+        let covered = vec![false; original.len()];
+        Self { original, lower, unleeted, covered, guesses_log10: 0.0, segments: 0, warnings: BTreeSet::new() }
+    }
+
+    fn free(&self, start: usize, end: usize) -> bool {
+        !self.covered[start..end].iter().any(|c| *c)
+    }
+
+    fn cover(&mut self, start: usize, end: usize, guesses: f64, warning: &'static str) {
+        self.covered[start..end].iter_mut().for_each(|c| *c = true);
+        self.guesses_log10 += guesses.max(1.0).log10();
+        self.segments += 1;
+        self.warnings.insert(warning);
+    }
+
+    /// `words` with the guesses each costs, longest matched first.
+    fn words(&mut self, words: &[(Vec<char>, f64, &'static str)]) {
+        let mut words: Vec<_> = words.iter().filter(|(word, _, _)| word.len() >= MIN_WORD_CHARS).collect();
+        words.sort_by_key(|(word, _, _)| std::cmp::Reverse(word.len()));
+        for (word, guesses, warning) in words {
+            let mut from = 0;
+            while let Some(start) = find(&self.unleeted, word, from) {
+                let end = start + word.len();
+                if self.free(start, end) {
+                    let mut guesses = *guesses;
+                    // Capitals and substitutions each about double the work
+                    if self.original[start..end].iter().any(|c| c.is_uppercase()) {
+                        guesses *= 2.0;
+                    }
+                    if self.lower[start..end] != self.unleeted[start..end] {
+                        guesses *= 2.0;
+                    }
+                    self.cover(start, end, guesses, warning);
+                }
+                from = start + 1;
+            }
+        }
+    }
+
+    /// Runs of at least `min` characters where `step` holds between neighbours.
+    fn runs(&mut self, min: usize, step: impl Fn(&[char], usize) -> bool, guesses: impl Fn(&[char]) -> f64, warning: &'static str) {
+        let n = self.lower.len();
+        let mut start = 0;
+        while start < n {
+            let mut end = start + 1;
+            while end < n && step(&self.lower, end) {
+                end += 1;
+            }
+            if end - start >= min && self.free(start, end) {
+                let cost = guesses(&self.lower[start..end]);
+                self.cover(start, end, cost, warning);
+            }
+            start = end;
+        }
+    }
+
+    fn keyboard_rows(&mut self) {
+        let rows: Vec<Vec<char>> = KEYBOARD_ROWS
+            .iter()
+            .flat_map(|row| [row.chars().collect(), row.chars().rev().collect()])
+            .collect();
+        let n = self.lower.len();
+        let mut start = 0;
+        while start < n {
+            let longest = rows
+                .iter()
+                .map(|row| (start..=n).rev().find(|&end| end - start >= 4 && find(row, &self.lower[start..end], 0).is_some()))
+                .max()
+                .flatten();
+            match longest {
+                Some(end) if self.free(start, end) => {
+                    self.cover(start, end, 12.0 * (end - start) as f64, "Straight rows of keys are easy to guess");
+                    start = end;
+                }
+                _ => start += 1,
+            }
+        }
+    }
+
+    fn years(&mut self) {
+        let n = self.lower.len();
+        for start in 0..n.saturating_sub(3) {
+            let digits: String = self.lower[start..start + 4].iter().collect();
+            let recent = digits.parse::<u32>().is_ok_and(|year| (1900..=2049).contains(&year));
+            let bounded = (start == 0 || !self.lower[start - 1].is_ascii_digit())
+                && (start + 4 == n || !self.lower[start + 4].is_ascii_digit());
+            if recent && bounded && self.free(start, start + 4) {
+                self.cover(start, start + 4, 150.0, "Years are easy to guess");
+            }
+        }
+    }
+
+    /// Brute force what no pattern covered, and the order of the parts.
+    fn finish(mut self) -> Estimate {
+        let n = self.original.len();
+        let mut start = 0;
+        while start < n {
+            if self.covered[start] {
+                start += 1;
+                continue;
+            }
+            let end = (start..n).find(|&i| self.covered[i]).unwrap_or(n);
+            let chars = &self.original[start..end];
+            self.guesses_log10 += chars.len() as f64 * pool_of(chars).log10();
+            self.segments += 1;
+            start = end;
+        }
+        let arrangements: f64 = (2..=self.segments).map(|k| (k as f64).log10()).sum();
+        Estimate { guesses_log10: self.guesses_log10 + arrangements, warnings: self.warnings }
+    }
+}
+
+fn estimate(password: &str, banned: &[Vec<char>], user_words: &[Vec<char>]) -> Estimate {
+    let mut words: Vec<(Vec<char>, f64, &'static str)> = Vec::new();
+    words.extend(user_words.iter().map(|word| (word.clone(), 10.0, "Personal details are easy to guess")));
+    words.extend(banned.iter().map(|word| (word.clone(), 100.0, "Words tied to the service are easy to guess")));
+    words.extend(COMMON_WORDS.iter().enumerate().map(|(rank, word)| {
+        (normalize(word), 10.0 * (rank + 1) as f64, "Common passwords are easy to guess")
+    }));
+
+    let mut estimator = Estimator::new(password);
+    estimator.words(&words);
+    estimator.keyboard_rows();
+    estimator.runs(3, |chars, i| chars[i] == chars[i - 1], |run| pool_of(run) * run.len() as f64, "Repeated characters are easy to guess");
+    estimator.runs(
+        3,
+        |chars, i| {
+            let (a, b) = (chars[i - 1], chars[i]);
+            a.is_ascii_alphanumeric() && b.is_ascii_alphanumeric() && (a as i32 - b as i32).abs() == 1
+        },
+        |run| {
+            let obvious = matches!(run[0], 'a' | 'z' | '0' | '1' | '9');
+            let start = if obvious { 4.0 } else if run[0].is_ascii_digit() { 10.0 } else { 26.0 };
+            let descending = run[1] < run[0];
+            start * run.len() as f64 * if descending { 2.0 } else { 1.0 }
+        },
+        "Sequences like abc or 6543 are easy to guess",
+    );
+    estimator.years();
+    estimator.finish()
+}
+
+fn score(guesses_log10: f64) -> u8 {
+    match guesses_log10 {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    }
+}
+
+/// Words of the user's details worth looking for in a password.
+fn user_words(user_inputs: &[String]) -> Vec<Vec<char>> {
+    user_inputs
+        .iter()
+        .flat_map(|input| {
+            let whole = normalize(input.trim());
+            let parts = input.split(|c: char| !c.is_alphanumeric()).map(normalize).collect::<Vec<_>>();
+            std::iter::once(whole).chain(parts)
+        })
+        .filter(|word| word.len() >= MIN_WORD_CHARS)
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Violation {
+    pub code: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BreachResult {
+    /// Whether the range API answered.
+    pub checked: bool,
+    /// Times the password appears in known breaches.
+    pub occurrences: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StrengthReport {
+    pub acceptable: bool,
+    pub length: usize,
+    pub character_classes: Vec<&'static str>,
+    /// 0-4, as zxcvbn scores.
+    pub score: u8,
+    pub guesses_log10: f64,
+    pub entropy_bits: f64,
+    pub violations: Vec<Violation>,
+    /// Patterns that made the password easier to guess.
+    pub warnings: Vec<&'static str>,
+    pub suggestions: Vec<&'static str>,
+    /// `None` when breach checks are off or the password already failed.
+    pub breach: Option<BreachResult>,
+}
+
+type Range = Arc<HashMap<String, u64>>;
+
+pub struct PasswordService {
+    config: PasswordConfig,
+    /// Normalized banned words.
+    banned: Vec<Vec<char>>,
+    client: reqwest::Client,
+    ranges: Mutex<HashMap<String, (DateTime<Utc>, Range)>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl PasswordService {
+    pub async fn new(config: &Config, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let mut words = config.password.banned_words.clone();
+        if let Some(path) = &config.password.banned_words_file {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| SecurityError::ConfigError(format!("Cannot read {}: {}", path, e)))?;
+            words.extend(contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).map(str::to_string));
+        }
+        let banned: Vec<Vec<char>> = words.iter().map(|word| normalize(word)).filter(|word| !word.is_empty()).collect();
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(config.password.breach_timeout_secs))
+            .user_agent("cotai-security")
+            .build()
+            .map_err(|e| SecurityError::ConfigError(format!("Breach check client: {}", e)))?;
+
+        info!("Password service initialized with {} banned words", banned.len());
+        Ok(Self {
+            config: config.password.clone(),
+            banned,
+            client,
+            ranges: Mutex::new(HashMap::new()),
+            clock,
+        })
+    }
+
+    /// Suffixes and counts of the hashes starting with `prefix`.
+    async fn range(&self, prefix: &str) -> Result<Range, SecurityError> {
+        let now = self.clock.now();
+        let ttl = Duration::seconds(self.config.breach_cache_secs);
+        if let Some((fetched_at, range)) = self.ranges.lock().unwrap_or_else(|e| e.into_inner()).get(prefix) {
+            if now - *fetched_at < ttl {
+                return Ok(range.clone());
+            }
+        }
+
+        let url = format!("{}{}", self.config.breach_api_url, prefix);
+        let response = self.client.get(&url).header("Add-Padding", "true").send().await
+            .map_err(|e| SecurityError::DeliveryError(format!("Breach API unreachable: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(SecurityError::DeliveryError(format!("Breach API returned {}", response.status())));
+        }
+        let body = response.text().await
+            .map_err(|e| SecurityError::DeliveryError(format!("Breach API response: {}", e)))?;
+        // Padding entries have a count of 0
+        let range: Range = Arc::new(
+            body.lines()
+                .filter_map(|line| line.trim().split_once(':'))
+                .filter_map(|(suffix, count)| Some((suffix.to_ascii_uppercase(), count.parse::<u64>().ok()?)))
+                .filter(|(_, count)| *count > 0)
+                .collect(),
+        );
+
+        let mut ranges = self.ranges.lock().unwrap_or_else(|e| e.into_inner());
+        ranges.retain(|_, (fetched_at, _)| now - *fetched_at < ttl);
+        if ranges.len() >= self.config.breach_cache_ranges {
+            let oldest = ranges.iter().min_by_key(|(_, (fetched_at, _))| *fetched_at).map(|(prefix, _)| prefix.clone());
+            if let Some(oldest) = oldest {
+                ranges.remove(&oldest);
+            }
+        }
+        if self.config.breach_cache_ranges > 0 {
+            ranges.insert(prefix.to_string(), (now, range.clone()));
+        }
+        Ok(range)
+    }
+
+    /// Times `password` appears in the breach corpus.
+    pub async fn breach_occurrences(&self, password: &str) -> Result<u64, SecurityError> {
+        let hash = hex::encode_upper(digest(&SHA1_FOR_LEGACY_USE_ONLY, password.as_bytes()));
+        let (prefix, suffix) = hash.split_at(5);
+        Ok(self.range(prefix).await?.get(suffix).copied().unwrap_or(0))
+    }
+
+    /// Score `password` and check it against the policy. `breach_lookup` is
+    /// false where SHA-1, which the lookup needs, is forbidden.
+    pub async fn check(&self, password: &str, user_inputs: &[String], breach_lookup: bool) -> StrengthReport {
+        let config = &self.config;
+        let length = password.chars().count();
+        let classes: BTreeSet<Class> = password.chars().map(Class::of).collect();
+        let mut violations = Vec::new();
+        let mut violate = |code: &'static str, message: String| violations.push(Violation { code, message });
+
+        if length > config.max_length {
+            violate("too_long", format!("Use at most {} characters", config.max_length));
+            return StrengthReport {
+                acceptable: false,
+                length,
+                character_classes: classes.iter().map(Class::as_str).collect(),
+                score: 0,
+                guesses_log10: 0.0,
+                entropy_bits: 0.0,
+                violations,
+                warnings: Vec::new(),
+                suggestions: Vec::new(),
+                breach: None,
+            };
+        }
+        if length < config.min_length {
+            violate("too_short", format!("Use at least {} characters", config.min_length));
+        }
+        if classes.len() < config.min_character_classes {
+            violate(
+                "too_few_character_classes",
+                format!("Mix at least {} of lowercase, uppercase, digits and symbols", config.min_character_classes),
+            );
+        }
+        let normalized = normalize(password);
+        if self.banned.iter().any(|word| find(&normalized, word, 0).is_some()) {
+            violate("banned_word", "Avoid words tied to the service".to_string());
+        }
+        let user_words = user_words(user_inputs);
+        if user_words.iter().any(|word| find(&normalized, word, 0).is_some()) {
+            violate("personal_information", "Avoid your name, e-mail or other personal details".to_string());
+        }
+
+        let estimate = estimate(password, &self.banned, &user_words);
+        let score = score(estimate.guesses_log10);
+        if score < config.min_score {
+            violate("too_guessable", format!("Too easy to guess; aim for a score of at least {}", config.min_score));
+        }
+
+        // Only passwords passing everything else are worth sending out
+        let breach = if config.breach_check && breach_lookup && violations.is_empty() {
+            Some(match self.breach_occurrences(password).await {
+                Ok(occurrences) => BreachResult { checked: true, occurrences },
+                Err(e) => {
+                    warn!("Breached-password check unavailable: {}", e);
+                    BreachResult { checked: false, occurrences: 0 }
+                }
+            })
+        } else {
+            None
+        };
+        match &breach {
+            Some(BreachResult { checked: true, occurrences }) if *occurrences > 0 => violations.push(Violation {
+                code: "breached",
+                message: format!("This password has appeared in {} known data breaches", occurrences),
+            }),
+            Some(BreachResult { checked: false, .. }) if !config.breach_fail_open => violations.push(Violation {
+                code: "breach_check_unavailable",
+                message: "The password could not be checked against known breaches; try again later".to_string(),
+            }),
+            _ => {}
+        }
+
+        let mut suggestions = Vec::new();
+        if length < config.min_length.max(16) {
+            suggestions.push("Add more words or characters; length matters most");
+        }
+        if !estimate.warnings.is_empty() {
+            suggestions.push("Avoid predictable words and patterns");
+        }
+        if classes.len() < config.min_character_classes {
+            suggestions.push("Mix in other kinds of characters");
+        }
+
+        StrengthReport {
+            acceptable: violations.is_empty(),
+            length,
+            character_classes: classes.iter().map(Class::as_str).collect(),
+            score,
+            guesses_log10: (estimate.guesses_log10 * 100.0).round() / 100.0,
+            entropy_bits: (estimate.guesses_log10 * std::f64::consts::LOG2_10 * 10.0).round() / 10.0,
+            violations,
+            warnings: estimate.warnings.into_iter().collect(),
+            suggestions,
+            breach,
+        }
+    }
+}
+
+/// Check a password being set, refusing one the policy does not accept.
+pub async fn enforce(state: &AppState, password: &str, user_inputs: &[String]) -> Result<StrengthReport, SecurityError> {
+    let report = state.passwords.check(password, user_inputs, breach_lookup(state).await).await;
+    count(state, &report);
+    if !report.acceptable {
+        let messages: Vec<&str> = report.violations.iter().map(|v| v.message.as_str()).collect();
+        return Err(SecurityError::ValidationError(messages.join("; ")));
+    }
+    Ok(report)
+}
+
+/// Whether the breached-password lookup may use SHA-1.
+async fn breach_lookup(state: &AppState) -> bool {
+    match algorithms::check(state, None, "SHA-1", "breach_check").await {
+        Ok(_) => true,
+        Err(e) => {
+            warn!("Breached-password check skipped: {}", e);
+            false
+        }
+    }
+}
+
+fn count(state: &AppState, report: &StrengthReport) {
+    state.metrics_service.increment(
+        "cotai_password_checks_total",
+        &[("acceptable", if report.acceptable { "true" } else { "false" })],
+    );
+}
+
+// HTTP handlers
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CheckRequest {
+    pub password: String,
+    /// The user's name, e-mail and similar details, which the password
+    /// should not contain.
+    #[serde(default)]
+    pub user_inputs: Vec<String>,
+}
+
+pub async fn check_handler(
+    request: web::Json<CheckRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    if request.user_inputs.len() > MAX_USER_INPUTS {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("At most {} user_inputs are accepted", MAX_USER_INPUTS)
+        })));
+    }
+
+    let report = state.passwords.check(&request.password, &request.user_inputs, breach_lookup(&state).await).await;
+    count(&state, &report);
+    Ok(HttpResponse::Ok()
+        .insert_header(("Cache-Control", "no-store"))
+        .json(report))
+}
@@ -0,0 +1,1030 @@
+/*!
+Service Accounts
+Non-human callers authenticating with key pairs instead of shared secrets
+
+A service account belongs to one tenant and holds a set of scopes and one
+or more public keys, registered as JWKs (Ed25519, P-256 or RSA of at least
+2048 bits). The private key never leaves the caller. To get an access
+token it posts a signed assertion to `POST /auth/token`, as in RFC 7523
+client authentication (`private_key_jwt`):
+
+- `grant_type=client_credentials`
+- `client_assertion_type=urn:ietf:params:oauth:client-assertion-type:jwt-bearer`
+- `client_assertion`: a JWT with `kid` set to the registered key id, `iss`
+  and `sub` set to the account id, `aud` one of `SERVICE_ACCOUNT_AUDIENCES`,
+  a unique `jti` and a lifetime of at most
+  `SERVICE_ACCOUNT_ASSERTION_MAX_LIFETIME_SECS`
+- `scope`, optional: a subset of the account's scopes; all of them if absent
+
+Tokens carry `SERVICE_ACCOUNT_ROLE`, the granted scopes and the tenant's
+access token TTL. Keys rotate by adding the new key, switching the caller
+over and revoking the old one; up to `SERVICE_ACCOUNT_MAX_KEYS` may be
+active at once, and keys with an expiry are tracked by `expiry`. Every
+token request from a known key is counted per day and outcome for
+`/admin/service-accounts/{id}/usage`.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use jsonwebtoken::jwk::{AlgorithmParameters, EllipticCurve, Jwk};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::{oauth_error, IssueRequest, TokenRequest};
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, ServiceAccountConfig};
+use crate::crypto::algorithms;
+use crate::errors::SecurityError;
+use crate::expiry::{Expiring, ExpiryFuture, ExpiryRegistry};
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::Storage;
+use crate::AppState;
+
+pub const ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "created_at", column: "created_at", kind: KeyKind::Timestamp },
+    SortField { name: "name", column: "name", kind: KeyKind::Text },
+];
+
+const ACCOUNT_COLUMNS: &str = "id, tenant_id, name, description, scopes, status, created_by, created_at, \
+    updated_at, last_used_at";
+
+const KEY_COLUMNS: &str = "key_id, account_id, algorithm, public_key, status, created_by, created_at, \
+    expires_at, revoked_at, last_used_at";
+
+/// JWK members that only private keys have.
+const PRIVATE_MEMBERS: &[&str] = &["d", "p", "q", "dp", "dq", "qi", "k"];
+
+const MIN_RSA_BITS: usize = 2048;
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ServiceAccount {
+    pub id: Uuid,
+    pub tenant_id: String,
+    pub name: String,
+    pub description: String,
+    pub scopes: Vec<String>,
+    /// `active` or `disabled`.
+    pub status: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ServiceAccountKey {
+    /// The `kid` callers put in their assertion header.
+    pub key_id: String,
+    pub account_id: Uuid,
+    /// `EdDSA`, `ES256` or `RS256`.
+    pub algorithm: String,
+    pub public_key: Json<serde_json::Value>,
+    /// `active` or `revoked`.
+    pub status: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Token requests from one key on one day with one outcome.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct UsageDay {
+    pub day: NaiveDate,
+    pub key_id: String,
+    /// `issued`, or why the request was rejected.
+    pub outcome: String,
+    pub count: i64,
+    pub last_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateAccountRequest {
+    pub tenant_id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub scopes: Vec<String>,
+    pub key: KeyRequest,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyRequest {
+    /// Public JWK.
+    pub public_key: serde_json::Value,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateAccountRequest {
+    pub description: Option<String>,
+    pub scopes: Option<Vec<String>>,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AccountFilter {
+    pub tenant_id: Option<String>,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    #[serde(default = "default_usage_days")]
+    pub days: i64,
+}
+
+fn default_usage_days() -> i64 {
+    30
+}
+
+#[derive(Debug, Deserialize)]
+struct AssertionClaims {
+    iss: String,
+    sub: String,
+    exp: i64,
+    iat: Option<i64>,
+    jti: Option<String>,
+}
+
+/// A verified assertion: who asked, with which key, for what.
+#[derive(Debug, Clone)]
+pub struct Grant {
+    pub account: ServiceAccount,
+    pub key_id: String,
+    pub scopes: Vec<String>,
+}
+
+/// Why a token request from a known key was refused; `outcome` is what
+/// usage analytics count it as.
+struct Rejection {
+    outcome: &'static str,
+    error: SecurityError,
+}
+
+fn reject(outcome: &'static str, error: SecurityError) -> Rejection {
+    Rejection { outcome, error }
+}
+
+pub(super) fn validate_scopes(scopes: &[String]) -> Result<(), SecurityError> {
+    // RFC 6749 scope-token: printable ASCII except space, quote and backslash
+    let valid = |scope: &String| {
+        !scope.is_empty() && scope.chars().all(|c| c.is_ascii_graphic() && c != '"' && c != '\\')
+    };
+    if let Some(scope) = scopes.iter().find(|scope| !valid(scope)) {
+        return Err(SecurityError::ValidationError(format!("Invalid scope {:?}", scope)));
+    }
+    Ok(())
+}
+
+/// Parse a public JWK, rejecting private or symmetric material, weak keys
+/// and unsupported curves. Returns the key and its signing algorithm.
+fn parse_public_key(value: &serde_json::Value) -> Result<(Jwk, Algorithm), SecurityError> {
+    let invalid = |msg: &str| SecurityError::ValidationError(format!("public_key: {}", msg));
+    if PRIVATE_MEMBERS.iter().any(|member| value.get(member).is_some()) {
+        return Err(invalid("contains private key material; register the public key only"));
+    }
+
+    let jwk: Jwk = serde_json::from_value(value.clone()).map_err(|e| invalid(&e.to_string()))?;
+    let algorithm = match &jwk.algorithm {
+        AlgorithmParameters::OctetKeyPair(params) if params.curve == EllipticCurve::Ed25519 => Algorithm::EdDSA,
+        AlgorithmParameters::EllipticCurve(params) if params.curve == EllipticCurve::P256 => Algorithm::ES256,
+        AlgorithmParameters::RSA(params) => {
+            let modulus = base64::decode_config(&params.n, base64::URL_SAFE_NO_PAD)
+                .map_err(|_| invalid("modulus is not base64url"))?;
+            let bits = modulus.iter().skip_while(|b| **b == 0).count() * 8;
+            if bits < MIN_RSA_BITS {
+                return Err(invalid(&format!("RSA keys must be at least {} bits", MIN_RSA_BITS)));
+            }
+            Algorithm::RS256
+        }
+        _ => return Err(invalid("must be an Ed25519, P-256 or RSA key")),
+    };
+    DecodingKey::from_jwk(&jwk).map_err(|e| invalid(&e.to_string()))?;
+    Ok((jwk, algorithm))
+}
+
+fn algorithm_name(algorithm: Algorithm) -> &'static str {
+    match algorithm {
+        Algorithm::EdDSA => "EdDSA",
+        Algorithm::ES256 => "ES256",
+        _ => "RS256",
+    }
+}
+
+pub struct ServiceAccountService {
+    config: ServiceAccountConfig,
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+}
+
+impl ServiceAccountService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+
+        info!("Service account service initialized successfully");
+        Ok(Self {
+            config: config.service_accounts.clone(),
+            storage,
+            clock,
+        })
+    }
+
+    /// Register an account together with its first key.
+    pub async fn create(
+        &self,
+        principal: &Principal,
+        request: CreateAccountRequest,
+    ) -> Result<(ServiceAccount, ServiceAccountKey), SecurityError> {
+        let name = request.name.trim();
+        if name.is_empty() || name.len() > 100 {
+            return Err(SecurityError::ValidationError("name must be 1-100 characters".to_string()));
+        }
+        if request.tenant_id.trim().is_empty() {
+            return Err(SecurityError::ValidationError("tenant_id is required".to_string()));
+        }
+        validate_scopes(&request.scopes)?;
+        let (jwk, algorithm) = parse_public_key(&request.key.public_key)?;
+
+        let mut tx = self.storage.begin().await?;
+        let account = sqlx::query_as::<_, ServiceAccount>(&format!(
+            "INSERT INTO service_accounts (id, tenant_id, name, description, scopes, created_by) \
+             VALUES ($1, $2, $3, $4, $5, $6) RETURNING {}",
+            ACCOUNT_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(&request.tenant_id)
+        .bind(name)
+        .bind(&request.description)
+        .bind(&request.scopes)
+        .bind(&principal.subject)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => SecurityError::Conflict(format!(
+                "Tenant {} already has a service account named {}",
+                request.tenant_id, name
+            )),
+            e => e.into(),
+        })?;
+        let key = self.insert_key(&mut tx, account.id, jwk, algorithm, request.key.expires_at, principal).await?;
+        tx.commit().await?;
+
+        info!("{} created service account {} ({}) for tenant {}", principal.subject, account.id, account.name, account.tenant_id);
+        Ok((account, key))
+    }
+
+    async fn insert_key(
+        &self,
+        tx: &mut sqlx::Transaction<'static, Postgres>,
+        account_id: Uuid,
+        mut jwk: Jwk,
+        algorithm: Algorithm,
+        expires_at: Option<DateTime<Utc>>,
+        principal: &Principal,
+    ) -> Result<ServiceAccountKey, SecurityError> {
+        if expires_at.is_some_and(|at| at <= self.clock.now()) {
+            return Err(SecurityError::ValidationError("expires_at must be in the future".to_string()));
+        }
+
+        // Published back with the id callers must put in their headers
+        let key_id = Uuid::new_v4().to_string();
+        jwk.common.key_id = Some(key_id.clone());
+        let public_key = serde_json::to_value(&jwk)
+            .map_err(|e| SecurityError::ValidationError(format!("public_key: {}", e)))?;
+
+        let key = sqlx::query_as::<_, ServiceAccountKey>(&format!(
+            "INSERT INTO service_account_keys (key_id, account_id, algorithm, public_key, created_by, expires_at) \
+             VALUES ($1, $2, $3, $4, $5, $6) RETURNING {}",
+            KEY_COLUMNS
+        ))
+        .bind(&key_id)
+        .bind(account_id)
+        .bind(algorithm_name(algorithm))
+        .bind(Json(public_key))
+        .bind(&principal.subject)
+        .bind(expires_at)
+        .fetch_one(&mut **tx)
+        .await?;
+        Ok(key)
+    }
+
+    pub async fn list(&self, filter: &AccountFilter, page: &PageRequest) -> Result<Page<ServiceAccount>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM service_accounts WHERE 1 = 1",
+            ACCOUNT_COLUMNS
+        ));
+        if let Some(tenant_id) = &filter.tenant_id {
+            builder.push(" AND tenant_id = ").push_bind(tenant_id.clone());
+        }
+        if let Some(status) = &filter.status {
+            builder.push(" AND status = ").push_bind(status.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let accounts = builder
+            .build_query_as::<ServiceAccount>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(accounts, |account, field| match field {
+            "name" => (SortKey::Text(account.name.clone()), account.id),
+            _ => (SortKey::Timestamp(account.created_at), account.id),
+        }))
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<ServiceAccount, SecurityError> {
+        sqlx::query_as::<_, ServiceAccount>(&format!(
+            "SELECT {} FROM service_accounts WHERE id = $1",
+            ACCOUNT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("Service account not found".to_string()))
+    }
+
+    pub async fn keys(&self, account_id: Uuid) -> Result<Vec<ServiceAccountKey>, SecurityError> {
+        let keys = sqlx::query_as::<_, ServiceAccountKey>(&format!(
+            "SELECT {} FROM service_account_keys WHERE account_id = $1 ORDER BY created_at",
+            KEY_COLUMNS
+        ))
+        .bind(account_id)
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(keys)
+    }
+
+    pub async fn update(&self, id: Uuid, request: UpdateAccountRequest) -> Result<ServiceAccount, SecurityError> {
+        if let Some(scopes) = &request.scopes {
+            validate_scopes(scopes)?;
+        }
+        if let Some(status) = &request.status {
+            if status != "active" && status != "disabled" {
+                return Err(SecurityError::ValidationError("status must be active or disabled".to_string()));
+            }
+        }
+
+        sqlx::query_as::<_, ServiceAccount>(&format!(
+            "UPDATE service_accounts SET description = COALESCE($2, description), scopes = COALESCE($3, scopes), \
+             status = COALESCE($4, status), updated_at = NOW() WHERE id = $1 RETURNING {}",
+            ACCOUNT_COLUMNS
+        ))
+        .bind(id)
+        .bind(&request.description)
+        .bind(&request.scopes)
+        .bind(&request.status)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("Service account not found".to_string()))
+    }
+
+    /// Add a key for rotation. The old key keeps working until revoked.
+    pub async fn add_key(
+        &self,
+        principal: &Principal,
+        account_id: Uuid,
+        request: KeyRequest,
+    ) -> Result<ServiceAccountKey, SecurityError> {
+        let (jwk, algorithm) = parse_public_key(&request.public_key)?;
+
+        let mut tx = self.storage.begin().await?;
+        // Locks the account so concurrent additions cannot both pass the limit
+        sqlx::query_scalar::<_, Uuid>("SELECT id FROM service_accounts WHERE id = $1 FOR UPDATE")
+            .bind(account_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| SecurityError::NotFound("Service account not found".to_string()))?;
+        let active: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM service_account_keys WHERE account_id = $1 AND status = 'active' \
+             AND (expires_at IS NULL OR expires_at > $2)",
+        )
+        .bind(account_id)
+        .bind(self.clock.now())
+        .fetch_one(&mut *tx)
+        .await?;
+        if active >= self.config.max_keys {
+            return Err(SecurityError::Conflict(format!(
+                "Service account {} already has {} active keys; revoke one first",
+                account_id, active
+            )));
+        }
+
+        let key = self.insert_key(&mut tx, account_id, jwk, algorithm, request.expires_at, principal).await?;
+        tx.commit().await?;
+
+        info!("{} added key {} to service account {}", principal.subject, key.key_id, account_id);
+        Ok(key)
+    }
+
+    pub async fn revoke_key(&self, account_id: Uuid, key_id: &str) -> Result<ServiceAccountKey, SecurityError> {
+        let key = sqlx::query_as::<_, ServiceAccountKey>(&format!(
+            "UPDATE service_account_keys SET status = 'revoked', revoked_at = $3 \
+             WHERE account_id = $1 AND key_id = $2 AND status = 'active' RETURNING {}",
+            KEY_COLUMNS
+        ))
+        .bind(account_id)
+        .bind(key_id)
+        .bind(self.clock.now())
+        .fetch_optional(self.storage.pool())
+        .await?;
+
+        match key {
+            Some(key) => Ok(key),
+            None => {
+                let exists: bool = sqlx::query_scalar(
+                    "SELECT EXISTS (SELECT 1 FROM service_account_keys WHERE account_id = $1 AND key_id = $2)",
+                )
+                .bind(account_id)
+                .bind(key_id)
+                .fetch_one(self.storage.pool())
+                .await?;
+                if exists {
+                    Err(SecurityError::Conflict(format!("Key {} is already revoked", key_id)))
+                } else {
+                    Err(SecurityError::NotFound("Service account key not found".to_string()))
+                }
+            }
+        }
+    }
+
+    /// Per-day token request counts since `days` ago, newest first.
+    pub async fn usage(&self, account_id: Uuid, days: i64) -> Result<Vec<UsageDay>, SecurityError> {
+        let since = (self.clock.now() - Duration::days(days.clamp(1, 365))).date_naive();
+        let usage = sqlx::query_as::<_, UsageDay>(
+            "SELECT day, key_id, outcome, count, last_at FROM service_account_usage \
+             WHERE account_id = $1 AND day >= $2 ORDER BY day DESC, key_id, outcome",
+        )
+        .bind(account_id)
+        .bind(since)
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(usage)
+    }
+
+    /// Verify a client assertion and work out the scopes to grant. Requests
+    /// naming an unknown key are refused without being counted.
+    pub async fn authenticate(&self, assertion: &str, request: &TokenRequest) -> Result<Grant, SecurityError> {
+        let invalid_client = |msg: &str| SecurityError::AuthError(msg.to_string());
+        let header = decode_header(assertion).map_err(|_| invalid_client("Malformed client assertion"))?;
+        let key_id = header.kid.clone().ok_or_else(|| invalid_client("Client assertion header has no kid"))?;
+
+        let key = sqlx::query_as::<_, ServiceAccountKey>(&format!(
+            "SELECT {} FROM service_account_keys WHERE key_id = $1",
+            KEY_COLUMNS
+        ))
+        .bind(&key_id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| invalid_client("Unknown client key"))?;
+        let account = self.get(key.account_id).await?;
+
+        let now = self.clock.now();
+        let outcome = self.check(&account, &key, header.alg, assertion, request, now).await;
+        let recorded = self.record_usage(account.id, &key_id, match &outcome {
+            Ok(_) => "issued",
+            Err(rejection) => rejection.outcome,
+        }, now).await;
+        if let Err(e) = recorded {
+            warn!("Failed to record usage of service account {}: {:?}", account.id, e);
+        }
+
+        let scopes = outcome.map_err(|rejection| rejection.error)?;
+        Ok(Grant { account, key_id, scopes })
+    }
+
+    async fn check(
+        &self,
+        account: &ServiceAccount,
+        key: &ServiceAccountKey,
+        alg: Algorithm,
+        assertion: &str,
+        request: &TokenRequest,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<String>, Rejection> {
+        let invalid_client = |outcome, msg: &str| reject(outcome, SecurityError::AuthError(msg.to_string()));
+
+        if account.status != "active" {
+            return Err(invalid_client("account_disabled", "Service account is disabled"));
+        }
+        if key.status != "active" {
+            return Err(invalid_client("key_revoked", "Client key is revoked"));
+        }
+        if key.expires_at.is_some_and(|at| at <= now) {
+            return Err(invalid_client("key_expired", "Client key has expired"));
+        }
+        if algorithm_name(alg) != key.algorithm {
+            return Err(invalid_client("invalid_assertion", "Assertion algorithm does not match the key"));
+        }
+
+        let jwk: Jwk = serde_json::from_value(key.public_key.0.clone())
+            .map_err(|e| reject("invalid_assertion", SecurityError::CryptoError(e.to_string())))?;
+        let decoding_key = DecodingKey::from_jwk(&jwk)
+            .map_err(|e| reject("invalid_assertion", SecurityError::CryptoError(e.to_string())))?;
+
+        let account_id = account.id.to_string();
+        let mut validation = Validation::new(alg);
+        validation.validate_exp = false;
+        validation.set_audience(&self.config.audiences);
+        validation.set_issuer(&[&account_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "sub", "aud"]);
+        let claims = decode::<AssertionClaims>(assertion, &decoding_key, &validation)
+            .map_err(|e| invalid_client("invalid_assertion", &format!("Invalid client assertion: {}", e)))?
+            .claims;
+
+        if claims.sub != claims.iss || request.client_id.as_ref().is_some_and(|id| *id != claims.iss) {
+            return Err(invalid_client("invalid_assertion", "Assertion subject does not match the client"));
+        }
+        let now_secs = now.timestamp();
+        if claims.exp.saturating_add(validation.leeway as i64) < now_secs {
+            return Err(invalid_client("invalid_assertion", "Client assertion has expired"));
+        }
+        if claims.exp - claims.iat.unwrap_or(now_secs) > self.config.assertion_max_lifetime_secs {
+            return Err(invalid_client("invalid_assertion", "Client assertion lifetime is too long"));
+        }
+        let jti = claims.jti
+            .filter(|jti| !jti.is_empty())
+            .ok_or_else(|| invalid_client("invalid_assertion", "Client assertion has no jti"))?;
+
+        let scopes = match request.scope.as_deref() {
+            None => account.scopes.clone(),
+            Some(scope) => {
+                let allowed: HashSet<&str> = account.scopes.iter().map(String::as_str).collect();
+                let requested: Vec<String> = scope.split_whitespace().map(String::from).collect();
+                if let Some(denied) = requested.iter().find(|s| !allowed.contains(s.as_str())) {
+                    return Err(reject(
+                        "invalid_scope",
+                        SecurityError::AccessDenied(format!("Scope {} is not granted to this account", denied)),
+                    ));
+                }
+                requested
+            }
+        };
+
+        // Last, so a rejected request does not burn the jti
+        let fresh = self.consume_jti(account.id, &jti, claims.exp, now)
+            .await
+            .map_err(|e| reject("error", e))?;
+        if !fresh {
+            return Err(invalid_client("replayed", "Client assertion was already used"));
+        }
+
+        let touched = sqlx::query(
+            "WITH key AS (UPDATE service_account_keys SET last_used_at = $3 WHERE key_id = $2) \
+             UPDATE service_accounts SET last_used_at = $3 WHERE id = $1",
+        )
+        .bind(account.id)
+        .bind(&key.key_id)
+        .bind(now)
+        .execute(self.storage.pool())
+        .await;
+        if let Err(e) = touched {
+            warn!("Failed to record last use of service account {}: {:?}", account.id, e);
+        }
+        Ok(scopes)
+    }
+
+    /// Remember `jti` until the assertion expires. False if already seen.
+    async fn consume_jti(&self, account_id: Uuid, jti: &str, exp: i64, now: DateTime<Utc>) -> Result<bool, SecurityError> {
+        let expires_at = DateTime::from_timestamp(exp, 0).unwrap_or(now);
+        let mut tx = self.storage.begin().await?;
+        sqlx::query("DELETE FROM service_account_assertions WHERE account_id = $1 AND expires_at < $2")
+            .bind(account_id)
+            .bind(now - Duration::seconds(Validation::default().leeway as i64))
+            .execute(&mut *tx)
+            .await?;
+        let inserted = sqlx::query(
+            "INSERT INTO service_account_assertions (account_id, jti, expires_at) VALUES ($1, $2, $3) \
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(account_id)
+        .bind(jti)
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        tx.commit().await?;
+        Ok(inserted == 1)
+    }
+
+    async fn record_usage(&self, account_id: Uuid, key_id: &str, outcome: &str, now: DateTime<Utc>) -> Result<(), SecurityError> {
+        sqlx::query(
+            "INSERT INTO service_account_usage (account_id, day, key_id, outcome, count, last_at) \
+             VALUES ($1, $2, $3, $4, 1, $5) \
+             ON CONFLICT (account_id, day, key_id, outcome) DO UPDATE SET \
+             count = service_account_usage.count + 1, last_at = GREATEST(service_account_usage.last_at, EXCLUDED.last_at)",
+        )
+        .bind(account_id)
+        .bind(now.date_naive())
+        .bind(key_id)
+        .bind(outcome)
+        .bind(now)
+        .execute(self.storage.pool())
+        .await?;
+        Ok(())
+    }
+
+    /// Active keys with an expiry, for the expiry scan.
+    async fn expiring_keys(&self) -> Result<Vec<Expiring>, SecurityError> {
+        let rows: Vec<(String, String, String, DateTime<Utc>)> = sqlx::query_as(
+            "SELECT k.key_id, a.name, a.tenant_id, k.expires_at FROM service_account_keys k \
+             JOIN service_accounts a ON a.id = k.account_id \
+             WHERE k.status = 'active' AND a.status = 'active' AND k.expires_at IS NOT NULL",
+        )
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(key_id, name, tenant_id, expires_at)| Expiring {
+                source: "service_account_keys",
+                kind: "service_account_key".to_string(),
+                name: format!("{}/{}", name, key_id),
+                tenant_id: Some(tenant_id),
+                expires_at,
+                fingerprint: None,
+            })
+            .collect())
+    }
+}
+
+fn expiring_keys(state: &AppState) -> ExpiryFuture<'_> {
+    Box::pin(state.service_accounts.expiring_keys())
+}
+
+pub fn register_expiry_sources(registry: &mut ExpiryRegistry) {
+    registry.register("service_account_keys", expiring_keys);
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Service account operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Service account operation failed"
+            }))
+        }
+    }
+}
+
+/// Check the algorithm of a key being registered for the account's tenant.
+async fn check_key_algorithm(
+    state: &AppState,
+    tenant_id: &str,
+    public_key: &serde_json::Value,
+) -> Result<Option<String>, SecurityError> {
+    let (_, algorithm) = parse_public_key(public_key)?;
+    algorithms::check(state, Some(tenant_id), algorithm_name(algorithm), "service_account_key").await
+}
+
+async fn audit(state: &AppState, principal: &Principal, action: &str, account_id: Uuid, payload: serde_json::Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: principal.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: None,
+        action: action.to_string(),
+        resource: format!("service_account:{}", account_id),
+        outcome: "success".to_string(),
+        payload,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} on service account {}: {:?}", action, account_id, e);
+    }
+}
+
+/// The `client_credentials` grant of `POST /auth/token`.
+pub(super) async fn client_credentials(req: &HttpRequest, form: TokenRequest, state: &AppState) -> HttpResponse {
+    use actix_web::http::StatusCode;
+
+    let assertion = match (form.client_assertion_type.as_deref(), form.client_assertion.as_deref()) {
+        (Some(ASSERTION_TYPE), Some(assertion)) => assertion,
+        _ => {
+            return oauth_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_request",
+                "A jwt-bearer client_assertion is required",
+            );
+        }
+    };
+
+    if state.threats.locked_until(form.client_id.as_deref(), None).is_some() {
+        return oauth_error(StatusCode::UNAUTHORIZED, "invalid_client", "Client is temporarily locked out");
+    }
+
+    let grant = state.service_accounts
+        .authenticate(assertion, &form)
+        .await;
+    let grant = match grant {
+        Ok(grant) => grant,
+        Err(e) => {
+            let ip = client_ip(req);
+            warn!("Service account token request from {:?} refused: {}", ip, e);
+            let recorded = state.audit_service.record(NewAuditEvent {
+                tenant_id: None,
+                actor: form.client_id.clone().unwrap_or_else(|| "unknown".to_string()),
+                actor_ip: ip,
+                action: "auth.service_account.token".to_string(),
+                resource: "service_account".to_string(),
+                outcome: "failure".to_string(),
+                payload: serde_json::json!({ "reason": e.to_string() }),
+            }).await;
+            if let Err(e) = recorded {
+                warn!("Failed to audit refused service account token: {:?}", e);
+            }
+            return match e {
+                SecurityError::AuthError(msg) => oauth_error(StatusCode::UNAUTHORIZED, "invalid_client", &msg),
+                SecurityError::AccessDenied(msg) => oauth_error(StatusCode::BAD_REQUEST, "invalid_scope", &msg),
+                e => {
+                    error!("Service account authentication failed: {:?}", e);
+                    oauth_error(StatusCode::INTERNAL_SERVER_ERROR, "server_error", "Token issuance failed")
+                }
+            };
+        }
+    };
+
+    // client_credentials gets no refresh token (RFC 6749 section 4.4.3)
+    let account = &grant.account;
+    let subject = format!("service_account:{}", account.id);
+    let issued = state.tokens.issue(state, &IssueRequest {
+        subject: subject.clone(),
+        tenant_id: Some(account.tenant_id.clone()),
+        roles: vec![state.config.service_accounts.role.clone()],
+        scopes: grant.scopes.clone(),
+        ..Default::default()
+    }).await;
+    let issued = match issued {
+        Ok(issued) => issued,
+        Err(e) => {
+            error!("Failed to sign token for service account {}: {:?}", account.id, e);
+            return oauth_error(StatusCode::INTERNAL_SERVER_ERROR, "server_error", "Token issuance failed");
+        }
+    };
+
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: Some(account.tenant_id.clone()),
+        actor: subject,
+        actor_ip: client_ip(req),
+        action: "auth.service_account.token".to_string(),
+        resource: format!("service_account:{}", account.id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "key_id": grant.key_id,
+            "scope": issued.scope,
+            "jti": issued.claims.as_ref().map(|c| c.jti.clone())
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit token for service account {}: {:?}", account.id, e);
+    }
+
+    HttpResponse::Ok()
+        .insert_header(("Cache-Control", "no-store"))
+        .json(issued)
+}
+
+pub async fn create_handler(
+    req: HttpRequest,
+    request: web::Json<CreateAccountRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let request = request.into_inner();
+    let warning = match check_key_algorithm(&state, &request.tenant_id, &request.key.public_key).await {
+        Ok(warning) => warning,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.service_accounts.create(&principal, request).await {
+        Ok((account, key)) => {
+            audit(&state, &principal, "auth.service_account.create", account.id, serde_json::json!({
+                "tenant_id": account.tenant_id,
+                "scopes": account.scopes,
+                "key_id": key.key_id
+            })).await;
+            let response = HttpResponse::Created().json(serde_json::json!({
+                "account": account,
+                "key": key
+            }));
+            Ok(algorithms::with_warning(response, warning.as_deref()))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_handler(
+    req: HttpRequest,
+    filter: web::Query<AccountFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.service_accounts.list(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "accounts": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let id = path.into_inner();
+    let account = match state.service_accounts.get(id).await {
+        Ok(account) => account,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.service_accounts.keys(id).await {
+        Ok(keys) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "account": account,
+            "keys": keys
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn update_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<UpdateAccountRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let request = request.into_inner();
+    let changes = serde_json::json!({
+        "description": request.description,
+        "scopes": request.scopes,
+        "status": request.status
+    });
+    match state.service_accounts.update(path.into_inner(), request).await {
+        Ok(account) => {
+            audit(&state, &principal, "auth.service_account.update", account.id, changes).await;
+            Ok(HttpResponse::Ok().json(account))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn add_key_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<KeyRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let id = path.into_inner();
+    let request = request.into_inner();
+    let account = match state.service_accounts.get(id).await {
+        Ok(account) => account,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let warning = match check_key_algorithm(&state, &account.tenant_id, &request.public_key).await {
+        Ok(warning) => warning,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.service_accounts.add_key(&principal, id, request).await {
+        Ok(key) => {
+            audit(&state, &principal, "auth.service_account.key.add", id, serde_json::json!({
+                "key_id": key.key_id,
+                "algorithm": key.algorithm,
+                "expires_at": key.expires_at
+            })).await;
+            Ok(algorithms::with_warning(HttpResponse::Created().json(key), warning.as_deref()))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn revoke_key_handler(
+    req: HttpRequest,
+    path: web::Path<(Uuid, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let (id, key_id) = path.into_inner();
+    match state.service_accounts.revoke_key(id, &key_id).await {
+        Ok(key) => {
+            audit(&state, &principal, "auth.service_account.key.revoke", id, serde_json::json!({
+                "key_id": key.key_id
+            })).await;
+            Ok(HttpResponse::Ok().json(key))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn usage_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<UsageQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let id = path.into_inner();
+    let account = match state.service_accounts.get(id).await {
+        Ok(account) => account,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let keys = match state.service_accounts.keys(id).await {
+        Ok(keys) => keys,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let usage = match state.service_accounts.usage(id, query.days).await {
+        Ok(usage) => usage,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    let mut totals = serde_json::Map::new();
+    for day in &usage {
+        let total = totals.get(&day.outcome).and_then(|v| v.as_i64()).unwrap_or(0) + day.count;
+        totals.insert(day.outcome.clone(), total.into());
+    }
+    let last_used: Vec<_> = keys.iter()
+        .map(|key| serde_json::json!({
+            "key_id": key.key_id,
+            "status": key.status,
+            "last_used_at": key.last_used_at
+        }))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "account_id": account.id,
+        "last_used_at": account.last_used_at,
+        "totals": totals,
+        "keys": last_used,
+        "days": usage
+    })))
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/service-accounts")
+            .route("", web::post().to(create_handler))
+            .route("", web::get().to(list_handler))
+            .route("/{id}", web::get().to(get_handler))
+            .route("/{id}", web::patch().to(update_handler))
+            .route("/{id}/keys", web::post().to(add_key_handler))
+            .route("/{id}/keys/{key_id}", web::delete().to(revoke_key_handler))
+            .route("/{id}/usage", web::get().to(usage_handler))
+    );
+}
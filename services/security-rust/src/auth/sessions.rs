@@ -0,0 +1,527 @@
+/*!
+Session Module
+Server-side login sessions with device tracking, idle timeout and forced logout
+
+A session starts when `POST /auth/tokens` issues a refresh token, and is
+that token's family (see `tokens`): every rotated refresh token and the
+access tokens issued alongside belong to it. It records where it started
+(`client_ip` and `user_agent` as reported by the login flow, else the
+caller's own) and where and when it was last seen, updated on each refresh.
+
+A session ends:
+
+- `AUTH_SESSION_MAX_AGE_SECS` after it started, however active it is
+- after `AUTH_SESSION_IDLE_TIMEOUT_SECS` without a refresh
+- when its user revokes it at `DELETE /auth/sessions/{id}`, or all of them
+  at `DELETE /auth/sessions` (`?keep_current=true` spares the caller's)
+- when an admin revokes it at `DELETE /admin/sessions/{id}`, or every
+  session of a compromised account at `DELETE /admin/sessions?subject=..`
+- when its refresh token is revoked or replayed, or consent is withdrawn
+
+Ending a session revokes its refresh tokens and the unexpired access
+tokens issued in it. Users list their active sessions at
+`GET /auth/sessions`, the one making the request marked `current`; admins
+list anyone's at `GET /admin/sessions?subject=..`. Starts, revocations and
+expiries are audited as `auth.session.*`.
+
+Access tokens minted without a refresh token are not sessions; they expire
+on their own.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, Transaction};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+use crate::AppState;
+
+pub const SYSTEM_ACTOR: &str = "system:auth";
+
+/// Longer user agents are cut; the rest says nothing more about the device.
+const MAX_USER_AGENT_CHARS: usize = 512;
+
+/// Sessions ended per sweep.
+const SWEEP_BATCH: i64 = 500;
+
+const SESSION_COLUMNS: &str = "id, subject, tenant_id, client_id, ip, user_agent, last_ip, last_seen_at, \
+    created_at, expires_at, ended_at, end_reason, ended_by";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Session {
+    pub id: Uuid,
+    pub subject: String,
+    pub tenant_id: Option<String>,
+    pub client_id: Option<Uuid>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub last_ip: Option<String>,
+    pub last_seen_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub end_reason: Option<String>,
+    pub ended_by: Option<String>,
+}
+
+/// Where a request comes from.
+#[derive(Debug, Clone, Default)]
+pub struct Device {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl Device {
+    pub fn of(req: &HttpRequest) -> Self {
+        Self {
+            ip: client_ip(req),
+            user_agent: req.headers()
+                .get("User-Agent")
+                .and_then(|ua| ua.to_str().ok())
+                .map(|ua| ua.chars().take(MAX_USER_AGENT_CHARS).collect()),
+        }
+    }
+}
+
+/// A session's state when its refresh token is used.
+pub(crate) enum Touch {
+    /// The family predates sessions.
+    Untracked,
+    Active,
+    Idle(Box<Session>),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevokeAllQuery {
+    #[serde(default)]
+    pub keep_current: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubjectQuery {
+    pub subject: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionView {
+    #[serde(flatten)]
+    pub session: Session,
+    pub current: bool,
+}
+
+pub struct SessionService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    max_age: Duration,
+    idle_timeout: Option<Duration>,
+}
+
+impl SessionService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let idle = config.auth.session_idle_timeout_secs;
+        info!("Session service initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            max_age: Duration::seconds(config.auth.session_max_age_secs),
+            idle_timeout: (idle > 0).then(|| Duration::seconds(idle)),
+        })
+    }
+
+    /// When a session started now must end.
+    pub fn expiry(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now + self.max_age
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn start(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+        subject: &str,
+        tenant_id: Option<&str>,
+        client_id: Option<Uuid>,
+        device: &Device,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), SecurityError> {
+        let now = self.clock.now();
+        sqlx::query(
+            "INSERT INTO sessions (id, subject, tenant_id, client_id, ip, user_agent, last_ip, last_seen_at, \
+             created_at, expires_at) VALUES ($1, $2, $3, $4, $5, $6, $5, $7, $7, $8)",
+        )
+        .bind(id)
+        .bind(subject)
+        .bind(tenant_id)
+        .bind(client_id)
+        .bind(&device.ip)
+        .bind(&device.user_agent)
+        .bind(now)
+        .bind(expires_at)
+        .execute(&mut **tx)
+        .await?;
+        // Kept after the session is purged, see `dormancy`
+        sqlx::query(
+            "INSERT INTO subject_logins (subject, tenant_id, last_login_at) VALUES ($1, $2, $3) \
+             ON CONFLICT (subject) DO UPDATE SET tenant_id = EXCLUDED.tenant_id, last_login_at = EXCLUDED.last_login_at",
+        )
+        .bind(subject)
+        .bind(tenant_id)
+        .bind(now)
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    /// Record a refresh in session `id`, unless the session has gone idle.
+    pub(crate) async fn touch(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+        device: &Device,
+    ) -> Result<Touch, SecurityError> {
+        let now = self.clock.now();
+        let session = sqlx::query_as::<_, Session>(&format!(
+            "SELECT {} FROM sessions WHERE id = $1 FOR UPDATE",
+            SESSION_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&mut **tx)
+        .await?;
+        let Some(session) = session else {
+            return Ok(Touch::Untracked);
+        };
+        if session.ended_at.is_some() || session.expires_at <= now {
+            return Err(SecurityError::AuthError("Session has ended".to_string()));
+        }
+        if self.idle_timeout.is_some_and(|idle| session.last_seen_at + idle <= now) {
+            return Ok(Touch::Idle(Box::new(session)));
+        }
+
+        sqlx::query("UPDATE sessions SET last_seen_at = $2, last_ip = COALESCE($3, last_ip) WHERE id = $1")
+            .bind(id)
+            .bind(now)
+            .bind(&device.ip)
+            .execute(&mut **tx)
+            .await?;
+        Ok(Touch::Active)
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<Session, SecurityError> {
+        sqlx::query_as::<_, Session>(&format!("SELECT {} FROM sessions WHERE id = $1", SESSION_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound(format!("No session {}", id)))
+    }
+
+    /// Sessions of `subject` that have not ended, most recently seen first.
+    pub async fn active(&self, subject: &str) -> Result<Vec<Session>, SecurityError> {
+        let now = self.clock.now();
+        let seen_since = self.idle_timeout.map(|idle| now - idle);
+        Ok(sqlx::query_as::<_, Session>(&format!(
+            "SELECT {} FROM sessions WHERE subject = $1 AND ended_at IS NULL AND expires_at > $2 \
+             AND ($3::timestamptz IS NULL OR last_seen_at > $3) ORDER BY last_seen_at DESC",
+            SESSION_COLUMNS
+        ))
+        .bind(subject)
+        .bind(now)
+        .bind(seen_since)
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    /// The session an access token was issued in, if any.
+    pub async fn of_token(&self, jti: &str) -> Result<Option<Uuid>, SecurityError> {
+        Ok(sqlx::query_scalar("SELECT family_id FROM refresh_tokens WHERE access_token_id = $1")
+            .bind(jti)
+            .fetch_optional(self.storage.pool())
+            .await?)
+    }
+
+    /// Sessions past their absolute or idle expiry that have not been ended
+    /// yet, with the reason each is due.
+    pub async fn due(&self) -> Result<Vec<(Session, &'static str)>, SecurityError> {
+        let now = self.clock.now();
+        let seen_before = self.idle_timeout.map(|idle| now - idle);
+        let sessions = sqlx::query_as::<_, Session>(&format!(
+            "SELECT {} FROM sessions WHERE ended_at IS NULL AND (expires_at <= $1 OR last_seen_at <= $2) \
+             ORDER BY last_seen_at LIMIT $3",
+            SESSION_COLUMNS
+        ))
+        .bind(now)
+        .bind(seen_before)
+        .bind(SWEEP_BATCH)
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        Ok(sessions
+            .into_iter()
+            .map(|session| {
+                let reason = if session.expires_at <= now { "expired" } else { "idle_timeout" };
+                (session, reason)
+            })
+            .collect())
+    }
+
+    /// Drop ended sessions once they would have expired anyway, as their
+    /// refresh tokens are.
+    pub async fn purge(&self) -> Result<u64, SecurityError> {
+        let deleted = sqlx::query("DELETE FROM sessions WHERE ended_at IS NOT NULL AND expires_at <= $1")
+            .bind(self.clock.now())
+            .execute(self.storage.pool())
+            .await?;
+        Ok(deleted.rows_affected())
+    }
+}
+
+pub(crate) async fn audit(
+    state: &AppState,
+    actor: &str,
+    actor_ip: Option<String>,
+    action: &str,
+    session: &Session,
+    payload: serde_json::Value,
+) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: session.tenant_id.clone(),
+        actor: actor.to_string(),
+        actor_ip,
+        action: action.to_string(),
+        resource: format!("session:{}", session.id),
+        outcome: "success".to_string(),
+        payload,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} of session {}: {:?}", action, session.id, e);
+    }
+}
+
+/// End sessions that have expired or gone idle, revoking their tokens.
+pub async fn run_expiry(state: web::Data<AppState>) {
+    // Checked as often as revocations are reloaded, which bounds how long
+    // an idle session's last access token outlives it
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        state.config.auth.revocation_refresh_secs,
+    ));
+    loop {
+        interval.tick().await;
+        let due = match state.sessions.due().await {
+            Ok(due) => due,
+            Err(e) => {
+                warn!("Failed to find expired sessions: {:?}", e);
+                continue;
+            }
+        };
+        for (session, reason) in due {
+            match state.tokens.end_session(session.id, reason, SYSTEM_ACTOR).await {
+                Ok(revoked) => {
+                    audit(&state, SYSTEM_ACTOR, None, "auth.session.expire", &session, serde_json::json!({
+                        "subject": session.subject,
+                        "reason": reason,
+                        "last_seen_at": session.last_seen_at,
+                        "revoked_access_tokens": revoked
+                    })).await;
+                }
+                Err(e) => error!("Failed to end session {}: {:?}", session.id, e),
+            }
+        }
+        if let Err(e) = state.sessions.purge().await {
+            warn!("Failed to purge ended sessions: {:?}", e);
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Session operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Session operation failed"
+            }))
+        }
+    }
+}
+
+async fn list(state: &AppState, subject: &str, current: Option<Uuid>) -> HttpResponse {
+    match state.sessions.active(subject).await {
+        Ok(sessions) => {
+            let sessions: Vec<SessionView> = sessions
+                .into_iter()
+                .map(|session| SessionView { current: Some(session.id) == current, session })
+                .collect();
+            HttpResponse::Ok().json(serde_json::json!({
+                "sessions": sessions
+            }))
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+/// End one session. Sessions of other subjects are not found unless
+/// `any_subject`.
+async fn revoke_one(state: &AppState, req: &HttpRequest, principal: &Principal, id: Uuid, any_subject: bool) -> HttpResponse {
+    let session = match state.sessions.get(id).await {
+        Ok(session) if any_subject || session.subject == principal.subject => session,
+        Ok(_) => return error_response(SecurityError::NotFound(format!("No session {}", id))),
+        Err(e) => return error_response(e),
+    };
+    if session.ended_at.is_some() {
+        return error_response(SecurityError::Conflict(format!("Session {} has already ended", id)));
+    }
+
+    let revoked = match state.tokens.end_session(id, "revoked", &principal.subject).await {
+        Ok(revoked) => revoked,
+        Err(e) => return error_response(e),
+    };
+    audit(state, &principal.subject, client_ip(req), "auth.session.revoke", &session, serde_json::json!({
+        "subject": session.subject,
+        "revoked_access_tokens": revoked
+    })).await;
+
+    match state.sessions.get(id).await {
+        Ok(session) => HttpResponse::Ok().json(session),
+        Err(e) => error_response(e),
+    }
+}
+
+/// End every session of `subject`, except `keep`.
+async fn revoke_all(state: &AppState, req: &HttpRequest, principal: &Principal, subject: &str, keep: Option<Uuid>) -> HttpResponse {
+    match state.tokens.end_sessions(subject, keep, "revoked", &principal.subject).await {
+        Ok((sessions, revoked)) => {
+            let recorded = state.audit_service.record(NewAuditEvent {
+                tenant_id: principal.tenant_id.clone(),
+                actor: principal.subject.clone(),
+                actor_ip: client_ip(req),
+                action: "auth.session.revoke_all".to_string(),
+                resource: format!("subject:{}", subject),
+                outcome: "success".to_string(),
+                payload: serde_json::json!({
+                    "sessions": sessions,
+                    "kept": keep,
+                    "revoked_access_tokens": revoked
+                }),
+            }).await;
+            if let Err(e) = recorded {
+                warn!("Failed to audit revocation of {}'s sessions: {:?}", subject, e);
+            }
+            HttpResponse::Ok().json(serde_json::json!({
+                "revoked": sessions
+            }))
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+/// The session the caller's token belongs to.
+async fn current(state: &AppState, principal: &Principal) -> Result<Option<Uuid>, SecurityError> {
+    match &principal.token_id {
+        Some(jti) => state.sessions.of_token(jti).await,
+        None => Ok(None),
+    }
+}
+
+pub async fn list_own_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match current(&state, &principal).await {
+        Ok(current) => Ok(list(&state, &principal.subject, current).await),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn revoke_own_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    Ok(revoke_one(&state, &req, &principal, path.into_inner(), false).await)
+}
+
+pub async fn revoke_all_own_handler(
+    req: HttpRequest,
+    query: web::Query<RevokeAllQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let keep = if query.keep_current {
+        match current(&state, &principal).await {
+            Ok(current) => current,
+            Err(e) => return Ok(error_response(e)),
+        }
+    } else {
+        None
+    };
+
+    let subject = principal.subject.clone();
+    Ok(revoke_all(&state, &req, &principal, &subject, keep).await)
+}
+
+pub async fn admin_list_handler(
+    req: HttpRequest,
+    query: web::Query<SubjectQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    Ok(list(&state, &query.subject, None).await)
+}
+
+pub async fn admin_revoke_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    Ok(revoke_one(&state, &req, &principal, path.into_inner(), true).await)
+}
+
+/// Log a subject out everywhere.
+pub async fn admin_revoke_all_handler(
+    req: HttpRequest,
+    query: web::Query<SubjectQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    Ok(revoke_all(&state, &req, &principal, &query.subject, None).await)
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/sessions")
+            .route("", web::get().to(admin_list_handler))
+            .route("", web::delete().to(admin_revoke_all_handler))
+            .route("/{id}", web::delete().to(admin_revoke_handler))
+    );
+}
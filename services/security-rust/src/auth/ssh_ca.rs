@@ -0,0 +1,1119 @@
+/*!
+SSH Certificate Authority
+Short-lived OpenSSH certificates for ops access to COTAI hosts
+
+Hosts trust the CA instead of per-user `authorized_keys`: `GET
+/auth/ssh/trust` returns the user CA keys for sshd's `TrustedUserCAKeys`
+and the host CA keys as `@cert-authority` lines for `known_hosts`. Both
+CAs are Ed25519 keys generated at startup, sealed by the crypto service.
+
+Operators get a user certificate in two steps, each with their own bearer
+token:
+
+- `POST /auth/ssh/certificates` with `{public_key, reason, ttl_secs}`
+  sends a one-time code to the operator's registered destination (see
+  `otp`)
+- `POST /auth/ssh/certificates/{id}/verify` with `{code}` signs the key
+
+Its principals come from the operator's roles, just-in-time grants
+included (see `elevation`), through `SSH_CA_ROLE_PRINCIPALS`, e.g.
+`ops=deploy,super_admin=deploy:root`; callers whose roles map to none are
+refused. It is valid for `ttl_secs`, at most `SSH_CA_USER_CERT_TTL_SECS`,
+and carries `SSH_CA_USER_EXTENSIONS`. Holders of `SSH_CA_ADMIN_ROLES`
+register operators' destinations under `/admin/ssh-ca/operators`, list
+certificates and rotate the CA keys; a replaced key stays trusted until
+the certificates it signed have expired.
+
+Host provisioning, holding `SSH_CA_HOST_SCOPE`, gets host certificates
+with `POST /admin/ssh-ca/host-certificates` and `{public_key, hostnames}`,
+valid for `SSH_CA_HOST_CERT_TTL_SECS`. Requests, codes, issuance,
+registrations and rotations are audited.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use ring::digest;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, Transaction};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::NewAuditEvent;
+use crate::auth::otp::{self, StartRequest, Verification};
+use crate::auth::sessions::SYSTEM_ACTOR;
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, SshCaConfig};
+use crate::crypto::{CryptoService, DecryptionRequest, EncryptionRequest};
+use crate::delivery::Channel;
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+use crate::AppState;
+
+pub const USER: &str = "user";
+pub const HOST: &str = "host";
+
+const CA_KEY_TYPE: &str = "ssh-ed25519";
+
+/// Key types certificates are issued for.
+const KEY_TYPES: &[&str] = &[
+    "ssh-ed25519",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "ecdsa-sha2-nistp521",
+    "ssh-rsa",
+];
+
+const MIN_RSA_BITS: usize = 2048;
+
+/// How far back validity starts, for hosts with a slow clock.
+const BACKDATE_SECS: i64 = 300;
+
+const KEY_COLUMNS: &str = "id, kind, public_key, created_by, created_at, retire_at";
+
+const OPERATOR_COLUMNS: &str = "subject, channel, destination_hint, created_by, created_at";
+
+const CERTIFICATE_COLUMNS: &str = "id, serial, kind, subject, principals, fingerprint, status, reason, client_ip, \
+    challenge_id, ca_key_id, requested_at, valid_after, valid_before, issued_at";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct CaKey {
+    pub id: Uuid,
+    /// `user` or `host`.
+    pub kind: String,
+    /// OpenSSH public key line.
+    pub public_key: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    /// When a replaced key stops being trusted.
+    pub retire_at: Option<DateTime<Utc>>,
+}
+
+#[derive(FromRow)]
+struct SealedCaKey {
+    id: Uuid,
+    secret_key_id: String,
+    secret_nonce: Vec<u8>,
+    secret_ciphertext: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Operator {
+    pub subject: String,
+    pub channel: String,
+    /// Masked destination of their one-time codes.
+    pub destination_hint: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(FromRow)]
+struct SealedOperator {
+    channel: String,
+    encrypted_destination: String,
+    key_id: String,
+    nonce: String,
+    context_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Certificate {
+    pub id: Uuid,
+    /// Assigned at issuance.
+    pub serial: Option<i64>,
+    /// `user` or `host`.
+    pub kind: String,
+    /// The operator, or the host provisioning principal.
+    pub subject: String,
+    /// Login names, or host names.
+    pub principals: Vec<String>,
+    /// SHA256 fingerprint of the certified key.
+    pub fingerprint: String,
+    /// `pending_mfa`, `issued` or `failed`.
+    pub status: String,
+    pub reason: Option<String>,
+    pub client_ip: Option<String>,
+    pub challenge_id: Option<Uuid>,
+    pub ca_key_id: Option<Uuid>,
+    pub requested_at: DateTime<Utc>,
+    pub valid_after: Option<DateTime<Utc>>,
+    pub valid_before: Option<DateTime<Utc>>,
+    pub issued_at: Option<DateTime<Utc>>,
+}
+
+#[derive(FromRow)]
+struct PendingCertificate {
+    #[sqlx(flatten)]
+    certificate: Certificate,
+    public_key: String,
+    ttl_secs: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CertificateRequest {
+    /// OpenSSH public key line.
+    pub public_key: String,
+    pub reason: String,
+    #[serde(default)]
+    pub ttl_secs: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HostCertificateRequest {
+    pub public_key: String,
+    pub hostnames: Vec<String>,
+    #[serde(default)]
+    pub ttl_secs: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OperatorRequest {
+    pub subject: String,
+    pub channel: Channel,
+    pub destination: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CertificateFilter {
+    pub subject: Option<String>,
+    pub kind: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// A signed certificate, as the `-cert.pub` file holds it.
+#[derive(Debug, Serialize)]
+pub struct Issued {
+    #[serde(flatten)]
+    pub certificate: Certificate,
+    #[serde(rename = "certificate")]
+    pub line: String,
+}
+
+/// Outcome of checking a request's code.
+pub enum Completion {
+    Issued(Issued),
+    Rejected { reason: &'static str, attempts_remaining: i32, certificate: Certificate },
+}
+
+/// A certificate to be signed.
+struct Draft<'a> {
+    key: PublicKey,
+    public_key: &'a str,
+    kind: &'static str,
+    /// Who it is for, as its key id names them.
+    subject: &'a str,
+    principals: Vec<String>,
+    ttl_secs: i64,
+}
+
+/// A public key from an OpenSSH key line.
+struct PublicKey {
+    key_type: String,
+    /// The wire-format key.
+    blob: Vec<u8>,
+}
+
+impl PublicKey {
+    fn parse(line: &str) -> Result<Self, SecurityError> {
+        let invalid = |msg: &str| SecurityError::ValidationError(format!("public_key: {}", msg));
+        let mut fields = line.split_whitespace();
+        let (Some(key_type), Some(encoded)) = (fields.next(), fields.next()) else {
+            return Err(invalid("must be an OpenSSH public key line"));
+        };
+        if !KEY_TYPES.contains(&key_type) {
+            return Err(invalid(&format!("type must be one of {}", KEY_TYPES.join(", "))));
+        }
+        let blob = base64::decode(encoded).map_err(|_| invalid("is not base64"))?;
+
+        let mut reader = Reader(&blob);
+        if reader.string() != Some(key_type.as_bytes()) {
+            return Err(invalid("does not match its type"));
+        }
+        let valid = match key_type {
+            "ssh-ed25519" => reader.string().is_some_and(|key| key.len() == 32),
+            "ssh-rsa" => {
+                let exponent = reader.string();
+                let modulus = reader.string().map(|n| n.iter().skip_while(|byte| **byte == 0).count() * 8);
+                if modulus.is_some_and(|bits| bits < MIN_RSA_BITS) {
+                    return Err(invalid(&format!("RSA keys must have at least {} bits", MIN_RSA_BITS)));
+                }
+                exponent.is_some() && modulus.is_some()
+            }
+            _ => {
+                let curve = reader.string();
+                curve.is_some_and(|curve| key_type.ends_with(&*String::from_utf8_lossy(curve))) && reader.string().is_some()
+            }
+        };
+        if !valid || !reader.0.is_empty() {
+            return Err(invalid("is malformed"));
+        }
+        Ok(Self { key_type: key_type.to_string(), blob })
+    }
+
+    fn fingerprint(&self) -> String {
+        let hash = digest::digest(&digest::SHA256, &self.blob);
+        format!("SHA256:{}", base64::encode_config(hash.as_ref(), base64::STANDARD_NO_PAD))
+    }
+
+    /// The key's fields after its type, as certificates embed them.
+    fn fields(&self) -> &[u8] {
+        &self.blob[4 + self.key_type.len()..]
+    }
+}
+
+/// Reads SSH wire-format strings.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn string(&mut self) -> Option<&'a [u8]> {
+        let len = u32::from_be_bytes(self.0.get(..4)?.try_into().ok()?) as usize;
+        let value = self.0.get(4..4 + len)?;
+        self.0 = &self.0[4 + len..];
+        Some(value)
+    }
+}
+
+fn put_string(out: &mut Vec<u8>, value: &[u8]) {
+    out.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    out.extend_from_slice(value);
+}
+
+fn ca_blob(public_key: &[u8]) -> Vec<u8> {
+    let mut blob = Vec::new();
+    put_string(&mut blob, CA_KEY_TYPE.as_bytes());
+    put_string(&mut blob, public_key);
+    blob
+}
+
+/// What a certificate for `key` says, before the CA's signature.
+struct Contents<'a> {
+    key: &'a PublicKey,
+    nonce: Vec<u8>,
+    serial: u64,
+    kind: &'a str,
+    key_id: String,
+    principals: &'a [String],
+    valid_after: DateTime<Utc>,
+    valid_before: DateTime<Utc>,
+    /// Sorted, as OpenSSH requires.
+    extensions: &'a BTreeSet<String>,
+}
+
+impl Contents<'_> {
+    /// The certificate signed by `ca`, as an OpenSSH certificate line.
+    fn sign(&self, ca: &Ed25519KeyPair) -> String {
+        let cert_type = format!("{}-cert-v01@openssh.com", self.key.key_type);
+        let mut body = Vec::new();
+        put_string(&mut body, cert_type.as_bytes());
+        put_string(&mut body, &self.nonce);
+        body.extend_from_slice(self.key.fields());
+        body.extend_from_slice(&self.serial.to_be_bytes());
+        body.extend_from_slice(&(if self.kind == HOST { 2u32 } else { 1u32 }).to_be_bytes());
+        put_string(&mut body, self.key_id.as_bytes());
+        let mut principals = Vec::new();
+        for principal in self.principals {
+            put_string(&mut principals, principal.as_bytes());
+        }
+        put_string(&mut body, &principals);
+        body.extend_from_slice(&(self.valid_after.timestamp().max(0) as u64).to_be_bytes());
+        body.extend_from_slice(&(self.valid_before.timestamp().max(0) as u64).to_be_bytes());
+        // No critical options
+        put_string(&mut body, &[]);
+        let mut extensions = Vec::new();
+        for extension in self.extensions {
+            put_string(&mut extensions, extension.as_bytes());
+            put_string(&mut extensions, &[]);
+        }
+        put_string(&mut body, &extensions);
+        // Reserved
+        put_string(&mut body, &[]);
+        put_string(&mut body, &ca_blob(ca.public_key().as_ref()));
+
+        let mut signature = Vec::new();
+        put_string(&mut signature, CA_KEY_TYPE.as_bytes());
+        put_string(&mut signature, ca.sign(&body).as_ref());
+        put_string(&mut body, &signature);
+        format!("{} {} {}", cert_type, base64::encode(&body), self.key_id)
+    }
+}
+
+fn ca_context(id: Uuid) -> HashMap<String, String> {
+    HashMap::from([
+        ("purpose".to_string(), "ssh_ca_key".to_string()),
+        ("key_id".to_string(), id.to_string()),
+    ])
+}
+
+fn destination_context(subject: &str) -> HashMap<String, String> {
+    HashMap::from([
+        ("purpose".to_string(), "ssh_operator_destination".to_string()),
+        ("subject".to_string(), subject.to_string()),
+    ])
+}
+
+fn key_error(e: impl std::fmt::Display) -> SecurityError {
+    SecurityError::CryptoError(format!("SSH CA key: {}", e))
+}
+
+fn valid_kind(kind: &str) -> Result<&'static str, SecurityError> {
+    match kind {
+        USER => Ok(USER),
+        HOST => Ok(HOST),
+        other => Err(SecurityError::ValidationError(format!("kind must be user or host, not '{}'", other))),
+    }
+}
+
+pub struct SshCaService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: SshCaConfig,
+}
+
+impl SshCaService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        info!("SSH CA initialized with {} role mappings", config.ssh_ca.role_principals.len());
+        Ok(Self {
+            storage,
+            clock,
+            config: config.ssh_ca.clone(),
+        })
+    }
+
+    /// The login names `principal`'s roles map to.
+    pub fn principals_for(&self, principal: &Principal) -> Vec<String> {
+        self.config
+            .role_principals
+            .iter()
+            .filter(|(role, _)| principal.roles.contains(role))
+            .flat_map(|(_, logins)| logins.split(':').map(str::trim).filter(|login| !login.is_empty()))
+            .map(String::from)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Keys hosts should trust: the active one and replaced ones still
+    /// backing unexpired certificates.
+    pub async fn trusted_keys(&self) -> Result<Vec<CaKey>, SecurityError> {
+        Ok(sqlx::query_as::<_, CaKey>(&format!(
+            "SELECT {} FROM ssh_ca_keys WHERE retire_at IS NULL OR retire_at > $1 ORDER BY kind, created_at DESC",
+            KEY_COLUMNS
+        ))
+        .bind(self.clock.now())
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    /// Lock `kind`'s keys and load the active one.
+    async fn lock(&self, tx: &mut Transaction<'static, Postgres>, kind: &str) -> Result<Option<SealedCaKey>, SecurityError> {
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+            .bind(format!("ssh_ca:{}", kind))
+            .execute(&mut **tx)
+            .await?;
+        Ok(sqlx::query_as::<_, SealedCaKey>(
+            "SELECT id, secret_key_id, secret_nonce, secret_ciphertext FROM ssh_ca_keys \
+             WHERE kind = $1 AND retire_at IS NULL",
+        )
+        .bind(kind)
+        .fetch_optional(&mut **tx)
+        .await?)
+    }
+
+    async fn generate(
+        &self,
+        tx: &mut Transaction<'static, Postgres>,
+        crypto: &CryptoService,
+        kind: &str,
+        actor: &str,
+    ) -> Result<SealedCaKey, SecurityError> {
+        let id = Uuid::new_v4();
+        let seed = crypto.secure_random(32).await?;
+        let pair = Ed25519KeyPair::from_seed_unchecked(&seed).map_err(key_error)?;
+        let public_key = format!(
+            "{} {} cotai-{}-ca",
+            CA_KEY_TYPE,
+            base64::encode(ca_blob(pair.public_key().as_ref())),
+            kind
+        );
+        let sealed = crypto.encrypt_bytes(seed, None, Some(&ca_context(id)))?;
+        sqlx::query(
+            "INSERT INTO ssh_ca_keys (id, kind, public_key, secret_key_id, secret_nonce, secret_ciphertext, \
+             created_by, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(id)
+        .bind(kind)
+        .bind(&public_key)
+        .bind(&sealed.key_id)
+        .bind(&sealed.nonce)
+        .bind(&sealed.ciphertext)
+        .bind(actor)
+        .bind(self.clock.now())
+        .execute(&mut **tx)
+        .await?;
+        info!("Generated SSH {} CA key {}", kind, id);
+        Ok(SealedCaKey {
+            id,
+            secret_key_id: sealed.key_id,
+            secret_nonce: sealed.nonce,
+            secret_ciphertext: sealed.ciphertext,
+        })
+    }
+
+    fn open(&self, crypto: &CryptoService, key: &SealedCaKey) -> Result<Ed25519KeyPair, SecurityError> {
+        let context_hash = crypto.context_hash(Some(&ca_context(key.id)))?;
+        let seed = crypto.decrypt_bytes(
+            &key.secret_key_id,
+            &key.secret_nonce,
+            key.secret_ciphertext.clone(),
+            context_hash.as_deref(),
+        )?;
+        Ed25519KeyPair::from_seed_unchecked(&seed).map_err(key_error)
+    }
+
+    /// Replace `kind`'s CA key. The old one stays trusted for as long as
+    /// certificates it signed may last.
+    pub async fn rotate(&self, crypto: &CryptoService, principal: &Principal, kind: &str) -> Result<CaKey, SecurityError> {
+        let kind = valid_kind(kind)?;
+        let mut tx = self.storage.begin().await?;
+        if let Some(current) = self.lock(&mut tx, kind).await? {
+            let lifetime = if kind == HOST { self.config.host_cert_ttl_secs } else { self.config.user_cert_ttl_secs };
+            sqlx::query("UPDATE ssh_ca_keys SET retire_at = $2 WHERE id = $1")
+                .bind(current.id)
+                .bind(self.clock.now() + Duration::seconds(lifetime + BACKDATE_SECS))
+                .execute(&mut *tx)
+                .await?;
+        }
+        let key = self.generate(&mut tx, crypto, kind, &principal.subject).await?;
+        let key = sqlx::query_as::<_, CaKey>(&format!("SELECT {} FROM ssh_ca_keys WHERE id = $1", KEY_COLUMNS))
+            .bind(key.id)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(key)
+    }
+
+    /// Make sure both CAs have a key, so the trust bundle is complete
+    /// before the first certificate is asked for.
+    pub async fn ensure_keys(&self, crypto: &CryptoService) -> Result<(), SecurityError> {
+        for kind in [USER, HOST] {
+            let mut tx = self.storage.begin().await?;
+            if self.lock(&mut tx, kind).await?.is_none() {
+                self.generate(&mut tx, crypto, kind, SYSTEM_ACTOR).await?;
+            }
+            tx.commit().await?;
+        }
+        Ok(())
+    }
+
+    /// Sign a certificate, recording it as issued.
+    async fn sign(&self, crypto: &CryptoService, id: Uuid, draft: &Draft<'_>) -> Result<Issued, SecurityError> {
+        let kind = draft.kind;
+        let mut tx = self.storage.begin().await?;
+        let ca = match self.lock(&mut tx, kind).await? {
+            Some(ca) => ca,
+            None => self.generate(&mut tx, crypto, kind, SYSTEM_ACTOR).await?,
+        };
+        let pair = self.open(crypto, &ca)?;
+        let serial: i64 = sqlx::query_scalar("SELECT nextval('ssh_certificate_serials')")
+            .fetch_one(&mut *tx)
+            .await?;
+        let now = self.clock.now();
+        let extensions = if kind == USER { self.config.user_extensions.iter().cloned().collect() } else { BTreeSet::new() };
+        let contents = Contents {
+            key: &draft.key,
+            nonce: crypto.secure_random(32).await?,
+            serial: serial as u64,
+            kind,
+            key_id: format!("{}:{}", draft.subject, serial),
+            principals: &draft.principals,
+            valid_after: now - Duration::seconds(BACKDATE_SECS),
+            valid_before: now + Duration::seconds(draft.ttl_secs),
+            extensions: &extensions,
+        };
+        let line = contents.sign(&pair);
+
+        let certificate = sqlx::query_as::<_, Certificate>(&format!(
+            "UPDATE ssh_certificates SET serial = $2, status = 'issued', ca_key_id = $3, valid_after = $4, \
+             valid_before = $5, issued_at = $6 WHERE id = $1 RETURNING {}",
+            CERTIFICATE_COLUMNS
+        ))
+        .bind(id)
+        .bind(serial)
+        .bind(ca.id)
+        .bind(contents.valid_after)
+        .bind(contents.valid_before)
+        .bind(now)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(Issued { certificate, line })
+    }
+
+    async fn insert(&self, draft: &Draft<'_>, reason: Option<&str>, ip: Option<String>) -> Result<Uuid, SecurityError> {
+        let id = Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO ssh_certificates (id, kind, subject, principals, public_key, fingerprint, ttl_secs, \
+             status, reason, client_ip, requested_at) VALUES ($1, $2, $3, $4, $5, $6, $7, 'pending_mfa', $8, $9, $10)",
+        )
+        .bind(id)
+        .bind(draft.kind)
+        .bind(draft.subject)
+        .bind(&draft.principals)
+        .bind(draft.public_key.trim())
+        .bind(draft.key.fingerprint())
+        .bind(draft.ttl_secs)
+        .bind(reason)
+        .bind(&ip)
+        .bind(self.clock.now())
+        .execute(self.storage.pool())
+        .await?;
+        Ok(id)
+    }
+
+    pub async fn certificate(&self, id: Uuid) -> Result<Certificate, SecurityError> {
+        sqlx::query_as::<_, Certificate>(&format!("SELECT {} FROM ssh_certificates WHERE id = $1", CERTIFICATE_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound(format!("Certificate request {} not found", id)))
+    }
+
+    pub async fn certificates(&self, filter: &CertificateFilter) -> Result<Vec<Certificate>, SecurityError> {
+        Ok(sqlx::query_as::<_, Certificate>(&format!(
+            "SELECT {} FROM ssh_certificates WHERE ($1::text IS NULL OR subject = $1) \
+             AND ($2::text IS NULL OR kind = $2) ORDER BY requested_at DESC LIMIT $3",
+            CERTIFICATE_COLUMNS
+        ))
+        .bind(&filter.subject)
+        .bind(&filter.kind)
+        .bind(filter.limit.unwrap_or(100).clamp(1, 1000))
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    /// Start a user certificate request, sending its code.
+    pub async fn request(
+        &self,
+        state: &AppState,
+        principal: &Principal,
+        request: &CertificateRequest,
+        ip: Option<String>,
+    ) -> Result<(Certificate, otp::Challenge), SecurityError> {
+        let reason = request.reason.trim();
+        if reason.is_empty() {
+            return Err(SecurityError::ValidationError("reason is required".to_string()));
+        }
+        let ttl_secs = request.ttl_secs.unwrap_or(self.config.user_cert_ttl_secs);
+        if ttl_secs <= 0 || ttl_secs > self.config.user_cert_ttl_secs {
+            return Err(SecurityError::ValidationError(format!(
+                "ttl_secs must be 1-{}",
+                self.config.user_cert_ttl_secs
+            )));
+        }
+        let principals = self.principals_for(principal);
+        if principals.is_empty() {
+            return Err(SecurityError::AccessDenied("None of your roles grants SSH access".to_string()));
+        }
+        let draft = Draft {
+            key: PublicKey::parse(&request.public_key)?,
+            public_key: &request.public_key,
+            kind: USER,
+            subject: &principal.subject,
+            principals,
+            ttl_secs,
+        };
+
+        let operator = sqlx::query_as::<_, SealedOperator>(
+            "SELECT channel, encrypted_destination, key_id, nonce, context_hash FROM ssh_operators WHERE subject = $1",
+        )
+        .bind(&principal.subject)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::AccessDenied("No MFA destination is registered for you".to_string()))?;
+        if state.crypto_service.context_hash(Some(&destination_context(&principal.subject)))? != operator.context_hash {
+            return Err(SecurityError::CryptoError("Destination binding mismatch".to_string()));
+        }
+        let destination = state.crypto_service.decrypt_data(DecryptionRequest {
+            encrypted_data: operator.encrypted_destination,
+            key_id: operator.key_id,
+            nonce: operator.nonce,
+            context_hash: operator.context_hash,
+        }).await?;
+        let channel = Channel::parse(&operator.channel)
+            .ok_or_else(|| SecurityError::ConfigError(format!("Unknown channel '{}'", operator.channel)))?;
+
+        let id = self.insert(&draft, Some(reason), ip).await?;
+        let locale = state.tenant_settings.effective(principal.tenant_id.as_deref()).await.locale;
+        let challenge = state.otp.start(&state.delivery, &state.i18n, &locale, &StartRequest {
+            subject: principal.subject.clone(),
+            tenant_id: principal.tenant_id.clone(),
+            channel,
+            destination,
+            locale: None,
+        }, SYSTEM_ACTOR).await?;
+        sqlx::query("UPDATE ssh_certificates SET challenge_id = $2 WHERE id = $1")
+            .bind(id)
+            .bind(challenge.id)
+            .execute(self.storage.pool())
+            .await?;
+        Ok((self.certificate(id).await?, challenge))
+    }
+
+    async fn fail(&self, id: Uuid) -> Result<Option<Certificate>, SecurityError> {
+        Ok(sqlx::query_as::<_, Certificate>(&format!(
+            "UPDATE ssh_certificates SET status = 'failed' WHERE id = $1 AND status = 'pending_mfa' RETURNING {}",
+            CERTIFICATE_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?)
+    }
+
+    /// Check a request's code; a good one signs the certificate. Only the
+    /// operator who asked may complete it.
+    pub async fn verify(
+        &self,
+        state: &AppState,
+        principal: &Principal,
+        id: Uuid,
+        code: &str,
+    ) -> Result<Completion, SecurityError> {
+        let pending = sqlx::query_as::<_, PendingCertificate>(&format!(
+            "SELECT {}, public_key, ttl_secs FROM ssh_certificates WHERE id = $1 AND kind = 'user'",
+            CERTIFICATE_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .filter(|pending| pending.certificate.subject == principal.subject)
+        .ok_or_else(|| SecurityError::NotFound(format!("Certificate request {} not found", id)))?;
+        let certificate = pending.certificate;
+        let Some(challenge_id) = certificate.challenge_id.filter(|_| certificate.status == "pending_mfa") else {
+            return Err(SecurityError::Conflict(format!("Certificate request is {}", certificate.status)));
+        };
+
+        match state.otp.verify(challenge_id, code).await? {
+            Verification::Verified(_) => {}
+            Verification::Rejected { reason, challenge } => {
+                let attempts_remaining = state.otp.attempts_remaining(&challenge);
+                let certificate = if matches!(reason, "locked" | "expired") || attempts_remaining == 0 {
+                    self.fail(id).await?.unwrap_or(certificate)
+                } else {
+                    certificate
+                };
+                return Ok(Completion::Rejected { reason, attempts_remaining, certificate });
+            }
+        }
+
+        // The roles may have changed since the request
+        let principals = self.principals_for(principal);
+        if principals.is_empty() {
+            self.fail(id).await?;
+            return Err(SecurityError::AccessDenied("None of your roles grants SSH access".to_string()));
+        }
+        sqlx::query("UPDATE ssh_certificates SET principals = $2 WHERE id = $1")
+            .bind(id)
+            .bind(&principals)
+            .execute(self.storage.pool())
+            .await?;
+        let draft = Draft {
+            key: PublicKey::parse(&pending.public_key)?,
+            public_key: &pending.public_key,
+            kind: USER,
+            subject: &principal.subject,
+            principals,
+            ttl_secs: pending.ttl_secs,
+        };
+        Ok(Completion::Issued(self.sign(&state.crypto_service, id, &draft).await?))
+    }
+
+    pub async fn issue_host(
+        &self,
+        crypto: &CryptoService,
+        principal: &Principal,
+        request: &HostCertificateRequest,
+        ip: Option<String>,
+    ) -> Result<Issued, SecurityError> {
+        let ttl_secs = request.ttl_secs.unwrap_or(self.config.host_cert_ttl_secs);
+        if ttl_secs <= 0 || ttl_secs > self.config.host_cert_ttl_secs {
+            return Err(SecurityError::ValidationError(format!(
+                "ttl_secs must be 1-{}",
+                self.config.host_cert_ttl_secs
+            )));
+        }
+        let hostnames: Vec<String> = request
+            .hostnames
+            .iter()
+            .map(|host| host.trim().trim_end_matches('.').to_lowercase())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let valid = |host: &String| {
+            !host.is_empty()
+                && host.len() <= 253
+                && host.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '*' | ':'))
+        };
+        if hostnames.is_empty() || !hostnames.iter().all(valid) {
+            return Err(SecurityError::ValidationError("hostnames must list host names".to_string()));
+        }
+        let draft = Draft {
+            key: PublicKey::parse(&request.public_key)?,
+            public_key: &request.public_key,
+            kind: HOST,
+            subject: &principal.subject,
+            principals: hostnames,
+            ttl_secs,
+        };
+        let id = self.insert(&draft, None, ip).await?;
+        self.sign(crypto, id, &draft).await
+    }
+
+    pub async fn operators(&self) -> Result<Vec<Operator>, SecurityError> {
+        Ok(sqlx::query_as::<_, Operator>(&format!(
+            "SELECT {} FROM ssh_operators ORDER BY subject",
+            OPERATOR_COLUMNS
+        ))
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    /// Register, or replace, where `request.subject`'s codes go.
+    pub async fn set_operator(
+        &self,
+        crypto: &CryptoService,
+        request: &OperatorRequest,
+        created_by: &str,
+    ) -> Result<Operator, SecurityError> {
+        let subject = request.subject.trim();
+        if subject.is_empty() {
+            return Err(SecurityError::ValidationError("subject is required".to_string()));
+        }
+        let destination = otp::normalize(request.channel, &request.destination)?;
+        let sealed = crypto.encrypt_data(EncryptionRequest {
+            data: destination.clone(),
+            key_id: None,
+            context: Some(destination_context(subject)),
+        }).await?;
+        Ok(sqlx::query_as::<_, Operator>(&format!(
+            "INSERT INTO ssh_operators (subject, channel, encrypted_destination, key_id, nonce, context_hash, \
+             destination_hint, created_by, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) \
+             ON CONFLICT (subject) DO UPDATE SET channel = $2, encrypted_destination = $3, key_id = $4, nonce = $5, \
+             context_hash = $6, destination_hint = $7, created_by = $8, created_at = $9 RETURNING {}",
+            OPERATOR_COLUMNS
+        ))
+        .bind(subject)
+        .bind(request.channel.as_str())
+        .bind(&sealed.encrypted_data)
+        .bind(&sealed.key_id)
+        .bind(&sealed.nonce)
+        .bind(&sealed.context_hash)
+        .bind(otp::hint(request.channel, &destination))
+        .bind(created_by)
+        .bind(self.clock.now())
+        .fetch_one(self.storage.pool())
+        .await?)
+    }
+
+    pub async fn remove_operator(&self, subject: &str) -> Result<(), SecurityError> {
+        let removed = sqlx::query("DELETE FROM ssh_operators WHERE subject = $1")
+            .bind(subject)
+            .execute(self.storage.pool())
+            .await?;
+        if removed.rows_affected() == 0 {
+            return Err(SecurityError::NotFound(format!("No SSH operator '{}'", subject)));
+        }
+        Ok(())
+    }
+}
+
+/// Create missing CA keys at startup.
+pub async fn run_setup(state: web::Data<AppState>) {
+    if let Err(e) = state.ssh_ca.ensure_keys(&state.crypto_service).await {
+        error!("SSH CA key setup failed: {:?}", e);
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::AccessDenied(msg) => HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("SSH CA operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "SSH CA operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit(
+    state: &AppState,
+    principal: &Principal,
+    actor_ip: Option<String>,
+    action: &str,
+    resource: String,
+    outcome: &str,
+    payload: serde_json::Value,
+) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: principal.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip,
+        action: action.to_string(),
+        resource,
+        outcome: outcome.to_string(),
+        payload,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {}: {:?}", action, e);
+    }
+}
+
+fn certificate_payload(certificate: &Certificate) -> serde_json::Value {
+    serde_json::json!({
+        "serial": certificate.serial,
+        "principals": certificate.principals,
+        "fingerprint": certificate.fingerprint,
+        "valid_before": certificate.valid_before,
+        "reason": certificate.reason
+    })
+}
+
+pub async fn trust_handler(state: web::Data<AppState>) -> Result<HttpResponse> {
+    match state.ssh_ca.trusted_keys().await {
+        Ok(keys) => {
+            let of_kind = |kind: &str| keys.iter().filter(|key| key.kind == kind).map(|key| key.public_key.clone()).collect::<Vec<_>>();
+            let known_hosts: Vec<String> = of_kind(HOST).iter().map(|key| format!("@cert-authority * {}", key)).collect();
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "user_ca_keys": of_kind(USER),
+                "host_ca_keys": of_kind(HOST),
+                "known_hosts": known_hosts
+            })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn request_handler(
+    req: HttpRequest,
+    request: web::Json<CertificateRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let ip = client_ip(&req);
+    match state.ssh_ca.request(&state, &principal, &request, ip.clone()).await {
+        Ok((certificate, challenge)) => {
+            audit(&state, &principal, ip, "ssh_ca.request", format!("ssh_certificate:{}", certificate.id), "success",
+                certificate_payload(&certificate)).await;
+            Ok(HttpResponse::Accepted().json(serde_json::json!({
+                "request": certificate,
+                "challenge": {
+                    "id": challenge.id,
+                    "channel": challenge.channel,
+                    "destination_hint": challenge.destination_hint,
+                    "expires_at": challenge.expires_at
+                }
+            })))
+        }
+        Err(e) => {
+            if let SecurityError::AccessDenied(msg) = &e {
+                audit(&state, &principal, ip, "ssh_ca.request", format!("ssh_subject:{}", principal.subject), "denied",
+                    serde_json::json!({ "error": msg })).await;
+            }
+            Ok(error_response(e))
+        }
+    }
+}
+
+pub async fn verify_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<VerifyRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let ip = client_ip(&req);
+    match state.ssh_ca.verify(&state, &principal, path.into_inner(), &request.code).await {
+        Ok(Completion::Issued(issued)) => {
+            audit(&state, &principal, ip, "ssh_ca.issue", format!("ssh_certificate:{}", issued.certificate.id), "success",
+                certificate_payload(&issued.certificate)).await;
+            Ok(HttpResponse::Ok().insert_header(("Cache-Control", "no-store")).json(issued))
+        }
+        Ok(Completion::Rejected { reason, attempts_remaining, certificate }) => {
+            audit(&state, &principal, ip, "ssh_ca.verify", format!("ssh_certificate:{}", certificate.id), "failure",
+                serde_json::json!({
+                    "reason": reason,
+                    "attempts_remaining": attempts_remaining
+                })).await;
+            Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": reason,
+                "attempts_remaining": attempts_remaining,
+                "request": certificate
+            })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn host_certificate_handler(
+    req: HttpRequest,
+    request: web::Json<HostCertificateRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.ssh_ca.host_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let ip = client_ip(&req);
+    match state.ssh_ca.issue_host(&state.crypto_service, &principal, &request, ip.clone()).await {
+        Ok(issued) => {
+            audit(&state, &principal, ip, "ssh_ca.host_issue", format!("ssh_certificate:{}", issued.certificate.id), "success",
+                certificate_payload(&issued.certificate)).await;
+            Ok(HttpResponse::Ok().json(issued))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_certificates_handler(
+    req: HttpRequest,
+    filter: web::Query<CertificateFilter>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_roles(&req, &state.config.ssh_ca.admin_roles) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.ssh_ca.certificates(&filter).await {
+        Ok(certificates) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "certificates": certificates
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_keys_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_roles(&req, &state.config.ssh_ca.admin_roles) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.ssh_ca.trusted_keys().await {
+        Ok(keys) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "keys": keys
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn rotate_handler(req: HttpRequest, path: web::Path<String>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_roles(&req, &state.config.ssh_ca.admin_roles) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.ssh_ca.rotate(&state.crypto_service, &principal, &path).await {
+        Ok(key) => {
+            audit(&state, &principal, client_ip(&req), "ssh_ca.rotate", format!("ssh_ca_key:{}", key.id), "success",
+                serde_json::json!({ "kind": key.kind })).await;
+            Ok(HttpResponse::Ok().json(key))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_operators_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_roles(&req, &state.config.ssh_ca.admin_roles) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.ssh_ca.operators().await {
+        Ok(operators) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "operators": operators
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn set_operator_handler(
+    req: HttpRequest,
+    request: web::Json<OperatorRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_roles(&req, &state.config.ssh_ca.admin_roles) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.ssh_ca.set_operator(&state.crypto_service, &request, &principal.subject).await {
+        Ok(operator) => {
+            audit(&state, &principal, client_ip(&req), "ssh_ca.operator.set", format!("ssh_operator:{}", operator.subject),
+                "success", serde_json::json!({ "channel": operator.channel })).await;
+            Ok(HttpResponse::Ok().json(operator))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn remove_operator_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_roles(&req, &state.config.ssh_ca.admin_roles) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let subject = path.into_inner();
+    match state.ssh_ca.remove_operator(&subject).await {
+        Ok(()) => {
+            audit(&state, &principal, client_ip(&req), "ssh_ca.operator.remove", format!("ssh_operator:{}", subject),
+                "success", serde_json::json!({})).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/ssh-ca")
+            .route("/certificates", web::get().to(list_certificates_handler))
+            .route("/host-certificates", web::post().to(host_certificate_handler))
+            .route("/keys", web::get().to(list_keys_handler))
+            .route("/keys/{kind}/rotate", web::post().to(rotate_handler))
+            .route("/operators", web::get().to(list_operators_handler))
+            .route("/operators", web::post().to(set_operator_handler))
+            .route("/operators/{subject}", web::delete().to(remove_operator_handler))
+    );
+}
@@ -0,0 +1,1215 @@
+/*!
+Token Service
+Access and refresh tokens issued by this service
+
+Access tokens are JWTs signed with the `AUTH_TOKEN_ALGORITHM` signing key
+(see `signing`), so anyone holding the JWKS can verify them. They carry a
+`jti`, the tenant's access token TTL and the issuer `AUTH_TOKEN_ISSUER`.
+
+- `POST /auth/tokens` mints a token for a subject, for callers holding
+  `AUTH_ISSUE_SCOPE` (the platform's login flow) or an admin role. Only
+  admins may mint admin roles. With `client_id` the token is for a
+  third-party application and needs the subject's consent (see `consent`).
+- `POST /auth/token` with `grant_type=refresh_token` rotates a refresh
+  token. `client_credentials` is handled by `service_accounts` and token
+  exchange by `exchange`.
+- `POST /auth/revoke` revokes a refresh or access token (RFC 7009).
+- `POST /auth/introspect` reports whether a token is active, with its
+  subject, scopes and expiry (RFC 7662). Admins and holders of
+  `AUTH_INTROSPECT_SCOPE` may introspect any token; `<scope>:access_token`
+  or `<scope>:refresh_token` limit a caller to one type, and tokens of
+  other types come back inactive. Active access tokens are answered with
+  a `max-age` of up to `AUTH_INTROSPECTION_CACHE_SECS`, never past their
+  expiry, so resource servers can cache the result; a revocation may go
+  unseen by a cache for that long. Refresh tokens and inactive results
+  are `no-store`.
+
+Refresh tokens are opaque, stored as SHA-256 hashes, and belong to a
+family started by the first issue. Each use consumes the token and issues
+the next one in the family, which keeps the family's original expiry. A
+consumed token presented again means it leaked: the whole family and the
+access tokens it issued are revoked, and a `refresh_token_reuse` incident
+is opened.
+
+Revoked access tokens are kept by `jti` until they would have expired.
+Each replica checks them on every verification and reloads the list every
+`AUTH_REVOCATION_REFRESH_SECS`, so a revocation made elsewhere takes
+effect within that interval.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use cotai_verify::Actor;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, Transaction};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::alerting::Severity;
+use crate::audit::NewAuditEvent;
+use crate::auth::sessions::{self, Device, Session, Touch, SYSTEM_ACTOR};
+use crate::auth::{auth_error_response, client_ip, exchange, service_accounts, Principal};
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::detection::{self, NewIncident};
+use crate::errors::SecurityError;
+use crate::random::{self, RandomSource};
+use crate::signing::Algorithm;
+use crate::storage::Storage;
+use crate::AppState;
+
+pub const REFRESH_TOKEN_PREFIX: &str = "rt_";
+
+const REFRESH_COLUMNS: &str = "id, family_id, subject, tenant_id, roles, scope, client_id, issued_at, \
+    expires_at, used_at, revoked_at";
+
+/// Claims of the access tokens issued here. A superset of what
+/// `cotai_verify::Claims` reads.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessClaims {
+    pub iss: String,
+    pub sub: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: String,
+    #[serde(rename = "type")]
+    pub token_type: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+    /// The third-party client the token was issued to, see `consent`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+}
+
+/// What a token is issued for.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct IssueRequest {
+    pub subject: String,
+    pub tenant_id: Option<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// Also issue a refresh token.
+    #[serde(default)]
+    pub refresh: bool,
+    /// Issue to this third-party client, which needs the subject's consent
+    /// to every scope.
+    #[serde(default)]
+    pub client_id: Option<Uuid>,
+    /// Set by token exchange only.
+    #[serde(skip)]
+    pub audience: Option<String>,
+    #[serde(skip)]
+    pub act: Option<Actor>,
+    /// Latest `exp` allowed, in Unix seconds.
+    #[serde(skip)]
+    pub not_after: Option<i64>,
+    /// Where the user is logging in from, recorded on the session a
+    /// refresh token starts; defaults to the caller's address and agent.
+    #[serde(default)]
+    pub client_ip: Option<String>,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+}
+
+/// RFC 6749 section 5.1 token response.
+#[derive(Debug, Clone, Serialize)]
+pub struct IssuedTokens {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub scope: String,
+    /// RFC 8693 responses only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issued_token_type: Option<&'static str>,
+    #[serde(skip)]
+    pub claims: Option<AccessClaims>,
+    /// The session a refresh token was issued in, see `sessions`.
+    #[serde(skip)]
+    pub session_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct RefreshToken {
+    id: Uuid,
+    family_id: Uuid,
+    subject: String,
+    tenant_id: Option<String>,
+    roles: Vec<String>,
+    scope: String,
+    client_id: Option<Uuid>,
+    issued_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    used_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+/// Why a refresh token cannot be exchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Refusal {
+    Revoked,
+    /// Already exchanged: a replay, which ends the session.
+    Reused,
+    Expired,
+}
+
+impl RefreshToken {
+    /// Why the token cannot be exchanged at `now`, if it cannot. A replay
+    /// is reported even once the token has expired.
+    fn refusal(&self, now: DateTime<Utc>) -> Option<Refusal> {
+        if self.revoked_at.is_some() {
+            Some(Refusal::Revoked)
+        } else if self.used_at.is_some() {
+            Some(Refusal::Reused)
+        } else if self.expires_at <= now {
+            Some(Refusal::Expired)
+        } else {
+            None
+        }
+    }
+}
+
+/// Form body of `POST /auth/token`.
+#[derive(Debug, Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: String,
+    pub client_assertion_type: Option<String>,
+    pub client_assertion: Option<String>,
+    pub client_id: Option<String>,
+    pub scope: Option<String>,
+    pub refresh_token: Option<String>,
+    pub subject_token: Option<String>,
+    pub subject_token_type: Option<String>,
+    pub actor_token: Option<String>,
+    pub actor_token_type: Option<String>,
+    pub audience: Option<String>,
+    pub requested_token_type: Option<String>,
+}
+
+/// Form body of `POST /auth/revoke` and `POST /auth/introspect`.
+#[derive(Debug, Deserialize)]
+pub struct TokenForm {
+    pub token: String,
+    pub token_type_hint: Option<String>,
+}
+
+/// The token types this service issues, by RFC 7662 `token_type_hint` name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Access,
+    Refresh,
+}
+
+impl TokenKind {
+    pub const ALL: [TokenKind; 2] = [TokenKind::Access, TokenKind::Refresh];
+
+    pub fn of(token: &str) -> Self {
+        if token.starts_with(REFRESH_TOKEN_PREFIX) {
+            TokenKind::Refresh
+        } else {
+            TokenKind::Access
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenKind::Access => "access_token",
+            TokenKind::Refresh => "refresh_token",
+        }
+    }
+}
+
+/// Revoked access token ids and when the tokens expire, checked on every
+/// verification.
+#[derive(Default)]
+pub struct RevocationList {
+    revoked: RwLock<HashMap<String, DateTime<Utc>>>,
+}
+
+impl RevocationList {
+    pub fn contains(&self, jti: &str) -> bool {
+        self.revoked.read().unwrap_or_else(|e| e.into_inner()).contains_key(jti)
+    }
+
+    fn insert(&self, jti: String, expires_at: DateTime<Utc>) {
+        self.revoked.write().unwrap_or_else(|e| e.into_inner()).insert(jti, expires_at);
+    }
+
+    fn replace(&self, revoked: HashMap<String, DateTime<Utc>>) {
+        *self.revoked.write().unwrap_or_else(|e| e.into_inner()) = revoked;
+    }
+}
+
+fn token_hash(token: &str) -> String {
+    hex::encode(digest::digest(&digest::SHA256, token.as_bytes()))
+}
+
+fn split_scope(scope: &str) -> Vec<String> {
+    scope.split_whitespace().map(String::from).collect()
+}
+
+pub struct TokenService {
+    issuer: String,
+    algorithm: Algorithm,
+    refresh_ttl: Duration,
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    rng: Arc<dyn RandomSource>,
+    revocations: Arc<RevocationList>,
+}
+
+impl TokenService {
+    pub async fn new(
+        config: &Config,
+        storage: Storage,
+        clock: Arc<dyn Clock>,
+        rng: Arc<dyn RandomSource>,
+        revocations: Arc<RevocationList>,
+    ) -> Result<Self, SecurityError> {
+        let algorithm = Algorithm::parse(&config.auth.token_algorithm)
+            .filter(|a| *a != Algorithm::Hs256)
+            .ok_or_else(|| SecurityError::ConfigError("Unsupported token signing algorithm".to_string()))?;
+
+        info!("Token service initialized successfully");
+        Ok(Self {
+            issuer: config.auth.token_issuer.clone(),
+            algorithm,
+            refresh_ttl: Duration::seconds(config.auth.refresh_token_ttl_secs),
+            storage,
+            clock,
+            rng,
+            revocations,
+        })
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// Sign an access token for `request` with the tenant's TTL.
+    async fn access_token(&self, state: &AppState, request: &IssueRequest) -> Result<(String, AccessClaims), SecurityError> {
+        let ttl = state.tenant_settings.effective(request.tenant_id.as_deref()).await.access_token_ttl_secs;
+        let now = self.clock.now().timestamp();
+        let claims = AccessClaims {
+            iss: self.issuer.clone(),
+            sub: request.subject.clone(),
+            iat: now,
+            exp: request.not_after.map_or(now + ttl, |not_after| not_after.min(now + ttl)),
+            jti: random::uuid_v4(self.rng.as_ref())?.to_string(),
+            token_type: "access".to_string(),
+            roles: request.roles.clone(),
+            tenant_id: request.tenant_id.clone(),
+            scope: Some(request.scopes.join(" ")).filter(|s| !s.is_empty()),
+            aud: request.audience.clone(),
+            act: request.act.clone(),
+            client_id: request.client_id.map(|id| id.to_string()),
+        };
+        let token = state.crypto_service.signing_keys().sign_jwt(self.algorithm, &claims)?;
+        Ok((token, claims))
+    }
+
+    /// Issue an access token, and a refresh token starting a new family if
+    /// `request.refresh` is set.
+    pub async fn issue(&self, state: &AppState, request: &IssueRequest) -> Result<IssuedTokens, SecurityError> {
+        if request.subject.trim().is_empty() {
+            return Err(SecurityError::ValidationError("subject is required".to_string()));
+        }
+        if let Some(client_id) = request.client_id {
+            state.consents.check(client_id, &request.subject, &request.scopes).await?;
+        }
+        let (access_token, claims) = self.access_token(state, request).await?;
+
+        let (refresh_token, session_id) = if request.refresh {
+            let mut tx = self.storage.begin().await?;
+            let scope = request.scopes.join(" ");
+            let now = self.clock.now();
+            let expires_at = (now + self.refresh_ttl).min(state.sessions.expiry(now));
+            let session_id = Uuid::new_v4();
+            let token = self.store_refresh(&mut tx, session_id, &claims, &scope, request.client_id, expires_at).await?;
+            let device = Device { ip: request.client_ip.clone(), user_agent: request.user_agent.clone() };
+            state.sessions.start(
+                &mut tx,
+                session_id,
+                &request.subject,
+                request.tenant_id.as_deref(),
+                request.client_id,
+                &device,
+                expires_at,
+            ).await?;
+            tx.commit().await?;
+            (Some(token), Some(session_id))
+        } else {
+            (None, None)
+        };
+
+        Ok(IssuedTokens {
+            access_token,
+            token_type: "Bearer",
+            expires_in: claims.exp - claims.iat,
+            refresh_token,
+            scope: claims.scope.clone().unwrap_or_default(),
+            issued_token_type: None,
+            claims: Some(claims),
+            session_id,
+        })
+    }
+
+    async fn store_refresh(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        family_id: Uuid,
+        access: &AccessClaims,
+        scope: &str,
+        client_id: Option<Uuid>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<String, SecurityError> {
+        let mut secret = [0u8; 32];
+        self.rng.fill(&mut secret)?;
+        let token = format!("{}{}", REFRESH_TOKEN_PREFIX, base64::encode_config(secret, base64::URL_SAFE_NO_PAD));
+
+        sqlx::query(
+            "INSERT INTO refresh_tokens \
+             (id, family_id, token_hash, subject, tenant_id, roles, scope, client_id, access_token_id, access_expires_at, \
+              issued_at, expires_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+        )
+        .bind(Uuid::new_v4())
+        .bind(family_id)
+        .bind(token_hash(&token))
+        .bind(&access.sub)
+        .bind(&access.tenant_id)
+        .bind(&access.roles)
+        .bind(scope)
+        .bind(client_id)
+        .bind(&access.jti)
+        .bind(Utc.timestamp_opt(access.exp, 0).single().unwrap_or_else(|| self.clock.now()))
+        .bind(self.clock.now())
+        .bind(expires_at)
+        .execute(&mut **tx)
+        .await?;
+        Ok(token)
+    }
+
+    /// Exchange a refresh token for a new pair. `scope` may narrow the
+    /// access token; the new refresh token keeps the family's scope.
+    pub async fn refresh(
+        &self,
+        state: &AppState,
+        token: &str,
+        scope: Option<&str>,
+        device: &Device,
+    ) -> Result<IssuedTokens, SecurityError> {
+        let now = self.clock.now();
+        let mut tx = self.storage.begin().await?;
+        let row = sqlx::query_as::<_, RefreshToken>(&format!(
+            "SELECT {} FROM refresh_tokens WHERE token_hash = $1 FOR UPDATE",
+            REFRESH_COLUMNS
+        ))
+        .bind(token_hash(token))
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| SecurityError::AuthError("Unknown refresh token".to_string()))?;
+
+        match row.refusal(now) {
+            Some(Refusal::Revoked) => return Err(SecurityError::AuthError("Refresh token revoked".to_string())),
+            Some(Refusal::Reused) => {
+                self.handle_reuse(state, tx, &row).await?;
+                return Err(SecurityError::AuthError("Refresh token reuse detected; the session is revoked".to_string()));
+            }
+            Some(Refusal::Expired) => return Err(SecurityError::AuthError("Refresh token expired".to_string())),
+            None => {}
+        }
+        if let Touch::Idle(session) = state.sessions.touch(&mut tx, row.family_id, device).await? {
+            self.end_idle(state, tx, &session).await?;
+            return Err(SecurityError::AuthError("Session expired after inactivity".to_string()));
+        }
+
+        let granted = split_scope(&row.scope);
+        let scopes = match scope {
+            Some(requested) => {
+                let requested = split_scope(requested);
+                if let Some(extra) = requested.iter().find(|s| !granted.contains(s)) {
+                    return Err(SecurityError::AccessDenied(format!("Scope '{}' was not granted", extra)));
+                }
+                requested
+            }
+            None => granted,
+        };
+        let principal = Principal {
+            subject: row.subject.clone(),
+            tenant_id: row.tenant_id.clone(),
+            roles: row.roles.clone(),
+            scopes: scopes.clone(),
+            token_id: None,
+            delegation: Vec::new(),
+        };
+        state.auth_service.check_containment(&principal, None)?;
+        if let Some(client_id) = row.client_id {
+            state.consents.check(client_id, &row.subject, &principal.scopes).await.map_err(|e| match e {
+                SecurityError::AccessDenied(msg) => SecurityError::AuthError(msg),
+                e => e,
+            })?;
+        }
+
+        sqlx::query("UPDATE refresh_tokens SET used_at = $2 WHERE id = $1")
+            .bind(row.id)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+
+        let request = IssueRequest {
+            subject: row.subject.clone(),
+            tenant_id: row.tenant_id.clone(),
+            roles: row.roles.clone(),
+            scopes,
+            refresh: true,
+            client_id: row.client_id,
+            ..Default::default()
+        };
+        let (access_token, claims) = self.access_token(state, &request).await?;
+        let refresh_token = self.store_refresh(&mut tx, row.family_id, &claims, &row.scope, row.client_id, row.expires_at).await?;
+        tx.commit().await?;
+
+        Ok(IssuedTokens {
+            access_token,
+            token_type: "Bearer",
+            expires_in: claims.exp - claims.iat,
+            refresh_token: Some(refresh_token),
+            scope: claims.scope.clone().unwrap_or_default(),
+            issued_token_type: None,
+            claims: Some(claims),
+            session_id: Some(row.family_id),
+        })
+    }
+
+    /// A session went idle before this refresh: end it, committing `tx`.
+    async fn end_idle(&self, state: &AppState, mut tx: Transaction<'static, Postgres>, session: &Session) -> Result<(), SecurityError> {
+        let now = self.clock.now();
+        let revoked = self.revoke_family(&mut tx, session.id, "idle_timeout", SYSTEM_ACTOR, now).await?;
+        tx.commit().await?;
+        let count = revoked.len();
+        for (jti, expires_at) in revoked {
+            self.revocations.insert(jti, expires_at);
+        }
+        sessions::audit(state, SYSTEM_ACTOR, None, "auth.session.expire", session, serde_json::json!({
+            "subject": session.subject,
+            "reason": "idle_timeout",
+            "last_seen_at": session.last_seen_at,
+            "revoked_access_tokens": count
+        })).await;
+        Ok(())
+    }
+
+    /// A consumed refresh token came back: revoke its family and open an
+    /// incident, committing `tx`.
+    async fn handle_reuse(&self, state: &AppState, mut tx: Transaction<'static, Postgres>, row: &RefreshToken) -> Result<(), SecurityError> {
+        let now = self.clock.now();
+        let revoked = self.revoke_family(&mut tx, row.family_id, "reuse_detected", SYSTEM_ACTOR, now).await?;
+        warn!("Refresh token reuse for {} in family {}; revoked {} access tokens", row.subject, row.family_id, revoked.len());
+
+        let audit_id = state.audit_service.record(NewAuditEvent {
+            tenant_id: row.tenant_id.clone(),
+            actor: row.subject.clone(),
+            actor_ip: None,
+            action: "auth.refresh_token.reuse".to_string(),
+            resource: format!("refresh_token_family:{}", row.family_id),
+            outcome: "failure".to_string(),
+            payload: serde_json::json!({
+                "first_used_at": row.used_at,
+                "revoked_access_tokens": revoked.len()
+            }),
+        }).await;
+        let event_ids = match audit_id {
+            Ok(id) => vec![id],
+            Err(e) => {
+                warn!("Failed to audit refresh token reuse in family {}: {:?}", row.family_id, e);
+                Vec::new()
+            }
+        };
+
+        let opened = detection::open(state, &mut tx, NewIncident {
+            rule: "refresh_token_reuse".to_string(),
+            severity: Severity::High,
+            tenant_id: row.tenant_id.clone(),
+            entity_type: "actor",
+            entity: row.subject.clone(),
+            event_ids,
+            first_seen: row.used_at.unwrap_or(now),
+            last_seen: now,
+        }).await?;
+        tx.commit().await?;
+
+        for (jti, expires_at) in revoked {
+            self.revocations.insert(jti, expires_at);
+        }
+        detection::announce(state, &[opened]).await;
+        Ok(())
+    }
+
+    /// Revoke every live token of a family and the unexpired access tokens
+    /// issued with them, ending its session. Returns the access tokens revoked.
+    async fn revoke_family(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        family_id: Uuid,
+        reason: &str,
+        actor: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<(String, DateTime<Utc>)>, SecurityError> {
+        sqlx::query(
+            "UPDATE refresh_tokens SET revoked_at = $2, revoked_reason = $3 \
+             WHERE family_id = $1 AND revoked_at IS NULL",
+        )
+        .bind(family_id)
+        .bind(now)
+        .bind(reason)
+        .execute(&mut **tx)
+        .await?;
+
+        sqlx::query(
+            "UPDATE sessions SET ended_at = $2, end_reason = $3, ended_by = $4 WHERE id = $1 AND ended_at IS NULL",
+        )
+        .bind(family_id)
+        .bind(now)
+        .bind(reason)
+        .bind(actor)
+        .execute(&mut **tx)
+        .await?;
+
+        let issued: Vec<(String, String, DateTime<Utc>)> = sqlx::query_as(
+            "SELECT access_token_id, subject, access_expires_at FROM refresh_tokens \
+             WHERE family_id = $1 AND access_expires_at > $2",
+        )
+        .bind(family_id)
+        .bind(now)
+        .fetch_all(&mut **tx)
+        .await?;
+
+        for (jti, subject, expires_at) in &issued {
+            insert_revoked(tx, jti, subject, *expires_at, reason, actor).await?;
+        }
+        Ok(issued.into_iter().map(|(jti, _, expires_at)| (jti, expires_at)).collect())
+    }
+
+    /// Revoke a refresh token's family or a single access token. Unknown
+    /// and invalid tokens are ignored, as RFC 7009 requires.
+    pub async fn revoke(&self, state: &AppState, token: &str, actor: &str) -> Result<bool, SecurityError> {
+        let now = self.clock.now();
+        if token.starts_with(REFRESH_TOKEN_PREFIX) {
+            let mut tx = self.storage.begin().await?;
+            let family_id: Option<Uuid> = sqlx::query_scalar("SELECT family_id FROM refresh_tokens WHERE token_hash = $1")
+                .bind(token_hash(token))
+                .fetch_optional(&mut *tx)
+                .await?;
+            let Some(family_id) = family_id else { return Ok(false) };
+            let revoked = self.revoke_family(&mut tx, family_id, "revoked", actor, now).await?;
+            tx.commit().await?;
+            for (jti, expires_at) in revoked {
+                self.revocations.insert(jti, expires_at);
+            }
+            return Ok(true);
+        }
+
+        let Ok(principal) = state.auth_service.verify_issued(token) else { return Ok(false) };
+        let (Some(jti), Some(claims)) = (principal.token_id.clone(), payload(token)) else { return Ok(false) };
+        let expires_at = claims.get("exp")
+            .and_then(|exp| exp.as_i64())
+            .and_then(|exp| Utc.timestamp_opt(exp, 0).single())
+            .unwrap_or(now);
+
+        let mut tx = self.storage.begin().await?;
+        insert_revoked(&mut tx, &jti, &principal.subject, expires_at, "revoked", actor).await?;
+        tx.commit().await?;
+        self.revocations.insert(jti, expires_at);
+        Ok(true)
+    }
+
+    /// Revoke access tokens by `jti`, for callers that kept track of what
+    /// they issued.
+    pub async fn revoke_access(
+        &self,
+        tokens: &[(String, DateTime<Utc>)],
+        subject: &str,
+        reason: &str,
+        actor: &str,
+    ) -> Result<(), SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        for (jti, expires_at) in tokens {
+            insert_revoked(&mut tx, jti, subject, *expires_at, reason, actor).await?;
+        }
+        tx.commit().await?;
+        for (jti, expires_at) in tokens {
+            self.revocations.insert(jti.clone(), *expires_at);
+        }
+        Ok(())
+    }
+
+    /// Revoke the refresh token families `subject` has given `client_id`,
+    /// after consent is withdrawn. Returns how many access tokens were revoked.
+    pub async fn revoke_client(&self, subject: &str, client_id: Uuid, actor: &str) -> Result<usize, SecurityError> {
+        let now = self.clock.now();
+        let mut tx = self.storage.begin().await?;
+        let families: Vec<Uuid> = sqlx::query_scalar(
+            "SELECT DISTINCT family_id FROM refresh_tokens \
+             WHERE subject = $1 AND client_id = $2 AND revoked_at IS NULL",
+        )
+        .bind(subject)
+        .bind(client_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut revoked = Vec::new();
+        for family_id in families {
+            revoked.extend(self.revoke_family(&mut tx, family_id, "consent_revoked", actor, now).await?);
+        }
+        tx.commit().await?;
+
+        let count = revoked.len();
+        for (jti, expires_at) in revoked {
+            self.revocations.insert(jti, expires_at);
+        }
+        Ok(count)
+    }
+
+    /// End a session: revoke its refresh token family and the access tokens
+    /// issued in it. Returns how many access tokens were revoked.
+    pub async fn end_session(&self, session_id: Uuid, reason: &str, actor: &str) -> Result<usize, SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let revoked = self.revoke_family(&mut tx, session_id, reason, actor, self.clock.now()).await?;
+        tx.commit().await?;
+
+        let count = revoked.len();
+        for (jti, expires_at) in revoked {
+            self.revocations.insert(jti, expires_at);
+        }
+        Ok(count)
+    }
+
+    /// End every session of `subject` but `keep`, including refresh token
+    /// families from before sessions were tracked. Returns the sessions
+    /// ended and how many access tokens were revoked.
+    pub async fn end_sessions(
+        &self,
+        subject: &str,
+        keep: Option<Uuid>,
+        reason: &str,
+        actor: &str,
+    ) -> Result<(Vec<Uuid>, usize), SecurityError> {
+        let now = self.clock.now();
+        let mut tx = self.storage.begin().await?;
+        let families: Vec<Uuid> = sqlx::query_scalar(
+            "SELECT id FROM sessions WHERE subject = $1 AND ended_at IS NULL \
+             UNION SELECT family_id FROM refresh_tokens WHERE subject = $1 AND revoked_at IS NULL AND expires_at > $2",
+        )
+        .bind(subject)
+        .bind(now)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let families: Vec<Uuid> = families.into_iter().filter(|id| Some(*id) != keep).collect();
+        let mut revoked = Vec::new();
+        for family_id in &families {
+            revoked.extend(self.revoke_family(&mut tx, *family_id, reason, actor, now).await?);
+        }
+        tx.commit().await?;
+
+        let count = revoked.len();
+        for (jti, expires_at) in revoked {
+            self.revocations.insert(jti, expires_at);
+        }
+        Ok((families, count))
+    }
+
+    /// Revoke the refresh token families through which `subject` holds
+    /// `role` in `tenant_id`, with the access tokens issued in them. Returns
+    /// how many families were revoked.
+    pub async fn revoke_role(
+        &self,
+        subject: &str,
+        tenant_id: Option<&str>,
+        role: &str,
+        reason: &str,
+        actor: &str,
+    ) -> Result<usize, SecurityError> {
+        let now = self.clock.now();
+        let mut tx = self.storage.begin().await?;
+        let families: Vec<Uuid> = sqlx::query_scalar(
+            "SELECT DISTINCT family_id FROM refresh_tokens \
+             WHERE subject = $1 AND tenant_id IS NOT DISTINCT FROM $2 AND $3 = ANY(roles) \
+             AND revoked_at IS NULL AND expires_at > $4",
+        )
+        .bind(subject)
+        .bind(tenant_id)
+        .bind(role)
+        .bind(now)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut revoked = Vec::new();
+        for family_id in &families {
+            revoked.extend(self.revoke_family(&mut tx, *family_id, reason, actor, now).await?);
+        }
+        tx.commit().await?;
+
+        for (jti, expires_at) in revoked {
+            self.revocations.insert(jti, expires_at);
+        }
+        Ok(families.len())
+    }
+
+    /// RFC 7662 introspection response for `token`. Tokens not of an
+    /// `allowed` kind are reported inactive.
+    pub async fn introspect(&self, state: &AppState, token: &str, allowed: &[TokenKind]) -> Result<serde_json::Value, SecurityError> {
+        let inactive = serde_json::json!({ "active": false });
+        let kind = TokenKind::of(token);
+        if !allowed.contains(&kind) {
+            return Ok(inactive);
+        }
+        if kind == TokenKind::Refresh {
+            let row = sqlx::query_as::<_, RefreshToken>(&format!(
+                "SELECT {} FROM refresh_tokens WHERE token_hash = $1",
+                REFRESH_COLUMNS
+            ))
+            .bind(token_hash(token))
+            .fetch_optional(self.storage.pool())
+            .await?;
+            let Some(row) = row.filter(|row| {
+                row.used_at.is_none() && row.revoked_at.is_none() && row.expires_at > self.clock.now()
+            }) else {
+                return Ok(inactive);
+            };
+            return Ok(serde_json::json!({
+                "active": true,
+                "token_type": kind.as_str(),
+                "sub": row.subject,
+                "tenant_id": row.tenant_id,
+                "scope": row.scope,
+                "client_id": row.client_id,
+                "iss": self.issuer,
+                "iat": row.issued_at.timestamp(),
+                "exp": row.expires_at.timestamp()
+            }));
+        }
+
+        let Ok(principal) = state.auth_service.verify_issued(token) else { return Ok(inactive) };
+        let claims = payload(token).unwrap_or_default();
+        Ok(serde_json::json!({
+            "active": true,
+            "token_type": kind.as_str(),
+            "sub": principal.subject,
+            "tenant_id": principal.tenant_id,
+            "roles": principal.roles,
+            "scope": principal.scopes.join(" "),
+            "jti": principal.token_id,
+            "aud": claims.get("aud"),
+            "act": claims.get("act"),
+            "client_id": claims.get("client_id"),
+            "iss": claims.get("iss"),
+            "iat": claims.get("iat"),
+            "exp": claims.get("exp")
+        }))
+    }
+
+    /// Reload unexpired revocations and drop rows that no longer matter.
+    pub async fn refresh_revocations(&self) -> Result<(), SecurityError> {
+        let now = self.clock.now();
+        let rows: Vec<(String, DateTime<Utc>)> = sqlx::query_as(
+            "SELECT jti, expires_at FROM revoked_tokens WHERE expires_at > $1",
+        )
+        .bind(now)
+        .fetch_all(self.storage.pool())
+        .await?;
+        self.revocations.replace(rows.into_iter().collect());
+
+        sqlx::query("DELETE FROM revoked_tokens WHERE expires_at <= $1")
+            .bind(now)
+            .execute(self.storage.pool())
+            .await?;
+        sqlx::query("DELETE FROM refresh_tokens WHERE expires_at <= $1")
+            .bind(now)
+            .execute(self.storage.pool())
+            .await?;
+        Ok(())
+    }
+}
+
+async fn insert_revoked(
+    tx: &mut Transaction<'_, Postgres>,
+    jti: &str,
+    subject: &str,
+    expires_at: DateTime<Utc>,
+    reason: &str,
+    actor: &str,
+) -> Result<(), SecurityError> {
+    sqlx::query(
+        "INSERT INTO revoked_tokens (jti, subject, expires_at, reason, revoked_by) \
+         VALUES ($1, $2, $3, $4, $5) ON CONFLICT (jti) DO NOTHING",
+    )
+    .bind(jti)
+    .bind(subject)
+    .bind(expires_at)
+    .bind(reason)
+    .bind(actor)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// The claims of an already verified JWT, for members `Principal` drops.
+pub(super) fn payload(token: &str) -> Option<serde_json::Map<String, serde_json::Value>> {
+    let encoded = token.split('.').nth(1)?;
+    let bytes = base64::decode_config(encoded, base64::URL_SAFE_NO_PAD).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// RFC 6749 section 5.2 error bodies, which OAuth client libraries expect.
+pub fn oauth_error(status: actix_web::http::StatusCode, error: &str, description: &str) -> HttpResponse {
+    HttpResponse::build(status)
+        .insert_header(("Cache-Control", "no-store"))
+        .json(serde_json::json!({
+            "error": error,
+            "error_description": description
+        }))
+}
+
+async fn audit(state: &AppState, tenant_id: Option<String>, actor: &str, actor_ip: Option<String>, action: &str, subject: &str, payload: serde_json::Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id,
+        actor: actor.to_string(),
+        actor_ip,
+        action: action.to_string(),
+        resource: format!("token:{}", subject),
+        outcome: "success".to_string(),
+        payload,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} for {}: {:?}", action, subject, e);
+    }
+}
+
+/// Authenticate a caller that needs `scope`; admins pass without it.
+pub(crate) fn authorize_scope(state: &AppState, req: &HttpRequest, scope: &str) -> Result<Principal, SecurityError> {
+    state.auth_service.authorize_scope(req, scope)
+}
+
+// HTTP handlers
+
+pub async fn token_handler(
+    req: HttpRequest,
+    form: web::Form<TokenRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    use actix_web::http::StatusCode;
+
+    let form = form.into_inner();
+    match form.grant_type.as_str() {
+        "client_credentials" => return Ok(service_accounts::client_credentials(&req, form, &state).await),
+        exchange::GRANT_TYPE => return Ok(exchange::token_exchange(&req, form, &state).await),
+        "refresh_token" => {}
+        _ => {
+            return Ok(oauth_error(
+                StatusCode::BAD_REQUEST,
+                "unsupported_grant_type",
+                "Supported grants are client_credentials, refresh_token and token-exchange",
+            ));
+        }
+    }
+    let Some(refresh_token) = form.refresh_token.as_deref() else {
+        return Ok(oauth_error(StatusCode::BAD_REQUEST, "invalid_request", "refresh_token is required"));
+    };
+
+    match state.tokens.refresh(&state, refresh_token, form.scope.as_deref(), &Device::of(&req)).await {
+        Ok(issued) => {
+            if let Some(claims) = &issued.claims {
+                audit(&state, claims.tenant_id.clone(), &claims.sub, client_ip(&req), "auth.token.refresh", &claims.jti, serde_json::json!({
+                    "scope": issued.scope
+                })).await;
+            }
+            Ok(HttpResponse::Ok()
+                .insert_header(("Cache-Control", "no-store"))
+                .json(issued))
+        }
+        Err(SecurityError::AuthError(msg)) => {
+            warn!("Refresh from {:?} refused: {}", client_ip(&req), msg);
+            Ok(oauth_error(StatusCode::BAD_REQUEST, "invalid_grant", &msg))
+        }
+        Err(SecurityError::AccessDenied(msg)) => Ok(oauth_error(StatusCode::BAD_REQUEST, "invalid_scope", &msg)),
+        Err(e) => {
+            error!("Token refresh failed: {:?}", e);
+            Ok(oauth_error(StatusCode::INTERNAL_SERVER_ERROR, "server_error", "Token issuance failed"))
+        }
+    }
+}
+
+pub async fn issue_handler(
+    req: HttpRequest,
+    request: web::Json<IssueRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.auth.issue_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let mut request = request.into_inner();
+    let device = Device::of(&req);
+    request.client_ip = request.client_ip.or(device.ip);
+    request.user_agent = request.user_agent.or(device.user_agent);
+    let admin_roles = &state.config.auth.admin_roles;
+    if request.roles.iter().any(|r| admin_roles.contains(r)) && !principal.has_any_role(admin_roles) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Only admins may issue admin roles"
+        })));
+    }
+    if let Err(e) = state.sod.check_assignment(&state, request.tenant_id.as_deref(), &request.subject, &[], &request.roles).await {
+        return Ok(error_response(e));
+    }
+
+    match state.tokens.issue(&state, &request).await {
+        Ok(issued) => {
+            if let Some(claims) = &issued.claims {
+                audit(&state, claims.tenant_id.clone(), &principal.subject, client_ip(&req), "auth.token.issue", &claims.jti, serde_json::json!({
+                    "subject": claims.sub,
+                    "roles": claims.roles,
+                    "scope": issued.scope,
+                    "refresh": issued.refresh_token.is_some()
+                })).await;
+            }
+            if let Some(session_id) = issued.session_id {
+                match state.sessions.get(session_id).await {
+                    Ok(session) => sessions::audit(&state, &principal.subject, client_ip(&req), "auth.session.create", &session, serde_json::json!({
+                        "subject": session.subject,
+                        "ip": session.ip,
+                        "user_agent": session.user_agent,
+                        "expires_at": session.expires_at
+                    })).await,
+                    Err(e) => warn!("Failed to audit start of session {}: {:?}", session_id, e),
+                }
+            }
+            Ok(HttpResponse::Created()
+                .insert_header(("Cache-Control", "no-store"))
+                .json(issued))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn revoke_handler(
+    req: HttpRequest,
+    form: web::Form<TokenForm>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let actor = state.auth_service.authenticate(&req)
+        .map(|principal| principal.subject)
+        .unwrap_or_else(|_| "anonymous".to_string());
+
+    match state.tokens.revoke(&state, &form.token, &actor).await {
+        Ok(revoked) => {
+            if revoked {
+                audit(&state, None, &actor, client_ip(&req), "auth.token.revoke", &token_hash(&form.token), serde_json::json!({
+                    "token_type_hint": form.token_type_hint
+                })).await;
+            }
+            Ok(HttpResponse::Ok().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn introspect_handler(
+    req: HttpRequest,
+    form: web::Form<TokenForm>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let allowed = introspectable(&state, &principal);
+    if allowed.is_empty() {
+        let e = SecurityError::AccessDenied(format!("{} may not introspect tokens", principal.subject));
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.tokens.introspect(&state, &form.token, &allowed).await {
+        Ok(response) => {
+            let cacheable = response["token_type"] == TokenKind::Access.as_str();
+            let max_age = response["exp"].as_i64()
+                .filter(|_| cacheable)
+                .map(|exp| (exp - state.clock.now().timestamp()).min(state.config.auth.introspection_cache_secs))
+                .filter(|max_age| *max_age > 0);
+            let cache_control = match max_age {
+                Some(max_age) => format!("private, max-age={}", max_age),
+                None => "no-store".to_string(),
+            };
+            Ok(HttpResponse::Ok()
+                .insert_header(("Cache-Control", cache_control))
+                .json(response))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+/// Token types `principal` may introspect: all for admins and holders of
+/// the introspection scope, else those its `<scope>:<token type>` scopes name.
+fn introspectable(state: &AppState, principal: &Principal) -> Vec<TokenKind> {
+    let scope = &state.config.auth.introspect_scope;
+    if principal.has_any_role(&state.config.auth.admin_roles) || principal.scopes.contains(scope) {
+        return TokenKind::ALL.to_vec();
+    }
+    TokenKind::ALL.into_iter()
+        .filter(|kind| principal.scopes.contains(&format!("{}:{}", scope, kind.as_str())))
+        .collect()
+}
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({ "error": msg })),
+        SecurityError::AccessDenied(msg) => HttpResponse::Forbidden().json(serde_json::json!({ "error": msg })),
+        e => {
+            error!("Token operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Token operation failed"
+            }))
+        }
+    }
+}
+
+/// Keep the revocation list in step with other replicas.
+pub async fn run_revocation_refresh(state: web::Data<AppState>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        state.config.auth.revocation_refresh_secs,
+    ));
+    loop {
+        interval.tick().await;
+        match state.tokens.refresh_revocations().await {
+            Ok(()) => state.startup.recovered("token_revocations"),
+            Err(e) => warn!("Failed to refresh token revocations: {:?}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::api_keys::ApiKeyRing;
+    use crate::auth::elevation::ElevationGrants;
+    use crate::auth::AuthService;
+    use crate::clock::ManualClock;
+    use crate::containment::Denylist;
+    use crate::random::SeededRandomSource;
+    use actix_web::test::TestRequest;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    fn clock() -> Arc<ManualClock> {
+        Arc::new(ManualClock::new(Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap()))
+    }
+
+    async fn tokens(config: &Config, clock: Arc<ManualClock>) -> TokenService {
+        let rng = Arc::new(SeededRandomSource::new(7));
+        TokenService::new(config, Storage::unreachable(), clock, rng, Arc::new(RevocationList::default()))
+            .await
+            .expect("token service")
+    }
+
+    /// A refresh token as `refresh` reads it back, issued now.
+    fn issued(service: &TokenService, rng: &dyn RandomSource) -> RefreshToken {
+        let now = service.clock.now();
+        RefreshToken {
+            id: random::uuid_v4(rng).unwrap(),
+            family_id: random::uuid_v4(rng).unwrap(),
+            subject: "alice".to_string(),
+            tenant_id: None,
+            roles: Vec::new(),
+            scope: "crypto:use".to_string(),
+            client_id: None,
+            issued_at: now,
+            expires_at: now + service.refresh_ttl,
+            used_at: None,
+            revoked_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn refresh_tokens_are_exchanged_once() {
+        let config = Config::for_tests(&[("AUTH_REFRESH_TOKEN_TTL_SECS", "3600")]);
+        let clock = clock();
+        let service = tokens(&config, clock.clone()).await;
+        let mut token = issued(&service, service.rng.as_ref());
+        assert_eq!(token.refusal(clock.now()), None);
+
+        clock.advance(Duration::minutes(5));
+        assert_eq!(token.refusal(clock.now()), None);
+        token.used_at = Some(clock.now());
+
+        // Presenting it again is a replay, not a second exchange
+        clock.advance(Duration::seconds(1));
+        assert_eq!(token.refusal(clock.now()), Some(Refusal::Reused));
+        clock.advance(Duration::hours(2));
+        assert_eq!(token.refusal(clock.now()), Some(Refusal::Reused));
+    }
+
+    #[tokio::test]
+    async fn unused_refresh_tokens_expire_and_revocation_comes_first() {
+        let config = Config::for_tests(&[("AUTH_REFRESH_TOKEN_TTL_SECS", "3600")]);
+        let clock = clock();
+        let service = tokens(&config, clock.clone()).await;
+        let mut token = issued(&service, service.rng.as_ref());
+
+        clock.advance(Duration::seconds(3599));
+        assert_eq!(token.refusal(clock.now()), None);
+        clock.advance(Duration::seconds(1));
+        assert_eq!(token.refusal(clock.now()), Some(Refusal::Expired));
+
+        token.used_at = Some(clock.now());
+        token.revoked_at = Some(clock.now());
+        assert_eq!(token.refusal(clock.now()), Some(Refusal::Revoked));
+    }
+
+    #[tokio::test]
+    async fn reuse_revokes_the_family_access_tokens() {
+        let config = Config::for_tests(&[]);
+        let clock = clock();
+        let revocations = Arc::new(RevocationList::default());
+        let auth = AuthService::new(
+            &config,
+            clock.clone(),
+            Arc::new(Denylist::default()),
+            revocations.clone(),
+            Arc::new(ApiKeyRing::default()),
+            Arc::new(ElevationGrants::new(&config.elevation)),
+            &serde_json::json!({ "keys": [] }),
+        )
+        .await
+        .expect("auth service");
+
+        let rng = SeededRandomSource::new(7);
+        let jti = random::uuid_v4(&rng).unwrap().to_string();
+        let expires_at = clock.now() + Duration::minutes(15);
+        let claims = serde_json::json!({ "sub": "alice", "exp": expires_at.timestamp(), "jti": jti });
+        let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(config.auth.jwt_secret.as_bytes()))
+            .expect("signed token");
+        let req = TestRequest::get()
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_http_request();
+        assert!(auth.authenticate(&req).is_ok());
+
+        // What `handle_reuse` does with each access token of the family
+        revocations.insert(jti, expires_at);
+        assert!(matches!(auth.authenticate(&req), Err(SecurityError::AuthError(_))));
+    }
+}
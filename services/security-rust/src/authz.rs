@@ -0,0 +1,651 @@
+/*!
+Authz Module
+Central allow/deny decisions for the platform's services
+
+Services ask `POST /authz/check` (needs `AUTHZ_CHECK_SCOPE`) whether a
+subject may perform an action on a resource instead of each keeping its
+own rules:
+
+```json
+{"subject": {"id": "user:42", "tenant_id": "t1", "roles": ["buyer"]},
+ "action": "tender:update",
+ "resource": {"id": "tender:7", "tenant_id": "t1", "attributes": {"status": "draft"}},
+ "context": {"channel": "web"}}
+```
+
+Without `subject` the caller is checked. `POST /authz/check/bulk` takes up
+to `AUTHZ_MAX_BATCH` checks at once.
+
+Rules come from `AUTHZ_POLICY_FILE`, for every tenant, and from `authz`
+policy documents (see `policies`), global or for the subject's tenant:
+
+```json
+{"rules": [{"id": "buyers-edit-drafts", "effect": "allow",
+            "subjects": ["role:buyer"], "actions": ["tender:update"],
+            "resources": ["tender:*"],
+            "conditions": [{"attribute": "resource.tenant_id", "op": "eq",
+                            "value_of": "subject.tenant_id"},
+                           {"attribute": "resource.status", "op": "eq", "value": "draft"}]}]}
+```
+
+Subjects match the subject id or `role:<role>` / `scope:<scope>`; subject,
+action and resource patterns take `*` wildcards. Conditions read
+`subject.*`, `resource.*` and `context.*` (attributes by name, plus `id`,
+`tenant_id` and `roles`) and compare with a literal `value` or another
+attribute (`value_of`). For anything they cannot say, a rule takes a `when`
+expression (see `expr`) over `subject`, `resource`, `action` and
+`context`, e.g. `"when": "resource.amount <= subject.approval_limit"`.
+Subjects also carry their place in the organization chart (see `org`):
+`department`, `units` and `managers`, and resources with an `owner` the
+owner's as `owner_department`, `owner_units` and `owner_managers`, unless
+the caller gave them. Resources registered by the services owning them
+(see `registry`) carry the registered attributes over the caller's, and
+`resource.registered` says whether they were found. The context always
+holds the subject's tenant's clock (see `timezone`), whatever the caller
+sent: `local_hour`, `local_weekday` (`mon`..`sun`) and `business_hours`,
+so a rule can say `"when": "context.business_hours"`. A `when` that fails to evaluate, such as on a missing attribute, leaves an
+`allow` rule unmatched but makes a `deny` rule match, so errors fail
+closed. A matching `deny` rule wins over any `allow`; with
+no matching rule the answer is deny. Separation of duties rules (see `sod`)
+are checked around these: a subject holding a forbidden role combination,
+or asking for an action conflicting with one it performed on the same
+resource, is denied with the reason `separation_of_duties`. Decisions name
+the rule that decided them and are audited as `authz.decision` (allows only with
+`AUTHZ_LOG_ALLOWS`).
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{Datelike, Timelike};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cell::OnceCell;
+use tracing::{error, info, warn};
+
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::config::{AuthzConfig, Config};
+use crate::errors::SecurityError;
+use crate::expr::{Binding, Environment, Object, Program, Type};
+use crate::sod::{self, Conflict, SourcedRule};
+use crate::AppState;
+
+/// Name of the policy documents holding authorization rules.
+pub const POLICY_NAME: &str = "authz";
+
+const OPS: &[&str] = &["eq", "ne", "in", "not_in", "exists", "absent"];
+
+static SUBJECT: Object = Object {
+    name: "subject",
+    open: true,
+    fields: &[
+        Binding { name: "id", ty: Type::String, doc: "Subject id, e.g. user:42" },
+        Binding { name: "tenant_id", ty: Type::String, doc: "Null for platform subjects" },
+        Binding { name: "roles", ty: Type::List(&Type::String), doc: "Roles held" },
+        Binding { name: "scopes", ty: Type::List(&Type::String), doc: "Scopes granted" },
+        Binding { name: "units", ty: Type::List(&Type::String), doc: "Org unit and those above it" },
+        Binding { name: "managers", ty: Type::List(&Type::String), doc: "Management chain, nearest first" },
+    ],
+};
+
+static RESOURCE: Object = Object {
+    name: "resource",
+    open: true,
+    fields: &[
+        Binding { name: "id", ty: Type::String, doc: "Resource id, e.g. tender:7" },
+        Binding { name: "tenant_id", ty: Type::String, doc: "Owning tenant" },
+    ],
+};
+
+/// What `when` conditions of authz rules see. Subject and resource
+/// attributes sit beside their built-in fields.
+pub static CONDITIONS: Environment = Environment {
+    name: "authz",
+    description: "when conditions of authz policy rules",
+    bindings: &[
+        Binding { name: "subject", ty: Type::Object(&SUBJECT), doc: "Who acts" },
+        Binding { name: "resource", ty: Type::Object(&RESOURCE), doc: "What is acted on" },
+        Binding { name: "action", ty: Type::String, doc: "e.g. tender:update" },
+        Binding { name: "context", ty: Type::Map(&Type::Dyn), doc: "Context of the check" },
+    ],
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthzPolicy {
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub id: String,
+    pub effect: Effect,
+    pub subjects: Vec<String>,
+    pub actions: Vec<String>,
+    pub resources: Vec<String>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    /// Expression that must also hold, over `CONDITIONS`.
+    pub when: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Condition {
+    /// `subject.<name>`, `resource.<name>` or `context.<name>`.
+    pub attribute: String,
+    pub op: String,
+    pub value: Option<Value>,
+    /// Compare with another attribute instead of `value`.
+    pub value_of: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Subject {
+    pub id: String,
+    pub tenant_id: Option<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub attributes: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Resource {
+    pub id: String,
+    pub tenant_id: Option<String>,
+    #[serde(default)]
+    pub attributes: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CheckRequest {
+    /// Defaults to the caller.
+    pub subject: Option<Subject>,
+    pub action: String,
+    pub resource: Resource,
+    #[serde(default)]
+    pub context: Map<String, Value>,
+    /// The caller is about to perform the action if allowed; note it for
+    /// separation of duties (see `sod`).
+    #[serde(default)]
+    pub record: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BulkCheckRequest {
+    pub checks: Vec<CheckRequest>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchedRule {
+    pub id: String,
+    pub effect: Effect,
+    /// `config`, or `policy:<id>` for a stored policy.
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Decision {
+    pub allowed: bool,
+    pub matched_rule: Option<MatchedRule>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'static str>,
+}
+
+/// Check an `authz` policy document before it is stored.
+pub fn validate_policy(document: &Value) -> Result<(), SecurityError> {
+    let policy: AuthzPolicy = serde_json::from_value(document.clone())
+        .map_err(|e| SecurityError::ValidationError(format!("Invalid authz policy: {}", e)))?;
+    let problems = rule_problems(&policy.rules);
+    if !problems.is_empty() {
+        return Err(SecurityError::ValidationError(problems.join("; ")));
+    }
+    Ok(())
+}
+
+fn rule_problems(rules: &[Rule]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for rule in rules {
+        let name = if rule.id.trim().is_empty() { "(unnamed)" } else { rule.id.as_str() };
+        if rule.id.trim().is_empty() {
+            problems.push("every rule needs an id".to_string());
+        }
+        for (field, patterns) in [("subjects", &rule.subjects), ("actions", &rule.actions), ("resources", &rule.resources)] {
+            if patterns.is_empty() || patterns.iter().any(|p| p.trim().is_empty()) {
+                problems.push(format!("rule {}: {} must be non-empty patterns", name, field));
+            }
+        }
+        for condition in &rule.conditions {
+            if !OPS.contains(&condition.op.as_str()) {
+                problems.push(format!("rule {}: unknown op '{}' (known: {})", name, condition.op, OPS.join(", ")));
+            }
+            for attribute in std::iter::once(&condition.attribute).chain(condition.value_of.as_ref()) {
+                if !["subject.", "resource.", "context."].iter().any(|p| attribute.starts_with(p)) {
+                    problems.push(format!("rule {}: attribute '{}' must start with subject., resource. or context.", name, attribute));
+                }
+            }
+            let needs_value = !matches!(condition.op.as_str(), "exists" | "absent");
+            if needs_value && condition.value.is_some() == condition.value_of.is_some() {
+                problems.push(format!("rule {}: '{}' needs exactly one of value or value_of", name, condition.op));
+            }
+        }
+        if let Some(Err(e)) = rule.when.as_deref().map(|when| Program::condition(when, &CONDITIONS)) {
+            problems.push(format!("rule {}: when: {}", name, e));
+        }
+    }
+    problems
+}
+
+/// `*` matches any run of characters, including none.
+pub(crate) fn glob(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+struct Check<'a> {
+    subject: &'a Subject,
+    action: &'a str,
+    resource: &'a Resource,
+    context: &'a Map<String, Value>,
+    /// What `when` expressions see, built for the first one.
+    bindings: OnceCell<Map<String, Value>>,
+}
+
+impl Check<'_> {
+    fn attribute(&self, path: &str) -> Option<Value> {
+        let (scope, name) = path.split_once('.')?;
+        match (scope, name) {
+            ("subject", "id") => Some(Value::from(self.subject.id.as_str())),
+            ("subject", "tenant_id") => self.subject.tenant_id.as_deref().map(Value::from),
+            ("subject", "roles") => Some(Value::from(self.subject.roles.clone())),
+            ("subject", "scopes") => Some(Value::from(self.subject.scopes.clone())),
+            ("subject", name) => self.subject.attributes.get(name).cloned(),
+            ("resource", "id") => Some(Value::from(self.resource.id.as_str())),
+            ("resource", "tenant_id") => self.resource.tenant_id.as_deref().map(Value::from),
+            ("resource", name) => self.resource.attributes.get(name).cloned(),
+            ("context", name) => self.context.get(name).cloned(),
+            _ => None,
+        }
+    }
+
+    fn subject_matches(&self, pattern: &str) -> bool {
+        if let Some(role) = pattern.strip_prefix("role:") {
+            return self.subject.roles.iter().any(|r| glob(role, r));
+        }
+        if let Some(scope) = pattern.strip_prefix("scope:") {
+            return self.subject.scopes.iter().any(|s| glob(scope, s));
+        }
+        glob(pattern, &self.subject.id)
+    }
+
+    fn holds(&self, condition: &Condition) -> bool {
+        let actual = self.attribute(&condition.attribute).filter(|v| !v.is_null());
+        let expected = match &condition.value_of {
+            Some(path) => self.attribute(path),
+            None => condition.value.clone(),
+        };
+        match condition.op.as_str() {
+            "exists" => actual.is_some(),
+            "absent" => actual.is_none(),
+            "eq" => actual.is_some() && actual == expected,
+            "ne" => actual.is_some() && actual != expected,
+            "in" => actual.is_some_and(|a| expected.as_ref().and_then(Value::as_array).is_some_and(|set| set.contains(&a))),
+            "not_in" => actual.is_some_and(|a| !expected.as_ref().and_then(Value::as_array).is_some_and(|set| set.contains(&a))),
+            _ => false,
+        }
+    }
+
+    fn bindings(&self) -> &Map<String, Value> {
+        self.bindings.get_or_init(|| {
+            let mut subject = self.subject.attributes.clone();
+            subject.insert("id".to_string(), Value::from(self.subject.id.as_str()));
+            subject.insert("tenant_id".to_string(), Value::from(self.subject.tenant_id.clone()));
+            subject.insert("roles".to_string(), Value::from(self.subject.roles.clone()));
+            subject.insert("scopes".to_string(), Value::from(self.subject.scopes.clone()));
+            let mut resource = self.resource.attributes.clone();
+            resource.insert("id".to_string(), Value::from(self.resource.id.as_str()));
+            resource.insert("tenant_id".to_string(), Value::from(self.resource.tenant_id.clone()));
+
+            let mut bindings = Map::new();
+            bindings.insert("subject".to_string(), Value::Object(subject));
+            bindings.insert("resource".to_string(), Value::Object(resource));
+            bindings.insert("action".to_string(), Value::from(self.action));
+            bindings.insert("context".to_string(), Value::Object(self.context.clone()));
+            bindings
+        })
+    }
+
+    /// Whether the rule's `when` holds; failures count as a match for deny
+    /// rules only.
+    fn when_holds(&self, rule: &Rule) -> bool {
+        let Some(when) = &rule.when else {
+            return true;
+        };
+        let held = Program::condition(when, &CONDITIONS).and_then(|program| program.holds(self.bindings()));
+        held.unwrap_or_else(|e| {
+            warn!("authz rule {} when on {}: {}", rule.id, self.resource.id, e);
+            rule.effect == Effect::Deny
+        })
+    }
+
+    fn matches(&self, rule: &Rule) -> bool {
+        rule.subjects.iter().any(|p| self.subject_matches(p))
+            && rule.actions.iter().any(|p| glob(p, self.action))
+            && rule.resources.iter().any(|p| glob(p, &self.resource.id))
+            && rule.conditions.iter().all(|c| self.holds(c))
+            && self.when_holds(rule)
+    }
+}
+
+/// Deny overrides allow; no match is a deny.
+fn decide(rules: &[(String, Rule)], check: &Check) -> Decision {
+    let mut allow = None;
+    for (source, rule) in rules {
+        if !check.matches(rule) {
+            continue;
+        }
+        let matched = MatchedRule { id: rule.id.clone(), effect: rule.effect, source: source.clone() };
+        match rule.effect {
+            Effect::Deny => return Decision { allowed: false, matched_rule: Some(matched), reason: None },
+            Effect::Allow => {
+                allow.get_or_insert(matched);
+            }
+        }
+    }
+    match allow {
+        Some(matched) => Decision { allowed: true, matched_rule: Some(matched), reason: None },
+        None => Decision { allowed: false, matched_rule: None, reason: Some("no_matching_rule") },
+    }
+}
+
+fn sod_denial(conflict: Conflict) -> Decision {
+    let matched = MatchedRule { id: conflict.rule, effect: Effect::Deny, source: conflict.source };
+    Decision { allowed: false, matched_rule: Some(matched), reason: Some("separation_of_duties") }
+}
+
+/// A subject tenant's authz rules and SoD rules, loaded once per check batch.
+type TenantRules = (Option<String>, Vec<(String, Rule)>, Vec<SourcedRule>);
+
+pub struct AuthzService {
+    config: AuthzConfig,
+    /// Rules from `AUTHZ_POLICY_FILE`.
+    configured: Vec<Rule>,
+}
+
+impl AuthzService {
+    pub async fn new(config: &Config) -> Result<Self, SecurityError> {
+        let configured = match &config.authz.policy_file {
+            Some(path) => {
+                let text = tokio::fs::read_to_string(path).await
+                    .map_err(|e| SecurityError::ConfigError(format!("AUTHZ_POLICY_FILE {}: {}", path, e)))?;
+                let policy: AuthzPolicy = serde_json::from_str(&text)
+                    .map_err(|e| SecurityError::ConfigError(format!("AUTHZ_POLICY_FILE {}: {}", path, e)))?;
+                let problems = rule_problems(&policy.rules);
+                if !problems.is_empty() {
+                    return Err(SecurityError::ConfigError(format!("AUTHZ_POLICY_FILE {}: {}", path, problems.join("; "))));
+                }
+                policy.rules
+            }
+            None => Vec::new(),
+        };
+
+        info!("Authz service initialized with {} configured rules", configured.len());
+        Ok(Self { config: config.authz.clone(), configured })
+    }
+
+    /// Configured rules, then those of the stored policies for `tenant_id`.
+    async fn rules(&self, state: &AppState, tenant_id: Option<&str>) -> Result<Vec<(String, Rule)>, SecurityError> {
+        let mut rules: Vec<(String, Rule)> = self.configured.iter().map(|r| ("config".to_string(), r.clone())).collect();
+        for policy in state.policy_service.manifest(tenant_id).await? {
+            if policy.name != POLICY_NAME {
+                continue;
+            }
+            match serde_json::from_value::<AuthzPolicy>(policy.document) {
+                Ok(document) => {
+                    let source = format!("policy:{}", policy.id);
+                    rules.extend(document.rules.into_iter().map(|rule| (source.clone(), rule)));
+                }
+                Err(e) => warn!("Skipping unreadable authz policy {}: {}", policy.id, e),
+            }
+        }
+        Ok(rules)
+    }
+
+    /// Decide each check. Rules are loaded once per subject tenant and
+    /// resource attributes resolved from the registry before any rule. SoD
+    /// rules deny whatever the authz rules say, and allowed checks asking
+    /// to be recorded are noted as duties performed.
+    pub async fn check(
+        &self,
+        state: &AppState,
+        caller: &Principal,
+        checks: &[CheckRequest],
+    ) -> Result<Vec<Decision>, SecurityError> {
+        if checks.len() > self.config.max_batch {
+            return Err(SecurityError::ValidationError(format!(
+                "At most {} checks per request",
+                self.config.max_batch
+            )));
+        }
+
+        let mut loaded: Vec<TenantRules> = Vec::new();
+        let mut decisions = Vec::with_capacity(checks.len());
+        for request in checks {
+            let mut subject = subject_for(caller, request)?;
+            if request.action.trim().is_empty() || request.resource.id.trim().is_empty() {
+                return Err(SecurityError::ValidationError("action and resource.id are required".to_string()));
+            }
+            let tenant_id = subject.tenant_id.clone();
+            state.org.enrich(tenant_id.as_deref(), &subject.id, "", &mut subject.attributes).await?;
+            let mut resource = request.resource.clone();
+            let resource_tenant = resource.tenant_id.clone().or(tenant_id);
+            if !state.resource_registry.resolve(resource_tenant.as_deref(), &mut resource).await? {
+                decisions.push(Decision { allowed: false, matched_rule: None, reason: Some("resource_not_registered") });
+                continue;
+            }
+            if let Some(owner) = resource.attributes.get("owner").and_then(Value::as_str).map(str::to_string) {
+                state.org.enrich(resource_tenant.as_deref(), &owner, "owner_", &mut resource.attributes).await?;
+            }
+            let context = local_context(state, subject.tenant_id.as_deref(), &request.context).await;
+            let index = match loaded.iter().position(|(tenant, _, _)| *tenant == subject.tenant_id) {
+                Some(index) => index,
+                None => {
+                    let rules = self.rules(state, subject.tenant_id.as_deref()).await?;
+                    let sod_rules = state.sod.rules(state, subject.tenant_id.as_deref()).await?;
+                    loaded.push((subject.tenant_id.clone(), rules, sod_rules));
+                    loaded.len() - 1
+                }
+            };
+            let (_, rules, sod_rules) = &loaded[index];
+            if let Some(conflict) = sod::role_conflict(sod_rules, &subject.roles) {
+                decisions.push(sod_denial(conflict));
+                continue;
+            }
+            let check = Check {
+                subject: &subject,
+                action: &request.action,
+                resource: &resource,
+                context: &context,
+                bindings: OnceCell::new(),
+            };
+            let mut decision = decide(rules, &check);
+            if decision.allowed {
+                let resource = &request.resource.id;
+                if let Some(conflict) = state.sod.duty_conflict(sod_rules, &subject.id, &request.action, resource).await? {
+                    decision = sod_denial(conflict);
+                } else if request.record {
+                    state.sod.record(&subject.id, subject.tenant_id.as_deref(), &request.action, resource).await?;
+                }
+            }
+            decisions.push(decision);
+        }
+        Ok(decisions)
+    }
+
+    pub fn logs(&self, decision: &Decision) -> bool {
+        !decision.allowed || self.config.log_allows
+    }
+}
+
+/// `context` with the tenant's local time added.
+async fn local_context(state: &AppState, tenant_id: Option<&str>, context: &Map<String, Value>) -> Map<String, Value> {
+    let settings = state.tenant_settings.effective(tenant_id).await;
+    let (zone, now) = (settings.zone(), state.clock.now());
+    let local = now.with_timezone(&zone);
+    let business_hours = settings.hours().is_some_and(|hours| hours.contains(zone, now));
+    let mut context = context.clone();
+    context.insert("local_hour".to_string(), Value::from(local.hour()));
+    context.insert("local_weekday".to_string(), Value::from(local.weekday().to_string().to_lowercase()));
+    context.insert("business_hours".to_string(), Value::from(business_hours));
+    context
+}
+
+/// The subject of a check: the one given, kept within a tenant-bound
+/// caller's tenant, or the caller.
+fn subject_for(caller: &Principal, request: &CheckRequest) -> Result<Subject, SecurityError> {
+    match &request.subject {
+        Some(subject) => {
+            if subject.id.trim().is_empty() {
+                return Err(SecurityError::ValidationError("subject.id is required".to_string()));
+            }
+            if caller.tenant_id.is_some() && subject.tenant_id != caller.tenant_id {
+                return Err(SecurityError::AccessDenied(format!(
+                    "{} can only check subjects of its own tenant",
+                    caller.subject
+                )));
+            }
+            Ok(subject.clone())
+        }
+        None => Ok(Subject {
+            id: caller.subject.clone(),
+            tenant_id: caller.tenant_id.clone(),
+            roles: caller.roles.clone(),
+            scopes: caller.scopes.clone(),
+            attributes: Map::new(),
+        }),
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::AccessDenied(msg) => HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Authorization check failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Authorization check failed"
+            }))
+        }
+    }
+}
+
+async fn log_decisions(state: &AppState, req: &HttpRequest, caller: &Principal, checks: &[CheckRequest], decisions: &[Decision]) {
+    for (request, decision) in checks.iter().zip(decisions) {
+        if !state.authz.logs(decision) {
+            continue;
+        }
+        let subject = request.subject.as_ref().map(|s| s.id.as_str()).unwrap_or(&caller.subject);
+        let tenant_id = request.subject.as_ref().map_or(caller.tenant_id.clone(), |s| s.tenant_id.clone());
+        let recorded = state.audit_service.record(NewAuditEvent {
+            tenant_id,
+            actor: caller.subject.clone(),
+            actor_ip: client_ip(req),
+            action: "authz.decision".to_string(),
+            resource: request.resource.id.clone(),
+            outcome: if decision.allowed { "success" } else { "failure" }.to_string(),
+            payload: serde_json::json!({
+                "subject": subject,
+                "action": request.action,
+                "allowed": decision.allowed,
+                "matched_rule": decision.matched_rule,
+                "reason": decision.reason
+            }),
+        }).await;
+        if let Err(e) = recorded {
+            warn!("Failed to audit authz decision on {}: {:?}", request.resource.id, e);
+        }
+    }
+}
+
+pub async fn check_handler(
+    req: HttpRequest,
+    request: web::Json<CheckRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let caller = match authorize_scope(&state, &req, &state.config.authz.check_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let checks = [request.into_inner()];
+    match state.authz.check(&state, &caller, &checks).await {
+        Ok(decisions) => {
+            log_decisions(&state, &req, &caller, &checks, &decisions).await;
+            Ok(HttpResponse::Ok().json(&decisions[0]))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn bulk_check_handler(
+    req: HttpRequest,
+    request: web::Json<BulkCheckRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let caller = match authorize_scope(&state, &req, &state.config.authz.check_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let checks = request.into_inner().checks;
+    match state.authz.check(&state, &caller, &checks).await {
+        Ok(decisions) => {
+            log_decisions(&state, &req, &caller, &checks, &decisions).await;
+            Ok(HttpResponse::Ok().json(serde_json::json!({ "decisions": decisions })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/authz")
+            .route("/check", web::post().to(check_handler))
+            .route("/check/bulk", web::post().to(bulk_check_handler)),
+    );
+}
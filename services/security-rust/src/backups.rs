@@ -0,0 +1,903 @@
+/*!
+Backups and Restore Drills
+Encrypted backups of keys and policies, and proof that they restore
+
+Every `BACKUP_INTERVAL_SECS` one replica writes a backup to
+`BACKUP_BUCKET` under `BACKUP_PREFIX`: the data keys as stored (wrapped by
+the key provider) with each key's check value, the signing keys as stored,
+and the live policies. The backup is encrypted like a configuration bundle
+(see `bundles`): AES-256-GCM under a fresh data key wrapped by the key
+provider as `backup:<backup_id>`, with `cotai-backup:<backup_id>:<payload
+sha256>` as AAD.
+
+```json
+{"backup_id": "...", "created_at": "...", "provider": "vault",
+ "wrapped_key": "...", "nonce": "<base64>", "ciphertext": "<base64>",
+ "payload_sha256": "<hex>"}
+```
+
+Every `BACKUP_DRILL_INTERVAL_SECS` a drill takes the newest backup in the
+bucket, whoever wrote it, and restores it into a schema of its own inside
+a transaction that is always rolled back, so nothing it restores is ever
+visible to the service. It then checks that
+
+- the backup is no older than `BACKUP_MAX_AGE_SECS`
+- it decrypts, and its payload hash matches
+- the restored tables hold what the payload lists
+- every restored data key unwraps to a key with the recorded check value
+- every restored signing key opens to its recorded public key
+- every restored policy is valid
+- SHA-256, HMAC-SHA256, AES-256-GCM and Ed25519 give their published
+  known answers
+
+The outcome of each check is kept under `GET /admin/backups/drills`; a
+failed drill is audited and alerted on. Admins take a backup or run a
+drill at once with `POST /admin/backups` and `POST /admin/backups/drills`.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use futures::TryStreamExt;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+use ring::digest::{digest, SHA256};
+use ring::hmac;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{FromRow, Postgres, Transaction};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::alerting::{Alert, Severity};
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, client_ip};
+use crate::clock::Clock;
+use crate::config::{BackupConfig, Config};
+use crate::crypto::CryptoService;
+use crate::errors::SecurityError;
+use crate::key_provider::KeyProvider;
+use crate::policies::{self, PolicyRequest};
+use crate::storage::{KeyRecord, SigningKeyRecord, Storage};
+use crate::AppState;
+
+pub const SYSTEM_ACTOR: &str = "system:backups";
+
+/// How often the loops look whether a backup or drill is due.
+const CHECK_INTERVAL_SECS: u64 = 300;
+
+const BACKUP_COLUMNS: &str = "backup_id, object_key, created_at, payload_sha256, crypto_keys, signing_keys, policies, bytes";
+
+const DRILL_COLUMNS: &str = "id, backup_id, object_key, backup_created_at, passed, checks, started_by, started_at, finished_at";
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(digest(&SHA256, data))
+}
+
+fn store_error(e: impl std::fmt::Display) -> SecurityError {
+    SecurityError::StorageError(format!("Backup store: {}", e))
+}
+
+fn invalid(e: impl std::fmt::Display) -> SecurityError {
+    SecurityError::ValidationError(format!("Invalid backup: {}", e))
+}
+
+/// What a backup holds, in the clear.
+#[derive(Debug, Serialize, Deserialize)]
+struct Payload {
+    backup_id: Uuid,
+    created_at: DateTime<Utc>,
+    crypto_keys: Vec<DataKeyEntry>,
+    signing_keys: Vec<SigningKeyEntry>,
+    policies: Vec<PolicyEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+struct DataKeyEntry {
+    key_id: String,
+    algorithm: String,
+    provider: String,
+    wrapped_key: String,
+    source: String,
+    state: String,
+    created_at: DateTime<Utc>,
+    /// Not a column: recorded from the live key at backup time.
+    #[sqlx(default)]
+    check_value: String,
+}
+
+impl DataKeyEntry {
+    fn record(&self) -> KeyRecord {
+        KeyRecord {
+            key_id: self.key_id.clone(),
+            algorithm: self.algorithm.clone(),
+            provider: self.provider.clone(),
+            wrapped_key: self.wrapped_key.clone(),
+            source: self.source.clone(),
+            state: self.state.clone(),
+            created_at: self.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+struct SigningKeyEntry {
+    key_id: String,
+    algorithm: String,
+    provider: String,
+    wrapped_key: String,
+    public_key: String,
+    state: String,
+    created_at: DateTime<Utc>,
+}
+
+impl SigningKeyEntry {
+    fn record(&self) -> SigningKeyRecord {
+        SigningKeyRecord {
+            key_id: self.key_id.clone(),
+            algorithm: self.algorithm.clone(),
+            provider: self.provider.clone(),
+            wrapped_key: self.wrapped_key.clone(),
+            public_key: self.public_key.clone(),
+            state: self.state.clone(),
+            created_at: self.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+struct PolicyEntry {
+    id: Uuid,
+    tenant_id: Option<String>,
+    name: String,
+    description: Option<String>,
+    document: serde_json::Value,
+    version: i64,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    updated_by: String,
+}
+
+/// A backup as stored in the bucket.
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    backup_id: Uuid,
+    created_at: DateTime<Utc>,
+    provider: String,
+    wrapped_key: String,
+    nonce: String,
+    ciphertext: String,
+    payload_sha256: String,
+}
+
+impl Envelope {
+    fn aad(&self) -> String {
+        format!("cotai-backup:{}:{}", self.backup_id, self.payload_sha256)
+    }
+
+    fn key_id(&self) -> String {
+        format!("backup:{}", self.backup_id)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Backup {
+    pub backup_id: Uuid,
+    pub object_key: String,
+    pub created_at: DateTime<Utc>,
+    pub payload_sha256: String,
+    pub crypto_keys: i32,
+    pub signing_keys: i32,
+    pub policies: i32,
+    pub bytes: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &str, passed: bool, detail: impl Into<String>) -> Self {
+        Self { name: name.to_string(), passed, detail: detail.into() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Drill {
+    pub id: Uuid,
+    pub backup_id: Option<Uuid>,
+    pub object_key: Option<String>,
+    pub backup_created_at: Option<DateTime<Utc>>,
+    pub passed: bool,
+    pub checks: Json<Vec<Check>>,
+    pub started_by: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+}
+
+impl Drill {
+    fn failures(&self) -> Vec<&Check> {
+        self.checks.0.iter().filter(|check| !check.passed).collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    pub limit: Option<i64>,
+}
+
+/// Known answers: SHA-256 of "abc" (FIPS 180-2), HMAC-SHA256 test case 2
+/// of RFC 4231, AES-256-GCM test case 14 of the GCM specification and
+/// Ed25519 test 1 of RFC 8032.
+fn known_answer_tests() -> Vec<Check> {
+    let sha256 = sha256_hex(b"abc") == "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, b"Jefe"), b"what do ya want for nothing?");
+    let hmac_sha256 = hex::encode(tag.as_ref()) == "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843";
+
+    let aes_gcm = UnboundKey::new(&AES_256_GCM, &[0u8; 32])
+        .map(LessSafeKey::new)
+        .ok()
+        .and_then(|key| {
+            let mut block = vec![0u8; 16];
+            key.seal_in_place_append_tag(Nonce::assume_unique_for_key([0u8; 12]), Aad::empty(), &mut block).ok()?;
+            Some(hex::encode(block))
+        })
+        .is_some_and(|sealed| sealed == "cea7403d4d606b6e074ec5d3baf39d18d0d1c8a799996bf0265b98b5d48ab919");
+
+    let ed25519 = hex::decode("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60")
+        .ok()
+        .and_then(|seed| Ed25519KeyPair::from_seed_unchecked(&seed).ok())
+        .is_some_and(|pair| {
+            hex::encode(pair.public_key().as_ref()) == "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+                && hex::encode(pair.sign(b"").as_ref())
+                    == "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"
+        });
+
+    [("kat.sha256", sha256), ("kat.hmac_sha256", hmac_sha256), ("kat.aes_256_gcm", aes_gcm), ("kat.ed25519", ed25519)]
+        .into_iter()
+        .map(|(name, passed)| Check::new(name, passed, if passed { "matches" } else { "does not match the known answer" }))
+        .collect()
+}
+
+pub struct BackupService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: BackupConfig,
+    key_provider: Arc<dyn KeyProvider>,
+    store: Option<Arc<dyn ObjectStore>>,
+}
+
+impl BackupService {
+    pub async fn new(
+        config: &Config,
+        key_provider: Arc<dyn KeyProvider>,
+        storage: Storage,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, SecurityError> {
+        let store: Option<Arc<dyn ObjectStore>> = match &config.backups.bucket {
+            Some(bucket) => Some(Arc::new(
+                AmazonS3Builder::from_env()
+                    .with_bucket_name(bucket)
+                    .build()
+                    .map_err(|e| SecurityError::ConfigError(format!("Backup store: {}", e)))?,
+            )),
+            None => None,
+        };
+
+        info!("Backups {}", if store.is_some() { "enabled" } else { "disabled (no BACKUP_BUCKET)" });
+        Ok(Self {
+            storage,
+            clock,
+            config: config.backups.clone(),
+            key_provider,
+            store,
+        })
+    }
+
+    fn store(&self) -> Result<&Arc<dyn ObjectStore>, SecurityError> {
+        self.store
+            .as_ref()
+            .ok_or_else(|| SecurityError::ValidationError("Backups are not configured".to_string()))
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.store.is_some()
+    }
+
+    async fn snapshot(&self, crypto: &CryptoService, backup_id: Uuid) -> Result<Payload, SecurityError> {
+        let mut crypto_keys = sqlx::query_as::<_, DataKeyEntry>(
+            // Archived keys are in the cold tier only, see `key_archive`
+            "SELECT key_id, algorithm, provider, wrapped_key, source, state, created_at FROM crypto_keys \
+             WHERE state <> 'archived' ORDER BY created_at",
+        )
+        .fetch_all(self.storage.pool())
+        .await?;
+        for key in &mut crypto_keys {
+            // Keys this replica has not loaded are checked from storage
+            key.check_value = match crypto.key_check_value(&key.key_id) {
+                Ok(check_value) => check_value,
+                Err(_) => crypto.restored_key_check_value(&key.record()).await?,
+            };
+        }
+        let signing_keys = sqlx::query_as::<_, SigningKeyEntry>(
+            "SELECT key_id, algorithm, provider, wrapped_key, public_key, state, created_at FROM signing_keys \
+             ORDER BY created_at",
+        )
+        .fetch_all(self.storage.pool())
+        .await?;
+        let policies = sqlx::query_as::<_, PolicyEntry>(
+            "SELECT id, tenant_id, name, description, document, version, created_at, updated_at, updated_by \
+             FROM policies WHERE deleted_at IS NULL ORDER BY created_at",
+        )
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(Payload {
+            backup_id,
+            created_at: self.clock.now(),
+            crypto_keys,
+            signing_keys,
+            policies,
+        })
+    }
+
+    async fn seal(&self, crypto: &CryptoService, payload: &Payload) -> Result<Envelope, SecurityError> {
+        let plaintext = serde_json::to_vec(payload).map_err(|e| SecurityError::StorageError(e.to_string()))?;
+        let data_key = crypto.secure_random(32).await?;
+        let nonce = crypto.secure_random(12).await?;
+        let mut envelope = Envelope {
+            backup_id: payload.backup_id,
+            created_at: payload.created_at,
+            provider: self.key_provider.name().to_string(),
+            wrapped_key: String::new(),
+            nonce: base64::encode(&nonce),
+            ciphertext: String::new(),
+            payload_sha256: sha256_hex(&plaintext),
+        };
+        envelope.wrapped_key = self.key_provider.wrap(&envelope.key_id(), &data_key).await?;
+
+        let key = UnboundKey::new(&AES_256_GCM, &data_key)
+            .map(LessSafeKey::new)
+            .map_err(|_| SecurityError::CryptoError("Backup data key is not an AES-256 key".to_string()))?;
+        let nonce = Nonce::try_assume_unique_for_key(&nonce)
+            .map_err(|_| SecurityError::CryptoError("Invalid backup nonce".to_string()))?;
+        let mut sealed = plaintext;
+        key.seal_in_place_append_tag(nonce, Aad::from(envelope.aad().as_bytes()), &mut sealed)
+            .map_err(|_| SecurityError::CryptoError("Backup encryption failed".to_string()))?;
+        envelope.ciphertext = base64::encode(&sealed);
+        Ok(envelope)
+    }
+
+    async fn open(&self, envelope: &Envelope) -> Result<(Payload, String), SecurityError> {
+        if envelope.provider != self.key_provider.name() {
+            return Err(SecurityError::CryptoError(format!(
+                "Backup was wrapped by {}, not the configured {}",
+                envelope.provider,
+                self.key_provider.name()
+            )));
+        }
+        let data_key = self.key_provider.unwrap(&envelope.key_id(), &envelope.wrapped_key).await?;
+        let key = UnboundKey::new(&AES_256_GCM, &data_key)
+            .map(LessSafeKey::new)
+            .map_err(|_| SecurityError::CryptoError("Backup data key is not an AES-256 key".to_string()))?;
+        let fails = || SecurityError::CryptoError(format!("Backup {} does not decrypt", envelope.backup_id));
+        let nonce = base64::decode(&envelope.nonce)
+            .ok()
+            .and_then(|nonce| Nonce::try_assume_unique_for_key(&nonce).ok())
+            .ok_or_else(fails)?;
+        let mut sealed = base64::decode(&envelope.ciphertext).map_err(|_| fails())?;
+        let plaintext = key
+            .open_in_place(nonce, Aad::from(envelope.aad().as_bytes()), &mut sealed)
+            .map_err(|_| fails())?;
+        let payload = serde_json::from_slice(plaintext).map_err(invalid)?;
+        Ok((payload, sha256_hex(plaintext)))
+    }
+
+    /// Take a backup, unless another replica took one within the interval
+    /// (`force` aside). Returns the backup taken.
+    pub async fn backup(&self, crypto: &CryptoService, force: bool) -> Result<Option<Backup>, SecurityError> {
+        let store = self.store()?;
+        let mut tx = self.storage.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext('backups'))").execute(&mut *tx).await?;
+        let now = self.clock.now();
+        let latest: Option<DateTime<Utc>> = sqlx::query_scalar("SELECT MAX(created_at) FROM backups")
+            .fetch_one(&mut *tx)
+            .await?;
+        if !force && latest.is_some_and(|latest| latest > now - Duration::seconds(self.config.interval_secs as i64)) {
+            return Ok(None);
+        }
+
+        let backup_id = Uuid::new_v4();
+        let payload = self.snapshot(crypto, backup_id).await?;
+        let envelope = self.seal(crypto, &payload).await?;
+        let body = serde_json::to_vec(&envelope).map_err(|e| SecurityError::StorageError(e.to_string()))?;
+        let object_key = format!(
+            "{}/{}-{}.json",
+            self.config.prefix.trim_end_matches('/'),
+            payload.created_at.format("%Y%m%dT%H%M%SZ"),
+            backup_id
+        );
+        store.put(&Path::from(object_key.as_str()), body.clone().into()).await.map_err(store_error)?;
+
+        let backup = sqlx::query_as::<_, Backup>(&format!(
+            "INSERT INTO backups (backup_id, object_key, created_at, payload_sha256, crypto_keys, signing_keys, \
+             policies, bytes) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING {}",
+            BACKUP_COLUMNS
+        ))
+        .bind(backup_id)
+        .bind(&object_key)
+        .bind(payload.created_at)
+        .bind(&envelope.payload_sha256)
+        .bind(payload.crypto_keys.len() as i32)
+        .bind(payload.signing_keys.len() as i32)
+        .bind(payload.policies.len() as i32)
+        .bind(body.len() as i64)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        info!("Wrote backup {} to {}", backup_id, object_key);
+        Ok(Some(backup))
+    }
+
+    pub async fn backups(&self, limit: i64) -> Result<Vec<Backup>, SecurityError> {
+        Ok(sqlx::query_as::<_, Backup>(&format!(
+            "SELECT {} FROM backups ORDER BY created_at DESC LIMIT $1",
+            BACKUP_COLUMNS
+        ))
+        .bind(limit.clamp(1, 1000))
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    pub async fn drills(&self, limit: i64) -> Result<Vec<Drill>, SecurityError> {
+        Ok(sqlx::query_as::<_, Drill>(&format!(
+            "SELECT {} FROM backup_drills ORDER BY started_at DESC LIMIT $1",
+            DRILL_COLUMNS
+        ))
+        .bind(limit.clamp(1, 1000))
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    /// The newest backup in the bucket, by its key's timestamp.
+    async fn latest_object(&self) -> Result<Option<String>, SecurityError> {
+        let prefix = Path::from(self.config.prefix.trim_end_matches('/'));
+        let objects: Vec<_> = self.store()?.list(Some(&prefix)).try_collect().await.map_err(store_error)?;
+        Ok(objects
+            .into_iter()
+            .map(|meta| meta.location.to_string())
+            .filter(|key| key.ends_with(".json"))
+            .max())
+    }
+
+    /// Restore `payload` into `schema` and check what comes back.
+    async fn restore(
+        &self,
+        tx: &mut Transaction<'static, Postgres>,
+        crypto: &CryptoService,
+        schema: &str,
+        payload: &Payload,
+    ) -> Result<Vec<Check>, SecurityError> {
+        sqlx::query(&format!("CREATE SCHEMA {}", schema)).execute(&mut **tx).await?;
+        for table in ["crypto_keys", "signing_keys", "policies"] {
+            sqlx::query(&format!("CREATE TABLE {0}.{1} (LIKE public.{1} INCLUDING DEFAULTS)", schema, table))
+                .execute(&mut **tx)
+                .await?;
+        }
+        for key in &payload.crypto_keys {
+            sqlx::query(&format!(
+                "INSERT INTO {}.crypto_keys (key_id, algorithm, provider, wrapped_key, source, state, created_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                schema
+            ))
+            .bind(&key.key_id)
+            .bind(&key.algorithm)
+            .bind(&key.provider)
+            .bind(&key.wrapped_key)
+            .bind(&key.source)
+            .bind(&key.state)
+            .bind(key.created_at)
+            .execute(&mut **tx)
+            .await?;
+        }
+        for key in &payload.signing_keys {
+            sqlx::query(&format!(
+                "INSERT INTO {}.signing_keys (key_id, algorithm, provider, wrapped_key, public_key, state, created_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                schema
+            ))
+            .bind(&key.key_id)
+            .bind(&key.algorithm)
+            .bind(&key.provider)
+            .bind(&key.wrapped_key)
+            .bind(&key.public_key)
+            .bind(&key.state)
+            .bind(key.created_at)
+            .execute(&mut **tx)
+            .await?;
+        }
+        for policy in &payload.policies {
+            sqlx::query(&format!(
+                "INSERT INTO {}.policies (id, tenant_id, name, description, document, version, created_at, \
+                 updated_at, updated_by) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+                schema
+            ))
+            .bind(policy.id)
+            .bind(&policy.tenant_id)
+            .bind(&policy.name)
+            .bind(&policy.description)
+            .bind(&policy.document)
+            .bind(policy.version)
+            .bind(policy.created_at)
+            .bind(policy.updated_at)
+            .bind(&policy.updated_by)
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        // Everything below reads what was restored, not the payload
+        let mut checks = Vec::new();
+        let restored_keys = sqlx::query_as::<_, DataKeyEntry>(&format!(
+            "SELECT key_id, algorithm, provider, wrapped_key, source, state, created_at FROM {}.crypto_keys",
+            schema
+        ))
+        .fetch_all(&mut **tx)
+        .await?;
+        let restored_signing = sqlx::query_as::<_, SigningKeyEntry>(&format!(
+            "SELECT key_id, algorithm, provider, wrapped_key, public_key, state, created_at FROM {}.signing_keys",
+            schema
+        ))
+        .fetch_all(&mut **tx)
+        .await?;
+        let restored_policies = sqlx::query_as::<_, PolicyEntry>(&format!(
+            "SELECT id, tenant_id, name, description, document, version, created_at, updated_at, updated_by \
+             FROM {}.policies",
+            schema
+        ))
+        .fetch_all(&mut **tx)
+        .await?;
+        let counts = (restored_keys.len(), restored_signing.len(), restored_policies.len());
+        let expected = (payload.crypto_keys.len(), payload.signing_keys.len(), payload.policies.len());
+        checks.push(Check::new(
+            "restore.counts",
+            counts == expected,
+            format!(
+                "{} data keys, {} signing keys, {} policies restored of {} / {} / {}",
+                counts.0, counts.1, counts.2, expected.0, expected.1, expected.2
+            ),
+        ));
+
+        let mut bad_keys = Vec::new();
+        for key in &restored_keys {
+            let recorded = payload.crypto_keys.iter().find(|entry| entry.key_id == key.key_id);
+            match crypto.restored_key_check_value(&key.record()).await {
+                Ok(value) if recorded.is_some_and(|entry| entry.check_value == value) => {}
+                Ok(_) => bad_keys.push(format!("{}: check value differs", key.key_id)),
+                Err(e) => bad_keys.push(format!("{}: {}", key.key_id, e)),
+            }
+        }
+        checks.push(Check::new(
+            "restore.data_keys",
+            bad_keys.is_empty(),
+            if bad_keys.is_empty() { format!("{} keys unwrap to their check values", restored_keys.len()) } else { bad_keys.join("; ") },
+        ));
+
+        let mut bad_signing = Vec::new();
+        for key in &restored_signing {
+            match crypto.restored_signing_public_key(&key.record()).await {
+                Ok(public_key) if public_key == key.public_key => {}
+                Ok(_) => bad_signing.push(format!("{}: public key differs", key.key_id)),
+                Err(e) => bad_signing.push(format!("{}: {}", key.key_id, e)),
+            }
+        }
+        checks.push(Check::new(
+            "restore.signing_keys",
+            bad_signing.is_empty(),
+            if bad_signing.is_empty() { format!("{} keys open to their public keys", restored_signing.len()) } else { bad_signing.join("; ") },
+        ));
+
+        let bad_policies: Vec<String> = restored_policies
+            .iter()
+            .filter_map(|policy| {
+                policies::validate(&PolicyRequest {
+                    tenant_id: policy.tenant_id.clone(),
+                    name: policy.name.clone(),
+                    description: policy.description.clone(),
+                    document: policy.document.clone(),
+                })
+                .err()
+                .map(|e| format!("{}: {}", policy.name, e))
+            })
+            .collect();
+        checks.push(Check::new(
+            "restore.policies",
+            bad_policies.is_empty(),
+            if bad_policies.is_empty() { format!("{} policies valid", restored_policies.len()) } else { bad_policies.join("; ") },
+        ));
+        Ok(checks)
+    }
+
+    async fn drill_checks(&self, crypto: &CryptoService) -> (Option<(Uuid, DateTime<Utc>)>, Option<String>, Vec<Check>) {
+        let mut checks = known_answer_tests();
+        let object_key = match self.latest_object().await {
+            Ok(Some(key)) => key,
+            Ok(None) => {
+                checks.push(Check::new("fetch", false, "The bucket holds no backup"));
+                return (None, None, checks);
+            }
+            Err(e) => {
+                checks.push(Check::new("fetch", false, e.to_string()));
+                return (None, None, checks);
+            }
+        };
+        let fetched = match self.store() {
+            Ok(store) => match store.get(&Path::from(object_key.as_str())).await {
+                Ok(object) => object.bytes().await.map_err(store_error),
+                Err(e) => Err(store_error(e)),
+            },
+            Err(e) => Err(e),
+        };
+        let envelope: Envelope = match fetched.and_then(|body| serde_json::from_slice(&body).map_err(invalid)) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                checks.push(Check::new("fetch", false, e.to_string()));
+                return (None, Some(object_key), checks);
+            }
+        };
+        checks.push(Check::new("fetch", true, object_key.clone()));
+        let backup = Some((envelope.backup_id, envelope.created_at));
+
+        let age = self.clock.now() - envelope.created_at;
+        checks.push(Check::new(
+            "age",
+            age <= Duration::seconds(self.config.max_age_secs),
+            format!("{} hours old", age.num_hours()),
+        ));
+
+        let (payload, payload_sha256) = match self.open(&envelope).await {
+            Ok(opened) => opened,
+            Err(e) => {
+                checks.push(Check::new("decrypt", false, e.to_string()));
+                return (backup, Some(object_key), checks);
+            }
+        };
+        checks.push(Check::new("decrypt", true, format!("unwrapped with {}", envelope.provider)));
+        checks.push(Check::new(
+            "payload_hash",
+            payload_sha256 == envelope.payload_sha256 && payload.backup_id == envelope.backup_id,
+            payload_sha256,
+        ));
+
+        let schema = format!("backup_drill_{}", Uuid::new_v4().simple());
+        let restored = async {
+            let mut tx = self.storage.begin().await?;
+            let checks = self.restore(&mut tx, crypto, &schema, &payload).await;
+            // The restore is never kept
+            tx.rollback().await?;
+            checks
+        }
+        .await;
+        match restored {
+            Ok(restore_checks) => checks.extend(restore_checks),
+            Err(e) => checks.push(Check::new("restore", false, e.to_string())),
+        }
+        (backup, Some(object_key), checks)
+    }
+
+    /// Restore the newest backup and check it, unless a drill ran within
+    /// the interval (`force` aside).
+    pub async fn drill(&self, crypto: &CryptoService, actor: &str, force: bool) -> Result<Option<Drill>, SecurityError> {
+        self.store()?;
+        let mut tx = self.storage.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext('backup_drills'))").execute(&mut *tx).await?;
+        let started_at = self.clock.now();
+        let latest: Option<DateTime<Utc>> = sqlx::query_scalar("SELECT MAX(started_at) FROM backup_drills")
+            .fetch_one(&mut *tx)
+            .await?;
+        if !force && latest.is_some_and(|latest| latest > started_at - Duration::seconds(self.config.drill_interval_secs as i64)) {
+            return Ok(None);
+        }
+
+        let (backup, object_key, checks) = self.drill_checks(crypto).await;
+        let passed = checks.iter().all(|check| check.passed);
+        let drill = sqlx::query_as::<_, Drill>(&format!(
+            "INSERT INTO backup_drills (id, backup_id, object_key, backup_created_at, passed, checks, started_by, \
+             started_at, finished_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING {}",
+            DRILL_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(backup.map(|(id, _)| id))
+        .bind(&object_key)
+        .bind(backup.map(|(_, created_at)| created_at))
+        .bind(passed)
+        .bind(Json(&checks))
+        .bind(actor)
+        .bind(started_at)
+        .bind(self.clock.now())
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(Some(drill))
+    }
+}
+
+async fn report_drill(state: &AppState, actor: &str, actor_ip: Option<String>, drill: &Drill) {
+    let outcome = if drill.passed { "success" } else { "failure" };
+    state.metrics_service.increment("cotai_backup_drills_total", &[("outcome", outcome)]);
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: None,
+        actor: actor.to_string(),
+        actor_ip,
+        action: "backup.drill".to_string(),
+        resource: format!("backup_drill:{}", drill.id),
+        outcome: outcome.to_string(),
+        payload: serde_json::json!({
+            "backup_id": drill.backup_id,
+            "object_key": drill.object_key,
+            "failed_checks": drill.failures()
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit backup drill {}: {:?}", drill.id, e);
+    }
+    if drill.passed {
+        info!("Backup drill {} passed", drill.id);
+        return;
+    }
+    let title = format!("Backup restore drill failed {} checks", drill.failures().len());
+    let details = serde_json::json!({
+        "drill_id": drill.id,
+        "object_key": drill.object_key,
+        "failed_checks": drill.failures()
+    });
+    state.alerting_service.send(&Alert::new("backups", Severity::High, title, details, state.clock.now()), &[]).await;
+}
+
+pub async fn run_backups(state: web::Data<AppState>) {
+    if !state.backups.enabled() {
+        return;
+    }
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(CHECK_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        match state.backups.backup(&state.crypto_service, false).await {
+            Ok(Some(_)) => state.metrics_service.increment("cotai_backups_total", &[("outcome", "success")]),
+            Ok(None) => {}
+            Err(e) => {
+                error!("Backup failed: {:?}", e);
+                state.metrics_service.increment("cotai_backups_total", &[("outcome", "failure")]);
+            }
+        }
+    }
+}
+
+pub async fn run_drills(state: web::Data<AppState>) {
+    if !state.backups.enabled() {
+        return;
+    }
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(CHECK_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        match state.backups.drill(&state.crypto_service, SYSTEM_ACTOR, false).await {
+            Ok(Some(drill)) => report_drill(&state, SYSTEM_ACTOR, None, &drill).await,
+            Ok(None) => {}
+            Err(e) => error!("Backup drill could not run: {:?}", e),
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Backup operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Backup operation failed"
+            }))
+        }
+    }
+}
+
+pub async fn list_backups_handler(
+    req: HttpRequest,
+    query: web::Query<ListQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.backups.backups(query.limit.unwrap_or(50)).await {
+        Ok(backups) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "backups": backups
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn backup_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.backups.backup(&state.crypto_service, true).await {
+        Ok(Some(backup)) => {
+            let recorded = state.audit_service.record(NewAuditEvent {
+                tenant_id: None,
+                actor: principal.subject.clone(),
+                actor_ip: client_ip(&req),
+                action: "backup.create".to_string(),
+                resource: format!("backup:{}", backup.backup_id),
+                outcome: "success".to_string(),
+                payload: serde_json::json!({
+                    "object_key": backup.object_key,
+                    "payload_sha256": backup.payload_sha256
+                }),
+            }).await;
+            if let Err(e) = recorded {
+                warn!("Failed to audit backup {}: {:?}", backup.backup_id, e);
+            }
+            Ok(HttpResponse::Created().json(backup))
+        }
+        Ok(None) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "No backup was taken"
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_drills_handler(
+    req: HttpRequest,
+    query: web::Query<ListQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.backups.drills(query.limit.unwrap_or(50)).await {
+        Ok(drills) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "drills": drills
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn drill_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.backups.drill(&state.crypto_service, &principal.subject, true).await {
+        Ok(Some(drill)) => {
+            report_drill(&state, &principal.subject, client_ip(&req), &drill).await;
+            Ok(HttpResponse::Ok().json(drill))
+        }
+        Ok(None) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "No drill was run"
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/backups")
+            .route("", web::get().to(list_backups_handler))
+            .route("", web::post().to(backup_handler))
+            .route("/drills", web::get().to(list_drills_handler))
+            .route("/drills", web::post().to(drill_handler)),
+    );
+}
@@ -0,0 +1,711 @@
+/*!
+Billing Module
+Cost units per request, totalled per tenant and day and exported for billing
+
+Requests are charged for the cryptographic work they cause, on four meters:
+
+- `aead_bytes`: bytes encrypted or decrypted with AES-256-GCM. Streams are
+  charged their declared `Content-Length`, as their body is processed
+  after the request has been accounted for.
+- `signature_ops`: signatures made or verified
+- `hash_ops`: SHA-256 hashes, including encryption context hashes
+- `password_hash_ops`: Argon2id hashes and verifications
+
+`CryptoService` reports work through `charge` as it does it. `meter`, a
+middleware, converts what one request caused into cost units with
+`BILLING_UNIT_WEIGHTS` (`meter=units`, per started KiB for `aead_bytes`),
+answers with the total in `X-Cost-Units` and charges it to the caller's
+tenant, an empty `tenant_id` for callers without one. Work done outside a
+request, by background jobs, is not charged.
+
+Totals are kept in memory and added to `billing_usage` every
+`BILLING_FLUSH_INTERVAL_SECS`. Once a UTC day has been over for
+`BILLING_EXPORT_DELAY_SECS`, long enough for every replica to have flushed
+it, one replica exports it, one row per tenant and meter:
+
+- with `BILLING_EXPORT_BUCKET`, as
+  `{BILLING_EXPORT_PREFIX}/{day}/billing-{day}.csv` and `.parquet`
+- with `BILLING_WEBHOOK_URL`, as a JSON `POST` of the rows and each
+  tenant's total, signed like SOAR pushes (`X-Cotai-Signature`) with the
+  `BILLING_WEBHOOK_SECRET` webhook secret, see `crypto::webhook`.
+  Receivers should key on `day`, as an export run again is sent again.
+
+A failed export raises an alert. Admins read totals with `GET
+/admin/billing/usage?from=&to=&tenant_id=` (`&format=csv` for a
+spreadsheet), list exports with `GET /admin/billing/exports` and run one
+again with `POST /admin/billing/exports {"day": "2026-01-31"}`.
+*/
+
+use actix_web::body::BoxBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpRequest, HttpResponse, Result};
+use arrow::array::{ArrayRef, Date32Array, Int64Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use parquet::arrow::async_writer::{AsyncArrowWriter, ParquetObjectWriter};
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use tracing::{error, info, warn};
+
+use crate::alerting::{Alert, Severity};
+use crate::audit::NewAuditEvent;
+use crate::auth::auth_error_response;
+use crate::clock::Clock;
+use crate::config::{BillingConfig, Config};
+use crate::crypto::webhook::WebhookSigner;
+use crate::deadline;
+use crate::errors::SecurityError;
+use crate::soar::SIGNATURE_HEADER;
+use crate::storage::Storage;
+use crate::AppState;
+
+pub const COST_HEADER: &str = "x-cost-units";
+
+const SYSTEM_ACTOR: &str = "system:billing";
+
+/// Longest range `GET /admin/billing/usage` reads at once.
+const MAX_DAYS: i64 = 366;
+
+/// How long a running export holds its day before another may take over.
+const STALE_EXPORT_SECS: i64 = 3600;
+
+const EXPORT_COLUMNS: &str = "day, status, objects, row_count, total_units, webhook_status, error, requested_by, \
+    created_at, completed_at";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Meter {
+    AeadBytes,
+    SignatureOps,
+    HashOps,
+    PasswordHashOps,
+}
+
+impl Meter {
+    pub const ALL: [Meter; 4] = [Meter::AeadBytes, Meter::SignatureOps, Meter::HashOps, Meter::PasswordHashOps];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Meter::AeadBytes => "aead_bytes",
+            Meter::SignatureOps => "signature_ops",
+            Meter::HashOps => "hash_ops",
+            Meter::PasswordHashOps => "password_hash_ops",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Meter::ALL.into_iter().find(|meter| meter.as_str() == value)
+    }
+
+    /// Argon2id is tuned to take tens of milliseconds; the rest take
+    /// microseconds.
+    fn default_weight(&self) -> i64 {
+        match self {
+            Meter::AeadBytes => 1,
+            Meter::SignatureOps => 2,
+            Meter::HashOps => 1,
+            Meter::PasswordHashOps => 100,
+        }
+    }
+}
+
+type RequestCosts = Rc<RefCell<BTreeMap<Meter, u64>>>;
+
+tokio::task_local! {
+    static REQUEST_COSTS: RequestCosts;
+}
+
+/// Charge work to the request being served, if any.
+pub fn charge(meter: Meter, quantity: u64) {
+    let _ = REQUEST_COSTS.try_with(|costs| *costs.borrow_mut().entry(meter).or_default() += quantity);
+}
+
+/// A tenant's charges on one meter for a day, not yet flushed.
+#[derive(Debug, Default)]
+struct Tally {
+    requests: i64,
+    quantity: i64,
+    units: i64,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct UsageRow {
+    pub day: NaiveDate,
+    pub tenant_id: String,
+    pub meter: String,
+    pub requests: i64,
+    pub quantity: i64,
+    pub units: i64,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct BillingExport {
+    pub day: NaiveDate,
+    /// `running`, `completed` or `failed`.
+    pub status: String,
+    pub objects: Vec<String>,
+    pub row_count: Option<i64>,
+    pub total_units: Option<i64>,
+    /// `sent` or `failed`; `None` without a webhook.
+    pub webhook_status: Option<String>,
+    pub error: Option<String>,
+    pub requested_by: String,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UsageFilter {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub tenant_id: Option<String>,
+    /// `json` (default) or `csv`.
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportRequest {
+    pub day: NaiveDate,
+}
+
+fn export_error(e: impl std::fmt::Display) -> SecurityError {
+    SecurityError::StorageError(format!("Billing export failed: {}", e))
+}
+
+fn to_csv(rows: &[UsageRow]) -> Result<Vec<u8>, SecurityError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row).map_err(export_error)?;
+    }
+    writer.into_inner().map_err(export_error)
+}
+
+fn export_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("day", DataType::Date32, false),
+        Field::new("tenant_id", DataType::Utf8, false),
+        Field::new("meter", DataType::Utf8, false),
+        Field::new("requests", DataType::Int64, false),
+        Field::new("quantity", DataType::Int64, false),
+        Field::new("units", DataType::Int64, false),
+    ]))
+}
+
+fn to_record_batch(schema: &SchemaRef, rows: &[UsageRow]) -> Result<RecordBatch, SecurityError> {
+    let epoch = DateTime::UNIX_EPOCH.date_naive();
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Date32Array::from_iter_values(rows.iter().map(|r| (r.day - epoch).num_days() as i32))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.tenant_id.as_str()))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.meter.as_str()))),
+        Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.requests))),
+        Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.quantity))),
+        Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.units))),
+    ];
+    RecordBatch::try_new(schema.clone(), columns).map_err(export_error)
+}
+
+pub struct BillingService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: BillingConfig,
+    weights: HashMap<Meter, i64>,
+    store: Option<Arc<dyn ObjectStore>>,
+    client: reqwest::Client,
+    pending: Mutex<HashMap<(String, NaiveDate, Meter), Tally>>,
+}
+
+impl BillingService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let weights = Meter::ALL
+            .into_iter()
+            .map(|meter| {
+                let configured = config.billing.unit_weights.iter().find(|(name, _)| name == meter.as_str());
+                (meter, configured.map_or(meter.default_weight(), |(_, weight)| *weight))
+            })
+            .collect();
+        let store: Option<Arc<dyn ObjectStore>> = match &config.billing.export_bucket {
+            Some(bucket) => Some(Arc::new(
+                AmazonS3Builder::from_env()
+                    .with_bucket_name(bucket)
+                    .build()
+                    .map_err(|e| SecurityError::ConfigError(format!("Billing export store: {}", e)))?,
+            )),
+            None => None,
+        };
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(config.billing.timeout_secs))
+            .build()
+            .map_err(|e| SecurityError::ConfigError(format!("Billing webhook client: {}", e)))?;
+
+        info!("Billing service initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            config: config.billing.clone(),
+            weights,
+            store,
+            client,
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Cost units for one request's `quantity` on `meter`.
+    pub fn units(&self, meter: Meter, quantity: u64) -> i64 {
+        let quantity = match meter {
+            Meter::AeadBytes => quantity.div_ceil(1024),
+            _ => quantity,
+        };
+        let weight = self.weights.get(&meter).copied().unwrap_or_else(|| meter.default_weight());
+        (quantity as i64).saturating_mul(weight)
+    }
+
+    /// Add one request's charges to its tenant's totals for today.
+    pub fn note(&self, tenant_id: Option<&str>, meter: Meter, quantity: u64, units: i64) {
+        let day = self.clock.now().date_naive();
+        let mut pending = self.pending.lock().unwrap();
+        let tally = pending.entry((tenant_id.unwrap_or_default().to_string(), day, meter)).or_default();
+        tally.requests += 1;
+        tally.quantity += quantity as i64;
+        tally.units += units;
+    }
+
+    /// Add this replica's totals to storage. On failure they are kept for
+    /// the next attempt.
+    pub async fn flush(&self) -> Result<(), SecurityError> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let written = async {
+            let mut tx = self.storage.begin().await?;
+            for ((tenant_id, day, meter), tally) in &pending {
+                sqlx::query(
+                    "INSERT INTO billing_usage (tenant_id, day, meter, requests, quantity, units) \
+                     VALUES ($1, $2, $3, $4, $5, $6) \
+                     ON CONFLICT (tenant_id, day, meter) DO UPDATE SET \
+                     requests = billing_usage.requests + EXCLUDED.requests, \
+                     quantity = billing_usage.quantity + EXCLUDED.quantity, \
+                     units = billing_usage.units + EXCLUDED.units",
+                )
+                .bind(tenant_id)
+                .bind(day)
+                .bind(meter.as_str())
+                .bind(tally.requests)
+                .bind(tally.quantity)
+                .bind(tally.units)
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+            Ok::<_, SecurityError>(())
+        }
+        .await;
+
+        if let Err(e) = written {
+            let mut current = self.pending.lock().unwrap();
+            for (key, tally) in pending {
+                let merged = current.entry(key).or_default();
+                merged.requests += tally.requests;
+                merged.quantity += tally.quantity;
+                merged.units += tally.units;
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub async fn usage(&self, filter: &UsageFilter) -> Result<Vec<UsageRow>, SecurityError> {
+        let to = filter.to.unwrap_or_else(|| self.clock.now().date_naive());
+        let from = filter.from.unwrap_or(to - Duration::days(30));
+        if from > to {
+            return Err(SecurityError::ValidationError("from must not be after to".to_string()));
+        }
+        if (to - from).num_days() >= MAX_DAYS {
+            return Err(SecurityError::ValidationError(format!("At most {} days can be read at once", MAX_DAYS)));
+        }
+
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "SELECT day, tenant_id, meter, requests, quantity, units FROM billing_usage WHERE day >= ",
+        );
+        builder.push_bind(from).push(" AND day <= ").push_bind(to);
+        if let Some(tenant_id) = &filter.tenant_id {
+            builder.push(" AND tenant_id = ").push_bind(tenant_id.clone());
+        }
+        builder.push(" ORDER BY day, tenant_id, meter");
+
+        let rows = builder.build_query_as::<UsageRow>().fetch_all(self.storage.pool()).await?;
+        Ok(rows)
+    }
+
+    pub async fn list_exports(&self) -> Result<Vec<BillingExport>, SecurityError> {
+        let exports = sqlx::query_as::<_, BillingExport>(&format!(
+            "SELECT {} FROM billing_exports ORDER BY day DESC LIMIT 90",
+            EXPORT_COLUMNS
+        ))
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(exports)
+    }
+
+    /// Export `day`, which must be over. Run again, an export replaces the
+    /// earlier one unless that is still running. `None` when another
+    /// replica holds the day.
+    pub async fn export(
+        &self,
+        webhooks: &WebhookSigner,
+        day: NaiveDate,
+        actor: &str,
+        again: bool,
+    ) -> Result<Option<BillingExport>, SecurityError> {
+        let now = self.clock.now();
+        if day >= now.date_naive() {
+            return Err(SecurityError::ValidationError("Only days that are over can be exported".to_string()));
+        }
+        if self.store.is_none() && self.config.webhook_url.is_none() {
+            return Err(SecurityError::ConfigError(
+                "Billing exports need BILLING_EXPORT_BUCKET or BILLING_WEBHOOK_URL".to_string(),
+            ));
+        }
+
+        let claim = if again {
+            "INSERT INTO billing_exports (day, requested_by, created_at) VALUES ($1, $2, $3) \
+             ON CONFLICT (day) DO UPDATE SET status = 'running', objects = '{}', row_count = NULL, \
+             total_units = NULL, webhook_status = NULL, error = NULL, requested_by = EXCLUDED.requested_by, \
+             created_at = EXCLUDED.created_at, completed_at = NULL \
+             WHERE billing_exports.status <> 'running' OR billing_exports.created_at < $4 \
+             RETURNING day"
+        } else {
+            "INSERT INTO billing_exports (day, requested_by, created_at) VALUES ($1, $2, $3) \
+             ON CONFLICT (day) DO NOTHING RETURNING day"
+        };
+        let claimed = sqlx::query_scalar::<_, NaiveDate>(claim)
+            .bind(day)
+            .bind(actor)
+            .bind(now)
+            .bind(now - Duration::seconds(STALE_EXPORT_SECS))
+            .fetch_optional(self.storage.pool())
+            .await?;
+        if claimed.is_none() {
+            if again {
+                return Err(SecurityError::Conflict(format!("The export of {} is already running", day)));
+            }
+            return Ok(None);
+        }
+
+        let rows = sqlx::query_as::<_, UsageRow>(
+            "SELECT day, tenant_id, meter, requests, quantity, units FROM billing_usage \
+             WHERE day = $1 ORDER BY tenant_id, meter",
+        )
+        .bind(day)
+        .fetch_all(self.storage.pool())
+        .await?;
+        let total_units: i64 = rows.iter().map(|row| row.units).sum();
+
+        let mut objects = Vec::new();
+        let written = self.write_objects(day, &rows, &mut objects).await;
+        let webhook_status = match (&written, &self.config.webhook_url) {
+            (Ok(()), Some(url)) => Some(self.send(webhooks, url, day, &rows, &objects).await),
+            _ => None,
+        };
+        let error = match (&written, &webhook_status) {
+            (Err(e), _) => Some(e.to_string()),
+            (_, Some(Err(e))) => Some(e.to_string()),
+            _ => None,
+        };
+
+        let export = sqlx::query_as::<_, BillingExport>(&format!(
+            "UPDATE billing_exports SET status = $2, objects = $3, row_count = $4, total_units = $5, \
+             webhook_status = $6, error = $7, completed_at = $8 WHERE day = $1 RETURNING {}",
+            EXPORT_COLUMNS
+        ))
+        .bind(day)
+        .bind(if error.is_some() { "failed" } else { "completed" })
+        .bind(&objects)
+        .bind(rows.len() as i64)
+        .bind(total_units)
+        .bind(webhook_status.as_ref().map(|sent| if sent.is_ok() { "sent" } else { "failed" }))
+        .bind(&error)
+        .bind(self.clock.now())
+        .fetch_one(self.storage.pool())
+        .await?;
+        Ok(Some(export))
+    }
+
+    /// Write the day's CSV and Parquet files, naming each in `objects` once
+    /// it is written.
+    async fn write_objects(&self, day: NaiveDate, rows: &[UsageRow], objects: &mut Vec<String>) -> Result<(), SecurityError> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+        let prefix = self.config.export_prefix.trim_end_matches('/');
+
+        let csv_key = format!("{}/{}/billing-{}.csv", prefix, day, day);
+        store.put(&Path::from(csv_key.as_str()), to_csv(rows)?.into()).await.map_err(export_error)?;
+        objects.push(csv_key);
+
+        let parquet_key = format!("{}/{}/billing-{}.parquet", prefix, day, day);
+        let schema = export_schema();
+        let props = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .build();
+        let mut writer = AsyncArrowWriter::try_new(
+            ParquetObjectWriter::new(store.clone(), Path::from(parquet_key.as_str())),
+            schema.clone(),
+            Some(props),
+        )
+        .map_err(export_error)?;
+        writer.write(&to_record_batch(&schema, rows)?).await.map_err(export_error)?;
+        writer.close().await.map_err(export_error)?;
+        objects.push(parquet_key);
+        Ok(())
+    }
+
+    async fn send(
+        &self,
+        webhooks: &WebhookSigner,
+        url: &str,
+        day: NaiveDate,
+        rows: &[UsageRow],
+        objects: &[String],
+    ) -> Result<(), SecurityError> {
+        let mut tenants: BTreeMap<&str, i64> = BTreeMap::new();
+        for row in rows {
+            *tenants.entry(row.tenant_id.as_str()).or_default() += row.units;
+        }
+        let body = serde_json::to_vec(&serde_json::json!({
+            "event": "billing.export",
+            "day": day,
+            "tenants": tenants
+                .iter()
+                .map(|(tenant_id, units)| serde_json::json!({ "tenant_id": tenant_id, "units": units }))
+                .collect::<Vec<_>>(),
+            "rows": rows,
+            "objects": objects
+        }))
+        .map_err(|e| SecurityError::DeliveryError(e.to_string()))?;
+        let (signature, _) = webhooks.sign(&self.config.webhook_secret, &body)?;
+
+        let response = deadline::outbound(self.client.post(url))
+            .header("Content-Type", "application/json")
+            .header(SIGNATURE_HEADER, signature)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| SecurityError::DeliveryError(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(SecurityError::DeliveryError(format!("Billing webhook returned {}", response.status())));
+        }
+        Ok(())
+    }
+
+    /// Export the last day that is over by `BILLING_EXPORT_DELAY_SECS`,
+    /// unless it has been already.
+    async fn export_due(&self, webhooks: &WebhookSigner) -> Result<Option<BillingExport>, SecurityError> {
+        if self.store.is_none() && self.config.webhook_url.is_none() {
+            return Ok(None);
+        }
+        let closed = (self.clock.now() - Duration::seconds(self.config.export_delay_secs)).date_naive();
+        let Some(day) = closed.pred_opt() else {
+            return Ok(None);
+        };
+        self.export(webhooks, day, SYSTEM_ACTOR, false).await
+    }
+}
+
+/// Middleware charging each request's work to its caller's tenant; see the
+/// module docs.
+pub async fn meter(
+    state: web::Data<AppState>,
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let costs = RequestCosts::default();
+    let mut res = REQUEST_COSTS.scope(costs.clone(), next.call(req)).await?;
+
+    let costs = costs.take();
+    if costs.is_empty() || !state.config.billing.enabled {
+        return Ok(res);
+    }
+    let principal = state.auth_service.authenticate(res.request()).ok();
+    let tenant_id = principal.as_ref().and_then(|p| p.tenant_id.as_deref());
+    let mut total = 0i64;
+    for (meter, quantity) in costs {
+        let units = state.billing.units(meter, quantity);
+        state.billing.note(tenant_id, meter, quantity, units);
+        state.metrics_service.increment_by(
+            "cotai_billing_cost_units_total",
+            &[("meter", meter.as_str())],
+            units.max(0) as u64,
+        );
+        total = total.saturating_add(units);
+    }
+    res.headers_mut().insert(HeaderName::from_static(COST_HEADER), HeaderValue::from(total));
+    Ok(res)
+}
+
+async fn audit(state: &AppState, actor: &str, export: &BillingExport) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: None,
+        actor: actor.to_string(),
+        actor_ip: None,
+        action: "billing.export".to_string(),
+        resource: format!("billing_export:{}", export.day),
+        outcome: if export.status == "completed" { "success" } else { "failure" }.to_string(),
+        payload: serde_json::json!({
+            "objects": export.objects,
+            "row_count": export.row_count,
+            "total_units": export.total_units,
+            "webhook_status": export.webhook_status,
+            "error": export.error
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit billing export of {}: {:?}", export.day, e);
+    }
+}
+
+async fn alert_failed(state: &AppState, export: &BillingExport) {
+    let details = serde_json::json!({
+        "day": export.day,
+        "error": export.error,
+        "webhook_status": export.webhook_status
+    });
+    let title = format!("Billing export of {} failed", export.day);
+    state.alerting_service.send(&Alert::new("billing", Severity::High, title, details, state.clock.now()), &[]).await;
+}
+
+/// Flush totals and export closed days in the background.
+pub async fn run_billing(state: web::Data<AppState>) {
+    let interval_secs = state.config.billing.flush_interval_secs;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = state.billing.flush().await {
+            warn!("Failed to record billing usage: {:?}", e);
+        }
+        match state.billing.export_due(&state.webhooks).await {
+            Ok(Some(export)) => {
+                audit(&state, SYSTEM_ACTOR, &export).await;
+                if export.status == "failed" {
+                    alert_failed(&state, &export).await;
+                } else {
+                    info!("Exported billing for {}: {} rows", export.day, export.row_count.unwrap_or(0));
+                }
+            }
+            Ok(None) => {}
+            Err(e) => error!("Billing export failed: {:?}", e),
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ConfigError(msg) => HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Billing operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Billing operation failed"
+            }))
+        }
+    }
+}
+
+pub async fn usage_handler(
+    req: HttpRequest,
+    filter: web::Query<UsageFilter>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let rows = match state.billing.usage(&filter).await {
+        Ok(rows) => rows,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match filter.format.as_deref() {
+        None | Some("json") => Ok(HttpResponse::Ok().json(serde_json::json!({ "usage": rows }))),
+        Some("csv") => match to_csv(&rows) {
+            Ok(body) => Ok(HttpResponse::Ok()
+                .content_type("text/csv")
+                .insert_header(("Content-Disposition", "attachment; filename=\"billing-usage.csv\""))
+                .body(body)),
+            Err(e) => Ok(error_response(e)),
+        },
+        Some(other) => Ok(error_response(SecurityError::ValidationError(format!(
+            "format must be json or csv, not '{}'",
+            other
+        )))),
+    }
+}
+
+pub async fn list_exports_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.billing.list_exports().await {
+        Ok(exports) => Ok(HttpResponse::Ok().json(serde_json::json!({ "exports": exports }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn export_handler(
+    req: HttpRequest,
+    request: web::Json<ExportRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    // So the day's last charges on this replica are in the export
+    if let Err(e) = state.billing.flush().await {
+        warn!("Failed to record billing usage: {:?}", e);
+    }
+    match state.billing.export(&state.webhooks, request.day, &principal.subject, true).await {
+        Ok(Some(export)) => {
+            audit(&state, &principal.subject, &export).await;
+            Ok(HttpResponse::Ok().json(export))
+        }
+        Ok(None) => Ok(error_response(SecurityError::Conflict(format!(
+            "The export of {} is already running",
+            request.day
+        )))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/billing")
+            .route("/usage", web::get().to(usage_handler))
+            .route("/exports", web::get().to(list_exports_handler))
+            .route("/exports", web::post().to(export_handler))
+    );
+}
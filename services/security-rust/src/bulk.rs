@@ -0,0 +1,205 @@
+/*!
+Bulk Administrative Operations
+Concurrent batch actions with per-item results and dry-run support
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, Principal};
+use crate::errors::SecurityError;
+use crate::AppState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkOperation {
+    ReplayDeadLetters,
+    DiscardDeadLetters,
+}
+
+impl BulkOperation {
+    fn action(&self) -> &'static str {
+        match self {
+            BulkOperation::ReplayDeadLetters => "admin.bulk.replay_dead_letter",
+            BulkOperation::DiscardDeadLetters => "admin.bulk.discard_dead_letter",
+        }
+    }
+
+    fn resource(&self, id: &str) -> String {
+        match self {
+            BulkOperation::ReplayDeadLetters | BulkOperation::DiscardDeadLetters => format!("dead_letter:{}", id),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkRequest {
+    pub operation: BulkOperation,
+    pub ids: Vec<String>,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemStatus {
+    Succeeded,
+    /// Dry run: the item passed every check and would be processed.
+    WouldSucceed,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ItemResult {
+    pub id: String,
+    pub status: ItemStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkReport {
+    pub operation: BulkOperation,
+    pub dry_run: bool,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<ItemResult>,
+}
+
+fn parse_id(id: &str) -> Result<Uuid, SecurityError> {
+    Uuid::parse_str(id).map_err(|_| SecurityError::ValidationError(format!("'{}' is not a valid id", id)))
+}
+
+async fn apply(state: &AppState, operation: BulkOperation, id: &str, dry_run: bool) -> Result<(), SecurityError> {
+    match operation {
+        BulkOperation::ReplayDeadLetters => {
+            let entry = state.dead_letters.pending(parse_id(id)?).await?;
+            if dry_run {
+                return Ok(());
+            }
+            state.dead_letters.replay_entry(&entry).await
+        }
+        BulkOperation::DiscardDeadLetters => {
+            let id = parse_id(id)?;
+            state.dead_letters.pending(id).await?;
+            if dry_run {
+                return Ok(());
+            }
+            match state.dead_letters.discard(&[id]).await? {
+                0 => Err(SecurityError::NotFound(format!("No pending dead letter {}", id))),
+                _ => Ok(()),
+            }
+        }
+    }
+}
+
+async fn run_item(state: &AppState, principal: &Principal, request: &BulkRequest, id: String) -> ItemResult {
+    let result = apply(state, request.operation, &id, request.dry_run).await;
+
+    if !request.dry_run {
+        let recorded = state.audit_service.record(NewAuditEvent {
+            tenant_id: principal.tenant_id.clone(),
+            actor: principal.subject.clone(),
+            actor_ip: None,
+            action: request.operation.action().to_string(),
+            resource: request.operation.resource(&id),
+            outcome: if result.is_ok() { "success" } else { "failure" }.to_string(),
+            payload: serde_json::json!({
+                "error": result.as_ref().err().map(|e| e.to_string()),
+            }),
+        }).await;
+        if let Err(e) = recorded {
+            warn!("Failed to audit bulk item {}: {:?}", id, e);
+        }
+    }
+
+    match result {
+        Ok(()) => ItemResult {
+            id,
+            status: if request.dry_run { ItemStatus::WouldSucceed } else { ItemStatus::Succeeded },
+            error: None,
+        },
+        Err(SecurityError::StorageError(e)) => {
+            error!("Bulk item {} failed: {}", id, e);
+            ItemResult {
+                id,
+                status: ItemStatus::Failed,
+                error: Some("Internal error".to_string()),
+            }
+        }
+        Err(e) => ItemResult {
+            id,
+            status: ItemStatus::Failed,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+pub async fn execute(
+    state: &AppState,
+    principal: &Principal,
+    request: &BulkRequest,
+) -> Result<BulkReport, SecurityError> {
+    if request.ids.is_empty() {
+        return Err(SecurityError::ValidationError("No items given".to_string()));
+    }
+    if request.ids.len() > state.config.bulk.max_items {
+        return Err(SecurityError::ValidationError(format!(
+            "At most {} items per bulk request",
+            state.config.bulk.max_items
+        )));
+    }
+
+    let results: Vec<ItemResult> = stream::iter(request.ids.iter().cloned())
+        .map(|id| run_item(state, principal, request, id))
+        .buffered(state.config.bulk.concurrency.max(1))
+        .collect()
+        .await;
+
+    let failed = results.iter().filter(|r| r.status == ItemStatus::Failed).count();
+    Ok(BulkReport {
+        operation: request.operation,
+        dry_run: request.dry_run,
+        succeeded: results.len() - failed,
+        failed,
+        results,
+    })
+}
+
+// HTTP handlers
+
+pub async fn bulk_handler(
+    req: HttpRequest,
+    request: web::Json<BulkRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match execute(&state, &principal, &request).await {
+        Ok(report) => {
+            info!(
+                "Bulk {:?} by {} (dry_run={}): {} succeeded, {} failed",
+                report.operation, principal.subject, report.dry_run, report.succeeded, report.failed
+            );
+            if report.failed == 0 {
+                Ok(HttpResponse::Ok().json(report))
+            } else {
+                Ok(HttpResponse::MultiStatus().json(report))
+            }
+        }
+        Err(e) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e.to_string()
+        }))),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/admin/bulk", web::post().to(bulk_handler));
+}
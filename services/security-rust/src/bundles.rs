@@ -0,0 +1,431 @@
+/*!
+Configuration Bundles
+Signed, encrypted policies, correlation rules and feature flags from the platform pipeline
+
+On-prem installs take their configuration from a central pipeline rather
+than from their own admins. The pipeline publishes a bundle:
+
+```json
+{"bundle_id": "2026-10-16.1", "version": 42, "created_at": "2026-10-16T09:00:00Z",
+ "signer": "platform", "wrapped_key": "...", "nonce": "<base64>",
+ "ciphertext": "<base64>", "signature": "<base64>"}
+```
+
+`signature` is Ed25519 by the `signer` key in `CONFIG_BUNDLE_SIGNERS` over
+`cotai-config-bundle:<bundle_id>:<version>:<created_at>:<signer>:<wrapped
+key sha256>:<nonce>:<ciphertext sha256>`, and is checked before anything
+is decrypted. `wrapped_key` is a 32-byte data key wrapped by this
+service's key provider (the KEK, see `key_provider`) under the key id
+`config-bundle:<bundle_id>`; the pipeline wraps with the same Vault
+transit or KMS key. The ciphertext is AES-256-GCM under that data key
+with `cotai-config-bundle:<bundle_id>:<version>` as AAD, and opens to
+
+```json
+{"policies": [{"tenant_id": null, "name": "...", "document": {...}}],
+ "correlation_rules": {"<name>": {...}},
+ "flags": {"<key>": {"enabled": true, ...}}}
+```
+
+Everything in a bundle is written in one transaction, so a bundle is
+applied whole or not at all. Policies are matched by tenant and name,
+rules and flags by name, and each write is in the change history like an
+admin's. Anything a bundle does not mention is left alone. Bundles apply
+only over an older version; replicas racing on the same bundle apply it
+once.
+
+`CONFIG_BUNDLE_FILE` is read on startup and every
+`CONFIG_BUNDLE_POLL_SECS`, so dropping a newer bundle in place rolls it
+out. `POST /admin/config-bundles` applies one directly and
+`GET /admin/config-bundles` lists those applied.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+use ring::digest::{digest, SHA256};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::{debug, error, info, warn};
+
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, client_ip};
+use crate::config::{BundleConfig, Config};
+use crate::detection::correlation::RuleDefinition;
+use crate::errors::SecurityError;
+use crate::flags::FlagRequest;
+use crate::key_provider::KeyProvider;
+use crate::policies::PolicyRequest;
+use crate::storage::Storage;
+use crate::AppState;
+
+const BUNDLE_COLUMNS: &str = "version, bundle_id, signer, created_at, payload_sha256, policies, \
+    correlation_rules, flags, applied_by, applied_at";
+
+/// Bundles listed at most.
+const MAX_LISTED: i64 = 50;
+
+/// A bundle as published.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bundle {
+    pub bundle_id: String,
+    pub version: i64,
+    pub created_at: DateTime<Utc>,
+    pub signer: String,
+    pub wrapped_key: String,
+    pub nonce: String,
+    pub ciphertext: String,
+    pub signature: String,
+}
+
+impl Bundle {
+    fn message(&self) -> String {
+        format!(
+            "cotai-config-bundle:{}:{}:{}:{}:{}:{}:{}",
+            self.bundle_id,
+            self.version,
+            self.created_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            self.signer,
+            sha256_hex(self.wrapped_key.as_bytes()),
+            self.nonce,
+            sha256_hex(self.ciphertext.as_bytes()),
+        )
+    }
+
+    fn aad(&self) -> String {
+        format!("cotai-config-bundle:{}:{}", self.bundle_id, self.version)
+    }
+}
+
+/// What a bundle opens to.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Payload {
+    #[serde(default)]
+    pub policies: Vec<PolicyRequest>,
+    #[serde(default)]
+    pub correlation_rules: BTreeMap<String, RuleDefinition>,
+    #[serde(default)]
+    pub flags: BTreeMap<String, FlagRequest>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct AppliedBundle {
+    pub version: i64,
+    pub bundle_id: String,
+    pub signer: String,
+    pub created_at: DateTime<Utc>,
+    pub payload_sha256: String,
+    pub policies: i32,
+    pub correlation_rules: i32,
+    pub flags: i32,
+    pub applied_by: String,
+    pub applied_at: DateTime<Utc>,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(digest(&SHA256, data))
+}
+
+fn valid_bundle_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 128
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_')
+}
+
+pub struct ConfigBundles {
+    storage: Storage,
+    key_provider: Arc<dyn KeyProvider>,
+    config: BundleConfig,
+    /// Public keys by signer name.
+    signers: BTreeMap<String, Vec<u8>>,
+}
+
+impl ConfigBundles {
+    pub async fn new(config: &Config, key_provider: Arc<dyn KeyProvider>, storage: Storage) -> Result<Self, SecurityError> {
+        let signers = config
+            .bundles
+            .signers
+            .iter()
+            .map(|(name, key)| {
+                base64::decode(key)
+                    .map(|key| (name.clone(), key))
+                    .map_err(|_| SecurityError::ConfigError(format!("CONFIG_BUNDLE_SIGNERS: '{}' is not base64", name)))
+            })
+            .collect::<Result<BTreeMap<_, _>, _>>()?;
+
+        info!(
+            "Configuration bundles initialized ({} signers, file {})",
+            signers.len(),
+            config.bundles.file.as_deref().unwrap_or("none")
+        );
+        Ok(Self { storage, key_provider, config: config.bundles.clone(), signers })
+    }
+
+    /// Read a bundle, refusing anything over `CONFIG_BUNDLE_MAX_BYTES`.
+    pub fn parse(&self, data: &[u8]) -> Result<Bundle, SecurityError> {
+        if data.len() > self.config.max_bytes {
+            return Err(SecurityError::ValidationError(format!(
+                "Bundle is over {} bytes",
+                self.config.max_bytes
+            )));
+        }
+        let bundle: Bundle = serde_json::from_slice(data)
+            .map_err(|e| SecurityError::ValidationError(format!("Invalid bundle: {}", e)))?;
+        if !valid_bundle_id(&bundle.bundle_id) {
+            return Err(SecurityError::ValidationError(
+                "bundle_id must be 1-128 letters, digits, '.', '-' or '_'".to_string(),
+            ));
+        }
+        if bundle.version < 1 {
+            return Err(SecurityError::ValidationError("Bundle version must be positive".to_string()));
+        }
+        Ok(bundle)
+    }
+
+    fn verify(&self, bundle: &Bundle) -> Result<(), SecurityError> {
+        let key = self.signers.get(&bundle.signer).ok_or_else(|| {
+            SecurityError::ValidationError(format!("Signer '{}' is not in CONFIG_BUNDLE_SIGNERS", bundle.signer))
+        })?;
+        let signature = base64::decode(&bundle.signature)
+            .map_err(|_| SecurityError::ValidationError("Bundle signature must be base64".to_string()))?;
+        UnparsedPublicKey::new(&ED25519, key)
+            .verify(bundle.message().as_bytes(), &signature)
+            .map_err(|_| SecurityError::ValidationError("Bundle signature does not verify".to_string()))
+    }
+
+    /// Check the signature, unwrap the data key and decrypt.
+    async fn open(&self, bundle: &Bundle) -> Result<(Payload, String), SecurityError> {
+        self.verify(bundle)?;
+
+        let key_id = format!("config-bundle:{}", bundle.bundle_id);
+        let data_key = self.key_provider.unwrap(&key_id, &bundle.wrapped_key).await?;
+        let key = UnboundKey::new(&AES_256_GCM, &data_key)
+            .map(LessSafeKey::new)
+            .map_err(|_| SecurityError::CryptoError("Bundle data key is not an AES-256 key".to_string()))?;
+
+        let invalid = || SecurityError::CryptoError(format!("Bundle {} does not decrypt", bundle.bundle_id));
+        let nonce = base64::decode(&bundle.nonce)
+            .ok()
+            .and_then(|nonce| Nonce::try_assume_unique_for_key(&nonce).ok())
+            .ok_or_else(invalid)?;
+        let mut sealed = base64::decode(&bundle.ciphertext).map_err(|_| invalid())?;
+        let plaintext = key
+            .open_in_place(nonce, Aad::from(bundle.aad().as_bytes()), &mut sealed)
+            .map_err(|_| invalid())?;
+
+        let payload = serde_json::from_slice(plaintext)
+            .map_err(|e| SecurityError::ValidationError(format!("Invalid bundle payload: {}", e)))?;
+        Ok((payload, sha256_hex(plaintext)))
+    }
+
+    /// The version of the newest bundle applied, if any.
+    pub async fn applied_version(&self) -> Result<Option<i64>, SecurityError> {
+        let version: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM config_bundles")
+            .fetch_one(self.storage.pool())
+            .await?;
+        Ok(version)
+    }
+
+    pub async fn list(&self) -> Result<Vec<AppliedBundle>, SecurityError> {
+        let bundles = sqlx::query_as::<_, AppliedBundle>(&format!(
+            "SELECT {} FROM config_bundles ORDER BY version DESC LIMIT $1",
+            BUNDLE_COLUMNS
+        ))
+        .bind(MAX_LISTED)
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(bundles)
+    }
+
+    /// Verify, decrypt and apply a bundle in one transaction. A bundle no
+    /// newer than the last one applied is a conflict.
+    pub async fn apply(&self, state: &AppState, bundle: &Bundle, applied_by: &str) -> Result<AppliedBundle, SecurityError> {
+        let (payload, payload_sha256) = self.open(bundle).await?;
+
+        let mut tx = self.storage.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext('config-bundles'))")
+            .execute(&mut *tx)
+            .await?;
+        let applied: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM config_bundles")
+            .fetch_one(&mut *tx)
+            .await?;
+        if let Some(applied) = applied.filter(|applied| *applied >= bundle.version) {
+            return Err(SecurityError::Conflict(format!(
+                "Bundle version {} is not newer than the applied version {}",
+                bundle.version, applied
+            )));
+        }
+
+        let counts = (
+            payload.policies.len() as i32,
+            payload.correlation_rules.len() as i32,
+            payload.flags.len() as i32,
+        );
+        for policy in payload.policies {
+            state.policy_service.put_in(&mut tx, applied_by, policy).await?;
+        }
+        for (name, definition) in payload.correlation_rules {
+            state.correlation.put_in(&mut tx, applied_by, &name, definition).await?;
+        }
+        for (key, flag) in payload.flags {
+            state.feature_flags.put_in(&mut tx, applied_by, &key, None, flag).await?;
+        }
+
+        let record = sqlx::query_as::<_, AppliedBundle>(&format!(
+            "INSERT INTO config_bundles (version, bundle_id, signer, created_at, payload_sha256, policies, \
+             correlation_rules, flags, applied_by) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING {}",
+            BUNDLE_COLUMNS
+        ))
+        .bind(bundle.version)
+        .bind(&bundle.bundle_id)
+        .bind(&bundle.signer)
+        .bind(bundle.created_at)
+        .bind(&payload_sha256)
+        .bind(counts.0)
+        .bind(counts.1)
+        .bind(counts.2)
+        .bind(applied_by)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        state.feature_flags.refresh().await?;
+        info!(
+            "Applied configuration bundle {} version {} ({} policies, {} rules, {} flags)",
+            record.bundle_id, record.version, record.policies, record.correlation_rules, record.flags
+        );
+        Ok(record)
+    }
+
+    /// Apply `CONFIG_BUNDLE_FILE` if it holds a newer bundle than applied.
+    async fn apply_file(&self, state: &AppState, path: &str) -> Result<Option<AppliedBundle>, SecurityError> {
+        let data = tokio::fs::read(path)
+            .await
+            .map_err(|e| SecurityError::ConfigError(format!("CONFIG_BUNDLE_FILE {}: {}", path, e)))?;
+        let bundle = self.parse(&data)?;
+        if self.applied_version().await?.is_some_and(|applied| applied >= bundle.version) {
+            debug!("Configuration bundle {} version {} already applied", bundle.bundle_id, bundle.version);
+            return Ok(None);
+        }
+        let applied_by = format!("config-bundle:{}", bundle.signer);
+        match self.apply(state, &bundle, &applied_by).await {
+            Ok(record) => Ok(Some(record)),
+            Err(SecurityError::Conflict(msg)) => {
+                // Another replica applied it first
+                if self.applied_version().await?.is_some_and(|applied| applied >= bundle.version) {
+                    return Ok(None);
+                }
+                Err(SecurityError::Conflict(msg))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+async fn audit_bundle(state: &AppState, actor: String, actor_ip: Option<String>, record: &AppliedBundle) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: None,
+        actor,
+        actor_ip,
+        action: "config_bundle.apply".to_string(),
+        resource: format!("config_bundle:{}", record.version),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "bundle_id": record.bundle_id,
+            "signer": record.signer,
+            "payload_sha256": record.payload_sha256,
+            "policies": record.policies,
+            "correlation_rules": record.correlation_rules,
+            "flags": record.flags
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit configuration bundle {}: {:?}", record.version, e);
+    }
+}
+
+/// Background loop applying `CONFIG_BUNDLE_FILE` on startup and whenever
+/// a newer bundle is put in its place.
+pub async fn run_watch(state: web::Data<AppState>) {
+    let Some(path) = state.config.bundles.file.clone() else {
+        return;
+    };
+    let interval_secs = state.config.bundles.poll_interval_secs;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+
+    loop {
+        interval.tick().await;
+        match state.bundles.apply_file(&state, &path).await {
+            Ok(Some(record)) => audit_bundle(&state, record.applied_by.clone(), None, &record).await,
+            Ok(None) => {}
+            Err(e) => error!("Configuration bundle from {} not applied: {:?}", path, e),
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::CryptoError(msg) => HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Configuration bundle operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Configuration bundle operation failed"
+            }))
+        }
+    }
+}
+
+pub async fn apply_handler(req: HttpRequest, body: web::Bytes, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let bundle = match state.bundles.parse(&body) {
+        Ok(bundle) => bundle,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.bundles.apply(&state, &bundle, &principal.subject).await {
+        Ok(record) => {
+            audit_bundle(&state, principal.subject.clone(), client_ip(&req), &record).await;
+            Ok(HttpResponse::Created().json(record))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.bundles.list().await {
+        Ok(bundles) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "bundles": bundles,
+            "signers": state.config.bundles.signers.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            "file": state.config.bundles.file
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/config-bundles")
+            .route("", web::post().to(apply_handler))
+            .route("", web::get().to(list_handler)),
+    );
+}
@@ -0,0 +1,314 @@
+/*!
+Change History Module
+Versioned record of administrative changes with diffs and rollback
+
+Changes made while serving a recorded admin command carry its id, so the
+command log (see `commands`) shows the state each command changed.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder, Transaction};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::auth::auth_error_response;
+use crate::commands;
+use crate::concurrency;
+use crate::containment::PlaybookDefinition;
+use crate::detection::correlation::RuleDefinition;
+use crate::errors::SecurityError;
+use crate::flags::FlagRequest;
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::policies::PolicyRequest;
+use crate::soar::FieldMapping;
+use crate::storage::Storage;
+use crate::tenant_settings::TenantOverrides;
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "changed_at", column: "changed_at", kind: KeyKind::Timestamp },
+];
+
+const SELECT_COLUMNS: &str = "id, resource_type, resource_id, version, action, author, tenant_id, \
+    changed_at, before, after, diff, command_id";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Change {
+    pub id: Uuid,
+    pub resource_type: String,
+    pub resource_id: String,
+    pub version: Option<i64>,
+    pub action: String,
+    pub author: String,
+    pub tenant_id: Option<String>,
+    pub changed_at: DateTime<Utc>,
+    /// Editable state before the change; `None` on creation.
+    pub before: Option<serde_json::Value>,
+    /// Editable state after the change; `None` on deletion.
+    pub after: Option<serde_json::Value>,
+    pub diff: serde_json::Value,
+    /// The admin command the change was made by, when recorded.
+    pub command_id: Option<Uuid>,
+}
+
+pub struct NewChange<'a> {
+    pub resource_type: &'a str,
+    pub resource_id: String,
+    pub version: Option<i64>,
+    pub action: &'a str,
+    pub author: &'a str,
+    pub tenant_id: Option<String>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ChangeFilter {
+    pub resource_type: Option<String>,
+    pub resource_id: Option<String>,
+    pub author: Option<String>,
+}
+
+/// Record a change inside the caller's transaction, next to the write it describes.
+pub async fn record(tx: &mut Transaction<'_, Postgres>, change: NewChange<'_>) -> Result<Uuid, SecurityError> {
+    let empty = serde_json::json!({});
+    let diff = concurrency::json_diff(
+        change.before.as_ref().unwrap_or(&empty),
+        change.after.as_ref().unwrap_or(&empty),
+    );
+
+    let id = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO change_history \
+         (id, resource_type, resource_id, version, action, author, tenant_id, before, after, diff, command_id) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+    )
+    .bind(id)
+    .bind(change.resource_type)
+    .bind(&change.resource_id)
+    .bind(change.version)
+    .bind(change.action)
+    .bind(change.author)
+    .bind(&change.tenant_id)
+    .bind(&change.before)
+    .bind(&change.after)
+    .bind(serde_json::to_value(diff).unwrap_or_default())
+    .bind(commands::current())
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(id)
+}
+
+pub struct ChangeHistory {
+    storage: Storage,
+}
+
+impl ChangeHistory {
+    pub async fn new(storage: Storage) -> Result<Self, SecurityError> {
+
+        info!("Change history initialized successfully");
+        Ok(Self { storage })
+    }
+
+    pub async fn list(&self, filter: &ChangeFilter, page: &PageRequest) -> Result<Page<Change>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM change_history WHERE 1 = 1",
+            SELECT_COLUMNS
+        ));
+        if let Some(resource_type) = &filter.resource_type {
+            builder.push(" AND resource_type = ").push_bind(resource_type.clone());
+        }
+        if let Some(resource_id) = &filter.resource_id {
+            builder.push(" AND resource_id = ").push_bind(resource_id.clone());
+        }
+        if let Some(author) = &filter.author {
+            builder.push(" AND author = ").push_bind(author.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let changes = builder
+            .build_query_as::<Change>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(changes, |change, _| (SortKey::Timestamp(change.changed_at), change.id)))
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<Change, SecurityError> {
+        sqlx::query_as::<_, Change>(&format!(
+            "SELECT {} FROM change_history WHERE id = $1",
+            SELECT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("Change not found".to_string()))
+    }
+
+    /// Changes made by one admin command, in order.
+    pub async fn for_command(&self, command_id: Uuid) -> Result<Vec<Change>, SecurityError> {
+        Ok(sqlx::query_as::<_, Change>(&format!(
+            "SELECT {} FROM change_history WHERE command_id = $1 ORDER BY changed_at, id",
+            SELECT_COLUMNS
+        ))
+        .bind(command_id)
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Change history operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Change history operation failed"
+            }))
+        }
+    }
+}
+
+pub async fn list_changes_handler(
+    req: HttpRequest,
+    filter: web::Query<ChangeFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.change_history.list(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "changes": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_change_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.change_history.get(path.into_inner()).await {
+        Ok(change) => Ok(HttpResponse::Ok().json(change)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+/// Return the resource to the state recorded right after the given change.
+pub async fn rollback_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let change = match state.change_history.get(path.into_inner()).await {
+        Ok(change) => change,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    let Some(snapshot) = change.after.clone() else {
+        return Ok(error_response(SecurityError::ValidationError(
+            "This change deleted the resource; restore it instead".to_string(),
+        )));
+    };
+
+    let result = match change.resource_type.as_str() {
+        "policy" => {
+            let id = Uuid::parse_str(&change.resource_id)
+                .map_err(|_| SecurityError::ValidationError("Corrupt policy id".to_string()));
+            let request = serde_json::from_value::<PolicyRequest>(snapshot)
+                .map_err(|e| SecurityError::ValidationError(format!("Corrupt policy snapshot: {}", e)));
+            match (id, request) {
+                (Ok(id), Ok(request)) => state.policy_service
+                    .rollback(&principal, id, request)
+                    .await
+                    .map(|policy| serde_json::to_value(policy).unwrap_or_default()),
+                (Err(e), _) | (_, Err(e)) => Err(e),
+            }
+        }
+        "feature_flag" => match serde_json::from_value::<FlagRequest>(snapshot) {
+            Ok(request) => state.feature_flags
+                .put(&principal, &change.resource_id, None, request)
+                .await
+                .map(|flag| serde_json::to_value(flag).unwrap_or_default()),
+            Err(e) => Err(SecurityError::ValidationError(format!("Corrupt flag snapshot: {}", e))),
+        },
+        "tenant_settings" => match serde_json::from_value::<TenantOverrides>(snapshot) {
+            Ok(overrides) => state.tenant_settings
+                .put(&principal, &change.resource_id, overrides)
+                .await
+                .map(|settings| serde_json::to_value(settings).unwrap_or_default()),
+            Err(e) => Err(SecurityError::ValidationError(format!("Corrupt tenant settings snapshot: {}", e))),
+        },
+        "correlation_rule" => match serde_json::from_value::<RuleDefinition>(snapshot) {
+            Ok(definition) => state.correlation
+                .put(&principal, &change.resource_id, definition)
+                .await
+                .map(|rule| serde_json::to_value(rule).unwrap_or_default()),
+            Err(e) => Err(SecurityError::ValidationError(format!("Corrupt correlation rule snapshot: {}", e))),
+        },
+        "playbook" => match serde_json::from_value::<PlaybookDefinition>(snapshot) {
+            Ok(definition) => state.containment
+                .put_playbook(&principal, &change.resource_id, definition)
+                .await
+                .map(|playbook| serde_json::to_value(playbook).unwrap_or_default()),
+            Err(e) => Err(SecurityError::ValidationError(format!("Corrupt playbook snapshot: {}", e))),
+        },
+        "soar_mapping" => match serde_json::from_value::<FieldMapping>(snapshot) {
+            Ok(mapping) => state.soar
+                .put_mapping(&principal, &change.resource_id, mapping)
+                .await
+                .map(|mapping| serde_json::to_value(mapping).unwrap_or_default()),
+            Err(e) => Err(SecurityError::ValidationError(format!("Corrupt SOAR mapping snapshot: {}", e))),
+        },
+        other => Err(SecurityError::ValidationError(format!("Rollback is not supported for '{}'", other))),
+    };
+
+    match result {
+        Ok(resource) => {
+            info!("{} rolled back {} {} to change {}", principal.subject, change.resource_type, change.resource_id, change.id);
+            Ok(HttpResponse::Ok().json(resource))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/changes")
+            .route("", web::get().to(list_changes_handler))
+            .route("/{id}", web::get().to(get_change_handler))
+            .route("/{id}/rollback", web::post().to(rollback_handler))
+    );
+}
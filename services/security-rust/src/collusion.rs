@@ -0,0 +1,410 @@
+/*!
+Collusion Module
+Duplicate-bidder and collusion screening over blind indexes
+
+`POST /collusion/analyses` (needs `COLLUSION_ANALYZE_SCOPE`) takes the bids
+of one tender, each with the identifiers its supplier declared, and
+reports which bids share identifiers they should not: the same CNPJ under
+two bids, a partner's CPF in two suppliers, one bank account paying out
+for both. The analyst sees scores and evidence references, never a
+document number or account.
+
+Each identifier is sent either as a token from the vault (see
+`crypto::tokenization`) or as a value. Tokens resolve to the vault's blind
+index of their value without being detokenized; values are normalized,
+blind-indexed in memory and dropped. Either way bids are compared only by
+keyed hashes, and a token and the value behind it compare equal.
+
+A pair of bids scores `1 - Π(1 - weight)` over the kinds of identifier
+they share, so one strong link or several weak ones flag it; pairs at or
+above `COLLUSION_THRESHOLD` are `flagged`. Default weights, which
+`COLLUSION_WEIGHTS` overrides per kind:
+
+| kind | weight | |
+|------|--------|-|
+| `cnpj` | 1.0 | the same company behind two bids |
+| `bank_account` | 0.9 | |
+| `cpf` | 0.7 | a partner or representative in common |
+| `email` | 0.6 | |
+| `phone` | 0.5 | |
+| `address` | 0.4 | |
+| `ip` | 0.3 | bids sent from the same address |
+
+Every shared identifier is one piece of evidence, referenced by an id that
+means nothing outside its analysis and listing the tokens behind it, if
+any were sent. Turning those into values takes a detokenization, with its
+own scope, purpose and audit trail. Analyses are kept and audited;
+`GET /collusion/analyses/{id}` returns one again.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::FromRow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::sync::Arc;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::audit::receipts::{self, RECEIPT_HEADER};
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{CollusionConfig, Config};
+use crate::crypto::{tokenization, CryptoService};
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+use crate::AppState;
+
+/// Kinds of identifier and their default weights.
+pub const KINDS: &[(&str, f64)] = &[
+    ("cnpj", 1.0),
+    ("bank_account", 0.9),
+    ("cpf", 0.7),
+    ("email", 0.6),
+    ("phone", 0.5),
+    ("address", 0.4),
+    ("ip", 0.3),
+];
+
+/// Kinds the vault tokenizes; their indexes are the vault's.
+const VAULT_KINDS: &[&str] = &["cpf", "cnpj", "bank_account"];
+
+pub(crate) const ANALYSIS_COLUMNS: &str = "id, tenant_id, tender, bid_count, identifier_count, flagged, pairs, created_by, created_at";
+
+const MAX_IDENTIFIERS_PER_BID: usize = 50;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Identifier {
+    pub kind: String,
+    /// A vault token for the value; `cpf`, `cnpj` and `bank_account` only.
+    pub token: Option<String>,
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bid {
+    /// The caller's reference for the bid, e.g. `bid:12`.
+    pub bid: String,
+    pub identifiers: Vec<Identifier>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnalyzeRequest {
+    pub tender: String,
+    pub bids: Vec<Bid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Evidence {
+    /// Meaningful only within its analysis.
+    pub id: String,
+    pub kind: String,
+    /// Every bid declaring the identifier, not only the pair's.
+    pub bids: Vec<String>,
+    /// Vault tokens sent for it; values sent directly leave none.
+    pub tokens: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairScore {
+    pub bids: [String; 2],
+    pub score: f64,
+    pub flagged: bool,
+    /// Kinds of identifier the pair shares.
+    pub shared: Vec<String>,
+    pub evidence: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Findings {
+    pub pairs: Vec<PairScore>,
+    pub evidence: Vec<Evidence>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Analysis {
+    pub id: Uuid,
+    pub tenant_id: Option<String>,
+    pub tender: String,
+    pub bid_count: i32,
+    pub identifier_count: i32,
+    pub flagged: i32,
+    /// Pairs sharing anything, highest score first, with their evidence.
+    pub pairs: Json<Findings>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A value as indexed. Vault kinds normalize as the vault does, so tokens
+/// and values meet.
+fn normalize(kind: &str, value: &str) -> Result<String, SecurityError> {
+    if VAULT_KINDS.contains(&kind) {
+        return tokenization::normalize(kind, value);
+    }
+    let invalid = |msg: &str| SecurityError::ValidationError(format!("Invalid {}: {}", kind, msg));
+    let normalized = match kind {
+        "email" => value.trim().to_lowercase(),
+        "phone" => value.chars().filter(char::is_ascii_digit).collect(),
+        "address" => value.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase(),
+        _ => value.trim().parse::<IpAddr>().map_err(|_| invalid("not an IP address"))?.to_string(),
+    };
+    if normalized.is_empty() || normalized.len() > 512 {
+        return Err(invalid("must be 1-512 characters"));
+    }
+    Ok(normalized)
+}
+
+pub struct CollusionService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: CollusionConfig,
+}
+
+impl CollusionService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        info!("Collusion screening initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            config: config.collusion.clone(),
+        })
+    }
+
+    fn weight(&self, kind: &str) -> f64 {
+        self.config
+            .weights
+            .iter()
+            .find(|(name, _)| name == kind)
+            .map(|(_, weight)| *weight)
+            .or_else(|| KINDS.iter().find(|(name, _)| *name == kind).map(|(_, weight)| *weight))
+            .unwrap_or(0.0)
+    }
+
+    /// Vault indexes of the caller's tenant's tokens, by token.
+    async fn token_indexes(&self, tenant_id: Option<&str>, tokens: &[String]) -> Result<HashMap<String, (String, String)>, SecurityError> {
+        let rows: Vec<(String, String, String)> = sqlx::query_as(
+            "SELECT token, data_type, value_index FROM pii_tokens WHERE COALESCE(tenant_id, '') = $1 AND token = ANY($2)",
+        )
+        .bind(tenant_id.unwrap_or_default())
+        .bind(tokens)
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(rows.into_iter().map(|(token, data_type, index)| (token, (data_type, index))).collect())
+    }
+
+    pub async fn analyze(
+        &self,
+        crypto: &CryptoService,
+        principal: &Principal,
+        request: &AnalyzeRequest,
+    ) -> Result<Analysis, SecurityError> {
+        let tender = request.tender.trim();
+        if tender.is_empty() || tender.len() > 200 {
+            return Err(SecurityError::ValidationError("tender must be 1-200 characters".to_string()));
+        }
+        if request.bids.len() < 2 || request.bids.len() > self.config.max_bids {
+            return Err(SecurityError::ValidationError(format!("Send 2-{} bids", self.config.max_bids)));
+        }
+        let mut seen = BTreeSet::new();
+        for bid in &request.bids {
+            if bid.bid.trim().is_empty() || !seen.insert(bid.bid.trim()) {
+                return Err(SecurityError::ValidationError("Each bid needs its own non-empty reference".to_string()));
+            }
+            if bid.identifiers.len() > MAX_IDENTIFIERS_PER_BID {
+                return Err(SecurityError::ValidationError(format!(
+                    "At most {} identifiers per bid",
+                    MAX_IDENTIFIERS_PER_BID
+                )));
+            }
+        }
+
+        let tokens: Vec<String> = request
+            .bids
+            .iter()
+            .flat_map(|bid| bid.identifiers.iter().filter_map(|identifier| identifier.token.clone()))
+            .collect();
+        let resolved = self.token_indexes(principal.tenant_id.as_deref(), &tokens).await?;
+
+        // (kind, index) -> bids declaring it and the tokens sent for it
+        let mut declared: BTreeMap<(String, String), (BTreeSet<String>, BTreeSet<String>)> = BTreeMap::new();
+        let mut identifier_count = 0;
+        for bid in &request.bids {
+            for identifier in &bid.identifiers {
+                let kind = identifier.kind.as_str();
+                if !KINDS.iter().any(|(name, _)| *name == kind) {
+                    return Err(SecurityError::ValidationError(format!("Unknown identifier kind '{}'", kind)));
+                }
+                let index = match (&identifier.token, &identifier.value) {
+                    (Some(token), None) => match resolved.get(token) {
+                        Some((data_type, index)) if data_type == kind => index.clone(),
+                        _ => return Err(SecurityError::ValidationError(format!("Unknown {} token", kind))),
+                    },
+                    (None, Some(value)) => {
+                        let domain = if VAULT_KINDS.contains(&kind) { "pii_token" } else { "collusion" };
+                        crypto.blind_index(domain, &format!("{}:{}", kind, normalize(kind, value)?))
+                    }
+                    _ => {
+                        return Err(SecurityError::ValidationError(
+                            "Each identifier needs exactly one of token or value".to_string(),
+                        ))
+                    }
+                };
+                identifier_count += 1;
+                let entry = declared.entry((kind.to_string(), index)).or_default();
+                entry.0.insert(bid.bid.trim().to_string());
+                entry.1.extend(identifier.token.clone());
+            }
+        }
+
+        let id = Uuid::new_v4();
+        let mut evidence = Vec::new();
+        let mut pairs: BTreeMap<(String, String), (BTreeSet<String>, Vec<String>)> = BTreeMap::new();
+        for ((kind, index), (bids, tokens)) in declared {
+            if bids.len() < 2 {
+                continue;
+            }
+            // Bound to the analysis, so references never link analyses
+            let reference = format!("ev_{}", &crypto.blind_index("collusion_evidence", &format!("{}:{}", id, index))[..16]);
+            let bids: Vec<String> = bids.into_iter().collect();
+            for (i, first) in bids.iter().enumerate() {
+                for second in &bids[i + 1..] {
+                    let pair = pairs.entry((first.clone(), second.clone())).or_default();
+                    pair.0.insert(kind.clone());
+                    pair.1.push(reference.clone());
+                }
+            }
+            evidence.push(Evidence { id: reference, kind, bids, tokens: tokens.into_iter().collect() });
+        }
+
+        let mut scored: Vec<PairScore> = pairs
+            .into_iter()
+            .map(|((first, second), (shared, evidence))| {
+                let score = 1.0 - shared.iter().map(|kind| 1.0 - self.weight(kind)).product::<f64>();
+                let score = (score * 1000.0).round() / 1000.0;
+                PairScore {
+                    bids: [first, second],
+                    score,
+                    flagged: score >= self.config.threshold,
+                    shared: shared.into_iter().collect(),
+                    evidence,
+                }
+            })
+            .collect();
+        scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+        let flagged = scored.iter().filter(|pair| pair.flagged).count();
+
+        let analysis = sqlx::query_as::<_, Analysis>(&format!(
+            "INSERT INTO collusion_analyses ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING {}",
+            ANALYSIS_COLUMNS, ANALYSIS_COLUMNS
+        ))
+        .bind(id)
+        .bind(&principal.tenant_id)
+        .bind(tender)
+        .bind(request.bids.len() as i32)
+        .bind(identifier_count)
+        .bind(flagged as i32)
+        .bind(Json(Findings { pairs: scored, evidence }))
+        .bind(&principal.subject)
+        .bind(self.clock.now())
+        .fetch_one(self.storage.pool())
+        .await?;
+        info!("Collusion analysis {} of {} flagged {} pairs", analysis.id, analysis.tender, analysis.flagged);
+        Ok(analysis)
+    }
+
+    pub async fn get(&self, tenant_id: Option<&str>, id: Uuid) -> Result<Analysis, SecurityError> {
+        sqlx::query_as::<_, Analysis>(&format!("SELECT {} FROM collusion_analyses WHERE id = $1", ANALYSIS_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .filter(|analysis| tenant_id.is_none() || analysis.tenant_id.as_deref() == tenant_id)
+            .ok_or_else(|| SecurityError::NotFound(format!("No analysis {}", id)))
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Collusion analysis failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Collusion analysis failed"
+            }))
+        }
+    }
+}
+
+pub async fn analyze_handler(
+    req: HttpRequest,
+    request: web::Json<AnalyzeRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.collusion.analyze_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let analysis = match state.collusion.analyze(&state.crypto_service, &principal, &request).await {
+        Ok(analysis) => analysis,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    let receipt = receipts::record_or_warn(&state, NewAuditEvent {
+        tenant_id: analysis.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(&req),
+        action: "collusion.analyze".to_string(),
+        resource: format!("collusion_analysis:{}", analysis.id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "tender": analysis.tender,
+            "bid_count": analysis.bid_count,
+            "identifier_count": analysis.identifier_count,
+            "flagged": analysis.flagged
+        }),
+    }).await;
+
+    let mut response = HttpResponse::Created();
+    if let Some(receipt) = receipt {
+        response.insert_header((RECEIPT_HEADER, receipt));
+    }
+    Ok(response.json(analysis))
+}
+
+pub async fn get_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.collusion.analyze_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.collusion.get(principal.tenant_id.as_deref(), path.into_inner()).await {
+        Ok(analysis) => Ok(HttpResponse::Ok().json(analysis)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/collusion/analyses")
+            .route("", web::post().to(analyze_handler))
+            .route("/{id}", web::get().to(get_handler)),
+    );
+}
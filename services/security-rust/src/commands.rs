@@ -0,0 +1,488 @@
+/*!
+Admin Command Log
+Replayable record of what operators changed
+
+Every mutating request (anything but `GET`, `HEAD` and `OPTIONS`) from a
+caller holding one of `SECURITY_ADMIN_ROLES`, or to a path under
+`/api/v1/admin`, is recorded as a command once it has been answered: who
+sent it, from where, under which login (the token's `jti`, so one
+operator's session reads as one), the method, path and query, the JSON
+body, the status and the JSON response. Requests the pipeline rejects
+never reach a handler and are not recorded. Changes the command made
+through the change history (see `changes`) carry its id, so a command
+shows each resource's state before and after it.
+
+Bodies are kept up to `COMMAND_LOG_MAX_BODY_BYTES`. Values of JSON and
+query fields whose names contain an entry of `COMMAND_LOG_REDACT_FIELDS`
+are replaced before storing, in requests and responses alike; a command
+whose request lost anything that way, or whose body was cut off or not
+JSON, is kept but marked not replayable.
+
+Holders of `COMMAND_LOG_ROLES` review the log under `/admin/commands`,
+filtered by session, actor, tenant, path and time, and export it with
+`GET /admin/commands/replay`: the successful commands in the order they
+were recorded, one JSON object per line, each with the method, path, query
+and body needed to send it again against another environment and whether
+it can be. Exports are audited as `commands.export`.
+
+Recording never fails a request: a command that cannot be stored is logged
+and counted in `cotai_command_log_failures_total`.
+*/
+
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderMap};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::web::{self, Bytes};
+use actix_web::{Error, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::time::Instant;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::audit::receipts;
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, client_ip};
+use crate::changes::Change;
+use crate::config::CommandLogConfig;
+use crate::errors::SecurityError;
+use crate::forensics::{self, redact_json, redact_pairs};
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::Storage;
+use crate::AppState;
+
+const ADMIN_PREFIX: &str = "/api/v1/admin";
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "started_at", column: "started_at", kind: KeyKind::Timestamp },
+];
+
+const COMMAND_COLUMNS: &str = "id, sequence, session, actor, tenant_id, actor_ip, method, path, query, route, \
+    body, body_state, status, result, started_at, duration_ms";
+
+tokio::task_local! {
+    static CURRENT: Uuid;
+}
+
+/// The command being recorded for the request being served, if any.
+pub fn current() -> Option<Uuid> {
+    CURRENT.try_with(|id| *id).ok()
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Command {
+    pub id: Uuid,
+    /// Order of recording, across replicas.
+    pub sequence: i64,
+    /// The login the command was sent under.
+    pub session: Option<String>,
+    pub actor: String,
+    pub tenant_id: Option<String>,
+    pub actor_ip: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    /// The route pattern the path matched, e.g. `/api/v1/admin/flags/{key}`.
+    pub route: Option<String>,
+    pub body: Option<Value>,
+    /// `none`, `json`, `redacted`, `truncated` or `omitted` (not JSON).
+    pub body_state: String,
+    pub status: i32,
+    /// The JSON response, redacted like the body.
+    pub result: Option<Value>,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: i64,
+}
+
+impl Command {
+    /// Why sending the command again would not do the same, if it would not.
+    pub fn unreplayable(&self) -> Option<&'static str> {
+        match self.body_state.as_str() {
+            "redacted" => Some("body had redacted values"),
+            "truncated" => Some("body was cut off"),
+            "omitted" => Some("body was not JSON"),
+            _ if !(200..300).contains(&self.status) => Some("command failed"),
+            _ => None,
+        }
+    }
+}
+
+/// A command as recorded, before storage numbers it.
+struct NewCommand {
+    id: Uuid,
+    session: Option<String>,
+    actor: String,
+    tenant_id: Option<String>,
+    actor_ip: Option<String>,
+    method: String,
+    path: String,
+    query: String,
+    route: Option<String>,
+    body: Option<Value>,
+    body_state: &'static str,
+    status: i32,
+    result: Option<Value>,
+    started_at: DateTime<Utc>,
+    duration_ms: i64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CommandFilter {
+    pub session: Option<String>,
+    pub actor: Option<String>,
+    pub tenant_id: Option<String>,
+    /// Path prefix.
+    pub path: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl CommandFilter {
+    fn push(&self, builder: &mut QueryBuilder<Postgres>) {
+        if let Some(session) = &self.session {
+            builder.push(" AND session = ").push_bind(session.clone());
+        }
+        if let Some(actor) = &self.actor {
+            builder.push(" AND actor = ").push_bind(actor.clone());
+        }
+        if let Some(tenant_id) = &self.tenant_id {
+            builder.push(" AND tenant_id = ").push_bind(tenant_id.clone());
+        }
+        if let Some(path) = &self.path {
+            builder.push(" AND starts_with(path, ").push_bind(path.clone()).push(")");
+        }
+        if let Some(since) = self.since {
+            builder.push(" AND started_at >= ").push_bind(since);
+        }
+        if let Some(until) = self.until {
+            builder.push(" AND started_at < ").push_bind(until);
+        }
+    }
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(';').next().is_some_and(|mime| {
+            let mime = mime.trim();
+            mime == "application/json" || mime.ends_with("+json")
+        }))
+}
+
+pub struct CommandLog {
+    storage: Storage,
+    config: CommandLogConfig,
+}
+
+impl CommandLog {
+    pub async fn new(config: &crate::config::Config, storage: Storage) -> Result<Self, SecurityError> {
+        info!(
+            "Command log initialized: {}",
+            if config.command_log.enabled { "recording" } else { "disabled" }
+        );
+        Ok(Self { storage, config: config.command_log.clone() })
+    }
+
+    /// The request body as stored, and its state.
+    fn body(&self, bytes: &[u8], truncated: bool, json: bool) -> (Option<Value>, &'static str) {
+        if bytes.is_empty() && !truncated {
+            return (None, "none");
+        }
+        if truncated {
+            return (None, "truncated");
+        }
+        let Some(value) = json.then(|| serde_json::from_slice::<Value>(bytes).ok()).flatten() else {
+            return (None, "omitted");
+        };
+        let redacted = redact_json(&self.config.redact_fields, value.clone());
+        let state = if redacted == value { "json" } else { "redacted" };
+        (Some(redacted), state)
+    }
+
+    async fn store(&self, command: &NewCommand) -> Result<(), SecurityError> {
+        sqlx::query(
+            "INSERT INTO admin_commands (id, session, actor, tenant_id, actor_ip, method, path, query, route, body, \
+             body_state, status, result, started_at, duration_ms) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)",
+        )
+        .bind(command.id)
+        .bind(&command.session)
+        .bind(&command.actor)
+        .bind(&command.tenant_id)
+        .bind(&command.actor_ip)
+        .bind(&command.method)
+        .bind(&command.path)
+        .bind(&command.query)
+        .bind(&command.route)
+        .bind(&command.body)
+        .bind(command.body_state)
+        .bind(command.status)
+        .bind(&command.result)
+        .bind(command.started_at)
+        .bind(command.duration_ms)
+        .execute(self.storage.pool())
+        .await?;
+        Ok(())
+    }
+
+    pub async fn list(&self, filter: &CommandFilter, page: &PageRequest) -> Result<Page<Command>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM admin_commands WHERE 1 = 1",
+            COMMAND_COLUMNS
+        ));
+        filter.push(&mut builder);
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let commands = builder
+            .build_query_as::<Command>()
+            .fetch_all(self.storage.pool())
+            .await?;
+        Ok(page.page(commands, |command, _| (SortKey::Timestamp(command.started_at), command.id)))
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<Command, SecurityError> {
+        sqlx::query_as::<_, Command>(&format!("SELECT {} FROM admin_commands WHERE id = $1", COMMAND_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound(format!("No command {}", id)))
+    }
+
+    /// Successful commands in recording order, for replay.
+    pub async fn replay(&self, filter: &CommandFilter) -> Result<Vec<Command>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM admin_commands WHERE status BETWEEN 200 AND 299",
+            COMMAND_COLUMNS
+        ));
+        filter.push(&mut builder);
+        builder.push(" ORDER BY sequence LIMIT ").push_bind(self.config.max_replay + 1);
+
+        let commands = builder
+            .build_query_as::<Command>()
+            .fetch_all(self.storage.pool())
+            .await?;
+        if commands.len() as i64 > self.config.max_replay {
+            return Err(SecurityError::ValidationError(format!(
+                "More than {} commands match; narrow the filter",
+                self.config.max_replay
+            )));
+        }
+        Ok(commands)
+    }
+}
+
+fn mutates(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Take the response body when it is JSON and small enough to keep, putting
+/// it back for the client.
+async fn response_body(res: ServiceResponse<BoxBody>, limit: usize) -> Result<(ServiceResponse<BoxBody>, Option<Bytes>), Error> {
+    let small = matches!(res.response().body().size(), BodySize::Sized(size) if size as usize <= limit);
+    if !small || !is_json(res.headers()) {
+        return Ok((res, None));
+    }
+    let (request, response) = res.into_parts();
+    let (response, body) = response.into_parts();
+    let bytes = actix_web::body::to_bytes(body)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
+    let response = response.set_body(BoxBody::new(bytes.clone()));
+    Ok((ServiceResponse::new(request, response), Some(bytes)))
+}
+
+/// Middleware recording the mutating admin requests it passes.
+pub async fn record(
+    state: web::Data<AppState>,
+    mut req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let log = &state.command_log;
+    if !log.config.enabled || !mutates(req.method()) {
+        return next.call(req).await;
+    }
+    let admin_path = req.path().starts_with(ADMIN_PREFIX);
+    let Some(principal) = state
+        .auth_service
+        .authenticate(req.request())
+        .ok()
+        .filter(|principal| admin_path || principal.has_any_role(&state.config.auth.admin_roles))
+    else {
+        return next.call(req).await;
+    };
+
+    let id = Uuid::new_v4();
+    let started_at = state.clock.now();
+    let started = Instant::now();
+    let (bytes, truncated) = forensics::peek_body(&mut req, log.config.max_body_bytes).await;
+    let (body, body_state) = log.body(&bytes, truncated, is_json(req.headers()));
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    let query = redact_pairs(&log.config.redact_fields, req.query_string());
+    let route = req.match_pattern();
+    let actor_ip = client_ip(req.request());
+
+    let res = CURRENT.scope(id, next.call(req)).await?;
+    let status = res.status().as_u16() as i32;
+    let (res, result) = response_body(res, log.config.max_body_bytes).await?;
+    let result = result
+        .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+        .map(|value| redact_json(&log.config.redact_fields, value));
+
+    let command = NewCommand {
+        id,
+        session: principal.token_id.clone(),
+        actor: principal.subject.clone(),
+        tenant_id: principal.tenant_id.clone(),
+        actor_ip,
+        method,
+        path,
+        query,
+        route,
+        body,
+        body_state,
+        status,
+        result,
+        started_at,
+        duration_ms: started.elapsed().as_millis() as i64,
+    };
+    if let Err(e) = log.store(&command).await {
+        error!("Admin command {} {} by {} not recorded: {:?}", command.method, command.path, command.actor, e);
+        state.metrics_service.increment("cotai_command_log_failures_total", &[]);
+    }
+    Ok(res)
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Command log operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Command log operation failed"
+            }))
+        }
+    }
+}
+
+pub async fn list_handler(
+    req: HttpRequest,
+    filter: web::Query<CommandFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_roles(&req, &state.config.command_log.roles) {
+        return Ok(auth_error_response(&e));
+    }
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.command_log.list(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "commands": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_roles(&req, &state.config.command_log.roles) {
+        return Ok(auth_error_response(&e));
+    }
+    let id = path.into_inner();
+
+    let command = match state.command_log.get(id).await {
+        Ok(command) => command,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let changes: Vec<Change> = match state.change_history.for_command(id).await {
+        Ok(changes) => changes,
+        Err(e) => return Ok(error_response(e)),
+    };
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "command": command,
+        "replayable": command.unreplayable().is_none(),
+        "changes": changes
+    })))
+}
+
+pub async fn replay_handler(
+    req: HttpRequest,
+    filter: web::Query<CommandFilter>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_roles(&req, &state.config.command_log.roles) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let commands = match state.command_log.replay(&filter).await {
+        Ok(commands) => commands,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let mut lines = String::new();
+    for command in &commands {
+        let line = serde_json::json!({
+            "id": command.id,
+            "sequence": command.sequence,
+            "actor": command.actor,
+            "started_at": command.started_at,
+            "method": command.method,
+            "path": command.path,
+            "query": command.query,
+            "body": command.body,
+            "replayable": command.unreplayable().is_none(),
+            "skip_reason": command.unreplayable()
+        });
+        lines.push_str(&line.to_string());
+        lines.push('\n');
+    }
+
+    receipts::record_or_warn(&state, NewAuditEvent {
+        tenant_id: principal.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(&req),
+        action: "commands.export".to_string(),
+        resource: "admin_commands".to_string(),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "session": filter.session,
+            "actor": filter.actor,
+            "tenant_id": filter.tenant_id,
+            "path": filter.path,
+            "since": filter.since,
+            "until": filter.until,
+            "commands": commands.len()
+        }),
+    }).await;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .insert_header(("Cache-Control", "no-store"))
+        .body(lines))
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/commands")
+            .route("", web::get().to(list_handler))
+            .route("/replay", web::get().to(replay_handler))
+            .route("/{id}", web::get().to(get_handler)),
+    );
+}
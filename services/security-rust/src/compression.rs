@@ -0,0 +1,121 @@
+/*!
+Compression Module
+Response compression policy that keeps secrets out of compressed bodies
+*/
+
+use actix_web::dev::ServiceResponse;
+use actix_web::http::header::{self, ContentEncoding};
+
+/// Path prefixes of responses that carry plaintext or secrets next to
+/// caller-controlled input. Compressing them would let an attacker recover
+/// the secret from response sizes (BREACH). Matching is by prefix, so
+/// `/api/v1/crypto/decrypt` covers every `decrypt-*` variant too.
+const SECRET_BEARING: &[&str] = &[
+    "/api/v1/crypto/decrypt",
+    "/api/v1/crypto/detokenize",
+    "/api/v1/crypto/hash",
+    "/api/v1/crypto/sign",
+    "/api/v1/crypto/webhook/sign",
+    "/api/v1/auth/",
+    "/api/v1/admin/api-keys",
+    "/api/v1/admin/outbound-credentials",
+    "/api/v1/admin/service-accounts",
+    "/api/v1/admin/break-glass",
+    "/api/v1/admin/s3-gateway/credentials",
+    "/api/v1/mailbox/",
+    "/api/v1/whistleblower/",
+];
+
+/// Besides the paths above, any response that sets a cookie or is marked
+/// `no-store` (how handlers flag one-time secrets) is left uncompressed.
+fn is_secret_bearing<B>(path: &str, res: &ServiceResponse<B>) -> bool {
+    let no_store = res
+        .headers()
+        .get(header::CACHE_CONTROL)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|directive| directive.trim().eq_ignore_ascii_case("no-store")));
+    SECRET_BEARING.iter().any(|prefix| path.starts_with(prefix))
+        || no_store
+        || res.headers().contains_key(header::SET_COOKIE)
+}
+
+/// Mark secret-bearing responses as `identity` so the outer `Compress`
+/// middleware leaves them alone. Everything else, notably audit exports
+/// and report listings, is compressed with whatever the client accepts.
+pub fn apply_policy<B>(mut res: ServiceResponse<B>) -> ServiceResponse<B> {
+    let path = res.request().path().to_string();
+    if is_secret_bearing(&path, &res) {
+        res.headers_mut().insert(header::CONTENT_ENCODING, ContentEncoding::Identity.to_header_value());
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::MessageBody;
+    use actix_web::dev::ServiceRequest;
+    use actix_web::middleware::{from_fn, Compress, Next};
+    use actix_web::{test, web, App, Error, HttpRequest, HttpResponse};
+
+    async fn policy(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+        Ok(apply_policy(next.call(req).await?))
+    }
+
+    /// The `Content-Encoding` a gzip-accepting client gets for `path`, with
+    /// the policy inside `Compress` as in the pipeline.
+    async fn encoding(path: &str) -> Option<String> {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(policy))
+                .wrap(Compress::default())
+                .default_service(web::to(|req: HttpRequest| async move {
+                    let mut res = HttpResponse::Ok();
+                    if req.path().ends_with("/minted") {
+                        res.insert_header((header::CACHE_CONTROL, "private, no-store"));
+                    }
+                    res.body("plaintext ".repeat(200))
+                })),
+        )
+        .await;
+        let req = test::TestRequest::post()
+            .uri(path)
+            .insert_header((header::ACCEPT_ENCODING, "gzip"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        res.headers()
+            .get(header::CONTENT_ENCODING)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[actix_web::test]
+    async fn plaintext_and_secrets_are_sent_uncompressed() {
+        for path in [
+            "/api/v1/crypto/decrypt",
+            "/api/v1/crypto/decrypt-document",
+            "/api/v1/crypto/decrypt-labeled",
+            "/api/v1/crypto/decrypt-batch",
+            "/api/v1/crypto/decrypt-stream",
+            "/api/v1/crypto/detokenize",
+            "/api/v1/auth/token",
+            "/api/v1/admin/api-keys",
+            "/api/v1/admin/api-keys/key-1/rotate",
+            "/api/v1/admin/outbound-credentials",
+            "/api/v1/admin/service-accounts/sa-1/keys",
+            "/api/v1/admin/break-glass/accounts",
+            "/api/v1/admin/s3-gateway/credentials",
+            "/api/v1/mailbox/messages/1",
+            "/api/v1/whistleblower/reports/1",
+            "/api/v1/admin/ssh-ca/minted",
+        ] {
+            assert_ne!(encoding(path).await.as_deref(), Some("gzip"), "{} was compressed", path);
+        }
+    }
+
+    #[actix_web::test]
+    async fn other_responses_are_compressed() {
+        for path in ["/api/v1/audit/events", "/api/v1/crypto/encrypt", "/api/v1/admin/policies"] {
+            assert_eq!(encoding(path).await.as_deref(), Some("gzip"), "{} was not compressed", path);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+/*!
+Optimistic Concurrency Module
+ETag/If-Match handling and JSON diffs for versioned resources
+*/
+
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+use serde::Serialize;
+use serde_json::Value;
+
+pub fn etag(version: i64) -> String {
+    format!("\"v{}\"", version)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IfMatch {
+    Missing,
+    /// `If-Match: *` — any current version.
+    Any,
+    Version(i64),
+    /// Present but not an ETag this service issued; it can never match.
+    Unknown,
+}
+
+impl IfMatch {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let Some(value) = req.headers().get(header::IF_MATCH) else {
+            return IfMatch::Missing;
+        };
+        let Ok(value) = value.to_str() else {
+            return IfMatch::Unknown;
+        };
+
+        let value = value.trim();
+        if value == "*" {
+            return IfMatch::Any;
+        }
+        value
+            .strip_prefix("\"v")
+            .and_then(|v| v.strip_suffix('"'))
+            .and_then(|v| v.parse().ok())
+            .map(IfMatch::Version)
+            .unwrap_or(IfMatch::Unknown)
+    }
+
+    /// The version an update must be applied against, or the response to send instead.
+    #[allow(clippy::result_large_err)]
+    pub fn expected_version(self) -> Result<Option<i64>, HttpResponse> {
+        match self {
+            IfMatch::Missing => Err(HttpResponse::build(actix_web::http::StatusCode::PRECONDITION_REQUIRED)
+                .json(serde_json::json!({
+                    "error": "Updates require an If-Match header"
+                }))),
+            IfMatch::Unknown => Err(HttpResponse::PreconditionFailed().json(serde_json::json!({
+                "error": "If-Match does not match the current version"
+            }))),
+            IfMatch::Any => Ok(None),
+            IfMatch::Version(version) => Ok(Some(version)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldChange {
+    /// JSON Pointer (RFC 6901) of the changed value.
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+}
+
+/// Leaf-level differences between two JSON values. Objects are compared
+/// key by key; arrays and scalars are compared as a whole.
+pub fn json_diff(before: &Value, after: &Value) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    diff_into(String::new(), Some(before), Some(after), &mut changes);
+    changes
+}
+
+fn diff_into(path: String, before: Option<&Value>, after: Option<&Value>, changes: &mut Vec<FieldChange>) {
+    match (before, after) {
+        (Some(Value::Object(a)), Some(Value::Object(b))) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let escaped = key.replace('~', "~0").replace('/', "~1");
+                diff_into(format!("{}/{}", path, escaped), a.get(key), b.get(key), changes);
+            }
+        }
+        (a, b) if a == b => {}
+        (a, b) => changes.push(FieldChange {
+            path,
+            before: a.cloned(),
+            after: b.cloned(),
+        }),
+    }
+}
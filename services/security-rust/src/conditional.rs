@@ -0,0 +1,101 @@
+/*!
+Conditional Requests Module
+ETag/Last-Modified validation and Cache-Control for frequently polled reads
+*/
+
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse, HttpResponseBuilder};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::config::HttpCacheConfig;
+
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+pub enum CacheControl<'a> {
+    /// Admin reads: never served stale, but cheap to revalidate.
+    Revalidate,
+    /// Material polled by verifiers: cacheable per caller, with stale hints
+    /// so an edge keeps answering while it refreshes or while we are down.
+    Private(&'a HttpCacheConfig),
+    /// Material anyone may fetch, like the JWKS: shared caches may keep it.
+    Public(&'a HttpCacheConfig),
+}
+
+impl CacheControl<'_> {
+    fn header_value(&self) -> String {
+        match self {
+            CacheControl::Revalidate => "private, no-cache".to_string(),
+            CacheControl::Private(config) => format!(
+                "private, max-age={}, stale-while-revalidate={}, stale-if-error={}",
+                config.max_age_secs, config.stale_while_revalidate_secs, config.stale_if_error_secs
+            ),
+            CacheControl::Public(config) => format!(
+                "public, max-age={}, stale-while-revalidate={}, stale-if-error={}",
+                config.max_age_secs, config.stale_while_revalidate_secs, config.stale_if_error_secs
+            ),
+        }
+    }
+}
+
+pub struct Validators {
+    etag: String,
+    last_modified: Option<DateTime<Utc>>,
+}
+
+impl Validators {
+    pub fn new(etag: String) -> Self {
+        Self { etag, last_modified: None }
+    }
+
+    pub fn last_modified(mut self, at: DateTime<Utc>) -> Self {
+        self.last_modified = Some(at);
+        self
+    }
+
+    /// Whether the client's copy is current. If-None-Match takes precedence
+    /// over If-Modified-Since (RFC 9110 §13.2.2); ETags compare weakly.
+    pub fn is_fresh(&self, req: &HttpRequest) -> bool {
+        if let Some(value) = req.headers().get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+            let ours = self.etag.trim_start_matches("W/");
+            return value
+                .split(',')
+                .map(|tag| tag.trim())
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == ours);
+        }
+
+        let since = req.headers()
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| DateTime::parse_from_rfc2822(v).ok());
+        match (self.last_modified, since) {
+            // HTTP dates have second precision
+            (Some(modified), Some(since)) => modified.timestamp() <= since.timestamp(),
+            _ => false,
+        }
+    }
+
+    fn apply(&self, builder: &mut HttpResponseBuilder, cache: &CacheControl) {
+        builder
+            .insert_header((header::ETAG, self.etag.clone()))
+            .insert_header((header::CACHE_CONTROL, cache.header_value()));
+        if !matches!(cache, CacheControl::Public(_)) {
+            builder.insert_header((header::VARY, "Authorization"));
+        }
+        if let Some(modified) = self.last_modified {
+            builder.insert_header((header::LAST_MODIFIED, modified.format(HTTP_DATE).to_string()));
+        }
+    }
+
+    /// 304 when the client's copy is current, otherwise 200 with the body.
+    pub fn respond<T: Serialize>(&self, req: &HttpRequest, cache: CacheControl, body: &T) -> HttpResponse {
+        let fresh = self.is_fresh(req);
+        let mut builder = if fresh { HttpResponse::NotModified() } else { HttpResponse::Ok() };
+        self.apply(&mut builder, &cache);
+        if fresh {
+            builder.finish()
+        } else {
+            builder.json(body)
+        }
+    }
+}
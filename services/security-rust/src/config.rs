@@ -0,0 +1,3473 @@
+/*!
+Configuration Module
+Service configuration loaded from environment variables
+
+Secret fields also accept `NAME_FILE` pointing at a file holding the value
+(Kubernetes secret mounts, Vault agent templates), and `kms://` or
+`vault://` references that `secrets` resolves at startup.
+
+`CONFIG_FILE` names a file of `NAME=value` lines that take precedence over
+the environment. Unlike the environment it can change under a running
+service: on `SIGHUP`, or when the file changes (checked every
+`CONFIG_RELOAD_INTERVAL_SECS`), the configuration is loaded and validated
+again, and if valid these settings take effect without a restart:
+
+- `RATE_LIMIT_ROUTES` and `RATE_LIMIT_DEFAULT_ALGORITHM`
+- `CORS_ALLOWED_ORIGINS`
+- `CRYPTO_KEY_ROTATION_INTERVAL_SECS`
+
+Services read them from `LiveConfig`; everything else keeps its startup
+value until the next restart. An invalid configuration is logged and the
+running one kept. Reloads that change a setting are audited as
+`config.reload`.
+*/
+
+use actix_web::web;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info, warn};
+
+use crate::crypto::algorithms::{self, AlgorithmRegistry, AlgorithmStatus};
+use crate::errors::SecurityError;
+use crate::network::{Cidr, CLIENT_IP_HEADERS};
+use crate::AppState;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    pub database_url: String,
+    /// Overrides any password in `database_url`, so the URL can stay non-secret.
+    pub database_password: Option<String>,
+    pub redis_url: String,
+    /// Optional internal-only listener for admin APIs such as GraphQL.
+    pub admin_bind: Option<String>,
+    pub server: ServerConfig,
+    pub tls: TlsConfig,
+    pub secrets: SecretsConfig,
+    pub middleware: MiddlewareConfig,
+    pub deadline: DeadlineConfig,
+    pub rate_limit: RateLimitConfig,
+    pub http_cache: HttpCacheConfig,
+    pub metrics: MetricsConfig,
+    pub threats: ThreatsConfig,
+    pub startup: StartupConfig,
+    pub crypto: CryptoConfig,
+    pub auth: AuthConfig,
+    pub audit: AuditConfig,
+    pub privacy: PrivacyConfig,
+    pub alerting: AlertingConfig,
+    pub dlq: DlqConfig,
+    pub bulk: BulkConfig,
+    pub soft_delete: SoftDeleteConfig,
+    pub flags: FlagsConfig,
+    pub experiments: ExperimentsConfig,
+    pub maintenance: MaintenanceConfig,
+    pub tenant_settings: TenantSettingsConfig,
+    pub correlation: CorrelationConfig,
+    pub ueba: UebaConfig,
+    pub usage: UsageConfig,
+    pub containment: ContainmentConfig,
+    pub soar: SoarConfig,
+    pub degraded: DegradedConfig,
+    pub health: HealthConfig,
+    pub events: EventsConfig,
+    pub expiry: ExpiryConfig,
+    pub service_accounts: ServiceAccountConfig,
+    pub api_keys: ApiKeyConfig,
+    pub credentials: CredentialsConfig,
+    pub delivery: DeliveryConfig,
+    pub captcha: CaptchaConfig,
+    pub password: PasswordConfig,
+    pub notary: NotaryConfig,
+    pub seal: SealConfig,
+    pub manifests: ManifestConfig,
+    pub custody: CustodyConfig,
+    pub authz: AuthzConfig,
+    pub tokenization: TokenizationConfig,
+    pub crypto_guard: CryptoGuardConfig,
+    pub bulk_decrypt: BulkDecryptConfig,
+    pub webhooks: WebhookConfig,
+    pub plugins: PluginsConfig,
+    pub hooks: HooksConfig,
+    pub network: NetworkConfig,
+    pub forensics: ForensicsConfig,
+    pub command_log: CommandLogConfig,
+    pub break_glass: BreakGlassConfig,
+    pub elevation: ElevationConfig,
+    pub access_review: AccessReviewConfig,
+    pub dormancy: DormancyConfig,
+    pub org: OrgConfig,
+    pub resource_registry: ResourceRegistryConfig,
+    pub search: SearchConfig,
+    pub i18n: I18nConfig,
+    pub problem: ProblemConfig,
+    pub echo: EchoConfig,
+    pub status_page: StatusPageConfig,
+    pub bundles: BundleConfig,
+    pub retention: RetentionConfig,
+    pub whistleblower: WhistleblowerConfig,
+    pub mailbox: MailboxConfig,
+    pub grpc: GrpcConfig,
+    pub pgproxy: PgProxyConfig,
+    pub s3_gateway: S3GatewayConfig,
+    pub email: EmailConfig,
+    pub domain_verification: DomainVerificationConfig,
+    pub ssh_ca: SshCaConfig,
+    pub backups: BackupConfig,
+    pub quorum: QuorumConfig,
+    pub submissions: SubmissionConfig,
+    pub collusion: CollusionConfig,
+    pub dossiers: DossierConfig,
+    pub residency: ResidencyConfig,
+    pub key_archive: KeyArchiveConfig,
+    pub rng: RngConfig,
+    pub billing: BillingConfig,
+    pub jobs: JobsConfig,
+    pub reload: ReloadConfig,
+    pub sources: ConfigSources,
+}
+
+/// Where secret values came from when not a plain variable, so problems
+/// point at the file or reference to fix.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSources {
+    by_var: HashMap<&'static str, String>,
+}
+
+impl ConfigSources {
+    pub fn record(&mut self, var: &'static str, source: String) {
+        self.by_var.insert(var, source);
+    }
+
+    pub fn describe(&self, var: &str) -> String {
+        match self.by_var.get(var) {
+            Some(source) => format!("{} from {}", var, source),
+            None => var.to_string(),
+        }
+    }
+}
+
+/// Where reloads come from; see the module docs.
+#[derive(Debug, Clone)]
+pub struct ReloadConfig {
+    pub file: Option<String>,
+    /// How often `file` is checked for changes; 0 reloads on `SIGHUP` only.
+    pub interval_secs: u64,
+}
+
+/// Actix server tuning. Defaults match actix's own except where noted.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Worker threads; 0 means one per physical core.
+    pub workers: usize,
+    /// Idle keep-alive; 0 disables keep-alive.
+    pub keep_alive_secs: u64,
+    pub client_request_timeout_ms: u64,
+    pub client_disconnect_timeout_ms: u64,
+    /// Per-worker limit on concurrent connections.
+    pub max_connections: usize,
+    /// Per-worker limit on concurrent TLS handshakes.
+    pub max_connection_rate: usize,
+    /// Listen queue; raised from actix's 1024 for verification bursts.
+    pub backlog: u32,
+    /// How long in-flight requests, then queued audit work, get at shutdown.
+    pub shutdown_timeout_secs: u64,
+    /// Accept prior-knowledge HTTP/2 (h2c) next to HTTP/1.1 on the plain listener.
+    pub http2_cleartext: bool,
+    /// gzip/brotli/zstd per Accept-Encoding, minus the exemptions in `compression`.
+    pub compression: bool,
+}
+
+/// Client addresses behind proxies; see `network` and `proxy_protocol`.
+#[derive(Debug, Clone)]
+pub struct NetworkConfig {
+    /// Addresses and CIDR ranges of the proxies whose forwarding headers,
+    /// or PROXY headers, are believed.
+    pub trusted_proxies: Vec<String>,
+    /// `x-forwarded-for`, `forwarded`, `x-real-ip` or `none`.
+    pub client_ip_header: String,
+    /// Require a PROXY protocol header on every public connection.
+    pub proxy_protocol: bool,
+    pub proxy_protocol_timeout_ms: u64,
+}
+
+/// Encrypted copies of suspicious requests; see `forensics`.
+#[derive(Debug, Clone)]
+pub struct ForensicsConfig {
+    pub enabled: bool,
+    /// Pipeline stages whose rejections and flags are captured.
+    pub stages: Vec<String>,
+    /// Request body bytes kept; the rest is cut off.
+    pub max_body_bytes: usize,
+    /// Captures per client address, or account, per minute.
+    pub max_per_minute: u32,
+    /// Headers, and JSON, form or query fields containing one of these
+    /// names, whose values are never stored.
+    pub redact_headers: Vec<String>,
+    pub redact_fields: Vec<String>,
+    /// Retention of captures unless `RETENTION_POLICIES` sets `forensics`.
+    pub retention_days: i64,
+    /// Who may review captures; admin roles do not imply it.
+    pub roles: Vec<String>,
+    /// Captures waiting to be written before new ones are dropped.
+    pub queue_size: usize,
+}
+
+/// Record of mutating admin requests; see `commands`.
+#[derive(Debug, Clone)]
+pub struct CommandLogConfig {
+    pub enabled: bool,
+    /// Request and response body bytes kept per command.
+    pub max_body_bytes: usize,
+    /// JSON and query fields containing one of these names, whose values
+    /// are never stored.
+    pub redact_fields: Vec<String>,
+    /// Who may review and export the log; admin roles do not imply it.
+    pub roles: Vec<String>,
+    /// Most commands one replay export holds.
+    pub max_replay: i64,
+}
+
+/// Sealed emergency credentials; see `auth::break_glass`.
+#[derive(Debug, Clone)]
+pub struct BreakGlassConfig {
+    /// Roles an activation's tokens carry.
+    pub roles: Vec<String>,
+    /// How long an activation lasts once its code is checked.
+    pub ttl_secs: i64,
+    /// Who provisions, reseals and disables accounts.
+    pub admin_roles: Vec<String>,
+    /// Who reviews activations once they end.
+    pub review_roles: Vec<String>,
+    /// Alerting sinks paged on every use; all of them when empty.
+    pub alert_sinks: Vec<String>,
+}
+
+/// Short-lived SSH certificates; see `auth::ssh_ca`.
+#[derive(Debug, Clone)]
+pub struct SshCaConfig {
+    /// Roles and the login names, `:`-separated, their holders' user
+    /// certificates carry.
+    pub role_principals: Vec<(String, String)>,
+    /// Longest a user certificate may last.
+    pub user_cert_ttl_secs: i64,
+    /// Longest a host certificate may last.
+    pub host_cert_ttl_secs: i64,
+    /// Extensions user certificates carry, e.g. `permit-pty`.
+    pub user_extensions: Vec<String>,
+    /// Who registers operators and rotates the CA keys.
+    pub admin_roles: Vec<String>,
+    /// Scope host provisioning needs for host certificates.
+    pub host_scope: String,
+}
+
+/// Encrypted backups and restore drills; see `backups`.
+#[derive(Debug, Clone)]
+pub struct BackupConfig {
+    /// Bucket backups are written to and drilled from; unset turns both
+    /// off.
+    pub bucket: Option<String>,
+    pub prefix: String,
+    /// How often a backup is taken.
+    pub interval_secs: u64,
+    /// How often the newest backup is restored and checked.
+    pub drill_interval_secs: u64,
+    /// Oldest the newest backup may be before a drill fails.
+    pub max_age_secs: i64,
+}
+
+/// k-of-n signing of published results; see `quorum`.
+#[derive(Debug, Clone)]
+pub struct QuorumConfig {
+    /// Scope needed to open publications.
+    pub publish_scope: String,
+    /// Scope officers need to sign them.
+    pub sign_scope: String,
+    /// Officer signatures a publication needs.
+    pub threshold: usize,
+    /// How long a publication collects signatures before it lapses.
+    pub window_secs: i64,
+    /// `EdDSA` or `ES256`, as for the notary.
+    pub algorithm: String,
+}
+
+/// Deadline-enforced bid submissions; see `submissions`.
+#[derive(Debug, Clone)]
+pub struct SubmissionConfig {
+    /// Scope needed to open calls for bids and read their receipts.
+    pub manage_scope: String,
+    /// Scope bidders need to submit.
+    pub submit_scope: String,
+    /// `EdDSA` or `ES256`, as for the notary.
+    pub algorithm: String,
+    /// Furthest this replica's clock may be off the database's before it
+    /// stops issuing receipts; 0 turns the check off.
+    pub max_clock_skew_ms: u64,
+}
+
+/// Duplicate-bidder screening; see `collusion`.
+#[derive(Debug, Clone)]
+pub struct CollusionConfig {
+    pub analyze_scope: String,
+    /// Score at which a pair of bids is flagged.
+    pub threshold: f64,
+    /// Weights replacing the defaults for some identifier kinds.
+    pub weights: Vec<(String, f64)>,
+    /// Most bids in one analysis.
+    pub max_bids: usize,
+}
+
+/// Signed, encrypted tender dossiers; see `dossiers`.
+#[derive(Debug, Clone)]
+pub struct DossierConfig {
+    pub export_scope: String,
+    /// Where sealed archives are written; exports are refused without it.
+    pub bucket: Option<String>,
+    pub prefix: String,
+    pub algorithm: String,
+    /// Most audit events in one dossier.
+    pub max_events: i64,
+}
+
+/// Regions tenants' stored data may be pinned to; see `residency`.
+#[derive(Debug, Clone)]
+pub struct ResidencyConfig {
+    /// Region of the service's own buckets.
+    pub home_region: String,
+    /// Other regions and their buckets.
+    pub regions: Vec<(String, String)>,
+}
+
+/// Cold tier for retired data keys; see `key_archive`.
+#[derive(Debug, Clone)]
+pub struct KeyArchiveConfig {
+    /// Where archived keys are written; archival is off without it. Meant
+    /// for a bucket whose lifecycle rules move objects to a cold class.
+    pub bucket: Option<String>,
+    pub prefix: String,
+    /// How long a key stays retired in the hot store before it is archived.
+    pub after_days: i64,
+    /// Wait between a restore's approval and the key coming back.
+    pub restore_delay_secs: i64,
+    /// How long a restored key decrypts before it is retired and archived
+    /// again.
+    pub restore_window_secs: i64,
+    pub interval_secs: u64,
+}
+
+/// Health tests on random output and the jitter fallback; see `random`.
+#[derive(Debug, Clone)]
+pub struct RngConfig {
+    pub health_checks: bool,
+    /// Min-entropy per output byte the health tests assume, in bits; lower
+    /// is more lenient.
+    pub min_entropy_bits: f64,
+    /// Mix CPU jitter entropy into all output, and serve from it alone
+    /// while the source fails its tests.
+    pub jitter: bool,
+    pub jitter_reseed_secs: u64,
+    pub interval_secs: u64,
+}
+
+/// Cost accounting and billing exports; see `billing`.
+#[derive(Debug, Clone)]
+pub struct BillingConfig {
+    pub enabled: bool,
+    /// Cost units per unit of a meter, replacing its default.
+    pub unit_weights: Vec<(String, i64)>,
+    pub flush_interval_secs: u64,
+    /// How long after midnight UTC a day is exported, so every replica has
+    /// flushed it.
+    pub export_delay_secs: i64,
+    /// Where CSV and Parquet exports are written; none without it.
+    pub export_bucket: Option<String>,
+    pub export_prefix: String,
+    pub webhook_url: Option<String>,
+    /// Name of the webhook secret signing webhook deliveries.
+    pub webhook_secret: String,
+    pub timeout_secs: u64,
+}
+
+/// Slow operations answered with 202 and run on; see `jobs`.
+#[derive(Debug, Clone)]
+pub struct JobsConfig {
+    /// How long a request waits for the operation before answering 202.
+    pub inline_wait_ms: u64,
+    /// How long a finished job's result is kept.
+    pub result_ttl_secs: i64,
+    pub max_runtime_secs: u64,
+    /// Longest `?wait=` a status request may hold.
+    pub max_wait_secs: u64,
+    /// `Retry-After` on 202 answers.
+    pub poll_after_secs: u64,
+    /// Hosts `X-Callback-Url` may point at; callbacks are refused without any.
+    pub callback_hosts: Vec<String>,
+    /// Name of the webhook secret signing callbacks.
+    pub callback_secret: String,
+    pub callback_max_attempts: i32,
+    pub interval_secs: u64,
+    pub timeout_secs: u64,
+}
+
+/// Roles held just in time; see `auth::elevation`.
+#[derive(Debug, Clone)]
+pub struct ElevationConfig {
+    /// Elevatable roles and how requests for each are decided, `auto` or
+    /// `manual`.
+    pub policies: Vec<(String, String)>,
+    pub default_duration_secs: i64,
+    pub max_duration_secs: i64,
+    /// Take elevatable roles out of tokens, so they are held only by grant.
+    pub strip_standing: bool,
+    /// Subjects keeping standing elevatable roles, e.g. service accounts.
+    pub exempt_subjects: Vec<String>,
+    /// Who decides `manual` requests.
+    pub approver_roles: Vec<String>,
+    /// How long a request may wait for a decision.
+    pub pending_ttl_secs: i64,
+}
+
+/// Periodic recertification of access; see `auth::access_reviews`.
+#[derive(Debug, Clone)]
+pub struct AccessReviewConfig {
+    /// Days between scheduled campaigns; 0 leaves them to admins.
+    pub interval_days: i64,
+    /// Days reviewers have to decide before non-responses are handled.
+    pub deadline_days: i64,
+    /// Revoke access nobody decided on by the deadline, rather than keep it.
+    pub revoke_unanswered: bool,
+    /// Who runs campaigns and assigns managers.
+    pub admin_roles: Vec<String>,
+    /// Who decides items of subjects without a manager.
+    pub fallback_roles: Vec<String>,
+    /// Who reads campaigns and their archives.
+    pub auditor_roles: Vec<String>,
+}
+
+/// Stale credentials and dormant accounts; see `auth::dormancy`.
+#[derive(Debug, Clone)]
+pub struct DormancyConfig {
+    pub enabled: bool,
+    pub interval_secs: u64,
+    /// Days unused before an API key is dormant; 0 never.
+    pub api_key_days: i64,
+    /// `notify`, `disable` or `delete`, as for the other kinds.
+    pub api_key_action: String,
+    /// Days since the last login before a user is dormant; 0 never.
+    pub user_days: i64,
+    pub user_action: String,
+    /// Days without a refresh before an open session is dormant; 0 never.
+    pub session_idle_days: i64,
+    pub session_action: String,
+    pub exempt_subjects: Vec<String>,
+    pub exempt_api_keys: Vec<String>,
+}
+
+/// Organization chart; see `org`.
+#[derive(Debug, Clone)]
+pub struct OrgConfig {
+    /// Scope needed to read and change the chart, including over SCIM.
+    pub sync_scope: String,
+    /// How long each replica keeps a tenant's chart before reloading it.
+    pub cache_ttl_secs: u64,
+    /// Put before SCIM user names to give subjects.
+    pub scim_subject_prefix: String,
+}
+
+/// Server-side resource attributes; see `registry`.
+#[derive(Debug, Clone)]
+pub struct ResourceRegistryConfig {
+    /// Scope needed to push, sync and read resources.
+    pub scope: String,
+    /// Resource types whose attributes come only from the registry;
+    /// unregistered resources of these types are denied.
+    pub required_types: Vec<String>,
+    /// How long each replica keeps a looked-up resource.
+    pub cache_ttl_secs: u64,
+    /// Most resources each replica keeps cached.
+    pub max_cached: usize,
+    /// Most resources in one sync.
+    pub max_batch: usize,
+}
+
+/// Admin search; see `search`.
+#[derive(Debug, Clone)]
+pub struct SearchConfig {
+    /// Shortest query searched for.
+    pub min_query_len: usize,
+    /// Most hits returned, and the default.
+    pub max_results: usize,
+    /// Least trigram similarity of a fuzzy match, from 0 to 1.
+    pub fuzzy_threshold: f64,
+}
+
+/// Message catalogs; see `i18n`. Locales are under `TenantSettingsConfig`.
+#[derive(Debug, Clone)]
+pub struct I18nConfig {
+    /// JSON file of texts per locale, over the built-in ones.
+    pub catalog_file: Option<String>,
+}
+
+/// Error bodies; see `problem`.
+#[derive(Debug, Clone)]
+pub struct ProblemConfig {
+    /// `negotiate`: problem documents for clients asking for them;
+    /// `always`: for every client.
+    pub mode: String,
+    /// Put before error codes to give problem types.
+    pub type_base: String,
+}
+
+/// The request echo; see `echo`.
+#[derive(Debug, Clone)]
+pub struct EchoConfig {
+    pub enabled: bool,
+}
+
+/// The public status page; see `status_page`.
+#[derive(Debug, Clone)]
+pub struct StatusPageConfig {
+    pub title: String,
+    /// Where the status page is published, linked from the feed.
+    pub public_url: Option<String>,
+    /// Least severity of the security incidents shown, or `none`.
+    pub incident_severity: String,
+    /// How long a snapshot is served before checks run again.
+    pub cache_secs: u64,
+    /// Requests per minute per client address.
+    pub rate_limit_rpm: u32,
+}
+
+/// Signed, encrypted configuration bundles; see `bundles`.
+#[derive(Debug, Clone)]
+pub struct BundleConfig {
+    /// `name=<base64 Ed25519 public key>` per platform signing key.
+    pub signers: Vec<(String, String)>,
+    /// A bundle applied on startup and again whenever it is replaced.
+    pub file: Option<String>,
+    pub poll_interval_secs: u64,
+    pub max_bytes: usize,
+}
+
+/// TLS on the public listener; see `tls`. Without a certificate the
+/// listener serves plain HTTP.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first, and its private key; reloaded
+    /// when the files change.
+    pub cert_path: Option<String>,
+    pub key_path: Option<String>,
+    /// The same given inline instead of as files; never reloaded.
+    pub cert_pem: Option<String>,
+    pub key_pem: Option<String>,
+    /// PEM bundle of the CAs client certificates must chain to; set turns
+    /// on mutual TLS for the scope of the `client_cert` stage.
+    pub client_ca_path: Option<String>,
+    /// How often the certificate files are checked; 0 turns reloading off.
+    pub reload_interval_secs: u64,
+}
+
+impl TlsConfig {
+    pub fn enabled(&self) -> bool {
+        self.cert_path.is_some() || self.cert_pem.is_some()
+    }
+}
+
+/// Backends for `vault://` and `kms://` secret references.
+#[derive(Debug, Clone)]
+pub struct SecretsConfig {
+    pub vault_addr: Option<String>,
+    pub vault_token: Option<String>,
+    pub vault_namespace: Option<String>,
+    /// KV version 2 mount that `vault://` paths are relative to.
+    pub vault_kv_mount: String,
+    /// AWS region of the KMS keys; credentials come from the usual `AWS_*` variables.
+    pub kms_region: Option<String>,
+    /// KMS-compatible endpoint used instead of AWS's, e.g. a local KMS.
+    pub kms_endpoint: Option<String>,
+    pub timeout_secs: u64,
+}
+
+/// Request pipeline. Parsed and validated by `pipeline::Pipeline` at startup.
+#[derive(Debug, Clone)]
+pub struct MiddlewareConfig {
+    /// In-house stages, outermost first. `stage@/a|/b` limits a stage to
+    /// those path prefixes.
+    pub pipeline: Vec<String>,
+    /// Scope the `scopes` stage requires, by path prefix; the longest
+    /// matching prefix wins.
+    pub route_scopes: Vec<(String, String)>,
+    pub cors: bool,
+    /// Origins allowed cross-origin requests; empty allows any `https://` origin.
+    pub cors_origins: Vec<String>,
+    pub request_log: bool,
+}
+
+/// Request rate limits; see `rate_limiting`.
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    /// `redis`, shared by every replica, or `memory`, per replica.
+    pub backend: String,
+    /// `/prefix=algorithm:limit/seconds[@ip|subnet|asn]` per route, longest
+    /// prefix winning.
+    pub routes: Vec<(String, String)>,
+    /// Algorithm of the tenant limit (`TENANT_RATE_LIMIT_RPM`) applied to
+    /// routes without their own.
+    pub default_algorithm: String,
+    /// How long to wait for Redis before limiting from memory instead.
+    pub timeout_ms: u64,
+    pub key_prefix: String,
+    /// Leading bits of an IPv4 and an IPv6 address that make its subnet.
+    pub subnet_v4_prefix: u8,
+    pub subnet_v6_prefix: u8,
+    /// ip2asn table for counting per autonomous system.
+    pub asn_file: Option<String>,
+    /// Distinct addresses of one subnet, or subnets of one AS, seen on a
+    /// rule within `escalate_window_secs` that make it count the whole
+    /// subnet or AS for `escalate_secs`; 0 never escalates.
+    pub escalate_threshold: usize,
+    pub escalate_window_secs: u64,
+    pub escalate_secs: u64,
+}
+
+/// Request budgets; see `deadline`.
+#[derive(Debug, Clone)]
+pub struct DeadlineConfig {
+    pub enabled: bool,
+    /// Budget for requests that do not set one; 0 means none.
+    pub default_ms: u64,
+    /// Cap on any requested budget.
+    pub max_ms: u64,
+}
+
+/// Cache-Control hints for material verifiers poll, such as the policy manifest.
+#[derive(Debug, Clone)]
+pub struct HttpCacheConfig {
+    pub max_age_secs: u64,
+    pub stale_while_revalidate_secs: u64,
+    pub stale_if_error_secs: u64,
+}
+
+/// Request metrics; see `monitoring`.
+#[derive(Debug, Clone)]
+pub struct MetricsConfig {
+    /// Upper bounds, in seconds, of the request latency histogram buckets.
+    pub latency_buckets: Vec<f64>,
+}
+
+/// Brute-force detection; see `monitoring::threats`.
+#[derive(Debug, Clone)]
+pub struct ThreatsConfig {
+    /// Audit action prefixes whose failures count as authentication failures.
+    pub auth_actions: Vec<String>,
+    /// Failures one account may have within the window.
+    pub account_max_failures: u32,
+    /// Failures and `401` responses one address may have within the window.
+    pub ip_max_failures: u32,
+    /// Rate limit rejections one caller may have within the window.
+    pub rate_limit_max_rejections: u32,
+    pub window_secs: u64,
+    /// How long a detection locks its account or address out; 0 only
+    /// detects.
+    pub lockout_secs: i64,
+    pub refresh_interval_secs: u64,
+    /// Signals waiting to be counted before new ones are dropped.
+    pub queue_size: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct StartupConfig {
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct CryptoConfig {
+    pub master_key: String,
+    /// Backend wrapping data keys: `local`, `vault` or `aws-kms`.
+    pub provider: String,
+    /// Vault transit mount and key used by the `vault` provider.
+    pub vault_transit_mount: String,
+    pub vault_transit_key: String,
+    /// KMS key id, ARN or alias used by the `aws-kms` provider.
+    pub kms_key_id: Option<String>,
+    /// Directory for the encrypted data-key cache; unset disables it.
+    pub key_cache_dir: Option<String>,
+    pub key_cache_key_file: Option<String>,
+    pub key_cache_ttl_secs: i64,
+    /// Age at which the active data key is replaced.
+    pub key_rotation_interval_secs: u64,
+    /// How often key states are reloaded, usage counts flushed and
+    /// rotation checked.
+    pub key_refresh_interval_secs: u64,
+    /// How long a new signing key is published before it signs; longer
+    /// than verifiers cache the JWKS.
+    pub signing_propagation_secs: i64,
+    /// How long a replaced signing key stays published; at least the
+    /// longest token lifetime.
+    pub signing_retire_after_secs: i64,
+    /// Plaintext bytes per segment of `/crypto/encrypt-stream` output.
+    pub stream_segment_bytes: u32,
+    /// Largest body either stream endpoint accepts.
+    pub stream_max_bytes: u64,
+    /// Most fields one `/crypto/encrypt-document` or `/crypto/decrypt-document`
+    /// call may touch.
+    pub document_max_fields: usize,
+    /// Full UTC days of the key usage report, see `crypto::usage`.
+    pub usage_recent_days: i64,
+    /// Days before the recent window its daily average is compared with.
+    pub usage_baseline_days: i64,
+    /// Ratio between the two averages reported as a sharp change.
+    pub usage_change_factor: f64,
+    /// Daily operations one of the averages must reach for a change to count.
+    pub usage_min_daily: f64,
+    /// Days without use after which a key is reported unused.
+    pub usage_unused_days: i64,
+    pub usage_top_callers: i64,
+    pub usage_caller_retention_days: i64,
+    /// Statuses replacing the defaults of some algorithms; see
+    /// `crypto::algorithms`.
+    pub algorithms: Vec<(String, AlgorithmStatus)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    pub jwt_secret: String,
+    pub jwt_algorithm: String,
+    pub admin_roles: Vec<String>,
+    /// `iss` of tokens this service issues.
+    pub token_issuer: String,
+    /// Signing key algorithm for issued tokens: `EdDSA` or `ES256`.
+    pub token_algorithm: String,
+    pub refresh_token_ttl_secs: i64,
+    /// Scope a caller needs to mint tokens for others, e.g. the login flow.
+    pub issue_scope: String,
+    /// Scope a caller needs for `/auth/introspect`. `<scope>:access_token`
+    /// and `<scope>:refresh_token` grant one token type only.
+    pub introspect_scope: String,
+    /// Longest `max-age` suggested for active introspection results; 0
+    /// disables caching.
+    pub introspection_cache_secs: i64,
+    /// How often the revocation list is reloaded from storage.
+    pub revocation_refresh_secs: u64,
+    /// How long a one-time code stays valid, see `auth::otp`.
+    pub otp_ttl_secs: i64,
+    /// Digits per one-time code.
+    pub otp_length: u32,
+    /// Wrong codes before a challenge is locked.
+    pub otp_max_attempts: i32,
+    /// Longest a login session lasts, however active; see `auth::sessions`.
+    pub session_max_age_secs: i64,
+    /// Sessions not refreshed for this long end; 0 turns it off.
+    pub session_idle_timeout_secs: i64,
+    /// Clearances, lowest first. A `clearance:<level>` role grants that
+    /// level and those below it; see `auth::labels`.
+    pub clearance_levels: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditConfig {
+    pub pseudonymization_key: String,
+    pub soc_roles: Vec<String>,
+    pub tenant_admin_roles: Vec<String>,
+    pub support_roles: Vec<String>,
+    pub max_query_limit: i64,
+    pub scheduler_interval_secs: u64,
+    pub digest_max_events: usize,
+    pub import_batch_size: usize,
+    pub import_max_errors: usize,
+    pub export_bucket: Option<String>,
+    pub export_prefix: String,
+    pub filter_max_cost: u32,
+    pub buffer_max_events: usize,
+    pub integrity_interval_secs: u64,
+    pub integrity_sample_size: i64,
+    /// How often new events are linked into the hash chain.
+    pub chain_interval_secs: u64,
+    pub chain_batch_size: i64,
+    /// Shortest time between signed checkpoints of the chain.
+    pub checkpoint_interval_secs: i64,
+    /// `EdDSA` or `ES256`; checkpoints must stay verifiable without the
+    /// master key.
+    pub checkpoint_algorithm: String,
+    /// `name=url` per SIEM the audit trail is streamed to, see `audit::siem`.
+    pub siem_sinks: Vec<(String, String)>,
+    pub siem_interval_ms: u64,
+    pub siem_batch_size: i64,
+    pub siem_timeout_secs: u64,
+    pub siem_max_backoff_secs: u64,
+    /// Events a sink may fall behind before `/ready` reports it.
+    pub siem_max_lag: i64,
+    /// HOSTNAME field of syslog messages.
+    pub siem_hostname: String,
+    /// Age after which events leave the database, see `audit::retention`;
+    /// 0 keeps them forever.
+    pub retention_days: i64,
+    /// Write events to the export bucket before removing them.
+    pub retention_archive: bool,
+    pub retention_interval_secs: u64,
+    pub retention_batch_size: i64,
+    /// Audit actions whose callers get a signed receipt, see `audit::receipts`.
+    pub receipt_actions: Vec<String>,
+    /// `EdDSA` or `ES256`, so receipts verify against the JWKS.
+    pub receipt_algorithm: String,
+}
+
+/// Noise on aggregates shown outside the SOC; see `privacy`.
+#[derive(Debug, Clone)]
+pub struct PrivacyConfig {
+    /// Privacy budget per released count; smaller is noisier.
+    pub epsilon: f64,
+    /// Noisy counts under this are withheld.
+    pub min_count: i64,
+    /// Privacy budget per tenant across releases over overlapping windows.
+    pub budget: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct AlertingConfig {
+    pub webhook_sinks: Vec<(String, String)>,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct DlqConfig {
+    pub max_replay_batch: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BulkConfig {
+    pub max_items: usize,
+    pub concurrency: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct SoftDeleteConfig {
+    pub retention_hours: i64,
+    pub purge_interval_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct FlagsConfig {
+    pub refresh_interval_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExperimentsConfig {
+    pub refresh_interval_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct MaintenanceConfig {
+    pub refresh_interval_secs: u64,
+}
+
+/// Global values for tenant-overridable settings, and the bounds overrides
+/// must stay within.
+#[derive(Debug, Clone)]
+pub struct TenantSettingsConfig {
+    pub cache_ttl_secs: u64,
+    pub rate_limit_rpm: u32,
+    pub rate_limit_max_rpm: u32,
+    /// When on, tenants cannot turn MFA off.
+    pub mfa_required: bool,
+    pub access_token_ttl_secs: i64,
+    pub access_token_min_ttl_secs: i64,
+    pub access_token_max_ttl_secs: i64,
+    /// Tenant origins must be HTTPS and end with one of these; empty means
+    /// tenants cannot add origins.
+    pub allowed_origin_suffixes: Vec<String>,
+    /// When on, every decryption must declare a purpose of use; tenants
+    /// can require it but not opt out.
+    pub purpose_required: bool,
+    /// Purposes of use callers may declare; tenants may narrow the list.
+    pub purposes: Vec<String>,
+    /// Locale of requests that neither ask for one nor belong to a tenant
+    /// choosing one.
+    pub locale: String,
+    /// Locales requests and tenants may choose.
+    pub locales: Vec<String>,
+    /// IANA time zone of tenants choosing none, and of jobs spanning
+    /// tenants.
+    pub time_zone: String,
+    /// Business hours of tenants choosing none; see `timezone`.
+    pub business_hours: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct CorrelationConfig {
+    pub interval_secs: u64,
+    pub batch_size: i64,
+    /// Events younger than this wait for the next batch, so late inserts
+    /// are not skipped by the cursor.
+    pub settle_secs: i64,
+    pub max_window_secs: i64,
+    pub max_steps: usize,
+    /// Per rule; the oldest partial matches are dropped beyond it.
+    pub max_tracked_entities: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct UebaConfig {
+    pub interval_secs: u64,
+    /// Days of audit history each baseline is learned from.
+    pub lookback_days: i64,
+    /// Actors with less history than this are still learning and not scored.
+    pub min_events: i64,
+    pub min_active_days: i64,
+    /// Score (0-100) at which an incident is opened, once per actor per day.
+    pub incident_threshold: f64,
+    pub retention_days: i64,
+}
+
+/// Per-endpoint request volume by hour of week; see `detection::usage`.
+#[derive(Debug, Clone)]
+pub struct UsageConfig {
+    pub enabled: bool,
+    pub interval_secs: u64,
+    /// Weeks of hourly counts each baseline is learned from.
+    pub lookback_weeks: i64,
+    /// Endpoints with less history than this are still learning.
+    pub min_weeks: i64,
+    /// Standard deviations over an hour's mean that count as anomalous.
+    pub threshold_sigma: f64,
+    /// Hours with no more requests than this never alert.
+    pub min_requests: i64,
+    /// Endpoints counted per replica between flushes; the rest are dropped.
+    pub max_tracked: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ContainmentConfig {
+    pub refresh_interval_secs: u64,
+    /// Applied to playbook actions that do not set their own.
+    pub default_duration_secs: i64,
+    pub max_duration_secs: i64,
+    /// Proposed actions nobody approved within this long lapse.
+    pub approval_ttl_secs: i64,
+    /// Subjects playbooks never act against, e.g. service accounts.
+    pub protected_subjects: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SoarConfig {
+    /// `name=url` per integration; incidents are pushed to each url.
+    pub endpoints: Vec<(String, String)>,
+    /// `name=secret` per integration, signing both directions.
+    pub secrets: Vec<(String, String)>,
+    pub timeout_secs: u64,
+    /// Callbacks signed further than this from now are rejected.
+    pub callback_tolerance_secs: i64,
+    pub delivery_interval_ms: u64,
+    pub delivery_batch_size: i64,
+    pub max_attempts: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct DegradedConfig {
+    pub probe_interval_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthConfig {
+    /// A readiness check that takes longer than this counts as failed.
+    pub check_timeout_ms: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct EventsConfig {
+    pub stream: String,
+    pub relay_interval_ms: u64,
+    pub relay_batch_size: i64,
+    pub max_attempts: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExpiryConfig {
+    pub interval_secs: u64,
+    /// Days before expiry to alert at, one alert per lead time.
+    pub lead_days: Vec<u32>,
+    /// PEM certificates on disk to track, e.g. mounted ingress certificates.
+    pub certificate_files: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServiceAccountConfig {
+    /// Accepted `aud` values in client assertions, usually the token
+    /// endpoint's public URL.
+    pub audiences: Vec<String>,
+    /// Longest an assertion may be valid for, `exp` minus `iat`.
+    pub assertion_max_lifetime_secs: i64,
+    /// Role granted to every service account token.
+    pub role: String,
+    /// Active keys an account may hold at once.
+    pub max_keys: i64,
+}
+
+/// Opaque API keys for machine callers; see `auth::api_keys`.
+#[derive(Debug, Clone)]
+pub struct ApiKeyConfig {
+    /// Scopes a key may be given.
+    pub scopes: Vec<String>,
+    pub max_lifetime_days: i64,
+    /// How long a rotated key keeps working unless the rotation says
+    /// otherwise, so callers can switch over.
+    pub rotation_overlap_secs: i64,
+    pub max_rotation_overlap_secs: i64,
+    /// How often keys created or revoked on other replicas are picked up.
+    pub refresh_interval_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct CredentialsConfig {
+    /// Timeout of the calls rotators make.
+    pub timeout_secs: u64,
+    /// How often due rotations are run and the cache is reloaded.
+    pub interval_secs: u64,
+    /// Wait before retrying a failed rotation.
+    pub retry_secs: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeliveryConfig {
+    /// `name=url` per email, SMS or WhatsApp provider, in order of
+    /// preference within a channel; see `delivery`.
+    pub providers: Vec<(String, String)>,
+    /// `name=messages per minute` per provider, across replicas.
+    pub rate_limits: Vec<(String, String)>,
+    /// `name=cost` per message a provider accepts, in the billing currency.
+    pub costs: Vec<(String, String)>,
+    /// Sender of email messages.
+    pub from_address: String,
+    /// Public base URL providers post delivery status to; unset leaves
+    /// callbacks off.
+    pub callback_base_url: Option<String>,
+    pub callback_tolerance_secs: i64,
+    pub timeout_secs: u64,
+    /// Consecutive failures that take a provider out of rotation.
+    pub failure_threshold: u32,
+    /// How long a failing provider stays out of rotation.
+    pub cooldown_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct CaptchaConfig {
+    /// `hcaptcha`, `recaptcha` or `turnstile`; unset turns CAPTCHA
+    /// verification off. See `auth::captcha`.
+    pub provider: Option<String>,
+    /// Site secret, unless the `captcha` credential is stored.
+    pub secret: Option<String>,
+    /// Overrides the provider's siteverify endpoint.
+    pub verify_url: Option<String>,
+    /// Lowest passing score for providers that report one (reCAPTCHA v3).
+    pub min_score: f64,
+    /// Hostnames a token may have been solved on; empty allows any.
+    pub hostnames: Vec<String>,
+    pub timeout_secs: u64,
+    /// Pass requests when the provider cannot be reached.
+    pub fail_open: bool,
+    pub failure_window_secs: i64,
+    /// Failures within the window after which callers should challenge.
+    pub failure_threshold: i64,
+    /// UEBA risk score after which callers should challenge.
+    pub risk_threshold: f64,
+}
+
+/// Password policy and breach screening; see `auth::password`.
+#[derive(Debug, Clone)]
+pub struct PasswordConfig {
+    pub min_length: usize,
+    /// Longer passwords are refused rather than scored.
+    pub max_length: usize,
+    /// Of lowercase, uppercase, digits and symbols.
+    pub min_character_classes: usize,
+    /// Lowest acceptable strength score, 0-4.
+    pub min_score: u8,
+    /// Words a password may not contain, matched ignoring case and common
+    /// character substitutions.
+    pub banned_words: Vec<String>,
+    /// File of further banned words, one per line.
+    pub banned_words_file: Option<String>,
+    /// Query the Have I Been Pwned range API.
+    pub breach_check: bool,
+    pub breach_api_url: String,
+    pub breach_timeout_secs: u64,
+    /// Accept passwords when the range API cannot be reached.
+    pub breach_fail_open: bool,
+    /// How long fetched hash ranges are reused, and how many are kept.
+    pub breach_cache_secs: i64,
+    pub breach_cache_ranges: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct NotaryConfig {
+    /// Scope needed to register documents; see `notary`.
+    pub register_scope: String,
+    /// `EdDSA` or `ES256`; proofs must stay verifiable without the master
+    /// key.
+    pub algorithm: String,
+}
+
+/// Signed file manifests of multi-file packages; see `manifests`.
+#[derive(Debug, Clone)]
+pub struct ManifestConfig {
+    /// Scope needed to create manifests.
+    pub create_scope: String,
+    /// Scope needed to read manifests and check packages against them.
+    pub verify_scope: String,
+    /// `EdDSA` or `ES256`, as for the notary.
+    pub algorithm: String,
+    pub max_files: usize,
+}
+
+/// PII tokenization; see `crypto::tokenization`.
+#[derive(Debug, Clone)]
+pub struct TokenizationConfig {
+    pub tokenize_scope: String,
+    /// Kept apart from `tokenize_scope` so most callers never see values.
+    pub detokenize_scope: String,
+    /// Most values or tokens in one request.
+    pub max_batch: usize,
+}
+
+/// Tripwire on plaintext reads per caller; see `crypto::guard`.
+#[derive(Debug, Clone)]
+pub struct CryptoGuardConfig {
+    pub enabled: bool,
+    /// Length of the windows reads are counted in.
+    pub window_secs: i64,
+    /// A caller's limit is this times their baseline.
+    pub factor: f64,
+    /// Baseline of callers still learning, and the least any caller gets.
+    pub min_baseline: i64,
+    /// Days of windows a baseline is learned from.
+    pub lookback_days: i64,
+    /// Windows with reads a caller needs before their own peak counts.
+    pub min_windows: i64,
+    /// How often counts are shared between instances and limits updated.
+    pub sync_interval_secs: u64,
+    /// Subjects never paused, e.g. batch jobs with their own controls.
+    pub exempt_subjects: Vec<String>,
+}
+
+/// Approved grants for decrypting many records at once; see `crypto::bulk`.
+#[derive(Debug, Clone)]
+pub struct BulkDecryptConfig {
+    /// Most records one request decrypts without a grant.
+    pub threshold: usize,
+    /// Most items in one `POST /crypto/decrypt-batch`, grant or not.
+    pub max_batch: usize,
+    /// Most records one grant may cover.
+    pub max_grant_records: i64,
+    /// How long an approved grant can be used.
+    pub grant_ttl_secs: i64,
+    /// How long a request waits for approval before lapsing.
+    pub approval_ttl_secs: i64,
+    /// Scope of non-admins who may approve grants.
+    pub approve_scope: String,
+}
+
+/// Signing secrets for outgoing webhooks; see `crypto::webhook`.
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// `name=secret`, a name repeated for each secret while rotating.
+    pub secrets: Vec<(String, String)>,
+    /// Furthest a verified signature's timestamp may be from now.
+    pub tolerance_secs: i64,
+    /// `name=secs` overriding `tolerance_secs` per name.
+    pub tolerances: Vec<(String, i64)>,
+}
+
+/// Sandboxed WebAssembly plugins; see `plugins`.
+#[derive(Debug, Clone)]
+pub struct PluginsConfig {
+    pub refresh_interval_secs: u64,
+    pub max_module_bytes: usize,
+    /// Linear memory one call may grow to.
+    pub max_memory_bytes: usize,
+    /// Fuel one call may burn, about one unit per instruction.
+    pub max_fuel: u64,
+    pub max_output_bytes: usize,
+}
+
+/// Request hooks run by the `hooks` pipeline stage; see `hooks`.
+#[derive(Debug, Clone)]
+pub struct HooksConfig {
+    /// JSON list of hook definitions.
+    pub file: Option<String>,
+    /// Largest JSON body read for hooks; larger ones are rejected with 413.
+    pub max_body_bytes: usize,
+}
+
+/// Authorization decisions for other services; see `authz`.
+#[derive(Debug, Clone)]
+pub struct AuthzConfig {
+    /// JSON file of rules applying to every tenant, alongside `authz`
+    /// policy documents.
+    pub policy_file: Option<String>,
+    /// Scope needed to ask for decisions.
+    pub check_scope: String,
+    /// Most checks in one bulk request.
+    pub max_batch: usize,
+    /// Audit allows as well as denies.
+    pub log_allows: bool,
+    /// JSON file of separation of duties rules for every tenant, alongside
+    /// `sod` policy documents.
+    pub sod_policy_file: Option<String>,
+}
+
+/// Chain of custody of evidence files; see `custody`.
+#[derive(Debug, Clone)]
+pub struct CustodyConfig {
+    /// Scope needed to flag evidence and record access, transfer and export.
+    pub record_scope: String,
+    /// Scope needed to generate signed custody reports.
+    pub report_scope: String,
+    /// `EdDSA` or `ES256`; reports must verify without the master key.
+    pub algorithm: String,
+}
+
+/// Retention of data other than audit events; see `retention`.
+#[derive(Debug, Clone)]
+pub struct RetentionConfig {
+    /// Days each data type is kept; types not listed are kept as long as
+    /// their own modules keep them.
+    pub policies: Vec<(String, i64)>,
+    pub interval_secs: u64,
+    /// Most rows deleted per statement.
+    pub batch_size: i64,
+    /// Local time (`HH:MM`, in `TENANT_TIME_ZONE`) to run once a day at
+    /// instead of every interval.
+    pub run_at: Option<String>,
+}
+
+/// Anonymous reporting channel; see `whistleblower`.
+#[derive(Debug, Clone)]
+pub struct WhistleblowerConfig {
+    /// File holding the public key reports are encrypted to; the channel is
+    /// closed without it.
+    pub public_key_file: Option<String>,
+    /// The only role that may handle reports. Admin roles do not imply it.
+    pub role: String,
+    /// `exact`, `hour` or `day`: how precisely report and message times
+    /// are kept.
+    pub received_precision: String,
+    /// Reporter-supplied metadata kept; anything else is dropped.
+    pub metadata_fields: Vec<String>,
+    /// How long an approved access request lets a handler read a report.
+    pub access_ttl_secs: i64,
+    /// Largest ciphertext accepted, decoded.
+    pub max_bytes: usize,
+}
+
+/// Store-and-forward messages between services; see `mailbox`.
+#[derive(Debug, Clone)]
+pub struct MailboxConfig {
+    pub send_scope: String,
+    pub receive_scope: String,
+    pub default_ttl_secs: i64,
+    pub max_ttl_secs: i64,
+    /// Largest message body accepted.
+    pub max_bytes: usize,
+    /// How often expiry and webhook notification run.
+    pub interval_ms: u64,
+    pub batch_size: i64,
+    /// Webhook attempts before a notification is given up on.
+    pub max_attempts: i32,
+    pub timeout_secs: u64,
+}
+
+/// Internal gRPC listener for other services (feature `grpc`); see `grpc`.
+#[derive(Debug, Clone)]
+pub struct GrpcConfig {
+    /// Served on `host`; 0 disables the listener.
+    pub port: u16,
+    /// Threads of the listener's own runtime; 0 means one per core.
+    pub worker_threads: usize,
+    /// Largest request or response message.
+    pub max_message_bytes: usize,
+    /// Largest `EmitAuditEvent` payload, in bytes.
+    pub max_audit_payload_bytes: usize,
+}
+
+/// Field-encrypting PostgreSQL proxy for legacy services; see `pgproxy`.
+#[derive(Debug, Clone)]
+pub struct PgProxyConfig {
+    /// Served on `host`; 0 disables the proxy.
+    pub port: u16,
+    /// PostgreSQL server sessions are relayed to, as `host:port`.
+    pub upstream: Option<String>,
+    /// Encrypted columns, as `table.column`.
+    pub columns: Vec<String>,
+    /// Key new values are encrypted under; unset uses the active key.
+    pub key_id: Option<String>,
+    /// Sessions open at once; further clients wait to be accepted.
+    pub max_connections: usize,
+    /// Largest protocol message, either way.
+    pub max_message_bytes: usize,
+    /// Threads of the proxy's own runtime; 0 means one per core.
+    pub worker_threads: usize,
+}
+
+/// S3-compatible gateway encrypting objects for legacy services; see
+/// `s3_gateway`.
+#[derive(Debug, Clone)]
+pub struct S3GatewayConfig {
+    /// Served on `host`; 0 disables the listener.
+    pub port: u16,
+    /// Backing bucket objects are stored in, encrypted.
+    pub bucket: Option<String>,
+    /// S3-compatible endpoint instead of AWS.
+    pub endpoint: Option<String>,
+    /// Path within the bucket tenants' prefixes go under.
+    pub prefix: String,
+    /// Key data keys are wrapped under; unset uses the active key.
+    pub key_id: Option<String>,
+    /// Largest object accepted, in bytes.
+    pub max_object_bytes: usize,
+    /// How far a request's signing time may be from now.
+    pub max_clock_skew_secs: i64,
+}
+
+/// DKIM signing and SPF/DKIM/DMARC checks of received mail; see `email`.
+#[derive(Debug, Clone)]
+pub struct EmailConfig {
+    /// Domains keys are kept and mail is signed for.
+    pub dkim_domains: Vec<String>,
+    /// Each domain has a key, and mail a signature, per algorithm.
+    pub dkim_algorithms: Vec<String>,
+    pub dkim_selector_prefix: String,
+    /// Header fields signed, every occurrence.
+    pub dkim_headers: Vec<String>,
+    pub dkim_rsa_bits: usize,
+    /// Age at which an active key is replaced; 0 rotates only on demand.
+    pub dkim_rotation_days: i64,
+    /// How long a new key's record is published before the key signs.
+    pub dkim_propagation_secs: i64,
+    /// How long a replaced key's record stays published.
+    pub dkim_retire_after_secs: i64,
+    pub dkim_interval_secs: u64,
+    pub sign_scope: String,
+    pub verify_scope: String,
+    pub dns_timeout_secs: u64,
+    /// Largest message signed or checked.
+    pub max_message_bytes: usize,
+}
+
+/// Proof of tenant domain ownership by DNS TXT challenge; see `domains`.
+#[derive(Debug, Clone)]
+pub struct DomainVerificationConfig {
+    /// Whether tenant origins and webhooks must be in a verified domain.
+    pub required: bool,
+    /// Resolver addresses challenges are looked up with; empty uses the
+    /// system's.
+    pub resolvers: Vec<String>,
+    /// Reject answers from unsigned zones.
+    pub require_dnssec: bool,
+    /// Label under the domain holding the TXT record.
+    pub challenge_label: String,
+    /// How long a pending challenge may go unanswered.
+    pub challenge_ttl_hours: i64,
+    /// Gap between lookups of a pending challenge, and after resolver
+    /// failures.
+    pub pending_interval_secs: i64,
+    /// Gap between re-checks of a verified domain.
+    pub recheck_interval_secs: i64,
+    /// Failed re-checks in a row after which a domain lapses.
+    pub lapse_after_failures: i32,
+    pub interval_secs: u64,
+    /// Domains checked per pass.
+    pub batch_size: i64,
+    pub check_scope: String,
+    pub dns_timeout_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SealConfig {
+    /// PAdES signer holding the seal key; unset turns sealing off. See
+    /// `seal`.
+    pub signer_url: Option<String>,
+    /// RFC 3161 authority the signer timestamps with; unset leaves the
+    /// choice to the signer.
+    pub tsa_url: Option<String>,
+    /// Label of the seal key in the signer's HSM.
+    pub key_label: String,
+    /// PAdES baseline level: `B-T`, `B-LT` (revocation data embedded) or
+    /// `B-LTA` (also archive-timestamped).
+    pub level: String,
+    /// Validation service checking sealed documents on revalidation; unset
+    /// turns revalidation off.
+    pub validator_url: Option<String>,
+    pub reason: String,
+    pub location: String,
+    /// Bucket holding originals and sealed documents.
+    pub bucket: Option<String>,
+    pub prefix: String,
+    /// Scope needed to submit documents and fetch the results.
+    pub submit_scope: String,
+    pub max_bytes: usize,
+    pub timeout_secs: u64,
+    pub interval_ms: u64,
+    pub batch_size: i64,
+    pub max_attempts: i32,
+}
+
+impl Config {
+    /// Load from the environment and `CONFIG_FILE`.
+    pub fn from_env() -> Result<Self, SecurityError> {
+        let file_vars = match env::var("CONFIG_FILE") {
+            Ok(path) => read_config_file(&path)?,
+            Err(_) => HashMap::new(),
+        };
+        Self::from_vars(file_vars)
+    }
+
+    /// Load from `vars`, falling back to the environment for anything they
+    /// do not set. Lets integration tests configure instances side by side.
+    pub fn from_vars(vars: HashMap<String, String>) -> Result<Self, SecurityError> {
+        FILE_VARS.with(|file_vars| *file_vars.borrow_mut() = vars);
+        let loaded = Self::load();
+        FILE_VARS.with(|file_vars| file_vars.borrow_mut().clear());
+        loaded
+    }
+
+    /// The defaults, with `vars` and what has no default set, for unit
+    /// tests. Nothing is read from a database or other service.
+    #[cfg(test)]
+    pub(crate) fn for_tests(vars: &[(&str, &str)]) -> Self {
+        let mut all: HashMap<String, String> = [
+            ("DATABASE_URL", "postgres://cotai@127.0.0.1:1/cotai"),
+            ("SECURITY_MASTER_KEY", "unit-test-master-key-of-32-bytes"),
+            ("SECRET_KEY", "unit-test-token-secret-of-at-least-32-bytes"),
+            ("RATE_LIMIT_BACKEND", "memory"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        all.extend(vars.iter().map(|(name, value)| (name.to_string(), value.to_string())));
+        Self::from_vars(all).expect("test configuration")
+    }
+
+    fn load() -> Result<Self, SecurityError> {
+        let mut vars = Vars::default();
+        let master_key = vars.required_secret("SECURITY_MASTER_KEY");
+
+        let config = Self {
+            host: env_or("SECURITY_HOST", "0.0.0.0"),
+            port: vars.parse_or("SECURITY_PORT", 8080),
+            database_url: vars.required_secret("DATABASE_URL"),
+            database_password: vars.secret_var("DATABASE_PASSWORD"),
+            redis_url: vars.secret_var("REDIS_URL").unwrap_or_else(|| "redis://127.0.0.1:6379".to_string()),
+            admin_bind: var("SECURITY_ADMIN_BIND").ok(),
+            server: ServerConfig {
+                workers: vars.parse_or("SERVER_WORKERS", 0),
+                keep_alive_secs: vars.parse_or("SERVER_KEEP_ALIVE_SECS", 5),
+                client_request_timeout_ms: vars.parse_or("SERVER_CLIENT_REQUEST_TIMEOUT_MS", 5000),
+                client_disconnect_timeout_ms: vars.parse_or("SERVER_CLIENT_DISCONNECT_TIMEOUT_MS", 5000),
+                max_connections: vars.parse_or("SERVER_MAX_CONNECTIONS", 25000),
+                max_connection_rate: vars.parse_or("SERVER_MAX_CONNECTION_RATE", 256),
+                backlog: vars.parse_or("SERVER_BACKLOG", 4096),
+                shutdown_timeout_secs: vars.parse_or("SERVER_SHUTDOWN_TIMEOUT_SECS", 30),
+                http2_cleartext: vars.parse_or("SERVER_HTTP2_CLEARTEXT", false),
+                compression: vars.parse_or("SERVER_COMPRESSION", true),
+            },
+            tls: TlsConfig {
+                cert_path: var("TLS_CERT_PATH").ok(),
+                key_path: var("TLS_KEY_PATH").ok(),
+                cert_pem: var("TLS_CERT_PEM").ok(),
+                key_pem: vars.secret_var("TLS_KEY_PEM"),
+                client_ca_path: var("TLS_CLIENT_CA_PATH").ok(),
+                reload_interval_secs: vars.parse_or("TLS_RELOAD_INTERVAL_SECS", 30),
+            },
+            secrets: SecretsConfig {
+                vault_addr: var("VAULT_ADDR").ok(),
+                vault_token: vars.secret_var("VAULT_TOKEN"),
+                vault_namespace: var("VAULT_NAMESPACE").ok(),
+                vault_kv_mount: env_or("VAULT_KV_MOUNT", "secret"),
+                kms_region: var("AWS_REGION").or_else(|_| var("AWS_DEFAULT_REGION")).ok(),
+                kms_endpoint: var("AWS_KMS_ENDPOINT").ok(),
+                timeout_secs: vars.parse_or("SECRETS_TIMEOUT_SECS", 10),
+            },
+            middleware: MiddlewareConfig {
+                pipeline: list_or(
+                    "MIDDLEWARE_PIPELINE",
+                    &["compression_policy", "client_cert", "maintenance", "lockout", "rate_limit", "scopes"],
+                ),
+                route_scopes: match var("MIDDLEWARE_ROUTE_SCOPES") {
+                    Ok(_) => vars.pairs_or("MIDDLEWARE_ROUTE_SCOPES"),
+                    Err(_) => DEFAULT_ROUTE_SCOPES
+                        .iter()
+                        .map(|(prefix, scope)| (prefix.to_string(), scope.to_string()))
+                        .collect(),
+                },
+                cors: vars.parse_or("MIDDLEWARE_CORS", true),
+                cors_origins: list_or("CORS_ALLOWED_ORIGINS", &[]),
+                request_log: vars.parse_or("MIDDLEWARE_REQUEST_LOG", true),
+            },
+            rate_limit: RateLimitConfig {
+                backend: env_or("RATE_LIMIT_BACKEND", "redis"),
+                routes: vars.pairs_or("RATE_LIMIT_ROUTES"),
+                default_algorithm: env_or("RATE_LIMIT_DEFAULT_ALGORITHM", "token_bucket"),
+                timeout_ms: vars.parse_or("RATE_LIMIT_TIMEOUT_MS", 100),
+                key_prefix: env_or("RATE_LIMIT_KEY_PREFIX", "cotai:ratelimit"),
+                subnet_v4_prefix: vars.parse_or("RATE_LIMIT_SUBNET_V4_PREFIX", 24),
+                subnet_v6_prefix: vars.parse_or("RATE_LIMIT_SUBNET_V6_PREFIX", 48),
+                asn_file: var("RATE_LIMIT_ASN_FILE").ok(),
+                escalate_threshold: vars.parse_or("RATE_LIMIT_ESCALATE_THRESHOLD", 16),
+                escalate_window_secs: vars.parse_or("RATE_LIMIT_ESCALATE_WINDOW_SECS", 60),
+                escalate_secs: vars.parse_or("RATE_LIMIT_ESCALATE_SECS", 900),
+            },
+            deadline: DeadlineConfig {
+                enabled: vars.parse_or("DEADLINE_ENABLED", true),
+                default_ms: vars.parse_or("DEADLINE_DEFAULT_MS", 0),
+                max_ms: vars.parse_or("DEADLINE_MAX_MS", 60000),
+            },
+            http_cache: HttpCacheConfig {
+                max_age_secs: vars.parse_or("HTTP_CACHE_MAX_AGE_SECS", 300),
+                stale_while_revalidate_secs: vars.parse_or("HTTP_CACHE_STALE_WHILE_REVALIDATE_SECS", 60),
+                stale_if_error_secs: vars.parse_or("HTTP_CACHE_STALE_IF_ERROR_SECS", 86400),
+            },
+            metrics: MetricsConfig {
+                latency_buckets: vars.parse_list_or(
+                    "METRICS_LATENCY_BUCKETS",
+                    &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0],
+                ),
+            },
+            threats: ThreatsConfig {
+                auth_actions: list_or("THREAT_AUTH_ACTIONS", &["auth.", "captcha."]),
+                account_max_failures: vars.parse_or("THREAT_ACCOUNT_MAX_FAILURES", 10),
+                ip_max_failures: vars.parse_or("THREAT_IP_MAX_FAILURES", 50),
+                rate_limit_max_rejections: vars.parse_or("THREAT_RATE_LIMIT_MAX_REJECTIONS", 100),
+                window_secs: vars.parse_or("THREAT_WINDOW_SECS", 600),
+                lockout_secs: vars.parse_or("THREAT_LOCKOUT_SECS", 900),
+                refresh_interval_secs: vars.parse_or("THREAT_REFRESH_INTERVAL_SECS", 10),
+                queue_size: vars.parse_or("THREAT_QUEUE_SIZE", 10000),
+            },
+            startup: StartupConfig {
+                max_attempts: vars.parse_or("STARTUP_MAX_ATTEMPTS", 10),
+                initial_backoff_ms: vars.parse_or("STARTUP_INITIAL_BACKOFF_MS", 500),
+                max_backoff_ms: vars.parse_or("STARTUP_MAX_BACKOFF_MS", 30000),
+            },
+            crypto: CryptoConfig {
+                master_key: master_key.clone(),
+                provider: env_or("CRYPTO_KEY_PROVIDER", "local"),
+                vault_transit_mount: env_or("CRYPTO_VAULT_TRANSIT_MOUNT", "transit"),
+                vault_transit_key: env_or("CRYPTO_VAULT_TRANSIT_KEY", "cotai-data-keys"),
+                kms_key_id: var("CRYPTO_KMS_KEY_ID").ok(),
+                key_cache_dir: var("CRYPTO_KEY_CACHE_DIR").ok(),
+                key_cache_key_file: var("CRYPTO_KEY_CACHE_KEY_FILE").ok(),
+                key_cache_ttl_secs: vars.parse_or("CRYPTO_KEY_CACHE_TTL_SECS", 604800),
+                key_rotation_interval_secs: vars.parse_or("CRYPTO_KEY_ROTATION_INTERVAL_SECS", 86400),
+                key_refresh_interval_secs: vars.parse_or("CRYPTO_KEY_REFRESH_INTERVAL_SECS", 30),
+                signing_propagation_secs: vars.parse_or("SIGNING_ROLLOVER_PROPAGATION_SECS", 3600),
+                signing_retire_after_secs: vars.parse_or("SIGNING_ROLLOVER_RETIRE_AFTER_SECS", 7200),
+                stream_segment_bytes: vars.parse_or("CRYPTO_STREAM_SEGMENT_BYTES", 65536),
+                stream_max_bytes: vars.parse_or("CRYPTO_STREAM_MAX_BYTES", 256 * 1024 * 1024),
+                document_max_fields: vars.parse_or("CRYPTO_DOCUMENT_MAX_FIELDS", 1000),
+                usage_recent_days: vars.parse_or("CRYPTO_KEY_USAGE_RECENT_DAYS", 7),
+                usage_baseline_days: vars.parse_or("CRYPTO_KEY_USAGE_BASELINE_DAYS", 28),
+                usage_change_factor: vars.parse_or("CRYPTO_KEY_USAGE_CHANGE_FACTOR", 3.0),
+                usage_min_daily: vars.parse_or("CRYPTO_KEY_USAGE_MIN_DAILY", 10.0),
+                usage_unused_days: vars.parse_or("CRYPTO_KEY_USAGE_UNUSED_DAYS", 30),
+                usage_top_callers: vars.parse_or("CRYPTO_KEY_USAGE_TOP_CALLERS", 5),
+                usage_caller_retention_days: vars.parse_or("CRYPTO_KEY_USAGE_CALLER_RETENTION_DAYS", 90),
+                algorithms: vars.parse_pairs_or("CRYPTO_ALGORITHMS"),
+            },
+            auth: AuthConfig {
+                jwt_secret: vars.required_secret("SECRET_KEY"),
+                jwt_algorithm: env_or("JWT_ALGORITHM", "HS256"),
+                admin_roles: list_or("SECURITY_ADMIN_ROLES", &["super_admin"]),
+                token_issuer: env_or("AUTH_TOKEN_ISSUER", "cotai-security"),
+                token_algorithm: env_or("AUTH_TOKEN_ALGORITHM", "EdDSA"),
+                refresh_token_ttl_secs: vars.parse_or("AUTH_REFRESH_TOKEN_TTL_SECS", 2592000),
+                issue_scope: env_or("AUTH_ISSUE_SCOPE", "auth:issue"),
+                introspect_scope: env_or("AUTH_INTROSPECT_SCOPE", "auth:introspect"),
+                introspection_cache_secs: vars.parse_or("AUTH_INTROSPECTION_CACHE_SECS", 30),
+                revocation_refresh_secs: vars.parse_or("AUTH_REVOCATION_REFRESH_SECS", 15),
+                otp_ttl_secs: vars.parse_or("AUTH_OTP_TTL_SECS", 300),
+                otp_length: vars.parse_or("AUTH_OTP_LENGTH", 6),
+                otp_max_attempts: vars.parse_or("AUTH_OTP_MAX_ATTEMPTS", 5),
+                session_max_age_secs: vars.parse_or("AUTH_SESSION_MAX_AGE_SECS", 43200),
+                session_idle_timeout_secs: vars.parse_or("AUTH_SESSION_IDLE_TIMEOUT_SECS", 1800),
+                clearance_levels: list_or("AUTH_CLEARANCE_LEVELS", &["public", "internal", "confidential", "secret"]),
+            },
+            audit: AuditConfig {
+                pseudonymization_key: vars.secret_var("AUDIT_PSEUDONYMIZATION_KEY").unwrap_or(master_key),
+                soc_roles: list_or("AUDIT_SOC_ROLES", &["soc_analyst", "super_admin"]),
+                tenant_admin_roles: list_or("AUDIT_TENANT_ADMIN_ROLES", &["admin"]),
+                support_roles: list_or("AUDIT_SUPPORT_ROLES", &["support"]),
+                max_query_limit: vars.parse_or("AUDIT_MAX_QUERY_LIMIT", 500),
+                scheduler_interval_secs: vars.parse_or("AUDIT_SCHEDULER_INTERVAL_SECS", 60),
+                digest_max_events: vars.parse_or("AUDIT_DIGEST_MAX_EVENTS", 50),
+                import_batch_size: vars.parse_or("AUDIT_IMPORT_BATCH_SIZE", 500),
+                import_max_errors: vars.parse_or("AUDIT_IMPORT_MAX_ERRORS", 100),
+                export_bucket: var("AUDIT_EXPORT_BUCKET").ok(),
+                export_prefix: env_or("AUDIT_EXPORT_PREFIX", "audit-exports"),
+                filter_max_cost: vars.parse_or("AUDIT_FILTER_MAX_COST", 40),
+                buffer_max_events: vars.parse_or("AUDIT_BUFFER_MAX_EVENTS", 10000),
+                integrity_interval_secs: vars.parse_or("AUDIT_INTEGRITY_INTERVAL_SECS", 300),
+                integrity_sample_size: vars.parse_or("AUDIT_INTEGRITY_SAMPLE_SIZE", 20),
+                chain_interval_secs: vars.parse_or("AUDIT_CHAIN_INTERVAL_SECS", 5),
+                chain_batch_size: vars.parse_or("AUDIT_CHAIN_BATCH_SIZE", 1000),
+                checkpoint_interval_secs: vars.parse_or("AUDIT_CHECKPOINT_INTERVAL_SECS", 3600),
+                checkpoint_algorithm: env_or("AUDIT_CHECKPOINT_ALGORITHM", "EdDSA"),
+                siem_sinks: vars.pairs_or("AUDIT_SIEM_SINKS"),
+                siem_interval_ms: vars.parse_or("AUDIT_SIEM_INTERVAL_MS", 1000),
+                siem_batch_size: vars.parse_or("AUDIT_SIEM_BATCH_SIZE", 500),
+                siem_timeout_secs: vars.parse_or("AUDIT_SIEM_TIMEOUT_SECS", 10),
+                siem_max_backoff_secs: vars.parse_or("AUDIT_SIEM_MAX_BACKOFF_SECS", 300),
+                siem_max_lag: vars.parse_or("AUDIT_SIEM_MAX_LAG", 10000),
+                siem_hostname: var("AUDIT_SIEM_HOSTNAME")
+                    .or_else(|_| var("HOSTNAME"))
+                    .unwrap_or_else(|_| "-".to_string()),
+                retention_days: vars.parse_or("AUDIT_RETENTION_DAYS", 0),
+                retention_archive: vars.parse_or("AUDIT_RETENTION_ARCHIVE", true),
+                retention_interval_secs: vars.parse_or("AUDIT_RETENTION_INTERVAL_SECS", 3600),
+                retention_batch_size: vars.parse_or("AUDIT_RETENTION_BATCH_SIZE", 5000),
+                receipt_actions: list_or("AUDIT_RECEIPT_ACTIONS", &["crypto.decrypt", "crypto.key.rotate"]),
+                receipt_algorithm: env_or("AUDIT_RECEIPT_ALGORITHM", "EdDSA"),
+            },
+            privacy: PrivacyConfig {
+                epsilon: vars.parse_or("DP_EPSILON", 1.0),
+                min_count: vars.parse_or("DP_MIN_COUNT", 10),
+                budget: vars.parse_or("DP_EPSILON_BUDGET", 100.0),
+            },
+            alerting: AlertingConfig {
+                webhook_sinks: vars.pairs_or("ALERT_WEBHOOK_SINKS"),
+                timeout_secs: vars.parse_or("ALERT_TIMEOUT_SECS", 10),
+            },
+            dlq: DlqConfig {
+                max_replay_batch: vars.parse_or("DLQ_MAX_REPLAY_BATCH", 500),
+            },
+            bulk: BulkConfig {
+                max_items: vars.parse_or("BULK_MAX_ITEMS", 1000),
+                concurrency: vars.parse_or("BULK_CONCURRENCY", 8),
+            },
+            soft_delete: SoftDeleteConfig {
+                retention_hours: vars.parse_or("SOFT_DELETE_RETENTION_HOURS", 720),
+                purge_interval_secs: vars.parse_or("SOFT_DELETE_PURGE_INTERVAL_SECS", 3600),
+            },
+            flags: FlagsConfig {
+                refresh_interval_secs: vars.parse_or("FLAGS_REFRESH_INTERVAL_SECS", 30),
+            },
+            experiments: ExperimentsConfig {
+                refresh_interval_secs: vars.parse_or("EXPERIMENTS_REFRESH_INTERVAL_SECS", 30),
+            },
+            maintenance: MaintenanceConfig {
+                refresh_interval_secs: vars.parse_or("MAINTENANCE_REFRESH_INTERVAL_SECS", 10),
+            },
+            tenant_settings: TenantSettingsConfig {
+                cache_ttl_secs: vars.parse_or("TENANT_SETTINGS_CACHE_TTL_SECS", 60),
+                rate_limit_rpm: vars.parse_or("TENANT_RATE_LIMIT_RPM", 600),
+                rate_limit_max_rpm: vars.parse_or("TENANT_RATE_LIMIT_MAX_RPM", 6000),
+                mfa_required: vars.parse_or("TENANT_MFA_REQUIRED", false),
+                access_token_ttl_secs: vars.parse_or("TENANT_ACCESS_TOKEN_TTL_SECS", 900),
+                access_token_min_ttl_secs: vars.parse_or("TENANT_ACCESS_TOKEN_MIN_TTL_SECS", 60),
+                access_token_max_ttl_secs: vars.parse_or("TENANT_ACCESS_TOKEN_MAX_TTL_SECS", 3600),
+                allowed_origin_suffixes: list_or("TENANT_ALLOWED_ORIGIN_SUFFIXES", &[]),
+                purpose_required: vars.parse_or("TENANT_PURPOSE_REQUIRED", false),
+                purposes: list_or("TENANT_PURPOSES", &[
+                    "contract_execution",
+                    "legal_obligation",
+                    "procurement_review",
+                    "audit",
+                    "fraud_prevention",
+                    "data_subject_request",
+                    "support",
+                ]),
+                locale: env_or("TENANT_LOCALE", "en"),
+                locales: list_or("TENANT_LOCALES", &["en", "pt-BR"]),
+                time_zone: env_or("TENANT_TIME_ZONE", "America/Sao_Paulo"),
+                business_hours: env_or("TENANT_BUSINESS_HOURS", "mon-fri 08:00-18:00"),
+            },
+            correlation: CorrelationConfig {
+                interval_secs: vars.parse_or("CORRELATION_INTERVAL_SECS", 5),
+                batch_size: vars.parse_or("CORRELATION_BATCH_SIZE", 1000),
+                settle_secs: vars.parse_or("CORRELATION_SETTLE_SECS", 5),
+                max_window_secs: vars.parse_or("CORRELATION_MAX_WINDOW_SECS", 86400),
+                max_steps: vars.parse_or("CORRELATION_MAX_STEPS", 10),
+                max_tracked_entities: vars.parse_or("CORRELATION_MAX_TRACKED_ENTITIES", 10000),
+            },
+            ueba: UebaConfig {
+                interval_secs: vars.parse_or("UEBA_INTERVAL_SECS", 900),
+                lookback_days: vars.parse_or("UEBA_LOOKBACK_DAYS", 30),
+                min_events: vars.parse_or("UEBA_MIN_EVENTS", 50),
+                min_active_days: vars.parse_or("UEBA_MIN_ACTIVE_DAYS", 5),
+                incident_threshold: vars.parse_or("UEBA_INCIDENT_THRESHOLD", 80.0),
+                retention_days: vars.parse_or("UEBA_RETENTION_DAYS", 90),
+            },
+            usage: UsageConfig {
+                enabled: vars.parse_or("USAGE_ENABLED", true),
+                interval_secs: vars.parse_or("USAGE_INTERVAL_SECS", 60),
+                lookback_weeks: vars.parse_or("USAGE_LOOKBACK_WEEKS", 4),
+                min_weeks: vars.parse_or("USAGE_MIN_WEEKS", 2),
+                threshold_sigma: vars.parse_or("USAGE_THRESHOLD_SIGMA", 4.0),
+                min_requests: vars.parse_or("USAGE_MIN_REQUESTS", 100),
+                max_tracked: vars.parse_or("USAGE_MAX_TRACKED", 10000),
+            },
+            containment: ContainmentConfig {
+                refresh_interval_secs: vars.parse_or("CONTAINMENT_REFRESH_INTERVAL_SECS", 10),
+                default_duration_secs: vars.parse_or("CONTAINMENT_DEFAULT_DURATION_SECS", 3600),
+                max_duration_secs: vars.parse_or("CONTAINMENT_MAX_DURATION_SECS", 604800),
+                approval_ttl_secs: vars.parse_or("CONTAINMENT_APPROVAL_TTL_SECS", 86400),
+                protected_subjects: list_or("CONTAINMENT_PROTECTED_SUBJECTS", &[]),
+            },
+            soar: SoarConfig {
+                endpoints: vars.pairs_or("SOAR_ENDPOINTS"),
+                secrets: vars.secret_pairs("SOAR_SECRETS"),
+                timeout_secs: vars.parse_or("SOAR_TIMEOUT_SECS", 10),
+                callback_tolerance_secs: vars.parse_or("SOAR_CALLBACK_TOLERANCE_SECS", 300),
+                delivery_interval_ms: vars.parse_or("SOAR_DELIVERY_INTERVAL_MS", 1000),
+                delivery_batch_size: vars.parse_or("SOAR_DELIVERY_BATCH_SIZE", 50),
+                max_attempts: vars.parse_or("SOAR_MAX_ATTEMPTS", 10),
+            },
+            degraded: DegradedConfig {
+                probe_interval_secs: vars.parse_or("DEGRADED_PROBE_INTERVAL_SECS", 5),
+            },
+            health: HealthConfig {
+                check_timeout_ms: vars.parse_or("HEALTH_CHECK_TIMEOUT_MS", 2000),
+            },
+            events: EventsConfig {
+                stream: env_or("EVENTS_STREAM", "cotai:security:events"),
+                relay_interval_ms: vars.parse_or("EVENTS_RELAY_INTERVAL_MS", 500),
+                relay_batch_size: vars.parse_or("EVENTS_RELAY_BATCH_SIZE", 100),
+                max_attempts: vars.parse_or("EVENTS_MAX_ATTEMPTS", 10),
+            },
+            expiry: ExpiryConfig {
+                interval_secs: vars.parse_or("EXPIRY_INTERVAL_SECS", 3600),
+                lead_days: vars.parse_list_or("EXPIRY_LEAD_DAYS", &[30, 7, 1]),
+                certificate_files: list_or("EXPIRY_CERTIFICATE_FILES", &[]),
+            },
+            service_accounts: ServiceAccountConfig {
+                audiences: list_or("SERVICE_ACCOUNT_AUDIENCES", &["cotai-security"]),
+                assertion_max_lifetime_secs: vars.parse_or("SERVICE_ACCOUNT_ASSERTION_MAX_LIFETIME_SECS", 300),
+                role: env_or("SERVICE_ACCOUNT_ROLE", "service_account"),
+                max_keys: vars.parse_or("SERVICE_ACCOUNT_MAX_KEYS", 3),
+            },
+            api_keys: ApiKeyConfig {
+                scopes: list_or(
+                    "API_KEY_SCOPES",
+                    &[
+                        "crypto:encrypt",
+                        "crypto:decrypt",
+                        "crypto:rewrap",
+                        "crypto:hash",
+                        "crypto:sign",
+                        "crypto:verify",
+                        "crypto:tokenize",
+                        "crypto:detokenize",
+                        "audit:read",
+                        "auth:introspect",
+                        "notary:register",
+                        "seal:submit",
+                        "manifest:create",
+                        "manifest:verify",
+                        "authz:check",
+                        "mailbox:send",
+                        "mailbox:receive",
+                    ],
+                ),
+                max_lifetime_days: vars.parse_or("API_KEY_MAX_LIFETIME_DAYS", 365),
+                rotation_overlap_secs: vars.parse_or("API_KEY_ROTATION_OVERLAP_SECS", 86400),
+                max_rotation_overlap_secs: vars.parse_or("API_KEY_MAX_ROTATION_OVERLAP_SECS", 604800),
+                refresh_interval_secs: vars.parse_or("API_KEY_REFRESH_INTERVAL_SECS", 30),
+            },
+            credentials: CredentialsConfig {
+                timeout_secs: vars.parse_or("OUTBOUND_CREDENTIALS_TIMEOUT_SECS", 10),
+                interval_secs: vars.parse_or("OUTBOUND_CREDENTIALS_INTERVAL_SECS", 60),
+                retry_secs: vars.parse_or("OUTBOUND_CREDENTIALS_RETRY_SECS", 300),
+            },
+            delivery: DeliveryConfig {
+                providers: vars.pairs_or("DELIVERY_PROVIDERS"),
+                rate_limits: vars.pairs_or("DELIVERY_RATE_LIMITS"),
+                costs: vars.pairs_or("DELIVERY_COSTS"),
+                from_address: env_or("DELIVERY_FROM_ADDRESS", "no-reply@cotai.local"),
+                callback_base_url: var("DELIVERY_CALLBACK_BASE_URL").ok(),
+                callback_tolerance_secs: vars.parse_or("DELIVERY_CALLBACK_TOLERANCE_SECS", 300),
+                timeout_secs: vars.parse_or("DELIVERY_TIMEOUT_SECS", 10),
+                failure_threshold: vars.parse_or("DELIVERY_FAILURE_THRESHOLD", 3),
+                cooldown_secs: vars.parse_or("DELIVERY_COOLDOWN_SECS", 60),
+            },
+            captcha: CaptchaConfig {
+                provider: var("CAPTCHA_PROVIDER").ok(),
+                secret: vars.secret_var("CAPTCHA_SECRET"),
+                verify_url: var("CAPTCHA_VERIFY_URL").ok(),
+                min_score: vars.parse_or("CAPTCHA_MIN_SCORE", 0.5),
+                hostnames: list_or("CAPTCHA_HOSTNAMES", &[]),
+                timeout_secs: vars.parse_or("CAPTCHA_TIMEOUT_SECS", 5),
+                fail_open: vars.parse_or("CAPTCHA_FAIL_OPEN", false),
+                failure_window_secs: vars.parse_or("CAPTCHA_FAILURE_WINDOW_SECS", 900),
+                failure_threshold: vars.parse_or("CAPTCHA_FAILURE_THRESHOLD", 3),
+                risk_threshold: vars.parse_or("CAPTCHA_RISK_THRESHOLD", 70.0),
+            },
+            password: PasswordConfig {
+                min_length: vars.parse_or("PASSWORD_MIN_LENGTH", 12),
+                max_length: vars.parse_or("PASSWORD_MAX_LENGTH", 128),
+                min_character_classes: vars.parse_or("PASSWORD_MIN_CHARACTER_CLASSES", 3),
+                min_score: vars.parse_or("PASSWORD_MIN_SCORE", 3),
+                banned_words: list_or("PASSWORD_BANNED_WORDS", &["cotai", "licitacao", "pregao"]),
+                banned_words_file: var("PASSWORD_BANNED_WORDS_FILE").ok(),
+                breach_check: vars.parse_or("PASSWORD_BREACH_CHECK", false),
+                breach_api_url: env_or("PASSWORD_BREACH_API_URL", "https://api.pwnedpasswords.com/range/"),
+                breach_timeout_secs: vars.parse_or("PASSWORD_BREACH_TIMEOUT_SECS", 3),
+                breach_fail_open: vars.parse_or("PASSWORD_BREACH_FAIL_OPEN", true),
+                breach_cache_secs: vars.parse_or("PASSWORD_BREACH_CACHE_SECS", 3600),
+                breach_cache_ranges: vars.parse_or("PASSWORD_BREACH_CACHE_RANGES", 256),
+            },
+            notary: NotaryConfig {
+                register_scope: env_or("NOTARY_REGISTER_SCOPE", "notary:register"),
+                algorithm: env_or("NOTARY_ALGORITHM", "EdDSA"),
+            },
+            seal: SealConfig {
+                signer_url: var("SEAL_SIGNER_URL").ok(),
+                tsa_url: var("SEAL_TSA_URL").ok(),
+                key_label: env_or("SEAL_KEY_LABEL", "cotai-institutional-seal"),
+                level: env_or("SEAL_LEVEL", "B-LTA"),
+                validator_url: var("SEAL_VALIDATOR_URL").ok(),
+                reason: env_or("SEAL_REASON", "Official document sealed by COTAI"),
+                location: env_or("SEAL_LOCATION", "BR"),
+                bucket: var("SEAL_BUCKET").ok(),
+                prefix: env_or("SEAL_PREFIX", "seals"),
+                submit_scope: env_or("SEAL_SUBMIT_SCOPE", "seal:submit"),
+                max_bytes: vars.parse_or("SEAL_MAX_BYTES", 52428800),
+                timeout_secs: vars.parse_or("SEAL_TIMEOUT_SECS", 60),
+                interval_ms: vars.parse_or("SEAL_INTERVAL_MS", 1000),
+                batch_size: vars.parse_or("SEAL_BATCH_SIZE", 10),
+                max_attempts: vars.parse_or("SEAL_MAX_ATTEMPTS", 5),
+            },
+            manifests: ManifestConfig {
+                create_scope: env_or("MANIFEST_CREATE_SCOPE", "manifest:create"),
+                verify_scope: env_or("MANIFEST_VERIFY_SCOPE", "manifest:verify"),
+                algorithm: env_or("MANIFEST_ALGORITHM", "EdDSA"),
+                max_files: vars.parse_or("MANIFEST_MAX_FILES", 10000),
+            },
+            tokenization: TokenizationConfig {
+                tokenize_scope: env_or("TOKENIZATION_TOKENIZE_SCOPE", "crypto:tokenize"),
+                detokenize_scope: env_or("TOKENIZATION_DETOKENIZE_SCOPE", "crypto:detokenize"),
+                max_batch: vars.parse_or("TOKENIZATION_MAX_BATCH", 100),
+            },
+            crypto_guard: CryptoGuardConfig {
+                enabled: vars.parse_or("CRYPTO_GUARD_ENABLED", true),
+                window_secs: vars.parse_or("CRYPTO_GUARD_WINDOW_SECS", 3600),
+                factor: vars.parse_or("CRYPTO_GUARD_FACTOR", 5.0),
+                min_baseline: vars.parse_or("CRYPTO_GUARD_MIN_BASELINE", 200),
+                lookback_days: vars.parse_or("CRYPTO_GUARD_LOOKBACK_DAYS", 14),
+                min_windows: vars.parse_or("CRYPTO_GUARD_MIN_WINDOWS", 24),
+                sync_interval_secs: vars.parse_or("CRYPTO_GUARD_SYNC_INTERVAL_SECS", 15),
+                exempt_subjects: list_or("CRYPTO_GUARD_EXEMPT_SUBJECTS", &[]),
+            },
+            bulk_decrypt: BulkDecryptConfig {
+                threshold: vars.parse_or("CRYPTO_BULK_DECRYPT_THRESHOLD", 50),
+                max_batch: vars.parse_or("CRYPTO_BULK_DECRYPT_MAX_BATCH", 1000),
+                max_grant_records: vars.parse_or("CRYPTO_BULK_GRANT_MAX_RECORDS", 100_000),
+                grant_ttl_secs: vars.parse_or("CRYPTO_BULK_GRANT_TTL_SECS", 3600),
+                approval_ttl_secs: vars.parse_or("CRYPTO_BULK_GRANT_APPROVAL_TTL_SECS", 86400),
+                approve_scope: env_or("CRYPTO_BULK_GRANT_APPROVE_SCOPE", "crypto:bulk-approve"),
+            },
+            webhooks: WebhookConfig {
+                secrets: vars.secret_pairs("WEBHOOK_SIGNING_SECRETS"),
+                tolerance_secs: vars.parse_or("WEBHOOK_TOLERANCE_SECS", 300),
+                tolerances: vars.parse_pairs_or("WEBHOOK_TOLERANCES"),
+            },
+            plugins: PluginsConfig {
+                refresh_interval_secs: vars.parse_or("PLUGIN_REFRESH_INTERVAL_SECS", 30),
+                max_module_bytes: vars.parse_or("PLUGIN_MAX_MODULE_BYTES", 4 * 1024 * 1024),
+                max_memory_bytes: vars.parse_or("PLUGIN_MAX_MEMORY_BYTES", 16 * 1024 * 1024),
+                max_fuel: vars.parse_or("PLUGIN_MAX_FUEL", 10_000_000),
+                max_output_bytes: vars.parse_or("PLUGIN_MAX_OUTPUT_BYTES", 65536),
+            },
+            network: NetworkConfig {
+                // Loopback and private ranges, where in-cluster load balancers live
+                trusted_proxies: list_or(
+                    "TRUSTED_PROXIES",
+                    &["127.0.0.0/8", "::1", "10.0.0.0/8", "172.16.0.0/12", "192.168.0.0/16", "fc00::/7"],
+                ),
+                client_ip_header: env_or("CLIENT_IP_HEADER", "x-forwarded-for").to_lowercase(),
+                proxy_protocol: vars.parse_or("PROXY_PROTOCOL", false),
+                proxy_protocol_timeout_ms: vars.parse_or("PROXY_PROTOCOL_TIMEOUT_MS", 5000),
+            },
+            forensics: ForensicsConfig {
+                enabled: vars.parse_or("FORENSICS_ENABLED", false),
+                stages: list_or("FORENSICS_STAGES", &["lockout", "rate_limit", "hooks"]),
+                max_body_bytes: vars.parse_or("FORENSICS_MAX_BODY_BYTES", 65536),
+                max_per_minute: vars.parse_or("FORENSICS_MAX_PER_MINUTE", 10),
+                redact_headers: list_or(
+                    "FORENSICS_REDACT_HEADERS",
+                    &["authorization", "proxy-authorization", "cookie", "x-api-key", "x-captcha-token", "x-followup-token"],
+                )
+                .into_iter()
+                .map(|name| name.to_lowercase())
+                .collect(),
+                redact_fields: list_or(
+                    "FORENSICS_REDACT_FIELDS",
+                    &["password", "secret", "token", "otp", "api_key", "private_key", "plaintext"],
+                )
+                .into_iter()
+                .map(|name| name.to_lowercase())
+                .collect(),
+                retention_days: vars.parse_or("FORENSICS_RETENTION_DAYS", 30),
+                roles: list_or("FORENSICS_ROLES", &["soc_analyst", "super_admin"]),
+                queue_size: vars.parse_or("FORENSICS_QUEUE_SIZE", 1000),
+            },
+            command_log: CommandLogConfig {
+                enabled: vars.parse_or("COMMAND_LOG_ENABLED", true),
+                max_body_bytes: vars.parse_or("COMMAND_LOG_MAX_BODY_BYTES", 65536),
+                redact_fields: list_or(
+                    "COMMAND_LOG_REDACT_FIELDS",
+                    &["password", "secret", "token", "private_key", "api_key", "plaintext"],
+                )
+                .into_iter()
+                .map(|name| name.to_lowercase())
+                .collect(),
+                roles: list_or("COMMAND_LOG_ROLES", &["soc_analyst", "super_admin"]),
+                max_replay: vars.parse_or("COMMAND_LOG_MAX_REPLAY", 10000),
+            },
+            break_glass: BreakGlassConfig {
+                roles: list_or("BREAK_GLASS_ROLES", &["super_admin"]),
+                ttl_secs: vars.parse_or("BREAK_GLASS_TTL_SECS", 3600),
+                admin_roles: list_or("BREAK_GLASS_ADMIN_ROLES", &["super_admin"]),
+                review_roles: list_or("BREAK_GLASS_REVIEW_ROLES", &["soc_analyst", "super_admin"]),
+                alert_sinks: list_or("BREAK_GLASS_ALERT_SINKS", &[]),
+            },
+            ssh_ca: SshCaConfig {
+                role_principals: vars.pairs_or("SSH_CA_ROLE_PRINCIPALS"),
+                user_cert_ttl_secs: vars.parse_or("SSH_CA_USER_CERT_TTL_SECS", 3600),
+                host_cert_ttl_secs: vars.parse_or("SSH_CA_HOST_CERT_TTL_SECS", 2592000),
+                user_extensions: list_or(
+                    "SSH_CA_USER_EXTENSIONS",
+                    &["permit-pty", "permit-agent-forwarding", "permit-port-forwarding"],
+                ),
+                admin_roles: list_or("SSH_CA_ADMIN_ROLES", &["super_admin"]),
+                host_scope: env_or("SSH_CA_HOST_SCOPE", "ssh:host"),
+            },
+            backups: BackupConfig {
+                bucket: var("BACKUP_BUCKET").ok(),
+                prefix: env_or("BACKUP_PREFIX", "backups"),
+                interval_secs: vars.parse_or("BACKUP_INTERVAL_SECS", 86400),
+                drill_interval_secs: vars.parse_or("BACKUP_DRILL_INTERVAL_SECS", 604800),
+                max_age_secs: vars.parse_or("BACKUP_MAX_AGE_SECS", 172800),
+            },
+            quorum: QuorumConfig {
+                publish_scope: env_or("QUORUM_PUBLISH_SCOPE", "quorum:publish"),
+                sign_scope: env_or("QUORUM_SIGN_SCOPE", "quorum:sign"),
+                threshold: vars.parse_or("QUORUM_THRESHOLD", 3),
+                window_secs: vars.parse_or("QUORUM_WINDOW_SECS", 172800),
+                algorithm: env_or("QUORUM_ALGORITHM", "EdDSA"),
+            },
+            submissions: SubmissionConfig {
+                manage_scope: env_or("SUBMISSION_MANAGE_SCOPE", "submission:manage"),
+                submit_scope: env_or("SUBMISSION_SUBMIT_SCOPE", "submission:submit"),
+                algorithm: env_or("SUBMISSION_ALGORITHM", "EdDSA"),
+                max_clock_skew_ms: vars.parse_or("SUBMISSION_MAX_CLOCK_SKEW_MS", 1000),
+            },
+            collusion: CollusionConfig {
+                analyze_scope: env_or("COLLUSION_ANALYZE_SCOPE", "collusion:analyze"),
+                threshold: vars.parse_or("COLLUSION_THRESHOLD", 0.6),
+                weights: vars.parse_pairs_or("COLLUSION_WEIGHTS"),
+                max_bids: vars.parse_or("COLLUSION_MAX_BIDS", 500),
+            },
+            dossiers: DossierConfig {
+                export_scope: env_or("DOSSIER_EXPORT_SCOPE", "dossier:export"),
+                bucket: var("DOSSIER_BUCKET").ok(),
+                prefix: env_or("DOSSIER_PREFIX", "dossiers"),
+                algorithm: env_or("DOSSIER_ALGORITHM", "EdDSA"),
+                max_events: vars.parse_or("DOSSIER_MAX_EVENTS", 100_000),
+            },
+            residency: ResidencyConfig {
+                home_region: var("RESIDENCY_HOME_REGION")
+                    .or_else(|_| var("AWS_REGION"))
+                    .unwrap_or_else(|_| "home".to_string()),
+                regions: vars.pairs_or("RESIDENCY_REGIONS"),
+            },
+            key_archive: KeyArchiveConfig {
+                bucket: var("KEY_ARCHIVE_BUCKET").ok(),
+                prefix: env_or("KEY_ARCHIVE_PREFIX", "key-archive"),
+                after_days: vars.parse_or("KEY_ARCHIVE_AFTER_DAYS", 30),
+                restore_delay_secs: vars.parse_or("KEY_ARCHIVE_RESTORE_DELAY_SECS", 86400),
+                restore_window_secs: vars.parse_or("KEY_ARCHIVE_RESTORE_WINDOW_SECS", 604800),
+                interval_secs: vars.parse_or("KEY_ARCHIVE_INTERVAL_SECS", 3600),
+            },
+            rng: RngConfig {
+                health_checks: vars.parse_or("RNG_HEALTH_CHECKS", true),
+                min_entropy_bits: vars.parse_or("RNG_MIN_ENTROPY_BITS", 4.0),
+                jitter: vars.parse_or("RNG_JITTER", false),
+                jitter_reseed_secs: vars.parse_or("RNG_JITTER_RESEED_SECS", 300),
+                interval_secs: vars.parse_or("RNG_HEALTH_INTERVAL_SECS", 60),
+            },
+            billing: BillingConfig {
+                enabled: vars.parse_or("BILLING_ENABLED", true),
+                unit_weights: vars.parse_pairs_or("BILLING_UNIT_WEIGHTS"),
+                flush_interval_secs: vars.parse_or("BILLING_FLUSH_INTERVAL_SECS", 60),
+                export_delay_secs: vars.parse_or("BILLING_EXPORT_DELAY_SECS", 3600),
+                export_bucket: var("BILLING_EXPORT_BUCKET").ok(),
+                export_prefix: env_or("BILLING_EXPORT_PREFIX", "billing"),
+                webhook_url: var("BILLING_WEBHOOK_URL").ok(),
+                webhook_secret: env_or("BILLING_WEBHOOK_SECRET", "billing"),
+                timeout_secs: vars.parse_or("BILLING_TIMEOUT_SECS", 10),
+            },
+            jobs: JobsConfig {
+                inline_wait_ms: vars.parse_or("JOBS_INLINE_WAIT_MS", 10000),
+                result_ttl_secs: vars.parse_or("JOBS_RESULT_TTL_SECS", 86400),
+                max_runtime_secs: vars.parse_or("JOBS_MAX_RUNTIME_SECS", 3600),
+                max_wait_secs: vars.parse_or("JOBS_MAX_WAIT_SECS", 30),
+                poll_after_secs: vars.parse_or("JOBS_POLL_AFTER_SECS", 2),
+                callback_hosts: list_or("JOBS_CALLBACK_HOSTS", &[]),
+                callback_secret: env_or("JOBS_CALLBACK_SECRET", "jobs"),
+                callback_max_attempts: vars.parse_or("JOBS_CALLBACK_MAX_ATTEMPTS", 5),
+                interval_secs: vars.parse_or("JOBS_INTERVAL_SECS", 30),
+                timeout_secs: vars.parse_or("JOBS_CALLBACK_TIMEOUT_SECS", 10),
+            },
+            elevation: ElevationConfig {
+                policies: vars.pairs_or("ELEVATION_ROLES"),
+                default_duration_secs: vars.parse_or("ELEVATION_DEFAULT_DURATION_SECS", 3600),
+                max_duration_secs: vars.parse_or("ELEVATION_MAX_DURATION_SECS", 14400),
+                strip_standing: vars.parse_or("ELEVATION_STRIP_STANDING", false),
+                exempt_subjects: list_or("ELEVATION_EXEMPT_SUBJECTS", &[]),
+                approver_roles: list_or("ELEVATION_APPROVER_ROLES", &["security_approver"]),
+                pending_ttl_secs: vars.parse_or("ELEVATION_PENDING_TTL_SECS", 3600),
+            },
+            access_review: AccessReviewConfig {
+                interval_days: vars.parse_or("ACCESS_REVIEW_INTERVAL_DAYS", 90),
+                deadline_days: vars.parse_or("ACCESS_REVIEW_DEADLINE_DAYS", 14),
+                revoke_unanswered: vars.parse_or("ACCESS_REVIEW_REVOKE_UNANSWERED", true),
+                admin_roles: list_or("ACCESS_REVIEW_ADMIN_ROLES", &["security_admin"]),
+                fallback_roles: list_or("ACCESS_REVIEW_FALLBACK_ROLES", &["security_admin"]),
+                auditor_roles: list_or("ACCESS_REVIEW_AUDITOR_ROLES", &["auditor"]),
+            },
+            dormancy: DormancyConfig {
+                enabled: vars.parse_or("DORMANCY_ENABLED", true),
+                interval_secs: vars.parse_or("DORMANCY_INTERVAL_SECS", 3600),
+                api_key_days: vars.parse_or("DORMANCY_API_KEY_DAYS", 90),
+                api_key_action: env_or("DORMANCY_API_KEY_ACTION", "notify"),
+                user_days: vars.parse_or("DORMANCY_USER_DAYS", 90),
+                user_action: env_or("DORMANCY_USER_ACTION", "notify"),
+                session_idle_days: vars.parse_or("DORMANCY_SESSION_IDLE_DAYS", 30),
+                session_action: env_or("DORMANCY_SESSION_ACTION", "notify"),
+                exempt_subjects: list_or("DORMANCY_EXEMPT_SUBJECTS", &[]),
+                exempt_api_keys: list_or("DORMANCY_EXEMPT_API_KEYS", &[]),
+            },
+            org: OrgConfig {
+                sync_scope: env_or("ORG_SYNC_SCOPE", "org:sync"),
+                cache_ttl_secs: vars.parse_or("ORG_CACHE_TTL_SECS", 60),
+                scim_subject_prefix: env_or("ORG_SCIM_SUBJECT_PREFIX", "user:"),
+            },
+            resource_registry: ResourceRegistryConfig {
+                scope: env_or("RESOURCE_REGISTRY_SCOPE", "resources:sync"),
+                required_types: list_or("RESOURCE_REGISTRY_REQUIRED_TYPES", &[]),
+                cache_ttl_secs: vars.parse_or("RESOURCE_REGISTRY_CACHE_TTL_SECS", 30),
+                max_cached: vars.parse_or("RESOURCE_REGISTRY_MAX_CACHED", 10000),
+                max_batch: vars.parse_or("RESOURCE_REGISTRY_MAX_BATCH", 1000),
+            },
+            search: SearchConfig {
+                min_query_len: vars.parse_or("SEARCH_MIN_QUERY_LEN", 2),
+                max_results: vars.parse_or("SEARCH_MAX_RESULTS", 50),
+                fuzzy_threshold: vars.parse_or("SEARCH_FUZZY_THRESHOLD", 0.3),
+            },
+            i18n: I18nConfig {
+                catalog_file: var("I18N_CATALOG_FILE").ok(),
+            },
+            problem: ProblemConfig {
+                mode: env_or("PROBLEM_DETAILS", "negotiate"),
+                type_base: env_or("PROBLEM_TYPE_BASE", "urn:cotai:problem:"),
+            },
+            echo: EchoConfig {
+                enabled: vars.parse_or("ECHO_ENABLED", false),
+            },
+            status_page: StatusPageConfig {
+                title: env_or("STATUS_TITLE", "COTAI status"),
+                public_url: var("STATUS_PUBLIC_URL").ok(),
+                incident_severity: env_or("STATUS_INCIDENT_SEVERITY", "critical"),
+                cache_secs: vars.parse_or("STATUS_CACHE_SECS", 30),
+                rate_limit_rpm: vars.parse_or("STATUS_RATE_LIMIT_RPM", 60),
+            },
+            bundles: BundleConfig {
+                signers: vars.pairs_or("CONFIG_BUNDLE_SIGNERS"),
+                file: var("CONFIG_BUNDLE_FILE").ok(),
+                poll_interval_secs: vars.parse_or("CONFIG_BUNDLE_POLL_SECS", 60),
+                max_bytes: vars.parse_or("CONFIG_BUNDLE_MAX_BYTES", 4 * 1024 * 1024),
+            },
+            hooks: HooksConfig {
+                file: var("REQUEST_HOOKS_FILE").ok(),
+                max_body_bytes: vars.parse_or("REQUEST_HOOKS_MAX_BODY_BYTES", 1024 * 1024),
+            },
+            authz: AuthzConfig {
+                policy_file: var("AUTHZ_POLICY_FILE").ok(),
+                check_scope: env_or("AUTHZ_CHECK_SCOPE", "authz:check"),
+                max_batch: vars.parse_or("AUTHZ_MAX_BATCH", 100),
+                log_allows: vars.parse_or("AUTHZ_LOG_ALLOWS", true),
+                sod_policy_file: var("SOD_POLICY_FILE").ok(),
+            },
+            custody: CustodyConfig {
+                record_scope: env_or("CUSTODY_RECORD_SCOPE", "custody:record"),
+                report_scope: env_or("CUSTODY_REPORT_SCOPE", "custody:report"),
+                algorithm: env_or("CUSTODY_ALGORITHM", "EdDSA"),
+            },
+            retention: RetentionConfig {
+                policies: vars.parse_pairs_or("RETENTION_POLICIES"),
+                interval_secs: vars.parse_or("RETENTION_INTERVAL_SECS", 3600),
+                batch_size: vars.parse_or("RETENTION_BATCH_SIZE", 1000),
+                run_at: var("RETENTION_RUN_AT").ok(),
+            },
+            whistleblower: WhistleblowerConfig {
+                public_key_file: var("WHISTLEBLOWER_PUBLIC_KEY_FILE").ok(),
+                role: env_or("WHISTLEBLOWER_ROLE", "whistleblower_handler"),
+                received_precision: env_or("WHISTLEBLOWER_RECEIVED_PRECISION", "day"),
+                metadata_fields: list_or("WHISTLEBLOWER_METADATA_FIELDS", &["category", "language"]),
+                access_ttl_secs: vars.parse_or("WHISTLEBLOWER_ACCESS_TTL_SECS", 86400),
+                max_bytes: vars.parse_or("WHISTLEBLOWER_MAX_BYTES", 1048576),
+            },
+            mailbox: MailboxConfig {
+                send_scope: env_or("MAILBOX_SEND_SCOPE", "mailbox:send"),
+                receive_scope: env_or("MAILBOX_RECEIVE_SCOPE", "mailbox:receive"),
+                default_ttl_secs: vars.parse_or("MAILBOX_DEFAULT_TTL_SECS", 86400),
+                max_ttl_secs: vars.parse_or("MAILBOX_MAX_TTL_SECS", 604800),
+                max_bytes: vars.parse_or("MAILBOX_MAX_BYTES", 65536),
+                interval_ms: vars.parse_or("MAILBOX_INTERVAL_MS", 5000),
+                batch_size: vars.parse_or("MAILBOX_BATCH_SIZE", 50),
+                max_attempts: vars.parse_or("MAILBOX_WEBHOOK_MAX_ATTEMPTS", 8),
+                timeout_secs: vars.parse_or("MAILBOX_WEBHOOK_TIMEOUT_SECS", 10),
+            },
+            grpc: GrpcConfig {
+                port: vars.parse_or("GRPC_PORT", 0),
+                worker_threads: vars.parse_or("GRPC_WORKER_THREADS", 0),
+                max_message_bytes: vars.parse_or("GRPC_MAX_MESSAGE_BYTES", 4 * 1024 * 1024),
+                max_audit_payload_bytes: vars.parse_or("GRPC_MAX_AUDIT_PAYLOAD_BYTES", 65536),
+            },
+            pgproxy: PgProxyConfig {
+                port: vars.parse_or("PGPROXY_PORT", 0),
+                upstream: env::var("PGPROXY_UPSTREAM").ok(),
+                columns: list_or("PGPROXY_COLUMNS", &[]),
+                key_id: env::var("PGPROXY_KEY_ID").ok(),
+                max_connections: vars.parse_or("PGPROXY_MAX_CONNECTIONS", 200),
+                max_message_bytes: vars.parse_or("PGPROXY_MAX_MESSAGE_BYTES", 64 * 1024 * 1024),
+                worker_threads: vars.parse_or("PGPROXY_WORKER_THREADS", 0),
+            },
+            s3_gateway: S3GatewayConfig {
+                port: vars.parse_or("S3_GATEWAY_PORT", 0),
+                bucket: env::var("S3_GATEWAY_BUCKET").ok(),
+                endpoint: env::var("S3_GATEWAY_ENDPOINT").ok(),
+                prefix: env_or("S3_GATEWAY_PREFIX", "tenants"),
+                key_id: env::var("S3_GATEWAY_KEY_ID").ok(),
+                max_object_bytes: vars.parse_or("S3_GATEWAY_MAX_OBJECT_BYTES", 64 * 1024 * 1024),
+                max_clock_skew_secs: vars.parse_or("S3_GATEWAY_MAX_CLOCK_SKEW_SECS", 900),
+            },
+            email: EmailConfig {
+                dkim_domains: list_or("EMAIL_DKIM_DOMAINS", &[]),
+                dkim_algorithms: list_or("EMAIL_DKIM_ALGORITHMS", &["rsa-sha256", "ed25519-sha256"]),
+                dkim_selector_prefix: env_or("EMAIL_DKIM_SELECTOR_PREFIX", "cotai"),
+                dkim_headers: list_or(
+                    "EMAIL_DKIM_HEADERS",
+                    &[
+                        "from", "to", "cc", "reply-to", "subject", "date", "message-id", "mime-version",
+                        "content-type", "content-transfer-encoding",
+                    ],
+                ),
+                dkim_rsa_bits: vars.parse_or("EMAIL_DKIM_RSA_BITS", 2048),
+                dkim_rotation_days: vars.parse_or("EMAIL_DKIM_ROTATION_DAYS", 180),
+                dkim_propagation_secs: vars.parse_or("EMAIL_DKIM_PROPAGATION_SECS", 172800),
+                dkim_retire_after_secs: vars.parse_or("EMAIL_DKIM_RETIRE_AFTER_SECS", 604800),
+                dkim_interval_secs: vars.parse_or("EMAIL_DKIM_INTERVAL_SECS", 3600),
+                sign_scope: env_or("EMAIL_SIGN_SCOPE", "email:sign"),
+                verify_scope: env_or("EMAIL_VERIFY_SCOPE", "email:verify"),
+                dns_timeout_secs: vars.parse_or("EMAIL_DNS_TIMEOUT_SECS", 5),
+                max_message_bytes: vars.parse_or("EMAIL_MAX_MESSAGE_BYTES", 10 * 1024 * 1024),
+            },
+            domain_verification: DomainVerificationConfig {
+                required: vars.parse_or("DOMAIN_VERIFICATION_REQUIRED", true),
+                resolvers: list_or("DOMAIN_VERIFICATION_RESOLVERS", &[]),
+                require_dnssec: vars.parse_or("DOMAIN_VERIFICATION_REQUIRE_DNSSEC", false),
+                challenge_label: env_or("DOMAIN_VERIFICATION_CHALLENGE_LABEL", "_cotai-challenge"),
+                challenge_ttl_hours: vars.parse_or("DOMAIN_VERIFICATION_CHALLENGE_TTL_HOURS", 72),
+                pending_interval_secs: vars.parse_or("DOMAIN_VERIFICATION_PENDING_INTERVAL_SECS", 300),
+                recheck_interval_secs: vars.parse_or("DOMAIN_VERIFICATION_RECHECK_INTERVAL_SECS", 86400),
+                lapse_after_failures: vars.parse_or("DOMAIN_VERIFICATION_LAPSE_AFTER_FAILURES", 3),
+                interval_secs: vars.parse_or("DOMAIN_VERIFICATION_INTERVAL_SECS", 60),
+                batch_size: vars.parse_or("DOMAIN_VERIFICATION_BATCH_SIZE", 100),
+                check_scope: env_or("DOMAIN_VERIFICATION_CHECK_SCOPE", "domains:check"),
+                dns_timeout_secs: vars.parse_or("DOMAIN_VERIFICATION_DNS_TIMEOUT_SECS", 5),
+            },
+            reload: ReloadConfig {
+                file: env::var("CONFIG_FILE").ok(),
+                interval_secs: vars.parse_or("CONFIG_RELOAD_INTERVAL_SECS", 10),
+            },
+            sources: std::mem::take(&mut vars.sources),
+        };
+
+        // Secret references are checked once `secrets` has resolved them
+        let mut problems = vars.problems;
+        problems.extend(config.problems(false));
+        report(problems)?;
+        Ok(config)
+    }
+}
+
+const HMAC_ALGORITHMS: &[&str] = &["HS256", "HS384", "HS512"];
+/// Crypto and audit read routes callers need a scope for.
+const DEFAULT_ROUTE_SCOPES: &[(&str, &str)] = &[
+    ("/api/v1/crypto/encrypt", "crypto:encrypt"),
+    ("/api/v1/crypto/decrypt", "crypto:decrypt"),
+    ("/api/v1/crypto/rewrap", "crypto:rewrap"),
+    ("/api/v1/crypto/hash", "crypto:hash"),
+    ("/api/v1/crypto/sign", "crypto:sign"),
+    ("/api/v1/crypto/verify", "crypto:verify"),
+    ("/api/v1/crypto/webhook/sign", "crypto:sign"),
+    ("/api/v1/crypto/webhook/verify", "crypto:verify"),
+    ("/api/v1/audit/events", "audit:read"),
+];
+const KEY_PROVIDERS: &[&str] = &["local", "vault", "aws-kms"];
+const MIN_SECRET_BYTES: usize = 32;
+
+impl Config {
+    /// Check the whole configuration, reporting every problem at once.
+    /// `from_env` already does this except for secret references; call it
+    /// again after changing a loaded config.
+    pub fn validate(&self) -> Result<(), SecurityError> {
+        report(self.problems(true))
+    }
+
+    /// With `secrets_resolved` off, secret fields still holding a
+    /// `scheme://` reference are skipped.
+    fn problems(&self, secrets_resolved: bool) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, var: &str, problem: &str| {
+            if !ok {
+                problems.push(format!("{}: {}", self.sources.describe(var), problem));
+            }
+        };
+        let pending = |value: &str, native: &[&str]| {
+            !secrets_resolved
+                && value.split_once("://").is_some_and(|(scheme, _)| !native.contains(&scheme))
+        };
+
+        // Listeners
+        check(self.port != 0, "SECURITY_PORT", "must be 1-65535");
+        if let Some(bind) = &self.admin_bind {
+            let valid = bind.rsplit_once(':').is_some_and(|(host, port)| {
+                !host.is_empty() && port.parse::<u16>().is_ok_and(|port| port != 0)
+            });
+            check(valid, "SECURITY_ADMIN_BIND", "must be host:port");
+        }
+        check(self.grpc.port != self.port, "GRPC_PORT", "must differ from SECURITY_PORT");
+        check(self.grpc.max_message_bytes > 0, "GRPC_MAX_MESSAGE_BYTES", "must be positive");
+        check(self.grpc.max_audit_payload_bytes > 0, "GRPC_MAX_AUDIT_PAYLOAD_BYTES", "must be positive");
+        if self.pgproxy.port != 0 {
+            check(
+                self.pgproxy.port != self.port && self.pgproxy.port != self.grpc.port,
+                "PGPROXY_PORT",
+                "must differ from SECURITY_PORT and GRPC_PORT",
+            );
+            let upstream = self.pgproxy.upstream.as_deref().unwrap_or_default();
+            check(
+                upstream.rsplit_once(':').is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok()),
+                "PGPROXY_UPSTREAM",
+                "must be host:port when PGPROXY_PORT is set",
+            );
+            check(!self.pgproxy.columns.is_empty(), "PGPROXY_COLUMNS", "must list at least one table.column");
+            if let Err(entry) = crate::pgproxy::Columns::parse(&self.pgproxy.columns) {
+                check(false, "PGPROXY_COLUMNS", &format!("'{}' is not table.column", entry));
+            }
+            check(self.pgproxy.max_connections > 0, "PGPROXY_MAX_CONNECTIONS", "must be positive");
+            check(self.pgproxy.max_message_bytes > 0, "PGPROXY_MAX_MESSAGE_BYTES", "must be positive");
+        }
+        if self.s3_gateway.port != 0 {
+            check(
+                ![self.port, self.grpc.port, self.pgproxy.port].contains(&self.s3_gateway.port),
+                "S3_GATEWAY_PORT",
+                "must differ from SECURITY_PORT, GRPC_PORT and PGPROXY_PORT",
+            );
+            check(self.s3_gateway.bucket.is_some(), "S3_GATEWAY_BUCKET", "must be set when S3_GATEWAY_PORT is");
+            check(self.s3_gateway.max_object_bytes > 0, "S3_GATEWAY_MAX_OBJECT_BYTES", "must be positive");
+            check(self.s3_gateway.max_clock_skew_secs > 0, "S3_GATEWAY_MAX_CLOCK_SKEW_SECS", "must be positive");
+        }
+        let email = &self.email;
+        check(!email.dkim_algorithms.is_empty(), "EMAIL_DKIM_ALGORITHMS", "must not be empty");
+        check(
+            email.dkim_algorithms.iter().all(|algorithm| crate::email::dkim::ALGORITHMS.contains(&algorithm.as_str())),
+            "EMAIL_DKIM_ALGORITHMS",
+            "must list rsa-sha256 or ed25519-sha256",
+        );
+        check(
+            email.dkim_domains.iter().all(|domain| domain.contains('.') && !domain.contains(['@', '/', ' '])),
+            "EMAIL_DKIM_DOMAINS",
+            "must list domain names",
+        );
+        check(
+            !email.dkim_selector_prefix.is_empty()
+                && email.dkim_selector_prefix.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'),
+            "EMAIL_DKIM_SELECTOR_PREFIX",
+            "must be letters, digits and '-'",
+        );
+        check((2048..=4096).contains(&email.dkim_rsa_bits), "EMAIL_DKIM_RSA_BITS", "must be 2048-4096");
+        check(email.dkim_rotation_days >= 0, "EMAIL_DKIM_ROTATION_DAYS", "must not be negative");
+        check(email.dkim_propagation_secs >= 0, "EMAIL_DKIM_PROPAGATION_SECS", "must not be negative");
+        check(email.dkim_retire_after_secs >= 0, "EMAIL_DKIM_RETIRE_AFTER_SECS", "must not be negative");
+        check(
+            email.dkim_rotation_days == 0 || email.dkim_rotation_days * 86400 > email.dkim_propagation_secs,
+            "EMAIL_DKIM_PROPAGATION_SECS",
+            "must be shorter than EMAIL_DKIM_ROTATION_DAYS",
+        );
+        check(!email.sign_scope.is_empty(), "EMAIL_SIGN_SCOPE", "must not be empty");
+        check(!email.verify_scope.is_empty(), "EMAIL_VERIFY_SCOPE", "must not be empty");
+        check(email.max_message_bytes > 0, "EMAIL_MAX_MESSAGE_BYTES", "must be positive");
+        let domains = &self.domain_verification;
+        check(
+            domains.resolvers.iter().all(|ip| ip.parse::<std::net::IpAddr>().is_ok()),
+            "DOMAIN_VERIFICATION_RESOLVERS",
+            "must list IP addresses",
+        );
+        check(
+            !domains.challenge_label.is_empty()
+                && domains.challenge_label.split('.').all(|label| {
+                    !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                }),
+            "DOMAIN_VERIFICATION_CHALLENGE_LABEL",
+            "must be DNS labels",
+        );
+        check(domains.challenge_ttl_hours > 0, "DOMAIN_VERIFICATION_CHALLENGE_TTL_HOURS", "must be positive");
+        check(domains.pending_interval_secs > 0, "DOMAIN_VERIFICATION_PENDING_INTERVAL_SECS", "must be positive");
+        check(domains.recheck_interval_secs > 0, "DOMAIN_VERIFICATION_RECHECK_INTERVAL_SECS", "must be positive");
+        check(domains.lapse_after_failures > 0, "DOMAIN_VERIFICATION_LAPSE_AFTER_FAILURES", "must be positive");
+        check(domains.batch_size > 0, "DOMAIN_VERIFICATION_BATCH_SIZE", "must be positive");
+        check(!domains.check_scope.is_empty(), "DOMAIN_VERIFICATION_CHECK_SCOPE", "must not be empty");
+        check(self.server.max_connections > 0, "SERVER_MAX_CONNECTIONS", "must be positive");
+        check(self.server.max_connection_rate > 0, "SERVER_MAX_CONNECTION_RATE", "must be positive");
+        check(self.server.backlog > 0, "SERVER_BACKLOG", "must be positive");
+        let tls = &self.tls;
+        check(
+            tls.cert_path.is_none() || tls.cert_pem.is_none(),
+            "TLS_CERT_PEM",
+            "set only one of TLS_CERT_PATH and TLS_CERT_PEM",
+        );
+        check(
+            tls.key_path.is_none() || tls.key_pem.is_none(),
+            "TLS_KEY_PEM",
+            "set only one of TLS_KEY_PATH and TLS_KEY_PEM",
+        );
+        check(tls.cert_path.is_some() == tls.key_path.is_some(), "TLS_CERT_PATH", "must be set with TLS_KEY_PATH");
+        check(tls.cert_pem.is_some() == tls.key_pem.is_some(), "TLS_CERT_PEM", "must be set with TLS_KEY_PEM");
+        check(
+            tls.client_ca_path.is_none() || tls.enabled(),
+            "TLS_CLIENT_CA_PATH",
+            "needs a server certificate (TLS_CERT_PATH or TLS_CERT_PEM)",
+        );
+        check(
+            !(tls.enabled() && self.server.http2_cleartext),
+            "SERVER_HTTP2_CLEARTEXT",
+            "cannot be combined with TLS, which negotiates HTTP/2 itself",
+        );
+
+        // Secrets
+        if !pending(&self.crypto.master_key, &[]) {
+            check(
+                self.crypto.master_key.len() == 32,
+                "SECURITY_MASTER_KEY",
+                &format!("must be exactly 32 bytes for AES-256-GCM, got {}", self.crypto.master_key.len()),
+            );
+        }
+        match self.crypto.provider.as_str() {
+            "local" => {}
+            "vault" => check(self.secrets.vault_addr.is_some(), "CRYPTO_KEY_PROVIDER", "vault needs VAULT_ADDR"),
+            "aws-kms" => {
+                check(self.secrets.kms_region.is_some(), "CRYPTO_KEY_PROVIDER", "aws-kms needs AWS_REGION");
+                check(self.crypto.kms_key_id.is_some(), "CRYPTO_KMS_KEY_ID", "must be set for the aws-kms provider");
+            }
+            other => check(
+                false,
+                "CRYPTO_KEY_PROVIDER",
+                &format!("must be one of {}, got '{}'", KEY_PROVIDERS.join(", "), other),
+            ),
+        }
+        check(
+            HMAC_ALGORITHMS.contains(&self.auth.jwt_algorithm.as_str()),
+            "JWT_ALGORITHM",
+            &format!("must be one of {}", HMAC_ALGORITHMS.join(", ")),
+        );
+        check(
+            matches!(self.auth.token_algorithm.as_str(), "EdDSA" | "ES256"),
+            "AUTH_TOKEN_ALGORITHM",
+            "must be EdDSA or ES256",
+        );
+        check(
+            matches!(self.audit.checkpoint_algorithm.as_str(), "EdDSA" | "ES256"),
+            "AUDIT_CHECKPOINT_ALGORITHM",
+            "must be EdDSA or ES256",
+        );
+        check(
+            matches!(self.audit.receipt_algorithm.as_str(), "EdDSA" | "ES256"),
+            "AUDIT_RECEIPT_ALGORITHM",
+            "must be EdDSA or ES256",
+        );
+        check(
+            matches!(self.notary.algorithm.as_str(), "EdDSA" | "ES256"),
+            "NOTARY_ALGORITHM",
+            "must be EdDSA or ES256",
+        );
+        check(
+            matches!(self.manifests.algorithm.as_str(), "EdDSA" | "ES256"),
+            "MANIFEST_ALGORITHM",
+            "must be EdDSA or ES256",
+        );
+        check(
+            matches!(self.quorum.algorithm.as_str(), "EdDSA" | "ES256"),
+            "QUORUM_ALGORITHM",
+            "must be EdDSA or ES256",
+        );
+        check(
+            matches!(self.submissions.algorithm.as_str(), "EdDSA" | "ES256"),
+            "SUBMISSION_ALGORITHM",
+            "must be EdDSA or ES256",
+        );
+        check(
+            matches!(self.dossiers.algorithm.as_str(), "EdDSA" | "ES256"),
+            "DOSSIER_ALGORITHM",
+            "must be EdDSA or ES256",
+        );
+        check(
+            matches!(self.custody.algorithm.as_str(), "EdDSA" | "ES256"),
+            "CUSTODY_ALGORITHM",
+            "must be EdDSA or ES256",
+        );
+        for (algorithm, _) in &self.crypto.algorithms {
+            check(
+                algorithms::catalogued(algorithm),
+                "CRYPTO_ALGORITHMS",
+                &format!("'{}' is not in the algorithm catalog", algorithm),
+            );
+        }
+        // What the service signs with on its own cannot be refused per call
+        let registry = AlgorithmRegistry::new(&self.crypto);
+        for (name, algorithm) in [
+            ("JWT_ALGORITHM", &self.auth.jwt_algorithm),
+            ("AUTH_TOKEN_ALGORITHM", &self.auth.token_algorithm),
+            ("AUDIT_CHECKPOINT_ALGORITHM", &self.audit.checkpoint_algorithm),
+            ("AUDIT_RECEIPT_ALGORITHM", &self.audit.receipt_algorithm),
+            ("NOTARY_ALGORITHM", &self.notary.algorithm),
+            ("MANIFEST_ALGORITHM", &self.manifests.algorithm),
+            ("QUORUM_ALGORITHM", &self.quorum.algorithm),
+            ("SUBMISSION_ALGORITHM", &self.submissions.algorithm),
+            ("DOSSIER_ALGORITHM", &self.dossiers.algorithm),
+            ("CUSTODY_ALGORITHM", &self.custody.algorithm),
+        ] {
+            check(
+                registry.resolve(algorithm, &BTreeMap::new()) != AlgorithmStatus::Forbidden,
+                name,
+                &format!("{} is forbidden by CRYPTO_ALGORITHMS", algorithm),
+            );
+        }
+        check(self.auth.refresh_token_ttl_secs > 0, "AUTH_REFRESH_TOKEN_TTL_SECS", "must be positive");
+        check(self.auth.introspection_cache_secs >= 0, "AUTH_INTROSPECTION_CACHE_SECS", "must not be negative");
+        if !pending(&self.auth.jwt_secret, &[]) {
+            check(
+                self.auth.jwt_secret.len() >= MIN_SECRET_BYTES,
+                "SECRET_KEY",
+                &format!("must be at least {} bytes", MIN_SECRET_BYTES),
+            );
+        }
+        // Unset, it falls back to the master key, checked above
+        let own_key = self.audit.pseudonymization_key != self.crypto.master_key;
+        if own_key && !pending(&self.audit.pseudonymization_key, &[]) {
+            check(
+                self.audit.pseudonymization_key.len() >= MIN_SECRET_BYTES,
+                "AUDIT_PSEUDONYMIZATION_KEY",
+                &format!("must be at least {} bytes", MIN_SECRET_BYTES),
+            );
+        }
+
+        // URLs
+        let postgres = &["postgres", "postgresql"];
+        if !pending(&self.database_url, postgres) {
+            check(has_scheme(&self.database_url, postgres), "DATABASE_URL", "must be a postgres:// URL");
+        }
+        let redis = &["redis", "rediss", "redis+unix", "unix"];
+        if !pending(&self.redis_url, redis) {
+            check(has_scheme(&self.redis_url, redis), "REDIS_URL", "must be a redis:// or rediss:// URL");
+        }
+        if let Some(addr) = &self.secrets.vault_addr {
+            check(has_scheme(addr, &["http", "https"]), "VAULT_ADDR", "must be an http(s) URL");
+        }
+        for (name, url) in &self.alerting.webhook_sinks {
+            check(
+                has_scheme(url, &["http", "https"]),
+                "ALERT_WEBHOOK_SINKS",
+                &format!("sink '{}' must be an http(s) URL", name),
+            );
+        }
+        for (name, url) in &self.audit.siem_sinks {
+            if let Err(e) = crate::audit::siem::Target::parse(url) {
+                check(false, "AUDIT_SIEM_SINKS", &format!("sink '{}': {}", name, e));
+            }
+        }
+        for (name, url) in &self.delivery.providers {
+            if let Err(e) = crate::delivery::Target::parse(url) {
+                check(false, "DELIVERY_PROVIDERS", &format!("provider '{}': {}", name, e));
+            }
+        }
+        if let Some(url) = &self.delivery.callback_base_url {
+            check(has_scheme(url, &["http", "https"]), "DELIVERY_CALLBACK_BASE_URL", "must be an http(s) URL");
+        }
+        check(
+            ["redis", "memory"].contains(&self.rate_limit.backend.as_str()),
+            "RATE_LIMIT_BACKEND",
+            "must be redis or memory",
+        );
+        for (prefix, spec) in &self.rate_limit.routes {
+            if let Err(e) = crate::rate_limiting::Rule::parse(prefix, spec) {
+                check(false, "RATE_LIMIT_ROUTES", &format!("route '{}': {}", prefix, e));
+            }
+        }
+        check(
+            crate::rate_limiting::Algorithm::parse(&self.rate_limit.default_algorithm).is_some(),
+            "RATE_LIMIT_DEFAULT_ALGORITHM",
+            "must be token_bucket or sliding_window",
+        );
+        check(
+            (1..=32).contains(&self.rate_limit.subnet_v4_prefix),
+            "RATE_LIMIT_SUBNET_V4_PREFIX",
+            "must be between 1 and 32",
+        );
+        check(
+            (1..=128).contains(&self.rate_limit.subnet_v6_prefix),
+            "RATE_LIMIT_SUBNET_V6_PREFIX",
+            "must be between 1 and 128",
+        );
+        if let Some(provider) = &self.captcha.provider {
+            check(
+                crate::auth::captcha::Provider::parse(provider).is_some(),
+                "CAPTCHA_PROVIDER",
+                "must be one of hcaptcha, recaptcha, turnstile",
+            );
+        }
+        if let Some(url) = &self.captcha.verify_url {
+            check(has_scheme(url, &["http", "https"]), "CAPTCHA_VERIFY_URL", "must be an http(s) URL");
+        }
+        let password = &self.password;
+        check(
+            has_scheme(&password.breach_api_url, &["http", "https"]) && password.breach_api_url.ends_with('/'),
+            "PASSWORD_BREACH_API_URL",
+            "must be an http(s) URL ending in '/'",
+        );
+        check(password.min_length > 0, "PASSWORD_MIN_LENGTH", "must be positive");
+        check(password.max_length >= password.min_length, "PASSWORD_MAX_LENGTH", "must be at least PASSWORD_MIN_LENGTH");
+        check(password.min_character_classes <= 4, "PASSWORD_MIN_CHARACTER_CLASSES", "must be 0-4");
+        check(password.min_score <= 4, "PASSWORD_MIN_SCORE", "must be 0-4");
+        check(password.breach_cache_secs >= 0, "PASSWORD_BREACH_CACHE_SECS", "must not be negative");
+        if let Some(url) = &self.seal.signer_url {
+            check(has_scheme(url, &["http", "https"]), "SEAL_SIGNER_URL", "must be an http(s) URL");
+        }
+        if let Some(url) = &self.seal.tsa_url {
+            check(has_scheme(url, &["http", "https"]), "SEAL_TSA_URL", "must be an http(s) URL");
+        }
+        if let Some(url) = &self.seal.validator_url {
+            check(has_scheme(url, &["http", "https"]), "SEAL_VALIDATOR_URL", "must be an http(s) URL");
+        }
+        check(
+            crate::seal::LEVELS.contains(&self.seal.level.as_str()),
+            "SEAL_LEVEL",
+            "must be one of B-T, B-LT, B-LTA",
+        );
+        for (name, url) in &self.soar.endpoints {
+            check(
+                has_scheme(url, &["http", "https"]),
+                "SOAR_ENDPOINTS",
+                &format!("integration '{}' must be an http(s) URL", name),
+            );
+            // Without one here the secret is expected from the credential vault
+            let secret = self.soar.secrets.iter().find(|(n, _)| n == name).map(|(_, s)| s);
+            check(
+                secret.is_none_or(|s| s.len() >= 32),
+                "SOAR_SECRETS",
+                &format!("integration '{}' needs a secret of at least 32 bytes", name),
+            );
+        }
+        for (name, _) in &self.soar.secrets {
+            check(
+                self.soar.endpoints.iter().any(|(n, _)| n == name),
+                "SOAR_SECRETS",
+                &format!("'{}' is not in SOAR_ENDPOINTS", name),
+            );
+        }
+
+        // Settings that only work together
+        check(
+            self.secrets.vault_addr.is_some() == self.secrets.vault_token.is_some(),
+            "VAULT_ADDR",
+            "VAULT_ADDR and VAULT_TOKEN (or VAULT_TOKEN_FILE) must be set together",
+        );
+        check(
+            self.crypto.key_cache_dir.is_some() == self.crypto.key_cache_key_file.is_some(),
+            "CRYPTO_KEY_CACHE_DIR",
+            "CRYPTO_KEY_CACHE_DIR and CRYPTO_KEY_CACHE_KEY_FILE must be set together",
+        );
+        check(self.deadline.max_ms > 0, "DEADLINE_MAX_MS", "must be positive");
+        check(
+            self.deadline.default_ms <= self.deadline.max_ms,
+            "DEADLINE_DEFAULT_MS",
+            "must not exceed DEADLINE_MAX_MS",
+        );
+        let buckets = &self.metrics.latency_buckets;
+        check(
+            !buckets.is_empty() && buckets[0] > 0.0 && buckets.windows(2).all(|pair| pair[0] < pair[1]),
+            "METRICS_LATENCY_BUCKETS",
+            "must list one or more positive bounds in increasing order",
+        );
+        check(self.threats.account_max_failures > 0, "THREAT_ACCOUNT_MAX_FAILURES", "must be positive");
+        check(self.threats.ip_max_failures > 0, "THREAT_IP_MAX_FAILURES", "must be positive");
+        check(self.threats.rate_limit_max_rejections > 0, "THREAT_RATE_LIMIT_MAX_REJECTIONS", "must be positive");
+        check(self.threats.lockout_secs >= 0, "THREAT_LOCKOUT_SECS", "must not be negative");
+        check(self.threats.queue_size > 0, "THREAT_QUEUE_SIZE", "must be positive");
+        check(self.startup.max_attempts > 0, "STARTUP_MAX_ATTEMPTS", "must be positive");
+        check(
+            self.startup.initial_backoff_ms <= self.startup.max_backoff_ms,
+            "STARTUP_INITIAL_BACKOFF_MS",
+            "must not exceed STARTUP_MAX_BACKOFF_MS",
+        );
+        check(
+            (1024..=crate::crypto_stream::MAX_SEGMENT_BYTES).contains(&self.crypto.stream_segment_bytes),
+            "CRYPTO_STREAM_SEGMENT_BYTES",
+            "must be between 1 KiB and 16 MiB",
+        );
+        check(self.crypto.stream_max_bytes > 0, "CRYPTO_STREAM_MAX_BYTES", "must be positive");
+        check(self.crypto.document_max_fields > 0, "CRYPTO_DOCUMENT_MAX_FIELDS", "must be positive");
+        check(self.crypto.usage_recent_days > 0, "CRYPTO_KEY_USAGE_RECENT_DAYS", "must be positive");
+        check(self.crypto.usage_baseline_days > 0, "CRYPTO_KEY_USAGE_BASELINE_DAYS", "must be positive");
+        check(self.crypto.usage_change_factor > 1.0, "CRYPTO_KEY_USAGE_CHANGE_FACTOR", "must be greater than 1");
+        check(self.crypto.usage_unused_days > 0, "CRYPTO_KEY_USAGE_UNUSED_DAYS", "must be positive");
+        check(self.crypto.usage_top_callers > 0, "CRYPTO_KEY_USAGE_TOP_CALLERS", "must be positive");
+        check(
+            self.crypto.usage_caller_retention_days >= self.crypto.usage_recent_days,
+            "CRYPTO_KEY_USAGE_CALLER_RETENTION_DAYS",
+            "must be at least CRYPTO_KEY_USAGE_RECENT_DAYS",
+        );
+        check(self.crypto.signing_propagation_secs >= 0, "SIGNING_ROLLOVER_PROPAGATION_SECS", "must not be negative");
+        check(
+            self.crypto.signing_retire_after_secs >= self.tenant_settings.access_token_max_ttl_secs,
+            "SIGNING_ROLLOVER_RETIRE_AFTER_SECS",
+            "must be at least TENANT_ACCESS_TOKEN_MAX_TTL_SECS, or tokens would outlive their key",
+        );
+
+        let tenant = &self.tenant_settings;
+        check(
+            (1..=tenant.rate_limit_max_rpm).contains(&tenant.rate_limit_rpm),
+            "TENANT_RATE_LIMIT_RPM",
+            "must be between 1 and TENANT_RATE_LIMIT_MAX_RPM",
+        );
+        check(
+            tenant.access_token_min_ttl_secs > 0
+                && (tenant.access_token_min_ttl_secs..=tenant.access_token_max_ttl_secs)
+                    .contains(&tenant.access_token_ttl_secs),
+            "TENANT_ACCESS_TOKEN_TTL_SECS",
+            "must be between TENANT_ACCESS_TOKEN_MIN_TTL_SECS (positive) and TENANT_ACCESS_TOKEN_MAX_TTL_SECS",
+        );
+        check(!tenant.purposes.is_empty(), "TENANT_PURPOSES", "must list at least one purpose");
+        check(
+            crate::timezone::parse_zone(&tenant.time_zone).is_ok(),
+            "TENANT_TIME_ZONE",
+            "must be an IANA time zone such as America/Sao_Paulo",
+        );
+        check(
+            tenant.business_hours.parse::<crate::timezone::BusinessHours>().is_ok(),
+            "TENANT_BUSINESS_HOURS",
+            "must look like 'mon-fri 08:00-18:00'",
+        );
+        for purpose in &tenant.purposes {
+            check(
+                !purpose.is_empty() && purpose.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_'),
+                "TENANT_PURPOSES",
+                &format!("'{}' must be lowercase letters, digits and underscores", purpose),
+            );
+        }
+
+        // Zero-length intervals would panic the background loops
+        for (var, value) in [
+            ("AUDIT_SCHEDULER_INTERVAL_SECS", self.audit.scheduler_interval_secs),
+            ("AUDIT_INTEGRITY_INTERVAL_SECS", self.audit.integrity_interval_secs),
+            ("AUDIT_CHAIN_INTERVAL_SECS", self.audit.chain_interval_secs),
+            ("AUDIT_SIEM_INTERVAL_MS", self.audit.siem_interval_ms),
+            ("AUDIT_RETENTION_INTERVAL_SECS", self.audit.retention_interval_secs),
+            ("CRYPTO_KEY_ROTATION_INTERVAL_SECS", self.crypto.key_rotation_interval_secs),
+            ("CRYPTO_KEY_REFRESH_INTERVAL_SECS", self.crypto.key_refresh_interval_secs),
+            ("SOFT_DELETE_PURGE_INTERVAL_SECS", self.soft_delete.purge_interval_secs),
+            ("FLAGS_REFRESH_INTERVAL_SECS", self.flags.refresh_interval_secs),
+            ("EXPERIMENTS_REFRESH_INTERVAL_SECS", self.experiments.refresh_interval_secs),
+            ("MAINTENANCE_REFRESH_INTERVAL_SECS", self.maintenance.refresh_interval_secs),
+            ("DEGRADED_PROBE_INTERVAL_SECS", self.degraded.probe_interval_secs),
+            ("CORRELATION_INTERVAL_SECS", self.correlation.interval_secs),
+            ("UEBA_INTERVAL_SECS", self.ueba.interval_secs),
+            ("DORMANCY_INTERVAL_SECS", self.dormancy.interval_secs),
+            ("EMAIL_DKIM_INTERVAL_SECS", self.email.dkim_interval_secs),
+            ("EMAIL_DNS_TIMEOUT_SECS", self.email.dns_timeout_secs),
+            ("DOMAIN_VERIFICATION_INTERVAL_SECS", self.domain_verification.interval_secs),
+            ("DOMAIN_VERIFICATION_DNS_TIMEOUT_SECS", self.domain_verification.dns_timeout_secs),
+            ("USAGE_INTERVAL_SECS", self.usage.interval_secs),
+            ("CRYPTO_GUARD_SYNC_INTERVAL_SECS", self.crypto_guard.sync_interval_secs),
+            ("CONTAINMENT_REFRESH_INTERVAL_SECS", self.containment.refresh_interval_secs),
+            ("SOAR_DELIVERY_INTERVAL_MS", self.soar.delivery_interval_ms),
+            ("EVENTS_RELAY_INTERVAL_MS", self.events.relay_interval_ms),
+            ("HEALTH_CHECK_TIMEOUT_MS", self.health.check_timeout_ms),
+            ("EXPIRY_INTERVAL_SECS", self.expiry.interval_secs),
+            ("AUTH_REVOCATION_REFRESH_SECS", self.auth.revocation_refresh_secs),
+            ("OUTBOUND_CREDENTIALS_INTERVAL_SECS", self.credentials.interval_secs),
+            ("DELIVERY_TIMEOUT_SECS", self.delivery.timeout_secs),
+            ("DELIVERY_COOLDOWN_SECS", self.delivery.cooldown_secs),
+            ("CAPTCHA_TIMEOUT_SECS", self.captcha.timeout_secs),
+            ("PASSWORD_BREACH_TIMEOUT_SECS", self.password.breach_timeout_secs),
+            ("RATE_LIMIT_TIMEOUT_MS", self.rate_limit.timeout_ms),
+            ("RATE_LIMIT_ESCALATE_WINDOW_SECS", self.rate_limit.escalate_window_secs),
+            ("RATE_LIMIT_ESCALATE_SECS", self.rate_limit.escalate_secs),
+            ("SEAL_TIMEOUT_SECS", self.seal.timeout_secs),
+            ("SEAL_INTERVAL_MS", self.seal.interval_ms),
+            ("THREAT_WINDOW_SECS", self.threats.window_secs),
+            ("THREAT_REFRESH_INTERVAL_SECS", self.threats.refresh_interval_secs),
+            ("RETENTION_INTERVAL_SECS", self.retention.interval_secs),
+            ("MAILBOX_INTERVAL_MS", self.mailbox.interval_ms),
+            ("MAILBOX_WEBHOOK_TIMEOUT_SECS", self.mailbox.timeout_secs),
+            ("API_KEY_REFRESH_INTERVAL_SECS", self.api_keys.refresh_interval_secs),
+        ] {
+            check(value > 0, var, "must be positive");
+        }
+        check(self.audit.integrity_sample_size > 0, "AUDIT_INTEGRITY_SAMPLE_SIZE", "must be positive");
+        check(self.audit.chain_batch_size > 0, "AUDIT_CHAIN_BATCH_SIZE", "must be positive");
+        check(self.audit.siem_batch_size > 0, "AUDIT_SIEM_BATCH_SIZE", "must be positive");
+        check(self.audit.siem_max_lag > 0, "AUDIT_SIEM_MAX_LAG", "must be positive");
+        check(self.audit.retention_days >= 0, "AUDIT_RETENTION_DAYS", "must not be negative");
+        check(self.audit.retention_batch_size > 0, "AUDIT_RETENTION_BATCH_SIZE", "must be positive");
+        check(
+            self.audit.retention_days == 0 || !self.audit.retention_archive || self.audit.export_bucket.is_some(),
+            "AUDIT_RETENTION_ARCHIVE",
+            "needs AUDIT_EXPORT_BUCKET, or set it to false to delete without archiving",
+        );
+        check(self.audit.checkpoint_interval_secs > 0, "AUDIT_CHECKPOINT_INTERVAL_SECS", "must be positive");
+        check(self.privacy.epsilon > 0.0, "DP_EPSILON", "must be positive");
+        check(self.privacy.min_count >= 0, "DP_MIN_COUNT", "must not be negative");
+        check(self.privacy.budget >= self.privacy.epsilon, "DP_EPSILON_BUDGET", "must be at least DP_EPSILON");
+        check(self.bulk.concurrency > 0, "BULK_CONCURRENCY", "must be positive");
+        check(self.correlation.batch_size > 0, "CORRELATION_BATCH_SIZE", "must be positive");
+        check(self.correlation.max_steps > 0, "CORRELATION_MAX_STEPS", "must be positive");
+        check(self.ueba.lookback_days > 0, "UEBA_LOOKBACK_DAYS", "must be positive");
+        check(self.ueba.retention_days > 0, "UEBA_RETENTION_DAYS", "must be positive");
+        check(
+            self.ueba.incident_threshold > 0.0 && self.ueba.incident_threshold <= 100.0,
+            "UEBA_INCIDENT_THRESHOLD",
+            "must be in (0, 100]",
+        );
+        check(self.usage.lookback_weeks > 0, "USAGE_LOOKBACK_WEEKS", "must be positive");
+        check(
+            self.usage.min_weeks > 0 && self.usage.min_weeks <= self.usage.lookback_weeks,
+            "USAGE_MIN_WEEKS",
+            "must be between 1 and USAGE_LOOKBACK_WEEKS",
+        );
+        check(self.usage.threshold_sigma > 0.0, "USAGE_THRESHOLD_SIGMA", "must be positive");
+        check(self.usage.max_tracked > 0, "USAGE_MAX_TRACKED", "must be at least 1");
+        check(
+            self.containment.default_duration_secs > 0
+                && self.containment.default_duration_secs <= self.containment.max_duration_secs,
+            "CONTAINMENT_DEFAULT_DURATION_SECS",
+            "must be between 1 and CONTAINMENT_MAX_DURATION_SECS",
+        );
+        check(self.containment.approval_ttl_secs > 0, "CONTAINMENT_APPROVAL_TTL_SECS", "must be positive");
+        check(self.soar.delivery_batch_size > 0, "SOAR_DELIVERY_BATCH_SIZE", "must be positive");
+        check(self.soar.max_attempts > 0, "SOAR_MAX_ATTEMPTS", "must be positive");
+        check(self.events.relay_batch_size > 0, "EVENTS_RELAY_BATCH_SIZE", "must be positive");
+        check(
+            !self.expiry.lead_days.is_empty() && self.expiry.lead_days.iter().all(|days| *days > 0),
+            "EXPIRY_LEAD_DAYS",
+            "must list one or more positive day counts",
+        );
+        check(self.credentials.retry_secs > 0, "OUTBOUND_CREDENTIALS_RETRY_SECS", "must be positive");
+        check(self.auth.otp_ttl_secs > 0, "AUTH_OTP_TTL_SECS", "must be positive");
+        check((4..=10).contains(&self.auth.otp_length), "AUTH_OTP_LENGTH", "must be between 4 and 10");
+        check(self.auth.otp_max_attempts > 0, "AUTH_OTP_MAX_ATTEMPTS", "must be positive");
+        check(self.auth.session_max_age_secs > 0, "AUTH_SESSION_MAX_AGE_SECS", "must be positive");
+        check(self.auth.session_idle_timeout_secs >= 0, "AUTH_SESSION_IDLE_TIMEOUT_SECS", "must not be negative");
+        check(
+            !self.auth.clearance_levels.is_empty()
+                && self.auth.clearance_levels.iter().enumerate().all(|(i, level)| !self.auth.clearance_levels[..i].contains(level)),
+            "AUTH_CLEARANCE_LEVELS",
+            "must list at least one level, each once",
+        );
+        check(self.delivery.failure_threshold > 0, "DELIVERY_FAILURE_THRESHOLD", "must be positive");
+        let is_provider = |name: &str| self.delivery.providers.iter().any(|(provider, _)| provider == name);
+        for (name, limit) in &self.delivery.rate_limits {
+            check(
+                is_provider(name) && limit.parse::<u32>().is_ok_and(|limit| limit > 0),
+                "DELIVERY_RATE_LIMITS",
+                &format!("'{}' must be a provider with a positive limit", name),
+            );
+        }
+        for (name, cost) in &self.delivery.costs {
+            check(
+                is_provider(name) && cost.parse::<f64>().is_ok_and(|cost| cost >= 0.0),
+                "DELIVERY_COSTS",
+                &format!("'{}' must be a provider with a cost of zero or more", name),
+            );
+        }
+        check((0.0..=1.0).contains(&self.captcha.min_score), "CAPTCHA_MIN_SCORE", "must be between 0 and 1");
+        check(self.captcha.failure_window_secs > 0, "CAPTCHA_FAILURE_WINDOW_SECS", "must be positive");
+        check(self.captcha.failure_threshold > 0, "CAPTCHA_FAILURE_THRESHOLD", "must be positive");
+        check(
+            (0.0..=100.0).contains(&self.captcha.risk_threshold),
+            "CAPTCHA_RISK_THRESHOLD",
+            "must be between 0 and 100",
+        );
+        check(
+            self.seal.signer_url.is_none() || self.seal.bucket.is_some(),
+            "SEAL_SIGNER_URL",
+            "needs SEAL_BUCKET to hold originals and sealed documents",
+        );
+        check(self.seal.max_bytes > 0, "SEAL_MAX_BYTES", "must be positive");
+        check(self.seal.batch_size > 0, "SEAL_BATCH_SIZE", "must be positive");
+        check(self.seal.max_attempts > 0, "SEAL_MAX_ATTEMPTS", "must be positive");
+        check(self.manifests.max_files > 0, "MANIFEST_MAX_FILES", "must be positive");
+        check(self.authz.max_batch > 0, "AUTHZ_MAX_BATCH", "must be positive");
+        check(self.tokenization.max_batch > 0, "TOKENIZATION_MAX_BATCH", "must be positive");
+        let guard = &self.crypto_guard;
+        check(guard.window_secs >= 60, "CRYPTO_GUARD_WINDOW_SECS", "must be at least 60");
+        check(guard.factor >= 1.0, "CRYPTO_GUARD_FACTOR", "must be at least 1");
+        check(guard.min_baseline > 0, "CRYPTO_GUARD_MIN_BASELINE", "must be positive");
+        check(guard.lookback_days > 0, "CRYPTO_GUARD_LOOKBACK_DAYS", "must be positive");
+        check(guard.min_windows > 0, "CRYPTO_GUARD_MIN_WINDOWS", "must be positive");
+        let bulk = &self.bulk_decrypt;
+        check(bulk.threshold > 0, "CRYPTO_BULK_DECRYPT_THRESHOLD", "must be positive");
+        check(
+            bulk.max_batch >= bulk.threshold,
+            "CRYPTO_BULK_DECRYPT_MAX_BATCH",
+            "must be at least CRYPTO_BULK_DECRYPT_THRESHOLD",
+        );
+        check(
+            bulk.max_grant_records > bulk.threshold as i64,
+            "CRYPTO_BULK_GRANT_MAX_RECORDS",
+            "must be more than CRYPTO_BULK_DECRYPT_THRESHOLD",
+        );
+        check(bulk.grant_ttl_secs > 0, "CRYPTO_BULK_GRANT_TTL_SECS", "must be positive");
+        check(bulk.approval_ttl_secs > 0, "CRYPTO_BULK_GRANT_APPROVAL_TTL_SECS", "must be positive");
+        for (name, secret) in &self.webhooks.secrets {
+            check(
+                secret.len() >= MIN_SECRET_BYTES,
+                "WEBHOOK_SIGNING_SECRETS",
+                &format!("'{}' needs secrets of at least {} bytes", name, MIN_SECRET_BYTES),
+            );
+        }
+        check(self.webhooks.tolerance_secs > 0, "WEBHOOK_TOLERANCE_SECS", "must be positive");
+        for (name, secs) in &self.webhooks.tolerances {
+            check(*secs > 0, "WEBHOOK_TOLERANCES", &format!("'{}' must be positive", name));
+        }
+        check(self.plugins.refresh_interval_secs > 0, "PLUGIN_REFRESH_INTERVAL_SECS", "must be positive");
+        check(self.plugins.max_module_bytes > 0, "PLUGIN_MAX_MODULE_BYTES", "must be positive");
+        check(self.plugins.max_memory_bytes > 0, "PLUGIN_MAX_MEMORY_BYTES", "must be positive");
+        check(self.plugins.max_fuel > 0, "PLUGIN_MAX_FUEL", "must be positive");
+        check(self.plugins.max_output_bytes > 0, "PLUGIN_MAX_OUTPUT_BYTES", "must be positive");
+        check(self.hooks.max_body_bytes > 0, "REQUEST_HOOKS_MAX_BODY_BYTES", "must be positive");
+        for cidr in &self.network.trusted_proxies {
+            if let Err(e) = cidr.parse::<Cidr>() {
+                check(false, "TRUSTED_PROXIES", &e);
+            }
+        }
+        check(
+            CLIENT_IP_HEADERS.contains(&self.network.client_ip_header.as_str()),
+            "CLIENT_IP_HEADER",
+            &format!("must be one of {}", CLIENT_IP_HEADERS.join(", ")),
+        );
+        check(self.network.proxy_protocol_timeout_ms > 0, "PROXY_PROTOCOL_TIMEOUT_MS", "must be positive");
+        check(
+            !self.network.proxy_protocol || !self.network.trusted_proxies.is_empty(),
+            "PROXY_PROTOCOL",
+            "needs TRUSTED_PROXIES naming the load balancers",
+        );
+        let forensics = &self.forensics;
+        for stage in &forensics.stages {
+            check(
+                stage.parse::<crate::pipeline::Stage>().is_ok(),
+                "FORENSICS_STAGES",
+                &format!("unknown stage '{}'", stage),
+            );
+        }
+        check(forensics.max_body_bytes > 0, "FORENSICS_MAX_BODY_BYTES", "must be positive");
+        check(forensics.max_per_minute > 0, "FORENSICS_MAX_PER_MINUTE", "must be positive");
+        check(forensics.retention_days > 0, "FORENSICS_RETENTION_DAYS", "must be positive");
+        check(forensics.queue_size > 0, "FORENSICS_QUEUE_SIZE", "must be positive");
+        check(!forensics.roles.is_empty(), "FORENSICS_ROLES", "must not be empty");
+        check(self.command_log.max_body_bytes > 0, "COMMAND_LOG_MAX_BODY_BYTES", "must be positive");
+        check(!self.command_log.roles.is_empty(), "COMMAND_LOG_ROLES", "must not be empty");
+        check(self.command_log.max_replay > 0, "COMMAND_LOG_MAX_REPLAY", "must be positive");
+        check(!self.break_glass.roles.is_empty(), "BREAK_GLASS_ROLES", "must not be empty");
+        check(self.break_glass.ttl_secs > 0, "BREAK_GLASS_TTL_SECS", "must be positive");
+        check(!self.break_glass.admin_roles.is_empty(), "BREAK_GLASS_ADMIN_ROLES", "must not be empty");
+        check(!self.break_glass.review_roles.is_empty(), "BREAK_GLASS_REVIEW_ROLES", "must not be empty");
+        check(
+            self.ssh_ca.role_principals.iter().all(|(_, logins)| {
+                logins.split(':').all(|login| {
+                    !login.is_empty() && login.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+                })
+            }),
+            "SSH_CA_ROLE_PRINCIPALS",
+            "must map roles to ':'-separated login names",
+        );
+        check(
+            (1..=86400).contains(&self.ssh_ca.user_cert_ttl_secs),
+            "SSH_CA_USER_CERT_TTL_SECS",
+            "must be 1-86400",
+        );
+        check(self.ssh_ca.host_cert_ttl_secs > 0, "SSH_CA_HOST_CERT_TTL_SECS", "must be positive");
+        check(
+            self.ssh_ca.user_extensions.iter().all(|extension| {
+                [
+                    "no-touch-required",
+                    "permit-X11-forwarding",
+                    "permit-agent-forwarding",
+                    "permit-port-forwarding",
+                    "permit-pty",
+                    "permit-user-rc",
+                ]
+                .contains(&extension.as_str())
+            }),
+            "SSH_CA_USER_EXTENSIONS",
+            "must list OpenSSH certificate extensions",
+        );
+        check(!self.ssh_ca.admin_roles.is_empty(), "SSH_CA_ADMIN_ROLES", "must not be empty");
+        check(!self.ssh_ca.host_scope.is_empty(), "SSH_CA_HOST_SCOPE", "must not be empty");
+        check(!self.backups.prefix.trim_matches('/').is_empty(), "BACKUP_PREFIX", "must not be empty");
+        check(self.backups.interval_secs > 0, "BACKUP_INTERVAL_SECS", "must be positive");
+        check(self.backups.drill_interval_secs > 0, "BACKUP_DRILL_INTERVAL_SECS", "must be positive");
+        check(
+            self.backups.max_age_secs > self.backups.interval_secs as i64,
+            "BACKUP_MAX_AGE_SECS",
+            "must exceed BACKUP_INTERVAL_SECS",
+        );
+        check(self.quorum.threshold > 0, "QUORUM_THRESHOLD", "must be positive");
+        check(self.quorum.window_secs > 0, "QUORUM_WINDOW_SECS", "must be positive");
+        check(!self.quorum.publish_scope.is_empty(), "QUORUM_PUBLISH_SCOPE", "must not be empty");
+        check(!self.quorum.sign_scope.is_empty(), "QUORUM_SIGN_SCOPE", "must not be empty");
+        check(!self.submissions.manage_scope.is_empty(), "SUBMISSION_MANAGE_SCOPE", "must not be empty");
+        check(!self.submissions.submit_scope.is_empty(), "SUBMISSION_SUBMIT_SCOPE", "must not be empty");
+        check(!self.collusion.analyze_scope.is_empty(), "COLLUSION_ANALYZE_SCOPE", "must not be empty");
+        check(
+            self.collusion.threshold > 0.0 && self.collusion.threshold <= 1.0,
+            "COLLUSION_THRESHOLD",
+            "must be in (0, 1]",
+        );
+        for (kind, weight) in &self.collusion.weights {
+            check(
+                crate::collusion::KINDS.iter().any(|(name, _)| *name == kind.as_str()),
+                "COLLUSION_WEIGHTS",
+                &format!("'{}' is not an identifier kind", kind),
+            );
+            check((0.0..=1.0).contains(weight), "COLLUSION_WEIGHTS", &format!("weight for '{}' must be in [0, 1]", kind));
+        }
+        check(self.collusion.max_bids >= 2, "COLLUSION_MAX_BIDS", "must be at least 2");
+        check(!self.dossiers.export_scope.is_empty(), "DOSSIER_EXPORT_SCOPE", "must not be empty");
+        check(self.dossiers.max_events > 0, "DOSSIER_MAX_EVENTS", "must be positive");
+        check(!self.residency.home_region.is_empty(), "RESIDENCY_HOME_REGION", "must not be empty");
+        for (region, bucket) in &self.residency.regions {
+            check(
+                *region != self.residency.home_region,
+                "RESIDENCY_REGIONS",
+                &format!("'{}' is the home region, whose buckets are the service's own", region),
+            );
+            check(!bucket.is_empty(), "RESIDENCY_REGIONS", &format!("'{}' needs a bucket", region));
+        }
+        check(self.key_archive.after_days >= 0, "KEY_ARCHIVE_AFTER_DAYS", "must not be negative");
+        // The delay is the window to notice a rogue restore; it must not be waived
+        check(self.key_archive.restore_delay_secs >= 3600, "KEY_ARCHIVE_RESTORE_DELAY_SECS", "must be at least 3600");
+        check(self.key_archive.restore_window_secs > 0, "KEY_ARCHIVE_RESTORE_WINDOW_SECS", "must be positive");
+        check(self.key_archive.interval_secs > 0, "KEY_ARCHIVE_INTERVAL_SECS", "must be positive");
+        check((1.0..=8.0).contains(&self.rng.min_entropy_bits), "RNG_MIN_ENTROPY_BITS", "must be in [1, 8]");
+        check(self.rng.jitter_reseed_secs > 0, "RNG_JITTER_RESEED_SECS", "must be positive");
+        check(self.rng.interval_secs > 0, "RNG_HEALTH_INTERVAL_SECS", "must be positive");
+        for (meter, weight) in &self.billing.unit_weights {
+            check(
+                crate::billing::Meter::parse(meter).is_some(),
+                "BILLING_UNIT_WEIGHTS",
+                &format!("'{}' is not aead_bytes, signature_ops, hash_ops or password_hash_ops", meter),
+            );
+            check(*weight >= 0, "BILLING_UNIT_WEIGHTS", &format!("weight of '{}' must not be negative", meter));
+        }
+        check(self.billing.flush_interval_secs > 0, "BILLING_FLUSH_INTERVAL_SECS", "must be positive");
+        check(
+            self.billing.export_delay_secs > self.billing.flush_interval_secs as i64,
+            "BILLING_EXPORT_DELAY_SECS",
+            "must exceed BILLING_FLUSH_INTERVAL_SECS",
+        );
+        check(self.billing.timeout_secs > 0, "BILLING_TIMEOUT_SECS", "must be positive");
+        if let Some(url) = &self.billing.webhook_url {
+            check(has_scheme(url, &["https"]), "BILLING_WEBHOOK_URL", "must be an https URL");
+        }
+        check(self.jobs.result_ttl_secs > 0, "JOBS_RESULT_TTL_SECS", "must be positive");
+        check(self.jobs.max_runtime_secs > 0, "JOBS_MAX_RUNTIME_SECS", "must be positive");
+        check(self.jobs.callback_max_attempts > 0, "JOBS_CALLBACK_MAX_ATTEMPTS", "must be positive");
+        check(self.jobs.interval_secs > 0, "JOBS_INTERVAL_SECS", "must be positive");
+        check(self.jobs.timeout_secs > 0, "JOBS_CALLBACK_TIMEOUT_SECS", "must be positive");
+        let elevation = &self.elevation;
+        for (role, policy) in &elevation.policies {
+            check(
+                crate::auth::elevation::POLICIES.contains(&policy.as_str()),
+                "ELEVATION_ROLES",
+                &format!("policy for '{}' must be auto or manual", role),
+            );
+            // Approvers holding the role only by grant could approve themselves into it
+            check(
+                !elevation.approver_roles.contains(role),
+                "ELEVATION_ROLES",
+                &format!("'{}' is an approver role", role),
+            );
+        }
+        check(elevation.max_duration_secs > 0, "ELEVATION_MAX_DURATION_SECS", "must be positive");
+        check(
+            (1..=elevation.max_duration_secs).contains(&elevation.default_duration_secs),
+            "ELEVATION_DEFAULT_DURATION_SECS",
+            "must be positive and at most ELEVATION_MAX_DURATION_SECS",
+        );
+        check(elevation.pending_ttl_secs > 0, "ELEVATION_PENDING_TTL_SECS", "must be positive");
+        check(!elevation.approver_roles.is_empty(), "ELEVATION_APPROVER_ROLES", "must not be empty");
+        let access_review = &self.access_review;
+        check(access_review.interval_days >= 0, "ACCESS_REVIEW_INTERVAL_DAYS", "must not be negative");
+        check(access_review.deadline_days > 0, "ACCESS_REVIEW_DEADLINE_DAYS", "must be positive");
+        check(
+            access_review.interval_days == 0 || access_review.deadline_days < access_review.interval_days,
+            "ACCESS_REVIEW_DEADLINE_DAYS",
+            "must be shorter than ACCESS_REVIEW_INTERVAL_DAYS",
+        );
+        check(!access_review.admin_roles.is_empty(), "ACCESS_REVIEW_ADMIN_ROLES", "must not be empty");
+        check(!access_review.fallback_roles.is_empty(), "ACCESS_REVIEW_FALLBACK_ROLES", "must not be empty");
+        let dormancy = &self.dormancy;
+        for (days_var, days, action_var, action) in [
+            ("DORMANCY_API_KEY_DAYS", dormancy.api_key_days, "DORMANCY_API_KEY_ACTION", &dormancy.api_key_action),
+            ("DORMANCY_USER_DAYS", dormancy.user_days, "DORMANCY_USER_ACTION", &dormancy.user_action),
+            ("DORMANCY_SESSION_IDLE_DAYS", dormancy.session_idle_days, "DORMANCY_SESSION_ACTION", &dormancy.session_action),
+        ] {
+            check(days >= 0, days_var, "must not be negative");
+            check(
+                crate::auth::dormancy::ACTIONS.contains(&action.as_str()),
+                action_var,
+                "must be notify, disable or delete",
+            );
+        }
+        check(!self.org.sync_scope.is_empty(), "ORG_SYNC_SCOPE", "must not be empty");
+        check(!self.resource_registry.scope.is_empty(), "RESOURCE_REGISTRY_SCOPE", "must not be empty");
+        check(self.resource_registry.max_cached > 0, "RESOURCE_REGISTRY_MAX_CACHED", "must be at least 1");
+        check(self.resource_registry.max_batch > 0, "RESOURCE_REGISTRY_MAX_BATCH", "must be at least 1");
+        check(
+            self.tenant_settings.locales.contains(&self.tenant_settings.locale),
+            "TENANT_LOCALE",
+            "must be one of TENANT_LOCALES",
+        );
+        check(
+            ["none", "info", "low", "medium", "high", "critical"].contains(&self.status_page.incident_severity.as_str()),
+            "STATUS_INCIDENT_SEVERITY",
+            "must be none, info, low, medium, high or critical",
+        );
+        check(self.status_page.rate_limit_rpm > 0, "STATUS_RATE_LIMIT_RPM", "must be positive");
+        for (name, key) in &self.bundles.signers {
+            check(
+                base64::decode(key).is_ok_and(|key| key.len() == 32),
+                "CONFIG_BUNDLE_SIGNERS",
+                &format!("'{}' must be a base64 Ed25519 public key", name),
+            );
+        }
+        check(
+            self.bundles.file.is_none() || !self.bundles.signers.is_empty(),
+            "CONFIG_BUNDLE_FILE",
+            "needs CONFIG_BUNDLE_SIGNERS",
+        );
+        check(self.bundles.poll_interval_secs > 0, "CONFIG_BUNDLE_POLL_SECS", "must be positive");
+        check(self.bundles.max_bytes > 0, "CONFIG_BUNDLE_MAX_BYTES", "must be positive");
+        check(
+            ["negotiate", "always"].contains(&self.problem.mode.as_str()),
+            "PROBLEM_DETAILS",
+            "must be negotiate or always",
+        );
+        check(self.search.min_query_len > 0, "SEARCH_MIN_QUERY_LEN", "must be at least 1");
+        check(self.search.max_results > 0, "SEARCH_MAX_RESULTS", "must be at least 1");
+        check((0.0..=1.0).contains(&self.search.fuzzy_threshold), "SEARCH_FUZZY_THRESHOLD", "must be between 0 and 1");
+        check(
+            ["exact", "hour", "day"].contains(&self.whistleblower.received_precision.as_str()),
+            "WHISTLEBLOWER_RECEIVED_PRECISION",
+            "must be exact, hour or day",
+        );
+        // Reports must stay out of reach of admins who were not designated
+        check(
+            !self.whistleblower.role.is_empty() && !self.auth.admin_roles.contains(&self.whistleblower.role),
+            "WHISTLEBLOWER_ROLE",
+            "must be set and not one of SECURITY_ADMIN_ROLES",
+        );
+        check(self.whistleblower.access_ttl_secs > 0, "WHISTLEBLOWER_ACCESS_TTL_SECS", "must be positive");
+        check(self.whistleblower.max_bytes > 0, "WHISTLEBLOWER_MAX_BYTES", "must be positive");
+        check(
+            (1..=self.mailbox.max_ttl_secs).contains(&self.mailbox.default_ttl_secs),
+            "MAILBOX_DEFAULT_TTL_SECS",
+            "must be between 1 and MAILBOX_MAX_TTL_SECS",
+        );
+        check(self.mailbox.max_bytes > 0, "MAILBOX_MAX_BYTES", "must be positive");
+        check(self.mailbox.batch_size > 0, "MAILBOX_BATCH_SIZE", "must be positive");
+        check(self.mailbox.max_attempts > 0, "MAILBOX_WEBHOOK_MAX_ATTEMPTS", "must be positive");
+        check(self.retention.batch_size > 0, "RETENTION_BATCH_SIZE", "must be positive");
+        check(
+            self.retention.run_at.iter().all(|at| chrono::NaiveTime::parse_from_str(at, "%H:%M").is_ok()),
+            "RETENTION_RUN_AT",
+            "must be a time of day such as 03:00",
+        );
+        for (data_type, days) in &self.retention.policies {
+            check(
+                crate::retention::DataType::parse(data_type).is_some(),
+                "RETENTION_POLICIES",
+                &format!("unknown data type '{}'", data_type),
+            );
+            check(*days > 0, "RETENTION_POLICIES", &format!("days for '{}' must be positive", data_type));
+        }
+        check(!self.service_accounts.audiences.is_empty(), "SERVICE_ACCOUNT_AUDIENCES", "must not be empty");
+        check(
+            self.service_accounts.assertion_max_lifetime_secs > 0,
+            "SERVICE_ACCOUNT_ASSERTION_MAX_LIFETIME_SECS",
+            "must be positive",
+        );
+        // Admin roles must never be reachable through a key pair
+        check(
+            !self.auth.admin_roles.contains(&self.service_accounts.role),
+            "SERVICE_ACCOUNT_ROLE",
+            "must not be one of SECURITY_ADMIN_ROLES",
+        );
+        check(self.service_accounts.max_keys > 0, "SERVICE_ACCOUNT_MAX_KEYS", "must be positive");
+        check(self.api_keys.max_lifetime_days > 0, "API_KEY_MAX_LIFETIME_DAYS", "must be positive");
+        check(
+            (0..=self.api_keys.max_rotation_overlap_secs).contains(&self.api_keys.rotation_overlap_secs),
+            "API_KEY_ROTATION_OVERLAP_SECS",
+            "must be between 0 and API_KEY_MAX_ROTATION_OVERLAP_SECS",
+        );
+        for (prefix, scope) in &self.middleware.route_scopes {
+            check(
+                prefix.starts_with('/') && !scope.is_empty(),
+                "MIDDLEWARE_ROUTE_SCOPES",
+                &format!("'{}={}' must map a path prefix to a scope", prefix, scope),
+            );
+        }
+        for origin in &self.middleware.cors_origins {
+            check(
+                has_scheme(origin, &["http", "https"]),
+                "CORS_ALLOWED_ORIGINS",
+                &format!("'{}' must be an http(s) origin", origin),
+            );
+        }
+
+        problems
+    }
+}
+
+/// The running configuration, with the reloadable settings as last loaded.
+pub struct LiveConfig {
+    current: RwLock<Arc<Config>>,
+}
+
+impl LiveConfig {
+    pub fn new(config: Config) -> Self {
+        Self { current: RwLock::new(Arc::new(config)) }
+    }
+
+    pub fn current(&self) -> Arc<Config> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Load and validate the configuration again, then swap in the current
+    /// one with its reloadable settings replaced. Everything else, secrets
+    /// included, is kept as resolved at startup. Returns the variables that
+    /// changed.
+    pub fn reload(&self) -> Result<Vec<&'static str>, SecurityError> {
+        let loaded = Config::from_env()?;
+        let mut current = self.current.write().unwrap();
+        let mut config = Config::clone(&current);
+        let mut changed = Vec::new();
+        let mut note = |var: &'static str, changes: bool| {
+            if changes {
+                changed.push(var);
+            }
+        };
+        note("RATE_LIMIT_ROUTES", config.rate_limit.routes != loaded.rate_limit.routes);
+        note(
+            "RATE_LIMIT_DEFAULT_ALGORITHM",
+            config.rate_limit.default_algorithm != loaded.rate_limit.default_algorithm,
+        );
+        note("CORS_ALLOWED_ORIGINS", config.middleware.cors_origins != loaded.middleware.cors_origins);
+        note(
+            "CRYPTO_KEY_ROTATION_INTERVAL_SECS",
+            config.crypto.key_rotation_interval_secs != loaded.crypto.key_rotation_interval_secs,
+        );
+        config.rate_limit.routes = loaded.rate_limit.routes;
+        config.rate_limit.default_algorithm = loaded.rate_limit.default_algorithm;
+        config.middleware.cors_origins = loaded.middleware.cors_origins;
+        config.crypto.key_rotation_interval_secs = loaded.crypto.key_rotation_interval_secs;
+        *current = Arc::new(config);
+        Ok(changed)
+    }
+}
+
+fn modified_at(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Reload on `SIGHUP`, and when `CONFIG_FILE` changes.
+pub async fn run_reload(state: web::Data<AppState>) {
+    let settings = state.config.reload.clone();
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => Some(hangups),
+        Err(e) => {
+            warn!("Not reloading configuration on SIGHUP: {}", e);
+            None
+        }
+    };
+    let watching = settings.file.is_some() && settings.interval_secs > 0;
+    let mut modified = settings.file.as_deref().and_then(modified_at);
+    let mut interval = tokio::time::interval(Duration::from_secs(settings.interval_secs.max(1)));
+
+    loop {
+        tokio::select! {
+            Some(()) = async { hangups.as_mut()?.recv().await } => {
+                info!("SIGHUP received, reloading configuration");
+            }
+            _ = interval.tick(), if watching => {
+                let now = settings.file.as_deref().and_then(modified_at);
+                if now == modified {
+                    continue;
+                }
+                modified = now;
+                info!("CONFIG_FILE changed, reloading configuration");
+            }
+            else => return,
+        }
+        reload(&state).await;
+    }
+}
+
+async fn reload(state: &AppState) {
+    let changed = match state.live_config.reload() {
+        Ok(changed) => changed,
+        Err(e) => {
+            error!("Configuration not reloaded, keeping the running one: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = state.rate_limiter.reload(&state.live_config.current()) {
+        error!("Rate limits not reloaded: {}", e);
+    }
+    if changed.is_empty() {
+        info!("Configuration reloaded, no reloadable setting changed");
+        return;
+    }
+    info!("Configuration reloaded: {} changed", changed.join(", "));
+    let recorded = state.audit_service.record(crate::audit::NewAuditEvent {
+        tenant_id: None,
+        actor: "system:config".to_string(),
+        actor_ip: None,
+        action: "config.reload".to_string(),
+        resource: "config".to_string(),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({ "changed": changed }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit configuration reload: {:?}", e);
+    }
+}
+
+fn has_scheme(url: &str, schemes: &[&str]) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|url| schemes.contains(&url.scheme()))
+}
+
+fn report(problems: Vec<String>) -> Result<(), SecurityError> {
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(SecurityError::ConfigError(format!(
+        "{} problem(s):\n  - {}",
+        problems.len(),
+        problems.join("\n  - ")
+    )))
+}
+
+/// Reads variables, collecting problems instead of stopping at the first.
+/// A bad value is replaced by its default so loading can carry on.
+#[derive(Default)]
+struct Vars {
+    problems: Vec<String>,
+    sources: ConfigSources,
+}
+
+impl Vars {
+    /// A secret from `name`, or read from the file `name_FILE` points at.
+    /// Setting both is an error. `kms://` and `vault://` values are returned
+    /// as-is for `secrets` to resolve.
+    fn secret_var(&mut self, name: &'static str) -> Option<String> {
+        let file_var = format!("{}_FILE", name);
+        match (var(name), var(&file_var)) {
+            (Ok(_), Ok(_)) => {
+                self.problems.push(format!("{}: set only one of {} and {}", name, name, file_var));
+                None
+            }
+            (Ok(value), Err(_)) => Some(value),
+            (Err(_), Ok(path)) => match fs::read_to_string(&path) {
+                Ok(value) => {
+                    self.sources.record(name, format!("{} ({})", file_var, path));
+                    Some(value.trim_end_matches(['\r', '\n']).to_string())
+                }
+                Err(e) => {
+                    self.problems.push(format!("{} ({}): cannot be read: {}", file_var, path, e));
+                    None
+                }
+            },
+            (Err(_), Err(_)) => None,
+        }
+    }
+
+    fn required_secret(&mut self, name: &'static str) -> String {
+        let reported = self.problems.len();
+        let value = self.secret_var(name);
+        if value.is_none() && self.problems.len() == reported {
+            self.problems.push(format!("{}: not set (set {} or {}_FILE)", name, name, name));
+        }
+        value.unwrap_or_default()
+    }
+
+    fn parse_or<T: FromStr>(&mut self, name: &str, default: T) -> T {
+        match var(name) {
+            Ok(value) => value.parse().unwrap_or_else(|_| {
+                self.problems.push(format!(
+                    "{}: '{}' is not a valid {}",
+                    name,
+                    value,
+                    std::any::type_name::<T>()
+                ));
+                default
+            }),
+            Err(_) => default,
+        }
+    }
+
+    /// Parse comma-separated lists of `T`.
+    fn parse_list_or<T: FromStr + Clone>(&mut self, name: &str, default: &[T]) -> Vec<T> {
+        if var(name).is_err() {
+            return default.to_vec();
+        }
+        list_or(name, &[])
+            .into_iter()
+            .filter_map(|entry| match entry.parse() {
+                Ok(value) => Some(value),
+                Err(_) => {
+                    self.problems.push(format!(
+                        "{}: '{}' is not a valid {}",
+                        name,
+                        entry,
+                        std::any::type_name::<T>()
+                    ));
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Parse `name=value,name2=value2` lists.
+    fn pairs_or(&mut self, name: &str) -> Vec<(String, String)> {
+        let entries = list_or(name, &[]);
+        self.pairs(name, entries, false)
+    }
+
+    /// `pairs_or` with values parsed as `T`.
+    fn parse_pairs_or<T: FromStr>(&mut self, name: &str) -> Vec<(String, T)> {
+        self.pairs_or(name)
+            .into_iter()
+            .filter_map(|(key, value)| match value.parse() {
+                Ok(parsed) => Some((key, parsed)),
+                Err(_) => {
+                    self.problems.push(format!(
+                        "{}: '{}' for '{}' is not a valid {}",
+                        name,
+                        value,
+                        key,
+                        std::any::type_name::<T>()
+                    ));
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// `pairs_or` for a list of secrets, which may also come from `NAME_FILE`.
+    fn secret_pairs(&mut self, name: &'static str) -> Vec<(String, String)> {
+        let entries = self
+            .secret_var(name)
+            .map(|value| value.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+        self.pairs(name, entries, true)
+    }
+
+    fn pairs(&mut self, name: &str, entries: Vec<String>, secret: bool) -> Vec<(String, String)> {
+        entries
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, entry)| match entry.split_once('=') {
+                Some((k, v)) => Some((k.trim().to_string(), v.trim().to_string())),
+                None if secret => {
+                    self.problems.push(format!("{}: entry {} must be name=value", name, i + 1));
+                    None
+                }
+                None => {
+                    self.problems.push(format!("{}: entry '{}' must be name=value", name, entry));
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+thread_local! {
+    /// `CONFIG_FILE` entries while `from_env` runs.
+    static FILE_VARS: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+}
+
+/// A variable from `CONFIG_FILE`, or else the environment.
+fn var(name: &str) -> Result<String, env::VarError> {
+    match FILE_VARS.with(|vars| vars.borrow().get(name).cloned()) {
+        Some(value) => Ok(value),
+        None => env::var(name),
+    }
+}
+
+/// `NAME=value` lines; blank lines and `#` comments are skipped.
+fn read_config_file(path: &str) -> Result<HashMap<String, String>, SecurityError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| SecurityError::ConfigError(format!("CONFIG_FILE ({}): cannot be read: {}", path, e)))?;
+    let mut vars = HashMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((name, value)) = line.split_once('=') else {
+            return Err(SecurityError::ConfigError(format!(
+                "CONFIG_FILE ({}): line {} must be NAME=value",
+                path,
+                i + 1
+            )));
+        };
+        vars.insert(name.trim().to_string(), value.trim().to_string());
+    }
+    Ok(vars)
+}
+
+fn env_or(name: &str, default: &str) -> String {
+    var(name).unwrap_or_else(|_| default.to_string())
+}
+
+fn list_or(name: &str, default: &[&str]) -> Vec<String> {
+    match var(name) {
+        Ok(value) => value
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect(),
+        Err(_) => default.iter().map(|s| s.to_string()).collect(),
+    }
+}
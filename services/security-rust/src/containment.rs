@@ -0,0 +1,891 @@
+/*!
+Containment Module
+Playbooks that respond automatically to detection incidents
+
+A playbook names the incident rules and minimum severity it reacts to, and
+the actions to take against the incident's entity or its tenant:
+
+| action               | target            | carried out by                              |
+|----------------------|-------------------|---------------------------------------------|
+| `revoke_sessions`    | actor             | this service, rejecting the actor's tokens  |
+| `block`              | actor, IP, tenant | this service, rejecting matching requests   |
+| `disable_api_keys`   | actor             | the key owner, from the outbox event        |
+| `quarantine_uploads` | tenant            | the upload service, from the outbox event   |
+
+Every action lasts a bounded time and is published as `containment.applied`,
+then `containment.rolled_back` or `containment.expired`, so services owning
+the resource can act on and undo it. Playbooks marked `requires_approval`
+only propose their actions; an admin approves or rejects each one, and
+proposals lapse after `CONTAINMENT_APPROVAL_TTL_SECS`. Actions already open
+against the same target are not repeated, and subjects listed in
+`CONTAINMENT_PROTECTED_SUBJECTS` are never acted against.
+
+Access tokens carry no issue time, so revoking sessions holds the actor's
+tokens until the action expires; it should outlast the access token TTL.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{FromRow, Postgres, QueryBuilder, Transaction};
+use std::sync::{Arc, RwLock};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::alerting::Severity;
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, Principal};
+use crate::changes::{self, NewChange};
+use crate::clock::Clock;
+use crate::config::{Config, ContainmentConfig};
+use crate::detection::Incident;
+use crate::errors::SecurityError;
+use crate::events::{self, DomainEvent};
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::Storage;
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "created_at", column: "created_at", kind: KeyKind::Timestamp },
+];
+
+const PLAYBOOK_COLUMNS: &str = "name, definition, version, updated_by, updated_at";
+
+const ACTION_COLUMNS: &str = "id, playbook, incident_id, action, target_type, target, tenant_id, status, \
+    duration_secs, expires_at, created_at, decided_by, decided_at, rolled_back_by, rolled_back_at";
+
+/// Who automatic actions are audited as.
+const SYSTEM_ACTOR: &str = "system:containment";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionKind {
+    RevokeSessions,
+    DisableApiKeys,
+    QuarantineUploads,
+    Block,
+}
+
+impl ActionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActionKind::RevokeSessions => "revoke_sessions",
+            ActionKind::DisableApiKeys => "disable_api_keys",
+            ActionKind::QuarantineUploads => "quarantine_uploads",
+            ActionKind::Block => "block",
+        }
+    }
+
+    fn applies_to(&self, target_type: &str) -> bool {
+        match self {
+            ActionKind::RevokeSessions | ActionKind::DisableApiKeys => target_type == "actor",
+            ActionKind::QuarantineUploads => target_type == "tenant_id",
+            ActionKind::Block => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    /// What the incident is about: an actor, source IP or tenant.
+    #[default]
+    Entity,
+    /// The incident's tenant.
+    Tenant,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlaybookAction {
+    pub action: ActionKind,
+    #[serde(default)]
+    pub target: Target,
+    pub duration_secs: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlaybookDefinition {
+    pub description: Option<String>,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    /// Incident rules it reacts to, e.g. a correlation rule name or `ueba`;
+    /// empty reacts to every rule.
+    #[serde(default)]
+    pub rules: Vec<String>,
+    pub min_severity: Severity,
+    #[serde(default)]
+    pub requires_approval: bool,
+    pub actions: Vec<PlaybookAction>,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Playbook {
+    pub name: String,
+    pub definition: Json<PlaybookDefinition>,
+    pub version: i64,
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ContainmentAction {
+    pub id: Uuid,
+    pub playbook: String,
+    pub incident_id: Uuid,
+    pub action: String,
+    pub target_type: String,
+    pub target: String,
+    pub tenant_id: Option<String>,
+    /// `pending_approval`, `active`, `rejected`, `rolled_back` or `expired`.
+    pub status: String,
+    pub duration_secs: i64,
+    /// Set once the action is in force.
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub rolled_back_by: Option<String>,
+    pub rolled_back_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ActionFilter {
+    pub status: Option<String>,
+    pub target: Option<String>,
+    pub incident_id: Option<Uuid>,
+}
+
+/// Actions this service enforces itself, checked on every authentication.
+#[derive(Default)]
+pub struct Denylist {
+    holds: RwLock<Vec<ContainmentAction>>,
+}
+
+impl Denylist {
+    fn replace(&self, active: Vec<ContainmentAction>) {
+        let holds = active
+            .into_iter()
+            .filter(|a| a.action == ActionKind::RevokeSessions.as_str() || a.action == ActionKind::Block.as_str())
+            .collect();
+        *self.holds.write().unwrap_or_else(|e| e.into_inner()) = holds;
+    }
+
+    /// Reject a caller held by an active action.
+    pub fn check(&self, principal: &Principal, ip: Option<&str>, now: DateTime<Utc>) -> Result<(), SecurityError> {
+        let holds = self.holds.read().unwrap_or_else(|e| e.into_inner());
+        let held = holds.iter().find(|hold| {
+            hold.expires_at.is_some_and(|at| at > now)
+                && match hold.target_type.as_str() {
+                    "actor" => hold.target == principal.subject,
+                    "tenant_id" => principal.tenant_id.as_deref() == Some(hold.target.as_str()),
+                    "actor_ip" => ip == Some(hold.target.as_str()),
+                    _ => false,
+                }
+        });
+        match held {
+            Some(hold) if hold.action == ActionKind::RevokeSessions.as_str() => {
+                Err(SecurityError::AuthError("Session revoked".to_string()))
+            }
+            Some(_) => Err(SecurityError::AuthError("Access blocked".to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+fn validate(name: &str, definition: &PlaybookDefinition, config: &ContainmentConfig) -> Result<(), SecurityError> {
+    let mut problems = Vec::new();
+
+    let valid_name = !name.is_empty()
+        && name.len() <= 100
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !valid_name {
+        problems.push("name must be 1-100 characters of letters, digits, '_', '-' or '.'".to_string());
+    }
+    if definition.actions.is_empty() {
+        problems.push("a playbook needs at least one action".to_string());
+    }
+    for (i, action) in definition.actions.iter().enumerate() {
+        if let Some(secs) = action.duration_secs {
+            if secs <= 0 || secs > config.max_duration_secs {
+                problems.push(format!("action {}: duration_secs must be 1-{}", i + 1, config.max_duration_secs));
+            }
+        }
+        if action.target == Target::Tenant && !action.action.applies_to("tenant_id") {
+            problems.push(format!("action {}: {} cannot target a tenant", i + 1, action.action.as_str()));
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(SecurityError::ValidationError(problems.join("; ")));
+    }
+    Ok(())
+}
+
+fn severity_of(incident: &Incident) -> Option<Severity> {
+    serde_json::from_value(serde_json::Value::String(incident.severity.clone())).ok()
+}
+
+pub struct ContainmentService {
+    storage: Storage,
+    config: ContainmentConfig,
+    denylist: Arc<Denylist>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ContainmentService {
+    pub async fn new(
+        config: &Config,
+        storage: Storage,
+        denylist: Arc<Denylist>,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, SecurityError> {
+
+        info!("Containment service initialized successfully");
+        Ok(Self {
+            storage,
+            config: config.containment.clone(),
+            denylist,
+            clock,
+        })
+    }
+
+    /// Reload the actions in force into the denylist.
+    pub async fn refresh(&self) -> Result<(), SecurityError> {
+        let active = sqlx::query_as::<_, ContainmentAction>(&format!(
+            "SELECT {} FROM containment_actions WHERE status = 'active' AND expires_at > NOW()",
+            ACTION_COLUMNS
+        ))
+        .fetch_all(self.storage.pool())
+        .await?;
+        self.denylist.replace(active);
+        Ok(())
+    }
+
+    /// Queue the playbook actions an incident triggers, inside the
+    /// transaction that opens it.
+    pub async fn plan(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        incident: &Incident,
+    ) -> Result<Vec<ContainmentAction>, SecurityError> {
+        let Some(severity) = severity_of(incident) else {
+            return Ok(Vec::new());
+        };
+        let playbooks = sqlx::query_as::<_, Playbook>(&format!(
+            "SELECT {} FROM playbooks ORDER BY name",
+            PLAYBOOK_COLUMNS
+        ))
+        .fetch_all(&mut **tx)
+        .await?;
+
+        let now = self.clock.now();
+        let mut planned = Vec::new();
+        for playbook in playbooks {
+            let definition = &playbook.definition.0;
+            let triggered = definition.enabled
+                && severity >= definition.min_severity
+                && (definition.rules.is_empty() || definition.rules.contains(&incident.rule));
+            if !triggered {
+                continue;
+            }
+
+            for step in &definition.actions {
+                let (target_type, target) = match step.target {
+                    Target::Entity => (incident.entity_type.as_str(), incident.entity.clone()),
+                    Target::Tenant => match &incident.tenant_id {
+                        Some(tenant_id) => ("tenant_id", tenant_id.clone()),
+                        None => continue,
+                    },
+                };
+                if !step.action.applies_to(target_type) {
+                    continue;
+                }
+                if target_type == "actor" && self.config.protected_subjects.contains(&target) {
+                    warn!("Playbook {} skipped {} against protected subject {}", playbook.name, step.action.as_str(), target);
+                    continue;
+                }
+
+                let duration_secs = step.duration_secs.unwrap_or(self.config.default_duration_secs);
+                let (status, expires_at) = if definition.requires_approval {
+                    ("pending_approval", None)
+                } else {
+                    ("active", Some(now + Duration::seconds(duration_secs)))
+                };
+
+                // An action already open against the target is not repeated
+                let action = sqlx::query_as::<_, ContainmentAction>(&format!(
+                    "INSERT INTO containment_actions \
+                     (id, playbook, incident_id, action, target_type, target, tenant_id, status, duration_secs, expires_at) \
+                     SELECT $1, $2, $3, $4, $5, $6, $7, $8, $9, $10 \
+                     WHERE NOT EXISTS (SELECT 1 FROM containment_actions WHERE action = $4 AND target_type = $5 \
+                     AND target = $6 AND status IN ('pending_approval', 'active')) \
+                     RETURNING {}",
+                    ACTION_COLUMNS
+                ))
+                .bind(Uuid::new_v4())
+                .bind(&playbook.name)
+                .bind(incident.id)
+                .bind(step.action.as_str())
+                .bind(target_type)
+                .bind(&target)
+                .bind(&incident.tenant_id)
+                .bind(status)
+                .bind(duration_secs)
+                .bind(expires_at)
+                .fetch_optional(&mut **tx)
+                .await?;
+
+                if let Some(action) = action {
+                    if action.status == "active" {
+                        publish(tx, "containment.applied", &action, now).await?;
+                    }
+                    planned.push(action);
+                }
+            }
+        }
+
+        Ok(planned)
+    }
+
+    /// Audit what `plan` did once its transaction has committed, and put
+    /// new holds in force on this instance without waiting for a refresh.
+    pub async fn announce(&self, state: &crate::AppState, actions: &[ContainmentAction]) {
+        if actions.is_empty() {
+            return;
+        }
+        for action in actions {
+            let (verb, outcome) = match action.status.as_str() {
+                "active" => ("containment.apply", "success"),
+                _ => ("containment.propose", "pending"),
+            };
+            audit_containment(state, SYSTEM_ACTOR, verb, action, outcome).await;
+            info!("Playbook {} {} {} against {} {}", action.playbook, verb, action.action, action.target_type, action.target);
+        }
+        if let Err(e) = self.refresh().await {
+            warn!("Containment refresh after new actions failed: {:?}", e);
+        }
+    }
+
+    pub async fn list(&self, filter: &ActionFilter, page: &PageRequest) -> Result<Page<ContainmentAction>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM containment_actions WHERE 1 = 1",
+            ACTION_COLUMNS
+        ));
+        if let Some(status) = &filter.status {
+            builder.push(" AND status = ").push_bind(status.clone());
+        }
+        if let Some(target) = &filter.target {
+            builder.push(" AND target = ").push_bind(target.clone());
+        }
+        if let Some(incident_id) = filter.incident_id {
+            builder.push(" AND incident_id = ").push_bind(incident_id);
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let actions = builder
+            .build_query_as::<ContainmentAction>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(actions, |action, _| (SortKey::Timestamp(action.created_at), action.id)))
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<ContainmentAction, SecurityError> {
+        sqlx::query_as::<_, ContainmentAction>(&format!(
+            "SELECT {} FROM containment_actions WHERE id = $1",
+            ACTION_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("Containment action not found".to_string()))
+    }
+
+    /// Apply `assignments` ($2 is the actor, $3 the time) to an action in
+    /// status `from`, publishing `event` if given.
+    async fn transition(
+        &self,
+        id: Uuid,
+        from: &str,
+        assignments: &str,
+        actor: &str,
+        event: Option<&str>,
+    ) -> Result<ContainmentAction, SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let current = sqlx::query_as::<_, ContainmentAction>(&format!(
+            "SELECT {} FROM containment_actions WHERE id = $1 FOR UPDATE",
+            ACTION_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("Containment action not found".to_string()))?;
+        if current.status != from {
+            return Err(SecurityError::Conflict(format!(
+                "Action is {}, not {}",
+                current.status, from
+            )));
+        }
+
+        let action = sqlx::query_as::<_, ContainmentAction>(&format!(
+            "UPDATE containment_actions SET {} WHERE id = $1 RETURNING {}",
+            assignments, ACTION_COLUMNS
+        ))
+        .bind(id)
+        .bind(actor)
+        .bind(self.clock.now())
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if let Some(event) = event {
+            publish(&mut tx, event, &action, self.clock.now()).await?;
+        }
+        tx.commit().await?;
+
+        if let Err(e) = self.refresh().await {
+            warn!("Containment refresh after {} failed: {:?}", id, e);
+        }
+        Ok(action)
+    }
+
+    pub async fn approve(&self, actor: &Principal, id: Uuid) -> Result<ContainmentAction, SecurityError> {
+        self.transition(
+            id,
+            "pending_approval",
+            "status = 'active', decided_by = $2, decided_at = $3, \
+             expires_at = $3 + duration_secs * INTERVAL '1 second'",
+            &actor.subject,
+            Some("containment.applied"),
+        )
+        .await
+    }
+
+    pub async fn reject(&self, actor: &Principal, id: Uuid) -> Result<ContainmentAction, SecurityError> {
+        self.transition(
+            id,
+            "pending_approval",
+            "status = 'rejected', decided_by = $2, decided_at = $3",
+            &actor.subject,
+            None,
+        )
+        .await
+    }
+
+    pub async fn rollback(&self, actor: &Principal, id: Uuid) -> Result<ContainmentAction, SecurityError> {
+        self.transition(
+            id,
+            "active",
+            "status = 'rolled_back', rolled_back_by = $2, rolled_back_at = $3",
+            &actor.subject,
+            Some("containment.rolled_back"),
+        )
+        .await
+    }
+
+    /// End actions past their expiry and lapse stale proposals.
+    pub async fn expire(&self) -> Result<usize, SecurityError> {
+        let now = self.clock.now();
+        let mut tx = self.storage.begin().await?;
+
+        let expired = sqlx::query_as::<_, ContainmentAction>(&format!(
+            "UPDATE containment_actions SET status = 'expired' \
+             WHERE (status = 'active' AND expires_at <= $1) \
+             OR (status = 'pending_approval' AND created_at <= $2) \
+             RETURNING {}",
+            ACTION_COLUMNS
+        ))
+        .bind(now)
+        .bind(now - Duration::seconds(self.config.approval_ttl_secs))
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for action in expired.iter().filter(|a| a.expires_at.is_some()) {
+            publish(&mut tx, "containment.expired", action, now).await?;
+        }
+        tx.commit().await?;
+
+        Ok(expired.len())
+    }
+
+    pub async fn list_playbooks(&self) -> Result<Vec<Playbook>, SecurityError> {
+        let playbooks = sqlx::query_as::<_, Playbook>(&format!(
+            "SELECT {} FROM playbooks ORDER BY name",
+            PLAYBOOK_COLUMNS
+        ))
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(playbooks)
+    }
+
+    pub async fn get_playbook(&self, name: &str) -> Result<Playbook, SecurityError> {
+        sqlx::query_as::<_, Playbook>(&format!(
+            "SELECT {} FROM playbooks WHERE name = $1",
+            PLAYBOOK_COLUMNS
+        ))
+        .bind(name)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("Playbook '{}' not found", name)))
+    }
+
+    pub async fn put_playbook(&self, actor: &Principal, name: &str, definition: PlaybookDefinition) -> Result<Playbook, SecurityError> {
+        validate(name, &definition, &self.config)?;
+
+        let mut tx = self.storage.begin().await?;
+        let current = sqlx::query_as::<_, Playbook>(&format!(
+            "SELECT {} FROM playbooks WHERE name = $1 FOR UPDATE",
+            PLAYBOOK_COLUMNS
+        ))
+        .bind(name)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let playbook = sqlx::query_as::<_, Playbook>(&format!(
+            "INSERT INTO playbooks (name, definition, updated_by) VALUES ($1, $2, $3) \
+             ON CONFLICT (name) DO UPDATE SET definition = $2, updated_by = $3, updated_at = NOW(), \
+             version = playbooks.version + 1 \
+             RETURNING {}",
+            PLAYBOOK_COLUMNS
+        ))
+        .bind(name)
+        .bind(Json(&definition))
+        .bind(&actor.subject)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        changes::record(&mut tx, NewChange {
+            resource_type: "playbook",
+            resource_id: name.to_string(),
+            version: Some(playbook.version),
+            action: if current.is_some() { "update" } else { "create" },
+            author: &actor.subject,
+            tenant_id: None,
+            before: current.as_ref().and_then(|p| serde_json::to_value(&p.definition.0).ok()),
+            after: serde_json::to_value(&playbook.definition.0).ok(),
+        }).await?;
+        tx.commit().await?;
+
+        Ok(playbook)
+    }
+
+    pub async fn delete_playbook(&self, actor: &Principal, name: &str) -> Result<(), SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let deleted = sqlx::query_as::<_, Playbook>(&format!(
+            "DELETE FROM playbooks WHERE name = $1 RETURNING {}",
+            PLAYBOOK_COLUMNS
+        ))
+        .bind(name)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("Playbook '{}' not found", name)))?;
+
+        changes::record(&mut tx, NewChange {
+            resource_type: "playbook",
+            resource_id: name.to_string(),
+            version: Some(deleted.version),
+            action: "delete",
+            author: &actor.subject,
+            tenant_id: None,
+            before: serde_json::to_value(&deleted.definition.0).ok(),
+            after: None,
+        }).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+async fn publish(
+    tx: &mut Transaction<'_, Postgres>,
+    event_type: &str,
+    action: &ContainmentAction,
+    now: DateTime<Utc>,
+) -> Result<(), SecurityError> {
+    let event = DomainEvent::new(
+        event_type,
+        "containment",
+        action.id,
+        action.tenant_id.clone(),
+        serde_json::to_value(action).unwrap_or_default(),
+        now,
+    );
+    events::enqueue(tx, &event).await
+}
+
+/// Expire due actions and reload the denylist.
+pub async fn run_refresh(state: web::Data<crate::AppState>) {
+    let interval_secs = state.config.containment.refresh_interval_secs;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+
+    loop {
+        interval.tick().await;
+        match state.containment.expire().await {
+            Ok(0) => {}
+            Ok(count) => info!("Expired {} containment actions", count),
+            Err(e) => error!("Containment expiry failed: {:?}", e),
+        }
+        match state.containment.refresh().await {
+            Ok(()) => state.startup.recovered("containment"),
+            Err(e) => error!("Containment refresh failed: {:?}", e),
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Containment operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Containment operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit_containment(
+    state: &crate::AppState,
+    actor: &str,
+    verb: &str,
+    action: &ContainmentAction,
+    outcome: &str,
+) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: action.tenant_id.clone(),
+        actor: actor.to_string(),
+        actor_ip: None,
+        action: verb.to_string(),
+        resource: format!("containment:{}", action.id),
+        outcome: outcome.to_string(),
+        payload: serde_json::json!({
+            "playbook": action.playbook,
+            "incident_id": action.incident_id,
+            "action": action.action,
+            "target_type": action.target_type,
+            "target": action.target,
+            "expires_at": action.expires_at
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} on containment {}: {:?}", verb, action.id, e);
+    }
+}
+
+async fn audit_playbook_change(state: &crate::AppState, actor: &Principal, action: &str, name: &str, detail: serde_json::Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: actor.tenant_id.clone(),
+        actor: actor.subject.clone(),
+        actor_ip: None,
+        action: action.to_string(),
+        resource: format!("playbook:{}", name),
+        outcome: "success".to_string(),
+        payload: detail,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit playbook change on {}: {:?}", name, e);
+    }
+}
+
+pub async fn list_actions_handler(
+    req: HttpRequest,
+    filter: web::Query<ActionFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.containment.list(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "actions": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_action_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.containment.get(path.into_inner()).await {
+        Ok(action) => Ok(HttpResponse::Ok().json(action)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn approve_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.containment.approve(&principal, path.into_inner()).await {
+        Ok(action) => {
+            audit_containment(&state, &principal.subject, "containment.approve", &action, "success").await;
+            Ok(HttpResponse::Ok().json(action))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn reject_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.containment.reject(&principal, path.into_inner()).await {
+        Ok(action) => {
+            audit_containment(&state, &principal.subject, "containment.reject", &action, "success").await;
+            Ok(HttpResponse::Ok().json(action))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn rollback_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.containment.rollback(&principal, path.into_inner()).await {
+        Ok(action) => {
+            audit_containment(&state, &principal.subject, "containment.rollback", &action, "success").await;
+            Ok(HttpResponse::Ok().json(action))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_playbooks_handler(
+    req: HttpRequest,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.containment.list_playbooks().await {
+        Ok(playbooks) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "playbooks": playbooks
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_playbook_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.containment.get_playbook(&path).await {
+        Ok(playbook) => Ok(HttpResponse::Ok().json(playbook)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn put_playbook_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    definition: web::Json<PlaybookDefinition>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let name = path.into_inner();
+    let definition = definition.into_inner();
+    match state.containment.put_playbook(&principal, &name, definition.clone()).await {
+        Ok(playbook) => {
+            audit_playbook_change(&state, &principal, "playbook.put", &name, serde_json::to_value(&definition).unwrap_or_default()).await;
+            Ok(HttpResponse::Ok().json(playbook))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn delete_playbook_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let name = path.into_inner();
+    match state.containment.delete_playbook(&principal, &name).await {
+        Ok(()) => {
+            audit_playbook_change(&state, &principal, "playbook.delete", &name, serde_json::json!({})).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/playbooks")
+            .route("", web::get().to(list_playbooks_handler))
+            .route("/{name}", web::get().to(get_playbook_handler))
+            .route("/{name}", web::put().to(put_playbook_handler))
+            .route("/{name}", web::delete().to(delete_playbook_handler))
+    )
+    .service(
+        web::scope("/admin/containment")
+            .route("", web::get().to(list_actions_handler))
+            .route("/{id}", web::get().to(get_action_handler))
+            .route("/{id}/approve", web::post().to(approve_handler))
+            .route("/{id}/reject", web::post().to(reject_handler))
+            .route("/{id}/rollback", web::post().to(rollback_handler))
+    );
+}
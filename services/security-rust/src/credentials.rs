@@ -0,0 +1,975 @@
+/*!
+Outbound Credentials
+Encrypted store for the credentials the service presents to others
+
+SIEMs, SMS gateways, timestamp authorities and SOAR platforms each want a
+token, password or signing key. They are kept here, encrypted with the data
+keys and bound to their name, instead of in config files, and managed under
+`/admin/outbound-credentials`; reads never return the secret.
+
+A credential is one of:
+
+- `bearer`: `Authorization: Bearer <token>`
+- `basic`: `Authorization: Basic` from a username and password
+- `header`: any other header, e.g. an API key header
+- `hmac`: a signing key of at least 32 bytes, used by the caller rather
+  than sent
+- `oauth2`: client credentials for `token_url`, sent as the bearer token
+  last fetched with them
+
+Callers inject them by name through `CredentialCache`, which holds the
+decrypted set and is reloaded every `OUTBOUND_CREDENTIALS_INTERVAL_SECS`.
+Alert webhooks use `alerting.<sink>` and SOAR integrations `soar.<name>`,
+falling back to config while none is stored.
+
+Credentials whose API allows it are rotated on schedule by a named
+rotator. Built in:
+
+- `oauth2`: fetches a fresh access token, and again before it expires.
+- `http`: POSTs to `rotation_url` authenticated with the current
+  credential and stores the `secret` of the JSON response as the new
+  token, password, header value, key or client secret.
+
+A credential with a rotator is rotated once `rotation_interval_secs` has
+passed since it was stored or last rotated, or at once without an
+interval. A rotator reporting `expires_in` schedules the next rotation at
+three quarters of it. Failures are retried after
+`OUTBOUND_CREDENTIALS_RETRY_SECS`, audited and alerted on. Hosts can add
+rotators with `SecurityServiceBuilder::credential_rotator`. Credentials
+given an `expires_at` are reported to the expiry scan.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use tracing::{error, info, warn};
+
+use crate::alerting::{Alert, Severity};
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, CredentialsConfig};
+use crate::crypto::{CryptoService, DecryptionRequest, EncryptionRequest};
+use crate::deadline;
+use crate::errors::SecurityError;
+use crate::expiry::{Expiring, ExpiryFuture, ExpiryRegistry};
+use crate::storage::Storage;
+use crate::AppState;
+
+const SELECT_COLUMNS: &str = "name, kind, key_id, version, rotator, rotation_url, rotation_interval_secs, \
+    next_rotation_at, rotated_at, last_error, expires_at, created_by, created_at, updated_at";
+
+const SEALED_COLUMNS: &str = "name, ciphertext, key_id, nonce, context_hash";
+
+/// Shortest HMAC key accepted, as for `SOAR_SECRETS`.
+const MIN_HMAC_KEY_BYTES: usize = 32;
+
+/// Secret material of a credential.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Secret {
+    Bearer {
+        token: String,
+    },
+    Basic {
+        username: String,
+        password: String,
+    },
+    Header {
+        name: String,
+        value: String,
+    },
+    Hmac {
+        key: String,
+    },
+    #[serde(rename = "oauth2")]
+    OAuth2 {
+        token_url: String,
+        client_id: String,
+        client_secret: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        scope: Option<String>,
+        /// Filled in by the `oauth2` rotator.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        access_token: Option<String>,
+    },
+}
+
+// Keeps secrets out of logs and error messages
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({})", self.kind())
+    }
+}
+
+impl Secret {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Secret::Bearer { .. } => "bearer",
+            Secret::Basic { .. } => "basic",
+            Secret::Header { .. } => "header",
+            Secret::Hmac { .. } => "hmac",
+            Secret::OAuth2 { .. } => "oauth2",
+        }
+    }
+
+    /// Add this credential to an outbound request. HMAC keys and OAuth2
+    /// credentials without a token leave it unchanged.
+    pub fn apply(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self {
+            Secret::Bearer { token } => request.bearer_auth(token),
+            Secret::Basic { username, password } => request.basic_auth(username, Some(password)),
+            Secret::Header { name, value } => request.header(name.as_str(), value.as_str()),
+            Secret::Hmac { .. } => request,
+            Secret::OAuth2 { access_token, .. } => match access_token {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            },
+        }
+    }
+
+    /// Replace the part an `http` rotation renews.
+    fn renewed(&self, secret: String) -> Secret {
+        let mut renewed = self.clone();
+        match &mut renewed {
+            Secret::Bearer { token } => *token = secret,
+            Secret::Basic { password, .. } => *password = secret,
+            Secret::Header { value, .. } => *value = secret,
+            Secret::Hmac { key } => *key = secret,
+            Secret::OAuth2 { client_secret, .. } => *client_secret = secret,
+        }
+        renewed
+    }
+
+    fn validate(&self, problems: &mut Vec<String>) {
+        let empty = match self {
+            Secret::Bearer { token } => token.is_empty(),
+            Secret::Basic { username, password } => username.is_empty() || password.is_empty(),
+            Secret::Header { name, value } => {
+                if reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_err()
+                    || reqwest::header::HeaderValue::from_str(value).is_err()
+                {
+                    problems.push("header name or value is not valid in HTTP".to_string());
+                }
+                value.is_empty()
+            }
+            Secret::Hmac { key } => {
+                if key.len() < MIN_HMAC_KEY_BYTES {
+                    problems.push(format!("hmac key must be at least {} bytes", MIN_HMAC_KEY_BYTES));
+                }
+                false
+            }
+            Secret::OAuth2 { token_url, client_id, client_secret, .. } => {
+                if !is_http_url(token_url) {
+                    problems.push("token_url must be an http(s) URL".to_string());
+                }
+                client_id.is_empty() || client_secret.is_empty()
+            }
+        };
+        if empty {
+            problems.push(format!("{} credential has empty fields", self.kind()));
+        }
+    }
+}
+
+fn is_http_url(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Decrypted credentials by name, shared with the services that call out.
+#[derive(Default)]
+pub struct CredentialCache {
+    secrets: RwLock<HashMap<String, Secret>>,
+}
+
+impl CredentialCache {
+    pub fn get(&self, name: &str) -> Option<Secret> {
+        self.secrets.read().unwrap_or_else(|e| e.into_inner()).get(name).cloned()
+    }
+
+    /// Add the named credential to `request`, if one is stored.
+    pub fn apply(&self, name: &str, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self.get(name) {
+            Some(secret) => secret.apply(request),
+            None => request,
+        }
+    }
+
+    /// Key of the named `hmac` credential.
+    pub fn hmac_key(&self, name: &str) -> Option<String> {
+        match self.get(name)? {
+            Secret::Hmac { key } => Some(key),
+            _ => None,
+        }
+    }
+
+    /// Token of the named `bearer` credential.
+    pub fn bearer_token(&self, name: &str) -> Option<String> {
+        match self.get(name)? {
+            Secret::Bearer { token } => Some(token),
+            _ => None,
+        }
+    }
+
+    fn insert(&self, name: String, secret: Secret) {
+        self.secrets.write().unwrap_or_else(|e| e.into_inner()).insert(name, secret);
+    }
+
+    fn remove(&self, name: &str) {
+        self.secrets.write().unwrap_or_else(|e| e.into_inner()).remove(name);
+    }
+
+    fn replace(&self, secrets: HashMap<String, Secret>) {
+        *self.secrets.write().unwrap_or_else(|e| e.into_inner()) = secrets;
+    }
+}
+
+/// A credential without its secret.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Credential {
+    pub name: String,
+    pub kind: String,
+    /// Data key the secret is encrypted with.
+    pub key_id: String,
+    pub version: i32,
+    pub rotator: Option<String>,
+    pub rotation_url: Option<String>,
+    pub rotation_interval_secs: Option<i64>,
+    pub next_rotation_at: Option<DateTime<Utc>>,
+    pub rotated_at: Option<DateTime<Utc>>,
+    /// Why the last rotation failed, until one succeeds.
+    pub last_error: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(FromRow)]
+struct Sealed {
+    name: String,
+    ciphertext: String,
+    key_id: String,
+    nonce: String,
+    context_hash: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PutCredentialRequest {
+    pub secret: Secret,
+    pub rotator: Option<String>,
+    pub rotation_url: Option<String>,
+    pub rotation_interval_secs: Option<i64>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// What a rotation produced.
+pub struct Rotated {
+    pub secret: Secret,
+    /// Seconds until the new secret stops working, if the API says.
+    pub expires_in: Option<i64>,
+}
+
+pub trait CredentialRotator: Send + Sync {
+    /// Obtain a replacement for `current`.
+    fn rotate<'a>(
+        &'a self,
+        client: &'a reqwest::Client,
+        credential: &'a Credential,
+        current: &'a Secret,
+    ) -> BoxFuture<'a, Result<Rotated, SecurityError>>;
+}
+
+#[derive(Default, Clone)]
+pub struct CredentialRotators {
+    by_name: HashMap<String, Arc<dyn CredentialRotator>>,
+}
+
+impl CredentialRotators {
+    /// The `oauth2` and `http` rotators.
+    pub fn built_in() -> Self {
+        let mut rotators = Self::default();
+        rotators.register("oauth2", Arc::new(OAuth2Rotator));
+        rotators.register("http", Arc::new(HttpRotator));
+        rotators
+    }
+
+    pub fn register(&mut self, name: &str, rotator: Arc<dyn CredentialRotator>) -> &mut Self {
+        self.by_name.insert(name.to_string(), rotator);
+        self
+    }
+
+    /// Add `other`'s rotators, which win on name clashes.
+    pub fn extend(&mut self, other: CredentialRotators) {
+        self.by_name.extend(other.by_name);
+    }
+
+    fn get(&self, name: &str) -> Option<Arc<dyn CredentialRotator>> {
+        self.by_name.get(name).cloned()
+    }
+}
+
+fn rotation_error(credential: &Credential, message: impl fmt::Display) -> SecurityError {
+    SecurityError::DeliveryError(format!("Rotating '{}': {}", credential.name, message))
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<i64>,
+}
+
+/// Client credentials grant against the credential's own token endpoint.
+struct OAuth2Rotator;
+
+impl CredentialRotator for OAuth2Rotator {
+    fn rotate<'a>(
+        &'a self,
+        client: &'a reqwest::Client,
+        credential: &'a Credential,
+        current: &'a Secret,
+    ) -> BoxFuture<'a, Result<Rotated, SecurityError>> {
+        Box::pin(async move {
+            let Secret::OAuth2 { token_url, client_id, client_secret, scope, .. } = current else {
+                return Err(rotation_error(credential, "the oauth2 rotator needs an oauth2 credential"));
+            };
+            let mut form = vec![("grant_type", "client_credentials")];
+            if let Some(scope) = scope {
+                form.push(("scope", scope));
+            }
+
+            let response = deadline::outbound(client.post(token_url))
+                .basic_auth(client_id, Some(client_secret))
+                .form(&form)
+                .send()
+                .await
+                .map_err(|e| rotation_error(credential, e))?;
+            if !response.status().is_success() {
+                return Err(rotation_error(credential, format!("token endpoint returned {}", response.status())));
+            }
+            let token: TokenResponse = response.json().await.map_err(|e| rotation_error(credential, e))?;
+
+            let mut secret = current.clone();
+            if let Secret::OAuth2 { access_token, .. } = &mut secret {
+                *access_token = Some(token.access_token);
+            }
+            Ok(Rotated { secret, expires_in: token.expires_in })
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct RotationResponse {
+    secret: String,
+    expires_in: Option<i64>,
+}
+
+/// Asks the integration's own rotation endpoint for a new secret.
+struct HttpRotator;
+
+impl CredentialRotator for HttpRotator {
+    fn rotate<'a>(
+        &'a self,
+        client: &'a reqwest::Client,
+        credential: &'a Credential,
+        current: &'a Secret,
+    ) -> BoxFuture<'a, Result<Rotated, SecurityError>> {
+        Box::pin(async move {
+            let Some(url) = credential.rotation_url.as_deref() else {
+                return Err(rotation_error(credential, "the http rotator needs a rotation_url"));
+            };
+
+            let response = current.apply(deadline::outbound(client.post(url)))
+                .json(&serde_json::json!({ "name": credential.name, "version": credential.version }))
+                .send()
+                .await
+                .map_err(|e| rotation_error(credential, e))?;
+            if !response.status().is_success() {
+                return Err(rotation_error(credential, format!("rotation endpoint returned {}", response.status())));
+            }
+            let rotated: RotationResponse = response.json().await.map_err(|e| rotation_error(credential, e))?;
+            if rotated.secret.is_empty() {
+                return Err(rotation_error(credential, "rotation endpoint returned an empty secret"));
+            }
+
+            Ok(Rotated { secret: current.renewed(rotated.secret), expires_in: rotated.expires_in })
+        })
+    }
+}
+
+pub struct OutboundCredentials {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: CredentialsConfig,
+    client: reqwest::Client,
+    rotators: CredentialRotators,
+    cache: Arc<CredentialCache>,
+}
+
+impl OutboundCredentials {
+    pub async fn new(
+        config: &Config,
+        storage: Storage,
+        clock: Arc<dyn Clock>,
+        rotators: CredentialRotators,
+        cache: Arc<CredentialCache>,
+    ) -> Result<Self, SecurityError> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(config.credentials.timeout_secs))
+            .build()
+            .map_err(|e| SecurityError::ConfigError(format!("Credential rotation client: {}", e)))?;
+
+        info!("Outbound credentials initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            config: config.credentials.clone(),
+            client,
+            rotators,
+            cache,
+        })
+    }
+
+    pub fn cache(&self) -> &CredentialCache {
+        &self.cache
+    }
+
+    fn validate(&self, name: &str, request: &PutCredentialRequest) -> Result<(), SecurityError> {
+        let mut problems = Vec::new();
+
+        let valid_name = !name.is_empty()
+            && name.len() <= 100
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if !valid_name {
+            problems.push("name must be 1-100 characters of letters, digits, '_', '-' or '.'".to_string());
+        }
+        request.secret.validate(&mut problems);
+        if let Some(rotator) = &request.rotator {
+            if self.rotators.get(rotator).is_none() {
+                problems.push(format!("unknown rotator '{}'", rotator));
+            }
+            if rotator == "http" && request.rotation_url.is_none() {
+                problems.push("the http rotator needs a rotation_url".to_string());
+            }
+        } else if request.rotation_url.is_some() || request.rotation_interval_secs.is_some() {
+            problems.push("rotation_url and rotation_interval_secs need a rotator".to_string());
+        }
+        if let Some(url) = &request.rotation_url {
+            if !is_http_url(url) {
+                problems.push("rotation_url must be an http(s) URL".to_string());
+            }
+        }
+        if request.rotation_interval_secs.is_some_and(|secs| secs <= 0) {
+            problems.push("rotation_interval_secs must be positive".to_string());
+        }
+
+        if !problems.is_empty() {
+            return Err(SecurityError::ValidationError(problems.join("; ")));
+        }
+        Ok(())
+    }
+
+    async fn seal(&self, crypto: &CryptoService, name: &str, secret: &Secret) -> Result<Sealed, SecurityError> {
+        let data = serde_json::to_string(secret)
+            .map_err(|e| SecurityError::CryptoError(format!("Credential encoding: {}", e)))?;
+        let sealed = crypto.encrypt_data(EncryptionRequest {
+            data,
+            key_id: None,
+            context: Some(context(name)),
+        }).await?;
+
+        Ok(Sealed {
+            name: name.to_string(),
+            ciphertext: sealed.encrypted_data,
+            key_id: sealed.key_id,
+            nonce: sealed.nonce,
+            context_hash: sealed.context_hash,
+        })
+    }
+
+    async fn open(&self, crypto: &CryptoService, sealed: Sealed) -> Result<Secret, SecurityError> {
+        // The hash must match the name, so rows cannot be swapped
+        let expected = crypto.context_hash(Some(&context(&sealed.name)))?;
+        if sealed.context_hash != expected {
+            return Err(SecurityError::CryptoError(format!("Credential '{}' is bound to another name", sealed.name)));
+        }
+        let data = crypto.decrypt_data(DecryptionRequest {
+            encrypted_data: sealed.ciphertext,
+            key_id: sealed.key_id,
+            nonce: sealed.nonce,
+            context_hash: sealed.context_hash,
+        }).await?;
+
+        serde_json::from_str(&data)
+            .map_err(|e| SecurityError::CryptoError(format!("Credential '{}' is unreadable: {}", sealed.name, e)))
+    }
+
+    pub async fn list(&self) -> Result<Vec<Credential>, SecurityError> {
+        let credentials = sqlx::query_as::<_, Credential>(&format!(
+            "SELECT {} FROM outbound_credentials ORDER BY name",
+            SELECT_COLUMNS
+        ))
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(credentials)
+    }
+
+    pub async fn get(&self, name: &str) -> Result<Credential, SecurityError> {
+        sqlx::query_as::<_, Credential>(&format!(
+            "SELECT {} FROM outbound_credentials WHERE name = $1",
+            SELECT_COLUMNS
+        ))
+        .bind(name)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("Credential '{}' not found", name)))
+    }
+
+    /// Store a credential, replacing any under the same name. Returns it and
+    /// whether it is new.
+    pub async fn put(
+        &self,
+        crypto: &CryptoService,
+        actor: &Principal,
+        name: &str,
+        request: PutCredentialRequest,
+    ) -> Result<(Credential, bool), SecurityError> {
+        self.validate(name, &request)?;
+        let sealed = self.seal(crypto, name, &request.secret).await?;
+        let now = self.clock.now();
+        let next_rotation_at = request.rotator.as_ref()
+            .map(|_| now + Duration::seconds(request.rotation_interval_secs.unwrap_or(0)));
+
+        let mut tx = self.storage.begin().await?;
+        let existed: Option<(i32,)> = sqlx::query_as(
+            "SELECT version FROM outbound_credentials WHERE name = $1 FOR UPDATE",
+        )
+        .bind(name)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let credential = sqlx::query_as::<_, Credential>(&format!(
+            "INSERT INTO outbound_credentials (name, kind, ciphertext, key_id, nonce, context_hash, rotator, \
+             rotation_url, rotation_interval_secs, next_rotation_at, expires_at, created_by, created_at, updated_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $13) \
+             ON CONFLICT (name) DO UPDATE SET kind = $2, ciphertext = $3, key_id = $4, nonce = $5, \
+             context_hash = $6, rotator = $7, rotation_url = $8, rotation_interval_secs = $9, \
+             next_rotation_at = $10, expires_at = $11, version = outbound_credentials.version + 1, \
+             rotated_at = NULL, last_error = NULL, updated_at = $13 \
+             RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(name)
+        .bind(request.secret.kind())
+        .bind(&sealed.ciphertext)
+        .bind(&sealed.key_id)
+        .bind(&sealed.nonce)
+        .bind(&sealed.context_hash)
+        .bind(&request.rotator)
+        .bind(&request.rotation_url)
+        .bind(request.rotation_interval_secs)
+        .bind(next_rotation_at)
+        .bind(request.expires_at)
+        .bind(&actor.subject)
+        .bind(now)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.cache.insert(name.to_string(), request.secret);
+        Ok((credential, existed.is_none()))
+    }
+
+    pub async fn delete(&self, name: &str) -> Result<(), SecurityError> {
+        let result = sqlx::query("DELETE FROM outbound_credentials WHERE name = $1")
+            .bind(name)
+            .execute(self.storage.pool())
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(SecurityError::NotFound(format!("Credential '{}' not found", name)));
+        }
+        self.cache.remove(name);
+        Ok(())
+    }
+
+    /// Rotate one credential now, whatever its schedule.
+    pub async fn rotate(&self, crypto: &CryptoService, name: &str) -> Result<Credential, SecurityError> {
+        let credential = self.get(name).await?;
+        if credential.rotator.is_none() {
+            return Err(SecurityError::ValidationError(format!("Credential '{}' has no rotator", name)));
+        }
+        self.rotate_locked(crypto, name, false).await?
+            .ok_or_else(|| SecurityError::Conflict(format!("Credential '{}' is being rotated", name)))
+    }
+
+    /// Rotate the credentials that are due, each on its own. Returns the
+    /// outcome per credential.
+    pub async fn rotate_due(&self, crypto: &CryptoService) -> Result<Vec<(String, Result<Credential, SecurityError>)>, SecurityError> {
+        let due: Vec<(String,)> = sqlx::query_as(
+            "SELECT name FROM outbound_credentials WHERE next_rotation_at <= $1 ORDER BY next_rotation_at",
+        )
+        .bind(self.clock.now())
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        let mut outcomes = Vec::new();
+        for (name,) in due {
+            match self.rotate_locked(crypto, &name, true).await {
+                Ok(Some(credential)) => outcomes.push((name, Ok(credential))),
+                // Taken by another replica, or no longer due
+                Ok(None) => {}
+                Err(e) => outcomes.push((name, Err(e))),
+            }
+        }
+        Ok(outcomes)
+    }
+
+    /// Rotate under a row lock so replicas do not rotate the same
+    /// credential twice. `None` if the row is locked, or not due when
+    /// `only_due` is set.
+    async fn rotate_locked(&self, crypto: &CryptoService, name: &str, only_due: bool) -> Result<Option<Credential>, SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let Some(credential) = sqlx::query_as::<_, Credential>(&format!(
+            "SELECT {} FROM outbound_credentials WHERE name = $1 FOR UPDATE SKIP LOCKED",
+            SELECT_COLUMNS
+        ))
+        .bind(name)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+        let now = self.clock.now();
+        if only_due && credential.next_rotation_at.is_none_or(|at| at > now) {
+            return Ok(None);
+        }
+
+        let sealed = sqlx::query_as::<_, Sealed>(&format!(
+            "SELECT {} FROM outbound_credentials WHERE name = $1",
+            SEALED_COLUMNS
+        ))
+        .bind(name)
+        .fetch_one(&mut *tx)
+        .await?;
+        let rotated = match self.open(crypto, sealed).await {
+            Ok(current) => self.run_rotator(&credential, &current).await,
+            Err(e) => Err(e),
+        };
+        let rotated = match rotated {
+            Ok(rotated) => rotated,
+            Err(e) => {
+                sqlx::query(
+                    "UPDATE outbound_credentials SET last_error = $2, next_rotation_at = $3 WHERE name = $1",
+                )
+                .bind(name)
+                .bind(e.to_string())
+                .bind(now + Duration::seconds(self.config.retry_secs))
+                .execute(&mut *tx)
+                .await?;
+                tx.commit().await?;
+                return Err(e);
+            }
+        };
+
+        let sealed = self.seal(crypto, name, &rotated.secret).await?;
+        let expires_at = rotated.expires_in.map(|secs| now + Duration::seconds(secs));
+        let next_rotation_at = match (rotated.expires_in, credential.rotation_interval_secs) {
+            (Some(secs), interval) => {
+                let before_expiry = secs * 3 / 4;
+                Some(now + Duration::seconds(interval.map_or(before_expiry, |i| i.min(before_expiry)).max(1)))
+            }
+            (None, Some(interval)) => Some(now + Duration::seconds(interval)),
+            (None, None) => None,
+        };
+        let credential = sqlx::query_as::<_, Credential>(&format!(
+            "UPDATE outbound_credentials SET ciphertext = $2, key_id = $3, nonce = $4, context_hash = $5, \
+             version = version + 1, rotated_at = $6, next_rotation_at = $7, expires_at = $8, last_error = NULL, \
+             updated_at = $6 WHERE name = $1 RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(name)
+        .bind(&sealed.ciphertext)
+        .bind(&sealed.key_id)
+        .bind(&sealed.nonce)
+        .bind(&sealed.context_hash)
+        .bind(now)
+        .bind(next_rotation_at)
+        .bind(expires_at)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.cache.insert(name.to_string(), rotated.secret);
+        Ok(Some(credential))
+    }
+
+    async fn run_rotator(&self, credential: &Credential, current: &Secret) -> Result<Rotated, SecurityError> {
+        let rotator = credential.rotator.as_deref()
+            .and_then(|rotator| self.rotators.get(rotator))
+            .ok_or_else(|| rotation_error(credential, format!("rotator {:?} is not available", credential.rotator)))?;
+        let rotated = rotator.rotate(&self.client, credential, current).await?;
+
+        let mut problems = Vec::new();
+        rotated.secret.validate(&mut problems);
+        if rotated.secret.kind() != credential.kind {
+            problems.push(format!("rotator returned a {} credential", rotated.secret.kind()));
+        }
+        if !problems.is_empty() {
+            return Err(rotation_error(credential, problems.join("; ")));
+        }
+        Ok(rotated)
+    }
+
+    /// Reload the cache, picking up changes made by other replicas. A
+    /// credential that cannot be decrypted is left out and reported.
+    pub async fn refresh(&self, crypto: &CryptoService) -> Result<(), SecurityError> {
+        let rows = sqlx::query_as::<_, Sealed>(&format!("SELECT {} FROM outbound_credentials", SEALED_COLUMNS))
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        let mut secrets = HashMap::with_capacity(rows.len());
+        for sealed in rows {
+            let name = sealed.name.clone();
+            match self.open(crypto, sealed).await {
+                Ok(secret) => {
+                    secrets.insert(name, secret);
+                }
+                Err(e) => error!("Failed to load outbound credential '{}': {:?}", name, e),
+            }
+        }
+        self.cache.replace(secrets);
+        Ok(())
+    }
+
+    /// Credentials with a known expiry, for the expiry scan.
+    async fn expiring(&self) -> Result<Vec<Expiring>, SecurityError> {
+        let rows: Vec<(String, DateTime<Utc>)> = sqlx::query_as(
+            "SELECT name, expires_at FROM outbound_credentials WHERE expires_at IS NOT NULL",
+        )
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(name, expires_at)| Expiring {
+                source: "outbound_credentials",
+                kind: "outbound_credential".to_string(),
+                name,
+                tenant_id: None,
+                expires_at,
+                fingerprint: None,
+            })
+            .collect())
+    }
+}
+
+fn context(name: &str) -> HashMap<String, String> {
+    HashMap::from([("outbound_credential".to_string(), name.to_string())])
+}
+
+fn expiring(state: &AppState) -> ExpiryFuture<'_> {
+    Box::pin(state.credentials.expiring())
+}
+
+pub fn register_expiry_sources(registry: &mut ExpiryRegistry) {
+    registry.register("outbound_credentials", expiring);
+}
+
+async fn audit(state: &AppState, actor: &str, action: &str, name: &str, outcome: &str, payload: serde_json::Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: None,
+        actor: actor.to_string(),
+        actor_ip: None,
+        action: action.to_string(),
+        resource: format!("outbound_credential:{}", name),
+        outcome: outcome.to_string(),
+        payload,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} on credential '{}': {:?}", action, name, e);
+    }
+}
+
+/// Audit a rotation, and alert when it failed.
+async fn report_rotation(state: &AppState, actor: &str, name: &str, outcome: &Result<Credential, SecurityError>) {
+    match outcome {
+        Ok(credential) => {
+            audit(state, actor, "credentials.rotate", name, "success", serde_json::json!({
+                "version": credential.version,
+                "next_rotation_at": credential.next_rotation_at
+            })).await;
+        }
+        Err(e) => {
+            warn!("Rotation of outbound credential '{}' failed: {}", name, e);
+            audit(state, actor, "credentials.rotate", name, "failure", serde_json::json!({
+                "error": e.to_string()
+            })).await;
+            let alert = Alert::new(
+                "credentials",
+                Severity::High,
+                format!("Outbound credential '{}' could not be rotated", name),
+                serde_json::json!({ "credential": name, "error": e.to_string() }),
+                state.clock.now(),
+            );
+            state.alerting_service.send(&alert, &[]).await;
+        }
+    }
+}
+
+/// Background loop running due rotations and reloading the cache.
+pub async fn run_rotation(state: web::Data<AppState>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(state.config.credentials.interval_secs));
+
+    loop {
+        interval.tick().await;
+        match state.credentials.rotate_due(&state.crypto_service).await {
+            Ok(outcomes) => {
+                for (name, outcome) in &outcomes {
+                    report_rotation(&state, "system", name, outcome).await;
+                }
+            }
+            Err(e) => error!("Failed to run outbound credential rotations: {:?}", e),
+        }
+        match state.credentials.refresh(&state.crypto_service).await {
+            Ok(()) => state.startup.recovered("outbound_credentials"),
+            Err(e) => warn!("Failed to refresh outbound credentials: {:?}", e),
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::DeliveryError(msg) => HttpResponse::BadGateway().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Outbound credential operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Outbound credential operation failed"
+            }))
+        }
+    }
+}
+
+pub async fn list_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.credentials.list().await {
+        Ok(credentials) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "credentials": credentials
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.credentials.get(&path).await {
+        Ok(credential) => Ok(HttpResponse::Ok().json(credential)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn put_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    request: web::Json<PutCredentialRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let name = path.into_inner();
+    match state.credentials.put(&state.crypto_service, &principal, &name, request.into_inner()).await {
+        Ok((credential, created)) => {
+            audit(&state, &principal.subject, "credentials.put", &name, "success", serde_json::json!({
+                "kind": credential.kind,
+                "version": credential.version,
+                "rotator": credential.rotator
+            })).await;
+            let mut response = if created { HttpResponse::Created() } else { HttpResponse::Ok() };
+            Ok(response.json(credential))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn delete_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let name = path.into_inner();
+    match state.credentials.delete(&name).await {
+        Ok(()) => {
+            audit(&state, &principal.subject, "credentials.delete", &name, "success", serde_json::json!({})).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn rotate_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let name = path.into_inner();
+    let outcome = state.credentials.rotate(&state.crypto_service, &name).await;
+    if !matches!(&outcome, Err(SecurityError::NotFound(_) | SecurityError::ValidationError(_) | SecurityError::Conflict(_))) {
+        report_rotation(&state, &principal.subject, &name, &outcome).await;
+    }
+    match outcome {
+        Ok(credential) => Ok(HttpResponse::Ok().json(credential)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/outbound-credentials")
+            .route("", web::get().to(list_handler))
+            .route("/{name}", web::get().to(get_handler))
+            .route("/{name}", web::put().to(put_handler))
+            .route("/{name}", web::delete().to(delete_handler))
+            .route("/{name}/rotate", web::post().to(rotate_handler))
+    );
+}
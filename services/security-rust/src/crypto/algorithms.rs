@@ -0,0 +1,204 @@
+/*!
+Algorithm Registry
+Which algorithms operations may use, per environment and tenant
+
+Every algorithm the service operates with has a status:
+
+- `preferred`: what new data should use
+- `allowed`
+- `deprecated`: still works, but each use is logged, counted in
+  `cotai_deprecated_algorithm_uses_total` and answered with a `Warning`
+  header naming the replacement, so callers still on it can be found
+  and moved off
+- `forbidden`: refused
+
+`CATALOG` holds the defaults. `CRYPTO_ALGORITHMS` (e.g.
+`SHA-1=forbidden,HS256=deprecated`) sets them for the environment, and a
+tenant's `algorithms` setting (see `tenant_settings`) deprecates or
+forbids more for its own calls; it cannot relax what the environment
+restricts. Algorithms outside the catalog are forbidden.
+
+Checked on `/crypto/sign`, `/crypto/verify`, `/crypto/hash`, signing key
+rollovers, service account key registration and the breached-password
+lookup, which needs SHA-1. The algorithms the service signs with on its
+own (`JWT_ALGORITHM`, `NOTARY_ALGORITHM` and the like) must not be
+forbidden, or it does not start. `GET /crypto/algorithms` lists the
+statuses that apply to the caller.
+*/
+
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use tracing::warn;
+
+use crate::auth::auth_error_response;
+use crate::config::CryptoConfig;
+use crate::errors::SecurityError;
+use crate::AppState;
+
+/// Ordered from most to least permissive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlgorithmStatus {
+    Preferred,
+    Allowed,
+    Deprecated,
+    Forbidden,
+}
+
+impl AlgorithmStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlgorithmStatus::Preferred => "preferred",
+            AlgorithmStatus::Allowed => "allowed",
+            AlgorithmStatus::Deprecated => "deprecated",
+            AlgorithmStatus::Forbidden => "forbidden",
+        }
+    }
+}
+
+impl FromStr for AlgorithmStatus {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "preferred" => Ok(AlgorithmStatus::Preferred),
+            "allowed" => Ok(AlgorithmStatus::Allowed),
+            "deprecated" => Ok(AlgorithmStatus::Deprecated),
+            "forbidden" => Ok(AlgorithmStatus::Forbidden),
+            other => Err(format!("'{}' is not preferred, allowed, deprecated or forbidden", other)),
+        }
+    }
+}
+
+/// Name, what it is used for, and default status.
+pub const CATALOG: &[(&str, &str, AlgorithmStatus)] = &[
+    ("AES-256-GCM", "encryption", AlgorithmStatus::Preferred),
+    ("EdDSA", "signature", AlgorithmStatus::Preferred),
+    ("ES256", "signature", AlgorithmStatus::Allowed),
+    ("RS256", "signature", AlgorithmStatus::Allowed),
+    ("HS256", "mac", AlgorithmStatus::Preferred),
+    ("HS384", "mac", AlgorithmStatus::Allowed),
+    ("HS512", "mac", AlgorithmStatus::Allowed),
+    ("argon2id", "password_hash", AlgorithmStatus::Preferred),
+    ("SHA-256", "hash", AlgorithmStatus::Preferred),
+    // Only for the breached-password range API, which is keyed by it
+    ("SHA-1", "hash", AlgorithmStatus::Deprecated),
+];
+
+pub fn catalogued(algorithm: &str) -> bool {
+    CATALOG.iter().any(|(name, _, _)| *name == algorithm)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AlgorithmEntry {
+    pub algorithm: &'static str,
+    pub kind: &'static str,
+    pub status: AlgorithmStatus,
+}
+
+/// Statuses for the environment: the catalog with `CRYPTO_ALGORITHMS` applied.
+pub struct AlgorithmRegistry {
+    statuses: BTreeMap<&'static str, AlgorithmStatus>,
+}
+
+impl AlgorithmRegistry {
+    pub fn new(config: &CryptoConfig) -> Self {
+        let statuses = CATALOG
+            .iter()
+            .map(|(name, _, default)| {
+                let configured = config.algorithms.iter().find(|(algorithm, _)| algorithm == name);
+                (*name, configured.map_or(*default, |(_, status)| *status))
+            })
+            .collect();
+        Self { statuses }
+    }
+
+    /// The stricter of the environment's status and the tenant's.
+    pub fn resolve(&self, algorithm: &str, tenant: &BTreeMap<String, AlgorithmStatus>) -> AlgorithmStatus {
+        let environment = self.statuses.get(algorithm).copied().unwrap_or(AlgorithmStatus::Forbidden);
+        tenant.get(algorithm).map_or(environment, |status| environment.max(*status))
+    }
+
+    pub fn entries(&self, tenant: &BTreeMap<String, AlgorithmStatus>) -> Vec<AlgorithmEntry> {
+        CATALOG
+            .iter()
+            .map(|(name, kind, _)| AlgorithmEntry { algorithm: name, kind, status: self.resolve(name, tenant) })
+            .collect()
+    }
+
+    /// The best algorithm of the same kind still usable, to move to.
+    fn replacement(&self, algorithm: &str, tenant: &BTreeMap<String, AlgorithmStatus>) -> Option<&'static str> {
+        let (_, kind, _) = CATALOG.iter().find(|(name, _, _)| *name == algorithm)?;
+        CATALOG
+            .iter()
+            .filter(|(name, other, _)| other == kind && *name != algorithm)
+            .map(|(name, _, _)| (self.resolve(name, tenant), *name))
+            .filter(|(status, _)| *status <= AlgorithmStatus::Allowed)
+            .min()
+            .map(|(_, name)| name)
+    }
+}
+
+/// Check that `operation` may use `algorithm` for the tenant. Deprecated
+/// uses pass, logged and counted, with a warning for the caller.
+pub async fn check(
+    state: &AppState,
+    tenant_id: Option<&str>,
+    algorithm: &str,
+    operation: &str,
+) -> Result<Option<String>, SecurityError> {
+    let settings = state.tenant_settings.effective(tenant_id).await;
+    let registry = state.crypto_service.algorithms();
+    match registry.resolve(algorithm, &settings.algorithms) {
+        AlgorithmStatus::Forbidden => {
+            state.metrics_service.increment(
+                "cotai_forbidden_algorithm_rejections_total",
+                &[("algorithm", algorithm), ("operation", operation)],
+            );
+            Err(SecurityError::ValidationError(format!("{} is forbidden for {}", algorithm, operation)))
+        }
+        AlgorithmStatus::Deprecated => {
+            state.metrics_service.increment(
+                "cotai_deprecated_algorithm_uses_total",
+                &[("algorithm", algorithm), ("operation", operation)],
+            );
+            warn!(
+                "Deprecated algorithm {} used for {} (tenant {})",
+                algorithm,
+                operation,
+                tenant_id.unwrap_or("-")
+            );
+            Ok(Some(match registry.replacement(algorithm, &settings.algorithms) {
+                Some(replacement) => format!("{} is deprecated; move to {}", algorithm, replacement),
+                None => format!("{} is deprecated", algorithm),
+            }))
+        }
+        AlgorithmStatus::Preferred | AlgorithmStatus::Allowed => Ok(None),
+    }
+}
+
+/// Add the deprecation from `check`, if any, as a `Warning` header.
+pub fn with_warning(mut response: HttpResponse, warning: Option<&str>) -> HttpResponse {
+    let value = warning.and_then(|warning| HeaderValue::from_str(&format!("299 - \"{}\"", warning.replace('"', "'"))).ok());
+    if let Some(value) = value {
+        response.headers_mut().insert(header::WARNING, value);
+    }
+    response
+}
+
+// HTTP handlers
+
+pub async fn list_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let settings = state.tenant_settings.effective(principal.tenant_id.as_deref()).await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "algorithms": state.crypto_service.algorithms().entries(&settings.algorithms)
+    })))
+}
@@ -0,0 +1,631 @@
+/*!
+Bulk Decryption
+Approved, time-boxed grants for decrypting many records in one request
+
+One request may read at most `CRYPTO_BULK_DECRYPT_THRESHOLD` records:
+ciphertexts in `POST /crypto/decrypt-batch`, tokens in
+`POST /crypto/detokenize`. Larger exports need a grant, presented in the
+`X-Bulk-Decrypt-Grant` header:
+
+1. The exporter asks for one with `POST /crypto/bulk-grants`, stating how
+   many records, the purpose (one the tenant allows) and why. The grant
+   token comes back once, inert until approved; only its hash is stored.
+2. Someone else with `CRYPTO_BULK_GRANT_APPROVE_SCOPE`, or an admin, of
+   the same tenant approves or rejects it at
+   `POST /crypto/bulk-grants/{id}/approve` or `/reject` within
+   `CRYPTO_BULK_GRANT_APPROVAL_TTL_SECS`. Nobody approves their own.
+3. For `CRYPTO_BULK_GRANT_TTL_SECS` after approval the requester, and only
+   they, may decrypt up to the granted number of records with it, in as
+   many requests as they like, each declaring the granted purpose. Records
+   are counted when a request is admitted, whether or not each decrypts.
+
+Requests, approvals, rejections and every read under a grant are audited
+against the resource `bulk_decrypt_grant:{id}`, and each read names the
+approval's audit event as `approval_event_id`, so an export traces back to
+who allowed it. Reads under a grant are not counted by the read volume
+guard (see `guard`), though a held caller stays paused.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::receipts::{self, RECEIPT_HEADER};
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{BulkDecryptConfig, Config};
+use crate::crypto::{
+    audit_cache_served, audit_compromised_use, guard, purpose, CryptoService, DecryptionRequest, KeyState,
+};
+use crate::errors::SecurityError;
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::Storage;
+use crate::AppState;
+
+pub const GRANT_HEADER: &str = "X-Bulk-Decrypt-Grant";
+
+const TOKEN_PREFIX: &str = "bdg_";
+const TOKEN_BYTES: usize = 32;
+
+const MAX_REASON_LENGTH: usize = 1000;
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "created_at", column: "created_at", kind: KeyKind::Timestamp },
+];
+
+const GRANT_COLUMNS: &str = "id, requested_by, tenant_id, purpose, reason, records, records_used, status, \
+    created_at, decided_by, decided_at, approval_event_id, expires_at, last_used_at";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Grant {
+    pub id: Uuid,
+    pub requested_by: String,
+    pub tenant_id: Option<String>,
+    pub purpose: String,
+    pub reason: String,
+    pub records: i64,
+    pub records_used: i64,
+    /// `pending_approval`, `approved` or `rejected`.
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub approval_event_id: Option<Uuid>,
+    /// When a pending request lapses, or an approved grant stops working.
+    pub expires_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GrantRequest {
+    pub records: i64,
+    pub purpose: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct GrantFilter {
+    pub status: Option<String>,
+    pub requested_by: Option<String>,
+}
+
+/// Body of `POST /crypto/decrypt-batch`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchRequest {
+    pub items: Vec<DecryptionRequest>,
+    pub purpose: Option<String>,
+}
+
+/// One item of a batch, in the order given.
+#[derive(Debug, Serialize)]
+pub struct BatchResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn token_hash(token: &str) -> String {
+    hex::encode(digest(&SHA256, token.as_bytes()))
+}
+
+pub struct BulkDecryption {
+    storage: Storage,
+    config: BulkDecryptConfig,
+    clock: Arc<dyn Clock>,
+}
+
+impl BulkDecryption {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        info!("Bulk decryption grants initialized successfully");
+        Ok(Self {
+            storage,
+            config: config.bulk_decrypt.clone(),
+            clock,
+        })
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.config.threshold
+    }
+
+    /// Store a pending grant, returning it with its token. `purpose` has
+    /// been checked against the tenant's.
+    pub async fn request(
+        &self,
+        crypto: &CryptoService,
+        principal: &Principal,
+        purpose: String,
+        request: &GrantRequest,
+    ) -> Result<(Grant, String), SecurityError> {
+        if request.records <= self.config.threshold as i64 || request.records > self.config.max_grant_records {
+            return Err(SecurityError::ValidationError(format!(
+                "records must be {}-{}; fewer need no grant",
+                self.config.threshold + 1,
+                self.config.max_grant_records
+            )));
+        }
+        let reason = request.reason.trim();
+        if reason.is_empty() || reason.len() > MAX_REASON_LENGTH {
+            return Err(SecurityError::ValidationError(format!(
+                "reason must be 1-{} characters",
+                MAX_REASON_LENGTH
+            )));
+        }
+
+        let secret = base64::encode_config(crypto.secure_random(TOKEN_BYTES).await?, base64::URL_SAFE_NO_PAD);
+        let token = format!("{}{}", TOKEN_PREFIX, secret);
+        let now = self.clock.now();
+        let grant = sqlx::query_as::<_, Grant>(&format!(
+            "INSERT INTO bulk_decrypt_grants (id, token_hash, requested_by, tenant_id, purpose, reason, records, \
+             created_at, expires_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING {}",
+            GRANT_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(token_hash(&token))
+        .bind(&principal.subject)
+        .bind(&principal.tenant_id)
+        .bind(purpose)
+        .bind(reason)
+        .bind(request.records)
+        .bind(now)
+        .bind(now + Duration::seconds(self.config.approval_ttl_secs))
+        .fetch_one(self.storage.pool())
+        .await?;
+        Ok((grant, token))
+    }
+
+    /// Grants `viewer` may see: all of their tenant's when `approver`,
+    /// otherwise their own.
+    pub async fn list(
+        &self,
+        viewer: &Principal,
+        approver: bool,
+        filter: &GrantFilter,
+        page: &PageRequest,
+    ) -> Result<Page<Grant>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM bulk_decrypt_grants WHERE 1 = 1",
+            GRANT_COLUMNS
+        ));
+        if !approver {
+            builder.push(" AND requested_by = ").push_bind(viewer.subject.clone());
+        } else if let Some(tenant_id) = &viewer.tenant_id {
+            builder.push(" AND tenant_id = ").push_bind(tenant_id.clone());
+        }
+        if let Some(status) = &filter.status {
+            builder.push(" AND status = ").push_bind(status.clone());
+        }
+        if let Some(requested_by) = &filter.requested_by {
+            builder.push(" AND requested_by = ").push_bind(requested_by.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let grants = builder
+            .build_query_as::<Grant>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(grants, |grant, _| (SortKey::Timestamp(grant.created_at), grant.id)))
+    }
+
+    /// A grant `viewer` may see, as for `list`.
+    pub async fn get(&self, viewer: &Principal, approver: bool, id: Uuid) -> Result<Grant, SecurityError> {
+        sqlx::query_as::<_, Grant>(&format!("SELECT {} FROM bulk_decrypt_grants WHERE id = $1", GRANT_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .filter(|grant| {
+                if approver {
+                    viewer.tenant_id.is_none() || viewer.tenant_id == grant.tenant_id
+                } else {
+                    grant.requested_by == viewer.subject
+                }
+            })
+            .ok_or_else(|| SecurityError::NotFound("Grant not found".to_string()))
+    }
+
+    /// Approve or reject someone else's pending request.
+    pub async fn decide(&self, approver: &Principal, id: Uuid, approve: bool) -> Result<Grant, SecurityError> {
+        let pending = self.get(approver, true, id).await?;
+        if pending.requested_by == approver.subject {
+            return Err(SecurityError::AccessDenied("A grant must be approved by someone else".to_string()));
+        }
+
+        let now = self.clock.now();
+        let (status, expires_at) = if approve {
+            ("approved", now + Duration::seconds(self.config.grant_ttl_secs))
+        } else {
+            ("rejected", now)
+        };
+        sqlx::query_as::<_, Grant>(&format!(
+            "UPDATE bulk_decrypt_grants SET status = $2, decided_by = $3, decided_at = $4, expires_at = $5 \
+             WHERE id = $1 AND status = 'pending_approval' AND expires_at > $4 RETURNING {}",
+            GRANT_COLUMNS
+        ))
+        .bind(id)
+        .bind(status)
+        .bind(&approver.subject)
+        .bind(now)
+        .bind(expires_at)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| match pending.status.as_str() {
+            "pending_approval" => SecurityError::Conflict(format!("Grant request {} has lapsed", id)),
+            status => SecurityError::Conflict(format!("Grant {} is already {}", id, status)),
+        })
+    }
+
+    async fn link_approval(&self, id: Uuid, event_id: Uuid) -> Result<Grant, SecurityError> {
+        Ok(sqlx::query_as::<_, Grant>(&format!(
+            "UPDATE bulk_decrypt_grants SET approval_event_id = $2 WHERE id = $1 RETURNING {}",
+            GRANT_COLUMNS
+        ))
+        .bind(id)
+        .bind(event_id)
+        .fetch_one(self.storage.pool())
+        .await?)
+    }
+
+    /// Count `records` against the grant `token`, which must be the
+    /// caller's, approved, unexpired, for `purpose` and have them left.
+    pub async fn consume(
+        &self,
+        principal: &Principal,
+        token: &str,
+        records: i64,
+        purpose: Option<&str>,
+    ) -> Result<Grant, SecurityError> {
+        let hash = token_hash(token);
+        let now = self.clock.now();
+        let consumed = sqlx::query_as::<_, Grant>(&format!(
+            "UPDATE bulk_decrypt_grants SET records_used = records_used + $3, last_used_at = $4 \
+             WHERE token_hash = $1 AND requested_by = $2 AND status = 'approved' AND expires_at > $4 \
+             AND records_used + $3 <= records AND purpose = $5 RETURNING {}",
+            GRANT_COLUMNS
+        ))
+        .bind(&hash)
+        .bind(&principal.subject)
+        .bind(records)
+        .bind(now)
+        .bind(purpose)
+        .fetch_optional(self.storage.pool())
+        .await?;
+        if let Some(grant) = consumed {
+            return Ok(grant);
+        }
+
+        // Say why, without confirming grants of other callers exist
+        let grant = sqlx::query_as::<_, Grant>(&format!(
+            "SELECT {} FROM bulk_decrypt_grants WHERE token_hash = $1 AND requested_by = $2",
+            GRANT_COLUMNS
+        ))
+        .bind(&hash)
+        .bind(&principal.subject)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::AccessDenied("Unknown bulk-decrypt grant".to_string()))?;
+        let reason = match grant.status.as_str() {
+            "pending_approval" => "is not approved yet".to_string(),
+            "rejected" => "was rejected".to_string(),
+            _ if grant.expires_at <= now => "has expired".to_string(),
+            _ if purpose != Some(grant.purpose.as_str()) => format!("is for purpose '{}'", grant.purpose),
+            _ => format!("has {} of {} records left", grant.records - grant.records_used, grant.records),
+        };
+        Err(SecurityError::AccessDenied(format!("Bulk-decrypt grant {} {}", grant.id, reason)))
+    }
+}
+
+/// The grant a read of `records` by `principal` goes under: `None` up to
+/// the threshold, otherwise the one in `X-Bulk-Decrypt-Grant`, consumed.
+pub async fn authorize(
+    state: &AppState,
+    req: &HttpRequest,
+    principal: &Principal,
+    records: usize,
+    purpose: Option<&str>,
+) -> Result<Option<Grant>, SecurityError> {
+    let bulk = &state.bulk_decrypt;
+    if records <= bulk.threshold() {
+        return Ok(None);
+    }
+    let token = req
+        .headers()
+        .get(GRANT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| SecurityError::AccessDenied(format!(
+            "Reading more than {} records at once needs an approved bulk-decrypt grant in {}",
+            bulk.threshold(),
+            GRANT_HEADER
+        )))?;
+    bulk.consume(principal, token, records as i64, purpose).await.map(Some)
+}
+
+/// Count a read of `records` against the volume guard, unless a grant
+/// covers it; held callers are refused either way.
+pub async fn admit(
+    state: &AppState,
+    req: &HttpRequest,
+    principal: &Principal,
+    grant: Option<&Grant>,
+    records: usize,
+) -> Result<(), SecurityError> {
+    let operations = if grant.is_some() { 0 } else { records as i64 };
+    guard::admit(state, Some(principal), client_ip(req).as_deref(), operations).await
+}
+
+/// The response for a read `authorize` refused.
+pub fn refused_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::AccessDenied(msg) => HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg,
+            "code": "bulk_grant_required"
+        })),
+        e => error_response(e),
+    }
+}
+
+async fn audit(state: &AppState, req: &HttpRequest, actor: &Principal, action: &str, grant: &Grant) -> Option<(Uuid, Option<String>)> {
+    let recorded = receipts::record(state, NewAuditEvent {
+        tenant_id: grant.tenant_id.clone(),
+        actor: actor.subject.clone(),
+        actor_ip: client_ip(req),
+        action: action.to_string(),
+        resource: format!("bulk_decrypt_grant:{}", grant.id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "requested_by": grant.requested_by,
+            "purpose": grant.purpose,
+            "reason": grant.reason,
+            "records": grant.records,
+            "expires_at": grant.expires_at
+        }),
+    }).await;
+    match recorded {
+        Ok(recorded) => Some(recorded),
+        Err(e) => {
+            warn!("Failed to audit {} of grant {}: {:?}", action, grant.id, e);
+            None
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::AccessDenied(msg) => HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Bulk decryption failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Bulk decryption failed"
+            }))
+        }
+    }
+}
+
+fn with_receipt(mut response: actix_web::HttpResponseBuilder, receipt: Option<String>) -> actix_web::HttpResponseBuilder {
+    if let Some(receipt) = receipt {
+        response.insert_header((RECEIPT_HEADER, receipt));
+    }
+    response
+}
+
+fn is_approver(state: &AppState, principal: &Principal) -> bool {
+    principal.scopes.iter().any(|s| s == &state.config.bulk_decrypt.approve_scope)
+        || principal.has_any_role(&state.config.auth.admin_roles)
+}
+
+pub async fn request_grant_handler(
+    req: HttpRequest,
+    request: web::Json<GrantRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let purpose = match purpose::check(&state, principal.tenant_id.as_deref(), Some(&request.purpose), true).await {
+        Ok(purpose) => purpose.unwrap_or_default(),
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.bulk_decrypt.request(&state.crypto_service, &principal, purpose, &request).await {
+        Ok((grant, token)) => {
+            info!("{} asked for a grant to decrypt {} records", principal.subject, grant.records);
+            let receipt = audit(&state, &req, &principal, "crypto.bulk_grant.request", &grant).await
+                .and_then(|(_, receipt)| receipt);
+            Ok(with_receipt(HttpResponse::Created(), receipt)
+                .insert_header(("Cache-Control", "no-store"))
+                .json(serde_json::json!({
+                    "grant": grant,
+                    "token": token
+                })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_grants_handler(
+    req: HttpRequest,
+    filter: web::Query<GrantFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    let approver = is_approver(&state, &principal);
+    match state.bulk_decrypt.list(&principal, approver, &filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "grants": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_grant_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let approver = is_approver(&state, &principal);
+    match state.bulk_decrypt.get(&principal, approver, path.into_inner()).await {
+        Ok(grant) => Ok(HttpResponse::Ok().json(grant)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+async fn decide(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>, approve: bool) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) if is_approver(&state, &principal) => principal,
+        Ok(principal) => return Ok(auth_error_response(&SecurityError::AccessDenied(format!(
+            "{} lacks scope {}",
+            principal.subject, state.config.bulk_decrypt.approve_scope
+        )))),
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let grant = match state.bulk_decrypt.decide(&principal, path.into_inner(), approve).await {
+        Ok(grant) => grant,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let action = if approve { "crypto.bulk_grant.approve" } else { "crypto.bulk_grant.reject" };
+    info!("Grant {} for {} {} by {}", grant.id, grant.requested_by, grant.status, principal.subject);
+    let recorded = audit(&state, &req, &principal, action, &grant).await;
+    let receipt = recorded.as_ref().and_then(|(_, receipt)| receipt.clone());
+    let grant = match recorded {
+        Some((event_id, _)) if approve => match state.bulk_decrypt.link_approval(grant.id, event_id).await {
+            Ok(linked) => linked,
+            Err(e) => {
+                error!("Failed to link grant {} to its approval: {:?}", grant.id, e);
+                grant
+            }
+        },
+        _ => grant,
+    };
+    Ok(with_receipt(HttpResponse::Ok(), receipt).json(grant))
+}
+
+pub async fn approve_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    decide(req, path, state, true).await
+}
+
+pub async fn reject_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    decide(req, path, state, false).await
+}
+
+/// Decrypt several ciphertexts, more than the threshold under a grant.
+pub async fn decrypt_batch_handler(
+    req: HttpRequest,
+    request: web::Json<BatchRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let BatchRequest { items, purpose } = request.into_inner();
+    let max_batch = state.config.bulk_decrypt.max_batch;
+    if items.is_empty() || items.len() > max_batch {
+        return Ok(error_response(SecurityError::ValidationError(format!(
+            "items must hold 1-{} ciphertexts",
+            max_batch
+        ))));
+    }
+    let bulk = items.len() > state.bulk_decrypt.threshold();
+    let purpose = match purpose::check(&state, principal.tenant_id.as_deref(), purpose.as_deref(), bulk).await {
+        Ok(purpose) => purpose,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let grant = match authorize(&state, &req, &principal, items.len(), purpose.as_deref()).await {
+        Ok(grant) => grant,
+        Err(e) => return Ok(refused_response(e)),
+    };
+    if let Err(e) = admit(&state, &req, &principal, grant.as_ref(), items.len()).await {
+        return Ok(guard::paused_response(&e));
+    }
+
+    let mut key_ids = BTreeSet::new();
+    let mut results = Vec::with_capacity(items.len());
+    for item in items {
+        key_ids.insert(item.key_id.clone());
+        results.push(match state.crypto_service.decrypt_data(item).await {
+            Ok(data) => BatchResult { data: Some(data), error: None },
+            Err(SecurityError::Conflict(msg)) => BatchResult { data: None, error: Some(msg) },
+            Err(e) => {
+                error!("Batch decryption item failed: {:?}", e);
+                BatchResult { data: None, error: Some("Decryption failed".to_string()) }
+            }
+        });
+    }
+    for key_id in &key_ids {
+        if state.crypto_service.served_from_cache(key_id) {
+            audit_cache_served(&state, "decrypt_batch", key_id).await;
+        }
+        if state.crypto_service.key_state(key_id) == Some(KeyState::Compromised) {
+            audit_compromised_use(&state, "decrypt_batch", key_id).await;
+        }
+    }
+
+    let failures = results.iter().filter(|result| result.error.is_some()).count();
+    let receipt = receipts::record_or_warn(&state, NewAuditEvent {
+        tenant_id: principal.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(&req),
+        action: "crypto.decrypt_batch".to_string(),
+        resource: grant.as_ref().map_or_else(
+            || "crypto_batch".to_string(),
+            |grant| format!("bulk_decrypt_grant:{}", grant.id),
+        ),
+        outcome: if failures == results.len() { "failure" } else { "success" }.to_string(),
+        payload: serde_json::json!({
+            "purpose": purpose,
+            "count": results.len(),
+            "failures": failures,
+            "key_ids": key_ids,
+            "grant_id": grant.as_ref().map(|grant| grant.id),
+            "approval_event_id": grant.as_ref().and_then(|grant| grant.approval_event_id),
+            "records_used": grant.as_ref().map(|grant| grant.records_used),
+            "records": grant.as_ref().map(|grant| grant.records)
+        }),
+    }).await;
+
+    Ok(with_receipt(HttpResponse::Ok(), receipt)
+        .insert_header(("Cache-Control", "no-store"))
+        .json(serde_json::json!({ "results": results })))
+}
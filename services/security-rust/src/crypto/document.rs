@@ -0,0 +1,408 @@
+/*!
+Document Encryption
+Field-level encryption of JSON documents
+
+`POST /crypto/encrypt-document` takes a JSON document and selectors naming
+the fields to protect, and replaces each selected value, whatever its type,
+with an envelope:
+
+```json
+{ "$enc": "v1", "key_id": "...", "nonce": "...", "data": "...", "context_hash": null }
+```
+
+The rest of the document is returned as it came, so a tender or supplier
+record keeps its shape and can still be indexed on its clear fields.
+`POST /crypto/decrypt-document` puts the values back: every envelope in the
+document, or only those under the given selectors. A `context` given on
+decryption must be the one used to encrypt.
+
+Selectors are a subset of JSONPath: `$.supplier.cnpj`, `$.items[*].price`,
+`$.bank.*`, `$.items[0]['unit price']`. Fields a selector does not reach
+are skipped rather than refused, since records of one kind often differ in
+optional fields. Already encrypted fields are left alone.
+
+The routes fall under the `crypto:encrypt` and `crypto:decrypt` entries of
+`MIDDLEWARE_ROUTE_SCOPES`, and are audited like their single-value
+counterparts, decryption taking a `purpose` the same way (see `purpose`).
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use tracing::error;
+
+use crate::audit::receipts::{self, RECEIPT_HEADER};
+use crate::audit::NewAuditEvent;
+use crate::auth::client_ip;
+use crate::errors::SecurityError;
+use super::{admit_read, audit_cache_served, audit_compromised_use, guard, purpose, CryptoService, DecryptionRequest, KeyState};
+
+const ENVELOPE_VERSION: &str = "v1";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+    /// Every member of an object or element of an array.
+    Wildcard,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    #[serde(rename = "$enc")]
+    version: String,
+    key_id: String,
+    nonce: String,
+    data: String,
+    context_hash: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DocumentEncryptionRequest {
+    pub document: Value,
+    pub fields: Vec<String>,
+    pub key_id: Option<String>,
+    pub context: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DocumentDecryptionRequest {
+    pub document: Value,
+    /// Every encrypted field when absent.
+    pub fields: Option<Vec<String>>,
+    pub context: Option<HashMap<String, String>>,
+    pub purpose: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DocumentResponse {
+    pub document: Value,
+    /// Paths of the fields encrypted or decrypted.
+    pub fields: Vec<String>,
+    /// Keys used, so callers can tell when to rewrap.
+    pub key_ids: Vec<String>,
+}
+
+fn invalid(selector: &str, problem: &str) -> SecurityError {
+    SecurityError::ValidationError(format!("Invalid selector '{}': {}", selector, problem))
+}
+
+fn parse_selector(selector: &str) -> Result<Vec<Segment>, SecurityError> {
+    // `supplier.cnpj` reads as `$.supplier.cnpj`
+    let trimmed = selector.trim();
+    let normalized = match trimmed.strip_prefix('$') {
+        Some(rest) => rest.to_string(),
+        None if trimmed.starts_with(['.', '[']) => trimmed.to_string(),
+        None => format!(".{}", trimmed),
+    };
+    let mut rest = normalized.as_str();
+    let mut segments = Vec::new();
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            let name = &after[..end];
+            segments.push(match name {
+                "" => return Err(invalid(selector, "empty field name")),
+                "*" => Segment::Wildcard,
+                name => Segment::Key(name.to_string()),
+            });
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = if after.starts_with(['\'', '"']) {
+                let quote = &after[..1];
+                after[1..].find(quote).map(|i| i + 2).ok_or_else(|| invalid(selector, "unclosed quote"))?
+            } else {
+                after.find(']').ok_or_else(|| invalid(selector, "unclosed bracket"))?
+            };
+            let inner = &after[..end];
+            segments.push(match inner {
+                "*" => Segment::Wildcard,
+                quoted if quoted.len() >= 2 && quoted.starts_with(['\'', '"']) => {
+                    Segment::Key(quoted[1..quoted.len() - 1].to_string())
+                }
+                index => Segment::Index(index.parse().map_err(|_| invalid(selector, "index must be a number, '*' or a quoted name"))?),
+            });
+            rest = after[end..].strip_prefix(']').ok_or_else(|| invalid(selector, "unclosed bracket"))?;
+        } else {
+            return Err(invalid(selector, "expected '.' or '['"));
+        }
+    }
+
+    if segments.is_empty() {
+        return Err(invalid(selector, "the whole document cannot be a field"));
+    }
+    Ok(segments)
+}
+
+fn child_path(path: &str, segment: &Segment) -> String {
+    match segment {
+        Segment::Key(key) if key.chars().all(|c| c.is_alphanumeric() || c == '_') => format!("{}.{}", path, key),
+        Segment::Key(key) => format!("{}['{}']", path, key),
+        Segment::Index(index) => format!("{}[{}]", path, index),
+        Segment::Wildcard => format!("{}.*", path),
+    }
+}
+
+/// Call `apply` on every value `segments` reach under `value`, with its path.
+fn visit<F>(value: &mut Value, segments: &[Segment], path: String, apply: &mut F) -> Result<(), SecurityError>
+where
+    F: FnMut(&mut Value, &str) -> Result<(), SecurityError>,
+{
+    let Some((segment, rest)) = segments.split_first() else {
+        return apply(value, &path);
+    };
+    match (segment, value) {
+        (Segment::Key(key), Value::Object(map)) => {
+            if let Some(child) = map.get_mut(key) {
+                visit(child, rest, child_path(&path, segment), apply)?;
+            }
+        }
+        (Segment::Index(index), Value::Array(items)) => {
+            if let Some(child) = items.get_mut(*index) {
+                visit(child, rest, child_path(&path, segment), apply)?;
+            }
+        }
+        (Segment::Wildcard, Value::Object(map)) => {
+            for (key, child) in map.iter_mut() {
+                visit(child, rest, child_path(&path, &Segment::Key(key.clone())), apply)?;
+            }
+        }
+        (Segment::Wildcard, Value::Array(items)) => {
+            for (index, child) in items.iter_mut().enumerate() {
+                visit(child, rest, child_path(&path, &Segment::Index(index)), apply)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Call `apply` on every envelope in `value`.
+fn visit_envelopes<F>(value: &mut Value, path: String, apply: &mut F) -> Result<(), SecurityError>
+where
+    F: FnMut(&mut Value, &str) -> Result<(), SecurityError>,
+{
+    if is_envelope(value) {
+        return apply(value, &path);
+    }
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                visit_envelopes(child, child_path(&path, &Segment::Key(key.clone())), apply)?;
+            }
+        }
+        Value::Array(items) => {
+            for (index, child) in items.iter_mut().enumerate() {
+                visit_envelopes(child, child_path(&path, &Segment::Index(index)), apply)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn is_envelope(value: &Value) -> bool {
+    value.get("$enc").and_then(Value::as_str) == Some(ENVELOPE_VERSION)
+}
+
+impl CryptoService {
+    pub async fn encrypt_document(
+        &self,
+        request: DocumentEncryptionRequest,
+        max_fields: usize,
+    ) -> Result<DocumentResponse, SecurityError> {
+        let selectors = request.fields.iter().map(|s| parse_selector(s)).collect::<Result<Vec<_>, _>>()?;
+        if selectors.is_empty() {
+            return Err(SecurityError::ValidationError("fields must not be empty".to_string()));
+        }
+        let key_id = self.resolve_key_id(request.key_id)?;
+        let context_hash = self.context_hash(request.context.as_ref())?;
+
+        let mut document = request.document;
+        let mut fields = Vec::new();
+        for segments in &selectors {
+            visit(&mut document, segments, "$".to_string(), &mut |value, path| {
+                if is_envelope(value) {
+                    return Ok(());
+                }
+                if fields.len() == max_fields {
+                    return Err(SecurityError::ValidationError(format!("At most {} fields can be encrypted", max_fields)));
+                }
+                let plaintext = serde_json::to_vec(value)
+                    .map_err(|e| SecurityError::CryptoError(format!("Field {} not serializable: {}", path, e)))?;
+                let sealed = self.seal(&key_id, plaintext, context_hash.clone())?;
+                self.note_use(&key_id, "encrypt");
+                *value = serde_json::to_value(Envelope {
+                    version: ENVELOPE_VERSION.to_string(),
+                    key_id: sealed.key_id,
+                    nonce: sealed.nonce,
+                    data: sealed.encrypted_data,
+                    context_hash: sealed.context_hash,
+                })
+                .map_err(|e| SecurityError::CryptoError(e.to_string()))?;
+                fields.push(path.to_string());
+                Ok(())
+            })?;
+        }
+
+        let key_ids = if fields.is_empty() { Vec::new() } else { vec![key_id] };
+        Ok(DocumentResponse { document, fields, key_ids })
+    }
+
+    pub async fn decrypt_document(
+        &self,
+        request: DocumentDecryptionRequest,
+        max_fields: usize,
+    ) -> Result<DocumentResponse, SecurityError> {
+        let selectors = match &request.fields {
+            Some(fields) => Some(fields.iter().map(|s| parse_selector(s)).collect::<Result<Vec<_>, _>>()?),
+            None => None,
+        };
+        let expected_context = self.context_hash(request.context.as_ref())?;
+
+        let mut document = request.document;
+        let mut fields = Vec::new();
+        let mut key_ids = BTreeSet::new();
+        let mut open = |value: &mut Value, path: &str| -> Result<(), SecurityError> {
+            if !is_envelope(value) {
+                return Ok(());
+            }
+            if fields.len() == max_fields {
+                return Err(SecurityError::ValidationError(format!("At most {} fields can be decrypted", max_fields)));
+            }
+            let envelope: Envelope = serde_json::from_value(value.clone())
+                .map_err(|e| SecurityError::ValidationError(format!("Field {} is not a valid envelope: {}", path, e)))?;
+            if request.context.is_some() && envelope.context_hash != expected_context {
+                return Err(SecurityError::ValidationError(format!("Field {} was encrypted under another context", path)));
+            }
+            let plaintext = self.open(&DecryptionRequest {
+                encrypted_data: envelope.data,
+                key_id: envelope.key_id.clone(),
+                nonce: envelope.nonce,
+                context_hash: envelope.context_hash,
+            })?;
+            self.note_use(&envelope.key_id, "decrypt");
+            *value = serde_json::from_slice(&plaintext)
+                .map_err(|_| SecurityError::CryptoError(format!("Field {} did not decrypt to JSON", path)))?;
+            fields.push(path.to_string());
+            key_ids.insert(envelope.key_id);
+            Ok(())
+        };
+        match &selectors {
+            Some(selectors) => {
+                for segments in selectors {
+                    visit(&mut document, segments, "$".to_string(), &mut open)?;
+                }
+            }
+            None => visit_envelopes(&mut document, "$".to_string(), &mut open)?,
+        }
+
+        Ok(DocumentResponse { document, fields, key_ids: key_ids.into_iter().collect() })
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError, operation: &str) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Document {} failed: {:?}", operation, e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Document {} failed", operation)
+            }))
+        }
+    }
+}
+
+pub async fn encrypt_document_handler(
+    request: web::Json<DocumentEncryptionRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let max_fields = state.config.crypto.document_max_fields;
+    match state.crypto_service.encrypt_document(request.into_inner(), max_fields).await {
+        Ok(response) => {
+            for key_id in &response.key_ids {
+                if state.crypto_service.served_from_cache(key_id) {
+                    audit_cache_served(&state, "encrypt", key_id).await;
+                }
+            }
+            Ok(HttpResponse::Ok().json(response))
+        }
+        Err(e) => Ok(error_response(e, "encryption")),
+    }
+}
+
+/// As `decrypt_receipt`, naming the document by the hash of what was sent.
+async fn decrypt_document_receipt(
+    state: &crate::AppState,
+    req: &HttpRequest,
+    document: &Value,
+    response: &DocumentResponse,
+    purpose: Option<&str>,
+) -> Option<String> {
+    if purpose.is_none() && !state.config.audit.receipt_actions.iter().any(|action| action == "crypto.decrypt") {
+        return None;
+    }
+    let principal = state.auth_service.authenticate(req).ok();
+    let sent = serde_json::to_vec(document).unwrap_or_default();
+    receipts::record_or_warn(state, NewAuditEvent {
+        tenant_id: principal.as_ref().and_then(|p| p.tenant_id.clone()),
+        actor: principal.map_or_else(|| "anonymous".to_string(), |p| p.subject),
+        actor_ip: client_ip(req),
+        action: "crypto.decrypt".to_string(),
+        resource: format!("document:{}", hex::encode(digest(&SHA256, &sent))),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "key_ids": response.key_ids,
+            "fields": response.fields,
+            "purpose": purpose
+        }),
+    }).await
+}
+
+pub async fn decrypt_document_handler(
+    req: HttpRequest,
+    request: web::Json<DocumentDecryptionRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    let tenant_id = state.auth_service.authenticate(&req).ok().and_then(|principal| principal.tenant_id);
+    let purpose = match purpose::check(&state, tenant_id.as_deref(), request.purpose.as_deref(), false).await {
+        Ok(purpose) => purpose,
+        Err(e) => return Ok(error_response(e, "decryption")),
+    };
+    if let Err(e) = admit_read(&state, &req, 1).await {
+        return Ok(guard::paused_response(&e));
+    }
+    let sent = request.document.clone();
+    let max_fields = state.config.crypto.document_max_fields;
+    match state.crypto_service.decrypt_document(request, max_fields).await {
+        Ok(response) => {
+            for key_id in &response.key_ids {
+                if state.crypto_service.served_from_cache(key_id) {
+                    audit_cache_served(&state, "decrypt", key_id).await;
+                }
+                if state.crypto_service.key_state(key_id) == Some(KeyState::Compromised) {
+                    audit_compromised_use(&state, "decrypt", key_id).await;
+                }
+            }
+            let mut builder = HttpResponse::Ok();
+            if let Some(receipt) = decrypt_document_receipt(&state, &req, &sent, &response, purpose.as_deref()).await {
+                builder.insert_header((RECEIPT_HEADER, receipt));
+            }
+            Ok(builder.json(response))
+        }
+        Err(e) => Ok(error_response(e, "decryption")),
+    }
+}
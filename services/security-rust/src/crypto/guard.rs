@@ -0,0 +1,593 @@
+/*!
+Read Volume Guard
+Tripwire pausing callers whose plaintext reads run far over their baseline
+
+Every call that hands back plaintext (decryption in all its forms, over
+HTTP or gRPC, and detokenization, which counts once per token) is counted
+per caller in windows of `CRYPTO_GUARD_WINDOW_SECS`. Instances share their
+counts through the database every `CRYPTO_GUARD_SYNC_INTERVAL_SECS`.
+
+A caller's baseline is their busiest window over the last
+`CRYPTO_GUARD_LOOKBACK_DAYS`, once they have `CRYPTO_GUARD_MIN_WINDOWS`
+windows of history, and never less than `CRYPTO_GUARD_MIN_BASELINE`.
+Reading more than `CRYPTO_GUARD_FACTOR` times the baseline in one window
+trips the guard:
+
+- the caller is held: every further plaintext read is refused with
+  `423 Locked` until an admin releases the hold
+- a `crypto_volume` incident is opened against the caller, which alerts
+  the SOC and runs the containment playbooks and SOAR integrations
+- the hold is audited as `crypto.guard.hold`
+
+Admins review holds at `GET /admin/crypto/holds` and release one with
+`POST /admin/crypto/holds/{id}/release`, audited as `crypto.guard.release`;
+nobody releases their own. A released caller may read as much again in the
+same window before the guard trips anew. Callers first seen since the last
+sync are counted but only checked once it has learned their limit, so the
+guard trips at most one sync interval late. Subjects in
+`CRYPTO_GUARD_EXEMPT_SUBJECTS` are never counted.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::alerting::Severity;
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, CryptoGuardConfig};
+use crate::detection::{self, NewIncident};
+use crate::errors::SecurityError;
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::Storage;
+use crate::AppState;
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "created_at", column: "created_at", kind: KeyKind::Timestamp },
+];
+
+const HOLD_COLUMNS: &str = "id, caller, tenant_id, window_start, observed, threshold, baseline, learned, \
+    incident_id, status, created_at, released_by, released_at, note";
+
+/// Who holds are audited as.
+const SYSTEM_ACTOR: &str = "system:crypto_guard";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Hold {
+    pub id: Uuid,
+    pub caller: String,
+    pub tenant_id: Option<String>,
+    pub window_start: DateTime<Utc>,
+    pub observed: i64,
+    pub threshold: i64,
+    pub baseline: i64,
+    pub learned: bool,
+    pub incident_id: Option<Uuid>,
+    /// `held` or `released`.
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub released_by: Option<String>,
+    pub released_at: Option<DateTime<Utc>>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HoldFilter {
+    pub status: Option<String>,
+    pub caller: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReleaseRequest {
+    pub note: Option<String>,
+}
+
+/// A caller's limit for the current window.
+#[derive(Debug, Clone, Copy)]
+struct Limit {
+    threshold: i64,
+    baseline: i64,
+    learned: bool,
+}
+
+/// What one instance knows about the current window.
+#[derive(Debug, Default)]
+struct Counts {
+    window_start: Option<DateTime<Utc>>,
+    /// Counted here since the last sync, with the caller's tenant.
+    pending: HashMap<String, (Option<String>, i64)>,
+    /// Totals across instances as of the last sync.
+    synced: HashMap<String, i64>,
+    limits: HashMap<String, Limit>,
+    held: HashSet<String>,
+}
+
+/// A caller over their limit, not yet held.
+struct Trip {
+    caller: String,
+    tenant_id: Option<String>,
+    observed: i64,
+    limit: Limit,
+}
+
+pub struct ReadGuard {
+    storage: Storage,
+    config: CryptoGuardConfig,
+    counts: Mutex<Counts>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ReadGuard {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+
+        info!("Read volume guard initialized successfully");
+        Ok(Self {
+            storage,
+            config: config.crypto_guard.clone(),
+            counts: Mutex::new(Counts::default()),
+            clock,
+        })
+    }
+
+    fn window_start(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let secs = now.timestamp();
+        DateTime::from_timestamp(secs - secs.rem_euclid(self.config.window_secs), 0).unwrap_or(now)
+    }
+
+    fn counts(&self) -> std::sync::MutexGuard<'_, Counts> {
+        self.counts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Count `operations` reads by `caller`, returning the trip when they
+    /// take a caller with a known limit over it.
+    fn count(&self, caller: &str, tenant_id: Option<&str>, operations: i64) -> Result<Option<Trip>, SecurityError> {
+        let window_start = self.window_start(self.clock.now());
+        let mut counts = self.counts();
+        if counts.held.contains(caller) {
+            return Err(SecurityError::AccessDenied(
+                "Plaintext reads are paused pending review of unusual volume".to_string(),
+            ));
+        }
+        if counts.window_start != Some(window_start) {
+            // Unsynced counts of the last window carry over rather than vanish
+            counts.window_start = Some(window_start);
+            counts.synced.clear();
+            counts.limits.clear();
+        }
+
+        let pending = counts.pending.entry(caller.to_string()).or_insert((tenant_id.map(str::to_string), 0));
+        pending.1 += operations;
+        let observed = pending.1 + counts.synced.get(caller).copied().unwrap_or(0);
+        match counts.limits.get(caller).copied() {
+            Some(limit) if observed > limit.threshold => {
+                counts.held.insert(caller.to_string());
+                Ok(Some(Trip {
+                    caller: caller.to_string(),
+                    tenant_id: tenant_id.map(str::to_string),
+                    observed,
+                    limit,
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Reload the callers currently held.
+    pub async fn refresh(&self) -> Result<(), SecurityError> {
+        let held: Vec<String> = sqlx::query_scalar("SELECT caller FROM crypto_holds WHERE status = 'held'")
+            .fetch_all(self.storage.pool())
+            .await?;
+        self.counts().held = held.into_iter().collect();
+        Ok(())
+    }
+
+    /// Share this instance's counts, learn the limits of the window's
+    /// callers and return those over them.
+    async fn sync(&self) -> Result<Vec<Trip>, SecurityError> {
+        let window_start = self.window_start(self.clock.now());
+        let pending = std::mem::take(&mut self.counts().pending);
+        if let Err(e) = self.flush(window_start, &pending).await {
+            let mut counts = self.counts();
+            for (caller, (tenant_id, operations)) in pending {
+                counts.pending.entry(caller).or_insert((tenant_id, 0)).1 += operations;
+            }
+            return Err(e);
+        }
+
+        let totals = sqlx::query_as::<_, (String, Option<String>, i64)>(
+            "SELECT caller, tenant_id, operations FROM crypto_read_volume WHERE window_start = $1",
+        )
+        .bind(window_start)
+        .fetch_all(self.storage.pool())
+        .await?;
+        let callers: Vec<&str> = totals.iter().map(|(caller, _, _)| caller.as_str()).collect();
+
+        // Busiest earlier window and how many windows with reads
+        let history: HashMap<String, (i64, i64)> = sqlx::query_as::<_, (String, i64, i64)>(
+            "SELECT caller, MAX(operations), COUNT(*) FROM crypto_read_volume \
+             WHERE window_start >= $1 AND window_start < $2 AND caller = ANY($3) GROUP BY caller",
+        )
+        .bind(window_start - Duration::days(self.config.lookback_days))
+        .bind(window_start)
+        .bind(&callers)
+        .fetch_all(self.storage.pool())
+        .await?
+        .into_iter()
+        .map(|(caller, peak, windows)| (caller, (peak, windows)))
+        .collect();
+
+        // Reads already reviewed and released this window
+        let released: HashMap<String, i64> = sqlx::query_as::<_, (String, i64)>(
+            "SELECT caller, MAX(observed) FROM crypto_holds \
+             WHERE status = 'released' AND window_start = $1 GROUP BY caller",
+        )
+        .bind(window_start)
+        .fetch_all(self.storage.pool())
+        .await?
+        .into_iter()
+        .collect();
+
+        let held: HashSet<String> = sqlx::query_scalar("SELECT caller FROM crypto_holds WHERE status = 'held'")
+            .fetch_all(self.storage.pool())
+            .await?
+            .into_iter()
+            .collect();
+
+        let mut trips = Vec::new();
+        let mut counts = self.counts();
+        counts.window_start = Some(window_start);
+        counts.synced.clear();
+        counts.limits.clear();
+        for (caller, tenant_id, observed) in totals {
+            let limit = self.limit(history.get(&caller).copied(), released.get(&caller).copied());
+            if observed > limit.threshold && !held.contains(&caller) {
+                trips.push(Trip { caller: caller.clone(), tenant_id, observed, limit });
+            }
+            counts.limits.insert(caller.clone(), limit);
+            counts.synced.insert(caller, observed);
+        }
+        counts.held = held;
+        counts.held.extend(trips.iter().map(|trip| trip.caller.clone()));
+        Ok(trips)
+    }
+
+    fn limit(&self, history: Option<(i64, i64)>, released: Option<i64>) -> Limit {
+        let learned = history.filter(|(_, windows)| *windows >= self.config.min_windows).map(|(peak, _)| peak);
+        let baseline = learned.unwrap_or(0).max(self.config.min_baseline);
+        let threshold = (baseline as f64 * self.config.factor).ceil() as i64 + released.unwrap_or(0);
+        Limit { threshold, baseline, learned: learned.is_some_and(|peak| peak >= self.config.min_baseline) }
+    }
+
+    async fn flush(
+        &self,
+        window_start: DateTime<Utc>,
+        pending: &HashMap<String, (Option<String>, i64)>,
+    ) -> Result<(), SecurityError> {
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let mut builder: QueryBuilder<Postgres> =
+            QueryBuilder::new("INSERT INTO crypto_read_volume (caller, window_start, tenant_id, operations) ");
+        builder.push_values(pending, |mut row, (caller, (tenant_id, operations))| {
+            row.push_bind(caller).push_bind(window_start).push_bind(tenant_id).push_bind(*operations);
+        });
+        builder.push(
+            " ON CONFLICT (caller, window_start) DO UPDATE \
+             SET operations = crypto_read_volume.operations + EXCLUDED.operations",
+        );
+        builder.build().execute(self.storage.pool()).await?;
+        Ok(())
+    }
+
+    /// Drop counts older than any baseline looks back.
+    async fn purge(&self) -> Result<u64, SecurityError> {
+        let cutoff = self.window_start(self.clock.now()) - Duration::days(self.config.lookback_days + 1);
+        let result = sqlx::query("DELETE FROM crypto_read_volume WHERE window_start < $1")
+            .bind(cutoff)
+            .execute(self.storage.pool())
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn list(&self, filter: &HoldFilter, page: &PageRequest) -> Result<Page<Hold>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM crypto_holds WHERE 1 = 1",
+            HOLD_COLUMNS
+        ));
+        if let Some(status) = &filter.status {
+            builder.push(" AND status = ").push_bind(status.clone());
+        }
+        if let Some(caller) = &filter.caller {
+            builder.push(" AND caller = ").push_bind(caller.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let holds = builder
+            .build_query_as::<Hold>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(holds, |hold, _| (SortKey::Timestamp(hold.created_at), hold.id)))
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<Hold, SecurityError> {
+        sqlx::query_as::<_, Hold>(&format!("SELECT {} FROM crypto_holds WHERE id = $1", HOLD_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound("Hold not found".to_string()))
+    }
+
+    pub async fn release(&self, actor: &Principal, id: Uuid, note: Option<String>) -> Result<Hold, SecurityError> {
+        let hold = self.get(id).await?;
+        if hold.caller == actor.subject {
+            return Err(SecurityError::AccessDenied("A hold cannot be released by its caller".to_string()));
+        }
+        let released = sqlx::query_as::<_, Hold>(&format!(
+            "UPDATE crypto_holds SET status = 'released', released_by = $2, released_at = $3, note = $4 \
+             WHERE id = $1 AND status = 'held' RETURNING {}",
+            HOLD_COLUMNS
+        ))
+        .bind(id)
+        .bind(&actor.subject)
+        .bind(self.clock.now())
+        .bind(note)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::Conflict(format!("Hold {} is already {}", id, hold.status)))?;
+
+        let mut counts = self.counts();
+        counts.held.remove(&released.caller);
+        // Lifted here at once; the next sync raises the limit everywhere
+        if let Some(limit) = counts.limits.get_mut(&released.caller) {
+            limit.threshold += released.observed;
+        }
+        Ok(released)
+    }
+
+    /// Store a hold and open its incident. `None` when the caller was
+    /// already held, e.g. by another instance.
+    async fn hold(&self, state: &AppState, trip: &Trip) -> Result<Option<(Hold, detection::Opened)>, SecurityError> {
+        let now = self.clock.now();
+        let window_start = self.window_start(now);
+        let mut tx = self.storage.begin().await?;
+        let Some(hold) = sqlx::query_as::<_, Hold>(&format!(
+            "INSERT INTO crypto_holds (id, caller, tenant_id, window_start, observed, threshold, baseline, learned) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT (caller) WHERE status = 'held' DO NOTHING \
+             RETURNING {}",
+            HOLD_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(&trip.caller)
+        .bind(&trip.tenant_id)
+        .bind(window_start)
+        .bind(trip.observed)
+        .bind(trip.limit.threshold)
+        .bind(trip.limit.baseline)
+        .bind(trip.limit.learned)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let opened = detection::open(state, &mut tx, NewIncident {
+            rule: "crypto_volume".to_string(),
+            severity: Severity::High,
+            tenant_id: trip.tenant_id.clone(),
+            entity_type: "actor",
+            entity: trip.caller.clone(),
+            event_ids: Vec::new(),
+            first_seen: window_start,
+            last_seen: now,
+        }).await?;
+        let hold = sqlx::query_as::<_, Hold>(&format!(
+            "UPDATE crypto_holds SET incident_id = $2 WHERE id = $1 RETURNING {}",
+            HOLD_COLUMNS
+        ))
+        .bind(hold.id)
+        .bind(opened.incident.id)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(Some((hold, opened)))
+    }
+
+    /// Hold tripped callers, alert and audit.
+    async fn intervene(&self, state: &AppState, trips: Vec<Trip>) {
+        for trip in trips {
+            match self.hold(state, &trip).await {
+                Ok(Some((hold, opened))) => {
+                    warn!(
+                        "Held {} after {} plaintext reads (limit {}, baseline {})",
+                        hold.caller, hold.observed, hold.threshold, hold.baseline
+                    );
+                    detection::announce(state, &[opened]).await;
+                    audit(state, SYSTEM_ACTOR, "crypto.guard.hold", &hold).await;
+                }
+                Ok(None) => {}
+                Err(e) => error!("Failed to hold {}: {:?}", trip.caller, e),
+            }
+        }
+    }
+}
+
+/// Count a plaintext read of `operations` values against its caller,
+/// refusing callers that are held or have just tripped the guard.
+pub async fn admit(
+    state: &AppState,
+    principal: Option<&Principal>,
+    ip: Option<&str>,
+    operations: i64,
+) -> Result<(), SecurityError> {
+    let guard = &state.crypto_guard;
+    if !guard.config.enabled {
+        return Ok(());
+    }
+    let (caller, tenant_id) = match principal {
+        Some(principal) if guard.config.exempt_subjects.contains(&principal.subject) => return Ok(()),
+        Some(principal) => (principal.subject.clone(), principal.tenant_id.as_deref()),
+        None => (format!("ip:{}", ip.unwrap_or("unknown")), None),
+    };
+    let Some(trip) = guard.count(&caller, tenant_id, operations)? else {
+        return Ok(());
+    };
+    guard.intervene(state, vec![trip]).await;
+    Err(SecurityError::AccessDenied(
+        "Plaintext reads are paused pending review of unusual volume".to_string(),
+    ))
+}
+
+/// The response for a read `admit` refused.
+pub fn paused_response(e: &SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::AccessDenied(msg) => HttpResponse::Locked().json(serde_json::json!({
+            "error": msg,
+            "code": "reads_paused"
+        })),
+        e => auth_error_response(e),
+    }
+}
+
+/// Share counts between instances and hold callers over their limit.
+pub async fn run_sync(state: web::Data<AppState>) {
+    if !state.config.crypto_guard.enabled {
+        return;
+    }
+    let interval_secs = state.config.crypto_guard.sync_interval_secs;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+    let mut last_purge: Option<DateTime<Utc>> = None;
+    loop {
+        interval.tick().await;
+        match state.crypto_guard.sync().await {
+            Ok(trips) => state.crypto_guard.intervene(&state, trips).await,
+            Err(e) => error!("Read volume sync failed: {:?}", e),
+        }
+        let now = state.clock.now();
+        if last_purge.is_none_or(|at| now - at >= Duration::hours(1)) {
+            match state.crypto_guard.purge().await {
+                Ok(0) => {}
+                Ok(count) => info!("Purged {} read volume windows", count),
+                Err(e) => warn!("Read volume purge failed: {:?}", e),
+            }
+            last_purge = Some(now);
+        }
+    }
+}
+
+async fn audit(state: &AppState, actor: &str, action: &str, hold: &Hold) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: hold.tenant_id.clone(),
+        actor: actor.to_string(),
+        actor_ip: None,
+        action: action.to_string(),
+        resource: format!("crypto_hold:{}", hold.id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "caller": hold.caller,
+            "observed": hold.observed,
+            "threshold": hold.threshold,
+            "baseline": hold.baseline,
+            "learned": hold.learned,
+            "incident_id": hold.incident_id,
+            "note": hold.note
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} of hold {}: {:?}", action, hold.id, e);
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::AccessDenied(msg) => HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Hold operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Hold operation failed"
+            }))
+        }
+    }
+}
+
+pub async fn list_holds_handler(
+    req: HttpRequest,
+    filter: web::Query<HoldFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.crypto_guard.list(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "holds": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_hold_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.crypto_guard.get(path.into_inner()).await {
+        Ok(hold) => Ok(HttpResponse::Ok().json(hold)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn release_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: Option<web::Json<ReleaseRequest>>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let note = request.and_then(|request| request.into_inner().note);
+    match state.crypto_guard.release(&principal, path.into_inner(), note).await {
+        Ok(hold) => {
+            info!("Hold {} on {} released by {}", hold.id, hold.caller, principal.subject);
+            audit(&state, &principal.subject, "crypto.guard.release", &hold).await;
+            Ok(HttpResponse::Ok().json(hold))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
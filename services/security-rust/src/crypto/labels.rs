@@ -0,0 +1,215 @@
+/*!
+Labeled Encryption
+Ciphertext bound to access labels, opened only for callers the labels admit
+
+`POST /crypto/encrypt-labeled` seals data for a label policy (see
+`auth::labels`) and returns an envelope carrying the policy in clear:
+
+```json
+{ "$enc": "labels-v1", "labels": { "any_of": [["tenant:t1", "clearance:secret"]] },
+  "key_id": "...", "nonce": "...", "data": "..." }
+```
+
+The canonical policy is hashed into the AAD, so editing the labels makes
+the envelope undecryptable rather than more permissive. On
+`POST /crypto/decrypt-labeled` the caller's own token is checked against
+the labels by the auth module before anything is decrypted; holding
+`crypto:decrypt` is not enough, and admins get no exemption. Envelopes can
+therefore be shared through storage and queues that every party reads.
+
+Both routes fall under the `crypto:encrypt` and `crypto:decrypt` entries of
+`MIDDLEWARE_ROUTE_SCOPES`. Decryptions and refusals are both audited as
+`crypto.decrypt_labeled`, with the declared `purpose` (see `purpose`).
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::error;
+
+use crate::audit::receipts::{self, RECEIPT_HEADER};
+use crate::audit::NewAuditEvent;
+use crate::auth::labels::LabelPolicy;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::errors::SecurityError;
+use super::{audit_cache_served, audit_compromised_use, guard, purpose, CryptoService, DecryptionRequest, KeyState};
+
+const ENVELOPE_VERSION: &str = "labels-v1";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LabeledEnvelope {
+    #[serde(rename = "$enc")]
+    pub version: String,
+    pub labels: LabelPolicy,
+    pub key_id: String,
+    pub nonce: String,
+    pub data: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LabeledEncryptionRequest {
+    pub data: String,
+    pub labels: LabelPolicy,
+    pub key_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LabeledDecryptionRequest {
+    pub envelope: LabeledEnvelope,
+    pub purpose: Option<String>,
+}
+
+impl CryptoService {
+    /// AAD binding the ciphertext to exactly these labels.
+    fn labels_hash(&self, labels: &LabelPolicy) -> Result<Option<String>, SecurityError> {
+        let canonical = serde_json::to_string(labels)
+            .map_err(|e| SecurityError::CryptoError(e.to_string()))?;
+        self.context_hash(Some(&HashMap::from([("access_labels".to_string(), canonical)])))
+    }
+
+    /// Seal `data` for `labels`, which must already be normalized.
+    pub async fn encrypt_labeled(
+        &self,
+        data: String,
+        labels: LabelPolicy,
+        key_id: Option<String>,
+    ) -> Result<LabeledEnvelope, SecurityError> {
+        let key_id = self.resolve_key_id(key_id)?;
+        let sealed = self.seal(&key_id, data.into_bytes(), self.labels_hash(&labels)?)?;
+        self.note_use(&key_id, "encrypt");
+        Ok(LabeledEnvelope {
+            version: ENVELOPE_VERSION.to_string(),
+            labels,
+            key_id: sealed.key_id,
+            nonce: sealed.nonce,
+            data: sealed.encrypted_data,
+        })
+    }
+
+    /// Open an envelope. Callers check the labels first.
+    pub async fn decrypt_labeled(&self, envelope: LabeledEnvelope) -> Result<String, SecurityError> {
+        if envelope.version != ENVELOPE_VERSION {
+            return Err(SecurityError::ValidationError(format!("Unsupported envelope version '{}'", envelope.version)));
+        }
+        let plaintext = self.open(&DecryptionRequest {
+            context_hash: self.labels_hash(&envelope.labels)?,
+            encrypted_data: envelope.data,
+            key_id: envelope.key_id.clone(),
+            nonce: envelope.nonce,
+        })?;
+        self.note_use(&envelope.key_id, "decrypt");
+        String::from_utf8(plaintext).map_err(|_| SecurityError::CryptoError("Invalid UTF-8 data".to_string()))
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError, operation: &str) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Labeled {} failed: {:?}", operation, e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Labeled {} failed", operation)
+            }))
+        }
+    }
+}
+
+async fn audit_decrypt(
+    state: &crate::AppState,
+    req: &HttpRequest,
+    principal: &Principal,
+    envelope: &LabeledEnvelope,
+    purpose: Option<&str>,
+    outcome: &str,
+) -> Option<String> {
+    receipts::record_or_warn(state, NewAuditEvent {
+        tenant_id: principal.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(req),
+        action: "crypto.decrypt_labeled".to_string(),
+        resource: format!("ciphertext:{}", hex::encode(digest(&SHA256, envelope.data.as_bytes()))),
+        outcome: outcome.to_string(),
+        payload: serde_json::json!({
+            "key_id": envelope.key_id,
+            "labels": envelope.labels,
+            "purpose": purpose
+        }),
+    }).await
+}
+
+pub async fn encrypt_labeled_handler(
+    request: web::Json<LabeledEncryptionRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let request = request.into_inner();
+    let labels = match request.labels.normalize(state.auth_service.clearance_levels()) {
+        Ok(labels) => labels,
+        Err(e) => return Ok(error_response(e, "encryption")),
+    };
+
+    match state.crypto_service.encrypt_labeled(request.data, labels, request.key_id).await {
+        Ok(envelope) => {
+            if state.crypto_service.served_from_cache(&envelope.key_id) {
+                audit_cache_served(&state, "encrypt", &envelope.key_id).await;
+            }
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "envelope": envelope
+            })))
+        }
+        Err(e) => Ok(error_response(e, "encryption")),
+    }
+}
+
+pub async fn decrypt_labeled_handler(
+    req: HttpRequest,
+    request: web::Json<LabeledDecryptionRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let LabeledDecryptionRequest { envelope, purpose } = request.into_inner();
+    let purpose = match purpose::check(&state, principal.tenant_id.as_deref(), purpose.as_deref(), false).await {
+        Ok(purpose) => purpose,
+        Err(e) => return Ok(error_response(e, "decryption")),
+    };
+    if let Err(e) = state.auth_service.check_labels(&principal, &envelope.labels) {
+        audit_decrypt(&state, &req, &principal, &envelope, purpose.as_deref(), "denied").await;
+        return Ok(auth_error_response(&e));
+    }
+    if let Err(e) = guard::admit(&state, Some(&principal), client_ip(&req).as_deref(), 1).await {
+        return Ok(guard::paused_response(&e));
+    }
+
+    let key_id = envelope.key_id.clone();
+    match state.crypto_service.decrypt_labeled(envelope.clone()).await {
+        Ok(data) => {
+            if state.crypto_service.served_from_cache(&key_id) {
+                audit_cache_served(&state, "decrypt", &key_id).await;
+            }
+            if state.crypto_service.key_state(&key_id) == Some(KeyState::Compromised) {
+                audit_compromised_use(&state, "decrypt", &key_id).await;
+            }
+            let mut response = HttpResponse::Ok();
+            if let Some(receipt) = audit_decrypt(&state, &req, &principal, &envelope, purpose.as_deref(), "success").await {
+                response.insert_header((RECEIPT_HEADER, receipt));
+            }
+            Ok(response.json(serde_json::json!({
+                "data": data
+            })))
+        }
+        Err(e) => Ok(error_response(e, "decryption")),
+    }
+}
@@ -0,0 +1,278 @@
+/*!
+Purpose of Use
+Declared reasons for reading plaintext, checked per tenant and reported for accountability
+
+Calls that hand back plaintext state why: `purpose` in the body of
+`POST /crypto/decrypt`, `/crypto/decrypt-batch`, `/crypto/decrypt-document`,
+`/crypto/decrypt-labeled` and `/crypto/detokenize`, or the `X-Purpose-Of-Use`
+header on `/crypto/decrypt-stream`, whose body is the ciphertext. A purpose
+is a code from `TENANT_PURPOSES`, which a tenant may narrow with
+`allowed_purposes`; anything else is refused. Detokenization and batches
+needing a bulk-decrypt grant always need one, decryption when
+`purpose_required` is on for the tenant (`TENANT_PURPOSE_REQUIRED` turns it
+on for all).
+
+The declared purpose goes into the call's audit event as `payload.purpose`,
+and a decryption that declares one is audited whether or not it gets a
+receipt. `GET /crypto/purposes` lists what the caller may declare;
+`GET /crypto/purposes/report` counts successful reads per tenant, action and
+purpose over a window, or over a `period` (`day`, `week` or `month`) holding
+`date` in the tenant's time zone (see `timezone`), for the LGPD
+accountability record. The report is open to audit readers, tenant admins
+seeing their own tenant only; outside the SOC its window is widened to
+whole days, its counts are noised and small ones withheld, and each new
+window spends privacy budget (see `privacy`).
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::BTreeMap;
+use tracing::{error, warn};
+
+use crate::audit::visibility::AuditView;
+use crate::auth::auth_error_response;
+use crate::errors::SecurityError;
+use crate::privacy::{self, NoiseLayer, Released};
+use crate::storage::Storage;
+use crate::timezone::{self, Period};
+use crate::AppState;
+
+pub const PURPOSE_HEADER: &str = "X-Purpose-Of-Use";
+
+/// Audit actions of calls that release plaintext.
+pub(crate) const ACTIONS: &[&str] = &["crypto.decrypt", "crypto.decrypt_batch", "crypto.decrypt_labeled", "crypto.detokenize"];
+
+const DEFAULT_REPORT_DAYS: i64 = 30;
+const MAX_REPORT_DAYS: i64 = 366;
+/// Released counts one read can move: its group's reads and actors, and its purpose's total.
+const RELEASED_PER_READ: u32 = 3;
+
+/// The purpose declared for a plaintext read, checked against what the
+/// tenant allows; `None` when none was declared and none is needed.
+/// `always` needs one whatever the tenant setting.
+pub async fn check(
+    state: &AppState,
+    tenant_id: Option<&str>,
+    purpose: Option<&str>,
+    always: bool,
+) -> Result<Option<String>, SecurityError> {
+    let settings = state.tenant_settings.effective(tenant_id).await;
+    let allowed = || settings.allowed_purposes.join(", ");
+    match purpose.map(str::trim).filter(|purpose| !purpose.is_empty()) {
+        None if always || settings.purpose_required => Err(SecurityError::ValidationError(format!(
+            "A purpose is required; declare one of {}",
+            allowed()
+        ))),
+        None => Ok(None),
+        Some(purpose) if settings.allowed_purposes.iter().any(|p| p == purpose) => Ok(Some(purpose.to_string())),
+        Some(purpose) => Err(SecurityError::ValidationError(format!(
+            "'{}' is not an allowed purpose; declare one of {}",
+            purpose,
+            allowed()
+        ))),
+    }
+}
+
+pub fn from_header(req: &HttpRequest) -> Option<&str> {
+    req.headers().get(PURPOSE_HEADER).and_then(|value| value.to_str().ok())
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct PurposeCount {
+    pub tenant_id: Option<String>,
+    pub action: String,
+    /// `None` for reads that declared no purpose.
+    pub purpose: Option<String>,
+    pub reads: i64,
+    pub actors: i64,
+}
+
+/// A `PurposeCount` as released outside the SOC.
+#[derive(Debug, Serialize)]
+pub struct ReleasedPurposeCount {
+    pub tenant_id: Option<String>,
+    pub action: String,
+    pub purpose: Option<String>,
+    pub reads: Released,
+    pub actors: Released,
+}
+
+impl ReleasedPurposeCount {
+    fn new(count: PurposeCount, noise: &NoiseLayer, window: &str) -> Self {
+        let cell = |name: &str| {
+            format!(
+                "purpose.{}:{}:{}:{}:{}",
+                name,
+                count.tenant_id.as_deref().unwrap_or_default(),
+                window,
+                count.action,
+                count.purpose.as_deref().unwrap_or_default()
+            )
+        };
+        Self {
+            reads: noise.count(&cell("reads"), count.reads),
+            actors: noise.count(&cell("actors"), count.actors),
+            tenant_id: count.tenant_id,
+            action: count.action,
+            purpose: count.purpose,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportQuery {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Ignored for tenant admins, who only see their own tenant.
+    pub tenant_id: Option<String>,
+    /// Replaces `since` and `until` with this local period.
+    pub period: Option<Period>,
+    pub date: Option<NaiveDate>,
+}
+
+/// Successful plaintext reads in `[since, until)` by tenant, action and purpose.
+pub async fn distribution(
+    storage: &Storage,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    tenant_id: Option<&str>,
+) -> Result<Vec<PurposeCount>, SecurityError> {
+    let counts = sqlx::query_as::<_, PurposeCount>(
+        "SELECT tenant_id, action, payload->>'purpose' AS purpose, \
+         COUNT(*) AS reads, COUNT(DISTINCT actor) AS actors \
+         FROM audit_events WHERE occurred_at >= $1 AND occurred_at < $2 AND action = ANY($3) \
+         AND outcome = 'success' AND ($4::text IS NULL OR tenant_id = $4) \
+         GROUP BY 1, 2, 3 ORDER BY 1 NULLS FIRST, 2, 4 DESC",
+    )
+    .bind(since)
+    .bind(until)
+    .bind(ACTIONS)
+    .bind(tenant_id)
+    .fetch_all(storage.pool())
+    .await?;
+    Ok(counts)
+}
+
+// HTTP handlers
+
+pub async fn catalog_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let settings = state.tenant_settings.effective(principal.tenant_id.as_deref()).await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "purposes": settings.allowed_purposes,
+        "required": settings.purpose_required
+    })))
+}
+
+pub async fn report_handler(
+    req: HttpRequest,
+    query: web::Query<ReportQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let view = match AuditView::for_principal(&principal, &state.config.audit) {
+        Ok(view) => view,
+        Err(e) => {
+            warn!("Purpose report denied for {}: {:?}", principal.subject, e);
+            return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "Insufficient privileges for audit data"
+            })));
+        }
+    };
+
+    let query = query.into_inner();
+    let tenant_id = match &view {
+        AuditView::Tenant { tenant_id } => Some(tenant_id.clone()),
+        _ => query.tenant_id,
+    };
+    let zone = state.tenant_settings.effective(tenant_id.as_deref()).await.zone();
+    let (since, until) = match query.period {
+        Some(period) => {
+            let date = query.date.unwrap_or_else(|| timezone::local_date(zone, state.clock.now()));
+            timezone::period_bounds(zone, period, date)
+        }
+        None => {
+            let until = query.until.unwrap_or_else(|| state.clock.now());
+            (query.since.unwrap_or(until - Duration::days(DEFAULT_REPORT_DAYS)), until)
+        }
+    };
+    if since >= until || until - since > Duration::days(MAX_REPORT_DAYS) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("since must be before until, at most {} days apart", MAX_REPORT_DAYS)
+        })));
+    }
+    let (since, until) = match view {
+        AuditView::Full => (since, until),
+        _ => privacy::bucket(zone, since, until),
+    };
+
+    match distribution(&state.storage, since, until, tenant_id.as_deref()).await {
+        Ok(counts) => {
+            let mut totals: BTreeMap<String, i64> = BTreeMap::new();
+            for count in &counts {
+                *totals.entry(count.purpose.clone().unwrap_or_else(|| "undeclared".to_string())).or_default() += count.reads;
+            }
+            if view == AuditView::Full {
+                return Ok(HttpResponse::Ok().json(serde_json::json!({
+                    "since": since,
+                    "until": until,
+                    "tenant_id": tenant_id,
+                    "privacy": null,
+                    "totals": totals,
+                    "counts": counts
+                })));
+            }
+            let mut tenants: Vec<String> = match &tenant_id {
+                Some(tenant_id) => vec![tenant_id.clone()],
+                None => counts.iter().map(|count| count.tenant_id.clone().unwrap_or_default()).collect(),
+            };
+            tenants.dedup();
+            match state.noise.spend(&state.storage, "purpose", &tenants, RELEASED_PER_READ, (since, until), state.clock.now()).await {
+                Ok(true) => {}
+                Ok(false) => return Ok(privacy::budget_spent()),
+                Err(e) => {
+                    error!("Purpose report budget check failed: {:?}", e);
+                    return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                        "error": "Purpose report failed"
+                    })));
+                }
+            }
+            let window = format!("{}:{}", since.timestamp(), until.timestamp());
+            let scope = tenant_id.as_deref().unwrap_or_default();
+            let totals: BTreeMap<String, Released> = totals
+                .into_iter()
+                .map(|(purpose, reads)| {
+                    let released = state.noise.count(&format!("purpose.total:{}:{}:{}", scope, window, purpose), reads);
+                    (purpose, released)
+                })
+                .collect();
+            let counts: Vec<ReleasedPurposeCount> = counts
+                .into_iter()
+                .map(|count| ReleasedPurposeCount::new(count, &state.noise, &window))
+                .collect();
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "since": since,
+                "until": until,
+                "tenant_id": tenant_id,
+                "privacy": state.noise.disclosure(),
+                "totals": totals,
+                "counts": counts
+            })))
+        }
+        Err(e) => {
+            error!("Purpose report failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Purpose report failed"
+            })))
+        }
+    }
+}
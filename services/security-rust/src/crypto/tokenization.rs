@@ -0,0 +1,459 @@
+/*!
+Tokenization
+Opaque tokens standing in for CPF/CNPJ and bank account data
+
+Downstream services should carry a token rather than a document number or
+its ciphertext. `POST /crypto/tokenize` (needs
+`TOKENIZATION_TOKENIZE_SCOPE`) swaps values of one `data_type` (`cpf`,
+`cnpj` or `bank_account`) for tokens; `POST /crypto/detokenize` (needs
+`TOKENIZATION_DETOKENIZE_SCOPE`) swaps tokens back and requires a
+`purpose` the tenant allows (see `purpose`); more tokens than
+`CRYPTO_BULK_DECRYPT_THRESHOLD` need a bulk-decrypt grant (see `bulk`).
+
+Tokens are `tok_` and 22 random characters, or with `format_preserving`
+(CPF and CNPJ only) random numbers with valid check digits, punctuated like
+the value was, so they pass the same field validation. A value keeps its
+token: values are looked up by a keyed hash (`CryptoService::blind_index`)
+and stored encrypted under the current data key, bound to their tenant and
+type. Tokens belong to the caller's tenant; other tenants cannot
+detokenize them.
+
+Both directions are audited without the values: tokenization with the
+type and count, each detokenization with the token, the purpose and a
+receipt.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use cotai_validation::{validate, Rule};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::receipts::{self, RECEIPT_HEADER};
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, TokenizationConfig};
+use crate::crypto::{bulk, guard, purpose, CryptoService, DecryptionRequest, EncryptionRequest};
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+use crate::AppState;
+
+const DATA_TYPES: &[&str] = &["cpf", "cnpj", "bank_account"];
+
+const OPAQUE_PREFIX: &str = "tok_";
+const OPAQUE_BYTES: usize = 16;
+
+/// New tokens drawn before giving up on collisions.
+const MAX_ATTEMPTS: usize = 5;
+
+const MAX_BANK_ACCOUNT_LENGTH: usize = 64;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenizeRequest {
+    pub data_type: String,
+    pub values: Vec<String>,
+    #[serde(default)]
+    pub format_preserving: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DetokenizeRequest {
+    pub tokens: Vec<String>,
+    /// Why the values are needed, one of the tenant's allowed purposes.
+    pub purpose: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Detokenized {
+    pub token: String,
+    /// `None` for tokens this tenant never issued.
+    pub value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_type: Option<String>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct StoredToken {
+    id: Uuid,
+    data_type: String,
+    token: String,
+    encrypted_value: String,
+    key_id: String,
+    nonce: String,
+    context_hash: Option<String>,
+}
+
+fn encryption_context(tenant_id: Option<&str>, data_type: &str) -> HashMap<String, String> {
+    HashMap::from([
+        ("purpose".to_string(), "pii_token".to_string()),
+        ("tenant_id".to_string(), tenant_id.unwrap_or_default().to_string()),
+        ("data_type".to_string(), data_type.to_string()),
+    ])
+}
+
+/// The value as stored and indexed: CPF/CNPJ as bare digits.
+pub(crate) fn normalize(data_type: &str, value: &str) -> Result<String, SecurityError> {
+    let invalid = |msg: &str| SecurityError::ValidationError(format!("Invalid {}: {}", data_type, msg));
+    match data_type {
+        "cpf" | "cnpj" => {
+            let rule = if data_type == "cpf" { Rule::Cpf } else { Rule::Cnpj };
+            validate(rule, value).map_err(|issue| invalid(issue.message))?;
+            Ok(value.chars().filter(char::is_ascii_digit).collect())
+        }
+        _ => {
+            let value = value.trim();
+            if value.is_empty() || value.len() > MAX_BANK_ACCOUNT_LENGTH {
+                return Err(invalid(&format!("must be 1-{} characters", MAX_BANK_ACCOUNT_LENGTH)));
+            }
+            Ok(value.to_string())
+        }
+    }
+}
+
+fn mod11_digit(digits: &[u32], weights: &[u32]) -> u32 {
+    let sum: u32 = digits.iter().zip(weights).map(|(d, w)| d * w).sum();
+    match sum % 11 {
+        0 | 1 => 0,
+        r => 11 - r,
+    }
+}
+
+/// `base` completed with its CPF or CNPJ check digits.
+fn with_check_digits(data_type: &str, mut base: Vec<u32>) -> Vec<u32> {
+    let (first, second): (&[u32], &[u32]) = if data_type == "cpf" {
+        (&[10, 9, 8, 7, 6, 5, 4, 3, 2], &[11, 10, 9, 8, 7, 6, 5, 4, 3, 2])
+    } else {
+        (&[5, 4, 3, 2, 9, 8, 7, 6, 5, 4, 3, 2], &[6, 5, 4, 3, 2, 9, 8, 7, 6, 5, 4, 3, 2])
+    };
+    base.push(mod11_digit(&base, first));
+    base.push(mod11_digit(&base, second));
+    base
+}
+
+/// Digits punctuated as `000.000.000-00` or `00.000.000/0000-00`.
+fn punctuate(data_type: &str, digits: &str) -> String {
+    let cuts: &[(usize, char)] = if data_type == "cpf" {
+        &[(3, '.'), (6, '.'), (9, '-')]
+    } else {
+        &[(2, '.'), (5, '.'), (8, '/'), (12, '-')]
+    };
+    let mut out = String::with_capacity(digits.len() + cuts.len());
+    for (i, c) in digits.chars().enumerate() {
+        if let Some((_, sep)) = cuts.iter().find(|(at, _)| *at == i) {
+            out.push(*sep);
+        }
+        out.push(c);
+    }
+    out
+}
+
+pub struct TokenVault {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: TokenizationConfig,
+}
+
+impl TokenVault {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        info!("Token vault initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            config: config.tokenization.clone(),
+        })
+    }
+
+    fn check_batch(&self, len: usize) -> Result<(), SecurityError> {
+        if len == 0 || len > self.config.max_batch {
+            return Err(SecurityError::ValidationError(format!(
+                "Send 1-{} items per request",
+                self.config.max_batch
+            )));
+        }
+        Ok(())
+    }
+
+    /// A fresh token for a normalized value.
+    async fn draw(
+        &self,
+        crypto: &CryptoService,
+        data_type: &str,
+        value: &str,
+        format_preserving: bool,
+        punctuated: bool,
+    ) -> Result<String, SecurityError> {
+        if !format_preserving {
+            let random = crypto.secure_random(OPAQUE_BYTES).await?;
+            return Ok(format!("{}{}", OPAQUE_PREFIX, base64::encode_config(random, base64::URL_SAFE_NO_PAD)));
+        }
+
+        let base_len = if data_type == "cpf" { 9 } else { 12 };
+        loop {
+            // Rejection sampling keeps the digits uniform
+            let random = crypto.secure_random(base_len * 2).await?;
+            let base: Vec<u32> = random.iter().filter(|b| **b < 250).map(|b| u32::from(*b) % 10).take(base_len).collect();
+            if base.len() < base_len || base.iter().all(|d| *d == base[0]) {
+                continue;
+            }
+            let digits: String = with_check_digits(data_type, base).iter().map(|d| char::from_digit(*d, 10).unwrap_or('0')).collect();
+            if digits == value {
+                continue;
+            }
+            return Ok(if punctuated { punctuate(data_type, &digits) } else { digits });
+        }
+    }
+
+    /// Tokens for `values`, in order, reusing the token a value already has.
+    pub async fn tokenize(
+        &self,
+        crypto: &CryptoService,
+        principal: &Principal,
+        request: &TokenizeRequest,
+    ) -> Result<Vec<String>, SecurityError> {
+        let data_type = request.data_type.as_str();
+        if !DATA_TYPES.contains(&data_type) {
+            return Err(SecurityError::ValidationError(format!(
+                "data_type must be one of {}",
+                DATA_TYPES.join(", ")
+            )));
+        }
+        if request.format_preserving && data_type == "bank_account" {
+            return Err(SecurityError::ValidationError("format_preserving applies to cpf and cnpj only".to_string()));
+        }
+        self.check_batch(request.values.len())?;
+
+        let mut tokens = Vec::with_capacity(request.values.len());
+        for raw in &request.values {
+            tokens.push(self.token_for(crypto, principal, data_type, raw, request.format_preserving).await?);
+        }
+        Ok(tokens)
+    }
+
+    async fn token_for(
+        &self,
+        crypto: &CryptoService,
+        principal: &Principal,
+        data_type: &str,
+        raw: &str,
+        format_preserving: bool,
+    ) -> Result<String, SecurityError> {
+        let tenant_id = principal.tenant_id.as_deref();
+        let value = normalize(data_type, raw)?;
+        let index = crypto.blind_index("pii_token", &format!("{}:{}", data_type, value));
+        let existing: Option<(String,)> = sqlx::query_as(
+            "SELECT token FROM pii_tokens \
+             WHERE COALESCE(tenant_id, '') = $1 AND data_type = $2 AND format_preserving = $3 AND value_index = $4",
+        )
+        .bind(tenant_id.unwrap_or_default())
+        .bind(data_type)
+        .bind(format_preserving)
+        .bind(&index)
+        .fetch_optional(self.storage.pool())
+        .await?;
+        if let Some((token,)) = existing {
+            return Ok(token);
+        }
+
+        let sealed = crypto.encrypt_data(EncryptionRequest {
+            data: value.clone(),
+            key_id: None,
+            context: Some(encryption_context(tenant_id, data_type)),
+        }).await?;
+
+        let punctuated = raw.contains(['.', '-', '/']);
+        for _ in 0..MAX_ATTEMPTS {
+            let token = self.draw(crypto, data_type, &value, format_preserving, punctuated).await?;
+            // A concurrent request for the same value wins; its token is returned
+            let inserted: Option<(String,)> = sqlx::query_as(
+                "INSERT INTO pii_tokens (id, tenant_id, data_type, format_preserving, token, value_index, \
+                 encrypted_value, key_id, nonce, context_hash, created_by, created_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) \
+                 ON CONFLICT (COALESCE(tenant_id, ''), data_type, format_preserving, value_index) \
+                 DO UPDATE SET value_index = EXCLUDED.value_index RETURNING token",
+            )
+            .bind(Uuid::new_v4())
+            .bind(tenant_id)
+            .bind(data_type)
+            .bind(format_preserving)
+            .bind(&token)
+            .bind(&index)
+            .bind(&sealed.encrypted_data)
+            .bind(&sealed.key_id)
+            .bind(&sealed.nonce)
+            .bind(&sealed.context_hash)
+            .bind(&principal.subject)
+            .bind(self.clock.now())
+            .fetch_optional(self.storage.pool())
+            .await
+            .or_else(|e| match e {
+                // The token itself collided; draw another
+                sqlx::Error::Database(db) if db.is_unique_violation() => Ok(None),
+                e => Err(e),
+            })?;
+            if let Some((token,)) = inserted {
+                return Ok(token);
+            }
+        }
+        Err(SecurityError::Conflict("Could not draw an unused token".to_string()))
+    }
+
+    /// Values behind `tokens`, in order, for the caller's tenant.
+    pub async fn detokenize(
+        &self,
+        crypto: &CryptoService,
+        principal: &Principal,
+        tokens: &[String],
+    ) -> Result<Vec<Detokenized>, SecurityError> {
+        self.check_batch(tokens.len())?;
+        let tenant_id = principal.tenant_id.as_deref();
+        let stored = sqlx::query_as::<_, StoredToken>(
+            "UPDATE pii_tokens SET detokenize_count = detokenize_count + 1, last_detokenized_at = $3 \
+             WHERE COALESCE(tenant_id, '') = $1 AND token = ANY($2) \
+             RETURNING id, data_type, token, encrypted_value, key_id, nonce, context_hash",
+        )
+        .bind(tenant_id.unwrap_or_default())
+        .bind(tokens)
+        .bind(self.clock.now())
+        .fetch_all(self.storage.pool())
+        .await?;
+        let by_token: HashMap<&str, &StoredToken> = stored.iter().map(|s| (s.token.as_str(), s)).collect();
+
+        let mut values = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            let Some(stored) = by_token.get(token.as_str()) else {
+                values.push(Detokenized { token: token.clone(), value: None, data_type: None });
+                continue;
+            };
+            if crypto.context_hash(Some(&encryption_context(tenant_id, &stored.data_type)))? != stored.context_hash {
+                error!("Token {} is bound to another tenant or type", stored.id);
+                return Err(SecurityError::CryptoError("Token binding mismatch".to_string()));
+            }
+            let value = crypto.decrypt_data(DecryptionRequest {
+                encrypted_data: stored.encrypted_value.clone(),
+                key_id: stored.key_id.clone(),
+                nonce: stored.nonce.clone(),
+                context_hash: stored.context_hash.clone(),
+            }).await?;
+            values.push(Detokenized {
+                token: token.clone(),
+                value: Some(value),
+                data_type: Some(stored.data_type.clone()),
+            });
+        }
+        Ok(values)
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Tokenization failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Tokenization failed"
+            }))
+        }
+    }
+}
+
+pub async fn tokenize_handler(
+    req: HttpRequest,
+    request: web::Json<TokenizeRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.tokenization.tokenize_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let tokens = match state.token_vault.tokenize(&state.crypto_service, &principal, &request).await {
+        Ok(tokens) => tokens,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: principal.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(&req),
+        action: "crypto.tokenize".to_string(),
+        resource: format!("pii_tokens:{}", request.data_type),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "data_type": request.data_type,
+            "count": tokens.len(),
+            "format_preserving": request.format_preserving
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit tokenization: {:?}", e);
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "tokens": tokens })))
+}
+
+pub async fn detokenize_handler(
+    req: HttpRequest,
+    request: web::Json<DetokenizeRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.tokenization.detokenize_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let purpose = match purpose::check(&state, principal.tenant_id.as_deref(), Some(&request.purpose), true).await {
+        Ok(purpose) => purpose,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let grant = match bulk::authorize(&state, &req, &principal, request.tokens.len(), purpose.as_deref()).await {
+        Ok(grant) => grant,
+        Err(e) => return Ok(bulk::refused_response(e)),
+    };
+    if let Err(e) = bulk::admit(&state, &req, &principal, grant.as_ref(), request.tokens.len()).await {
+        return Ok(guard::paused_response(&e));
+    }
+
+    let values = match state.token_vault.detokenize(&state.crypto_service, &principal, &request.tokens).await {
+        Ok(values) => values,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    let mut last_receipt = None;
+    for detokenized in &values {
+        let receipt = receipts::record_or_warn(&state, NewAuditEvent {
+            tenant_id: principal.tenant_id.clone(),
+            actor: principal.subject.clone(),
+            actor_ip: client_ip(&req),
+            action: "crypto.detokenize".to_string(),
+            resource: format!("pii_token:{}", detokenized.token),
+            outcome: if detokenized.value.is_some() { "success" } else { "failure" }.to_string(),
+            payload: serde_json::json!({
+                "purpose": purpose,
+                "data_type": detokenized.data_type,
+                "grant_id": grant.as_ref().map(|grant| grant.id)
+            }),
+        }).await;
+        last_receipt = receipt.or(last_receipt);
+    }
+
+    let mut response = HttpResponse::Ok();
+    if let Some(receipt) = last_receipt {
+        response.insert_header((RECEIPT_HEADER, receipt));
+    }
+    Ok(response
+        .insert_header(("Cache-Control", "no-store"))
+        .json(serde_json::json!({ "values": values })))
+}
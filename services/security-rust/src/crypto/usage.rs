@@ -0,0 +1,404 @@
+/*!
+Key Usage
+Per-key operation counts, last use and top callers, for rotation and cleanup
+
+Every encryption, decryption and rewrap is counted per data key in memory
+and added to per-day totals (`crypto_key_usage`) on each key maintenance
+pass. Operations served for an HTTP request are also counted per caller,
+the authenticated subject (see `monitoring::track`), into
+`crypto_key_callers`, which is pruned after
+`CRYPTO_KEY_USAGE_CALLER_RETENTION_DAYS`.
+
+`GET /crypto/keys/usage` reports every data key with its operations and
+top callers over the last `CRYPTO_KEY_USAGE_RECENT_DAYS` full UTC days,
+its last use, and findings:
+
+- `unused`: an active or decrypt-only key not used for
+  `CRYPTO_KEY_USAGE_UNUSED_DAYS`, a candidate for retirement;
+- `usage_spike` / `usage_drop`: the recent daily average is at least
+  `CRYPTO_KEY_USAGE_CHANGE_FACTOR` times above or below the daily average of
+  the `CRYPTO_KEY_USAGE_BASELINE_DAYS` before, and one of them reaches
+  `CRYPTO_KEY_USAGE_MIN_DAILY`.
+
+`?finding=` keeps only keys with that finding. `GET /crypto/keys/{key_id}/usage`
+gives one key's daily counts and top callers over `?days=` (default the
+recent window). Both are for admins; the report may answer `202` with a
+job to follow (see `jobs`).
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::{BTreeMap, HashMap};
+use tracing::{error, warn};
+
+use crate::auth::auth_error_response;
+use crate::config::CryptoConfig;
+use crate::errors::SecurityError;
+use crate::jobs::{self, Output};
+use crate::storage::KeyUsage;
+use super::{CryptoService, KeyState, UsageCount};
+
+const MAX_DAYS: i64 = 366;
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct KeyCaller {
+    pub caller: String,
+    pub count: i64,
+    pub last_at: DateTime<Utc>,
+}
+
+#[derive(Debug, FromRow)]
+struct RankedCaller {
+    key_id: String,
+    caller: String,
+    count: i64,
+    last_at: DateTime<Utc>,
+}
+
+#[derive(Debug, FromRow)]
+struct KeyTotals {
+    key_id: String,
+    recent: Option<i64>,
+    baseline: Option<i64>,
+    last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow)]
+struct OperationCount {
+    key_id: String,
+    operation: String,
+    count: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Finding {
+    Unused,
+    UsageSpike,
+    UsageDrop,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyUsageSummary {
+    pub key_id: String,
+    pub state: KeyState,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// Per operation, over the recent window.
+    pub operations: BTreeMap<String, i64>,
+    pub recent_daily: f64,
+    pub baseline_daily: f64,
+    pub top_callers: Vec<KeyCaller>,
+    pub findings: Vec<Finding>,
+}
+
+/// The recent and baseline windows, as `[start, end)` UTC days ending today.
+struct Windows {
+    baseline_start: NaiveDate,
+    recent_start: NaiveDate,
+    end: NaiveDate,
+}
+
+impl Windows {
+    fn new(config: &CryptoConfig, now: DateTime<Utc>) -> Self {
+        let end = now.date_naive();
+        let recent_start = end - Duration::days(config.usage_recent_days);
+        Self {
+            baseline_start: recent_start - Duration::days(config.usage_baseline_days),
+            recent_start,
+            end,
+        }
+    }
+}
+
+fn findings(config: &CryptoConfig, key: &KeyUsageSummary, windows: &Windows, now: DateTime<Utc>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let idle_since = now - Duration::days(config.usage_unused_days);
+    if matches!(key.state, KeyState::Active | KeyState::DecryptOnly)
+        && key.created_at < idle_since
+        && key.last_used_at.is_none_or(|at| at < idle_since)
+    {
+        findings.push(Finding::Unused);
+    }
+    // Keys younger than both windows have no baseline to change from
+    let (recent, baseline) = (key.recent_daily, key.baseline_daily);
+    if key.created_at.date_naive() <= windows.baseline_start && recent.max(baseline) >= config.usage_min_daily {
+        if recent >= baseline * config.usage_change_factor {
+            findings.push(Finding::UsageSpike);
+        } else if baseline >= recent * config.usage_change_factor {
+            findings.push(Finding::UsageDrop);
+        }
+    }
+    findings
+}
+
+impl CryptoService {
+    /// Count `ops`, made for a request by `caller`, against their keys.
+    pub fn note_callers(&self, caller: &str, ops: &[(&'static str, String)]) {
+        let now = self.clock.now();
+        let mut callers = self.callers.lock().unwrap();
+        for (_, key_id) in ops {
+            callers.entry((key_id.clone(), caller.to_string()))
+                .and_modify(|usage| {
+                    usage.count += 1;
+                    usage.last_at = now;
+                })
+                .or_insert(UsageCount { count: 1, first_at: now, last_at: now });
+        }
+    }
+
+    /// Write caller counts noted since the last flush, keeping them for
+    /// the next attempt on failure.
+    pub(super) async fn flush_callers(&self) -> Result<(), SecurityError> {
+        let pending: Vec<_> = self.callers.lock().unwrap().drain().collect();
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let written: Result<(), SecurityError> = async {
+            let mut tx = self.storage.pool().begin().await?;
+            for ((key_id, caller), usage) in &pending {
+                sqlx::query(
+                    "INSERT INTO crypto_key_callers (key_id, day, caller, count, last_at) \
+                     VALUES ($1, $2, $3, $4, $5) \
+                     ON CONFLICT (key_id, day, caller) DO UPDATE SET \
+                     count = crypto_key_callers.count + EXCLUDED.count, \
+                     last_at = GREATEST(crypto_key_callers.last_at, EXCLUDED.last_at)",
+                )
+                .bind(key_id)
+                .bind(usage.first_at.date_naive())
+                .bind(caller)
+                .bind(usage.count)
+                .bind(usage.last_at)
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+            Ok(())
+        }
+        .await;
+        if written.is_err() {
+            let mut callers = self.callers.lock().unwrap();
+            for (entry, count) in pending {
+                callers.entry(entry)
+                    .and_modify(|current| {
+                        current.count += count.count;
+                        current.first_at = current.first_at.min(count.first_at);
+                    })
+                    .or_insert(count);
+            }
+        }
+        written
+    }
+
+    /// Drop caller counts older than `CRYPTO_KEY_USAGE_CALLER_RETENTION_DAYS`.
+    pub async fn prune_callers(&self, retention_days: i64) -> Result<u64, SecurityError> {
+        let cutoff = self.clock.now().date_naive() - Duration::days(retention_days);
+        let pruned = sqlx::query("DELETE FROM crypto_key_callers WHERE day < $1")
+            .bind(cutoff)
+            .execute(self.storage.pool())
+            .await?
+            .rows_affected();
+        Ok(pruned)
+    }
+
+    /// Up to `limit` callers per key with the most operations since `since`.
+    async fn top_callers(
+        &self,
+        key_id: Option<&str>,
+        since: NaiveDate,
+        limit: i64,
+    ) -> Result<HashMap<String, Vec<KeyCaller>>, SecurityError> {
+        let ranked = sqlx::query_as::<_, RankedCaller>(
+            "SELECT key_id, caller, count, last_at FROM ( \
+             SELECT key_id, caller, SUM(count)::BIGINT AS count, MAX(last_at) AS last_at, \
+             ROW_NUMBER() OVER (PARTITION BY key_id ORDER BY SUM(count) DESC, caller) AS rank \
+             FROM crypto_key_callers WHERE day >= $1 AND ($2::text IS NULL OR key_id = $2) GROUP BY 1, 2 \
+             ) ranked WHERE rank <= $3 ORDER BY key_id, rank",
+        )
+        .bind(since)
+        .bind(key_id)
+        .bind(limit)
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        let mut callers: HashMap<String, Vec<KeyCaller>> = HashMap::new();
+        for row in ranked {
+            callers.entry(row.key_id).or_default().push(KeyCaller {
+                caller: row.caller,
+                count: row.count,
+                last_at: row.last_at,
+            });
+        }
+        Ok(callers)
+    }
+
+    /// Every data key's usage and findings, newest key first.
+    pub async fn usage_report(&self, config: &CryptoConfig) -> Result<Vec<KeyUsageSummary>, SecurityError> {
+        let now = self.clock.now();
+        let windows = Windows::new(config, now);
+
+        let totals = sqlx::query_as::<_, KeyTotals>(
+            "SELECT key_id, \
+             SUM(count) FILTER (WHERE day >= $2 AND day < $3)::BIGINT AS recent, \
+             SUM(count) FILTER (WHERE day >= $1 AND day < $2)::BIGINT AS baseline, \
+             MAX(last_at) AS last_used_at \
+             FROM crypto_key_usage GROUP BY key_id",
+        )
+        .bind(windows.baseline_start)
+        .bind(windows.recent_start)
+        .bind(windows.end)
+        .fetch_all(self.storage.pool())
+        .await?;
+        let mut totals: HashMap<String, KeyTotals> = totals.into_iter().map(|row| (row.key_id.clone(), row)).collect();
+
+        let operations = sqlx::query_as::<_, OperationCount>(
+            "SELECT key_id, operation, SUM(count)::BIGINT AS count FROM crypto_key_usage \
+             WHERE day >= $1 AND day < $2 GROUP BY 1, 2",
+        )
+        .bind(windows.recent_start)
+        .bind(windows.end)
+        .fetch_all(self.storage.pool())
+        .await?;
+        let mut by_key: HashMap<String, BTreeMap<String, i64>> = HashMap::new();
+        for row in operations {
+            by_key.entry(row.key_id).or_default().insert(row.operation, row.count);
+        }
+
+        let mut callers = self.top_callers(None, windows.recent_start, config.usage_top_callers).await?;
+
+        let report = self.key_metadata().into_iter()
+            .map(|key| {
+                let totals = totals.remove(&key.key_id);
+                let mut summary = KeyUsageSummary {
+                    operations: by_key.remove(&key.key_id).unwrap_or_default(),
+                    top_callers: callers.remove(&key.key_id).unwrap_or_default(),
+                    last_used_at: totals.as_ref().and_then(|t| t.last_used_at),
+                    recent_daily: totals.as_ref().and_then(|t| t.recent).unwrap_or(0) as f64
+                        / config.usage_recent_days as f64,
+                    baseline_daily: totals.as_ref().and_then(|t| t.baseline).unwrap_or(0) as f64
+                        / config.usage_baseline_days as f64,
+                    key_id: key.key_id,
+                    state: key.state,
+                    created_at: key.created_at,
+                    findings: Vec::new(),
+                };
+                summary.findings = findings(config, &summary, &windows, now);
+                summary
+            })
+            .collect();
+        Ok(report)
+    }
+
+    /// Daily counts of one key, by operation, and its top callers since `since`.
+    pub async fn key_usage_detail(
+        &self,
+        key_id: &str,
+        since: NaiveDate,
+        top_callers: i64,
+    ) -> Result<(Vec<KeyUsage>, Vec<KeyCaller>), SecurityError> {
+        let daily = sqlx::query_as::<_, KeyUsage>(
+            "SELECT key_id, day, operation, count, first_at, last_at FROM crypto_key_usage \
+             WHERE key_id = $1 AND day >= $2 ORDER BY day, operation",
+        )
+        .bind(key_id)
+        .bind(since)
+        .fetch_all(self.storage.pool())
+        .await?;
+        let callers = self.top_callers(Some(key_id), since, top_callers).await?
+            .remove(key_id)
+            .unwrap_or_default();
+        Ok((daily, callers))
+    }
+}
+
+// HTTP handlers
+
+#[derive(Debug, Deserialize)]
+pub struct ReportQuery {
+    pub finding: Option<Finding>,
+}
+
+pub async fn report_handler(
+    req: HttpRequest,
+    query: web::Query<ReportQuery>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let finding = query.finding;
+    let work_state = state.clone();
+    Ok(jobs::respond(&state, &req, &principal, "crypto.key_usage_report", async move {
+        let state = work_state;
+        // Counts still in memory belong in the report
+        if let Err(e) = state.crypto_service.flush_usage().await {
+            warn!("Failed to record data key usage: {:?}", e);
+        }
+        let config = &state.config.crypto;
+        let mut keys = state.crypto_service.usage_report(config).await?;
+        if let Some(finding) = finding {
+            keys.retain(|key| key.findings.contains(&finding));
+        }
+        Ok(Output::json(serde_json::json!({
+            "recent_days": config.usage_recent_days,
+            "baseline_days": config.usage_baseline_days,
+            "unused_days": config.usage_unused_days,
+            "keys": keys
+        })))
+    })
+    .await)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DetailQuery {
+    pub days: Option<i64>,
+}
+
+pub async fn detail_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<DetailQuery>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let key_id = path.into_inner();
+    let Some(key_state) = state.crypto_service.key_state(&key_id) else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Key not found"
+        })));
+    };
+    let config = &state.config.crypto;
+    let days = query.days.unwrap_or(config.usage_recent_days);
+    if !(1..=MAX_DAYS).contains(&days) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("days must be between 1 and {}", MAX_DAYS)
+        })));
+    }
+
+    if let Err(e) = state.crypto_service.flush_usage().await {
+        warn!("Failed to record data key usage: {:?}", e);
+    }
+    let since = state.clock.now().date_naive() - Duration::days(days - 1);
+    match state.crypto_service.key_usage_detail(&key_id, since, config.usage_top_callers).await {
+        Ok((daily, callers)) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "key_id": key_id,
+            "state": key_state,
+            "since": since,
+            "daily": daily,
+            "top_callers": callers
+        }))),
+        Err(e) => {
+            error!("Key usage for {} failed: {:?}", key_id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Key usage lookup failed"
+            })))
+        }
+    }
+}
@@ -0,0 +1,379 @@
+/*!
+Webhook Signatures
+Stripe-style signing and verification of webhook payloads
+
+COTAI services sending webhooks to suppliers have them signed here instead
+of holding the secrets themselves, and check the ones suppliers send back
+the same way. The signature header reads
+
+```text
+t=1718000000,v1=5257a869...,v1=9f1c0b2e...
+```
+
+where each `v1` is the hex HMAC-SHA256 of `{t}.{payload}` under one secret
+of the name signed with. SOAR pushes and callbacks and mailbox deliveries
+use the same scheme with their own secrets (see `soar`).
+
+Secrets are named: `WEBHOOK_SIGNING_SECRETS` holds `name=secret` pairs, and
+a stored `hmac` credential `webhook.<name>` (see `credentials`) comes
+before them. A name may have several secrets, which is how one is rotated:
+add the new secret, let suppliers accept it, then drop the old one.
+Signing emits a `v1` per secret and verification accepts a match with any.
+
+Verification rejects a header timestamped further from now than the
+name's `WEBHOOK_TOLERANCES` entry, or `WEBHOOK_TOLERANCE_SECS` without one.
+Every failure is audited as `crypto.webhook.verify` with its reason.
+
+Receivers getting their own checks wrong can post a delivery they got,
+with their secret and, optionally, the signature they computed, to
+`/crypto/webhook/check`. The answer says whether it verifies and why not,
+shows what should have been signed and names the usual mistake their
+signature matches instead (hashing the body alone, base64 instead of hex,
+...). Nothing is stored or logged; `cotai_verify::verify_delivery` does the
+same check in their code.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{error, warn};
+
+use crate::audit::receipts;
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, client_ip};
+use crate::clock::Clock;
+use crate::config::{Config, WebhookConfig};
+use crate::credentials::CredentialCache;
+use crate::errors::SecurityError;
+use crate::AppState;
+
+/// Why a signature header was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// No timestamp, or no `v1` signature.
+    Malformed,
+    /// Timestamped outside the tolerance.
+    Stale,
+    /// No `v1` matches any secret.
+    Mismatch,
+}
+
+impl Failure {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Failure::Malformed => "malformed",
+            Failure::Stale => "stale",
+            Failure::Mismatch => "mismatch",
+        }
+    }
+}
+
+fn digest(secret: &str, timestamp: i64, payload: &[u8]) -> hmac::Tag {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let mut context = hmac::Context::with_key(&key);
+    context.update(timestamp.to_string().as_bytes());
+    context.update(b".");
+    context.update(payload);
+    context.sign()
+}
+
+/// Signature header over `payload` with a `v1` for each of `secrets`.
+pub fn sign<S: AsRef<str>>(secrets: &[S], timestamp: i64, payload: &[u8]) -> String {
+    let mut header = format!("t={}", timestamp);
+    for secret in secrets {
+        header.push_str(",v1=");
+        header.push_str(&hex::encode(digest(secret.as_ref(), timestamp, payload).as_ref()));
+    }
+    header
+}
+
+/// Check `header` against `secrets`, returning its timestamp.
+pub fn verify<S: AsRef<str>>(
+    secrets: &[S],
+    header: &str,
+    payload: &[u8],
+    now: DateTime<Utc>,
+    tolerance_secs: i64,
+) -> Result<i64, Failure> {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signatures.extend(hex::decode(value).ok()),
+            _ => {}
+        }
+    }
+    let Some(timestamp) = timestamp.filter(|_| !signatures.is_empty()) else {
+        return Err(Failure::Malformed);
+    };
+    if (now.timestamp() - timestamp).abs() > tolerance_secs {
+        return Err(Failure::Stale);
+    }
+
+    let mut message = format!("{}.", timestamp).into_bytes();
+    message.extend_from_slice(payload);
+    let matched = secrets.iter().any(|secret| {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_ref().as_bytes());
+        signatures.iter().any(|signature| hmac::verify(&key, &message, signature).is_ok())
+    });
+    if !matched {
+        return Err(Failure::Mismatch);
+    }
+    Ok(timestamp)
+}
+
+pub struct WebhookSigner {
+    config: WebhookConfig,
+    credentials: Arc<CredentialCache>,
+    clock: Arc<dyn Clock>,
+}
+
+impl WebhookSigner {
+    pub fn new(config: &Config, clock: Arc<dyn Clock>, credentials: Arc<CredentialCache>) -> Self {
+        Self { config: config.webhooks.clone(), credentials, clock }
+    }
+
+    /// Secrets of `name`, the stored credential first.
+    fn secrets(&self, name: &str) -> Result<Vec<String>, SecurityError> {
+        let secrets: Vec<String> = self
+            .credentials
+            .hmac_key(&format!("webhook.{}", name))
+            .into_iter()
+            .chain(self.config.secrets.iter().filter(|(n, _)| n == name).map(|(_, secret)| secret.clone()))
+            .collect();
+        if secrets.is_empty() {
+            return Err(SecurityError::NotFound(format!("Unknown webhook secret '{}'", name)));
+        }
+        Ok(secrets)
+    }
+
+    fn tolerance_secs(&self, name: &str) -> i64 {
+        self.config
+            .tolerances
+            .iter()
+            .find(|(n, _)| n == name)
+            .map_or(self.config.tolerance_secs, |(_, secs)| *secs)
+    }
+
+    /// Signature header for `payload`, timestamped now.
+    pub fn sign(&self, name: &str, payload: &[u8]) -> Result<(String, i64), SecurityError> {
+        let secrets = self.secrets(name)?;
+        let timestamp = self.clock.now().timestamp();
+        Ok((sign(&secrets, timestamp, payload), timestamp))
+    }
+
+    /// `Ok(Err(..))` for a header that fails; `Err` for an unknown name.
+    pub fn verify(&self, name: &str, header: &str, payload: &[u8]) -> Result<Result<i64, Failure>, SecurityError> {
+        let secrets = self.secrets(name)?;
+        Ok(verify(&secrets, header, payload, self.clock.now(), self.tolerance_secs(name)))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignRequest {
+    /// Name of the signing secret.
+    pub name: String,
+    /// The exact body to be sent.
+    pub payload: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VerifyRequest {
+    pub name: String,
+    /// The exact body received.
+    pub payload: String,
+    /// The signature header received.
+    pub header: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CheckRequest {
+    /// The receiver's copy of the secret.
+    pub secret: String,
+    /// The exact body received.
+    pub payload: String,
+    /// The signature header received.
+    pub header: String,
+    /// The hex or base64 signature the receiver computed, if any.
+    pub computed: Option<String>,
+    /// Defaults to `WEBHOOK_TOLERANCE_SECS`.
+    pub tolerance_secs: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckReport {
+    pub valid: bool,
+    pub reason: Option<&'static str>,
+    pub timestamp: Option<i64>,
+    /// Seconds between the timestamp and now; negative if in the future.
+    pub age_secs: Option<i64>,
+    pub tolerance_secs: i64,
+    /// What is signed: the timestamp, a dot, then the body as received.
+    pub signed_prefix: Option<String>,
+    pub expected: Option<String>,
+    /// Which usual mistake produces `computed`, when it is wrong.
+    pub computed_as: Option<&'static str>,
+}
+
+type Compute = fn(&str, i64, &[u8]) -> String;
+
+/// Ways receivers get the signature wrong, and how each one computes it.
+const MISTAKES: &[(&str, Compute)] = &[
+    ("correct", |secret, t, payload| hex::encode(digest(secret, t, payload).as_ref())),
+    ("uppercase hex", |secret, t, payload| hex::encode_upper(digest(secret, t, payload).as_ref())),
+    ("base64 instead of hex", |secret, t, payload| base64::encode(digest(secret, t, payload).as_ref())),
+    ("body without the timestamp", |secret, _, payload| {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+        hex::encode(hmac::sign(&key, payload).as_ref())
+    }),
+    ("timestamp without the dot", |secret, t, payload| {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+        let mut message = t.to_string().into_bytes();
+        message.extend_from_slice(payload);
+        hex::encode(hmac::sign(&key, &message).as_ref())
+    }),
+    ("trimmed body", |secret, t, payload| {
+        let trimmed = String::from_utf8_lossy(payload);
+        hex::encode(digest(secret, t, trimmed.trim().as_bytes()).as_ref())
+    }),
+    ("hex-decoded secret", |secret, t, payload| match hex::decode(secret) {
+        Ok(bytes) => {
+            let key = hmac::Key::new(hmac::HMAC_SHA256, &bytes);
+            let mut message = format!("{}.", t).into_bytes();
+            message.extend_from_slice(payload);
+            hex::encode(hmac::sign(&key, &message).as_ref())
+        }
+        Err(_) => String::new(),
+    }),
+];
+
+/// Diagnose a receiver's check of `request`; see the module docs.
+pub fn check(request: &CheckRequest, now: DateTime<Utc>, default_tolerance_secs: i64) -> CheckReport {
+    let tolerance_secs = request.tolerance_secs.unwrap_or(default_tolerance_secs);
+    let payload = request.payload.as_bytes();
+    let result = verify(&[&request.secret], &request.header, payload, now, tolerance_secs);
+    let timestamp = request.header.split(',').find_map(|part| match part.trim().split_once('=') {
+        Some(("t", value)) => value.parse::<i64>().ok(),
+        _ => None,
+    });
+    let computed_as = match (&request.computed, timestamp) {
+        (Some(computed), Some(t)) => {
+            let computed = computed.trim().trim_start_matches("v1=");
+            Some(
+                MISTAKES
+                    .iter()
+                    .find(|(_, compute)| {
+                        let candidate = compute(&request.secret, t, payload);
+                        !candidate.is_empty() && candidate == computed
+                    })
+                    .map_or("unknown: check the secret and that the body is verified as received", |(name, _)| *name),
+            )
+        }
+        _ => None,
+    };
+
+    CheckReport {
+        valid: result.is_ok(),
+        reason: result.err().map(|failure| failure.as_str()),
+        timestamp,
+        age_secs: timestamp.map(|t| now.timestamp() - t),
+        tolerance_secs,
+        signed_prefix: timestamp.map(|t| format!("{}.", t)),
+        expected: timestamp.map(|t| format!("v1={}", hex::encode(digest(&request.secret, t, payload).as_ref()))),
+        computed_as,
+    }
+}
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Webhook signing failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Webhook signing failed"
+            }))
+        }
+    }
+}
+
+// HTTP handlers
+
+pub async fn sign_handler(
+    req: HttpRequest,
+    request: web::Json<SignRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authenticate(&req) {
+        return Ok(auth_error_response(&e));
+    }
+    match state.webhooks.sign(&request.name, request.payload.as_bytes()) {
+        Ok((header, timestamp)) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "header": header,
+            "timestamp": timestamp
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn verify_handler(
+    req: HttpRequest,
+    request: web::Json<VerifyRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let failure = match state.webhooks.verify(&request.name, &request.header, request.payload.as_bytes()) {
+        Ok(Ok(timestamp)) => {
+            return Ok(HttpResponse::Ok().json(serde_json::json!({
+                "valid": true,
+                "timestamp": timestamp
+            })))
+        }
+        Ok(Err(failure)) => failure,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    warn!("Webhook signature for '{}' rejected: {}", request.name, failure.as_str());
+    receipts::record_or_warn(&state, NewAuditEvent {
+        tenant_id: principal.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(&req),
+        action: "crypto.webhook.verify".to_string(),
+        resource: format!("webhook_secret:{}", request.name),
+        outcome: "failure".to_string(),
+        payload: serde_json::json!({
+            "reason": failure.as_str(),
+            "payload_bytes": request.payload.len()
+        }),
+    }).await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "valid": false,
+        "reason": failure.as_str()
+    })))
+}
+
+pub async fn check_handler(
+    req: HttpRequest,
+    request: web::Json<CheckRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authenticate(&req) {
+        return Ok(auth_error_response(&e));
+    }
+    if request.tolerance_secs.is_some_and(|secs| secs <= 0) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "tolerance_secs must be positive"
+        })));
+    }
+    Ok(HttpResponse::Ok().json(check(&request, state.clock.now(), state.config.webhooks.tolerance_secs)))
+}
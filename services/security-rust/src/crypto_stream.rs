@@ -0,0 +1,327 @@
+/*!
+Crypto Stream Module
+Segmented AES-256-GCM for payloads too large to hold in memory
+
+`POST /crypto/encrypt-stream` takes a raw body and returns the framed
+format below; `POST /crypto/decrypt-stream` reverses it. Neither buffers
+more than one segment, so document-sized inputs cost a constant amount of
+memory. Both are capped at `CRYPTO_STREAM_MAX_BYTES`.
+
+```text
+header:  "CTS1" | key id length (u8) | key id | nonce prefix (7 bytes)
+         | segment size (u32 BE) | context hash length (u8) | context hash
+segment: AES-256-GCM(segment size bytes of plaintext) | 16-byte tag
+```
+
+Every segment but the last holds exactly `segment size` bytes of
+plaintext; the last holds the rest, possibly none. Nonces follow the STREAM
+construction, prefix | segment counter (u32 BE) | last-segment flag, and
+the whole header is the AAD of every segment. Reordered, dropped or
+appended segments and a truncated stream all fail to open.
+
+Decrypted plaintext is sent as each segment is verified, so a failure
+late in the stream aborts the response after earlier segments went out.
+Clients must discard the output of a response that does not complete.
+*/
+
+use actix_web::web::{self, Bytes};
+use futures::{Stream, StreamExt};
+use ring::aead::{Aad, LessSafeKey, Nonce};
+
+use crate::errors::SecurityError;
+
+pub const MAGIC: &[u8; 4] = b"CTS1";
+pub const CONTENT_TYPE: &str = "application/vnd.cotai.crypto-stream";
+
+const NONCE_PREFIX_LEN: usize = 7;
+const TAG_LEN: usize = 16;
+/// Largest segment a stream may declare, so a crafted header cannot make
+/// the decryptor buffer without bound.
+pub const MAX_SEGMENT_BYTES: u32 = 16 * 1024 * 1024;
+
+fn invalid(detail: &str) -> SecurityError {
+    SecurityError::ValidationError(format!("Invalid crypto stream: {}", detail))
+}
+
+pub struct StreamHeader {
+    pub key_id: String,
+    pub context_hash: Option<String>,
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    segment_size: u32,
+    encoded: Vec<u8>,
+}
+
+impl StreamHeader {
+    pub(crate) fn new(
+        key_id: &str,
+        nonce_prefix: [u8; NONCE_PREFIX_LEN],
+        segment_size: u32,
+        context_hash: Option<String>,
+    ) -> Result<Self, SecurityError> {
+        let context = context_hash.as_deref().unwrap_or_default();
+        if key_id.len() > u8::MAX as usize || context.len() > u8::MAX as usize {
+            return Err(SecurityError::CryptoError("Key id or context hash too long for a stream header".to_string()));
+        }
+
+        let mut encoded = Vec::with_capacity(MAGIC.len() + key_id.len() + context.len() + 13);
+        encoded.extend_from_slice(MAGIC);
+        encoded.push(key_id.len() as u8);
+        encoded.extend_from_slice(key_id.as_bytes());
+        encoded.extend_from_slice(&nonce_prefix);
+        encoded.extend_from_slice(&segment_size.to_be_bytes());
+        encoded.push(context.len() as u8);
+        encoded.extend_from_slice(context.as_bytes());
+
+        Ok(Self {
+            key_id: key_id.to_string(),
+            context_hash,
+            nonce_prefix,
+            segment_size,
+            encoded,
+        })
+    }
+
+    /// The header at the start of `input` and its length, or `None` if
+    /// more bytes are needed.
+    pub fn parse(input: &[u8]) -> Result<Option<(Self, usize)>, SecurityError> {
+        let mut reader = Reader { input, at: 0 };
+        let Some(magic) = reader.take(MAGIC.len()) else { return Ok(None) };
+        if magic != MAGIC {
+            return Err(invalid("unknown format"));
+        }
+        let Some(key_len) = reader.take(1) else { return Ok(None) };
+        let Some(key_id) = reader.take(key_len[0] as usize) else { return Ok(None) };
+        let Some(prefix) = reader.take(NONCE_PREFIX_LEN) else { return Ok(None) };
+        let Some(segment) = reader.take(4) else { return Ok(None) };
+        let Some(context_len) = reader.take(1) else { return Ok(None) };
+        let Some(context) = reader.take(context_len[0] as usize) else { return Ok(None) };
+
+        let key_id = std::str::from_utf8(key_id).map_err(|_| invalid("key id is not UTF-8"))?;
+        let segment_size = u32::from_be_bytes(segment.try_into().unwrap());
+        if segment_size == 0 || segment_size > MAX_SEGMENT_BYTES {
+            return Err(invalid("unsupported segment size"));
+        }
+        let context_hash = match context {
+            [] => None,
+            hash => Some(std::str::from_utf8(hash).map_err(|_| invalid("context hash is not UTF-8"))?.to_string()),
+        };
+
+        let header = Self::new(key_id, prefix.try_into().unwrap(), segment_size, context_hash)?;
+        Ok(Some((header, reader.at)))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.encoded
+    }
+
+    fn nonce(&self, counter: u32, last: bool) -> Nonce {
+        let mut nonce = [0u8; 12];
+        nonce[..NONCE_PREFIX_LEN].copy_from_slice(&self.nonce_prefix);
+        nonce[NONCE_PREFIX_LEN..11].copy_from_slice(&counter.to_be_bytes());
+        nonce[11] = last as u8;
+        Nonce::assume_unique_for_key(nonce)
+    }
+}
+
+struct Reader<'a> {
+    input: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.input.get(self.at..self.at + len)?;
+        self.at += len;
+        Some(bytes)
+    }
+}
+
+/// Feeds a body through segment by segment.
+pub trait SegmentCipher {
+    /// Output for whatever complete segments `data` finished.
+    fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, SecurityError>;
+    /// Output for the last segment; an incomplete stream is an error.
+    fn finish(&mut self) -> Result<Vec<u8>, SecurityError>;
+}
+
+fn next_counter(counter: &mut u32) -> Result<u32, SecurityError> {
+    let current = *counter;
+    *counter = counter.checked_add(1).ok_or_else(|| invalid("too many segments"))?;
+    Ok(current)
+}
+
+pub struct StreamEncryptor {
+    key: LessSafeKey,
+    header: StreamHeader,
+    buffer: Vec<u8>,
+    counter: u32,
+    header_sent: bool,
+    total: u64,
+    max_bytes: u64,
+}
+
+impl StreamEncryptor {
+    pub(crate) fn new(key: LessSafeKey, header: StreamHeader, max_bytes: u64) -> Self {
+        Self {
+            key,
+            header,
+            buffer: Vec::new(),
+            counter: 0,
+            header_sent: false,
+            total: 0,
+            max_bytes,
+        }
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.header.key_id
+    }
+
+    pub fn context_hash(&self) -> Option<&str> {
+        self.header.context_hash.as_deref()
+    }
+
+    fn seal_segment(&mut self, len: usize, last: bool, out: &mut Vec<u8>) -> Result<(), SecurityError> {
+        let nonce = self.header.nonce(next_counter(&mut self.counter)?, last);
+        let mut segment: Vec<u8> = self.buffer.drain(..len).collect();
+        self.key.seal_in_place_append_tag(nonce, Aad::from(self.header.as_bytes()), &mut segment)
+            .map_err(|_| SecurityError::CryptoError("Encryption failed".to_string()))?;
+        out.extend_from_slice(&segment);
+        Ok(())
+    }
+
+    fn start(&mut self, out: &mut Vec<u8>) {
+        if !self.header_sent {
+            out.extend_from_slice(self.header.as_bytes());
+            self.header_sent = true;
+        }
+    }
+}
+
+impl SegmentCipher for StreamEncryptor {
+    fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, SecurityError> {
+        self.total += data.len() as u64;
+        if self.total > self.max_bytes {
+            return Err(invalid(&format!("payload exceeds {} bytes", self.max_bytes)));
+        }
+
+        let mut out = Vec::new();
+        self.start(&mut out);
+        self.buffer.extend_from_slice(data);
+        // A full segment is only sealed once more data follows it, since
+        // only the last segment carries the final flag
+        let segment = self.header.segment_size as usize;
+        while self.buffer.len() > segment {
+            self.seal_segment(segment, false, &mut out)?;
+        }
+        Ok(out)
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>, SecurityError> {
+        let mut out = Vec::new();
+        self.start(&mut out);
+        let rest = self.buffer.len();
+        self.seal_segment(rest, true, &mut out)?;
+        Ok(out)
+    }
+}
+
+pub struct StreamDecryptor {
+    key: LessSafeKey,
+    header: StreamHeader,
+    buffer: Vec<u8>,
+    counter: u32,
+    total: u64,
+    max_bytes: u64,
+}
+
+impl StreamDecryptor {
+    pub(crate) fn new(key: LessSafeKey, header: StreamHeader, max_bytes: u64) -> Self {
+        Self {
+            key,
+            header,
+            buffer: Vec::new(),
+            counter: 0,
+            total: 0,
+            max_bytes,
+        }
+    }
+
+    fn open_segment(&mut self, len: usize, last: bool, out: &mut Vec<u8>) -> Result<(), SecurityError> {
+        let nonce = self.header.nonce(next_counter(&mut self.counter)?, last);
+        let mut segment: Vec<u8> = self.buffer.drain(..len).collect();
+        let plaintext = self.key.open_in_place(nonce, Aad::from(self.header.as_bytes()), &mut segment)
+            .map_err(|_| SecurityError::CryptoError("Decryption failed".to_string()))?;
+        out.extend_from_slice(plaintext);
+        Ok(())
+    }
+}
+
+impl SegmentCipher for StreamDecryptor {
+    fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, SecurityError> {
+        self.total += data.len() as u64;
+        if self.total > self.max_bytes {
+            return Err(invalid(&format!("payload exceeds {} bytes", self.max_bytes)));
+        }
+
+        let mut out = Vec::new();
+        self.buffer.extend_from_slice(data);
+        let segment = self.header.segment_size as usize + TAG_LEN;
+        while self.buffer.len() > segment {
+            self.open_segment(segment, false, &mut out)?;
+        }
+        Ok(out)
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>, SecurityError> {
+        if self.buffer.len() < TAG_LEN {
+            return Err(invalid("truncated"));
+        }
+        let mut out = Vec::new();
+        let rest = self.buffer.len();
+        self.open_segment(rest, true, &mut out)?;
+        Ok(out)
+    }
+}
+
+/// Read until the stream header is complete. Returns the header and the
+/// bytes read past it.
+pub async fn read_header(payload: &mut web::Payload, max_bytes: u64) -> Result<(StreamHeader, Vec<u8>), SecurityError> {
+    let mut pending = Vec::new();
+    loop {
+        if let Some((header, len)) = StreamHeader::parse(&pending)? {
+            return Ok((header, pending.split_off(len)));
+        }
+        match payload.next().await {
+            Some(Ok(chunk)) => pending.extend_from_slice(&chunk),
+            Some(Err(e)) => return Err(invalid(&e.to_string())),
+            None => return Err(invalid("truncated header")),
+        }
+        if pending.len() as u64 > max_bytes {
+            return Err(invalid(&format!("payload exceeds {} bytes", max_bytes)));
+        }
+    }
+}
+
+/// The response body: `payload` run through `cipher`. Errors end the
+/// stream, which aborts the response.
+pub fn pipe<C: SegmentCipher + 'static>(
+    payload: web::Payload,
+    cipher: C,
+) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+    let stream_error = |e: SecurityError| actix_web::error::ErrorBadRequest(e.to_string());
+    futures::stream::unfold(Some((payload, cipher)), move |state| async move {
+        let (mut payload, mut cipher) = state?;
+        loop {
+            match payload.next().await {
+                Some(Ok(chunk)) => match cipher.push(&chunk) {
+                    Ok(out) if out.is_empty() => continue,
+                    Ok(out) => return Some((Ok(Bytes::from(out)), Some((payload, cipher)))),
+                    Err(e) => return Some((Err(stream_error(e)), None)),
+                },
+                Some(Err(e)) => return Some((Err(e.into()), None)),
+                None => return Some((cipher.finish().map(Bytes::from).map_err(stream_error), None)),
+            }
+        }
+    })
+}
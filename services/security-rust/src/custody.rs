@@ -0,0 +1,769 @@
+/*!
+Custody Module
+Chain of custody of evidence files in disputes
+
+When a bid or award is disputed, the files it rests on become evidence and
+must be shown to have been handled properly from then on. `POST
+/custody/evidence` (needs `CUSTODY_RECORD_SCOPE`) flags a file by its
+SHA-256 under a case reference; the caller flagging it is its first
+custodian. The file itself never reaches this service.
+
+Every later access, transfer to another custodian and export outside the
+platform is recorded with its actor, address and purpose, and with the
+SHA-256 of the copy the actor holds. A copy that does not match marks the
+evidence `mismatch` and publishes `custody.hash_mismatch`; the event is
+still recorded, since a failed check is part of the history. Only the
+current custodian, or an admin, may transfer.
+
+Events form a hash chain per evidence file: each entry hashes its fields
+with the previous entry's hash. `POST /custody/evidence/{id}/report`
+(needs `CUSTODY_REPORT_SCOPE`) checks the chain and signs
+`cotai-custody-report:<evidence id>:<sha256>:<events>:<head hash>:
+<chain valid>:<generated_at>` with the `CUSTODY_ALGORITHM` key for the
+`custody-report` domain (see `signing`), so a report produced for an
+administrative or judicial proceeding verifies against
+`/.well-known/jwks.json` without this service. Reports over long chains
+answer `202` with a job to follow (see `jobs`).
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::receipts::{self, RECEIPT_HEADER};
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, CustodyConfig};
+use crate::crypto::CryptoService;
+use crate::errors::SecurityError;
+use crate::events::{self, DomainEvent};
+use crate::jobs::{self, Output};
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::signing::{Algorithm, CUSTODY_REPORT_DOMAIN};
+use crate::storage::Storage;
+use crate::AppState;
+
+const EVIDENCE_COLUMNS: &str = "id, tenant_id, case_reference, name, sha256, size, reason, custodian, integrity, \
+    event_count, head_hash, flagged_by, flagged_at";
+
+const EVENT_COLUMNS: &str = "id, evidence_id, seq, kind, actor, actor_ip, purpose, presented_sha256, \
+    hash_verified, counterparty, recorded_at, prev_hash, entry_hash";
+
+/// `prev_hash` of the first event of a chain.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "flagged_at", column: "flagged_at", kind: KeyKind::Timestamp },
+    SortField { name: "case_reference", column: "case_reference", kind: KeyKind::Text },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Flag,
+    Access,
+    Transfer,
+    Export,
+}
+
+impl Kind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Flag => "flag",
+            Kind::Access => "access",
+            Kind::Transfer => "transfer",
+            Kind::Export => "export",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Evidence {
+    pub id: Uuid,
+    pub tenant_id: Option<String>,
+    pub case_reference: String,
+    pub name: String,
+    pub sha256: String,
+    pub size: i64,
+    pub reason: String,
+    pub custodian: String,
+    /// `intact`, or `mismatch` once a presented copy failed to match.
+    pub integrity: String,
+    pub event_count: i32,
+    pub head_hash: String,
+    pub flagged_by: String,
+    pub flagged_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct CustodyEvent {
+    pub id: Uuid,
+    pub evidence_id: Uuid,
+    pub seq: i32,
+    pub kind: String,
+    pub actor: String,
+    pub actor_ip: Option<String>,
+    pub purpose: String,
+    pub presented_sha256: String,
+    pub hash_verified: bool,
+    /// New custodian of a transfer, destination of an export.
+    pub counterparty: Option<String>,
+    pub recorded_at: DateTime<Utc>,
+    pub prev_hash: String,
+    pub entry_hash: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CustodyReport {
+    pub evidence: Evidence,
+    pub events: Vec<CustodyEvent>,
+    /// Every entry hash recomputes and links to the one before, ending at
+    /// the evidence's head.
+    pub chain_valid: bool,
+    pub generated_by: String,
+    pub generated_at: DateTime<Utc>,
+    pub algorithm: String,
+    pub key_id: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FlagRequest {
+    pub case_reference: String,
+    pub name: String,
+    pub sha256: String,
+    pub size: i64,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventRequest {
+    pub purpose: String,
+    /// SHA-256 of the copy the actor holds.
+    pub sha256: String,
+    /// New custodian; transfers only.
+    pub to: Option<String>,
+    /// Where the copy goes, e.g. the court or agency; exports only.
+    pub destination: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EvidenceFilter {
+    pub case_reference: Option<String>,
+    pub integrity: Option<String>,
+}
+
+fn normalize_sha256(value: &str) -> Result<String, SecurityError> {
+    let value = value.trim().to_ascii_lowercase();
+    if value.len() != 64 || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(SecurityError::ValidationError("sha256 must be 64 hex characters".to_string()));
+    }
+    Ok(value)
+}
+
+fn required(field: &str, value: &str, max: usize) -> Result<String, SecurityError> {
+    let value = value.trim();
+    if value.is_empty() || value.len() > max {
+        return Err(SecurityError::ValidationError(format!("{} must be 1-{} characters", field, max)));
+    }
+    Ok(value.to_string())
+}
+
+/// Hash of an event's fields and its predecessor. The fields are hashed as
+/// a JSON array so no field can run into the next.
+fn entry_hash(event: &CustodyEvent) -> String {
+    let fields = serde_json::json!([
+        event.prev_hash,
+        event.evidence_id,
+        event.seq,
+        event.kind,
+        event.actor,
+        event.actor_ip,
+        event.purpose,
+        event.presented_sha256,
+        event.hash_verified,
+        event.counterparty,
+        event.recorded_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+    ]);
+    hex::encode(digest(&SHA256, fields.to_string().as_bytes()))
+}
+
+/// Whether `events`, in order, form the evidence's chain.
+fn chain_valid(evidence: &Evidence, events: &[CustodyEvent]) -> bool {
+    let mut prev = GENESIS;
+    for (i, event) in events.iter().enumerate() {
+        if event.seq != i as i32 + 1 || event.prev_hash != prev || entry_hash(event) != event.entry_hash {
+            return false;
+        }
+        prev = &event.entry_hash;
+    }
+    events.len() as i32 == evidence.event_count && prev == evidence.head_hash
+}
+
+/// The signed statement of a report.
+fn report_message(evidence: &Evidence, chain_valid: bool, generated_at: DateTime<Utc>) -> String {
+    format!(
+        "cotai-custody-report:{}:{}:{}:{}:{}:{}",
+        evidence.id,
+        evidence.sha256,
+        evidence.event_count,
+        evidence.head_hash,
+        chain_valid,
+        generated_at.to_rfc3339_opts(SecondsFormat::Micros, true)
+    )
+}
+
+pub struct CustodyService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: CustodyConfig,
+    admin_roles: Vec<String>,
+}
+
+impl CustodyService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        info!("Custody service initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            config: config.custody.clone(),
+            admin_roles: config.auth.admin_roles.clone(),
+        })
+    }
+
+    /// Append an event to the chain of `evidence`, locked in `tx`. `details`
+    /// are the purpose, the presented hash and the counterparty.
+    async fn append(
+        &self,
+        tx: &mut sqlx::Transaction<'static, Postgres>,
+        evidence: &Evidence,
+        kind: Kind,
+        actor: (&Principal, Option<String>),
+        details: (String, String, Option<String>),
+    ) -> Result<CustodyEvent, SecurityError> {
+        let (principal, actor_ip) = actor;
+        let (purpose, presented_sha256, counterparty) = details;
+        let mut event = CustodyEvent {
+            id: Uuid::new_v4(),
+            evidence_id: evidence.id,
+            seq: evidence.event_count + 1,
+            kind: kind.as_str().to_string(),
+            actor: principal.subject.clone(),
+            actor_ip,
+            purpose,
+            hash_verified: presented_sha256 == evidence.sha256,
+            presented_sha256,
+            counterparty,
+            recorded_at: self.clock.now(),
+            prev_hash: evidence.head_hash.clone(),
+            entry_hash: String::new(),
+        };
+        event.entry_hash = entry_hash(&event);
+
+        sqlx::query(&format!(
+            "INSERT INTO custody_events ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)",
+            EVENT_COLUMNS
+        ))
+        .bind(event.id)
+        .bind(event.evidence_id)
+        .bind(event.seq)
+        .bind(&event.kind)
+        .bind(&event.actor)
+        .bind(&event.actor_ip)
+        .bind(&event.purpose)
+        .bind(&event.presented_sha256)
+        .bind(event.hash_verified)
+        .bind(&event.counterparty)
+        .bind(event.recorded_at)
+        .bind(&event.prev_hash)
+        .bind(&event.entry_hash)
+        .execute(&mut **tx)
+        .await?;
+        Ok(event)
+    }
+
+    /// Flag a file as evidence, with the caller as its first custodian.
+    pub async fn flag(
+        &self,
+        principal: &Principal,
+        actor_ip: Option<String>,
+        request: FlagRequest,
+    ) -> Result<(Evidence, CustodyEvent), SecurityError> {
+        let case_reference = required("case_reference", &request.case_reference, 200)?;
+        let name = required("name", &request.name, 1024)?;
+        let reason = required("reason", &request.reason, 2000)?;
+        let sha256 = normalize_sha256(&request.sha256)?;
+        if request.size < 0 {
+            return Err(SecurityError::ValidationError("size must not be negative".to_string()));
+        }
+
+        let mut evidence = Evidence {
+            id: Uuid::new_v4(),
+            tenant_id: principal.tenant_id.clone(),
+            case_reference,
+            name,
+            sha256: sha256.clone(),
+            size: request.size,
+            reason: reason.clone(),
+            custodian: principal.subject.clone(),
+            integrity: "intact".to_string(),
+            event_count: 0,
+            head_hash: GENESIS.to_string(),
+            flagged_by: principal.subject.clone(),
+            flagged_at: self.clock.now(),
+        };
+
+        let mut tx = self.storage.begin().await?;
+        let event = self
+            .append(&mut tx, &evidence, Kind::Flag, (principal, actor_ip), (reason, sha256, Some(principal.subject.clone())))
+            .await?;
+        evidence.event_count = event.seq;
+        evidence.head_hash = event.entry_hash.clone();
+
+        sqlx::query(&format!(
+            "INSERT INTO custody_evidence ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)",
+            EVIDENCE_COLUMNS
+        ))
+        .bind(evidence.id)
+        .bind(&evidence.tenant_id)
+        .bind(&evidence.case_reference)
+        .bind(&evidence.name)
+        .bind(&evidence.sha256)
+        .bind(evidence.size)
+        .bind(&evidence.reason)
+        .bind(&evidence.custodian)
+        .bind(&evidence.integrity)
+        .bind(evidence.event_count)
+        .bind(&evidence.head_hash)
+        .bind(&evidence.flagged_by)
+        .bind(evidence.flagged_at)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        info!("{} flagged {} as evidence in {}", principal.subject, evidence.id, evidence.case_reference);
+        Ok((evidence, event))
+    }
+
+    /// Record an access, transfer or export of flagged evidence.
+    pub async fn record(
+        &self,
+        principal: &Principal,
+        actor_ip: Option<String>,
+        id: Uuid,
+        kind: Kind,
+        request: EventRequest,
+    ) -> Result<(Evidence, CustodyEvent), SecurityError> {
+        let purpose = required("purpose", &request.purpose, 2000)?;
+        let presented = normalize_sha256(&request.sha256)?;
+        let counterparty = match kind {
+            Kind::Transfer => Some(required("to", request.to.as_deref().unwrap_or_default(), 200)?),
+            Kind::Export => Some(required("destination", request.destination.as_deref().unwrap_or_default(), 500)?),
+            Kind::Access | Kind::Flag => None,
+        };
+
+        let mut tx = self.storage.begin().await?;
+        let evidence = sqlx::query_as::<_, Evidence>(&format!(
+            "SELECT {} FROM custody_evidence WHERE id = $1 FOR UPDATE",
+            EVIDENCE_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .filter(|evidence| principal.tenant_id.is_none() || principal.tenant_id == evidence.tenant_id)
+        .ok_or_else(|| SecurityError::NotFound(format!("No evidence {}", id)))?;
+
+        if kind == Kind::Transfer
+            && evidence.custodian != principal.subject
+            && !principal.has_any_role(&self.admin_roles)
+        {
+            return Err(SecurityError::AccessDenied(format!(
+                "Only the custodian, {}, can transfer evidence {}",
+                evidence.custodian, id
+            )));
+        }
+
+        let event = self
+            .append(&mut tx, &evidence, kind, (principal, actor_ip), (purpose, presented, counterparty))
+            .await?;
+        let custodian = match kind {
+            Kind::Transfer => event.counterparty.clone().unwrap_or_else(|| evidence.custodian.clone()),
+            _ => evidence.custodian.clone(),
+        };
+        let integrity = if event.hash_verified { evidence.integrity.clone() } else { "mismatch".to_string() };
+
+        let evidence = sqlx::query_as::<_, Evidence>(&format!(
+            "UPDATE custody_evidence SET event_count = $2, head_hash = $3, custodian = $4, integrity = $5 \
+             WHERE id = $1 RETURNING {}",
+            EVIDENCE_COLUMNS
+        ))
+        .bind(id)
+        .bind(event.seq)
+        .bind(&event.entry_hash)
+        .bind(&custodian)
+        .bind(&integrity)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if !event.hash_verified {
+            let mismatch = DomainEvent::new(
+                "custody.hash_mismatch",
+                "custody_evidence",
+                evidence.id,
+                evidence.tenant_id.clone(),
+                serde_json::json!({
+                    "case_reference": evidence.case_reference,
+                    "event_id": event.id,
+                    "kind": event.kind,
+                    "actor": event.actor,
+                    "expected_sha256": evidence.sha256,
+                    "presented_sha256": event.presented_sha256
+                }),
+                self.clock.now(),
+            );
+            events::enqueue(&mut tx, &mismatch).await?;
+        }
+        tx.commit().await?;
+
+        if !event.hash_verified {
+            warn!("{} presented a copy of evidence {} that does not match", principal.subject, id);
+        }
+        Ok((evidence, event))
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<Evidence, SecurityError> {
+        sqlx::query_as::<_, Evidence>(&format!("SELECT {} FROM custody_evidence WHERE id = $1", EVIDENCE_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound(format!("No evidence {}", id)))
+    }
+
+    pub async fn list(
+        &self,
+        tenant_id: Option<&str>,
+        filter: &EvidenceFilter,
+        page: &PageRequest,
+    ) -> Result<Page<Evidence>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM custody_evidence WHERE 1 = 1",
+            EVIDENCE_COLUMNS
+        ));
+        if let Some(tenant_id) = tenant_id {
+            builder.push(" AND tenant_id = ").push_bind(tenant_id.to_string());
+        }
+        if let Some(case_reference) = &filter.case_reference {
+            builder.push(" AND case_reference = ").push_bind(case_reference.clone());
+        }
+        if let Some(integrity) = &filter.integrity {
+            builder.push(" AND integrity = ").push_bind(integrity.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let evidence = builder
+            .build_query_as::<Evidence>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(evidence, |evidence, field| match field {
+            "case_reference" => (SortKey::Text(evidence.case_reference.clone()), evidence.id),
+            _ => (SortKey::Timestamp(evidence.flagged_at), evidence.id),
+        }))
+    }
+
+    pub async fn events(&self, evidence_id: Uuid) -> Result<Vec<CustodyEvent>, SecurityError> {
+        Ok(sqlx::query_as::<_, CustodyEvent>(&format!(
+            "SELECT {} FROM custody_events WHERE evidence_id = $1 ORDER BY seq",
+            EVENT_COLUMNS
+        ))
+        .bind(evidence_id)
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    /// Check the chain of `evidence` and sign the result.
+    pub async fn report(
+        &self,
+        crypto: &CryptoService,
+        principal: &Principal,
+        evidence: Evidence,
+    ) -> Result<CustodyReport, SecurityError> {
+        let algorithm = Algorithm::parse(&self.config.algorithm)
+            .ok_or_else(|| SecurityError::ConfigError("Invalid CUSTODY_ALGORITHM".to_string()))?;
+        let events = self.events(evidence.id).await?;
+        let chain_valid = chain_valid(&evidence, &events);
+        if !chain_valid {
+            error!("Custody chain of evidence {} does not verify", evidence.id);
+        }
+
+        let generated_at = self.clock.now();
+        let signed = crypto.generate_signature(CUSTODY_REPORT_DOMAIN, &report_message(&evidence, chain_valid, generated_at), None, algorithm)?;
+        Ok(CustodyReport {
+            evidence,
+            events,
+            chain_valid,
+            generated_by: principal.subject.clone(),
+            generated_at,
+            algorithm: algorithm.as_str().to_string(),
+            key_id: signed.key_id,
+            signature: signed.signature,
+        })
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::AccessDenied(msg) => HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Custody operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Custody operation failed"
+            }))
+        }
+    }
+}
+
+/// Evidence, if the caller may see it: tenant-bound callers only their tenant's.
+async fn authorized_evidence(state: &AppState, principal: &Principal, id: Uuid) -> Result<Evidence, HttpResponse> {
+    let evidence = state.custody.get(id).await.map_err(error_response)?;
+    if principal.tenant_id.is_some() && principal.tenant_id != evidence.tenant_id {
+        return Err(error_response(SecurityError::NotFound(format!("No evidence {}", id))));
+    }
+    Ok(evidence)
+}
+
+async fn audit_event(state: &AppState, evidence: &Evidence, event: &CustodyEvent) -> Option<String> {
+    receipts::record_or_warn(state, NewAuditEvent {
+        tenant_id: evidence.tenant_id.clone(),
+        actor: event.actor.clone(),
+        actor_ip: event.actor_ip.clone(),
+        action: format!("custody.{}", event.kind),
+        resource: format!("custody_evidence:{}", evidence.id),
+        outcome: if event.hash_verified { "success" } else { "failure" }.to_string(),
+        payload: serde_json::json!({
+            "case_reference": evidence.case_reference,
+            "event_id": event.id,
+            "seq": event.seq,
+            "purpose": event.purpose,
+            "counterparty": event.counterparty,
+            "hash_verified": event.hash_verified,
+            "entry_hash": event.entry_hash
+        }),
+    }).await
+}
+
+fn with_receipt(mut response: actix_web::HttpResponseBuilder, receipt: Option<String>) -> actix_web::HttpResponseBuilder {
+    if let Some(receipt) = receipt {
+        response.insert_header((RECEIPT_HEADER, receipt));
+    }
+    response
+}
+
+pub async fn flag_handler(
+    req: HttpRequest,
+    request: web::Json<FlagRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.custody.record_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.custody.flag(&principal, client_ip(&req), request.into_inner()).await {
+        Ok((evidence, event)) => {
+            let receipt = audit_event(&state, &evidence, &event).await;
+            Ok(with_receipt(HttpResponse::Created(), receipt).json(serde_json::json!({
+                "evidence": evidence,
+                "event": event
+            })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+async fn record(req: HttpRequest, path: web::Path<Uuid>, request: web::Json<EventRequest>, state: web::Data<AppState>, kind: Kind) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.custody.record_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.custody.record(&principal, client_ip(&req), path.into_inner(), kind, request.into_inner()).await {
+        Ok((evidence, event)) => {
+            let receipt = audit_event(&state, &evidence, &event).await;
+            Ok(with_receipt(HttpResponse::Created(), receipt).json(serde_json::json!({
+                "evidence": evidence,
+                "event": event
+            })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn access_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<EventRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    record(req, path, request, state, Kind::Access).await
+}
+
+pub async fn transfer_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<EventRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    record(req, path, request, state, Kind::Transfer).await
+}
+
+pub async fn export_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<EventRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    record(req, path, request, state, Kind::Export).await
+}
+
+pub async fn list_handler(
+    req: HttpRequest,
+    filter: web::Query<EvidenceFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.custody.record_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.custody.list(principal.tenant_id.as_deref(), &filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "evidence": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.custody.record_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match authorized_evidence(&state, &principal, path.into_inner()).await {
+        Ok(evidence) => Ok(HttpResponse::Ok().json(evidence)),
+        Err(response) => Ok(response),
+    }
+}
+
+pub async fn events_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.custody.record_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let evidence = match authorized_evidence(&state, &principal, path.into_inner()).await {
+        Ok(evidence) => evidence,
+        Err(response) => return Ok(response),
+    };
+
+    match state.custody.events(evidence.id).await {
+        Ok(events) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "evidence_id": evidence.id,
+            "events": events
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn report_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.custody.report_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let evidence = match authorized_evidence(&state, &principal, path.into_inner()).await {
+        Ok(evidence) => evidence,
+        Err(response) => return Ok(response),
+    };
+
+    // Long custody chains can take a while to verify
+    let actor_ip = client_ip(&req);
+    let owner = principal.clone();
+    let work_state = state.clone();
+    Ok(jobs::respond(&state, &req, &owner, "custody.report", async move {
+        let state = work_state;
+        let report = state.custody.report(&state.crypto_service, &principal, evidence).await?;
+
+        let receipt = receipts::record_or_warn(&state, NewAuditEvent {
+            tenant_id: report.evidence.tenant_id.clone(),
+            actor: principal.subject.clone(),
+            actor_ip,
+            action: "custody.report".to_string(),
+            resource: format!("custody_evidence:{}", report.evidence.id),
+            outcome: if report.chain_valid { "success" } else { "failure" }.to_string(),
+            payload: serde_json::json!({
+                "case_reference": report.evidence.case_reference,
+                "event_count": report.evidence.event_count,
+                "head_hash": report.evidence.head_hash,
+                "chain_valid": report.chain_valid
+            }),
+        }).await;
+
+        let output = Output::json(&report);
+        Ok(match receipt {
+            Some(receipt) => output.header(RECEIPT_HEADER, receipt),
+            None => output,
+        })
+    })
+    .await)
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/custody/evidence")
+            .route("", web::post().to(flag_handler))
+            .route("", web::get().to(list_handler))
+            .route("/{id}", web::get().to(get_handler))
+            .route("/{id}/events", web::get().to(events_handler))
+            .route("/{id}/access", web::post().to(access_handler))
+            .route("/{id}/transfer", web::post().to(transfer_handler))
+            .route("/{id}/export", web::post().to(export_handler))
+            .route("/{id}/report", web::post().to(report_handler)),
+    );
+}
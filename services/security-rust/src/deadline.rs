@@ -0,0 +1,131 @@
+/*!
+Deadline Module
+Per-request time budgets taken from the caller
+
+Callers set a budget with `grpc-timeout` (relative, e.g. `250m`) or
+`X-Request-Deadline` (absolute, RFC 3339 or Unix milliseconds); the
+tighter one wins, capped at `DEADLINE_MAX_MS`. Requests without either
+get `DEADLINE_DEFAULT_MS`, where 0 means no deadline.
+
+Work past the deadline is dropped and the caller gets a 504. Dropping the
+handler is the cleanup: open transactions roll back and outbound calls are
+cancelled. While a request runs, `remaining` exposes its budget so storage
+can bound statements server-side and outbound calls can pass it on.
+*/
+
+use actix_web::http::header::HeaderMap;
+use actix_web::HttpResponse;
+use chrono::{DateTime, Utc};
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::config::DeadlineConfig;
+
+pub const DEADLINE_HEADER: &str = "x-request-deadline";
+pub const GRPC_TIMEOUT_HEADER: &str = "grpc-timeout";
+
+tokio::task_local! {
+    static CURRENT: Instant;
+}
+
+/// The budget for a request, or `None` when it has no deadline. A zero
+/// budget means the deadline has already passed. Malformed headers are
+/// reported by name.
+pub fn budget(headers: &HeaderMap, now: DateTime<Utc>, config: &DeadlineConfig) -> Result<Option<Duration>, String> {
+    if !config.enabled {
+        return Ok(None);
+    }
+
+    let relative = header(headers, GRPC_TIMEOUT_HEADER)
+        .map(|value| parse_grpc_timeout(value).ok_or_else(|| invalid(GRPC_TIMEOUT_HEADER)))
+        .transpose()?;
+    let absolute = header(headers, DEADLINE_HEADER)
+        .map(|value| parse_deadline(value, now).ok_or_else(|| invalid("X-Request-Deadline")))
+        .transpose()?;
+
+    let requested = match (relative, absolute) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    let budget = match requested {
+        Some(budget) => budget,
+        None if config.default_ms > 0 => Duration::from_millis(config.default_ms),
+        None => return Ok(None),
+    };
+    Ok(Some(budget.min(Duration::from_millis(config.max_ms))))
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok()).map(str::trim)
+}
+
+fn invalid(name: &str) -> String {
+    format!("Invalid {} header", name)
+}
+
+/// `grpc-timeout`: up to eight digits followed by a unit (H, M, S, m, u, n).
+fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (amount, unit) = value.split_at(value.len() - 1);
+    if !amount.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let amount: u64 = amount.parse().ok()?;
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 3600)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
+
+/// `X-Request-Deadline`: RFC 3339 or Unix milliseconds. Past deadlines
+/// give a zero budget.
+fn parse_deadline(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let at = if value.bytes().all(|b| b.is_ascii_digit()) {
+        DateTime::<Utc>::from_timestamp_millis(value.parse().ok()?)?
+    } else {
+        DateTime::parse_from_rfc3339(value).ok()?.with_timezone(&Utc)
+    };
+    Some((at - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Run `work` within `budget`, making the deadline visible to `remaining`.
+/// `None` means the budget ran out and the work was dropped.
+pub async fn run<F: Future>(budget: Duration, work: F) -> Option<F::Output> {
+    let deadline = Instant::now() + budget;
+    CURRENT.scope(deadline, tokio::time::timeout_at(deadline, work)).await.ok()
+}
+
+/// Time left for the current request; `None` outside a request with a deadline.
+pub fn remaining() -> Option<Duration> {
+    CURRENT
+        .try_with(|deadline| deadline.saturating_duration_since(Instant::now()))
+        .ok()
+}
+
+/// Pass the remaining budget on to a downstream service.
+pub fn outbound(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    match remaining() {
+        Some(left) => request.header(GRPC_TIMEOUT_HEADER, format!("{}m", left.as_millis().clamp(1, 99_999_999))),
+        None => request,
+    }
+}
+
+pub fn rejected(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "error": message
+    }))
+}
+
+pub fn exceeded() -> HttpResponse {
+    HttpResponse::GatewayTimeout().json(serde_json::json!({
+        "error": "Request deadline exceeded"
+    }))
+}
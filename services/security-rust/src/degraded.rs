@@ -0,0 +1,201 @@
+/*!
+Degraded Mode Module
+Dependency probing and the matrix of what keeps working when a dependency is down
+*/
+
+use actix_web::web;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{error, info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dependency {
+    Storage,
+    Redis,
+}
+
+impl Dependency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Dependency::Storage => "storage",
+            Dependency::Redis => "redis",
+        }
+    }
+}
+
+const DEPENDENCIES: &[Dependency] = &[Dependency::Storage, Dependency::Redis];
+
+pub struct Capability {
+    pub name: &'static str,
+    pub needs: &'static [Dependency],
+    /// How the capability keeps working without its dependencies, if it does.
+    pub fallback: Option<&'static str>,
+}
+
+/// What each capability needs, and what it does when that is missing.
+pub const MATRIX: &[Capability] = &[
+    Capability { name: "token_verification", needs: &[], fallback: None },
+    Capability { name: "encryption", needs: &[], fallback: None },
+    Capability { name: "decryption", needs: &[], fallback: None },
+    Capability {
+        name: "feature_flags",
+        needs: &[Dependency::Storage],
+        fallback: Some("evaluated from the last loaded cache"),
+    },
+    Capability {
+        name: "experiments",
+        needs: &[Dependency::Storage],
+        fallback: Some("assigned from the last loaded cache"),
+    },
+    Capability {
+        name: "audit_recording",
+        needs: &[Dependency::Storage],
+        fallback: Some("buffered in memory until storage returns"),
+    },
+    Capability { name: "audit_search", needs: &[Dependency::Storage], fallback: None },
+    Capability { name: "admin_writes", needs: &[Dependency::Storage], fallback: None },
+    Capability {
+        name: "event_delivery",
+        needs: &[Dependency::Redis],
+        fallback: Some("held in the outbox until Redis returns"),
+    },
+    Capability {
+        name: "rate_limiting",
+        needs: &[Dependency::Redis],
+        fallback: Some("limited per replica from memory until Redis returns"),
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Availability {
+    Available,
+    Fallback,
+    Unavailable,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CapabilityStatus {
+    pub name: &'static str,
+    pub availability: Availability,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModeReport {
+    /// `normal` or `degraded`.
+    pub mode: &'static str,
+    /// Dependency name to whether the last probe reached it.
+    pub dependencies: BTreeMap<&'static str, bool>,
+    pub capabilities: Vec<CapabilityStatus>,
+}
+
+/// Last known state of each external dependency. Everything starts up,
+/// since startup only completes once storage has been reached.
+pub struct DependencyMonitor {
+    storage_up: AtomicBool,
+    redis_up: AtomicBool,
+}
+
+impl Default for DependencyMonitor {
+    fn default() -> Self {
+        Self {
+            storage_up: AtomicBool::new(true),
+            redis_up: AtomicBool::new(true),
+        }
+    }
+}
+
+impl DependencyMonitor {
+    fn flag(&self, dependency: Dependency) -> &AtomicBool {
+        match dependency {
+            Dependency::Storage => &self.storage_up,
+            Dependency::Redis => &self.redis_up,
+        }
+    }
+
+    pub fn is_up(&self, dependency: Dependency) -> bool {
+        self.flag(dependency).load(Ordering::Relaxed)
+    }
+
+    /// Record a probe result; returns whether the state changed.
+    pub fn set(&self, dependency: Dependency, up: bool) -> bool {
+        self.flag(dependency).swap(up, Ordering::Relaxed) != up
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        DEPENDENCIES.iter().any(|d| !self.is_up(*d))
+    }
+
+    pub fn report(&self) -> ModeReport {
+        let capabilities = MATRIX
+            .iter()
+            .map(|capability| {
+                let met = capability.needs.iter().all(|d| self.is_up(*d));
+                let availability = match (met, capability.fallback) {
+                    (true, _) => Availability::Available,
+                    (false, Some(_)) => Availability::Fallback,
+                    (false, None) => Availability::Unavailable,
+                };
+                CapabilityStatus {
+                    name: capability.name,
+                    availability,
+                    fallback: capability.fallback.filter(|_| availability == Availability::Fallback),
+                }
+            })
+            .collect();
+
+        ModeReport {
+            mode: if self.is_degraded() { "degraded" } else { "normal" },
+            dependencies: DEPENDENCIES.iter().map(|d| (d.as_str(), self.is_up(*d))).collect(),
+            capabilities,
+        }
+    }
+}
+
+/// Background loop probing dependencies, publishing the mode as metrics and
+/// draining the audit buffer once storage is back.
+pub async fn run_probe(state: web::Data<crate::AppState>) {
+    let interval_secs = state.config.degraded.probe_interval_secs;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+
+    loop {
+        interval.tick().await;
+
+        let probes = [
+            (Dependency::Storage, state.audit_service.is_ready().await),
+            (Dependency::Redis, state.event_bus.broker_ready().await),
+        ];
+        for (dependency, up) in probes {
+            if state.dependencies.set(dependency, up) {
+                if up {
+                    info!("{} is reachable again", dependency.as_str());
+                } else {
+                    warn!("{} is unreachable, entering degraded mode", dependency.as_str());
+                }
+            }
+            state.metrics_service.set_gauge(
+                "cotai_dependency_up",
+                &[("dependency", dependency.as_str())],
+                if up { 1.0 } else { 0.0 },
+            );
+        }
+
+        if state.dependencies.is_up(Dependency::Storage) && state.audit_service.buffered() > 0 {
+            match state.audit_service.flush_buffer().await {
+                Ok(flushed) => info!("Flushed {} buffered audit events", flushed),
+                Err(e) => error!("Audit buffer flush failed: {:?}", e),
+            }
+        }
+
+        state.metrics_service.set_gauge(
+            "cotai_degraded_mode",
+            &[],
+            if state.dependencies.is_degraded() { 1.0 } else { 0.0 },
+        );
+        state.metrics_service.set_gauge("cotai_audit_buffered_events", &[], state.audit_service.buffered() as f64);
+    }
+}
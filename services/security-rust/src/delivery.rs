@@ -0,0 +1,683 @@
+/*!
+Delivery Module
+Email, SMS and WhatsApp providers with failover, for one-time codes and
+notifications
+
+Each provider in `DELIVERY_PROVIDERS` (`name=url`) serves one channel:
+
+- `smtp://host:587` (STARTTLS) or `smtps://host:465`: email, sent from
+  `DELIVERY_FROM_ADDRESS`, authenticating with the `delivery.<name>` outbound
+  credential when it is a `basic` one
+- `sms+https://gateway/path` or `whatsapp+https://gateway/path`: a JSON
+  `POST` of `{reference, channel, to, body, status_callback}` with the
+  `delivery.<name>` outbound credential applied. An `id` in the response
+  is kept as the provider's message id.
+
+A message goes to the first provider of its channel, in configured order,
+that is in rotation and under its `DELIVERY_RATE_LIMITS` (messages per
+minute, counted across replicas). A provider that fails is skipped for the
+next one; `DELIVERY_FAILURE_THRESHOLD` failures in a row, or reported by
+status callbacks, take it out of rotation for `DELIVERY_COOLDOWN_SECS`.
+Providers out of rotation, or which the caller asks to avoid, are tried
+last rather than never, and `/ready` lists them.
+
+Every attempt is recorded without its recipient or body. Accepted messages
+are charged the provider's `DELIVERY_COSTS` price unless its status
+callback reports the actual cost. With `DELIVERY_CALLBACK_BASE_URL` set,
+providers are told to post status to `/delivery/{provider}/status`:
+
+```json
+{"reference": "<our message id>", "id": "<theirs>", "status": "delivered",
+ "error": null, "cost": 0.0075}
+```
+
+signed like SOAR callbacks (`X-Cotai-Signature: t=..,v1=..`) with the
+`delivery.<name>.webhook` HMAC credential. Reports of failed delivery reach
+one-time code challenges (see `auth::otp`) so the login flow can resend
+through another provider. `GET /admin/delivery/providers` shows each
+provider's rotation state, volumes and spend.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use futures::future::BoxFuture;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::auth::auth_error_response;
+use crate::clock::Clock;
+use crate::config::{Config, DeliveryConfig};
+use crate::credentials::{CredentialCache, Secret};
+use crate::errors::SecurityError;
+use crate::health::{CheckFuture, Criticality, HealthRegistry};
+use crate::soar::{signature_valid, SIGNATURE_HEADER};
+use crate::storage::Storage;
+
+const MESSAGE_COLUMNS: &str = "id, provider, channel, purpose, reference, tenant_id, status, \
+    provider_message_id, cost, error, created_at, updated_at";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    Email,
+    Sms,
+    Whatsapp,
+}
+
+impl Channel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Channel::Email => "email",
+            Channel::Sms => "sms",
+            Channel::Whatsapp => "whatsapp",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "email" => Some(Channel::Email),
+            "sms" => Some(Channel::Sms),
+            "whatsapp" => Some(Channel::Whatsapp),
+            _ => None,
+        }
+    }
+}
+
+/// Where a provider sends, parsed from its `DELIVERY_PROVIDERS` url.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Smtp { host: String, port: u16, implicit_tls: bool },
+    Http { channel: Channel, url: String },
+}
+
+impl Target {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let (scheme, rest) = url.split_once("://").ok_or("must be a url")?;
+        match scheme {
+            "smtp" | "smtps" => {
+                let parsed = reqwest::Url::parse(url).map_err(|e| e.to_string())?;
+                let host = parsed.host_str().filter(|host| !host.is_empty()).ok_or("needs a host")?;
+                let implicit_tls = scheme == "smtps";
+                Ok(Target::Smtp {
+                    host: host.to_string(),
+                    port: parsed.port().unwrap_or(if implicit_tls { 465 } else { 587 }),
+                    implicit_tls,
+                })
+            }
+            "sms+https" | "sms+http" | "whatsapp+https" | "whatsapp+http" => {
+                let (channel, scheme) = scheme.split_once('+').ok_or("must be a url")?;
+                let channel = if channel == "sms" { Channel::Sms } else { Channel::Whatsapp };
+                let parsed = reqwest::Url::parse(&format!("{}://{}", scheme, rest)).map_err(|e| e.to_string())?;
+                Ok(Target::Http { channel, url: parsed.to_string() })
+            }
+            other => Err(format!("unsupported scheme '{}'", other)),
+        }
+    }
+
+    pub fn channel(&self) -> Channel {
+        match self {
+            Target::Smtp { .. } => Channel::Email,
+            Target::Http { channel, .. } => *channel,
+        }
+    }
+}
+
+/// A message as handed to one provider.
+pub struct Message<'a> {
+    pub id: Uuid,
+    pub channel: Channel,
+    pub to: &'a str,
+    pub subject: &'a str,
+    pub body: &'a str,
+}
+
+pub trait DeliveryProvider: Send + Sync {
+    /// Hand a message to the provider, returning its own id for it if it
+    /// has one.
+    fn send<'a>(&'a self, message: &'a Message<'a>) -> BoxFuture<'a, Result<Option<String>, SecurityError>>;
+}
+
+fn delivery_error(e: impl std::fmt::Display) -> SecurityError {
+    SecurityError::DeliveryError(e.to_string())
+}
+
+struct SmtpProvider {
+    host: String,
+    port: u16,
+    implicit_tls: bool,
+    from: Mailbox,
+    credential: String,
+    credentials: Arc<CredentialCache>,
+    timeout: std::time::Duration,
+}
+
+impl DeliveryProvider for SmtpProvider {
+    fn send<'a>(&'a self, message: &'a Message<'a>) -> BoxFuture<'a, Result<Option<String>, SecurityError>> {
+        Box::pin(async move {
+            let to: Mailbox = message.to.parse()
+                .map_err(|e| SecurityError::ValidationError(format!("Invalid email address: {}", e)))?;
+
+            // Built per message so rotated credentials are picked up
+            let builder = if self.implicit_tls {
+                AsyncSmtpTransport::<Tokio1Executor>::relay(&self.host)
+            } else {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host)
+            };
+            let mut builder = builder.map_err(delivery_error)?.port(self.port).timeout(Some(self.timeout));
+            if let Some(Secret::Basic { username, password }) = self.credentials.get(&self.credential) {
+                builder = builder.credentials(Credentials::new(username, password));
+            }
+
+            let message_id = format!("<{}@{}>", message.id, self.from.email.domain());
+            let email = lettre::Message::builder()
+                .from(self.from.clone())
+                .to(to)
+                .subject(message.subject)
+                .message_id(Some(message_id.clone()))
+                .header(ContentType::TEXT_PLAIN)
+                .body(message.body.to_string())
+                .map_err(delivery_error)?;
+            builder.build().send(email).await.map_err(delivery_error)?;
+            Ok(Some(message_id))
+        })
+    }
+}
+
+struct HttpProvider {
+    url: String,
+    credential: String,
+    callback_url: Option<String>,
+    client: reqwest::Client,
+    credentials: Arc<CredentialCache>,
+}
+
+impl DeliveryProvider for HttpProvider {
+    fn send<'a>(&'a self, message: &'a Message<'a>) -> BoxFuture<'a, Result<Option<String>, SecurityError>> {
+        Box::pin(async move {
+            let request = self.client.post(&self.url).json(&serde_json::json!({
+                "reference": message.id,
+                "channel": message.channel,
+                "to": message.to,
+                "body": message.body,
+                "status_callback": self.callback_url
+            }));
+            let response = self.credentials.apply(&self.credential, request)
+                .send()
+                .await
+                .map_err(delivery_error)?;
+            if !response.status().is_success() {
+                return Err(delivery_error(format!("Provider returned {}", response.status())));
+            }
+
+            let accepted: serde_json::Value = response.json().await.unwrap_or_default();
+            Ok(accepted.get("id").and_then(|id| id.as_str()).map(String::from))
+        })
+    }
+}
+
+struct Provider {
+    name: String,
+    channel: Channel,
+    /// Messages per minute.
+    rate_limit: Option<i64>,
+    cost: f64,
+    sender: Arc<dyn DeliveryProvider>,
+}
+
+#[derive(Debug, Default)]
+struct Rotation {
+    consecutive_failures: u32,
+    out_until: Option<Instant>,
+}
+
+/// A message to deliver on some provider of `channel`.
+pub struct Outgoing<'a> {
+    pub channel: Channel,
+    pub to: &'a str,
+    pub subject: &'a str,
+    pub body: &'a str,
+    /// What the message is for, e.g. `otp`.
+    pub purpose: &'a str,
+    /// The record it belongs to, e.g. a challenge id.
+    pub reference: Option<Uuid>,
+    pub tenant_id: Option<&'a str>,
+}
+
+/// One attempt to deliver a message; recipients and bodies are not kept.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct DeliveryMessage {
+    pub id: Uuid,
+    pub provider: String,
+    pub channel: String,
+    pub purpose: String,
+    pub reference: Option<Uuid>,
+    pub tenant_id: Option<String>,
+    /// `sending`, `sent`, `delivered` or `failed`.
+    pub status: String,
+    pub provider_message_id: Option<String>,
+    pub cost: f64,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatusReport {
+    /// Our message id, as sent to the provider.
+    pub reference: Option<Uuid>,
+    /// The provider's message id.
+    pub id: Option<String>,
+    pub status: String,
+    pub error: Option<String>,
+    pub cost: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProviderStatus {
+    pub name: String,
+    pub channel: Channel,
+    pub in_rotation: bool,
+    pub consecutive_failures: u32,
+    pub rate_limit: Option<i64>,
+    pub unit_cost: f64,
+    /// Accepted and not yet reported on.
+    pub sent: i64,
+    pub delivered: i64,
+    pub failed: i64,
+    pub cost: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatusQuery {
+    pub since: Option<DateTime<Utc>>,
+}
+
+pub struct DeliveryService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: DeliveryConfig,
+    providers: Vec<Provider>,
+    credentials: Arc<CredentialCache>,
+    rotation: Mutex<HashMap<String, Rotation>>,
+}
+
+impl DeliveryService {
+    pub async fn new(
+        config: &Config,
+        storage: Storage,
+        clock: Arc<dyn Clock>,
+        credentials: Arc<CredentialCache>,
+    ) -> Result<Self, SecurityError> {
+        let delivery = &config.delivery;
+        let timeout = std::time::Duration::from_secs(delivery.timeout_secs);
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| SecurityError::ConfigError(format!("Delivery client: {}", e)))?;
+        let from: Mailbox = delivery.from_address.parse()
+            .map_err(|e| SecurityError::ConfigError(format!("DELIVERY_FROM_ADDRESS: {}", e)))?;
+        let rate_limits: HashMap<&str, i64> = delivery.rate_limits.iter()
+            .filter_map(|(name, limit)| Some((name.as_str(), limit.parse().ok()?)))
+            .collect();
+        let costs: HashMap<&str, f64> = delivery.costs.iter()
+            .filter_map(|(name, cost)| Some((name.as_str(), cost.parse().ok()?)))
+            .collect();
+
+        let mut providers = Vec::new();
+        for (name, url) in &delivery.providers {
+            let target = Target::parse(url)
+                .map_err(|e| SecurityError::ConfigError(format!("Delivery provider '{}': {}", name, e)))?;
+            let channel = target.channel();
+            let credential = format!("delivery.{}", name);
+            let sender: Arc<dyn DeliveryProvider> = match target {
+                Target::Smtp { host, port, implicit_tls } => Arc::new(SmtpProvider {
+                    host,
+                    port,
+                    implicit_tls,
+                    from: from.clone(),
+                    credential,
+                    credentials: credentials.clone(),
+                    timeout,
+                }),
+                Target::Http { url, .. } => Arc::new(HttpProvider {
+                    url,
+                    credential,
+                    callback_url: delivery.callback_base_url.as_ref()
+                        .map(|base| format!("{}/delivery/{}/status", base.trim_end_matches('/'), name)),
+                    client: client.clone(),
+                    credentials: credentials.clone(),
+                }),
+            };
+            providers.push(Provider {
+                name: name.clone(),
+                channel,
+                rate_limit: rate_limits.get(name.as_str()).copied(),
+                cost: costs.get(name.as_str()).copied().unwrap_or(0.0),
+                sender,
+            });
+        }
+
+        info!("Delivery service initialized with {} providers", providers.len());
+        Ok(Self {
+            storage,
+            clock,
+            config: delivery.clone(),
+            providers,
+            credentials,
+            rotation: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn in_rotation(&self, provider: &str) -> bool {
+        let rotation = self.rotation.lock().unwrap_or_else(|e| e.into_inner());
+        rotation.get(provider).and_then(|r| r.out_until).is_none_or(|until| until <= Instant::now())
+    }
+
+    fn record_success(&self, provider: &str) {
+        self.rotation.lock().unwrap_or_else(|e| e.into_inner()).remove(provider);
+    }
+
+    fn record_failure(&self, provider: &str) {
+        let mut rotation = self.rotation.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = rotation.entry(provider.to_string()).or_default();
+        entry.consecutive_failures += 1;
+        if entry.consecutive_failures >= self.config.failure_threshold {
+            let cooldown = std::time::Duration::from_secs(self.config.cooldown_secs);
+            if entry.out_until.is_none_or(|until| until <= Instant::now()) {
+                warn!(
+                    "Delivery provider '{}' out of rotation for {}s after {} failures",
+                    provider, self.config.cooldown_secs, entry.consecutive_failures
+                );
+            }
+            entry.out_until = Some(Instant::now() + cooldown);
+        }
+    }
+
+    async fn rate_limited(&self, provider: &Provider) -> Result<bool, SecurityError> {
+        let Some(limit) = provider.rate_limit else {
+            return Ok(false);
+        };
+        let (recent,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM delivery_messages WHERE provider = $1 AND created_at > $2",
+        )
+        .bind(&provider.name)
+        .bind(self.clock.now() - Duration::minutes(1))
+        .fetch_one(self.storage.pool())
+        .await?;
+        Ok(recent >= limit)
+    }
+
+    /// Deliver through the first provider of the channel that takes the
+    /// message. Providers in `avoid` are tried last, like those out of
+    /// rotation.
+    pub async fn send(&self, outgoing: &Outgoing<'_>, avoid: &[String]) -> Result<DeliveryMessage, SecurityError> {
+        let (preferred, fallback): (Vec<&Provider>, Vec<&Provider>) = self.providers.iter()
+            .filter(|provider| provider.channel == outgoing.channel)
+            .partition(|provider| self.in_rotation(&provider.name) && !avoid.contains(&provider.name));
+        if preferred.is_empty() && fallback.is_empty() {
+            return Err(SecurityError::ValidationError(format!(
+                "No delivery provider for {}", outgoing.channel.as_str()
+            )));
+        }
+
+        let mut failures = Vec::new();
+        for provider in preferred.into_iter().chain(fallback) {
+            if self.rate_limited(provider).await? {
+                failures.push(format!("{}: rate limited", provider.name));
+                continue;
+            }
+
+            let id = Uuid::new_v4();
+            sqlx::query(
+                "INSERT INTO delivery_messages (id, provider, channel, purpose, reference, tenant_id, status, created_at, updated_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, 'sending', $7, $7)",
+            )
+            .bind(id)
+            .bind(&provider.name)
+            .bind(outgoing.channel.as_str())
+            .bind(outgoing.purpose)
+            .bind(outgoing.reference)
+            .bind(outgoing.tenant_id)
+            .bind(self.clock.now())
+            .execute(self.storage.pool())
+            .await?;
+
+            let message = Message {
+                id,
+                channel: outgoing.channel,
+                to: outgoing.to,
+                subject: outgoing.subject,
+                body: outgoing.body,
+            };
+            match provider.sender.send(&message).await {
+                Ok(provider_message_id) => {
+                    self.record_success(&provider.name);
+                    return Ok(sqlx::query_as::<_, DeliveryMessage>(&format!(
+                        "UPDATE delivery_messages SET status = 'sent', provider_message_id = $2, cost = $3, updated_at = NOW() \
+                         WHERE id = $1 RETURNING {}",
+                        MESSAGE_COLUMNS
+                    ))
+                    .bind(id)
+                    .bind(provider_message_id)
+                    .bind(provider.cost)
+                    .fetch_one(self.storage.pool())
+                    .await?);
+                }
+                // A bad recipient fails on every provider alike
+                Err(SecurityError::ValidationError(msg)) => {
+                    sqlx::query("UPDATE delivery_messages SET status = 'failed', error = $2, updated_at = NOW() WHERE id = $1")
+                        .bind(id)
+                        .bind(&msg)
+                        .execute(self.storage.pool())
+                        .await?;
+                    return Err(SecurityError::ValidationError(msg));
+                }
+                Err(e) => {
+                    warn!("Delivery provider '{}' failed, trying the next: {}", provider.name, e);
+                    self.record_failure(&provider.name);
+                    sqlx::query("UPDATE delivery_messages SET status = 'failed', error = $2, updated_at = NOW() WHERE id = $1")
+                        .bind(id)
+                        .bind(e.to_string())
+                        .execute(self.storage.pool())
+                        .await?;
+                    failures.push(format!("{}: {}", provider.name, e));
+                }
+            }
+        }
+
+        Err(SecurityError::DeliveryError(format!(
+            "No {} provider took the message ({})",
+            outgoing.channel.as_str(),
+            failures.join("; ")
+        )))
+    }
+
+    /// Signing key of a provider's status callbacks.
+    fn webhook_secret(&self, provider: &str) -> Option<String> {
+        if !self.providers.iter().any(|p| p.name == provider) {
+            return None;
+        }
+        self.credentials.hmac_key(&format!("delivery.{}.webhook", provider))
+    }
+
+    /// Apply a provider's status callback to the message it names.
+    pub async fn report_status(&self, provider: &str, report: &StatusReport) -> Result<DeliveryMessage, SecurityError> {
+        let status = match report.status.as_str() {
+            "queued" | "accepted" | "sent" => "sent",
+            "delivered" | "read" => "delivered",
+            "failed" | "undelivered" | "rejected" | "bounced" => "failed",
+            other => return Err(SecurityError::ValidationError(format!("Unknown status '{}'", other))),
+        };
+        if report.reference.is_none() && report.id.is_none() {
+            return Err(SecurityError::ValidationError("reference or id is required".to_string()));
+        }
+        if report.cost.is_some_and(|cost| cost < 0.0) {
+            return Err(SecurityError::ValidationError("cost must not be negative".to_string()));
+        }
+
+        // A late `sent` never undoes a final status
+        let message = sqlx::query_as::<_, DeliveryMessage>(&format!(
+            "UPDATE delivery_messages SET \
+             status = CASE WHEN status IN ('delivered', 'failed') AND $4 = 'sent' THEN status ELSE $4 END, \
+             error = COALESCE($5, error), cost = COALESCE($6, cost), updated_at = NOW() \
+             WHERE provider = $1 AND (id = $2 OR provider_message_id = $3) RETURNING {}",
+            MESSAGE_COLUMNS
+        ))
+        .bind(provider)
+        .bind(report.reference)
+        .bind(&report.id)
+        .bind(status)
+        .bind(&report.error)
+        .bind(report.cost)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("No such message".to_string()))?;
+
+        match status {
+            "delivered" => self.record_success(provider),
+            "failed" => self.record_failure(provider),
+            _ => {}
+        }
+        Ok(message)
+    }
+
+    /// Rotation state, volumes and spend per provider since `since`.
+    pub async fn status(&self, since: DateTime<Utc>) -> Result<Vec<ProviderStatus>, SecurityError> {
+        let rows: Vec<(String, i64, i64, i64, f64)> = sqlx::query_as(
+            "SELECT provider, \
+             COUNT(*) FILTER (WHERE status = 'sent'), \
+             COUNT(*) FILTER (WHERE status = 'delivered'), \
+             COUNT(*) FILTER (WHERE status = 'failed'), \
+             COALESCE(SUM(cost), 0) \
+             FROM delivery_messages WHERE created_at >= $1 GROUP BY provider",
+        )
+        .bind(since)
+        .fetch_all(self.storage.pool())
+        .await?;
+        let totals: HashMap<String, (i64, i64, i64, f64)> = rows.into_iter()
+            .map(|(provider, sent, delivered, failed, cost)| (provider, (sent, delivered, failed, cost)))
+            .collect();
+
+        let rotation = self.rotation.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(self.providers.iter()
+            .map(|provider| {
+                let (sent, delivered, failed, cost) = totals.get(&provider.name).copied().unwrap_or_default();
+                let state = rotation.get(&provider.name);
+                ProviderStatus {
+                    name: provider.name.clone(),
+                    channel: provider.channel,
+                    in_rotation: state.and_then(|r| r.out_until).is_none_or(|until| until <= Instant::now()),
+                    consecutive_failures: state.map(|r| r.consecutive_failures).unwrap_or(0),
+                    rate_limit: provider.rate_limit,
+                    unit_cost: provider.cost,
+                    sent,
+                    delivered,
+                    failed,
+                    cost,
+                }
+            })
+            .collect())
+    }
+}
+
+fn providers_in_rotation(state: &crate::AppState) -> CheckFuture<'_> {
+    Box::pin(async move {
+        let delivery = &state.delivery;
+        let out: Vec<String> = delivery.providers.iter()
+            .filter(|provider| !delivery.in_rotation(&provider.name))
+            .map(|provider| format!("{} ({})", provider.name, provider.channel.as_str()))
+            .collect();
+        if out.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Out of rotation: {}", out.join(", ")))
+        }
+    })
+}
+
+pub fn register_health_checks(registry: &mut HealthRegistry) {
+    registry.register("delivery", Criticality::NonCritical, providers_in_rotation);
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Delivery operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Delivery operation failed"
+            }))
+        }
+    }
+}
+
+/// Delivery status posted back by a provider.
+pub async fn status_callback_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Bytes,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let provider = path.into_inner();
+    let Some(secret) = state.delivery.webhook_secret(&provider) else {
+        return Ok(error_response(SecurityError::NotFound(format!("Unknown provider '{}'", provider))));
+    };
+
+    let header = req.headers().get(SIGNATURE_HEADER).and_then(|h| h.to_str().ok()).unwrap_or_default();
+    let tolerance = state.config.delivery.callback_tolerance_secs;
+    if !signature_valid(&secret, header, &body, state.clock.now(), tolerance) {
+        warn!("Rejected delivery status from {} with a bad signature", provider);
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Invalid signature"
+        })));
+    }
+
+    let report: StatusReport = match serde_json::from_slice(&body) {
+        Ok(report) => report,
+        Err(e) => return Ok(error_response(SecurityError::ValidationError(format!("Invalid JSON: {}", e)))),
+    };
+    match state.delivery.report_status(&provider, &report).await {
+        Ok(message) => Ok(HttpResponse::Ok().json(message)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn providers_handler(
+    req: HttpRequest,
+    query: web::Query<StatusQuery>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let since = query.since.unwrap_or_else(|| state.clock.now() - Duration::days(30));
+    match state.delivery.status(since).await {
+        Ok(providers) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "since": since,
+            "providers": providers
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/delivery/{provider}/status", web::post().to(status_callback_handler))
+        .route("/admin/delivery/providers", web::get().to(providers_handler));
+}
@@ -0,0 +1,666 @@
+/*!
+Correlation Rules
+Multi-event rules matched over time windows, per entity
+
+A rule is a sequence of steps, each an audit `$filter` expression that
+must match `count` times, in order, for the same entity (actor, source IP
+or tenant) within `window_secs`. "Five failed logins, then a success,
+then an API key creation, within ten minutes" is:
+
+```json
+{
+  "severity": "high",
+  "group_by": "actor",
+  "window_secs": 600,
+  "steps": [
+    { "filter": "action eq 'auth.login' and outcome eq 'failure'", "count": 5 },
+    { "filter": "action eq 'auth.login' and outcome eq 'success'" },
+    { "filter": "action eq 'api_key.create'" }
+  ]
+}
+```
+
+A step may instead, or as well, give a `when` expression (see `expr`) over
+the `event`, for what `$filter` cannot say, such as arithmetic on payload
+values or tests over payload lists:
+
+```json
+{ "when": "event.payload.amount > 10 * event.payload.average", "count": 3 }
+```
+
+An event the expression fails on, e.g. one without the payload field, does
+not match. A step may also name a detection `plugin` (see `plugins`), which
+gets `{"event": ...}` and must report a match; an event the plugin fails on,
+or a plugin with no active version, does not match either.
+
+The window runs from the first matched event; while a rule is still on
+its first step it slides, so old matches age out. A completed sequence
+opens one incident and starts over.
+
+The engine tails `audit_events` in batches. The cursor and the partial
+matches live in one locked row, so any replica can run a batch and a
+restart picks up where the last one stopped. Events are read once they
+are `CORRELATION_SETTLE_SECS` old; events recorded later than that with
+an earlier timestamp (flushed from the audit buffer) are not correlated.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::types::Json;
+use sqlx::{FromRow, Postgres, Transaction};
+use std::collections::HashMap;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use super::{error_response, NewIncident};
+use crate::alerting::Severity;
+use crate::audit::filter::Filter;
+use crate::audit::{AuditEvent, NewAuditEvent, EVENT_COLUMNS};
+use crate::auth::{auth_error_response, Principal};
+use crate::changes::{self, NewChange};
+use crate::plugins::PluginHost;
+use crate::config::{Config, CorrelationConfig};
+use crate::errors::SecurityError;
+use crate::expr::{Binding, Environment, Object, Program, Type};
+use crate::storage::Storage;
+
+const SELECT_COLUMNS: &str = "name, definition, version, updated_by, updated_at";
+const MAX_STEP_COUNT: u32 = 1000;
+
+static EVENT: Object = Object {
+    name: "event",
+    open: false,
+    fields: &[
+        Binding { name: "id", ty: Type::String, doc: "" },
+        Binding { name: "occurred_at", ty: Type::String, doc: "RFC 3339, UTC" },
+        Binding { name: "tenant_id", ty: Type::String, doc: "" },
+        Binding { name: "actor", ty: Type::String, doc: "" },
+        Binding { name: "actor_ip", ty: Type::String, doc: "" },
+        Binding { name: "action", ty: Type::String, doc: "e.g. auth.login" },
+        Binding { name: "resource", ty: Type::String, doc: "" },
+        Binding { name: "outcome", ty: Type::String, doc: "success or failure" },
+        Binding { name: "source", ty: Type::String, doc: "live or imported" },
+        Binding { name: "payload", ty: Type::Map(&Type::Dyn), doc: "" },
+    ],
+};
+
+/// What `when` expressions of correlation rule steps see.
+pub static EVENTS: Environment = Environment {
+    name: "correlation",
+    description: "when expressions of correlation rule steps",
+    bindings: &[Binding { name: "event", ty: Type::Object(&EVENT), doc: "The audit event" }],
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    Actor,
+    ActorIp,
+    TenantId,
+}
+
+impl GroupBy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GroupBy::Actor => "actor",
+            GroupBy::ActorIp => "actor_ip",
+            GroupBy::TenantId => "tenant_id",
+        }
+    }
+
+    fn key(&self, event: &AuditEvent) -> Option<String> {
+        match self {
+            GroupBy::Actor => Some(event.actor.clone()),
+            GroupBy::ActorIp => event.actor_ip.clone(),
+            GroupBy::TenantId => event.tenant_id.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleStep {
+    /// Audit `$filter` expression.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    /// Expression over `EVENTS`, checked alongside `filter`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+    /// Detection plugin, checked alongside `filter` and `when`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plugin: Option<String>,
+    #[serde(default = "one")]
+    pub count: u32,
+}
+
+fn one() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleDefinition {
+    pub description: Option<String>,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    pub severity: Severity,
+    pub group_by: GroupBy,
+    pub window_secs: i64,
+    pub steps: Vec<RuleStep>,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct CorrelationRule {
+    pub name: String,
+    pub definition: Json<RuleDefinition>,
+    pub version: i64,
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A rule ready to run, with its filters parsed.
+struct CompiledRule {
+    name: String,
+    severity: Severity,
+    group_by: GroupBy,
+    window: Duration,
+    steps: Vec<Step>,
+}
+
+struct Step {
+    filter: Option<Filter>,
+    when: Option<Program>,
+    plugin: Option<String>,
+    count: u32,
+}
+
+impl Step {
+    fn matches(&self, event: &AuditEvent, bindings: &Map<String, Value>, plugins: &PluginHost) -> bool {
+        self.filter.as_ref().is_none_or(|filter| filter.matches(event))
+            && self.when.as_ref().is_none_or(|when| when.holds(bindings).unwrap_or(false))
+            && self.plugin.as_ref().is_none_or(|plugin| {
+                plugins.detect(plugin, &Value::Object(bindings.clone())).unwrap_or_else(|e| {
+                    warn!("Correlation plugin '{}' failed on event {}: {}", plugin, event.id, e);
+                    false
+                })
+            })
+    }
+}
+
+/// What `when` expressions and plugins are evaluated with.
+fn event_bindings(event: &AuditEvent) -> Map<String, Value> {
+    let mut bindings = Map::new();
+    bindings.insert("event".to_string(), serde_json::to_value(event).unwrap_or_default());
+    bindings
+}
+
+fn compile(name: &str, definition: &RuleDefinition, config: &CorrelationConfig, max_cost: u32) -> Result<CompiledRule, SecurityError> {
+    let mut problems = Vec::new();
+
+    let valid_name = !name.is_empty()
+        && name.len() <= 100
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !valid_name {
+        problems.push("name must be 1-100 characters of letters, digits, '_', '-' or '.'".to_string());
+    }
+    if definition.window_secs <= 0 || definition.window_secs > config.max_window_secs {
+        problems.push(format!("window_secs must be 1-{}", config.max_window_secs));
+    }
+    if definition.steps.is_empty() || definition.steps.len() > config.max_steps {
+        problems.push(format!("a rule needs 1-{} steps", config.max_steps));
+    }
+
+    let mut steps = Vec::new();
+    for (i, step) in definition.steps.iter().enumerate() {
+        if step.count == 0 || step.count > MAX_STEP_COUNT {
+            problems.push(format!("step {}: count must be 1-{}", i + 1, MAX_STEP_COUNT));
+        }
+        if step.filter.is_none() && step.when.is_none() && step.plugin.is_none() {
+            problems.push(format!("step {}: needs a filter, a when or a plugin", i + 1));
+        }
+        let filter = match step.filter.as_deref().map(|filter| Filter::parse(filter, max_cost)) {
+            Some(Err(e)) => {
+                problems.push(format!("step {}: {}", i + 1, e));
+                None
+            }
+            parsed => parsed.and_then(Result::ok),
+        };
+        let when = match step.when.as_deref().map(|when| Program::condition(when, &EVENTS)) {
+            Some(Err(e)) => {
+                problems.push(format!("step {}: when: {}", i + 1, e));
+                None
+            }
+            compiled => compiled.and_then(Result::ok),
+        };
+        steps.push(Step { filter, when, plugin: step.plugin.clone(), count: step.count });
+    }
+
+    if !problems.is_empty() {
+        return Err(SecurityError::ValidationError(problems.join("; ")));
+    }
+    Ok(CompiledRule {
+        name: name.to_string(),
+        severity: definition.severity,
+        group_by: definition.group_by,
+        window: Duration::seconds(definition.window_secs),
+        steps,
+    })
+}
+
+/// Progress of one entity through one rule.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Partial {
+    step: usize,
+    /// Matches of the current step so far.
+    current: u32,
+    /// Every matched event, oldest first.
+    events: Vec<(DateTime<Utc>, Uuid)>,
+    tenant_id: Option<String>,
+}
+
+/// Partial matches by rule, then entity.
+type EngineState = HashMap<String, HashMap<String, Partial>>;
+
+impl CompiledRule {
+    fn needs_bindings(&self) -> bool {
+        self.steps.iter().any(|step| step.when.is_some() || step.plugin.is_some())
+    }
+
+    /// Feed one event; returns the completed partial when the sequence finishes.
+    fn observe(
+        &self,
+        partials: &mut HashMap<String, Partial>,
+        event: &AuditEvent,
+        bindings: &Map<String, Value>,
+        plugins: &PluginHost,
+    ) -> Option<(String, Partial)> {
+        let entity = self.group_by.key(event)?;
+        let partial = partials.entry(entity.clone()).or_default();
+
+        let window_start = event.occurred_at - self.window;
+        if partial.events.first().is_some_and(|(at, _)| *at < window_start) {
+            if partial.step == 0 {
+                partial.events.retain(|(at, _)| *at >= window_start);
+                partial.current = partial.events.len() as u32;
+            } else {
+                *partial = Partial::default();
+            }
+        }
+
+        let step = &self.steps[partial.step];
+        if !step.matches(event, bindings, plugins) {
+            if partial.events.is_empty() {
+                partials.remove(&entity);
+            }
+            return None;
+        }
+
+        partial.events.push((event.occurred_at, event.id));
+        if partial.tenant_id.is_none() {
+            partial.tenant_id = event.tenant_id.clone();
+        }
+        partial.current += 1;
+        if partial.current >= step.count {
+            partial.step += 1;
+            partial.current = 0;
+        }
+
+        if partial.step < self.steps.len() {
+            return None;
+        }
+        partials.remove(&entity).map(|done| (entity, done))
+    }
+
+    /// Drop partials whose window has closed, then the oldest beyond `limit`.
+    fn prune(&self, partials: &mut HashMap<String, Partial>, now: DateTime<Utc>, limit: usize) {
+        let window_start = now - self.window;
+        partials.retain(|_, partial| partial.events.first().is_some_and(|(at, _)| *at >= window_start));
+
+        if partials.len() > limit {
+            let mut started: Vec<(DateTime<Utc>, String)> = partials
+                .iter()
+                .filter_map(|(entity, partial)| partial.events.first().map(|(at, _)| (*at, entity.clone())))
+                .collect();
+            started.sort();
+            for (_, entity) in started.into_iter().take(partials.len() - limit) {
+                partials.remove(&entity);
+            }
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct Cursor {
+    processed_at: DateTime<Utc>,
+    processed_id: Uuid,
+    state: Json<EngineState>,
+}
+
+pub struct CorrelationEngine {
+    storage: Storage,
+    config: CorrelationConfig,
+    max_filter_cost: u32,
+}
+
+impl CorrelationEngine {
+    pub async fn new(config: &Config, storage: Storage) -> Result<Self, SecurityError> {
+
+        info!("Correlation engine initialized successfully");
+        Ok(Self {
+            storage,
+            config: config.correlation.clone(),
+            max_filter_cost: config.audit.filter_max_cost,
+        })
+    }
+
+    pub async fn list(&self) -> Result<Vec<CorrelationRule>, SecurityError> {
+        let rules = sqlx::query_as::<_, CorrelationRule>(&format!(
+            "SELECT {} FROM correlation_rules ORDER BY name",
+            SELECT_COLUMNS
+        ))
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(rules)
+    }
+
+    pub async fn get(&self, name: &str) -> Result<CorrelationRule, SecurityError> {
+        sqlx::query_as::<_, CorrelationRule>(&format!(
+            "SELECT {} FROM correlation_rules WHERE name = $1",
+            SELECT_COLUMNS
+        ))
+        .bind(name)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("Rule '{}' not found", name)))
+    }
+
+    pub async fn put(&self, actor: &Principal, name: &str, definition: RuleDefinition) -> Result<CorrelationRule, SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let rule = self.put_in(&mut tx, &actor.subject, name, definition).await?;
+        tx.commit().await?;
+
+        Ok(rule)
+    }
+
+    /// `put` inside the caller's transaction.
+    pub(crate) async fn put_in(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        author: &str,
+        name: &str,
+        definition: RuleDefinition,
+    ) -> Result<CorrelationRule, SecurityError> {
+        compile(name, &definition, &self.config, self.max_filter_cost)?;
+
+        let current = sqlx::query_as::<_, CorrelationRule>(&format!(
+            "SELECT {} FROM correlation_rules WHERE name = $1 FOR UPDATE",
+            SELECT_COLUMNS
+        ))
+        .bind(name)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        let rule = sqlx::query_as::<_, CorrelationRule>(&format!(
+            "INSERT INTO correlation_rules (name, definition, updated_by) VALUES ($1, $2, $3) \
+             ON CONFLICT (name) DO UPDATE SET definition = $2, updated_by = $3, updated_at = NOW(), \
+             version = correlation_rules.version + 1 \
+             RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(name)
+        .bind(Json(&definition))
+        .bind(author)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        changes::record(tx, NewChange {
+            resource_type: "correlation_rule",
+            resource_id: name.to_string(),
+            version: Some(rule.version),
+            action: if current.is_some() { "update" } else { "create" },
+            author,
+            tenant_id: None,
+            before: current.as_ref().and_then(|r| serde_json::to_value(&r.definition.0).ok()),
+            after: serde_json::to_value(&rule.definition.0).ok(),
+        }).await?;
+
+        Ok(rule)
+    }
+
+    pub async fn delete(&self, actor: &Principal, name: &str) -> Result<(), SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let deleted = sqlx::query_as::<_, CorrelationRule>(&format!(
+            "DELETE FROM correlation_rules WHERE name = $1 RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(name)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("Rule '{}' not found", name)))?;
+
+        changes::record(&mut tx, NewChange {
+            resource_type: "correlation_rule",
+            resource_id: name.to_string(),
+            version: Some(deleted.version),
+            action: "delete",
+            author: &actor.subject,
+            tenant_id: None,
+            before: serde_json::to_value(&deleted.definition.0).ok(),
+            after: None,
+        }).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn compiled_rules(&self) -> Result<Vec<CompiledRule>, SecurityError> {
+        let mut compiled = Vec::new();
+        for rule in self.list().await? {
+            if !rule.definition.enabled {
+                continue;
+            }
+            // Validated on write; limits may have tightened since
+            match compile(&rule.name, &rule.definition, &self.config, self.max_filter_cost) {
+                Ok(rule) => compiled.push(rule),
+                Err(e) => warn!("Skipping correlation rule '{}': {}", rule.name, e),
+            }
+        }
+        Ok(compiled)
+    }
+
+    /// Correlate the next batch of audit events. Returns the number of
+    /// incidents opened; another replica holding the cursor means zero.
+    pub async fn run_batch(&self, state: &crate::AppState) -> Result<usize, SecurityError> {
+        let rules = self.compiled_rules().await?;
+        let mut tx = self.storage.begin().await?;
+
+        let Some(cursor) = sqlx::query_as::<_, Cursor>(
+            "SELECT processed_at, processed_id, state FROM correlation_cursor FOR UPDATE SKIP LOCKED",
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(0);
+        };
+
+        let settled = state.clock.now() - Duration::seconds(self.config.settle_secs);
+        let events = sqlx::query_as::<_, AuditEvent>(&format!(
+            "SELECT {} FROM audit_events WHERE (occurred_at, id) > ($1, $2) AND occurred_at <= $3 \
+             ORDER BY occurred_at, id LIMIT $4",
+            EVENT_COLUMNS
+        ))
+        .bind(cursor.processed_at)
+        .bind(cursor.processed_id)
+        .bind(settled)
+        .bind(self.config.batch_size)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let Some(last) = events.last() else {
+            return Ok(0);
+        };
+        let (processed_at, processed_id) = (last.occurred_at, last.id);
+
+        let mut engine_state = cursor.state.0;
+        let mut opened = Vec::new();
+        let needs_bindings = rules.iter().any(CompiledRule::needs_bindings);
+        for event in &events {
+            let bindings = if needs_bindings { event_bindings(event) } else { Map::new() };
+            for rule in &rules {
+                let partials = engine_state.entry(rule.name.clone()).or_default();
+                if let Some((entity, done)) = rule.observe(partials, event, &bindings, &state.plugins) {
+                    opened.push(super::open(state, &mut tx, NewIncident {
+                        rule: rule.name.clone(),
+                        severity: rule.severity,
+                        tenant_id: done.tenant_id,
+                        entity_type: rule.group_by.as_str(),
+                        entity,
+                        event_ids: done.events.iter().map(|(_, id)| *id).collect(),
+                        first_seen: done.events.first().map(|(at, _)| *at).unwrap_or(event.occurred_at),
+                        last_seen: event.occurred_at,
+                    }).await?);
+                }
+            }
+        }
+
+        engine_state.retain(|name, _| rules.iter().any(|rule| &rule.name == name));
+        for rule in &rules {
+            if let Some(partials) = engine_state.get_mut(&rule.name) {
+                rule.prune(partials, processed_at, self.config.max_tracked_entities);
+            }
+        }
+
+        sqlx::query(
+            "UPDATE correlation_cursor SET processed_at = $1, processed_id = $2, state = $3, updated_at = NOW()",
+        )
+        .bind(processed_at)
+        .bind(processed_id)
+        .bind(Json(&engine_state))
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        super::announce(state, &opened).await;
+        Ok(opened.len())
+    }
+}
+
+/// Background loop feeding the audit stream through the correlation rules.
+pub async fn run_engine(state: web::Data<crate::AppState>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(state.config.correlation.interval_secs));
+
+    loop {
+        interval.tick().await;
+        match state.correlation.run_batch(&state).await {
+            Ok(0) => {}
+            Ok(count) => info!("Correlation opened {} incidents", count),
+            Err(e) => error!("Correlation batch failed: {:?}", e),
+        }
+    }
+}
+
+// HTTP handlers
+
+async fn audit_rule_change(state: &crate::AppState, actor: &Principal, action: &str, name: &str, detail: serde_json::Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: actor.tenant_id.clone(),
+        actor: actor.subject.clone(),
+        actor_ip: None,
+        action: action.to_string(),
+        resource: format!("correlation_rule:{}", name),
+        outcome: "success".to_string(),
+        payload: detail,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit rule change on {}: {:?}", name, e);
+    }
+}
+
+pub async fn list_rules_handler(
+    req: HttpRequest,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.correlation.list().await {
+        Ok(rules) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "rules": rules
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_rule_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.correlation.get(&path).await {
+        Ok(rule) => Ok(HttpResponse::Ok().json(rule)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn put_rule_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    definition: web::Json<RuleDefinition>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let name = path.into_inner();
+    let definition = definition.into_inner();
+    match state.correlation.put(&principal, &name, definition.clone()).await {
+        Ok(rule) => {
+            audit_rule_change(&state, &principal, "correlation_rule.put", &name, serde_json::to_value(&definition).unwrap_or_default()).await;
+            Ok(HttpResponse::Ok().json(rule))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn delete_rule_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let name = path.into_inner();
+    match state.correlation.delete(&principal, &name).await {
+        Ok(()) => {
+            audit_rule_change(&state, &principal, "correlation_rule.delete", &name, serde_json::json!({})).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/correlation/rules")
+            .route("", web::get().to(list_rules_handler))
+            .route("/{name}", web::get().to(get_rule_handler))
+            .route("/{name}", web::put().to(put_rule_handler))
+            .route("/{name}", web::delete().to(delete_rule_handler))
+    );
+}
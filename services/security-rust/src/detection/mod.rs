@@ -0,0 +1,395 @@
+/*!
+Detection Module
+Security incidents raised from the audit stream
+
+Detectors (`correlation`, `ueba`, `usage`) open incidents; each one is
+stored, published as an `incident.opened` domain event through the outbox,
+handed to the containment playbooks, queued for the SOAR integrations and
+sent to the alerting sinks. Analysts and SOAR callbacks move incidents through `open`,
+`acknowledged` and `resolved` and attach enrichment; every update is
+published as `incident.updated` and pushed to the other integrations.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{FromRow, Postgres, QueryBuilder, Transaction};
+use tracing::{error, info, warn};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::alerting::{Alert, Severity};
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, Principal};
+use crate::clock::Clock;
+use crate::containment::ContainmentAction;
+use crate::config::{Config, SoarConfig};
+use crate::errors::SecurityError;
+use crate::events::{self, DomainEvent};
+use crate::soar;
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::Storage;
+
+pub mod correlation;
+pub mod ueba;
+pub mod usage;
+
+pub use correlation::CorrelationEngine;
+pub use ueba::UebaService;
+pub use usage::UsageBaselines;
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "created_at", column: "created_at", kind: KeyKind::Timestamp },
+];
+
+pub(crate) const SELECT_COLUMNS: &str = "id, rule, severity, tenant_id, entity_type, entity, event_ids, \
+    first_seen, last_seen, status, enrichment, external_refs, created_at, updated_at";
+
+pub(crate) const STATUSES: &[&str] = &["open", "acknowledged", "resolved"];
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Incident {
+    pub id: Uuid,
+    /// Detector rule that raised it.
+    pub rule: String,
+    pub severity: String,
+    pub tenant_id: Option<String>,
+    /// What the evidence is grouped by, e.g. `actor` or `actor_ip`.
+    pub entity_type: String,
+    pub entity: String,
+    /// Audit events that make up the match, oldest first.
+    pub event_ids: Vec<Uuid>,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub status: String,
+    /// Context attached by analysts and SOAR playbooks.
+    pub enrichment: serde_json::Value,
+    /// The incident's id in each SOAR integration, by integration name.
+    pub external_refs: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+pub struct NewIncident {
+    pub rule: String,
+    pub severity: Severity,
+    pub tenant_id: Option<String>,
+    pub entity_type: &'static str,
+    pub entity: String,
+    pub event_ids: Vec<Uuid>,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct IncidentFilter {
+    pub status: Option<String>,
+    pub rule: Option<String>,
+    pub entity: Option<String>,
+    pub tenant_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatusRequest {
+    pub status: String,
+}
+
+/// A change to an incident, from an analyst or a SOAR callback.
+#[derive(Debug, Default)]
+pub struct IncidentUpdate {
+    pub status: Option<String>,
+    /// Merged into the stored enrichment key by key.
+    pub enrichment: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Integration name and the incident's id there.
+    pub external_ref: Option<(String, String)>,
+}
+
+pub fn severity_name(severity: Severity) -> String {
+    serde_json::to_value(severity)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// An incident opened in a transaction, with the containment it triggered.
+pub struct Opened {
+    pub incident: Incident,
+    pub severity: Severity,
+    pub containment: Vec<ContainmentAction>,
+}
+
+/// Store an incident, queue its domain event and plan its containment
+/// inside the caller's transaction. Pass the result to `announce` once
+/// the transaction has committed.
+pub async fn open(
+    state: &crate::AppState,
+    tx: &mut Transaction<'_, Postgres>,
+    incident: NewIncident,
+) -> Result<Opened, SecurityError> {
+    let severity = incident.severity;
+    let incident = sqlx::query_as::<_, Incident>(&format!(
+        "INSERT INTO security_incidents \
+         (id, rule, severity, tenant_id, entity_type, entity, event_ids, first_seen, last_seen) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING {}",
+        SELECT_COLUMNS
+    ))
+    .bind(Uuid::new_v4())
+    .bind(&incident.rule)
+    .bind(severity_name(incident.severity))
+    .bind(&incident.tenant_id)
+    .bind(incident.entity_type)
+    .bind(&incident.entity)
+    .bind(&incident.event_ids)
+    .bind(incident.first_seen)
+    .bind(incident.last_seen)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let event = DomainEvent::new(
+        "incident.opened",
+        "incident",
+        incident.id,
+        incident.tenant_id.clone(),
+        serde_json::to_value(&incident).unwrap_or_default(),
+        state.clock.now(),
+    );
+    events::enqueue(tx, &event).await?;
+    soar::queue(tx, &state.config.soar, incident.id, "incident.opened", None).await?;
+
+    let containment = state.containment.plan(tx, &incident).await?;
+    Ok(Opened { incident, severity, containment })
+}
+
+/// Alert on committed incidents and audit the containment they started.
+pub async fn announce(state: &crate::AppState, opened: &[Opened]) {
+    for Opened { incident, severity, containment } in opened {
+        info!("Incident {} opened by rule {} for {} {}", incident.id, incident.rule, incident.entity_type, incident.entity);
+        state.alerting_service.send(&alert(incident, *severity, state.clock.now()), &[]).await;
+        state.containment.announce(state, containment).await;
+    }
+}
+
+/// Alert for a newly opened incident.
+pub fn alert(incident: &Incident, severity: Severity, now: DateTime<Utc>) -> Alert {
+    Alert::new(
+        "detection",
+        severity,
+        format!("{}: {} {}", incident.rule, incident.entity_type, incident.entity),
+        serde_json::to_value(incident).unwrap_or_default(),
+        now,
+    )
+}
+
+pub struct IncidentService {
+    storage: Storage,
+    soar: SoarConfig,
+    clock: Arc<dyn Clock>,
+}
+
+impl IncidentService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+
+        info!("Incident service initialized successfully");
+        Ok(Self {
+            storage,
+            soar: config.soar.clone(),
+            clock,
+        })
+    }
+
+    pub async fn list(&self, filter: &IncidentFilter, page: &PageRequest) -> Result<Page<Incident>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM security_incidents WHERE 1 = 1",
+            SELECT_COLUMNS
+        ));
+        if let Some(status) = &filter.status {
+            builder.push(" AND status = ").push_bind(status.clone());
+        }
+        if let Some(rule) = &filter.rule {
+            builder.push(" AND rule = ").push_bind(rule.clone());
+        }
+        if let Some(entity) = &filter.entity {
+            builder.push(" AND entity = ").push_bind(entity.clone());
+        }
+        if let Some(tenant_id) = &filter.tenant_id {
+            builder.push(" AND tenant_id = ").push_bind(tenant_id.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let incidents = builder
+            .build_query_as::<Incident>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(incidents, |incident, _| (SortKey::Timestamp(incident.created_at), incident.id)))
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<Incident, SecurityError> {
+        sqlx::query_as::<_, Incident>(&format!(
+            "SELECT {} FROM security_incidents WHERE id = $1",
+            SELECT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("Incident not found".to_string()))
+    }
+
+    /// Apply an update and push it to every SOAR integration but `origin`.
+    pub async fn update(&self, id: Uuid, update: IncidentUpdate, origin: Option<&str>) -> Result<Incident, SecurityError> {
+        if let Some(status) = &update.status {
+            if !STATUSES.contains(&status.as_str()) {
+                return Err(SecurityError::ValidationError(format!(
+                    "status must be one of {}",
+                    STATUSES.join(", ")
+                )));
+            }
+        }
+        let external_ref = match &update.external_ref {
+            Some((integration, external_id)) => serde_json::json!({ integration.as_str(): external_id }),
+            None => serde_json::json!({}),
+        };
+
+        let mut tx = self.storage.begin().await?;
+        let incident = sqlx::query_as::<_, Incident>(&format!(
+            "UPDATE security_incidents SET status = COALESCE($2, status), enrichment = enrichment || $3, \
+             external_refs = external_refs || $4, updated_at = NOW() WHERE id = $1 RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(id)
+        .bind(&update.status)
+        .bind(Json(serde_json::Value::Object(update.enrichment.unwrap_or_default())))
+        .bind(Json(external_ref))
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("Incident not found".to_string()))?;
+
+        let event = DomainEvent::new(
+            "incident.updated",
+            "incident",
+            incident.id,
+            incident.tenant_id.clone(),
+            serde_json::to_value(&incident).unwrap_or_default(),
+            self.clock.now(),
+        );
+        events::enqueue(&mut tx, &event).await?;
+        soar::queue(&mut tx, &self.soar, incident.id, "incident.updated", origin).await?;
+        tx.commit().await?;
+
+        Ok(incident)
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Detection operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Detection operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit_detection(state: &crate::AppState, actor: &Principal, action: &str, resource: String, detail: serde_json::Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: actor.tenant_id.clone(),
+        actor: actor.subject.clone(),
+        actor_ip: None,
+        action: action.to_string(),
+        resource: resource.clone(),
+        outcome: "success".to_string(),
+        payload: detail,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} on {}: {:?}", action, resource, e);
+    }
+}
+
+pub async fn list_incidents_handler(
+    req: HttpRequest,
+    filter: web::Query<IncidentFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.incidents.list(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "incidents": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_incident_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.incidents.get(path.into_inner()).await {
+        Ok(incident) => Ok(HttpResponse::Ok().json(incident)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn set_status_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<StatusRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let id = path.into_inner();
+    let update = IncidentUpdate {
+        status: Some(request.status.clone()),
+        ..IncidentUpdate::default()
+    };
+    match state.incidents.update(id, update, None).await {
+        Ok(incident) => {
+            audit_detection(&state, &principal, "incident.status", format!("incident:{}", id), serde_json::json!({
+                "status": request.status
+            })).await;
+            Ok(HttpResponse::Ok().json(incident))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/incidents")
+            .route("", web::get().to(list_incidents_handler))
+            .route("/{id}", web::get().to(get_incident_handler))
+            .route("/{id}/status", web::put().to(set_status_handler))
+    )
+    .configure(correlation::configure_routes)
+    .configure(ueba::configure_routes)
+    .configure(usage::configure_routes);
+}
@@ -0,0 +1,564 @@
+/*!
+User Behavior Baselines
+Per-actor behavioral profiles and daily risk scores
+
+Once a day each actor's profile is relearned from the preceding
+`UEBA_LOOKBACK_DAYS` of audit history: which actions they perform, at
+which hours of their tenant's local time (see `timezone`), from which
+networks, and how many events they produce on an active local day. Networks are the source address truncated to /24 (IPv4)
+or /48 (IPv6); there is no geolocation data, so a network stands in for
+geography.
+
+Every `UEBA_INTERVAL_SECS` the day's activity so far is scored against the
+profile, 0-100:
+
+| factor               | points | measure                                         |
+|----------------------|--------|-------------------------------------------------|
+| `unfamiliar_actions` | 30     | share of events whose action is rare or unseen  |
+| `unusual_hours`      | 20     | share of events in hours the actor is rarely on |
+| `new_networks`       | 30     | share of events from networks never seen        |
+| `volume`             | 20     | event count z-score, scaled from 2 to 5         |
+
+Actors short of `UEBA_MIN_EVENTS` or `UEBA_MIN_ACTIVE_DAYS` are still
+learning and are not scored. Days turn at midnight in `TENANT_TIME_ZONE`.
+One score per actor per day is kept, so the
+stored rows are the risk trend; crossing `UEBA_INCIDENT_THRESHOLD` opens
+an incident. Other components read scores through `UebaService::current_risk`.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{FromRow, Postgres, Transaction};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use tracing::{error, info};
+
+use super::{error_response, NewIncident};
+use crate::alerting::Severity;
+use crate::auth::auth_error_response;
+use crate::config::{Config, UebaConfig};
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+use crate::timezone;
+
+/// Largest action or network map kept per profile; the rest count as unseen.
+const MAX_PROFILE_ENTRIES: usize = 200;
+/// Baseline share below which an action or hour counts as unusual.
+const RARE_ACTION_SHARE: f64 = 0.01;
+const RARE_HOUR_SHARE: f64 = 0.02;
+const MAX_RISK_LIMIT: i64 = 500;
+
+const SCORE_COLUMNS: &str = "actor, day, score, factors, events, scored_at";
+
+/// What an actor normally does.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    /// Share of events per action.
+    pub actions: HashMap<String, f64>,
+    /// Share of events per local hour, 24 entries.
+    pub hours: Vec<f64>,
+    /// Share of events per network.
+    pub networks: HashMap<String, f64>,
+    /// Events per active day.
+    pub daily_mean: f64,
+    pub daily_stddev: f64,
+    pub events: i64,
+    pub active_days: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Factors {
+    pub unfamiliar_actions: f64,
+    pub unusual_hours: f64,
+    pub new_networks: f64,
+    pub volume_zscore: f64,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Baseline {
+    pub actor: String,
+    pub profile: Json<Profile>,
+    pub learned_for: NaiveDate,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct RiskScore {
+    pub actor: String,
+    pub day: NaiveDate,
+    pub score: f64,
+    pub factors: Json<Factors>,
+    /// Events scored, i.e. the actor's activity that day so far.
+    pub events: i64,
+    pub scored_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrendQuery {
+    pub days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RiskQuery {
+    pub day: Option<NaiveDate>,
+    pub min_score: Option<f64>,
+    pub limit: Option<i64>,
+}
+
+/// Counts of one actor's events over a period.
+#[derive(Debug, Default)]
+struct Activity {
+    events: i64,
+    tenant_id: Option<String>,
+    first_seen: Option<DateTime<Utc>>,
+    last_seen: Option<DateTime<Utc>>,
+    actions: HashMap<String, i64>,
+    hours: [i64; 24],
+    networks: HashMap<String, i64>,
+    daily: Vec<i64>,
+}
+
+#[derive(FromRow)]
+struct Bucket {
+    actor: String,
+    bucket: String,
+    n: i64,
+}
+
+#[derive(FromRow)]
+struct Span {
+    actor: String,
+    tenant_id: Option<String>,
+    first_seen: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+    n: i64,
+}
+
+/// The /24 or /48 an address belongs to; `None` for anything unparseable.
+fn network(ip: &str) -> Option<String> {
+    match ip.parse::<IpAddr>().ok()? {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            Some(format!("{}.{}.{}.0/24", a, b, c))
+        }
+        IpAddr::V6(v6) => {
+            let s = v6.segments();
+            Some(format!("{:x}:{:x}:{:x}::/48", s[0], s[1], s[2]))
+        }
+    }
+}
+
+fn shares(counts: &HashMap<String, i64>, total: i64) -> HashMap<String, f64> {
+    let mut top: Vec<(&String, &i64)> = counts.iter().collect();
+    top.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    top.into_iter()
+        .take(MAX_PROFILE_ENTRIES)
+        .map(|(key, n)| (key.clone(), *n as f64 / total.max(1) as f64))
+        .collect()
+}
+
+fn learn(activity: &Activity) -> Profile {
+    let days = activity.daily.len().max(1) as f64;
+    let mean = activity.daily.iter().sum::<i64>() as f64 / days;
+    let variance = activity.daily.iter().map(|n| (*n as f64 - mean).powi(2)).sum::<f64>() / days;
+
+    Profile {
+        actions: shares(&activity.actions, activity.events),
+        hours: activity.hours.iter().map(|n| *n as f64 / activity.events.max(1) as f64).collect(),
+        networks: shares(&activity.networks, activity.events),
+        daily_mean: mean,
+        daily_stddev: variance.sqrt(),
+        events: activity.events,
+        active_days: activity.daily.len() as i64,
+    }
+}
+
+/// Score the day's activity against the profile, 0-100.
+fn score(profile: &Profile, today: &Activity) -> (f64, Factors) {
+    let total = today.events.max(1) as f64;
+    let share_where = |counts: &HashMap<String, i64>, unusual: &dyn Fn(&str) -> bool| {
+        counts.iter().filter(|(key, _)| unusual(key)).map(|(_, n)| *n).sum::<i64>() as f64 / total
+    };
+
+    let unfamiliar_actions = share_where(&today.actions, &|action| {
+        profile.actions.get(action).copied().unwrap_or(0.0) < RARE_ACTION_SHARE
+    });
+    let new_networks = share_where(&today.networks, &|net| !profile.networks.contains_key(net));
+    let unusual_hours = today
+        .hours
+        .iter()
+        .enumerate()
+        .filter(|(hour, _)| profile.hours.get(*hour).copied().unwrap_or(0.0) < RARE_HOUR_SHARE)
+        .map(|(_, n)| *n)
+        .sum::<i64>() as f64
+        / total;
+    let volume_zscore = (today.events as f64 - profile.daily_mean) / profile.daily_stddev.max(1.0);
+
+    let points = 30.0 * unfamiliar_actions
+        + 20.0 * unusual_hours
+        + 30.0 * new_networks
+        + 20.0 * ((volume_zscore - 2.0) / 3.0).clamp(0.0, 1.0);
+
+    (points.clamp(0.0, 100.0), Factors {
+        unfamiliar_actions,
+        unusual_hours,
+        new_networks,
+        volume_zscore,
+    })
+}
+
+pub struct UebaService {
+    storage: Storage,
+    config: UebaConfig,
+    zone: Tz,
+}
+
+impl UebaService {
+    pub async fn new(config: &Config, storage: Storage) -> Result<Self, SecurityError> {
+
+        let zone = timezone::parse_zone(&config.tenant_settings.time_zone)
+            .map_err(|_| SecurityError::ConfigError(format!("Unknown time zone '{}'", config.tenant_settings.time_zone)))?;
+
+        info!("UEBA service initialized successfully");
+        Ok(Self {
+            storage,
+            config: config.ueba.clone(),
+            zone,
+        })
+    }
+
+    /// The scoring day `now` falls in.
+    pub fn today(&self, now: DateTime<Utc>) -> NaiveDate {
+        timezone::local_date(self.zone, now)
+    }
+
+    fn start_of(&self, day: NaiveDate) -> DateTime<Utc> {
+        timezone::day_start(self.zone, day)
+    }
+
+    /// Per-actor activity in `[from, to)`.
+    async fn activity(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<HashMap<String, Activity>, SecurityError> {
+        let spans = sqlx::query_as::<_, Span>(
+            "SELECT actor, MAX(tenant_id) AS tenant_id, MIN(occurred_at) AS first_seen, \
+             MAX(occurred_at) AS last_seen, COUNT(*) AS n \
+             FROM audit_events WHERE occurred_at >= $1 AND occurred_at < $2 GROUP BY actor",
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_all(&mut **tx)
+        .await?;
+
+        let mut activity: HashMap<String, Activity> = spans
+            .into_iter()
+            .map(|span| (span.actor, Activity {
+                events: span.n,
+                tenant_id: span.tenant_id,
+                first_seen: Some(span.first_seen),
+                last_seen: Some(span.last_seen),
+                ..Activity::default()
+            }))
+            .collect();
+
+        for (dimension, expr) in [
+            ("action", "action"),
+            ("hour", "EXTRACT(HOUR FROM occurred_at AT TIME ZONE COALESCE(ts.overrides->>'time_zone', $3))::INT::TEXT"),
+            ("ip", "actor_ip"),
+            ("day", "(occurred_at AT TIME ZONE COALESCE(ts.overrides->>'time_zone', $3))::DATE::TEXT"),
+        ] {
+            // Hours and days by the actor's tenant's clock
+            let buckets = sqlx::query_as::<_, Bucket>(&format!(
+                "SELECT actor, {expr} AS bucket, COUNT(*) AS n FROM audit_events e \
+                 LEFT JOIN tenant_settings ts ON ts.tenant_id = e.tenant_id \
+                 WHERE occurred_at >= $1 AND occurred_at < $2 AND {expr} IS NOT NULL GROUP BY 1, 2"
+            ))
+            .bind(from)
+            .bind(to)
+            .bind(self.zone.name())
+            .fetch_all(&mut **tx)
+            .await?;
+
+            for Bucket { actor, bucket, n } in buckets {
+                let Some(entry) = activity.get_mut(&actor) else { continue };
+                match dimension {
+                    "action" => {
+                        entry.actions.insert(bucket, n);
+                    }
+                    "hour" => {
+                        if let Some(slot) = bucket.parse::<usize>().ok().and_then(|h| entry.hours.get_mut(h)) {
+                            *slot += n;
+                        }
+                    }
+                    "ip" => {
+                        if let Some(net) = network(&bucket) {
+                            *entry.networks.entry(net).or_default() += n;
+                        }
+                    }
+                    _ => entry.daily.push(n),
+                }
+            }
+        }
+
+        Ok(activity)
+    }
+
+    /// Rebuild every profile from the lookback window ending at `day`.
+    async fn relearn(&self, tx: &mut Transaction<'_, Postgres>, day: NaiveDate) -> Result<usize, SecurityError> {
+        let to = self.start_of(day);
+        let from = to - Duration::days(self.config.lookback_days);
+        let activity = self.activity(tx, from, to).await?;
+
+        for (actor, activity) in &activity {
+            sqlx::query(
+                "INSERT INTO user_baselines (actor, profile, learned_for) VALUES ($1, $2, $3) \
+                 ON CONFLICT (actor) DO UPDATE SET profile = $2, learned_for = $3, updated_at = NOW()",
+            )
+            .bind(actor)
+            .bind(Json(learn(activity)))
+            .bind(day)
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        // Actors with no history left in the window
+        sqlx::query("DELETE FROM user_baselines WHERE learned_for < $1")
+            .bind(day)
+            .execute(&mut **tx)
+            .await?;
+
+        sqlx::query("UPDATE ueba_state SET learned_for = $1, updated_at = NOW()")
+            .bind(day)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(activity.len())
+    }
+
+    fn ready(&self, profile: &Profile) -> bool {
+        profile.events >= self.config.min_events && profile.active_days >= self.config.min_active_days
+    }
+
+    /// Relearn if the day has turned, then score the day so far. Returns the
+    /// number of actors scored; another replica holding the lock means zero.
+    pub async fn run_pass(&self, state: &crate::AppState) -> Result<usize, SecurityError> {
+        let now = state.clock.now();
+        let today = self.today(now);
+        let mut tx = self.storage.begin().await?;
+
+        let Some(learned_for) = sqlx::query_scalar::<_, Option<NaiveDate>>(
+            "SELECT learned_for FROM ueba_state FOR UPDATE SKIP LOCKED",
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(0);
+        };
+
+        if learned_for != Some(today) {
+            let learned = self.relearn(&mut tx, today).await?;
+            info!("Relearned {} behavioral baselines", learned);
+        }
+
+        let profiles: HashMap<String, Profile> = sqlx::query_as::<_, (String, Json<Profile>)>(
+            "SELECT actor, profile FROM user_baselines",
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|(actor, profile)| (actor, profile.0))
+        .collect();
+
+        let previous: HashMap<String, f64> = sqlx::query_as::<_, (String, f64)>(
+            "SELECT actor, score FROM user_risk_scores WHERE day = $1",
+        )
+        .bind(today)
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .collect();
+
+        let activity = self.activity(&mut tx, self.start_of(today), now).await?;
+        let threshold = self.config.incident_threshold;
+        let mut scored = 0;
+        let mut opened = Vec::new();
+
+        for (actor, today_activity) in &activity {
+            let Some(profile) = profiles.get(actor).filter(|p| self.ready(p)) else {
+                continue;
+            };
+            let (risk, factors) = score(profile, today_activity);
+
+            sqlx::query(
+                "INSERT INTO user_risk_scores (actor, day, score, factors, events) VALUES ($1, $2, $3, $4, $5) \
+                 ON CONFLICT (actor, day) DO UPDATE SET score = $3, factors = $4, events = $5, scored_at = NOW()",
+            )
+            .bind(actor)
+            .bind(today)
+            .bind(risk)
+            .bind(Json(&factors))
+            .bind(today_activity.events)
+            .execute(&mut *tx)
+            .await?;
+            scored += 1;
+
+            let was_below = previous.get(actor).is_none_or(|before| *before < threshold);
+            if risk >= threshold && was_below {
+                opened.push(super::open(state, &mut tx, NewIncident {
+                    rule: "ueba".to_string(),
+                    severity: Severity::High,
+                    tenant_id: today_activity.tenant_id.clone(),
+                    entity_type: "actor",
+                    entity: actor.clone(),
+                    event_ids: Vec::new(),
+                    first_seen: today_activity.first_seen.unwrap_or(now),
+                    last_seen: today_activity.last_seen.unwrap_or(now),
+                }).await?);
+            }
+        }
+
+        sqlx::query("DELETE FROM user_risk_scores WHERE day < $1")
+            .bind(today - Duration::days(self.config.retention_days))
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        super::announce(state, &opened).await;
+        Ok(scored)
+    }
+
+    pub async fn baseline(&self, actor: &str) -> Result<Option<Baseline>, SecurityError> {
+        let baseline = sqlx::query_as::<_, Baseline>(
+            "SELECT actor, profile, learned_for, updated_at FROM user_baselines WHERE actor = $1",
+        )
+        .bind(actor)
+        .fetch_optional(self.storage.pool())
+        .await?;
+        Ok(baseline)
+    }
+
+    /// Daily scores for the last `days` days, oldest first.
+    pub async fn trend(&self, actor: &str, days: i64, today: NaiveDate) -> Result<Vec<RiskScore>, SecurityError> {
+        let days = days.clamp(1, self.config.retention_days);
+        let scores = sqlx::query_as::<_, RiskScore>(&format!(
+            "SELECT {} FROM user_risk_scores WHERE actor = $1 AND day > $2 ORDER BY day",
+            SCORE_COLUMNS
+        ))
+        .bind(actor)
+        .bind(today - Duration::days(days))
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(scores)
+    }
+
+    /// Highest-risk actors on a day, for dashboards.
+    pub async fn riskiest(&self, day: NaiveDate, min_score: f64, limit: i64) -> Result<Vec<RiskScore>, SecurityError> {
+        let scores = sqlx::query_as::<_, RiskScore>(&format!(
+            "SELECT {} FROM user_risk_scores WHERE day = $1 AND score >= $2 \
+             ORDER BY score DESC, actor LIMIT $3",
+            SCORE_COLUMNS
+        ))
+        .bind(day)
+        .bind(min_score)
+        .bind(limit.clamp(1, MAX_RISK_LIMIT))
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(scores)
+    }
+
+    /// Today's score for an actor; `None` while they are learning or idle.
+    pub async fn current_risk(&self, actor: &str, today: NaiveDate) -> Result<Option<RiskScore>, SecurityError> {
+        let score = sqlx::query_as::<_, RiskScore>(&format!(
+            "SELECT {} FROM user_risk_scores WHERE actor = $1 AND day = $2",
+            SCORE_COLUMNS
+        ))
+        .bind(actor)
+        .bind(today)
+        .fetch_optional(self.storage.pool())
+        .await?;
+        Ok(score)
+    }
+}
+
+/// Background loop relearning baselines and scoring the day's activity.
+pub async fn run_scoring(state: web::Data<crate::AppState>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(state.config.ueba.interval_secs));
+
+    loop {
+        interval.tick().await;
+        match state.ueba.run_pass(&state).await {
+            Ok(0) => {}
+            Ok(count) => info!("Scored behavior of {} actors", count),
+            Err(e) => error!("Behavior scoring failed: {:?}", e),
+        }
+    }
+}
+
+// HTTP handlers
+
+pub async fn user_risk_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TrendQuery>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let actor = path.into_inner();
+    let today = state.ueba.today(state.clock.now());
+    let baseline = match state.ueba.baseline(&actor).await {
+        Ok(baseline) => baseline,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let trend = match state.ueba.trend(&actor, query.days.unwrap_or(30), today).await {
+        Ok(trend) => trend,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    let status = match &baseline {
+        None => "unknown",
+        Some(b) if state.ueba.ready(&b.profile) => "ready",
+        Some(_) => "learning",
+    };
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "actor": actor,
+        "status": status,
+        "baseline": baseline,
+        "trend": trend
+    })))
+}
+
+pub async fn riskiest_handler(
+    req: HttpRequest,
+    query: web::Query<RiskQuery>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let day = query.day.unwrap_or_else(|| state.ueba.today(state.clock.now()));
+    match state.ueba.riskiest(day, query.min_score.unwrap_or(0.0), query.limit.unwrap_or(50)).await {
+        Ok(scores) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "day": day,
+            "scores": scores
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/ueba")
+            .route("/risk", web::get().to(riskiest_handler))
+            .route("/users/{actor}", web::get().to(user_risk_handler))
+    );
+}
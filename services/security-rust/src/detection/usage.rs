@@ -0,0 +1,758 @@
+/*!
+Usage Baselines
+Per-endpoint request volume learned by hour of week
+
+Fixed thresholds misfire when tenders close: the same hour that is quiet
+on a Sunday is the busiest of the week on a closing Friday. Every request
+under `/api/v1` is counted by matched route and the caller's tenant (see
+`monitoring::track`); replicas add their counts to hourly rows every
+`USAGE_INTERVAL_SECS`.
+
+Once a day each endpoint's profile is relearned from the preceding
+`USAGE_LOOKBACK_WEEKS`: the mean and standard deviation of requests for
+each of the 168 hours of the week, by its tenant's local time (see
+`timezone`). An hour is anomalous above
+
+```text
+max(mean + USAGE_THRESHOLD_SIGMA * max(stddev, sqrt(mean), 1), USAGE_MIN_REQUESTS)
+```
+
+for its hour of the week; the `sqrt(mean)` floor keeps perfectly regular
+history from alerting on counting noise. Endpoints with less than
+`USAGE_MIN_WEEKS` of history are still learning and are not checked.
+
+An admin can override an endpoint: `max_requests` replaces the learned
+threshold outright (and applies while learning), `sigma` replaces
+`USAGE_THRESHOLD_SIGMA` for it. The current and previous hour are checked
+every pass; each anomalous hour opens one `usage_anomaly` incident for
+the endpoint.
+
+`GET /admin/usage/preview` replays the last week's hours under proposed
+settings next to the current ones, so a threshold change can be judged by
+what it would have alerted on before it is made.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, DurationRound, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{FromRow, PgExecutor, Postgres, Transaction};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use super::{audit_detection, error_response, NewIncident};
+use crate::alerting::Severity;
+use crate::auth::auth_error_response;
+use crate::config::{Config, UsageConfig};
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+use crate::timezone;
+
+/// Hours in a week; profiles have one entry per hour.
+const SLOTS: usize = 168;
+
+const OVERRIDE_COLUMNS: &str = "tenant_id, route, max_requests, sigma, reason, updated_by, updated_at";
+
+const ANOMALY_COLUMNS: &str = "tenant_id, route, hour, requests, threshold, incident_id, detected_at";
+
+/// Anomalies listed per endpoint.
+const MAX_ANOMALIES: i64 = 50;
+
+/// The hour of the week of `u.hour` by the tenant's clock, Monday 00:00
+/// first; `$zone` is the default time zone.
+fn slot_expr(zone: usize) -> String {
+    let local = format!("u.hour AT TIME ZONE COALESCE(ts.overrides->>'time_zone', ${})", zone);
+    format!("((EXTRACT(ISODOW FROM {local}) - 1) * 24 + EXTRACT(HOUR FROM {local}))::INT")
+}
+
+/// An endpoint's normal volume.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    /// Mean requests per hour of the week, 168 entries.
+    pub means: Vec<f64>,
+    pub stddevs: Vec<f64>,
+    /// Weeks of history the profile was learned from.
+    pub weeks: i64,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Baseline {
+    pub tenant_id: String,
+    pub route: String,
+    pub profile: Json<Profile>,
+    pub learned_for: NaiveDate,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct UsageOverride {
+    pub tenant_id: String,
+    pub route: String,
+    pub max_requests: Option<i64>,
+    pub sigma: Option<f64>,
+    pub reason: String,
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Anomaly {
+    pub tenant_id: String,
+    pub route: String,
+    pub hour: DateTime<Utc>,
+    pub requests: i64,
+    pub threshold: i64,
+    pub incident_id: Option<Uuid>,
+    pub detected_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EndpointQuery {
+    pub route: String,
+    pub tenant_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OverrideRequest {
+    pub route: String,
+    pub tenant_id: Option<String>,
+    pub max_requests: Option<i64>,
+    pub sigma: Option<f64>,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewQuery {
+    pub route: String,
+    pub tenant_id: Option<String>,
+    pub max_requests: Option<i64>,
+    pub sigma: Option<f64>,
+    pub min_requests: Option<i64>,
+}
+
+/// What decides an endpoint's threshold.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Settings {
+    pub max_requests: Option<i64>,
+    pub sigma: f64,
+    pub min_requests: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PreviewHour {
+    pub hour: DateTime<Utc>,
+    pub slot: i32,
+    pub requests: i64,
+    pub current_threshold: Option<i64>,
+    pub proposed_threshold: Option<i64>,
+}
+
+#[derive(FromRow)]
+struct SlotTotal {
+    tenant_id: String,
+    route: String,
+    slot: i32,
+    total: i64,
+    squares: f64,
+}
+
+#[derive(FromRow)]
+struct HourCount {
+    tenant_id: String,
+    route: String,
+    hour: DateTime<Utc>,
+    requests: i64,
+    slot: i32,
+}
+
+fn learn(totals: &[(usize, i64, f64)], weeks: i64) -> Profile {
+    let mut profile = Profile {
+        means: vec![0.0; SLOTS],
+        stddevs: vec![0.0; SLOTS],
+        weeks,
+    };
+    let n = weeks.max(1) as f64;
+    for (slot, total, squares) in totals {
+        let (Some(mean), Some(stddev)) = (profile.means.get_mut(*slot), profile.stddevs.get_mut(*slot)) else {
+            continue;
+        };
+        *mean = *total as f64 / n;
+        *stddev = (squares / n - *mean * *mean).max(0.0).sqrt();
+    }
+    profile
+}
+
+/// The most requests an hour may see; `None` while nothing applies.
+fn threshold(profile: Option<&Profile>, slot: usize, settings: &Settings, min_weeks: i64) -> Option<i64> {
+    if let Some(max) = settings.max_requests {
+        return Some(max);
+    }
+    let profile = profile.filter(|p| p.weeks >= min_weeks)?;
+    let mean = profile.means.get(slot).copied().unwrap_or(0.0);
+    let stddev = profile.stddevs.get(slot).copied().unwrap_or(0.0);
+    let limit = (mean + settings.sigma * stddev.max(mean.sqrt()).max(1.0)).ceil() as i64;
+    Some(limit.max(settings.min_requests))
+}
+
+/// Tenant ('' for none) and route.
+type Endpoint = (String, String);
+type CountKey = (String, String, DateTime<Utc>);
+
+pub struct UsageBaselines {
+    storage: Storage,
+    config: UsageConfig,
+    zone: Tz,
+    /// Requests since the last flush by tenant ('' for none), route and hour.
+    counts: Mutex<HashMap<CountKey, i64>>,
+    dropped: AtomicU64,
+}
+
+impl UsageBaselines {
+    pub async fn new(config: &Config, storage: Storage) -> Result<Self, SecurityError> {
+        let zone = timezone::parse_zone(&config.tenant_settings.time_zone)
+            .map_err(|_| SecurityError::ConfigError(format!("Unknown time zone '{}'", config.tenant_settings.time_zone)))?;
+
+        info!("Usage baselines initialized ({})", if config.usage.enabled { "counting" } else { "disabled" });
+        Ok(Self {
+            storage,
+            config: config.usage.clone(),
+            zone,
+            counts: Mutex::new(HashMap::new()),
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    /// Count a request; dropped once `USAGE_MAX_TRACKED` endpoints are
+    /// waiting for the next flush.
+    pub fn note(&self, route: &str, tenant_id: Option<&str>, at: DateTime<Utc>) {
+        let hour = at.duration_trunc(Duration::hours(1)).unwrap_or(at);
+        let key = (tenant_id.unwrap_or_default().to_string(), route.to_string(), hour);
+        let mut counts = self.counts.lock().unwrap();
+        if counts.len() >= self.config.max_tracked && !counts.contains_key(&key) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        *counts.entry(key).or_default() += 1;
+    }
+
+    /// Add this replica's counts to storage.
+    pub async fn flush(&self) -> Result<(), SecurityError> {
+        let counts = std::mem::take(&mut *self.counts.lock().unwrap());
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            warn!("{} requests not counted for usage baselines: over USAGE_MAX_TRACKED", dropped);
+        }
+        if counts.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = self.storage.begin().await?;
+        for ((tenant_id, route, hour), n) in &counts {
+            sqlx::query(
+                "INSERT INTO endpoint_usage (tenant_id, route, hour, requests) VALUES ($1, $2, $3, $4) \
+                 ON CONFLICT (tenant_id, route, hour) DO UPDATE SET requests = endpoint_usage.requests + $4",
+            )
+            .bind(tenant_id)
+            .bind(route)
+            .bind(hour)
+            .bind(n)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub fn today(&self, now: DateTime<Utc>) -> NaiveDate {
+        timezone::local_date(self.zone, now)
+    }
+
+    /// Rebuild every profile from the lookback window ending at the start of `day`.
+    async fn relearn(&self, tx: &mut Transaction<'_, Postgres>, day: NaiveDate) -> Result<usize, SecurityError> {
+        let to = timezone::day_start(self.zone, day);
+        let from = to - Duration::weeks(self.config.lookback_weeks);
+
+        let totals = sqlx::query_as::<_, SlotTotal>(&format!(
+            "SELECT u.tenant_id, u.route, {} AS slot, SUM(u.requests)::BIGINT AS total, \
+             SUM(u.requests::FLOAT8 * u.requests) AS squares \
+             FROM endpoint_usage u LEFT JOIN tenant_settings ts ON ts.tenant_id = NULLIF(u.tenant_id, '') \
+             WHERE u.hour >= $1 AND u.hour < $2 GROUP BY 1, 2, 3",
+            slot_expr(3)
+        ))
+        .bind(from)
+        .bind(to)
+        .bind(self.zone.name())
+        .fetch_all(&mut **tx)
+        .await?;
+
+        let first_seen: HashMap<(String, String), DateTime<Utc>> = sqlx::query_as::<_, (String, String, DateTime<Utc>)>(
+            "SELECT tenant_id, route, MIN(hour) FROM endpoint_usage WHERE hour >= $1 AND hour < $2 GROUP BY 1, 2",
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_all(&mut **tx)
+        .await?
+        .into_iter()
+        .map(|(tenant_id, route, first)| ((tenant_id, route), first))
+        .collect();
+
+        let mut by_endpoint: HashMap<Endpoint, Vec<(usize, i64, f64)>> = HashMap::new();
+        for SlotTotal { tenant_id, route, slot, total, squares } in totals {
+            by_endpoint.entry((tenant_id, route)).or_default().push((slot as usize, total, squares));
+        }
+
+        for ((tenant_id, route), totals) in &by_endpoint {
+            // Weeks the endpoint has been in use, so new ones are not averaged over silence
+            let since = first_seen.get(&(tenant_id.clone(), route.clone())).copied().unwrap_or(from);
+            let weeks = ((to - since).num_hours() + SLOTS as i64 - 1) / SLOTS as i64;
+            let profile = learn(totals, weeks.clamp(1, self.config.lookback_weeks));
+
+            sqlx::query(
+                "INSERT INTO usage_baselines (tenant_id, route, profile, learned_for) VALUES ($1, $2, $3, $4) \
+                 ON CONFLICT (tenant_id, route) DO UPDATE SET profile = $3, learned_for = $4, updated_at = NOW()",
+            )
+            .bind(tenant_id)
+            .bind(route)
+            .bind(Json(profile))
+            .bind(day)
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        // Endpoints with no traffic left in the window
+        sqlx::query("DELETE FROM usage_baselines WHERE learned_for < $1")
+            .bind(day)
+            .execute(&mut **tx)
+            .await?;
+        for table in ["endpoint_usage", "usage_anomalies"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE hour < $1", table))
+                .bind(from - Duration::weeks(1))
+                .execute(&mut **tx)
+                .await?;
+        }
+        sqlx::query("UPDATE usage_state SET learned_for = $1, updated_at = NOW()")
+            .bind(day)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(by_endpoint.len())
+    }
+
+    fn settings(&self, usage_override: Option<&UsageOverride>) -> Settings {
+        Settings {
+            max_requests: usage_override.and_then(|o| o.max_requests),
+            sigma: usage_override.and_then(|o| o.sigma).unwrap_or(self.config.threshold_sigma),
+            min_requests: self.config.min_requests,
+        }
+    }
+
+    async fn hour_counts<'e>(
+        &self,
+        executor: impl PgExecutor<'e>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        endpoint: Option<(&str, &str)>,
+    ) -> Result<Vec<HourCount>, SecurityError> {
+        let (tenant_id, route) = endpoint.unzip();
+        let counts = sqlx::query_as::<_, HourCount>(&format!(
+            "SELECT u.tenant_id, u.route, u.hour, u.requests, {} AS slot \
+             FROM endpoint_usage u LEFT JOIN tenant_settings ts ON ts.tenant_id = NULLIF(u.tenant_id, '') \
+             WHERE u.hour >= $1 AND u.hour < $2 \
+             AND ($4::TEXT IS NULL OR u.tenant_id = $4) AND ($5::TEXT IS NULL OR u.route = $5) \
+             ORDER BY u.hour",
+            slot_expr(3)
+        ))
+        .bind(from)
+        .bind(to)
+        .bind(self.zone.name())
+        .bind(tenant_id)
+        .bind(route)
+        .fetch_all(executor)
+        .await?;
+        Ok(counts)
+    }
+
+    /// Flush, relearn if the day has turned, then check the current and
+    /// previous hour. Returns the number of anomalies found; another
+    /// replica holding the lock means zero.
+    pub async fn run_pass(&self, state: &crate::AppState) -> Result<usize, SecurityError> {
+        self.flush().await?;
+
+        let now = state.clock.now();
+        let today = self.today(now);
+        let mut tx = self.storage.begin().await?;
+
+        let Some(learned_for) = sqlx::query_scalar::<_, Option<NaiveDate>>(
+            "SELECT learned_for FROM usage_state FOR UPDATE SKIP LOCKED",
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(0);
+        };
+
+        if learned_for != Some(today) {
+            let learned = self.relearn(&mut tx, today).await?;
+            info!("Relearned {} usage baselines", learned);
+        }
+
+        let profiles: HashMap<(String, String), Profile> = sqlx::query_as::<_, (String, String, Json<Profile>)>(
+            "SELECT tenant_id, route, profile FROM usage_baselines",
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|(tenant_id, route, profile)| ((tenant_id, route), profile.0))
+        .collect();
+
+        let overrides: HashMap<(String, String), UsageOverride> = sqlx::query_as::<_, UsageOverride>(&format!(
+            "SELECT {} FROM usage_overrides",
+            OVERRIDE_COLUMNS
+        ))
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|o| ((o.tenant_id.clone(), o.route.clone()), o))
+        .collect();
+
+        let hour = now.duration_trunc(Duration::hours(1)).unwrap_or(now);
+        let counts = self.hour_counts(&mut *tx, hour - Duration::hours(1), hour + Duration::hours(1), None).await?;
+        let mut opened = Vec::new();
+
+        for count in counts {
+            let key = (count.tenant_id.clone(), count.route.clone());
+            let settings = self.settings(overrides.get(&key));
+            let Some(limit) = threshold(profiles.get(&key), count.slot as usize, &settings, self.config.min_weeks) else {
+                continue;
+            };
+            if count.requests <= limit {
+                continue;
+            }
+
+            // One incident per endpoint and hour
+            let inserted = sqlx::query(
+                "INSERT INTO usage_anomalies (tenant_id, route, hour, requests, threshold) VALUES ($1, $2, $3, $4, $5) \
+                 ON CONFLICT (tenant_id, route, hour) DO NOTHING",
+            )
+            .bind(&count.tenant_id)
+            .bind(&count.route)
+            .bind(count.hour)
+            .bind(count.requests)
+            .bind(limit)
+            .execute(&mut *tx)
+            .await?;
+            if inserted.rows_affected() == 0 {
+                continue;
+            }
+
+            let incident = super::open(state, &mut tx, NewIncident {
+                rule: "usage_anomaly".to_string(),
+                severity: Severity::Medium,
+                tenant_id: Some(count.tenant_id.clone()).filter(|t| !t.is_empty()),
+                entity_type: "route",
+                entity: count.route.clone(),
+                event_ids: Vec::new(),
+                first_seen: count.hour,
+                last_seen: now,
+            }).await?;
+            sqlx::query("UPDATE usage_anomalies SET incident_id = $4 WHERE tenant_id = $1 AND route = $2 AND hour = $3")
+                .bind(&count.tenant_id)
+                .bind(&count.route)
+                .bind(count.hour)
+                .bind(incident.incident.id)
+                .execute(&mut *tx)
+                .await?;
+            opened.push(incident);
+        }
+        tx.commit().await?;
+
+        super::announce(state, &opened).await;
+        Ok(opened.len())
+    }
+
+    pub async fn baseline(&self, tenant_id: &str, route: &str) -> Result<Option<Baseline>, SecurityError> {
+        let baseline = sqlx::query_as::<_, Baseline>(
+            "SELECT tenant_id, route, profile, learned_for, updated_at FROM usage_baselines \
+             WHERE tenant_id = $1 AND route = $2",
+        )
+        .bind(tenant_id)
+        .bind(route)
+        .fetch_optional(self.storage.pool())
+        .await?;
+        Ok(baseline)
+    }
+
+    pub async fn get_override(&self, tenant_id: &str, route: &str) -> Result<Option<UsageOverride>, SecurityError> {
+        let found = sqlx::query_as::<_, UsageOverride>(&format!(
+            "SELECT {} FROM usage_overrides WHERE tenant_id = $1 AND route = $2",
+            OVERRIDE_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(route)
+        .fetch_optional(self.storage.pool())
+        .await?;
+        Ok(found)
+    }
+
+    pub async fn anomalies(&self, tenant_id: &str, route: &str) -> Result<Vec<Anomaly>, SecurityError> {
+        let anomalies = sqlx::query_as::<_, Anomaly>(&format!(
+            "SELECT {} FROM usage_anomalies WHERE tenant_id = $1 AND route = $2 ORDER BY hour DESC LIMIT $3",
+            ANOMALY_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(route)
+        .bind(MAX_ANOMALIES)
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(anomalies)
+    }
+
+    pub async fn put_override(&self, actor: &str, request: &OverrideRequest) -> Result<UsageOverride, SecurityError> {
+        if request.route.trim().is_empty() {
+            return Err(SecurityError::ValidationError("route is required".to_string()));
+        }
+        if request.reason.trim().is_empty() {
+            return Err(SecurityError::ValidationError("reason is required".to_string()));
+        }
+        if request.max_requests.is_none() && request.sigma.is_none() {
+            return Err(SecurityError::ValidationError("Set max_requests, sigma or both".to_string()));
+        }
+        if request.max_requests.is_some_and(|max| max < 1) {
+            return Err(SecurityError::ValidationError("max_requests must be at least 1".to_string()));
+        }
+        if request.sigma.is_some_and(|sigma| sigma <= 0.0) {
+            return Err(SecurityError::ValidationError("sigma must be positive".to_string()));
+        }
+
+        let saved = sqlx::query_as::<_, UsageOverride>(&format!(
+            "INSERT INTO usage_overrides (tenant_id, route, max_requests, sigma, reason, updated_by) \
+             VALUES ($1, $2, $3, $4, $5, $6) \
+             ON CONFLICT (tenant_id, route) DO UPDATE SET max_requests = $3, sigma = $4, reason = $5, \
+             updated_by = $6, updated_at = NOW() RETURNING {}",
+            OVERRIDE_COLUMNS
+        ))
+        .bind(request.tenant_id.as_deref().unwrap_or_default())
+        .bind(request.route.trim())
+        .bind(request.max_requests)
+        .bind(request.sigma)
+        .bind(request.reason.trim())
+        .bind(actor)
+        .fetch_one(self.storage.pool())
+        .await?;
+        Ok(saved)
+    }
+
+    pub async fn delete_override(&self, tenant_id: &str, route: &str) -> Result<(), SecurityError> {
+        let deleted = sqlx::query("DELETE FROM usage_overrides WHERE tenant_id = $1 AND route = $2")
+            .bind(tenant_id)
+            .bind(route)
+            .execute(self.storage.pool())
+            .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(SecurityError::NotFound(format!("No override for {}", route)));
+        }
+        Ok(())
+    }
+
+    /// The last week's hours for an endpoint under its current settings
+    /// and under `proposed`, with the current profile.
+    pub async fn preview(
+        &self,
+        now: DateTime<Utc>,
+        tenant_id: &str,
+        route: &str,
+        proposed: &Settings,
+    ) -> Result<(Settings, Vec<PreviewHour>), SecurityError> {
+        let baseline = self.baseline(tenant_id, route).await?;
+        let current = self.settings(self.get_override(tenant_id, route).await?.as_ref());
+        let profile = baseline.as_ref().map(|b| &b.profile.0);
+
+        let to = now.duration_trunc(Duration::hours(1)).unwrap_or(now);
+        let counts = self
+            .hour_counts(self.storage.pool(), to - Duration::weeks(1), to, Some((tenant_id, route)))
+            .await?;
+        let hours = counts
+            .into_iter()
+            .map(|count| PreviewHour {
+                hour: count.hour,
+                slot: count.slot,
+                requests: count.requests,
+                current_threshold: threshold(profile, count.slot as usize, &current, self.config.min_weeks),
+                proposed_threshold: threshold(profile, count.slot as usize, proposed, self.config.min_weeks),
+            })
+            .collect();
+        Ok((current, hours))
+    }
+}
+
+/// Background loop flushing counts, relearning baselines and checking
+/// the latest hours.
+pub async fn run_scoring(state: web::Data<crate::AppState>) {
+    if !state.config.usage.enabled {
+        return;
+    }
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(state.config.usage.interval_secs));
+
+    loop {
+        interval.tick().await;
+        match state.usage.run_pass(&state).await {
+            Ok(0) => {}
+            Ok(count) => info!("Found {} usage anomalies", count),
+            Err(e) => error!("Usage scoring failed: {:?}", e),
+        }
+    }
+}
+
+// HTTP handlers
+
+fn status(baseline: Option<&Baseline>, min_weeks: i64) -> &'static str {
+    match baseline {
+        None => "unknown",
+        Some(b) if b.profile.weeks >= min_weeks => "ready",
+        Some(_) => "learning",
+    }
+}
+
+pub async fn endpoint_handler(
+    req: HttpRequest,
+    query: web::Query<EndpointQuery>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let tenant_id = query.tenant_id.as_deref().unwrap_or_default();
+    let usage = &state.usage;
+    let baseline = match usage.baseline(tenant_id, &query.route).await {
+        Ok(baseline) => baseline,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let usage_override = match usage.get_override(tenant_id, &query.route).await {
+        Ok(found) => found,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let anomalies = match usage.anomalies(tenant_id, &query.route).await {
+        Ok(anomalies) => anomalies,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    let settings = usage.settings(usage_override.as_ref());
+    let min_weeks = state.config.usage.min_weeks;
+    let thresholds: Vec<Option<i64>> = (0..SLOTS)
+        .map(|slot| threshold(baseline.as_ref().map(|b| &b.profile.0), slot, &settings, min_weeks))
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "route": query.route,
+        "tenant_id": query.tenant_id,
+        "status": status(baseline.as_ref(), min_weeks),
+        "baseline": baseline,
+        "override": usage_override,
+        "settings": settings,
+        "thresholds": thresholds,
+        "anomalies": anomalies
+    })))
+}
+
+pub async fn put_override_handler(
+    req: HttpRequest,
+    request: web::Json<OverrideRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.usage.put_override(&principal.subject, &request).await {
+        Ok(saved) => {
+            audit_detection(&state, &principal, "usage_override.put", format!("usage_override:{}", saved.route), serde_json::json!({
+                "tenant_id": saved.tenant_id,
+                "max_requests": saved.max_requests,
+                "sigma": saved.sigma,
+                "reason": saved.reason
+            })).await;
+            Ok(HttpResponse::Ok().json(saved))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn delete_override_handler(
+    req: HttpRequest,
+    query: web::Query<EndpointQuery>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let tenant_id = query.tenant_id.as_deref().unwrap_or_default();
+    match state.usage.delete_override(tenant_id, &query.route).await {
+        Ok(()) => {
+            audit_detection(&state, &principal, "usage_override.delete", format!("usage_override:{}", query.route), serde_json::json!({
+                "tenant_id": tenant_id
+            })).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn preview_handler(
+    req: HttpRequest,
+    query: web::Query<PreviewQuery>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+    if query.sigma.is_some_and(|sigma| sigma <= 0.0) || query.max_requests.is_some_and(|max| max < 1) {
+        return Ok(error_response(SecurityError::ValidationError(
+            "sigma must be positive and max_requests at least 1".to_string(),
+        )));
+    }
+
+    let config = &state.config.usage;
+    let proposed = Settings {
+        max_requests: query.max_requests,
+        sigma: query.sigma.unwrap_or(config.threshold_sigma),
+        min_requests: query.min_requests.unwrap_or(config.min_requests),
+    };
+    let tenant_id = query.tenant_id.as_deref().unwrap_or_default();
+    match state.usage.preview(state.clock.now(), tenant_id, &query.route, &proposed).await {
+        Ok((current, hours)) => {
+            let over = |hour: &PreviewHour, limit: Option<i64>| limit.is_some_and(|limit| hour.requests > limit);
+            let current_alerts = hours.iter().filter(|h| over(h, h.current_threshold)).count();
+            let proposed_alerts = hours.iter().filter(|h| over(h, h.proposed_threshold)).count();
+            let alerting: Vec<&PreviewHour> = hours
+                .iter()
+                .filter(|h| over(h, h.current_threshold) || over(h, h.proposed_threshold))
+                .collect();
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "route": query.route,
+                "tenant_id": query.tenant_id,
+                "hours_checked": hours.len(),
+                "current": { "settings": current, "alerts": current_alerts },
+                "proposed": { "settings": proposed, "alerts": proposed_alerts },
+                "alerting_hours": alerting
+            })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/usage")
+            .route("/endpoint", web::get().to(endpoint_handler))
+            .route("/overrides", web::put().to(put_override_handler))
+            .route("/overrides", web::delete().to(delete_override_handler))
+            .route("/preview", web::get().to(preview_handler))
+    );
+}
@@ -0,0 +1,386 @@
+/*!
+Dead-Letter Queue
+Inspectable store of failed outbound deliveries with bulk replay
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::auth::auth_error_response;
+use crate::config::Config;
+use crate::deadline;
+use crate::errors::SecurityError;
+use crate::events::{DomainEvent, EventPublisher};
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::Storage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliverySubsystem {
+    /// Outbound HTTP webhooks (alert sinks and webhook subscriptions).
+    Webhook,
+    /// Domain events the outbox relay could not publish.
+    EventBus,
+}
+
+impl DeliverySubsystem {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliverySubsystem::Webhook => "webhook",
+            DeliverySubsystem::EventBus => "event_bus",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "webhook" => Some(DeliverySubsystem::Webhook),
+            "event_bus" => Some(DeliverySubsystem::EventBus),
+            _ => None,
+        }
+    }
+}
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "created_at", column: "created_at", kind: KeyKind::Timestamp },
+    SortField { name: "last_attempt_at", column: "last_attempt_at", kind: KeyKind::Timestamp },
+];
+
+const SELECT_COLUMNS: &str = "id, subsystem, target, payload, failure_reason, attempts, status, created_at, last_attempt_at";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct DeadLetter {
+    pub id: Uuid,
+    pub subsystem: String,
+    pub target: String,
+    pub payload: serde_json::Value,
+    pub failure_reason: String,
+    pub attempts: i32,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub last_attempt_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DeadLetterFilter {
+    pub subsystem: Option<String>,
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayRequest {
+    /// Explicit entries to replay; when empty, all pending entries matching `subsystem`.
+    #[serde(default)]
+    pub ids: Vec<Uuid>,
+    pub subsystem: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReplayReport {
+    pub replayed: Vec<Uuid>,
+    pub failed: Vec<ReplayFailure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplayFailure {
+    pub id: Uuid,
+    pub reason: String,
+}
+
+#[derive(Clone)]
+pub struct DeadLetterQueue {
+    storage: Storage,
+    client: reqwest::Client,
+    publisher: EventPublisher,
+    max_replay_batch: i64,
+}
+
+impl DeadLetterQueue {
+    pub async fn new(config: &Config, storage: Storage, publisher: EventPublisher) -> Result<Self, SecurityError> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.alerting.timeout_secs))
+            .build()
+            .map_err(|e| SecurityError::ConfigError(format!("DLQ client: {}", e)))?;
+
+        info!("Dead-letter queue initialized successfully");
+        Ok(Self {
+            storage,
+            client,
+            publisher,
+            max_replay_batch: config.dlq.max_replay_batch,
+        })
+    }
+
+    pub async fn push(
+        &self,
+        subsystem: DeliverySubsystem,
+        target: &str,
+        payload: &serde_json::Value,
+        failure_reason: &str,
+    ) -> Result<Uuid, SecurityError> {
+        let id = Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO dead_letters (id, subsystem, target, payload, failure_reason) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(id)
+        .bind(subsystem.as_str())
+        .bind(target)
+        .bind(payload)
+        .bind(failure_reason)
+        .execute(self.storage.pool())
+        .await?;
+
+        warn!("Delivery to {} dead-lettered as {}: {}", target, id, failure_reason);
+        Ok(id)
+    }
+
+    pub async fn list(&self, filter: &DeadLetterFilter) -> Result<Vec<DeadLetter>, SecurityError> {
+        let entries = sqlx::query_as::<_, DeadLetter>(&format!(
+            "SELECT {} FROM dead_letters \
+             WHERE ($1::TEXT IS NULL OR subsystem = $1) AND status = COALESCE($2, 'pending') \
+             ORDER BY created_at LIMIT $3",
+            SELECT_COLUMNS
+        ))
+        .bind(&filter.subsystem)
+        .bind(&filter.status)
+        .bind(filter.limit.unwrap_or(100).clamp(1, 1000))
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        Ok(entries)
+    }
+
+    pub async fn list_page(
+        &self,
+        filter: &DeadLetterFilter,
+        page: &PageRequest,
+    ) -> Result<Page<DeadLetter>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM dead_letters WHERE status = ",
+            SELECT_COLUMNS
+        ));
+        builder.push_bind(filter.status.clone().unwrap_or_else(|| "pending".to_string()));
+        if let Some(subsystem) = &filter.subsystem {
+            builder.push(" AND subsystem = ").push_bind(subsystem.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let entries = builder
+            .build_query_as::<DeadLetter>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(entries, |entry, sort| {
+            let key = match sort {
+                "last_attempt_at" => entry.last_attempt_at,
+                _ => entry.created_at,
+            };
+            (SortKey::Timestamp(key), entry.id)
+        }))
+    }
+
+    /// Pending entry counts per subsystem, exported as the DLQ depth gauge.
+    pub async fn depth(&self) -> Result<Vec<(String, i64)>, SecurityError> {
+        let rows: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT subsystem, COUNT(*) FROM dead_letters WHERE status = 'pending' GROUP BY subsystem",
+        )
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        Ok(rows)
+    }
+
+    pub async fn replay(&self, request: &ReplayRequest) -> Result<ReplayReport, SecurityError> {
+        let entries = if request.ids.is_empty() {
+            self.list(&DeadLetterFilter {
+                subsystem: request.subsystem.clone(),
+                status: None,
+                limit: Some(self.max_replay_batch),
+            })
+            .await?
+        } else {
+            sqlx::query_as::<_, DeadLetter>(&format!(
+                "SELECT {} FROM dead_letters WHERE id = ANY($1) AND status = 'pending'",
+                SELECT_COLUMNS
+            ))
+            .bind(&request.ids)
+            .fetch_all(self.storage.pool())
+            .await?
+        };
+
+        let mut report = ReplayReport::default();
+        for entry in entries {
+            match self.replay_entry(&entry).await {
+                Ok(()) => report.replayed.push(entry.id),
+                Err(SecurityError::DeliveryError(reason)) => {
+                    report.failed.push(ReplayFailure { id: entry.id, reason });
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        info!("DLQ replay: {} replayed, {} failed", report.replayed.len(), report.failed.len());
+        Ok(report)
+    }
+
+    /// A single pending entry; anything already replayed or discarded is `NotFound`.
+    pub async fn pending(&self, id: Uuid) -> Result<DeadLetter, SecurityError> {
+        sqlx::query_as::<_, DeadLetter>(&format!(
+            "SELECT {} FROM dead_letters WHERE id = $1 AND status = 'pending'",
+            SELECT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("No pending dead letter {}", id)))
+    }
+
+    /// Redeliver one entry and record the attempt; delivery failures surface as `DeliveryError`.
+    pub async fn replay_entry(&self, entry: &DeadLetter) -> Result<(), SecurityError> {
+        match self.redeliver(entry).await {
+            Ok(()) => {
+                sqlx::query(
+                    "UPDATE dead_letters SET status = 'replayed', attempts = attempts + 1, last_attempt_at = NOW() WHERE id = $1",
+                )
+                .bind(entry.id)
+                .execute(self.storage.pool())
+                .await?;
+                Ok(())
+            }
+            Err(e) => {
+                let reason = e.to_string();
+                sqlx::query(
+                    "UPDATE dead_letters SET failure_reason = $2, attempts = attempts + 1, last_attempt_at = NOW() WHERE id = $1",
+                )
+                .bind(entry.id)
+                .bind(&reason)
+                .execute(self.storage.pool())
+                .await?;
+                Err(SecurityError::DeliveryError(reason))
+            }
+        }
+    }
+
+    pub async fn discard(&self, ids: &[Uuid]) -> Result<u64, SecurityError> {
+        let result = sqlx::query("UPDATE dead_letters SET status = 'discarded' WHERE id = ANY($1) AND status = 'pending'")
+            .bind(ids)
+            .execute(self.storage.pool())
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn redeliver(&self, entry: &DeadLetter) -> Result<(), SecurityError> {
+        match DeliverySubsystem::parse(&entry.subsystem) {
+            Some(DeliverySubsystem::Webhook) => {
+                let response = deadline::outbound(self.client.post(&entry.target))
+                    .json(&entry.payload)
+                    .send()
+                    .await
+                    .map_err(|e| SecurityError::DeliveryError(e.to_string()))?;
+
+                if !response.status().is_success() {
+                    return Err(SecurityError::DeliveryError(format!("Target returned {}", response.status())));
+                }
+                Ok(())
+            }
+            Some(DeliverySubsystem::EventBus) => {
+                let event: DomainEvent = serde_json::from_value(entry.payload.clone())
+                    .map_err(|e| SecurityError::DeliveryError(format!("Corrupt event payload: {}", e)))?;
+                self.publisher.publish(&event).await
+            }
+            None => Err(SecurityError::DeliveryError(format!("Unknown subsystem '{}'", entry.subsystem))),
+        }
+    }
+}
+
+// HTTP handlers
+
+pub async fn list_handler(
+    req: HttpRequest,
+    query: web::Query<DeadLetterFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Asc) {
+        Ok(page) => page,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e.to_string()
+        }))),
+    };
+
+    match state.dead_letters.list_page(&query, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "entries": page.items,
+            "page": page.info
+        }))),
+        Err(e) => {
+            error!("DLQ listing failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "DLQ listing failed"
+            })))
+        }
+    }
+}
+
+pub async fn replay_handler(
+    req: HttpRequest,
+    request: web::Json<ReplayRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    info!("DLQ replay requested by {}", principal.subject);
+    match state.dead_letters.replay(&request).await {
+        Ok(report) => Ok(HttpResponse::Ok().json(report)),
+        Err(e) => {
+            error!("DLQ replay failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "DLQ replay failed"
+            })))
+        }
+    }
+}
+
+pub async fn discard_handler(
+    req: HttpRequest,
+    request: web::Json<Vec<Uuid>>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.dead_letters.discard(&request).await {
+        Ok(discarded) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "discarded": discarded
+        }))),
+        Err(e) => {
+            error!("DLQ discard failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "DLQ discard failed"
+            })))
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/dlq")
+            .route("", web::get().to(list_handler))
+            .route("/replay", web::post().to(replay_handler))
+            .route("/discard", web::post().to(discard_handler))
+    );
+}
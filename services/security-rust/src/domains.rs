@@ -0,0 +1,738 @@
+/*!
+Domain Verification
+Proof that a tenant controls a domain, by DNS TXT challenge
+
+Tenant-specific endpoints pointing at a domain (CORS origins in tenant
+settings, mailbox webhooks of tenant identities, and SAML endpoints the
+identity service registers) are only accepted for domains the tenant has
+verified, while `DOMAIN_VERIFICATION_REQUIRED` is on. A verified domain
+covers its subdomains.
+
+Admins claim a domain for a tenant with `POST /admin/domains` and get a
+challenge to publish:
+
+```text
+_cotai-challenge.example.com.br  TXT  "cotai-domain-verification=<token>"
+```
+
+(`DOMAIN_VERIFICATION_CHALLENGE_LABEL` names the record). Challenges are
+looked up every `DOMAIN_VERIFICATION_PENDING_INTERVAL_SECS`, or at once
+with `POST /admin/domains/{id}/verify`, until they pass or
+`DOMAIN_VERIFICATION_CHALLENGE_TTL_HOURS` run out (`expired`; a new token
+comes from `POST /admin/domains/{id}/challenge`).
+
+Lookups go to `DOMAIN_VERIFICATION_RESOLVERS` (the system's when empty),
+bypassing any cache, through a validating resolver. Answers from signed
+zones must validate: a bogus signature fails the check. Unsigned zones are
+accepted and recorded as such, unless `DOMAIN_VERIFICATION_REQUIRE_DNSSEC`
+is on.
+
+Verified domains are checked again every
+`DOMAIN_VERIFICATION_RECHECK_INTERVAL_SECS`, so the record must stay
+published. After `DOMAIN_VERIFICATION_LAPSE_AFTER_FAILURES` failed
+re-checks in a row (resolver outages do not count) a domain `lapses`: its
+origins stop being served and an alert is sent. A lapsed domain whose
+record comes back is verified again. Other services ask
+`GET /domains/check?tenant_id=..&url=..` (`DOMAIN_VERIFICATION_CHECK_SCOPE`)
+before accepting an endpoint. Status changes are audited.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use hickory_resolver::error::{ResolveError, ResolveErrorKind};
+use hickory_resolver::proto::error::ProtoErrorKind;
+use hickory_resolver::TokioAsyncResolver;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::alerting::{Alert, Severity};
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, DomainVerificationConfig};
+use crate::crypto::CryptoService;
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+use crate::AppState;
+
+pub const SYSTEM_ACTOR: &str = "system:domains";
+
+const TOKEN_PREFIX: &str = "cotai-domain-verification=";
+const TOKEN_BYTES: usize = 16;
+
+const DOMAIN_COLUMNS: &str = "id, tenant_id, domain, token, status, dnssec, failures, last_error, created_by, \
+                              created_at, expires_at, verified_at, last_checked_at, next_check_at";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct TenantDomain {
+    pub id: Uuid,
+    pub tenant_id: String,
+    pub domain: String,
+    #[serde(skip)]
+    pub token: String,
+    /// `pending`, `verified`, `lapsed` or `expired`.
+    pub status: String,
+    /// Whether the last passing answer was DNSSEC-validated.
+    pub dnssec: bool,
+    /// Failed re-checks in a row.
+    pub failures: i32,
+    pub last_error: Option<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    /// When a pending challenge runs out.
+    pub expires_at: DateTime<Utc>,
+    pub verified_at: Option<DateTime<Utc>>,
+    pub last_checked_at: Option<DateTime<Utc>>,
+    pub next_check_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Challenge {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub record_type: &'static str,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DomainView {
+    #[serde(flatten)]
+    pub domain: TenantDomain,
+    pub challenge: Challenge,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClaimRequest {
+    pub tenant_id: String,
+    pub domain: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DomainFilter {
+    pub tenant_id: Option<String>,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CheckQuery {
+    pub tenant_id: String,
+    /// A URL, origin or bare host name.
+    pub url: String,
+}
+
+/// A domain whose status a check changed.
+#[derive(Debug, Clone)]
+pub struct StatusChange {
+    /// `verified`, `lapsed`, `restored` or `expired`.
+    pub action: &'static str,
+    pub domain: TenantDomain,
+}
+
+/// What the challenge record lookup found.
+enum Lookup {
+    Found { values: Vec<String>, dnssec: bool },
+    Missing,
+    /// The answer cannot be trusted: DNSSEC validation failed, or the zone
+    /// is unsigned where signing is required.
+    Rejected(String),
+    /// The resolver failed; nothing was learned.
+    Unavailable(String),
+}
+
+/// The host a URL, origin or bare name points at, lowercased.
+pub fn host_of(url: &str) -> Option<String> {
+    let host = match reqwest::Url::parse(url) {
+        Ok(parsed) => parsed.host_str()?.to_string(),
+        Err(_) => url.trim().to_string(),
+    };
+    let host = host.trim_end_matches('.').to_lowercase();
+    valid_domain(&host).then_some(host)
+}
+
+fn valid_domain(domain: &str) -> bool {
+    domain.len() <= 253
+        && domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Whether `domain` covers `host`: the domain itself or a subdomain.
+pub fn covers(domain: &str, host: &str) -> bool {
+    host == domain || host.strip_suffix(domain).is_some_and(|rest| rest.ends_with('.'))
+}
+
+/// Domains `tenant_id` has verified.
+pub async fn verified_domains(storage: &Storage, tenant_id: &str) -> Result<Vec<String>, SecurityError> {
+    Ok(sqlx::query_scalar::<_, String>(
+        "SELECT domain FROM tenant_domains WHERE tenant_id = $1 AND status = 'verified'",
+    )
+    .bind(tenant_id)
+    .fetch_all(storage.pool())
+    .await?)
+}
+
+fn resolver(config: &DomainVerificationConfig, validate: bool) -> Result<TokioAsyncResolver, SecurityError> {
+    let (resolver_config, mut opts) = if config.resolvers.is_empty() {
+        hickory_resolver::system_conf::read_system_conf()
+            .map_err(|e| SecurityError::ConfigError(format!("System resolver configuration: {}", e)))?
+    } else {
+        let ips = config
+            .resolvers
+            .iter()
+            .map(|ip| ip.parse::<IpAddr>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| SecurityError::ConfigError(format!("DOMAIN_VERIFICATION_RESOLVERS: {}", e)))?;
+        let servers = NameServerConfigGroup::from_ips_clear(&ips, 53, true);
+        (ResolverConfig::from_parts(None, vec![], servers), ResolverOpts::default())
+    };
+    opts.timeout = std::time::Duration::from_secs(config.dns_timeout_secs);
+    opts.validate = validate;
+    // Every check asks the authoritative servers' current answer
+    opts.cache_size = 0;
+    Ok(TokioAsyncResolver::tokio(resolver_config, opts))
+}
+
+fn unsigned(e: &ResolveError) -> bool {
+    matches!(e.kind(), ResolveErrorKind::Proto(proto) if matches!(proto.kind(), ProtoErrorKind::RrsigsNotPresent { .. }))
+}
+
+pub struct DomainVerificationService {
+    storage: Storage,
+    config: DomainVerificationConfig,
+    clock: Arc<dyn Clock>,
+    validating: TokioAsyncResolver,
+    plain: TokioAsyncResolver,
+}
+
+impl DomainVerificationService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let domains = &config.domain_verification;
+        info!("Domain verification initialized successfully");
+        Ok(Self {
+            storage,
+            config: domains.clone(),
+            clock,
+            validating: resolver(domains, true)?,
+            plain: resolver(domains, false)?,
+        })
+    }
+
+    fn challenge(&self, domain: &TenantDomain) -> Challenge {
+        Challenge {
+            name: format!("{}.{}", self.config.challenge_label, domain.domain),
+            record_type: "TXT",
+            value: format!("{}{}", TOKEN_PREFIX, domain.token),
+        }
+    }
+
+    pub fn view(&self, domain: TenantDomain) -> DomainView {
+        DomainView {
+            challenge: self.challenge(&domain),
+            domain,
+        }
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<TenantDomain, SecurityError> {
+        sqlx::query_as::<_, TenantDomain>(&format!("SELECT {} FROM tenant_domains WHERE id = $1", DOMAIN_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound(format!("Domain {} not found", id)))
+    }
+
+    pub async fn list(&self, filter: &DomainFilter) -> Result<Vec<TenantDomain>, SecurityError> {
+        Ok(sqlx::query_as::<_, TenantDomain>(&format!(
+            "SELECT {} FROM tenant_domains WHERE ($1::text IS NULL OR tenant_id = $1) \
+             AND ($2::text IS NULL OR status = $2) ORDER BY tenant_id, domain",
+            DOMAIN_COLUMNS
+        ))
+        .bind(&filter.tenant_id)
+        .bind(&filter.status)
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    async fn token(crypto: &CryptoService) -> Result<String, SecurityError> {
+        Ok(hex::encode(crypto.secure_random(TOKEN_BYTES).await?))
+    }
+
+    pub async fn claim(&self, crypto: &CryptoService, principal: &Principal, request: ClaimRequest) -> Result<TenantDomain, SecurityError> {
+        let tenant_id = request.tenant_id.trim();
+        if tenant_id.is_empty() {
+            return Err(SecurityError::ValidationError("tenant_id must not be empty".to_string()));
+        }
+        let domain = request.domain.trim().trim_end_matches('.').to_lowercase();
+        if !valid_domain(&domain) {
+            return Err(SecurityError::ValidationError(format!("'{}' is not a domain name", domain)));
+        }
+
+        let now = self.clock.now();
+        let claimed = sqlx::query_as::<_, TenantDomain>(&format!(
+            "INSERT INTO tenant_domains (id, tenant_id, domain, token, status, dnssec, failures, created_by, \
+             created_at, expires_at, next_check_at) \
+             VALUES ($1, $2, $3, $4, 'pending', FALSE, 0, $5, $6, $7, $6) \
+             ON CONFLICT (tenant_id, domain) DO NOTHING RETURNING {}",
+            DOMAIN_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(tenant_id)
+        .bind(&domain)
+        .bind(Self::token(crypto).await?)
+        .bind(&principal.subject)
+        .bind(now)
+        .bind(now + Duration::hours(self.config.challenge_ttl_hours))
+        .fetch_optional(self.storage.pool())
+        .await?;
+        let claimed = claimed.ok_or_else(|| {
+            SecurityError::Conflict(format!("Tenant {} has already claimed {}", tenant_id, domain))
+        })?;
+        info!("{} claimed {} for tenant {}", principal.subject, domain, tenant_id);
+        Ok(claimed)
+    }
+
+    /// A fresh token for a claim, which must be verified again.
+    pub async fn renew_challenge(&self, crypto: &CryptoService, id: Uuid) -> Result<TenantDomain, SecurityError> {
+        let now = self.clock.now();
+        sqlx::query_as::<_, TenantDomain>(&format!(
+            "UPDATE tenant_domains SET token = $2, status = 'pending', failures = 0, last_error = NULL, \
+             expires_at = $3, next_check_at = $4 WHERE id = $1 AND status <> 'verified' RETURNING {}",
+            DOMAIN_COLUMNS
+        ))
+        .bind(id)
+        .bind(Self::token(crypto).await?)
+        .bind(now + Duration::hours(self.config.challenge_ttl_hours))
+        .bind(now)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("No unverified domain {}", id)))
+    }
+
+    pub async fn remove(&self, id: Uuid) -> Result<TenantDomain, SecurityError> {
+        sqlx::query_as::<_, TenantDomain>(&format!("DELETE FROM tenant_domains WHERE id = $1 RETURNING {}", DOMAIN_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound(format!("Domain {} not found", id)))
+    }
+
+    /// The challenge record's values, validated where the zone is signed.
+    async fn lookup(&self, name: &str) -> Lookup {
+        let name = format!("{}.", name);
+        let txt = |lookup: hickory_resolver::lookup::TxtLookup| -> Vec<String> {
+            lookup
+                .iter()
+                .map(|txt| txt.txt_data().iter().map(|part| String::from_utf8_lossy(part)).collect::<String>())
+                .collect()
+        };
+
+        let validation = match self.validating.txt_lookup(name.as_str()).await {
+            Ok(lookup) => return Lookup::Found { values: txt(lookup), dnssec: true },
+            Err(e) => e,
+        };
+        if let ResolveErrorKind::NoRecordsFound { .. } = validation.kind() {
+            return Lookup::Missing;
+        }
+        // Unsigned zones, and resolver trouble, are told apart by asking
+        // without validation
+        let plain = match self.plain.txt_lookup(name.as_str()).await {
+            Ok(lookup) => txt(lookup),
+            Err(e) => {
+                return match e.kind() {
+                    ResolveErrorKind::NoRecordsFound { .. } => Lookup::Missing,
+                    _ => Lookup::Unavailable(e.to_string()),
+                };
+            }
+        };
+        if !unsigned(&validation) {
+            return Lookup::Rejected(format!("DNSSEC validation failed: {}", validation));
+        }
+        if self.config.require_dnssec {
+            return Lookup::Rejected("The zone is not DNSSEC-signed".to_string());
+        }
+        Lookup::Found { values: plain, dnssec: false }
+    }
+
+    /// Look the challenge up and record the outcome. Returns the domain
+    /// and any status change.
+    async fn check(&self, tx: &mut sqlx::Transaction<'static, sqlx::Postgres>, domain: &TenantDomain) -> Result<(TenantDomain, Option<&'static str>), SecurityError> {
+        let now = self.clock.now();
+        let challenge = self.challenge(domain);
+        let failure = match self.lookup(&challenge.name).await {
+            Lookup::Found { values, dnssec } if values.iter().any(|value| value.trim() == challenge.value) => {
+                let action = match domain.status.as_str() {
+                    "verified" => None,
+                    "lapsed" => Some("restored"),
+                    _ => Some("verified"),
+                };
+                let updated = sqlx::query_as::<_, TenantDomain>(&format!(
+                    "UPDATE tenant_domains SET status = 'verified', dnssec = $2, failures = 0, last_error = NULL, \
+                     verified_at = COALESCE(CASE WHEN status = 'verified' THEN verified_at END, $3), \
+                     last_checked_at = $3, next_check_at = $4 WHERE id = $1 RETURNING {}",
+                    DOMAIN_COLUMNS
+                ))
+                .bind(domain.id)
+                .bind(dnssec)
+                .bind(now)
+                .bind(now + Duration::seconds(self.config.recheck_interval_secs))
+                .fetch_one(&mut **tx)
+                .await?;
+                return Ok((updated, action));
+            }
+            Lookup::Found { .. } => Some(format!("{} does not hold the expected value", challenge.name)),
+            Lookup::Missing => Some(format!("No TXT record at {}", challenge.name)),
+            Lookup::Rejected(reason) => Some(reason),
+            Lookup::Unavailable(reason) => {
+                warn!("Domain check of {} could not reach a resolver: {}", domain.domain, reason);
+                let updated = sqlx::query_as::<_, TenantDomain>(&format!(
+                    "UPDATE tenant_domains SET last_error = $2, last_checked_at = $3, next_check_at = $4 \
+                     WHERE id = $1 RETURNING {}",
+                    DOMAIN_COLUMNS
+                ))
+                .bind(domain.id)
+                .bind(format!("Resolver unavailable: {}", reason))
+                .bind(now)
+                .bind(now + Duration::seconds(self.config.pending_interval_secs))
+                .fetch_one(&mut **tx)
+                .await?;
+                return Ok((updated, None));
+            }
+        };
+
+        // Pending claims just wait; verified ones count towards lapsing
+        let counts = domain.status == "verified";
+        let failures = if counts { domain.failures + 1 } else { domain.failures };
+        let lapses = counts && failures >= self.config.lapse_after_failures;
+        let status = if lapses { "lapsed" } else { domain.status.as_str() };
+        // A failing verified domain is retried soon, a lapsed one waits
+        let next_check_at = if status == "lapsed" {
+            now + Duration::seconds(self.config.recheck_interval_secs)
+        } else {
+            now + Duration::seconds(self.config.pending_interval_secs)
+        };
+        let updated = sqlx::query_as::<_, TenantDomain>(&format!(
+            "UPDATE tenant_domains SET status = $2, failures = $3, last_error = $4, last_checked_at = $5, \
+             next_check_at = $6 WHERE id = $1 RETURNING {}",
+            DOMAIN_COLUMNS
+        ))
+        .bind(domain.id)
+        .bind(status)
+        .bind(failures)
+        .bind(failure)
+        .bind(now)
+        .bind(next_check_at)
+        .fetch_one(&mut **tx)
+        .await?;
+        Ok((updated, lapses.then_some("lapsed")))
+    }
+
+    /// Check one domain now.
+    pub async fn verify(&self, id: Uuid) -> Result<(TenantDomain, Option<StatusChange>), SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let domain = sqlx::query_as::<_, TenantDomain>(&format!(
+            "SELECT {} FROM tenant_domains WHERE id = $1 FOR UPDATE",
+            DOMAIN_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("Domain {} not found", id)))?;
+        if domain.status == "expired" {
+            return Err(SecurityError::ValidationError(
+                "The challenge has expired; request a new one".to_string(),
+            ));
+        }
+        let (domain, action) = self.check(&mut tx, &domain).await?;
+        tx.commit().await?;
+        let change = action.map(|action| StatusChange { action, domain: domain.clone() });
+        Ok((domain, change))
+    }
+
+    /// Expire stale challenges and check domains that are due.
+    pub async fn run_pass(&self) -> Result<Vec<StatusChange>, SecurityError> {
+        let now = self.clock.now();
+        let mut changes: Vec<StatusChange> = sqlx::query_as::<_, TenantDomain>(&format!(
+            "UPDATE tenant_domains SET status = 'expired' WHERE status = 'pending' AND expires_at <= $1 \
+             RETURNING {}",
+            DOMAIN_COLUMNS
+        ))
+        .bind(now)
+        .fetch_all(self.storage.pool())
+        .await?
+        .into_iter()
+        .map(|domain| StatusChange { action: "expired", domain })
+        .collect();
+
+        let mut tx = self.storage.begin().await?;
+        let due = sqlx::query_as::<_, TenantDomain>(&format!(
+            "SELECT {} FROM tenant_domains WHERE status IN ('pending', 'verified', 'lapsed') \
+             AND next_check_at <= $1 ORDER BY next_check_at LIMIT $2 FOR UPDATE SKIP LOCKED",
+            DOMAIN_COLUMNS
+        ))
+        .bind(now)
+        .bind(self.config.batch_size)
+        .fetch_all(&mut *tx)
+        .await?;
+        for domain in &due {
+            let (domain, action) = self.check(&mut tx, domain).await?;
+            if let Some(action) = action {
+                changes.push(StatusChange { action, domain });
+            }
+        }
+        tx.commit().await?;
+        Ok(changes)
+    }
+
+    /// Whether `url` points into a domain `tenant_id` has verified: the
+    /// covering domain, if so.
+    pub async fn covering(&self, tenant_id: &str, url: &str) -> Result<Option<String>, SecurityError> {
+        let host = host_of(url).ok_or_else(|| SecurityError::ValidationError(format!("'{}' has no host name", url)))?;
+        Ok(verified_domains(&self.storage, tenant_id)
+            .await?
+            .into_iter()
+            .find(|domain| covers(domain, &host)))
+    }
+
+    /// Reject `url` for `tenant_id` unless it points into a verified
+    /// domain, when verification is required.
+    pub async fn require(&self, tenant_id: &str, url: &str) -> Result<(), SecurityError> {
+        if !self.config.required {
+            return Ok(());
+        }
+        match self.covering(tenant_id, url).await? {
+            Some(_) => Ok(()),
+            None => Err(SecurityError::ValidationError(format!(
+                "'{}' is not in a domain tenant {} has verified",
+                url, tenant_id
+            ))),
+        }
+    }
+}
+
+pub async fn run_checks(state: web::Data<AppState>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(state.config.domain_verification.interval_secs));
+    loop {
+        interval.tick().await;
+        match state.domains.run_pass().await {
+            Ok(changes) => {
+                for change in &changes {
+                    audit_change(&state, SYSTEM_ACTOR, None, change).await;
+                }
+                alert_lapsed(&state, &changes).await;
+            }
+            Err(e) => error!("Domain verification pass failed: {:?}", e),
+        }
+    }
+}
+
+async fn alert_lapsed(state: &AppState, changes: &[StatusChange]) {
+    let lapsed: Vec<&TenantDomain> = changes
+        .iter()
+        .filter(|change| change.action == "lapsed")
+        .map(|change| &change.domain)
+        .collect();
+    if lapsed.is_empty() {
+        return;
+    }
+    let title = format!("{} tenant domains lost their verification", lapsed.len());
+    let details = serde_json::json!({
+        "domains": lapsed
+    });
+    state.alerting_service.send(&Alert::new("domains", Severity::Medium, title, details, state.clock.now()), &[]).await;
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Domain verification operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Domain verification operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit_change(state: &AppState, actor: &str, actor_ip: Option<String>, change: &StatusChange) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: Some(change.domain.tenant_id.clone()),
+        actor: actor.to_string(),
+        actor_ip,
+        action: format!("domain.{}", change.action),
+        resource: format!("tenant_domain:{}", change.domain.domain),
+        outcome: if change.action == "lapsed" || change.action == "expired" { "failure" } else { "success" }.to_string(),
+        payload: serde_json::json!({
+            "domain_id": change.domain.id,
+            "status": change.domain.status,
+            "dnssec": change.domain.dnssec,
+            "failures": change.domain.failures,
+            "error": change.domain.last_error
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit domain {} {}: {:?}", change.domain.domain, change.action, e);
+    }
+}
+
+async fn audit_admin(state: &AppState, req: &HttpRequest, principal: &Principal, action: &str, domain: &TenantDomain) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: Some(domain.tenant_id.clone()),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(req),
+        action: action.to_string(),
+        resource: format!("tenant_domain:{}", domain.domain),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "domain_id": domain.id,
+            "status": domain.status
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} of {}: {:?}", action, domain.domain, e);
+    }
+}
+
+pub async fn list_handler(
+    req: HttpRequest,
+    filter: web::Query<DomainFilter>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.domains.list(&filter).await {
+        Ok(domains) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "domains": domains.into_iter().map(|domain| state.domains.view(domain)).collect::<Vec<_>>()
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn claim_handler(
+    req: HttpRequest,
+    request: web::Json<ClaimRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.domains.claim(&state.crypto_service, &principal, request.into_inner()).await {
+        Ok(domain) => {
+            audit_admin(&state, &req, &principal, "domain.claim", &domain).await;
+            Ok(HttpResponse::Created().json(state.domains.view(domain)))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.domains.get(path.into_inner()).await {
+        Ok(domain) => Ok(HttpResponse::Ok().json(state.domains.view(domain))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn verify_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.domains.verify(path.into_inner()).await {
+        Ok((domain, change)) => {
+            if let Some(change) = &change {
+                audit_change(&state, &principal.subject, client_ip(&req), change).await;
+                alert_lapsed(&state, std::slice::from_ref(change)).await;
+            }
+            Ok(HttpResponse::Ok().json(state.domains.view(domain)))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn challenge_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.domains.renew_challenge(&state.crypto_service, path.into_inner()).await {
+        Ok(domain) => {
+            audit_admin(&state, &req, &principal, "domain.challenge", &domain).await;
+            Ok(HttpResponse::Ok().json(state.domains.view(domain)))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn delete_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.domains.remove(path.into_inner()).await {
+        Ok(domain) => {
+            audit_admin(&state, &req, &principal, "domain.remove", &domain).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn check_handler(req: HttpRequest, query: web::Query<CheckQuery>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = authorize_scope(&state, &req, &state.config.domain_verification.check_scope) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.domains.covering(&query.tenant_id, &query.url).await {
+        Ok(domain) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "tenant_id": query.tenant_id,
+            "host": host_of(&query.url),
+            "verified": domain.is_some(),
+            "domain": domain,
+            "required": state.config.domain_verification.required
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/domains/check", web::get().to(check_handler)).service(
+        web::scope("/admin/domains")
+            .route("", web::get().to(list_handler))
+            .route("", web::post().to(claim_handler))
+            .route("/{id}", web::get().to(get_handler))
+            .route("/{id}", web::delete().to(delete_handler))
+            .route("/{id}/verify", web::post().to(verify_handler))
+            .route("/{id}/challenge", web::post().to(challenge_handler)),
+    );
+}
@@ -0,0 +1,927 @@
+/*!
+Dossiers Module
+Signed, encrypted security dossiers of a tender, for control bodies
+
+Control bodies (TCU, TCE) receive a tender's security record as one
+archive they can check without trusting whoever hands it over. Admins
+register each recipient's RSA public key at `/admin/dossiers/recipients`.
+`POST /dossiers` (needs `DOSSIER_EXPORT_SCOPE`) then compiles, in the
+background, everything this service holds about a tender reference:
+
+- `audit_events`: events on the tender and on the records below, with
+  their chain links, i.e. who did what to it, read or changed
+- `audit_chain`: the chain verified over those events when compiled, and
+  the signed checkpoints from the first of them on
+- `submissions`: calls for bids and their signed, chained receipts
+- `quorum`: publications with their officer signatures and attestations
+- `manifests`: signed file lists of the tender's packages
+- `notary`: inclusion proofs for the published documents
+- `collusion`: duplicate-bidder analyses, without any identifier
+- `keys`: the JWK Set of the signing keys, as published when compiled
+
+Each section is a JSON document. The index lists their SHA-256 and is
+signed with the `DOSSIER_ALGORITHM` key over `cotai-dossier:<id>:<index
+sha256>`. The dossier is encrypted with AES-256-GCM under a fresh key that
+only the recipient can unwrap (RSA-OAEP with SHA-256), and the sealed
+archive is signed again over `cotai-dossier-sealed:<id>:<recipient key
+sha256>:<archive ciphertext sha256>`, so its origin shows before it is
+opened:
+
+```json
+{"format": "cotai-dossier-sealed/1", "dossier_id": "...", "recipient": "TCU",
+ "recipient_key_sha256": "<hex>", "wrapped_key": "<base64>", "nonce": "<base64>",
+ "ciphertext": "<base64>", "ciphertext_sha256": "<hex>",
+ "algorithm": "EdDSA", "key_id": "...", "signature": "<hex>"}
+```
+
+Archives are written once to `DOSSIER_BUCKET`, or to the region's bucket
+for tenants pinned to another region (see `residency`), and their hash
+kept, so `GET /dossiers/{id}/archive` refuses to serve one that changed.
+The recipient checks one with `cotai-dossier verify` (the `cotai-dossier`
+crate), which also re-checks the records inside: event chain links,
+checkpoint, receipt and attestation signatures.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+use ring::digest::{digest, SHA256};
+use rsa::pkcs8::{DecodePublicKey, EncodePublicKey};
+use rsa::traits::PublicKeyParts;
+use rsa::{Oaep, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::FromRow;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::chain::{Checkpoint, VerifyQuery, CHECKPOINT_COLUMNS};
+use crate::audit::receipts::{self, RECEIPT_HEADER};
+use crate::audit::{AuditEvent, NewAuditEvent, EVENT_COLUMNS};
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::collusion::{self, Analysis};
+use crate::config::{Config, DossierConfig};
+use crate::errors::SecurityError;
+use crate::manifests::{self, Manifest};
+use crate::quorum::{self, OfficerSignature, Publication};
+use crate::residency;
+use crate::signing::{Algorithm, DOSSIER_ARCHIVE_DOMAIN, DOSSIER_INDEX_DOMAIN};
+use crate::storage::Storage;
+use crate::submissions::{self, Call, Receipt};
+use crate::AppState;
+
+pub const FORMAT: &str = "cotai-dossier/1";
+pub const SEALED_FORMAT: &str = "cotai-dossier-sealed/1";
+
+/// Who completes dossiers in the audit trail.
+const SYSTEM_ACTOR: &str = "system:dossiers";
+
+const RECIPIENT_COLUMNS: &str = "id, name, public_key, key_sha256, key_bits, registered_by, registered_at";
+
+const DOSSIER_COLUMNS: &str = "id, tenant_id, reference, recipient_id, status, region, object_key, sections, \
+    index_sha256, archive_sha256, error, requested_by, created_at, completed_at";
+
+const MIN_KEY_BITS: usize = 2048;
+
+fn sha256(data: &[u8]) -> String {
+    hex::encode(digest(&SHA256, data))
+}
+
+fn store_error(e: impl std::fmt::Display) -> SecurityError {
+    SecurityError::StorageError(format!("Dossier store: {}", e))
+}
+
+fn json_error(e: serde_json::Error) -> SecurityError {
+    SecurityError::StorageError(format!("Dossier encoding: {}", e))
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Recipient {
+    pub id: Uuid,
+    /// The control body, e.g. `TCU`.
+    pub name: String,
+    /// SubjectPublicKeyInfo PEM.
+    pub public_key: String,
+    /// SHA-256 of the key's DER, which the recipient matches to theirs.
+    pub key_sha256: String,
+    pub key_bits: i32,
+    pub registered_by: String,
+    pub registered_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SectionEntry {
+    pub sha256: String,
+    pub bytes: usize,
+    /// Records in the section.
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Dossier {
+    pub id: Uuid,
+    pub tenant_id: Option<String>,
+    pub reference: String,
+    pub recipient_id: Uuid,
+    /// `pending`, `running`, `completed` or `failed`.
+    pub status: String,
+    /// Region of the bucket holding the archive; `None` is the home region.
+    pub region: Option<String>,
+    pub object_key: Option<String>,
+    pub sections: Option<Json<BTreeMap<String, SectionEntry>>>,
+    pub index_sha256: Option<String>,
+    /// SHA-256 of the sealed archive as stored.
+    pub archive_sha256: Option<String>,
+    pub error: Option<String>,
+    pub requested_by: String,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// The index the dossier's signature covers.
+#[derive(Debug, Serialize)]
+struct Index<'a> {
+    format: &'static str,
+    dossier_id: Uuid,
+    tenant_id: Option<&'a str>,
+    reference: &'a str,
+    recipient: &'a str,
+    requested_by: &'a str,
+    compiled_at: DateTime<Utc>,
+    sections: &'a BTreeMap<String, SectionEntry>,
+}
+
+/// The plaintext of a sealed archive.
+#[derive(Debug, Serialize)]
+struct Contents {
+    format: &'static str,
+    dossier_id: Uuid,
+    /// The index JSON exactly as hashed and signed.
+    index: String,
+    index_sha256: String,
+    algorithm: String,
+    key_id: String,
+    signature: String,
+    /// Each section's JSON exactly as hashed.
+    sections: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Sealed<'a> {
+    format: &'static str,
+    dossier_id: Uuid,
+    recipient: &'a str,
+    recipient_key_sha256: &'a str,
+    wrapped_key: String,
+    nonce: String,
+    ciphertext: String,
+    ciphertext_sha256: String,
+    algorithm: String,
+    key_id: String,
+    signature: String,
+}
+
+/// An audit event with its chain fields; unlinked events have none yet.
+#[derive(Debug, Serialize, FromRow)]
+struct ChainedEvent {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    event: AuditEvent,
+    chain_index: Option<i64>,
+    prev_hash: Option<String>,
+    chain_hash: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct QuorumRecord {
+    #[serde(flatten)]
+    publication: Publication,
+    signatures: Vec<OfficerSignature>,
+}
+
+#[derive(Debug, Serialize)]
+struct SubmissionRecord {
+    #[serde(flatten)]
+    call: Call,
+    receipts: Vec<Receipt>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterRecipientRequest {
+    pub name: String,
+    pub public_key: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRequest {
+    /// The tender, e.g. `tender:7`.
+    pub reference: String,
+    pub recipient_id: Uuid,
+}
+
+/// Sections accumulated while compiling.
+#[derive(Default)]
+struct Sections {
+    entries: BTreeMap<String, SectionEntry>,
+    documents: BTreeMap<String, String>,
+}
+
+impl Sections {
+    fn add<T: Serialize>(&mut self, name: &str, records: &[T]) -> Result<(), SecurityError> {
+        self.add_value(name, records.len(), &records)
+    }
+
+    fn add_value<T: Serialize>(&mut self, name: &str, count: usize, value: &T) -> Result<(), SecurityError> {
+        let document = serde_json::to_string(value).map_err(json_error)?;
+        self.entries.insert(
+            name.to_string(),
+            SectionEntry { sha256: sha256(document.as_bytes()), bytes: document.len(), count },
+        );
+        self.documents.insert(name.to_string(), document);
+        Ok(())
+    }
+}
+
+pub struct DossierService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: DossierConfig,
+    store: Option<Arc<dyn ObjectStore>>,
+    regional_stores: HashMap<String, Arc<dyn ObjectStore>>,
+}
+
+impl DossierService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let store: Option<Arc<dyn ObjectStore>> = match &config.dossiers.bucket {
+            Some(bucket) => Some(Arc::new(
+                AmazonS3Builder::from_env()
+                    .with_bucket_name(bucket)
+                    .build()
+                    .map_err(|e| SecurityError::ConfigError(format!("Dossier store: {}", e)))?,
+            )),
+            None => None,
+        };
+
+        let regional_stores = residency::build_stores(config)?;
+
+        info!("Dossier service initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            config: config.dossiers.clone(),
+            store,
+            regional_stores,
+        })
+    }
+
+    fn store_in(&self, region: Option<&str>) -> Result<&Arc<dyn ObjectStore>, SecurityError> {
+        if self.store.is_none() {
+            return Err(SecurityError::ConfigError("Dossier storage is not configured".to_string()));
+        }
+        residency::store_in(&self.regional_stores, self.store.as_ref(), region)
+    }
+
+    pub async fn register_recipient(
+        &self,
+        request: &RegisterRecipientRequest,
+        registered_by: &str,
+    ) -> Result<Recipient, SecurityError> {
+        let name = request.name.trim();
+        if name.is_empty() || name.len() > 100 {
+            return Err(SecurityError::ValidationError("name must be 1-100 characters".to_string()));
+        }
+        let key = RsaPublicKey::from_public_key_pem(request.public_key.trim())
+            .map_err(|_| SecurityError::ValidationError("public_key must be an RSA SubjectPublicKeyInfo PEM".to_string()))?;
+        let bits = key.size() * 8;
+        if bits < MIN_KEY_BITS {
+            return Err(SecurityError::ValidationError(format!("The key must have at least {} bits", MIN_KEY_BITS)));
+        }
+        let der = key
+            .to_public_key_der()
+            .map_err(|e| SecurityError::CryptoError(format!("Cannot encode the recipient key: {}", e)))?;
+
+        sqlx::query_as::<_, Recipient>(&format!(
+            "INSERT INTO dossier_recipients (id, name, public_key, key_sha256, key_bits, registered_by, registered_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING {}",
+            RECIPIENT_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(name)
+        .bind(request.public_key.trim())
+        .bind(sha256(der.as_bytes()))
+        .bind(bits as i32)
+        .bind(registered_by)
+        .bind(self.clock.now())
+        .fetch_one(self.storage.pool())
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                SecurityError::Conflict(format!("Recipient '{}' is already registered", name))
+            }
+            e => e.into(),
+        })
+    }
+
+    pub async fn revoke_recipient(&self, id: Uuid) -> Result<(), SecurityError> {
+        let revoked = sqlx::query("UPDATE dossier_recipients SET revoked_at = $2 WHERE id = $1 AND revoked_at IS NULL")
+            .bind(id)
+            .bind(self.clock.now())
+            .execute(self.storage.pool())
+            .await?;
+        if revoked.rows_affected() == 0 {
+            return Err(SecurityError::NotFound(format!("No recipient {}", id)));
+        }
+        Ok(())
+    }
+
+    pub async fn recipients(&self) -> Result<Vec<Recipient>, SecurityError> {
+        Ok(sqlx::query_as::<_, Recipient>(&format!(
+            "SELECT {} FROM dossier_recipients WHERE revoked_at IS NULL ORDER BY name",
+            RECIPIENT_COLUMNS
+        ))
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    async fn recipient(&self, id: Uuid) -> Result<Recipient, SecurityError> {
+        sqlx::query_as::<_, Recipient>(&format!(
+            "SELECT {} FROM dossier_recipients WHERE id = $1 AND revoked_at IS NULL",
+            RECIPIENT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("No recipient {}", id)))
+    }
+
+    pub async fn create(
+        &self,
+        principal: &Principal,
+        request: &CreateRequest,
+        region: Option<&str>,
+    ) -> Result<Dossier, SecurityError> {
+        self.store_in(region)?;
+        let reference = request.reference.trim();
+        if reference.is_empty() || reference.len() > 200 {
+            return Err(SecurityError::ValidationError("reference must be 1-200 characters".to_string()));
+        }
+        self.recipient(request.recipient_id).await?;
+
+        Ok(sqlx::query_as::<_, Dossier>(&format!(
+            "INSERT INTO dossiers (id, tenant_id, reference, recipient_id, status, region, requested_by, created_at) \
+             VALUES ($1, $2, $3, $4, 'pending', $5, $6, $7) RETURNING {}",
+            DOSSIER_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(&principal.tenant_id)
+        .bind(reference)
+        .bind(request.recipient_id)
+        .bind(region)
+        .bind(&principal.subject)
+        .bind(self.clock.now())
+        .fetch_one(self.storage.pool())
+        .await?)
+    }
+
+    pub async fn get(&self, tenant_id: Option<&str>, id: Uuid) -> Result<Dossier, SecurityError> {
+        sqlx::query_as::<_, Dossier>(&format!("SELECT {} FROM dossiers WHERE id = $1", DOSSIER_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .filter(|dossier| tenant_id.is_none() || dossier.tenant_id.as_deref() == tenant_id)
+            .ok_or_else(|| SecurityError::NotFound(format!("No dossier {}", id)))
+    }
+
+    /// Everything held about the dossier's reference, as sections.
+    async fn compile(&self, state: &AppState, dossier: &Dossier) -> Result<Sections, SecurityError> {
+        let tenant_id = dossier.tenant_id.as_deref();
+        let reference = dossier.reference.as_str();
+        let pool = self.storage.pool();
+        let mut sections = Sections::default();
+        let mut resources = vec![reference.to_string()];
+
+        let calls = sqlx::query_as::<_, Call>(&format!(
+            "SELECT {} FROM submission_calls WHERE reference = $1 AND ($2::text IS NULL OR tenant_id = $2) \
+             ORDER BY created_at",
+            submissions::CALL_COLUMNS
+        ))
+        .bind(reference)
+        .bind(tenant_id)
+        .fetch_all(pool)
+        .await?;
+        let mut submission_records = Vec::with_capacity(calls.len());
+        for call in calls {
+            let receipts = sqlx::query_as::<_, Receipt>(&format!(
+                "SELECT {} FROM submission_receipts WHERE call_id = $1 ORDER BY sequence",
+                submissions::RECEIPT_COLUMNS
+            ))
+            .bind(call.id)
+            .fetch_all(pool)
+            .await?;
+            resources.push(format!("submission_call:{}", call.id));
+            resources.extend(receipts.iter().map(|receipt| format!("submission_receipt:{}", receipt.id)));
+            submission_records.push(SubmissionRecord { call, receipts });
+        }
+        sections.add("submissions", &submission_records)?;
+
+        let publications = sqlx::query_as::<_, Publication>(&format!(
+            "SELECT {} FROM quorum_publications WHERE reference = $1 AND ($2::text IS NULL OR tenant_id = $2) \
+             ORDER BY created_at",
+            quorum::PUBLICATION_COLUMNS
+        ))
+        .bind(reference)
+        .bind(tenant_id)
+        .fetch_all(pool)
+        .await?;
+        let mut quorum_records = Vec::with_capacity(publications.len());
+        let mut notary_proofs = Vec::new();
+        for publication in publications {
+            let signatures = sqlx::query_as::<_, OfficerSignature>(&format!(
+                "SELECT {} FROM quorum_signatures WHERE publication_id = $1 ORDER BY signed_at",
+                quorum::SIGNATURE_COLUMNS
+            ))
+            .bind(publication.id)
+            .fetch_all(pool)
+            .await?;
+            if publication.status == "published" {
+                notary_proofs.push(state.notary.lookup(&state.crypto_service, &publication.document_sha256).await?);
+            }
+            resources.push(format!("quorum_publication:{}", publication.id));
+            quorum_records.push(QuorumRecord { publication, signatures });
+        }
+        sections.add("quorum", &quorum_records)?;
+        sections.add("notary", &notary_proofs)?;
+
+        let manifests = sqlx::query_as::<_, Manifest>(&format!(
+            "SELECT {} FROM manifests WHERE package = $1 AND ($2::text IS NULL OR tenant_id = $2) ORDER BY version",
+            manifests::MANIFEST_COLUMNS
+        ))
+        .bind(reference)
+        .bind(tenant_id)
+        .fetch_all(pool)
+        .await?;
+        resources.extend(manifests.iter().map(|manifest| format!("manifest:{}", manifest.id)));
+        sections.add("manifests", &manifests)?;
+
+        let analyses = sqlx::query_as::<_, Analysis>(&format!(
+            "SELECT {} FROM collusion_analyses WHERE tender = $1 AND ($2::text IS NULL OR tenant_id = $2) \
+             ORDER BY created_at",
+            collusion::ANALYSIS_COLUMNS
+        ))
+        .bind(reference)
+        .bind(tenant_id)
+        .fetch_all(pool)
+        .await?;
+        resources.extend(analyses.iter().map(|analysis| format!("collusion_analysis:{}", analysis.id)));
+        sections.add("collusion", &analyses)?;
+
+        let events = sqlx::query_as::<_, ChainedEvent>(&format!(
+            "SELECT {}, chain_index, prev_hash, chain_hash FROM audit_events \
+             WHERE (resource = ANY($1) OR payload->>'reference' = $2 OR payload->>'tender' = $2) \
+             AND ($3::text IS NULL OR tenant_id = $3) ORDER BY occurred_at, id LIMIT $4",
+            EVENT_COLUMNS
+        ))
+        .bind(&resources)
+        .bind(reference)
+        .bind(tenant_id)
+        .bind(self.config.max_events + 1)
+        .fetch_all(pool)
+        .await?;
+        if events.len() as i64 > self.config.max_events {
+            return Err(SecurityError::ValidationError(format!(
+                "The tender has more than DOSSIER_MAX_EVENTS ({}) audit events",
+                self.config.max_events
+            )));
+        }
+
+        let indexes: Vec<i64> = events.iter().filter_map(|event| event.chain_index).collect();
+        let chain = match (indexes.iter().min(), indexes.iter().max()) {
+            (Some(&from), Some(&to)) => {
+                let verification = state
+                    .audit_service
+                    .verify_chain(&state.crypto_service, &VerifyQuery { from: Some(from), to: Some(to) })
+                    .await?;
+                let checkpoints = sqlx::query_as::<_, Checkpoint>(&format!(
+                    "SELECT {} FROM audit_checkpoints WHERE chain_index >= $1 ORDER BY chain_index",
+                    CHECKPOINT_COLUMNS
+                ))
+                .bind(from)
+                .fetch_all(pool)
+                .await?;
+                serde_json::json!({ "verification": verification, "checkpoints": checkpoints })
+            }
+            _ => serde_json::json!({ "verification": null, "checkpoints": [] }),
+        };
+        sections.add("audit_events", &events)?;
+        sections.add_value("audit_chain", indexes.len(), &chain)?;
+
+        let keys = state.crypto_service.signing_keys().jwks();
+        let key_count = keys["keys"].as_array().map_or(0, Vec::len);
+        sections.add_value("keys", key_count, &keys)?;
+        Ok(sections)
+    }
+
+    /// Sign, encrypt and store a compiled dossier. Returns the object key,
+    /// the index hash and the archive hash.
+    async fn seal(
+        &self,
+        state: &AppState,
+        dossier: &Dossier,
+        recipient: &Recipient,
+        sections: Sections,
+    ) -> Result<(String, String, String), SecurityError> {
+        let crypto = &state.crypto_service;
+        let algorithm = Algorithm::parse(&self.config.algorithm)
+            .ok_or_else(|| SecurityError::ConfigError("Invalid DOSSIER_ALGORITHM".to_string()))?;
+
+        let index = serde_json::to_string(&Index {
+            format: FORMAT,
+            dossier_id: dossier.id,
+            tenant_id: dossier.tenant_id.as_deref(),
+            reference: &dossier.reference,
+            recipient: &recipient.name,
+            requested_by: &dossier.requested_by,
+            compiled_at: self.clock.now(),
+            sections: &sections.entries,
+        })
+        .map_err(json_error)?;
+        let index_sha256 = sha256(index.as_bytes());
+        let signed = crypto.generate_signature(
+            DOSSIER_INDEX_DOMAIN,
+            &format!("cotai-dossier:{}:{}", dossier.id, index_sha256),
+            None,
+            algorithm,
+        )?;
+        let contents = serde_json::to_vec(&Contents {
+            format: FORMAT,
+            dossier_id: dossier.id,
+            index,
+            index_sha256: index_sha256.clone(),
+            algorithm: algorithm.as_str().to_string(),
+            key_id: signed.key_id,
+            signature: signed.signature,
+            sections: sections.documents,
+        })
+        .map_err(json_error)?;
+
+        let data_key = crypto.secure_random(32).await?;
+        let nonce = crypto.secure_random(12).await?;
+        let key = UnboundKey::new(&AES_256_GCM, &data_key)
+            .map(LessSafeKey::new)
+            .map_err(|_| SecurityError::CryptoError("Dossier key is not an AES-256 key".to_string()))?;
+        let aad = format!("cotai-dossier:{}:{}", dossier.id, recipient.key_sha256);
+        let mut ciphertext = contents;
+        key.seal_in_place_append_tag(
+            Nonce::try_assume_unique_for_key(&nonce)
+                .map_err(|_| SecurityError::CryptoError("Invalid dossier nonce".to_string()))?,
+            Aad::from(aad.as_bytes()),
+            &mut ciphertext,
+        )
+        .map_err(|_| SecurityError::CryptoError("Dossier encryption failed".to_string()))?;
+        let public_key = RsaPublicKey::from_public_key_pem(&recipient.public_key)
+            .map_err(|e| SecurityError::CryptoError(format!("Recipient key: {}", e)))?;
+        let wrapped_key = public_key
+            .encrypt(&mut rand::rngs::OsRng, Oaep::new::<sha2::Sha256>(), &data_key)
+            .map_err(|e| SecurityError::CryptoError(format!("Cannot wrap the dossier key: {}", e)))?;
+
+        let ciphertext_sha256 = sha256(&ciphertext);
+        let sealed_signature = crypto.generate_signature(
+            DOSSIER_ARCHIVE_DOMAIN,
+            &format!("cotai-dossier-sealed:{}:{}:{}", dossier.id, recipient.key_sha256, ciphertext_sha256),
+            None,
+            algorithm,
+        )?;
+        let archive = serde_json::to_vec(&Sealed {
+            format: SEALED_FORMAT,
+            dossier_id: dossier.id,
+            recipient: &recipient.name,
+            recipient_key_sha256: &recipient.key_sha256,
+            wrapped_key: base64::encode(wrapped_key),
+            nonce: base64::encode(&nonce),
+            ciphertext: base64::encode(&ciphertext),
+            ciphertext_sha256,
+            algorithm: algorithm.as_str().to_string(),
+            key_id: sealed_signature.key_id,
+            signature: sealed_signature.signature,
+        })
+        .map_err(json_error)?;
+
+        let object_key = format!(
+            "{}/{}/{}.json",
+            self.config.prefix.trim_end_matches('/'),
+            self.clock.now().format("%Y/%m/%d"),
+            dossier.id
+        );
+        let archive_sha256 = sha256(&archive);
+        self.store_in(dossier.region.as_deref())?
+            .put(&Path::from(object_key.as_str()), archive.into())
+            .await
+            .map_err(store_error)?;
+        Ok((object_key, index_sha256, archive_sha256))
+    }
+
+    /// Compile, seal and store a dossier, recording the outcome on it.
+    pub async fn run(&self, state: &AppState, id: Uuid) {
+        let outcome = async {
+            let dossier = sqlx::query_as::<_, Dossier>(&format!(
+                "UPDATE dossiers SET status = 'running' WHERE id = $1 AND status = 'pending' RETURNING {}",
+                DOSSIER_COLUMNS
+            ))
+            .bind(id)
+            .fetch_one(self.storage.pool())
+            .await?;
+            let recipient = self.recipient(dossier.recipient_id).await?;
+            let sections = self.compile(state, &dossier).await?;
+            let entries = sections.entries.clone();
+            let (object_key, index_sha256, archive_sha256) = self.seal(state, &dossier, &recipient, sections).await?;
+            Ok::<_, SecurityError>(sqlx::query_as::<_, Dossier>(&format!(
+                "UPDATE dossiers SET status = 'completed', object_key = $2, sections = $3, index_sha256 = $4, \
+                 archive_sha256 = $5, completed_at = $6 WHERE id = $1 RETURNING {}",
+                DOSSIER_COLUMNS
+            ))
+            .bind(id)
+            .bind(&object_key)
+            .bind(Json(&entries))
+            .bind(&index_sha256)
+            .bind(&archive_sha256)
+            .bind(self.clock.now())
+            .fetch_one(self.storage.pool())
+            .await?)
+        }
+        .await;
+
+        match outcome {
+            Ok(dossier) => {
+                info!("Dossier {} of {} completed", dossier.id, dossier.reference);
+                state.residency
+                    .record(dossier.tenant_id.as_deref(), "dossier", &format!("dossier:{}", dossier.id), dossier.region.as_deref())
+                    .await;
+                receipts::record_or_warn(state, NewAuditEvent {
+                    tenant_id: dossier.tenant_id.clone(),
+                    actor: SYSTEM_ACTOR.to_string(),
+                    actor_ip: None,
+                    action: "dossier.complete".to_string(),
+                    resource: format!("dossier:{}", dossier.id),
+                    outcome: "success".to_string(),
+                    payload: serde_json::json!({
+                        "reference": dossier.reference,
+                        "recipient_id": dossier.recipient_id,
+                        "index_sha256": dossier.index_sha256,
+                        "archive_sha256": dossier.archive_sha256
+                    }),
+                }).await;
+            }
+            Err(e) => {
+                error!("Dossier {} failed: {:?}", id, e);
+                let failed = sqlx::query("UPDATE dossiers SET status = 'failed', error = $2, completed_at = $3 WHERE id = $1")
+                    .bind(id)
+                    .bind(e.to_string())
+                    .bind(self.clock.now())
+                    .execute(self.storage.pool())
+                    .await;
+                if let Err(e) = failed {
+                    warn!("Failed to record dossier {} failure: {:?}", id, e);
+                }
+            }
+        }
+    }
+
+    /// The sealed archive, provided it is still the one stored.
+    pub async fn archive(&self, dossier: &Dossier) -> Result<Vec<u8>, SecurityError> {
+        let (Some(object_key), Some(archive_sha256)) = (&dossier.object_key, &dossier.archive_sha256) else {
+            return Err(SecurityError::Conflict(format!("Dossier {} is {}", dossier.id, dossier.status)));
+        };
+        let object = self.store_in(dossier.region.as_deref())?
+            .get(&Path::from(object_key.as_str()))
+            .await
+            .map_err(store_error)?;
+        let archive = object.bytes().await.map_err(store_error)?.to_vec();
+        if &sha256(&archive) != archive_sha256 {
+            return Err(SecurityError::CryptoError(format!("Dossier {} archive no longer matches its hash", dossier.id)));
+        }
+        Ok(archive)
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ConfigError(msg) => HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Dossier operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Dossier operation failed"
+            }))
+        }
+    }
+}
+
+pub async fn register_recipient_handler(
+    req: HttpRequest,
+    request: web::Json<RegisterRecipientRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.dossiers.register_recipient(&request, &principal.subject).await {
+        Ok(recipient) => {
+            let recorded = state.audit_service.record(NewAuditEvent {
+                tenant_id: None,
+                actor: principal.subject.clone(),
+                actor_ip: client_ip(&req),
+                action: "dossier.recipient.register".to_string(),
+                resource: format!("dossier_recipient:{}", recipient.id),
+                outcome: "success".to_string(),
+                payload: serde_json::json!({
+                    "name": recipient.name,
+                    "key_sha256": recipient.key_sha256
+                }),
+            }).await;
+            if let Err(e) = recorded {
+                warn!("Failed to audit dossier recipient {}: {:?}", recipient.id, e);
+            }
+            Ok(HttpResponse::Created().json(recipient))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_recipients_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.dossiers.recipients().await {
+        Ok(recipients) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "recipients": recipients
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn revoke_recipient_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let id = path.into_inner();
+
+    match state.dossiers.revoke_recipient(id).await {
+        Ok(()) => {
+            let recorded = state.audit_service.record(NewAuditEvent {
+                tenant_id: None,
+                actor: principal.subject.clone(),
+                actor_ip: client_ip(&req),
+                action: "dossier.recipient.revoke".to_string(),
+                resource: format!("dossier_recipient:{}", id),
+                outcome: "success".to_string(),
+                payload: serde_json::json!({}),
+            }).await;
+            if let Err(e) = recorded {
+                warn!("Failed to audit dossier recipient {}: {:?}", id, e);
+            }
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn create_handler(
+    req: HttpRequest,
+    request: web::Json<CreateRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.dossiers.export_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let created = async {
+        let region = state.residency.placement(principal.tenant_id.as_deref()).await?;
+        state.dossiers.create(&principal, &request, region.as_deref()).await
+    }
+    .await;
+    let dossier = match created {
+        Ok(dossier) => dossier,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    let receipt = receipts::record_or_warn(&state, NewAuditEvent {
+        tenant_id: dossier.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(&req),
+        action: "dossier.create".to_string(),
+        resource: format!("dossier:{}", dossier.id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "reference": dossier.reference,
+            "recipient_id": dossier.recipient_id
+        }),
+    }).await;
+
+    let worker_state = state.clone();
+    let id = dossier.id;
+    tokio::spawn(async move {
+        worker_state.dossiers.run(&worker_state, id).await;
+    });
+
+    let mut response = HttpResponse::Accepted();
+    if let Some(receipt) = receipt {
+        response.insert_header((RECEIPT_HEADER, receipt));
+    }
+    Ok(response.json(dossier))
+}
+
+pub async fn get_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.dossiers.export_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.dossiers.get(principal.tenant_id.as_deref(), path.into_inner()).await {
+        Ok(dossier) => Ok(HttpResponse::Ok().json(dossier)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn archive_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.dossiers.export_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let dossier = match state.dossiers.get(principal.tenant_id.as_deref(), path.into_inner()).await {
+        Ok(dossier) => dossier,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let archive = match state.dossiers.archive(&dossier).await {
+        Ok(archive) => archive,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    receipts::record_or_warn(&state, NewAuditEvent {
+        tenant_id: dossier.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(&req),
+        action: "dossier.download".to_string(),
+        resource: format!("dossier:{}", dossier.id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "archive_sha256": dossier.archive_sha256
+        }),
+    }).await;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"dossier-{}.json\"", dossier.id)))
+        .body(archive))
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/dossiers")
+            .route("", web::post().to(create_handler))
+            .route("/{id}", web::get().to(get_handler))
+            .route("/{id}/archive", web::get().to(archive_handler)),
+    )
+    .service(
+        web::scope("/admin/dossiers/recipients")
+            .route("", web::post().to(register_recipient_handler))
+            .route("", web::get().to(list_recipients_handler))
+            .route("/{id}", web::delete().to(revoke_recipient_handler)),
+    );
+}
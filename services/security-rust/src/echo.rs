@@ -0,0 +1,139 @@
+/*!
+Echo Module
+How the service perceived a request, for integration troubleshooting
+
+`/debug/echo`, with any method, answers an authenticated caller with what
+the service made of the request instead of acting on it:
+
+- `client`: the connection's address, whether it is a trusted proxy, the
+  hops `CLIENT_IP_HEADER` listed and the client address resolved from them
+  (see `network`)
+- `identity`: subject, tenant (and the `X-Tenant-Id` header, which only
+  anonymous requests use), roles, scopes, delegation and whether an API key
+  or a bearer token was presented
+- `rate_limit`: the rule and counter the request was charged to and what
+  is left of it
+- `pipeline`: each middleware stage and whether it passed the request or
+  did not apply to its path (see `pipeline`)
+- `request`: method, path, query and headers, with credentials redacted
+
+The body is never read. The request still counts against rate limits like
+any other, which is what lets `rate_limit` show the bucket. The endpoint
+is off unless `ECHO_ENABLED` is set.
+*/
+
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Result};
+use serde_json::{Map, Value};
+
+use crate::auth::api_keys::API_KEY_HEADER;
+use crate::auth::{auth_error_response, captcha};
+use crate::crypto::bulk;
+use crate::pipeline::Trace;
+use crate::rate_limiting::{Bucket, Decision};
+use crate::whistleblower;
+use crate::AppState;
+
+/// Headers whose values are never echoed.
+const REDACTED_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    API_KEY_HEADER,
+    captcha::TOKEN_HEADER,
+    bulk::GRANT_HEADER,
+    whistleblower::FOLLOWUP_HEADER,
+];
+
+const TENANT_HEADER: &str = "x-tenant-id";
+
+fn headers(req: &HttpRequest) -> Map<String, Value> {
+    let mut headers = Map::new();
+    for (name, value) in req.headers() {
+        let shown = if REDACTED_HEADERS.iter().any(|h| name.as_str().eq_ignore_ascii_case(h)) {
+            "[redacted]".to_string()
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).into_owned()
+        };
+        match headers.get_mut(name.as_str()) {
+            Some(Value::Array(values)) => values.push(Value::from(shown)),
+            _ => {
+                headers.insert(name.to_string(), Value::from(vec![shown]));
+            }
+        }
+    }
+    headers
+}
+
+// HTTP handlers
+
+pub async fn echo_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if !state.config.echo.enabled {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Not found"
+        })));
+    }
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let peer = req.peer_addr().map(|addr| addr.ip());
+    let client = serde_json::json!({
+        "peer": peer.map(|ip| ip.to_string()),
+        "peer_trusted": peer.map(|ip| state.client_ips.is_trusted(ip)),
+        "header": state.config.network.client_ip_header,
+        "hops": state.client_ips.hops(req.headers()),
+        "resolved": state.client_ips.resolve(&req).map(|ip| ip.to_string())
+    });
+    let credential = if req.headers().contains_key(API_KEY_HEADER) { "api_key" } else { "bearer" };
+    let identity = serde_json::json!({
+        "subject": principal.subject,
+        "tenant_id": principal.tenant_id,
+        "tenant_header": req.headers().get(TENANT_HEADER).and_then(|v| v.to_str().ok()),
+        "roles": principal.roles,
+        "scopes": principal.scopes,
+        "delegation": principal.delegation,
+        "credential": credential
+    });
+
+    let extensions = req.extensions();
+    let rate_limit = match (extensions.get::<Bucket>(), extensions.get::<Decision>()) {
+        (Some(bucket), decision) => serde_json::json!({
+            "rule": bucket.rule,
+            "key": bucket.key,
+            "limit": bucket.limit,
+            "period_secs": bucket.period_secs,
+            "remaining": decision.map(|d| d.remaining)
+        }),
+        (None, _) => Value::Null,
+    };
+    let pipeline: Vec<Value> = extensions
+        .get::<Trace>()
+        .map(|trace| {
+            trace.0.iter().map(|(stage, outcome)| serde_json::json!({
+                "stage": stage,
+                "outcome": outcome
+            })).collect()
+        })
+        .unwrap_or_default();
+    drop(extensions);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "client": client,
+        "identity": identity,
+        "rate_limit": rate_limit,
+        "pipeline": pipeline,
+        "request": {
+            "method": req.method().as_str(),
+            "path": req.path(),
+            "query": req.query_string(),
+            "version": format!("{:?}", req.version()),
+            "headers": headers(&req)
+        }
+    })))
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/debug/echo", web::route().to(echo_handler));
+}
@@ -0,0 +1,275 @@
+/*!
+DKIM Signatures
+Producing and checking `DKIM-Signature` fields (RFC 6376, RFC 8463)
+
+Signatures made here use `relaxed/relaxed` canonicalization, list every
+occurrence of the configured header fields in `h=` and are folded one tag
+per line. Checking accepts `rsa-sha256` and `ed25519-sha256` with either
+canonicalization; `rsa-sha1`, which RFC 8301 retired, is a `permerror`.
+RSA keys down to 1024 bits still verify, as many senders publish them.
+*/
+
+use ring::{digest, signature};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::dns::{Dns, DnsError};
+use super::message::{canon_body, canon_header, compact, tag, tags, Canon, Header, Message};
+
+pub const RSA_SHA256: &str = "rsa-sha256";
+pub const ED25519_SHA256: &str = "ed25519-sha256";
+pub const ALGORITHMS: &[&str] = &[RSA_SHA256, ED25519_SHA256];
+
+pub const FIELD: &str = "DKIM-Signature";
+
+/// Signatures checked per message; more are ignored.
+const MAX_SIGNATURES: usize = 5;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DkimResult {
+    /// The signing domain, `d=`.
+    pub domain: String,
+    pub selector: String,
+    pub algorithm: String,
+    /// `pass`, `fail`, `temperror` or `permerror`.
+    pub result: &'static str,
+    pub reason: Option<String>,
+}
+
+/// The `k=` a key for `algorithm` is published under.
+pub fn key_type(algorithm: &str) -> Option<&'static str> {
+    match algorithm {
+        RSA_SHA256 => Some("rsa"),
+        ED25519_SHA256 => Some("ed25519"),
+        _ => None,
+    }
+}
+
+pub fn sha256(data: &[u8]) -> Vec<u8> {
+    digest::digest(&digest::SHA256, data).as_ref().to_vec()
+}
+
+/// The field with its `b=` value emptied, as it is signed.
+fn without_signature(field: &Header) -> Header {
+    let value = field
+        .value()
+        .split(';')
+        .map(|part| match part.split_once('=') {
+            Some((name, _)) if name.trim() == "b" => format!("{}=", name),
+            _ => part.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(";");
+    Header {
+        name: field.name.clone(),
+        raw: format!("{}:{}\r\n", field.name, value),
+    }
+}
+
+/// What a signature covers: the fields `names` selects, bottom-most
+/// occurrence first for repeated names, then the signature field itself
+/// with `b=` empty and no final CRLF.
+fn signed_data(message: &Message, names: &[String], canon: Canon, field: &Header) -> Vec<u8> {
+    let mut used: HashMap<String, usize> = HashMap::new();
+    let mut data = String::new();
+    for name in names {
+        let name = name.trim().to_lowercase();
+        let count = used.entry(name.clone()).or_default();
+        // Names beyond the fields present select nothing
+        if let Some(header) = message.headers_named(&name).rev().nth(*count) {
+            data.push_str(&canon_header(header, canon));
+        }
+        *count += 1;
+    }
+    let field = canon_header(&without_signature(field), canon);
+    data.push_str(field.strip_suffix("\r\n").unwrap_or(&field));
+    data.into_bytes()
+}
+
+/// An unsigned signature field for `message`, and the data to sign for it.
+/// Ed25519 signs the data's SHA-256 (RFC 8463), RSA the data itself.
+pub fn prepare(
+    message: &Message,
+    domain: &str,
+    selector: &str,
+    algorithm: &str,
+    headers: &[String],
+    timestamp: i64,
+) -> Result<(Header, Vec<u8>), String> {
+    if message.header("from").is_none() {
+        return Err("The message has no From field".to_string());
+    }
+    let mut names = Vec::new();
+    for name in headers {
+        let name = name.trim().to_lowercase();
+        names.extend(std::iter::repeat_n(name.clone(), message.headers_named(&name).count()));
+    }
+    let body_hash = base64::encode(sha256(canon_body(&message.body, Canon::Relaxed).as_bytes()));
+    let value = format!(
+        " v=1; a={}; c=relaxed/relaxed; d={}; s={}; t={}; h={}; bh={}; b=",
+        algorithm,
+        domain,
+        selector,
+        timestamp,
+        names.join(":"),
+        body_hash
+    );
+    let field = Header {
+        name: FIELD.to_string(),
+        raw: format!("{}:{}\r\n", FIELD, value),
+    };
+    let data = signed_data(message, &names, Canon::Relaxed, &field);
+    let data = if algorithm == ED25519_SHA256 { sha256(&data) } else { data };
+    Ok((field, data))
+}
+
+/// The signed field, one tag per line, without the final CRLF. Folding
+/// does not change what relaxed canonicalization signed.
+pub fn finish(field: &Header, signature: &[u8]) -> String {
+    format!("{}:{}{}", field.name, field.value().replace("; ", ";\r\n\t"), base64::encode(signature))
+}
+
+/// One DER element: its tag, contents and what follows it.
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count].iter().fold(0usize, |len, byte| (len << 8) | *byte as usize);
+        (len, &rest[count..])
+    };
+    if rest.len() < len {
+        return None;
+    }
+    Some((tag, &rest[..len], &rest[len..]))
+}
+
+/// The PKCS#1 key inside a SubjectPublicKeyInfo, which `p=` normally
+/// holds; a bare PKCS#1 key is returned as is.
+fn rsa_public_key(der: &[u8]) -> &[u8] {
+    if let Some((0x30, info, _)) = der_element(der) {
+        if let Some((0x30, _algorithm, rest)) = der_element(info) {
+            if let Some((0x03, [0, key @ ..], _)) = der_element(rest) {
+                return key;
+            }
+        }
+    }
+    der
+}
+
+fn canonicalizations(value: Option<&str>) -> Option<(Canon, Canon)> {
+    let value = value.unwrap_or("simple/simple").to_lowercase();
+    let (header, body) = value.split_once('/').unwrap_or((&value, "simple"));
+    Some((Canon::parse(header)?, Canon::parse(body)?))
+}
+
+/// The `p=` of the key record for `selector` and `domain`.
+async fn public_key(dns: &Dns, selector: &str, domain: &str, algorithm: &str) -> Result<Vec<u8>, (&'static str, String)> {
+    let name = format!("{}._domainkey.{}", selector, domain);
+    let records = match dns.txt(&name).await {
+        Ok(records) => records,
+        Err(DnsError::NotFound) => return Err(("permerror", format!("No key record at {}", name))),
+        Err(DnsError::Temporary(e)) => return Err(("temperror", e)),
+    };
+    let record = records
+        .iter()
+        .map(|record| tags(record))
+        .find(|tags| tag(tags, "v").is_none_or(|version| version == "DKIM1") && tag(tags, "p").is_some())
+        .ok_or_else(|| ("permerror", format!("No usable key record at {}", name)))?;
+    if Some(tag(&record, "k").unwrap_or("rsa")) != key_type(algorithm) {
+        return Err(("permerror", format!("The key at {} is not for {}", name, algorithm)));
+    }
+    let key = compact(tag(&record, "p").unwrap_or_default());
+    if key.is_empty() {
+        return Err(("fail", format!("The key at {} is revoked", name)));
+    }
+    base64::decode(&key).map_err(|_| ("permerror", format!("The key at {} is not base64", name)))
+}
+
+fn settled(result: DkimResult, outcome: &'static str, reason: String) -> DkimResult {
+    DkimResult {
+        result: outcome,
+        reason: Some(reason),
+        ..result
+    }
+}
+
+async fn verify_one(dns: &Dns, message: &Message, field: &Header, now: i64) -> DkimResult {
+    let signature_tags = tags(field.value());
+    let result = DkimResult {
+        domain: tag(&signature_tags, "d").unwrap_or_default().to_lowercase(),
+        selector: tag(&signature_tags, "s").unwrap_or_default().to_string(),
+        algorithm: tag(&signature_tags, "a").unwrap_or_default().to_lowercase(),
+        result: "permerror",
+        reason: None,
+    };
+
+    if tag(&signature_tags, "v") != Some("1") {
+        return settled(result, "permerror", "Unsupported signature version".to_string());
+    }
+    let (Some(encoded_signature), Some(body_hash), Some(names)) = (tag(&signature_tags, "b"), tag(&signature_tags, "bh"), tag(&signature_tags, "h")) else {
+        return settled(result, "permerror", "Missing b=, bh= or h=".to_string());
+    };
+    if result.domain.is_empty() || result.selector.is_empty() {
+        return settled(result, "permerror", "Missing d= or s=".to_string());
+    }
+    if key_type(&result.algorithm).is_none() {
+        let reason = format!("Unsupported algorithm '{}'", result.algorithm);
+        return settled(result, "permerror", reason);
+    }
+    let names: Vec<String> = names.split(':').map(|name| name.trim().to_lowercase()).collect();
+    if !names.iter().any(|name| name == "from") {
+        return settled(result, "permerror", "The From field is not signed".to_string());
+    }
+    let Some((header_canon, body_canon)) = canonicalizations(tag(&signature_tags, "c")) else {
+        return settled(result, "permerror", "Unknown canonicalization".to_string());
+    };
+    if let Some(expires) = tag(&signature_tags, "x").and_then(|x| x.parse::<i64>().ok()) {
+        if expires < now {
+            return settled(result, "fail", "The signature has expired".to_string());
+        }
+    }
+
+    let body = canon_body(&message.body, body_canon).into_bytes();
+    let body = match tag(&signature_tags, "l").map(|l| l.parse::<usize>()) {
+        None => &body[..],
+        Some(Ok(length)) if length <= body.len() => &body[..length],
+        Some(_) => return settled(result, "permerror", "Invalid body length l=".to_string()),
+    };
+    if base64::encode(sha256(body)) != compact(body_hash) {
+        return settled(result, "fail", "The body hash does not match".to_string());
+    }
+    let Ok(signed) = base64::decode(compact(encoded_signature)) else {
+        return settled(result, "permerror", "The signature is not base64".to_string());
+    };
+
+    let key = match public_key(dns, &result.selector, &result.domain, &result.algorithm).await {
+        Ok(key) => key,
+        Err((failure, reason)) => return settled(result, failure, reason),
+    };
+    let data = signed_data(message, &names, header_canon, field);
+    let verified = if result.algorithm == RSA_SHA256 {
+        signature::UnparsedPublicKey::new(&signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY, rsa_public_key(&key))
+            .verify(&data, &signed)
+    } else {
+        signature::UnparsedPublicKey::new(&signature::ED25519, &key).verify(&sha256(&data), &signed)
+    };
+    match verified {
+        Ok(()) => DkimResult { result: "pass", ..result },
+        Err(_) => settled(result, "fail", "The signature does not verify".to_string()),
+    }
+}
+
+/// Check each of the message's signatures, top first.
+pub async fn verify(dns: &Dns, message: &Message, now: i64) -> Vec<DkimResult> {
+    let mut results = Vec::new();
+    for field in message.headers_named(FIELD).take(MAX_SIGNATURES) {
+        results.push(verify_one(dns, message, field, now).await);
+    }
+    results
+}
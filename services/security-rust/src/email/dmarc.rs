@@ -0,0 +1,151 @@
+/*!
+DMARC Evaluation
+Whether a message's `From` domain is backed by an aligned SPF or DKIM pass,
+per RFC 7489, and what its owner asks receivers to do when it is not
+
+The organizational domain is approximated without the Public Suffix List:
+the last two labels, or three under two-level registries such as `com.br`
+or `co.uk`. Exotic suffixes may therefore align more loosely than a
+receiver using the list would.
+*/
+
+use serde::Serialize;
+
+use super::dkim::DkimResult;
+use super::dns::{Dns, DnsError};
+use super::message::{tag, tags};
+use super::spf::SpfResult;
+
+/// Second-level labels registries commonly sell names under.
+const SECOND_LEVEL: &[&str] = &["com", "net", "org", "gov", "edu", "co", "ac", "or", "ne", "go", "mil", "nom"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DmarcResult {
+    /// `pass`, `fail`, `none`, `temperror` or `permerror`.
+    pub result: &'static str,
+    pub from_domain: Option<String>,
+    /// The policy that applies to the `From` domain: `none`, `quarantine`
+    /// or `reject`.
+    pub policy: Option<String>,
+    pub spf_aligned: bool,
+    pub dkim_aligned: bool,
+    /// What the policy asks for this message, `pct` aside.
+    pub disposition: &'static str,
+    pub reason: Option<String>,
+}
+
+struct Record {
+    policy: String,
+    subdomain_policy: Option<String>,
+    strict_dkim: bool,
+    strict_spf: bool,
+}
+
+pub fn organizational_domain(domain: &str) -> String {
+    let labels: Vec<&str> = domain.trim_end_matches('.').split('.').collect();
+    let keep = match labels.as_slice() {
+        [.., second, tld] if tld.len() == 2 && SECOND_LEVEL.contains(second) => 3,
+        _ => 2,
+    };
+    labels[labels.len().saturating_sub(keep)..].join(".").to_lowercase()
+}
+
+fn aligned(from: &str, other: &str, strict: bool) -> bool {
+    let other = other.to_lowercase();
+    if strict {
+        from == other
+    } else {
+        organizational_domain(from) == organizational_domain(&other)
+    }
+}
+
+fn parse_record(record: &str) -> Option<Record> {
+    let tags = tags(record);
+    if tags.first().map(|(name, value)| (name.as_str(), value.as_str())) != Some(("v", "DMARC1")) {
+        return None;
+    }
+    let policy = tag(&tags, "p")?.to_lowercase();
+    if !["none", "quarantine", "reject"].contains(&policy.as_str()) {
+        return None;
+    }
+    Some(Record {
+        policy,
+        subdomain_policy: tag(&tags, "sp").map(str::to_lowercase),
+        strict_dkim: tag(&tags, "adkim") == Some("s"),
+        strict_spf: tag(&tags, "aspf") == Some("s"),
+    })
+}
+
+/// The DMARC record for `domain`, falling back to its organizational
+/// domain's, and whether it was the fallback. Errors are resolver
+/// failures.
+async fn lookup(dns: &Dns, domain: &str) -> Result<Option<(Record, bool)>, String> {
+    let organizational = organizational_domain(domain);
+    let mut candidates = vec![(domain.to_string(), false)];
+    if organizational != domain {
+        candidates.push((organizational, true));
+    }
+    for (candidate, inherited) in candidates {
+        let records = match dns.txt(&format!("_dmarc.{}", candidate)).await {
+            Ok(records) => records,
+            Err(DnsError::NotFound) => continue,
+            Err(DnsError::Temporary(e)) => return Err(e),
+        };
+        if let Some(record) = records.iter().find_map(|record| parse_record(record)) {
+            return Ok(Some((record, inherited)));
+        }
+    }
+    Ok(None)
+}
+
+/// Evaluate `from_domain` against the SPF and DKIM results.
+pub async fn evaluate(dns: &Dns, from_domain: Option<&str>, spf: &SpfResult, dkim: &[DkimResult]) -> DmarcResult {
+    let mut result = DmarcResult {
+        result: "none",
+        from_domain: from_domain.map(String::from),
+        policy: None,
+        spf_aligned: false,
+        dkim_aligned: false,
+        disposition: "none",
+        reason: None,
+    };
+    let Some(from_domain) = from_domain else {
+        result.result = "permerror";
+        result.reason = Some("The message needs exactly one From address".to_string());
+        return result;
+    };
+
+    let (record, inherited) = match lookup(dns, from_domain).await {
+        Ok(Some(found)) => found,
+        Ok(None) => {
+            result.reason = Some(format!("{} publishes no DMARC record", from_domain));
+            return result;
+        }
+        Err(e) => {
+            result.result = "temperror";
+            result.reason = Some(e);
+            return result;
+        }
+    };
+
+    result.spf_aligned = spf.result == "pass" && aligned(from_domain, &spf.domain, record.strict_spf);
+    result.dkim_aligned = dkim
+        .iter()
+        .any(|signature| signature.result == "pass" && aligned(from_domain, &signature.domain, record.strict_dkim));
+    let policy = match (inherited, record.subdomain_policy) {
+        (true, Some(subdomain_policy)) => subdomain_policy,
+        _ => record.policy,
+    };
+    if result.spf_aligned || result.dkim_aligned {
+        result.result = "pass";
+    } else {
+        result.result = "fail";
+        result.disposition = match policy.as_str() {
+            "reject" => "reject",
+            "quarantine" => "quarantine",
+            _ => "none",
+        };
+    }
+    result.policy = Some(policy);
+    result
+}
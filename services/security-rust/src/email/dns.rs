@@ -0,0 +1,73 @@
+/*!
+DNS Lookups
+The TXT, address and MX queries SPF, DKIM and DMARC checks make, through
+the system's resolvers
+*/
+
+use hickory_resolver::error::{ResolveError, ResolveErrorKind};
+use hickory_resolver::TokioAsyncResolver;
+use std::net::IpAddr;
+use tracing::warn;
+
+/// Why a lookup found nothing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DnsError {
+    /// The name does not exist or has no records of the type.
+    NotFound,
+    /// The resolver failed or timed out; the answer may exist.
+    Temporary(String),
+}
+
+fn dns_error(e: ResolveError) -> DnsError {
+    match e.kind() {
+        ResolveErrorKind::NoRecordsFound { .. } => DnsError::NotFound,
+        _ => DnsError::Temporary(e.to_string()),
+    }
+}
+
+/// Names are looked up as absolute so search domains never apply.
+fn absolute(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
+
+pub struct Dns {
+    resolver: TokioAsyncResolver,
+}
+
+impl Dns {
+    pub fn new(timeout_secs: u64) -> Self {
+        let (config, mut opts) = hickory_resolver::system_conf::read_system_conf().unwrap_or_else(|e| {
+            warn!("No system resolver configuration ({}), using defaults", e);
+            Default::default()
+        });
+        opts.timeout = std::time::Duration::from_secs(timeout_secs);
+        Self {
+            resolver: TokioAsyncResolver::tokio(config, opts),
+        }
+    }
+
+    /// Each TXT record with its strings joined.
+    pub async fn txt(&self, name: &str) -> Result<Vec<String>, DnsError> {
+        let lookup = self.resolver.txt_lookup(absolute(name)).await.map_err(dns_error)?;
+        Ok(lookup
+            .iter()
+            .map(|txt| txt.txt_data().iter().map(|part| String::from_utf8_lossy(part)).collect::<String>())
+            .collect())
+    }
+
+    pub async fn ips(&self, name: &str) -> Result<Vec<IpAddr>, DnsError> {
+        let lookup = self.resolver.lookup_ip(absolute(name)).await.map_err(dns_error)?;
+        Ok(lookup.iter().collect())
+    }
+
+    /// Exchange hosts, most preferred first.
+    pub async fn mx(&self, name: &str) -> Result<Vec<String>, DnsError> {
+        let lookup = self.resolver.mx_lookup(absolute(name)).await.map_err(dns_error)?;
+        let mut exchanges: Vec<(u16, String)> = lookup
+            .iter()
+            .map(|mx| (mx.preference(), mx.exchange().to_utf8().trim_end_matches('.').to_string()))
+            .collect();
+        exchanges.sort();
+        Ok(exchanges.into_iter().map(|(_, host)| host).collect())
+    }
+}
@@ -0,0 +1,196 @@
+/*!
+Message Parsing
+Header fields and body of an RFC 5322 message, and their DKIM
+canonicalizations (RFC 6376 section 3.4)
+
+Line endings are normalized to CRLF on parsing, since messages handed over
+JSON rarely keep them; everything else is kept byte for byte so `simple`
+canonicalization still sees the fields as sent.
+*/
+
+/// One header field, as received.
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub name: String,
+    /// The whole field, folding included, ending in CRLF.
+    pub raw: String,
+}
+
+impl Header {
+    /// Everything after the colon, folding kept, without the final CRLF.
+    pub fn value(&self) -> &str {
+        let value = self.raw.split_once(':').map_or("", |(_, value)| value);
+        value.strip_suffix("\r\n").unwrap_or(value)
+    }
+
+    pub fn is(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub headers: Vec<Header>,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Canon {
+    Simple,
+    Relaxed,
+}
+
+impl Canon {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "simple" => Some(Self::Simple),
+            "relaxed" => Some(Self::Relaxed),
+            _ => None,
+        }
+    }
+}
+
+fn normalize(raw: &str) -> String {
+    raw.replace("\r\n", "\n").replace('\r', "\n").replace('\n', "\r\n")
+}
+
+/// Split a message into header fields and body.
+pub fn parse(raw: &str) -> Result<Message, String> {
+    let raw = normalize(raw);
+    let (head, body) = match raw.split_once("\r\n\r\n") {
+        Some((head, body)) => (head.to_string(), body.to_string()),
+        None => (raw.trim_end_matches("\r\n").to_string(), String::new()),
+    };
+
+    let mut headers: Vec<Header> = Vec::new();
+    for line in head.split("\r\n") {
+        if line.starts_with([' ', '\t']) {
+            let last = headers.last_mut().ok_or("The message starts with a continuation line")?;
+            last.raw.push_str(line);
+            last.raw.push_str("\r\n");
+            continue;
+        }
+        let (name, _) = line.split_once(':').ok_or_else(|| format!("Malformed header line '{}'", line))?;
+        if name.is_empty() || name.contains([' ', '\t']) {
+            return Err(format!("Malformed header name '{}'", name));
+        }
+        headers.push(Header {
+            name: name.to_string(),
+            raw: format!("{}\r\n", line),
+        });
+    }
+    Ok(Message { headers, body })
+}
+
+impl Message {
+    /// Fields named `name`, top first.
+    pub fn headers_named<'a>(&'a self, name: &'a str) -> impl DoubleEndedIterator<Item = &'a Header> + 'a {
+        self.headers.iter().filter(move |header| header.is(name))
+    }
+
+    pub fn header<'a>(&'a self, name: &'a str) -> Option<&'a Header> {
+        self.headers_named(name).next()
+    }
+
+    /// The message as sent, with `fields` prepended.
+    pub fn with_headers(&self, fields: &[String]) -> String {
+        let mut out = String::new();
+        for field in fields {
+            out.push_str(field);
+            out.push_str("\r\n");
+        }
+        for header in &self.headers {
+            out.push_str(&header.raw);
+        }
+        out.push_str("\r\n");
+        out.push_str(&self.body);
+        out
+    }
+}
+
+/// Unfolded, whitespace runs collapsed to one space, trimmed.
+fn relaxed_value(value: &str) -> String {
+    value.replace("\r\n", "").split([' ', '\t']).filter(|part| !part.is_empty()).collect::<Vec<_>>().join(" ")
+}
+
+/// A header field canonicalized, ending in CRLF.
+pub fn canon_header(header: &Header, canon: Canon) -> String {
+    match canon {
+        Canon::Simple => header.raw.clone(),
+        Canon::Relaxed => format!("{}:{}\r\n", header.name.trim_end().to_lowercase(), relaxed_value(header.value())),
+    }
+}
+
+pub fn canon_body(body: &str, canon: Canon) -> String {
+    let mut lines: Vec<String> = body
+        .split("\r\n")
+        .map(|line| match canon {
+            Canon::Simple => line.to_string(),
+            Canon::Relaxed => {
+                // Whitespace runs become one space; trailing ones go
+                let mut out = String::with_capacity(line.len());
+                let mut in_space = false;
+                for c in line.chars() {
+                    if c == ' ' || c == '\t' {
+                        in_space = true;
+                        continue;
+                    }
+                    if in_space {
+                        out.push(' ');
+                        in_space = false;
+                    }
+                    out.push(c);
+                }
+                out
+            }
+        })
+        .collect();
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    if lines.is_empty() {
+        return match canon {
+            Canon::Simple => "\r\n".to_string(),
+            Canon::Relaxed => String::new(),
+        };
+    }
+    let mut out = lines.join("\r\n");
+    out.push_str("\r\n");
+    out
+}
+
+/// `name=value` pairs of a DKIM or DMARC tag list, names lowercased and
+/// values trimmed.
+pub fn tags(list: &str) -> Vec<(String, String)> {
+    list.split(';')
+        .filter_map(|tag| {
+            let (name, value) = tag.split_once('=')?;
+            Some((name.trim().to_lowercase(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+pub fn tag<'a>(tags: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+}
+
+/// Whitespace removed, as base64 tag values are compared and decoded.
+pub fn compact(value: &str) -> String {
+    value.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+/// The domain of the single address in a `From`-like field, lowercased.
+/// None for a field listing several.
+pub fn address_domain(value: &str) -> Option<String> {
+    let value = value.replace("\r\n", "");
+    if value.contains(',') && value.matches('@').count() > 1 {
+        return None;
+    }
+    let address = match (value.rfind('<'), value.rfind('>')) {
+        (Some(start), Some(end)) if start < end => value[start + 1..end].to_string(),
+        _ => value.trim().to_string(),
+    };
+    let (_, domain) = address.rsplit_once('@')?;
+    let domain = domain.trim().trim_end_matches('.').to_lowercase();
+    (!domain.is_empty() && !domain.contains([' ', ',', ';'])).then_some(domain)
+}
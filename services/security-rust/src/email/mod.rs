@@ -0,0 +1,738 @@
+/*!
+Email Authentication
+DKIM signing for outgoing mail, and SPF, DKIM and DMARC checks for
+incoming mail
+
+The notification service hands award emails, fully composed, to
+`POST /email/dkim/sign` (`EMAIL_SIGN_SCOPE`) with the domain to sign for:
+
+```json
+{ "domain": "cotai.com.br", "message": "From: ...\r\n...\r\n\r\nbody" }
+```
+
+and gets back the message with a `DKIM-Signature` per algorithm in
+`EMAIL_DKIM_ALGORITHMS` prepended, ready to relay. Only domains in
+`EMAIL_DKIM_DOMAINS` are signed for, and only messages whose `From`
+address shares the domain's organizational domain, so DMARC can align on
+the signature.
+
+Keys are generated here, one per domain and algorithm, and kept encrypted
+by `CryptoService`; nobody sees the private half. Every
+`EMAIL_DKIM_INTERVAL_SECS` the rotation pass:
+
+1. gives a domain without keys its first ones, active at once
+2. starts a rotation when the active key is `EMAIL_DKIM_ROTATION_DAYS`
+   old: a `pending` key under a new selector
+3. activates a pending key once its record has had
+   `EMAIL_DKIM_PROPAGATION_SECS` to reach resolvers, moving the old key to
+   `verify_only`
+4. retires `verify_only` keys after `EMAIL_DKIM_RETIRE_AFTER_SECS`, when
+   mail they signed has been delivered
+
+Admins see the TXT records to publish (pending, active and verify-only
+keys) at `GET /admin/email/dkim/{domain}/dns`, list keys at
+`GET /admin/email/dkim/keys` and rotate at once with
+`POST /admin/email/dkim/{domain}/rotate`. A domain's first keys sign at
+once, before their records can have been published, so publish them before
+relaying mail. Key changes, signatures and checks are audited.
+
+Complaint and report messages received from mailbox providers go to
+`POST /email/verify` (`EMAIL_VERIFY_SCOPE`) with the SMTP session's client
+address, envelope sender and HELO name, when known. The answer holds the
+SPF result (see `spf`), each DKIM signature's (see `dkim`) and the DMARC
+result and disposition (see `dmarc`), so forged reports can be set aside.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair, RsaKeyPair, RSA_PKCS1_SHA256};
+use rsa::pkcs8::{EncodePrivateKey, EncodePublicKey};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, Transaction};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, EmailConfig};
+use crate::crypto::CryptoService;
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+use crate::AppState;
+
+pub mod dkim;
+pub mod dmarc;
+pub mod dns;
+pub mod message;
+pub mod spf;
+
+use dkim::{DkimResult, ED25519_SHA256, RSA_SHA256};
+use dmarc::DmarcResult;
+use dns::Dns;
+use spf::SpfResult;
+
+pub const SYSTEM_ACTOR: &str = "system:dkim";
+
+const KEY_COLUMNS: &str =
+    "selector, domain, algorithm, public_key, state, created_by, created_at, activate_at, retire_at, retired_at";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct DkimKey {
+    pub selector: String,
+    pub domain: String,
+    pub algorithm: String,
+    /// The record's `p=` value.
+    pub public_key: String,
+    /// `pending`, `active`, `verify_only` or `retired`.
+    pub state: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    /// When the key signs, or started to.
+    pub activate_at: DateTime<Utc>,
+    pub retire_at: Option<DateTime<Utc>>,
+    pub retired_at: Option<DateTime<Utc>>,
+}
+
+impl DkimKey {
+    pub fn dns_record(&self) -> DnsRecord {
+        DnsRecord {
+            name: format!("{}._domainkey.{}", self.selector, self.domain),
+            record_type: "TXT",
+            value: format!(
+                "v=DKIM1; k={}; p={}",
+                dkim::key_type(&self.algorithm).unwrap_or("rsa"),
+                self.public_key
+            ),
+            selector: self.selector.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DnsRecord {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub record_type: &'static str,
+    pub value: String,
+    pub selector: String,
+    pub state: String,
+}
+
+#[derive(Debug, FromRow)]
+struct SigningKey {
+    selector: String,
+    algorithm: String,
+    secret_key_id: String,
+    secret_nonce: Vec<u8>,
+    secret_ciphertext: Vec<u8>,
+}
+
+/// A change the rotation pass or an admin made to a key.
+#[derive(Debug, Clone)]
+pub struct KeyChange {
+    /// `create`, `rotate`, `activate` or `retire`.
+    pub action: &'static str,
+    pub key: DkimKey,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignRequest {
+    pub domain: String,
+    pub message: String,
+    /// Header fields to sign instead of `EMAIL_DKIM_HEADERS`; `From` is
+    /// always signed.
+    pub headers: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SignedMessage {
+    /// The `DKIM-Signature` fields, as prepended.
+    pub signatures: Vec<String>,
+    pub selectors: Vec<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VerifyRequest {
+    pub message: String,
+    /// Address of the SMTP client that delivered the message.
+    pub client_ip: Option<IpAddr>,
+    /// Envelope sender (`MAIL FROM`); empty for bounces.
+    pub mail_from: Option<String>,
+    pub helo: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Verification {
+    pub spf: SpfResult,
+    pub dkim: Vec<DkimResult>,
+    pub dmarc: DmarcResult,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeyFilter {
+    pub domain: Option<String>,
+    pub include_retired: Option<bool>,
+}
+
+fn key_context(selector: &str) -> HashMap<String, String> {
+    HashMap::from([
+        ("purpose".to_string(), "dkim-key".to_string()),
+        ("selector".to_string(), selector.to_string()),
+    ])
+}
+
+fn key_error(e: impl std::fmt::Display) -> SecurityError {
+    SecurityError::CryptoError(format!("DKIM key: {}", e))
+}
+
+pub struct EmailService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: EmailConfig,
+    dns: Dns,
+}
+
+impl EmailService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        info!("Email authentication initialized for {} signing domains", config.email.dkim_domains.len());
+        Ok(Self {
+            storage,
+            clock,
+            config: config.email.clone(),
+            dns: Dns::new(config.email.dns_timeout_secs),
+        })
+    }
+
+    fn signing_domain(&self, domain: &str) -> Result<String, SecurityError> {
+        let domain = domain.trim().trim_end_matches('.').to_lowercase();
+        if !self.config.dkim_domains.iter().any(|configured| configured.eq_ignore_ascii_case(&domain)) {
+            return Err(SecurityError::NotFound(format!("{} is not a DKIM signing domain", domain)));
+        }
+        Ok(domain)
+    }
+
+    pub async fn keys(&self, filter: &KeyFilter) -> Result<Vec<DkimKey>, SecurityError> {
+        Ok(sqlx::query_as::<_, DkimKey>(&format!(
+            "SELECT {} FROM dkim_keys WHERE ($1::text IS NULL OR domain = $1) AND ($2 OR state <> 'retired') \
+             ORDER BY domain, algorithm, created_at DESC",
+            KEY_COLUMNS
+        ))
+        .bind(filter.domain.as_ref().map(|domain| domain.to_lowercase()))
+        .bind(filter.include_retired.unwrap_or(false))
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    /// The records to publish for `domain`: every key that signs, will
+    /// sign or still has mail in flight.
+    pub async fn dns_records(&self, domain: &str) -> Result<Vec<DnsRecord>, SecurityError> {
+        let domain = self.signing_domain(domain)?;
+        let filter = KeyFilter { domain: Some(domain), include_retired: Some(false) };
+        Ok(self.keys(&filter).await?.iter().map(DkimKey::dns_record).collect())
+    }
+
+    /// Lock `domain`'s keys for `algorithm` and load those not retired.
+    async fn lock(&self, tx: &mut Transaction<'static, Postgres>, domain: &str, algorithm: &str) -> Result<Vec<DkimKey>, SecurityError> {
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+            .bind(format!("dkim:{}:{}", domain, algorithm))
+            .execute(&mut **tx)
+            .await?;
+        Ok(sqlx::query_as::<_, DkimKey>(&format!(
+            "SELECT {} FROM dkim_keys WHERE domain = $1 AND algorithm = $2 AND state <> 'retired' \
+             ORDER BY created_at",
+            KEY_COLUMNS
+        ))
+        .bind(domain)
+        .bind(algorithm)
+        .fetch_all(&mut **tx)
+        .await?)
+    }
+
+    /// Generate and store a key, `active` when `activate_at` is now.
+    async fn generate(
+        &self,
+        tx: &mut Transaction<'static, Postgres>,
+        crypto: &CryptoService,
+        domain: &str,
+        algorithm: &str,
+        actor: &str,
+        activate_at: DateTime<Utc>,
+    ) -> Result<DkimKey, SecurityError> {
+        let now = self.clock.now();
+        let (secret, public_key) = match algorithm {
+            ED25519_SHA256 => {
+                let seed = crypto.secure_random(32).await?;
+                let pair = Ed25519KeyPair::from_seed_unchecked(&seed).map_err(key_error)?;
+                let public_key = pair.public_key().as_ref().to_vec();
+                (seed, public_key)
+            }
+            RSA_SHA256 => {
+                // RSA generation comes from the rsa crate and the OS
+                // generator, not the service's random source
+                let bits = self.config.dkim_rsa_bits;
+                tokio::task::spawn_blocking(move || -> Result<(Vec<u8>, Vec<u8>), SecurityError> {
+                    let key = rsa::RsaPrivateKey::new(&mut rand::rngs::OsRng, bits).map_err(key_error)?;
+                    let secret = key.to_pkcs8_der().map_err(key_error)?.as_bytes().to_vec();
+                    let public_key = key.to_public_key().to_public_key_der().map_err(key_error)?.as_bytes().to_vec();
+                    Ok((secret, public_key))
+                })
+                .await
+                .map_err(key_error)??
+            }
+            other => return Err(SecurityError::ConfigError(format!("Unsupported DKIM algorithm {}", other))),
+        };
+
+        let selector = format!(
+            "{}-{}-{}-{}",
+            self.config.dkim_selector_prefix,
+            dkim::key_type(algorithm).unwrap_or("rsa"),
+            now.format("%Y%m%d"),
+            hex::encode(crypto.secure_random(4).await?)
+        );
+        let sealed = crypto.encrypt_bytes(secret, None, Some(&key_context(&selector)))?;
+        let state = if activate_at <= now { "active" } else { "pending" };
+
+        let key = sqlx::query_as::<_, DkimKey>(&format!(
+            "INSERT INTO dkim_keys (selector, domain, algorithm, public_key, secret_key_id, secret_nonce, \
+             secret_ciphertext, state, created_by, created_at, activate_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) RETURNING {}",
+            KEY_COLUMNS
+        ))
+        .bind(&selector)
+        .bind(domain)
+        .bind(algorithm)
+        .bind(base64::encode(public_key))
+        .bind(&sealed.key_id)
+        .bind(&sealed.nonce)
+        .bind(&sealed.ciphertext)
+        .bind(state)
+        .bind(actor)
+        .bind(now)
+        .bind(activate_at)
+        .fetch_one(&mut **tx)
+        .await?;
+        info!("Generated {} DKIM key {} for {} ({})", algorithm, selector, domain, state);
+        Ok(key)
+    }
+
+    async fn set_state(
+        &self,
+        tx: &mut Transaction<'static, Postgres>,
+        selector: &str,
+        state: &str,
+        retire_at: Option<DateTime<Utc>>,
+    ) -> Result<DkimKey, SecurityError> {
+        let now = self.clock.now();
+        Ok(sqlx::query_as::<_, DkimKey>(&format!(
+            "UPDATE dkim_keys SET state = $2, retire_at = COALESCE($3, retire_at), \
+             retired_at = CASE WHEN $2 = 'retired' THEN $4 ELSE retired_at END \
+             WHERE selector = $1 RETURNING {}",
+            KEY_COLUMNS
+        ))
+        .bind(selector)
+        .bind(state)
+        .bind(retire_at)
+        .bind(now)
+        .fetch_one(&mut **tx)
+        .await?)
+    }
+
+    /// Move one domain's keys for one algorithm along.
+    async fn advance(&self, crypto: &CryptoService, domain: &str, algorithm: &str) -> Result<Vec<KeyChange>, SecurityError> {
+        let now = self.clock.now();
+        let mut tx = self.storage.begin().await?;
+        let keys = self.lock(&mut tx, domain, algorithm).await?;
+        let mut changes = Vec::new();
+
+        for key in keys.iter().filter(|key| key.state == "verify_only" && key.retire_at.is_some_and(|at| at <= now)) {
+            let key = self.set_state(&mut tx, &key.selector, "retired", None).await?;
+            changes.push(KeyChange { action: "retire", key });
+        }
+
+        let due = keys.iter().rfind(|key| key.state == "pending" && key.activate_at <= now);
+        let mut active = keys.iter().find(|key| key.state == "active").cloned();
+        if let Some(due) = due {
+            let retire_at = now + Duration::seconds(self.config.dkim_retire_after_secs);
+            for old in keys.iter().filter(|key| key.state == "active") {
+                self.set_state(&mut tx, &old.selector, "verify_only", Some(retire_at)).await?;
+            }
+            let key = self.set_state(&mut tx, &due.selector, "active", None).await?;
+            active = Some(key.clone());
+            changes.push(KeyChange { action: "activate", key });
+        }
+
+        let pending = keys.iter().any(|key| key.state == "pending" && key.activate_at > now);
+        match active {
+            None if !pending => {
+                let key = self.generate(&mut tx, crypto, domain, algorithm, SYSTEM_ACTOR, now).await?;
+                changes.push(KeyChange { action: "create", key });
+            }
+            Some(active)
+                if !pending
+                    && self.config.dkim_rotation_days > 0
+                    && active.activate_at + Duration::days(self.config.dkim_rotation_days) <= now =>
+            {
+                let activate_at = now + Duration::seconds(self.config.dkim_propagation_secs);
+                let key = self.generate(&mut tx, crypto, domain, algorithm, SYSTEM_ACTOR, activate_at).await?;
+                changes.push(KeyChange { action: "rotate", key });
+            }
+            _ => {}
+        }
+
+        tx.commit().await?;
+        Ok(changes)
+    }
+
+    /// One rotation pass over every signing domain and algorithm.
+    pub async fn run_pass(&self, crypto: &CryptoService) -> Result<Vec<KeyChange>, SecurityError> {
+        let mut changes = Vec::new();
+        for domain in &self.config.dkim_domains {
+            for algorithm in &self.config.dkim_algorithms {
+                changes.extend(self.advance(crypto, &domain.to_lowercase(), algorithm).await?);
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Start a rotation of `domain`'s keys now: new pending keys, active
+    /// after the propagation delay.
+    pub async fn rotate(&self, crypto: &CryptoService, principal: &Principal, domain: &str) -> Result<Vec<KeyChange>, SecurityError> {
+        let domain = self.signing_domain(domain)?;
+        let activate_at = self.clock.now() + Duration::seconds(self.config.dkim_propagation_secs);
+        let mut tx = self.storage.begin().await?;
+        let mut changes = Vec::new();
+        for algorithm in &self.config.dkim_algorithms {
+            let keys = self.lock(&mut tx, &domain, algorithm).await?;
+            if keys.iter().any(|key| key.state == "pending") {
+                return Err(SecurityError::Conflict(format!(
+                    "A {} rotation is already pending for {}",
+                    algorithm, domain
+                )));
+            }
+            let key = self.generate(&mut tx, crypto, &domain, algorithm, &principal.subject, activate_at).await?;
+            changes.push(KeyChange { action: "rotate", key });
+        }
+        tx.commit().await?;
+        Ok(changes)
+    }
+
+    fn sign_with(&self, crypto: &CryptoService, key: &SigningKey, data: &[u8]) -> Result<Vec<u8>, SecurityError> {
+        let context_hash = crypto.context_hash(Some(&key_context(&key.selector)))?;
+        let secret = crypto.decrypt_bytes(
+            &key.secret_key_id,
+            &key.secret_nonce,
+            key.secret_ciphertext.clone(),
+            context_hash.as_deref(),
+        )?;
+        match key.algorithm.as_str() {
+            ED25519_SHA256 => {
+                let pair = Ed25519KeyPair::from_seed_unchecked(&secret).map_err(key_error)?;
+                Ok(pair.sign(data).as_ref().to_vec())
+            }
+            _ => {
+                let pair = RsaKeyPair::from_pkcs8(&secret).map_err(key_error)?;
+                let mut signature = vec![0u8; pair.public().modulus_len()];
+                pair.sign(&RSA_PKCS1_SHA256, &SystemRandom::new(), data, &mut signature)
+                    .map_err(key_error)?;
+                Ok(signature)
+            }
+        }
+    }
+
+    /// Sign `request.message` with each active key of its domain.
+    pub async fn sign(&self, crypto: &CryptoService, request: SignRequest) -> Result<SignedMessage, SecurityError> {
+        let domain = self.signing_domain(&request.domain)?;
+        if request.message.len() > self.config.max_message_bytes {
+            return Err(SecurityError::ValidationError(format!(
+                "The message exceeds {} bytes",
+                self.config.max_message_bytes
+            )));
+        }
+        let parsed = message::parse(&request.message).map_err(SecurityError::ValidationError)?;
+        let from_domain = parsed
+            .header("from")
+            .and_then(|from| message::address_domain(from.value()))
+            .ok_or_else(|| SecurityError::ValidationError("The message needs a From field with one address".to_string()))?;
+        if dmarc::organizational_domain(&from_domain) != dmarc::organizational_domain(&domain) {
+            return Err(SecurityError::ValidationError(format!(
+                "The From domain {} does not align with {}",
+                from_domain, domain
+            )));
+        }
+
+        let mut headers = request.headers.unwrap_or_else(|| self.config.dkim_headers.clone());
+        if !headers.iter().any(|name| name.eq_ignore_ascii_case("from")) {
+            headers.insert(0, "from".to_string());
+        }
+
+        let keys = sqlx::query_as::<_, SigningKey>(
+            "SELECT selector, algorithm, secret_key_id, secret_nonce, secret_ciphertext FROM dkim_keys \
+             WHERE domain = $1 AND state = 'active' ORDER BY algorithm",
+        )
+        .bind(&domain)
+        .fetch_all(self.storage.pool())
+        .await?;
+        if keys.is_empty() {
+            return Err(SecurityError::NotFound(format!("No active DKIM key for {}", domain)));
+        }
+
+        let timestamp = self.clock.now().timestamp();
+        let mut signatures = Vec::new();
+        for key in &keys {
+            let (field, data) = dkim::prepare(&parsed, &domain, &key.selector, &key.algorithm, &headers, timestamp)
+                .map_err(SecurityError::ValidationError)?;
+            let signature = self.sign_with(crypto, key, &data)?;
+            signatures.push(dkim::finish(&field, &signature));
+        }
+        Ok(SignedMessage {
+            message: parsed.with_headers(&signatures),
+            signatures,
+            selectors: keys.into_iter().map(|key| key.selector).collect(),
+        })
+    }
+
+    /// SPF, DKIM and DMARC results for a received message.
+    pub async fn verify(&self, request: &VerifyRequest) -> Result<Verification, SecurityError> {
+        if request.message.len() > self.config.max_message_bytes {
+            return Err(SecurityError::ValidationError(format!(
+                "The message exceeds {} bytes",
+                self.config.max_message_bytes
+            )));
+        }
+        let parsed = message::parse(&request.message).map_err(SecurityError::ValidationError)?;
+
+        // Bounces have no envelope sender and are checked by HELO name
+        let helo = request.helo.as_deref().map(|helo| helo.trim().trim_end_matches('.').to_lowercase());
+        let spf_domain = request
+            .mail_from
+            .as_deref()
+            .and_then(message::address_domain)
+            .or(helo.filter(|helo| !helo.is_empty()));
+        let spf = match (request.client_ip, spf_domain) {
+            (Some(ip), Some(domain)) => spf::check(&self.dns, ip, &domain).await,
+            (ip, domain) => SpfResult {
+                result: "none",
+                reason: Some(if ip.is_none() { "No client address given" } else { "No sender domain given" }.to_string()),
+                domain: domain.unwrap_or_default(),
+            },
+        };
+
+        let dkim = dkim::verify(&self.dns, &parsed, self.clock.now().timestamp()).await;
+        let mut from = parsed.headers_named("from");
+        let from_domain = match (from.next(), from.next()) {
+            (Some(from), None) => message::address_domain(from.value()),
+            _ => None,
+        };
+        let dmarc = dmarc::evaluate(&self.dns, from_domain.as_deref(), &spf, &dkim).await;
+        Ok(Verification { spf, dkim, dmarc })
+    }
+}
+
+pub async fn run_rotation(state: web::Data<AppState>) {
+    if state.config.email.dkim_domains.is_empty() {
+        return;
+    }
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(state.config.email.dkim_interval_secs));
+    loop {
+        interval.tick().await;
+        match state.email.run_pass(&state.crypto_service).await {
+            Ok(changes) => {
+                for change in &changes {
+                    audit_change(&state, SYSTEM_ACTOR, None, change).await;
+                }
+            }
+            Err(e) => error!("DKIM rotation pass failed: {:?}", e),
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Email authentication operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Email authentication operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit_change(state: &AppState, actor: &str, actor_ip: Option<String>, change: &KeyChange) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: None,
+        actor: actor.to_string(),
+        actor_ip,
+        action: format!("email.dkim.{}", change.action),
+        resource: format!("dkim_key:{}", change.key.selector),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "domain": change.key.domain,
+            "algorithm": change.key.algorithm,
+            "state": change.key.state,
+            "activate_at": change.key.activate_at,
+            "dns": change.key.dns_record()
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit DKIM {} of {}: {:?}", change.action, change.key.selector, e);
+    }
+}
+
+async fn audit_use(state: &AppState, req: &HttpRequest, principal: &Principal, action: &str, resource: String, payload: serde_json::Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: principal.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(req),
+        action: action.to_string(),
+        resource,
+        outcome: "success".to_string(),
+        payload,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {}: {:?}", action, e);
+    }
+}
+
+pub async fn sign_handler(
+    req: HttpRequest,
+    request: web::Json<SignRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.email.sign_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let request = request.into_inner();
+    let domain = request.domain.to_lowercase();
+    let message_id = message::parse(&request.message)
+        .ok()
+        .and_then(|parsed| parsed.header("message-id").map(|id| id.value().trim().to_string()));
+    match state.email.sign(&state.crypto_service, request).await {
+        Ok(signed) => {
+            state.metrics_service.increment("cotai_dkim_signatures_total", &[("domain", &domain)]);
+            audit_use(&state, &req, &principal, "email.dkim.sign", format!("email_domain:{}", domain), serde_json::json!({
+                "selectors": signed.selectors,
+                "message_id": message_id
+            })).await;
+            Ok(HttpResponse::Ok().json(signed))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn verify_handler(
+    req: HttpRequest,
+    request: web::Json<VerifyRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.email.verify_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.email.verify(&request).await {
+        Ok(verification) => {
+            state.metrics_service.increment("cotai_email_verifications_total", &[("dmarc", verification.dmarc.result)]);
+            let from_domain = verification.dmarc.from_domain.clone().unwrap_or_default();
+            audit_use(&state, &req, &principal, "email.verify", format!("email_domain:{}", from_domain), serde_json::json!({
+                "client_ip": request.client_ip,
+                "spf": verification.spf.result,
+                "dkim": verification.dkim.iter().map(|result| (&result.domain, result.result)).collect::<Vec<_>>(),
+                "dmarc": verification.dmarc.result,
+                "disposition": verification.dmarc.disposition
+            })).await;
+            Ok(HttpResponse::Ok().json(verification))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_keys_handler(
+    req: HttpRequest,
+    filter: web::Query<KeyFilter>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.email.keys(&filter).await {
+        Ok(keys) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "keys": keys
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn dns_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.email.dns_records(&path).await {
+        Ok(records) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "records": records
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn rotate_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.email.rotate(&state.crypto_service, &principal, &path).await {
+        Ok(changes) => {
+            for change in &changes {
+                audit_change(&state, &principal.subject, client_ip(&req), change).await;
+            }
+            Ok(HttpResponse::Created().json(serde_json::json!({
+                "keys": changes.iter().map(|change| &change.key).collect::<Vec<_>>(),
+                "records": changes.iter().map(|change| change.key.dns_record()).collect::<Vec<_>>()
+            })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/email/dkim/sign", web::post().to(sign_handler))
+        .route("/email/verify", web::post().to(verify_handler))
+        .service(
+            web::scope("/admin/email/dkim")
+                .route("/keys", web::get().to(list_keys_handler))
+                .route("/{domain}/dns", web::get().to(dns_handler))
+                .route("/{domain}/rotate", web::post().to(rotate_handler)),
+        );
+}
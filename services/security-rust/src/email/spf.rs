@@ -0,0 +1,226 @@
+/*!
+SPF Evaluation
+Whether a client may send for a domain, per RFC 7208
+
+Supported mechanisms are `all`, `include`, `a`, `mx`, `ip4`, `ip6` and
+`exists`, with the `redirect` modifier. `ptr` never matches, as the RFC
+advises. Macros are not expanded: a record using them is a `permerror`,
+which DMARC then treats as no SPF pass. At most ten terms may cause DNS
+lookups across the whole evaluation.
+*/
+
+use futures::future::BoxFuture;
+use serde::Serialize;
+use std::net::IpAddr;
+
+use super::dns::{Dns, DnsError};
+use crate::network::Cidr;
+
+const MAX_LOOKUPS: u32 = 10;
+/// Addresses looked up per `mx` term.
+const MAX_MX: usize = 10;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SpfResult {
+    /// `pass`, `fail`, `softfail`, `neutral`, `none`, `temperror` or
+    /// `permerror`.
+    pub result: &'static str,
+    /// The domain checked: the envelope sender's, or the HELO name's.
+    pub domain: String,
+    pub reason: Option<String>,
+}
+
+enum Stop {
+    Temp(String),
+    Perm(String),
+}
+
+impl From<DnsError> for Stop {
+    fn from(e: DnsError) -> Self {
+        match e {
+            DnsError::NotFound => Stop::Perm("no such name".to_string()),
+            DnsError::Temporary(e) => Stop::Temp(e),
+        }
+    }
+}
+
+/// Result of the record for `domain`: None when there is none.
+type Evaluation = Result<Option<&'static str>, Stop>;
+
+fn qualifier_result(qualifier: char) -> &'static str {
+    match qualifier {
+        '-' => "fail",
+        '~' => "softfail",
+        '?' => "neutral",
+        _ => "pass",
+    }
+}
+
+async fn record(dns: &Dns, domain: &str) -> Result<Option<String>, Stop> {
+    let records = match dns.txt(domain).await {
+        Ok(records) => records,
+        Err(DnsError::NotFound) => return Ok(None),
+        Err(DnsError::Temporary(e)) => return Err(Stop::Temp(e)),
+    };
+    let mut spf = records.into_iter().filter(|record| {
+        let lower = record.to_ascii_lowercase();
+        lower == "v=spf1" || lower.starts_with("v=spf1 ")
+    });
+    match (spf.next(), spf.next()) {
+        (None, _) => Ok(None),
+        (Some(record), None) => Ok(Some(record)),
+        _ => Err(Stop::Perm(format!("{} publishes more than one SPF record", domain))),
+    }
+}
+
+/// `domain[/v4][//v6]` split, defaulting the domain to the current one.
+fn target_and_prefixes(argument: Option<&str>, current: &str) -> Result<(String, u8, u8), Stop> {
+    let argument = argument.unwrap_or("");
+    let (rest, v6) = match argument.split_once("//") {
+        Some((rest, v6)) => (rest, Some(v6)),
+        None => (argument, None),
+    };
+    let (domain, v4) = match rest.rsplit_once('/') {
+        Some((domain, v4)) => (domain, Some(v4)),
+        None => (rest, None),
+    };
+    let prefix = |value: Option<&str>, max: u8| -> Result<u8, Stop> {
+        match value {
+            None => Ok(max),
+            Some(value) => value
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max)
+                .ok_or_else(|| Stop::Perm(format!("invalid prefix length '{}'", value))),
+        }
+    };
+    let domain = if domain.is_empty() { current.to_string() } else { domain.trim_start_matches(':').to_string() };
+    Ok((domain, prefix(v4, 32)?, prefix(v6, 128)?))
+}
+
+fn within(ip: IpAddr, candidate: IpAddr, v4_prefix: u8, v6_prefix: u8) -> bool {
+    ip.is_ipv4() == candidate.is_ipv4() && Cidr::around(candidate, v4_prefix, v6_prefix).contains(ip)
+}
+
+/// Addresses of `name`, none when it does not exist.
+async fn addresses(dns: &Dns, name: &str) -> Result<Vec<IpAddr>, Stop> {
+    match dns.ips(name).await {
+        Ok(ips) => Ok(ips),
+        Err(DnsError::NotFound) => Ok(Vec::new()),
+        Err(DnsError::Temporary(e)) => Err(Stop::Temp(e)),
+    }
+}
+
+fn evaluate<'a>(dns: &'a Dns, ip: IpAddr, domain: String, lookups: &'a mut u32) -> BoxFuture<'a, Evaluation> {
+    Box::pin(async move {
+        let Some(record) = record(dns, &domain).await? else {
+            return Ok(None);
+        };
+        let mut redirect = None;
+        for term in record.split_whitespace().skip(1) {
+            if term.contains('%') {
+                return Err(Stop::Perm(format!("macros are not supported ('{}')", term)));
+            }
+            if let Some((name, value)) = term.split_once('=') {
+                if name.eq_ignore_ascii_case("redirect") {
+                    redirect = Some(value.to_string());
+                }
+                // exp= and unknown modifiers are ignored
+                continue;
+            }
+
+            let (qualifier, mechanism) = match term.chars().next() {
+                Some(c @ ('+' | '-' | '~' | '?')) => (c, &term[1..]),
+                _ => ('+', term),
+            };
+            let (name, argument) = match mechanism.find([':', '/']) {
+                Some(at) => (&mechanism[..at], Some(&mechanism[at..])),
+                None => (mechanism, None),
+            };
+            let argument_value = argument.and_then(|argument| argument.strip_prefix(':'));
+            let name = name.to_ascii_lowercase();
+            if matches!(name.as_str(), "include" | "a" | "mx" | "ptr" | "exists") {
+                *lookups += 1;
+                if *lookups > MAX_LOOKUPS {
+                    return Err(Stop::Perm("more than 10 DNS lookups".to_string()));
+                }
+            }
+
+            let matched = match name.as_str() {
+                "all" => true,
+                "ip4" | "ip6" => {
+                    let network = argument_value.ok_or_else(|| Stop::Perm(format!("'{}' needs a network", term)))?;
+                    let cidr: Cidr = network.parse().map_err(Stop::Perm)?;
+                    (name == "ip4") == ip.is_ipv4() && cidr.contains(ip)
+                }
+                "include" => {
+                    let target = argument_value.ok_or_else(|| Stop::Perm("include needs a domain".to_string()))?;
+                    match evaluate(dns, ip, target.to_string(), lookups).await? {
+                        Some("pass") => true,
+                        Some(_) => false,
+                        None => return Err(Stop::Perm(format!("included {} has no SPF record", target))),
+                    }
+                }
+                "a" => {
+                    let (target, v4, v6) = target_and_prefixes(argument, &domain)?;
+                    addresses(dns, &target).await?.into_iter().any(|candidate| within(ip, candidate, v4, v6))
+                }
+                "mx" => {
+                    let (target, v4, v6) = target_and_prefixes(argument, &domain)?;
+                    let hosts = match dns.mx(&target).await {
+                        Ok(hosts) => hosts,
+                        Err(DnsError::NotFound) => Vec::new(),
+                        Err(e) => return Err(e.into()),
+                    };
+                    let mut matched = false;
+                    for host in hosts.iter().take(MAX_MX) {
+                        if addresses(dns, host).await?.into_iter().any(|candidate| within(ip, candidate, v4, v6)) {
+                            matched = true;
+                            break;
+                        }
+                    }
+                    matched
+                }
+                "exists" => {
+                    let target = argument_value.ok_or_else(|| Stop::Perm("exists needs a domain".to_string()))?;
+                    !addresses(dns, target).await?.is_empty()
+                }
+                "ptr" => false,
+                _ => return Err(Stop::Perm(format!("unknown mechanism '{}'", term))),
+            };
+            if matched {
+                return Ok(Some(qualifier_result(qualifier)));
+            }
+        }
+
+        match redirect {
+            Some(target) => {
+                *lookups += 1;
+                if *lookups > MAX_LOOKUPS {
+                    return Err(Stop::Perm("more than 10 DNS lookups".to_string()));
+                }
+                match evaluate(dns, ip, target.clone(), lookups).await? {
+                    Some(result) => Ok(Some(result)),
+                    None => Err(Stop::Perm(format!("redirect target {} has no SPF record", target))),
+                }
+            }
+            None => Ok(Some("neutral")),
+        }
+    })
+}
+
+/// Check `ip` against the SPF record of `domain`.
+pub async fn check(dns: &Dns, ip: IpAddr, domain: &str) -> SpfResult {
+    let mut lookups = 0;
+    let (result, reason) = match evaluate(dns, ip, domain.to_string(), &mut lookups).await {
+        Ok(Some(result)) => (result, None),
+        Ok(None) => ("none", Some(format!("{} publishes no SPF record", domain))),
+        Err(Stop::Temp(e)) => ("temperror", Some(e)),
+        Err(Stop::Perm(e)) => ("permerror", Some(e)),
+    };
+    SpfResult {
+        result,
+        domain: domain.to_string(),
+        reason,
+    }
+}
@@ -0,0 +1,54 @@
+/*!
+Error Types
+Shared error definitions for all security service modules
+*/
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SecurityError {
+    #[error("Configuration error: {0}")]
+    ConfigError(String),
+
+    #[error("Crypto initialization error: {0}")]
+    CryptoInitError(String),
+
+    #[error("Crypto error: {0}")]
+    CryptoError(String),
+
+    #[error("Storage error: {0}")]
+    StorageError(String),
+
+    #[error("Authentication error: {0}")]
+    AuthError(String),
+
+    #[error("Access denied: {0}")]
+    AccessDenied(String),
+
+    #[error("Audit error: {0}")]
+    AuditError(String),
+
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+
+    #[error("Alert delivery error: {0}")]
+    AlertError(String),
+
+    #[error("Delivery error: {0}")]
+    DeliveryError(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Plugin error: {0}")]
+    PluginError(String),
+}
+
+impl From<sqlx::Error> for SecurityError {
+    fn from(err: sqlx::Error) -> Self {
+        SecurityError::StorageError(err.to_string())
+    }
+}
@@ -0,0 +1,237 @@
+/*!
+Event Bus Module
+Transactional outbox and relay publishing domain events to Redis Streams
+*/
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, Transaction};
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::config::{Config, EventsConfig};
+use crate::dlq::DeliverySubsystem;
+use crate::errors::SecurityError;
+use crate::health::{CheckFuture, Criticality, HealthRegistry};
+use crate::storage::Storage;
+
+/// A state change other services may react to. Consumers should
+/// de-duplicate on `id`: the relay guarantees at-least-once delivery.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DomainEvent {
+    pub id: Uuid,
+    pub event_type: String,
+    pub aggregate_type: String,
+    pub aggregate_id: String,
+    pub tenant_id: Option<String>,
+    pub payload: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+impl DomainEvent {
+    pub fn new(
+        event_type: &str,
+        aggregate_type: &str,
+        aggregate_id: impl ToString,
+        tenant_id: Option<String>,
+        payload: serde_json::Value,
+        created_at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            event_type: event_type.to_string(),
+            aggregate_type: aggregate_type.to_string(),
+            aggregate_id: aggregate_id.to_string(),
+            tenant_id,
+            payload,
+            created_at,
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct OutboxRow {
+    #[sqlx(flatten)]
+    event: DomainEvent,
+    attempts: i32,
+}
+
+/// Write an event into the outbox inside the caller's transaction, so it is
+/// committed or rolled back together with the state change it describes.
+pub async fn enqueue(tx: &mut Transaction<'_, Postgres>, event: &DomainEvent) -> Result<(), SecurityError> {
+    sqlx::query(
+        "INSERT INTO event_outbox (id, event_type, aggregate_type, aggregate_id, tenant_id, payload, created_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(event.id)
+    .bind(&event.event_type)
+    .bind(&event.aggregate_type)
+    .bind(&event.aggregate_id)
+    .bind(&event.tenant_id)
+    .bind(&event.payload)
+    .bind(event.created_at)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct EventPublisher {
+    client: redis::Client,
+    stream: String,
+}
+
+impl EventPublisher {
+    pub fn new(config: &Config) -> Result<Self, SecurityError> {
+        let client = redis::Client::open(config.redis_url.as_str())
+            .map_err(|e| SecurityError::ConfigError(format!("Invalid Redis URL: {}", e)))?;
+
+        Ok(Self {
+            client,
+            stream: config.events.stream.clone(),
+        })
+    }
+
+    pub async fn publish(&self, event: &DomainEvent) -> Result<(), SecurityError> {
+        let mut conn = self.client.get_multiplexed_async_connection()
+            .await
+            .map_err(|e| SecurityError::DeliveryError(format!("Redis connection failed: {}", e)))?;
+
+        let body = serde_json::to_string(event)
+            .map_err(|e| SecurityError::DeliveryError(e.to_string()))?;
+
+        redis::cmd("XADD")
+            .arg(&self.stream)
+            .arg("*")
+            .arg("event_id")
+            .arg(event.id.to_string())
+            .arg("event_type")
+            .arg(&event.event_type)
+            .arg("body")
+            .arg(body)
+            .query_async::<_, String>(&mut conn)
+            .await
+            .map_err(|e| SecurityError::DeliveryError(format!("XADD failed: {}", e)))?;
+
+        Ok(())
+    }
+
+    pub fn stream(&self) -> &str {
+        &self.stream
+    }
+
+    pub async fn is_ready(&self) -> bool {
+        let Ok(mut conn) = self.client.get_multiplexed_async_connection().await else {
+            return false;
+        };
+        redis::cmd("PING").query_async::<_, String>(&mut conn).await.is_ok()
+    }
+}
+
+pub struct EventBus {
+    storage: Storage,
+    publisher: EventPublisher,
+    config: EventsConfig,
+}
+
+impl EventBus {
+    pub async fn new(config: &Config, storage: Storage, publisher: EventPublisher) -> Result<Self, SecurityError> {
+
+        info!("Event bus initialized on stream {}", publisher.stream());
+        Ok(Self {
+            storage,
+            publisher,
+            config: config.events.clone(),
+        })
+    }
+
+    pub async fn broker_ready(&self) -> bool {
+        self.publisher.is_ready().await
+    }
+
+    /// Publish one batch of pending outbox rows. Rows are locked for the
+    /// duration so concurrent relays (other replicas) never double-publish.
+    pub async fn relay_batch(&self, state: &crate::AppState) -> Result<usize, SecurityError> {
+        let mut tx = self.storage.begin().await?;
+
+        let pending = sqlx::query_as::<_, OutboxRow>(
+            "SELECT id, event_type, aggregate_type, aggregate_id, tenant_id, payload, created_at, attempts \
+             FROM event_outbox WHERE published_at IS NULL \
+             ORDER BY created_at LIMIT $1 FOR UPDATE SKIP LOCKED",
+        )
+        .bind(self.config.relay_batch_size)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut published = 0;
+        for OutboxRow { event, attempts } in pending {
+            match self.publisher.publish(&event).await {
+                Ok(()) => {
+                    sqlx::query("UPDATE event_outbox SET published_at = NOW(), attempts = attempts + 1 WHERE id = $1")
+                        .bind(event.id)
+                        .execute(&mut *tx)
+                        .await?;
+                    published += 1;
+                }
+                Err(e) if attempts + 1 >= self.config.max_attempts => {
+                    // Give up on the outbox row; the DLQ keeps it replayable
+                    let payload = serde_json::to_value(&event)
+                        .map_err(|e| SecurityError::DeliveryError(e.to_string()))?;
+                    state.dead_letters
+                        .push(DeliverySubsystem::EventBus, self.publisher.stream(), &payload, &e.to_string())
+                        .await?;
+                    sqlx::query(
+                        "UPDATE event_outbox SET published_at = NOW(), attempts = attempts + 1, last_error = $2 WHERE id = $1",
+                    )
+                    .bind(event.id)
+                    .bind(format!("dead-lettered: {}", e))
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                Err(e) => {
+                    warn!("Publishing event {} failed: {:?}", event.id, e);
+                    sqlx::query("UPDATE event_outbox SET attempts = attempts + 1, last_error = $2 WHERE id = $1")
+                        .bind(event.id)
+                        .bind(e.to_string())
+                        .execute(&mut *tx)
+                        .await?;
+                    // Keep ordering: stop at the first transient failure
+                    break;
+                }
+            }
+        }
+
+        tx.commit().await?;
+        Ok(published)
+    }
+}
+
+/// Background loop draining the outbox into the event stream.
+pub async fn run_relay(state: actix_web::web::Data<crate::AppState>) {
+    let mut interval = tokio::time::interval(Duration::from_millis(state.event_bus.config.relay_interval_ms));
+
+    loop {
+        interval.tick().await;
+        match state.event_bus.relay_batch(&state).await {
+            Ok(0) => {}
+            Ok(count) => info!("Outbox relay published {} events", count),
+            Err(e) => error!("Outbox relay failed: {:?}", e),
+        }
+    }
+}
+
+fn broker_reachable(state: &crate::AppState) -> CheckFuture<'_> {
+    Box::pin(async move {
+        if state.event_bus.broker_ready().await {
+            Ok(())
+        } else {
+            Err("Redis is unreachable; events wait in the outbox".to_string())
+        }
+    })
+}
+
+pub fn register_health_checks(registry: &mut HealthRegistry) {
+    registry.register("redis", Criticality::NonCritical, broker_reachable);
+}
@@ -0,0 +1,398 @@
+/*!
+Experiments Module
+Deterministic variant assignment and exposure logging for A/B tests
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::FromRow;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+use tracing::{error, info, warn};
+
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, Principal};
+use crate::changes::{self, NewChange};
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+
+const SELECT_COLUMNS: &str = "key, description, status, variants, salt, version, updated_at, updated_by";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExperimentStatus {
+    Draft,
+    Running,
+    Stopped,
+}
+
+impl ExperimentStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExperimentStatus::Draft => "draft",
+            ExperimentStatus::Running => "running",
+            ExperimentStatus::Stopped => "stopped",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Variant {
+    pub name: String,
+    pub weight: u32,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Experiment {
+    pub key: String,
+    pub description: Option<String>,
+    pub status: String,
+    pub variants: sqlx::types::Json<Vec<Variant>>,
+    /// Changing the salt reshuffles every subject into fresh buckets.
+    pub salt: String,
+    pub version: i64,
+    pub updated_at: DateTime<Utc>,
+    pub updated_by: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExperimentRequest {
+    pub description: Option<String>,
+    pub status: ExperimentStatus,
+    pub variants: Vec<Variant>,
+    pub salt: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssignRequest {
+    pub experiments: Vec<String>,
+}
+
+/// Fixed set, so outcome metrics keep a bounded label cardinality.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Completed,
+    Abandoned,
+    FraudBlocked,
+}
+
+impl Outcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Completed => "completed",
+            Outcome::Abandoned => "abandoned",
+            Outcome::FraudBlocked => "fraud_blocked",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OutcomeRequest {
+    pub outcome: Outcome,
+}
+
+impl Experiment {
+    fn editable(&self) -> ExperimentRequest {
+        ExperimentRequest {
+            description: self.description.clone(),
+            status: match self.status.as_str() {
+                "running" => ExperimentStatus::Running,
+                "stopped" => ExperimentStatus::Stopped,
+                _ => ExperimentStatus::Draft,
+            },
+            variants: self.variants.0.clone(),
+            salt: Some(self.salt.clone()),
+        }
+    }
+
+    /// The subject's variant, stable for as long as the variants and salt are unchanged.
+    pub fn variant_for(&self, subject: &str) -> Option<&str> {
+        if self.status != ExperimentStatus::Running.as_str() {
+            return None;
+        }
+
+        let total: u64 = self.variants.iter().map(|v| v.weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let digest = Sha256::digest(format!("{}:{}:{}", self.key, self.salt, subject).as_bytes());
+        let mut point = u64::from_be_bytes(digest[..8].try_into().expect("8 bytes")) % total;
+        for variant in self.variants.iter() {
+            if point < variant.weight as u64 {
+                return Some(&variant.name);
+            }
+            point -= variant.weight as u64;
+        }
+        None
+    }
+}
+
+fn validate(key: &str, request: &ExperimentRequest) -> Result<(), SecurityError> {
+    let valid_key = !key.is_empty()
+        && key.len() <= 64
+        && key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '.');
+    if !valid_key {
+        return Err(SecurityError::ValidationError(
+            "Experiment keys are 1-64 characters of a-z, 0-9, '_' and '.'".to_string(),
+        ));
+    }
+    if request.variants.len() < 2 {
+        return Err(SecurityError::ValidationError("An experiment needs at least two variants".to_string()));
+    }
+    if request.variants.iter().all(|v| v.weight == 0) {
+        return Err(SecurityError::ValidationError("At least one variant needs a weight".to_string()));
+    }
+    let mut names: Vec<&str> = request.variants.iter().map(|v| v.name.as_str()).collect();
+    names.sort();
+    names.dedup();
+    if names.len() != request.variants.len() || names.iter().any(|n| n.is_empty()) {
+        return Err(SecurityError::ValidationError("Variant names must be unique and non-empty".to_string()));
+    }
+    Ok(())
+}
+
+pub struct ExperimentService {
+    storage: Storage,
+    cache: RwLock<HashMap<String, Experiment>>,
+}
+
+impl ExperimentService {
+    pub async fn new(storage: Storage) -> Result<Self, SecurityError> {
+        let service = Self {
+            storage,
+            cache: RwLock::new(HashMap::new()),
+        };
+
+        info!("Experiment service initialized successfully");
+        Ok(service)
+    }
+
+    pub async fn refresh(&self) -> Result<(), SecurityError> {
+        let experiments = sqlx::query_as::<_, Experiment>(&format!("SELECT {} FROM experiments", SELECT_COLUMNS))
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        let experiments = experiments.into_iter().map(|e| (e.key.clone(), e)).collect();
+        *self.cache.write().unwrap() = experiments;
+        Ok(())
+    }
+
+    /// Variant for a subject, or `None` when the experiment is unknown or not running.
+    pub fn assign(&self, key: &str, subject: &str) -> Option<String> {
+        self.cache.read().unwrap()
+            .get(key)
+            .and_then(|experiment| experiment.variant_for(subject))
+            .map(str::to_string)
+    }
+
+    pub async fn list(&self) -> Result<Vec<Experiment>, SecurityError> {
+        let experiments = sqlx::query_as::<_, Experiment>(&format!(
+            "SELECT {} FROM experiments ORDER BY key",
+            SELECT_COLUMNS
+        ))
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        Ok(experiments)
+    }
+
+    pub async fn put(
+        &self,
+        actor: &Principal,
+        key: &str,
+        request: ExperimentRequest,
+    ) -> Result<Experiment, SecurityError> {
+        validate(key, &request)?;
+
+        let mut tx = self.storage.begin().await?;
+        let current = sqlx::query_as::<_, Experiment>(&format!(
+            "SELECT {} FROM experiments WHERE key = $1 FOR UPDATE",
+            SELECT_COLUMNS
+        ))
+        .bind(key)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let salt = request.salt.clone()
+            .or_else(|| current.as_ref().map(|e| e.salt.clone()))
+            .unwrap_or_else(|| key.to_string());
+
+        let experiment = sqlx::query_as::<_, Experiment>(&format!(
+            "INSERT INTO experiments (key, description, status, variants, salt, updated_by) \
+             VALUES ($1, $2, $3, $4, $5, $6) \
+             ON CONFLICT (key) DO UPDATE SET description = $2, status = $3, variants = $4, salt = $5, \
+             updated_by = $6, updated_at = NOW(), version = experiments.version + 1 \
+             RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(key)
+        .bind(&request.description)
+        .bind(request.status.as_str())
+        .bind(sqlx::types::Json(&request.variants))
+        .bind(&salt)
+        .bind(&actor.subject)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        changes::record(&mut tx, NewChange {
+            resource_type: "experiment",
+            resource_id: key.to_string(),
+            version: Some(experiment.version),
+            action: if current.is_some() { "update" } else { "create" },
+            author: &actor.subject,
+            tenant_id: None,
+            before: current.as_ref().and_then(|e| serde_json::to_value(e.editable()).ok()),
+            after: serde_json::to_value(experiment.editable()).ok(),
+        }).await?;
+        tx.commit().await?;
+
+        self.refresh().await?;
+        Ok(experiment)
+    }
+}
+
+/// Background loop picking up experiment changes made through other instances.
+pub async fn run_refresh(state: web::Data<crate::AppState>) {
+    let interval_secs = state.config.experiments.refresh_interval_secs;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+
+    loop {
+        interval.tick().await;
+        match state.experiments.refresh().await {
+            Ok(()) => state.startup.recovered("experiments"),
+            Err(e) => error!("Experiment refresh failed: {:?}", e),
+        }
+    }
+}
+
+/// Log that a subject was shown a variant, to both metrics and the audit trail.
+pub async fn record_exposure(state: &crate::AppState, principal: &Principal, key: &str, variant: &str) {
+    state.metrics_service.increment(
+        "cotai_experiment_exposures_total",
+        &[("experiment", key), ("variant", variant)],
+    );
+
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: principal.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: None,
+        action: "experiment.exposure".to_string(),
+        resource: format!("experiment:{}", key),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({ "variant": variant }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit exposure to {}: {:?}", key, e);
+    }
+}
+
+// HTTP handlers
+
+pub async fn assign_handler(
+    req: HttpRequest,
+    request: web::Json<AssignRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let mut assignments = BTreeMap::new();
+    for key in &request.experiments {
+        let variant = state.experiments.assign(key, &principal.subject);
+        if let Some(variant) = &variant {
+            record_exposure(&state, &principal, key, variant).await;
+        }
+        assignments.insert(key.clone(), variant);
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "assignments": assignments
+    })))
+}
+
+pub async fn outcome_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    request: web::Json<OutcomeRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    // Attribute the outcome to the variant the subject was assigned, never one the client claims
+    let Some(variant) = state.experiments.assign(&path, &principal.subject) else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Experiment is not running"
+        })));
+    };
+
+    state.metrics_service.increment(
+        "cotai_experiment_outcomes_total",
+        &[("experiment", &path), ("variant", &variant), ("outcome", request.outcome.as_str())],
+    );
+    Ok(HttpResponse::Accepted().finish())
+}
+
+pub async fn list_experiments_handler(
+    req: HttpRequest,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.experiments.list().await {
+        Ok(experiments) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "experiments": experiments
+        }))),
+        Err(e) => {
+            error!("Experiment listing failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Experiment listing failed"
+            })))
+        }
+    }
+}
+
+pub async fn put_experiment_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    request: web::Json<ExperimentRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.experiments.put(&principal, &path, request.into_inner()).await {
+        Ok(experiment) => Ok(HttpResponse::Ok().json(experiment)),
+        Err(SecurityError::ValidationError(msg)) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        }))),
+        Err(e) => {
+            error!("Experiment update failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Experiment update failed"
+            })))
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/experiments/assign", web::post().to(assign_handler))
+        .route("/experiments/{key}/outcome", web::post().to(outcome_handler))
+        .service(
+            web::scope("/admin/experiments")
+                .route("", web::get().to(list_experiments_handler))
+                .route("/{key}", web::put().to(put_experiment_handler))
+        );
+}
@@ -0,0 +1,124 @@
+/*!
+Certificate Validity
+Reads the validity period and fingerprint out of PEM certificates
+
+Only enough DER is walked to reach `tbsCertificate.validity`; nothing is
+verified. Used to track when certificates lapse, never to trust them.
+*/
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use ring::digest::{digest, SHA256};
+use serde::Serialize;
+
+use crate::errors::SecurityError;
+
+const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+const END: &str = "-----END CERTIFICATE-----";
+
+const SEQUENCE: u8 = 0x30;
+const VERSION: u8 = 0xa0;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CertificateInfo {
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+    /// SHA-256 of the DER encoding, hex.
+    pub fingerprint: String,
+}
+
+/// Every certificate in a PEM bundle, in order; the leaf usually comes first.
+pub fn parse_pem(pem: &str) -> Result<Vec<CertificateInfo>, SecurityError> {
+    let mut certificates = Vec::new();
+    let mut rest = pem;
+    while let Some(start) = rest.find(BEGIN) {
+        let body = &rest[start + BEGIN.len()..];
+        let end = body.find(END)
+            .ok_or_else(|| invalid("unterminated PEM block"))?;
+        let encoded: String = body[..end].chars().filter(|c| !c.is_whitespace()).collect();
+        let der = base64::decode(encoded).map_err(|_| invalid("PEM block is not base64"))?;
+        certificates.push(parse_der(&der)?);
+        rest = &body[end + END.len()..];
+    }
+
+    if certificates.is_empty() {
+        return Err(invalid("no certificate found"));
+    }
+    Ok(certificates)
+}
+
+pub fn parse_der(der: &[u8]) -> Result<CertificateInfo, SecurityError> {
+    let (certificate, _) = expect(der, SEQUENCE)?;
+    let (mut tbs, _) = expect(certificate, SEQUENCE)?;
+
+    if tbs.first() == Some(&VERSION) {
+        tbs = read(tbs)?.2;
+    }
+    // serialNumber, signature, issuer
+    for _ in 0..3 {
+        tbs = read(tbs)?.2;
+    }
+    let (validity, _) = expect(tbs, SEQUENCE)?;
+    let (not_before, rest) = time(validity)?;
+    let (not_after, _) = time(rest)?;
+
+    Ok(CertificateInfo {
+        not_before,
+        not_after,
+        fingerprint: hex::encode(digest(&SHA256, der).as_ref()),
+    })
+}
+
+fn invalid(detail: &str) -> SecurityError {
+    SecurityError::ValidationError(format!("Invalid certificate: {}", detail))
+}
+
+/// One TLV: its tag, contents and whatever follows it.
+fn read(input: &[u8]) -> Result<(u8, &[u8], &[u8]), SecurityError> {
+    let truncated = || invalid("truncated DER");
+    let (&tag, input) = input.split_first().ok_or_else(truncated)?;
+    let (&first, mut input) = input.split_first().ok_or_else(truncated)?;
+
+    let len = if first < 0x80 {
+        first as usize
+    } else {
+        let octets = (first & 0x7f) as usize;
+        if octets == 0 || octets > 4 || input.len() < octets {
+            return Err(invalid("unsupported DER length"));
+        }
+        let len = input[..octets].iter().fold(0usize, |len, b| (len << 8) | *b as usize);
+        input = &input[octets..];
+        len
+    };
+
+    if input.len() < len {
+        return Err(truncated());
+    }
+    Ok((tag, &input[..len], &input[len..]))
+}
+
+fn expect(input: &[u8], tag: u8) -> Result<(&[u8], &[u8]), SecurityError> {
+    match read(input)? {
+        (found, contents, rest) if found == tag => Ok((contents, rest)),
+        _ => Err(invalid("unexpected DER structure")),
+    }
+}
+
+fn time(input: &[u8]) -> Result<(DateTime<Utc>, &[u8]), SecurityError> {
+    let (tag, contents, rest) = read(input)?;
+    let text = std::str::from_utf8(contents).map_err(|_| invalid("validity is not ASCII"))?;
+    // RFC 5280: two-digit years below 50 are 20xx
+    let full = match tag {
+        UTC_TIME if text.len() == 13 => {
+            let century = if text[..2].parse::<u8>().map_err(|_| invalid("bad UTCTime"))? < 50 { "20" } else { "19" };
+            format!("{}{}", century, text)
+        }
+        GENERALIZED_TIME if text.len() == 15 => text.to_string(),
+        _ => return Err(invalid("unsupported validity time")),
+    };
+
+    let at = NaiveDateTime::parse_from_str(&full, "%Y%m%d%H%M%SZ")
+        .map_err(|_| invalid("bad validity time"))?;
+    Ok((at.and_utc(), rest))
+}
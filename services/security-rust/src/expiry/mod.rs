@@ -0,0 +1,595 @@
+/*!
+Expiry Module
+Tracks when managed artifacts lapse and alerts before they do
+
+Artifacts come from named sources, registered the way health checks are:
+modules that own expiring material expose `register_expiry_sources`, and an
+embedding host can add its own through the builder. Built in:
+
+- `registered`: artifacts an admin records under `/admin/expiry/artifacts`,
+  for material kept elsewhere such as ingress TLS certificates, SAML IdP
+  certificates or partner webhook secrets. A PEM certificate can be given
+  instead of a date.
+- `certificate_files`: PEM files listed in `EXPIRY_CERTIFICATE_FILES`,
+  re-read on every scan so renewed certificates are picked up.
+
+Every `EXPIRY_INTERVAL_SECS` the sources are collected and an alert is sent
+once per artifact for the tightest of `EXPIRY_LEAD_DAYS` it has crossed,
+and once more when it expires. Sent alerts are recorded, so replicas do not
+repeat them, and a renewal, which changes the expiry, starts over.
+`/admin/expiry/report` lists everything with its status, answering `202`
+with a job to follow when sources are slow (see `jobs`).
+*/
+
+pub mod certificate;
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use futures::future::{join_all, BoxFuture};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::alerting::{Alert, Severity};
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, Principal};
+use crate::config::{Config, ExpiryConfig};
+use crate::errors::SecurityError;
+use crate::jobs::{self, Output};
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::Storage;
+use crate::AppState;
+
+/// Kinds of artifact tracked.
+pub const KINDS: &[&str] = &[
+    "tls_certificate",
+    "api_key",
+    "jwks_key",
+    "saml_idp_certificate",
+    "webhook_secret",
+];
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "expires_at", column: "expires_at", kind: KeyKind::Timestamp },
+    SortField { name: "created_at", column: "created_at", kind: KeyKind::Timestamp },
+];
+
+const SELECT_COLUMNS: &str = "id, kind, name, tenant_id, expires_at, fingerprint, notes, registered_by, \
+    created_at, updated_at";
+
+/// Sent alerts older than this past their expiry are dropped.
+const ALERT_RETENTION_DAYS: i64 = 90;
+
+/// Something that stops working at `expires_at` unless renewed.
+#[derive(Debug, Clone, Serialize)]
+pub struct Expiring {
+    pub source: &'static str,
+    pub kind: String,
+    pub name: String,
+    pub tenant_id: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+}
+
+pub type ExpiryFuture<'a> = BoxFuture<'a, Result<Vec<Expiring>, SecurityError>>;
+pub type ExpirySourceFn = for<'a> fn(&'a AppState) -> ExpiryFuture<'a>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceFailure {
+    pub source: &'static str,
+    pub error: String,
+}
+
+#[derive(Default)]
+pub struct ExpiryRegistry {
+    sources: Vec<(&'static str, ExpirySourceFn)>,
+}
+
+impl ExpiryRegistry {
+    /// Add a source. Registering a name again replaces the earlier source.
+    pub fn register(&mut self, name: &'static str, source: ExpirySourceFn) -> &mut Self {
+        self.sources.retain(|(n, _)| *n != name);
+        self.sources.push((name, source));
+        self
+    }
+
+    /// Add `other`'s sources, which win on name clashes.
+    pub fn extend(&mut self, other: ExpiryRegistry) {
+        for (name, source) in other.sources {
+            self.register(name, source);
+        }
+    }
+
+    /// Collect every source concurrently. A failing source is reported
+    /// rather than hiding what the others found.
+    pub async fn collect(&self, state: &AppState) -> (Vec<Expiring>, Vec<SourceFailure>) {
+        let results = join_all(self.sources.iter().map(|(name, source)| async move {
+            (*name, source(state).await)
+        }))
+        .await;
+
+        let mut artifacts = Vec::new();
+        let mut failures = Vec::new();
+        for (source, result) in results {
+            match result {
+                Ok(found) => artifacts.extend(found),
+                Err(e) => failures.push(SourceFailure { source, error: e.to_string() }),
+            }
+        }
+        artifacts.sort_by_key(|a| a.expires_at);
+        (artifacts, failures)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ManagedArtifact {
+    pub id: Uuid,
+    pub kind: String,
+    pub name: String,
+    pub tenant_id: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub fingerprint: Option<String>,
+    pub notes: Option<String>,
+    pub registered_by: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArtifactRequest {
+    pub tenant_id: Option<String>,
+    /// Required unless `certificate_pem` is given.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// The certificate or chain; the earliest expiry in it is tracked.
+    pub certificate_pem: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ArtifactFilter {
+    pub kind: Option<String>,
+    pub tenant_id: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReportQuery {
+    /// Only artifacts expiring within this many days; expired ones are
+    /// always included.
+    pub within_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportEntry {
+    #[serde(flatten)]
+    pub artifact: Expiring,
+    /// `expired`, `expiring` (within the longest lead time) or `ok`.
+    pub status: &'static str,
+    pub days_remaining: i64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReportSummary {
+    pub expired: usize,
+    pub expiring: usize,
+    pub ok: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExpiryReport {
+    pub generated_at: DateTime<Utc>,
+    pub lead_days: Vec<u32>,
+    pub summary: ReportSummary,
+    pub artifacts: Vec<ReportEntry>,
+    pub failed_sources: Vec<SourceFailure>,
+}
+
+pub struct ExpiryService {
+    storage: Storage,
+    config: ExpiryConfig,
+}
+
+impl ExpiryService {
+    pub async fn new(config: &Config, storage: Storage) -> Result<Self, SecurityError> {
+
+        info!("Expiry service initialized successfully");
+        Ok(Self {
+            storage,
+            config: config.expiry.clone(),
+        })
+    }
+
+    pub async fn put_artifact(
+        &self,
+        actor: &Principal,
+        kind: &str,
+        name: &str,
+        request: ArtifactRequest,
+    ) -> Result<ManagedArtifact, SecurityError> {
+        if !KINDS.contains(&kind) {
+            return Err(SecurityError::ValidationError(format!("kind must be one of {}", KINDS.join(", "))));
+        }
+        if name.trim().is_empty() {
+            return Err(SecurityError::ValidationError("name must not be empty".to_string()));
+        }
+
+        let (expires_at, fingerprint) = match (request.expires_at, &request.certificate_pem) {
+            (Some(expires_at), None) => (expires_at, None),
+            (None, Some(pem)) => {
+                let chain = certificate::parse_pem(pem)?;
+                let expires_at = chain.iter().map(|c| c.not_after).min().unwrap_or_default();
+                (expires_at, Some(chain[0].fingerprint.clone()))
+            }
+            _ => {
+                return Err(SecurityError::ValidationError(
+                    "give exactly one of expires_at and certificate_pem".to_string(),
+                ))
+            }
+        };
+
+        let artifact = sqlx::query_as::<_, ManagedArtifact>(&format!(
+            "INSERT INTO managed_artifacts (id, kind, name, tenant_id, expires_at, fingerprint, notes, registered_by) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8) \
+             ON CONFLICT (kind, name) DO UPDATE SET tenant_id = $4, expires_at = $5, fingerprint = $6, \
+             notes = $7, registered_by = $8, updated_at = NOW() \
+             RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(kind)
+        .bind(name)
+        .bind(&request.tenant_id)
+        .bind(expires_at)
+        .bind(&fingerprint)
+        .bind(&request.notes)
+        .bind(&actor.subject)
+        .fetch_one(self.storage.pool())
+        .await?;
+
+        Ok(artifact)
+    }
+
+    pub async fn delete_artifact(&self, kind: &str, name: &str) -> Result<(), SecurityError> {
+        let deleted = sqlx::query("DELETE FROM managed_artifacts WHERE kind = $1 AND name = $2")
+            .bind(kind)
+            .bind(name)
+            .execute(self.storage.pool())
+            .await?
+            .rows_affected();
+        if deleted == 0 {
+            return Err(SecurityError::NotFound("Artifact not found".to_string()));
+        }
+        Ok(())
+    }
+
+    pub async fn list_artifacts(&self, filter: &ArtifactFilter, page: &PageRequest) -> Result<Page<ManagedArtifact>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM managed_artifacts WHERE 1 = 1",
+            SELECT_COLUMNS
+        ));
+        if let Some(kind) = &filter.kind {
+            builder.push(" AND kind = ").push_bind(kind.clone());
+        }
+        if let Some(tenant_id) = &filter.tenant_id {
+            builder.push(" AND tenant_id = ").push_bind(tenant_id.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let artifacts = builder
+            .build_query_as::<ManagedArtifact>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(artifacts, |artifact, field| match field {
+            "created_at" => (SortKey::Timestamp(artifact.created_at), artifact.id),
+            _ => (SortKey::Timestamp(artifact.expires_at), artifact.id),
+        }))
+    }
+
+    async fn registered(&self) -> Result<Vec<Expiring>, SecurityError> {
+        let artifacts = sqlx::query_as::<_, ManagedArtifact>(&format!(
+            "SELECT {} FROM managed_artifacts ORDER BY expires_at",
+            SELECT_COLUMNS
+        ))
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        Ok(artifacts
+            .into_iter()
+            .map(|a| Expiring {
+                source: "registered",
+                kind: a.kind,
+                name: a.name,
+                tenant_id: a.tenant_id,
+                expires_at: a.expires_at,
+                fingerprint: a.fingerprint,
+            })
+            .collect())
+    }
+
+    async fn certificate_files(&self) -> Result<Vec<Expiring>, SecurityError> {
+        let mut found = Vec::new();
+        for path in &self.config.certificate_files {
+            let pem = tokio::fs::read_to_string(path)
+                .await
+                .map_err(|e| SecurityError::ValidationError(format!("{}: cannot be read: {}", path, e)))?;
+            let chain = certificate::parse_pem(&pem)
+                .map_err(|e| SecurityError::ValidationError(format!("{}: {}", path, e)))?;
+
+            for (i, certificate) in chain.into_iter().enumerate() {
+                found.push(Expiring {
+                    source: "certificate_files",
+                    kind: "tls_certificate".to_string(),
+                    name: if i == 0 { path.clone() } else { format!("{}#{}", path, i + 1) },
+                    tenant_id: None,
+                    expires_at: certificate.not_after,
+                    fingerprint: Some(certificate.fingerprint),
+                });
+            }
+        }
+        Ok(found)
+    }
+
+    /// The tightest lead time `artifact` has crossed at `now`; 0 once expired.
+    fn crossed_lead(&self, artifact: &Expiring, now: DateTime<Utc>) -> Option<u32> {
+        let remaining = artifact.expires_at - now;
+        if remaining <= Duration::zero() {
+            return Some(0);
+        }
+        self.config
+            .lead_days
+            .iter()
+            .copied()
+            .filter(|days| remaining <= Duration::days(*days as i64))
+            .min()
+    }
+
+    /// Alert on artifacts that crossed a lead time since the last scan.
+    /// Returns how many alerts were sent.
+    pub async fn scan(&self, state: &AppState) -> Result<usize, SecurityError> {
+        let now = state.clock.now();
+        let (artifacts, failures) = state.expiry_sources.collect(state).await;
+        for failure in &failures {
+            warn!("Expiry source {} failed: {}", failure.source, failure.error);
+        }
+
+        let mut sent = 0;
+        for artifact in artifacts {
+            let Some(lead_days) = self.crossed_lead(&artifact, now) else {
+                continue;
+            };
+            let claimed = sqlx::query(
+                "INSERT INTO expiry_alerts (kind, name, expires_at, lead_days) VALUES ($1, $2, $3, $4) \
+                 ON CONFLICT DO NOTHING",
+            )
+            .bind(&artifact.kind)
+            .bind(&artifact.name)
+            .bind(artifact.expires_at)
+            .bind(lead_days as i32)
+            .execute(self.storage.pool())
+            .await?
+            .rows_affected();
+            if claimed == 0 {
+                continue;
+            }
+
+            let (severity, title) = match lead_days {
+                0 => (Severity::Critical, format!("{} {} has expired", artifact.kind, artifact.name)),
+                1 => (Severity::High, format!("{} {} expires within a day", artifact.kind, artifact.name)),
+                days if days <= 7 => (Severity::Medium, format!("{} {} expires within {} days", artifact.kind, artifact.name, days)),
+                days => (Severity::Low, format!("{} {} expires within {} days", artifact.kind, artifact.name, days)),
+            };
+            state.alerting_service
+                .send(&Alert::new("expiry", severity, title, serde_json::to_value(&artifact).unwrap_or_default(), state.clock.now()), &[])
+                .await;
+            sent += 1;
+        }
+
+        sqlx::query("DELETE FROM expiry_alerts WHERE expires_at < $1")
+            .bind(now - Duration::days(ALERT_RETENTION_DAYS))
+            .execute(self.storage.pool())
+            .await?;
+        Ok(sent)
+    }
+
+    pub async fn report(&self, state: &AppState, query: &ReportQuery) -> ExpiryReport {
+        let now = state.clock.now();
+        let (artifacts, failed_sources) = state.expiry_sources.collect(state).await;
+        let horizon = self.config.lead_days.iter().copied().max().unwrap_or_default();
+
+        let mut summary = ReportSummary::default();
+        let entries = artifacts
+            .into_iter()
+            .filter_map(|artifact| {
+                let remaining = artifact.expires_at - now;
+                if query.within_days.is_some_and(|days| remaining > Duration::days(days)) {
+                    return None;
+                }
+                let status = if remaining <= Duration::zero() {
+                    summary.expired += 1;
+                    "expired"
+                } else if remaining <= Duration::days(horizon as i64) {
+                    summary.expiring += 1;
+                    "expiring"
+                } else {
+                    summary.ok += 1;
+                    "ok"
+                };
+                Some(ReportEntry { artifact, status, days_remaining: remaining.num_days() })
+            })
+            .collect();
+
+        ExpiryReport {
+            generated_at: now,
+            lead_days: self.config.lead_days.clone(),
+            summary,
+            artifacts: entries,
+            failed_sources,
+        }
+    }
+}
+
+fn registered(state: &AppState) -> ExpiryFuture<'_> {
+    Box::pin(state.expiry.registered())
+}
+
+fn certificate_files(state: &AppState) -> ExpiryFuture<'_> {
+    Box::pin(state.expiry.certificate_files())
+}
+
+pub fn register_expiry_sources(registry: &mut ExpiryRegistry) {
+    registry.register("registered", registered);
+    registry.register("certificate_files", certificate_files);
+}
+
+/// Background loop alerting on artifacts approaching expiry.
+pub async fn run_scan(state: web::Data<AppState>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(state.config.expiry.interval_secs));
+
+    loop {
+        interval.tick().await;
+        match state.expiry.scan(&state).await {
+            Ok(0) => {}
+            Ok(sent) => info!("Sent {} expiry alerts", sent),
+            Err(e) => error!("Expiry scan failed: {:?}", e),
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Expiry operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Expiry operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit_artifact_change(state: &AppState, actor: &Principal, action: &str, kind: &str, name: &str, detail: serde_json::Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: actor.tenant_id.clone(),
+        actor: actor.subject.clone(),
+        actor_ip: None,
+        action: action.to_string(),
+        resource: format!("artifact:{}/{}", kind, name),
+        outcome: "success".to_string(),
+        payload: detail,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit change to artifact {}/{}: {:?}", kind, name, e);
+    }
+}
+
+pub async fn report_handler(
+    req: HttpRequest,
+    query: web::Query<ReportQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    // Sources are asked live, and some answer slowly
+    let query = query.into_inner();
+    let work_state = state.clone();
+    Ok(jobs::respond(&state, &req, &principal, "expiry.report", async move {
+        Ok(Output::json(work_state.expiry.report(&work_state, &query).await))
+    })
+    .await)
+}
+
+pub async fn list_artifacts_handler(
+    req: HttpRequest,
+    filter: web::Query<ArtifactFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Asc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.expiry.list_artifacts(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "artifacts": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn put_artifact_handler(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    request: web::Json<ArtifactRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let (kind, name) = path.into_inner();
+    match state.expiry.put_artifact(&principal, &kind, &name, request.into_inner()).await {
+        Ok(artifact) => {
+            let detail = serde_json::json!({
+                "expires_at": artifact.expires_at,
+                "fingerprint": artifact.fingerprint
+            });
+            audit_artifact_change(&state, &principal, "expiry.artifact.put", &kind, &name, detail).await;
+            Ok(HttpResponse::Ok().json(artifact))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn delete_artifact_handler(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let (kind, name) = path.into_inner();
+    match state.expiry.delete_artifact(&kind, &name).await {
+        Ok(()) => {
+            audit_artifact_change(&state, &principal, "expiry.artifact.delete", &kind, &name, serde_json::json!({})).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/expiry")
+            .route("/report", web::get().to(report_handler))
+            .route("/artifacts", web::get().to(list_artifacts_handler))
+            .route("/artifacts/{kind}/{name}", web::put().to(put_artifact_handler))
+            .route("/artifacts/{kind}/{name}", web::delete().to(delete_artifact_handler))
+    );
+}
@@ -0,0 +1,1575 @@
+/*!
+Expression Language
+Sandboxed, typed conditions shared by authorization rules, correlation rules and PII masking
+
+Conditions are written in a subset of CEL:
+
+```text
+resource.tenant_id == subject.tenant_id && "buyer" in subject.roles
+event.action.startsWith("auth.") && event.payload.attempts >= 5
+finding.rule in ["cpf", "cnpj"] && !(context.channel == "internal")
+subject.scopes.exists(s, s.glob("tender:*")) ? has(resource.owner) : false
+```
+
+- literals: `null`, `true`, `false`, integers, doubles, `"strings"` or
+  `'strings'`, lists `[a, b]`
+- operators, loosest first: `?:`, `||`, `&&`, `== != < <= > >= in`,
+  `+ -`, `* / %`, unary `! -`; `.field` and `[index]` select
+- functions: `size(x)`, `int(x)`, `double(x)`, `string(x)`, `lower(s)`,
+  `upper(s)`, `has(x.field)` (present and not null); methods
+  `s.startsWith(t)`, `s.endsWith(t)`, `s.contains(t)`, `s.glob(pattern)`
+  (`*` matches any run), `x.size()`; macros `list.all(v, cond)`,
+  `list.exists(v, cond)` and `list.exists_one(v, cond)`
+
+Each consumer declares an `Environment`: the variables it binds and their
+types. Expressions are checked against it when stored, so a misspelt field
+on a closed object or a string compared with a number is refused up front
+rather than silently never matching. Objects marked open, like authz
+subjects and resources, also take attributes not declared.
+
+Nothing reaches outside the values bound: there are no side effects, no
+I/O and no user-defined functions. Expressions are limited in length,
+nesting and size when compiled, and evaluation stops after `MAX_STEPS`
+steps or once it builds strings or lists over their limits. `&&` and `||`
+absorb errors the way CEL does: `false && error` is `false`.
+
+`POST /expressions/validate` checks an expression against an environment,
+`POST /expressions/test` evaluates one against sample bindings, and
+`GET /expressions/environments` lists the environments and their bindings.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value as Json};
+use std::borrow::Cow;
+
+use crate::auth::auth_error_response;
+use crate::errors::SecurityError;
+use crate::AppState;
+
+pub const MAX_EXPRESSION_LENGTH: usize = 4096;
+const MAX_DEPTH: usize = 32;
+const MAX_NODES: usize = 512;
+pub const MAX_STEPS: u64 = 10_000;
+const MAX_STRING_BYTES: usize = 65_536;
+const MAX_LIST_ITEMS: usize = 10_000;
+
+/// Environments known to the validate and test endpoints.
+pub static ENVIRONMENTS: &[&Environment] = &[
+    &crate::authz::CONDITIONS,
+    &crate::detection::correlation::EVENTS,
+    &crate::hooks::HOOKS,
+    &crate::validation::MASKING,
+];
+
+/// Static type of a binding or expression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    Bool,
+    Int,
+    Double,
+    String,
+    Null,
+    /// Known only at evaluation.
+    Dyn,
+    List(&'static Type),
+    /// String keys to values of one type.
+    Map(&'static Type),
+    Object(&'static Object),
+}
+
+impl Type {
+    pub fn name(&self) -> String {
+        match self {
+            Type::Bool => "bool".to_string(),
+            Type::Int => "int".to_string(),
+            Type::Double => "double".to_string(),
+            Type::String => "string".to_string(),
+            Type::Null => "null".to_string(),
+            Type::Dyn => "dyn".to_string(),
+            Type::List(item) => format!("list({})", item.name()),
+            Type::Map(value) => format!("map({})", value.name()),
+            Type::Object(object) => object.name.to_string(),
+        }
+    }
+
+    fn numeric(&self) -> bool {
+        matches!(self, Type::Int | Type::Double | Type::Dyn)
+    }
+
+    fn describe(&self) -> Json {
+        match self {
+            Type::Object(object) => serde_json::json!({
+                "type": object.name,
+                "open": object.open,
+                "fields": describe_bindings(object.fields)
+            }),
+            other => Json::from(other.name()),
+        }
+    }
+}
+
+/// A record with known fields; `open` ones also take undeclared fields,
+/// typed `dyn`.
+#[derive(Debug, PartialEq)]
+pub struct Object {
+    pub name: &'static str,
+    pub fields: &'static [Binding],
+    pub open: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Binding {
+    pub name: &'static str,
+    pub ty: Type,
+    pub doc: &'static str,
+}
+
+/// The variables a kind of expression is evaluated with.
+#[derive(Debug)]
+pub struct Environment {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub bindings: &'static [Binding],
+}
+
+impl Environment {
+    fn binding(&self, name: &str) -> Option<&'static Binding> {
+        self.bindings.iter().find(|binding| binding.name == name)
+    }
+
+    /// Check sample bindings against the declared types; absent and null
+    /// values are accepted.
+    pub fn conforms(&self, bindings: &Map<String, Json>) -> Result<(), SecurityError> {
+        for (name, value) in bindings {
+            let binding = self.binding(name).ok_or_else(|| {
+                SecurityError::ValidationError(format!("'{}' is not a binding of {}", name, self.name))
+            })?;
+            conforms(binding.ty, value, name).map_err(SecurityError::ValidationError)?;
+        }
+        Ok(())
+    }
+}
+
+fn conforms(ty: Type, value: &Json, path: &str) -> Result<(), String> {
+    let ok = match (ty, value) {
+        (_, Json::Null) | (Type::Dyn, _) => true,
+        (Type::Bool, Json::Bool(_)) | (Type::String, Json::String(_)) => true,
+        (Type::Int, Json::Number(n)) => n.is_i64() || n.is_u64(),
+        (Type::Double, Json::Number(_)) => true,
+        (Type::List(item), Json::Array(items)) => {
+            for (i, value) in items.iter().enumerate() {
+                conforms(*item, value, &format!("{}[{}]", path, i))?;
+            }
+            true
+        }
+        (Type::Map(item), Json::Object(map)) => {
+            for (key, value) in map {
+                conforms(*item, value, &format!("{}.{}", path, key))?;
+            }
+            true
+        }
+        (Type::Object(object), Json::Object(map)) => {
+            for (key, value) in map {
+                match object.fields.iter().find(|field| field.name == key) {
+                    Some(field) => conforms(field.ty, value, &format!("{}.{}", path, key))?,
+                    None if object.open => {}
+                    None => return Err(format!("{} has no field '{}'", path, key)),
+                }
+            }
+            true
+        }
+        _ => false,
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(format!("{} must be {}", path, ty.name()))
+    }
+}
+
+fn describe_bindings(bindings: &[Binding]) -> Vec<Json> {
+    bindings
+        .iter()
+        .map(|binding| serde_json::json!({
+            "name": binding.name,
+            "type": binding.ty.describe(),
+            "doc": binding.doc
+        }))
+        .collect()
+}
+
+fn invalid(msg: impl Into<String>) -> SecurityError {
+    SecurityError::ValidationError(format!("Invalid expression: {}", msg.into()))
+}
+
+// Lexing
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Double(f64),
+    Str(String),
+    Ident(String),
+    Punct(&'static str),
+}
+
+const PUNCTUATION: &[&str] = &[
+    "&&", "||", "==", "!=", "<=", ">=", "(", ")", "[", "]", ",", ".", "?", ":", "!", "-", "+", "*", "/", "%", "<", ">",
+];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, SecurityError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ' ' | '\t' | '\n' | '\r' => i += 1,
+            '"' | '\'' => {
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some('\\') => {
+                            let escaped = match chars.get(i + 1) {
+                                Some('n') => '\n',
+                                Some('t') => '\t',
+                                Some('r') => '\r',
+                                Some(ch @ ('\\' | '"' | '\'')) => *ch,
+                                other => return Err(invalid(format!("bad escape \\{}", other.copied().unwrap_or(' ')))),
+                            };
+                            value.push(escaped);
+                            i += 2;
+                        }
+                        Some(ch) if *ch == c => {
+                            i += 1;
+                            break;
+                        }
+                        Some(ch) => {
+                            value.push(*ch);
+                            i += 1;
+                        }
+                        None => return Err(invalid("unterminated string literal")),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            c if c.is_ascii_digit() => {
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let fraction = chars.get(i) == Some(&'.') && chars.get(i + 1).is_some_and(char::is_ascii_digit);
+                if fraction {
+                    i += 1;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+                let text: String = chars[start..i].iter().collect();
+                if fraction {
+                    tokens.push(Token::Double(text.parse().map_err(|_| invalid(format!("bad number '{}'", text)))?));
+                } else {
+                    tokens.push(Token::Int(text.parse().map_err(|_| invalid(format!("integer '{}' out of range", text)))?));
+                }
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            }
+            _ => {
+                let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+                let punct = PUNCTUATION
+                    .iter()
+                    .find(|p| rest.starts_with(**p))
+                    .ok_or_else(|| invalid(format!("unexpected character '{}'", c)))?;
+                tokens.push(Token::Punct(punct));
+                i += punct.len();
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Parsing
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Double(f64),
+    Str(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    In,
+}
+
+impl BinOp {
+    fn symbol(&self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Rem => "%",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::In => "in",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Func {
+    Size,
+    Int,
+    Double,
+    String,
+    Lower,
+    Upper,
+    StartsWith,
+    EndsWith,
+    Contains,
+    Glob,
+}
+
+impl Func {
+    fn name(&self) -> &'static str {
+        match self {
+            Func::Size => "size",
+            Func::Int => "int",
+            Func::Double => "double",
+            Func::String => "string",
+            Func::Lower => "lower",
+            Func::Upper => "upper",
+            Func::StartsWith => "startsWith",
+            Func::EndsWith => "endsWith",
+            Func::Contains => "contains",
+            Func::Glob => "glob",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Quantifier {
+    All,
+    Exists,
+    ExistsOne,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Lit(Literal),
+    Ident(String),
+    Select(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    List(Vec<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// Functions, with methods' target as the first argument.
+    Call(Func, Vec<Expr>),
+    Has(Box<Expr>, String),
+    Comprehension { quantifier: Quantifier, range: Box<Expr>, var: String, body: Box<Expr> },
+}
+
+fn global(name: &str) -> Option<(Func, usize)> {
+    match name {
+        "size" => Some((Func::Size, 1)),
+        "int" => Some((Func::Int, 1)),
+        "double" => Some((Func::Double, 1)),
+        "string" => Some((Func::String, 1)),
+        "lower" => Some((Func::Lower, 1)),
+        "upper" => Some((Func::Upper, 1)),
+        _ => None,
+    }
+}
+
+/// Methods and the arguments they take besides their target.
+fn method(name: &str) -> Option<(Func, usize)> {
+    match name {
+        "size" => Some((Func::Size, 0)),
+        "startsWith" => Some((Func::StartsWith, 1)),
+        "endsWith" => Some((Func::EndsWith, 1)),
+        "contains" => Some((Func::Contains, 1)),
+        "glob" => Some((Func::Glob, 1)),
+        _ => None,
+    }
+}
+
+fn quantifier(name: &str) -> Option<Quantifier> {
+    match name {
+        "all" => Some(Quantifier::All),
+        "exists" => Some(Quantifier::Exists),
+        "exists_one" => Some(Quantifier::ExistsOne),
+        _ => None,
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Punct(p)) if *p == punct) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), SecurityError> {
+        if self.eat(punct) {
+            return Ok(());
+        }
+        Err(invalid(format!("expected '{}', found {}", punct, self.describe_next())))
+    }
+
+    fn describe_next(&self) -> String {
+        match self.peek() {
+            None => "end of input".to_string(),
+            Some(Token::Int(n)) => n.to_string(),
+            Some(Token::Double(n)) => n.to_string(),
+            Some(Token::Str(s)) => format!("\"{}\"", s),
+            Some(Token::Ident(id)) => format!("'{}'", id),
+            Some(Token::Punct(p)) => format!("'{}'", p),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, SecurityError> {
+        match self.next() {
+            Some(Token::Ident(id)) if !matches!(id.as_str(), "true" | "false" | "null" | "in") => Ok(id),
+            _ => {
+                self.pos -= 1;
+                Err(invalid(format!("expected a name, found {}", self.describe_next())))
+            }
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, SecurityError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(invalid("expression nested too deeply"));
+        }
+        let expr = self.conditional();
+        self.depth -= 1;
+        expr
+    }
+
+    fn conditional(&mut self) -> Result<Expr, SecurityError> {
+        let condition = self.or()?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        let then = self.expr()?;
+        self.expect(":")?;
+        let otherwise = self.expr()?;
+        Ok(Expr::Cond(Box::new(condition), Box::new(then), Box::new(otherwise)))
+    }
+
+    fn or(&mut self) -> Result<Expr, SecurityError> {
+        let mut left = self.and()?;
+        while self.eat("||") {
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, SecurityError> {
+        let mut left = self.relation()?;
+        while self.eat("&&") {
+            left = Expr::And(Box::new(left), Box::new(self.relation()?));
+        }
+        Ok(left)
+    }
+
+    fn relation(&mut self) -> Result<Expr, SecurityError> {
+        let mut left = self.sum()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Punct("==")) => BinOp::Eq,
+                Some(Token::Punct("!=")) => BinOp::Ne,
+                Some(Token::Punct("<")) => BinOp::Lt,
+                Some(Token::Punct("<=")) => BinOp::Le,
+                Some(Token::Punct(">")) => BinOp::Gt,
+                Some(Token::Punct(">=")) => BinOp::Ge,
+                Some(Token::Ident(id)) if id == "in" => BinOp::In,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.sum()?));
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, SecurityError> {
+        let mut left = self.product()?;
+        loop {
+            let op = if self.eat("+") {
+                BinOp::Add
+            } else if self.eat("-") {
+                BinOp::Sub
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.product()?));
+        }
+    }
+
+    fn product(&mut self) -> Result<Expr, SecurityError> {
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.eat("*") {
+                BinOp::Mul
+            } else if self.eat("/") {
+                BinOp::Div
+            } else if self.eat("%") {
+                BinOp::Rem
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, SecurityError> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.nested_unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(match self.nested_unary()? {
+                Expr::Lit(Literal::Int(n)) => Expr::Lit(Literal::Int(-n)),
+                Expr::Lit(Literal::Double(n)) => Expr::Lit(Literal::Double(-n)),
+                operand => Expr::Neg(Box::new(operand)),
+            });
+        }
+        self.member()
+    }
+
+    fn nested_unary(&mut self) -> Result<Expr, SecurityError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(invalid("expression nested too deeply"));
+        }
+        let operand = self.unary();
+        self.depth -= 1;
+        operand
+    }
+
+    fn arguments(&mut self) -> Result<Vec<Expr>, SecurityError> {
+        let mut args = Vec::new();
+        if self.eat(")") {
+            return Ok(args);
+        }
+        loop {
+            args.push(self.expr()?);
+            if self.eat(")") {
+                return Ok(args);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn member(&mut self) -> Result<Expr, SecurityError> {
+        let mut target = self.primary()?;
+        loop {
+            if self.eat("[") {
+                let index = self.expr()?;
+                self.expect("]")?;
+                target = Expr::Index(Box::new(target), Box::new(index));
+            } else if self.eat(".") {
+                let name = self.ident()?;
+                if !self.eat("(") {
+                    target = Expr::Select(Box::new(target), name);
+                    continue;
+                }
+                if let Some(quantifier) = quantifier(&name) {
+                    let var = self.ident()?;
+                    self.expect(",")?;
+                    let body = self.expr()?;
+                    self.expect(")")?;
+                    target = Expr::Comprehension { quantifier, range: Box::new(target), var, body: Box::new(body) };
+                    continue;
+                }
+                let (func, arity) = method(&name).ok_or_else(|| invalid(format!("unknown method '{}'", name)))?;
+                let mut args = vec![target];
+                args.extend(self.arguments()?);
+                if args.len() != arity + 1 {
+                    return Err(invalid(format!("{}() takes {} argument(s)", name, arity)));
+                }
+                target = Expr::Call(func, args);
+            } else {
+                return Ok(target);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, SecurityError> {
+        match self.next() {
+            Some(Token::Int(n)) => Ok(Expr::Lit(Literal::Int(n))),
+            Some(Token::Double(n)) => Ok(Expr::Lit(Literal::Double(n))),
+            Some(Token::Str(s)) => Ok(Expr::Lit(Literal::Str(s))),
+            Some(Token::Punct("(")) => {
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Punct("[")) => {
+                let mut items = Vec::new();
+                if !self.eat("]") {
+                    loop {
+                        items.push(self.expr()?);
+                        if self.eat("]") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                Ok(Expr::List(items))
+            }
+            Some(Token::Ident(id)) => match id.as_str() {
+                "null" => Ok(Expr::Lit(Literal::Null)),
+                "true" => Ok(Expr::Lit(Literal::Bool(true))),
+                "false" => Ok(Expr::Lit(Literal::Bool(false))),
+                "in" => Err(invalid("unexpected 'in'")),
+                "has" if self.eat("(") => match self.expr()? {
+                    Expr::Select(target, field) => {
+                        self.expect(")")?;
+                        Ok(Expr::Has(target, field))
+                    }
+                    _ => Err(invalid("has() takes a field selection, e.g. has(resource.owner)")),
+                },
+                name if self.eat("(") => {
+                    let (func, arity) = global(name).ok_or_else(|| invalid(format!("unknown function '{}'", name)))?;
+                    let args = self.arguments()?;
+                    if args.len() != arity {
+                        return Err(invalid(format!("{}() takes {} argument(s)", name, arity)));
+                    }
+                    Ok(Expr::Call(func, args))
+                }
+                _ => Ok(Expr::Ident(id)),
+            },
+            _ => {
+                self.pos -= 1;
+                Err(invalid(format!("unexpected {}", self.describe_next())))
+            }
+        }
+    }
+}
+
+fn node_count(expr: &Expr) -> usize {
+    1 + match expr {
+        Expr::Lit(_) | Expr::Ident(_) => 0,
+        Expr::Select(target, _) | Expr::Has(target, _) | Expr::Not(target) | Expr::Neg(target) => node_count(target),
+        Expr::Index(l, r) | Expr::Binary(_, l, r) | Expr::And(l, r) | Expr::Or(l, r) => node_count(l) + node_count(r),
+        Expr::Cond(c, t, f) => node_count(c) + node_count(t) + node_count(f),
+        Expr::List(items) | Expr::Call(_, items) => items.iter().map(node_count).sum(),
+        Expr::Comprehension { range, body, .. } => node_count(range) + node_count(body),
+    }
+}
+
+// Type checking
+
+struct Checker<'e> {
+    env: &'e Environment,
+    locals: Vec<(String, Type)>,
+}
+
+fn mismatch(what: &str, ty: Type) -> SecurityError {
+    invalid(format!("{} cannot take {}", what, ty.name()))
+}
+
+impl Checker<'_> {
+    fn check(&mut self, expr: &Expr) -> Result<Type, SecurityError> {
+        match expr {
+            Expr::Lit(Literal::Null) => Ok(Type::Null),
+            Expr::Lit(Literal::Bool(_)) => Ok(Type::Bool),
+            Expr::Lit(Literal::Int(_)) => Ok(Type::Int),
+            Expr::Lit(Literal::Double(_)) => Ok(Type::Double),
+            Expr::Lit(Literal::Str(_)) => Ok(Type::String),
+            Expr::Ident(name) => {
+                if let Some((_, ty)) = self.locals.iter().rev().find(|(local, _)| local == name) {
+                    return Ok(*ty);
+                }
+                self.env
+                    .binding(name)
+                    .map(|binding| binding.ty)
+                    .ok_or_else(|| invalid(format!("undeclared reference to '{}'", name)))
+            }
+            Expr::Select(target, field) | Expr::Has(target, field) => {
+                let target = self.check(target)?;
+                let ty = self.select(target, field)?;
+                Ok(if matches!(expr, Expr::Has(..)) { Type::Bool } else { ty })
+            }
+            Expr::Index(target, index) => {
+                let (target, index) = (self.check(target)?, self.check(index)?);
+                match target {
+                    Type::List(item) if matches!(index, Type::Int | Type::Dyn) => Ok(*item),
+                    Type::Map(item) if matches!(index, Type::String | Type::Dyn) => Ok(*item),
+                    Type::Object(_) | Type::Dyn if matches!(index, Type::String | Type::Int | Type::Dyn) => Ok(Type::Dyn),
+                    _ => Err(invalid(format!("cannot index {} with {}", target.name(), index.name()))),
+                }
+            }
+            Expr::List(items) => {
+                for item in items {
+                    self.check(item)?;
+                }
+                Ok(Type::List(&Type::Dyn))
+            }
+            Expr::Not(operand) => match self.check(operand)? {
+                Type::Bool | Type::Dyn => Ok(Type::Bool),
+                ty => Err(mismatch("'!'", ty)),
+            },
+            Expr::Neg(operand) => match self.check(operand)? {
+                ty @ (Type::Int | Type::Double | Type::Dyn) => Ok(ty),
+                ty => Err(mismatch("'-'", ty)),
+            },
+            Expr::And(l, r) | Expr::Or(l, r) => {
+                for operand in [l, r] {
+                    let ty = self.check(operand)?;
+                    if !matches!(ty, Type::Bool | Type::Dyn) {
+                        return Err(mismatch("'&&' and '||'", ty));
+                    }
+                }
+                Ok(Type::Bool)
+            }
+            Expr::Cond(condition, then, otherwise) => {
+                let ty = self.check(condition)?;
+                if !matches!(ty, Type::Bool | Type::Dyn) {
+                    return Err(mismatch("a condition", ty));
+                }
+                let (then, otherwise) = (self.check(then)?, self.check(otherwise)?);
+                Ok(if then == otherwise { then } else { Type::Dyn })
+            }
+            Expr::Binary(op, l, r) => {
+                let (l, r) = (self.check(l)?, self.check(r)?);
+                self.binary(*op, l, r)
+            }
+            Expr::Call(func, args) => {
+                let types = args.iter().map(|arg| self.check(arg)).collect::<Result<Vec<_>, _>>()?;
+                self.call(*func, &types)
+            }
+            Expr::Comprehension { quantifier: _, range, var, body } => {
+                let item = match self.check(range)? {
+                    Type::List(item) => *item,
+                    Type::Map(_) | Type::Object(_) => Type::String,
+                    Type::Dyn => Type::Dyn,
+                    ty => return Err(mismatch("all(), exists() and exists_one()", ty)),
+                };
+                self.locals.push((var.clone(), item));
+                let ty = self.check(body);
+                self.locals.pop();
+                match ty? {
+                    Type::Bool | Type::Dyn => Ok(Type::Bool),
+                    ty => Err(invalid(format!("the condition of all(), exists() and exists_one() is {}, not bool", ty.name()))),
+                }
+            }
+        }
+    }
+
+    fn select(&self, target: Type, field: &str) -> Result<Type, SecurityError> {
+        match target {
+            Type::Object(object) => match object.fields.iter().find(|f| f.name == field) {
+                Some(f) => Ok(f.ty),
+                None if object.open => Ok(Type::Dyn),
+                None => Err(invalid(format!("{} has no field '{}'", object.name, field))),
+            },
+            Type::Map(item) => Ok(*item),
+            Type::Dyn => Ok(Type::Dyn),
+            ty => Err(invalid(format!("cannot select '{}' from {}", field, ty.name()))),
+        }
+    }
+
+    fn binary(&self, op: BinOp, l: Type, r: Type) -> Result<Type, SecurityError> {
+        let comparable = |l: Type, r: Type| {
+            l == r || matches!(l, Type::Dyn | Type::Null) || matches!(r, Type::Dyn | Type::Null) || (l.numeric() && r.numeric())
+                || matches!((l, r), (Type::List(_), Type::List(_)) | (Type::Map(_), Type::Map(_)) | (Type::Object(_), Type::Map(_)) | (Type::Map(_), Type::Object(_)))
+        };
+        let fail = || invalid(format!("no operator '{}' for {} and {}", op.symbol(), l.name(), r.name()));
+        match op {
+            BinOp::Eq | BinOp::Ne if comparable(l, r) => Ok(Type::Bool),
+            BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
+                let ordered = (l.numeric() && r.numeric())
+                    || matches!((l, r), (Type::String | Type::Dyn, Type::String | Type::Dyn));
+                if ordered { Ok(Type::Bool) } else { Err(fail()) }
+            }
+            BinOp::In => match r {
+                Type::List(item) if comparable(l, *item) => Ok(Type::Bool),
+                Type::Map(_) | Type::Object(_) if matches!(l, Type::String | Type::Dyn) => Ok(Type::Bool),
+                Type::Dyn => Ok(Type::Bool),
+                _ => Err(fail()),
+            },
+            BinOp::Add => match (l, r) {
+                (Type::String, Type::String) => Ok(Type::String),
+                (Type::List(_), Type::List(_)) => Ok(Type::List(&Type::Dyn)),
+                (Type::Int, Type::Int) => Ok(Type::Int),
+                (Type::Dyn, _) | (_, Type::Dyn) => Ok(Type::Dyn),
+                (l, r) if l.numeric() && r.numeric() => Ok(Type::Double),
+                _ => Err(fail()),
+            },
+            BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Rem => match (l, r) {
+                (Type::Int, Type::Int) => Ok(Type::Int),
+                (Type::Dyn, _) | (_, Type::Dyn) if l.numeric() && r.numeric() => Ok(Type::Dyn),
+                (l, r) if l.numeric() && r.numeric() && op != BinOp::Rem => Ok(Type::Double),
+                _ => Err(fail()),
+            },
+            _ => Err(fail()),
+        }
+    }
+
+    fn call(&self, func: Func, args: &[Type]) -> Result<Type, SecurityError> {
+        let is = |ty: Type, allowed: &[Type]| ty == Type::Dyn || allowed.contains(&ty);
+        let fail = || {
+            let names: Vec<String> = args.iter().map(Type::name).collect();
+            invalid(format!("no overload of {}() for ({})", func.name(), names.join(", ")))
+        };
+        match func {
+            Func::Size => match args[0] {
+                Type::String | Type::List(_) | Type::Map(_) | Type::Object(_) | Type::Dyn => Ok(Type::Int),
+                _ => Err(fail()),
+            },
+            Func::Int if is(args[0], &[Type::Int, Type::Double, Type::String]) => Ok(Type::Int),
+            Func::Double if is(args[0], &[Type::Int, Type::Double, Type::String]) => Ok(Type::Double),
+            Func::String if is(args[0], &[Type::Int, Type::Double, Type::String, Type::Bool]) => Ok(Type::String),
+            Func::Lower | Func::Upper if is(args[0], &[Type::String]) => Ok(Type::String),
+            Func::StartsWith | Func::EndsWith | Func::Contains | Func::Glob
+                if is(args[0], &[Type::String]) && is(args[1], &[Type::String]) => Ok(Type::Bool),
+            _ => Err(fail()),
+        }
+    }
+}
+
+/// A checked expression, ready to evaluate.
+#[derive(Debug, Clone)]
+pub struct Program {
+    expr: Expr,
+    pub result: Type,
+    pub nodes: usize,
+}
+
+/// The outcome of evaluating a program.
+#[derive(Debug)]
+pub struct Evaluation {
+    pub value: Json,
+    pub steps: u64,
+}
+
+impl Program {
+    /// Parse `source` and check it against `env`.
+    pub fn compile(source: &str, env: &Environment) -> Result<Self, SecurityError> {
+        if source.trim().is_empty() {
+            return Err(invalid("empty expression"));
+        }
+        if source.len() > MAX_EXPRESSION_LENGTH {
+            return Err(invalid(format!("longer than {} bytes", MAX_EXPRESSION_LENGTH)));
+        }
+        let mut parser = Parser { tokens: tokenize(source)?, pos: 0, depth: 0 };
+        let expr = parser.expr()?;
+        if parser.pos != parser.tokens.len() {
+            return Err(invalid(format!("unexpected {}", parser.describe_next())));
+        }
+        let nodes = node_count(&expr);
+        if nodes > MAX_NODES {
+            return Err(invalid(format!("{} nodes exceed the limit of {}", nodes, MAX_NODES)));
+        }
+        let result = Checker { env, locals: Vec::new() }.check(&expr)?;
+        Ok(Self { expr, result, nodes })
+    }
+
+    /// `compile`, for expressions that must yield a bool.
+    pub fn condition(source: &str, env: &Environment) -> Result<Self, SecurityError> {
+        let program = Self::compile(source, env)?;
+        if !matches!(program.result, Type::Bool | Type::Dyn) {
+            return Err(invalid(format!("a condition must be bool, not {}", program.result.name())));
+        }
+        Ok(program)
+    }
+
+    pub fn evaluate(&self, bindings: &Map<String, Json>) -> Result<Evaluation, SecurityError> {
+        let mut eval = Eval { root: bindings, locals: Vec::new(), steps: 0 };
+        let value = eval.eval(&self.expr).map_err(|e| SecurityError::ValidationError(format!("Expression failed: {}", e)))?;
+        Ok(Evaluation { value: value.into_json(), steps: eval.steps })
+    }
+
+    /// Whether the condition holds; errors, and results other than bools,
+    /// are the caller's to treat as it sees fit.
+    pub fn holds(&self, bindings: &Map<String, Json>) -> Result<bool, SecurityError> {
+        let mut eval = Eval { root: bindings, locals: Vec::new(), steps: 0 };
+        match eval.eval(&self.expr) {
+            Ok(Val::Bool(b)) => Ok(b),
+            Ok(other) => Err(SecurityError::ValidationError(format!(
+                "Expression failed: yielded {}, not bool",
+                other.kind()
+            ))),
+            Err(e) => Err(SecurityError::ValidationError(format!("Expression failed: {}", e))),
+        }
+    }
+}
+
+// Evaluation
+
+#[derive(Debug, Clone)]
+enum Val<'a> {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Double(f64),
+    Str(Cow<'a, str>),
+    List(Vec<Val<'a>>),
+    /// A bound array or object, borrowed.
+    Json(&'a Json),
+}
+
+impl<'a> Val<'a> {
+    fn from_json(value: &'a Json) -> Self {
+        match value {
+            Json::Null => Val::Null,
+            Json::Bool(b) => Val::Bool(*b),
+            Json::Number(n) => match n.as_i64() {
+                Some(i) => Val::Int(i),
+                None => Val::Double(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Json::String(s) => Val::Str(Cow::Borrowed(s)),
+            Json::Array(_) | Json::Object(_) => Val::Json(value),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Val::Null => "null",
+            Val::Bool(_) => "bool",
+            Val::Int(_) => "int",
+            Val::Double(_) => "double",
+            Val::Str(_) => "string",
+            Val::List(_) | Val::Json(Json::Array(_)) => "list",
+            Val::Json(_) => "map",
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        match self {
+            Val::Int(i) => Some(*i as f64),
+            Val::Double(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    /// The items of a list, whether built or bound.
+    fn items(&self) -> Option<Vec<Val<'a>>> {
+        match self {
+            Val::List(items) => Some(items.clone()),
+            Val::Json(Json::Array(items)) => Some(items.iter().map(Val::from_json).collect()),
+            _ => None,
+        }
+    }
+
+    fn into_json(self) -> Json {
+        match self {
+            Val::Null => Json::Null,
+            Val::Bool(b) => Json::Bool(b),
+            Val::Int(i) => Json::from(i),
+            Val::Double(d) => Json::from(d),
+            Val::Str(s) => Json::String(s.into_owned()),
+            Val::List(items) => Json::Array(items.into_iter().map(Val::into_json).collect()),
+            Val::Json(value) => value.clone(),
+        }
+    }
+}
+
+fn equals(l: &Val, r: &Val) -> bool {
+    match (l, r) {
+        (Val::Null, Val::Null) => true,
+        (Val::Bool(a), Val::Bool(b)) => a == b,
+        (Val::Str(a), Val::Str(b)) => a == b,
+        (Val::Int(a), Val::Int(b)) => a == b,
+        (a, b) if a.number().is_some() && b.number().is_some() => a.number() == b.number(),
+        (Val::Json(Json::Object(a)), Val::Json(Json::Object(b))) => a == b,
+        (a, b) => match (a.items(), b.items()) {
+            (Some(a), Some(b)) => a.len() == b.len() && a.iter().zip(&b).all(|(x, y)| equals(x, y)),
+            _ => false,
+        },
+    }
+}
+
+struct Eval<'a> {
+    root: &'a Map<String, Json>,
+    locals: Vec<(String, Val<'a>)>,
+    steps: u64,
+}
+
+type Outcome<'a> = std::result::Result<Val<'a>, String>;
+
+impl<'a> Eval<'a> {
+    fn eval(&mut self, expr: &Expr) -> Outcome<'a> {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return Err(format!("evaluation exceeded {} steps", MAX_STEPS));
+        }
+        match expr {
+            Expr::Lit(Literal::Null) => Ok(Val::Null),
+            Expr::Lit(Literal::Bool(b)) => Ok(Val::Bool(*b)),
+            Expr::Lit(Literal::Int(i)) => Ok(Val::Int(*i)),
+            Expr::Lit(Literal::Double(d)) => Ok(Val::Double(*d)),
+            Expr::Lit(Literal::Str(s)) => Ok(Val::Str(Cow::Owned(s.clone()))),
+            Expr::Ident(name) => {
+                if let Some((_, value)) = self.locals.iter().rev().find(|(local, _)| local == name) {
+                    return Ok(value.clone());
+                }
+                self.root.get(name).map(Val::from_json).ok_or_else(|| format!("no value bound to '{}'", name))
+            }
+            Expr::Select(target, field) => match self.eval(target)? {
+                Val::Json(Json::Object(map)) => map.get(field).map(Val::from_json).ok_or_else(|| format!("no such key '{}'", field)),
+                other => Err(format!("cannot select '{}' from {}", field, other.kind())),
+            },
+            Expr::Has(target, field) => match self.eval(target)? {
+                Val::Json(Json::Object(map)) => Ok(Val::Bool(map.get(field).is_some_and(|v| !v.is_null()))),
+                Val::Null => Ok(Val::Bool(false)),
+                other => Err(format!("has() cannot test {}", other.kind())),
+            },
+            Expr::Index(target, index) => {
+                let (target, index) = (self.eval(target)?, self.eval(index)?);
+                match (&target, &index) {
+                    (Val::Json(Json::Object(map)), Val::Str(key)) => {
+                        map.get(key.as_ref()).map(Val::from_json).ok_or_else(|| format!("no such key '{}'", key))
+                    }
+                    (_, Val::Int(i)) => {
+                        let items = target.items().ok_or_else(|| format!("cannot index {}", target.kind()))?;
+                        usize::try_from(*i)
+                            .ok()
+                            .and_then(|i| items.into_iter().nth(i))
+                            .ok_or_else(|| format!("index {} out of range", i))
+                    }
+                    _ => Err(format!("cannot index {} with {}", target.kind(), index.kind())),
+                }
+            }
+            Expr::List(items) => {
+                if items.len() > MAX_LIST_ITEMS {
+                    return Err(format!("list longer than {} items", MAX_LIST_ITEMS));
+                }
+                Ok(Val::List(items.iter().map(|item| self.eval(item)).collect::<Result<_, _>>()?))
+            }
+            Expr::Not(operand) => match self.eval(operand)? {
+                Val::Bool(b) => Ok(Val::Bool(!b)),
+                other => Err(format!("'!' cannot take {}", other.kind())),
+            },
+            Expr::Neg(operand) => match self.eval(operand)? {
+                Val::Int(i) => i.checked_neg().map(Val::Int).ok_or_else(|| "integer overflow".to_string()),
+                Val::Double(d) => Ok(Val::Double(-d)),
+                other => Err(format!("'-' cannot take {}", other.kind())),
+            },
+            // CEL's commutative logic: a decisive side wins over an error
+            Expr::And(l, r) => {
+                let left = self.boolean(l);
+                if left == Ok(false) {
+                    return Ok(Val::Bool(false));
+                }
+                match (left, self.boolean(r)) {
+                    (_, Ok(false)) => Ok(Val::Bool(false)),
+                    (Ok(true), Ok(true)) => Ok(Val::Bool(true)),
+                    (Err(e), _) | (_, Err(e)) => Err(e),
+                    _ => unreachable!(),
+                }
+            }
+            Expr::Or(l, r) => {
+                let left = self.boolean(l);
+                if left == Ok(true) {
+                    return Ok(Val::Bool(true));
+                }
+                match (left, self.boolean(r)) {
+                    (_, Ok(true)) => Ok(Val::Bool(true)),
+                    (Ok(false), Ok(false)) => Ok(Val::Bool(false)),
+                    (Err(e), _) | (_, Err(e)) => Err(e),
+                    _ => unreachable!(),
+                }
+            }
+            Expr::Cond(condition, then, otherwise) => {
+                if self.boolean(condition)? {
+                    self.eval(then)
+                } else {
+                    self.eval(otherwise)
+                }
+            }
+            Expr::Binary(op, l, r) => {
+                let (l, r) = (self.eval(l)?, self.eval(r)?);
+                binary(*op, l, r)
+            }
+            Expr::Call(func, args) => {
+                let args = args.iter().map(|arg| self.eval(arg)).collect::<Result<Vec<_>, _>>()?;
+                call(*func, args)
+            }
+            Expr::Comprehension { quantifier, range, var, body } => {
+                let range = self.eval(range)?;
+                let items = match &range {
+                    Val::Json(Json::Object(map)) => map.keys().map(|key| Val::Str(Cow::Borrowed(key.as_str()))).collect(),
+                    _ => range.items().ok_or_else(|| format!("cannot iterate over {}", range.kind()))?,
+                };
+                self.locals.push((var.clone(), Val::Null));
+                let result = self.quantify(*quantifier, items, body);
+                self.locals.pop();
+                result
+            }
+        }
+    }
+
+    fn boolean(&mut self, expr: &Expr) -> std::result::Result<bool, String> {
+        match self.eval(expr)? {
+            Val::Bool(b) => Ok(b),
+            other => Err(format!("expected bool, found {}", other.kind())),
+        }
+    }
+
+    fn quantify(&mut self, quantifier: Quantifier, items: Vec<Val<'a>>, body: &Expr) -> Outcome<'a> {
+        let mut error = None;
+        let mut matched = 0;
+        for item in items {
+            if let Some(local) = self.locals.last_mut() {
+                local.1 = item;
+            }
+            match self.boolean(body) {
+                Ok(true) if quantifier == Quantifier::Exists => return Ok(Val::Bool(true)),
+                Ok(false) if quantifier == Quantifier::All => return Ok(Val::Bool(false)),
+                Ok(true) => matched += 1,
+                Ok(false) => {}
+                // Budget exhaustion ends the whole evaluation
+                Err(e) if self.steps > MAX_STEPS || quantifier == Quantifier::ExistsOne => return Err(e),
+                Err(e) => error = error.or(Some(e)),
+            }
+        }
+        match (quantifier, error) {
+            (_, Some(e)) => Err(e),
+            (Quantifier::All, None) => Ok(Val::Bool(true)),
+            (Quantifier::Exists, None) => Ok(Val::Bool(false)),
+            (Quantifier::ExistsOne, None) => Ok(Val::Bool(matched == 1)),
+        }
+    }
+}
+
+fn order(op: BinOp, ordering: Option<std::cmp::Ordering>) -> Outcome<'static> {
+    use std::cmp::Ordering::*;
+    let ordering = ordering.ok_or_else(|| "cannot order NaN".to_string())?;
+    Ok(Val::Bool(match op {
+        BinOp::Lt => ordering == Less,
+        BinOp::Le => ordering != Greater,
+        BinOp::Gt => ordering == Greater,
+        _ => ordering != Less,
+    }))
+}
+
+fn binary<'a>(op: BinOp, l: Val<'a>, r: Val<'a>) -> Outcome<'a> {
+    let overflow = || "integer overflow".to_string();
+    let fail = |l: &Val, r: &Val| format!("no operator '{}' for {} and {}", op.symbol(), l.kind(), r.kind());
+    match op {
+        BinOp::Eq => Ok(Val::Bool(equals(&l, &r))),
+        BinOp::Ne => Ok(Val::Bool(!equals(&l, &r))),
+        BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => match (&l, &r) {
+            (Val::Str(a), Val::Str(b)) => order(op, Some(a.cmp(b))),
+            (Val::Int(a), Val::Int(b)) => order(op, Some(a.cmp(b))),
+            _ => match (l.number(), r.number()) {
+                (Some(a), Some(b)) => order(op, a.partial_cmp(&b)),
+                _ => Err(fail(&l, &r)),
+            },
+        },
+        BinOp::In => match &r {
+            Val::Json(Json::Object(map)) => match &l {
+                Val::Str(key) => Ok(Val::Bool(map.contains_key(key.as_ref()))),
+                _ => Err(fail(&l, &r)),
+            },
+            _ => {
+                let items = r.items().ok_or_else(|| fail(&l, &r))?;
+                Ok(Val::Bool(items.iter().any(|item| equals(&l, item))))
+            }
+        },
+        BinOp::Add => match (&l, &r) {
+            (Val::Str(a), Val::Str(b)) => {
+                if a.len() + b.len() > MAX_STRING_BYTES {
+                    return Err(format!("string longer than {} bytes", MAX_STRING_BYTES));
+                }
+                Ok(Val::Str(Cow::Owned(format!("{}{}", a, b))))
+            }
+            (Val::Int(a), Val::Int(b)) => a.checked_add(*b).map(Val::Int).ok_or_else(overflow),
+            _ => match (l.number(), r.number(), l.items(), r.items()) {
+                (Some(a), Some(b), _, _) => Ok(Val::Double(a + b)),
+                (_, _, Some(mut a), Some(b)) => {
+                    if a.len() + b.len() > MAX_LIST_ITEMS {
+                        return Err(format!("list longer than {} items", MAX_LIST_ITEMS));
+                    }
+                    a.extend(b);
+                    Ok(Val::List(a))
+                }
+                _ => Err(fail(&l, &r)),
+            },
+        },
+        BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Rem => match (&l, &r) {
+            (Val::Int(_), Val::Int(0)) if matches!(op, BinOp::Div | BinOp::Rem) => Err("division by zero".to_string()),
+            (Val::Int(a), Val::Int(b)) => match op {
+                BinOp::Sub => a.checked_sub(*b),
+                BinOp::Mul => a.checked_mul(*b),
+                BinOp::Div => a.checked_div(*b),
+                _ => a.checked_rem(*b),
+            }
+            .map(Val::Int)
+            .ok_or_else(overflow),
+            _ => match (l.number(), r.number()) {
+                (Some(a), Some(b)) if op != BinOp::Rem => Ok(Val::Double(match op {
+                    BinOp::Sub => a - b,
+                    BinOp::Mul => a * b,
+                    _ => a / b,
+                })),
+                _ => Err(fail(&l, &r)),
+            },
+        },
+    }
+}
+
+fn call(func: Func, args: Vec<Val>) -> Outcome {
+    let fail = |args: &[Val]| {
+        let kinds: Vec<&str> = args.iter().map(Val::kind).collect();
+        format!("no overload of {}() for ({})", func.name(), kinds.join(", "))
+    };
+    match (func, args.as_slice()) {
+        (Func::Size, [Val::Str(s)]) => Ok(Val::Int(s.chars().count() as i64)),
+        (Func::Size, [Val::Json(Json::Object(map))]) => Ok(Val::Int(map.len() as i64)),
+        (Func::Size, [value]) => value.items().map(|items| Val::Int(items.len() as i64)).ok_or_else(|| fail(&args)),
+        (Func::Int, [Val::Int(i)]) => Ok(Val::Int(*i)),
+        (Func::Int, [Val::Double(d)]) if d.is_finite() && d.abs() < i64::MAX as f64 => Ok(Val::Int(d.trunc() as i64)),
+        (Func::Int, [Val::Str(s)]) => s.trim().parse().map(Val::Int).map_err(|_| format!("'{}' is not an int", s)),
+        (Func::Double, [Val::Str(s)]) => s.trim().parse().map(Val::Double).map_err(|_| format!("'{}' is not a double", s)),
+        (Func::Double, [value]) => value.number().map(Val::Double).ok_or_else(|| fail(&args)),
+        (Func::String, [Val::Str(s)]) => Ok(Val::Str(s.clone())),
+        (Func::String, [Val::Int(i)]) => Ok(Val::Str(Cow::Owned(i.to_string()))),
+        (Func::String, [Val::Double(d)]) => Ok(Val::Str(Cow::Owned(d.to_string()))),
+        (Func::String, [Val::Bool(b)]) => Ok(Val::Str(Cow::Owned(b.to_string()))),
+        (Func::Lower, [Val::Str(s)]) => Ok(Val::Str(Cow::Owned(s.to_lowercase()))),
+        (Func::Upper, [Val::Str(s)]) => Ok(Val::Str(Cow::Owned(s.to_uppercase()))),
+        (Func::StartsWith, [Val::Str(s), Val::Str(prefix)]) => Ok(Val::Bool(s.starts_with(prefix.as_ref()))),
+        (Func::EndsWith, [Val::Str(s), Val::Str(suffix)]) => Ok(Val::Bool(s.ends_with(suffix.as_ref()))),
+        (Func::Contains, [Val::Str(s), Val::Str(part)]) => Ok(Val::Bool(s.contains(part.as_ref()))),
+        (Func::Glob, [Val::Str(s), Val::Str(pattern)]) => Ok(Val::Bool(crate::authz::glob(pattern, s))),
+        _ => Err(fail(&args)),
+    }
+}
+
+// HTTP handlers
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ValidateRequest {
+    pub environment: String,
+    pub expression: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestRequest {
+    pub environment: String,
+    pub expression: String,
+    #[serde(default)]
+    pub bindings: Map<String, Json>,
+}
+
+fn environment(name: &str) -> Result<&'static Environment, SecurityError> {
+    ENVIRONMENTS.iter().copied().find(|env| env.name == name).ok_or_else(|| {
+        let names: Vec<&str> = ENVIRONMENTS.iter().map(|env| env.name).collect();
+        SecurityError::ValidationError(format!("Unknown environment '{}'; known: {}", name, names.join(", ")))
+    })
+}
+
+fn bad_request(e: SecurityError) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "error": e.to_string()
+    }))
+}
+
+pub async fn environments_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authenticate(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let environments: Vec<Json> = ENVIRONMENTS
+        .iter()
+        .map(|env| serde_json::json!({
+            "name": env.name,
+            "description": env.description,
+            "bindings": describe_bindings(env.bindings)
+        }))
+        .collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "environments": environments })))
+}
+
+/// Parse and type-check; a bad expression is still a 200.
+pub async fn validate_handler(
+    req: HttpRequest,
+    request: web::Json<ValidateRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authenticate(&req) {
+        return Ok(auth_error_response(&e));
+    }
+    let env = match environment(&request.environment) {
+        Ok(env) => env,
+        Err(e) => return Ok(bad_request(e)),
+    };
+
+    match Program::compile(&request.expression, env) {
+        Ok(program) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "valid": true,
+            "type": program.result.name(),
+            "nodes": program.nodes
+        }))),
+        Err(e) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "valid": false,
+            "error": e.to_string()
+        }))),
+    }
+}
+
+/// Evaluate against sample bindings, which must fit the environment.
+pub async fn test_handler(
+    req: HttpRequest,
+    request: web::Json<TestRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authenticate(&req) {
+        return Ok(auth_error_response(&e));
+    }
+    let env = match environment(&request.environment) {
+        Ok(env) => env,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    if let Err(e) = env.conforms(&request.bindings) {
+        return Ok(bad_request(e));
+    }
+
+    let program = match Program::compile(&request.expression, env) {
+        Ok(program) => program,
+        Err(e) => return Ok(HttpResponse::Ok().json(serde_json::json!({
+            "valid": false,
+            "error": e.to_string()
+        }))),
+    };
+    match program.evaluate(&request.bindings) {
+        Ok(evaluation) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "valid": true,
+            "result": evaluation.value,
+            "steps": evaluation.steps
+        }))),
+        Err(e) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "valid": true,
+            "error": e.to_string()
+        }))),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/expressions")
+            .route("/environments", web::get().to(environments_handler))
+            .route("/validate", web::post().to(validate_handler))
+            .route("/test", web::post().to(test_handler)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    static SUBJECT: Object = Object {
+        name: "subject",
+        fields: &[
+            Binding { name: "tenant_id", ty: Type::String, doc: "" },
+            Binding { name: "roles", ty: Type::List(&Type::String), doc: "" },
+        ],
+        open: true,
+    };
+
+    static EVENT: Object = Object {
+        name: "event",
+        fields: &[
+            Binding { name: "action", ty: Type::String, doc: "" },
+            Binding { name: "attempts", ty: Type::Int, doc: "" },
+            Binding { name: "payload", ty: Type::Map(&Type::Dyn), doc: "" },
+        ],
+        open: false,
+    };
+
+    static TEST: Environment = Environment {
+        name: "test",
+        description: "",
+        bindings: &[
+            Binding { name: "subject", ty: Type::Object(&SUBJECT), doc: "" },
+            Binding { name: "event", ty: Type::Object(&EVENT), doc: "" },
+            Binding { name: "tags", ty: Type::List(&Type::String), doc: "" },
+        ],
+    };
+
+    fn bindings() -> Map<String, Json> {
+        json!({
+            "subject": { "tenant_id": "t1", "roles": ["buyer", "viewer"], "department": "compras" },
+            "event": { "action": "auth.login_failed", "attempts": 5, "payload": { "ip": "192.0.2.1", "score": null } },
+            "tags": ["a", "b", "a"]
+        })
+        .as_object()
+        .unwrap()
+        .clone()
+    }
+
+    fn eval(source: &str) -> Json {
+        Program::compile(source, &TEST).unwrap().evaluate(&bindings()).unwrap().value
+    }
+
+    fn refused(source: &str) -> String {
+        Program::compile(source, &TEST).unwrap_err().to_string()
+    }
+
+    fn failed(source: &str) -> String {
+        Program::compile(source, &TEST).unwrap().evaluate(&bindings()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn operators_bind_in_order() {
+        assert_eq!(eval("1 + 2 * 3 - 4 / 2"), json!(5));
+        assert_eq!(eval("-(1 + 2) % 2"), json!(-1));
+        assert_eq!(eval("7 / 2.0"), json!(3.5));
+        assert_eq!(eval("true || false && false"), json!(true));
+        assert_eq!(eval("1 < 2 == true"), json!(true));
+        assert_eq!(eval("false ? 1 : true ? 2 : 3"), json!(2));
+        assert_eq!(eval("'a\\'b' + \"\\n\""), json!("a'b\n"));
+        assert_eq!(eval("[1, 2] + [3]"), json!([1, 2, 3]));
+        assert_eq!(eval("1 == 1.0 && [1, 'x'] == [1.0, 'x']"), json!(true));
+    }
+
+    #[test]
+    fn bindings_are_selected_and_indexed() {
+        assert_eq!(eval("subject.tenant_id == 't1' && 'buyer' in subject.roles"), json!(true));
+        assert_eq!(eval("event.action.startsWith('auth.') && event.attempts >= 5"), json!(true));
+        assert_eq!(eval("event.payload.ip"), json!("192.0.2.1"));
+        assert_eq!(eval("event.payload['ip'] == tags[0] + '.'"), json!(false));
+        assert_eq!(eval("subject.department"), json!("compras"));
+        assert_eq!(eval("'ip' in event.payload && !('port' in event.payload)"), json!(true));
+        assert_eq!(eval("has(event.payload.ip) && !has(event.payload.score)"), json!(true));
+    }
+
+    #[test]
+    fn functions_and_macros() {
+        assert_eq!(eval("size('ação') + size(tags) + tags.size()"), json!(10));
+        assert_eq!(eval("int('42') + int(2.9) + int(double('1.5'))"), json!(45));
+        assert_eq!(eval("string(5) + upper('x') + lower('Y')"), json!("5Xy"));
+        assert_eq!(eval("event.action.glob('auth.*') && event.action.endsWith('failed')"), json!(true));
+        assert_eq!(eval("subject.roles.exists(r, r.contains('view'))"), json!(true));
+        assert_eq!(eval("subject.roles.all(r, size(r) > 5)"), json!(false));
+        assert_eq!(eval("tags.exists_one(t, t == 'a')"), json!(false));
+        assert_eq!(eval("tags.exists_one(t, t == 'b')"), json!(true));
+        assert_eq!(eval("event.payload.all(k, k in ['ip', 'score'])"), json!(true));
+    }
+
+    #[test]
+    fn types_are_checked_when_compiled() {
+        assert!(refused("event.actoin == 'x'").contains("event has no field 'actoin'"));
+        assert!(refused("event.attempts == 'five'").contains("no operator '==' for int and string"));
+        assert!(refused("user.id").contains("undeclared reference to 'user'"));
+        assert!(refused("size(1)").contains("no overload of size() for (int)"));
+        assert!(refused("event.action && true").contains("'&&' and '||' cannot take string"));
+        assert!(refused("tags.all(t, t)").contains("is string, not bool"));
+        // Open objects take undeclared attributes
+        assert!(Program::compile("subject.clearance > 3", &TEST).is_ok());
+        assert!(Program::condition("event.attempts + 1", &TEST).unwrap_err().to_string().contains("must be bool, not int"));
+    }
+
+    #[test]
+    fn malformed_expressions_are_refused() {
+        assert!(refused("").contains("empty expression"));
+        assert!(refused("1 +").contains("Invalid expression"));
+        assert!(refused("(1").contains("Invalid expression"));
+        assert!(refused("1 2").contains("Invalid expression"));
+        assert!(refused("'open").contains("Invalid expression"));
+        assert!(refused(&"x".repeat(MAX_EXPRESSION_LENGTH + 1)).contains("longer than"));
+        assert!(refused(&format!("{}1{}", "(".repeat(MAX_DEPTH + 1), ")".repeat(MAX_DEPTH + 1))).contains("Invalid expression"));
+        let wide = vec!["1"; MAX_NODES].join(" + ");
+        assert!(refused(&wide).contains("nodes exceed the limit"));
+    }
+
+    #[test]
+    fn errors_are_absorbed_like_cel() {
+        // The missing key is an error either way round, unless the other
+        // side decides the result
+        assert_eq!(eval("event.payload.port == 1 && false"), json!(false));
+        assert_eq!(eval("false && event.payload.port == 1"), json!(false));
+        assert_eq!(eval("event.payload.port == 1 || true"), json!(true));
+        assert!(failed("event.payload.port == 1 && true").contains("no such key 'port'"));
+        assert!(failed("1 / (event.attempts - 5)").contains("division by zero"));
+        assert!(failed("9223372036854775807 + event.attempts").contains("integer overflow"));
+        assert!(failed("tags[3]").contains("index 3 out of range"));
+        assert!(failed("int('x')").contains("'x' is not an int"));
+    }
+
+    #[test]
+    fn holds_wants_a_bool() {
+        let program = Program::condition("event.payload.ip", &TEST).unwrap();
+        assert!(program.holds(&bindings()).unwrap_err().to_string().contains("yielded string, not bool"));
+        let program = Program::condition("event.attempts > 3", &TEST).unwrap();
+        assert!(program.holds(&bindings()).unwrap());
+        assert!(program.holds(&Map::new()).unwrap_err().to_string().contains("no value bound to 'event'"));
+    }
+
+    #[test]
+    fn evaluation_is_bounded() {
+        let mut bindings = bindings();
+        bindings.insert("tags".to_string(), json!(vec!["x"; 6000]));
+        let program = Program::compile("tags.all(a, tags.exists(b, b == 'y'))", &TEST).unwrap();
+        assert!(program.evaluate(&bindings).unwrap_err().to_string().contains("exceeded 10000 steps"));
+
+        let evaluation = Program::compile("1 + 2", &TEST).unwrap().evaluate(&bindings).unwrap();
+        assert_eq!(evaluation.steps, 3);
+    }
+
+    #[test]
+    fn sample_bindings_must_conform() {
+        assert!(TEST.conforms(&bindings()).is_ok());
+        let wrong = json!({ "event": { "attempts": "five" } }).as_object().unwrap().clone();
+        assert!(TEST.conforms(&wrong).unwrap_err().to_string().contains("event.attempts must be int"));
+        let closed = json!({ "event": { "source": "sso" } }).as_object().unwrap().clone();
+        assert!(TEST.conforms(&closed).unwrap_err().to_string().contains("event has no field 'source'"));
+        let unknown = json!({ "user": {} }).as_object().unwrap().clone();
+        assert!(TEST.conforms(&unknown).unwrap_err().to_string().contains("'user' is not a binding of test"));
+    }
+}
@@ -0,0 +1,468 @@
+/*!
+Feature Flags Module
+Stored flags with tenant, subject and percentage targeting
+*/
+
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, Postgres, Transaction};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+use tracing::{error, info, warn};
+
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, Principal};
+use crate::changes::{self, NewChange};
+use crate::concurrency::{self, IfMatch};
+use crate::conditional::{CacheControl, Validators};
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+
+const SELECT_COLUMNS: &str = "key, description, enabled, tenants, subjects, rollout_percentage, \
+    version, updated_at, updated_by";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct FeatureFlag {
+    pub key: String,
+    pub description: Option<String>,
+    /// Master switch; a disabled flag is off for everyone.
+    pub enabled: bool,
+    /// Tenants that always get the flag.
+    pub tenants: Vec<String>,
+    /// Subjects that always get the flag.
+    pub subjects: Vec<String>,
+    /// Share of remaining subjects, bucketed by a stable hash of flag and subject.
+    pub rollout_percentage: i16,
+    pub version: i64,
+    pub updated_at: DateTime<Utc>,
+    pub updated_by: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlagRequest {
+    pub description: Option<String>,
+    pub enabled: bool,
+    #[serde(default)]
+    pub tenants: Vec<String>,
+    #[serde(default)]
+    pub subjects: Vec<String>,
+    #[serde(default)]
+    pub rollout_percentage: i16,
+}
+
+/// Who a flag is evaluated for.
+#[derive(Debug, Clone, Default)]
+pub struct FlagContext {
+    pub tenant_id: Option<String>,
+    pub subject: Option<String>,
+}
+
+impl From<&Principal> for FlagContext {
+    fn from(principal: &Principal) -> Self {
+        Self {
+            tenant_id: principal.tenant_id.clone(),
+            subject: Some(principal.subject.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EvaluateRequest {
+    /// Flags to evaluate; all known flags when empty.
+    #[serde(default)]
+    pub flags: Vec<String>,
+}
+
+impl FeatureFlag {
+    fn editable(&self) -> FlagRequest {
+        FlagRequest {
+            description: self.description.clone(),
+            enabled: self.enabled,
+            tenants: self.tenants.clone(),
+            subjects: self.subjects.clone(),
+            rollout_percentage: self.rollout_percentage,
+        }
+    }
+
+    pub fn evaluate(&self, context: &FlagContext) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        if let Some(tenant_id) = &context.tenant_id {
+            if self.tenants.contains(tenant_id) {
+                return true;
+            }
+        }
+        if let Some(subject) = &context.subject {
+            if self.subjects.contains(subject) {
+                return true;
+            }
+        }
+
+        match context.subject.as_ref().or(context.tenant_id.as_ref()) {
+            Some(unit) => bucket(&self.key, unit) < self.rollout_percentage as u32,
+            None => self.rollout_percentage >= 100,
+        }
+    }
+}
+
+/// Stable bucket in 0..100 for a flag/unit pair.
+fn bucket(key: &str, unit: &str) -> u32 {
+    let digest = Sha256::digest(format!("{}:{}", key, unit).as_bytes());
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) % 100
+}
+
+fn validate(key: &str, request: &FlagRequest) -> Result<(), SecurityError> {
+    let valid_key = !key.is_empty()
+        && key.len() <= 64
+        && key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '.');
+    if !valid_key {
+        return Err(SecurityError::ValidationError(
+            "Flag keys are 1-64 characters of a-z, 0-9, '_' and '.'".to_string(),
+        ));
+    }
+    if !(0..=100).contains(&request.rollout_percentage) {
+        return Err(SecurityError::ValidationError("rollout_percentage must be 0-100".to_string()));
+    }
+    Ok(())
+}
+
+pub struct FeatureFlags {
+    storage: Storage,
+    cache: RwLock<HashMap<String, FeatureFlag>>,
+}
+
+impl FeatureFlags {
+    pub async fn new(storage: Storage) -> Result<Self, SecurityError> {
+        let flags = Self {
+            storage,
+            cache: RwLock::new(HashMap::new()),
+        };
+
+        info!("Feature flags initialized successfully");
+        Ok(flags)
+    }
+
+    /// Reload every flag from storage into the evaluation cache.
+    pub async fn refresh(&self) -> Result<(), SecurityError> {
+        let flags = sqlx::query_as::<_, FeatureFlag>(&format!("SELECT {} FROM feature_flags", SELECT_COLUMNS))
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        let flags = flags.into_iter().map(|flag| (flag.key.clone(), flag)).collect();
+        *self.cache.write().unwrap() = flags;
+        Ok(())
+    }
+
+    /// Gate for other modules. Unknown flags are off.
+    pub fn is_enabled(&self, key: &str, context: &FlagContext) -> bool {
+        self.cache.read().unwrap()
+            .get(key)
+            .map(|flag| flag.evaluate(context))
+            .unwrap_or(false)
+    }
+
+    pub fn evaluate_all(&self, keys: &[String], context: &FlagContext) -> BTreeMap<String, bool> {
+        let cache = self.cache.read().unwrap();
+        if keys.is_empty() {
+            cache.values().map(|flag| (flag.key.clone(), flag.evaluate(context))).collect()
+        } else {
+            keys.iter()
+                .map(|key| (key.clone(), cache.get(key).map(|f| f.evaluate(context)).unwrap_or(false)))
+                .collect()
+        }
+    }
+
+    pub async fn list(&self) -> Result<Vec<FeatureFlag>, SecurityError> {
+        let flags = sqlx::query_as::<_, FeatureFlag>(&format!(
+            "SELECT {} FROM feature_flags ORDER BY key",
+            SELECT_COLUMNS
+        ))
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        Ok(flags)
+    }
+
+    pub async fn get(&self, key: &str) -> Result<FeatureFlag, SecurityError> {
+        sqlx::query_as::<_, FeatureFlag>(&format!("SELECT {} FROM feature_flags WHERE key = $1", SELECT_COLUMNS))
+            .bind(key)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound(format!("Flag '{}' not found", key)))
+    }
+
+    /// Create or replace a flag. Replacing requires the current version
+    /// unless `expected_version` is `None` (`If-Match: *`).
+    pub async fn put(
+        &self,
+        actor: &Principal,
+        key: &str,
+        expected_version: Option<i64>,
+        request: FlagRequest,
+    ) -> Result<FeatureFlag, SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let flag = self.put_in(&mut tx, &actor.subject, key, expected_version, request).await?;
+        tx.commit().await?;
+
+        self.refresh().await?;
+        Ok(flag)
+    }
+
+    /// `put` inside the caller's transaction; the cache is not refreshed.
+    pub(crate) async fn put_in(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        author: &str,
+        key: &str,
+        expected_version: Option<i64>,
+        request: FlagRequest,
+    ) -> Result<FeatureFlag, SecurityError> {
+        validate(key, &request)?;
+
+        let current = sqlx::query_as::<_, FeatureFlag>(&format!(
+            "SELECT {} FROM feature_flags WHERE key = $1 FOR UPDATE",
+            SELECT_COLUMNS
+        ))
+        .bind(key)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        if let (Some(current), Some(expected)) = (&current, expected_version) {
+            if current.version != expected {
+                return Err(SecurityError::Conflict(format!(
+                    "Flag is at version {}, not {}",
+                    current.version, expected
+                )));
+            }
+        }
+
+        let flag = sqlx::query_as::<_, FeatureFlag>(&format!(
+            "INSERT INTO feature_flags (key, description, enabled, tenants, subjects, rollout_percentage, updated_by) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7) \
+             ON CONFLICT (key) DO UPDATE SET description = $2, enabled = $3, tenants = $4, subjects = $5, \
+             rollout_percentage = $6, updated_by = $7, updated_at = NOW(), version = feature_flags.version + 1 \
+             RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(key)
+        .bind(&request.description)
+        .bind(request.enabled)
+        .bind(&request.tenants)
+        .bind(&request.subjects)
+        .bind(request.rollout_percentage)
+        .bind(author)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        changes::record(tx, NewChange {
+            resource_type: "feature_flag",
+            resource_id: key.to_string(),
+            version: Some(flag.version),
+            action: if current.is_some() { "update" } else { "create" },
+            author,
+            tenant_id: None,
+            before: current.as_ref().and_then(|f| serde_json::to_value(f.editable()).ok()),
+            after: serde_json::to_value(flag.editable()).ok(),
+        }).await?;
+        Ok(flag)
+    }
+
+    pub async fn delete(&self, actor: &Principal, key: &str) -> Result<(), SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let deleted = sqlx::query_as::<_, FeatureFlag>(&format!(
+            "DELETE FROM feature_flags WHERE key = $1 RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(key)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("Flag '{}' not found", key)))?;
+
+        changes::record(&mut tx, NewChange {
+            resource_type: "feature_flag",
+            resource_id: key.to_string(),
+            version: Some(deleted.version),
+            action: "delete",
+            author: &actor.subject,
+            tenant_id: None,
+            before: serde_json::to_value(deleted.editable()).ok(),
+            after: None,
+        }).await?;
+        tx.commit().await?;
+
+        self.refresh().await?;
+        Ok(())
+    }
+}
+
+/// Background loop picking up flag changes made through other instances.
+pub async fn run_refresh(state: web::Data<crate::AppState>) {
+    let interval_secs = state.config.flags.refresh_interval_secs;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+
+    loop {
+        interval.tick().await;
+        match state.feature_flags.refresh().await {
+            Ok(()) => state.startup.recovered("feature_flags"),
+            Err(e) => error!("Feature flag refresh failed: {:?}", e),
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Feature flag operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Feature flag operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit_flag_change(state: &crate::AppState, actor: &Principal, action: &str, key: &str, detail: serde_json::Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: actor.tenant_id.clone(),
+        actor: actor.subject.clone(),
+        actor_ip: None,
+        action: action.to_string(),
+        resource: format!("feature_flag:{}", key),
+        outcome: "success".to_string(),
+        payload: detail,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit flag change on {}: {:?}", key, e);
+    }
+}
+
+pub async fn evaluate_handler(
+    req: HttpRequest,
+    request: web::Json<EvaluateRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let flags = state.feature_flags.evaluate_all(&request.flags, &FlagContext::from(&principal));
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "flags": flags
+    })))
+}
+
+pub async fn list_flags_handler(
+    req: HttpRequest,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.feature_flags.list().await {
+        Ok(flags) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "flags": flags
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_flag_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.feature_flags.get(&path).await {
+        Ok(flag) => Ok(Validators::new(concurrency::etag(flag.version))
+            .last_modified(flag.updated_at)
+            .respond(&req, CacheControl::Revalidate, &flag)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn put_flag_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    request: web::Json<FlagRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    // New flags need no precondition; replacing one does. Version 0 never
+    // matches, so a concurrent create still ends in a conflict.
+    let if_match = IfMatch::from_request(&req);
+    let is_new = if_match == IfMatch::Missing
+        && matches!(state.feature_flags.get(&path).await, Err(SecurityError::NotFound(_)));
+    let expected_version = if is_new {
+        Some(0)
+    } else {
+        match if_match.expected_version() {
+            Ok(version) => version,
+            Err(response) => return Ok(response),
+        }
+    };
+
+    let key = path.into_inner();
+    let request = request.into_inner();
+    match state.feature_flags.put(&principal, &key, expected_version, request.clone()).await {
+        Ok(flag) => {
+            audit_flag_change(&state, &principal, "feature_flag.put", &key, serde_json::to_value(&request).unwrap_or_default()).await;
+            Ok(HttpResponse::Ok()
+                .insert_header((header::ETAG, concurrency::etag(flag.version)))
+                .json(flag))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn delete_flag_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.feature_flags.delete(&principal, &path).await {
+        Ok(()) => {
+            audit_flag_change(&state, &principal, "feature_flag.delete", &path, serde_json::json!({})).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/flags/evaluate", web::post().to(evaluate_handler))
+        .service(
+            web::scope("/admin/flags")
+                .route("", web::get().to(list_flags_handler))
+                .route("/{key}", web::get().to(get_flag_handler))
+                .route("/{key}", web::put().to(put_flag_handler))
+                .route("/{key}", web::delete().to(delete_flag_handler))
+        );
+}
@@ -0,0 +1,630 @@
+/*!
+Forensics Module
+Encrypted copies of suspicious requests for analysts
+
+With `FORENSICS_ENABLED`, requests that a pipeline stage listed in
+`FORENSICS_STAGES` rejects (a lockout, a rate limit, a hook's `reject`) or
+flags (a hook's `capture`, see `hooks`) are copied into a store analysts
+can study attacks from, without request logging for everyone else. A
+capture holds the method, path, query, headers and up to
+`FORENSICS_MAX_BODY_BYTES` of the body, with the decision around it: the
+stage, why, the status answered, the caller and its rate limit.
+Whistleblower requests are never captured, as nothing about their senders
+may be kept (see `whistleblower`).
+
+Copies are sanitized before they are stored. Headers in
+`FORENSICS_REDACT_HEADERS`, and JSON, form and query fields whose names
+contain an entry of `FORENSICS_REDACT_FIELDS`, keep their name but lose
+their value; bodies that are neither JSON nor text are kept as base64. The
+copy is then encrypted under the current data key, bound to its capture.
+Stage, reason, status, method, path, caller and address stay readable, so
+captures can be searched without opening them.
+
+At most `FORENSICS_MAX_PER_MINUTE` captures are taken per caller (account,
+else client address), so a flood does not fill the store, and captures are
+written from a bounded queue (`FORENSICS_QUEUE_SIZE`), so requests never
+wait on storage. Each capture is counted in
+`cotai_forensic_captures_total{stage,outcome}` as `queued`, `throttled` or
+`dropped`.
+
+Captures are the retention engine's `forensics` data type, kept for
+`FORENSICS_RETENTION_DAYS` unless `RETENTION_POLICIES` says otherwise and
+exempt while under a legal hold (see `retention`).
+
+Holders of `FORENSICS_ROLES` review captures under `/forensics/captures`:
+listing reads only the readable fields; opening a capture decrypts it and
+is audited as `forensics.read`, and deleting one early as
+`forensics.delete`.
+*/
+
+use actix_http::BoxedPayloadStream;
+use actix_web::dev::{Payload, ServiceRequest};
+use actix_web::error::PayloadError;
+use actix_web::http::header::{self, HeaderMap};
+use actix_web::http::StatusCode;
+use actix_web::web::{self, Bytes, BytesMut};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::receipts::{self, RECEIPT_HEADER};
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, client_ip};
+use crate::clock::Clock;
+use crate::config::{Config, ForensicsConfig};
+use crate::crypto::{CryptoService, DecryptionRequest, EncryptionRequest};
+use crate::errors::SecurityError;
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::rate_limiting::{Aggregation, Algorithm, Decision, Rule};
+use crate::storage::Storage;
+use crate::whistleblower;
+use crate::AppState;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// How long a capture waits for the rest of a body before keeping what
+/// arrived; the request itself is not cut short.
+const BODY_WAIT: Duration = Duration::from_secs(2);
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "created_at", column: "created_at", kind: KeyKind::Timestamp },
+];
+
+const CAPTURE_COLUMNS: &str = "id, tenant_id, stage, reason, status, method, path, client_ip, subject, \
+    body_bytes, body_truncated, created_at";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct CaptureInfo {
+    pub id: Uuid,
+    pub tenant_id: Option<String>,
+    /// Pipeline stage that rejected or flagged the request.
+    pub stage: String,
+    pub reason: String,
+    /// What the request was answered with, when the stage rejected it.
+    pub status: Option<i32>,
+    pub method: String,
+    pub path: String,
+    pub client_ip: Option<String>,
+    pub subject: Option<String>,
+    /// Body bytes kept.
+    pub body_bytes: i32,
+    pub body_truncated: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, FromRow)]
+struct SealedCapture {
+    encrypted_capture: String,
+    key_id: String,
+    nonce: String,
+    context_hash: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CaptureFilter {
+    pub stage: Option<String>,
+    pub client_ip: Option<String>,
+    pub subject: Option<String>,
+    pub tenant_id: Option<String>,
+    /// Path prefix.
+    pub path: Option<String>,
+}
+
+/// Why a request is worth keeping, left on it by a stage that let it pass
+/// or is about to reject it.
+#[derive(Debug, Clone)]
+pub struct Flag {
+    pub stage: &'static str,
+    pub reason: String,
+}
+
+pub fn flag(req: &ServiceRequest, stage: &'static str, reason: String) {
+    req.extensions_mut().insert(Flag { stage, reason });
+}
+
+/// A capture on its way to storage, not yet encrypted.
+struct Pending {
+    info: CaptureInfo,
+    document: Value,
+}
+
+pub struct ForensicStore {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: ForensicsConfig,
+    /// Captures per caller, counted in the rate limiter.
+    throttle: Rule,
+    sender: mpsc::Sender<Pending>,
+    /// Taken by `run_writer`.
+    receiver: Mutex<Option<mpsc::Receiver<Pending>>>,
+}
+
+/// Whether a field called `name` holds a value `fields` keeps out of storage.
+fn redacts(fields: &[String], name: &str) -> bool {
+    let name = name.to_lowercase();
+    fields.iter().any(|field| name.contains(field.as_str()))
+}
+
+/// `value` with the values of fields `fields` redacts replaced, at any depth.
+pub fn redact_json(fields: &[String], value: Value) -> Value {
+    match value {
+        Value::Object(entries) => Value::Object(
+            entries
+                .into_iter()
+                .map(|(name, value)| {
+                    let value = if redacts(fields, &name) {
+                        Value::String(REDACTED.to_string())
+                    } else {
+                        redact_json(fields, value)
+                    };
+                    (name, value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(|item| redact_json(fields, item)).collect()),
+        other => other,
+    }
+}
+
+/// `name=value&...` with the values of fields `fields` redacts replaced.
+pub fn redact_pairs(fields: &[String], pairs: &str) -> String {
+    pairs
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) if redacts(fields, name) => format!("{}={}", name, REDACTED),
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn capture_context(id: Uuid) -> HashMap<String, String> {
+    HashMap::from([
+        ("purpose".to_string(), "forensics".to_string()),
+        ("capture_id".to_string(), id.to_string()),
+    ])
+}
+
+impl ForensicStore {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let (sender, receiver) = mpsc::channel(config.forensics.queue_size);
+        let throttle = Rule {
+            name: "forensics".to_string(),
+            algorithm: Algorithm::SlidingWindow,
+            limit: config.forensics.max_per_minute,
+            period: Duration::from_secs(60),
+            aggregation: Aggregation::Ip,
+        };
+
+        info!(
+            "Forensic store initialized: {}",
+            if config.forensics.enabled { "capturing" } else { "disabled" }
+        );
+        Ok(Self {
+            storage,
+            clock,
+            config: config.forensics.clone(),
+            throttle,
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+        })
+    }
+
+    fn redact_json(&self, value: Value) -> Value {
+        redact_json(&self.config.redact_fields, value)
+    }
+
+    fn redact_pairs(&self, pairs: &str) -> String {
+        redact_pairs(&self.config.redact_fields, pairs)
+    }
+
+    fn headers(&self, headers: &HeaderMap) -> Map<String, Value> {
+        let mut copied = Map::new();
+        for name in headers.keys() {
+            let value = if self.config.redact_headers.iter().any(|redacted| redacted == name.as_str()) {
+                REDACTED.to_string()
+            } else {
+                headers
+                    .get_all(name)
+                    .map(|value| value.to_str().unwrap_or("[binary]"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            copied.insert(name.as_str().to_string(), Value::String(value));
+        }
+        copied
+    }
+
+    /// The body as stored, and how it is encoded.
+    fn body(&self, bytes: &[u8], truncated: bool, content_type: &str) -> (Value, &'static str) {
+        if bytes.is_empty() {
+            return (Value::Null, "none");
+        }
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        if (mime == "application/json" || mime.ends_with("+json")) && !truncated {
+            if let Ok(value) = serde_json::from_slice::<Value>(bytes) {
+                return (self.redact_json(value), "json");
+            }
+        }
+        match std::str::from_utf8(bytes) {
+            Ok(text) if mime == "application/x-www-form-urlencoded" => (Value::String(self.redact_pairs(text)), "form"),
+            Ok(text) => (Value::String(text.to_string()), "text"),
+            Err(_) => (Value::String(base64::encode(bytes)), "base64"),
+        }
+    }
+
+    fn enqueue(&self, pending: Pending) -> bool {
+        match self.sender.try_send(pending) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Full(pending) | mpsc::error::TrySendError::Closed(pending)) => {
+                warn!("Forensic queue full, dropped capture of {} {}", pending.info.method, pending.info.path);
+                false
+            }
+        }
+    }
+
+    async fn store(&self, crypto: &CryptoService, pending: Pending) -> Result<(), SecurityError> {
+        let info = pending.info;
+        let sealed = crypto.encrypt_data(EncryptionRequest {
+            data: pending.document.to_string(),
+            key_id: None,
+            context: Some(capture_context(info.id)),
+        }).await?;
+
+        sqlx::query(
+            "INSERT INTO forensic_captures (id, tenant_id, stage, reason, status, method, path, client_ip, subject, \
+             body_bytes, body_truncated, encrypted_capture, key_id, nonce, context_hash, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)",
+        )
+        .bind(info.id)
+        .bind(&info.tenant_id)
+        .bind(&info.stage)
+        .bind(&info.reason)
+        .bind(info.status)
+        .bind(&info.method)
+        .bind(&info.path)
+        .bind(&info.client_ip)
+        .bind(&info.subject)
+        .bind(info.body_bytes)
+        .bind(info.body_truncated)
+        .bind(&sealed.encrypted_data)
+        .bind(&sealed.key_id)
+        .bind(&sealed.nonce)
+        .bind(&sealed.context_hash)
+        .bind(info.created_at)
+        .execute(self.storage.pool())
+        .await?;
+        Ok(())
+    }
+
+    pub async fn list(&self, filter: &CaptureFilter, page: &PageRequest) -> Result<Page<CaptureInfo>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM forensic_captures WHERE 1 = 1",
+            CAPTURE_COLUMNS
+        ));
+        if let Some(stage) = &filter.stage {
+            builder.push(" AND stage = ").push_bind(stage.clone());
+        }
+        if let Some(client_ip) = &filter.client_ip {
+            builder.push(" AND client_ip = ").push_bind(client_ip.clone());
+        }
+        if let Some(subject) = &filter.subject {
+            builder.push(" AND subject = ").push_bind(subject.clone());
+        }
+        if let Some(tenant_id) = &filter.tenant_id {
+            builder.push(" AND tenant_id = ").push_bind(tenant_id.clone());
+        }
+        if let Some(path) = &filter.path {
+            builder.push(" AND starts_with(path, ").push_bind(path.clone()).push(")");
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let captures = builder
+            .build_query_as::<CaptureInfo>()
+            .fetch_all(self.storage.pool())
+            .await?;
+        Ok(page.page(captures, |capture, _| (SortKey::Timestamp(capture.created_at), capture.id)))
+    }
+
+    fn not_found(id: Uuid) -> SecurityError {
+        SecurityError::NotFound(format!("No capture {}", id))
+    }
+
+    /// A capture with its decrypted copy.
+    pub async fn open(&self, crypto: &CryptoService, id: Uuid) -> Result<(CaptureInfo, Value), SecurityError> {
+        let info = sqlx::query_as::<_, CaptureInfo>(&format!(
+            "SELECT {} FROM forensic_captures WHERE id = $1",
+            CAPTURE_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| Self::not_found(id))?;
+
+        let sealed = sqlx::query_as::<_, SealedCapture>(
+            "SELECT encrypted_capture, key_id, nonce, context_hash FROM forensic_captures WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| Self::not_found(id))?;
+        if crypto.context_hash(Some(&capture_context(id)))? != sealed.context_hash {
+            error!("Forensic capture {} is bound to another capture", id);
+            return Err(SecurityError::CryptoError("Capture binding mismatch".to_string()));
+        }
+        let document = crypto.decrypt_data(DecryptionRequest {
+            encrypted_data: sealed.encrypted_capture,
+            key_id: sealed.key_id,
+            nonce: sealed.nonce,
+            context_hash: sealed.context_hash,
+        }).await?;
+        let document = serde_json::from_str(&document)
+            .map_err(|e| SecurityError::CryptoError(format!("Capture {} does not decode: {}", id, e)))?;
+        Ok((info, document))
+    }
+
+    pub async fn delete(&self, id: Uuid) -> Result<CaptureInfo, SecurityError> {
+        sqlx::query_as::<_, CaptureInfo>(&format!(
+            "DELETE FROM forensic_captures WHERE id = $1 RETURNING {}",
+            CAPTURE_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| Self::not_found(id))
+    }
+}
+
+/// Read up to `limit` bytes of the body, putting all of it back for the
+/// handler. Returns what was read and whether the body went on.
+pub async fn peek_body(req: &mut ServiceRequest, limit: usize) -> (Bytes, bool) {
+    let mut payload = req.take_payload();
+    let mut items: Vec<Result<Bytes, PayloadError>> = Vec::new();
+    let mut body = BytesMut::new();
+    let reading = async {
+        while body.len() <= limit {
+            let Some(item) = payload.next().await else {
+                break;
+            };
+            let failed = item.is_err();
+            if let Ok(chunk) = &item {
+                body.extend_from_slice(chunk);
+            }
+            items.push(item);
+            if failed {
+                break;
+            }
+        }
+    };
+    let timed_out = tokio::time::timeout(BODY_WAIT, reading).await.is_err();
+
+    let truncated = timed_out || body.len() > limit;
+    body.truncate(limit);
+    let rest: BoxedPayloadStream = Box::pin(stream::iter(items).chain(payload));
+    req.set_payload(Payload::from(rest));
+    (body.freeze(), truncated)
+}
+
+fn count(state: &AppState, stage: &str, outcome: &str) {
+    state.metrics_service.increment("cotai_forensic_captures_total", &[("stage", stage), ("outcome", outcome)]);
+}
+
+/// Copy `req` for analysts if `stage` is captured and rejected it with
+/// `status` or flagged it.
+pub async fn capture(state: &AppState, req: &mut ServiceRequest, stage: &str, status: Option<StatusCode>) {
+    let forensics = &state.forensics;
+    let flag = req.extensions_mut().remove::<Flag>();
+    if !forensics.config.enabled || !forensics.config.stages.iter().any(|captured| captured == stage) {
+        return;
+    }
+    // A capture would tie an anonymous reporter to their address and headers
+    if req.path().starts_with(whistleblower::PATH_PREFIX) {
+        return;
+    }
+    let reason = match (flag, status) {
+        (Some(flag), _) if flag.stage == stage => flag.reason,
+        (_, Some(status)) => format!("rejected with {}", status.as_u16()),
+        _ => return,
+    };
+
+    let principal = state.auth_service.authenticate(req.request()).ok();
+    let address = client_ip(req.request());
+    let caller = match (&principal, &address) {
+        (Some(principal), _) => principal.subject.clone(),
+        (None, Some(address)) => address.clone(),
+        (None, None) => String::new(),
+    };
+    if !state.rate_limiter.acquire(&forensics.throttle, &caller).await.allowed {
+        count(state, stage, "throttled");
+        return;
+    }
+
+    let (bytes, truncated) = peek_body(req, forensics.config.max_body_bytes).await;
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let (body, encoding) = forensics.body(&bytes, truncated, &content_type);
+    let rate_limit = req.extensions().get::<Decision>().map(|decision| {
+        serde_json::json!({ "limit": decision.limit, "remaining": decision.remaining })
+    });
+
+    let info = CaptureInfo {
+        id: Uuid::new_v4(),
+        tenant_id: principal.as_ref().and_then(|p| p.tenant_id.clone()),
+        stage: stage.to_string(),
+        reason,
+        status: status.map(|status| status.as_u16() as i32),
+        method: req.method().to_string(),
+        path: req.path().to_string(),
+        client_ip: address,
+        subject: principal.as_ref().map(|p| p.subject.clone()),
+        body_bytes: bytes.len() as i32,
+        body_truncated: truncated,
+        created_at: forensics.clock.now(),
+    };
+    let document = serde_json::json!({
+        "method": info.method,
+        "path": info.path,
+        "query": forensics.redact_pairs(req.query_string()),
+        "version": format!("{:?}", req.version()),
+        "headers": forensics.headers(req.headers()),
+        "body": {
+            "encoding": encoding,
+            "content": body,
+            "bytes": info.body_bytes,
+            "truncated": truncated
+        },
+        "decision": {
+            "stage": info.stage,
+            "reason": info.reason,
+            "status": info.status,
+            "subject": info.subject,
+            "roles": principal.as_ref().map(|p| p.roles.clone()),
+            "tenant_id": info.tenant_id,
+            "client_ip": info.client_ip,
+            "rate_limit": rate_limit
+        }
+    });
+
+    let queued = forensics.enqueue(Pending { info, document });
+    count(state, stage, if queued { "queued" } else { "dropped" });
+}
+
+/// Encrypt and store captures as they are taken.
+pub async fn run_writer(state: web::Data<AppState>) {
+    let Some(mut captures) = state.forensics.receiver.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+        return;
+    };
+    while let Some(pending) = captures.recv().await {
+        let id = pending.info.id;
+        if let Err(e) = state.forensics.store(&state.crypto_service, pending).await {
+            error!("Forensic capture {} lost: {:?}", id, e);
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Forensic operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Forensic operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit(state: &AppState, req: &HttpRequest, actor: &str, action: &str, capture: &CaptureInfo) -> Option<String> {
+    receipts::record_or_warn(state, NewAuditEvent {
+        tenant_id: capture.tenant_id.clone(),
+        actor: actor.to_string(),
+        actor_ip: client_ip(req),
+        action: action.to_string(),
+        resource: format!("forensic_capture:{}", capture.id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "stage": capture.stage,
+            "reason": capture.reason,
+            "method": capture.method,
+            "path": capture.path,
+            "client_ip": capture.client_ip,
+            "subject": capture.subject,
+            "captured_at": capture.created_at
+        }),
+    }).await
+}
+
+fn with_receipt(mut response: actix_web::HttpResponseBuilder, receipt: Option<String>) -> actix_web::HttpResponseBuilder {
+    if let Some(receipt) = receipt {
+        response.insert_header((RECEIPT_HEADER, receipt));
+    }
+    response
+}
+
+pub async fn list_handler(
+    req: HttpRequest,
+    filter: web::Query<CaptureFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_roles(&req, &state.config.forensics.roles) {
+        return Ok(auth_error_response(&e));
+    }
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.forensics.list(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "captures": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn read_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_roles(&req, &state.config.forensics.roles) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.forensics.open(&state.crypto_service, path.into_inner()).await {
+        Ok((capture, document)) => {
+            let receipt = audit(&state, &req, &principal.subject, "forensics.read", &capture).await;
+            Ok(with_receipt(HttpResponse::Ok(), receipt)
+                .insert_header(("Cache-Control", "no-store"))
+                .json(serde_json::json!({
+                    "capture": capture,
+                    "request": document
+                })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn delete_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_roles(&req, &state.config.forensics.roles) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.forensics.delete(path.into_inner()).await {
+        Ok(capture) => {
+            let receipt = audit(&state, &req, &principal.subject, "forensics.delete", &capture).await;
+            Ok(with_receipt(HttpResponse::NoContent(), receipt).finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/forensics")
+            .route("/captures", web::get().to(list_handler))
+            .route("/captures/{id}", web::get().to(read_handler))
+            .route("/captures/{id}", web::delete().to(delete_handler)),
+    );
+}
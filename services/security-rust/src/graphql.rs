@@ -0,0 +1,183 @@
+/*!
+Admin GraphQL Module
+Read-only inspection API served on the admin listener (feature `graphql`)
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Guard, Json, Object, Schema, SimpleObject,
+};
+use chrono::{DateTime, Utc};
+use tracing::{error, warn};
+
+use crate::audit::visibility::ProjectedAuditEvent;
+use crate::audit::AuditQuery;
+use crate::auth::{auth_error_response, Principal};
+use crate::errors::SecurityError;
+use crate::AppState;
+
+pub type AdminSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Field guard requiring one of the configured admin roles.
+struct AdminGuard;
+
+impl Guard for AdminGuard {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        let principal = ctx.data::<Principal>()?;
+        let state = ctx.data::<web::Data<AppState>>()?;
+        if principal.has_any_role(&state.config.auth.admin_roles) {
+            Ok(())
+        } else {
+            Err("Admin role required".into())
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct PrincipalNode {
+    subject: String,
+    tenant_id: Option<String>,
+    roles: Vec<String>,
+    scopes: Vec<String>,
+}
+
+#[derive(SimpleObject)]
+struct CryptoKeyNode {
+    key_id: String,
+    created_at: DateTime<Utc>,
+    state: String,
+}
+
+#[derive(SimpleObject)]
+struct AuditEventNode {
+    id: String,
+    occurred_at: DateTime<Utc>,
+    tenant_id: Option<String>,
+    actor: String,
+    actor_ip: Option<String>,
+    action: String,
+    resource: String,
+    outcome: String,
+    payload: Json<serde_json::Value>,
+    source: String,
+    redacted: bool,
+}
+
+impl From<ProjectedAuditEvent> for AuditEventNode {
+    fn from(event: ProjectedAuditEvent) -> Self {
+        Self {
+            id: event.id.to_string(),
+            occurred_at: event.occurred_at,
+            tenant_id: event.tenant_id,
+            actor: event.actor,
+            actor_ip: event.actor_ip,
+            action: event.action,
+            resource: event.resource,
+            outcome: event.outcome,
+            payload: Json(event.payload),
+            source: event.source,
+            redacted: event.redacted,
+        }
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// The authenticated caller.
+    async fn me(&self, ctx: &Context<'_>) -> async_graphql::Result<PrincipalNode> {
+        let principal = ctx.data::<Principal>()?.clone();
+        Ok(PrincipalNode {
+            subject: principal.subject,
+            tenant_id: principal.tenant_id,
+            roles: principal.roles,
+            scopes: principal.scopes,
+        })
+    }
+
+    /// Metadata of the data-encryption keys; never key material.
+    #[graphql(guard = "AdminGuard")]
+    async fn crypto_keys(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<CryptoKeyNode>> {
+        let state = ctx.data::<web::Data<AppState>>()?;
+        Ok(state.crypto_service
+            .key_metadata()
+            .into_iter()
+            .map(|k| CryptoKeyNode {
+                key_id: k.key_id,
+                created_at: k.created_at,
+                state: k.state.as_str().to_string(),
+            })
+            .collect())
+    }
+
+    /// Audit events, shaped by the same role-based view as the REST API.
+    #[allow(clippy::too_many_arguments)]
+    async fn audit_events(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<String>,
+        tenant_id: Option<String>,
+        actor: Option<String>,
+        action: Option<String>,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        limit: Option<i64>,
+    ) -> async_graphql::Result<Vec<AuditEventNode>> {
+        let principal = ctx.data::<Principal>()?;
+        let state = ctx.data::<web::Data<AppState>>()?;
+
+        let query = AuditQuery {
+            tenant_id,
+            actor,
+            action,
+            since,
+            until,
+            limit,
+            filter,
+            ..Default::default()
+        };
+
+        match state.audit_service.query_visible(principal, query).await {
+            Ok(events) => Ok(events.into_iter().map(AuditEventNode::from).collect()),
+            Err(SecurityError::ValidationError(msg)) | Err(SecurityError::AccessDenied(msg)) => Err(msg.into()),
+            Err(e) => {
+                error!("GraphQL audit query failed: {:?}", e);
+                Err("Audit query failed".into())
+            }
+        }
+    }
+}
+
+pub fn build_schema() -> AdminSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(8)
+        .limit_complexity(200)
+        .disable_introspection()
+        .finish()
+}
+
+// HTTP handlers
+
+pub async fn graphql_handler(
+    req: HttpRequest,
+    request: web::Json<async_graphql::Request>,
+    schema: web::Data<AdminSchema>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => {
+            warn!("GraphQL request rejected: {:?}", e);
+            return Ok(auth_error_response(&e));
+        }
+    };
+
+    let request = request.into_inner().data(principal).data(state.clone());
+    let response = schema.execute(request).await;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/graphql", web::post().to(graphql_handler));
+}
@@ -0,0 +1,378 @@
+/*!
+gRPC Module
+Internal service-to-service API over gRPC (feature `grpc`)
+
+Backends making many small encrypt, decrypt and signing calls use this
+instead of JSON over HTTP: payloads travel as raw bytes, not base64, over
+long-lived HTTP/2 connections. The service is defined in
+`proto/cotai/security/v1/security.proto` and served on `GRPC_PORT`, on a
+runtime of its own so the HTTP workers are not shared.
+
+Every RPC runs on the same services as its HTTP counterpart, authenticates
+the same credentials (`authorization` or `x-api-key` metadata) and needs a
+scope, admins excepted: `crypto:encrypt`, `crypto:decrypt`, `crypto:sign`,
+`crypto:verify`, `crypto:hash`, `ratelimit:check` and `audit:write`.
+Decryption takes a purpose of use, counts against the read volume guard
+(see `crypto::guard`) and is audited like `POST /crypto/decrypt`.
+`EmitAuditEvent` records on behalf of the calling
+service, which names itself in the event's `emitted_by`.
+
+At shutdown the listener stops with the HTTP ones, letting calls in
+flight finish.
+*/
+
+use actix_web::web;
+use chrono::{DateTime, Utc};
+use ring::digest::{digest, SHA256};
+use std::future::Future;
+use std::net::{SocketAddr, ToSocketAddrs};
+use tokio::sync::oneshot;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use tracing::{error, info};
+
+use crate::audit::receipts;
+use crate::audit::NewAuditEvent;
+use crate::auth::Principal;
+use crate::crypto::{audit_cache_served, audit_compromised_use, guard, purpose, KeyState, VerifyRequest};
+use crate::errors::SecurityError;
+use crate::network;
+use crate::signing::{Algorithm, CALLER_DOMAIN};
+use crate::AppState;
+
+pub mod proto {
+    tonic::include_proto!("cotai.security.v1");
+}
+
+use proto::security_server::{Security, SecurityServer};
+
+const API_KEY_METADATA: &str = "x-api-key";
+const MAX_PASSWORD_BYTES: usize = 1024;
+const MAX_AUDIT_FIELD_CHARS: usize = 256;
+
+fn status(e: SecurityError) -> Status {
+    match e {
+        SecurityError::ValidationError(msg) => Status::invalid_argument(msg),
+        SecurityError::NotFound(msg) => Status::not_found(msg),
+        SecurityError::Conflict(msg) => Status::failed_precondition(msg),
+        SecurityError::AuthError(msg) => Status::unauthenticated(msg),
+        SecurityError::AccessDenied(msg) => Status::permission_denied(msg),
+        e => {
+            error!("gRPC call failed: {:?}", e);
+            Status::internal("Internal error")
+        }
+    }
+}
+
+/// Who is calling, and from where.
+struct Caller {
+    principal: Principal,
+    ip: Option<String>,
+}
+
+pub struct SecurityRpc {
+    state: web::Data<AppState>,
+}
+
+impl SecurityRpc {
+    pub fn new(state: web::Data<AppState>) -> Self {
+        Self { state }
+    }
+
+    /// Authenticate the call and require `scope`, as `authorize_scope` does
+    /// for HTTP.
+    fn authorize<T>(&self, request: &Request<T>, scope: &str) -> Result<Caller, Status> {
+        let metadata = request.metadata();
+        let api_key = metadata
+            .get(API_KEY_METADATA)
+            .map(|key| key.to_str().map_err(|_| Status::unauthenticated("Invalid API key")))
+            .transpose()?;
+        let authorization = metadata.get("authorization").and_then(|value| value.to_str().ok());
+        let ip = request.remote_addr().map(|addr| network::normalize(addr.ip()).to_string());
+
+        let principal = self.state.auth_service
+            .authenticate_credentials(api_key, authorization, ip.as_deref())
+            .map_err(status)?;
+        if !principal.scopes.iter().any(|s| s == scope) && !principal.has_any_role(&self.state.config.auth.admin_roles) {
+            return Err(Status::permission_denied(format!("{} lacks scope {}", principal.subject, scope)));
+        }
+        Ok(Caller { principal, ip })
+    }
+}
+
+fn algorithm(name: Option<&str>) -> Result<Algorithm, Status> {
+    match name {
+        None => Ok(Algorithm::Hs256),
+        Some(name) => Algorithm::parse(name)
+            .ok_or_else(|| Status::invalid_argument(format!("Unknown algorithm '{}'", name))),
+    }
+}
+
+#[tonic::async_trait]
+impl Security for SecurityRpc {
+    async fn encrypt(
+        &self,
+        request: Request<proto::EncryptRequest>,
+    ) -> Result<Response<proto::EncryptResponse>, Status> {
+        self.authorize(&request, "crypto:encrypt")?;
+        let request = request.into_inner();
+        let context = (!request.context.is_empty()).then_some(&request.context);
+
+        let crypto = &self.state.crypto_service;
+        let sealed = crypto.encrypt_bytes(request.plaintext, request.key_id, context).map_err(status)?;
+        if crypto.served_from_cache(&sealed.key_id) {
+            audit_cache_served(&self.state, "encrypt", &sealed.key_id).await;
+        }
+        Ok(Response::new(proto::EncryptResponse {
+            ciphertext: sealed.ciphertext,
+            key_id: sealed.key_id,
+            nonce: sealed.nonce,
+            context_hash: sealed.context_hash,
+        }))
+    }
+
+    async fn decrypt(
+        &self,
+        request: Request<proto::DecryptRequest>,
+    ) -> Result<Response<proto::DecryptResponse>, Status> {
+        let caller = self.authorize(&request, "crypto:decrypt")?;
+        let request = request.into_inner();
+        let state = &self.state;
+        let purpose = purpose::check(state, caller.principal.tenant_id.as_deref(), request.purpose.as_deref(), false)
+            .await
+            .map_err(status)?;
+        guard::admit(state, Some(&caller.principal), caller.ip.as_deref(), 1).await.map_err(status)?;
+
+        // Hashed as HTTP callers send it, so resources match across interfaces
+        let resource = format!("ciphertext:{}", hex::encode(digest(&SHA256, base64::encode(&request.ciphertext).as_bytes())));
+        let crypto = &state.crypto_service;
+        let plaintext = crypto
+            .decrypt_bytes(&request.key_id, &request.nonce, request.ciphertext, request.context_hash.as_deref())
+            .map_err(status)?;
+        if crypto.served_from_cache(&request.key_id) {
+            audit_cache_served(state, "decrypt", &request.key_id).await;
+        }
+        if crypto.key_state(&request.key_id) == Some(KeyState::Compromised) {
+            audit_compromised_use(state, "decrypt", &request.key_id).await;
+        }
+
+        let audited = purpose.is_some() || state.config.audit.receipt_actions.iter().any(|action| action == "crypto.decrypt");
+        let receipt = if audited {
+            receipts::record_or_warn(state, NewAuditEvent {
+                tenant_id: caller.principal.tenant_id.clone(),
+                actor: caller.principal.subject.clone(),
+                actor_ip: caller.ip,
+                action: "crypto.decrypt".to_string(),
+                resource,
+                outcome: "success".to_string(),
+                payload: serde_json::json!({ "key_id": request.key_id, "purpose": purpose }),
+            }).await
+        } else {
+            None
+        };
+        Ok(Response::new(proto::DecryptResponse { plaintext, receipt }))
+    }
+
+    async fn sign(
+        &self,
+        request: Request<proto::SignRequest>,
+    ) -> Result<Response<proto::SignResponse>, Status> {
+        self.authorize(&request, "crypto:sign")?;
+        let request = request.into_inner();
+        let algorithm = algorithm(request.algorithm.as_deref())?;
+
+        let signed = self.state.crypto_service
+            .generate_signature(CALLER_DOMAIN, &request.data, request.key_id.as_deref(), algorithm)
+            .map_err(status)?;
+        Ok(Response::new(proto::SignResponse {
+            signature: signed.signature,
+            key_id: signed.key_id,
+            algorithm: signed.algorithm.as_str().to_string(),
+            timestamp: signed.timestamp.to_rfc3339(),
+        }))
+    }
+
+    async fn verify(
+        &self,
+        request: Request<proto::VerifyRequest>,
+    ) -> Result<Response<proto::VerifyResponse>, Status> {
+        self.authorize(&request, "crypto:verify")?;
+        let request = request.into_inner();
+        let timestamp = request.timestamp
+            .map(|timestamp| {
+                DateTime::parse_from_rfc3339(&timestamp)
+                    .map(|timestamp| timestamp.with_timezone(&Utc))
+                    .map_err(|_| Status::invalid_argument("timestamp must be RFC 3339"))
+            })
+            .transpose()?;
+
+        let valid = self.state.crypto_service
+            .verify(CALLER_DOMAIN, &VerifyRequest {
+                data: request.data,
+                signature: request.signature,
+                algorithm: Some(algorithm(request.algorithm.as_deref())?),
+                key_id: request.key_id,
+                timestamp,
+            })
+            .map_err(status)?;
+        Ok(Response::new(proto::VerifyResponse { valid }))
+    }
+
+    async fn hash_password(
+        &self,
+        request: Request<proto::HashPasswordRequest>,
+    ) -> Result<Response<proto::HashPasswordResponse>, Status> {
+        self.authorize(&request, "crypto:hash")?;
+        let password = request.into_inner().password;
+        if password.is_empty() || password.len() > MAX_PASSWORD_BYTES {
+            return Err(Status::invalid_argument(format!("password must be 1-{} bytes", MAX_PASSWORD_BYTES)));
+        }
+
+        // Argon2 is deliberately slow; keep it off the runtime's workers
+        let state = self.state.clone();
+        let hash = tokio::task::spawn_blocking(move || state.crypto_service.hash_password(&password))
+            .await
+            .map_err(|_| Status::internal("Internal error"))?
+            .map_err(status)?;
+        Ok(Response::new(proto::HashPasswordResponse { hash }))
+    }
+
+    async fn check_rate_limit(
+        &self,
+        request: Request<proto::CheckRateLimitRequest>,
+    ) -> Result<Response<proto::CheckRateLimitResponse>, Status> {
+        let caller = self.authorize(&request, "ratelimit:check")?;
+        let request = request.into_inner();
+        if request.key.is_empty() {
+            return Err(Status::invalid_argument("key is required"));
+        }
+
+        let limiter = &self.state.rate_limiter;
+        let rule = match limiter.route(&request.path) {
+            Some(rule) => rule,
+            None => {
+                let settings = self.state.tenant_settings.effective(caller.principal.tenant_id.as_deref()).await;
+                limiter.tenant_rule(settings.rate_limit_rpm)
+            }
+        };
+        let decision = limiter.acquire(&rule, &request.key).await;
+        Ok(Response::new(proto::CheckRateLimitResponse {
+            allowed: decision.allowed,
+            limit: decision.limit,
+            remaining: decision.remaining,
+            retry_after_ms: decision.retry_after.as_millis() as u64,
+            reset_ms: decision.reset.as_millis() as u64,
+        }))
+    }
+
+    async fn emit_audit_event(
+        &self,
+        request: Request<proto::EmitAuditEventRequest>,
+    ) -> Result<Response<proto::EmitAuditEventResponse>, Status> {
+        let caller = self.authorize(&request, "audit:write")?;
+        let request = request.into_inner();
+        let state = &self.state;
+
+        for (field, value) in [("action", &request.action), ("resource", &request.resource), ("outcome", &request.outcome)] {
+            if value.is_empty() || value.chars().count() > MAX_AUDIT_FIELD_CHARS {
+                return Err(Status::invalid_argument(format!("{} must be 1-{} characters", field, MAX_AUDIT_FIELD_CHARS)));
+            }
+        }
+        let payload_json = request.payload_json.as_deref().unwrap_or("{}");
+        if payload_json.len() > state.config.grpc.max_audit_payload_bytes {
+            return Err(Status::invalid_argument(format!(
+                "payload_json exceeds {} bytes",
+                state.config.grpc.max_audit_payload_bytes
+            )));
+        }
+        let mut payload = match serde_json::from_str::<serde_json::Value>(payload_json) {
+            Ok(serde_json::Value::Object(payload)) => payload,
+            _ => return Err(Status::invalid_argument("payload_json must be a JSON object")),
+        };
+        payload.insert("emitted_by".to_string(), serde_json::Value::String(caller.principal.subject.clone()));
+
+        let tenant_id = match (&caller.principal.tenant_id, request.tenant_id) {
+            (Some(own), Some(requested)) if *own != requested => {
+                return Err(Status::permission_denied("Events can only be recorded in the caller's tenant"));
+            }
+            (Some(own), _) => Some(own.clone()),
+            (None, requested) => requested,
+        };
+
+        let (id, receipt) = receipts::record(state, NewAuditEvent {
+            tenant_id,
+            actor: request.actor.unwrap_or_else(|| caller.principal.subject.clone()),
+            actor_ip: request.actor_ip.or(caller.ip),
+            action: request.action,
+            resource: request.resource,
+            outcome: request.outcome,
+            payload: serde_json::Value::Object(payload),
+        })
+        .await
+        .map_err(status)?;
+        Ok(Response::new(proto::EmitAuditEventResponse { id: id.to_string(), receipt }))
+    }
+}
+
+/// Serve until `shutdown` completes, then let in-flight calls finish.
+pub async fn serve(
+    state: web::Data<AppState>,
+    addr: SocketAddr,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), tonic::transport::Error> {
+    let max_bytes = state.config.grpc.max_message_bytes;
+    let service = SecurityServer::new(SecurityRpc::new(state))
+        .max_decoding_message_size(max_bytes)
+        .max_encoding_message_size(max_bytes);
+    Server::builder().add_service(service).serve_with_shutdown(addr, shutdown).await
+}
+
+/// The listener started by `spawn`.
+pub struct Listener {
+    stop: oneshot::Sender<()>,
+    thread: std::thread::JoinHandle<()>,
+}
+
+impl Listener {
+    /// Stop accepting calls and wait for those in flight. Blocks.
+    pub fn stop(self) {
+        let _ = self.stop.send(());
+        if self.thread.join().is_err() {
+            error!("gRPC listener thread panicked");
+        }
+    }
+}
+
+/// Start the listener on `GRPC_PORT` in a thread with its own runtime;
+/// does nothing when the port is 0.
+pub fn spawn(state: web::Data<AppState>) -> std::io::Result<Option<Listener>> {
+    let config = &state.config;
+    if config.grpc.port == 0 {
+        return Ok(None);
+    }
+    let addr = (config.host.as_str(), config.grpc.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "GRPC_PORT: no address to bind"))?;
+
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    runtime.enable_all().thread_name("grpc");
+    if config.grpc.worker_threads > 0 {
+        runtime.worker_threads(config.grpc.worker_threads);
+    }
+    let runtime = runtime.build()?;
+
+    info!("gRPC listener starting on {}", addr);
+    let (stop, stopped) = oneshot::channel();
+    let thread = std::thread::Builder::new()
+        .name("grpc".to_string())
+        .spawn(move || {
+            let shutdown = async {
+                let _ = stopped.await;
+            };
+            if let Err(e) = runtime.block_on(serve(state, addr, shutdown)) {
+                error!("gRPC listener on {} stopped: {}", addr, e);
+            }
+        })?;
+    Ok(Some(Listener { stop, thread }))
+}
@@ -0,0 +1,114 @@
+/*!
+Health Module
+Registry of named readiness checks contributed by each module
+
+Modules expose `register_health_checks` next to `configure_routes`; the
+builder collects them once at startup and `/ready` runs whatever is
+registered. A failing critical check makes the service not ready; a
+failing non-critical one only marks it degraded, matching the fallbacks in
+`degraded::MATRIX`.
+*/
+
+use futures::future::{join_all, BoxFuture};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+use crate::AppState;
+
+pub type CheckFuture<'a> = BoxFuture<'a, Result<(), String>>;
+pub type CheckFn = for<'a> fn(&'a AppState) -> CheckFuture<'a>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Criticality {
+    /// The service cannot answer correctly without it.
+    Critical,
+    /// Some capabilities fall back or stop; the rest keeps serving.
+    NonCritical,
+}
+
+#[derive(Clone, Copy)]
+pub struct HealthCheck {
+    pub name: &'static str,
+    pub criticality: Criticality,
+    pub check: CheckFn,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Readiness {
+    Ready,
+    Degraded,
+    NotReady,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub criticality: Criticality,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub latency_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub status: Readiness,
+    pub checks: Vec<CheckResult>,
+}
+
+#[derive(Default)]
+pub struct HealthRegistry {
+    checks: Vec<HealthCheck>,
+}
+
+impl HealthRegistry {
+    /// Add a check. Registering a name again replaces the earlier check, so
+    /// an embedding host can override a built-in one.
+    pub fn register(&mut self, name: &'static str, criticality: Criticality, check: CheckFn) -> &mut Self {
+        self.checks.retain(|c| c.name != name);
+        self.checks.push(HealthCheck { name, criticality, check });
+        self
+    }
+
+    /// Add `other`'s checks, which win on name clashes.
+    pub fn extend(&mut self, other: HealthRegistry) {
+        for check in other.checks {
+            self.register(check.name, check.criticality, check.check);
+        }
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.checks.iter().map(|c| c.name).collect()
+    }
+
+    /// Run every check concurrently, each bounded by `timeout`.
+    pub async fn run(&self, state: &AppState, timeout: Duration) -> HealthReport {
+        let checks = join_all(self.checks.iter().map(|c| async move {
+            let started = Instant::now();
+            let outcome = tokio::time::timeout(timeout, (c.check)(state))
+                .await
+                .unwrap_or_else(|_| Err(format!("Timed out after {}ms", timeout.as_millis())));
+            CheckResult {
+                name: c.name,
+                criticality: c.criticality,
+                passed: outcome.is_ok(),
+                detail: outcome.err(),
+                latency_ms: started.elapsed().as_millis() as u64,
+            }
+        }))
+        .await;
+
+        let failed = |criticality| checks.iter().any(|c| !c.passed && c.criticality == criticality);
+        let status = if failed(Criticality::Critical) {
+            Readiness::NotReady
+        } else if failed(Criticality::NonCritical) {
+            Readiness::Degraded
+        } else {
+            Readiness::Ready
+        };
+
+        HealthReport { status, checks }
+    }
+}
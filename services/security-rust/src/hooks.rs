@@ -0,0 +1,551 @@
+/*!
+Request Hooks
+Config-defined transformations and rejections in the middleware pipeline
+
+`REQUEST_HOOKS_FILE` holds a JSON list of hooks, run in order by the
+`hooks` pipeline stage (see `pipeline`). Each acts on requests, before the
+handler, or on responses, and is limited by path prefix, method and an
+optional `when` expression (see `expr`) over the `request` and, for
+response hooks, the `response`:
+
+```json
+[
+  { "name": "lowercase-tenant", "paths": ["/api/v1/validate"],
+    "action": { "type": "normalize_header", "name": "x-tenant-id", "lowercase": true } },
+  { "name": "strip-debug", "methods": ["POST"],
+    "when": "request.headers['x-client'] == 'legacy-portal'",
+    "action": { "type": "strip_fields", "fields": ["/debug", "/buyer/internal_notes"] } },
+  { "name": "municipal-checks", "paths": ["/api/v1/validate/schema"], "dry_run": true,
+    "action": { "type": "plugin", "name": "sao-paulo-suppliers" } }
+]
+```
+
+Request hooks may `set_header`, `remove_header`, `normalize_header` (trim,
+collapse inner whitespace, optionally lowercase), `strip_fields` from a
+JSON body by JSON pointer, `reject` with a status and message, or run a
+validation `plugin` (see `plugins`) on the JSON body, rejecting the request
+with its errors, or `capture` it for analysts without otherwise touching it
+(see `forensics`); rejections are captured too. Response hooks may only set or remove headers. JSON bodies
+up to `REQUEST_HOOKS_MAX_BODY_BYTES` are read for hooks and expressions as
+`request.body`; it is null for other bodies and in response hooks.
+
+A `dry_run` hook is evaluated but not applied: what it would have done is
+logged and counted, so a hook can be watched on real traffic before it is
+switched on. A hook whose expression or plugin fails is skipped.
+
+Every hook that matches is counted in `cotai_request_hooks_total` by hook
+and outcome (`applied`, `dry_run` or `error`), and timed in
+`cotai_request_hook_seconds`.
+*/
+
+use actix_web::body::BoxBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::web::{self, Bytes};
+use actix_web::{HttpMessage, HttpResponse};
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::time::Instant;
+use tracing::{info, warn};
+
+use crate::auth::client_ip;
+use crate::config::{Config, HooksConfig};
+use crate::errors::SecurityError;
+use crate::expr::{Binding, Environment, Object, Program, Type};
+use crate::{forensics, AppState};
+
+static REQUEST: Object = Object {
+    name: "request",
+    open: false,
+    fields: &[
+        Binding { name: "method", ty: Type::String, doc: "" },
+        Binding { name: "path", ty: Type::String, doc: "" },
+        Binding { name: "query", ty: Type::Map(&Type::String), doc: "" },
+        Binding { name: "headers", ty: Type::Map(&Type::String), doc: "Lowercase names" },
+        Binding { name: "ip", ty: Type::String, doc: "Client address" },
+        Binding { name: "body", ty: Type::Dyn, doc: "JSON body; null if there is none or it is not JSON" },
+    ],
+};
+
+static RESPONSE: Object = Object {
+    name: "response",
+    open: false,
+    fields: &[
+        Binding { name: "status", ty: Type::Int, doc: "" },
+        Binding { name: "headers", ty: Type::Map(&Type::String), doc: "Lowercase names" },
+    ],
+};
+
+/// What hook `when` expressions see.
+pub static HOOKS: Environment = Environment {
+    name: "request_hook",
+    description: "when expressions of request hooks",
+    bindings: &[
+        Binding { name: "request", ty: Type::Object(&REQUEST), doc: "" },
+        Binding { name: "response", ty: Type::Object(&RESPONSE), doc: "Null in request hooks" },
+    ],
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    #[default]
+    Request,
+    Response,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Action {
+    SetHeader { name: String, value: String },
+    RemoveHeader { name: String },
+    NormalizeHeader {
+        name: String,
+        #[serde(default)]
+        lowercase: bool,
+    },
+    /// JSON pointers into the body.
+    StripFields { fields: Vec<String> },
+    Reject {
+        #[serde(default = "bad_request")]
+        status: u16,
+        message: String,
+    },
+    /// A validation plugin, given the body as its value.
+    Plugin { name: String },
+    /// Keep a forensic copy of the request.
+    Capture { reason: String },
+}
+
+fn bad_request() -> u16 {
+    400
+}
+
+impl Action {
+    fn describe(&self) -> String {
+        match self {
+            Action::SetHeader { name, .. } => format!("set header {}", name),
+            Action::RemoveHeader { name } => format!("remove header {}", name),
+            Action::NormalizeHeader { name, .. } => format!("normalize header {}", name),
+            Action::StripFields { fields } => format!("strip {}", fields.join(", ")),
+            Action::Reject { status, .. } => format!("reject with {}", status),
+            Action::Plugin { name } => format!("check with plugin {}", name),
+            Action::Capture { .. } => "capture for forensics".to_string(),
+        }
+    }
+
+    fn edits_headers(&self) -> bool {
+        matches!(self, Action::SetHeader { .. } | Action::RemoveHeader { .. } | Action::NormalizeHeader { .. })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub phase: Phase,
+    /// Path prefixes; empty means every path.
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// Empty means every method.
+    #[serde(default)]
+    pub methods: Vec<String>,
+    /// Expression over `HOOKS`; omit to act on every request in scope.
+    pub when: Option<String>,
+    pub action: Action,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+struct Hook {
+    definition: HookDefinition,
+    when: Option<Program>,
+}
+
+impl Hook {
+    fn applies_to(&self, method: &str, path: &str) -> bool {
+        (self.definition.paths.is_empty() || self.definition.paths.iter().any(|prefix| path.starts_with(prefix.as_str())))
+            && (self.definition.methods.is_empty() || self.definition.methods.iter().any(|m| m.eq_ignore_ascii_case(method)))
+    }
+}
+
+fn header_problems(action: &Action) -> Option<String> {
+    let (name, value) = match action {
+        Action::SetHeader { name, value } => (name, Some(value)),
+        Action::RemoveHeader { name } | Action::NormalizeHeader { name, .. } => (name, None),
+        _ => return None,
+    };
+    if HeaderName::try_from(name.as_str()).is_err() {
+        return Some(format!("'{}' is not a header name", name));
+    }
+    if value.is_some_and(|value| HeaderValue::try_from(value.as_str()).is_err()) {
+        return Some(format!("the value of {} is not a valid header value", name));
+    }
+    None
+}
+
+fn hook_problems(hooks: &[HookDefinition]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (i, hook) in hooks.iter().enumerate() {
+        let at = format!("hook '{}'", hook.name);
+        if hook.name.is_empty() || hooks[..i].iter().any(|other| other.name == hook.name) {
+            problems.push(format!("hook {}: names must be present and unique", i + 1));
+        }
+        if hook.paths.iter().any(|path| !path.starts_with('/')) {
+            problems.push(format!("{}: paths must start with '/'", at));
+        }
+        if let Some(when) = &hook.when {
+            if let Err(e) = Program::condition(when, &HOOKS) {
+                problems.push(format!("{}: when: {}", at, e));
+            }
+        }
+        if hook.phase == Phase::Response && !matches!(hook.action, Action::SetHeader { .. } | Action::RemoveHeader { .. }) {
+            problems.push(format!("{}: response hooks can only set or remove headers", at));
+        }
+        if let Some(problem) = header_problems(&hook.action) {
+            problems.push(format!("{}: {}", at, problem));
+        }
+        match &hook.action {
+            Action::StripFields { fields } if fields.is_empty() || fields.iter().any(|f| !f.starts_with('/')) => {
+                problems.push(format!("{}: fields must be JSON pointers", at));
+            }
+            Action::Reject { status, .. } if !(400..600).contains(status) => {
+                problems.push(format!("{}: reject status must be 400-599", at));
+            }
+            _ => {}
+        }
+    }
+    problems
+}
+
+pub struct RequestHooks {
+    config: HooksConfig,
+    hooks: Vec<Hook>,
+}
+
+/// What a request hook decided.
+enum Outcome {
+    Continue,
+    Reject(HttpResponse),
+}
+
+impl RequestHooks {
+    pub async fn new(config: &Config) -> Result<Self, SecurityError> {
+        let definitions: Vec<HookDefinition> = match &config.hooks.file {
+            Some(path) => {
+                let text = tokio::fs::read_to_string(path).await
+                    .map_err(|e| SecurityError::ConfigError(format!("REQUEST_HOOKS_FILE {}: {}", path, e)))?;
+                let definitions: Vec<HookDefinition> = serde_json::from_str(&text)
+                    .map_err(|e| SecurityError::ConfigError(format!("REQUEST_HOOKS_FILE {}: {}", path, e)))?;
+                let problems = hook_problems(&definitions);
+                if !problems.is_empty() {
+                    return Err(SecurityError::ConfigError(format!("REQUEST_HOOKS_FILE {}: {}", path, problems.join("; "))));
+                }
+                definitions
+            }
+            None => Vec::new(),
+        };
+
+        let hooks = definitions
+            .into_iter()
+            .map(|definition| {
+                // Checked above
+                let when = definition.when.as_deref().and_then(|when| Program::condition(when, &HOOKS).ok());
+                Hook { definition, when }
+            })
+            .collect::<Vec<_>>();
+        let dry_run = hooks.iter().filter(|hook| hook.definition.dry_run).count();
+        info!("Request hooks initialized: {} configured, {} in dry run", hooks.len(), dry_run);
+        Ok(Self { config: config.hooks.clone(), hooks })
+    }
+
+    fn matching(&self, phase: Phase, method: &str, path: &str) -> impl Iterator<Item = &Hook> + '_ {
+        let (method, path) = (method.to_string(), path.to_string());
+        self.hooks
+            .iter()
+            .filter(move |hook| hook.definition.phase == phase && hook.applies_to(&method, &path))
+    }
+
+    /// Whether `hook` holds; a failing expression is counted and skips it.
+    fn holds(&self, state: &AppState, hook: &Hook, bindings: &Map<String, Value>) -> bool {
+        let Some(when) = &hook.when else {
+            return true;
+        };
+        match when.holds(bindings) {
+            Ok(holds) => holds,
+            Err(e) => {
+                warn!("Request hook '{}' skipped: {}", hook.definition.name, e);
+                count(state, hook, "error");
+                false
+            }
+        }
+    }
+}
+
+fn count(state: &AppState, hook: &Hook, outcome: &str) {
+    state.metrics_service.increment(
+        "cotai_request_hooks_total",
+        &[("hook", hook.definition.name.as_str()), ("outcome", outcome)],
+    );
+}
+
+fn header_map(headers: &HeaderMap) -> Value {
+    let mut map = Map::new();
+    for (name, value) in headers {
+        if let Ok(value) = value.to_str() {
+            map.insert(name.as_str().to_string(), Value::String(value.to_string()));
+        }
+    }
+    Value::Object(map)
+}
+
+fn request_value(req: &actix_web::HttpRequest, body: &Value) -> Value {
+    let query: Map<String, Value> = web::Query::<Vec<(String, String)>>::from_query(req.query_string())
+        .map(|query| query.into_inner().into_iter().map(|(k, v)| (k, Value::String(v))).collect())
+        .unwrap_or_default();
+    serde_json::json!({
+        "method": req.method().as_str(),
+        "path": req.path(),
+        "query": query,
+        "headers": header_map(req.headers()),
+        "ip": client_ip(req),
+        "body": body
+    })
+}
+
+fn is_json(req: &ServiceRequest) -> bool {
+    req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(';').next().is_some_and(|mime| {
+            let mime = mime.trim();
+            mime == "application/json" || mime.ends_with("+json")
+        }))
+}
+
+/// Read a JSON body for the hooks, putting it back for the handler. `None`
+/// when it is over the limit.
+async fn read_body(req: &mut ServiceRequest, limit: usize) -> Option<Bytes> {
+    let mut payload = req.take_payload();
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.ok()?;
+        if body.len() + chunk.len() > limit {
+            return None;
+        }
+        body.extend_from_slice(&chunk);
+    }
+    let body = body.freeze();
+    req.set_payload(Payload::from(body.clone()));
+    Some(body)
+}
+
+fn replace_body(req: &mut ServiceRequest, body: &Value) {
+    let bytes = Bytes::from(serde_json::to_vec(body).unwrap_or_default());
+    req.headers_mut().insert(header::CONTENT_LENGTH, HeaderValue::from(bytes.len()));
+    req.set_payload(Payload::from(bytes));
+}
+
+fn edit_headers(headers: &mut HeaderMap, action: &Action) {
+    match action {
+        Action::SetHeader { name, value } => {
+            if let (Ok(name), Ok(value)) = (HeaderName::try_from(name.as_str()), HeaderValue::try_from(value.as_str())) {
+                headers.insert(name, value);
+            }
+        }
+        Action::RemoveHeader { name } => {
+            headers.remove(name.as_str());
+        }
+        Action::NormalizeHeader { name, lowercase } => {
+            let Some(current) = headers.get(name.as_str()).and_then(|value| value.to_str().ok()) else {
+                return;
+            };
+            let mut normalized = current.split_whitespace().collect::<Vec<_>>().join(" ");
+            if *lowercase {
+                normalized = normalized.to_lowercase();
+            }
+            if let (Ok(name), Ok(value)) = (HeaderName::try_from(name.as_str()), HeaderValue::try_from(normalized)) {
+                headers.insert(name, value);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Remove the field a JSON pointer names, if it is there.
+fn strip(body: &mut Value, pointer: &str) -> bool {
+    let Some((parent, last)) = pointer.rsplit_once('/') else {
+        return false;
+    };
+    let last = last.replace("~1", "/").replace("~0", "~");
+    match body.pointer_mut(parent) {
+        Some(Value::Object(map)) => map.remove(&last).is_some(),
+        Some(Value::Array(items)) => match last.parse::<usize>() {
+            Ok(i) if i < items.len() => {
+                items.remove(i);
+                true
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn rejected(hook: &Hook, status: u16, message: &str, errors: Option<Value>) -> HttpResponse {
+    let mut body = serde_json::json!({
+        "error": message,
+        "code": "rejected_by_hook",
+        "hook": hook.definition.name
+    });
+    if let Some(errors) = errors {
+        body["errors"] = errors;
+    }
+    HttpResponse::build(StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_REQUEST)).json(body)
+}
+
+fn apply(
+    state: &AppState,
+    hook: &Hook,
+    req: &mut ServiceRequest,
+    body: &mut Option<Value>,
+) -> Result<Outcome, SecurityError> {
+    let action = &hook.definition.action;
+    if action.edits_headers() {
+        edit_headers(req.headers_mut(), action);
+        return Ok(Outcome::Continue);
+    }
+    match action {
+        Action::StripFields { fields } => {
+            if let Some(value) = body.as_mut() {
+                let mut stripped = false;
+                for field in fields {
+                    stripped |= strip(value, field);
+                }
+                if stripped {
+                    replace_body(req, value);
+                }
+            }
+            Ok(Outcome::Continue)
+        }
+        Action::Reject { status, message } => {
+            forensics::flag(req, "hooks", format!("hook '{}': {}", hook.definition.name, message));
+            Ok(Outcome::Reject(rejected(hook, *status, message, None)))
+        }
+        Action::Plugin { name } => {
+            let errors = state.plugins.validate(name, "$", body.clone().unwrap_or(Value::Null))?;
+            if errors.is_empty() {
+                return Ok(Outcome::Continue);
+            }
+            forensics::flag(
+                req,
+                "hooks",
+                format!("hook '{}': plugin {} found {} problems", hook.definition.name, name, errors.len()),
+            );
+            let errors = serde_json::to_value(&errors).unwrap_or_default();
+            Ok(Outcome::Reject(rejected(hook, 400, "Request failed validation", Some(errors))))
+        }
+        Action::Capture { reason } => {
+            forensics::flag(req, "hooks", format!("hook '{}': {}", hook.definition.name, reason));
+            Ok(Outcome::Continue)
+        }
+        _ => Ok(Outcome::Continue),
+    }
+}
+
+/// Request side of the `hooks` stage.
+pub async fn rejection(state: &AppState, req: &mut ServiceRequest) -> Option<HttpResponse> {
+    let hooks = &state.request_hooks;
+    let method = req.method().as_str().to_string();
+    let path = req.path().to_string();
+    hooks.matching(Phase::Request, &method, &path).next()?;
+
+    let mut body = None;
+    if is_json(req) {
+        match read_body(req, hooks.config.max_body_bytes).await {
+            Some(bytes) => body = serde_json::from_slice::<Value>(&bytes).ok(),
+            None => {
+                return Some(HttpResponse::PayloadTooLarge().json(serde_json::json!({
+                    "error": "Request body too large",
+                    "code": "payload_too_large"
+                })))
+            }
+        }
+    }
+
+    for hook in hooks.matching(Phase::Request, &method, &path) {
+        let started = Instant::now();
+        let mut bindings = Map::new();
+        bindings.insert("request".to_string(), request_value(req.request(), body.as_ref().unwrap_or(&Value::Null)));
+        bindings.insert("response".to_string(), Value::Null);
+        if !hooks.holds(state, hook, &bindings) {
+            continue;
+        }
+
+        let outcome = if hook.definition.dry_run {
+            info!("Request hook '{}' would {} on {} {}", hook.definition.name, hook.definition.action.describe(), method, path);
+            count(state, hook, "dry_run");
+            Outcome::Continue
+        } else {
+            match apply(state, hook, req, &mut body) {
+                Ok(outcome) => {
+                    count(state, hook, "applied");
+                    outcome
+                }
+                Err(e) => {
+                    warn!("Request hook '{}' skipped: {}", hook.definition.name, e);
+                    count(state, hook, "error");
+                    Outcome::Continue
+                }
+            }
+        };
+        state.metrics_service.observe(
+            "cotai_request_hook_seconds",
+            &[("hook", hook.definition.name.as_str())],
+            started.elapsed().as_secs_f64(),
+        );
+        if let Outcome::Reject(response) = outcome {
+            return Some(response);
+        }
+    }
+    None
+}
+
+/// Response side of the `hooks` stage.
+pub fn annotate(mut res: ServiceResponse<BoxBody>) -> ServiceResponse<BoxBody> {
+    let Some(state) = res.request().app_data::<web::Data<AppState>>().cloned() else {
+        return res;
+    };
+    let hooks = &state.request_hooks;
+    let method = res.request().method().as_str().to_string();
+    let path = res.request().path().to_string();
+
+    for hook in hooks.matching(Phase::Response, &method, &path) {
+        let started = Instant::now();
+        let mut bindings = Map::new();
+        bindings.insert("request".to_string(), request_value(res.request(), &Value::Null));
+        bindings.insert("response".to_string(), serde_json::json!({
+            "status": res.status().as_u16(),
+            "headers": header_map(res.headers())
+        }));
+        if !hooks.holds(&state, hook, &bindings) {
+            continue;
+        }
+
+        if hook.definition.dry_run {
+            info!("Response hook '{}' would {} on {} {}", hook.definition.name, hook.definition.action.describe(), method, path);
+            count(&state, hook, "dry_run");
+        } else {
+            edit_headers(res.headers_mut(), &hook.definition.action);
+            count(&state, hook, "applied");
+        }
+        state.metrics_service.observe(
+            "cotai_request_hook_seconds",
+            &[("hook", hook.definition.name.as_str())],
+            started.elapsed().as_secs_f64(),
+        );
+    }
+    res
+}
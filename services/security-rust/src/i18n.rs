@@ -0,0 +1,198 @@
+/*!
+Localization
+Message catalogs and locale negotiation for user-facing strings
+
+Texts people read, such as validation messages, the one-time code message
+and the consent screen, are looked up by message id in the catalog of the
+request's locale, falling back to English and then to the id itself.
+Codes meant for programs (`code` fields, error reasons, statuses) are never
+translated.
+
+A request's locale is the best of `TENANT_LOCALES` its `Accept-Language`
+header asks for (`pt` is enough for `pt-BR`), else its tenant's `locale`
+setting (see `tenant_settings`), else `TENANT_LOCALE`. The tenant is the
+caller's, or for anonymous requests the `X-Tenant-Id` header's.
+
+English and Brazilian Portuguese are built in. `I18N_CATALOG_FILE` names a
+JSON file of further texts per locale, `{"pt-BR": {"scope.tenders:read":
+"Ver suas licitações"}}`, added to or replacing the built-in ones; this is
+how consent screens describe a deployment's scopes (`scope.<scope>`).
+Placeholders are written `{name}`.
+*/
+
+use actix_web::http::header::{HeaderValue, ACCEPT_LANGUAGE, CONTENT_LANGUAGE};
+use actix_web::{HttpRequest, HttpResponse};
+use std::collections::HashMap;
+use std::fmt::Display;
+use tracing::info;
+
+use crate::config::Config;
+use crate::errors::SecurityError;
+use crate::validation::FieldError;
+use crate::AppState;
+
+pub const FALLBACK_LOCALE: &str = "en";
+
+const EN: &[(&str, &str)] = &[
+    ("otp.subject", "Your verification code"),
+    ("otp.body", "Your verification code is {code}. It expires in {minutes} minutes."),
+    ("consent.prompt", "{client} wants to access your account"),
+    ("consent.granted", "You already allowed {client} to:"),
+    ("consent.requested", "{client} asks to:"),
+];
+
+const PT_BR: &[(&str, &str)] = &[
+    ("otp.subject", "Seu código de verificação"),
+    ("otp.body", "Seu código de verificação é {code}. Ele expira em {minutes} minutos."),
+    ("consent.prompt", "{client} quer acessar sua conta"),
+    ("consent.granted", "Você já permitiu que {client}:"),
+    ("consent.requested", "{client} pede permissão para:"),
+    // Validation messages, by the error's code; English keeps the
+    // validators' own wording.
+    ("validation.cpf", "CPF inválido"),
+    ("validation.cnpj", "CNPJ inválido"),
+    ("validation.cep", "CEP inválido"),
+    ("validation.phone", "Telefone inválido"),
+    ("validation.email", "E-mail inválido"),
+    ("validation.uuid", "Deve ser um UUID"),
+    ("validation.type", "Tipo de valor inválido"),
+    ("validation.enum", "Valor não está entre os permitidos"),
+    ("validation.const", "Valor diferente do esperado"),
+    ("validation.required", "Campo obrigatório"),
+    ("validation.additionalProperties", "Campo não permitido"),
+    ("validation.minLength", "Texto curto demais"),
+    ("validation.maxLength", "Texto longo demais"),
+    ("validation.minimum", "Valor abaixo do mínimo"),
+    ("validation.maximum", "Valor acima do máximo"),
+    ("validation.exclusiveMinimum", "Valor abaixo do mínimo"),
+    ("validation.exclusiveMaximum", "Valor acima do máximo"),
+    ("validation.minItems", "Itens de menos"),
+    ("validation.maxItems", "Itens demais"),
+    ("validation.uniqueItems", "Os itens devem ser únicos"),
+    ("validation.minProperties", "Campos de menos"),
+    ("validation.maxProperties", "Campos demais"),
+    ("validation.sql_injection", "Conteúdo suspeito de injeção de SQL"),
+    ("validation.xss", "Conteúdo suspeito de script malicioso"),
+    ("validation.path_traversal", "Conteúdo suspeito de acesso a caminhos"),
+];
+
+/// Header naming the tenant of anonymous requests.
+const TENANT_HEADER: &str = "x-tenant-id";
+
+/// The best of `supported` an `Accept-Language` value asks for: by
+/// quality, then order; a bare language matches its regional variants.
+pub fn negotiate<'a>(header: &str, supported: &'a [String]) -> Option<&'a str> {
+    let mut ranges: Vec<(f32, usize, &str)> = header
+        .split(',')
+        .enumerate()
+        .filter_map(|(i, part)| {
+            let mut pieces = part.split(';');
+            let range = pieces.next()?.trim();
+            let quality = pieces
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (!range.is_empty() && quality > 0.0).then_some((quality, i, range))
+        })
+        .collect();
+    ranges.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    ranges.into_iter().find_map(|(_, _, range)| {
+        // Any language at all leaves the choice to the tenant
+        if range == "*" {
+            return None;
+        }
+        let language = |tag: &str| tag.split('-').next().unwrap_or(tag).to_ascii_lowercase();
+        supported
+            .iter()
+            .find(|locale| locale.eq_ignore_ascii_case(range))
+            .or_else(|| supported.iter().find(|locale| language(locale) == language(range)))
+            .map(String::as_str)
+    })
+}
+
+pub struct I18n {
+    catalogs: HashMap<String, HashMap<String, String>>,
+    locales: Vec<String>,
+}
+
+impl I18n {
+    pub fn new(config: &Config) -> Result<Self, SecurityError> {
+        let mut catalogs: HashMap<String, HashMap<String, String>> = HashMap::new();
+        for (locale, entries) in [("en", EN), ("pt-BR", PT_BR)] {
+            catalogs.insert(
+                locale.to_string(),
+                entries.iter().map(|(id, text)| (id.to_string(), text.to_string())).collect(),
+            );
+        }
+        if let Some(path) = &config.i18n.catalog_file {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| SecurityError::ConfigError(format!("Cannot read {}: {}", path, e)))?;
+            let extra: HashMap<String, HashMap<String, String>> = serde_json::from_str(&contents)
+                .map_err(|e| SecurityError::ConfigError(format!("Invalid message catalog {}: {}", path, e)))?;
+            for (locale, entries) in extra {
+                catalogs.entry(locale).or_default().extend(entries);
+            }
+        }
+        if let Some(locale) = config.tenant_settings.locales.iter().find(|l| !catalogs.contains_key(*l)) {
+            return Err(SecurityError::ConfigError(format!("No message catalog for locale {}", locale)));
+        }
+
+        info!("Localization initialized ({})", config.tenant_settings.locales.join(", "));
+        Ok(Self {
+            catalogs,
+            locales: config.tenant_settings.locales.clone(),
+        })
+    }
+
+    /// The locale to answer `req` in; see the module docs.
+    pub async fn locale(&self, state: &AppState, req: &HttpRequest, tenant_id: Option<&str>) -> String {
+        let asked = req.headers().get(ACCEPT_LANGUAGE).and_then(|v| v.to_str().ok());
+        if let Some(locale) = asked.and_then(|header| negotiate(header, &self.locales)) {
+            return locale.to_string();
+        }
+        let header_tenant = req.headers().get(TENANT_HEADER).and_then(|v| v.to_str().ok());
+        state.tenant_settings.effective(tenant_id.or(header_tenant)).await.locale
+    }
+
+    /// A locale the caller named outright, if supported.
+    pub fn supported(&self, locale: &str) -> Option<String> {
+        self.locales.iter().find(|l| l.eq_ignore_ascii_case(locale)).cloned()
+    }
+
+    fn lookup(&self, locale: &str, id: &str) -> Option<&str> {
+        self.catalogs.get(locale).and_then(|catalog| catalog.get(id)).map(String::as_str)
+    }
+
+    /// The text of message `id` in `locale`, with `{name}` placeholders
+    /// filled from `args`.
+    pub fn text(&self, locale: &str, id: &str, args: &[(&str, &dyn Display)]) -> String {
+        let template = self.lookup(locale, id).or_else(|| self.lookup(FALLBACK_LOCALE, id)).unwrap_or(id);
+        args.iter().fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), &value.to_string())
+        })
+    }
+
+    /// Message `id` in `locale`, or English, if either catalog has it.
+    pub fn translation(&self, locale: &str, id: &str) -> Option<String> {
+        self.lookup(locale, id).or_else(|| self.lookup(FALLBACK_LOCALE, id)).map(str::to_string)
+    }
+
+    /// Replace validation messages with `locale`'s text for their code,
+    /// where it has one.
+    pub fn localize_errors(&self, locale: &str, errors: &mut [FieldError]) {
+        for error in errors {
+            if let Some(text) = self.translation(locale, &format!("validation.{}", error.code)) {
+                error.message = text;
+            }
+        }
+    }
+}
+
+/// Mark `response` as written in `locale`.
+pub fn content_language(mut response: HttpResponse, locale: &str) -> HttpResponse {
+    if let Ok(value) = HeaderValue::from_str(locale) {
+        response.headers_mut().insert(CONTENT_LANGUAGE, value);
+    }
+    response
+}
@@ -0,0 +1,602 @@
+/*!
+Jobs Module
+Slow operations answered with 202 and a status URL instead of a timeout
+
+Some operations, such as signed reports over long histories, can outlast
+the gateway in front of the service. Handlers run them through `respond`,
+which answers as usual when the operation finishes within
+`JOBS_INLINE_WAIT_MS` and otherwise answers `202` with the job, a
+`Location: /api/v1/jobs/{id}` status URL and `Retry-After`, while the
+operation runs on. Callers that would rather not wait send `Prefer:
+respond-async` and get the `202` at once; `Prefer: wait=N` shortens the
+wait instead. The wait never runs past the request's deadline (see
+`deadline`).
+
+- `GET /jobs/{id}` returns the job, with the operation's status code and
+  body once it has finished; `?wait=N` holds the request open until it
+  finishes, for at most `JOBS_MAX_WAIT_SECS`.
+- `GET /jobs/{id}/result` answers exactly as the operation would have,
+  headers included, or `202` while it runs.
+
+Only the caller that started a job, or an admin, can read it. Results are
+kept for `JOBS_RESULT_TTL_SECS` after the job finishes, then the job is
+`expired` (`410`), and it is deleted a week later. A job still running
+after `JOBS_MAX_RUNTIME_SECS`, or left running by a replica that stopped,
+fails.
+
+With `X-Callback-Url`, the caller is told when the job finishes: a JSON
+`POST` of `{event: "job.completed", id, kind, status, status_code,
+status_url}` without the result, signed like SOAR pushes
+(`X-Cotai-Signature`) with the `JOBS_CALLBACK_SECRET` webhook secret (see
+`crypto::webhook`). The URL must be https on a host in
+`JOBS_CALLBACK_HOSTS`; without any, callbacks are refused. Failed
+callbacks are retried every `JOBS_INTERVAL_SECS`, up to
+`JOBS_CALLBACK_MAX_ATTEMPTS` attempts, so receivers should expect
+duplicates.
+
+Custody reports, the key usage, expiry and elevation reports run this
+way. Seals and dossiers keep their own queues, as their work must survive a
+restart.
+*/
+
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::types::Json;
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::auth::{auth_error_response, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, JobsConfig};
+use crate::crypto::webhook::WebhookSigner;
+use crate::deadline;
+use crate::errors::SecurityError;
+use crate::soar::SIGNATURE_HEADER;
+use crate::storage::Storage;
+use crate::AppState;
+
+pub const CALLBACK_HEADER: &str = "x-callback-url";
+
+/// Expired jobs are kept this long so their ids still answer `410`.
+const EXPIRED_KEEP_DAYS: i64 = 7;
+
+/// Room left before the request's deadline to answer `202`.
+const DEADLINE_MARGIN_MS: u64 = 500;
+
+const POLL_INTERVAL_MS: u64 = 500;
+
+const JOB_COLUMNS: &str = "id, kind, owner, tenant_id, status, status_code, result, headers, error, callback_url, \
+    callback_status, callback_attempts, created_at, completed_at, expires_at";
+
+/// What an operation answers with when it succeeds.
+pub struct Output {
+    pub body: Value,
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Output {
+    pub fn json(body: impl Serialize) -> Self {
+        Self {
+            body: serde_json::to_value(body).unwrap_or_default(),
+            headers: BTreeMap::new(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.insert(name.to_string(), value.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Job {
+    pub id: Uuid,
+    pub kind: String,
+    #[serde(skip)]
+    pub owner: String,
+    #[serde(skip)]
+    pub tenant_id: Option<String>,
+    /// `running`, `succeeded`, `failed` or `expired`.
+    pub status: String,
+    pub status_code: Option<i32>,
+    pub result: Option<Json<Value>>,
+    #[serde(skip)]
+    pub headers: Json<BTreeMap<String, String>>,
+    pub error: Option<String>,
+    #[serde(skip)]
+    pub callback_url: Option<String>,
+    pub callback_status: Option<String>,
+    #[serde(skip)]
+    pub callback_attempts: i32,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Job {
+    pub fn status_url(&self) -> String {
+        format!("/api/v1/jobs/{}", self.id)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct WaitQuery {
+    /// Seconds to wait for the job to finish.
+    pub wait: Option<u64>,
+}
+
+/// The status and message a failed operation answers with. Unexpected
+/// errors are logged rather than returned.
+fn failure(kind: &str, e: SecurityError) -> (u16, String) {
+    match e {
+        SecurityError::ValidationError(msg) => (400, msg),
+        SecurityError::AccessDenied(msg) => (403, msg),
+        SecurityError::NotFound(msg) => (404, msg),
+        SecurityError::Conflict(msg) => (409, msg),
+        SecurityError::ConfigError(msg) => (503, msg),
+        e => {
+            error!("{} failed: {:?}", kind, e);
+            (500, format!("{} failed", kind))
+        }
+    }
+}
+
+/// `respond-async` and `wait` from `Prefer` (RFC 7240).
+fn preferences(req: &HttpRequest) -> (bool, Option<u64>) {
+    let mut respond_async = false;
+    let mut wait = None;
+    for value in req.headers().get_all("Prefer").filter_map(|value| value.to_str().ok()) {
+        for preference in value.split(',').map(str::trim) {
+            if preference.eq_ignore_ascii_case("respond-async") {
+                respond_async = true;
+            } else if let Some(secs) = preference.strip_prefix("wait=") {
+                wait = secs.trim().parse().ok();
+            }
+        }
+    }
+    (respond_async, wait)
+}
+
+fn with_headers(mut response: HttpResponse, headers: &BTreeMap<String, String>) -> HttpResponse {
+    for (name, value) in headers {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name.as_str()), HeaderValue::from_str(value)) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    response
+}
+
+/// The answer of a finished operation.
+fn answer(kind: &str, outcome: Result<Output, SecurityError>) -> HttpResponse {
+    match outcome {
+        Ok(output) => with_headers(HttpResponse::Ok().json(output.body), &output.headers),
+        Err(e) => {
+            let (status, message) = failure(kind, e);
+            HttpResponse::build(StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR))
+                .json(serde_json::json!({ "error": message }))
+        }
+    }
+}
+
+pub struct JobService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: JobsConfig,
+    client: reqwest::Client,
+}
+
+impl JobService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(config.jobs.timeout_secs))
+            .build()
+            .map_err(|e| SecurityError::ConfigError(format!("Job callback client: {}", e)))?;
+
+        info!("Job service initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            config: config.jobs.clone(),
+            client,
+        })
+    }
+
+    /// The caller's `X-Callback-Url`, if it sent one we may call.
+    fn callback_url(&self, req: &HttpRequest) -> Result<Option<String>, SecurityError> {
+        let Some(value) = req.headers().get(CALLBACK_HEADER) else {
+            return Ok(None);
+        };
+        let url = value
+            .to_str()
+            .ok()
+            .and_then(|value| reqwest::Url::parse(value).ok())
+            .ok_or_else(|| SecurityError::ValidationError("X-Callback-Url is not a valid URL".to_string()))?;
+        if url.scheme() != "https" {
+            return Err(SecurityError::ValidationError("X-Callback-Url must be https".to_string()));
+        }
+        let allowed = url
+            .host_str()
+            .is_some_and(|host| self.config.callback_hosts.iter().any(|allowed| allowed.eq_ignore_ascii_case(host)));
+        if !allowed {
+            return Err(SecurityError::ValidationError("X-Callback-Url is not on an allowed host".to_string()));
+        }
+        Ok(Some(url.to_string()))
+    }
+
+    async fn create(
+        &self,
+        id: Uuid,
+        kind: &str,
+        principal: &Principal,
+        callback_url: Option<&str>,
+    ) -> Result<Job, SecurityError> {
+        let job = sqlx::query_as::<_, Job>(&format!(
+            "INSERT INTO async_jobs (id, kind, owner, tenant_id, callback_url, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6) RETURNING {}",
+            JOB_COLUMNS
+        ))
+        .bind(id)
+        .bind(kind)
+        .bind(&principal.subject)
+        .bind(&principal.tenant_id)
+        .bind(callback_url)
+        .bind(self.clock.now())
+        .fetch_one(self.storage.pool())
+        .await?;
+        Ok(job)
+    }
+
+    /// Record how a job ended, unless it has already been failed as
+    /// interrupted.
+    async fn complete(&self, id: Uuid, kind: &str, outcome: Result<Output, SecurityError>) -> Result<Option<Job>, SecurityError> {
+        let (status, status_code, result, headers, error) = match outcome {
+            Ok(output) => ("succeeded", 200, Some(Json(output.body)), output.headers, None),
+            Err(e) => {
+                let (status_code, message) = failure(kind, e);
+                ("failed", status_code, None, BTreeMap::new(), Some(message))
+            }
+        };
+        let now = self.clock.now();
+        let job = sqlx::query_as::<_, Job>(&format!(
+            "UPDATE async_jobs SET status = $2, status_code = $3, result = $4, headers = $5, error = $6, \
+             completed_at = $7, expires_at = $8, \
+             callback_status = CASE WHEN callback_url IS NULL THEN NULL ELSE 'pending' END \
+             WHERE id = $1 AND status = 'running' RETURNING {}",
+            JOB_COLUMNS
+        ))
+        .bind(id)
+        .bind(status)
+        .bind(status_code as i32)
+        .bind(result)
+        .bind(Json(headers))
+        .bind(error)
+        .bind(now)
+        .bind(now + Duration::seconds(self.config.result_ttl_secs))
+        .fetch_optional(self.storage.pool())
+        .await?;
+        Ok(job)
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<Job, SecurityError> {
+        sqlx::query_as::<_, Job>(&format!("SELECT {} FROM async_jobs WHERE id = $1", JOB_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound(format!("Job {} not found", id)))
+    }
+
+    /// The job once it has finished, or as it is after `wait`.
+    async fn wait_for(&self, id: Uuid, wait: std::time::Duration) -> Result<Job, SecurityError> {
+        let until = tokio::time::Instant::now() + wait;
+        loop {
+            let job = self.get(id).await?;
+            if job.status != "running" || tokio::time::Instant::now() >= until {
+                return Ok(job);
+            }
+            tokio::time::sleep_until(until.min(tokio::time::Instant::now() + std::time::Duration::from_millis(POLL_INTERVAL_MS))).await;
+        }
+    }
+
+    /// Fail jobs running past `JOBS_MAX_RUNTIME_SECS`, drop results past
+    /// their expiry and delete long-expired jobs.
+    async fn sweep(&self) -> Result<(), SecurityError> {
+        let now = self.clock.now();
+        let interrupted = sqlx::query(
+            "UPDATE async_jobs SET status = 'failed', status_code = 500, \
+             error = 'The job was interrupted or ran too long', completed_at = $2, expires_at = $3, \
+             callback_status = CASE WHEN callback_url IS NULL THEN NULL ELSE 'pending' END \
+             WHERE status = 'running' AND created_at < $1",
+        )
+        .bind(now - Duration::seconds(self.config.max_runtime_secs as i64))
+        .bind(now)
+        .bind(now + Duration::seconds(self.config.result_ttl_secs))
+        .execute(self.storage.pool())
+        .await?
+        .rows_affected();
+        if interrupted > 0 {
+            warn!("Failed {} jobs that were interrupted or ran too long", interrupted);
+        }
+
+        sqlx::query(
+            "UPDATE async_jobs SET status = 'expired', result = NULL, headers = '{}' \
+             WHERE status IN ('succeeded', 'failed') AND expires_at <= $1",
+        )
+        .bind(now)
+        .execute(self.storage.pool())
+        .await?;
+        sqlx::query("DELETE FROM async_jobs WHERE status = 'expired' AND expires_at <= $1")
+            .bind(now - Duration::days(EXPIRED_KEEP_DAYS))
+            .execute(self.storage.pool())
+            .await?;
+        Ok(())
+    }
+
+    /// Send due callbacks, or only that of job `id`. Claimed rows are
+    /// locked, so replicas do not send the same one at once.
+    async fn deliver_callbacks(&self, webhooks: &WebhookSigner, id: Option<Uuid>) -> Result<(), SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM async_jobs WHERE callback_status = 'pending'",
+            JOB_COLUMNS
+        ));
+        match id {
+            Some(id) => {
+                builder.push(" AND id = ").push_bind(id);
+            }
+            None => {
+                // Fresh ones get their first attempt from the replica that ran them
+                let settled = self.clock.now() - Duration::seconds(self.config.interval_secs as i64);
+                builder.push(" AND completed_at <= ").push_bind(settled);
+            }
+        }
+        builder.push(" ORDER BY completed_at LIMIT 100 FOR UPDATE SKIP LOCKED");
+        let due = builder.build_query_as::<Job>().fetch_all(&mut *tx).await?;
+
+        for job in &due {
+            let sent = self.send_callback(webhooks, job).await;
+            if let Err(e) = &sent {
+                warn!("Callback for job {} failed: {:?}", job.id, e);
+            }
+            let attempts = job.callback_attempts + 1;
+            let status = match sent {
+                Ok(()) => "sent",
+                Err(_) if attempts >= self.config.callback_max_attempts => "failed",
+                Err(_) => "pending",
+            };
+            sqlx::query("UPDATE async_jobs SET callback_status = $2, callback_attempts = $3 WHERE id = $1")
+                .bind(job.id)
+                .bind(status)
+                .bind(attempts)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn send_callback(&self, webhooks: &WebhookSigner, job: &Job) -> Result<(), SecurityError> {
+        let Some(url) = &job.callback_url else {
+            return Ok(());
+        };
+        let body = serde_json::to_vec(&serde_json::json!({
+            "event": "job.completed",
+            "id": job.id,
+            "kind": job.kind,
+            "status": job.status,
+            "status_code": job.status_code,
+            "status_url": job.status_url(),
+            "completed_at": job.completed_at
+        }))
+        .map_err(|e| SecurityError::DeliveryError(e.to_string()))?;
+        let (signature, _) = webhooks.sign(&self.config.callback_secret, &body)?;
+
+        let response = self
+            .client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header(SIGNATURE_HEADER, signature)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| SecurityError::DeliveryError(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(SecurityError::DeliveryError(format!("Callback returned {}", response.status())));
+        }
+        Ok(())
+    }
+}
+
+/// Answer `req` with the outcome of `work` if it finishes in time, and with
+/// `202` and a job to follow otherwise; see the module docs. `kind` names
+/// the operation, e.g. `custody.report`.
+pub async fn respond<F>(
+    state: &web::Data<AppState>,
+    req: &HttpRequest,
+    principal: &Principal,
+    kind: &'static str,
+    work: F,
+) -> HttpResponse
+where
+    F: Future<Output = Result<Output, SecurityError>> + Send + 'static,
+{
+    let jobs = &state.jobs;
+    let callback_url = match jobs.callback_url(req) {
+        Ok(url) => url,
+        Err(e) => return answer(kind, Err(e)),
+    };
+    let (respond_async, preferred_wait) = preferences(req);
+    let mut wait = std::time::Duration::from_millis(jobs.config.inline_wait_ms);
+    if let Some(secs) = preferred_wait {
+        wait = wait.min(std::time::Duration::from_secs(secs));
+    }
+    if let Some(remaining) = deadline::remaining() {
+        wait = wait.min(remaining.saturating_sub(std::time::Duration::from_millis(DEADLINE_MARGIN_MS)));
+    }
+
+    let mut handle = tokio::spawn(work);
+    if !respond_async && !wait.is_zero() {
+        if let Ok(joined) = tokio::time::timeout(wait, &mut handle).await {
+            let outcome = joined.unwrap_or_else(|e| Err(SecurityError::StorageError(format!("Job panicked: {}", e))));
+            return answer(kind, outcome);
+        }
+    }
+
+    let id = Uuid::new_v4();
+    let job = match jobs.create(id, kind, principal, callback_url.as_deref()).await {
+        Ok(job) => job,
+        Err(e) => {
+            handle.abort();
+            return answer(kind, Err(e));
+        }
+    };
+
+    let worker_state = state.clone();
+    let max_runtime = std::time::Duration::from_secs(jobs.config.max_runtime_secs);
+    tokio::spawn(async move {
+        let outcome = match tokio::time::timeout(max_runtime, &mut handle).await {
+            Ok(Ok(outcome)) => outcome,
+            Ok(Err(e)) => Err(SecurityError::StorageError(format!("Job panicked: {}", e))),
+            Err(_) => {
+                handle.abort();
+                Err(SecurityError::StorageError("Job ran past JOBS_MAX_RUNTIME_SECS".to_string()))
+            }
+        };
+        match worker_state.jobs.complete(id, kind, outcome).await {
+            Ok(Some(job)) if job.callback_status.is_some() => {
+                if let Err(e) = worker_state.jobs.deliver_callbacks(&worker_state.webhooks, Some(job.id)).await {
+                    warn!("Callback for job {} not sent: {:?}", job.id, e);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => error!("Failed to record the outcome of job {}: {:?}", id, e),
+        }
+    });
+
+    let mut response = HttpResponse::Accepted();
+    response
+        .insert_header(("Location", job.status_url()))
+        .insert_header(("Retry-After", jobs.config.poll_after_secs.to_string()));
+    if respond_async {
+        response.insert_header(("Preference-Applied", "respond-async"));
+    }
+    response.json(job)
+}
+
+/// Fail stuck jobs, expire results and retry callbacks in the background.
+pub async fn run_jobs(state: web::Data<AppState>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(state.config.jobs.interval_secs));
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = state.jobs.sweep().await {
+            error!("Job sweep failed: {:?}", e);
+        }
+        if let Err(e) = state.jobs.deliver_callbacks(&state.webhooks, None).await {
+            error!("Job callback delivery failed: {:?}", e);
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Job operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Job operation failed"
+            }))
+        }
+    }
+}
+
+/// The job, if the caller may read it: its owner or an admin.
+async fn authorized_job(state: &AppState, req: &HttpRequest, id: Uuid, wait: Option<u64>) -> Result<Job, HttpResponse> {
+    let principal = state.auth_service.authenticate(req).map_err(|e| auth_error_response(&e))?;
+    let job = state.jobs.get(id).await.map_err(error_response)?;
+    if job.owner != principal.subject && !principal.has_any_role(&state.config.auth.admin_roles) {
+        return Err(error_response(SecurityError::NotFound(format!("Job {} not found", id))));
+    }
+
+    let mut wait = std::time::Duration::from_secs(wait.unwrap_or(0).min(state.config.jobs.max_wait_secs));
+    if let Some(remaining) = deadline::remaining() {
+        wait = wait.min(remaining.saturating_sub(std::time::Duration::from_millis(DEADLINE_MARGIN_MS)));
+    }
+    if job.status != "running" || wait.is_zero() {
+        return Ok(job);
+    }
+    state.jobs.wait_for(id, wait).await.map_err(error_response)
+}
+
+fn running(state: &AppState, job: &Job) -> HttpResponse {
+    HttpResponse::Accepted()
+        .insert_header(("Retry-After", state.config.jobs.poll_after_secs.to_string()))
+        .json(job)
+}
+
+pub async fn get_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<WaitQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let job = match authorized_job(&state, &req, path.into_inner(), query.wait).await {
+        Ok(job) => job,
+        Err(response) => return Ok(response),
+    };
+
+    Ok(match job.status.as_str() {
+        "running" => running(&state, &job),
+        "expired" => HttpResponse::Gone().json(job),
+        _ => with_headers(HttpResponse::Ok().json(&job), &job.headers.0),
+    })
+}
+
+pub async fn result_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<WaitQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let job = match authorized_job(&state, &req, path.into_inner(), query.wait).await {
+        Ok(job) => job,
+        Err(response) => return Ok(response),
+    };
+
+    Ok(match job.status.as_str() {
+        "running" => running(&state, &job),
+        "expired" => HttpResponse::Gone().json(serde_json::json!({
+            "error": format!("The result of job {} has expired", job.id)
+        })),
+        _ => {
+            let status = job
+                .status_code
+                .and_then(|code| StatusCode::from_u16(code as u16).ok())
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            let body = match (&job.result, &job.error) {
+                (Some(Json(result)), _) => result.clone(),
+                (None, error) => serde_json::json!({ "error": error }),
+            };
+            with_headers(HttpResponse::build(status).json(body), &job.headers.0)
+        }
+    })
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/jobs")
+            .route("/{id}", web::get().to(get_handler))
+            .route("/{id}/result", web::get().to(result_handler))
+    );
+}
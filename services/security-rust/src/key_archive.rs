@@ -0,0 +1,727 @@
+/*!
+Key Archive Module
+Cold tier for retired data keys
+
+A retired key neither encrypts nor decrypts, yet its wrapped material in
+`crypto_keys` is as exposed as the live keys if the hot store leaks. Keys
+retired for `KEY_ARCHIVE_AFTER_DAYS` are therefore moved to
+`KEY_ARCHIVE_BUCKET`, meant for a bucket whose lifecycle rules move objects
+to a cold, slow-to-read class. The key is written there still wrapped by
+its provider, read back and checked, then its material is dropped from
+`crypto_keys` (the row stays, `archived`, so the id is never reused) and
+from every replica's memory and key cache. `POST
+/admin/crypto/archive/keys/{key_id}` archives a retired key at once.
+
+Getting a key back is slow on purpose:
+
+1. An admin requests a restore with a reason, `POST
+   /admin/crypto/archive/restores`.
+2. A second admin approves it, `POST .../restores/{id}/approve`. The key
+   becomes available `KEY_ARCHIVE_RESTORE_DELAY_SECS` later, never less
+   than an hour, and either admin can cancel until then.
+3. Once due, the key is read back from the archive, checked against its
+   hash and key check value, and loaded as `compromised`: it decrypts, each
+   use audited, so its data can be re-encrypted with `POST /crypto/rewrap`.
+4. After `KEY_ARCHIVE_RESTORE_WINDOW_SECS` it is retired and archived
+   again.
+
+Every step raises an alert on all sinks, so a restore nobody expected is
+noticed while it can still be cancelled.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::alerting::{Alert, Severity};
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, KeyArchiveConfig};
+use crate::crypto::{CryptoService, KeyState};
+use crate::errors::SecurityError;
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::{KeyRecord, Storage};
+use crate::AppState;
+
+pub const FORMAT: &str = "cotai-key-archive/1";
+
+const SYSTEM_ACTOR: &str = "system:key_archive";
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "requested_at", column: "requested_at", kind: KeyKind::Timestamp },
+];
+
+const ARCHIVE_COLUMNS: &str = "key_id, object_key, sha256, check_value, archived_by, archived_at, restored_at";
+
+const RESTORE_COLUMNS: &str = "id, key_id, reason, requested_by, requested_at, status, approved_by, approved_at, \
+    available_at, restored_at, expires_at, ended_by, ended_at, error";
+
+fn sha256(data: &[u8]) -> String {
+    hex::encode(digest(&SHA256, data))
+}
+
+fn store_error(e: impl std::fmt::Display) -> SecurityError {
+    SecurityError::StorageError(format!("Key archive store: {}", e))
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ArchivedKey {
+    pub key_id: String,
+    pub object_key: String,
+    pub sha256: String,
+    pub check_value: String,
+    pub archived_by: String,
+    pub archived_at: DateTime<Utc>,
+    pub restored_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct KeyRestore {
+    pub id: Uuid,
+    pub key_id: String,
+    pub reason: String,
+    pub requested_by: String,
+    pub requested_at: DateTime<Utc>,
+    /// `requested`, `approved`, `restored`, `closed`, `cancelled` or `failed`.
+    pub status: String,
+    pub approved_by: Option<String>,
+    pub approved_at: Option<DateTime<Utc>>,
+    /// When an approved restore runs.
+    pub available_at: Option<DateTime<Utc>>,
+    pub restored_at: Option<DateTime<Utc>>,
+    /// When a restored key is retired and archived again.
+    pub expires_at: Option<DateTime<Utc>>,
+    pub ended_by: Option<String>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+}
+
+/// The object written to the cold tier: the key as its provider wrapped it.
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchiveDocument {
+    format: String,
+    key_id: String,
+    algorithm: String,
+    provider: String,
+    wrapped_key: String,
+    source: String,
+    created_at: DateTime<Utc>,
+    check_value: String,
+    archived_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RestoreRequest {
+    pub key_id: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RestoreFilter {
+    pub status: Option<String>,
+    pub key_id: Option<String>,
+}
+
+pub struct KeyArchiveService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: KeyArchiveConfig,
+    store: Option<Arc<dyn ObjectStore>>,
+}
+
+impl KeyArchiveService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let store: Option<Arc<dyn ObjectStore>> = match &config.key_archive.bucket {
+            Some(bucket) => Some(Arc::new(
+                AmazonS3Builder::from_env()
+                    .with_bucket_name(bucket)
+                    .build()
+                    .map_err(|e| SecurityError::ConfigError(format!("Key archive store: {}", e)))?,
+            )),
+            None => None,
+        };
+
+        info!("Key archive service initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            config: config.key_archive.clone(),
+            store,
+        })
+    }
+
+    fn store(&self) -> Result<&Arc<dyn ObjectStore>, SecurityError> {
+        self.store
+            .as_ref()
+            .ok_or_else(|| SecurityError::ConfigError("Key archival is not configured".to_string()))
+    }
+
+    /// Move a retired key to the cold tier. It is written, read back and
+    /// checked before its material leaves `crypto_keys`.
+    pub async fn archive(&self, crypto: &CryptoService, key_id: &str, actor: &str) -> Result<ArchivedKey, SecurityError> {
+        let store = self.store()?;
+        let record = self.storage.load_key(key_id).await?
+            .ok_or_else(|| SecurityError::NotFound(format!("Key {} not found", key_id)))?;
+        if record.state != KeyState::Retired.as_str() {
+            return Err(SecurityError::Conflict(format!("Key {} is {}; only retired keys are archived", key_id, record.state)));
+        }
+
+        // A key that no longer unwraps would be archived beyond recovery
+        let check_value = crypto.restored_key_check_value(&record).await?;
+        let now = self.clock.now();
+        let document = serde_json::to_vec(&ArchiveDocument {
+            format: FORMAT.to_string(),
+            key_id: record.key_id.clone(),
+            algorithm: record.algorithm.clone(),
+            provider: record.provider.clone(),
+            wrapped_key: record.wrapped_key.clone(),
+            source: record.source.clone(),
+            created_at: record.created_at,
+            check_value: check_value.clone(),
+            archived_at: now,
+        })
+        .map_err(|e| SecurityError::StorageError(format!("Key archive encoding: {}", e)))?;
+        let document_sha256 = sha256(&document);
+
+        let object_key = format!("{}/{}.json", self.config.prefix.trim_end_matches('/'), key_id);
+        let path = Path::from(object_key.as_str());
+        store.put(&path, document.into()).await.map_err(store_error)?;
+        let written = store.get(&path).await.map_err(store_error)?.bytes().await.map_err(store_error)?;
+        if sha256(&written) != document_sha256 {
+            return Err(SecurityError::StorageError(format!("Archive of key {} did not read back intact", key_id)));
+        }
+
+        let archived = sqlx::query_as::<_, ArchivedKey>(&format!(
+            "INSERT INTO key_archives (key_id, object_key, sha256, check_value, archived_by, archived_at) \
+             VALUES ($1, $2, $3, $4, $5, $6) \
+             ON CONFLICT (key_id) DO UPDATE SET object_key = EXCLUDED.object_key, sha256 = EXCLUDED.sha256, \
+             check_value = EXCLUDED.check_value, archived_by = EXCLUDED.archived_by, \
+             archived_at = EXCLUDED.archived_at, restored_at = NULL \
+             RETURNING {}",
+            ARCHIVE_COLUMNS
+        ))
+        .bind(key_id)
+        .bind(&object_key)
+        .bind(&document_sha256)
+        .bind(&check_value)
+        .bind(actor)
+        .bind(now)
+        .fetch_one(self.storage.pool())
+        .await?;
+
+        if !self.storage.archive_key(key_id).await? {
+            return Err(SecurityError::Conflict(format!("Key {} changed state concurrently", key_id)));
+        }
+        crypto.forget(key_id);
+        Ok(archived)
+    }
+
+    /// Retired keys past `KEY_ARCHIVE_AFTER_DAYS`.
+    async fn due_keys(&self) -> Result<Vec<String>, SecurityError> {
+        let cutoff = self.clock.now() - Duration::days(self.config.after_days);
+        let keys = sqlx::query_scalar::<_, String>(
+            "SELECT key_id FROM crypto_keys WHERE state = 'retired' AND state_changed_at <= $1 ORDER BY state_changed_at",
+        )
+        .bind(cutoff)
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(keys)
+    }
+
+    pub async fn list_archived(&self) -> Result<Vec<ArchivedKey>, SecurityError> {
+        let archived = sqlx::query_as::<_, ArchivedKey>(&format!(
+            "SELECT {} FROM key_archives ORDER BY archived_at DESC",
+            ARCHIVE_COLUMNS
+        ))
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(archived)
+    }
+
+    async fn archived(&self, key_id: &str) -> Result<ArchivedKey, SecurityError> {
+        sqlx::query_as::<_, ArchivedKey>(&format!("SELECT {} FROM key_archives WHERE key_id = $1", ARCHIVE_COLUMNS))
+            .bind(key_id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound(format!("Key {} is not archived", key_id)))
+    }
+
+    pub async fn request_restore(&self, principal: &Principal, request: &RestoreRequest) -> Result<KeyRestore, SecurityError> {
+        if request.reason.trim().is_empty() {
+            return Err(SecurityError::ValidationError("reason is required".to_string()));
+        }
+        self.store()?;
+        self.archived(&request.key_id).await?;
+        let in_cold_tier = self.storage.load_key(&request.key_id).await?
+            .is_some_and(|record| record.state == "archived");
+        if !in_cold_tier {
+            return Err(SecurityError::Conflict(format!("Key {} is not in the archive", request.key_id)));
+        }
+
+        sqlx::query_as::<_, KeyRestore>(&format!(
+            "INSERT INTO key_restores (id, key_id, reason, requested_by, requested_at) \
+             VALUES ($1, $2, $3, $4, $5) RETURNING {}",
+            RESTORE_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(&request.key_id)
+        .bind(request.reason.trim())
+        .bind(&principal.subject)
+        .bind(self.clock.now())
+        .fetch_one(self.storage.pool())
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => SecurityError::Conflict(format!(
+                "Key {} already has a restore in progress",
+                request.key_id
+            )),
+            e => e.into(),
+        })
+    }
+
+    pub async fn list_restores(&self, filter: &RestoreFilter, page: &PageRequest) -> Result<Page<KeyRestore>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM key_restores WHERE 1 = 1",
+            RESTORE_COLUMNS
+        ));
+        if let Some(status) = &filter.status {
+            builder.push(" AND status = ").push_bind(status.clone());
+        }
+        if let Some(key_id) = &filter.key_id {
+            builder.push(" AND key_id = ").push_bind(key_id.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let restores = builder
+            .build_query_as::<KeyRestore>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(restores, |restore, _| (SortKey::Timestamp(restore.requested_at), restore.id)))
+    }
+
+    pub async fn get_restore(&self, id: Uuid) -> Result<KeyRestore, SecurityError> {
+        sqlx::query_as::<_, KeyRestore>(&format!("SELECT {} FROM key_restores WHERE id = $1", RESTORE_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound(format!("Key restore {} not found", id)))
+    }
+
+    /// Approve a requested restore, which then waits out the delay.
+    /// Requesters cannot approve their own.
+    pub async fn approve(&self, id: Uuid, approver: &str) -> Result<KeyRestore, SecurityError> {
+        let pending = self.get_restore(id).await?;
+        if pending.requested_by == approver {
+            return Err(SecurityError::AccessDenied("Restores cannot be approved by their requester".to_string()));
+        }
+        let now = self.clock.now();
+        sqlx::query_as::<_, KeyRestore>(&format!(
+            "UPDATE key_restores SET status = 'approved', approved_by = $2, approved_at = $3, available_at = $4 \
+             WHERE id = $1 AND status = 'requested' RETURNING {}",
+            RESTORE_COLUMNS
+        ))
+        .bind(id)
+        .bind(approver)
+        .bind(now)
+        .bind(now + Duration::seconds(self.config.restore_delay_secs))
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::Conflict(format!("Key restore is {}", pending.status)))
+    }
+
+    /// Cancel a restore that has not run yet.
+    pub async fn cancel(&self, id: Uuid, actor: &str) -> Result<KeyRestore, SecurityError> {
+        let pending = self.get_restore(id).await?;
+        sqlx::query_as::<_, KeyRestore>(&format!(
+            "UPDATE key_restores SET status = 'cancelled', ended_by = $2, ended_at = $3 \
+             WHERE id = $1 AND status IN ('requested', 'approved') RETURNING {}",
+            RESTORE_COLUMNS
+        ))
+        .bind(id)
+        .bind(actor)
+        .bind(self.clock.now())
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::Conflict(format!("Key restore is {}", pending.status)))
+    }
+
+    /// Read a key back from the cold tier and load it as `compromised`.
+    async fn restore(&self, crypto: &CryptoService, job: &KeyRestore) -> Result<(), SecurityError> {
+        let store = self.store()?;
+        let archived = self.archived(&job.key_id).await?;
+        let document = store
+            .get(&Path::from(archived.object_key.as_str()))
+            .await
+            .map_err(store_error)?
+            .bytes()
+            .await
+            .map_err(store_error)?;
+        if sha256(&document) != archived.sha256 {
+            return Err(SecurityError::Conflict(format!("Archive of key {} changed since it was written", job.key_id)));
+        }
+        let document: ArchiveDocument = serde_json::from_slice(&document)
+            .map_err(|e| SecurityError::StorageError(format!("Archive of key {} is unreadable: {}", job.key_id, e)))?;
+        if document.format != FORMAT || document.key_id != job.key_id {
+            return Err(SecurityError::Conflict(format!("Archive of key {} holds another key", job.key_id)));
+        }
+
+        let record = KeyRecord {
+            key_id: document.key_id,
+            algorithm: document.algorithm,
+            provider: document.provider,
+            wrapped_key: document.wrapped_key,
+            source: document.source,
+            state: KeyState::Compromised.as_str().to_string(),
+            created_at: document.created_at,
+        };
+        if crypto.restored_key_check_value(&record).await? != archived.check_value {
+            return Err(SecurityError::Conflict(format!("Archived key {} does not match its check value", job.key_id)));
+        }
+        if !self.storage.restore_key(&record.key_id, &record.provider, &record.wrapped_key, &record.state).await? {
+            return Err(SecurityError::Conflict(format!("Key {} is no longer archived", job.key_id)));
+        }
+        sqlx::query("UPDATE key_archives SET restored_at = $2 WHERE key_id = $1")
+            .bind(&job.key_id)
+            .bind(self.clock.now())
+            .execute(self.storage.pool())
+            .await?;
+        crypto.refresh_keys().await
+    }
+
+    /// Run restores past their delay, put restored keys back once their
+    /// window closes, and archive keys retired long enough.
+    pub async fn process_due(&self, state: &AppState) -> Result<(), SecurityError> {
+        if self.store.is_none() {
+            return Ok(());
+        }
+        let now = self.clock.now();
+
+        let due = sqlx::query_as::<_, KeyRestore>(&format!(
+            "SELECT {} FROM key_restores WHERE status = 'approved' AND available_at <= $1 ORDER BY available_at",
+            RESTORE_COLUMNS
+        ))
+        .bind(now)
+        .fetch_all(self.storage.pool())
+        .await?;
+        for job in due {
+            let outcome = self.restore(&state.crypto_service, &job).await;
+            let finished = sqlx::query_as::<_, KeyRestore>(&format!(
+                "UPDATE key_restores SET status = $2, restored_at = $3, expires_at = $4, error = $5 \
+                 WHERE id = $1 AND status = 'approved' RETURNING {}",
+                RESTORE_COLUMNS
+            ))
+            .bind(job.id)
+            .bind(if outcome.is_ok() { "restored" } else { "failed" })
+            .bind(outcome.is_ok().then_some(now))
+            .bind(outcome.is_ok().then(|| now + Duration::seconds(self.config.restore_window_secs)))
+            .bind(outcome.as_ref().err().map(|e| e.to_string()))
+            .fetch_optional(self.storage.pool())
+            .await?;
+            let Some(finished) = finished else {
+                continue;
+            };
+            match &outcome {
+                Ok(()) => warn!("Archived data key {} restored for restore {}", finished.key_id, finished.id),
+                Err(e) => error!("Restore {} of archived key {} failed: {:?}", finished.id, finished.key_id, e),
+            }
+            let action = if outcome.is_ok() { "restored" } else { "failed" };
+            audit(state, SYSTEM_ACTOR, &format!("crypto.key.restore.{}", action), &finished, outcome.is_ok()).await;
+            alert(state, &format!("Archived data key {} {}", finished.key_id, action), &finished).await;
+        }
+
+        let expired = sqlx::query_as::<_, KeyRestore>(&format!(
+            "SELECT {} FROM key_restores WHERE status = 'restored' AND expires_at <= $1 ORDER BY expires_at",
+            RESTORE_COLUMNS
+        ))
+        .bind(now)
+        .fetch_all(self.storage.pool())
+        .await?;
+        for job in expired {
+            // Already retired by hand is as good
+            match state.crypto_service.retire(&job.key_id).await {
+                Ok(()) | Err(SecurityError::Conflict(_)) => {}
+                Err(e) => return Err(e),
+            }
+            if let Err(e) = self.archive(&state.crypto_service, &job.key_id, SYSTEM_ACTOR).await {
+                error!("Failed to archive key {} again after restore {}: {:?}", job.key_id, job.id, e);
+                continue;
+            }
+            let closed = sqlx::query_as::<_, KeyRestore>(&format!(
+                "UPDATE key_restores SET status = 'closed', ended_by = $2, ended_at = $3 \
+                 WHERE id = $1 AND status = 'restored' RETURNING {}",
+                RESTORE_COLUMNS
+            ))
+            .bind(job.id)
+            .bind(SYSTEM_ACTOR)
+            .bind(now)
+            .fetch_optional(self.storage.pool())
+            .await?;
+            if let Some(closed) = closed {
+                info!("Restore window of key {} closed; archived again", closed.key_id);
+                audit(state, SYSTEM_ACTOR, "crypto.key.restore.close", &closed, true).await;
+            }
+        }
+
+        for key_id in self.due_keys().await? {
+            match self.archive(&state.crypto_service, &key_id, SYSTEM_ACTOR).await {
+                Ok(archived) => audit_archive(state, SYSTEM_ACTOR, &archived).await,
+                Err(e) => error!("Failed to archive retired key {}: {:?}", key_id, e),
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn audit(state: &AppState, actor: &str, action: &str, restore: &KeyRestore, success: bool) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: None,
+        actor: actor.to_string(),
+        actor_ip: None,
+        action: action.to_string(),
+        resource: format!("crypto_key:{}", restore.key_id),
+        outcome: if success { "success" } else { "failure" }.to_string(),
+        payload: serde_json::json!({
+            "restore_id": restore.id,
+            "status": restore.status,
+            "available_at": restore.available_at,
+            "expires_at": restore.expires_at,
+            "error": restore.error
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} of key {}: {:?}", action, restore.key_id, e);
+    }
+}
+
+async fn audit_archive(state: &AppState, actor: &str, archived: &ArchivedKey) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: None,
+        actor: actor.to_string(),
+        actor_ip: None,
+        action: "crypto.key.archive".to_string(),
+        resource: format!("crypto_key:{}", archived.key_id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({ "object_key": archived.object_key, "sha256": archived.sha256 }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit archival of key {}: {:?}", archived.key_id, e);
+    }
+}
+
+/// Every step of a restore is paged: one nobody expected must be noticed
+/// before its delay runs out.
+async fn alert(state: &AppState, title: &str, restore: &KeyRestore) {
+    let severity = match restore.status.as_str() {
+        "cancelled" => Severity::Medium,
+        "restored" | "failed" => Severity::Critical,
+        _ => Severity::High,
+    };
+    let details = serde_json::json!({
+        "restore_id": restore.id,
+        "key_id": restore.key_id,
+        "reason": restore.reason,
+        "requested_by": restore.requested_by,
+        "approved_by": restore.approved_by,
+        "available_at": restore.available_at,
+        "expires_at": restore.expires_at,
+        "error": restore.error
+    });
+    state.alerting_service.send(&Alert::new("key_archive", severity, title, details, state.clock.now()), &[]).await;
+}
+
+/// Archive and restore keys in the background; see
+/// `KeyArchiveService::process_due`.
+pub async fn run_archival(state: web::Data<AppState>) {
+    let interval_secs = state.config.key_archive.interval_secs;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = state.key_archive.process_due(&state).await {
+            error!("Key archival failed: {:?}", e);
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::AccessDenied(msg) => HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ConfigError(msg) => HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Key archive operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Key archive operation failed"
+            }))
+        }
+    }
+}
+
+pub async fn list_archived_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.key_archive.list_archived().await {
+        Ok(keys) => Ok(HttpResponse::Ok().json(serde_json::json!({ "keys": keys }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn archive_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.key_archive.archive(&state.crypto_service, &path.into_inner(), &principal.subject).await {
+        Ok(archived) => {
+            audit_archive(&state, &principal.subject, &archived).await;
+            Ok(HttpResponse::Ok().json(archived))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn request_restore_handler(
+    req: HttpRequest,
+    request: web::Json<RestoreRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.key_archive.request_restore(&principal, &request).await {
+        Ok(restore) => {
+            warn!("{} requested a restore of archived key {}", principal.subject, restore.key_id);
+            audit(&state, &principal.subject, "crypto.key.restore.request", &restore, true).await;
+            alert(&state, &format!("Restore of archived data key {} requested", restore.key_id), &restore).await;
+            Ok(HttpResponse::Created().json(restore))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_restores_handler(
+    req: HttpRequest,
+    filter: web::Query<RestoreFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.key_archive.list_restores(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "restores": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_restore_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.key_archive.get_restore(path.into_inner()).await {
+        Ok(restore) => Ok(HttpResponse::Ok().json(restore)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn approve_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.key_archive.approve(path.into_inner(), &principal.subject).await {
+        Ok(restore) => {
+            warn!("{} approved restore {} of archived key {}", principal.subject, restore.id, restore.key_id);
+            audit(&state, &principal.subject, "crypto.key.restore.approve", &restore, true).await;
+            alert(&state, &format!("Restore of archived data key {} approved", restore.key_id), &restore).await;
+            Ok(HttpResponse::Ok().json(restore))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn cancel_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.key_archive.cancel(path.into_inner(), &principal.subject).await {
+        Ok(restore) => {
+            audit(&state, &principal.subject, "crypto.key.restore.cancel", &restore, true).await;
+            alert(&state, &format!("Restore of archived data key {} cancelled", restore.key_id), &restore).await;
+            Ok(HttpResponse::Ok().json(restore))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/crypto/archive")
+            .route("", web::get().to(list_archived_handler))
+            .route("/keys/{key_id}", web::post().to(archive_handler))
+            .route("/restores", web::post().to(request_restore_handler))
+            .route("/restores", web::get().to(list_restores_handler))
+            .route("/restores/{id}", web::get().to(get_restore_handler))
+            .route("/restores/{id}/approve", web::post().to(approve_handler))
+            .route("/restores/{id}/cancel", web::post().to(cancel_handler))
+    );
+}
@@ -0,0 +1,188 @@
+/*!
+Key Cache Module
+Local, encrypted cache of data keys so crypto keeps working while the key source is unreachable
+*/
+
+use chrono::{DateTime, Duration, Utc};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::clock::Clock;
+use crate::config::CryptoConfig;
+use crate::errors::SecurityError;
+use crate::random::RandomSource;
+
+const CACHE_FILE: &str = "data-keys.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    nonce: String,
+    /// Key material sealed under the cache key; the key id is bound as AAD.
+    sealed: String,
+}
+
+pub struct CachedKey {
+    pub key_id: String,
+    pub key_bytes: Vec<u8>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct KeyCache {
+    path: PathBuf,
+    cache_key: LessSafeKey,
+    ttl: Duration,
+    clock: Arc<dyn Clock>,
+    rng: Arc<dyn RandomSource>,
+}
+
+impl KeyCache {
+    /// Open the cache if both a directory and a cache key file are configured.
+    pub fn open(
+        config: &CryptoConfig,
+        clock: Arc<dyn Clock>,
+        rng: Arc<dyn RandomSource>,
+    ) -> Result<Option<Self>, SecurityError> {
+        let (Some(dir), Some(key_file)) = (&config.key_cache_dir, &config.key_cache_key_file) else {
+            return Ok(None);
+        };
+
+        let key_bytes = read_cache_key(Path::new(key_file))?;
+        let unbound = UnboundKey::new(&AES_256_GCM, &key_bytes)
+            .map_err(|_| SecurityError::CryptoInitError("Key cache key must be 32 bytes".to_string()))?;
+
+        std::fs::create_dir_all(dir)
+            .map_err(|e| SecurityError::CryptoInitError(format!("Cannot create key cache directory: {}", e)))?;
+
+        info!("Key cache enabled at {}", dir);
+        Ok(Some(Self {
+            path: Path::new(dir).join(CACHE_FILE),
+            cache_key: LessSafeKey::new(unbound),
+            ttl: Duration::seconds(config.key_cache_ttl_secs),
+            clock,
+            rng,
+        }))
+    }
+
+    fn read_entries(&self) -> Result<BTreeMap<String, Entry>, SecurityError> {
+        match std::fs::read(&self.path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| SecurityError::CryptoError(format!("Corrupt key cache: {}", e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(SecurityError::CryptoError(format!("Cannot read key cache: {}", e))),
+        }
+    }
+
+    /// Replace the cache file atomically so a crash never leaves it half written.
+    fn write_entries(&self, entries: &BTreeMap<String, Entry>) -> Result<(), SecurityError> {
+        let body = serde_json::to_vec(entries)
+            .map_err(|e| SecurityError::CryptoError(e.to_string()))?;
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, body)
+            .and_then(|_| restrict_permissions(&tmp))
+            .and_then(|_| std::fs::rename(&tmp, &self.path))
+            .map_err(|e| SecurityError::CryptoError(format!("Cannot write key cache: {}", e)))
+    }
+
+    pub fn store(&self, key_id: &str, key_bytes: &[u8], created_at: DateTime<Utc>) -> Result<(), SecurityError> {
+        let mut nonce_bytes = [0u8; 12];
+        self.rng.fill(&mut nonce_bytes)
+            .map_err(|_| SecurityError::CryptoError("Failed to generate nonce".to_string()))?;
+
+        let mut sealed = key_bytes.to_vec();
+        self.cache_key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce_bytes), Aad::from(key_id.as_bytes()), &mut sealed)
+            .map_err(|_| SecurityError::CryptoError("Key cache sealing failed".to_string()))?;
+
+        let now = self.clock.now();
+        let mut entries = self.read_entries()?;
+        entries.retain(|_, entry| entry.expires_at > now);
+        entries.insert(key_id.to_string(), Entry {
+            created_at,
+            expires_at: now + self.ttl,
+            nonce: base64::encode(nonce_bytes),
+            sealed: base64::encode(&sealed),
+        });
+        self.write_entries(&entries)
+    }
+
+    /// Unexpired keys. Entries that fail to open are skipped, never trusted.
+    pub fn load(&self) -> Result<Vec<CachedKey>, SecurityError> {
+        let now = self.clock.now();
+        let mut keys = Vec::new();
+
+        for (key_id, entry) in self.read_entries()? {
+            if entry.expires_at <= now {
+                continue;
+            }
+            let opened = base64::decode(&entry.nonce).ok()
+                .and_then(|nonce| Nonce::try_assume_unique_for_key(&nonce).ok())
+                .zip(base64::decode(&entry.sealed).ok())
+                .and_then(|(nonce, mut sealed)| {
+                    self.cache_key
+                        .open_in_place(nonce, Aad::from(key_id.as_bytes()), &mut sealed)
+                        .ok()
+                        .map(|plain| plain.to_vec())
+                });
+            match opened {
+                Some(key_bytes) => keys.push(CachedKey { key_id, key_bytes, created_at: entry.created_at }),
+                None => warn!("Skipping key cache entry {} that failed to open", key_id),
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// Drop one key, or every key when `key_id` is `None`. Returns how many were removed.
+    pub fn invalidate(&self, key_id: Option<&str>) -> Result<usize, SecurityError> {
+        let mut entries = self.read_entries()?;
+        let before = entries.len();
+        match key_id {
+            Some(key_id) => {
+                entries.remove(key_id);
+            }
+            None => entries.clear(),
+        }
+        self.write_entries(&entries)?;
+        Ok(before - entries.len())
+    }
+}
+
+/// The cache key is 64 hex characters in a file only the service user can read.
+/// A TPM-sealed key can be supplied the same way by unsealing it to a tmpfs file.
+fn read_cache_key(path: &Path) -> Result<Vec<u8>, SecurityError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(path)
+            .map_err(|e| SecurityError::CryptoInitError(format!("Cannot read key cache key: {}", e)))?
+            .permissions()
+            .mode();
+        if mode & 0o077 != 0 {
+            return Err(SecurityError::CryptoInitError(
+                "Key cache key file must not be readable by group or others".to_string(),
+            ));
+        }
+    }
+
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| SecurityError::CryptoInitError(format!("Cannot read key cache key: {}", e)))?;
+    hex::decode(contents.trim())
+        .map_err(|_| SecurityError::CryptoInitError("Key cache key must be hex encoded".to_string()))
+}
+
+fn restrict_permissions(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
@@ -0,0 +1,430 @@
+/*!
+Key Compromise Module
+Emergency response to a data key that is no longer trusted
+
+Reporting a key compromised, via `POST /admin/crypto/keys/{key_id}/compromise`,
+does the following in order:
+
+1. The key stops encrypting immediately, on this replica at once and on the
+   others within `CRYPTO_KEY_REFRESH_INTERVAL_SECS`.
+2. A replacement key is generated.
+3. A critical `key_compromise` incident is opened for the key.
+4. A re-encryption job is created.
+
+The compromised key keeps decrypting. Each decryption under it is audited,
+so its ciphertexts can be moved to the replacement with `POST /crypto/rewrap`.
+
+The job's inventory comes from the per-day key usage ledger and the audit
+trail. Encryptions under the key, minus rewraps off it, give the
+ciphertexts still outstanding. Completing the job retires the key and
+resolves the incident. It is refused while ciphertexts are outstanding
+unless forced with `?force=true`, for data that was deleted rather than
+rewrapped.
+
+Tokens and signatures are keyed by the service secrets, not by data keys,
+so the inventory only covers ciphertexts.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::alerting::Severity;
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, Principal};
+use crate::detection::{self, IncidentUpdate, NewIncident};
+use crate::errors::SecurityError;
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::{KeyUsage, Storage};
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "created_at", column: "created_at", kind: KeyKind::Timestamp },
+];
+
+const SELECT_COLUMNS: &str = "id, key_id, reason, reported_by, incident_id, replacement_key_id, status, \
+    rewrapped, created_at, completed_by, completed_at";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct KeyCompromise {
+    pub id: Uuid,
+    pub key_id: String,
+    pub reason: String,
+    pub reported_by: String,
+    pub incident_id: Option<Uuid>,
+    pub replacement_key_id: Option<String>,
+    /// `reencrypting` or `completed`.
+    pub status: String,
+    pub rewrapped: i64,
+    pub created_at: DateTime<Utc>,
+    pub completed_by: Option<String>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// What was protected with a compromised key, and how much of it remains.
+#[derive(Debug, Serialize)]
+pub struct KeyInventory {
+    pub key_id: String,
+    pub encryptions: i64,
+    pub decryptions: i64,
+    pub rewrapped: i64,
+    /// Ciphertexts not yet moved to another key.
+    pub outstanding: i64,
+    pub first_used_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// Audit events naming the key, e.g. cache-served operations.
+    pub audit_events: i64,
+    pub usage: Vec<KeyUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompromiseRequest {
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CompleteQuery {
+    /// Complete even though ciphertexts are outstanding.
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CompromiseFilter {
+    pub status: Option<String>,
+    pub key_id: Option<String>,
+}
+
+pub struct KeyCompromiseService {
+    storage: Storage,
+}
+
+impl KeyCompromiseService {
+    pub async fn new(storage: Storage) -> Result<Self, SecurityError> {
+
+        info!("Key compromise service initialized successfully");
+        Ok(Self { storage })
+    }
+
+    /// Disable the key, rotate, open the incident and start the job.
+    pub async fn report(
+        &self,
+        state: &crate::AppState,
+        principal: &Principal,
+        key_id: &str,
+        reason: &str,
+    ) -> Result<KeyCompromise, SecurityError> {
+        if reason.trim().is_empty() {
+            return Err(SecurityError::ValidationError("reason is required".to_string()));
+        }
+
+        let replacement_key_id = state.crypto_service.compromise(key_id).await?;
+        warn!("{} reported data key {} compromised; replaced by {}", principal.subject, key_id, replacement_key_id);
+
+        let audit_id = state.audit_service.record(NewAuditEvent {
+            tenant_id: principal.tenant_id.clone(),
+            actor: principal.subject.clone(),
+            actor_ip: None,
+            action: "crypto.key.compromise".to_string(),
+            resource: format!("crypto_key:{}", key_id),
+            outcome: "success".to_string(),
+            payload: serde_json::json!({
+                "reason": reason,
+                "replacement_key_id": replacement_key_id
+            }),
+        }).await;
+        let event_ids = match audit_id {
+            Ok(id) => vec![id],
+            Err(e) => {
+                warn!("Failed to audit compromise of key {}: {:?}", key_id, e);
+                Vec::new()
+            }
+        };
+
+        let now = state.clock.now();
+        let mut tx = self.storage.begin().await?;
+        let opened = detection::open(state, &mut tx, NewIncident {
+            rule: "key_compromise".to_string(),
+            severity: Severity::Critical,
+            tenant_id: None,
+            entity_type: "crypto_key",
+            entity: key_id.to_string(),
+            event_ids,
+            first_seen: now,
+            last_seen: now,
+        }).await?;
+
+        let job = sqlx::query_as::<_, KeyCompromise>(&format!(
+            "INSERT INTO key_compromises (id, key_id, reason, reported_by, incident_id, replacement_key_id) \
+             VALUES ($1, $2, $3, $4, $5, $6) RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(key_id)
+        .bind(reason)
+        .bind(&principal.subject)
+        .bind(opened.incident.id)
+        .bind(&replacement_key_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        detection::announce(state, &[opened]).await;
+        Ok(job)
+    }
+
+    pub async fn list(&self, filter: &CompromiseFilter, page: &PageRequest) -> Result<Page<KeyCompromise>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM key_compromises WHERE 1 = 1",
+            SELECT_COLUMNS
+        ));
+        if let Some(status) = &filter.status {
+            builder.push(" AND status = ").push_bind(status.clone());
+        }
+        if let Some(key_id) = &filter.key_id {
+            builder.push(" AND key_id = ").push_bind(key_id.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let jobs = builder
+            .build_query_as::<KeyCompromise>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(jobs, |job, _| (SortKey::Timestamp(job.created_at), job.id)))
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<KeyCompromise, SecurityError> {
+        sqlx::query_as::<_, KeyCompromise>(&format!(
+            "SELECT {} FROM key_compromises WHERE id = $1",
+            SELECT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("Key compromise not found".to_string()))
+    }
+
+    pub async fn inventory(&self, job: &KeyCompromise) -> Result<KeyInventory, SecurityError> {
+        let usage = self.storage.key_usage(&job.key_id).await?;
+        let total = |operation: &str| -> i64 {
+            usage.iter().filter(|u| u.operation == operation).map(|u| u.count).sum()
+        };
+        let encryptions = total("encrypt");
+        let decryptions = total("decrypt");
+
+        let audit_events: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM audit_events WHERE resource = $1")
+            .bind(format!("crypto_key:{}", job.key_id))
+            .fetch_one(self.storage.pool())
+            .await?;
+
+        Ok(KeyInventory {
+            key_id: job.key_id.clone(),
+            encryptions,
+            decryptions,
+            rewrapped: job.rewrapped,
+            outstanding: (encryptions - job.rewrapped).max(0),
+            first_used_at: usage.iter().map(|u| u.first_at).min(),
+            last_used_at: usage.iter().map(|u| u.last_at).max(),
+            audit_events,
+            usage,
+        })
+    }
+
+    /// Count one ciphertext moved off a compromised key.
+    pub async fn record_rewrap(&self, key_id: &str) -> Result<(), SecurityError> {
+        sqlx::query("UPDATE key_compromises SET rewrapped = rewrapped + 1 WHERE key_id = $1 AND status = 'reencrypting'")
+            .bind(key_id)
+            .execute(self.storage.pool())
+            .await?;
+        Ok(())
+    }
+
+    /// Retire the key and resolve the incident once re-encryption is done.
+    pub async fn complete(
+        &self,
+        state: &crate::AppState,
+        principal: &Principal,
+        id: Uuid,
+        force: bool,
+    ) -> Result<KeyCompromise, SecurityError> {
+        let job = self.get(id).await?;
+        if job.status != "reencrypting" {
+            return Err(SecurityError::Conflict(format!("Key compromise {} is already {}", id, job.status)));
+        }
+
+        // Counts still held in memory would understate what is outstanding
+        if let Err(e) = state.crypto_service.flush_usage().await {
+            warn!("Failed to record data key usage before completing {}: {:?}", id, e);
+        }
+        let inventory = self.inventory(&job).await?;
+        if inventory.outstanding > 0 && !force {
+            return Err(SecurityError::Conflict(format!(
+                "{} ciphertexts under key {} have not been rewrapped",
+                inventory.outstanding, job.key_id
+            )));
+        }
+
+        state.crypto_service.retire(&job.key_id).await?;
+        let job = sqlx::query_as::<_, KeyCompromise>(&format!(
+            "UPDATE key_compromises SET status = 'completed', completed_by = $2, completed_at = NOW() \
+             WHERE id = $1 RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(id)
+        .bind(&principal.subject)
+        .fetch_one(self.storage.pool())
+        .await?;
+
+        if let Some(incident_id) = job.incident_id {
+            let mut enrichment = serde_json::Map::new();
+            enrichment.insert("key_compromise".to_string(), serde_json::json!({
+                "rewrapped": inventory.rewrapped,
+                "outstanding": inventory.outstanding,
+                "forced": force,
+                "completed_by": principal.subject
+            }));
+            let update = IncidentUpdate {
+                status: Some("resolved".to_string()),
+                enrichment: Some(enrichment),
+                external_ref: None,
+            };
+            if let Err(e) = state.incidents.update(incident_id, update, None).await {
+                warn!("Failed to resolve incident {} for key {}: {:?}", incident_id, job.key_id, e);
+            }
+        }
+
+        info!("{} completed re-encryption off key {}; key retired", principal.subject, job.key_id);
+        Ok(job)
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Key compromise operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Key compromise operation failed"
+            }))
+        }
+    }
+}
+
+pub async fn compromise_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    request: web::Json<CompromiseRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.key_compromises.report(&state, &principal, &path.into_inner(), &request.reason).await {
+        Ok(job) => Ok(HttpResponse::Created().json(job)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_handler(
+    req: HttpRequest,
+    filter: web::Query<CompromiseFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.key_compromises.list(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "compromises": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let job = match state.key_compromises.get(path.into_inner()).await {
+        Ok(job) => job,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.key_compromises.inventory(&job).await {
+        Ok(inventory) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "compromise": job,
+            "inventory": inventory
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn complete_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<CompleteQuery>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let force = query.force;
+    match state.key_compromises.complete(&state, &principal, path.into_inner(), force).await {
+        Ok(job) => {
+            let recorded = state.audit_service.record(NewAuditEvent {
+                tenant_id: principal.tenant_id.clone(),
+                actor: principal.subject.clone(),
+                actor_ip: None,
+                action: "crypto.key.retire".to_string(),
+                resource: format!("crypto_key:{}", job.key_id),
+                outcome: "success".to_string(),
+                payload: serde_json::json!({ "compromise_id": job.id, "forced": force }),
+            }).await;
+            if let Err(e) = recorded {
+                warn!("Failed to audit retirement of key {}: {:?}", job.key_id, e);
+            }
+            Ok(HttpResponse::Ok().json(job))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/crypto")
+            .route("/keys/{key_id}/compromise", web::post().to(compromise_handler))
+            .route("/compromises", web::get().to(list_handler))
+            .route("/compromises/{id}", web::get().to(get_handler))
+            .route("/compromises/{id}/complete", web::post().to(complete_handler))
+    );
+}
@@ -0,0 +1,76 @@
+/*!
+AWS KMS Key Provider
+Wraps data keys with an AWS KMS key
+
+Each data key is encrypted under `CRYPTO_KMS_KEY_ID` with its key id as
+encryption context, so a wrapped key cannot be passed off as another.
+Credentials and region are the ones the KMS secret resolver uses.
+*/
+
+use futures::future::BoxFuture;
+use std::sync::Arc;
+
+use super::KeyProvider;
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::errors::SecurityError;
+use crate::secrets::kms::KmsClient;
+
+pub const NAME: &str = "aws-kms";
+
+pub struct AwsKmsKeyProvider {
+    kms: KmsClient,
+    key_id: String,
+}
+
+impl AwsKmsKeyProvider {
+    pub fn new(config: &Config, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let key_id = config.crypto.kms_key_id.clone()
+            .ok_or_else(|| SecurityError::ConfigError("CRYPTO_KMS_KEY_ID is not set".to_string()))?;
+        Ok(Self { kms: KmsClient::new(&config.secrets, clock)?, key_id })
+    }
+
+    async fn encrypt(&self, key_id: &str, key: &[u8]) -> Result<String, SecurityError> {
+        let body = self.kms
+            .call("Encrypt", serde_json::json!({
+                "KeyId": self.key_id,
+                "Plaintext": base64::encode(key),
+                "EncryptionContext": { "key_id": key_id }
+            }))
+            .await
+            .map_err(SecurityError::CryptoError)?;
+        body["CiphertextBlob"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| SecurityError::CryptoError("KMS response has no ciphertext".to_string()))
+    }
+
+    async fn decrypt(&self, key_id: &str, wrapped: &str) -> Result<Vec<u8>, SecurityError> {
+        let body = self.kms
+            .call("Decrypt", serde_json::json!({
+                "KeyId": self.key_id,
+                "CiphertextBlob": wrapped,
+                "EncryptionContext": { "key_id": key_id }
+            }))
+            .await
+            .map_err(SecurityError::CryptoError)?;
+        body["Plaintext"]
+            .as_str()
+            .and_then(|encoded| base64::decode(encoded).ok())
+            .ok_or_else(|| SecurityError::CryptoError(format!("KMS returned no plaintext for key {}", key_id)))
+    }
+}
+
+impl KeyProvider for AwsKmsKeyProvider {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn wrap<'a>(&'a self, key_id: &'a str, key: &'a [u8]) -> BoxFuture<'a, Result<String, SecurityError>> {
+        Box::pin(self.encrypt(key_id, key))
+    }
+
+    fn unwrap<'a>(&'a self, key_id: &'a str, wrapped: &'a str) -> BoxFuture<'a, Result<Vec<u8>, SecurityError>> {
+        Box::pin(self.decrypt(key_id, wrapped))
+    }
+}
@@ -0,0 +1,70 @@
+/*!
+Local Key Provider
+Wraps data keys with AES-256-GCM under `SECURITY_MASTER_KEY`
+
+The master key lives in the service's own configuration, so this is for
+development and for migrating to an external provider.
+*/
+
+use futures::future::BoxFuture;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+use std::sync::Arc;
+
+use super::KeyProvider;
+use crate::errors::SecurityError;
+use crate::random::RandomSource;
+
+pub const NAME: &str = "local";
+
+pub struct LocalKeyProvider {
+    master_key: LessSafeKey,
+    rng: Arc<dyn RandomSource>,
+}
+
+impl LocalKeyProvider {
+    pub fn new(master_key: &[u8], rng: Arc<dyn RandomSource>) -> Result<Self, SecurityError> {
+        let unbound = UnboundKey::new(&AES_256_GCM, master_key)
+            .map_err(|_| SecurityError::CryptoInitError("Invalid master key".to_string()))?;
+        Ok(Self { master_key: LessSafeKey::new(unbound), rng })
+    }
+
+    /// `<base64 nonce>:<base64 sealed key>`, the key id bound as AAD.
+    fn seal(&self, key_id: &str, key: &[u8]) -> Result<String, SecurityError> {
+        let mut nonce_bytes = [0u8; 12];
+        self.rng.fill(&mut nonce_bytes)?;
+
+        let mut sealed = key.to_vec();
+        self.master_key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce_bytes), Aad::from(key_id.as_bytes()), &mut sealed)
+            .map_err(|_| SecurityError::CryptoError("Key wrapping failed".to_string()))?;
+        Ok(format!("{}:{}", base64::encode(nonce_bytes), base64::encode(&sealed)))
+    }
+
+    fn open(&self, key_id: &str, wrapped: &str) -> Result<Vec<u8>, SecurityError> {
+        let invalid = || SecurityError::CryptoError(format!("Key {} does not open under the master key", key_id));
+        let (nonce, sealed) = wrapped.split_once(':').ok_or_else(invalid)?;
+        let nonce = base64::decode(nonce).ok()
+            .and_then(|nonce| Nonce::try_assume_unique_for_key(&nonce).ok())
+            .ok_or_else(invalid)?;
+        let mut sealed = base64::decode(sealed).map_err(|_| invalid())?;
+
+        self.master_key
+            .open_in_place(nonce, Aad::from(key_id.as_bytes()), &mut sealed)
+            .map(|key| key.to_vec())
+            .map_err(|_| invalid())
+    }
+}
+
+impl KeyProvider for LocalKeyProvider {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn wrap<'a>(&'a self, key_id: &'a str, key: &'a [u8]) -> BoxFuture<'a, Result<String, SecurityError>> {
+        Box::pin(async move { self.seal(key_id, key) })
+    }
+
+    fn unwrap<'a>(&'a self, key_id: &'a str, wrapped: &'a str) -> BoxFuture<'a, Result<Vec<u8>, SecurityError>> {
+        Box::pin(async move { self.open(key_id, wrapped) })
+    }
+}
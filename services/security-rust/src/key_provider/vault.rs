@@ -0,0 +1,105 @@
+/*!
+Vault Transit Key Provider
+Wraps data keys with a HashiCorp Vault transit key
+
+Uses the Vault address, token and namespace from the secrets
+configuration. The transit key never leaves Vault; rotating it there is
+transparent, since Vault keeps older versions able to decrypt.
+*/
+
+use futures::future::BoxFuture;
+use std::time::Duration;
+
+use super::KeyProvider;
+use crate::config::Config;
+use crate::errors::SecurityError;
+
+pub const NAME: &str = "vault";
+
+pub struct VaultTransitKeyProvider {
+    client: reqwest::Client,
+    addr: String,
+    token: String,
+    namespace: Option<String>,
+    mount: String,
+    key: String,
+}
+
+impl VaultTransitKeyProvider {
+    pub fn new(config: &Config) -> Result<Self, SecurityError> {
+        let secrets = &config.secrets;
+        let addr = secrets.vault_addr.clone()
+            .ok_or_else(|| SecurityError::ConfigError("VAULT_ADDR is not set".to_string()))?;
+        let token = secrets.vault_token.clone()
+            .ok_or_else(|| SecurityError::ConfigError("VAULT_TOKEN (or VAULT_TOKEN_FILE) is not set".to_string()))?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(secrets.timeout_secs))
+            .build()
+            .map_err(|e| SecurityError::ConfigError(format!("Failed to build Vault client: {}", e)))?;
+
+        Ok(Self {
+            client,
+            addr: addr.trim_end_matches('/').to_string(),
+            token,
+            namespace: secrets.vault_namespace.clone(),
+            mount: config.crypto.vault_transit_mount.trim_matches('/').to_string(),
+            key: config.crypto.vault_transit_key.clone(),
+        })
+    }
+
+    async fn call(&self, operation: &str, body: serde_json::Value) -> Result<serde_json::Value, SecurityError> {
+        let url = format!("{}/v1/{}/{}/{}", self.addr, self.mount, operation, self.key);
+        let mut request = self.client.post(&url).header("X-Vault-Token", &self.token);
+        if let Some(namespace) = &self.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+
+        let response = request
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| SecurityError::CryptoError(format!("Vault transit {} failed: {}", operation, e)))?;
+        if !response.status().is_success() {
+            return Err(SecurityError::CryptoError(format!(
+                "Vault transit {} returned {}",
+                operation,
+                response.status()
+            )));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| SecurityError::CryptoError(format!("Vault transit response is not JSON: {}", e)))
+    }
+
+    async fn encrypt(&self, key: &[u8]) -> Result<String, SecurityError> {
+        let body = self.call("encrypt", serde_json::json!({ "plaintext": base64::encode(key) })).await?;
+        body["data"]["ciphertext"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| SecurityError::CryptoError("Vault transit response has no ciphertext".to_string()))
+    }
+
+    async fn decrypt(&self, key_id: &str, wrapped: &str) -> Result<Vec<u8>, SecurityError> {
+        let body = self.call("decrypt", serde_json::json!({ "ciphertext": wrapped })).await?;
+        body["data"]["plaintext"]
+            .as_str()
+            .and_then(|encoded| base64::decode(encoded).ok())
+            .ok_or_else(|| SecurityError::CryptoError(format!("Vault transit returned no plaintext for key {}", key_id)))
+    }
+}
+
+impl KeyProvider for VaultTransitKeyProvider {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn wrap<'a>(&'a self, _key_id: &'a str, key: &'a [u8]) -> BoxFuture<'a, Result<String, SecurityError>> {
+        Box::pin(self.encrypt(key))
+    }
+
+    fn unwrap<'a>(&'a self, key_id: &'a str, wrapped: &'a str) -> BoxFuture<'a, Result<Vec<u8>, SecurityError>> {
+        Box::pin(self.decrypt(key_id, wrapped))
+    }
+}
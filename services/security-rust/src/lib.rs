@@ -0,0 +1,311 @@
+/*!
+COTAI Security Service
+High-performance security modules written in Rust for critical security operations
+
+The `cotai-security` binary runs this as a standalone service. Small
+deployments can instead embed the endpoints in an existing Actix app, and
+tests can assemble one against their own storage, clock, randomness and
+master key; both go through `SecurityServiceBuilder` (see `app`).
+*/
+
+use actix_web::{web, HttpResponse, Result};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod alerting;
+pub mod app;
+pub mod backups;
+pub mod billing;
+pub mod bulk;
+pub mod bundles;
+pub mod changes;
+pub mod clock;
+pub mod collusion;
+pub mod commands;
+pub mod compression;
+pub mod concurrency;
+pub mod conditional;
+pub mod config;
+pub mod containment;
+pub mod credentials;
+pub mod crypto;
+pub mod crypto_stream;
+pub mod deadline;
+pub mod delivery;
+pub mod degraded;
+pub mod detection;
+pub mod dlq;
+pub mod email;
+pub mod domains;
+pub mod dossiers;
+pub mod auth;
+pub mod authz;
+pub mod audit;
+pub mod mailbox;
+pub mod monitoring;
+pub mod network;
+pub mod pagination;
+pub mod pgproxy;
+pub mod pipeline;
+pub mod proxy_protocol;
+pub mod plugins;
+pub mod policies;
+pub mod privacy;
+pub mod quorum;
+pub mod random;
+pub mod rate_limiting;
+pub mod s3_gateway;
+pub mod retention;
+pub mod seal;
+pub mod secrets;
+pub mod signing;
+pub mod soar;
+pub mod soft_delete;
+pub mod sod;
+pub mod startup;
+pub mod validation;
+pub mod whistleblower;
+pub mod storage;
+pub mod tenant_settings;
+pub mod tls;
+pub mod errors;
+pub mod events;
+pub mod experiments;
+pub mod expr;
+pub mod expiry;
+pub mod flags;
+pub mod forensics;
+pub mod health;
+pub mod hooks;
+pub mod jobs;
+pub mod key_archive;
+pub mod key_cache;
+pub mod key_compromise;
+pub mod key_provider;
+pub mod custody;
+pub mod maintenance;
+pub mod manifests;
+pub mod notary;
+pub mod org;
+pub mod registry;
+pub mod residency;
+pub mod search;
+pub mod i18n;
+pub mod timezone;
+pub mod problem;
+pub mod echo;
+pub mod status_page;
+pub mod submissions;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+
+use alerting::AlertingService;
+use changes::ChangeHistory;
+use clock::Clock;
+use commands::CommandLog;
+use config::{Config, LiveConfig};
+use containment::ContainmentService;
+use credentials::OutboundCredentials;
+use crypto::bulk::BulkDecryption;
+use crypto::webhook::WebhookSigner;
+use crypto::guard::ReadGuard;
+use crypto::tokenization::TokenVault;
+use crypto::CryptoService;
+use degraded::DependencyMonitor;
+use delivery::DeliveryService;
+use detection::{CorrelationEngine, IncidentService, UebaService, UsageBaselines};
+use dlq::DeadLetterQueue;
+use events::EventBus;
+use experiments::ExperimentService;
+use expiry::{ExpiryRegistry, ExpiryService};
+use flags::FeatureFlags;
+use health::{HealthRegistry, Readiness};
+use hooks::RequestHooks;
+use jobs::JobService;
+use key_archive::KeyArchiveService;
+use key_compromise::KeyCompromiseService;
+use maintenance::MaintenanceService;
+use network::ClientIps;
+use authz::AuthzService;
+use sod::SodService;
+use org::OrgService;
+use registry::ResourceRegistry;
+use search::SearchService;
+use i18n::I18n;
+use status_page::StatusPage;
+use backups::BackupService;
+use billing::BillingService;
+use bundles::ConfigBundles;
+use custody::CustodyService;
+use manifests::ManifestService;
+use notary::NotaryService;
+use quorum::QuorumService;
+use submissions::SubmissionService;
+use collusion::CollusionService;
+use dossiers::DossierService;
+use residency::ResidencyService;
+use retention::RetentionService;
+use whistleblower::WhistleblowerService;
+use mailbox::MailboxService;
+use forensics::ForensicStore;
+use soar::SoarService;
+use auth::AuthService;
+use auth::access_reviews::AccessReviewService;
+use auth::break_glass::BreakGlassService;
+use auth::captcha::CaptchaService;
+use auth::password::PasswordService;
+use auth::consent::ConsentService;
+use auth::dormancy::DormancyService;
+use s3_gateway::S3Gateway;
+use email::EmailService;
+use domains::DomainVerificationService;
+use auth::elevation::ElevationService;
+use auth::otp::OtpService;
+use auth::api_keys::ApiKeyService;
+use auth::service_accounts::ServiceAccountService;
+use auth::sessions::SessionService;
+use auth::ssh_ca::SshCaService;
+use auth::tokens::TokenService;
+use audit::{siem::SiemExporter, AuditService};
+use monitoring::threats::ThreatEngine;
+use monitoring::MetricsService;
+use plugins::PluginHost;
+use policies::PolicyService;
+use privacy::NoiseLayer;
+use random::{HealthTestedSource, RandomSource};
+use rate_limiting::RateLimiter;
+use seal::SealService;
+use startup::StartupReport;
+use storage::Storage;
+use tenant_settings::TenantSettingsService;
+
+pub use app::{SecurityService, SecurityServiceBuilder};
+pub use errors::SecurityError;
+
+pub struct AppState {
+    pub config: Config,
+    /// Settings that can change while running; see `config::run_reload`.
+    pub live_config: LiveConfig,
+    pub storage: Storage,
+    pub clock: Arc<dyn Clock>,
+    pub random: Arc<dyn RandomSource>,
+    /// Health of `random`, which it wraps.
+    pub random_health: Arc<HealthTestedSource>,
+    pub crypto_service: CryptoService,
+    pub auth_service: AuthService,
+    pub tokens: TokenService,
+    pub sessions: SessionService,
+    pub audit_service: AuditService,
+    pub metrics_service: MetricsService,
+    pub threats: ThreatEngine,
+    pub rate_limiter: RateLimiter,
+    pub alerting_service: AlertingService,
+    pub dead_letters: DeadLetterQueue,
+    pub event_bus: EventBus,
+    pub policy_service: PolicyService,
+    pub change_history: ChangeHistory,
+    pub feature_flags: FeatureFlags,
+    pub experiments: ExperimentService,
+    pub maintenance: MaintenanceService,
+    pub tenant_settings: TenantSettingsService,
+    pub incidents: IncidentService,
+    pub correlation: CorrelationEngine,
+    pub ueba: UebaService,
+    pub usage: UsageBaselines,
+    pub containment: ContainmentService,
+    pub soar: SoarService,
+    pub key_compromises: KeyCompromiseService,
+    pub service_accounts: ServiceAccountService,
+    pub api_keys: ApiKeyService,
+    pub consents: ConsentService,
+    pub otp: OtpService,
+    pub captcha: CaptchaService,
+    pub passwords: PasswordService,
+    pub notary: NotaryService,
+    pub seal: SealService,
+    pub manifests: ManifestService,
+    pub custody: CustodyService,
+    pub authz: AuthzService,
+    pub sod: SodService,
+    pub org: OrgService,
+    pub resource_registry: ResourceRegistry,
+    pub search: SearchService,
+    pub i18n: I18n,
+    pub status_page: StatusPage,
+    pub bundles: ConfigBundles,
+    pub token_vault: TokenVault,
+    pub crypto_guard: ReadGuard,
+    pub bulk_decrypt: BulkDecryption,
+    pub webhooks: WebhookSigner,
+    pub plugins: PluginHost,
+    pub request_hooks: RequestHooks,
+    pub client_ips: ClientIps,
+    pub noise: NoiseLayer,
+    pub retention: RetentionService,
+    pub whistleblower: WhistleblowerService,
+    pub mailbox: MailboxService,
+    pub forensics: ForensicStore,
+    pub command_log: CommandLog,
+    pub break_glass: BreakGlassService,
+    pub ssh_ca: SshCaService,
+    pub elevations: ElevationService,
+    pub access_reviews: AccessReviewService,
+    pub dormancy: DormancyService,
+    pub s3_gateway: S3Gateway,
+    pub email: EmailService,
+    pub domains: DomainVerificationService,
+    pub backups: BackupService,
+    pub quorum: QuorumService,
+    pub submissions: SubmissionService,
+    pub collusion: CollusionService,
+    pub dossiers: DossierService,
+    pub residency: ResidencyService,
+    pub key_archive: KeyArchiveService,
+    pub billing: BillingService,
+    pub jobs: JobService,
+    pub credentials: OutboundCredentials,
+    pub siem: SiemExporter,
+    pub delivery: DeliveryService,
+    pub expiry: ExpiryService,
+    pub expiry_sources: ExpiryRegistry,
+    pub startup: StartupReport,
+    pub health: HealthRegistry,
+    pub dependencies: DependencyMonitor,
+}
+
+pub async fn health_check(data: web::Data<AppState>) -> Result<HttpResponse> {
+    // Liveness stays 200 in degraded mode; the report says what still works
+    let mode = data.dependencies.report();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "healthy",
+        "service": "cotai-security",
+        "version": "1.0.0",
+        "mode": mode.mode,
+        "dependencies": mode.dependencies,
+        "capabilities": mode.capabilities
+    })))
+}
+
+pub async fn readiness_check(data: web::Data<AppState>) -> Result<HttpResponse> {
+    let timeout = Duration::from_millis(data.config.health.check_timeout_ms);
+    let mut report = data.health.run(&data, timeout).await;
+    let components = data.startup.components();
+
+    // Degraded components still serve traffic, so readiness holds
+    if report.status == Readiness::Ready && data.startup.is_degraded() {
+        report.status = Readiness::Degraded;
+    }
+
+    let mut response = if report.status == Readiness::NotReady {
+        HttpResponse::ServiceUnavailable()
+    } else {
+        HttpResponse::Ok()
+    };
+    Ok(response.json(serde_json::json!({
+        "status": report.status,
+        "checks": report.checks,
+        "components": components
+    })))
+}
@@ -0,0 +1,700 @@
+/*!
+Mailbox Module
+Store-and-forward messages between service identities
+
+Services pass each other material that must not travel through their own
+queues or logs, such as bid-opening keys handed between network segments
+that never talk directly. A caller with `MAILBOX_SEND_SCOPE` deposits a
+message for another identity, addressed by its subject
+(`service_account:<id>`, `api_key:<id>`, ...):
+
+```text
+POST /mailbox/messages  { "recipient": "...", "subject": "...", "body": "...", "ttl_secs": 3600 }
+```
+
+Bodies are encrypted at rest under the current data key, bound to the
+message, sender and recipient. The recipient, with `MAILBOX_RECEIVE_SCOPE`,
+polls `GET /mailbox/messages`, reads a message with
+`GET /mailbox/messages/{id}` and acknowledges it with
+`POST /mailbox/messages/{id}/ack`, which erases the body. Nobody else can
+read a message, admins included. Unacknowledged messages expire after
+`ttl_secs` (default `MAILBOX_DEFAULT_TTL_SECS`, at most
+`MAILBOX_MAX_TTL_SECS`) and their bodies are erased too.
+
+A recipient that registers a webhook with `PUT /mailbox/webhook` is told
+of each new message: a POST of its id, sender, subject and expiry, never
+the body, signed like SOAR deliveries with the secret returned at
+registration, and retried up to `MAILBOX_WEBHOOK_MAX_ATTEMPTS` times. A tenant's
+identities may only register URLs in a domain the tenant has verified
+(see `domains`).
+
+Sending, reading, acknowledging and expiry are all audited.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::receipts::{self, RECEIPT_HEADER};
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, MailboxConfig};
+use crate::crypto::{CryptoService, DecryptionRequest, EncryptionRequest};
+use crate::deadline;
+use crate::errors::SecurityError;
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::soar::{self, SIGNATURE_HEADER};
+use crate::storage::Storage;
+use crate::AppState;
+
+const SYSTEM_ACTOR: &str = "system:mailbox";
+
+const MAX_SUBJECT_CHARS: usize = 200;
+
+const SECRET_BYTES: usize = 32;
+
+const MAX_BACKOFF_SECS: i64 = 3600;
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "created_at", column: "created_at", kind: KeyKind::Timestamp },
+];
+
+const MESSAGE_COLUMNS: &str = "id, tenant_id, sender, recipient, subject_line, size, status, created_at, \
+    expires_at, read_at, acknowledged_at, notify_status";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct MessageInfo {
+    pub id: Uuid,
+    pub tenant_id: Option<String>,
+    pub sender: String,
+    pub recipient: String,
+    #[sqlx(rename = "subject_line")]
+    #[serde(rename = "subject")]
+    pub subject_line: String,
+    pub size: i32,
+    /// `pending`, `acknowledged` or `expired`.
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub read_at: Option<DateTime<Utc>>,
+    pub acknowledged_at: Option<DateTime<Utc>>,
+    /// `none`, `pending`, `sent` or `failed`.
+    pub notify_status: String,
+}
+
+#[derive(Debug, FromRow)]
+struct SealedBody {
+    encrypted_body: Option<String>,
+    key_id: Option<String>,
+    nonce: Option<String>,
+    context_hash: Option<String>,
+}
+
+/// A notification due, with where it goes.
+#[derive(Debug, FromRow)]
+struct DueNotification {
+    id: Uuid,
+    sender: String,
+    recipient: String,
+    subject_line: String,
+    expires_at: DateTime<Utc>,
+    notify_attempts: i32,
+    url: String,
+    encrypted_secret: String,
+    key_id: String,
+    nonce: String,
+    context_hash: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SendRequest {
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    pub ttl_secs: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookRequest {
+    pub url: String,
+}
+
+fn body_context(id: Uuid, sender: &str, recipient: &str) -> HashMap<String, String> {
+    HashMap::from([
+        ("purpose".to_string(), "mailbox".to_string()),
+        ("message_id".to_string(), id.to_string()),
+        ("sender".to_string(), sender.to_string()),
+        ("recipient".to_string(), recipient.to_string()),
+    ])
+}
+
+fn secret_context(subject: &str) -> HashMap<String, String> {
+    HashMap::from([
+        ("purpose".to_string(), "mailbox_webhook".to_string()),
+        ("subject".to_string(), subject.to_string()),
+    ])
+}
+
+pub struct MailboxService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: MailboxConfig,
+    client: reqwest::Client,
+}
+
+impl MailboxService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(config.mailbox.timeout_secs))
+            .build()
+            .map_err(|e| SecurityError::ConfigError(format!("Mailbox client: {}", e)))?;
+
+        info!("Mailbox service initialized successfully");
+        Ok(Self { storage, clock, config: config.mailbox.clone(), client })
+    }
+
+    pub async fn send(
+        &self,
+        crypto: &CryptoService,
+        sender: &Principal,
+        request: SendRequest,
+    ) -> Result<MessageInfo, SecurityError> {
+        let recipient = request.recipient.trim();
+        if recipient.is_empty() {
+            return Err(SecurityError::ValidationError("recipient is required".to_string()));
+        }
+        if request.subject.trim().is_empty() || request.subject.chars().count() > MAX_SUBJECT_CHARS {
+            return Err(SecurityError::ValidationError(format!(
+                "subject must be between 1 and {} characters",
+                MAX_SUBJECT_CHARS
+            )));
+        }
+        if request.body.is_empty() || request.body.len() > self.config.max_bytes {
+            return Err(SecurityError::ValidationError(format!(
+                "body must be between 1 and {} bytes",
+                self.config.max_bytes
+            )));
+        }
+        let ttl_secs = request.ttl_secs.unwrap_or(self.config.default_ttl_secs);
+        if !(1..=self.config.max_ttl_secs).contains(&ttl_secs) {
+            return Err(SecurityError::ValidationError(format!(
+                "ttl_secs must be between 1 and {}",
+                self.config.max_ttl_secs
+            )));
+        }
+
+        let id = Uuid::new_v4();
+        let size = request.body.len() as i32;
+        let sealed = crypto.encrypt_data(EncryptionRequest {
+            data: request.body,
+            key_id: None,
+            context: Some(body_context(id, &sender.subject, recipient)),
+        }).await?;
+        let now = self.clock.now();
+
+        // Recipients with a webhook are told; the rest poll
+        Ok(sqlx::query_as::<_, MessageInfo>(&format!(
+            "INSERT INTO mailbox_messages (id, tenant_id, sender, recipient, subject_line, encrypted_body, key_id, \
+             nonce, context_hash, size, created_at, expires_at, notify_status, notify_next_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, \
+             CASE WHEN EXISTS (SELECT 1 FROM mailbox_webhooks WHERE subject = $4) THEN 'pending' ELSE 'none' END, $11) \
+             RETURNING {}",
+            MESSAGE_COLUMNS
+        ))
+        .bind(id)
+        .bind(&sender.tenant_id)
+        .bind(&sender.subject)
+        .bind(recipient)
+        .bind(request.subject.trim())
+        .bind(&sealed.encrypted_data)
+        .bind(&sealed.key_id)
+        .bind(&sealed.nonce)
+        .bind(&sealed.context_hash)
+        .bind(size)
+        .bind(now)
+        .bind(now + Duration::seconds(ttl_secs))
+        .fetch_one(self.storage.pool())
+        .await?)
+    }
+
+    /// Pending messages for `recipient`.
+    pub async fn inbox(&self, recipient: &str, page: &PageRequest) -> Result<Page<MessageInfo>, SecurityError> {
+        self.list("recipient", recipient, Some("pending"), page).await
+    }
+
+    /// Messages `sender` sent, in any state.
+    pub async fn outbox(&self, sender: &str, page: &PageRequest) -> Result<Page<MessageInfo>, SecurityError> {
+        self.list("sender", sender, None, page).await
+    }
+
+    async fn list(
+        &self,
+        column: &str,
+        identity: &str,
+        status: Option<&str>,
+        page: &PageRequest,
+    ) -> Result<Page<MessageInfo>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM mailbox_messages WHERE {} = ",
+            MESSAGE_COLUMNS, column
+        ));
+        builder.push_bind(identity.to_string());
+        if let Some(status) = status {
+            builder.push(" AND status = ").push_bind(status.to_string());
+            builder.push(" AND expires_at > ").push_bind(self.clock.now());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let messages = builder
+            .build_query_as::<MessageInfo>()
+            .fetch_all(self.storage.pool())
+            .await?;
+        Ok(page.page(messages, |message, _| (SortKey::Timestamp(message.created_at), message.id)))
+    }
+
+    fn not_found(id: Uuid) -> SecurityError {
+        SecurityError::NotFound(format!("No message {}", id))
+    }
+
+    /// Open a pending message addressed to `recipient`.
+    pub async fn read(
+        &self,
+        crypto: &CryptoService,
+        recipient: &str,
+        id: Uuid,
+    ) -> Result<(MessageInfo, String), SecurityError> {
+        let now = self.clock.now();
+        let message = sqlx::query_as::<_, MessageInfo>(&format!(
+            "UPDATE mailbox_messages SET read_at = COALESCE(read_at, $3) \
+             WHERE id = $1 AND recipient = $2 AND status = 'pending' AND expires_at > $3 RETURNING {}",
+            MESSAGE_COLUMNS
+        ))
+        .bind(id)
+        .bind(recipient)
+        .bind(now)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| Self::not_found(id))?;
+
+        let sealed = sqlx::query_as::<_, SealedBody>(
+            "SELECT encrypted_body, key_id, nonce, context_hash FROM mailbox_messages WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_one(self.storage.pool())
+        .await?;
+        let (Some(encrypted_data), Some(key_id), Some(nonce)) = (sealed.encrypted_body, sealed.key_id, sealed.nonce) else {
+            return Err(Self::not_found(id));
+        };
+        if crypto.context_hash(Some(&body_context(id, &message.sender, &message.recipient)))? != sealed.context_hash {
+            error!("Mailbox message {} is bound to another sender or recipient", id);
+            return Err(SecurityError::CryptoError("Message binding mismatch".to_string()));
+        }
+        let body = crypto.decrypt_data(DecryptionRequest {
+            encrypted_data,
+            key_id,
+            nonce,
+            context_hash: sealed.context_hash,
+        }).await?;
+        Ok((message, body))
+    }
+
+    /// Mark a message handled and erase its body.
+    pub async fn acknowledge(&self, recipient: &str, id: Uuid) -> Result<MessageInfo, SecurityError> {
+        sqlx::query_as::<_, MessageInfo>(&format!(
+            "UPDATE mailbox_messages SET status = 'acknowledged', acknowledged_at = $3, encrypted_body = NULL, \
+             nonce = NULL WHERE id = $1 AND recipient = $2 AND status = 'pending' RETURNING {}",
+            MESSAGE_COLUMNS
+        ))
+        .bind(id)
+        .bind(recipient)
+        .bind(self.clock.now())
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| Self::not_found(id))
+    }
+
+    /// Expire unacknowledged messages past their time, erasing the bodies.
+    pub async fn expire(&self) -> Result<Vec<MessageInfo>, SecurityError> {
+        Ok(sqlx::query_as::<_, MessageInfo>(&format!(
+            "UPDATE mailbox_messages SET status = 'expired', encrypted_body = NULL, nonce = NULL, \
+             notify_status = CASE WHEN notify_status = 'pending' THEN 'failed' ELSE notify_status END \
+             WHERE id IN (SELECT id FROM mailbox_messages WHERE status = 'pending' AND expires_at <= $1 \
+             LIMIT $2 FOR UPDATE SKIP LOCKED) RETURNING {}",
+            MESSAGE_COLUMNS
+        ))
+        .bind(self.clock.now())
+        .bind(self.config.batch_size)
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    /// Register or replace the caller's webhook. Returns the new signing secret.
+    pub async fn set_webhook(&self, crypto: &CryptoService, subject: &str, url: &str) -> Result<String, SecurityError> {
+        let parsed = reqwest::Url::parse(url)
+            .map_err(|_| SecurityError::ValidationError("url is not a valid URL".to_string()))?;
+        if parsed.scheme() != "https" {
+            return Err(SecurityError::ValidationError("url must be https".to_string()));
+        }
+
+        let secret = hex::encode(crypto.secure_random(SECRET_BYTES).await?);
+        let sealed = crypto.encrypt_data(EncryptionRequest {
+            data: secret.clone(),
+            key_id: None,
+            context: Some(secret_context(subject)),
+        }).await?;
+        sqlx::query(
+            "INSERT INTO mailbox_webhooks (subject, url, encrypted_secret, key_id, nonce, context_hash, updated_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT (subject) DO UPDATE SET url = $2, \
+             encrypted_secret = $3, key_id = $4, nonce = $5, context_hash = $6, updated_at = $7",
+        )
+        .bind(subject)
+        .bind(url)
+        .bind(&sealed.encrypted_data)
+        .bind(&sealed.key_id)
+        .bind(&sealed.nonce)
+        .bind(&sealed.context_hash)
+        .bind(self.clock.now())
+        .execute(self.storage.pool())
+        .await?;
+        Ok(secret)
+    }
+
+    pub async fn delete_webhook(&self, subject: &str) -> Result<(), SecurityError> {
+        let deleted = sqlx::query("DELETE FROM mailbox_webhooks WHERE subject = $1")
+            .bind(subject)
+            .execute(self.storage.pool())
+            .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(SecurityError::NotFound("No webhook registered".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Send notifications that are due. Returns how many were attempted.
+    pub async fn notify_due(&self, crypto: &CryptoService) -> Result<usize, SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let due = sqlx::query_as::<_, DueNotification>(
+            "SELECT m.id, m.sender, m.recipient, m.subject_line, m.expires_at, m.notify_attempts, w.url, \
+             w.encrypted_secret, w.key_id, w.nonce, w.context_hash \
+             FROM mailbox_messages m JOIN mailbox_webhooks w ON w.subject = m.recipient \
+             WHERE m.notify_status = 'pending' AND m.notify_next_at <= $1 \
+             ORDER BY m.notify_next_at LIMIT $2 FOR UPDATE OF m SKIP LOCKED",
+        )
+        .bind(self.clock.now())
+        .bind(self.config.batch_size)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for notification in &due {
+            let attempts = notification.notify_attempts + 1;
+            match self.notify(crypto, notification).await {
+                Ok(()) => {
+                    sqlx::query(
+                        "UPDATE mailbox_messages SET notify_status = 'sent', notify_attempts = $2, \
+                         notify_error = NULL WHERE id = $1",
+                    )
+                    .bind(notification.id)
+                    .bind(attempts)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                Err(e) => {
+                    let status = if attempts >= self.config.max_attempts { "failed" } else { "pending" };
+                    if status == "failed" {
+                        warn!("Mailbox notification of {} to {} failed for good: {}", notification.id, notification.recipient, e);
+                    }
+                    let backoff = Duration::seconds((1i64 << attempts.min(12)).min(MAX_BACKOFF_SECS));
+                    sqlx::query(
+                        "UPDATE mailbox_messages SET notify_status = $2, notify_attempts = $3, notify_next_at = $4, \
+                         notify_error = $5 WHERE id = $1",
+                    )
+                    .bind(notification.id)
+                    .bind(status)
+                    .bind(attempts)
+                    .bind(self.clock.now() + backoff)
+                    .bind(e.to_string())
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
+        }
+        tx.commit().await?;
+
+        Ok(due.len())
+    }
+
+    async fn notify(&self, crypto: &CryptoService, notification: &DueNotification) -> Result<(), SecurityError> {
+        if crypto.context_hash(Some(&secret_context(&notification.recipient)))? != notification.context_hash {
+            return Err(SecurityError::CryptoError("Webhook secret binding mismatch".to_string()));
+        }
+        let secret = crypto.decrypt_data(DecryptionRequest {
+            encrypted_data: notification.encrypted_secret.clone(),
+            key_id: notification.key_id.clone(),
+            nonce: notification.nonce.clone(),
+            context_hash: notification.context_hash.clone(),
+        }).await?;
+
+        let body = serde_json::to_vec(&serde_json::json!({
+            "event": "mailbox.message",
+            "id": notification.id,
+            "sender": notification.sender,
+            "subject": notification.subject_line,
+            "expires_at": notification.expires_at
+        }))
+        .map_err(|e| SecurityError::DeliveryError(e.to_string()))?;
+        let response = deadline::outbound(self.client.post(&notification.url))
+            .header("Content-Type", "application/json")
+            .header(SIGNATURE_HEADER, soar::signature(&secret, self.clock.now().timestamp(), &body))
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| SecurityError::DeliveryError(e.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(SecurityError::DeliveryError(format!("Webhook returned {}", response.status())));
+        }
+        Ok(())
+    }
+}
+
+/// Background loop expiring messages and sending notifications.
+pub async fn run_delivery(state: web::Data<AppState>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_millis(state.config.mailbox.interval_ms));
+
+    loop {
+        interval.tick().await;
+        match state.mailbox.expire().await {
+            Ok(expired) => {
+                for message in &expired {
+                    audit(&state, SYSTEM_ACTOR, None, "mailbox.expire", message).await;
+                }
+            }
+            Err(e) => error!("Mailbox expiry failed: {:?}", e),
+        }
+        if let Err(e) = state.mailbox.notify_due(&state.crypto_service).await {
+            error!("Mailbox notification failed: {:?}", e);
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Mailbox operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Mailbox operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit(state: &AppState, actor: &str, actor_ip: Option<String>, action: &str, message: &MessageInfo) -> Option<String> {
+    receipts::record_or_warn(state, NewAuditEvent {
+        tenant_id: message.tenant_id.clone(),
+        actor: actor.to_string(),
+        actor_ip,
+        action: action.to_string(),
+        resource: format!("mailbox_message:{}", message.id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "sender": message.sender,
+            "recipient": message.recipient,
+            "subject": message.subject_line,
+            "size": message.size,
+            "expires_at": message.expires_at
+        }),
+    }).await
+}
+
+fn with_receipt(mut response: actix_web::HttpResponseBuilder, receipt: Option<String>) -> actix_web::HttpResponseBuilder {
+    if let Some(receipt) = receipt {
+        response.insert_header((RECEIPT_HEADER, receipt));
+    }
+    response
+}
+
+pub async fn send_handler(
+    req: HttpRequest,
+    request: web::Json<SendRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.mailbox.send_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.mailbox.send(&state.crypto_service, &principal, request.into_inner()).await {
+        Ok(message) => {
+            let receipt = audit(&state, &principal.subject, client_ip(&req), "mailbox.send", &message).await;
+            Ok(with_receipt(HttpResponse::Created(), receipt).json(message))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn inbox_handler(
+    req: HttpRequest,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.mailbox.receive_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Asc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.mailbox.inbox(&principal.subject, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "messages": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn sent_handler(
+    req: HttpRequest,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.mailbox.send_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.mailbox.outbox(&principal.subject, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "messages": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn read_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.mailbox.receive_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.mailbox.read(&state.crypto_service, &principal.subject, path.into_inner()).await {
+        Ok((message, body)) => {
+            let receipt = audit(&state, &principal.subject, client_ip(&req), "mailbox.read", &message).await;
+            Ok(with_receipt(HttpResponse::Ok(), receipt)
+                .insert_header(("Cache-Control", "no-store"))
+                .json(serde_json::json!({
+                    "message": message,
+                    "body": body
+                })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn ack_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.mailbox.receive_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.mailbox.acknowledge(&principal.subject, path.into_inner()).await {
+        Ok(message) => {
+            let receipt = audit(&state, &principal.subject, client_ip(&req), "mailbox.ack", &message).await;
+            Ok(with_receipt(HttpResponse::Ok(), receipt).json(message))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+async fn audit_webhook(state: &AppState, req: &HttpRequest, principal: &Principal, action: &str, url: Option<&str>) {
+    receipts::record_or_warn(state, NewAuditEvent {
+        tenant_id: principal.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(req),
+        action: action.to_string(),
+        resource: format!("mailbox_webhook:{}", principal.subject),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({ "url": url }),
+    }).await;
+}
+
+pub async fn set_webhook_handler(
+    req: HttpRequest,
+    request: web::Json<WebhookRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.mailbox.receive_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    // A tenant's identities may only post into domains the tenant has verified
+    if let Some(tenant_id) = &principal.tenant_id {
+        if let Err(e) = state.domains.require(tenant_id, &request.url).await {
+            return Ok(error_response(e));
+        }
+    }
+
+    match state.mailbox.set_webhook(&state.crypto_service, &principal.subject, &request.url).await {
+        Ok(secret) => {
+            audit_webhook(&state, &req, &principal, "mailbox.webhook.set", Some(&request.url)).await;
+            Ok(HttpResponse::Ok().insert_header(("Cache-Control", "no-store")).json(serde_json::json!({
+                "url": request.url,
+                "secret": secret
+            })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn delete_webhook_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.mailbox.receive_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.mailbox.delete_webhook(&principal.subject).await {
+        Ok(()) => {
+            audit_webhook(&state, &req, &principal, "mailbox.webhook.delete", None).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/mailbox")
+            .route("/messages", web::post().to(send_handler))
+            .route("/messages", web::get().to(inbox_handler))
+            .route("/messages/{id}", web::get().to(read_handler))
+            .route("/messages/{id}/ack", web::post().to(ack_handler))
+            .route("/sent", web::get().to(sent_handler))
+            .route("/webhook", web::put().to(set_webhook_handler))
+            .route("/webhook", web::delete().to(delete_webhook_handler)),
+    );
+}
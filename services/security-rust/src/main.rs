@@ -1,85 +1,43 @@
 /*!
 COTAI Security Service
-High-performance security modules written in Rust for critical security operations
+Standalone binary: configuration, HTTP server tuning and TLS, the admin, gRPC, S3 gateway and PostgreSQL proxy listeners
+
+On SIGTERM or SIGINT every listener stops accepting connections and gives
+requests in flight up to `SERVER_SHUTDOWN_TIMEOUT_SECS` to finish; then
+queued audit work is written out (see `app::drain`) before the process
+exits. SIGHUP reloads the configuration (see `config::run_reload`).
 */
 
-use actix_web::{web, App, HttpServer, HttpResponse, Result, middleware::Logger};
-use actix_cors::Cors;
+use actix_web::{App, HttpServer, middleware::Logger};
+use actix_web::dev::{Server, ServerHandle};
+use actix_web::http::KeepAlive;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
 use tracing::{info, error};
-use tracing_subscriber;
-
-mod config;
-mod crypto;
-mod auth;
-mod audit;
-mod monitoring;
-mod rate_limiting;
-mod validation;
-mod storage;
-mod errors;
-
-use config::Config;
-use crypto::CryptoService;
-use auth::AuthService;
-use audit::AuditService;
-use monitoring::MetricsService;
-use rate_limiting::RateLimiter;
-
-pub struct AppState {
-    pub config: Config,
-    pub crypto_service: CryptoService,
-    pub auth_service: AuthService,
-    pub audit_service: AuditService,
-    pub metrics_service: MetricsService,
-    pub rate_limiter: RateLimiter,
-}
 
-async fn health_check() -> Result<HttpResponse> {
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "status": "healthy",
-        "service": "cotai-security",
-        "version": "1.0.0"
-    })))
+use cotai_security::config::{self as configuration, Config};
+use cotai_security::{app, proxy_protocol, tls, SecurityError, SecurityServiceBuilder};
+
+fn startup_failure(component: &str, e: SecurityError) -> std::io::Error {
+    error!("Failed to initialize {}: {}", component, e);
+    std::io::Error::other(format!("{} initialization failed: {}", component, e))
 }
 
-async fn readiness_check(data: web::Data<AppState>) -> Result<HttpResponse> {
-    // Check all critical services
-    let mut checks = Vec::new();
-    
-    // Check crypto service
-    if data.crypto_service.is_ready().await {
-        checks.push(("crypto", "ready"));
-    } else {
-        checks.push(("crypto", "not_ready"));
-    }
-    
-    // Check auth service
-    if data.auth_service.is_ready().await {
-        checks.push(("auth", "ready"));
-    } else {
-        checks.push(("auth", "not_ready"));
-    }
-    
-    // Check audit service
-    if data.audit_service.is_ready().await {
-        checks.push(("audit", "ready"));
-    } else {
-        checks.push(("audit", "not_ready"));
-    }
-    
-    let all_ready = checks.iter().all(|(_, status)| *status == "ready");
-    
-    if all_ready {
-        Ok(HttpResponse::Ok().json(serde_json::json!({
-            "status": "ready",
-            "checks": checks
-        })))
-    } else {
-        Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
-            "status": "not_ready",
-            "checks": checks
-        })))
+/// Stop every listener gracefully on the first SIGTERM or SIGINT. Actix's
+/// own signal handling is off so all of them stop together.
+async fn stop_on_signal(handles: Vec<ServerHandle>) {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            error!("Cannot listen for SIGTERM: {}", e);
+            return;
+        }
+    };
+    tokio::select! {
+        _ = terminate.recv() => info!("SIGTERM received, shutting down"),
+        _ = tokio::signal::ctrl_c() => info!("SIGINT received, shutting down"),
     }
+    futures::future::join_all(handles.iter().map(|handle| handle.stop(true))).await;
 }
 
 #[actix_web::main]
@@ -92,62 +50,170 @@ async fn main() -> std::io::Result<()> {
     info!("Starting COTAI Security Service");
 
     // Load configuration
-    let config = Config::from_env().expect("Failed to load configuration");
+    let config = Config::from_env().map_err(|e| startup_failure("configuration", e))?;
     let bind_addr = format!("{}:{}", config.host, config.port);
+    let admin_bind = config.admin_bind.clone();
+    let tuning = config.server.clone();
+    let tls_config = config.tls.clone();
+    let network = config.network.clone();
+    let s3_gateway_bind = (config.s3_gateway.port != 0).then(|| format!("{}:{}", config.host, config.s3_gateway.port));
+
+    let service = SecurityServiceBuilder::new(config)
+        .build()
+        .await
+        .map_err(|e| startup_failure("security service", e))?;
+    let state = service.state();
+    let admin_state = service.state();
+    let s3_gateway_state = service.state();
+    tokio::spawn(configuration::run_reload(service.state()));
+
+    // Internal gRPC listener, on its own threads
+    #[cfg(feature = "grpc")]
+    let grpc = cotai_security::grpc::spawn(service.state())?;
+    #[cfg(not(feature = "grpc"))]
+    {
+        let port = service.state().config.grpc.port;
+        if port != 0 {
+            error!("GRPC_PORT={} ignored: built without gRPC", port);
+        }
+    }
+
+    // PostgreSQL encryption proxy, on its own threads
+    let pgproxy = cotai_security::pgproxy::spawn(service.state())?;
+
+    // Certificates are checked before anything listens
+    let tls = if tls_config.enabled() {
+        let (server_config, resolver) = tls::server_config(&tls_config).map_err(|e| startup_failure("TLS", e))?;
+        tokio::spawn(tls::run_reload(resolver, tls_config));
+        Some(server_config)
+    } else {
+        None
+    };
 
-    // Initialize services
-    let crypto_service = CryptoService::new(&config).await
-        .expect("Failed to initialize crypto service");
-    
-    let auth_service = AuthService::new(&config).await
-        .expect("Failed to initialize auth service");
-    
-    let audit_service = AuditService::new(&config).await
-        .expect("Failed to initialize audit service");
-    
-    let metrics_service = MetricsService::new(&config).await
-        .expect("Failed to initialize metrics service");
-    
-    let rate_limiter = RateLimiter::new(&config)
-        .expect("Failed to initialize rate limiter");
-
-    // Create application state
-    let app_state = web::Data::new(AppState {
-        config: config.clone(),
-        crypto_service,
-        auth_service,
-        audit_service,
-        metrics_service,
-        rate_limiter,
-    });
-
-    info!("Security service starting on {}", bind_addr);
+    info!(
+        "Security service starting on {}{}{}",
+        bind_addr,
+        if tls.is_some() { " (TLS)" } else { "" },
+        if network.proxy_protocol { " (PROXY protocol)" } else { "" }
+    );
+
+    let s3_gateway_tls = tls.clone();
 
     // Start HTTP server
-    HttpServer::new(move || {
-        App::new()
-            .app_data(app_state.clone())
-            .wrap(Logger::default())
-            .wrap(
-                Cors::default()
-                    .allowed_origin_fn(|origin, _req_head| {
-                        origin.as_bytes().starts_with(b"https://")
-                    })
-                    .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
-                    .allowed_headers(vec!["Authorization", "Content-Type"])
-                    .max_age(3600)
-            )
-            .route("/health", web::get().to(health_check))
-            .route("/ready", web::get().to(readiness_check))
-            .service(
-                web::scope("/api/v1")
-                    .configure(crypto::configure_routes)
-                    .configure(auth::configure_routes)
-                    .configure(audit::configure_routes)
-                    .configure(monitoring::configure_routes)
-            )
-    })
-    .bind(&bind_addr)?
-    .run()
-    .await
-}
\ No newline at end of file
+    let server = if network.proxy_protocol {
+        proxy_protocol::server(move || service.build_app(), &bind_addr, &tuning, tls, &network)?
+    } else {
+        let server = HttpServer::new(move || service.build_app())
+        .on_connect(tls::on_connect)
+        .keep_alive(match tuning.keep_alive_secs {
+            0 => KeepAlive::Disabled,
+            secs => KeepAlive::Timeout(Duration::from_secs(secs)),
+        })
+        .client_request_timeout(Duration::from_millis(tuning.client_request_timeout_ms))
+        .client_disconnect_timeout(Duration::from_millis(tuning.client_disconnect_timeout_ms))
+        .max_connections(tuning.max_connections)
+        .max_connection_rate(tuning.max_connection_rate)
+        .backlog(tuning.backlog)
+        .shutdown_timeout(tuning.shutdown_timeout_secs)
+        .disable_signals();
+
+        let server = if tuning.workers > 0 { server.workers(tuning.workers) } else { server };
+        if let Some(tls) = tls {
+            server.bind_rustls_021(&bind_addr, tls)?
+        } else if tuning.http2_cleartext {
+            server.bind_auto_h2c(&bind_addr)?
+        } else {
+            server.bind(&bind_addr)?
+        }
+        .run()
+    };
+
+    // Admin listener, kept off the public port
+    let admin_server: Option<Server> = match admin_bind {
+        None => None,
+        #[cfg(feature = "graphql")]
+        Some(admin_bind) => {
+            use actix_web::web;
+            use cotai_security::graphql;
+
+            let schema = web::Data::new(graphql::build_schema());
+            info!("Admin listener starting on {}", admin_bind);
+
+            let admin_server = HttpServer::new(move || {
+                App::new()
+                    .app_data(admin_state.clone())
+                    .app_data(schema.clone())
+                    .wrap(Logger::default())
+                    .configure(graphql::configure_routes)
+            })
+            .shutdown_timeout(tuning.shutdown_timeout_secs)
+            .disable_signals()
+            .bind(&admin_bind)?
+            .run();
+            Some(admin_server)
+        }
+        #[cfg(not(feature = "graphql"))]
+        Some(admin_bind) => {
+            let _ = admin_state;
+            error!("SECURITY_ADMIN_BIND={} ignored: built without admin APIs", admin_bind);
+            None
+        }
+    };
+
+    // S3 gateway listener, for clients that only speak S3
+    let s3_gateway_server: Option<Server> = match s3_gateway_bind {
+        None => None,
+        Some(s3_gateway_bind) => {
+            use actix_web::web;
+            use cotai_security::s3_gateway;
+
+            let max_object_bytes = s3_gateway_state.config.s3_gateway.max_object_bytes;
+            info!(
+                "S3 gateway listener starting on {}{}",
+                s3_gateway_bind,
+                if s3_gateway_tls.is_some() { " (TLS)" } else { "" }
+            );
+
+            let s3_gateway_server = HttpServer::new(move || {
+                App::new()
+                    .app_data(s3_gateway_state.clone())
+                    .app_data(web::PayloadConfig::new(max_object_bytes))
+                    .wrap(Logger::default())
+                    .configure(s3_gateway::s3::configure)
+            })
+            .shutdown_timeout(tuning.shutdown_timeout_secs)
+            .disable_signals();
+            let s3_gateway_server = match s3_gateway_tls {
+                Some(tls) => s3_gateway_server.bind_rustls_021(&s3_gateway_bind, tls)?,
+                None => s3_gateway_server.bind(&s3_gateway_bind)?,
+            }
+            .run();
+            Some(s3_gateway_server)
+        }
+    };
+
+    let servers: Vec<Server> = std::iter::once(server)
+        .chain(admin_server)
+        .chain(s3_gateway_server)
+        .collect();
+    let handles = servers.iter().map(|server| server.handle()).collect();
+    tokio::spawn(stop_on_signal(handles));
+
+    let served = futures::future::try_join_all(servers).await.map(|_| ());
+
+    // Nothing is accepted any more; finish what is in flight or queued
+    #[cfg(feature = "grpc")]
+    if let Some(grpc) = grpc {
+        if tokio::task::spawn_blocking(move || grpc.stop()).await.is_err() {
+            error!("Failed to stop the gRPC listener");
+        }
+    }
+    if let Some(pgproxy) = pgproxy {
+        if tokio::task::spawn_blocking(move || pgproxy.stop()).await.is_err() {
+            error!("Failed to stop the PostgreSQL proxy");
+        }
+    }
+    app::drain(&state).await;
+    info!("Security service stopped");
+    served
+}
@@ -0,0 +1,319 @@
+/*!
+Maintenance Module
+Scheduled maintenance windows that put the service into read-only mode
+*/
+
+use actix_web::dev::ServiceRequest;
+use actix_web::http::{header, Method};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::sync::{Arc, RwLock};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, Principal};
+use crate::clock::Clock;
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+
+const SELECT_COLUMNS: &str = "id, reason, starts_at, ends_at, created_by, created_at, cancelled_at, cancelled_by";
+
+/// Non-GET endpoints that only read state and stay available during maintenance.
+/// The maintenance endpoints themselves are always reachable so a window can be ended early.
+const READ_ONLY_POSTS: &[&str] = &[
+    "/api/v1/crypto/decrypt",
+    "/api/v1/crypto/hash",
+    "/api/v1/flags/evaluate",
+    "/api/v1/experiments/assign",
+    "/api/v1/validate",
+    "/api/v1/validate/pii",
+];
+
+const ADMIN_PREFIX: &str = "/api/v1/admin/maintenance";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct MaintenanceWindow {
+    pub id: Uuid,
+    pub reason: String,
+    pub starts_at: DateTime<Utc>,
+    /// Every window ends on its own; there is no open-ended maintenance.
+    pub ends_at: DateTime<Utc>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub cancelled_at: Option<DateTime<Utc>>,
+    pub cancelled_by: Option<String>,
+}
+
+impl MaintenanceWindow {
+    pub fn covers(&self, at: DateTime<Utc>) -> bool {
+        self.cancelled_at.is_none() && self.starts_at <= at && at < self.ends_at
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceRequest {
+    pub reason: String,
+    /// Defaults to now, which enters maintenance immediately.
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub duration_minutes: Option<i64>,
+}
+
+pub struct MaintenanceService {
+    storage: Storage,
+    cache: RwLock<Vec<MaintenanceWindow>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl MaintenanceService {
+    pub async fn new(storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let service = Self {
+            storage,
+            cache: RwLock::new(Vec::new()),
+            clock,
+        };
+
+        info!("Maintenance service initialized successfully");
+        Ok(service)
+    }
+
+    /// Reload windows that have not ended yet. Expiry needs no refresh:
+    /// `active` compares against the clock on every call.
+    pub async fn refresh(&self) -> Result<(), SecurityError> {
+        let windows = self.upcoming().await?;
+        *self.cache.write().unwrap() = windows;
+        Ok(())
+    }
+
+    /// The window in force right now; with overlaps, the one ending last.
+    pub fn active(&self) -> Option<MaintenanceWindow> {
+        let now = self.clock.now();
+        self.cache.read().unwrap()
+            .iter()
+            .filter(|window| window.covers(now))
+            .max_by_key(|window| window.ends_at)
+            .cloned()
+    }
+
+    /// Active and scheduled windows, soonest first.
+    pub async fn upcoming(&self) -> Result<Vec<MaintenanceWindow>, SecurityError> {
+        let windows = sqlx::query_as::<_, MaintenanceWindow>(&format!(
+            "SELECT {} FROM maintenance_windows \
+             WHERE cancelled_at IS NULL AND ends_at > NOW() \
+             ORDER BY starts_at",
+            SELECT_COLUMNS
+        ))
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        Ok(windows)
+    }
+
+    pub async fn schedule(
+        &self,
+        actor: &Principal,
+        request: &MaintenanceRequest,
+    ) -> Result<MaintenanceWindow, SecurityError> {
+        let reason = request.reason.trim();
+        if reason.is_empty() {
+            return Err(SecurityError::ValidationError("A maintenance reason is required".to_string()));
+        }
+
+        let now = self.clock.now();
+        let starts_at = request.starts_at.unwrap_or(now).max(now);
+        let ends_at = match (request.ends_at, request.duration_minutes) {
+            (Some(ends_at), None) => ends_at,
+            (None, Some(minutes)) if minutes > 0 => starts_at + Duration::minutes(minutes),
+            (None, Some(_)) => {
+                return Err(SecurityError::ValidationError("duration_minutes must be positive".to_string()));
+            }
+            _ => {
+                return Err(SecurityError::ValidationError(
+                    "Give exactly one of ends_at or duration_minutes".to_string(),
+                ));
+            }
+        };
+        if ends_at <= starts_at {
+            return Err(SecurityError::ValidationError("Maintenance must end after it starts".to_string()));
+        }
+
+        let window = sqlx::query_as::<_, MaintenanceWindow>(&format!(
+            "INSERT INTO maintenance_windows (id, reason, starts_at, ends_at, created_by) \
+             VALUES ($1, $2, $3, $4, $5) RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(reason)
+        .bind(starts_at)
+        .bind(ends_at)
+        .bind(&actor.subject)
+        .fetch_one(self.storage.pool())
+        .await?;
+
+        self.refresh().await?;
+        Ok(window)
+    }
+
+    /// Cancel a scheduled window, or end an active one now.
+    pub async fn cancel(&self, actor: &Principal, id: Uuid) -> Result<MaintenanceWindow, SecurityError> {
+        let window = sqlx::query_as::<_, MaintenanceWindow>(&format!(
+            "UPDATE maintenance_windows SET cancelled_at = NOW(), cancelled_by = $2 \
+             WHERE id = $1 AND cancelled_at IS NULL AND ends_at > NOW() RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(id)
+        .bind(&actor.subject)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("No pending maintenance window with that id".to_string()))?;
+
+        self.refresh().await?;
+        Ok(window)
+    }
+}
+
+/// Background loop picking up windows scheduled through other instances.
+pub async fn run_refresh(state: web::Data<crate::AppState>) {
+    let interval_secs = state.config.maintenance.refresh_interval_secs;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+
+    loop {
+        interval.tick().await;
+        match state.maintenance.refresh().await {
+            Ok(()) => state.startup.recovered("maintenance"),
+            Err(e) => error!("Maintenance refresh failed: {:?}", e),
+        }
+    }
+}
+
+fn is_read_only(req: &ServiceRequest) -> bool {
+    let path = req.path();
+    matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+        || READ_ONLY_POSTS.contains(&path)
+        || path == ADMIN_PREFIX
+        || path.starts_with(&format!("{}/", ADMIN_PREFIX))
+}
+
+/// The 503 to answer with instead of running a mutating request, if maintenance is on.
+pub fn rejection(state: &crate::AppState, req: &ServiceRequest) -> Option<HttpResponse> {
+    if is_read_only(req) {
+        return None;
+    }
+
+    let window = state.maintenance.active()?;
+    let retry_after = (window.ends_at - state.clock.now()).num_seconds().max(1);
+
+    state.metrics_service.increment("cotai_maintenance_rejections_total", &[("method", req.method().as_str())]);
+    Some(HttpResponse::ServiceUnavailable()
+        .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+        .json(serde_json::json!({
+            "error": "Service is in maintenance mode; only reads are available",
+            "code": "maintenance",
+            "reason": window.reason,
+            "maintenance_id": window.id,
+            "ends_at": window.ends_at
+        })))
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Maintenance operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Maintenance operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit_maintenance(state: &crate::AppState, actor: &Principal, action: &str, window: &MaintenanceWindow) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: actor.tenant_id.clone(),
+        actor: actor.subject.clone(),
+        actor_ip: None,
+        action: action.to_string(),
+        resource: format!("maintenance_window:{}", window.id),
+        outcome: "success".to_string(),
+        payload: serde_json::to_value(window).unwrap_or_default(),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit maintenance window {}: {:?}", window.id, e);
+    }
+}
+
+pub async fn status_handler(
+    req: HttpRequest,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.maintenance.upcoming().await {
+        Ok(windows) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "active": state.maintenance.active(),
+            "windows": windows
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn schedule_handler(
+    req: HttpRequest,
+    request: web::Json<MaintenanceRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.maintenance.schedule(&principal, &request).await {
+        Ok(window) => {
+            info!("{} scheduled maintenance {} from {} to {}", principal.subject, window.id, window.starts_at, window.ends_at);
+            audit_maintenance(&state, &principal, "maintenance.schedule", &window).await;
+            Ok(HttpResponse::Created().json(window))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn cancel_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.maintenance.cancel(&principal, path.into_inner()).await {
+        Ok(window) => {
+            info!("{} cancelled maintenance {}", principal.subject, window.id);
+            audit_maintenance(&state, &principal, "maintenance.cancel", &window).await;
+            Ok(HttpResponse::Ok().json(window))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/maintenance")
+            .route("", web::get().to(status_handler))
+            .route("", web::post().to(schedule_handler))
+            .route("/{id}", web::delete().to(cancel_handler))
+    );
+}
@@ -0,0 +1,647 @@
+/*!
+Manifests Module
+Signed file lists of multi-file packages and checks against them
+
+A tender package is published as many files, and a bidder who later
+presents "the package" must be held to exactly those files. `POST
+/manifests` (needs `MANIFEST_CREATE_SCOPE`) records a package's files by
+name, SHA-256 and size, as the next version of that package, and signs it
+with the `MANIFEST_ALGORITHM` key. Only hashes reach this service, never
+the files.
+
+The signature covers `cotai-manifest:<id>:<tenant>:<package>:<version>:
+<manifest sha256>:<created_at>`, where the manifest hash is the SHA-256 of
+one `<sha256> <size> <name>\n` line per file in name order. It is signed
+for the `manifest` domain (see `signing`), so a manifest also verifies
+offline against `/.well-known/jwks.json`.
+
+`POST /manifests/{id}/verify` (needs `MANIFEST_VERIFY_SCOPE`) takes the
+files of a package as they are now, or another manifest to compare with,
+and reports which files were added, removed or modified. The manifest's
+own signature and hash are checked on every comparison, so a tampered
+record never reports a match. Each comparison is kept;
+`GET /manifests/{id}/comparisons` is the history.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::audit::receipts::{self, RECEIPT_HEADER};
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, ManifestConfig};
+use crate::crypto::{CryptoService, VerifyRequest as SignatureCheck};
+use crate::errors::SecurityError;
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::signing::{Algorithm, MANIFEST_DOMAIN};
+use crate::storage::Storage;
+use crate::AppState;
+
+pub(crate) const MANIFEST_COLUMNS: &str = "id, tenant_id, package, version, label, files, file_count, total_bytes, \
+    manifest_sha256, algorithm, key_id, signature, created_by, created_at";
+
+const COMPARISON_COLUMNS: &str = "id, manifest_id, against_manifest_id, tenant_id, matches, signature_valid, \
+    added, removed, modified, unchanged, compared_by, compared_at";
+
+const MAX_NAME_LENGTH: usize = 1024;
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "created_at", column: "created_at", kind: KeyKind::Timestamp },
+    SortField { name: "package", column: "package", kind: KeyKind::Text },
+];
+
+const COMPARISON_SORT_FIELDS: &[SortField] = &[
+    SortField { name: "compared_at", column: "compared_at", kind: KeyKind::Timestamp },
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestFile {
+    /// Path within the package, e.g. `edital/anexo-1.pdf`.
+    pub name: String,
+    pub sha256: String,
+    pub size: i64,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Manifest {
+    pub id: Uuid,
+    pub tenant_id: Option<String>,
+    pub package: String,
+    pub version: i32,
+    pub label: Option<String>,
+    pub files: Json<Vec<ManifestFile>>,
+    pub file_count: i32,
+    pub total_bytes: i64,
+    pub manifest_sha256: String,
+    pub algorithm: String,
+    pub key_id: String,
+    pub signature: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModifiedFile {
+    pub name: String,
+    pub expected_sha256: String,
+    pub actual_sha256: String,
+    pub expected_size: i64,
+    pub actual_size: i64,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Comparison {
+    pub id: Uuid,
+    pub manifest_id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub against_manifest_id: Option<Uuid>,
+    #[serde(skip)]
+    pub tenant_id: Option<String>,
+    /// The manifest is intact and the files match it exactly.
+    pub matches: bool,
+    /// `None` when the signing key is no longer published.
+    pub signature_valid: Option<bool>,
+    pub added: Json<Vec<ManifestFile>>,
+    pub removed: Json<Vec<ManifestFile>>,
+    pub modified: Json<Vec<ModifiedFile>>,
+    pub unchanged: i32,
+    pub compared_by: String,
+    pub compared_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateRequest {
+    pub package: String,
+    pub label: Option<String>,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VerifyRequest {
+    /// The package's files as they are now.
+    pub files: Option<Vec<ManifestFile>>,
+    /// Or another manifest, e.g. a later version of the package.
+    pub against: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ManifestFilter {
+    pub package: Option<String>,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(digest(&SHA256, data))
+}
+
+/// Hash of the file list, which must already be in name order.
+fn manifest_hash(files: &[ManifestFile]) -> String {
+    let lines: String = files
+        .iter()
+        .map(|file| format!("{} {} {}\n", file.sha256, file.size, file.name))
+        .collect();
+    sha256_hex(lines.as_bytes())
+}
+
+/// The signed statement of a manifest.
+fn manifest_message(manifest: &Manifest) -> String {
+    format!(
+        "cotai-manifest:{}:{}:{}:{}:{}:{}",
+        manifest.id,
+        manifest.tenant_id.as_deref().unwrap_or(""),
+        manifest.package,
+        manifest.version,
+        manifest.manifest_sha256,
+        manifest.created_at.to_rfc3339_opts(SecondsFormat::Micros, true)
+    )
+}
+
+/// Validate a file list and put it in name order.
+fn normalize_files(files: Vec<ManifestFile>, max_files: usize) -> Result<Vec<ManifestFile>, SecurityError> {
+    if files.len() > max_files {
+        return Err(SecurityError::ValidationError(format!("Manifests are limited to {} files", max_files)));
+    }
+    let mut seen = HashSet::new();
+    let mut normalized = Vec::with_capacity(files.len());
+    for file in files {
+        let invalid = |msg: &str| SecurityError::ValidationError(format!("{:?}: {}", file.name, msg));
+        if file.name.is_empty() || file.name.len() > MAX_NAME_LENGTH || file.name.chars().any(char::is_control) {
+            return Err(invalid(&format!("name must be 1-{} characters without control characters", MAX_NAME_LENGTH)));
+        }
+        let sha256 = file.sha256.trim().to_ascii_lowercase();
+        if sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid("sha256 must be 64 hex characters"));
+        }
+        if file.size < 0 {
+            return Err(invalid("size must not be negative"));
+        }
+        if !seen.insert(file.name.clone()) {
+            return Err(invalid("listed more than once"));
+        }
+        normalized.push(ManifestFile { name: file.name, sha256, size: file.size });
+    }
+    normalized.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(normalized)
+}
+
+/// Files added, removed and modified going from `expected` to `actual`,
+/// and how many are unchanged.
+fn diff(expected: &[ManifestFile], actual: &[ManifestFile]) -> (Vec<ManifestFile>, Vec<ManifestFile>, Vec<ModifiedFile>, i32) {
+    let expected: BTreeMap<&str, &ManifestFile> = expected.iter().map(|f| (f.name.as_str(), f)).collect();
+    let actual: BTreeMap<&str, &ManifestFile> = actual.iter().map(|f| (f.name.as_str(), f)).collect();
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    let mut modified = Vec::new();
+    let mut unchanged = 0;
+
+    for (name, file) in &expected {
+        match actual.get(name) {
+            None => removed.push((*file).clone()),
+            Some(now) if now.sha256 != file.sha256 || now.size != file.size => modified.push(ModifiedFile {
+                name: name.to_string(),
+                expected_sha256: file.sha256.clone(),
+                actual_sha256: now.sha256.clone(),
+                expected_size: file.size,
+                actual_size: now.size,
+            }),
+            Some(_) => unchanged += 1,
+        }
+    }
+    for (name, file) in &actual {
+        if !expected.contains_key(name) {
+            added.push((*file).clone());
+        }
+    }
+    (added, removed, modified, unchanged)
+}
+
+/// Whether a manifest's signature holds; `None` when its key is gone.
+fn manifest_signed(crypto: &CryptoService, manifest: &Manifest) -> Result<Option<bool>, SecurityError> {
+    let Some(algorithm) = Algorithm::parse(&manifest.algorithm).filter(|a| *a != Algorithm::Hs256) else {
+        return Ok(Some(false));
+    };
+    let usable = crypto.signing_keys().metadata().iter()
+        .any(|key| key.key_id == manifest.key_id && key.state != "retired");
+    if !usable {
+        return Ok(None);
+    }
+
+    crypto.verify(MANIFEST_DOMAIN, &SignatureCheck {
+        data: manifest_message(manifest),
+        signature: manifest.signature.clone(),
+        algorithm: Some(algorithm),
+        key_id: Some(manifest.key_id.clone()),
+        timestamp: None,
+    })
+    .map(Some)
+}
+
+pub struct ManifestService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: ManifestConfig,
+}
+
+impl ManifestService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        info!("Manifest service initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            config: config.manifests.clone(),
+        })
+    }
+
+    /// Record and sign the next version of a package.
+    pub async fn create(
+        &self,
+        crypto: &CryptoService,
+        principal: &Principal,
+        request: CreateRequest,
+    ) -> Result<Manifest, SecurityError> {
+        let package = request.package.trim().to_string();
+        if package.is_empty() || package.len() > 200 {
+            return Err(SecurityError::ValidationError("package must be 1-200 characters".to_string()));
+        }
+        if request.files.is_empty() {
+            return Err(SecurityError::ValidationError("A manifest needs at least one file".to_string()));
+        }
+        let files = normalize_files(request.files, self.config.max_files)?;
+        let algorithm = Algorithm::parse(&self.config.algorithm)
+            .ok_or_else(|| SecurityError::ConfigError("Invalid MANIFEST_ALGORITHM".to_string()))?;
+
+        let mut tx = self.storage.begin().await?;
+        // Serialize versioning per package
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+            .bind(format!("manifest:{}:{}", principal.tenant_id.as_deref().unwrap_or(""), package))
+            .execute(&mut *tx)
+            .await?;
+        let (latest,): (Option<i32>,) = sqlx::query_as(
+            "SELECT MAX(version) FROM manifests WHERE COALESCE(tenant_id, '') = $1 AND package = $2",
+        )
+        .bind(principal.tenant_id.as_deref().unwrap_or(""))
+        .bind(&package)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let mut manifest = Manifest {
+            id: Uuid::new_v4(),
+            tenant_id: principal.tenant_id.clone(),
+            package,
+            version: latest.unwrap_or(0) + 1,
+            label: request.label,
+            file_count: files.len() as i32,
+            total_bytes: files.iter().map(|f| f.size).sum(),
+            manifest_sha256: manifest_hash(&files),
+            files: Json(files),
+            algorithm: algorithm.as_str().to_string(),
+            key_id: String::new(),
+            signature: String::new(),
+            created_by: principal.subject.clone(),
+            created_at: self.clock.now(),
+        };
+        let signed = crypto.generate_signature(MANIFEST_DOMAIN, &manifest_message(&manifest), None, algorithm)?;
+        manifest.key_id = signed.key_id;
+        manifest.signature = signed.signature;
+
+        sqlx::query(&format!(
+            "INSERT INTO manifests ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)",
+            MANIFEST_COLUMNS
+        ))
+        .bind(manifest.id)
+        .bind(&manifest.tenant_id)
+        .bind(&manifest.package)
+        .bind(manifest.version)
+        .bind(&manifest.label)
+        .bind(&manifest.files)
+        .bind(manifest.file_count)
+        .bind(manifest.total_bytes)
+        .bind(&manifest.manifest_sha256)
+        .bind(&manifest.algorithm)
+        .bind(&manifest.key_id)
+        .bind(&manifest.signature)
+        .bind(&manifest.created_by)
+        .bind(manifest.created_at)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        info!("Manifest {} v{} of {} created with {} files", manifest.id, manifest.version, manifest.package, manifest.file_count);
+        Ok(manifest)
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<Manifest, SecurityError> {
+        sqlx::query_as::<_, Manifest>(&format!("SELECT {} FROM manifests WHERE id = $1", MANIFEST_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound(format!("No manifest {}", id)))
+    }
+
+    pub async fn list(
+        &self,
+        tenant_id: Option<&str>,
+        filter: &ManifestFilter,
+        page: &PageRequest,
+    ) -> Result<Page<Manifest>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM manifests WHERE 1 = 1",
+            MANIFEST_COLUMNS
+        ));
+        if let Some(tenant_id) = tenant_id {
+            builder.push(" AND tenant_id = ").push_bind(tenant_id.to_string());
+        }
+        if let Some(package) = &filter.package {
+            builder.push(" AND package = ").push_bind(package.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let manifests = builder
+            .build_query_as::<Manifest>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(manifests, |manifest, field| match field {
+            "package" => (SortKey::Text(manifest.package.clone()), manifest.id),
+            _ => (SortKey::Timestamp(manifest.created_at), manifest.id),
+        }))
+    }
+
+    /// Compare files, or another manifest, with a manifest and keep the result.
+    pub async fn verify(
+        &self,
+        crypto: &CryptoService,
+        principal: &Principal,
+        manifest: &Manifest,
+        actual: Vec<ManifestFile>,
+        against: Option<&Manifest>,
+    ) -> Result<Comparison, SecurityError> {
+        let actual = normalize_files(actual, self.config.max_files)?;
+        let signature_valid = manifest_signed(crypto, manifest)?;
+        let intact = manifest_hash(&manifest.files) == manifest.manifest_sha256;
+        let (added, removed, modified, unchanged) = diff(&manifest.files, &actual);
+        let matches = intact
+            && signature_valid == Some(true)
+            && added.is_empty()
+            && removed.is_empty()
+            && modified.is_empty();
+
+        let comparison = sqlx::query_as::<_, Comparison>(&format!(
+            "INSERT INTO manifest_comparisons ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) \
+             RETURNING {}",
+            COMPARISON_COLUMNS, COMPARISON_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(manifest.id)
+        .bind(against.map(|m| m.id))
+        .bind(&manifest.tenant_id)
+        .bind(matches)
+        .bind(signature_valid)
+        .bind(Json(added))
+        .bind(Json(removed))
+        .bind(Json(modified))
+        .bind(unchanged)
+        .bind(&principal.subject)
+        .bind(self.clock.now())
+        .fetch_one(self.storage.pool())
+        .await?;
+
+        if !intact {
+            error!("Manifest {} no longer matches its recorded hash", manifest.id);
+        }
+        Ok(comparison)
+    }
+
+    pub async fn comparisons(&self, manifest_id: Uuid, page: &PageRequest) -> Result<Page<Comparison>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM manifest_comparisons WHERE manifest_id = ",
+            COMPARISON_COLUMNS
+        ));
+        builder.push_bind(manifest_id);
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let comparisons = builder
+            .build_query_as::<Comparison>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(comparisons, |comparison, _| (SortKey::Timestamp(comparison.compared_at), comparison.id)))
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Manifest operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Manifest operation failed"
+            }))
+        }
+    }
+}
+
+/// A manifest, if the caller may see it: tenant-bound callers only their tenant's.
+async fn authorized_manifest(state: &AppState, principal: &Principal, id: Uuid) -> Result<Manifest, HttpResponse> {
+    let manifest = state.manifests.get(id).await.map_err(error_response)?;
+    if principal.tenant_id.is_some() && principal.tenant_id != manifest.tenant_id {
+        return Err(error_response(SecurityError::NotFound(format!("No manifest {}", id))));
+    }
+    Ok(manifest)
+}
+
+pub async fn create_handler(
+    req: HttpRequest,
+    request: web::Json<CreateRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.manifests.create_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let manifest = match state.manifests.create(&state.crypto_service, &principal, request.into_inner()).await {
+        Ok(manifest) => manifest,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    let receipt = receipts::record_or_warn(&state, NewAuditEvent {
+        tenant_id: manifest.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(&req),
+        action: "manifest.create".to_string(),
+        resource: format!("manifest:{}", manifest.id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "package": manifest.package,
+            "version": manifest.version,
+            "file_count": manifest.file_count,
+            "manifest_sha256": manifest.manifest_sha256
+        }),
+    }).await;
+
+    let mut response = HttpResponse::Created();
+    if let Some(receipt) = receipt {
+        response.insert_header((RECEIPT_HEADER, receipt));
+    }
+    Ok(response.json(manifest))
+}
+
+pub async fn list_handler(
+    req: HttpRequest,
+    filter: web::Query<ManifestFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.manifests.verify_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.manifests.list(principal.tenant_id.as_deref(), &filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "manifests": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.manifests.verify_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match authorized_manifest(&state, &principal, path.into_inner()).await {
+        Ok(manifest) => Ok(HttpResponse::Ok().json(manifest)),
+        Err(response) => Ok(response),
+    }
+}
+
+pub async fn verify_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<VerifyRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.manifests.verify_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let manifest = match authorized_manifest(&state, &principal, path.into_inner()).await {
+        Ok(manifest) => manifest,
+        Err(response) => return Ok(response),
+    };
+
+    let request = request.into_inner();
+    let (files, against) = match (request.files, request.against) {
+        (Some(files), None) => (files, None),
+        (None, Some(id)) => match authorized_manifest(&state, &principal, id).await {
+            Ok(other) => (other.files.0.clone(), Some(other)),
+            Err(response) => return Ok(response),
+        },
+        _ => return Ok(error_response(SecurityError::ValidationError(
+            "Exactly one of files or against is required".to_string(),
+        ))),
+    };
+
+    let comparison = match state.manifests
+        .verify(&state.crypto_service, &principal, &manifest, files, against.as_ref())
+        .await
+    {
+        Ok(comparison) => comparison,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    let receipt = receipts::record_or_warn(&state, NewAuditEvent {
+        tenant_id: manifest.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(&req),
+        action: "manifest.verify".to_string(),
+        resource: format!("manifest:{}", manifest.id),
+        outcome: if comparison.matches { "success" } else { "failure" }.to_string(),
+        payload: serde_json::json!({
+            "comparison_id": comparison.id,
+            "against_manifest_id": comparison.against_manifest_id,
+            "added": comparison.added.len(),
+            "removed": comparison.removed.len(),
+            "modified": comparison.modified.len(),
+            "signature_valid": comparison.signature_valid
+        }),
+    }).await;
+
+    let mut response = HttpResponse::Ok();
+    if let Some(receipt) = receipt {
+        response.insert_header((RECEIPT_HEADER, receipt));
+    }
+    Ok(response.json(comparison))
+}
+
+pub async fn comparisons_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.manifests.verify_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let manifest = match authorized_manifest(&state, &principal, path.into_inner()).await {
+        Ok(manifest) => manifest,
+        Err(response) => return Ok(response),
+    };
+    let page = match page.resolve(COMPARISON_SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.manifests.comparisons(manifest.id, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "comparisons": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/manifests")
+            .route("", web::post().to(create_handler))
+            .route("", web::get().to(list_handler))
+            .route("/{id}", web::get().to(get_handler))
+            .route("/{id}/verify", web::post().to(verify_handler))
+            .route("/{id}/comparisons", web::get().to(comparisons_handler)),
+    );
+}
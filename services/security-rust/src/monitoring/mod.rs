@@ -0,0 +1,252 @@
+/*!
+Monitoring Module
+In-process metrics registry with Prometheus text exposition
+
+`GET /metrics` (and `/api/v1/monitoring/metrics`) serves the registry for
+scraping. Everything under `/api/v1` passes `track`, which records per
+request, without handlers doing anything:
+
+- `cotai_http_requests_total{method,route,status}`
+- `cotai_http_request_duration_seconds{method,route}`, a histogram with
+  `METRICS_LATENCY_BUCKETS`
+- `cotai_auth_failures_total{route,status}` for 401 and 403 answers
+- `cotai_crypto_operations_total{operation,key_id}` for every encrypt,
+  decrypt, rewrap and sign the request caused, reported by `CryptoService`
+  through `note_crypto_op`
+
+`route` is the matched route pattern, e.g. `/api/v1/seals/{id}`, so ids in
+paths do not multiply series; unmatched paths share `unmatched`. Rejections
+by the rate limiter are counted as `cotai_rate_limit_rejections_total{rule}`.
+
+`401` answers also go to the brute-force detection in `threats`, and
+with `USAGE_ENABLED` every matched request is counted by route and the
+caller's tenant for `detection::usage`.
+*/
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse, Result};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::rc::Rc;
+use std::sync::RwLock;
+use std::time::Instant;
+use tracing::info;
+
+use crate::auth::client_ip;
+use crate::config::Config;
+use crate::errors::SecurityError;
+use crate::AppState;
+
+pub mod threats;
+
+type Labels = Vec<(String, String)>;
+
+type CryptoOps = Rc<RefCell<Vec<(&'static str, String)>>>;
+
+tokio::task_local! {
+    static CRYPTO_OPS: CryptoOps;
+}
+
+/// Count a crypto operation against the request being served, if any.
+pub fn note_crypto_op(operation: &'static str, key_id: &str) {
+    let _ = CRYPTO_OPS.try_with(|ops| ops.borrow_mut().push((operation, key_id.to_string())));
+}
+
+struct Histogram {
+    /// Per bucket, not cumulative; the last one is `+Inf`.
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+pub struct MetricsService {
+    counters: RwLock<BTreeMap<String, BTreeMap<Labels, u64>>>,
+    gauges: RwLock<BTreeMap<String, BTreeMap<Labels, f64>>>,
+    histograms: RwLock<BTreeMap<String, BTreeMap<Labels, Histogram>>>,
+    buckets: Vec<f64>,
+}
+
+fn to_labels(labels: &[(&str, &str)]) -> Labels {
+    labels.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+fn format_labels(labels: &Labels) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let inner: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, v.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect();
+    format!("{{{}}}", inner.join(","))
+}
+
+impl MetricsService {
+    pub async fn new(config: &Config) -> Result<Self, SecurityError> {
+        info!("Metrics service initialized successfully");
+        Ok(Self {
+            counters: RwLock::new(BTreeMap::new()),
+            gauges: RwLock::new(BTreeMap::new()),
+            histograms: RwLock::new(BTreeMap::new()),
+            buckets: config.metrics.latency_buckets.clone(),
+        })
+    }
+
+    pub fn increment(&self, name: &str, labels: &[(&str, &str)]) {
+        self.increment_by(name, labels, 1);
+    }
+
+    pub fn increment_by(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        let mut counters = self.counters.write().unwrap();
+        *counters
+            .entry(name.to_string())
+            .or_default()
+            .entry(to_labels(labels))
+            .or_insert(0) += value;
+    }
+
+    pub fn set_gauge(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let mut gauges = self.gauges.write().unwrap();
+        gauges
+            .entry(name.to_string())
+            .or_default()
+            .insert(to_labels(labels), value);
+    }
+
+    /// Record one observation in a histogram with the configured buckets.
+    pub fn observe(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let bucket = self.buckets.iter().position(|bound| value <= *bound).unwrap_or(self.buckets.len());
+        let mut histograms = self.histograms.write().unwrap();
+        let histogram = histograms
+            .entry(name.to_string())
+            .or_default()
+            .entry(to_labels(labels))
+            .or_insert_with(|| Histogram {
+                counts: vec![0; self.buckets.len() + 1],
+                sum: 0.0,
+                count: 0,
+            });
+        histogram.counts[bucket] += 1;
+        histogram.sum += value;
+        histogram.count += 1;
+    }
+
+    /// Drop all series of a gauge before re-populating it from a fresh snapshot.
+    pub fn reset_gauge(&self, name: &str) {
+        self.gauges.write().unwrap().remove(name);
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        for (name, series) in self.counters.read().unwrap().iter() {
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (labels, value) in series {
+                let _ = writeln!(out, "{}{} {}", name, format_labels(labels), value);
+            }
+        }
+
+        for (name, series) in self.gauges.read().unwrap().iter() {
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            for (labels, value) in series {
+                let _ = writeln!(out, "{}{} {}", name, format_labels(labels), value);
+            }
+        }
+
+        for (name, series) in self.histograms.read().unwrap().iter() {
+            let _ = writeln!(out, "# TYPE {} histogram", name);
+            for (labels, histogram) in series {
+                let mut cumulative = 0;
+                for (i, count) in histogram.counts.iter().enumerate() {
+                    cumulative += count;
+                    let le = self.buckets.get(i).map_or_else(|| "+Inf".to_string(), |bound| bound.to_string());
+                    let mut bucket_labels = labels.clone();
+                    bucket_labels.push(("le".to_string(), le));
+                    let _ = writeln!(out, "{}_bucket{} {}", name, format_labels(&bucket_labels), cumulative);
+                }
+                let _ = writeln!(out, "{}_sum{} {}", name, format_labels(labels), histogram.sum);
+                let _ = writeln!(out, "{}_count{} {}", name, format_labels(labels), histogram.count);
+            }
+        }
+
+        out
+    }
+}
+
+/// Middleware recording request, latency, auth failure and crypto
+/// operation metrics; see the module docs.
+pub async fn track(
+    state: web::Data<AppState>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let method = req.method().to_string();
+    let route = req.match_pattern().unwrap_or_else(|| "unmatched".to_string());
+    let started = Instant::now();
+    let ops = CryptoOps::default();
+
+    let res = CRYPTO_OPS.scope(ops.clone(), next.call(req)).await?;
+
+    let metrics = &state.metrics_service;
+    let status = res.status();
+    metrics.increment(
+        "cotai_http_requests_total",
+        &[("method", &method), ("route", &route), ("status", status.as_str())],
+    );
+    metrics.observe(
+        "cotai_http_request_duration_seconds",
+        &[("method", &method), ("route", &route)],
+        started.elapsed().as_secs_f64(),
+    );
+    if status.as_u16() == 401 || status.as_u16() == 403 {
+        metrics.increment("cotai_auth_failures_total", &[("route", &route), ("status", status.as_str())]);
+    }
+    if status.as_u16() == 401 {
+        state.threats.report_unauthorized(client_ip(res.request()));
+    }
+    let ops = ops.borrow();
+    let note_usage = state.config.usage.enabled && route != "unmatched";
+    let principal = (note_usage || !ops.is_empty())
+        .then(|| state.auth_service.authenticate(res.request()).ok())
+        .flatten();
+    if note_usage {
+        let tenant_id = principal.as_ref().and_then(|p| p.tenant_id.as_deref());
+        state.usage.note(&route, tenant_id, state.clock.now());
+    }
+    if !ops.is_empty() {
+        let caller = principal.as_ref().map_or("anonymous", |p| p.subject.as_str());
+        state.crypto_service.note_callers(caller, &ops);
+    }
+    for (operation, key_id) in ops.iter() {
+        metrics.increment("cotai_crypto_operations_total", &[("operation", operation), ("key_id", key_id)]);
+    }
+    Ok(res)
+}
+
+// HTTP handlers
+
+pub async fn metrics_handler(state: web::Data<AppState>) -> Result<HttpResponse> {
+    // Gauges backed by storage are sampled at scrape time
+    if let Ok(depths) = state.dead_letters.depth().await {
+        state.metrics_service.reset_gauge("cotai_dlq_depth");
+        for (subsystem, count) in depths {
+            state.metrics_service.set_gauge("cotai_dlq_depth", &[("subsystem", &subsystem)], count as f64);
+        }
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(state.metrics_service.render()))
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/monitoring")
+            .route("/metrics", web::get().to(metrics_handler))
+            .route("/threats", web::get().to(threats::list_handler))
+            .route("/threats/{id}/reset", web::post().to(threats::reset_handler))
+    );
+}
@@ -0,0 +1,649 @@
+/*!
+Threat detection: brute-force and abuse velocity
+
+The engine counts signals per account or address over
+`THREAT_WINDOW_SECS`:
+
+| signal         | entity     | counted from                                      | threshold                          |
+|----------------|------------|---------------------------------------------------|------------------------------------|
+| `auth_failure` | `actor`    | failed audit events under `THREAT_AUTH_ACTIONS`   | `THREAT_ACCOUNT_MAX_FAILURES`      |
+| `auth_failure` | `actor_ip` | `401` answers seen by `track`                     | `THREAT_IP_MAX_FAILURES`           |
+| `rate_limited` | either     | `rate_limit` rejections, per caller               | `THREAT_RATE_LIMIT_MAX_REJECTIONS` |
+
+Counts live in the rate limiter's sliding windows, so replicas sharing
+Redis share them. Going over a threshold opens a detection, audited as
+`threat.detected` with severity `high`, published as `threat.detected` and
+counted in `cotai_threat_detections_total{signal,entity_type}`; further
+signals while it is open only raise its `hits`.
+
+With `THREAT_LOCKOUT_SECS` above 0 a detection also locks its entity out
+for that long: the `lockout` pipeline stage answers `429` to requests from
+a locked address or carrying a locked account's token, and
+`client_credentials` refuses locked service accounts. Subjects in
+`CONTAINMENT_PROTECTED_SUBJECTS` are detected but never locked out.
+
+Detections expire once their lock has ended and a window has passed
+without signals. An admin can reset one early, lifting its lock and
+clearing its count. Signals reach the engine through bounded queues and
+are dropped, with a warning, rather than slow requests down.
+*/
+
+use actix_web::dev::ServiceRequest;
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, ThreatsConfig};
+use crate::errors::SecurityError;
+use crate::events::{self, DomainEvent};
+use crate::rate_limiting::{Aggregation, Algorithm, Rule};
+use crate::storage::Storage;
+use crate::AppState;
+
+const DETECTION_COLUMNS: &str = "id, signal, entity_type, entity, tenant_id, hits, first_seen, last_seen, \
+    locked_until, status, reset_by, reset_at, created_at";
+
+/// Who detections are audited as.
+const SYSTEM_ACTOR: &str = "system:threats";
+
+/// Subject prefix of service account tokens, whose failures are audited
+/// under the bare client id.
+const SERVICE_ACCOUNT_PREFIX: &str = "service_account:";
+
+/// Actors recorded when the caller could not be identified.
+const UNIDENTIFIED_ACTORS: &[&str] = &["unknown", "anonymous"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    AuthFailure,
+    RateLimited,
+}
+
+impl Signal {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Signal::AuthFailure => "auth_failure",
+            Signal::RateLimited => "rate_limited",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        [Signal::AuthFailure, Signal::RateLimited].into_iter().find(|signal| signal.as_str() == s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntityType {
+    Account,
+    Address,
+}
+
+impl EntityType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntityType::Account => "actor",
+            EntityType::Address => "actor_ip",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        [EntityType::Account, EntityType::Address].into_iter().find(|kind| kind.as_str() == s)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Observation {
+    signal: Signal,
+    entity_type: EntityType,
+    entity: String,
+    tenant_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Detection {
+    pub id: Uuid,
+    pub signal: String,
+    pub entity_type: String,
+    pub entity: String,
+    pub tenant_id: Option<String>,
+    pub hits: i64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub status: String,
+    pub reset_by: Option<String>,
+    pub reset_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct Lock {
+    entity_type: String,
+    entity: String,
+    locked_until: DateTime<Utc>,
+}
+
+pub struct ThreatEngine {
+    storage: Storage,
+    config: ThreatsConfig,
+    protected_subjects: Vec<String>,
+    clock: Arc<dyn Clock>,
+    sender: mpsc::Sender<Observation>,
+    /// Taken by `run_engine`.
+    receiver: Mutex<Option<mpsc::Receiver<Observation>>>,
+    locks: RwLock<Vec<Lock>>,
+}
+
+impl ThreatEngine {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let (sender, receiver) = mpsc::channel(config.threats.queue_size);
+
+        info!("Threat engine initialized successfully");
+        Ok(Self {
+            storage,
+            config: config.threats.clone(),
+            protected_subjects: config.containment.protected_subjects.clone(),
+            clock,
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+            locks: RwLock::new(Vec::new()),
+        })
+    }
+
+    fn report(&self, observation: Observation) {
+        if let Err(mpsc::error::TrySendError::Full(observation)) = self.sender.try_send(observation) {
+            warn!(
+                "Threat engine queue full, dropped {} signal for {}",
+                observation.signal.as_str(),
+                observation.entity_type.as_str()
+            );
+        }
+    }
+
+    /// Count a `401` answer against the caller's address.
+    pub fn report_unauthorized(&self, ip: Option<String>) {
+        if let Some(ip) = ip.filter(|ip| !ip.is_empty()) {
+            self.report(Observation {
+                signal: Signal::AuthFailure,
+                entity_type: EntityType::Address,
+                entity: ip,
+                tenant_id: None,
+            });
+        }
+    }
+
+    /// Count a rate limit rejection against the caller, identified as the
+    /// rate limiter does.
+    pub fn report_rate_limited(&self, principal: Option<&Principal>, ip: Option<String>) {
+        let (entity_type, entity, tenant_id) = match principal {
+            Some(principal) => (EntityType::Account, principal.subject.clone(), principal.tenant_id.clone()),
+            None => match ip.filter(|ip| !ip.is_empty()) {
+                Some(ip) => (EntityType::Address, ip, None),
+                None => return,
+            },
+        };
+        self.report(Observation { signal: Signal::RateLimited, entity_type, entity, tenant_id });
+    }
+
+    /// The account signal a failed audit event stands for, if any.
+    fn observe_audit(&self, event: &NewAuditEvent) -> Option<Observation> {
+        let counted = self.config.auth_actions.iter().any(|prefix| event.action.starts_with(prefix.as_str()));
+        if !counted || event.actor.is_empty() || UNIDENTIFIED_ACTORS.contains(&event.actor.as_str()) {
+            return None;
+        }
+        Some(Observation {
+            signal: Signal::AuthFailure,
+            entity_type: EntityType::Account,
+            entity: event.actor.clone(),
+            tenant_id: event.tenant_id.clone(),
+        })
+    }
+
+    /// The window counting `signal` for one kind of entity.
+    fn rule(&self, signal: Signal, entity_type: EntityType) -> Rule {
+        let limit = match (signal, entity_type) {
+            (Signal::AuthFailure, EntityType::Account) => self.config.account_max_failures,
+            (Signal::AuthFailure, EntityType::Address) => self.config.ip_max_failures,
+            (Signal::RateLimited, _) => self.config.rate_limit_max_rejections,
+        };
+        Rule {
+            name: format!("threat:{}:{}", signal.as_str(), entity_type.as_str()),
+            algorithm: Algorithm::SlidingWindow,
+            limit,
+            period: std::time::Duration::from_secs(self.config.window_secs),
+            aggregation: Aggregation::Ip,
+        }
+    }
+
+    fn is_protected(&self, entity_type: EntityType, entity: &str) -> bool {
+        entity_type == EntityType::Account && self.protected_subjects.iter().any(|subject| subject == entity)
+    }
+
+    /// Until when a detection opened at `now` locks its entity out, if it does.
+    fn lock_until(&self, entity_type: EntityType, entity: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        (self.config.lockout_secs > 0 && !self.is_protected(entity_type, entity))
+            .then(|| now + Duration::seconds(self.config.lockout_secs))
+    }
+
+    /// Count one signal, opening a detection when it goes over the threshold.
+    async fn count(&self, state: &AppState, observation: Observation) {
+        let rule = self.rule(observation.signal, observation.entity_type);
+        if state.rate_limiter.acquire(&rule, &observation.entity).await.allowed {
+            return;
+        }
+
+        let detection = match self.detect(&observation, rule.limit).await {
+            Ok(Some(detection)) => detection,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Failed to record {} detection: {:?}", observation.signal.as_str(), e);
+                return;
+            }
+        };
+        warn!(
+            "Detected {} from {} {} ({} in {}s)",
+            detection.signal, detection.entity_type, detection.entity, rule.limit, self.config.window_secs
+        );
+        state.metrics_service.increment(
+            "cotai_threat_detections_total",
+            &[("signal", detection.signal.as_str()), ("entity_type", detection.entity_type.as_str())],
+        );
+        if let Some(locked_until) = detection.locked_until {
+            self.locks.write().unwrap_or_else(|e| e.into_inner()).push(Lock {
+                entity_type: detection.entity_type.clone(),
+                entity: detection.entity.clone(),
+                locked_until,
+            });
+        }
+        audit_detection(state, SYSTEM_ACTOR, "threat.detected", &detection, serde_json::json!({
+            "severity": "high",
+            "threshold": rule.limit,
+            "window_secs": self.config.window_secs
+        })).await;
+    }
+
+    /// Raise the open detection for the entity, or open one. Returns the
+    /// detection when it is new.
+    async fn detect(&self, observation: &Observation, threshold: u32) -> Result<Option<Detection>, SecurityError> {
+        let now = self.clock.now();
+        let mut tx = self.storage.begin().await?;
+
+        let raised = sqlx::query(
+            "UPDATE threat_detections SET hits = hits + 1, last_seen = $4 \
+             WHERE signal = $1 AND entity_type = $2 AND entity = $3 AND status = 'active'",
+        )
+        .bind(observation.signal.as_str())
+        .bind(observation.entity_type.as_str())
+        .bind(&observation.entity)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+        if raised.rows_affected() > 0 {
+            tx.commit().await?;
+            return Ok(None);
+        }
+
+        let locked_until = self.lock_until(observation.entity_type, &observation.entity, now);
+        // A replica opening the same detection first wins; this signal is then lost
+        let detection = sqlx::query_as::<_, Detection>(&format!(
+            "INSERT INTO threat_detections (id, signal, entity_type, entity, tenant_id, first_seen, last_seen, locked_until) \
+             VALUES ($1, $2, $3, $4, $5, $6, $6, $7) ON CONFLICT DO NOTHING RETURNING {}",
+            DETECTION_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(observation.signal.as_str())
+        .bind(observation.entity_type.as_str())
+        .bind(&observation.entity)
+        .bind(&observation.tenant_id)
+        .bind(now)
+        .bind(locked_until)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(detection) = &detection {
+            let event = DomainEvent::new(
+                "threat.detected",
+                "threat_detection",
+                detection.id,
+                detection.tenant_id.clone(),
+                serde_json::json!({
+                    "signal": detection.signal,
+                    "entity_type": detection.entity_type,
+                    "entity": detection.entity,
+                    "threshold": threshold,
+                    "window_secs": self.config.window_secs,
+                    "locked_until": detection.locked_until
+                }),
+                self.clock.now(),
+            );
+            events::enqueue(&mut tx, &event).await?;
+        }
+        tx.commit().await?;
+        Ok(detection)
+    }
+
+    /// Close detections whose lock has ended and that saw no signal for a
+    /// window. Returns how many were closed.
+    pub async fn expire(&self) -> Result<u64, SecurityError> {
+        let now = self.clock.now();
+        let quiet_since = now - Duration::seconds(self.config.window_secs as i64);
+        let result = sqlx::query(
+            "UPDATE threat_detections SET status = 'expired' \
+             WHERE status = 'active' AND last_seen < $1 AND (locked_until IS NULL OR locked_until <= $2)",
+        )
+        .bind(quiet_since)
+        .bind(now)
+        .execute(self.storage.pool())
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Reload the locks in force, including those opened by other replicas.
+    pub async fn refresh(&self) -> Result<(), SecurityError> {
+        let locks = sqlx::query_as::<_, Lock>(
+            "SELECT entity_type, entity, locked_until FROM threat_detections \
+             WHERE status = 'active' AND locked_until > $1",
+        )
+        .bind(self.clock.now())
+        .fetch_all(self.storage.pool())
+        .await?;
+        *self.locks.write().unwrap_or_else(|e| e.into_inner()) = locks;
+        Ok(())
+    }
+
+    /// Until when the account or address is locked out, if it is.
+    pub fn locked_until(&self, account: Option<&str>, ip: Option<&str>) -> Option<DateTime<Utc>> {
+        let now = self.clock.now();
+        let bare_account = account.map(|a| a.strip_prefix(SERVICE_ACCOUNT_PREFIX).unwrap_or(a));
+        let locks = self.locks.read().unwrap_or_else(|e| e.into_inner());
+        locks
+            .iter()
+            .filter(|lock| lock.locked_until > now)
+            .filter(|lock| match lock.entity_type.as_str() {
+                "actor" => account == Some(lock.entity.as_str()) || bare_account == Some(lock.entity.as_str()),
+                "actor_ip" => ip == Some(lock.entity.as_str()),
+                _ => false,
+            })
+            .map(|lock| lock.locked_until)
+            .max()
+    }
+
+    fn locks_accounts(&self) -> Option<bool> {
+        let locks = self.locks.read().unwrap_or_else(|e| e.into_inner());
+        (!locks.is_empty()).then(|| locks.iter().any(|lock| lock.entity_type == "actor"))
+    }
+
+    pub async fn list_active(&self) -> Result<Vec<Detection>, SecurityError> {
+        let detections = sqlx::query_as::<_, Detection>(&format!(
+            "SELECT {} FROM threat_detections WHERE status = 'active' ORDER BY last_seen DESC",
+            DETECTION_COLUMNS
+        ))
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(detections)
+    }
+
+    /// Close an active detection, lift its lock and clear its count.
+    pub async fn reset(&self, state: &AppState, principal: &Principal, id: Uuid) -> Result<Detection, SecurityError> {
+        let reset = sqlx::query_as::<_, Detection>(&format!(
+            "UPDATE threat_detections SET status = 'reset', reset_by = $2, reset_at = $3, locked_until = NULL \
+             WHERE id = $1 AND status = 'active' RETURNING {}",
+            DETECTION_COLUMNS
+        ))
+        .bind(id)
+        .bind(&principal.subject)
+        .bind(self.clock.now())
+        .fetch_optional(self.storage.pool())
+        .await?;
+
+        let Some(detection) = reset else {
+            let exists = sqlx::query_scalar::<_, Uuid>("SELECT id FROM threat_detections WHERE id = $1")
+                .bind(id)
+                .fetch_optional(self.storage.pool())
+                .await?;
+            return Err(match exists {
+                Some(_) => SecurityError::Conflict(format!("Detection {} is no longer active", id)),
+                None => SecurityError::NotFound(format!("Detection {} not found", id)),
+            });
+        };
+
+        if let (Some(signal), Some(entity_type)) =
+            (Signal::parse(&detection.signal), EntityType::parse(&detection.entity_type))
+        {
+            state.rate_limiter.clear(&self.rule(signal, entity_type), &detection.entity).await;
+        }
+        self.refresh().await?;
+        Ok(detection)
+    }
+}
+
+/// The `lockout` pipeline stage: reject locked out addresses and accounts.
+pub fn rejection(state: &AppState, req: &ServiceRequest) -> Option<HttpResponse> {
+    let threats = &state.threats;
+    let locks_accounts = threats.locks_accounts()?;
+    let ip = client_ip(req.request());
+    // Only verify the token when an account could be locked
+    let principal = if locks_accounts { state.auth_service.authenticate(req.request()).ok() } else { None };
+    let locked_until = threats.locked_until(principal.as_ref().map(|p| p.subject.as_str()), ip.as_deref())?;
+
+    state.metrics_service.increment("cotai_threat_lockout_rejections_total", &[]);
+    let retry_after = (locked_until - state.clock.now()).num_seconds().max(1);
+    Some(
+        HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+            .json(serde_json::json!({
+                "error": "Temporarily locked out after repeated failures",
+                "code": "locked_out",
+                "retry_after_secs": retry_after
+            })),
+    )
+}
+
+/// Count signals as they arrive and keep detections and locks current.
+pub async fn run_engine(state: web::Data<AppState>) {
+    let Some(mut signals) = state.threats.receiver.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+        return;
+    };
+    let mut failures = state.audit_service.subscribe_failures();
+    let interval_secs = state.config.threats.refresh_interval_secs;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+
+    loop {
+        tokio::select! {
+            Some(observation) = signals.recv() => state.threats.count(&state, observation).await,
+            event = failures.recv() => match event {
+                Ok(event) => {
+                    if let Some(observation) = state.threats.observe_audit(&event) {
+                        state.threats.count(&state, observation).await;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => warn!("Threat engine fell behind, skipped {} audit failures", skipped),
+                Err(RecvError::Closed) => {
+                    error!("Audit failure feed closed, threat engine stopped");
+                    return;
+                }
+            },
+            _ = interval.tick() => {
+                match state.threats.expire().await {
+                    Ok(0) => {}
+                    Ok(count) => info!("Expired {} threat detections", count),
+                    Err(e) => error!("Threat detection expiry failed: {:?}", e),
+                }
+                match state.threats.refresh().await {
+                    Ok(()) => state.startup.recovered("threats"),
+                    Err(e) => error!("Threat lock refresh failed: {:?}", e),
+                }
+            }
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Threat operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Threat operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit_detection(
+    state: &AppState,
+    actor: &str,
+    verb: &str,
+    detection: &Detection,
+    detail: serde_json::Value,
+) {
+    let mut payload = serde_json::json!({
+        "signal": detection.signal,
+        "entity_type": detection.entity_type,
+        "entity": detection.entity,
+        "hits": detection.hits,
+        "locked_until": detection.locked_until
+    });
+    if let (Some(payload), serde_json::Value::Object(detail)) = (payload.as_object_mut(), detail) {
+        payload.extend(detail);
+    }
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: detection.tenant_id.clone(),
+        actor: actor.to_string(),
+        actor_ip: None,
+        action: verb.to_string(),
+        resource: format!("threat_detection:{}", detection.id),
+        outcome: "success".to_string(),
+        payload,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} on detection {}: {:?}", verb, detection.id, e);
+    }
+}
+
+pub async fn list_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.threats.list_active().await {
+        Ok(detections) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "detections": detections
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn reset_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.threats.reset(&state, &principal, path.into_inner()).await {
+        Ok(detection) => {
+            audit_detection(&state, &principal.subject, "threat.reset", &detection, serde_json::Value::Null).await;
+            Ok(HttpResponse::Ok().json(detection))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use chrono::TimeZone;
+
+    async fn start(vars: &[(&str, &str)]) -> (ThreatEngine, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::new(Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap()));
+        let config = Config::for_tests(vars);
+        let engine = ThreatEngine::new(&config, Storage::unreachable(), clock.clone()).await.expect("threat engine");
+        (engine, clock)
+    }
+
+    /// Lock `entity` out as a detection opened now would.
+    fn detect(engine: &ThreatEngine, entity_type: EntityType, entity: &str) -> Option<DateTime<Utc>> {
+        let locked_until = engine.lock_until(entity_type, entity, engine.clock.now());
+        if let Some(locked_until) = locked_until {
+            engine.locks.write().unwrap().push(Lock {
+                entity_type: entity_type.as_str().to_string(),
+                entity: entity.to_string(),
+                locked_until,
+            });
+        }
+        locked_until
+    }
+
+    #[tokio::test]
+    async fn lockout_lasts_its_window() {
+        let (engine, clock) = start(&[("THREAT_LOCKOUT_SECS", "600")]).await;
+        let locked_until = detect(&engine, EntityType::Account, "alice").expect("locked");
+        assert_eq!(locked_until, clock.now() + Duration::seconds(600));
+
+        assert_eq!(engine.locked_until(Some("alice"), None), Some(locked_until));
+        assert_eq!(engine.locked_until(Some("bob"), None), None);
+        clock.advance(Duration::seconds(599));
+        assert_eq!(engine.locked_until(Some("alice"), None), Some(locked_until));
+        clock.advance(Duration::seconds(1));
+        assert_eq!(engine.locked_until(Some("alice"), None), None);
+    }
+
+    #[tokio::test]
+    async fn locks_apply_to_their_own_kind_of_entity() {
+        let (engine, _) = start(&[("THREAT_LOCKOUT_SECS", "600")]).await;
+        detect(&engine, EntityType::Address, "192.0.2.7");
+        detect(&engine, EntityType::Account, "ci-bot");
+
+        assert!(engine.locked_until(None, Some("192.0.2.7")).is_some());
+        assert!(engine.locked_until(Some("192.0.2.7"), None).is_none());
+        // Service account tokens are locked under the bare client id
+        assert!(engine.locked_until(Some("service_account:ci-bot"), None).is_some());
+        assert_eq!(engine.locks_accounts(), Some(true));
+    }
+
+    #[tokio::test]
+    async fn the_latest_lock_wins() {
+        let (engine, clock) = start(&[("THREAT_LOCKOUT_SECS", "600")]).await;
+        detect(&engine, EntityType::Account, "alice");
+        clock.advance(Duration::seconds(300));
+        let later = detect(&engine, EntityType::Address, "192.0.2.7");
+
+        assert_eq!(engine.locked_until(Some("alice"), Some("192.0.2.7")), later);
+        clock.advance(Duration::seconds(300));
+        assert_eq!(engine.locked_until(Some("alice"), Some("192.0.2.7")), later);
+        assert_eq!(engine.locked_until(Some("alice"), None), None);
+    }
+
+    #[tokio::test]
+    async fn protected_subjects_and_zero_lockout_never_lock() {
+        let (engine, _) = start(&[("THREAT_LOCKOUT_SECS", "600"), ("CONTAINMENT_PROTECTED_SUBJECTS", "breakglass")]).await;
+        assert_eq!(detect(&engine, EntityType::Account, "breakglass"), None);
+        assert!(detect(&engine, EntityType::Address, "breakglass").is_some());
+
+        let (engine, _) = start(&[("THREAT_LOCKOUT_SECS", "0")]).await;
+        assert_eq!(detect(&engine, EntityType::Account, "alice"), None);
+        assert_eq!(engine.locks_accounts(), None);
+    }
+}
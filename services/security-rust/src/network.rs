@@ -0,0 +1,439 @@
+/*!
+Client Addresses
+Which address a request came from, behind load balancers and proxies
+
+Every address-based feature (rate limits, lockouts, geo checks, audit)
+goes through `auth::client_ip`, which resolves it here. Forwarding headers
+are only believed from `TRUSTED_PROXIES`: the connection's peer must be a
+trusted proxy for `CLIENT_IP_HEADER` to be read at all, and its hops are
+then walked from the right, skipping trusted proxies, to the first address
+that is not one. A client cannot spoof its address by sending the header
+itself, since the proxies append to it; the leftmost hops are whatever the
+client claimed and are never reached past an untrusted one.
+
+`CLIENT_IP_HEADER` is `x-forwarded-for` (the default), `forwarded`
+(RFC 7239 `for=` parameters), `x-real-ip` or `none`. Behind load balancers
+speaking the PROXY protocol instead, `PROXY_PROTOCOL` makes the listener
+take the peer address from it (see `proxy_protocol`).
+
+Addresses are normalized: ports, brackets and IPv6 zones are dropped and
+IPv4-mapped IPv6 addresses (`::ffff:192.0.2.1`) become plain IPv4, so the
+same client counts the same however it was seen.
+
+`AsnTable` maps addresses to the autonomous system announcing them, for
+limits that count whole networks (see `rate_limiting`).
+*/
+
+use actix_web::http::header::HeaderMap;
+use actix_web::HttpRequest;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+
+use crate::config::Config;
+use crate::errors::SecurityError;
+
+/// Forwarding headers `CLIENT_IP_HEADER` may name.
+pub const CLIENT_IP_HEADERS: &[&str] = &["x-forwarded-for", "forwarded", "x-real-ip", "none"];
+
+/// An address range, e.g. `10.0.0.0/8` or `2001:db8::/32`; a bare address
+/// is a range of one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s.trim(), None),
+        };
+        let network = normalize(addr.parse::<IpAddr>().map_err(|_| format!("'{}' is not an address or range", s))?);
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max)
+                .ok_or_else(|| format!("'{}' has an invalid prefix length", s))?,
+            None => max,
+        };
+        Ok(Self { network, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+impl Cidr {
+    /// The range of the leading `v4_prefix` or `v6_prefix` bits of `ip`,
+    /// whichever family it is.
+    pub fn around(ip: IpAddr, v4_prefix: u8, v6_prefix: u8) -> Self {
+        match normalize(ip) {
+            IpAddr::V4(ip) => {
+                let prefix = v4_prefix.min(32);
+                let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                Self { network: IpAddr::V4(Ipv4Addr::from(u32::from(ip) & mask)), prefix }
+            }
+            IpAddr::V6(ip) => {
+                let prefix = v6_prefix.min(128);
+                let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                Self { network: IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask)), prefix }
+            }
+        }
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, normalize(ip)) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// IPv4-mapped IPv6 addresses as plain IPv4.
+pub fn normalize(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(IpAddr::V6(v6), IpAddr::V4),
+        v4 => v4,
+    }
+}
+
+/// An address as forwarding headers write it: bare, with a port, in
+/// brackets or with a zone. `unknown` and obfuscated identifiers are `None`.
+pub fn parse_address(s: &str) -> Option<IpAddr> {
+    let s = s.trim().trim_matches('"');
+    if let Ok(socket) = s.parse::<SocketAddr>() {
+        return Some(normalize(socket.ip()));
+    }
+    let s = s.trim_start_matches('[');
+    let s = s.split(']').next().unwrap_or(s);
+    let s = s.split('%').next().unwrap_or(s);
+    s.parse::<IpAddr>().ok().map(normalize)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Header {
+    XForwardedFor,
+    Forwarded,
+    XRealIp,
+    None,
+}
+
+/// Hops a request passed, as written, oldest first.
+fn hops(header: Header, headers: &HeaderMap) -> Vec<String> {
+    let values = |name: &str| -> Vec<String> {
+        headers
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|hop| hop.trim().to_string())
+            .collect()
+    };
+    match header {
+        Header::XForwardedFor => values("x-forwarded-for"),
+        Header::XRealIp => values("x-real-ip").into_iter().take(1).collect(),
+        // Each element is `for=...;proto=...`; an element without `for`
+        // still counts as a hop nobody vouched for
+        Header::Forwarded => values("forwarded")
+            .into_iter()
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                    .map(|(_, value)| value.trim().to_string())
+                    .unwrap_or_default()
+            })
+            .collect(),
+        Header::None => Vec::new(),
+    }
+}
+
+pub struct ClientIps {
+    trusted: Vec<Cidr>,
+    header: Header,
+}
+
+impl ClientIps {
+    pub fn new(config: &Config) -> Result<Self, SecurityError> {
+        let trusted = config
+            .network
+            .trusted_proxies
+            .iter()
+            .map(|cidr| cidr.parse::<Cidr>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| SecurityError::ConfigError(format!("TRUSTED_PROXIES: {}", e)))?;
+        let header = match config.network.client_ip_header.as_str() {
+            "x-forwarded-for" => Header::XForwardedFor,
+            "forwarded" => Header::Forwarded,
+            "x-real-ip" => Header::XRealIp,
+            "none" => Header::None,
+            other => return Err(SecurityError::ConfigError(format!("CLIENT_IP_HEADER '{}' is not supported", other))),
+        };
+        Ok(Self { trusted, header })
+    }
+
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /// The client behind the trusted proxies in front of `peer`.
+    pub fn resolve_from(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let mut client = normalize(peer);
+        if !self.is_trusted(client) {
+            return client;
+        }
+        for hop in hops(self.header, headers).iter().rev() {
+            // A hop that does not parse ends the chain at the proxy that
+            // added it
+            let Some(ip) = parse_address(hop) else {
+                break;
+            };
+            client = ip;
+            if !self.is_trusted(ip) {
+                break;
+            }
+        }
+        client
+    }
+
+    /// The hops `CLIENT_IP_HEADER` lists, oldest first.
+    pub fn hops(&self, headers: &HeaderMap) -> Vec<String> {
+        hops(self.header, headers)
+    }
+
+    pub fn resolve(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let peer = req.peer_addr()?.ip();
+        Some(self.resolve_from(peer, req.headers()))
+    }
+}
+
+/// Addresses of both families on one line, IPv4 as IPv4-mapped IPv6.
+fn ordinal(ip: IpAddr) -> u128 {
+    match normalize(ip) {
+        IpAddr::V4(ip) => u128::from(ip.to_ipv6_mapped()),
+        IpAddr::V6(ip) => u128::from(ip),
+    }
+}
+
+/// Which autonomous system announces an address.
+///
+/// Loaded from an ip2asn table (as published by iptoasn.com): one range
+/// per line, `first last asn` and optionally country and description,
+/// separated by tabs. Ranges with ASN 0 are not routed and are left out.
+pub struct AsnTable {
+    /// `(first, last, asn)`, sorted by `first` and not overlapping.
+    ranges: Vec<(u128, u128, u32)>,
+}
+
+impl AsnTable {
+    pub fn load(path: &str) -> Result<Self, SecurityError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| SecurityError::ConfigError(format!("Cannot read ASN table {}: {}", path, e)))?;
+        Self::parse(&text).map_err(|e| SecurityError::ConfigError(format!("ASN table {}: {}", path, e)))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut ranges = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+            let invalid = || format!("line {}: expected first address, last address and ASN", number + 1);
+            let [first, last, asn, ..] = fields.as_slice() else {
+                return Err(invalid());
+            };
+            let first = first.parse::<IpAddr>().map_err(|_| invalid())?;
+            let last = last.parse::<IpAddr>().map_err(|_| invalid())?;
+            let asn = asn.trim_start_matches("AS").parse::<u32>().map_err(|_| invalid())?;
+            let (first, last) = (ordinal(first), ordinal(last));
+            if first > last {
+                return Err(format!("line {}: range ends before it starts", number + 1));
+            }
+            if asn != 0 {
+                ranges.push((first, last, asn));
+            }
+        }
+        ranges.sort_by_key(|(first, _, _)| *first);
+        if let Some(pair) = ranges.windows(2).find(|pair| pair[1].0 <= pair[0].1) {
+            return Err(format!("ranges of AS{} and AS{} overlap", pair[0].2, pair[1].2));
+        }
+        Ok(Self { ranges })
+    }
+
+    pub fn lookup(&self, ip: IpAddr) -> Option<u32> {
+        let ip = ordinal(ip);
+        let index = self.ranges.partition_point(|(first, _, _)| *first <= ip);
+        let (_, last, asn) = self.ranges.get(index.checked_sub(1)?)?;
+        (ip <= *last).then_some(*asn)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(HeaderName::from_static(name), HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    fn client_ips(header: &str) -> ClientIps {
+        let config = Config::for_tests(&[("TRUSTED_PROXIES", "10.0.0.0/8,2001:db8::/32"), ("CLIENT_IP_HEADER", header)]);
+        ClientIps::new(&config).unwrap()
+    }
+
+    #[test]
+    fn ranges_parse_and_match() {
+        let v4: Cidr = "10.1.0.0/16".parse().unwrap();
+        assert!(v4.contains(ip("10.1.255.3")));
+        assert!(!v4.contains(ip("10.2.0.1")));
+        assert!(v4.contains(ip("::ffff:10.1.0.9")));
+        assert!(!v4.contains(ip("2001:db8::1")));
+
+        let v6: Cidr = "2001:db8::/32".parse().unwrap();
+        assert!(v6.contains(ip("2001:db8:ffff::1")));
+        assert!(!v6.contains(ip("2001:db9::1")));
+
+        let single: Cidr = " 192.0.2.7 ".parse().unwrap();
+        assert_eq!(single.to_string(), "192.0.2.7/32");
+        assert!(!single.contains(ip("192.0.2.8")));
+
+        let everything: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(everything.contains(ip("203.0.113.1")));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("2001:db8::/129".parse::<Cidr>().is_err());
+        assert!("10.0.0.0/x".parse::<Cidr>().is_err());
+        assert!("example.com".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn ranges_around_an_address() {
+        assert_eq!(Cidr::around(ip("203.0.113.77"), 24, 64).to_string(), "203.0.113.0/24");
+        assert_eq!(Cidr::around(ip("2001:db8:1:2:3::4"), 24, 48).to_string(), "2001:db8:1::/48");
+        assert_eq!(Cidr::around(ip("::ffff:203.0.113.77"), 16, 64).to_string(), "203.0.0.0/16");
+        assert_eq!(Cidr::around(ip("203.0.113.77"), 0, 0).to_string(), "0.0.0.0/0");
+    }
+
+    #[test]
+    fn addresses_are_normalized() {
+        assert_eq!(parse_address("192.0.2.1:443"), Some(ip("192.0.2.1")));
+        assert_eq!(parse_address("\"[2001:db8::1]:8443\""), Some(ip("2001:db8::1")));
+        assert_eq!(parse_address("[fe80::1%eth0]"), Some(ip("fe80::1")));
+        assert_eq!(parse_address("::ffff:192.0.2.1"), Some(ip("192.0.2.1")));
+        assert_eq!(parse_address("unknown"), None);
+        assert_eq!(parse_address("_hidden"), None);
+    }
+
+    #[test]
+    fn forwarding_headers_are_only_believed_from_trusted_proxies() {
+        let ips = client_ips("x-forwarded-for");
+        let forwarded = headers(&[("x-forwarded-for", "198.51.100.9")]);
+        assert_eq!(ips.resolve_from(ip("203.0.113.5"), &forwarded), ip("203.0.113.5"));
+        assert_eq!(ips.resolve_from(ip("10.0.0.2"), &forwarded), ip("198.51.100.9"));
+        assert_eq!(ips.resolve_from(ip("::ffff:10.0.0.2"), &forwarded), ip("198.51.100.9"));
+        assert_eq!(ips.resolve_from(ip("10.0.0.2"), &HeaderMap::new()), ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn hops_are_walked_from_the_right_to_the_first_untrusted() {
+        let ips = client_ips("x-forwarded-for");
+        // The client claimed 1.1.1.1; the proxies appended the rest
+        let spoofed = headers(&[("x-forwarded-for", "1.1.1.1, 198.51.100.9"), ("x-forwarded-for", "10.0.0.7")]);
+        assert_eq!(ips.resolve_from(ip("10.0.0.2"), &spoofed), ip("198.51.100.9"));
+        assert_eq!(ips.hops(&spoofed), vec!["1.1.1.1", "198.51.100.9", "10.0.0.7"]);
+
+        // An unparseable hop stops at the proxy that added it
+        let garbled = headers(&[("x-forwarded-for", "198.51.100.9, garbage, 10.0.0.7")]);
+        assert_eq!(ips.resolve_from(ip("10.0.0.2"), &garbled), ip("10.0.0.7"));
+    }
+
+    #[test]
+    fn forwarded_and_x_real_ip_are_read() {
+        let ips = client_ips("forwarded");
+        let forwarded = headers(&[("forwarded", "for=192.0.2.60;proto=https, for=\"[2001:db9::17]:4711\"")]);
+        assert_eq!(ips.resolve_from(ip("10.0.0.2"), &forwarded), ip("2001:db9::17"));
+        let without_for = headers(&[("forwarded", "for=198.51.100.9, proto=https")]);
+        assert_eq!(ips.resolve_from(ip("10.0.0.2"), &without_for), ip("10.0.0.2"));
+
+        let ips = client_ips("x-real-ip");
+        let real = headers(&[("x-real-ip", "198.51.100.9"), ("x-forwarded-for", "1.1.1.1")]);
+        assert_eq!(ips.resolve_from(ip("10.0.0.2"), &real), ip("198.51.100.9"));
+
+        let ips = client_ips("none");
+        assert_eq!(ips.resolve_from(ip("10.0.0.2"), &real), ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn asn_ranges_are_looked_up() {
+        let table = AsnTable::parse(
+            "# first\tlast\tasn\tcountry\tdescription\n\
+             1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET\n\
+             1.0.1.0\t1.0.3.255\t0\tNone\tNot routed\n\
+             8.8.8.0\t8.8.8.255\tAS15169\tUS\tGOOGLE\n\
+             \n\
+             2001:4860::\t2001:4860:ffff:ffff:ffff:ffff:ffff:ffff\t15169\n",
+        )
+        .unwrap();
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.lookup(ip("1.0.0.0")), Some(13335));
+        assert_eq!(table.lookup(ip("1.0.0.255")), Some(13335));
+        assert_eq!(table.lookup(ip("1.0.2.1")), None);
+        assert_eq!(table.lookup(ip("8.8.8.8")), Some(15169));
+        assert_eq!(table.lookup(ip("::ffff:8.8.4.4")), None);
+        assert_eq!(table.lookup(ip("2001:4860:4860::8888")), Some(15169));
+        assert_eq!(table.lookup(ip("0.0.0.1")), None);
+        assert_eq!(table.lookup(ip("255.255.255.255")), None);
+    }
+
+    #[test]
+    fn bad_asn_tables_are_refused() {
+        assert!(AsnTable::parse("").unwrap().is_empty());
+        assert_eq!(
+            AsnTable::parse("1.0.0.0\t1.0.0.255").err().unwrap(),
+            "line 1: expected first address, last address and ASN"
+        );
+        assert_eq!(
+            AsnTable::parse("1.0.0.255\t1.0.0.0\t13335").err().unwrap(),
+            "line 1: range ends before it starts"
+        );
+        assert_eq!(
+            AsnTable::parse("1.0.0.0\t1.0.0.255\t13335\n1.0.0.128\t1.0.1.0\t64500").err().unwrap(),
+            "ranges of AS13335 and AS64500 overlap"
+        );
+    }
+}
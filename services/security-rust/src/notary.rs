@@ -0,0 +1,605 @@
+/*!
+Notary Module
+Proof that a document existed, unaltered, at a point in time
+
+Procurement publishes tender documents and must later be able to show they
+were not changed after publication. The document itself never reaches this
+service: callers register its SHA-256 with `POST /notary/register` (needs
+`NOTARY_REGISTER_SCOPE`) and keep the proof they get back.
+
+Each registration is a leaf of an append-only Merkle tree, hashed as in
+RFC 6962 (`SHA-256(0x00 || leaf)` and `SHA-256(0x01 || left || right)`),
+over `cotai-notary-entry:<id>:<sha256>:<registered_at>`. The tree head
+after the append is signed with the `NOTARY_ALGORITHM` key, so the proof
+holds the registration time, the leaf's inclusion path up to the root and
+the signed head. Only the roots of complete subtrees are stored; any
+head's root and any inclusion path are derived from them.
+
+`POST /notary/verify` takes either a proof, which it checks end to end and
+against the ledger as it is now, or a bare hash, for which it looks up
+every registration and verifies it. A proof also verifies offline: rebuild
+the leaf, fold the path into the root and check the head signature, made
+for the `notary-head` domain (see `signing`), against
+`/.well-known/jwks.json`. `GET /notary/head` returns the latest signed
+head for publishing elsewhere.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgExecutor};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::audit::receipts::{self, RECEIPT_HEADER};
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip};
+use crate::clock::Clock;
+use crate::config::{Config, NotaryConfig};
+use crate::crypto::{CryptoService, VerifyRequest as SignatureCheck};
+use crate::errors::SecurityError;
+use crate::signing::{Algorithm, NOTARY_HEAD_DOMAIN};
+use crate::storage::Storage;
+use crate::AppState;
+
+const ENTRY_COLUMNS: &str = "id, leaf_index, document_sha256, label, tenant_id, registered_by, registered_at, \
+     leaf_hash, tree_size, root_hash, algorithm, key_id, signature";
+
+/// Stands in for node hashes while working out which nodes are needed.
+const PLACEHOLDER: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Reads a stored node by `(level, index)`.
+type Nodes<'a> = dyn FnMut(u32, i64) -> Option<String> + 'a;
+
+fn sha256(parts: &[&[u8]]) -> String {
+    let data: Vec<u8> = parts.concat();
+    hex::encode(digest(&SHA256, &data))
+}
+
+fn leaf_hash(entry_id: Uuid, document_sha256: &str, registered_at: DateTime<Utc>) -> String {
+    let data = format!(
+        "cotai-notary-entry:{}:{}:{}",
+        entry_id,
+        document_sha256,
+        registered_at.to_rfc3339_opts(SecondsFormat::Micros, true)
+    );
+    sha256(&[&[0x00], data.as_bytes()])
+}
+
+fn node_hash(left: &str, right: &str) -> Option<String> {
+    Some(sha256(&[&[0x01], &hex::decode(left).ok()?, &hex::decode(right).ok()?]))
+}
+
+/// The signed statement of a tree head.
+fn head_message(tree_size: i64, root_hash: &str, signed_at: DateTime<Utc>) -> String {
+    format!(
+        "cotai-notary-head:{}:{}:{}",
+        tree_size,
+        root_hash,
+        signed_at.to_rfc3339_opts(SecondsFormat::Micros, true)
+    )
+}
+
+/// Largest power of two below `n`, for `n > 1`.
+fn split(n: i64) -> i64 {
+    1 << (63 - (n - 1).leading_zeros())
+}
+
+/// Root of leaves `[start, end)`. Every left part of the RFC 6962 split is
+/// a complete, aligned subtree, so it is read as a stored node.
+fn subtree_hash(nodes: &mut Nodes, start: i64, end: i64) -> Option<String> {
+    let n = end - start;
+    if n <= 0 {
+        return None;
+    }
+    if n & (n - 1) == 0 && start % n == 0 {
+        return nodes(n.trailing_zeros(), start / n);
+    }
+    let k = split(n);
+    node_hash(&subtree_hash(nodes, start, start + k)?, &subtree_hash(nodes, start + k, end)?)
+}
+
+/// RFC 6962 audit path of leaf `m` within `[start, end)`, leaf side first.
+fn inclusion_path(nodes: &mut Nodes, m: i64, start: i64, end: i64, path: &mut Vec<String>) -> Option<()> {
+    let n = end - start;
+    if n <= 1 {
+        return Some(());
+    }
+    let k = split(n);
+    if m < k {
+        inclusion_path(nodes, m, start, start + k, path)?;
+        path.push(subtree_hash(nodes, start + k, end)?);
+    } else {
+        inclusion_path(nodes, m - k, start + k, end, path)?;
+        path.push(subtree_hash(nodes, start, start + k)?);
+    }
+    Some(())
+}
+
+/// Fold an inclusion path into the root it proves (RFC 9162, 2.1.3.2).
+fn root_from_path(leaf_index: i64, tree_size: i64, leaf_hash: &str, path: &[String]) -> Option<String> {
+    if leaf_index < 0 || leaf_index >= tree_size {
+        return None;
+    }
+    let (mut fn_, mut sn) = (leaf_index, tree_size - 1);
+    let mut root = leaf_hash.to_string();
+    for sibling in path {
+        if sn == 0 {
+            return None;
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            root = node_hash(sibling, &root)?;
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            root = node_hash(&root, sibling)?;
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    (sn == 0).then_some(root)
+}
+
+async fn load_nodes<'e>(
+    executor: impl PgExecutor<'e>,
+    keys: &[(i32, i64)],
+) -> Result<HashMap<(i32, i64), String>, SecurityError> {
+    let (levels, indexes): (Vec<i32>, Vec<i64>) = keys.iter().copied().unzip();
+    let rows: Vec<(i32, i64, String)> = sqlx::query_as(
+        "SELECT level, idx, hash FROM notary_nodes \
+         WHERE (level, idx) IN (SELECT * FROM UNNEST($1::INTEGER[], $2::BIGINT[]))",
+    )
+    .bind(&levels)
+    .bind(&indexes)
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().map(|(level, idx, hash)| ((level, idx), hash)).collect())
+}
+
+/// Evaluate `f` over stored nodes: once to learn which it reads, then
+/// again with those loaded.
+async fn with_nodes<'e, T>(
+    executor: impl PgExecutor<'e>,
+    f: impl Fn(&mut Nodes) -> Option<T>,
+) -> Result<T, SecurityError> {
+    let mut keys = Vec::new();
+    f(&mut |level, idx| {
+        keys.push((level as i32, idx));
+        Some(PLACEHOLDER.to_string())
+    });
+    let nodes = load_nodes(executor, &keys).await?;
+    f(&mut |level, idx| nodes.get(&(level as i32, idx)).cloned())
+        .ok_or_else(|| SecurityError::StorageError("Notary ledger is missing nodes".to_string()))
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Entry {
+    pub id: Uuid,
+    pub leaf_index: i64,
+    pub document_sha256: String,
+    pub label: Option<String>,
+    pub tenant_id: Option<String>,
+    pub registered_by: String,
+    pub registered_at: DateTime<Utc>,
+    pub leaf_hash: String,
+    /// Size of the tree head signed when the entry was appended.
+    pub tree_size: i64,
+    pub root_hash: String,
+    pub algorithm: String,
+    pub key_id: String,
+    pub signature: String,
+}
+
+/// What a caller keeps to prove a registration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Proof {
+    pub entry_id: Uuid,
+    pub document_sha256: String,
+    /// Also the time the tree head was signed.
+    pub registered_at: DateTime<Utc>,
+    pub leaf_index: i64,
+    pub leaf_hash: String,
+    pub tree_size: i64,
+    pub root_hash: String,
+    /// Sibling hashes from the leaf up to the root.
+    pub inclusion_path: Vec<String>,
+    pub algorithm: String,
+    pub key_id: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SignedHead {
+    pub tree_size: i64,
+    pub root_hash: String,
+    pub signed_at: DateTime<Utc>,
+    pub algorithm: String,
+    pub key_id: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterRequest {
+    /// Hex SHA-256 of the document.
+    pub sha256: String,
+    /// What the document is, e.g. the tender and file name.
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyRequest {
+    pub proof: Option<Proof>,
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProofVerification {
+    /// Every check below passed.
+    pub valid: bool,
+    /// The leaf hash matches the entry id, document hash and time.
+    pub leaf_valid: bool,
+    /// The inclusion path leads from the leaf to the signed root.
+    pub inclusion_valid: bool,
+    /// `None` when the signing key is no longer published.
+    pub signature_valid: Option<bool>,
+    /// The ledger still holds the entry and the same root at that size.
+    pub recorded: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerifiedProof {
+    pub proof: Proof,
+    #[serde(flatten)]
+    pub verification: ProofVerification,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Lookup {
+    pub document_sha256: String,
+    pub registered: bool,
+    pub proofs: Vec<VerifiedProof>,
+}
+
+pub(crate) fn normalize_sha256(value: &str) -> Result<String, SecurityError> {
+    let value = value.trim().to_ascii_lowercase();
+    if value.len() != 64 || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(SecurityError::ValidationError("sha256 must be 64 hex characters".to_string()));
+    }
+    Ok(value)
+}
+
+pub struct NotaryService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: NotaryConfig,
+}
+
+impl NotaryService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        info!("Notary service initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            config: config.notary.clone(),
+        })
+    }
+
+    /// Append a document hash to the ledger and sign the new head.
+    pub async fn register(
+        &self,
+        crypto: &CryptoService,
+        request: &RegisterRequest,
+        registered_by: &str,
+        tenant_id: Option<&str>,
+    ) -> Result<Proof, SecurityError> {
+        let document_sha256 = normalize_sha256(&request.sha256)?;
+        let algorithm = Algorithm::parse(&self.config.algorithm)
+            .ok_or_else(|| SecurityError::ConfigError("Invalid NOTARY_ALGORITHM".to_string()))?;
+
+        let mut tx = self.storage.begin().await?;
+        let (index,): (i64,) = sqlx::query_as("SELECT tree_size FROM notary_head WHERE singleton FOR UPDATE")
+            .fetch_one(&mut *tx)
+            .await?;
+        let id = Uuid::new_v4();
+        let now = self.clock.now();
+        let leaf = leaf_hash(id, &document_sha256, now);
+        let tree_size = index + 1;
+
+        // The leaf completes one subtree per trailing one bit of its index
+        let completed = (index + 1).trailing_zeros();
+        let siblings: Vec<(i32, i64)> = (0..completed).map(|level| (level as i32, (index >> level) - 1)).collect();
+        let stored = load_nodes(&mut *tx, &siblings).await?;
+        let mut levels = vec![0];
+        let mut indexes = vec![index];
+        let mut hashes = vec![leaf.clone()];
+        for (level, key) in (1..=completed).zip(&siblings) {
+            let left = stored.get(key)
+                .ok_or_else(|| SecurityError::StorageError("Notary ledger is missing nodes".to_string()))?;
+            let hash = node_hash(left, hashes.last().expect("leaf pushed"))
+                .ok_or_else(|| SecurityError::StorageError("Notary ledger holds a malformed node".to_string()))?;
+            levels.push(level as i32);
+            indexes.push(index >> level);
+            hashes.push(hash);
+        }
+        sqlx::query(
+            "INSERT INTO notary_nodes (level, idx, hash) \
+             SELECT * FROM UNNEST($1::INTEGER[], $2::BIGINT[], $3::TEXT[])",
+        )
+        .bind(&levels)
+        .bind(&indexes)
+        .bind(&hashes)
+        .execute(&mut *tx)
+        .await?;
+
+        let (root_hash, path) = with_nodes(&mut *tx, |nodes| {
+            let root = subtree_hash(nodes, 0, tree_size)?;
+            let mut path = Vec::new();
+            inclusion_path(nodes, index, 0, tree_size, &mut path)?;
+            Some((root, path))
+        })
+        .await?;
+        let signed = crypto.generate_signature(NOTARY_HEAD_DOMAIN, &head_message(tree_size, &root_hash, now), None, algorithm)?;
+
+        sqlx::query(&format!(
+            "INSERT INTO notary_entries ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)",
+            ENTRY_COLUMNS
+        ))
+        .bind(id)
+        .bind(index)
+        .bind(&document_sha256)
+        .bind(&request.label)
+        .bind(tenant_id)
+        .bind(registered_by)
+        .bind(now)
+        .bind(&leaf)
+        .bind(tree_size)
+        .bind(&root_hash)
+        .bind(algorithm.as_str())
+        .bind(&signed.key_id)
+        .bind(&signed.signature)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE notary_head SET tree_size = $1, updated_at = $2 WHERE singleton")
+            .bind(tree_size)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(Proof {
+            entry_id: id,
+            document_sha256,
+            registered_at: now,
+            leaf_index: index,
+            leaf_hash: leaf,
+            tree_size,
+            root_hash,
+            inclusion_path: path,
+            algorithm: algorithm.as_str().to_string(),
+            key_id: signed.key_id,
+            signature: signed.signature,
+        })
+    }
+
+    /// The proof handed out when `entry` was registered, rebuilt.
+    async fn proof(&self, entry: Entry) -> Result<Proof, SecurityError> {
+        let inclusion_path = with_nodes(self.storage.pool(), |nodes| {
+            let mut path = Vec::new();
+            inclusion_path(nodes, entry.leaf_index, 0, entry.tree_size, &mut path)?;
+            Some(path)
+        })
+        .await?;
+        Ok(Proof {
+            entry_id: entry.id,
+            document_sha256: entry.document_sha256,
+            registered_at: entry.registered_at,
+            leaf_index: entry.leaf_index,
+            leaf_hash: entry.leaf_hash,
+            tree_size: entry.tree_size,
+            root_hash: entry.root_hash,
+            inclusion_path,
+            algorithm: entry.algorithm,
+            key_id: entry.key_id,
+            signature: entry.signature,
+        })
+    }
+
+    /// Check a proof on its own, then against the ledger.
+    pub async fn verify(&self, crypto: &CryptoService, proof: &Proof) -> Result<ProofVerification, SecurityError> {
+        let leaf_valid = leaf_hash(proof.entry_id, &proof.document_sha256, proof.registered_at) == proof.leaf_hash;
+        let inclusion_valid = root_from_path(proof.leaf_index, proof.tree_size, &proof.leaf_hash, &proof.inclusion_path)
+            .is_some_and(|root| root == proof.root_hash);
+        let signature_valid = head_signed(crypto, proof)?;
+
+        let entry = sqlx::query_as::<_, Entry>(&format!("SELECT {} FROM notary_entries WHERE id = $1", ENTRY_COLUMNS))
+            .bind(proof.entry_id)
+            .fetch_optional(self.storage.pool())
+            .await?;
+        let entry_matches = entry.is_some_and(|entry| {
+            entry.leaf_index == proof.leaf_index
+                && entry.leaf_hash == proof.leaf_hash
+                && entry.tree_size == proof.tree_size
+                && entry.root_hash == proof.root_hash
+        });
+        let ledger_matches = if entry_matches {
+            let size = proof.tree_size;
+            with_nodes(self.storage.pool(), |nodes| subtree_hash(nodes, 0, size)).await? == proof.root_hash
+        } else {
+            false
+        };
+
+        let reason = if !leaf_valid {
+            Some("leaf_mismatch")
+        } else if !inclusion_valid {
+            Some("bad_inclusion_path")
+        } else if signature_valid.is_none() {
+            Some("signing_key_unavailable")
+        } else if signature_valid == Some(false) {
+            Some("bad_signature")
+        } else if !entry_matches {
+            Some("not_in_ledger")
+        } else if !ledger_matches {
+            Some("ledger_mismatch")
+        } else {
+            None
+        };
+
+        Ok(ProofVerification {
+            valid: reason.is_none(),
+            leaf_valid,
+            inclusion_valid,
+            signature_valid,
+            recorded: ledger_matches,
+            reason,
+        })
+    }
+
+    /// Every registration of a document hash, verified.
+    pub async fn lookup(&self, crypto: &CryptoService, sha256: &str) -> Result<Lookup, SecurityError> {
+        let document_sha256 = normalize_sha256(sha256)?;
+        let entries = sqlx::query_as::<_, Entry>(&format!(
+            "SELECT {} FROM notary_entries WHERE document_sha256 = $1 ORDER BY leaf_index",
+            ENTRY_COLUMNS
+        ))
+        .bind(&document_sha256)
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        let mut proofs = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let proof = self.proof(entry).await?;
+            let verification = self.verify(crypto, &proof).await?;
+            proofs.push(VerifiedProof { proof, verification });
+        }
+        Ok(Lookup {
+            document_sha256,
+            registered: !proofs.is_empty(),
+            proofs,
+        })
+    }
+
+    /// The head signed with the latest registration.
+    pub async fn head(&self) -> Result<SignedHead, SecurityError> {
+        let head: Option<(i64, String, DateTime<Utc>, String, String, String)> = sqlx::query_as(
+            "SELECT tree_size, root_hash, registered_at, algorithm, key_id, signature \
+             FROM notary_entries ORDER BY leaf_index DESC LIMIT 1",
+        )
+        .fetch_optional(self.storage.pool())
+        .await?;
+        let (tree_size, root_hash, signed_at, algorithm, key_id, signature) =
+            head.ok_or_else(|| SecurityError::NotFound("The notary ledger is empty".to_string()))?;
+        Ok(SignedHead { tree_size, root_hash, signed_at, algorithm, key_id, signature })
+    }
+}
+
+/// Whether a proof's head signature holds; `None` when its key is gone.
+fn head_signed(crypto: &CryptoService, proof: &Proof) -> Result<Option<bool>, SecurityError> {
+    let Some(algorithm) = Algorithm::parse(&proof.algorithm).filter(|a| *a != Algorithm::Hs256) else {
+        return Ok(Some(false));
+    };
+    let usable = crypto.signing_keys().metadata().iter()
+        .any(|key| key.key_id == proof.key_id && key.state != "retired");
+    if !usable {
+        return Ok(None);
+    }
+
+    crypto.verify(NOTARY_HEAD_DOMAIN, &SignatureCheck {
+        data: head_message(proof.tree_size, &proof.root_hash, proof.registered_at),
+        signature: proof.signature.clone(),
+        algorithm: Some(algorithm),
+        key_id: Some(proof.key_id.clone()),
+        timestamp: None,
+    })
+    .map(Some)
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Notary operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Notary operation failed"
+            }))
+        }
+    }
+}
+
+pub async fn register_handler(
+    req: HttpRequest,
+    request: web::Json<RegisterRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.notary.register_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let proof = match state.notary.register(&state.crypto_service, &request, &principal.subject, principal.tenant_id.as_deref()).await {
+        Ok(proof) => proof,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    let receipt = receipts::record_or_warn(&state, NewAuditEvent {
+        tenant_id: principal.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(&req),
+        action: "notary.register".to_string(),
+        resource: format!("notary_entry:{}", proof.entry_id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "document_sha256": proof.document_sha256,
+            "label": request.label,
+            "leaf_index": proof.leaf_index,
+            "root_hash": proof.root_hash
+        }),
+    }).await;
+
+    let mut response = HttpResponse::Created();
+    if let Some(receipt) = receipt {
+        response.insert_header((RECEIPT_HEADER, receipt));
+    }
+    Ok(response.json(proof))
+}
+
+pub async fn verify_handler(request: web::Json<VerifyRequest>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let result = match (&request.proof, &request.sha256) {
+        (Some(proof), _) => state.notary.verify(&state.crypto_service, proof).await
+            .map(|verification| HttpResponse::Ok().json(verification)),
+        (None, Some(sha256)) => state.notary.lookup(&state.crypto_service, sha256).await
+            .map(|lookup| HttpResponse::Ok().json(lookup)),
+        (None, None) => Err(SecurityError::ValidationError("proof or sha256 is required".to_string())),
+    };
+    Ok(result.unwrap_or_else(error_response))
+}
+
+pub async fn head_handler(state: web::Data<AppState>) -> Result<HttpResponse> {
+    match state.notary.head().await {
+        Ok(head) => Ok(HttpResponse::Ok().json(head)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/notary")
+            .route("/register", web::post().to(register_handler))
+            .route("/verify", web::post().to(verify_handler))
+            .route("/head", web::get().to(head_handler)),
+    );
+}
@@ -0,0 +1,956 @@
+/*!
+Organization Structure
+Departments, units and reporting lines feeding authorization
+
+Each tenant's chart is a tree of units, each a `department` or a plain
+`unit`, known by a `key` the HR system or identity provider chooses, with
+an optional manager. Subjects belong to one unit and may name a direct
+manager; without one, their manager is that of the nearest unit up the
+tree managed by someone else.
+
+The chart is kept in step three ways, all needing `ORG_SYNC_SCOPE`:
+
+- `PUT`/`DELETE /admin/org/units/{key}` and `/admin/org/members/{subject}`
+  one change at a time
+- `POST /admin/org/sync` with the whole chart, `{units, members, prune}`;
+  with `prune`, units and members missing from it are removed
+- SCIM 2.0 `/scim/v2/Users`, reading `department` and `manager` from the
+  enterprise user extension. SCIM ids are user names, prefixed with
+  `ORG_SCIM_SUBJECT_PREFIX` to give subjects; departments not yet in the
+  chart are created.
+
+Authorization sees a subject's effective membership (see `authz`):
+`subject.department` (the nearest department up the tree, absent when
+none), `subject.units` (the subject's unit and those above it) and
+`subject.managers` (the management chain, nearest first). A resource with
+an `owner` attribute gets the same for its owner as `resource.owner_department`,
+`resource.owner_units` and `resource.owner_managers`, so rules can say
+"same department" or "manager of":
+
+```json
+{"attribute": "resource.owner_department", "op": "eq", "value_of": "subject.department"}
+{"attribute": "subject.id", "op": "in", "value_of": "resource.owner_managers"}
+```
+
+Charts are cached per tenant for `ORG_CACHE_TTL_SECS`, memberships worked
+out once per cached chart; changes here drop the cache at once. Every
+change publishes an `org.*` event.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::{FromRow, Postgres, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, OrgConfig};
+use crate::errors::SecurityError;
+use crate::events::{self, DomainEvent};
+use crate::storage::Storage;
+use crate::AppState;
+
+pub const UNIT_KINDS: &[&str] = &["department", "unit"];
+
+/// Deepest tree and longest management chain followed.
+const MAX_DEPTH: usize = 32;
+
+const SCIM_USER: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+const SCIM_ENTERPRISE: &str = "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User";
+const SCIM_ERROR: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+
+const UNIT_COLUMNS: &str = "id, tenant_id, key, name, kind, parent, manager, updated_by, updated_at";
+const MEMBER_COLUMNS: &str = "subject, tenant_id, unit, manager, updated_by, updated_at";
+
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub struct Unit {
+    pub id: Uuid,
+    pub tenant_id: Option<String>,
+    pub key: String,
+    pub name: String,
+    /// `department` or `unit`.
+    pub kind: String,
+    /// Key of the unit above.
+    pub parent: Option<String>,
+    pub manager: Option<String>,
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub struct Member {
+    pub subject: String,
+    pub tenant_id: Option<String>,
+    /// Key of the subject's unit.
+    pub unit: String,
+    /// Direct manager, overriding the unit's.
+    pub manager: Option<String>,
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UnitRequest {
+    pub name: String,
+    pub kind: String,
+    pub parent: Option<String>,
+    pub manager: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MemberRequest {
+    pub unit: String,
+    pub manager: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncUnit {
+    pub key: String,
+    #[serde(flatten)]
+    pub unit: UnitRequest,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncMember {
+    pub subject: String,
+    #[serde(flatten)]
+    pub member: MemberRequest,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncRequest {
+    pub tenant_id: Option<String>,
+    #[serde(default)]
+    pub units: Vec<SyncUnit>,
+    #[serde(default)]
+    pub members: Vec<SyncMember>,
+    /// Remove units and members not in the request.
+    #[serde(default)]
+    pub prune: bool,
+}
+
+/// What changed in a sync.
+#[derive(Debug, Default, Serialize)]
+pub struct SyncResult {
+    pub units_changed: Vec<String>,
+    pub units_removed: Vec<String>,
+    pub members_changed: Vec<String>,
+    pub members_removed: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TenantQuery {
+    pub tenant_id: Option<String>,
+}
+
+/// Where a subject sits, as authorization sees it.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Membership {
+    pub unit: Option<String>,
+    pub department: Option<String>,
+    /// The subject's unit, then those above it.
+    pub units: Vec<String>,
+    /// Direct manager first.
+    pub managers: Vec<String>,
+}
+
+/// One tenant's units and members, as cached.
+struct Chart {
+    units: HashMap<String, Unit>,
+    members: HashMap<String, Member>,
+    memberships: Mutex<HashMap<String, Arc<Membership>>>,
+}
+
+impl Chart {
+    fn new(units: Vec<Unit>, members: Vec<Member>) -> Self {
+        Self {
+            units: units.into_iter().map(|unit| (unit.key.clone(), unit)).collect(),
+            members: members.into_iter().map(|member| (member.subject.clone(), member)).collect(),
+            memberships: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// `key` and the units above it.
+    fn lineage(&self, key: &str) -> Vec<&Unit> {
+        let mut lineage = Vec::new();
+        let mut next = Some(key);
+        while let Some(key) = next {
+            let Some(unit) = self.units.get(key) else { break };
+            if lineage.len() == MAX_DEPTH || lineage.iter().any(|u: &&Unit| u.key == unit.key) {
+                break;
+            }
+            lineage.push(unit);
+            next = unit.parent.as_deref();
+        }
+        lineage
+    }
+
+    fn direct_manager(&self, subject: &str) -> Option<String> {
+        let member = self.members.get(subject)?;
+        if let Some(manager) = &member.manager {
+            return Some(manager.clone());
+        }
+        self.lineage(&member.unit).into_iter()
+            .filter_map(|unit| unit.manager.as_ref())
+            .find(|manager| *manager != subject)
+            .cloned()
+    }
+
+    fn membership(&self, subject: &str) -> Arc<Membership> {
+        if let Some(membership) = self.memberships.lock().unwrap_or_else(|e| e.into_inner()).get(subject) {
+            return membership.clone();
+        }
+
+        let mut membership = Membership::default();
+        if let Some(member) = self.members.get(subject) {
+            let lineage = self.lineage(&member.unit);
+            membership.unit = Some(member.unit.clone());
+            membership.department = lineage.iter().find(|unit| unit.kind == "department").map(|unit| unit.key.clone());
+            membership.units = lineage.iter().map(|unit| unit.key.clone()).collect();
+            let mut seen = HashSet::from([subject.to_string()]);
+            let mut next = self.direct_manager(subject);
+            while let Some(manager) = next {
+                if membership.managers.len() == MAX_DEPTH || !seen.insert(manager.clone()) {
+                    break;
+                }
+                next = self.direct_manager(&manager);
+                membership.managers.push(manager);
+            }
+        }
+
+        let membership = Arc::new(membership);
+        self.memberships.lock().unwrap_or_else(|e| e.into_inner())
+            .insert(subject.to_string(), membership.clone());
+        membership
+    }
+
+    /// Parents that do not exist, cycles and members of missing units.
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for unit in self.units.values() {
+            if let Some(parent) = &unit.parent {
+                if !self.units.contains_key(parent) {
+                    problems.push(format!("unit {}: parent {} does not exist", unit.key, parent));
+                }
+            }
+            let lineage = self.lineage(&unit.key);
+            let top = lineage.last().and_then(|u| u.parent.as_deref());
+            if top.is_some_and(|parent| self.units.contains_key(parent)) {
+                problems.push(format!("unit {}: sits in a cycle or too deep a tree", unit.key));
+            }
+        }
+        for member in self.members.values() {
+            if !self.units.contains_key(&member.unit) {
+                problems.push(format!("member {}: unit {} does not exist", member.subject, member.unit));
+            }
+        }
+        problems.sort();
+        problems
+    }
+}
+
+fn validate_unit(key: &str, request: &UnitRequest) -> Result<(), SecurityError> {
+    if key.trim().is_empty() || key.len() > 200 || request.name.trim().is_empty() {
+        return Err(SecurityError::ValidationError("key and name are required, key at most 200 characters".to_string()));
+    }
+    if !UNIT_KINDS.contains(&request.kind.as_str()) {
+        return Err(SecurityError::ValidationError(format!("kind must be one of {}", UNIT_KINDS.join(", "))));
+    }
+    if request.parent.as_deref() == Some(key) {
+        return Err(SecurityError::ValidationError(format!("unit {} cannot be its own parent", key)));
+    }
+    Ok(())
+}
+
+fn validate_member(subject: &str, request: &MemberRequest) -> Result<(), SecurityError> {
+    if subject.trim().is_empty() || request.unit.trim().is_empty() {
+        return Err(SecurityError::ValidationError("subject and unit are required".to_string()));
+    }
+    if request.manager.as_deref() == Some(subject) {
+        return Err(SecurityError::ValidationError(format!("{} cannot manage themselves", subject)));
+    }
+    Ok(())
+}
+
+async fn load(tx: &mut Transaction<'_, Postgres>, tenant_id: Option<&str>) -> Result<Chart, SecurityError> {
+    let units = sqlx::query_as::<_, Unit>(&format!(
+        "SELECT {} FROM org_units WHERE tenant_id IS NOT DISTINCT FROM $1",
+        UNIT_COLUMNS
+    ))
+    .bind(tenant_id)
+    .fetch_all(&mut **tx)
+    .await?;
+    let members = sqlx::query_as::<_, Member>(&format!(
+        "SELECT {} FROM org_members WHERE tenant_id IS NOT DISTINCT FROM $1",
+        MEMBER_COLUMNS
+    ))
+    .bind(tenant_id)
+    .fetch_all(&mut **tx)
+    .await?;
+    Ok(Chart::new(units, members))
+}
+
+async fn upsert_unit(
+    tx: &mut Transaction<'_, Postgres>,
+    tenant_id: Option<&str>,
+    key: &str,
+    request: &UnitRequest,
+    actor: &str,
+    now: DateTime<Utc>,
+) -> Result<Unit, SecurityError> {
+    Ok(sqlx::query_as::<_, Unit>(&format!(
+        "INSERT INTO org_units (id, tenant_id, key, name, kind, parent, manager, updated_by, updated_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) \
+         ON CONFLICT (COALESCE(tenant_id, ''), key) DO UPDATE SET name = $4, kind = $5, parent = $6, manager = $7, \
+         updated_by = $8, updated_at = $9 RETURNING {}",
+        UNIT_COLUMNS
+    ))
+    .bind(Uuid::new_v4())
+    .bind(tenant_id)
+    .bind(key.trim())
+    .bind(request.name.trim())
+    .bind(&request.kind)
+    .bind(request.parent.as_deref().map(str::trim).filter(|p| !p.is_empty()))
+    .bind(request.manager.as_deref().map(str::trim).filter(|m| !m.is_empty()))
+    .bind(actor)
+    .bind(now)
+    .fetch_one(&mut **tx)
+    .await?)
+}
+
+async fn upsert_member(
+    tx: &mut Transaction<'_, Postgres>,
+    tenant_id: Option<&str>,
+    subject: &str,
+    request: &MemberRequest,
+    actor: &str,
+    now: DateTime<Utc>,
+) -> Result<Member, SecurityError> {
+    let member = sqlx::query_as::<_, Member>(&format!(
+        "INSERT INTO org_members (subject, tenant_id, unit, manager, updated_by, updated_at) \
+         VALUES ($1, $2, $3, $4, $5, $6) \
+         ON CONFLICT (subject) DO UPDATE SET unit = $3, manager = $4, updated_by = $5, updated_at = $6 \
+         WHERE org_members.tenant_id IS NOT DISTINCT FROM $2 RETURNING {}",
+        MEMBER_COLUMNS
+    ))
+    .bind(subject)
+    .bind(tenant_id)
+    .bind(request.unit.trim())
+    .bind(request.manager.as_deref().map(str::trim).filter(|m| !m.is_empty()))
+    .bind(actor)
+    .bind(now)
+    .fetch_optional(&mut **tx)
+    .await?;
+    member.ok_or_else(|| SecurityError::Conflict(format!("{} belongs to another tenant's chart", subject)))
+}
+
+/// Refuse a chart left with dangling references or cycles.
+fn check(chart: &Chart) -> Result<(), SecurityError> {
+    let problems = chart.problems();
+    if !problems.is_empty() {
+        return Err(SecurityError::ValidationError(problems.join("; ")));
+    }
+    Ok(())
+}
+
+type CachedChart = (DateTime<Utc>, Arc<Chart>);
+
+pub struct OrgService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: OrgConfig,
+    charts: RwLock<HashMap<Option<String>, CachedChart>>,
+}
+
+impl OrgService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        info!("Org service initialized (charts cached for {}s)", config.org.cache_ttl_secs);
+        Ok(Self {
+            storage,
+            clock,
+            config: config.org.clone(),
+            charts: RwLock::new(HashMap::new()),
+        })
+    }
+
+    async fn chart(&self, tenant_id: Option<&str>) -> Result<Arc<Chart>, SecurityError> {
+        let now = self.clock.now();
+        let key = tenant_id.map(str::to_string);
+        if let Some((expires_at, chart)) = self.charts.read().unwrap_or_else(|e| e.into_inner()).get(&key) {
+            if *expires_at > now {
+                return Ok(chart.clone());
+            }
+        }
+
+        let mut tx = self.storage.begin().await?;
+        let chart = Arc::new(load(&mut tx, tenant_id).await?);
+        tx.commit().await?;
+        self.charts.write().unwrap_or_else(|e| e.into_inner())
+            .insert(key, (now + Duration::seconds(self.config.cache_ttl_secs as i64), chart.clone()));
+        Ok(chart)
+    }
+
+    fn invalidate(&self, tenant_id: Option<&str>) {
+        self.charts.write().unwrap_or_else(|e| e.into_inner()).remove(&tenant_id.map(str::to_string));
+    }
+
+    pub async fn membership(&self, tenant_id: Option<&str>, subject: &str) -> Result<Arc<Membership>, SecurityError> {
+        Ok(self.chart(tenant_id).await?.membership(subject))
+    }
+
+    /// Add `subject`'s membership to `attributes` as `<prefix>department`,
+    /// `<prefix>units` and `<prefix>managers`, leaving any already given.
+    pub async fn enrich(
+        &self,
+        tenant_id: Option<&str>,
+        subject: &str,
+        prefix: &str,
+        attributes: &mut Map<String, Value>,
+    ) -> Result<(), SecurityError> {
+        let membership = self.membership(tenant_id, subject).await?;
+        if let Some(department) = &membership.department {
+            attributes.entry(format!("{}department", prefix)).or_insert_with(|| Value::from(department.as_str()));
+        }
+        attributes.entry(format!("{}units", prefix)).or_insert_with(|| Value::from(membership.units.clone()));
+        attributes.entry(format!("{}managers", prefix)).or_insert_with(|| Value::from(membership.managers.clone()));
+        Ok(())
+    }
+
+    pub async fn units(&self, tenant_id: Option<&str>) -> Result<Vec<Unit>, SecurityError> {
+        let mut units: Vec<Unit> = self.chart(tenant_id).await?.units.values().cloned().collect();
+        units.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(units)
+    }
+
+    pub async fn member(&self, tenant_id: Option<&str>, subject: &str) -> Result<(Member, Arc<Membership>), SecurityError> {
+        let chart = self.chart(tenant_id).await?;
+        let member = chart.members.get(subject).cloned()
+            .ok_or_else(|| SecurityError::NotFound(format!("{} is not in the chart", subject)))?;
+        Ok((member, chart.membership(subject)))
+    }
+
+    pub async fn put_unit(&self, tenant_id: Option<&str>, key: &str, request: &UnitRequest, actor: &str) -> Result<Unit, SecurityError> {
+        validate_unit(key, request)?;
+        let mut tx = self.storage.begin().await?;
+        let unit = upsert_unit(&mut tx, tenant_id, key, request, actor, self.clock.now()).await?;
+        check(&load(&mut tx, tenant_id).await?)?;
+        let event = DomainEvent::new("org.unit.updated", "org_unit", &unit.key, unit.tenant_id.clone(), serde_json::to_value(&unit).unwrap_or_default(), self.clock.now());
+        events::enqueue(&mut tx, &event).await?;
+        tx.commit().await?;
+        self.invalidate(tenant_id);
+        Ok(unit)
+    }
+
+    /// Remove a unit nothing sits under.
+    pub async fn delete_unit(&self, tenant_id: Option<&str>, key: &str) -> Result<Unit, SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let unit = sqlx::query_as::<_, Unit>(&format!(
+            "DELETE FROM org_units WHERE tenant_id IS NOT DISTINCT FROM $1 AND key = $2 RETURNING {}",
+            UNIT_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(key)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("Unit {} not found", key)))?;
+        if !load(&mut tx, tenant_id).await?.problems().is_empty() {
+            return Err(SecurityError::Conflict(format!("Units or members still sit under {}", key)));
+        }
+        let event = DomainEvent::new("org.unit.deleted", "org_unit", &unit.key, unit.tenant_id.clone(), serde_json::json!({}), self.clock.now());
+        events::enqueue(&mut tx, &event).await?;
+        tx.commit().await?;
+        self.invalidate(tenant_id);
+        Ok(unit)
+    }
+
+    pub async fn put_member(&self, tenant_id: Option<&str>, subject: &str, request: &MemberRequest, actor: &str) -> Result<Member, SecurityError> {
+        validate_member(subject, request)?;
+        let mut tx = self.storage.begin().await?;
+        let member = upsert_member(&mut tx, tenant_id, subject, request, actor, self.clock.now()).await?;
+        check(&load(&mut tx, tenant_id).await?)?;
+        let event = DomainEvent::new("org.member.updated", "org_member", subject, member.tenant_id.clone(), serde_json::json!({
+            "unit": member.unit,
+            "manager": member.manager
+        }), self.clock.now());
+        events::enqueue(&mut tx, &event).await?;
+        tx.commit().await?;
+        self.invalidate(tenant_id);
+        Ok(member)
+    }
+
+    pub async fn remove_member(&self, tenant_id: Option<&str>, subject: &str) -> Result<Member, SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let member = sqlx::query_as::<_, Member>(&format!(
+            "DELETE FROM org_members WHERE tenant_id IS NOT DISTINCT FROM $1 AND subject = $2 RETURNING {}",
+            MEMBER_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(subject)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("{} is not in the chart", subject)))?;
+        let event = DomainEvent::new("org.member.removed", "org_member", subject, member.tenant_id.clone(), serde_json::json!({
+            "unit": member.unit
+        }), self.clock.now());
+        events::enqueue(&mut tx, &event).await?;
+        tx.commit().await?;
+        self.invalidate(tenant_id);
+        Ok(member)
+    }
+
+    /// Bring a tenant's chart in line with `request` in one transaction.
+    pub async fn sync(&self, tenant_id: Option<&str>, request: &SyncRequest, actor: &str) -> Result<SyncResult, SecurityError> {
+        for unit in &request.units {
+            validate_unit(&unit.key, &unit.unit)?;
+        }
+        for member in &request.members {
+            validate_member(&member.subject, &member.member)?;
+        }
+
+        let now = self.clock.now();
+        let mut tx = self.storage.begin().await?;
+        let before = load(&mut tx, tenant_id).await?;
+        let mut result = SyncResult::default();
+        for unit in &request.units {
+            let synced = upsert_unit(&mut tx, tenant_id, &unit.key, &unit.unit, actor, now).await?;
+            let changed = match before.units.get(&synced.key) {
+                Some(old) => (&old.name, &old.kind, &old.parent, &old.manager) != (&synced.name, &synced.kind, &synced.parent, &synced.manager),
+                None => true,
+            };
+            if changed {
+                result.units_changed.push(synced.key);
+            }
+        }
+        for member in &request.members {
+            let synced = upsert_member(&mut tx, tenant_id, &member.subject, &member.member, actor, now).await?;
+            let changed = match before.members.get(&synced.subject) {
+                Some(old) => (&old.unit, &old.manager) != (&synced.unit, &synced.manager),
+                None => true,
+            };
+            if changed {
+                result.members_changed.push(synced.subject);
+            }
+        }
+        if request.prune {
+            let keys: Vec<String> = request.units.iter().map(|u| u.key.trim().to_string()).collect();
+            let subjects: Vec<String> = request.members.iter().map(|m| m.subject.clone()).collect();
+            result.members_removed = sqlx::query_scalar(
+                "DELETE FROM org_members WHERE tenant_id IS NOT DISTINCT FROM $1 AND NOT subject = ANY($2) RETURNING subject",
+            )
+            .bind(tenant_id)
+            .bind(&subjects)
+            .fetch_all(&mut *tx)
+            .await?;
+            result.units_removed = sqlx::query_scalar(
+                "DELETE FROM org_units WHERE tenant_id IS NOT DISTINCT FROM $1 AND NOT key = ANY($2) RETURNING key",
+            )
+            .bind(tenant_id)
+            .bind(&keys)
+            .fetch_all(&mut *tx)
+            .await?;
+        }
+        check(&load(&mut tx, tenant_id).await?)?;
+
+        let event = DomainEvent::new(
+            "org.synced",
+            "org_chart",
+            tenant_id.unwrap_or("platform"),
+            tenant_id.map(str::to_string),
+            serde_json::to_value(&result).unwrap_or_default(),
+            self.clock.now(),
+        );
+        events::enqueue(&mut tx, &event).await?;
+        tx.commit().await?;
+        self.invalidate(tenant_id);
+        Ok(result)
+    }
+
+    /// The unit a SCIM department names, by key or name, created as a
+    /// department when there is none.
+    async fn department_unit(&self, tenant_id: Option<&str>, department: &str, actor: &str) -> Result<String, SecurityError> {
+        let chart = self.chart(tenant_id).await?;
+        let found = chart.units.get(department)
+            .or_else(|| chart.units.values().find(|unit| unit.name == department));
+        if let Some(unit) = found {
+            return Ok(unit.key.clone());
+        }
+        let request = UnitRequest { name: department.to_string(), kind: "department".to_string(), parent: None, manager: None };
+        Ok(self.put_unit(tenant_id, department, &request, actor).await?.key)
+    }
+
+    fn scim_subject(&self, id: &str) -> String {
+        format!("{}{}", self.config.scim_subject_prefix, id)
+    }
+
+    fn scim_user(&self, member: &Member) -> Value {
+        let id = member.subject.strip_prefix(&self.config.scim_subject_prefix).unwrap_or(&member.subject);
+        let manager = member.manager.as_deref()
+            .map(|m| m.strip_prefix(&self.config.scim_subject_prefix).unwrap_or(m));
+        serde_json::json!({
+            "schemas": [SCIM_USER, SCIM_ENTERPRISE],
+            "id": id,
+            "userName": id,
+            "active": true,
+            SCIM_ENTERPRISE: {
+                "department": member.unit,
+                "manager": manager.map(|value| serde_json::json!({ "value": value }))
+            },
+            "meta": { "resourceType": "User", "lastModified": member.updated_at }
+        })
+    }
+
+    /// Apply a SCIM user. Inactive users and users without a department
+    /// leave the chart; returns `None` for those.
+    pub async fn scim_put(&self, tenant_id: Option<&str>, id: &str, user: &Value, actor: &str) -> Result<Option<Value>, SecurityError> {
+        if id.trim().is_empty() {
+            return Err(SecurityError::ValidationError("userName is required".to_string()));
+        }
+        let subject = self.scim_subject(id);
+        let enterprise = &user[SCIM_ENTERPRISE];
+        let department = enterprise["department"].as_str().map(str::trim).filter(|d| !d.is_empty());
+        let active = user["active"].as_bool().unwrap_or(true);
+        let Some(department) = department.filter(|_| active) else {
+            match self.remove_member(tenant_id, &subject).await {
+                Ok(_) | Err(SecurityError::NotFound(_)) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        };
+
+        let unit = self.department_unit(tenant_id, department, actor).await?;
+        let manager = enterprise["manager"]["value"].as_str().map(|m| self.scim_subject(m));
+        let member = self.put_member(tenant_id, &subject, &MemberRequest { unit, manager }, actor).await?;
+        Ok(Some(self.scim_user(&member)))
+    }
+
+    pub async fn scim_get(&self, tenant_id: Option<&str>, id: &str) -> Result<Value, SecurityError> {
+        let (member, _) = self.member(tenant_id, &self.scim_subject(id)).await?;
+        Ok(self.scim_user(&member))
+    }
+
+    pub async fn scim_delete(&self, tenant_id: Option<&str>, id: &str) -> Result<(), SecurityError> {
+        self.remove_member(tenant_id, &self.scim_subject(id)).await.map(|_| ())
+    }
+}
+
+/// The tenant a change applies to: the caller's own, or for platform
+/// callers the one asked for.
+fn tenant_of(principal: &Principal, requested: Option<&str>) -> Result<Option<String>, SecurityError> {
+    match (&principal.tenant_id, requested) {
+        (Some(own), Some(requested)) if own != requested => Err(SecurityError::AccessDenied(format!(
+            "{} can only change its own tenant's chart",
+            principal.subject
+        ))),
+        (Some(own), _) => Ok(Some(own.clone())),
+        (None, requested) => Ok(requested.map(str::to_string)),
+    }
+}
+
+async fn audit(state: &AppState, req: &HttpRequest, principal: &Principal, tenant_id: Option<String>, action: &str, resource: String, payload: Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id,
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(req),
+        action: action.to_string(),
+        resource: resource.clone(),
+        outcome: "success".to_string(),
+        payload,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} of {}: {:?}", action, resource, e);
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::AccessDenied(msg) => HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Org operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Org operation failed"
+            }))
+        }
+    }
+}
+
+/// SCIM clients expect errors in SCIM's own shape (RFC 7644 section 3.12).
+fn scim_error_response(e: SecurityError) -> HttpResponse {
+    let (status, detail) = match e {
+        SecurityError::ValidationError(msg) => (400, msg),
+        SecurityError::NotFound(msg) => (404, msg),
+        SecurityError::Conflict(msg) => (409, msg),
+        SecurityError::AccessDenied(msg) | SecurityError::AuthError(msg) => (403, msg),
+        e => {
+            error!("SCIM operation failed: {:?}", e);
+            (500, "SCIM operation failed".to_string())
+        }
+    };
+    let status = actix_web::http::StatusCode::from_u16(status).unwrap_or(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR);
+    HttpResponse::build(status)
+        .content_type("application/scim+json")
+        .json(serde_json::json!({ "schemas": [SCIM_ERROR], "status": status.as_str(), "detail": detail }))
+}
+
+fn authorize_sync(state: &AppState, req: &HttpRequest) -> Result<Principal, SecurityError> {
+    authorize_scope(state, req, &state.config.org.sync_scope)
+}
+
+pub async fn units_handler(req: HttpRequest, query: web::Query<TenantQuery>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match authorize_sync(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let tenant_id = match tenant_of(&principal, query.tenant_id.as_deref()) {
+        Ok(tenant_id) => tenant_id,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.org.units(tenant_id.as_deref()).await {
+        Ok(units) => Ok(HttpResponse::Ok().json(serde_json::json!({ "units": units }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn put_unit_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TenantQuery>,
+    request: web::Json<UnitRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_sync(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let tenant_id = match tenant_of(&principal, query.tenant_id.as_deref()) {
+        Ok(tenant_id) => tenant_id,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.org.put_unit(tenant_id.as_deref(), &path, &request, &principal.subject).await {
+        Ok(unit) => {
+            audit(&state, &req, &principal, tenant_id, "org.unit.update", format!("org_unit:{}", unit.key),
+                serde_json::to_value(&unit).unwrap_or_default()).await;
+            Ok(HttpResponse::Ok().json(unit))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn delete_unit_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TenantQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_sync(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let tenant_id = match tenant_of(&principal, query.tenant_id.as_deref()) {
+        Ok(tenant_id) => tenant_id,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.org.delete_unit(tenant_id.as_deref(), &path).await {
+        Ok(unit) => {
+            audit(&state, &req, &principal, tenant_id, "org.unit.delete", format!("org_unit:{}", unit.key),
+                serde_json::json!({ "name": unit.name })).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_member_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TenantQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_sync(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let tenant_id = match tenant_of(&principal, query.tenant_id.as_deref()) {
+        Ok(tenant_id) => tenant_id,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.org.member(tenant_id.as_deref(), &path).await {
+        Ok((member, membership)) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "member": member,
+            "effective": *membership
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn put_member_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TenantQuery>,
+    request: web::Json<MemberRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_sync(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let tenant_id = match tenant_of(&principal, query.tenant_id.as_deref()) {
+        Ok(tenant_id) => tenant_id,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.org.put_member(tenant_id.as_deref(), &path, &request, &principal.subject).await {
+        Ok(member) => {
+            audit(&state, &req, &principal, tenant_id, "org.member.update", format!("subject:{}", member.subject),
+                serde_json::json!({ "unit": member.unit, "manager": member.manager })).await;
+            Ok(HttpResponse::Ok().json(member))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn remove_member_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TenantQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_sync(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let tenant_id = match tenant_of(&principal, query.tenant_id.as_deref()) {
+        Ok(tenant_id) => tenant_id,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.org.remove_member(tenant_id.as_deref(), &path).await {
+        Ok(member) => {
+            audit(&state, &req, &principal, tenant_id, "org.member.remove", format!("subject:{}", member.subject),
+                serde_json::json!({ "unit": member.unit })).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn sync_handler(req: HttpRequest, request: web::Json<SyncRequest>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match authorize_sync(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let tenant_id = match tenant_of(&principal, request.tenant_id.as_deref()) {
+        Ok(tenant_id) => tenant_id,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.org.sync(tenant_id.as_deref(), &request, &principal.subject).await {
+        Ok(result) => {
+            audit(&state, &req, &principal, tenant_id, "org.sync", "org_chart".to_string(),
+                serde_json::to_value(&result).unwrap_or_default()).await;
+            Ok(HttpResponse::Ok().json(result))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+async fn scim_put(req: HttpRequest, id: Option<String>, user: Value, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match authorize_sync(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(scim_error_response(e)),
+    };
+    let tenant_id = principal.tenant_id.clone();
+    let created = id.is_none();
+    let id = id.or_else(|| user["userName"].as_str().map(str::to_string)).unwrap_or_default();
+    match state.org.scim_put(tenant_id.as_deref(), &id, &user, &principal.subject).await {
+        Ok(scim_user) => {
+            audit(&state, &req, &principal, tenant_id, "org.scim.user", format!("subject:{}", state.org.scim_subject(&id)),
+                serde_json::json!({ "in_chart": scim_user.is_some() })).await;
+            // Users outside the chart are still acknowledged, as SCIM clients expect
+            let body = scim_user.unwrap_or_else(|| serde_json::json!({
+                "schemas": [SCIM_USER],
+                "id": id,
+                "userName": id,
+                "active": user["active"].as_bool().unwrap_or(true)
+            }));
+            let mut response = if created { HttpResponse::Created() } else { HttpResponse::Ok() };
+            Ok(response.content_type("application/scim+json").json(body))
+        }
+        Err(e) => Ok(scim_error_response(e)),
+    }
+}
+
+pub async fn scim_create_handler(req: HttpRequest, user: web::Json<Value>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    scim_put(req, None, user.into_inner(), state).await
+}
+
+pub async fn scim_replace_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    user: web::Json<Value>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    scim_put(req, Some(path.into_inner()), user.into_inner(), state).await
+}
+
+pub async fn scim_get_handler(req: HttpRequest, path: web::Path<String>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match authorize_sync(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(scim_error_response(e)),
+    };
+    match state.org.scim_get(principal.tenant_id.as_deref(), &path).await {
+        Ok(user) => Ok(HttpResponse::Ok().content_type("application/scim+json").json(user)),
+        Err(e) => Ok(scim_error_response(e)),
+    }
+}
+
+pub async fn scim_delete_handler(req: HttpRequest, path: web::Path<String>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match authorize_sync(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(scim_error_response(e)),
+    };
+    let tenant_id = principal.tenant_id.clone();
+    match state.org.scim_delete(tenant_id.as_deref(), &path).await {
+        Ok(()) => {
+            audit(&state, &req, &principal, tenant_id, "org.scim.user.delete", format!("subject:{}", state.org.scim_subject(&path)),
+                serde_json::json!({})).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(scim_error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/org")
+            .route("/units", web::get().to(units_handler))
+            .route("/units/{key}", web::put().to(put_unit_handler))
+            .route("/units/{key}", web::delete().to(delete_unit_handler))
+            .route("/members/{subject}", web::get().to(get_member_handler))
+            .route("/members/{subject}", web::put().to(put_member_handler))
+            .route("/members/{subject}", web::delete().to(remove_member_handler))
+            .route("/sync", web::post().to(sync_handler)),
+    )
+    .service(
+        web::scope("/scim/v2/Users")
+            .route("", web::post().to(scim_create_handler))
+            .route("/{id}", web::get().to(scim_get_handler))
+            .route("/{id}", web::put().to(scim_replace_handler))
+            .route("/{id}", web::delete().to(scim_delete_handler)),
+    );
+}
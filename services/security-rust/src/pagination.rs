@@ -0,0 +1,358 @@
+/*!
+Pagination Module
+Shared cursor, sorting and limit conventions for list endpoints
+*/
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::errors::SecurityError;
+
+pub const DEFAULT_LIMIT: i64 = 50;
+pub const MAX_LIMIT: i64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    fn sql(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+
+    fn comparator(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => ">",
+            SortOrder::Desc => "<",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyKind {
+    Timestamp,
+    Text,
+}
+
+/// A column clients may sort by. Every sort is tie-broken on `id` so pages are stable.
+#[derive(Debug)]
+pub struct SortField {
+    pub name: &'static str,
+    pub column: &'static str,
+    pub kind: KeyKind,
+}
+
+/// Sort key of a row, as recorded in the cursor.
+pub enum SortKey {
+    Timestamp(DateTime<Utc>),
+    Text(String),
+}
+
+impl SortKey {
+    fn encode(&self) -> String {
+        match self {
+            SortKey::Timestamp(at) => at.to_rfc3339_opts(SecondsFormat::Micros, true),
+            SortKey::Text(text) => text.clone(),
+        }
+    }
+}
+
+/// Query parameters accepted by every list endpoint.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct PageParams {
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+    pub sort: Option<String>,
+    pub order: Option<SortOrder>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Cursor {
+    #[serde(rename = "s")]
+    sort: String,
+    #[serde(rename = "o")]
+    order: SortOrder,
+    #[serde(rename = "k")]
+    key: String,
+    id: Uuid,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).expect("cursor serializes");
+        base64::encode_config(json, base64::URL_SAFE_NO_PAD)
+    }
+
+    fn decode(value: &str) -> Result<Self, SecurityError> {
+        let invalid = || SecurityError::ValidationError("Invalid cursor".to_string());
+        let json = base64::decode_config(value, base64::URL_SAFE_NO_PAD).map_err(|_| invalid())?;
+        serde_json::from_slice(&json).map_err(|_| invalid())
+    }
+}
+
+/// Resolved paging request for one endpoint.
+#[derive(Debug)]
+pub struct PageRequest {
+    field: &'static SortField,
+    order: SortOrder,
+    limit: i64,
+    after: Option<Cursor>,
+}
+
+impl PageParams {
+    /// Validate against the endpoint's sortable fields; the first field is the default sort.
+    pub fn resolve(
+        &self,
+        fields: &'static [SortField],
+        default_order: SortOrder,
+    ) -> Result<PageRequest, SecurityError> {
+        let after = self.cursor.as_deref().map(Cursor::decode).transpose()?;
+
+        let requested = self.sort.as_deref()
+            .or_else(|| after.as_ref().map(|c| c.sort.as_str()))
+            .unwrap_or(fields[0].name);
+        let field = fields.iter()
+            .find(|f| f.name == requested)
+            .ok_or_else(|| SecurityError::ValidationError(format!("Cannot sort by '{}'", requested)))?;
+        let order = self.order
+            .or_else(|| after.as_ref().map(|c| c.order))
+            .unwrap_or(default_order);
+
+        if let Some(cursor) = &after {
+            if cursor.sort != field.name || cursor.order != order {
+                return Err(SecurityError::ValidationError(
+                    "Cursor does not match the requested sort".to_string(),
+                ));
+            }
+            if field.kind == KeyKind::Timestamp && DateTime::parse_from_rfc3339(&cursor.key).is_err() {
+                return Err(SecurityError::ValidationError("Invalid cursor".to_string()));
+            }
+        }
+
+        Ok(PageRequest {
+            field,
+            order,
+            limit: self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+            after,
+        })
+    }
+}
+
+impl PageRequest {
+    /// Append ` AND <keyset condition>` when resuming from a cursor.
+    pub fn push_after(&self, builder: &mut QueryBuilder<'static, Postgres>) {
+        let Some(cursor) = &self.after else {
+            return;
+        };
+
+        builder.push(format!(" AND ({}, id) {} (", self.field.column, self.order.comparator()));
+        match self.field.kind {
+            KeyKind::Timestamp => {
+                let at = DateTime::parse_from_rfc3339(&cursor.key)
+                    .expect("validated in resolve")
+                    .with_timezone(&Utc);
+                builder.push_bind(at);
+            }
+            KeyKind::Text => {
+                builder.push_bind(cursor.key.clone());
+            }
+        }
+        builder.push(", ").push_bind(cursor.id).push(")");
+    }
+
+    /// Append ordering and a limit one past the page size, to detect further pages.
+    pub fn push_order_limit(&self, builder: &mut QueryBuilder<'static, Postgres>) {
+        let direction = self.order.sql();
+        builder.push(format!(
+            " ORDER BY {} {}, id {} LIMIT ",
+            self.field.column, direction, direction
+        ));
+        builder.push_bind(self.limit + 1);
+    }
+
+    /// Trim the look-ahead row and build the cursor for the next page. `key`
+    /// returns the row's value for the named sort field and its id.
+    pub fn page<T>(&self, mut rows: Vec<T>, key: impl Fn(&T, &str) -> (SortKey, Uuid)) -> Page<T> {
+        let has_more = rows.len() as i64 > self.limit;
+        rows.truncate(self.limit as usize);
+
+        let next_cursor = if has_more {
+            rows.last().map(|row| {
+                let (sort_key, id) = key(row, self.field.name);
+                Cursor {
+                    sort: self.field.name.to_string(),
+                    order: self.order,
+                    key: sort_key.encode(),
+                    id,
+                }
+                .encode()
+            })
+        } else {
+            None
+        };
+
+        Page {
+            items: rows,
+            info: PageInfo {
+                next_cursor,
+                limit: self.limit,
+                sort: self.field.name,
+                order: self.order,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PageInfo {
+    pub next_cursor: Option<String>,
+    pub limit: i64,
+    pub sort: &'static str,
+    pub order: SortOrder,
+}
+
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub info: PageInfo,
+}
+
+impl<T> Page<T> {
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            info: self.info,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    static FIELDS: &[SortField] = &[
+        SortField { name: "created_at", column: "created_at", kind: KeyKind::Timestamp },
+        SortField { name: "name", column: "lower(name)", kind: KeyKind::Text },
+    ];
+
+    struct Row {
+        id: Uuid,
+        name: String,
+        created_at: DateTime<Utc>,
+    }
+
+    fn rows(n: usize) -> Vec<Row> {
+        (0..n)
+            .map(|i| Row {
+                id: Uuid::from_u128(i as u128 + 1),
+                name: format!("row {}", i),
+                created_at: Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap() + chrono::Duration::minutes(i as i64),
+            })
+            .collect()
+    }
+
+    fn key(row: &Row, field: &str) -> (SortKey, Uuid) {
+        match field {
+            "name" => (SortKey::Text(row.name.clone()), row.id),
+            _ => (SortKey::Timestamp(row.created_at), row.id),
+        }
+    }
+
+    fn params(limit: Option<i64>, cursor: Option<String>, sort: Option<&str>, order: Option<SortOrder>) -> PageParams {
+        PageParams { limit, cursor, sort: sort.map(String::from), order }
+    }
+
+    fn sql(request: &PageRequest) -> String {
+        let mut builder = QueryBuilder::new("SELECT * FROM t WHERE true");
+        request.push_after(&mut builder);
+        request.push_order_limit(&mut builder);
+        builder.sql().to_string()
+    }
+
+    #[test]
+    fn defaults_and_limits() {
+        let request = PageParams::default().resolve(FIELDS, SortOrder::Desc).unwrap();
+        assert_eq!(request.field.name, "created_at");
+        assert_eq!(request.order, SortOrder::Desc);
+        assert_eq!(request.limit, DEFAULT_LIMIT);
+        assert_eq!(sql(&request), "SELECT * FROM t WHERE true ORDER BY created_at DESC, id DESC LIMIT $1");
+
+        let clamp = |limit| params(Some(limit), None, None, None).resolve(FIELDS, SortOrder::Asc).unwrap().limit;
+        assert_eq!(clamp(0), 1);
+        assert_eq!(clamp(-5), 1);
+        assert_eq!(clamp(MAX_LIMIT + 1), MAX_LIMIT);
+    }
+
+    #[test]
+    fn unknown_sorts_are_refused() {
+        let error = params(None, None, Some("password"), None).resolve(FIELDS, SortOrder::Asc).unwrap_err();
+        assert!(matches!(error, SecurityError::ValidationError(message) if message == "Cannot sort by 'password'"));
+    }
+
+    #[test]
+    fn cursors_resume_after_the_last_row() {
+        let request = params(Some(2), None, Some("name"), Some(SortOrder::Asc)).resolve(FIELDS, SortOrder::Desc).unwrap();
+        let page = request.page(rows(3), key);
+        assert_eq!(page.items.len(), 2);
+        assert_eq!(page.info.sort, "name");
+        let cursor = page.info.next_cursor.expect("a further page");
+
+        // The cursor carries the sort, so the next request need not repeat it
+        let next = params(Some(2), Some(cursor), None, None).resolve(FIELDS, SortOrder::Desc).unwrap();
+        assert_eq!(next.field.name, "name");
+        assert_eq!(next.order, SortOrder::Asc);
+        assert_eq!(next.after.as_ref().unwrap().key, "row 1");
+        assert_eq!(next.after.as_ref().unwrap().id, Uuid::from_u128(2));
+        assert_eq!(
+            sql(&next),
+            "SELECT * FROM t WHERE true AND (lower(name), id) > ($1, $2) ORDER BY lower(name) ASC, id ASC LIMIT $3"
+        );
+    }
+
+    #[test]
+    fn the_last_page_has_no_cursor() {
+        let request = params(Some(3), None, None, None).resolve(FIELDS, SortOrder::Desc).unwrap();
+        let page = request.page(rows(3), key).map(|row| row.name);
+        assert_eq!(page.items, vec!["row 0", "row 1", "row 2"]);
+        assert!(page.info.next_cursor.is_none());
+    }
+
+    #[test]
+    fn timestamp_cursors_keep_microseconds() {
+        let request = params(Some(1), None, None, Some(SortOrder::Desc)).resolve(FIELDS, SortOrder::Desc).unwrap();
+        let mut rows = rows(2);
+        rows[0].created_at += chrono::Duration::microseconds(123_456);
+        let cursor = request.page(rows, key).info.next_cursor.unwrap();
+        let next = params(None, Some(cursor), None, None).resolve(FIELDS, SortOrder::Asc).unwrap();
+        assert_eq!(next.after.as_ref().unwrap().key, "2026-01-01T00:00:00.123456Z");
+        assert!(sql(&next).contains(" AND (created_at, id) < ($1, $2)"));
+    }
+
+    #[test]
+    fn cursors_must_match_the_sort() {
+        let request = params(Some(1), None, Some("name"), Some(SortOrder::Asc)).resolve(FIELDS, SortOrder::Asc).unwrap();
+        let cursor = request.page(rows(2), key).info.next_cursor.unwrap();
+        for (sort, order) in [(Some("created_at"), None), (None, Some(SortOrder::Desc))] {
+            let error = params(None, Some(cursor.clone()), sort, order).resolve(FIELDS, SortOrder::Asc).unwrap_err();
+            assert!(error.to_string().contains("Cursor does not match the requested sort"));
+        }
+    }
+
+    #[test]
+    fn tampered_cursors_are_refused() {
+        let forged = Cursor { sort: "created_at".to_string(), order: SortOrder::Asc, key: "yesterday".to_string(), id: Uuid::nil() };
+        for cursor in [forged.encode(), "not base64!".to_string(), base64::encode_config("{}", base64::URL_SAFE_NO_PAD)] {
+            let error = params(None, Some(cursor), None, None).resolve(FIELDS, SortOrder::Asc).unwrap_err();
+            assert!(error.to_string().contains("Invalid cursor"));
+        }
+    }
+}
@@ -0,0 +1,583 @@
+/*!
+PostgreSQL Encryption Proxy
+Field encryption on the PostgreSQL wire protocol, for services that cannot change
+
+Legacy services point their database URL at `PGPROXY_PORT` instead of the
+database; the proxy relays the session to `PGPROXY_UPSTREAM` and encrypts
+the columns listed in `PGPROXY_COLUMNS` (`table.column`) on the way in and
+decrypts them on the way out, under `CryptoService` keys
+(`PGPROXY_KEY_ID`, or the active key).
+
+Writes: parameters bound to an encrypted column (extended protocol) and
+plain string literals written to one (either protocol) are replaced by
+`cotai:pg1:<key id>:<nonce>:<ciphertext>`, base64 inside. Which values
+those are is worked out from the statement text (see `sql`). A write the
+proxy cannot make safe (a computed value, `INSERT ... SELECT`, `COPY`
+into the table, ...) is refused: the statement is swapped for one the
+server rejects as a syntax error naming `cotai_pgproxy_refused`, so the
+error arrives in order and the session carries on.
+
+Reads: any value in a result row carrying the prefix is decrypted,
+whatever the query, so aliases, joins and `RETURNING` work. A value that
+no longer decrypts (its key destroyed, say) is passed on as stored.
+
+Limits: encrypted columns must be `text` (or wide enough `varchar`); they
+cannot be searched, compared or indexed, as encryption is randomized;
+`COPY ... TO` output is not decrypted; the client encoding must be UTF-8
+for statements touching them. The upstream connection is plaintext, so
+run the proxy next to the database. Authentication is the database's own:
+whoever can log in through the proxy reads plaintext, so only the services
+it fronts should reach the port.
+
+Sessions run on a runtime of their own, at most `PGPROXY_MAX_CONNECTIONS`
+at a time. At shutdown open sessions are closed, as a database restart
+would.
+*/
+
+use actix_web::web;
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{oneshot, Semaphore};
+use tracing::{debug, error, info, warn};
+
+use crate::errors::SecurityError;
+use crate::AppState;
+
+mod sql;
+
+pub use sql::Columns;
+
+const PREFIX: &str = "cotai:pg1:";
+const SSL_REQUEST: i32 = 80877103;
+const GSSENC_REQUEST: i32 = 80877104;
+const CANCEL_REQUEST: i32 = 80877102;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Reads the fields of one message body.
+struct Fields<'a> {
+    body: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn new(body: &'a [u8]) -> Self {
+        Self { body, pos: 0 }
+    }
+
+    fn bytes(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let bytes = self.body.get(self.pos..self.pos + n).ok_or_else(|| invalid("truncated message"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn i16(&mut self) -> io::Result<i16> {
+        let bytes = self.bytes(2)?;
+        Ok(i16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        let bytes = self.bytes(4)?;
+        Ok(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// A NUL-terminated string, without the NUL.
+    fn cstr(&mut self) -> io::Result<&'a [u8]> {
+        let len = self.body[self.pos..].iter().position(|&b| b == 0).ok_or_else(|| invalid("unterminated string"))?;
+        let s = self.bytes(len)?;
+        self.pos += 1;
+        Ok(s)
+    }
+
+    /// A length-prefixed value; `None` for SQL NULL.
+    fn value(&mut self) -> io::Result<Option<&'a [u8]>> {
+        match self.i32()? {
+            -1 => Ok(None),
+            len if len < 0 => Err(invalid("negative value length")),
+            len => self.bytes(len as usize).map(Some),
+        }
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.body[self.pos..]
+    }
+}
+
+fn push_cstr(out: &mut Vec<u8>, s: &[u8]) {
+    out.extend_from_slice(s);
+    out.push(0);
+}
+
+fn push_value(out: &mut Vec<u8>, value: Option<&[u8]>) {
+    match value {
+        None => out.extend_from_slice(&(-1i32).to_be_bytes()),
+        Some(value) => {
+            out.extend_from_slice(&(value.len() as i32).to_be_bytes());
+            out.extend_from_slice(value);
+        }
+    }
+}
+
+async fn read_body<R: AsyncRead + Unpin>(reader: &mut R, max_bytes: usize) -> io::Result<Vec<u8>> {
+    let len = reader.read_i32().await?;
+    let len = usize::try_from(len)
+        .ok()
+        .filter(|len| (4..=max_bytes.saturating_add(4)).contains(len))
+        .ok_or_else(|| invalid("message length out of bounds"))?;
+    let mut body = vec![0; len - 4];
+    reader.read_exact(&mut body).await?;
+    Ok(body)
+}
+
+/// The next typed message, or `None` at end of stream.
+async fn read_message<R: AsyncRead + Unpin>(reader: &mut R, max_bytes: usize) -> io::Result<Option<(u8, Vec<u8>)>> {
+    let tag = match reader.read_u8().await {
+        Ok(tag) => tag,
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    Ok(Some((tag, read_body(reader, max_bytes).await?)))
+}
+
+async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, tag: Option<u8>, body: &[u8]) -> io::Result<()> {
+    let len = i32::try_from(body.len() + 4).map_err(|_| invalid("message too large"))?;
+    if let Some(tag) = tag {
+        writer.write_u8(tag).await?;
+    }
+    writer.write_i32(len).await?;
+    writer.write_all(body).await
+}
+
+/// The request code of an untyped startup-phase message.
+fn request_code(body: &[u8]) -> i32 {
+    Fields::new(body).i32().unwrap_or(0)
+}
+
+struct Proxy {
+    state: web::Data<AppState>,
+    columns: Columns,
+    upstream: String,
+    key_id: Option<String>,
+    max_message_bytes: usize,
+}
+
+impl Proxy {
+    fn encrypt(&self, plaintext: &[u8]) -> Result<String, SecurityError> {
+        let sealed = self.state.crypto_service.encrypt_bytes(plaintext.to_vec(), self.key_id.clone(), None)?;
+        Ok(format!(
+            "{}{}:{}:{}",
+            PREFIX,
+            sealed.key_id,
+            base64::encode(&sealed.nonce),
+            base64::encode(&sealed.ciphertext)
+        ))
+    }
+
+    /// The plaintext of a stored value, `None` when it is not one of ours
+    /// or does not decrypt.
+    fn decrypt(&self, stored: &[u8]) -> Option<Vec<u8>> {
+        let sealed = std::str::from_utf8(stored.strip_prefix(PREFIX.as_bytes())?).ok()?;
+        let mut parts = sealed.rsplitn(3, ':');
+        let (ciphertext, nonce, key_id) = (parts.next()?, parts.next()?, parts.next()?);
+        let nonce = base64::decode(nonce).ok()?;
+        let ciphertext = base64::decode(ciphertext).ok()?;
+        match self.state.crypto_service.decrypt_bytes(key_id, &nonce, ciphertext, None) {
+            Ok(plaintext) => Some(plaintext),
+            Err(e) => {
+                warn!("PostgreSQL proxy could not decrypt a value under key {}: {}", key_id, e);
+                None
+            }
+        }
+    }
+
+    /// A statement the server rejects, in place of one touching encrypted
+    /// columns unsafely.
+    fn refusal(&self, reason: &str) -> Vec<u8> {
+        warn!("PostgreSQL proxy refused a statement: {}", reason);
+        self.state.metrics_service.increment("cotai_pgproxy_refused_total", &[]);
+        format!("cotai_pgproxy_refused /* {} */", reason.replace("/*", "").replace("*/", "")).into_bytes()
+    }
+
+    /// `sql` made safe to send: literals for encrypted columns encrypted,
+    /// or the whole text refused; and the parameters to encrypt.
+    fn statement(&self, sql: &[u8]) -> io::Result<(Vec<u8>, Vec<usize>)> {
+        let Ok(text) = std::str::from_utf8(sql) else {
+            if self.columns.mentioned_in(&String::from_utf8_lossy(sql)) {
+                return Ok((self.refusal("statements touching encrypted tables must be UTF-8"), Vec::new()));
+            }
+            return Ok((sql.to_vec(), Vec::new()));
+        };
+        let plan = match sql::analyze(text, &self.columns) {
+            Ok(plan) => plan,
+            Err(reason) => return Ok((self.refusal(&reason), Vec::new())),
+        };
+        if plan.literals.is_empty() {
+            return Ok((sql.to_vec(), plan.params));
+        }
+        let rewritten = sql::rewrite(text, &plan, |value| self.encrypt(value.as_bytes()).map_err(|e| e.to_string()))
+            .map_err(|e| io::Error::other(format!("encryption failed: {}", e)))?;
+        Ok((rewritten.into_bytes(), plan.params))
+    }
+
+    /// Simple query: `Q` with the SQL text.
+    fn query(&self, body: Vec<u8>) -> io::Result<Vec<u8>> {
+        let sql = Fields::new(&body).cstr()?;
+        let (sql, _) = self.statement(sql)?;
+        let mut out = Vec::with_capacity(sql.len() + 1);
+        push_cstr(&mut out, &sql);
+        Ok(out)
+    }
+
+    /// Parse: `P` with the statement name, SQL text and parameter types.
+    fn parse(&self, body: Vec<u8>, statements: &mut HashMap<Vec<u8>, Vec<usize>>) -> io::Result<Vec<u8>> {
+        let mut fields = Fields::new(&body);
+        let name = fields.cstr()?;
+        let sql = fields.cstr()?;
+        let (sql, params) = self.statement(sql)?;
+        if params.is_empty() {
+            statements.remove(name);
+        } else {
+            statements.insert(name.to_vec(), params);
+        }
+        let mut out = Vec::with_capacity(body.len());
+        push_cstr(&mut out, name);
+        push_cstr(&mut out, &sql);
+        out.extend_from_slice(fields.rest());
+        Ok(out)
+    }
+
+    /// Bind: `B` with the portal, statement, parameter formats, parameter
+    /// values and result formats. Parameters for encrypted columns are
+    /// encrypted; as they are text, the text and binary formats agree.
+    fn bind(&self, body: Vec<u8>, statements: &HashMap<Vec<u8>, Vec<usize>>) -> io::Result<Vec<u8>> {
+        let mut fields = Fields::new(&body);
+        let portal = fields.cstr()?;
+        let statement = fields.cstr()?;
+        let Some(encrypted) = statements.get(statement) else {
+            return Ok(body);
+        };
+        let format_count = fields.i16()?.max(0) as usize;
+        let formats = fields.bytes(format_count * 2)?;
+        let param_count = fields.i16()?.max(0) as usize;
+
+        let mut out = Vec::with_capacity(body.len() * 2);
+        push_cstr(&mut out, portal);
+        push_cstr(&mut out, statement);
+        out.extend_from_slice(&(format_count as i16).to_be_bytes());
+        out.extend_from_slice(formats);
+        out.extend_from_slice(&(param_count as i16).to_be_bytes());
+        for index in 0..param_count {
+            let value = fields.value()?;
+            match value {
+                Some(plaintext) if encrypted.contains(&index) => {
+                    let sealed = self
+                        .encrypt(plaintext)
+                        .map_err(|e| io::Error::other(format!("encryption failed: {}", e)))?;
+                    push_value(&mut out, Some(sealed.as_bytes()));
+                }
+                value => push_value(&mut out, value),
+            }
+        }
+        out.extend_from_slice(fields.rest());
+        Ok(out)
+    }
+
+    /// DataRow: `D` with the column values; ours are decrypted.
+    fn data_row(&self, body: Vec<u8>) -> io::Result<Vec<u8>> {
+        if !body.windows(PREFIX.len()).any(|window| window == PREFIX.as_bytes()) {
+            return Ok(body);
+        }
+        let mut fields = Fields::new(&body);
+        let count = fields.i16()?;
+        let mut out = Vec::with_capacity(body.len());
+        out.extend_from_slice(&count.to_be_bytes());
+        for _ in 0..count.max(0) {
+            let value = fields.value()?;
+            match value.and_then(|stored| self.decrypt(stored)) {
+                Some(plaintext) => push_value(&mut out, Some(&plaintext)),
+                None => push_value(&mut out, value),
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Client to server: statements are checked, literals and parameters for
+/// encrypted columns encrypted.
+async fn relay_client<R, W>(proxy: &Proxy, mut client: BufReader<R>, mut server: BufWriter<W>) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    // Prepared statements with parameters to encrypt, by name
+    let mut statements: HashMap<Vec<u8>, Vec<usize>> = HashMap::new();
+    while let Some((tag, body)) = read_message(&mut client, proxy.max_message_bytes).await? {
+        let body = match tag {
+            b'Q' => proxy.query(body)?,
+            b'P' => proxy.parse(body, &mut statements)?,
+            b'B' => proxy.bind(body, &statements)?,
+            b'C' => {
+                let mut fields = Fields::new(&body);
+                if fields.bytes(1)? == b"S" {
+                    statements.remove(fields.cstr()?);
+                }
+                body
+            }
+            _ => body,
+        };
+        write_message(&mut server, Some(tag), &body).await?;
+        if client.buffer().is_empty() {
+            server.flush().await?;
+        }
+    }
+    server.flush().await
+}
+
+/// Server to client: result rows are decrypted.
+async fn relay_server<R, W>(proxy: &Proxy, mut server: BufReader<R>, mut client: BufWriter<W>) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    while let Some((tag, body)) = read_message(&mut server, proxy.max_message_bytes).await? {
+        let body = if tag == b'D' { proxy.data_row(body)? } else { body };
+        write_message(&mut client, Some(tag), &body).await?;
+        if server.buffer().is_empty() {
+            client.flush().await?;
+        }
+    }
+    client.flush().await
+}
+
+async fn session(proxy: Arc<Proxy>, client: TcpStream) -> io::Result<()> {
+    client.set_nodelay(true)?;
+    let (client_read, client_write) = client.into_split();
+    let mut client_read = BufReader::new(client_read);
+    let mut client_write = BufWriter::new(client_write);
+
+    // The startup phase has no message types. TLS and GSS encryption are
+    // declined: the proxy must see the traffic.
+    let startup = loop {
+        let body = read_body(&mut client_read, proxy.max_message_bytes).await?;
+        match request_code(&body) {
+            SSL_REQUEST | GSSENC_REQUEST => {
+                client_write.write_all(b"N").await?;
+                client_write.flush().await?;
+            }
+            _ => break body,
+        }
+    };
+
+    let upstream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(&proxy.upstream))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "upstream connection timed out"))??;
+    upstream.set_nodelay(true)?;
+    let (server_read, server_write) = upstream.into_split();
+    let mut server_write = BufWriter::new(server_write);
+    write_message(&mut server_write, None, &startup).await?;
+    server_write.flush().await?;
+    if request_code(&startup) == CANCEL_REQUEST {
+        return Ok(());
+    }
+
+    // Either side closing ends the session
+    tokio::select! {
+        relayed = relay_client(&proxy, client_read, server_write) => relayed,
+        relayed = relay_server(&proxy, BufReader::new(server_read), client_write) => relayed,
+    }
+}
+
+async fn serve(proxy: Arc<Proxy>, listener: TcpListener, max_connections: usize) {
+    let permits = Arc::new(Semaphore::new(max_connections));
+    loop {
+        let Ok(permit) = permits.clone().acquire_owned().await else {
+            return;
+        };
+        let (client, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                error!("PostgreSQL proxy accept failed: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        proxy.state.metrics_service.increment("cotai_pgproxy_sessions_total", &[]);
+        let proxy = proxy.clone();
+        tokio::spawn(async move {
+            if let Err(e) = session(proxy, client).await {
+                debug!("PostgreSQL proxy session from {} ended: {}", peer, e);
+            }
+            drop(permit);
+        });
+    }
+}
+
+/// The proxy started by `spawn`.
+pub struct Listener {
+    stop: oneshot::Sender<()>,
+    thread: std::thread::JoinHandle<()>,
+}
+
+impl Listener {
+    /// Stop accepting sessions and close those open. Blocks.
+    pub fn stop(self) {
+        let _ = self.stop.send(());
+        if self.thread.join().is_err() {
+            error!("PostgreSQL proxy thread panicked");
+        }
+    }
+}
+
+/// Start the proxy on `PGPROXY_PORT` in a thread with its own runtime;
+/// does nothing when the port is 0.
+pub fn spawn(state: web::Data<AppState>) -> io::Result<Option<Listener>> {
+    let config = &state.config.pgproxy;
+    if config.port == 0 {
+        return Ok(None);
+    }
+    let addr: SocketAddr = (state.config.host.as_str(), config.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "PGPROXY_PORT: no address to bind"))?;
+    let upstream = config
+        .upstream
+        .clone()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "PGPROXY_UPSTREAM is not set"))?;
+    let columns = Columns::parse(&config.columns)
+        .map_err(|entry| io::Error::new(io::ErrorKind::InvalidInput, format!("PGPROXY_COLUMNS: bad entry '{}'", entry)))?;
+    let max_connections = config.max_connections;
+
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    runtime.enable_all().thread_name("pgproxy");
+    if config.worker_threads > 0 {
+        runtime.worker_threads(config.worker_threads);
+    }
+    let runtime = runtime.build()?;
+    let listener = runtime.block_on(TcpListener::bind(addr))?;
+
+    let proxy = Arc::new(Proxy {
+        columns,
+        upstream,
+        key_id: config.key_id.clone(),
+        max_message_bytes: config.max_message_bytes,
+        state: state.clone(),
+    });
+    info!("PostgreSQL proxy starting on {}, relaying to {}", addr, proxy.upstream);
+    let (stop, stopped) = oneshot::channel();
+    let thread = std::thread::Builder::new()
+        .name("pgproxy".to_string())
+        .spawn(move || {
+            runtime.block_on(async {
+                tokio::select! {
+                    _ = serve(proxy, listener, max_connections) => {}
+                    _ = stopped => {}
+                }
+            });
+            // Dropping the runtime closes the open sessions
+        })?;
+    Ok(Some(Listener { stop, thread }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `Bind` for `statement` with text parameters.
+    fn bind(statement: &[u8], params: &[Option<&[u8]>]) -> Vec<u8> {
+        let mut body = Vec::new();
+        push_cstr(&mut body, b"");
+        push_cstr(&mut body, statement);
+        body.extend_from_slice(&1i16.to_be_bytes());
+        body.extend_from_slice(&0i16.to_be_bytes());
+        body.extend_from_slice(&(params.len() as i16).to_be_bytes());
+        for param in params {
+            push_value(&mut body, *param);
+        }
+        body.extend_from_slice(&0i16.to_be_bytes());
+        body
+    }
+
+    #[test]
+    fn fields_are_read_in_order() {
+        let body = bind(b"s1", &[Some(b"ana"), None, Some(b"")]);
+        let mut fields = Fields::new(&body);
+        assert_eq!(fields.cstr().unwrap(), b"");
+        assert_eq!(fields.cstr().unwrap(), b"s1");
+        assert_eq!(fields.i16().unwrap(), 1);
+        assert_eq!(fields.bytes(2).unwrap(), [0, 0]);
+        assert_eq!(fields.i16().unwrap(), 3);
+        assert_eq!(fields.value().unwrap(), Some(&b"ana"[..]));
+        assert_eq!(fields.value().unwrap(), None);
+        assert_eq!(fields.value().unwrap(), Some(&b""[..]));
+        assert_eq!(fields.rest(), [0, 0]);
+    }
+
+    #[test]
+    fn truncated_fields_are_refused() {
+        assert!(Fields::new(b"no terminator").cstr().is_err());
+        assert!(Fields::new(&[0, 0, 1]).i32().is_err());
+
+        let mut value = Vec::new();
+        push_value(&mut value, Some(b"ana"));
+        assert!(Fields::new(&value[..6]).value().is_err());
+        assert!(Fields::new(&(-2i32).to_be_bytes()).value().is_err());
+        assert!(Fields::new(&(i32::MAX).to_be_bytes()).value().is_err());
+    }
+
+    #[tokio::test]
+    async fn messages_round_trip() {
+        let mut wire = Vec::new();
+        write_message(&mut wire, Some(b'Q'), b"SELECT 1\0").await.unwrap();
+        write_message(&mut wire, Some(b'X'), b"").await.unwrap();
+        assert_eq!(&wire[..5], [b'Q', 0, 0, 0, 13]);
+
+        let mut reader = &wire[..];
+        assert_eq!(read_message(&mut reader, 64).await.unwrap(), Some((b'Q', b"SELECT 1\0".to_vec())));
+        assert_eq!(read_message(&mut reader, 64).await.unwrap(), Some((b'X', Vec::new())));
+        assert_eq!(read_message(&mut reader, 64).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn message_lengths_are_bounded() {
+        let mut wire = Vec::new();
+        write_message(&mut wire, Some(b'Q'), &[b'x'; 65]).await.unwrap();
+        let error = read_message(&mut &wire[..], 64).await.unwrap_err();
+        assert_eq!(error.to_string(), "message length out of bounds");
+
+        // Lengths count themselves, so anything under 4 is malformed
+        let error = read_message(&mut &[b'Q', 0, 0, 0, 3][..], 64).await.unwrap_err();
+        assert_eq!(error.to_string(), "message length out of bounds");
+        let error = read_message(&mut &[b'Q', 0xff, 0xff, 0xff, 0xff][..], 64).await.unwrap_err();
+        assert_eq!(error.to_string(), "message length out of bounds");
+
+        // A stream ending inside a message is an error, not a clean close
+        let error = read_message(&mut &[b'Q', 0, 0, 0, 9, b'S'][..], 64).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn startup_requests_are_told_apart() {
+        let mut wire = Vec::new();
+        write_message(&mut wire, None, &SSL_REQUEST.to_be_bytes()).await.unwrap();
+        let mut startup = 196608i32.to_be_bytes().to_vec();
+        push_cstr(&mut startup, b"user");
+        push_cstr(&mut startup, b"legacy");
+        push_cstr(&mut startup, b"");
+        write_message(&mut wire, None, &startup).await.unwrap();
+
+        let mut reader = &wire[..];
+        assert_eq!(request_code(&read_body(&mut reader, 1024).await.unwrap()), SSL_REQUEST);
+        let body = read_body(&mut reader, 1024).await.unwrap();
+        assert_eq!(request_code(&body), 196608);
+        assert_eq!(body, startup);
+        assert_eq!(request_code(&[]), 0);
+    }
+}
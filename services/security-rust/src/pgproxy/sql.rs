@@ -0,0 +1,491 @@
+/*!
+Statement Analysis
+Which parameters and literals of a statement land in encrypted columns
+
+Only what the proxy needs is recognized: `INSERT INTO t (cols) VALUES
+(...), ...` with an optional `ON CONFLICT ... DO UPDATE SET`, and `UPDATE t
+SET col = ..., ...`. A value written to an encrypted column must be a
+parameter (`$n`), a plain string literal, `NULL` or `DEFAULT` (or
+`EXCLUDED.col` in an upsert). Anything else touching an encrypted table
+that could write plaintext into it (`INSERT` without a column list or from
+a `SELECT`, `COPY`, `MERGE`, writes inside `WITH`, computed values) is
+refused rather than passed through.
+*/
+
+use std::ops::Range;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Lowercased unless quoted.
+    Ident(String),
+    /// A string literal; `plain` for standard `'...'` strings, which are the
+    /// only ones rewritten.
+    Str { value: String, plain: bool },
+    /// `$n`, 1-based.
+    Param(usize),
+    Punct(char),
+    Other,
+}
+
+#[derive(Debug, Clone)]
+struct Spanned {
+    token: Token,
+    span: Range<usize>,
+}
+
+/// What to encrypt in one statement.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Plan {
+    /// 0-based indexes of parameters bound for encrypted columns.
+    pub params: Vec<usize>,
+    /// Byte ranges of string literals for encrypted columns, with their values.
+    pub literals: Vec<(Range<usize>, String)>,
+}
+
+/// Encrypted columns as `(table, column)`, lowercased.
+#[derive(Debug, Clone, Default)]
+pub struct Columns(Vec<(String, String)>);
+
+impl Columns {
+    /// From `table.column` entries; the error is the first malformed one.
+    pub fn parse(entries: &[String]) -> Result<Self, String> {
+        entries
+            .iter()
+            .map(|entry| match entry.trim().rsplit_once('.') {
+                Some((table, column)) if !table.is_empty() && !column.is_empty() => {
+                    let table = table.rsplit('.').next().unwrap_or(table);
+                    Ok((table.to_lowercase(), column.to_lowercase()))
+                }
+                _ => Err(entry.clone()),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Columns)
+    }
+
+    fn has_table(&self, table: &str) -> bool {
+        self.0.iter().any(|(t, _)| t == table)
+    }
+
+    fn contains(&self, table: &str, column: &str) -> bool {
+        self.0.iter().any(|(t, c)| t == table && c == column)
+    }
+
+    /// Whether `sql` names a table with encrypted columns.
+    pub fn mentioned_in(&self, sql: &str) -> bool {
+        tokenize(sql)
+            .iter()
+            .any(|t| matches!(&t.token, Token::Ident(ident) if self.has_table(ident)))
+    }
+}
+
+fn tokenize(sql: &str) -> Vec<Spanned> {
+    let bytes = sql.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let c = bytes[i];
+        let token = match c {
+            b' ' | b'\t' | b'\n' | b'\r' | b'\x0c' => {
+                i += 1;
+                continue;
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let mut depth = 0;
+                while i < bytes.len() {
+                    if bytes[i..].starts_with(b"/*") {
+                        depth += 1;
+                        i += 2;
+                    } else if bytes[i..].starts_with(b"*/") {
+                        depth -= 1;
+                        i += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        i += 1;
+                    }
+                }
+                continue;
+            }
+            b'\'' => {
+                let (value, end) = quoted(sql, i, b'\'');
+                i = end;
+                Token::Str { value, plain: true }
+            }
+            b'e' | b'E' if bytes.get(i + 1) == Some(&b'\'') => {
+                i += 1;
+                while i < bytes.len() {
+                    i += 1;
+                    match bytes.get(i) {
+                        Some(b'\\') => i += 1,
+                        Some(b'\'') if bytes.get(i + 1) == Some(&b'\'') => i += 1,
+                        Some(b'\'') => {
+                            i += 1;
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                Token::Str { value: String::new(), plain: false }
+            }
+            b'"' => {
+                let (value, end) = quoted(sql, i, b'"');
+                i = end;
+                Token::Ident(value)
+            }
+            b'$' if bytes.get(i + 1).is_some_and(u8::is_ascii_digit) => {
+                i += 1;
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+                Token::Param(sql[start + 1..i].parse().unwrap_or(0))
+            }
+            b'$' => {
+                // Dollar quoting: $tag$ ... $tag$
+                let tag_end = sql[i + 1..]
+                    .find('$')
+                    .map(|at| i + 1 + at)
+                    .filter(|&end| sql[i + 1..end].bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_'));
+                match tag_end {
+                    Some(tag_end) => {
+                        let tag = &sql[i..=tag_end];
+                        i = sql[tag_end + 1..].find(tag).map_or(bytes.len(), |at| tag_end + 1 + at + tag.len());
+                        Token::Str { value: String::new(), plain: false }
+                    }
+                    None => {
+                        i += 1;
+                        Token::Other
+                    }
+                }
+            }
+            c if c.is_ascii_alphabetic() || c == b'_' || c >= 0x80 => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_' || bytes[i] == b'$' || bytes[i] >= 0x80) {
+                    i += 1;
+                }
+                Token::Ident(sql[start..i].to_lowercase())
+            }
+            c if c.is_ascii_digit() => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'.') {
+                    i += 1;
+                }
+                Token::Other
+            }
+            c => {
+                i += 1;
+                Token::Punct(c as char)
+            }
+        };
+        tokens.push(Spanned { token, span: start..i });
+    }
+    tokens
+}
+
+/// A `quote`-delimited run starting at `start`, with doubled quotes undone,
+/// and the offset just past it.
+fn quoted(sql: &str, start: usize, quote: u8) -> (String, usize) {
+    let bytes = sql.as_bytes();
+    let mut value = Vec::new();
+    let mut i = start + 1;
+    while i < bytes.len() {
+        if bytes[i] == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                value.push(quote);
+                i += 2;
+                continue;
+            }
+            return (String::from_utf8_lossy(&value).into_owned(), i + 1);
+        }
+        value.push(bytes[i]);
+        i += 1;
+    }
+    (String::from_utf8_lossy(&value).into_owned(), i)
+}
+
+fn is_keyword(token: &Token, keyword: &str) -> bool {
+    matches!(token, Token::Ident(ident) if ident == keyword)
+}
+
+struct Analyzer<'a> {
+    tokens: &'a [Spanned],
+    pos: usize,
+    columns: &'a Columns,
+    plan: Plan,
+}
+
+impl<'a> Analyzer<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos).map(|t| &t.token)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.peek();
+        self.pos += 1;
+        token
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek().is_some_and(|t| is_keyword(t, keyword));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_punct(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(&Token::Punct(c));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    /// `[schema.]name`, giving `name`.
+    fn table_name(&mut self) -> Option<String> {
+        let mut name = match self.next()? {
+            Token::Ident(name) => name.clone(),
+            _ => return None,
+        };
+        while self.eat_punct('.') {
+            match self.next()? {
+                Token::Ident(part) => name = part.clone(),
+                _ => return None,
+            }
+        }
+        Some(name)
+    }
+
+    /// Tokens of one value, up to a `,` or `)` at depth 0, or a keyword in
+    /// `stop`.
+    fn value(&mut self, stop: &[&str]) -> Range<usize> {
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Punct('(') => depth += 1,
+                Token::Punct(')') if depth == 0 => break,
+                Token::Punct(')') => depth -= 1,
+                Token::Punct(',') | Token::Punct(';') if depth == 0 => break,
+                Token::Ident(ident) if depth == 0 && stop.contains(&ident.as_str()) => break,
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        start..self.pos
+    }
+
+    /// Add the value at `range`, bound for `table.column`, to the plan.
+    fn encrypt(&mut self, table: &str, column: &str, range: Range<usize>) -> Result<(), String> {
+        if !self.columns.contains(table, column) {
+            return Ok(());
+        }
+        let tokens: Vec<&Token> = self.tokens[range.clone()].iter().map(|t| &t.token).collect();
+        match tokens.as_slice() {
+            [Token::Param(n)] if *n > 0 => self.plan.params.push(n - 1),
+            [Token::Str { value, plain: true }] => {
+                self.plan.literals.push((self.tokens[range.start].span.clone(), value.clone()));
+            }
+            [Token::Ident(ident)] if ident == "null" || ident == "default" => {}
+            [Token::Ident(excluded), Token::Punct('.'), Token::Ident(_)] if excluded == "excluded" => {}
+            _ => {
+                return Err(format!(
+                    "{}.{} is encrypted; write it from a parameter or a plain string literal",
+                    table, column
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self) -> Result<(), String> {
+        if !self.eat_keyword("into") {
+            return Ok(());
+        }
+        let Some(table) = self.table_name() else {
+            return Ok(());
+        };
+        if !self.columns.has_table(&table) {
+            return Ok(());
+        }
+        if self.eat_keyword("as") {
+            self.next();
+        }
+
+        let mut columns = Vec::new();
+        if self.eat_punct('(') {
+            loop {
+                match self.next() {
+                    Some(Token::Ident(column)) => columns.push(column.clone()),
+                    _ => return Err(format!("Could not read the column list of an insert into {}", table)),
+                }
+                if self.eat_punct(')') {
+                    break;
+                }
+                if !self.eat_punct(',') {
+                    return Err(format!("Could not read the column list of an insert into {}", table));
+                }
+            }
+        } else if self.eat_keyword("default") && self.eat_keyword("values") {
+            return Ok(());
+        } else {
+            return Err(format!("Inserts into {} need a column list", table));
+        }
+        let encrypted = columns.iter().any(|column| self.columns.contains(&table, column));
+
+        if self.eat_keyword("overriding") {
+            self.next();
+            self.next();
+        }
+        if !self.eat_keyword("values") {
+            if encrypted {
+                return Err(format!("Inserts into encrypted columns of {} need VALUES", table));
+            }
+            return Ok(());
+        }
+        loop {
+            if !self.eat_punct('(') {
+                return Err(format!("Could not read the values of an insert into {}", table));
+            }
+            let mut index = 0;
+            loop {
+                let range = self.value(&[]);
+                if let Some(column) = columns.get(index) {
+                    self.encrypt(&table, column, range)?;
+                }
+                index += 1;
+                if self.eat_punct(')') {
+                    break;
+                }
+                if !self.eat_punct(',') {
+                    return Err(format!("Could not read the values of an insert into {}", table));
+                }
+            }
+            if !self.eat_punct(',') {
+                break;
+            }
+        }
+
+        // ON CONFLICT ... DO UPDATE SET ...
+        while let Some(token) = self.next() {
+            if is_keyword(token, "do") && self.eat_keyword("update") && self.eat_keyword("set") {
+                return self.assignments(&table);
+            }
+        }
+        Ok(())
+    }
+
+    fn update(&mut self) -> Result<(), String> {
+        self.eat_keyword("only");
+        let Some(table) = self.table_name() else {
+            return Ok(());
+        };
+        if !self.columns.has_table(&table) {
+            return Ok(());
+        }
+        self.eat_punct('*');
+        if !self.eat_keyword("set") {
+            self.eat_keyword("as");
+            self.next();
+            if !self.eat_keyword("set") {
+                return Err(format!("Could not read an update of {}", table));
+            }
+        }
+        self.assignments(&table)
+    }
+
+    fn assignments(&mut self, table: &str) -> Result<(), String> {
+        const STOP: &[&str] = &["from", "where", "returning"];
+        loop {
+            let column = match self.next() {
+                Some(Token::Ident(first)) => {
+                    let mut column = first.clone();
+                    while self.eat_punct('.') {
+                        if let Some(Token::Ident(part)) = self.next() {
+                            column = part.clone();
+                        }
+                    }
+                    column
+                }
+                Some(Token::Punct('(')) => {
+                    // (a, b) = (...): refused if it touches an encrypted column
+                    let start = self.pos;
+                    self.value(&[]);
+                    let touches = self.tokens[start..self.pos]
+                        .iter()
+                        .any(|t| matches!(&t.token, Token::Ident(c) if self.columns.contains(table, c)));
+                    if touches {
+                        return Err(format!("Set encrypted columns of {} one at a time", table));
+                    }
+                    self.eat_punct(')');
+                    String::new()
+                }
+                _ => return Ok(()),
+            };
+            if !self.eat_punct('=') {
+                return Err(format!("Could not read an update of {}", table));
+            }
+            let range = self.value(STOP);
+            self.encrypt(table, &column, range)?;
+            if !self.eat_punct(',') {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Whether any identifier names a table with encrypted columns.
+    fn mentions_encrypted_table(&self) -> bool {
+        self.tokens
+            .iter()
+            .any(|t| matches!(&t.token, Token::Ident(ident) if self.columns.has_table(ident)))
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Ident(keyword)) => match keyword.as_str() {
+                "insert" => self.insert(),
+                "update" => self.update(),
+                "copy" | "merge" | "with" if self.mentions_encrypted_table() => {
+                    Err(format!("{} statements cannot touch encrypted tables", keyword.to_uppercase()))
+                }
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What to encrypt in `sql`, which may hold several statements.
+pub fn analyze(sql: &str, columns: &Columns) -> Result<Plan, String> {
+    let tokens = tokenize(sql);
+    let mut plan = Plan::default();
+    for statement in tokens.split(|t| t.token == Token::Punct(';')) {
+        let mut analyzer = Analyzer { tokens: statement, pos: 0, columns, plan: Plan::default() };
+        analyzer.statement()?;
+        plan.params.extend(analyzer.plan.params);
+        plan.literals.extend(analyzer.plan.literals);
+    }
+    Ok(plan)
+}
+
+/// `sql` with the literals of `plan` replaced by `encrypt`ed ones.
+pub fn rewrite(sql: &str, plan: &Plan, mut encrypt: impl FnMut(&str) -> Result<String, String>) -> Result<String, String> {
+    let mut out = String::with_capacity(sql.len());
+    let mut copied = 0;
+    let mut literals: Vec<&(Range<usize>, String)> = plan.literals.iter().collect();
+    literals.sort_by_key(|(span, _)| span.start);
+    for (span, value) in literals {
+        out.push_str(&sql[copied..span.start]);
+        out.push('\'');
+        out.push_str(&encrypt(value)?.replace('\'', "''"));
+        out.push('\'');
+        copied = span.end;
+    }
+    out.push_str(&sql[copied..]);
+    Ok(out)
+}
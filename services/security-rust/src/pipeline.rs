@@ -0,0 +1,241 @@
+/*!
+Pipeline Module
+Configurable ordering and scoping of the service's own request middleware
+
+`MIDDLEWARE_PIPELINE` lists stages outermost first. A stage runs for every
+request under `/api/v1` unless limited with `stage@/prefix|/prefix`.
+Requests pass the stages in order; responses, including early rejections,
+pass back through the stages that already ran in reverse order. Framework
+middleware (CORS, request logging, compression) only toggles on and off.
+
+Problems are collected and reported together at startup, so a bad
+pipeline never reaches traffic.
+*/
+
+use actix_web::body::BoxBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{HttpMessage, HttpResponse};
+use std::str::FromStr;
+
+use crate::auth::{api_keys, captcha};
+use crate::config::Config;
+use crate::errors::SecurityError;
+use crate::monitoring::threats;
+use crate::{compression, forensics, hooks, maintenance, rate_limiting, tls, AppState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Marks secret-bearing responses uncompressed (BREACH).
+    CompressionPolicy,
+    /// Rejects requests without a client certificate while mutual TLS is on.
+    ClientCert,
+    /// Rejects mutating requests during maintenance windows.
+    Maintenance,
+    /// Rejects mutating requests without a passing CAPTCHA token.
+    Captcha,
+    /// Rejects accounts and addresses locked out for brute force or abuse.
+    Lockout,
+    /// Rejects requests over their route or tenant limit.
+    RateLimit,
+    /// Rejects callers without roles that lack the route's scope.
+    Scopes,
+    /// Runs the configured request hooks (see `hooks`).
+    Hooks,
+}
+
+const STAGES: &[Stage] = &[
+    Stage::CompressionPolicy,
+    Stage::ClientCert,
+    Stage::Maintenance,
+    Stage::Captcha,
+    Stage::Lockout,
+    Stage::RateLimit,
+    Stage::Scopes,
+    Stage::Hooks,
+];
+
+impl Stage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::CompressionPolicy => "compression_policy",
+            Stage::ClientCert => "client_cert",
+            Stage::Maintenance => "maintenance",
+            Stage::Captcha => "captcha",
+            Stage::Lockout => "lockout",
+            Stage::RateLimit => "rate_limit",
+            Stage::Scopes => "scopes",
+            Stage::Hooks => "hooks",
+        }
+    }
+
+    async fn before(&self, state: &AppState, req: &mut ServiceRequest) -> Option<HttpResponse> {
+        match self {
+            Stage::ClientCert => tls::rejection(state, req),
+            Stage::Maintenance => maintenance::rejection(state, req),
+            Stage::Captcha => captcha::rejection(state, req).await,
+            Stage::Lockout => threats::rejection(state, req),
+            Stage::RateLimit => rate_limiting::rejection(state, req).await,
+            Stage::Scopes => api_keys::rejection(state, req),
+            Stage::Hooks => hooks::rejection(state, req).await,
+            Stage::CompressionPolicy => None,
+        }
+    }
+
+    fn after(&self, res: ServiceResponse) -> ServiceResponse {
+        match self {
+            Stage::CompressionPolicy => compression::apply_policy(res),
+            Stage::RateLimit => rate_limiting::annotate(res),
+            Stage::Hooks => hooks::annotate(res),
+            Stage::ClientCert | Stage::Maintenance | Stage::Captcha | Stage::Lockout | Stage::Scopes => res,
+        }
+    }
+}
+
+impl FromStr for Stage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        STAGES
+            .iter()
+            .copied()
+            .find(|stage| stage.as_str() == s)
+            .ok_or_else(|| {
+                let known: Vec<_> = STAGES.iter().map(Stage::as_str).collect();
+                format!("unknown stage '{}' (known: {})", s, known.join(", "))
+            })
+    }
+}
+
+/// What each step did to a request that passed them all, kept on the
+/// request for `echo`.
+#[derive(Debug, Clone, Default)]
+pub struct Trace(pub Vec<(&'static str, &'static str)>);
+
+#[derive(Debug, Clone)]
+struct Step {
+    stage: Stage,
+    /// Path prefixes; empty means every path.
+    scopes: Vec<String>,
+}
+
+impl Step {
+    fn applies_to(&self, path: &str) -> bool {
+        self.scopes.is_empty() || self.scopes.iter().any(|prefix| path.starts_with(prefix.as_str()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    steps: Vec<Step>,
+}
+
+impl Pipeline {
+    pub fn from_config(config: &Config) -> Result<Self, SecurityError> {
+        let mut problems = Vec::new();
+        let mut steps: Vec<Step> = Vec::new();
+
+        for entry in &config.middleware.pipeline {
+            let (name, scopes) = match entry.split_once('@') {
+                Some((name, scopes)) => (name.trim(), scopes.split('|').map(|s| s.trim().to_string()).collect()),
+                None => (entry.trim(), Vec::new()),
+            };
+            let stage = match name.parse::<Stage>() {
+                Ok(stage) => stage,
+                Err(e) => {
+                    problems.push(e);
+                    continue;
+                }
+            };
+            if steps.iter().any(|step| step.stage == stage) {
+                problems.push(format!("stage '{}' is listed more than once", name));
+            }
+            for scope in &scopes {
+                if !scope.starts_with('/') {
+                    problems.push(format!("scope '{}' of stage '{}' must start with '/'", scope, name));
+                }
+            }
+            steps.push(Step { stage, scopes });
+        }
+
+        if config.server.compression {
+            match steps.iter().find(|step| step.stage == Stage::CompressionPolicy) {
+                None => problems.push(
+                    "compression_policy is required while SERVER_COMPRESSION is on".to_string(),
+                ),
+                Some(step) if !step.scopes.is_empty() => problems.push(
+                    "compression_policy cannot be scoped while SERVER_COMPRESSION is on".to_string(),
+                ),
+                Some(_) => {}
+            }
+        }
+
+        if steps.iter().any(|step| step.stage == Stage::Captcha) && config.captcha.provider.is_none() {
+            problems.push("captcha needs CAPTCHA_PROVIDER".to_string());
+        }
+
+        if config.tls.client_ca_path.is_some() && !steps.iter().any(|step| step.stage == Stage::ClientCert) {
+            problems.push("client_cert is required while TLS_CLIENT_CA_PATH is set".to_string());
+        }
+
+        let hooks = steps.iter().any(|step| step.stage == Stage::Hooks);
+        if hooks != config.hooks.file.is_some() {
+            problems.push("hooks and REQUEST_HOOKS_FILE go together".to_string());
+        }
+
+        if !problems.is_empty() {
+            return Err(SecurityError::ConfigError(format!(
+                "MIDDLEWARE_PIPELINE is invalid: {}",
+                problems.join("; ")
+            )));
+        }
+        Ok(Self { steps })
+    }
+
+    pub fn stages(&self) -> Vec<&'static str> {
+        self.steps.iter().map(|step| step.stage.as_str()).collect()
+    }
+
+    /// Run the request side. An early response comes back with the number
+    /// of steps that ran, so `after` unwinds only those. Rejected and
+    /// flagged requests are offered to `forensics`.
+    pub async fn before(&self, state: &AppState, req: &mut ServiceRequest) -> Option<(usize, HttpResponse)> {
+        let path = req.path().to_string();
+        let mut trace = Trace::default();
+        for (i, step) in self.steps.iter().enumerate() {
+            if !step.applies_to(&path) {
+                trace.0.push((step.stage.as_str(), "not_applicable"));
+                continue;
+            }
+            if let Some(response) = step.stage.before(state, req).await {
+                forensics::capture(state, req, step.stage.as_str(), Some(response.status())).await;
+                return Some((i, response));
+            }
+            trace.0.push((step.stage.as_str(), "passed"));
+        }
+        req.extensions_mut().insert(trace);
+        let flagged = req.extensions().get::<forensics::Flag>().map(|flag| flag.stage);
+        if let Some(stage) = flagged {
+            forensics::capture(state, req, stage, None).await;
+        }
+        None
+    }
+
+    /// Run the response side of the first `ran` steps, innermost first.
+    pub fn after(&self, ran: usize, mut res: ServiceResponse<BoxBody>) -> ServiceResponse<BoxBody> {
+        let path = res.request().path().to_string();
+        for step in self.steps[..ran].iter().rev() {
+            if step.applies_to(&path) {
+                res = step.stage.after(res);
+            }
+        }
+        res
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
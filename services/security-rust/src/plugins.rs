@@ -0,0 +1,799 @@
+/*!
+Plugins
+Custom validation and detection logic as sandboxed WebAssembly modules
+
+Deployments with checks of their own, such as a municipality's rules for
+its supplier registry, upload them as WebAssembly modules instead of
+forking the service. A plugin is a named series of versions, at most one
+of them active; every replica loads active versions within
+`PLUGIN_REFRESH_INTERVAL_SECS`, so uploading, activating or rolling back a
+version takes effect without a restart.
+
+- `validation` plugins check a value: `{"value": ...}` in,
+  `{"errors": [{"field", "code", "message"}]}` out, empty when valid. They
+  run as the `plugin:<name>` rule of `/validate/fields`, and on whole
+  payloads at `POST /validate/plugins/{name}`.
+- `detection` plugins look at an audit event: `{"event": ...}` in,
+  `{"match": bool}` out. Correlation rule steps name them in `plugin`.
+
+A module exports `memory`, `alloc(len: i32) -> i32` and
+`evaluate(ptr: i32, len: i32) -> i64`, which returns its output's address
+in the high 32 bits and length in the low 32; input and output are UTF-8
+JSON. The host API is all it may import, from module `cotai`:
+
+- `log(level: i32, ptr: i32, len: i32)`: a message at 0 debug to 3 error
+- `now_ms() -> i64`: the service clock, in Unix milliseconds
+
+There is no WASI, so no files, network or randomness. Each call gets a
+fresh instance limited to `PLUGIN_MAX_MEMORY_BYTES` of memory and
+`PLUGIN_MAX_FUEL` units of fuel (about one per instruction); running out
+fails that call only. Modules are checked against this contract when
+uploaded, and run only in builds with the `plugins` feature; without it
+they can still be managed, but calls fail.
+
+Managed under `/admin/plugins`; uploads, activations and deactivations are
+audited and kept in the change history.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tracing::{error, info, warn};
+
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, Principal};
+use crate::changes::{self, NewChange};
+use crate::clock::Clock;
+use crate::config::{Config, PluginsConfig};
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+use crate::validation::FieldError;
+use crate::AppState;
+
+const SELECT_COLUMNS: &str = "name, version, kind, description, sha256, octet_length(module) AS size_bytes, \
+    active, created_by, created_at";
+
+const MAX_NAME_LENGTH: usize = 64;
+const MAX_DESCRIPTION_LENGTH: usize = 1000;
+
+/// Functions a module may import from `cotai`.
+pub const HOST_FUNCTIONS: &[&str] = &["log", "now_ms"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Validation,
+    Detection,
+}
+
+impl Kind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Validation => "validation",
+            Kind::Detection => "detection",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "validation" => Some(Kind::Validation),
+            "detection" => Some(Kind::Detection),
+            _ => None,
+        }
+    }
+}
+
+/// One uploaded version; the module itself is not returned.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct PluginVersion {
+    pub name: String,
+    pub version: i32,
+    pub kind: String,
+    pub description: String,
+    /// Hex SHA-256 of the module.
+    pub sha256: String,
+    pub size_bytes: i32,
+    pub active: bool,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UploadRequest {
+    pub kind: Kind,
+    #[serde(default)]
+    pub description: String,
+    /// The `.wasm` binary, base64.
+    pub module: String,
+    /// Make this the active version right away.
+    #[serde(default)]
+    pub activate: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ActivateRequest {
+    pub version: i32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestRequest {
+    /// Defaults to the active version.
+    pub version: Option<i32>,
+    pub input: Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PayloadRequest {
+    pub data: Value,
+}
+
+/// What a validation plugin reports.
+#[derive(Debug, Deserialize)]
+struct ValidationOutput {
+    #[serde(default)]
+    errors: Vec<PluginFieldError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PluginFieldError {
+    field: Option<String>,
+    code: String,
+    message: String,
+}
+
+/// What a detection plugin reports.
+#[derive(Debug, Deserialize)]
+struct DetectionOutput {
+    #[serde(rename = "match")]
+    matched: bool,
+}
+
+/// An active version, compiled.
+struct Loaded {
+    version: i32,
+    kind: Kind,
+    #[cfg(feature = "plugins")]
+    module: wasmtime::Module,
+}
+
+pub struct PluginHost {
+    storage: Storage,
+    config: PluginsConfig,
+    clock: Arc<dyn Clock>,
+    #[cfg(feature = "plugins")]
+    runtime: runtime::Runtime,
+    /// Active versions by name.
+    loaded: RwLock<HashMap<String, Arc<Loaded>>>,
+}
+
+fn check_name(name: &str) -> Result<(), SecurityError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if !valid {
+        return Err(SecurityError::ValidationError(format!(
+            "Plugin names are 1-{} lowercase letters, digits, '-' or '_'",
+            MAX_NAME_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+impl PluginHost {
+    pub fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        #[cfg(not(feature = "plugins"))]
+        info!("Built without the plugins feature; plugins can be managed but not run");
+        Ok(Self {
+            storage,
+            config: config.plugins.clone(),
+            clock,
+            #[cfg(feature = "plugins")]
+            runtime: runtime::Runtime::new()?,
+            loaded: RwLock::new(HashMap::new()),
+        })
+    }
+
+    pub async fn list(&self) -> Result<Vec<PluginVersion>, SecurityError> {
+        let versions = sqlx::query_as::<_, PluginVersion>(&format!(
+            "SELECT {} FROM plugins ORDER BY name, version DESC",
+            SELECT_COLUMNS
+        ))
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(versions)
+    }
+
+    /// Versions of `name`, newest first.
+    pub async fn versions(&self, name: &str) -> Result<Vec<PluginVersion>, SecurityError> {
+        let versions = sqlx::query_as::<_, PluginVersion>(&format!(
+            "SELECT {} FROM plugins WHERE name = $1 ORDER BY version DESC",
+            SELECT_COLUMNS
+        ))
+        .bind(name)
+        .fetch_all(self.storage.pool())
+        .await?;
+        if versions.is_empty() {
+            return Err(SecurityError::NotFound(format!("Plugin '{}' not found", name)));
+        }
+        Ok(versions)
+    }
+
+    /// Check `module` against the contract without storing it.
+    fn check_module(&self, module: &[u8]) -> Result<(), SecurityError> {
+        if module.len() > self.config.max_module_bytes {
+            return Err(SecurityError::ValidationError(format!(
+                "Modules are at most {} bytes",
+                self.config.max_module_bytes
+            )));
+        }
+        #[cfg(feature = "plugins")]
+        self.runtime.compile(module).map_err(SecurityError::ValidationError)?;
+        #[cfg(not(feature = "plugins"))]
+        if !module.starts_with(b"\0asm") {
+            return Err(SecurityError::ValidationError("Not a WebAssembly module".to_string()));
+        }
+        Ok(())
+    }
+
+    pub async fn upload(&self, actor: &Principal, name: &str, request: UploadRequest) -> Result<PluginVersion, SecurityError> {
+        check_name(name)?;
+        if request.description.len() > MAX_DESCRIPTION_LENGTH {
+            return Err(SecurityError::ValidationError(format!(
+                "description is at most {} characters",
+                MAX_DESCRIPTION_LENGTH
+            )));
+        }
+        let module = base64::decode(&request.module)
+            .map_err(|_| SecurityError::ValidationError("module must be base64".to_string()))?;
+        self.check_module(&module)?;
+
+        let mut tx = self.storage.begin().await?;
+        // Versions of one name are numbered in order, so uploads of it queue
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext('plugin:' || $1))")
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+        let kind: Option<String> = sqlx::query_scalar("SELECT kind FROM plugins WHERE name = $1 LIMIT 1")
+            .bind(name)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if kind.as_deref().is_some_and(|kind| kind != request.kind.as_str()) {
+            return Err(SecurityError::Conflict(format!(
+                "Plugin '{}' is a {} plugin",
+                name,
+                kind.unwrap_or_default()
+            )));
+        }
+        if request.activate {
+            sqlx::query("UPDATE plugins SET active = FALSE WHERE name = $1 AND active")
+                .bind(name)
+                .execute(&mut *tx)
+                .await?;
+        }
+        let version = sqlx::query_as::<_, PluginVersion>(&format!(
+            "INSERT INTO plugins (name, version, kind, description, module, sha256, active, created_by) \
+             SELECT $1, COALESCE(MAX(version), 0) + 1, $2, $3, $4, $5, $6, $7 FROM plugins WHERE name = $1 \
+             RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(name)
+        .bind(request.kind.as_str())
+        .bind(&request.description)
+        .bind(&module)
+        .bind(hex::encode(digest(&SHA256, &module).as_ref()))
+        .bind(request.activate)
+        .bind(&actor.subject)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        changes::record(&mut tx, NewChange {
+            resource_type: "plugin",
+            resource_id: name.to_string(),
+            version: Some(version.version as i64),
+            action: "upload",
+            author: &actor.subject,
+            tenant_id: None,
+            before: None,
+            after: serde_json::to_value(&version).ok(),
+        }).await?;
+        tx.commit().await?;
+        Ok(version)
+    }
+
+    /// Make `version` the active one, or with `None` deactivate the plugin.
+    pub async fn activate(&self, actor: &Principal, name: &str, version: Option<i32>) -> Result<Vec<PluginVersion>, SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let before: Option<i32> = sqlx::query_scalar("SELECT version FROM plugins WHERE name = $1 AND active FOR UPDATE")
+            .bind(name)
+            .fetch_optional(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE plugins SET active = FALSE WHERE name = $1 AND active")
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+        if let Some(version) = version {
+            let updated = sqlx::query("UPDATE plugins SET active = TRUE WHERE name = $1 AND version = $2")
+                .bind(name)
+                .bind(version)
+                .execute(&mut *tx)
+                .await?;
+            if updated.rows_affected() == 0 {
+                return Err(SecurityError::NotFound(format!("Plugin '{}' has no version {}", name, version)));
+            }
+        }
+
+        changes::record(&mut tx, NewChange {
+            resource_type: "plugin",
+            resource_id: name.to_string(),
+            version: version.map(i64::from),
+            action: if version.is_some() { "activate" } else { "deactivate" },
+            author: &actor.subject,
+            tenant_id: None,
+            before: Some(serde_json::json!({ "active_version": before })),
+            after: Some(serde_json::json!({ "active_version": version })),
+        }).await?;
+        tx.commit().await?;
+        self.versions(name).await
+    }
+
+    /// Load newly activated versions and drop deactivated ones.
+    pub async fn refresh(&self) -> Result<(), SecurityError> {
+        let active: Vec<(String, i32, String)> =
+            sqlx::query_as("SELECT name, version, kind FROM plugins WHERE active")
+                .fetch_all(self.storage.pool())
+                .await?;
+
+        let current = self.loaded.read().unwrap().clone();
+        let mut loaded = HashMap::new();
+        for (name, version, kind) in active {
+            if let Some(plugin) = current.get(&name).filter(|plugin| plugin.version == version) {
+                loaded.insert(name, plugin.clone());
+                continue;
+            }
+            match self.load(&name, version, &kind).await {
+                Ok(plugin) => {
+                    info!("Loaded plugin '{}' version {}", name, version);
+                    loaded.insert(name, Arc::new(plugin));
+                }
+                Err(e) => error!("Failed to load plugin '{}' version {}: {}", name, version, e),
+            }
+        }
+        *self.loaded.write().unwrap() = loaded;
+        Ok(())
+    }
+
+    async fn load(&self, name: &str, version: i32, kind: &str) -> Result<Loaded, SecurityError> {
+        let kind = Kind::parse(kind).ok_or_else(|| SecurityError::PluginError(format!("unknown kind '{}'", kind)))?;
+        let module: Vec<u8> = sqlx::query_scalar("SELECT module FROM plugins WHERE name = $1 AND version = $2")
+            .bind(name)
+            .bind(version)
+            .fetch_one(self.storage.pool())
+            .await?;
+        #[cfg(feature = "plugins")]
+        let module = self.runtime.compile(&module).map_err(SecurityError::PluginError)?;
+        #[cfg(not(feature = "plugins"))]
+        let _ = module;
+        Ok(Loaded {
+            version,
+            kind,
+            #[cfg(feature = "plugins")]
+            module,
+        })
+    }
+
+    /// Run the active version of `name`, which must be of `kind`.
+    pub fn call(&self, name: &str, kind: Kind, input: &Value) -> Result<Value, SecurityError> {
+        let plugin = self
+            .loaded
+            .read()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| SecurityError::NotFound(format!("No active plugin '{}'", name)))?;
+        if plugin.kind != kind {
+            return Err(SecurityError::ValidationError(format!(
+                "'{}' is a {} plugin, not {}",
+                name,
+                plugin.kind.as_str(),
+                kind.as_str()
+            )));
+        }
+        self.run(name, &plugin, input)
+    }
+
+    #[cfg(feature = "plugins")]
+    fn run(&self, name: &str, plugin: &Loaded, input: &Value) -> Result<Value, SecurityError> {
+        let input = serde_json::to_vec(input).map_err(|e| SecurityError::PluginError(e.to_string()))?;
+        let output = self
+            .runtime
+            .call(&plugin.module, name, &input, &self.config, self.clock.now().timestamp_millis())
+            .map_err(|e| SecurityError::PluginError(format!("'{}' version {}: {}", name, plugin.version, e)))?;
+        serde_json::from_slice(&output).map_err(|e| {
+            SecurityError::PluginError(format!("'{}' version {} returned invalid JSON: {}", name, plugin.version, e))
+        })
+    }
+
+    #[cfg(not(feature = "plugins"))]
+    fn run(&self, name: &str, plugin: &Loaded, _input: &Value) -> Result<Value, SecurityError> {
+        let _ = (&self.config, &self.clock);
+        Err(SecurityError::PluginError(format!(
+            "'{}' version {} cannot run: built without the plugins feature",
+            name, plugin.version
+        )))
+    }
+
+    /// Run a stored version, active or not, for `/admin/plugins/{name}/test`.
+    pub async fn test(&self, name: &str, version: Option<i32>, input: &Value) -> Result<Value, SecurityError> {
+        let row: Option<(i32, String)> = match version {
+            Some(version) => sqlx::query_as("SELECT version, kind FROM plugins WHERE name = $1 AND version = $2")
+                .bind(name)
+                .bind(version),
+            None => sqlx::query_as("SELECT version, kind FROM plugins WHERE name = $1 AND active").bind(name),
+        }
+        .fetch_optional(self.storage.pool())
+        .await?;
+        let (version, kind) = row.ok_or_else(|| SecurityError::NotFound(format!("Plugin '{}' has no such version", name)))?;
+        let plugin = self.load(name, version, &kind).await?;
+        self.run(name, &plugin, input)
+    }
+
+    /// Errors a validation plugin finds in `value`, under `field` unless it
+    /// names its own.
+    pub fn validate(&self, name: &str, field: &str, value: Value) -> Result<Vec<FieldError>, SecurityError> {
+        let output = self.call(name, Kind::Validation, &serde_json::json!({ "value": value }))?;
+        let output: ValidationOutput = serde_json::from_value(output)
+            .map_err(|e| SecurityError::PluginError(format!("'{}' returned {}", name, e)))?;
+        Ok(output
+            .errors
+            .into_iter()
+            .map(|error| FieldError {
+                field: error.field.unwrap_or_else(|| field.to_string()),
+                code: error.code,
+                message: error.message,
+            })
+            .collect())
+    }
+
+    /// Whether a detection plugin matches an event, given as `{"event": ...}`.
+    pub fn detect(&self, name: &str, input: &Value) -> Result<bool, SecurityError> {
+        let output = self.call(name, Kind::Detection, input)?;
+        let output: DetectionOutput = serde_json::from_value(output)
+            .map_err(|e| SecurityError::PluginError(format!("'{}' returned {}", name, e)))?;
+        Ok(output.matched)
+    }
+}
+
+/// Background loop picking up activations made anywhere.
+pub async fn run_refresh(state: web::Data<AppState>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(state.config.plugins.refresh_interval_secs));
+
+    loop {
+        interval.tick().await;
+        match state.plugins.refresh().await {
+            Ok(()) => state.startup.recovered("plugins"),
+            Err(e) => error!("Plugin refresh failed: {:?}", e),
+        }
+    }
+}
+
+#[cfg(feature = "plugins")]
+mod runtime {
+    use wasmtime::{Caller, Engine, Extern, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+    use super::HOST_FUNCTIONS;
+    use crate::config::PluginsConfig;
+    use crate::errors::SecurityError;
+
+    /// Log lines one call may write.
+    const MAX_LOG_LINES: usize = 100;
+    const MAX_LOG_BYTES: usize = 4096;
+
+    struct Host {
+        plugin: String,
+        now_ms: i64,
+        logged: usize,
+        limits: StoreLimits,
+    }
+
+    pub(super) struct Runtime {
+        engine: Engine,
+        linker: Linker<Host>,
+    }
+
+    fn log(mut caller: Caller<'_, Host>, level: i32, ptr: i32, len: i32) {
+        if caller.data().logged >= MAX_LOG_LINES {
+            return;
+        }
+        caller.data_mut().logged += 1;
+        let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+            return;
+        };
+        let mut message = vec![0; (len.max(0) as usize).min(MAX_LOG_BYTES)];
+        if memory.read(&caller, ptr as u32 as usize, &mut message).is_err() {
+            return;
+        }
+        let message = String::from_utf8_lossy(&message);
+        let plugin = &caller.data().plugin;
+        match level {
+            0 => tracing::debug!(plugin = %plugin, "{}", message),
+            1 => tracing::info!(plugin = %plugin, "{}", message),
+            2 => tracing::warn!(plugin = %plugin, "{}", message),
+            _ => tracing::error!(plugin = %plugin, "{}", message),
+        }
+    }
+
+    impl Runtime {
+        pub(super) fn new() -> Result<Self, SecurityError> {
+            let mut config = wasmtime::Config::new();
+            config.consume_fuel(true);
+            let engine = Engine::new(&config).map_err(|e| SecurityError::ConfigError(format!("Plugin engine: {}", e)))?;
+            let mut linker = Linker::new(&engine);
+            linker
+                .func_wrap("cotai", "log", log)
+                .and_then(|linker| linker.func_wrap("cotai", "now_ms", |caller: Caller<'_, Host>| caller.data().now_ms))
+                .map_err(|e| SecurityError::ConfigError(format!("Plugin host API: {}", e)))?;
+            Ok(Self { engine, linker })
+        }
+
+        /// Compile and check the imports and exports.
+        pub(super) fn compile(&self, wasm: &[u8]) -> Result<Module, String> {
+            let module = Module::new(&self.engine, wasm).map_err(|e| format!("Invalid module: {}", e))?;
+            for import in module.imports() {
+                if import.module() != "cotai" || !HOST_FUNCTIONS.contains(&import.name()) {
+                    return Err(format!("Imports {}.{}, which the host does not offer", import.module(), import.name()));
+                }
+            }
+            for export in ["memory", "alloc", "evaluate"] {
+                if module.get_export(export).is_none() {
+                    return Err(format!("Does not export '{}'", export));
+                }
+            }
+            Ok(module)
+        }
+
+        pub(super) fn call(
+            &self,
+            module: &Module,
+            plugin: &str,
+            input: &[u8],
+            config: &PluginsConfig,
+            now_ms: i64,
+        ) -> Result<Vec<u8>, String> {
+            let limits = StoreLimitsBuilder::new()
+                .memory_size(config.max_memory_bytes)
+                .instances(1)
+                .memories(1)
+                .tables(1)
+                .build();
+            let host = Host { plugin: plugin.to_string(), now_ms, logged: 0, limits };
+            let mut store = Store::new(&self.engine, host);
+            store.limiter(|host| &mut host.limits);
+            store.set_fuel(config.max_fuel).map_err(|e| e.to_string())?;
+
+            let instance = self.linker.instantiate(&mut store, module).map_err(|e| e.to_string())?;
+            let memory = instance.get_memory(&mut store, "memory").ok_or("No memory export")?;
+            let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc").map_err(|e| e.to_string())?;
+            let evaluate = instance
+                .get_typed_func::<(i32, i32), i64>(&mut store, "evaluate")
+                .map_err(|e| e.to_string())?;
+
+            let len = i32::try_from(input.len()).map_err(|_| "Input too large")?;
+            let ptr = alloc.call(&mut store, len).map_err(|e| e.to_string())?;
+            memory.write(&mut store, ptr as u32 as usize, input).map_err(|e| e.to_string())?;
+            let packed = evaluate.call(&mut store, (ptr, len)).map_err(|e| e.to_string())?;
+
+            let (out_ptr, out_len) = ((packed >> 32) as u32 as usize, packed as u32 as usize);
+            if out_len > config.max_output_bytes {
+                return Err(format!("Output of {} bytes is over {}", out_len, config.max_output_bytes));
+            }
+            let mut output = vec![0; out_len];
+            memory.read(&store, out_ptr, &mut output).map_err(|e| e.to_string())?;
+            Ok(output)
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::PluginError(msg) => HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Plugin operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Plugin operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit_plugin(state: &AppState, actor: &Principal, action: &str, name: &str, detail: Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: actor.tenant_id.clone(),
+        actor: actor.subject.clone(),
+        actor_ip: None,
+        action: action.to_string(),
+        resource: format!("plugin:{}", name),
+        outcome: "success".to_string(),
+        payload: detail,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} on plugin {}: {:?}", action, name, e);
+    }
+}
+
+/// Refresh after a change so this replica serves it at once.
+async fn refresh_now(state: &AppState) {
+    if let Err(e) = state.plugins.refresh().await {
+        warn!("Plugin refresh after change failed: {:?}", e);
+    }
+}
+
+pub async fn list_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+    match state.plugins.list().await {
+        Ok(plugins) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "plugins": plugins
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+    match state.plugins.versions(&path).await {
+        Ok(versions) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "versions": versions
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn upload_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    request: web::Json<UploadRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let name = path.into_inner();
+    match state.plugins.upload(&principal, &name, request.into_inner()).await {
+        Ok(version) => {
+            audit_plugin(&state, &principal, "plugin.upload", &name, serde_json::json!({
+                "version": version.version,
+                "kind": version.kind,
+                "sha256": version.sha256,
+                "active": version.active
+            })).await;
+            if version.active {
+                refresh_now(&state).await;
+            }
+            Ok(HttpResponse::Created().json(version))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn activate_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    request: web::Json<ActivateRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let name = path.into_inner();
+    match state.plugins.activate(&principal, &name, Some(request.version)).await {
+        Ok(versions) => {
+            audit_plugin(&state, &principal, "plugin.activate", &name, serde_json::json!({
+                "version": request.version
+            })).await;
+            refresh_now(&state).await;
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "versions": versions
+            })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn deactivate_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let name = path.into_inner();
+    match state.plugins.activate(&principal, &name, None).await {
+        Ok(versions) => {
+            audit_plugin(&state, &principal, "plugin.deactivate", &name, serde_json::json!({})).await;
+            refresh_now(&state).await;
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "versions": versions
+            })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+/// Run a version on a given input and return its raw output.
+pub async fn test_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    request: web::Json<TestRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+    match state.plugins.test(&path, request.version, &request.input).await {
+        Ok(output) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "output": output
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+/// A whole payload through a validation plugin, reported like `/validate/schema`.
+pub async fn validate_payload_handler(
+    path: web::Path<String>,
+    request: web::Json<PayloadRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    match state.plugins.validate(&path, "$", request.into_inner().data) {
+        Ok(errors) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "valid": errors.is_empty(),
+            "errors": errors
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/plugins")
+            .route("", web::get().to(list_handler))
+            .route("/{name}", web::get().to(get_handler))
+            .route("/{name}/versions", web::post().to(upload_handler))
+            .route("/{name}/activate", web::post().to(activate_handler))
+            .route("/{name}/deactivate", web::post().to(deactivate_handler))
+            .route("/{name}/test", web::post().to(test_handler)),
+    )
+    .route("/validate/plugins/{name}", web::post().to(validate_payload_handler));
+}
@@ -0,0 +1,647 @@
+/*!
+Policy Module
+Versioned policy documents administered with optimistic concurrency
+*/
+
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, Postgres, QueryBuilder, Transaction};
+use tracing::{error, info};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::{auth_error_response, exchange, Principal};
+use crate::authz;
+use crate::changes::{self, NewChange};
+use crate::clock::Clock;
+use crate::concurrency::{self, IfMatch};
+use crate::conditional::{CacheControl, Validators};
+use crate::errors::SecurityError;
+use crate::events::{self, DomainEvent};
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::soft_delete;
+use crate::sod;
+use crate::storage::Storage;
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "name", column: "name", kind: KeyKind::Text },
+    SortField { name: "updated_at", column: "updated_at", kind: KeyKind::Timestamp },
+];
+
+const SELECT_COLUMNS: &str = "id, tenant_id, name, description, document, version, \
+    created_at, updated_at, updated_by, deleted_at";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyRequest {
+    pub tenant_id: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
+    pub document: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Policy {
+    pub id: Uuid,
+    pub tenant_id: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
+    pub document: serde_json::Value,
+    pub version: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub updated_by: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl Policy {
+    /// The client-editable part of the policy, as submitted in a `PolicyRequest`.
+    pub fn editable(&self) -> PolicyRequest {
+        PolicyRequest {
+            tenant_id: self.tenant_id.clone(),
+            name: self.name.clone(),
+            description: self.description.clone(),
+            document: self.document.clone(),
+        }
+    }
+
+    fn snapshot(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self.editable()).ok()
+    }
+
+    fn change<'a>(
+        &self,
+        action: &'a str,
+        author: &'a str,
+        before: Option<&Policy>,
+        after: Option<&Policy>,
+    ) -> NewChange<'a> {
+        NewChange {
+            resource_type: "policy",
+            resource_id: self.id.to_string(),
+            version: Some(self.version),
+            action,
+            author,
+            tenant_id: self.tenant_id.clone(),
+            before: before.and_then(Policy::snapshot),
+            after: after.and_then(Policy::snapshot),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PolicyFilter {
+    pub tenant_id: Option<String>,
+}
+
+pub(crate) fn validate(request: &PolicyRequest) -> Result<(), SecurityError> {
+    if request.name.trim().is_empty() {
+        return Err(SecurityError::ValidationError("Policy name is required".to_string()));
+    }
+    if !request.document.is_object() {
+        return Err(SecurityError::ValidationError("Policy document must be a JSON object".to_string()));
+    }
+    if request.name.trim() == exchange::POLICY_NAME {
+        exchange::validate_policy(&request.document)?;
+    }
+    if request.name.trim() == authz::POLICY_NAME {
+        authz::validate_policy(&request.document)?;
+    }
+    if request.name.trim() == sod::POLICY_NAME {
+        sod::validate_policy(&request.document)?;
+    }
+    Ok(())
+}
+
+fn map_unique(e: sqlx::Error) -> SecurityError {
+    match e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            SecurityError::Conflict("A policy with this name already exists".to_string())
+        }
+        e => e.into(),
+    }
+}
+
+pub struct PolicyService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+}
+
+impl PolicyService {
+    pub async fn new(storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+
+        info!("Policy service initialized successfully");
+        Ok(Self { storage, clock })
+    }
+
+    pub async fn create(&self, actor: &Principal, request: PolicyRequest) -> Result<Policy, SecurityError> {
+        validate(&request)?;
+
+        let mut tx = self.storage.begin().await?;
+        let policy = self.insert(&mut tx, &actor.subject, request).await?;
+        tx.commit().await?;
+
+        Ok(policy)
+    }
+
+    /// Create or replace the policy with the request's tenant and name,
+    /// inside the caller's transaction.
+    pub(crate) async fn put_in(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        author: &str,
+        request: PolicyRequest,
+    ) -> Result<Policy, SecurityError> {
+        validate(&request)?;
+
+        let current = sqlx::query_as::<_, Policy>(&format!(
+            "SELECT {} FROM policies WHERE COALESCE(tenant_id, '') = COALESCE($1, '') AND name = $2 \
+             AND deleted_at IS NULL FOR UPDATE",
+            SELECT_COLUMNS
+        ))
+        .bind(&request.tenant_id)
+        .bind(request.name.trim())
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        match current {
+            Some(current) => self.replace(tx, author, current, request, "update").await,
+            None => self.insert(tx, author, request).await,
+        }
+    }
+
+    async fn insert(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        author: &str,
+        request: PolicyRequest,
+    ) -> Result<Policy, SecurityError> {
+        let policy = sqlx::query_as::<_, Policy>(&format!(
+            "INSERT INTO policies (id, tenant_id, name, description, document, updated_by) \
+             VALUES ($1, $2, $3, $4, $5, $6) RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(&request.tenant_id)
+        .bind(request.name.trim())
+        .bind(&request.description)
+        .bind(&request.document)
+        .bind(author)
+        .fetch_one(&mut **tx)
+        .await
+        .map_err(map_unique)?;
+
+        let event = DomainEvent::new(
+            "policy.created",
+            "policy",
+            policy.id,
+            policy.tenant_id.clone(),
+            serde_json::json!({ "name": policy.name, "version": policy.version }),
+            self.clock.now(),
+        );
+        events::enqueue(tx, &event).await?;
+        changes::record(tx, policy.change("create", author, None, Some(&policy))).await?;
+
+        Ok(policy)
+    }
+
+    pub async fn list(&self, filter: &PolicyFilter, page: &PageRequest) -> Result<Page<Policy>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM policies WHERE deleted_at IS NULL",
+            SELECT_COLUMNS
+        ));
+        if let Some(tenant_id) = &filter.tenant_id {
+            builder.push(" AND tenant_id = ").push_bind(tenant_id.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let policies = builder
+            .build_query_as::<Policy>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(policies, |policy, sort| {
+            let key = match sort {
+                "updated_at" => SortKey::Timestamp(policy.updated_at),
+                _ => SortKey::Text(policy.name.clone()),
+            };
+            (key, policy.id)
+        }))
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<Policy, SecurityError> {
+        sqlx::query_as::<_, Policy>(&format!(
+            "SELECT {} FROM policies WHERE id = $1 AND deleted_at IS NULL",
+            SELECT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("Policy not found".to_string()))
+    }
+
+    /// Live policies that apply to a tenant: its own plus the global ones.
+    pub async fn manifest(&self, tenant_id: Option<&str>) -> Result<Vec<Policy>, SecurityError> {
+        let policies = sqlx::query_as::<_, Policy>(&format!(
+            "SELECT {} FROM policies \
+             WHERE deleted_at IS NULL AND (tenant_id IS NULL OR tenant_id = $1) \
+             ORDER BY name",
+            SELECT_COLUMNS
+        ))
+        .bind(tenant_id)
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        Ok(policies)
+    }
+
+    /// Replace a policy. `expected_version` of `None` means `If-Match: *`.
+    pub async fn update(
+        &self,
+        actor: &Principal,
+        id: Uuid,
+        expected_version: Option<i64>,
+        request: PolicyRequest,
+    ) -> Result<Policy, SecurityError> {
+        self.write(actor, id, expected_version, request, "update").await
+    }
+
+    /// Replace a policy with an earlier snapshot from the change history.
+    pub async fn rollback(&self, actor: &Principal, id: Uuid, request: PolicyRequest) -> Result<Policy, SecurityError> {
+        self.write(actor, id, None, request, "rollback").await
+    }
+
+    async fn write(
+        &self,
+        actor: &Principal,
+        id: Uuid,
+        expected_version: Option<i64>,
+        request: PolicyRequest,
+        action: &str,
+    ) -> Result<Policy, SecurityError> {
+        validate(&request)?;
+
+        let mut tx = self.storage.begin().await?;
+        let current = sqlx::query_as::<_, Policy>(&format!(
+            "SELECT {} FROM policies WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+            SELECT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("Policy not found".to_string()))?;
+
+        if let Some(expected) = expected_version {
+            if expected != current.version {
+                return Err(SecurityError::Conflict(format!(
+                    "Policy is at version {}, not {}",
+                    current.version, expected
+                )));
+            }
+        }
+
+        let policy = self.replace(&mut tx, &actor.subject, current, request, action).await?;
+        tx.commit().await?;
+
+        Ok(policy)
+    }
+
+    async fn replace(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        author: &str,
+        current: Policy,
+        request: PolicyRequest,
+        action: &str,
+    ) -> Result<Policy, SecurityError> {
+        let policy = sqlx::query_as::<_, Policy>(&format!(
+            "UPDATE policies SET tenant_id = $2, name = $3, description = $4, document = $5, \
+             version = version + 1, updated_at = NOW(), updated_by = $6 \
+             WHERE id = $1 RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(current.id)
+        .bind(&request.tenant_id)
+        .bind(request.name.trim())
+        .bind(&request.description)
+        .bind(&request.document)
+        .bind(author)
+        .fetch_one(&mut **tx)
+        .await
+        .map_err(map_unique)?;
+
+        let event = DomainEvent::new(
+            "policy.updated",
+            "policy",
+            policy.id,
+            policy.tenant_id.clone(),
+            serde_json::json!({ "name": policy.name, "version": policy.version }),
+            self.clock.now(),
+        );
+        events::enqueue(tx, &event).await?;
+        changes::record(tx, policy.change(action, author, Some(&current), Some(&policy))).await?;
+
+        Ok(policy)
+    }
+
+    /// Soft-delete; the policy stays restorable until purged.
+    pub async fn delete(&self, actor: &Principal, id: Uuid) -> Result<(), SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let deleted = sqlx::query_as::<_, Policy>(&format!(
+            "UPDATE policies SET deleted_at = NOW(), updated_by = $2 \
+             WHERE id = $1 AND deleted_at IS NULL RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(id)
+        .bind(&actor.subject)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| SecurityError::NotFound("Policy not found".to_string()))?;
+
+        let event = DomainEvent::new("policy.deleted", "policy", id, deleted.tenant_id.clone(), serde_json::json!({}), self.clock.now());
+        events::enqueue(&mut tx, &event).await?;
+        changes::record(&mut tx, deleted.change("delete", &actor.subject, Some(&deleted), None)).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn restore(
+        &self,
+        actor: &Principal,
+        id: Uuid,
+        restorable_since: DateTime<Utc>,
+    ) -> Result<Policy, SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let policy = sqlx::query_as::<_, Policy>(&format!(
+            "UPDATE policies SET deleted_at = NULL, version = version + 1, updated_at = NOW(), updated_by = $3 \
+             WHERE id = $1 AND deleted_at >= $2 RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(id)
+        .bind(restorable_since)
+        .bind(&actor.subject)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(map_unique)?
+        .ok_or_else(|| SecurityError::NotFound("No restorable policy".to_string()))?;
+
+        let event = DomainEvent::new(
+            "policy.restored",
+            "policy",
+            policy.id,
+            policy.tenant_id.clone(),
+            serde_json::json!({ "name": policy.name, "version": policy.version }),
+            self.clock.now(),
+        );
+        events::enqueue(&mut tx, &event).await?;
+        changes::record(&mut tx, policy.change("restore", &actor.subject, None, Some(&policy))).await?;
+        tx.commit().await?;
+
+        Ok(policy)
+    }
+
+    /// Hard-delete policies soft-deleted before `cutoff`.
+    pub async fn purge_deleted(&self, cutoff: DateTime<Utc>) -> Result<u64, SecurityError> {
+        let result = sqlx::query("DELETE FROM policies WHERE deleted_at < $1")
+            .bind(cutoff)
+            .execute(self.storage.pool())
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Policy operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Policy operation failed"
+            }))
+        }
+    }
+}
+
+fn policy_response(mut response: actix_web::HttpResponseBuilder, policy: &Policy) -> HttpResponse {
+    response
+        .insert_header((header::ETAG, concurrency::etag(policy.version)))
+        .json(policy)
+}
+
+pub async fn create_policy_handler(
+    req: HttpRequest,
+    request: web::Json<PolicyRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.policy_service.create(&principal, request.into_inner()).await {
+        Ok(policy) => Ok(policy_response(HttpResponse::Created(), &policy)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_policies_handler(
+    req: HttpRequest,
+    filter: web::Query<PolicyFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Asc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.policy_service.list(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "policies": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_policy_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.policy_service.get(path.into_inner()).await {
+        Ok(policy) => Ok(Validators::new(concurrency::etag(policy.version))
+            .last_modified(policy.updated_at)
+            .respond(&req, CacheControl::Revalidate, &policy)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestEntry {
+    pub id: Uuid,
+    pub name: String,
+    pub version: i64,
+    pub updated_at: DateTime<Utc>,
+    pub document: serde_json::Value,
+}
+
+/// Policies in force for the caller's tenant, for verifiers that evaluate locally.
+/// Served with validators so pollers mostly get 304s.
+pub async fn manifest_handler(
+    req: HttpRequest,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let policies = match state.policy_service.manifest(principal.tenant_id.as_deref()).await {
+        Ok(policies) => policies,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    // The id/version set changes whenever any entry does, including deletions
+    let mut hasher = Sha256::new();
+    for policy in &policies {
+        hasher.update(format!("{}:{};", policy.id, policy.version).as_bytes());
+    }
+    let etag = format!("\"m{}\"", &hex::encode(hasher.finalize())[..16]);
+    let mut validators = Validators::new(etag);
+    if let Some(modified) = policies.iter().map(|p| p.updated_at).max() {
+        validators = validators.last_modified(modified);
+    }
+
+    let entries: Vec<ManifestEntry> = policies
+        .into_iter()
+        .map(|p| ManifestEntry {
+            id: p.id,
+            name: p.name,
+            version: p.version,
+            updated_at: p.updated_at,
+            document: p.document,
+        })
+        .collect();
+
+    Ok(validators.respond(
+        &req,
+        CacheControl::Private(&state.config.http_cache),
+        &serde_json::json!({ "policies": entries }),
+    ))
+}
+
+pub async fn update_policy_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<PolicyRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let expected_version = match IfMatch::from_request(&req).expected_version() {
+        Ok(version) => version,
+        Err(response) => return Ok(response),
+    };
+
+    let id = path.into_inner();
+    let request = request.into_inner();
+    let service = &state.policy_service;
+    match service.update(&principal, id, expected_version, request.clone()).await {
+        Ok(policy) => Ok(policy_response(HttpResponse::Ok(), &policy)),
+        Err(SecurityError::Conflict(msg)) => {
+            let current = match service.get(id).await {
+                Ok(current) => current,
+                Err(e) => return Ok(error_response(e)),
+            };
+            if expected_version.is_none_or(|v| v == current.version) {
+                // Not a version race, e.g. a duplicate name
+                return Ok(error_response(SecurityError::Conflict(msg)));
+            }
+
+            // Show the caller what changed underneath them
+            let before = serde_json::to_value(current.editable()).unwrap_or_default();
+            let after = serde_json::to_value(&request).unwrap_or_default();
+            Ok(HttpResponse::Conflict()
+                .insert_header((header::ETAG, concurrency::etag(current.version)))
+                .json(serde_json::json!({
+                    "error": msg,
+                    "current_version": current.version,
+                    "diff": concurrency::json_diff(&before, &after)
+                })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn delete_policy_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.policy_service.delete(&principal, path.into_inner()).await {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn restore_policy_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let since = soft_delete::restorable_since(&state.config, state.clock.now());
+    match state.policy_service.restore(&principal, path.into_inner(), since).await {
+        Ok(policy) => Ok(policy_response(HttpResponse::Ok(), &policy)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/policies/manifest", web::get().to(manifest_handler))
+        .service(
+            web::scope("/admin/policies")
+                .route("", web::post().to(create_policy_handler))
+                .route("", web::get().to(list_policies_handler))
+                .route("/{id}", web::get().to(get_policy_handler))
+                .route("/{id}", web::put().to(update_policy_handler))
+                .route("/{id}", web::delete().to(delete_policy_handler))
+                .route("/{id}/restore", web::post().to(restore_policy_handler))
+        );
+}
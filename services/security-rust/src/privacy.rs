@@ -0,0 +1,226 @@
+/*!
+Differential Privacy
+Laplace noise and small-count suppression for aggregates shown outside the SOC
+
+A count of a small tenant's login failures or decryptions can single out
+a person. Aggregates released to anyone without an `AUDIT_SOC_ROLES` role
+go through `NoiseLayer`: each count gets Laplace noise of scale
+`1 / DP_EPSILON` (one event moves a count by at most one), is rounded and
+clamped at zero, and is withheld as `null` when the noisy value is under
+`DP_MIN_COUNT`. Rates are derived from noisy counts only, and are `null`
+when either side is withheld.
+
+Noise is not drawn afresh per request: it is derived from an HMAC of the
+count's description (statistic, tenant, window and group) under a key
+derived from `AUDIT_PSEUDONYMIZATION_KEY`, so asking the same question
+again gives the same answer. The window is first widened to whole days in
+the tenant's time zone (`bucket`), and the widened window is both what is
+counted and what keys the noise, so windows shifted within the same days
+get the same release rather than fresh noise to average away.
+
+Windows over different days are different questions, and each one spends
+privacy budget: a report's cost is `DP_EPSILON` times the number of its
+counts a single event can move. `spend` records every new release per
+tenant in `dp_releases`, and refuses one once the releases overlapping its
+window would have spent more than `DP_EPSILON_BUDGET` for any tenant in
+it. Asking for a window already released costs nothing.
+*/
+
+use actix_web::HttpResponse;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use ring::hmac;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+use crate::timezone;
+
+/// A count as released: `None` when withheld.
+pub type Released = Option<i64>;
+
+/// What callers are told about the noise, so they can reason about it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Disclosure {
+    pub mechanism: &'static str,
+    pub epsilon: f64,
+    pub min_count: i64,
+    pub budget: f64,
+}
+
+pub struct NoiseLayer {
+    key: hmac::Key,
+    epsilon: f64,
+    min_count: i64,
+    budget: f64,
+}
+
+/// The answer when `NoiseLayer::spend` refuses a release.
+pub fn budget_spent() -> HttpResponse {
+    HttpResponse::TooManyRequests().json(serde_json::json!({
+        "error": "Privacy budget spent for this window; ask for a window already released or later days"
+    }))
+}
+
+/// `[since, until)` widened to whole days in `tz`: the window noised counts
+/// are released for.
+pub fn bucket(tz: Tz, since: DateTime<Utc>, until: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+    let start = timezone::day_start(tz, timezone::local_date(tz, since));
+    let last = timezone::local_date(tz, until);
+    let end = match timezone::day_start(tz, last) {
+        end if end == until => end,
+        _ => timezone::day_start(tz, last.succ_opt().unwrap_or(last)),
+    };
+    (start, end)
+}
+
+impl NoiseLayer {
+    pub fn new(config: &Config) -> Self {
+        let root = hmac::Key::new(hmac::HMAC_SHA256, config.audit.pseudonymization_key.as_bytes());
+        let derived = hmac::sign(&root, b"cotai-dp-noise");
+        Self {
+            key: hmac::Key::new(hmac::HMAC_SHA256, derived.as_ref()),
+            epsilon: config.privacy.epsilon,
+            min_count: config.privacy.min_count,
+            budget: config.privacy.budget,
+        }
+    }
+
+    pub fn disclosure(&self) -> Disclosure {
+        Disclosure {
+            mechanism: "laplace",
+            epsilon: self.epsilon,
+            min_count: self.min_count,
+            budget: self.budget,
+        }
+    }
+
+    /// Record the release of `question` over the bucketed `[since, until)`
+    /// for `tenants` (`""` for events without one), where one event can
+    /// move `counts` of its counts. `false` means a tenant's budget would be
+    /// overspent and nothing may be released.
+    pub async fn spend(
+        &self,
+        storage: &Storage,
+        question: &str,
+        tenants: &[String],
+        counts: u32,
+        (since, until): (DateTime<Utc>, DateTime<Utc>),
+        now: DateTime<Utc>,
+    ) -> Result<bool, SecurityError> {
+        let question = format!("{}:{}:{}", question, since.timestamp(), until.timestamp());
+        let epsilon = self.epsilon * counts as f64;
+        let mut tx = storage.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext('dp_releases'))").execute(&mut *tx).await?;
+        let overspent: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM unnest($1::text[]) AS t(tenant_id) \
+             WHERE NOT EXISTS (SELECT 1 FROM dp_releases r WHERE r.tenant_id = t.tenant_id AND r.question = $2) \
+             AND $5 + (SELECT COALESCE(SUM(r.epsilon), 0) FROM dp_releases r \
+             WHERE r.tenant_id = t.tenant_id AND r.since < $4 AND r.until > $3) > $6)",
+        )
+        .bind(tenants)
+        .bind(&question)
+        .bind(since)
+        .bind(until)
+        .bind(epsilon)
+        .bind(self.budget)
+        .fetch_one(&mut *tx)
+        .await?;
+        if overspent {
+            return Ok(false);
+        }
+        sqlx::query(
+            "INSERT INTO dp_releases (tenant_id, question, since, until, epsilon, released_at) \
+             SELECT t, $2, $3, $4, $5, $6 FROM unnest($1::text[]) AS t ON CONFLICT DO NOTHING",
+        )
+        .bind(tenants)
+        .bind(&question)
+        .bind(since)
+        .bind(until)
+        .bind(epsilon)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Laplace noise for the count described by `cell`, the same every time.
+    fn noise(&self, cell: &str) -> f64 {
+        let tag = hmac::sign(&self.key, cell.as_bytes());
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&tag.as_ref()[..8]);
+        // Uniform in (-0.5, 0.5), never at either end
+        let u = ((u64::from_be_bytes(bytes) >> 11) as f64 + 0.5) / (1u64 << 53) as f64 - 0.5;
+        -u.signum() * (1.0 - 2.0 * u.abs()).ln() / self.epsilon
+    }
+
+    /// `count` as released for `cell`.
+    pub fn count(&self, cell: &str, count: i64) -> Released {
+        let noisy = (count as f64 + self.noise(cell)).round().max(0.0) as i64;
+        (noisy >= self.min_count).then_some(noisy)
+    }
+
+    /// `numerator / denominator` from released counts.
+    pub fn rate(numerator: Released, denominator: Released) -> Option<f64> {
+        match (numerator, denominator) {
+            (Some(n), Some(d)) if d > 0 => Some((n as f64 / d as f64).min(1.0)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 3, day, hour, minute, 0).unwrap()
+    }
+
+    /// A count released the way the handlers do it: over the bucketed window.
+    fn release(noise: &NoiseLayer, since: DateTime<Utc>, until: DateTime<Utc>, count: i64) -> Released {
+        let (since, until) = bucket(Tz::UTC, since, until);
+        noise.count(&format!("audit.stats.decrypts:t1:{}:{}", since.timestamp(), until.timestamp()), count)
+    }
+
+    #[test]
+    fn windows_widen_to_whole_local_days() {
+        assert_eq!(bucket(Tz::UTC, at(2, 10, 15), at(5, 9, 0)), (at(2, 0, 0), at(6, 0, 0)));
+        assert_eq!(bucket(Tz::UTC, at(2, 0, 0), at(6, 0, 0)), (at(2, 0, 0), at(6, 0, 0)));
+
+        // Sao Paulo is three hours behind UTC: its days start at 03:00Z
+        let zone = timezone::parse_zone("America/Sao_Paulo").unwrap();
+        assert_eq!(bucket(zone, at(2, 1, 0), at(2, 4, 0)), (at(1, 3, 0), at(3, 3, 0)));
+        let (since, until) = timezone::period_bounds(zone, timezone::Period::Day, at(10, 12, 0).date_naive());
+        assert_eq!(bucket(zone, since, until), (since, until));
+    }
+
+    #[test]
+    fn shifted_windows_get_the_same_release() {
+        let noise = NoiseLayer::new(&Config::for_tests(&[("DP_MIN_COUNT", "0")]));
+        let first = release(&noise, at(2, 0, 0), at(6, 0, 0), 500);
+        for minutes in [1, 17, 60, 600, 1439] {
+            let shift = Duration::minutes(minutes);
+            assert_eq!(release(&noise, at(2, 0, 0) + shift, at(5, 0, 0) + shift, 500), first);
+        }
+    }
+
+    #[test]
+    fn different_days_are_different_questions() {
+        let noise = NoiseLayer::new(&Config::for_tests(&[("DP_MIN_COUNT", "0")]));
+        let releases: Vec<Released> = (2..12).map(|day| release(&noise, at(day, 0, 0), at(day + 1, 0, 0), 500)).collect();
+        assert!(releases.iter().any(|released| *released != releases[0]));
+    }
+
+    #[test]
+    fn small_counts_are_withheld() {
+        let noise = NoiseLayer::new(&Config::for_tests(&[("DP_EPSILON", "1000"), ("DP_EPSILON_BUDGET", "100000"), ("DP_MIN_COUNT", "10")]));
+        assert_eq!(release(&noise, at(2, 0, 0), at(3, 0, 0), 3), None);
+        assert_eq!(release(&noise, at(2, 0, 0), at(3, 0, 0), 50), Some(50));
+        assert_eq!(NoiseLayer::rate(Some(5), None), None);
+        assert_eq!(NoiseLayer::rate(Some(5), Some(20)), Some(0.25));
+    }
+}
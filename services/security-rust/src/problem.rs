@@ -0,0 +1,173 @@
+/*!
+Problem Details
+Error responses as RFC 9457 `application/problem+json`
+
+Handlers answer errors with `{"error": "...", "code": "...", ...}`. On the
+way out this middleware rewrites any 4xx or 5xx response into a problem
+document when the client's `Accept` lists `application/problem+json`, or
+for every client with `PROBLEM_DETAILS=always`:
+
+```json
+{"type": "urn:cotai:problem:rate_limited", "title": "Too Many Requests",
+ "status": 429, "detail": "Rate limit exceeded", "instance": "/api/v1/crypto/encrypt",
+ "code": "rate_limited", "retry_after": 12}
+```
+
+- `type` is `PROBLEM_TYPE_BASE` followed by the response's `code`, or for
+  responses without one a code standing for the status (`not_found`,
+  `validation_error`, ...)
+- `detail` is the handler's `error` (or `message`), or a plain-text body
+- every other member of the handler's body is kept as an extension, such
+  as the field errors under `errors`
+- `retry_after` repeats a `Retry-After` header in seconds
+
+Bodies larger than `MAX_BODY_BYTES`, or already problem documents, pass
+untouched, as does everything in the default `negotiate` mode for clients
+not asking; those responses carry `Vary: Accept`.
+*/
+
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderMap, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use serde_json::{Map, Value};
+
+use crate::config::ProblemConfig;
+use crate::AppState;
+
+pub const CONTENT_TYPE: &str = "application/problem+json";
+
+/// Largest error body rewritten.
+const MAX_BODY_BYTES: u64 = 64 * 1024;
+
+/// Members of a problem document a handler's body cannot replace.
+const STANDARD_MEMBERS: &[&str] = &["type", "title", "status", "detail", "instance"];
+
+/// The code of responses that do not name one.
+pub fn status_code(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "validation_error",
+        StatusCode::UNAUTHORIZED => "unauthenticated",
+        StatusCode::FORBIDDEN => "forbidden",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+        StatusCode::CONFLICT => "conflict",
+        StatusCode::GONE => "gone",
+        StatusCode::PRECONDITION_FAILED => "precondition_failed",
+        StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+        StatusCode::UNPROCESSABLE_ENTITY => "unprocessable",
+        StatusCode::LOCKED => "locked",
+        StatusCode::PRECONDITION_REQUIRED => "precondition_required",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limited",
+        StatusCode::SERVICE_UNAVAILABLE => "unavailable",
+        StatusCode::GATEWAY_TIMEOUT => "timeout",
+        s if s.is_client_error() => "client_error",
+        _ => "internal_error",
+    }
+}
+
+/// Whether `Accept` asks for problem documents.
+fn accepts_problem(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|range| {
+            let mut pieces = range.split(';');
+            let mime = pieces.next().unwrap_or("").trim();
+            let refused = pieces.any(|p| matches!(p.trim(), "q=0" | "q=0.0" | "q=0.00" | "q=0.000"));
+            mime.eq_ignore_ascii_case(CONTENT_TYPE) && !refused
+        })
+}
+
+/// The problem document for an error response's status, headers and body.
+pub fn document(config: &ProblemConfig, status: StatusCode, headers: &HeaderMap, path: &str, body: &[u8]) -> Value {
+    let mut members = Map::new();
+    let mut detail = None;
+    let mut code = None;
+    match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Object(fields)) => {
+            for (name, value) in fields {
+                match (name.as_str(), value) {
+                    ("error" | "message", Value::String(text)) if detail.is_none() => detail = Some(text),
+                    ("code", Value::String(text)) => code = Some(text),
+                    (name, _) if STANDARD_MEMBERS.contains(&name) => {}
+                    (_, value) => {
+                        members.insert(name, value);
+                    }
+                }
+            }
+        }
+        Ok(_) => {}
+        Err(_) => {
+            let text = String::from_utf8_lossy(body).trim().to_string();
+            if !text.is_empty() {
+                detail = Some(text);
+            }
+        }
+    }
+    let code = code.unwrap_or_else(|| status_code(status).to_string());
+
+    members.insert("type".to_string(), Value::from(format!("{}{}", config.type_base, code)));
+    members.insert("title".to_string(), Value::from(status.canonical_reason().unwrap_or("Error")));
+    members.insert("status".to_string(), Value::from(status.as_u16()));
+    if let Some(detail) = detail {
+        members.insert("detail".to_string(), Value::from(detail));
+    }
+    members.insert("instance".to_string(), Value::from(path));
+    members.insert("code".to_string(), Value::from(code));
+    let retry_after = headers
+        .get(header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+    if let Some(secs) = retry_after {
+        members.insert("retry_after".to_string(), Value::from(secs));
+    }
+    Value::Object(members)
+}
+
+/// Middleware rewriting error responses; see the module docs.
+pub async fn render(
+    state: web::Data<AppState>,
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let config = &state.config.problem;
+    let wanted = config.mode == "always" || accepts_problem(req.headers());
+    let mut res = next.call(req).await?;
+
+    let status = res.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return Ok(res);
+    }
+    if config.mode != "always" {
+        res.headers_mut().append(header::VARY, HeaderValue::from_static("Accept"));
+    }
+    let small = match res.response().body().size() {
+        BodySize::None => true,
+        BodySize::Sized(size) => size <= MAX_BODY_BYTES,
+        BodySize::Stream => false,
+    };
+    let already = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with(CONTENT_TYPE));
+    if !wanted || !small || already {
+        return Ok(res);
+    }
+
+    let path = res.request().path().to_string();
+    let (request, response) = res.into_parts();
+    let (mut response, body) = response.into_parts();
+    let bytes = actix_web::body::to_bytes(body)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
+    let problem = document(config, status, response.headers(), &path, &bytes);
+    response.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE));
+    let response = response.set_body(BoxBody::new(problem.to_string()));
+    Ok(ServiceResponse::new(request, response))
+}
@@ -0,0 +1,401 @@
+/*!
+PROXY Protocol
+Public listener for load balancers that pass the client address in a PROXY header
+
+With `PROXY_PROTOCOL` on, every connection to the public listener must
+start with a PROXY protocol header, version 1 (text) or 2 (binary), and
+come from one of `TRUSTED_PROXIES`; others are closed before any HTTP is
+read. The header's source address becomes the request's peer address, so
+`network` treats it as it would a direct connection. `LOCAL` headers,
+which load balancers send for their own health checks, keep the real peer.
+
+The header must arrive within `PROXY_PROTOCOL_TIMEOUT_MS`. TLS, HTTP/2 and
+the server tuning work as on the ordinary listener.
+*/
+
+use actix_http::body::{BoxBody, MessageBody};
+use actix_http::{HttpService, KeepAlive, Protocol, Request, Response};
+use actix_server::Server;
+use actix_service::{fn_service, map_config, IntoServiceFactory, ServiceFactory, ServiceFactoryExt};
+use actix_tls::accept::rustls_0_21::{Acceptor, TlsStream};
+use actix_web::dev::{AppConfig, Extensions};
+use actix_web::rt::net::{ActixStream, Ready, TcpStream};
+use std::any::Any;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tracing::debug;
+
+use crate::config::{NetworkConfig, ServerConfig};
+use crate::network::{normalize, Cidr};
+use crate::tls;
+
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+/// Longest version 1 header, CRLF included.
+const V1_MAX_LENGTH: usize = 107;
+const HTTP2_PREFACE: &[u8] = b"PRI * HTTP/2.0";
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("PROXY protocol: {}", message))
+}
+
+/// The source a version 1 header names; `None` for `UNKNOWN`.
+fn parse_v1(line: &str) -> io::Result<Option<SocketAddr>> {
+    let parts: Vec<&str> = line.trim_end_matches("\r\n").split(' ').collect();
+    match parts.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", source, _, port, _] => {
+            let ip = source.parse::<IpAddr>().map_err(|_| invalid("bad source address"))?;
+            let port = port.parse::<u16>().map_err(|_| invalid("bad source port"))?;
+            Ok(Some(SocketAddr::new(normalize(ip), port)))
+        }
+        _ => Err(invalid("malformed version 1 header")),
+    }
+}
+
+/// The source a version 2 header names, from the command byte on; `None`
+/// for `LOCAL` and address families other than TCP over IPv4 or IPv6.
+fn parse_v2(command: u8, family: u8, addresses: &[u8]) -> io::Result<Option<SocketAddr>> {
+    if command >> 4 != 2 {
+        return Err(invalid("unsupported version"));
+    }
+    match command & 0x0f {
+        0 => return Ok(None),
+        1 => {}
+        _ => return Err(invalid("unknown command")),
+    }
+    match family {
+        0x11 if addresses.len() >= 12 => {
+            let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Ok(Some(SocketAddr::new(IpAddr::V4(ip), port)))
+        }
+        0x21 if addresses.len() >= 36 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&addresses[..16]);
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Ok(Some(SocketAddr::new(normalize(IpAddr::V6(Ipv6Addr::from(octets))), port)))
+        }
+        0x11 | 0x21 => Err(invalid("truncated addresses")),
+        _ => Ok(None),
+    }
+}
+
+/// Read exactly the header off `stream`, leaving the HTTP bytes after it.
+async fn read_header(stream: &mut TcpStream) -> io::Result<Option<SocketAddr>> {
+    let mut start = [0u8; 12];
+    stream.read_exact(&mut start).await?;
+
+    if &start == V2_SIGNATURE {
+        let mut fixed = [0u8; 4];
+        stream.read_exact(&mut fixed).await?;
+        let length = u16::from_be_bytes([fixed[2], fixed[3]]) as usize;
+        let mut addresses = vec![0u8; length];
+        stream.read_exact(&mut addresses).await?;
+        return parse_v2(fixed[0], fixed[1], &addresses);
+    }
+
+    if !start.starts_with(b"PROXY ") {
+        return Err(invalid("missing header"));
+    }
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LENGTH {
+            return Err(invalid("version 1 header too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+    parse_v1(std::str::from_utf8(&line).map_err(|_| invalid("version 1 header is not text"))?)
+}
+
+/// A connection whose PROXY header has been read.
+pub struct Proxied {
+    stream: TcpStream,
+    /// The client the load balancer reported, else the peer itself.
+    pub source: Option<SocketAddr>,
+}
+
+impl Proxied {
+    async fn accept(mut stream: TcpStream, trusted: &[Cidr], timeout: Duration) -> io::Result<Self> {
+        let peer = stream.peer_addr()?;
+        if !trusted.iter().any(|cidr| cidr.contains(peer.ip())) {
+            return Err(invalid(&format!("{} is not a trusted proxy", peer.ip())));
+        }
+        let source = tokio::time::timeout(timeout, read_header(&mut stream))
+            .await
+            .map_err(|_| invalid("timed out waiting for the header"))??;
+        Ok(Self { stream, source: source.or(Some(peer)) })
+    }
+
+    async fn protocol(&self, http2_cleartext: bool) -> Protocol {
+        let mut preface = [0u8; 14];
+        if http2_cleartext
+            && matches!(self.stream.peek(&mut preface).await, Ok(read) if read == preface.len())
+            && preface == HTTP2_PREFACE
+        {
+            Protocol::Http2
+        } else {
+            Protocol::Http1
+        }
+    }
+}
+
+impl ActixStream for Proxied {
+    fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<Ready>> {
+        ActixStream::poll_read_ready(&self.stream, cx)
+    }
+
+    fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<Ready>> {
+        ActixStream::poll_write_ready(&self.stream, cx)
+    }
+}
+
+impl AsyncRead for Proxied {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Proxied {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+fn dropped<E: std::fmt::Debug>(e: E) -> actix_http::error::DispatchError {
+    debug!("Connection dropped: {:?}", e);
+    actix_http::error::DispatchError::Io(io::Error::other(format!("{:?}", e)))
+}
+
+/// The public listener, reading a PROXY header off each connection.
+pub fn server<F, I, S, B>(
+    app: F,
+    addr: &str,
+    tuning: &ServerConfig,
+    tls_config: Option<rustls::ServerConfig>,
+    network: &NetworkConfig,
+) -> io::Result<Server>
+where
+    F: Fn() -> I + Send + Clone + 'static,
+    I: IntoServiceFactory<S, Request>,
+    S: ServiceFactory<Request, Config = AppConfig> + 'static,
+    S::Future: 'static,
+    S::Error: Into<Response<BoxBody>> + 'static,
+    S::InitError: std::fmt::Debug,
+    S::Response: Into<Response<B>> + 'static,
+    <S::Service as actix_service::Service<Request>>::Future: 'static,
+    B: MessageBody + 'static,
+{
+    let trusted: Arc<Vec<Cidr>> = Arc::new(
+        network
+            .trusted_proxies
+            .iter()
+            .filter_map(|cidr| cidr.parse().ok())
+            .collect(),
+    );
+    let timeout = Duration::from_millis(network.proxy_protocol_timeout_ms);
+    let tuning = tuning.clone();
+    let keep_alive = match tuning.keep_alive_secs {
+        0 => KeepAlive::Disabled,
+        secs => KeepAlive::Timeout(Duration::from_secs(secs)),
+    };
+
+    let mut builder = Server::build()
+        .backlog(tuning.backlog)
+        .max_concurrent_connections(tuning.max_connections)
+        .shutdown_timeout(tuning.shutdown_timeout_secs)
+        .disable_signals();
+    if tuning.workers > 0 {
+        builder = builder.workers(tuning.workers);
+    }
+
+    let builder = match tls_config {
+        None => builder.bind("cotai-security-proxied", addr, move || {
+            let trusted = trusted.clone();
+            let http2_cleartext = tuning.http2_cleartext;
+            let http = HttpService::build()
+                .keep_alive(keep_alive)
+                .client_request_timeout(Duration::from_millis(tuning.client_request_timeout_ms))
+                .client_disconnect_timeout(Duration::from_millis(tuning.client_disconnect_timeout_ms))
+                .finish(map_config(app(), |_| AppConfig::default()));
+            fn_service(move |stream: TcpStream| {
+                let trusted = trusted.clone();
+                async move {
+                    let stream = Proxied::accept(stream, &trusted, timeout).await.map_err(dropped)?;
+                    let protocol = stream.protocol(http2_cleartext).await;
+                    let source = stream.source;
+                    Ok((stream, protocol, source))
+                }
+            })
+            .and_then(http)
+        })?,
+        Some(tls_config) => {
+            actix_tls::accept::max_concurrent_tls_connect(tuning.max_connection_rate);
+            builder.bind("cotai-security-proxied", addr, move || {
+                let trusted = trusted.clone();
+                let http = HttpService::build()
+                    .keep_alive(keep_alive)
+                    .client_request_timeout(Duration::from_millis(tuning.client_request_timeout_ms))
+                    .client_disconnect_timeout(Duration::from_millis(tuning.client_disconnect_timeout_ms))
+                    .secure()
+                    .on_connect_ext(|io: &TlsStream<Proxied>, extensions: &mut Extensions| {
+                        tls::on_connect(io as &dyn Any, extensions)
+                    })
+                    .finish(map_config(app(), |_| AppConfig::default()));
+                fn_service(move |stream: TcpStream| {
+                    let trusted = trusted.clone();
+                    async move { Proxied::accept(stream, &trusted, timeout).await.map_err(dropped) }
+                })
+                .and_then(Acceptor::new(tls_config.clone()).map_err(dropped))
+                .and_then(fn_service(|stream: TlsStream<Proxied>| async move {
+                    let (proxied, session) = stream.get_ref();
+                    let protocol = match session.alpn_protocol() {
+                        Some(b"h2") => Protocol::Http2,
+                        _ => Protocol::Http1,
+                    };
+                    let source = proxied.source;
+                    Ok((stream, protocol, source))
+                }))
+                .and_then(http)
+            })?
+        }
+    };
+    Ok(builder.run())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    /// What `read_header` makes of `bytes`, and what it left unread.
+    async fn read(bytes: &[u8]) -> (io::Result<Option<SocketAddr>>, Vec<u8>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (mut server, _) = listener.accept().await.unwrap();
+        client.write_all(bytes).await.unwrap();
+        client.shutdown().await.unwrap();
+        let header = read_header(&mut server).await;
+        let mut rest = Vec::new();
+        server.read_to_end(&mut rest).await.unwrap();
+        (header, rest)
+    }
+
+    fn v2(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend([command, family]);
+        header.extend((addresses.len() as u16).to_be_bytes());
+        header.extend(addresses);
+        header
+    }
+
+    #[test]
+    fn version_1_headers_name_the_source() {
+        assert_eq!(
+            parse_v1("PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n").unwrap(),
+            Some("192.0.2.1:56324".parse().unwrap())
+        );
+        assert_eq!(
+            parse_v1("PROXY TCP6 ::ffff:192.0.2.1 ::1 56324 443\r\n").unwrap(),
+            Some("192.0.2.1:56324".parse().unwrap())
+        );
+        assert_eq!(parse_v1("PROXY UNKNOWN\r\n").unwrap(), None);
+        assert!(parse_v1("PROXY TCP4 192.0.2.1 198.51.100.1 56324\r\n").is_err());
+        assert!(parse_v1("PROXY TCP4 nowhere 198.51.100.1 56324 443\r\n").is_err());
+        assert!(parse_v1("PROXY TCP4 192.0.2.1 198.51.100.1 99999 443\r\n").is_err());
+    }
+
+    #[test]
+    fn version_2_headers_name_the_source() {
+        let v4 = [192, 0, 2, 1, 198, 51, 100, 1, 0xdc, 0x04, 0x01, 0xbb];
+        assert_eq!(parse_v2(0x21, 0x11, &v4).unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+
+        let mut v6 = [0u8; 36];
+        v6[..16].copy_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        v6[32..34].copy_from_slice(&56324u16.to_be_bytes());
+        assert_eq!(parse_v2(0x21, 0x21, &v6).unwrap(), Some("[2001:db8::1]:56324".parse().unwrap()));
+
+        // LOCAL keeps the peer; UDP and unix sockets are not reported
+        assert_eq!(parse_v2(0x20, 0x11, &v4).unwrap(), None);
+        assert_eq!(parse_v2(0x21, 0x12, &v4).unwrap(), None);
+
+        assert!(parse_v2(0x11, 0x11, &v4).is_err());
+        assert!(parse_v2(0x22, 0x11, &v4).is_err());
+        assert!(parse_v2(0x21, 0x11, &v4[..8]).is_err());
+        assert!(parse_v2(0x21, 0x21, &v4).is_err());
+    }
+
+    #[tokio::test]
+    async fn headers_are_read_off_the_stream_exactly() {
+        let (header, rest) = read(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET / HTTP/1.1\r\n").await;
+        assert_eq!(header.unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(rest, b"GET / HTTP/1.1\r\n");
+
+        let mut bytes = v2(0x21, 0x11, &[192, 0, 2, 1, 198, 51, 100, 1, 0xdc, 0x04, 0x01, 0xbb]);
+        bytes.extend(b"GET / HTTP/1.1\r\n");
+        let (header, rest) = read(&bytes).await;
+        assert_eq!(header.unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(rest, b"GET / HTTP/1.1\r\n");
+    }
+
+    #[tokio::test]
+    async fn connections_without_a_valid_header_are_refused() {
+        let (header, _) = read(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n").await;
+        assert!(header.unwrap_err().to_string().contains("missing header"));
+
+        let long = format!("PROXY TCP4 {}\r\n", "1".repeat(V1_MAX_LENGTH));
+        let (header, _) = read(long.as_bytes()).await;
+        assert!(header.unwrap_err().to_string().contains("too long"));
+
+        let (header, _) = read(b"PROXY TCP4 192.0.2.1").await;
+        assert_eq!(header.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn only_trusted_proxies_may_connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let trusted: Vec<Cidr> = vec!["192.0.2.0/24".parse().unwrap()];
+        let _client = TcpStream::connect(addr).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        let refused = Proxied::accept(server, &trusted, Duration::from_secs(1)).await;
+        assert!(refused.err().unwrap().to_string().contains("is not a trusted proxy"));
+
+        let trusted: Vec<Cidr> = vec!["127.0.0.0/8".parse().unwrap()];
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        client.write_all(&v2(0x20, 0x00, &[])).await.unwrap();
+        let local = Proxied::accept(server, &trusted, Duration::from_secs(1)).await.unwrap();
+        assert_eq!(local.source, Some(client.local_addr().unwrap()));
+
+        let _silent = TcpStream::connect(addr).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        let timed_out = Proxied::accept(server, &trusted, Duration::from_millis(50)).await;
+        assert!(timed_out.err().unwrap().to_string().contains("timed out"));
+    }
+}
@@ -0,0 +1,801 @@
+/*!
+Quorum Module
+k-of-n officer signatures required to publish official results
+
+Official results, such as a tender's award, are published only once enough
+of the tenant's officers have signed them. Admins register each officer's
+Ed25519 public key at `/admin/quorum/officers`; the officers keep the
+private keys, so a signature here is one no one else could have made.
+
+1. `POST /quorum/publications` (needs `QUORUM_PUBLISH_SCOPE`) opens a
+   publication for a document's SHA-256 and the reference it publishes,
+   e.g. `tender:7`. The tenant's officers at that moment are its `n`;
+   `QUORUM_THRESHOLD` of them must sign within `QUORUM_WINDOW_SECS`, or
+   it lapses.
+2. Each officer signs the publication's `message`,
+   `cotai-quorum:<id>:<tenant>:<reference>:<document sha256>:<created_at>`,
+   and submits the signature, base64url, at
+   `POST /quorum/publications/{id}/signatures` (needs
+   `QUORUM_SIGN_SCOPE`). It is checked against their registered key.
+3. With the threshold met the publication is published: its signatures
+   are bundled into an attestation, which the service signs with the
+   `QUORUM_ALGORITHM` key over `cotai-quorum-attestation:<id>:<attestation
+   sha256>` for the `quorum-attestation` domain (see `signing`), and the document is registered with the notary (see `notary`)
+   so its publication time is provable as well.
+
+`GET /quorum/publications/{id}/attestation` returns the attestation as the
+exact string that was hashed, so it verifies offline: its SHA-256 and the
+service signature against `/.well-known/jwks.json`, then each officer
+signature against the public key the attestation names.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use ring::digest::{digest, SHA256};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::FromRow;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::receipts::{self, RECEIPT_HEADER};
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, QuorumConfig};
+use crate::crypto::CryptoService;
+use crate::errors::SecurityError;
+use crate::notary::{self, RegisterRequest};
+use crate::signing::{Algorithm, QUORUM_ATTESTATION_DOMAIN};
+use crate::storage::Storage;
+use crate::AppState;
+
+pub const SYSTEM_ACTOR: &str = "system:quorum";
+
+const OFFICER_COLUMNS: &str = "id, tenant_id, subject, public_key, registered_by, registered_at";
+
+pub(crate) const PUBLICATION_COLUMNS: &str = "id, tenant_id, reference, document_sha256, label, threshold, officers, \
+    status, created_by, created_at, expires_at, published_at, attestation, attestation_sha256, algorithm, \
+    key_id, signature, notary_entry_id";
+
+pub(crate) const SIGNATURE_COLUMNS: &str = "publication_id, officer, public_key, signature, signed_at";
+
+fn sha256(data: &[u8]) -> String {
+    hex::encode(digest(&SHA256, data))
+}
+
+/// An Ed25519 public key, base64url without padding.
+fn decode_public_key(value: &str) -> Result<Vec<u8>, SecurityError> {
+    base64::decode_config(value.trim(), base64::URL_SAFE_NO_PAD)
+        .ok()
+        .filter(|key| key.len() == 32)
+        .ok_or_else(|| SecurityError::ValidationError("public_key must be a base64url Ed25519 public key".to_string()))
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Officer {
+    pub id: Uuid,
+    pub tenant_id: Option<String>,
+    pub subject: String,
+    /// Ed25519, base64url.
+    pub public_key: String,
+    pub registered_by: String,
+    pub registered_at: DateTime<Utc>,
+}
+
+/// An officer as a publication recorded them when it opened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Signer {
+    pub subject: String,
+    pub public_key: String,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct OfficerSignature {
+    #[serde(skip)]
+    pub publication_id: Uuid,
+    pub officer: String,
+    pub public_key: String,
+    pub signature: String,
+    pub signed_at: DateTime<Utc>,
+}
+
+/// What the service signs once the threshold is met.
+#[derive(Debug, Clone, Serialize)]
+pub struct Attestation {
+    pub publication_id: Uuid,
+    pub tenant_id: Option<String>,
+    pub reference: String,
+    pub document_sha256: String,
+    /// What each officer signed.
+    pub message: String,
+    pub threshold: i32,
+    pub officers: Vec<Signer>,
+    pub signatures: Vec<OfficerSignature>,
+    pub published_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Publication {
+    pub id: Uuid,
+    pub tenant_id: Option<String>,
+    pub reference: String,
+    pub document_sha256: String,
+    pub label: Option<String>,
+    pub threshold: i32,
+    pub officers: Json<Vec<Signer>>,
+    /// `collecting`, `published` or `lapsed`.
+    pub status: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub published_at: Option<DateTime<Utc>>,
+    /// The attestation JSON exactly as hashed and signed.
+    pub attestation: Option<String>,
+    pub attestation_sha256: Option<String>,
+    pub algorithm: Option<String>,
+    pub key_id: Option<String>,
+    pub signature: Option<String>,
+    pub notary_entry_id: Option<Uuid>,
+}
+
+impl Publication {
+    /// What each officer signs.
+    pub fn message(&self) -> String {
+        format!(
+            "cotai-quorum:{}:{}:{}:{}:{}",
+            self.id,
+            self.tenant_id.as_deref().unwrap_or(""),
+            self.reference,
+            self.document_sha256,
+            self.created_at.to_rfc3339_opts(SecondsFormat::Micros, true)
+        )
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PublicationView {
+    #[serde(flatten)]
+    pub publication: Publication,
+    pub message: String,
+    pub signatures: Vec<OfficerSignature>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterOfficerRequest {
+    pub tenant_id: Option<String>,
+    pub subject: String,
+    pub public_key: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OfficerFilter {
+    pub tenant_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRequest {
+    /// What is published, e.g. `tender:7`.
+    pub reference: String,
+    pub document_sha256: String,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SignRequest {
+    /// Ed25519 signature of the publication's message, base64url.
+    pub signature: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PublicationFilter {
+    pub status: Option<String>,
+    pub reference: Option<String>,
+    pub limit: Option<i64>,
+}
+
+pub struct QuorumService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: QuorumConfig,
+}
+
+impl QuorumService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        info!("Quorum service initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            config: config.quorum.clone(),
+        })
+    }
+
+    /// Register `subject` as an officer of the tenant, replacing any key
+    /// they had.
+    pub async fn register_officer(&self, request: &RegisterOfficerRequest, registered_by: &str) -> Result<Officer, SecurityError> {
+        let subject = request.subject.trim();
+        if subject.is_empty() {
+            return Err(SecurityError::ValidationError("subject is required".to_string()));
+        }
+        decode_public_key(&request.public_key)?;
+
+        let mut tx = self.storage.begin().await?;
+        sqlx::query(
+            "UPDATE quorum_officers SET revoked_at = $3 \
+             WHERE COALESCE(tenant_id, '') = $1 AND subject = $2 AND revoked_at IS NULL",
+        )
+        .bind(request.tenant_id.as_deref().unwrap_or(""))
+        .bind(subject)
+        .bind(self.clock.now())
+        .execute(&mut *tx)
+        .await?;
+        let officer = sqlx::query_as::<_, Officer>(&format!(
+            "INSERT INTO quorum_officers (id, tenant_id, subject, public_key, registered_by, registered_at) \
+             VALUES ($1, $2, $3, $4, $5, $6) RETURNING {}",
+            OFFICER_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(&request.tenant_id)
+        .bind(subject)
+        .bind(request.public_key.trim())
+        .bind(registered_by)
+        .bind(self.clock.now())
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(officer)
+    }
+
+    pub async fn revoke_officer(&self, id: Uuid) -> Result<Officer, SecurityError> {
+        sqlx::query_as::<_, Officer>(&format!(
+            "UPDATE quorum_officers SET revoked_at = $2 WHERE id = $1 AND revoked_at IS NULL RETURNING {}",
+            OFFICER_COLUMNS
+        ))
+        .bind(id)
+        .bind(self.clock.now())
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("No officer {}", id)))
+    }
+
+    pub async fn officers(&self, tenant_id: Option<&str>) -> Result<Vec<Officer>, SecurityError> {
+        Ok(sqlx::query_as::<_, Officer>(&format!(
+            "SELECT {} FROM quorum_officers WHERE COALESCE(tenant_id, '') = $1 AND revoked_at IS NULL \
+             ORDER BY subject",
+            OFFICER_COLUMNS
+        ))
+        .bind(tenant_id.unwrap_or(""))
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    /// Open a publication; the tenant's officers now are the ones who may
+    /// sign it.
+    pub async fn create(&self, principal: &Principal, request: &CreateRequest) -> Result<Publication, SecurityError> {
+        let reference = request.reference.trim();
+        if reference.is_empty() || reference.len() > 200 {
+            return Err(SecurityError::ValidationError("reference must be 1-200 characters".to_string()));
+        }
+        let document_sha256 = notary::normalize_sha256(&request.document_sha256)?;
+        let officers: Vec<Signer> = self
+            .officers(principal.tenant_id.as_deref())
+            .await?
+            .into_iter()
+            .map(|officer| Signer { subject: officer.subject, public_key: officer.public_key })
+            .collect();
+        if officers.len() < self.config.threshold {
+            return Err(SecurityError::ValidationError(format!(
+                "Publishing needs {} registered officers, the tenant has {}",
+                self.config.threshold,
+                officers.len()
+            )));
+        }
+
+        let now = self.clock.now();
+        let publication = sqlx::query_as::<_, Publication>(&format!(
+            "INSERT INTO quorum_publications (id, tenant_id, reference, document_sha256, label, threshold, officers, \
+             status, created_by, created_at, expires_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, 'collecting', $8, $9, $10) RETURNING {}",
+            PUBLICATION_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(&principal.tenant_id)
+        .bind(reference)
+        .bind(&document_sha256)
+        .bind(&request.label)
+        .bind(self.config.threshold as i32)
+        .bind(Json(&officers))
+        .bind(&principal.subject)
+        .bind(now)
+        .bind(now + Duration::seconds(self.config.window_secs))
+        .fetch_one(self.storage.pool())
+        .await?;
+        info!(
+            "Quorum publication {} of {} opened, {} of {} signatures needed",
+            publication.id,
+            publication.reference,
+            publication.threshold,
+            officers.len()
+        );
+        Ok(publication)
+    }
+
+    /// Mark publications whose window has closed.
+    async fn lapse(&self) -> Result<(), SecurityError> {
+        sqlx::query("UPDATE quorum_publications SET status = 'lapsed' WHERE status = 'collecting' AND expires_at <= $1")
+            .bind(self.clock.now())
+            .execute(self.storage.pool())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get(&self, tenant_id: Option<&str>, id: Uuid) -> Result<PublicationView, SecurityError> {
+        self.lapse().await?;
+        let publication = sqlx::query_as::<_, Publication>(&format!(
+            "SELECT {} FROM quorum_publications WHERE id = $1",
+            PUBLICATION_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .filter(|publication| tenant_id.is_none() || publication.tenant_id.as_deref() == tenant_id)
+        .ok_or_else(|| SecurityError::NotFound(format!("No publication {}", id)))?;
+        let signatures = self.signatures(id).await?;
+        Ok(PublicationView { message: publication.message(), publication, signatures })
+    }
+
+    async fn signatures(&self, id: Uuid) -> Result<Vec<OfficerSignature>, SecurityError> {
+        Ok(sqlx::query_as::<_, OfficerSignature>(&format!(
+            "SELECT {} FROM quorum_signatures WHERE publication_id = $1 ORDER BY signed_at",
+            SIGNATURE_COLUMNS
+        ))
+        .bind(id)
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    pub async fn list(&self, tenant_id: Option<&str>, filter: &PublicationFilter) -> Result<Vec<Publication>, SecurityError> {
+        self.lapse().await?;
+        Ok(sqlx::query_as::<_, Publication>(&format!(
+            "SELECT {} FROM quorum_publications \
+             WHERE ($1::text IS NULL OR tenant_id = $1) AND ($2::text IS NULL OR status = $2) \
+             AND ($3::text IS NULL OR reference = $3) ORDER BY created_at DESC LIMIT $4",
+            PUBLICATION_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(&filter.status)
+        .bind(&filter.reference)
+        .bind(filter.limit.unwrap_or(50).clamp(1, 500))
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    /// Add the caller's signature; the one that meets the threshold also
+    /// publishes. Returns the publication and whether this call published it.
+    pub async fn sign(
+        &self,
+        crypto: &CryptoService,
+        principal: &Principal,
+        id: Uuid,
+        request: &SignRequest,
+    ) -> Result<(Publication, bool), SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let publication = sqlx::query_as::<_, Publication>(&format!(
+            "SELECT {} FROM quorum_publications WHERE id = $1 FOR UPDATE",
+            PUBLICATION_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .filter(|publication| principal.tenant_id.is_none() || publication.tenant_id == principal.tenant_id)
+        .ok_or_else(|| SecurityError::NotFound(format!("No publication {}", id)))?;
+        let now = self.clock.now();
+        if publication.status != "collecting" || publication.expires_at <= now {
+            return Err(SecurityError::Conflict(format!("Publication {} is no longer collecting signatures", id)));
+        }
+        let signer = publication
+            .officers
+            .0
+            .iter()
+            .find(|officer| officer.subject == principal.subject)
+            .ok_or_else(|| SecurityError::AccessDenied("Only the publication's officers may sign it".to_string()))?;
+
+        let signature = base64::decode_config(request.signature.trim(), base64::URL_SAFE_NO_PAD)
+            .map_err(|_| SecurityError::ValidationError("signature must be base64url".to_string()))?;
+        let public_key = decode_public_key(&signer.public_key)?;
+        UnparsedPublicKey::new(&ED25519, &public_key)
+            .verify(publication.message().as_bytes(), &signature)
+            .map_err(|_| SecurityError::ValidationError("The signature does not verify against your officer key".to_string()))?;
+
+        let inserted = sqlx::query(
+            "INSERT INTO quorum_signatures (publication_id, officer, public_key, signature, signed_at) \
+             VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING",
+        )
+        .bind(id)
+        .bind(&signer.subject)
+        .bind(&signer.public_key)
+        .bind(request.signature.trim())
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+        if inserted.rows_affected() == 0 {
+            return Err(SecurityError::Conflict("You have already signed this publication".to_string()));
+        }
+
+        let signatures = sqlx::query_as::<_, OfficerSignature>(&format!(
+            "SELECT {} FROM quorum_signatures WHERE publication_id = $1 ORDER BY signed_at, officer",
+            SIGNATURE_COLUMNS
+        ))
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await?;
+        if signatures.len() < publication.threshold as usize {
+            tx.commit().await?;
+            return Ok((publication, false));
+        }
+
+        let attestation = Attestation {
+            publication_id: id,
+            tenant_id: publication.tenant_id.clone(),
+            reference: publication.reference.clone(),
+            document_sha256: publication.document_sha256.clone(),
+            message: publication.message(),
+            threshold: publication.threshold,
+            officers: publication.officers.0.clone(),
+            signatures,
+            published_at: now,
+        };
+        let attestation = serde_json::to_string(&attestation).map_err(|e| SecurityError::CryptoError(e.to_string()))?;
+        let attestation_sha256 = sha256(attestation.as_bytes());
+        let algorithm = Algorithm::parse(&self.config.algorithm)
+            .ok_or_else(|| SecurityError::ConfigError("Invalid QUORUM_ALGORITHM".to_string()))?;
+        let signed = crypto.generate_signature(
+            QUORUM_ATTESTATION_DOMAIN,
+            &format!("cotai-quorum-attestation:{}:{}", id, attestation_sha256),
+            None,
+            algorithm,
+        )?;
+        let publication = sqlx::query_as::<_, Publication>(&format!(
+            "UPDATE quorum_publications SET status = 'published', published_at = $2, attestation = $3, \
+             attestation_sha256 = $4, algorithm = $5, key_id = $6, signature = $7 WHERE id = $1 RETURNING {}",
+            PUBLICATION_COLUMNS
+        ))
+        .bind(id)
+        .bind(now)
+        .bind(&attestation)
+        .bind(&attestation_sha256)
+        .bind(algorithm.as_str())
+        .bind(&signed.key_id)
+        .bind(&signed.signature)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        info!("Quorum publication {} of {} published", id, publication.reference);
+        Ok((publication, true))
+    }
+
+    /// Register a published document with the notary and keep the entry.
+    pub async fn notarize(&self, state: &AppState, publication: &Publication) -> Result<Publication, SecurityError> {
+        let proof = state
+            .notary
+            .register(
+                &state.crypto_service,
+                &RegisterRequest {
+                    sha256: publication.document_sha256.clone(),
+                    label: Some(format!("quorum:{} {}", publication.id, publication.reference)),
+                },
+                SYSTEM_ACTOR,
+                publication.tenant_id.as_deref(),
+            )
+            .await?;
+        Ok(sqlx::query_as::<_, Publication>(&format!(
+            "UPDATE quorum_publications SET notary_entry_id = $2 WHERE id = $1 RETURNING {}",
+            PUBLICATION_COLUMNS
+        ))
+        .bind(publication.id)
+        .bind(proof.entry_id)
+        .fetch_one(self.storage.pool())
+        .await?)
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::AccessDenied(msg) => HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Quorum operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Quorum operation failed"
+            }))
+        }
+    }
+}
+
+/// Either scope reads publications: publishers follow their progress,
+/// officers fetch what to sign.
+#[allow(clippy::result_large_err)]
+fn authorize_reader(state: &AppState, req: &HttpRequest) -> Result<Principal, HttpResponse> {
+    authorize_scope(state, req, &state.config.quorum.sign_scope)
+        .or_else(|_| authorize_scope(state, req, &state.config.quorum.publish_scope))
+        .map_err(|e| auth_error_response(&e))
+}
+
+pub async fn register_officer_handler(
+    req: HttpRequest,
+    request: web::Json<RegisterOfficerRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.quorum.register_officer(&request, &principal.subject).await {
+        Ok(officer) => {
+            let recorded = state.audit_service.record(NewAuditEvent {
+                tenant_id: officer.tenant_id.clone(),
+                actor: principal.subject.clone(),
+                actor_ip: client_ip(&req),
+                action: "quorum.officer.register".to_string(),
+                resource: format!("quorum_officer:{}", officer.id),
+                outcome: "success".to_string(),
+                payload: serde_json::json!({
+                    "subject": officer.subject,
+                    "public_key": officer.public_key
+                }),
+            }).await;
+            if let Err(e) = recorded {
+                warn!("Failed to audit quorum officer {}: {:?}", officer.id, e);
+            }
+            Ok(HttpResponse::Created().json(officer))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_officers_handler(
+    req: HttpRequest,
+    filter: web::Query<OfficerFilter>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.quorum.officers(filter.tenant_id.as_deref()).await {
+        Ok(officers) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "officers": officers
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn revoke_officer_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.quorum.revoke_officer(path.into_inner()).await {
+        Ok(officer) => {
+            let recorded = state.audit_service.record(NewAuditEvent {
+                tenant_id: officer.tenant_id.clone(),
+                actor: principal.subject.clone(),
+                actor_ip: client_ip(&req),
+                action: "quorum.officer.revoke".to_string(),
+                resource: format!("quorum_officer:{}", officer.id),
+                outcome: "success".to_string(),
+                payload: serde_json::json!({
+                    "subject": officer.subject
+                }),
+            }).await;
+            if let Err(e) = recorded {
+                warn!("Failed to audit quorum officer {}: {:?}", officer.id, e);
+            }
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn create_handler(
+    req: HttpRequest,
+    request: web::Json<CreateRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.quorum.publish_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let publication = match state.quorum.create(&principal, &request).await {
+        Ok(publication) => publication,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    let receipt = receipts::record_or_warn(&state, NewAuditEvent {
+        tenant_id: publication.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(&req),
+        action: "quorum.publication.create".to_string(),
+        resource: format!("quorum_publication:{}", publication.id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "reference": publication.reference,
+            "document_sha256": publication.document_sha256,
+            "threshold": publication.threshold,
+            "officers": publication.officers.0.len()
+        }),
+    }).await;
+
+    let mut response = HttpResponse::Created();
+    if let Some(receipt) = receipt {
+        response.insert_header((RECEIPT_HEADER, receipt));
+    }
+    Ok(response.json(PublicationView { message: publication.message(), publication, signatures: Vec::new() }))
+}
+
+pub async fn list_handler(
+    req: HttpRequest,
+    filter: web::Query<PublicationFilter>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_reader(&state, &req) {
+        Ok(principal) => principal,
+        Err(response) => return Ok(response),
+    };
+
+    match state.quorum.list(principal.tenant_id.as_deref(), &filter).await {
+        Ok(publications) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "publications": publications
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_reader(&state, &req) {
+        Ok(principal) => principal,
+        Err(response) => return Ok(response),
+    };
+
+    match state.quorum.get(principal.tenant_id.as_deref(), path.into_inner()).await {
+        Ok(view) => Ok(HttpResponse::Ok().json(view)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn attestation_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_reader(&state, &req) {
+        Ok(principal) => principal,
+        Err(response) => return Ok(response),
+    };
+
+    let view = match state.quorum.get(principal.tenant_id.as_deref(), path.into_inner()).await {
+        Ok(view) => view,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let publication = view.publication;
+    match publication.attestation {
+        Some(attestation) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "attestation": attestation,
+            "attestation_sha256": publication.attestation_sha256,
+            "algorithm": publication.algorithm,
+            "key_id": publication.key_id,
+            "signature": publication.signature,
+            "notary_entry_id": publication.notary_entry_id
+        }))),
+        None => Ok(error_response(SecurityError::Conflict(format!(
+            "Publication {} is {}",
+            publication.id, publication.status
+        )))),
+    }
+}
+
+pub async fn sign_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<SignRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.quorum.sign_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let id = path.into_inner();
+
+    let (mut publication, published) = match state.quorum.sign(&state.crypto_service, &principal, id, &request).await {
+        Ok(signed) => signed,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    let receipt = receipts::record_or_warn(&state, NewAuditEvent {
+        tenant_id: publication.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(&req),
+        action: "quorum.publication.sign".to_string(),
+        resource: format!("quorum_publication:{}", id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "reference": publication.reference,
+            "published": published
+        }),
+    }).await;
+
+    if published {
+        match state.quorum.notarize(&state, &publication).await {
+            Ok(notarized) => publication = notarized,
+            // The attestation stands; the notary entry can be added later
+            Err(e) => warn!("Failed to notarize quorum publication {}: {:?}", id, e),
+        }
+        receipts::record_or_warn(&state, NewAuditEvent {
+            tenant_id: publication.tenant_id.clone(),
+            actor: SYSTEM_ACTOR.to_string(),
+            actor_ip: None,
+            action: "quorum.publication.publish".to_string(),
+            resource: format!("quorum_publication:{}", id),
+            outcome: "success".to_string(),
+            payload: serde_json::json!({
+                "reference": publication.reference,
+                "document_sha256": publication.document_sha256,
+                "attestation_sha256": publication.attestation_sha256,
+                "notary_entry_id": publication.notary_entry_id
+            }),
+        }).await;
+    }
+
+    let signatures = match state.quorum.signatures(id).await {
+        Ok(signatures) => signatures,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let mut response = HttpResponse::Ok();
+    if let Some(receipt) = receipt {
+        response.insert_header((RECEIPT_HEADER, receipt));
+    }
+    Ok(response.json(PublicationView { message: publication.message(), publication, signatures }))
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/quorum/publications")
+            .route("", web::post().to(create_handler))
+            .route("", web::get().to(list_handler))
+            .route("/{id}", web::get().to(get_handler))
+            .route("/{id}/signatures", web::post().to(sign_handler))
+            .route("/{id}/attestation", web::get().to(attestation_handler)),
+    )
+    .service(
+        web::scope("/admin/quorum/officers")
+            .route("", web::post().to(register_officer_handler))
+            .route("", web::get().to(list_officers_handler))
+            .route("/{id}", web::delete().to(revoke_officer_handler)),
+    );
+}
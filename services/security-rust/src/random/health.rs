@@ -0,0 +1,225 @@
+/*!
+Continuous health tests on random output, after NIST SP 800-90B section 4.4
+
+Every output byte is a sample. The repetition count test fails on a run of
+one value longer than a source with the assumed min-entropy per byte would
+produce once in 2^40 samples. The adaptive proportion test fails when one
+value fills more of a 512-sample window than such a source would at that
+rate. A CSPRNG working as intended never gets near either cutoff; a stuck
+or badly seeded one trips them within a few hundred bytes.
+*/
+
+use serde::Serialize;
+use std::fmt;
+use std::sync::Mutex;
+
+/// -log2 of the false positive rate per sample.
+const ALPHA_LOG2: f64 = 40.0;
+
+const APT_WINDOW: u32 = 512;
+
+/// Samples tested at startup and on every periodic self-test; the standard
+/// asks for at least 1024.
+pub const STARTUP_SAMPLES: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "test", rename_all = "snake_case")]
+pub enum Failure {
+    RepetitionCount { value: u8, run: u32 },
+    AdaptiveProportion { value: u8, count: u32 },
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::RepetitionCount { value, run } => {
+                write!(f, "repetition count test: byte {:#04x} repeated {} times", value, run)
+            }
+            Failure::AdaptiveProportion { value, count } => write!(
+                f,
+                "adaptive proportion test: byte {:#04x} {} times in a {}-sample window",
+                value, count, APT_WINDOW
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    last: Option<u8>,
+    run: u32,
+    window_first: u8,
+    window_seen: u32,
+    window_count: u32,
+}
+
+impl State {
+    fn feed(&mut self, samples: &[u8], rct_cutoff: u32, apt_cutoff: u32) -> Result<(), Failure> {
+        for &sample in samples {
+            if self.last == Some(sample) {
+                self.run += 1;
+                if self.run >= rct_cutoff {
+                    return Err(Failure::RepetitionCount { value: sample, run: self.run });
+                }
+            } else {
+                self.last = Some(sample);
+                self.run = 1;
+            }
+
+            if self.window_seen == 0 {
+                self.window_first = sample;
+                self.window_count = 1;
+            } else if sample == self.window_first {
+                self.window_count += 1;
+                if self.window_count >= apt_cutoff {
+                    return Err(Failure::AdaptiveProportion { value: sample, count: self.window_count });
+                }
+            }
+            self.window_seen = (self.window_seen + 1) % APT_WINDOW;
+        }
+        Ok(())
+    }
+}
+
+/// Both tests over a continuous stream of samples.
+pub struct HealthTests {
+    rct_cutoff: u32,
+    apt_cutoff: u32,
+    state: Mutex<State>,
+}
+
+impl HealthTests {
+    /// Cutoffs for a source with `min_entropy_bits` of min-entropy per byte.
+    pub fn new(min_entropy_bits: f64) -> Self {
+        Self {
+            rct_cutoff: rct_cutoff(min_entropy_bits),
+            apt_cutoff: apt_cutoff(min_entropy_bits),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Repetition count and adaptive proportion cutoffs.
+    pub fn cutoffs(&self) -> (u32, u32) {
+        (self.rct_cutoff, self.apt_cutoff)
+    }
+
+    /// Feed samples following those already tested. A failure starts the
+    /// stream over.
+    pub fn check(&self, samples: &[u8]) -> Result<(), Failure> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let result = state.feed(samples, self.rct_cutoff, self.apt_cutoff);
+        if result.is_err() {
+            *state = State::default();
+        }
+        result
+    }
+
+    /// Test samples on their own, as the startup test does.
+    pub fn check_fresh(&self, samples: &[u8]) -> Result<(), Failure> {
+        State::default().feed(samples, self.rct_cutoff, self.apt_cutoff)
+    }
+}
+
+/// `1 + ceil(-log2(alpha) / H)`.
+fn rct_cutoff(min_entropy_bits: f64) -> u32 {
+    1 + (ALPHA_LOG2 / min_entropy_bits).ceil() as u32
+}
+
+/// `1 + CRITBINOM(W, 2^-H, 1 - alpha)`: one more than the smallest count a
+/// value exceeds in a window with probability at most alpha.
+fn apt_cutoff(min_entropy_bits: f64) -> u32 {
+    let p = (-min_entropy_bits).exp2();
+    let alpha = (-ALPHA_LOG2).exp2();
+    let n = APT_WINDOW as usize;
+
+    let mut pmf = vec![0f64; n + 1];
+    pmf[0] = (1.0 - p).powi(n as i32);
+    for k in 0..n {
+        pmf[k + 1] = pmf[k] * (n - k) as f64 / (k + 1) as f64 * p / (1.0 - p);
+    }
+
+    // Summed from the top, so the small tail is not lost to rounding
+    let (mut k, mut tail) = (n, 0.0);
+    while k > 0 && tail + pmf[k] <= alpha {
+        tail += pmf[k];
+        k -= 1;
+    }
+    1 + k as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{RngCore, SeedableRng};
+
+    #[test]
+    fn cutoffs_follow_the_assumed_entropy() {
+        assert_eq!(rct_cutoff(8.0), 6);
+        assert_eq!(rct_cutoff(1.0), 41);
+        assert_eq!(rct_cutoff(0.5), 81);
+
+        let cutoffs: Vec<u32> = [0.5, 1.0, 2.0, 4.0, 8.0].iter().map(|h| apt_cutoff(*h)).collect();
+        assert!(cutoffs.windows(2).all(|pair| pair[0] > pair[1]), "{:?}", cutoffs);
+        assert!(cutoffs.iter().all(|cutoff| *cutoff <= APT_WINDOW), "{:?}", cutoffs);
+        // Well above the 2 a full-entropy byte is expected to repeat in a window
+        assert!(cutoffs[4] > 2);
+    }
+
+    #[test]
+    fn random_bytes_pass() {
+        let tests = HealthTests::new(8.0);
+        let mut bytes = vec![0u8; STARTUP_SAMPLES];
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..16 {
+            rng.fill_bytes(&mut bytes);
+            assert_eq!(tests.check(&bytes), Ok(()));
+        }
+        assert_eq!(tests.check_fresh(&bytes), Ok(()));
+    }
+
+    #[test]
+    fn stuck_output_fails_the_repetition_count_test() {
+        let tests = HealthTests::new(8.0);
+        let (rct, _) = tests.cutoffs();
+        assert_eq!(tests.check(&vec![0xab; rct as usize - 1]), Ok(()));
+        assert_eq!(tests.check(&[0xab]), Err(Failure::RepetitionCount { value: 0xab, run: rct }));
+        // The stream starts over after a failure
+        assert_eq!(tests.check(&vec![0xab; rct as usize - 1]), Ok(()));
+        assert_eq!(tests.check_fresh(&vec![0; rct as usize]), Err(Failure::RepetitionCount { value: 0, run: rct }));
+    }
+
+    #[test]
+    fn biased_output_fails_the_adaptive_proportion_test() {
+        let tests = HealthTests::new(8.0);
+        let (_, apt) = tests.cutoffs();
+        // The first sample of the window, alternated so no run is long
+        let biased: Vec<u8> = (0..APT_WINDOW).map(|i| if i % 2 == 0 { 0x5a } else { i as u8 | 1 }).collect();
+        assert_eq!(
+            tests.check_fresh(&biased),
+            Err(Failure::AdaptiveProportion { value: 0x5a, count: apt })
+        );
+
+        // Counting starts over with each window: each holds one short of the
+        // cutoff of its first value
+        let mut window: Vec<u8> = (0..APT_WINDOW).map(|i| (i % 256) as u8).collect();
+        for i in 1..apt as usize - 2 {
+            window[i * 2] = 0;
+        }
+        assert_eq!(window.iter().filter(|sample| **sample == 0).count(), apt as usize - 1);
+        let spread = window.repeat(4);
+        assert_eq!(tests.check_fresh(&spread), Ok(()));
+    }
+
+    #[test]
+    fn failures_describe_themselves() {
+        assert_eq!(
+            Failure::RepetitionCount { value: 0, run: 6 }.to_string(),
+            "repetition count test: byte 0x00 repeated 6 times"
+        );
+        assert_eq!(
+            serde_json::to_value(Failure::AdaptiveProportion { value: 7, count: 20 }).unwrap(),
+            serde_json::json!({ "test": "adaptive_proportion", "value": 7, "count": 20 })
+        );
+    }
+}
@@ -0,0 +1,59 @@
+/*!
+Supplemental entropy from CPU timing jitter
+
+Small VMs can boot with a thin entropy pool and have no hardware source
+to fall back on. The pool here seeds an HMAC-SHA256 key from `rand_jitter`,
+which measures execution time variations of the CPU, and turns it into a
+keystream. The key is ratcheted after every use, so a key read from memory
+does not reveal earlier output, and fresh jitter is folded in on every
+reseed. Collecting jitter busies a core for tens of milliseconds, so it
+happens at startup and in the background, never on a request.
+*/
+
+use rand::RngCore;
+use rand_jitter::JitterRng;
+use ring::hmac::{self, HMAC_SHA256};
+use std::sync::Mutex;
+
+use crate::errors::SecurityError;
+
+pub struct JitterPool {
+    rng: Mutex<Box<dyn RngCore + Send>>,
+    key: Mutex<hmac::Key>,
+}
+
+impl JitterPool {
+    /// Fails where the CPU timer is too coarse to measure jitter.
+    pub fn new() -> Result<Self, SecurityError> {
+        let rng = JitterRng::new()
+            .map_err(|e| SecurityError::CryptoInitError(format!("Jitter entropy unavailable: {}", e)))?;
+        let pool = Self {
+            rng: Mutex::new(Box::new(rng)),
+            key: Mutex::new(hmac::Key::new(HMAC_SHA256, &[0u8; 32])),
+        };
+        pool.reseed();
+        Ok(pool)
+    }
+
+    /// Fold 256 bits of fresh jitter into the key.
+    pub fn reseed(&self) {
+        let mut seed = [0u8; 32];
+        self.rng.lock().unwrap_or_else(|e| e.into_inner()).fill_bytes(&mut seed);
+        let mut key = self.key.lock().unwrap_or_else(|e| e.into_inner());
+        let next = hmac::sign(&key, &seed);
+        *key = hmac::Key::new(HMAC_SHA256, next.as_ref());
+    }
+
+    /// XOR keystream into `dest`, then ratchet the key.
+    pub fn mix(&self, dest: &mut [u8]) {
+        let mut key = self.key.lock().unwrap_or_else(|e| e.into_inner());
+        for (counter, chunk) in dest.chunks_mut(32).enumerate() {
+            let block = hmac::sign(&key, &(counter as u64).to_be_bytes());
+            for (byte, pad) in chunk.iter_mut().zip(block.as_ref()) {
+                *byte ^= pad;
+            }
+        }
+        let next = hmac::sign(&key, b"ratchet");
+        *key = hmac::Key::new(HMAC_SHA256, next.as_ref());
+    }
+}
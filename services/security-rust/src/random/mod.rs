@@ -0,0 +1,274 @@
+/*!
+Random Module
+Injectable source of cryptographic randomness for keys, nonces and tokens
+
+Whatever source is in use runs behind `HealthTestedSource`. It tests the
+source at startup and every output as it is drawn (see `health`), and
+fails closed once a test fails: keys, nonces and tokens are refused
+rather than drawn from a source that may be stuck. `run_monitor` re-tests
+it every `RNG_HEALTH_INTERVAL_SECS`, alerts when it fails and recovers it
+once it passes again.
+
+With `RNG_JITTER` on, CPU jitter entropy (see `jitter`) is mixed into
+every output, and a source that fails its tests is failed over to the
+jitter pool alone instead of failing closed.
+*/
+
+pub mod health;
+pub mod jitter;
+
+use actix_web::web;
+use chrono::{DateTime, Utc};
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::alerting::{Alert, Severity};
+use crate::clock::Clock;
+use crate::config::RngConfig;
+use crate::errors::SecurityError;
+use crate::health::{CheckFuture, Criticality, HealthRegistry};
+use crate::AppState;
+use health::{HealthTests, STARTUP_SAMPLES};
+use jitter::JitterPool;
+
+pub trait RandomSource: Send + Sync {
+    fn fill(&self, dest: &mut [u8]) -> Result<(), SecurityError>;
+}
+
+/// The operating system CSPRNG via ring.
+#[derive(Debug)]
+pub struct SystemRandomSource(SystemRandom);
+
+impl Default for SystemRandomSource {
+    fn default() -> Self {
+        Self(SystemRandom::new())
+    }
+}
+
+impl RandomSource for SystemRandomSource {
+    fn fill(&self, dest: &mut [u8]) -> Result<(), SecurityError> {
+        self.0.fill(dest)
+            .map_err(|_| SecurityError::CryptoError("System random source failed".to_string()))
+    }
+}
+
+/// Reproducible stream from a fixed seed. Keys and nonces it produces are
+/// predictable by construction: tests only, never wired into a deployment.
+#[derive(Debug)]
+pub struct SeededRandomSource(Mutex<StdRng>);
+
+impl SeededRandomSource {
+    pub fn new(seed: u64) -> Self {
+        Self(Mutex::new(StdRng::seed_from_u64(seed)))
+    }
+}
+
+impl RandomSource for SeededRandomSource {
+    fn fill(&self, dest: &mut [u8]) -> Result<(), SecurityError> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RngStatus {
+    pub healthy: bool,
+    /// Serving from the jitter pool alone while the source is unhealthy.
+    pub failed_over: bool,
+    /// The failure that made it unhealthy.
+    pub failure: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    /// Failures since startup.
+    pub failures: u64,
+}
+
+/// A source whose output is health tested; see the module docs.
+pub struct HealthTestedSource {
+    inner: Arc<dyn RandomSource>,
+    tests: Option<HealthTests>,
+    jitter: Option<JitterPool>,
+    status: RwLock<RngStatus>,
+    clock: Arc<dyn Clock>,
+}
+
+impl HealthTestedSource {
+    /// Fails if the source does not pass the startup test.
+    pub fn new(config: &RngConfig, inner: Arc<dyn RandomSource>, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let tests = config.health_checks.then(|| HealthTests::new(config.min_entropy_bits));
+        let jitter = if config.jitter { Some(JitterPool::new()?) } else { None };
+        let source = Self {
+            inner,
+            tests,
+            jitter,
+            status: RwLock::new(RngStatus {
+                healthy: true,
+                failed_over: false,
+                failure: None,
+                since: None,
+                failures: 0,
+            }),
+            clock,
+        };
+
+        if let Some(tests) = &source.tests {
+            source.self_test().map_err(|e| SecurityError::CryptoInitError(format!("Random source startup test failed: {}", e)))?;
+            let (rct, apt) = tests.cutoffs();
+            info!(
+                "Random source health tests on: repetition cutoff {}, adaptive proportion cutoff {}, jitter {}",
+                rct,
+                apt,
+                if source.jitter.is_some() { "on" } else { "off" }
+            );
+        }
+        Ok(source)
+    }
+
+    pub fn status(&self) -> RngStatus {
+        self.status.read().unwrap().clone()
+    }
+
+    /// Draw `STARTUP_SAMPLES` and test them on their own. Marks the source
+    /// unhealthy on failure, and healthy again on success.
+    pub fn self_test(&self) -> Result<(), String> {
+        let Some(tests) = &self.tests else {
+            return Ok(());
+        };
+        let mut samples = vec![0u8; STARTUP_SAMPLES];
+        let tested = self.inner.fill(&mut samples)
+            .map_err(|e| e.to_string())
+            .and_then(|_| tests.check_fresh(&samples).map_err(|f| f.to_string()));
+        match tested {
+            Ok(()) => {
+                let mut status = self.status.write().unwrap();
+                if !status.healthy {
+                    info!("Random source passed its self-test; serving from it again");
+                    status.healthy = true;
+                    status.failed_over = false;
+                    status.failure = None;
+                    status.since = None;
+                }
+                Ok(())
+            }
+            Err(problem) => {
+                self.degrade(&problem);
+                Err(problem)
+            }
+        }
+    }
+
+    /// Fold fresh jitter into the pool, if there is one. Slow; see `jitter`.
+    pub fn reseed_jitter(&self) {
+        if let Some(jitter) = &self.jitter {
+            jitter.reseed();
+        }
+    }
+
+    fn degrade(&self, problem: &str) {
+        let mut status = self.status.write().unwrap();
+        status.failures += 1;
+        if status.healthy {
+            error!("Random source failed its health tests: {}", problem);
+            status.healthy = false;
+            status.failed_over = self.jitter.is_some();
+            status.failure = Some(problem.to_string());
+            status.since = Some(self.clock.now());
+        }
+    }
+
+    /// Output from the jitter pool alone, or nothing.
+    fn fail_over(&self, dest: &mut [u8]) -> Result<(), SecurityError> {
+        let Some(jitter) = &self.jitter else {
+            return Err(SecurityError::CryptoError("Random source failed its health tests".to_string()));
+        };
+        dest.fill(0);
+        jitter.mix(dest);
+        Ok(())
+    }
+}
+
+impl RandomSource for HealthTestedSource {
+    fn fill(&self, dest: &mut [u8]) -> Result<(), SecurityError> {
+        if !self.status.read().unwrap().healthy {
+            return self.fail_over(dest);
+        }
+        let drawn = self.inner.fill(dest).map_err(|e| e.to_string()).and_then(|_| match &self.tests {
+            Some(tests) => tests.check(dest).map_err(|f| f.to_string()),
+            None => Ok(()),
+        });
+        if let Err(problem) = drawn {
+            dest.fill(0);
+            self.degrade(&problem);
+            return self.fail_over(dest);
+        }
+        if let Some(jitter) = &self.jitter {
+            jitter.mix(dest);
+        }
+        Ok(())
+    }
+}
+
+/// Version 4 UUID from `random`, so identifiers follow the injected source too.
+pub fn uuid_v4(random: &dyn RandomSource) -> Result<Uuid, SecurityError> {
+    let mut bytes = [0u8; 16];
+    random.fill(&mut bytes)?;
+    Ok(uuid::Builder::from_random_bytes(bytes).into_uuid())
+}
+
+fn alert(status: &RngStatus, now: DateTime<Utc>) -> Alert {
+    let details = serde_json::to_value(status).unwrap_or_default();
+    if status.healthy {
+        Alert::new("random", Severity::Info, "Random source healthy again", details, now)
+    } else if status.failed_over {
+        Alert::new("random", Severity::Critical, "Random source failed its health tests; serving from jitter entropy", details, now)
+    } else {
+        Alert::new("random", Severity::Critical, "Random source failed its health tests; keys and nonces refused", details, now)
+    }
+}
+
+/// Re-test the source and reseed the jitter pool on schedule, alerting
+/// whenever the source's health changes.
+pub async fn run_monitor(state: web::Data<AppState>) {
+    let config = state.config.rng.clone();
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(config.interval_secs));
+    let reseed_every = std::time::Duration::from_secs(config.jitter_reseed_secs);
+    let mut reseeded = tokio::time::Instant::now();
+    let mut alerted_healthy = true;
+
+    loop {
+        interval.tick().await;
+        if config.jitter && reseeded.elapsed() >= reseed_every {
+            // Jitter collection spins a core; keep it off the request workers
+            let source = state.random_health.clone();
+            if let Err(e) = tokio::task::spawn_blocking(move || source.reseed_jitter()).await {
+                warn!("Jitter reseed failed: {:?}", e);
+            }
+            reseeded = tokio::time::Instant::now();
+        }
+
+        let _ = state.random_health.self_test();
+        let status = state.random_health.status();
+        if status.healthy != alerted_healthy {
+            state.alerting_service.send(&alert(&status, state.clock.now()), &[]).await;
+            alerted_healthy = status.healthy;
+        }
+    }
+}
+
+fn source_healthy(state: &AppState) -> CheckFuture<'_> {
+    Box::pin(async move {
+        let status = state.random_health.status();
+        match status.failure {
+            Some(failure) if !status.failed_over => Err(failure),
+            _ => Ok(()),
+        }
+    })
+}
+
+pub fn register_health_checks(registry: &mut HealthRegistry) {
+    registry.register("random", Criticality::Critical, source_healthy);
+}
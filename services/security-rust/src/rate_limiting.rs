@@ -0,0 +1,817 @@
+/*!
+Rate Limiting Module
+Request limits shared across replicas through Redis
+
+The `rate_limit` pipeline stage (see `pipeline`) takes every request under
+its scopes through one rule:
+
+- the longest `RATE_LIMIT_ROUTES` prefix matching the path, e.g.
+  `/api/v1/auth/token=sliding_window:20/60`, counted per caller
+- otherwise the caller's tenant limit, `rate_limit_rpm` from tenant
+  settings, counted per tenant with `RATE_LIMIT_DEFAULT_ALGORITHM`, or per
+  caller when there is no tenant
+
+The caller is, in that order, the service account (this service's API
+keys, see `auth::service_accounts`), the token subject, or the client
+address when unauthenticated. Identities are only taken from verified
+tokens, so a client cannot spread its requests over made-up keys.
+
+Addresses are cheap to rotate, so a route can count unauthenticated
+callers by network instead: `@subnet` after its spec (e.g.
+`sliding_window:20/60@subnet`) counts the whole `/24` or `/48` the address
+is in (`RATE_LIMIT_SUBNET_V4_PREFIX`, `RATE_LIMIT_SUBNET_V6_PREFIX`), and
+`@asn` the autonomous system announcing it, looked up in the ip2asn table
+`RATE_LIMIT_ASN_FILE` (addresses not in it count per subnet). `@ip`, the
+default, counts each address.
+
+Counting escalates by itself when addresses rotate: once a rule sees
+`RATE_LIMIT_ESCALATE_THRESHOLD` distinct addresses from one subnet within
+`RATE_LIMIT_ESCALATE_WINDOW_SECS`, it counts that subnet as one caller for
+`RATE_LIMIT_ESCALATE_SECS`, and likewise a whole AS once that many of its
+subnets show up. Escalations are decided per replica, from the requests
+it sees, and logged and counted in `cotai_rate_limit_escalations_total`.
+
+`token_bucket:N/S` holds up to N requests and refills N every S seconds,
+so bursts are allowed after quiet periods. `sliding_window:N/S` allows at
+most N requests in any S seconds, keeping a timestamp per request.
+
+With `RATE_LIMIT_BACKEND=redis` each check is one Lua script, so reading
+and updating a counter is atomic and every replica sees the same limit;
+the script takes its time from Redis, so replica clocks do not matter.
+When Redis does not answer within `RATE_LIMIT_TIMEOUT_MS` the check falls
+back to this replica's own counters until it does. `memory` only ever
+limits per replica, timed by the service clock.
+
+Responses, allowed or not, carry `X-RateLimit-Limit`,
+`X-RateLimit-Remaining` and `X-RateLimit-Reset` (Unix time at which the
+caller's full allowance is back), alongside the draft standard
+`RateLimit-Limit` and `RateLimit-Remaining`. Rejections are `429` with
+`Retry-After`, and count towards abuse detection (see
+`monitoring::threats`).
+
+`RATE_LIMIT_ROUTES` and `RATE_LIMIT_DEFAULT_ALGORITHM` are reloaded with
+the configuration (see `config::run_reload`); counters carry over.
+*/
+
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use actix_web::{web, HttpMessage, HttpResponse};
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::auth::{client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::errors::SecurityError;
+use crate::network::{AsnTable, Cidr};
+use crate::AppState;
+
+/// Subject prefix of service account tokens.
+const SERVICE_ACCOUNT_PREFIX: &str = "service_account:";
+
+/// Keys the memory store holds before dropping idle ones.
+const MEMORY_MAX_KEYS: usize = 100_000;
+
+/// Refills `ARGV[1]` tokens every `ARGV[2]` ms into a bucket of that size
+/// and takes one. Returns allowed, tokens left, ms until the next one and
+/// ms until the bucket is full.
+const TOKEN_BUCKET_SCRIPT: &str = r"
+local capacity = tonumber(ARGV[1])
+local period = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'at')
+local tokens = tonumber(state[1]) or capacity
+local at = tonumber(state[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - at) * capacity / period)
+local allowed = 0
+local retry = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+else
+    retry = math.ceil((1 - tokens) * period / capacity)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'at', now)
+redis.call('PEXPIRE', KEYS[1], period)
+return {allowed, math.floor(tokens), retry, math.ceil((capacity - tokens) * period / capacity)}
+";
+
+/// Allows `ARGV[1]` requests in any `ARGV[2]` ms, logging each under the
+/// unique `ARGV[3]`. Returns allowed, requests left, ms until a slot frees
+/// and ms until every slot has.
+const SLIDING_WINDOW_SCRIPT: &str = r"
+local limit = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', now - window)
+local count = redis.call('ZCARD', KEYS[1])
+if count < limit then
+    redis.call('ZADD', KEYS[1], now, ARGV[3])
+    redis.call('PEXPIRE', KEYS[1], window)
+    return {1, limit - count - 1, 0, window}
+end
+local oldest = redis.call('ZRANGE', KEYS[1], 0, 0, 'WITHSCORES')
+local newest = redis.call('ZRANGE', KEYS[1], -1, -1, 'WITHSCORES')
+return {0, 0, math.max(1, tonumber(oldest[2]) + window - now), math.max(1, tonumber(newest[2]) + window - now)}
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    TokenBucket,
+    SlidingWindow,
+}
+
+impl Algorithm {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "token_bucket" => Some(Algorithm::TokenBucket),
+            "sliding_window" => Some(Algorithm::SlidingWindow),
+            _ => None,
+        }
+    }
+}
+
+/// What unauthenticated requests are counted against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    Ip,
+    Subnet,
+    Asn,
+}
+
+impl Aggregation {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "ip" => Some(Aggregation::Ip),
+            "subnet" => Some(Aggregation::Subnet),
+            "asn" => Some(Aggregation::Asn),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Aggregation::Ip => "ip",
+            Aggregation::Subnet => "subnet",
+            Aggregation::Asn => "asn",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    /// Path prefix, or `tenant` for the tenant limit.
+    pub name: String,
+    pub algorithm: Algorithm,
+    pub limit: u32,
+    pub period: Duration,
+    pub aggregation: Aggregation,
+}
+
+impl Rule {
+    /// Parse a `RATE_LIMIT_ROUTES` entry,
+    /// `/prefix=algorithm:limit/seconds[@ip|subnet|asn]`.
+    pub fn parse(prefix: &str, spec: &str) -> Result<Self, String> {
+        if !prefix.starts_with('/') {
+            return Err("prefix must start with '/'".to_string());
+        }
+        let (spec, aggregation) = match spec.split_once('@') {
+            Some((spec, aggregation)) => (
+                spec,
+                Aggregation::parse(aggregation)
+                    .ok_or_else(|| format!("unknown aggregation '{}' (ip, subnet, asn)", aggregation))?,
+            ),
+            None => (spec, Aggregation::Ip),
+        };
+        let (algorithm, rate) = spec.split_once(':').ok_or("expected algorithm:limit/seconds")?;
+        let algorithm = Algorithm::parse(algorithm)
+            .ok_or_else(|| format!("unknown algorithm '{}' (token_bucket, sliding_window)", algorithm))?;
+        let (limit, secs) = rate.split_once('/').ok_or("expected algorithm:limit/seconds")?;
+        let limit: u32 = limit.parse().ok().filter(|l| *l > 0).ok_or("limit must be a positive integer")?;
+        let secs: u64 = secs.parse().ok().filter(|s| *s > 0).ok_or("seconds must be a positive integer")?;
+
+        Ok(Self {
+            name: prefix.to_string(),
+            algorithm,
+            limit,
+            period: Duration::from_secs(secs),
+            aggregation,
+        })
+    }
+}
+
+/// Outcome of one check, kept on the request for the response headers.
+#[derive(Debug, Clone, Copy)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    pub retry_after: Duration,
+    /// Until the full limit is available again.
+    pub reset: Duration,
+}
+
+/// The counter a request let through was counted against, kept on the
+/// request for `echo`.
+#[derive(Debug, Clone)]
+pub struct Bucket {
+    pub rule: String,
+    pub key: String,
+    pub limit: u32,
+    pub period_secs: u64,
+}
+
+struct RedisStore {
+    client: redis::Client,
+    /// Shared by every check; dropped on errors so the next one reconnects.
+    connection: Mutex<Option<redis::aio::MultiplexedConnection>>,
+    token_bucket: redis::Script,
+    sliding_window: redis::Script,
+}
+
+impl RedisStore {
+    async fn connection(&self) -> Result<redis::aio::MultiplexedConnection, SecurityError> {
+        if let Some(conn) = self.connection.lock().unwrap_or_else(|e| e.into_inner()).clone() {
+            return Ok(conn);
+        }
+        let conn = self.client.get_multiplexed_async_connection()
+            .await
+            .map_err(|e| SecurityError::DeliveryError(format!("Redis connection failed: {}", e)))?;
+        *self.connection.lock().unwrap_or_else(|e| e.into_inner()) = Some(conn.clone());
+        Ok(conn)
+    }
+
+    async fn acquire(&self, rule: &Rule, key: &str) -> Result<Decision, SecurityError> {
+        let mut conn = self.connection().await?;
+        let period_ms = rule.period.as_millis() as u64;
+        let mut invocation = match rule.algorithm {
+            Algorithm::TokenBucket => self.token_bucket.key(key),
+            Algorithm::SlidingWindow => self.sliding_window.key(key),
+        };
+        invocation.arg(rule.limit).arg(period_ms);
+        if rule.algorithm == Algorithm::SlidingWindow {
+            invocation.arg(Uuid::new_v4().to_string());
+        }
+
+        let result: Result<(i64, i64, i64, i64), _> = invocation.invoke_async(&mut conn).await;
+        let (allowed, remaining, retry_ms, reset_ms) = result.map_err(|e| {
+            *self.connection.lock().unwrap_or_else(|e| e.into_inner()) = None;
+            SecurityError::DeliveryError(format!("Rate limit script failed: {}", e))
+        })?;
+
+        Ok(Decision {
+            allowed: allowed == 1,
+            limit: rule.limit,
+            remaining: remaining.max(0) as u32,
+            retry_after: Duration::from_millis(retry_ms.max(0) as u64),
+            reset: Duration::from_millis(reset_ms.max(0) as u64),
+        })
+    }
+
+    async fn clear(&self, key: &str) -> Result<(), SecurityError> {
+        let mut conn = self.connection().await?;
+        let result: Result<(), _> = redis::cmd("DEL").arg(key).query_async(&mut conn).await;
+        result.map_err(|e| {
+            *self.connection.lock().unwrap_or_else(|e| e.into_inner()) = None;
+            SecurityError::DeliveryError(format!("Rate limit reset failed: {}", e))
+        })
+    }
+}
+
+/// Time from `then` to `now`; nothing if the clock went back.
+fn elapsed(then: DateTime<Utc>, now: DateTime<Utc>) -> Duration {
+    (now - then).to_std().unwrap_or(Duration::ZERO)
+}
+
+enum Counter {
+    Bucket { tokens: f64, at: DateTime<Utc> },
+    Log(VecDeque<DateTime<Utc>>),
+}
+
+impl Counter {
+    /// Whether the counter is back to its initial state.
+    fn is_idle(&self, period: Duration, now: DateTime<Utc>) -> bool {
+        match self {
+            Counter::Bucket { at, .. } => elapsed(*at, now) >= period,
+            Counter::Log(log) => log.back().is_none_or(|last| elapsed(*last, now) >= period),
+        }
+    }
+}
+
+/// Per-replica counters, running the same algorithms as the scripts.
+#[derive(Default)]
+struct MemoryStore {
+    counters: Mutex<HashMap<String, (Duration, Counter)>>,
+}
+
+impl MemoryStore {
+    fn take(&self, rule: &Rule, key: &str, now: DateTime<Utc>) -> Decision {
+        let limit = rule.limit as f64;
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        if counters.len() >= MEMORY_MAX_KEYS {
+            counters.retain(|_, (period, counter)| !counter.is_idle(*period, now));
+        }
+
+        let (_, counter) = counters.entry(key.to_string()).or_insert_with(|| {
+            let counter = match rule.algorithm {
+                Algorithm::TokenBucket => Counter::Bucket { tokens: limit, at: now },
+                Algorithm::SlidingWindow => Counter::Log(VecDeque::new()),
+            };
+            (rule.period, counter)
+        });
+
+        match counter {
+            Counter::Bucket { tokens, at } => {
+                let rate = limit / rule.period.as_secs_f64();
+                *tokens = (*tokens + elapsed(*at, now).as_secs_f64() * rate).min(limit);
+                *at = now;
+                let allowed = *tokens >= 1.0;
+                if allowed {
+                    *tokens -= 1.0;
+                }
+                Decision {
+                    allowed,
+                    limit: rule.limit,
+                    remaining: *tokens as u32,
+                    retry_after: if allowed {
+                        Duration::ZERO
+                    } else {
+                        Duration::from_secs_f64((1.0 - *tokens) / rate)
+                    },
+                    reset: Duration::from_secs_f64((limit - *tokens) / rate),
+                }
+            }
+            Counter::Log(log) => {
+                while log.front().is_some_and(|t| elapsed(*t, now) >= rule.period) {
+                    log.pop_front();
+                }
+                let count = log.len() as u32;
+                if count < rule.limit {
+                    log.push_back(now);
+                    return Decision {
+                        allowed: true,
+                        limit: rule.limit,
+                        remaining: rule.limit - count - 1,
+                        retry_after: Duration::ZERO,
+                        reset: rule.period,
+                    };
+                }
+                let oldest = log.front().copied().unwrap_or(now);
+                let newest = log.back().copied().unwrap_or(now);
+                Decision {
+                    allowed: false,
+                    limit: rule.limit,
+                    remaining: 0,
+                    retry_after: rule.period.saturating_sub(elapsed(oldest, now)),
+                    reset: rule.period.saturating_sub(elapsed(newest, now)),
+                }
+            }
+        }
+    }
+}
+
+/// Distinct members (addresses, or subnets) one group (a subnet, or an
+/// AS) has shown on a rule since `since`.
+struct Spread {
+    since: DateTime<Utc>,
+    members: HashSet<String>,
+    escalated_until: Option<DateTime<Utc>>,
+}
+
+/// Groups whose members rotate fast enough to be counted as one.
+struct Escalations {
+    threshold: usize,
+    window: Duration,
+    duration: chrono::Duration,
+    spreads: Mutex<HashMap<(String, String), Spread>>,
+}
+
+impl Escalations {
+    /// Note `member` of `group` on `rule`. `None` while the group is not
+    /// escalated, else whether it just was.
+    fn observe(&self, rule: &str, group: &str, member: &str, now: DateTime<Utc>) -> Option<bool> {
+        if self.threshold == 0 {
+            return None;
+        }
+        let mut spreads = self.spreads.lock().unwrap_or_else(|e| e.into_inner());
+        if spreads.len() >= MEMORY_MAX_KEYS {
+            spreads.retain(|_, spread| {
+                elapsed(spread.since, now) < self.window || spread.escalated_until.is_some_and(|until| until > now)
+            });
+        }
+
+        let spread = spreads.entry((rule.to_string(), group.to_string())).or_insert_with(|| Spread {
+            since: now,
+            members: HashSet::new(),
+            escalated_until: None,
+        });
+        if spread.escalated_until.is_some_and(|until| until > now) {
+            return Some(false);
+        }
+        if spread.escalated_until.is_some() || elapsed(spread.since, now) >= self.window {
+            *spread = Spread { since: now, members: HashSet::new(), escalated_until: None };
+        }
+        spread.members.insert(member.to_string());
+        if spread.members.len() < self.threshold {
+            return None;
+        }
+        spread.members.clear();
+        spread.escalated_until = Some(now + self.duration);
+        Some(true)
+    }
+}
+
+/// What an unauthenticated request is counted against.
+pub struct Address {
+    pub key: String,
+    /// Set when this request escalated its group to this aggregation.
+    pub escalated: Option<(Aggregation, String)>,
+}
+
+pub struct RateLimiter {
+    /// Redis when configured; `None` limits from memory only.
+    shared: Option<RedisStore>,
+    local: MemoryStore,
+    limits: RwLock<Limits>,
+    timeout: Duration,
+    key_prefix: String,
+    subnet_prefixes: (u8, u8),
+    asns: Option<AsnTable>,
+    escalations: Escalations,
+    clock: Arc<dyn Clock>,
+}
+
+/// The reloadable part of the rate limit config.
+struct Limits {
+    /// Longest prefix first.
+    routes: Vec<Rule>,
+    default_algorithm: Algorithm,
+}
+
+impl Limits {
+    fn new(config: &Config) -> Result<Self, SecurityError> {
+        let rate_limit = &config.rate_limit;
+        let mut routes = rate_limit.routes
+            .iter()
+            .map(|(prefix, spec)| Rule::parse(prefix, spec)
+                .map_err(|e| SecurityError::ConfigError(format!("RATE_LIMIT_ROUTES '{}': {}", prefix, e))))
+            .collect::<Result<Vec<_>, _>>()?;
+        routes.sort_by_key(|rule| std::cmp::Reverse(rule.name.len()));
+        let default_algorithm = Algorithm::parse(&rate_limit.default_algorithm).ok_or_else(|| {
+            SecurityError::ConfigError(format!("Unknown rate limit algorithm '{}'", rate_limit.default_algorithm))
+        })?;
+        Ok(Self { routes, default_algorithm })
+    }
+}
+
+impl RateLimiter {
+    pub fn new(config: &Config, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let rate_limit = &config.rate_limit;
+        let shared = match rate_limit.backend.as_str() {
+            "redis" => Some(RedisStore {
+                client: redis::Client::open(config.redis_url.as_str())
+                    .map_err(|e| SecurityError::ConfigError(format!("Invalid Redis URL: {}", e)))?,
+                connection: Mutex::new(None),
+                token_bucket: redis::Script::new(TOKEN_BUCKET_SCRIPT),
+                sliding_window: redis::Script::new(SLIDING_WINDOW_SCRIPT),
+            }),
+            "memory" => None,
+            other => return Err(SecurityError::ConfigError(format!("Unknown rate limit backend '{}'", other))),
+        };
+
+        let asns = match &rate_limit.asn_file {
+            Some(path) => {
+                let asns = AsnTable::load(path)?;
+                info!("Loaded {} ASN ranges from {}", asns.len(), path);
+                Some(asns)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            shared,
+            local: MemoryStore::default(),
+            limits: RwLock::new(Limits::new(config)?),
+            timeout: Duration::from_millis(rate_limit.timeout_ms),
+            key_prefix: rate_limit.key_prefix.clone(),
+            subnet_prefixes: (rate_limit.subnet_v4_prefix, rate_limit.subnet_v6_prefix),
+            asns,
+            escalations: Escalations {
+                threshold: rate_limit.escalate_threshold,
+                window: Duration::from_secs(rate_limit.escalate_window_secs),
+                duration: chrono::Duration::seconds(rate_limit.escalate_secs as i64),
+                spreads: Mutex::new(HashMap::new()),
+            },
+            clock,
+        })
+    }
+
+    /// Take new routes and default algorithm from `config`, keeping the
+    /// current ones if they do not parse.
+    pub fn reload(&self, config: &Config) -> Result<(), SecurityError> {
+        let limits = Limits::new(config)?;
+        *self.limits.write().unwrap() = limits;
+        Ok(())
+    }
+
+    pub fn route(&self, path: &str) -> Option<Rule> {
+        let limits = self.limits.read().unwrap();
+        limits.routes.iter().find(|rule| path.starts_with(rule.name.as_str())).cloned()
+    }
+
+    /// The tenant limit, in requests per minute.
+    pub fn tenant_rule(&self, rpm: u32) -> Rule {
+        self.minute_rule("tenant", rpm)
+    }
+
+    /// A limit of `rpm` requests per minute under the default algorithm,
+    /// for endpoints outside the pipeline.
+    pub fn minute_rule(&self, name: &str, rpm: u32) -> Rule {
+        Rule {
+            name: name.to_string(),
+            algorithm: self.limits.read().unwrap().default_algorithm,
+            limit: rpm,
+            period: Duration::from_secs(60),
+            aggregation: Aggregation::Ip,
+        }
+    }
+
+    /// The key `ip` is counted under for `rule`: its own, its subnet's or
+    /// its AS's, per the rule's aggregation or an escalation.
+    pub fn address(&self, rule: &Rule, ip: IpAddr) -> Address {
+        let now = self.clock.now();
+        let ip_key = ip.to_string();
+        let subnet = Cidr::around(ip, self.subnet_prefixes.0, self.subnet_prefixes.1).to_string();
+        let asn = self.asns.as_ref().and_then(|asns| asns.lookup(ip)).map(|asn| format!("AS{}", asn));
+
+        let mut aggregation = rule.aggregation;
+        let mut escalated = None;
+        if aggregation == Aggregation::Ip {
+            if let Some(new) = self.escalations.observe(&rule.name, &subnet, &ip_key, now) {
+                aggregation = Aggregation::Subnet;
+                escalated = new.then(|| (Aggregation::Subnet, subnet.clone()));
+            }
+        }
+        if aggregation == Aggregation::Subnet {
+            if let Some(asn) = &asn {
+                if let Some(new) = self.escalations.observe(&rule.name, asn, &subnet, now) {
+                    aggregation = Aggregation::Asn;
+                    escalated = new.then(|| (Aggregation::Asn, asn.clone())).or(escalated);
+                }
+            }
+        }
+
+        let key = match (aggregation, asn) {
+            (Aggregation::Ip, _) => format!("ip:{}", ip_key),
+            (Aggregation::Asn, Some(asn)) => format!("asn:{}", asn),
+            (_, _) => format!("subnet:{}", subnet),
+        };
+        Address { key, escalated }
+    }
+
+    /// Count a request against `rule` for `key`.
+    pub async fn acquire(&self, rule: &Rule, key: &str) -> Decision {
+        let key = format!("{}:{}:{}", self.key_prefix, rule.name, key);
+        if let Some(shared) = &self.shared {
+            match tokio::time::timeout(self.timeout, shared.acquire(rule, &key)).await {
+                Ok(Ok(decision)) => return decision,
+                Ok(Err(e)) => warn!("Rate limiting from memory: {}", e),
+                Err(_) => warn!("Rate limiting from memory: Redis did not answer within {:?}", self.timeout),
+            }
+        }
+        self.local.take(rule, &key, self.clock.now())
+    }
+
+    /// Forget what has been counted against `rule` for `key`.
+    pub async fn clear(&self, rule: &Rule, key: &str) {
+        let key = format!("{}:{}:{}", self.key_prefix, rule.name, key);
+        if let Some(shared) = &self.shared {
+            match tokio::time::timeout(self.timeout, shared.clear(&key)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => warn!("Rate limit counter {} kept: {}", key, e),
+                Err(_) => warn!("Rate limit counter {} kept: Redis did not answer within {:?}", key, self.timeout),
+            }
+        }
+        self.local.counters.lock().unwrap_or_else(|e| e.into_inner()).remove(&key);
+    }
+}
+
+/// Who a request is counted against under `rule`: service account, then
+/// user, then client address as the rule aggregates it.
+fn identity(state: &AppState, rule: &Rule, principal: Option<&Principal>, req: &ServiceRequest) -> String {
+    if let Some(principal) = principal {
+        return match principal.subject.strip_prefix(SERVICE_ACCOUNT_PREFIX) {
+            Some(account) => format!("key:{}", account),
+            None => format!("user:{}", principal.subject),
+        };
+    }
+    let Some(ip) = client_ip(req.request()).and_then(|ip| ip.parse::<IpAddr>().ok()) else {
+        return "ip:".to_string();
+    };
+
+    let address = state.rate_limiter.address(rule, ip);
+    if let Some((aggregation, group)) = &address.escalated {
+        warn!(
+            "Rate limit rule {} now counts {} as one caller: addresses are rotating within it",
+            rule.name, group
+        );
+        state.metrics_service.increment(
+            "cotai_rate_limit_escalations_total",
+            &[("rule", rule.name.as_str()), ("aggregation", aggregation.as_str())],
+        );
+    }
+    address.key
+}
+
+fn set_headers(headers: &mut HeaderMap, decision: &Decision, now: DateTime<Utc>) {
+    let reset = now.timestamp() + decision.reset.as_secs_f64().ceil() as i64;
+    for (name, value) in [
+        ("x-ratelimit-limit", HeaderValue::from(decision.limit)),
+        ("x-ratelimit-remaining", HeaderValue::from(decision.remaining)),
+        ("x-ratelimit-reset", HeaderValue::from(reset)),
+        ("ratelimit-limit", HeaderValue::from(decision.limit)),
+        ("ratelimit-remaining", HeaderValue::from(decision.remaining)),
+    ] {
+        headers.insert(HeaderName::from_static(name), value);
+    }
+}
+
+/// The `rate_limit` pipeline stage: the 429 to answer with when the request
+/// is over its limit.
+pub async fn rejection(state: &AppState, req: &ServiceRequest) -> Option<HttpResponse> {
+    let principal = state.auth_service.authenticate(req.request()).ok();
+
+    let limiter = &state.rate_limiter;
+    let (rule, key) = match limiter.route(req.path()) {
+        Some(rule) => {
+            let key = identity(state, &rule, principal.as_ref(), req);
+            (rule, key)
+        }
+        None => {
+            let tenant_id = principal.as_ref().and_then(|p| p.tenant_id.as_deref());
+            let settings = state.tenant_settings.effective(tenant_id).await;
+            let rule = limiter.tenant_rule(settings.rate_limit_rpm);
+            let key = match tenant_id {
+                Some(tenant_id) => format!("tenant:{}", tenant_id),
+                None => identity(state, &rule, principal.as_ref(), req),
+            };
+            (rule, key)
+        }
+    };
+
+    let decision = limiter.acquire(&rule, &key).await;
+    if decision.allowed {
+        req.extensions_mut().insert(decision);
+        req.extensions_mut().insert(Bucket {
+            rule: rule.name.clone(),
+            key,
+            limit: rule.limit,
+            period_secs: rule.period.as_secs(),
+        });
+        return None;
+    }
+
+    state.metrics_service.increment("cotai_rate_limit_rejections_total", &[("rule", rule.name.as_str())]);
+    state.threats.report_rate_limited(principal.as_ref(), client_ip(req.request()));
+    let retry_after = decision.retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    let mut response = HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+        .json(serde_json::json!({
+            "error": "Too many requests",
+            "code": "rate_limited",
+            "retry_after_secs": retry_after
+        }));
+    set_headers(response.headers_mut(), &decision, state.clock.now());
+    Some(response)
+}
+
+/// Response side of the stage: report the caller's remaining allowance.
+pub fn annotate(mut res: ServiceResponse) -> ServiceResponse {
+    let Some(state) = res.request().app_data::<web::Data<AppState>>().cloned() else {
+        return res;
+    };
+    let decision = res.request().extensions().get::<Decision>().copied();
+    if let Some(decision) = decision {
+        set_headers(res.headers_mut(), &decision, state.clock.now());
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use chrono::TimeZone;
+
+    fn limiter(vars: &[(&str, &str)]) -> (RateLimiter, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::new(Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap()));
+        let limiter = RateLimiter::new(&Config::for_tests(vars), clock.clone()).expect("rate limiter");
+        (limiter, clock)
+    }
+
+    fn rule(spec: &str) -> Rule {
+        Rule::parse("/api/v1/test", spec).expect("valid rule")
+    }
+
+    #[tokio::test]
+    async fn token_bucket_refills_at_its_rate() {
+        let (limiter, clock) = limiter(&[]);
+        let rule = rule("token_bucket:3/60");
+
+        for remaining in [2, 1, 0] {
+            let decision = limiter.acquire(&rule, "user:a").await;
+            assert!(decision.allowed);
+            assert_eq!(decision.remaining, remaining);
+        }
+        let refused = limiter.acquire(&rule, "user:a").await;
+        assert!(!refused.allowed);
+        assert_eq!(refused.retry_after, Duration::from_secs(20));
+        assert_eq!(refused.reset, Duration::from_secs(60));
+
+        // One token every 20 seconds
+        clock.advance(chrono::Duration::seconds(20));
+        assert!(limiter.acquire(&rule, "user:a").await.allowed);
+        assert!(!limiter.acquire(&rule, "user:a").await.allowed);
+
+        // Never more than the bucket holds, however long it was quiet
+        clock.advance(chrono::Duration::hours(1));
+        for _ in 0..3 {
+            assert!(limiter.acquire(&rule, "user:a").await.allowed);
+        }
+        assert!(!limiter.acquire(&rule, "user:a").await.allowed);
+    }
+
+    #[tokio::test]
+    async fn sliding_window_frees_slots_as_requests_age_out() {
+        let (limiter, clock) = limiter(&[]);
+        let rule = rule("sliding_window:2/10");
+
+        assert!(limiter.acquire(&rule, "user:a").await.allowed);
+        clock.advance(chrono::Duration::seconds(4));
+        assert!(limiter.acquire(&rule, "user:a").await.allowed);
+        let refused = limiter.acquire(&rule, "user:a").await;
+        assert!(!refused.allowed);
+        assert_eq!(refused.retry_after, Duration::from_secs(6));
+        assert_eq!(refused.reset, Duration::from_secs(10));
+
+        clock.advance(chrono::Duration::seconds(6));
+        assert!(limiter.acquire(&rule, "user:a").await.allowed);
+        assert!(!limiter.acquire(&rule, "user:a").await.allowed);
+    }
+
+    #[tokio::test]
+    async fn buckets_are_per_key_and_cleared_on_request() {
+        let (limiter, _) = limiter(&[]);
+        let rule = rule("token_bucket:1/60");
+
+        assert!(limiter.acquire(&rule, "user:a").await.allowed);
+        assert!(!limiter.acquire(&rule, "user:a").await.allowed);
+        assert!(limiter.acquire(&rule, "user:b").await.allowed);
+
+        limiter.clear(&rule, "user:a").await;
+        assert!(limiter.acquire(&rule, "user:a").await.allowed);
+    }
+
+    #[test]
+    fn rotating_addresses_escalate_to_their_subnet_for_a_while() {
+        let (limiter, clock) = limiter(&[
+            ("RATE_LIMIT_ESCALATE_THRESHOLD", "3"),
+            ("RATE_LIMIT_ESCALATE_WINDOW_SECS", "60"),
+            ("RATE_LIMIT_ESCALATE_SECS", "300"),
+        ]);
+        let rule = rule("sliding_window:20/60");
+        let ip = |last: u8| IpAddr::from([203, 0, 113, last]);
+
+        assert_eq!(limiter.address(&rule, ip(1)).key, "ip:203.0.113.1");
+        assert_eq!(limiter.address(&rule, ip(2)).key, "ip:203.0.113.2");
+        let escalated = limiter.address(&rule, ip(3));
+        assert_eq!(escalated.key, "subnet:203.0.113.0/24");
+        assert!(matches!(escalated.escalated, Some((Aggregation::Subnet, _))));
+        let held = limiter.address(&rule, ip(4));
+        assert_eq!(held.key, "subnet:203.0.113.0/24");
+        assert!(held.escalated.is_none());
+
+        clock.advance(chrono::Duration::seconds(301));
+        assert_eq!(limiter.address(&rule, ip(5)).key, "ip:203.0.113.5");
+    }
+
+    #[test]
+    fn spread_out_addresses_do_not_escalate() {
+        let (limiter, clock) = limiter(&[
+            ("RATE_LIMIT_ESCALATE_THRESHOLD", "2"),
+            ("RATE_LIMIT_ESCALATE_WINDOW_SECS", "60"),
+        ]);
+        let rule = rule("sliding_window:20/60");
+
+        assert_eq!(limiter.address(&rule, IpAddr::from([198, 51, 100, 1])).key, "ip:198.51.100.1");
+        clock.advance(chrono::Duration::seconds(61));
+        assert_eq!(limiter.address(&rule, IpAddr::from([198, 51, 100, 2])).key, "ip:198.51.100.2");
+    }
+}
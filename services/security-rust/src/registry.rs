@@ -0,0 +1,458 @@
+/*!
+Resource Registry
+Server-side resource attributes for authorization
+
+Callers of `/authz/check` describe the resource they ask about, but
+attributes such as a tender's status, its owning department or its
+classification are not theirs to vouch for. Services owning resources push
+them here instead (needs `RESOURCE_REGISTRY_SCOPE`):
+
+- `PUT /resources/{id}` with `{tenant_id, attributes, version}`; a
+  `version` lower than the stored one is refused as stale, so pushes
+  arriving out of order cannot roll a resource back
+- `DELETE /resources/{id}`
+- `POST /resources/sync` with `{tenant_id, type, resources, prune}`, up to
+  `RESOURCE_REGISTRY_MAX_BATCH` at once; with `prune`, resources of `type`
+  (the id up to its first `:`) missing from the batch are removed
+
+During a check, a registered resource's attributes replace any the caller
+gave under the same name, and `resource.registered` tells rules whether it
+was found. For `RESOURCE_REGISTRY_REQUIRED_TYPES` the caller's attributes
+are ignored altogether and unregistered resources are denied with the
+reason `resource_not_registered`.
+
+Lookups are cached per replica for `RESOURCE_REGISTRY_CACHE_TTL_SECS`,
+misses included; changes made on a replica drop its cached entry at once.
+Every change publishes a `resource.*` event.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::{FromRow, Postgres, Transaction};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tracing::{error, info, warn};
+
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::authz::Resource;
+use crate::clock::Clock;
+use crate::config::{Config, ResourceRegistryConfig};
+use crate::errors::SecurityError;
+use crate::events::{self, DomainEvent};
+use crate::storage::Storage;
+use crate::AppState;
+
+const RESOURCE_COLUMNS: &str = "id, tenant_id, resource_type, attributes, version, updated_by, updated_at";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct RegisteredResource {
+    pub id: String,
+    pub tenant_id: Option<String>,
+    /// The id up to its first `:`.
+    pub resource_type: String,
+    pub attributes: Value,
+    pub version: i64,
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResourceRequest {
+    pub tenant_id: Option<String>,
+    #[serde(default)]
+    pub attributes: Map<String, Value>,
+    /// The owning service's version; defaults to one past the stored one.
+    pub version: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncResource {
+    pub id: String,
+    #[serde(default)]
+    pub attributes: Map<String, Value>,
+    pub version: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncRequest {
+    pub tenant_id: Option<String>,
+    #[serde(rename = "type")]
+    pub resource_type: String,
+    pub resources: Vec<SyncResource>,
+    #[serde(default)]
+    pub prune: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SyncResult {
+    pub updated: usize,
+    /// Ids whose version was behind the stored one.
+    pub stale: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TenantQuery {
+    pub tenant_id: Option<String>,
+}
+
+/// The part of a resource id naming its type, e.g. `tender` of `tender:7`.
+pub fn resource_type(id: &str) -> &str {
+    id.split_once(':').map_or(id, |(kind, _)| kind)
+}
+
+type CacheKey = (Option<String>, String);
+type CacheEntry = (DateTime<Utc>, Option<Arc<RegisteredResource>>);
+
+pub struct ResourceRegistry {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: ResourceRegistryConfig,
+    cache: RwLock<HashMap<CacheKey, CacheEntry>>,
+}
+
+impl ResourceRegistry {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        info!(
+            "Resource registry initialized (required for: {})",
+            config.resource_registry.required_types.join(", ")
+        );
+        Ok(Self {
+            storage,
+            clock,
+            config: config.resource_registry.clone(),
+            cache: RwLock::new(HashMap::new()),
+        })
+    }
+
+    pub async fn get(&self, tenant_id: Option<&str>, id: &str) -> Result<Option<Arc<RegisteredResource>>, SecurityError> {
+        let now = self.clock.now();
+        let key = (tenant_id.map(str::to_string), id.to_string());
+        if let Some((expires_at, resource)) = self.cache.read().unwrap_or_else(|e| e.into_inner()).get(&key) {
+            if *expires_at > now {
+                return Ok(resource.clone());
+            }
+        }
+
+        let resource = sqlx::query_as::<_, RegisteredResource>(&format!(
+            "SELECT {} FROM registered_resources WHERE tenant_id IS NOT DISTINCT FROM $1 AND id = $2",
+            RESOURCE_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .map(Arc::new);
+
+        let mut cache = self.cache.write().unwrap_or_else(|e| e.into_inner());
+        if cache.len() >= self.config.max_cached {
+            cache.retain(|_, (expires_at, _)| *expires_at > now);
+            if cache.len() >= self.config.max_cached {
+                cache.clear();
+            }
+        }
+        cache.insert(key, (now + Duration::seconds(self.config.cache_ttl_secs as i64), resource.clone()));
+        Ok(resource)
+    }
+
+    fn invalidate(&self, tenant_id: Option<&str>, id: &str) {
+        self.cache.write().unwrap_or_else(|e| e.into_inner())
+            .remove(&(tenant_id.map(str::to_string), id.to_string()));
+    }
+
+    /// Replace what the caller said about `resource` with what is
+    /// registered under `tenant_id`. Returns `false` for an unregistered
+    /// resource of a required type, which must be denied.
+    pub async fn resolve(&self, tenant_id: Option<&str>, resource: &mut Resource) -> Result<bool, SecurityError> {
+        let required = self.config.required_types.iter().any(|t| t == resource_type(&resource.id));
+        if required {
+            resource.attributes.clear();
+        }
+        let registered = self.get(tenant_id, &resource.id).await?;
+        if let Some(Value::Object(attributes)) = registered.as_ref().map(|r| &r.attributes) {
+            for (name, value) in attributes {
+                resource.attributes.insert(name.clone(), value.clone());
+            }
+        }
+        resource.attributes.insert("registered".to_string(), Value::from(registered.is_some()));
+        Ok(registered.is_some() || !required)
+    }
+
+    /// Store `attributes` for `id`, unless `version` is behind the stored
+    /// one. Returns `None` when stale.
+    async fn upsert(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        tenant_id: Option<&str>,
+        id: &str,
+        attributes: &Map<String, Value>,
+        version: Option<i64>,
+        actor: &str,
+    ) -> Result<Option<RegisteredResource>, SecurityError> {
+        Ok(sqlx::query_as::<_, RegisteredResource>(&format!(
+            "INSERT INTO registered_resources (id, tenant_id, resource_type, attributes, version, updated_by, updated_at) \
+             VALUES ($1, $2, $3, $4, COALESCE($5, 1), $6, $7) \
+             ON CONFLICT (COALESCE(tenant_id, ''), id) DO UPDATE SET attributes = $4, \
+             version = COALESCE($5, registered_resources.version + 1), updated_by = $6, updated_at = $7 \
+             WHERE $5 IS NULL OR registered_resources.version <= $5 RETURNING {}",
+            RESOURCE_COLUMNS
+        ))
+        .bind(id)
+        .bind(tenant_id)
+        .bind(resource_type(id))
+        .bind(Value::Object(attributes.clone()))
+        .bind(version)
+        .bind(actor)
+        .bind(self.clock.now())
+        .fetch_optional(&mut **tx)
+        .await?)
+    }
+
+    pub async fn put(&self, tenant_id: Option<&str>, id: &str, request: &ResourceRequest, actor: &str) -> Result<RegisteredResource, SecurityError> {
+        validate_id(id)?;
+        let mut tx = self.storage.begin().await?;
+        let resource = self.upsert(&mut tx, tenant_id, id, &request.attributes, request.version, actor).await?
+            .ok_or_else(|| SecurityError::Conflict(format!("{} has a newer version than {}", id, request.version.unwrap_or_default())))?;
+        let event = DomainEvent::new("resource.updated", "resource", id, resource.tenant_id.clone(), serde_json::json!({
+            "version": resource.version,
+            "attributes": resource.attributes
+        }), self.clock.now());
+        events::enqueue(&mut tx, &event).await?;
+        tx.commit().await?;
+        self.invalidate(tenant_id, id);
+        Ok(resource)
+    }
+
+    pub async fn delete(&self, tenant_id: Option<&str>, id: &str) -> Result<RegisteredResource, SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let resource = sqlx::query_as::<_, RegisteredResource>(&format!(
+            "DELETE FROM registered_resources WHERE tenant_id IS NOT DISTINCT FROM $1 AND id = $2 RETURNING {}",
+            RESOURCE_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("{} is not registered", id)))?;
+        let event = DomainEvent::new("resource.deleted", "resource", id, resource.tenant_id.clone(), serde_json::json!({}), self.clock.now());
+        events::enqueue(&mut tx, &event).await?;
+        tx.commit().await?;
+        self.invalidate(tenant_id, id);
+        Ok(resource)
+    }
+
+    /// Register a batch of one type, optionally removing the rest of it.
+    pub async fn sync(&self, tenant_id: Option<&str>, request: &SyncRequest, actor: &str) -> Result<SyncResult, SecurityError> {
+        if request.resources.len() > self.config.max_batch {
+            return Err(SecurityError::ValidationError(format!(
+                "At most {} resources per sync",
+                self.config.max_batch
+            )));
+        }
+        let kind = request.resource_type.trim();
+        if kind.is_empty() {
+            return Err(SecurityError::ValidationError("type is required".to_string()));
+        }
+        for resource in &request.resources {
+            validate_id(&resource.id)?;
+            if resource_type(&resource.id) != kind {
+                return Err(SecurityError::ValidationError(format!("{} is not of type {}", resource.id, kind)));
+            }
+        }
+
+        let mut tx = self.storage.begin().await?;
+        let mut result = SyncResult::default();
+        for resource in &request.resources {
+            match self.upsert(&mut tx, tenant_id, &resource.id, &resource.attributes, resource.version, actor).await? {
+                Some(_) => result.updated += 1,
+                None => result.stale.push(resource.id.clone()),
+            }
+        }
+        if request.prune {
+            let ids: Vec<String> = request.resources.iter().map(|r| r.id.clone()).collect();
+            result.removed = sqlx::query_scalar(
+                "DELETE FROM registered_resources WHERE tenant_id IS NOT DISTINCT FROM $1 AND resource_type = $2 \
+                 AND NOT id = ANY($3) RETURNING id",
+            )
+            .bind(tenant_id)
+            .bind(kind)
+            .bind(&ids)
+            .fetch_all(&mut *tx)
+            .await?;
+        }
+        let event = DomainEvent::new("resource.synced", "resource_type", kind, tenant_id.map(str::to_string), serde_json::json!({
+            "updated": result.updated,
+            "stale": result.stale,
+            "removed": result.removed
+        }), self.clock.now());
+        events::enqueue(&mut tx, &event).await?;
+        tx.commit().await?;
+
+        for id in request.resources.iter().map(|r| &r.id).chain(&result.removed) {
+            self.invalidate(tenant_id, id);
+        }
+        Ok(result)
+    }
+}
+
+fn validate_id(id: &str) -> Result<(), SecurityError> {
+    if id.trim().is_empty() || id.len() > 500 {
+        return Err(SecurityError::ValidationError("id must be 1-500 characters".to_string()));
+    }
+    Ok(())
+}
+
+/// The tenant a change applies to: the caller's own, or for platform
+/// callers the one asked for.
+fn tenant_of(principal: &Principal, requested: Option<&str>) -> Result<Option<String>, SecurityError> {
+    match (&principal.tenant_id, requested) {
+        (Some(own), Some(requested)) if own != requested => Err(SecurityError::AccessDenied(format!(
+            "{} can only register its own tenant's resources",
+            principal.subject
+        ))),
+        (Some(own), _) => Ok(Some(own.clone())),
+        (None, requested) => Ok(requested.map(str::to_string)),
+    }
+}
+
+async fn audit(state: &AppState, req: &HttpRequest, principal: &Principal, tenant_id: Option<String>, action: &str, resource: String, payload: Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id,
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(req),
+        action: action.to_string(),
+        resource: resource.clone(),
+        outcome: "success".to_string(),
+        payload,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} of {}: {:?}", action, resource, e);
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::AccessDenied(msg) => HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Resource registry operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Resource registry operation failed"
+            }))
+        }
+    }
+}
+
+fn authorize(state: &AppState, req: &HttpRequest) -> Result<Principal, SecurityError> {
+    authorize_scope(state, req, &state.config.resource_registry.scope)
+}
+
+pub async fn get_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TenantQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let tenant_id = match tenant_of(&principal, query.tenant_id.as_deref()) {
+        Ok(tenant_id) => tenant_id,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.resource_registry.get(tenant_id.as_deref(), &path).await {
+        Ok(Some(resource)) => Ok(HttpResponse::Ok().json(&*resource)),
+        Ok(None) => Ok(error_response(SecurityError::NotFound(format!("{} is not registered", path)))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn put_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    request: web::Json<ResourceRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let tenant_id = match tenant_of(&principal, request.tenant_id.as_deref()) {
+        Ok(tenant_id) => tenant_id,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.resource_registry.put(tenant_id.as_deref(), &path, &request, &principal.subject).await {
+        Ok(resource) => Ok(HttpResponse::Ok().json(resource)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn delete_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TenantQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let tenant_id = match tenant_of(&principal, query.tenant_id.as_deref()) {
+        Ok(tenant_id) => tenant_id,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.resource_registry.delete(tenant_id.as_deref(), &path).await {
+        Ok(resource) => {
+            audit(&state, &req, &principal, tenant_id, "resource.delete", format!("resource:{}", resource.id),
+                serde_json::json!({ "version": resource.version })).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn sync_handler(req: HttpRequest, request: web::Json<SyncRequest>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match authorize(&state, &req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let tenant_id = match tenant_of(&principal, request.tenant_id.as_deref()) {
+        Ok(tenant_id) => tenant_id,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.resource_registry.sync(tenant_id.as_deref(), &request, &principal.subject).await {
+        Ok(result) => {
+            audit(&state, &req, &principal, tenant_id, "resource.sync", format!("resource_type:{}", request.resource_type.trim()),
+                serde_json::to_value(&result).unwrap_or_default()).await;
+            Ok(HttpResponse::Ok().json(result))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/resources")
+            .route("/sync", web::post().to(sync_handler))
+            .route("/{id}", web::get().to(get_handler))
+            .route("/{id}", web::put().to(put_handler))
+            .route("/{id}", web::delete().to(delete_handler)),
+    );
+}
@@ -0,0 +1,443 @@
+/*!
+Residency Module
+Pinning tenants' stored data to a region
+
+Some tenants must keep their data in one region. The service's own
+buckets are in `RESIDENCY_HOME_REGION`; `RESIDENCY_REGIONS` adds a bucket
+per other region (`sa-east-1=cotai-sa,...`, credentials from the usual
+`AWS_*` variables). `PUT /admin/tenants/{tenant_id}/residency` with
+`{region}` pins a tenant to one of them, after which:
+
+- their audit exports (see `audit::export`) and the events of theirs that
+  retention archives (see `audit::retention`) are written to the region's
+  bucket, a retention batch being split into one archive per region
+- their dossiers (see `dossiers`) and sealed documents (see `seal`) are
+  kept there
+- an export to another region is refused, and so is an export spanning
+  tenants while any tenant is pinned elsewhere
+
+Objects keep the region they were written to, so pinning a tenant does not
+move what it already has. Every tenant-scoped object written is recorded
+as a placement, and `GET /admin/residency` reports per tenant where its
+objects are and whether the tenant complies: pinned tenants need all of
+them in their region, their region's bucket configured, and the key
+provider in their region. Data keys are the service's, wrapped by the one
+provider (see `key_provider`); `aws-kms` keys are in `AWS_REGION`, the
+others are taken to run in the home region. The database itself is not
+routed.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use object_store::aws::AmazonS3Builder;
+use object_store::ObjectStore;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, client_ip};
+use crate::clock::Clock;
+use crate::config::{Config, ResidencyConfig};
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+
+const POLICY_COLUMNS: &str = "tenant_id, region, updated_by, updated_at";
+
+/// One store per `RESIDENCY_REGIONS` entry, by region.
+pub fn build_stores(config: &Config) -> Result<HashMap<String, Arc<dyn ObjectStore>>, SecurityError> {
+    config
+        .residency
+        .regions
+        .iter()
+        .map(|(region, bucket)| {
+            let store = AmazonS3Builder::from_env()
+                .with_bucket_name(bucket)
+                .with_region(region)
+                .build()
+                .map_err(|e| SecurityError::ConfigError(format!("Residency store for {}: {}", region, e)))?;
+            Ok((region.clone(), Arc::new(store) as Arc<dyn ObjectStore>))
+        })
+        .collect()
+}
+
+/// The store objects of `region` go to; `None` is the home region's.
+pub fn store_in<'a>(
+    stores: &'a HashMap<String, Arc<dyn ObjectStore>>,
+    home: Option<&'a Arc<dyn ObjectStore>>,
+    region: Option<&str>,
+) -> Result<&'a Arc<dyn ObjectStore>, SecurityError> {
+    match region {
+        Some(region) => stores
+            .get(region)
+            .ok_or_else(|| SecurityError::ConfigError(format!("No storage is configured for region {}", region))),
+        None => home.ok_or_else(|| SecurityError::ConfigError("Storage is not configured".to_string())),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Policy {
+    pub tenant_id: String,
+    pub region: String,
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyRequest {
+    pub region: String,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct PlacementCount {
+    pub kind: String,
+    pub region: String,
+    pub objects: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyResidency {
+    pub provider: String,
+    pub region: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TenantResidency {
+    pub tenant_id: String,
+    /// Unpinned tenants have no requirement and always comply.
+    pub region: Option<String>,
+    pub storage_configured: bool,
+    pub keys_in_region: bool,
+    pub placements: Vec<PlacementCount>,
+    /// Objects outside the pinned region, e.g. written before pinning.
+    pub out_of_region: i64,
+    pub compliant: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub home_region: String,
+    pub regions: Vec<String>,
+    pub keys: KeyResidency,
+    pub tenants: Vec<TenantResidency>,
+}
+
+pub struct ResidencyService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: ResidencyConfig,
+    keys: KeyResidency,
+}
+
+impl ResidencyService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let keys = KeyResidency {
+            provider: config.crypto.provider.clone(),
+            region: match (config.crypto.provider.as_str(), &config.secrets.kms_region) {
+                ("aws-kms", Some(region)) => region.clone(),
+                _ => config.residency.home_region.clone(),
+            },
+        };
+
+        info!("Residency service initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            config: config.residency.clone(),
+            keys,
+        })
+    }
+
+    fn known(&self, region: &str) -> bool {
+        region == self.config.home_region || self.config.regions.iter().any(|(name, _)| name == region)
+    }
+
+    /// The region a tenant is pinned to, if any.
+    pub async fn pinned(&self, tenant_id: Option<&str>) -> Result<Option<String>, SecurityError> {
+        let Some(tenant_id) = tenant_id else {
+            return Ok(None);
+        };
+        let region: Option<(String,)> = sqlx::query_as("SELECT region FROM residency_policies WHERE tenant_id = $1")
+            .bind(tenant_id)
+            .fetch_optional(self.storage.pool())
+            .await?;
+        Ok(region.map(|(region,)| region))
+    }
+
+    /// Where a tenant's new objects go: its region, or `None` for the home
+    /// region's stores.
+    pub async fn placement(&self, tenant_id: Option<&str>) -> Result<Option<String>, SecurityError> {
+        Ok(self.pinned(tenant_id).await?.and_then(|region| self.regional(region)))
+    }
+
+    /// `region` as stored on objects: `None` for the home region.
+    pub fn regional(&self, region: String) -> Option<String> {
+        Some(region).filter(|region| *region != self.config.home_region)
+    }
+
+    /// Refuse exporting a tenant's data, or every tenant's when `tenant_id`
+    /// is `None`, to `destination` (`None` for the home region).
+    pub async fn check_export(&self, tenant_id: Option<&str>, destination: Option<&str>) -> Result<(), SecurityError> {
+        let destination = destination.unwrap_or(&self.config.home_region);
+        if !self.known(destination) {
+            return Err(SecurityError::ValidationError(format!("Unknown region '{}'", destination)));
+        }
+        match tenant_id {
+            Some(tenant_id) => match self.pinned(Some(tenant_id)).await? {
+                Some(region) if region != destination => Err(SecurityError::AccessDenied(format!(
+                    "Tenant {} is pinned to {}; its data may not be exported to {}",
+                    tenant_id, region, destination
+                ))),
+                _ => Ok(()),
+            },
+            None => {
+                let elsewhere: Vec<(String,)> = sqlx::query_as(
+                    "SELECT tenant_id FROM residency_policies WHERE region <> $1 ORDER BY tenant_id LIMIT 10",
+                )
+                .bind(destination)
+                .fetch_all(self.storage.pool())
+                .await?;
+                if elsewhere.is_empty() {
+                    return Ok(());
+                }
+                let tenants: Vec<String> = elsewhere.into_iter().map(|(tenant,)| tenant).collect();
+                Err(SecurityError::AccessDenied(format!(
+                    "Tenants pinned outside {} (e.g. {}) would be exported; export one tenant at a time",
+                    destination,
+                    tenants.join(", ")
+                )))
+            }
+        }
+    }
+
+    /// Record that a tenant's object was written to `region` (`None` for
+    /// the home region).
+    pub async fn record(&self, tenant_id: Option<&str>, kind: &str, resource: &str, region: Option<&str>) {
+        let Some(tenant_id) = tenant_id else {
+            return;
+        };
+        let recorded = sqlx::query(
+            "INSERT INTO residency_placements (id, tenant_id, kind, resource, region, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(Uuid::new_v4())
+        .bind(tenant_id)
+        .bind(kind)
+        .bind(resource)
+        .bind(region.unwrap_or(&self.config.home_region))
+        .bind(self.clock.now())
+        .execute(self.storage.pool())
+        .await;
+        if let Err(e) = recorded {
+            warn!("Failed to record the placement of {}: {:?}", resource, e);
+        }
+    }
+
+    pub async fn get(&self, tenant_id: &str) -> Result<Policy, SecurityError> {
+        sqlx::query_as::<_, Policy>(&format!("SELECT {} FROM residency_policies WHERE tenant_id = $1", POLICY_COLUMNS))
+            .bind(tenant_id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound(format!("Tenant {} is not pinned", tenant_id)))
+    }
+
+    pub async fn put(&self, tenant_id: &str, region: &str, updated_by: &str) -> Result<Policy, SecurityError> {
+        let region = region.trim();
+        if !self.known(region) {
+            return Err(SecurityError::ValidationError(format!(
+                "Unknown region '{}'; add it to RESIDENCY_REGIONS first",
+                region
+            )));
+        }
+        Ok(sqlx::query_as::<_, Policy>(&format!(
+            "INSERT INTO residency_policies (tenant_id, region, updated_by, updated_at) VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (tenant_id) DO UPDATE SET region = $2, updated_by = $3, updated_at = $4 RETURNING {}",
+            POLICY_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(region)
+        .bind(updated_by)
+        .bind(self.clock.now())
+        .fetch_one(self.storage.pool())
+        .await?)
+    }
+
+    pub async fn delete(&self, tenant_id: &str) -> Result<(), SecurityError> {
+        let deleted = sqlx::query("DELETE FROM residency_policies WHERE tenant_id = $1")
+            .bind(tenant_id)
+            .execute(self.storage.pool())
+            .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(SecurityError::NotFound(format!("Tenant {} is not pinned", tenant_id)));
+        }
+        Ok(())
+    }
+
+    pub async fn report(&self) -> Result<Report, SecurityError> {
+        let pool = self.storage.pool();
+        let policies = sqlx::query_as::<_, Policy>(&format!("SELECT {} FROM residency_policies", POLICY_COLUMNS))
+            .fetch_all(pool)
+            .await?;
+        let counts: Vec<(String, String, String, i64)> = sqlx::query_as(
+            "SELECT tenant_id, kind, region, COUNT(*) FROM residency_placements \
+             GROUP BY tenant_id, kind, region ORDER BY tenant_id, kind, region",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut tenants: BTreeMap<String, (Option<String>, Vec<PlacementCount>)> = BTreeMap::new();
+        for policy in policies {
+            tenants.entry(policy.tenant_id).or_default().0 = Some(policy.region);
+        }
+        for (tenant_id, kind, region, objects) in counts {
+            tenants.entry(tenant_id).or_default().1.push(PlacementCount { kind, region, objects });
+        }
+
+        let tenants = tenants
+            .into_iter()
+            .map(|(tenant_id, (region, placements))| {
+                let (storage_configured, keys_in_region, out_of_region) = match &region {
+                    Some(region) => (
+                        *region == self.config.home_region || self.config.regions.iter().any(|(name, _)| name == region),
+                        self.keys.region == *region,
+                        placements.iter().filter(|p| p.region != *region).map(|p| p.objects).sum(),
+                    ),
+                    None => (true, true, 0),
+                };
+                TenantResidency {
+                    tenant_id,
+                    region,
+                    storage_configured,
+                    keys_in_region,
+                    placements,
+                    out_of_region,
+                    compliant: storage_configured && keys_in_region && out_of_region == 0,
+                }
+            })
+            .collect();
+
+        Ok(Report {
+            home_region: self.config.home_region.clone(),
+            regions: self.config.regions.iter().map(|(name, _)| name.clone()).collect(),
+            keys: KeyResidency { provider: self.keys.provider.clone(), region: self.keys.region.clone() },
+            tenants,
+        })
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Residency operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Residency operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit_policy_change(state: &crate::AppState, req: &HttpRequest, actor: &str, action: &str, tenant_id: &str, detail: serde_json::Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: Some(tenant_id.to_string()),
+        actor: actor.to_string(),
+        actor_ip: client_ip(req),
+        action: action.to_string(),
+        resource: format!("residency_policy:{}", tenant_id),
+        outcome: "success".to_string(),
+        payload: detail,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit residency change for tenant {}: {:?}", tenant_id, e);
+    }
+}
+
+pub async fn get_policy_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.residency.get(&path.into_inner()).await {
+        Ok(policy) => Ok(HttpResponse::Ok().json(policy)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn put_policy_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    request: web::Json<PolicyRequest>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let tenant_id = path.into_inner();
+    match state.residency.put(&tenant_id, &request.region, &principal.subject).await {
+        Ok(policy) => {
+            audit_policy_change(&state, &req, &principal.subject, "residency.pin", &tenant_id, serde_json::json!({
+                "region": policy.region
+            })).await;
+            Ok(HttpResponse::Ok().json(policy))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn delete_policy_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let tenant_id = path.into_inner();
+    match state.residency.delete(&tenant_id).await {
+        Ok(()) => {
+            audit_policy_change(&state, &req, &principal.subject, "residency.unpin", &tenant_id, serde_json::json!({})).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn report_handler(req: HttpRequest, state: web::Data<crate::AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.residency.report().await {
+        Ok(report) => Ok(HttpResponse::Ok().json(report)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/admin/residency", web::get().to(report_handler))
+        .service(
+            web::scope("/admin/tenants/{tenant_id}/residency")
+                .route("", web::get().to(get_policy_handler))
+                .route("", web::put().to(put_policy_handler))
+                .route("", web::delete().to(delete_policy_handler))
+        );
+}
@@ -0,0 +1,625 @@
+/*!
+Retention Module
+Ageing out operational data other than audit events
+
+Audit events have their own, chain-aware retention (see
+`audit::retention`). Everything else this service stores is covered here:
+`RETENTION_POLICIES` lists `data_type=days` pairs, and every
+`RETENTION_INTERVAL_SECS`, or once a day at `RETENTION_RUN_AT` local time,
+rows older than that are deleted, at most `RETENTION_BATCH_SIZE` per
+statement. Days are counted in `TENANT_TIME_ZONE` and whole: a row goes
+once the local day it was last touched in is that many days past, however
+long DST made those days. Types not listed are left to their own modules'
+clean-up.
+
+| Data type   | Table                   | Aged by                       | Only once                                   |
+|-------------|-------------------------|-------------------------------|---------------------------------------------|
+| `sessions`  | `refresh_tokens`        | revocation, else expiry       |                                             |
+| `tokens`    | `revoked_tokens`        | revocation                    | the revoked token has expired               |
+| `otps`      | `otp_challenges`        | expiry                        |                                             |
+| `messages`  | `delivery_messages`     | creation                      | no OTP challenge refers to it               |
+| `webhooks`  | `soar_deliveries`       | creation                      | delivered or failed                         |
+| `incidents` | `security_incidents`    | last seen                     | resolved, with nothing referring to it      |
+| `captcha`   | `captcha_verifications` | creation                      |                                             |
+| `threats`   | `threat_detections`     | last seen                     | no longer active                            |
+| `forensics` | `forensic_captures`     | creation                      |                                             |
+
+`forensics` follows `FORENSICS_RETENTION_DAYS` when not listed, so request
+captures are never kept indefinitely.
+
+Types are enforced in that order, so webhook deliveries go before the
+incidents they belong to and OTP challenges before their messages.
+
+A legal hold exempts rows from every policy until released: all of one data
+type, one tenant's rows, or both. A hold naming a tenant on a type with no
+tenant (`tokens`, `captcha`) holds the whole type. Admins manage holds under
+`/admin/retention/holds`; `GET /admin/retention/preview` counts what the
+next run would delete and what holds are keeping.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::receipts::{self, RECEIPT_HEADER};
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, RetentionConfig};
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+use crate::timezone;
+use crate::AppState;
+
+const SYSTEM_ACTOR: &str = "system:retention";
+
+const HOLD_COLUMNS: &str = "id, data_type, tenant_id, case_reference, reason, created_by, created_at, \
+    released_by, released_at";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataType {
+    Sessions,
+    Tokens,
+    Otps,
+    Messages,
+    Webhooks,
+    Incidents,
+    Captcha,
+    Threats,
+    Forensics,
+}
+
+/// Where a data type lives and which of its rows may go. Expressions refer
+/// to the row as `t`.
+struct Table {
+    name: &'static str,
+    key: &'static str,
+    aged_by: &'static str,
+    /// The row's tenant, for types that have one.
+    tenant: Option<&'static str>,
+    /// What a row must also meet to be deleted, whatever its age.
+    only: Option<&'static str>,
+}
+
+impl DataType {
+    /// In enforcement order.
+    pub const ALL: [DataType; 9] = [
+        DataType::Sessions,
+        DataType::Tokens,
+        DataType::Otps,
+        DataType::Messages,
+        DataType::Webhooks,
+        DataType::Incidents,
+        DataType::Captcha,
+        DataType::Threats,
+        DataType::Forensics,
+    ];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.as_str() == name)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataType::Sessions => "sessions",
+            DataType::Tokens => "tokens",
+            DataType::Otps => "otps",
+            DataType::Messages => "messages",
+            DataType::Webhooks => "webhooks",
+            DataType::Incidents => "incidents",
+            DataType::Captcha => "captcha",
+            DataType::Threats => "threats",
+            DataType::Forensics => "forensics",
+        }
+    }
+
+    fn table(&self) -> Table {
+        match self {
+            DataType::Sessions => Table {
+                name: "refresh_tokens",
+                key: "id",
+                aged_by: "COALESCE(t.revoked_at, t.expires_at)",
+                tenant: Some("t.tenant_id"),
+                only: None,
+            },
+            // An unexpired revocation is still rejecting its token
+            DataType::Tokens => Table {
+                name: "revoked_tokens",
+                key: "jti",
+                aged_by: "t.revoked_at",
+                tenant: None,
+                only: Some("t.expires_at <= NOW()"),
+            },
+            DataType::Otps => Table {
+                name: "otp_challenges",
+                key: "id",
+                aged_by: "t.expires_at",
+                tenant: Some("t.tenant_id"),
+                only: None,
+            },
+            DataType::Messages => Table {
+                name: "delivery_messages",
+                key: "id",
+                aged_by: "t.created_at",
+                tenant: Some("t.tenant_id"),
+                only: Some("NOT EXISTS (SELECT 1 FROM otp_challenges o WHERE o.message_id = t.id)"),
+            },
+            DataType::Webhooks => Table {
+                name: "soar_deliveries",
+                key: "id",
+                aged_by: "t.created_at",
+                tenant: Some("(SELECT i.tenant_id FROM security_incidents i WHERE i.id = t.incident_id)"),
+                only: Some("t.status <> 'pending'"),
+            },
+            DataType::Incidents => Table {
+                name: "security_incidents",
+                key: "id",
+                aged_by: "t.last_seen",
+                tenant: Some("t.tenant_id"),
+                only: Some(
+                    "t.status = 'resolved' \
+                     AND NOT EXISTS (SELECT 1 FROM soar_deliveries d WHERE d.incident_id = t.id) \
+                     AND NOT EXISTS (SELECT 1 FROM containment_actions c WHERE c.incident_id = t.id) \
+                     AND NOT EXISTS (SELECT 1 FROM key_compromises k WHERE k.incident_id = t.id)",
+                ),
+            },
+            DataType::Captcha => Table {
+                name: "captcha_verifications",
+                key: "id",
+                aged_by: "t.created_at",
+                tenant: None,
+                only: None,
+            },
+            DataType::Threats => Table {
+                name: "threat_detections",
+                key: "id",
+                aged_by: "t.last_seen",
+                tenant: Some("t.tenant_id"),
+                only: Some("t.status <> 'active'"),
+            },
+            DataType::Forensics => Table {
+                name: "forensic_captures",
+                key: "id",
+                aged_by: "t.created_at",
+                tenant: Some("t.tenant_id"),
+                only: None,
+            },
+        }
+    }
+
+    /// Rows past `$1` this type's policy would delete, held or not.
+    fn expired(&self) -> String {
+        let table = self.table();
+        match table.only {
+            Some(only) => format!("{} < $1 AND {}", table.aged_by, only),
+            None => format!("{} < $1", table.aged_by),
+        }
+    }
+
+    /// Rows an active legal hold keeps.
+    fn held(&self) -> String {
+        let tenant = match self.table().tenant {
+            Some(tenant) => format!(" AND (h.tenant_id IS NULL OR h.tenant_id = {})", tenant),
+            None => String::new(),
+        };
+        format!(
+            "EXISTS (SELECT 1 FROM legal_holds h WHERE h.released_at IS NULL \
+             AND (h.data_type IS NULL OR h.data_type = '{}'){})",
+            self.as_str(),
+            tenant
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Policy {
+    pub data_type: DataType,
+    pub retention_days: i64,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct LegalHold {
+    pub id: Uuid,
+    /// Every data type when absent.
+    pub data_type: Option<String>,
+    /// Every tenant when absent.
+    pub tenant_id: Option<String>,
+    pub case_reference: String,
+    pub reason: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub released_by: Option<String>,
+    pub released_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HoldRequest {
+    pub data_type: Option<String>,
+    pub tenant_id: Option<String>,
+    pub case_reference: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HoldFilter {
+    #[serde(default)]
+    pub include_released: bool,
+}
+
+/// What the next run would do for one policy.
+#[derive(Debug, Serialize)]
+pub struct Preview {
+    pub data_type: DataType,
+    pub retention_days: i64,
+    pub cutoff: DateTime<Utc>,
+    pub deletable: i64,
+    /// Past the cutoff but kept by a legal hold.
+    pub held: i64,
+    pub oldest_deletable: Option<DateTime<Utc>>,
+}
+
+pub struct RetentionService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: RetentionConfig,
+    zone: Tz,
+    run_at: Option<NaiveTime>,
+    /// In enforcement order.
+    policies: Vec<Policy>,
+}
+
+impl RetentionService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let policies = DataType::ALL
+            .into_iter()
+            .filter_map(|data_type| {
+                let days = match config.retention.policies.iter().find(|(name, _)| name == data_type.as_str()) {
+                    Some((_, days)) => *days,
+                    None if data_type == DataType::Forensics => config.forensics.retention_days,
+                    None => return None,
+                };
+                Some(Policy { data_type, retention_days: days })
+            })
+            .collect();
+
+        let zone = timezone::parse_zone(&config.tenant_settings.time_zone)
+            .map_err(|_| SecurityError::ConfigError(format!("Unknown time zone '{}'", config.tenant_settings.time_zone)))?;
+        let run_at = match &config.retention.run_at {
+            Some(at) => Some(NaiveTime::parse_from_str(at, "%H:%M")
+                .map_err(|_| SecurityError::ConfigError(format!("Invalid RETENTION_RUN_AT '{}'", at)))?),
+            None => None,
+        };
+
+        info!("Retention service initialized successfully");
+        Ok(Self { storage, clock, config: config.retention.clone(), zone, run_at, policies })
+    }
+
+    pub fn policies(&self) -> &[Policy] {
+        &self.policies
+    }
+
+    fn cutoff(&self, policy: &Policy, now: DateTime<Utc>) -> DateTime<Utc> {
+        let today = timezone::local_date(self.zone, now);
+        timezone::day_start(self.zone, today - Duration::days(policy.retention_days))
+    }
+
+    /// How long until the next run is due.
+    fn until_next_run(&self) -> std::time::Duration {
+        let now = self.clock.now();
+        match self.run_at {
+            Some(at) => (timezone::next_at(self.zone, now, at) - now).to_std().unwrap_or_default(),
+            None => std::time::Duration::from_secs(self.config.interval_secs),
+        }
+    }
+
+    pub async fn preview(&self) -> Result<Vec<Preview>, SecurityError> {
+        let now = self.clock.now();
+        let mut previews = Vec::with_capacity(self.policies.len());
+        for policy in &self.policies {
+            let data_type = policy.data_type;
+            let cutoff = self.cutoff(policy, now);
+            let held = data_type.held();
+            let (deletable, held_count, oldest): (i64, i64, Option<DateTime<Utc>>) = sqlx::query_as(&format!(
+                "SELECT COUNT(*) FILTER (WHERE NOT {held}), COUNT(*) FILTER (WHERE {held}), \
+                 MIN({aged_by}) FILTER (WHERE NOT {held}) FROM {table} t WHERE {expired}",
+                held = held,
+                aged_by = data_type.table().aged_by,
+                table = data_type.table().name,
+                expired = data_type.expired(),
+            ))
+            .bind(cutoff)
+            .fetch_one(self.storage.pool())
+            .await?;
+
+            previews.push(Preview {
+                data_type,
+                retention_days: policy.retention_days,
+                cutoff,
+                deletable,
+                held: held_count,
+                oldest_deletable: oldest,
+            });
+        }
+        Ok(previews)
+    }
+
+    /// Delete the next batch of expired, unheld rows of a type.
+    async fn delete_batch(&self, data_type: DataType, cutoff: DateTime<Utc>) -> Result<u64, SecurityError> {
+        let table = data_type.table();
+        let result = sqlx::query(&format!(
+            "DELETE FROM {table} WHERE {key} IN (SELECT t.{key} FROM {table} t WHERE {expired} AND NOT {held} \
+             LIMIT $2 FOR UPDATE SKIP LOCKED)",
+            table = table.name,
+            key = table.key,
+            expired = data_type.expired(),
+            held = data_type.held(),
+        ))
+        .bind(cutoff)
+        .bind(self.config.batch_size)
+        .execute(self.storage.pool())
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Apply every policy. Returns how many rows each removed.
+    pub async fn enforce(&self) -> Result<Vec<(Policy, DateTime<Utc>, u64)>, SecurityError> {
+        let now = self.clock.now();
+        let mut removed = Vec::with_capacity(self.policies.len());
+        for policy in &self.policies {
+            let cutoff = self.cutoff(policy, now);
+            let mut total = 0;
+            loop {
+                let deleted = self.delete_batch(policy.data_type, cutoff).await?;
+                total += deleted;
+                if deleted < self.config.batch_size as u64 {
+                    break;
+                }
+            }
+            removed.push((policy.clone(), cutoff, total));
+        }
+        Ok(removed)
+    }
+
+    pub async fn list_holds(&self, include_released: bool) -> Result<Vec<LegalHold>, SecurityError> {
+        let holds = sqlx::query_as::<_, LegalHold>(&format!(
+            "SELECT {} FROM legal_holds WHERE $1 OR released_at IS NULL ORDER BY created_at DESC",
+            HOLD_COLUMNS
+        ))
+        .bind(include_released)
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(holds)
+    }
+
+    pub async fn create_hold(&self, principal: &Principal, request: HoldRequest) -> Result<LegalHold, SecurityError> {
+        if let Some(data_type) = &request.data_type {
+            if DataType::parse(data_type).is_none() {
+                return Err(SecurityError::ValidationError(format!("Unknown data type '{}'", data_type)));
+            }
+        }
+        if request.tenant_id.as_deref().is_some_and(|t| t.trim().is_empty()) {
+            return Err(SecurityError::ValidationError("tenant_id must not be empty".to_string()));
+        }
+        if request.case_reference.trim().is_empty() || request.reason.trim().is_empty() {
+            return Err(SecurityError::ValidationError("case_reference and reason are required".to_string()));
+        }
+
+        let hold = sqlx::query_as::<_, LegalHold>(&format!(
+            "INSERT INTO legal_holds (id, data_type, tenant_id, case_reference, reason, created_by, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING {}",
+            HOLD_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(&request.data_type)
+        .bind(&request.tenant_id)
+        .bind(request.case_reference.trim())
+        .bind(request.reason.trim())
+        .bind(&principal.subject)
+        .bind(self.clock.now())
+        .fetch_one(self.storage.pool())
+        .await?;
+        info!("Legal hold {} placed for {}", hold.id, hold.case_reference);
+        Ok(hold)
+    }
+
+    pub async fn release_hold(&self, principal: &Principal, id: Uuid) -> Result<LegalHold, SecurityError> {
+        let released = sqlx::query_as::<_, LegalHold>(&format!(
+            "UPDATE legal_holds SET released_by = $2, released_at = $3 \
+             WHERE id = $1 AND released_at IS NULL RETURNING {}",
+            HOLD_COLUMNS
+        ))
+        .bind(id)
+        .bind(&principal.subject)
+        .bind(self.clock.now())
+        .fetch_optional(self.storage.pool())
+        .await?;
+        released.ok_or_else(|| SecurityError::NotFound(format!("No active legal hold {}", id)))
+    }
+}
+
+/// Background loop enforcing the policies.
+pub async fn run_enforcement(state: web::Data<AppState>) {
+    if state.retention.policies().is_empty() {
+        return;
+    }
+    let mut due = tokio::time::Instant::now();
+    if state.config.retention.run_at.is_some() {
+        due += state.retention.until_next_run();
+    }
+
+    loop {
+        tokio::time::sleep_until(due).await;
+        due = tokio::time::Instant::now() + state.retention.until_next_run();
+        let removed = match state.retention.enforce().await {
+            Ok(removed) => removed,
+            Err(e) => {
+                warn!("Retention run failed: {:?}", e);
+                continue;
+            }
+        };
+        for (policy, cutoff, count) in removed.into_iter().filter(|(_, _, count)| *count > 0) {
+            info!("Retention removed {} {} older than {}", count, policy.data_type.as_str(), cutoff);
+            let recorded = state.audit_service.record(NewAuditEvent {
+                tenant_id: None,
+                actor: SYSTEM_ACTOR.to_string(),
+                actor_ip: None,
+                action: "retention.enforce".to_string(),
+                resource: format!("retention:{}", policy.data_type.as_str()),
+                outcome: "success".to_string(),
+                payload: serde_json::json!({
+                    "retention_days": policy.retention_days,
+                    "cutoff": cutoff,
+                    "deleted": count
+                }),
+            }).await;
+            if let Err(e) = recorded {
+                warn!("Failed to audit retention of {}: {:?}", policy.data_type.as_str(), e);
+            }
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Retention operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Retention operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit_hold(state: &AppState, req: &HttpRequest, principal: &Principal, verb: &str, hold: &LegalHold) -> Option<String> {
+    receipts::record_or_warn(state, NewAuditEvent {
+        tenant_id: hold.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(req),
+        action: format!("retention.hold.{}", verb),
+        resource: format!("legal_hold:{}", hold.id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "data_type": hold.data_type,
+            "case_reference": hold.case_reference,
+            "reason": hold.reason
+        }),
+    }).await
+}
+
+fn with_receipt(mut response: actix_web::HttpResponseBuilder, receipt: Option<String>) -> actix_web::HttpResponseBuilder {
+    if let Some(receipt) = receipt {
+        response.insert_header((RECEIPT_HEADER, receipt));
+    }
+    response
+}
+
+pub async fn status_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.retention.list_holds(false).await {
+        Ok(holds) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "policies": state.retention.policies(),
+            "interval_secs": state.config.retention.interval_secs,
+            "run_at": state.config.retention.run_at,
+            "time_zone": state.config.tenant_settings.time_zone,
+            "holds": holds
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn preview_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.retention.preview().await {
+        Ok(previews) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "policies": previews
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_holds_handler(
+    req: HttpRequest,
+    filter: web::Query<HoldFilter>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.retention.list_holds(filter.include_released).await {
+        Ok(holds) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "holds": holds
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn create_hold_handler(
+    req: HttpRequest,
+    request: web::Json<HoldRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.retention.create_hold(&principal, request.into_inner()).await {
+        Ok(hold) => {
+            let receipt = audit_hold(&state, &req, &principal, "create", &hold).await;
+            Ok(with_receipt(HttpResponse::Created(), receipt).json(hold))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn release_hold_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.retention.release_hold(&principal, path.into_inner()).await {
+        Ok(hold) => {
+            let receipt = audit_hold(&state, &req, &principal, "release", &hold).await;
+            Ok(with_receipt(HttpResponse::Ok(), receipt).json(hold))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/retention")
+            .route("", web::get().to(status_handler))
+            .route("/preview", web::get().to(preview_handler))
+            .route("/holds", web::get().to(list_holds_handler))
+            .route("/holds", web::post().to(create_hold_handler))
+            .route("/holds/{id}", web::delete().to(release_hold_handler)),
+    );
+}
@@ -0,0 +1,676 @@
+/*!
+S3 Gateway
+A minimal S3-compatible front for object storage, envelope-encrypting every object
+
+Services speaking S3 point their endpoint at `S3_GATEWAY_PORT` (path-style
+addressing; TLS as for the main listener) instead of the object store.
+Each object is sealed under a fresh AES-256-GCM data key, and the data key
+is wrapped by `CryptoService` (`S3_GATEWAY_KEY_ID`, or the active key) with
+the tenant, bucket and key as context. The stored object is:
+
+```text
+"COTAIS3" 0x01 | header length (u32 BE) | header (JSON) | ciphertext | tag
+```
+
+with the wrapped key, nonces and content type in the header, and the
+header as the AAD of the ciphertext. Moving an object to another tenant,
+bucket or key, or editing its header, makes it fail to open.
+
+Everything lands in one backing bucket, `S3_GATEWAY_BUCKET` (credentials
+and region as for the audit export, `S3_GATEWAY_ENDPOINT` for other
+S3-compatible stores), under `<S3_GATEWAY_PREFIX>/<tenant>/<bucket>/<key>`.
+The tenant comes from the caller's credential, so a client can only reach
+its own tenant's prefix; buckets are namespaces within it and need not be
+created.
+
+Callers sign requests with SigV4 (see `sigv4`) using access keys admins
+issue per tenant under `/admin/s3-gateway/credentials`, each allowed to
+`read`, `write` or both. The secret is shown once and kept encrypted, as
+verifying a signature needs it. Every access, allowed or not, is audited;
+see `s3` for the operations served.
+*/
+
+use actix_web::web::{self, Bytes};
+use actix_web::{HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::{ObjectMeta, ObjectStore, PutPayload};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, S3GatewayConfig};
+use crate::crypto::CryptoService;
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+use crate::AppState;
+
+pub mod s3;
+pub mod sigv4;
+
+pub const ACCESS_LEVELS: &[&str] = &["read", "write", "read-write"];
+
+const MAGIC: &[u8; 8] = b"COTAIS3\x01";
+/// Header bytes read for a HEAD; headers are kept well under this.
+const HEADER_READ_BYTES: usize = 4096;
+const MAX_CONTENT_TYPE_CHARS: usize = 255;
+const MAX_KEY_BYTES: usize = 1024;
+const ACCESS_KEY_BYTES: usize = 10;
+const SECRET_BYTES: usize = 30;
+const DATA_KEY_BYTES: usize = 32;
+const NONCE_BYTES: usize = 12;
+
+const CREDENTIAL_COLUMNS: &str = "access_key_id, tenant_id, description, access, created_by, created_at, \
+    last_used_at, revoked_at";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Credential {
+    pub access_key_id: String,
+    pub tenant_id: String,
+    pub description: String,
+    pub access: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// A new credential with its secret, returned once.
+#[derive(Debug, Serialize)]
+pub struct MintedCredential {
+    #[serde(flatten)]
+    pub credential: Credential,
+    pub secret_access_key: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCredentialRequest {
+    pub tenant_id: String,
+    pub description: String,
+    /// `read`, `write` or `read-write` (the default).
+    pub access: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CredentialFilter {
+    pub tenant_id: Option<String>,
+}
+
+#[derive(Debug, FromRow)]
+struct StoredSecret {
+    tenant_id: String,
+    access: String,
+    secret_key_id: String,
+    secret_nonce: Vec<u8>,
+    secret_ciphertext: Vec<u8>,
+}
+
+/// Whose S3 request this is, once its signature checks out.
+#[derive(Debug, Clone)]
+pub struct Caller {
+    pub access_key_id: String,
+    pub tenant_id: String,
+    pub access: String,
+}
+
+impl Caller {
+    pub fn can_read(&self) -> bool {
+        self.access != "write"
+    }
+
+    pub fn can_write(&self) -> bool {
+        self.access != "read"
+    }
+}
+
+/// Header of a stored object.
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    key_id: String,
+    wrapped_key: String,
+    wrap_nonce: String,
+    context_hash: Option<String>,
+    nonce: String,
+    content_type: Option<String>,
+    size: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectInfo {
+    /// Plaintext size.
+    pub size: u64,
+    pub content_type: Option<String>,
+    pub last_modified: DateTime<Utc>,
+    pub etag: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ListEntry {
+    pub key: String,
+    /// Stored (encrypted) size; listing does not open objects.
+    pub size: u64,
+    pub last_modified: DateTime<Utc>,
+    pub etag: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Listing {
+    pub entries: Vec<ListEntry>,
+    pub truncated: bool,
+}
+
+fn store_error(e: object_store::Error) -> SecurityError {
+    match e {
+        object_store::Error::NotFound { .. } => SecurityError::NotFound("No such key".to_string()),
+        e => SecurityError::StorageError(format!("Object store: {}", e)),
+    }
+}
+
+fn invalid_object() -> SecurityError {
+    SecurityError::CryptoError("Stored object is not a gateway envelope".to_string())
+}
+
+/// Bucket names as S3 has them: 3-63 lowercase letters, digits, dots and
+/// hyphens.
+fn valid_bucket(bucket: &str) -> bool {
+    (3..=63).contains(&bucket.len())
+        && bucket.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'.' || b == b'-')
+        && !bucket.starts_with(['.', '-'])
+}
+
+fn object_context(tenant_id: &str, bucket: &str, key: &str) -> HashMap<String, String> {
+    HashMap::from([
+        ("purpose".to_string(), "s3-gateway".to_string()),
+        ("tenant_id".to_string(), tenant_id.to_string()),
+        ("bucket".to_string(), bucket.to_string()),
+        ("key".to_string(), key.to_string()),
+    ])
+}
+
+fn secret_context(access_key_id: &str) -> HashMap<String, String> {
+    HashMap::from([
+        ("purpose".to_string(), "s3-gateway-credential".to_string()),
+        ("access_key_id".to_string(), access_key_id.to_string()),
+    ])
+}
+
+fn data_cipher(data_key: &[u8]) -> Result<LessSafeKey, SecurityError> {
+    UnboundKey::new(&AES_256_GCM, data_key)
+        .map(LessSafeKey::new)
+        .map_err(|_| SecurityError::CryptoError("Invalid data key".to_string()))
+}
+
+/// The envelope and ciphertext of a stored object.
+fn split_stored(stored: &[u8]) -> Result<(Envelope, &[u8], &[u8]), SecurityError> {
+    let rest = stored.strip_prefix(MAGIC.as_slice()).ok_or_else(invalid_object)?;
+    let len = rest.get(..4).ok_or_else(invalid_object)?;
+    let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+    let header = rest.get(4..4 + len).ok_or_else(invalid_object)?;
+    let envelope = serde_json::from_slice(header).map_err(|_| invalid_object())?;
+    Ok((envelope, header, &rest[4 + len..]))
+}
+
+pub struct S3Gateway {
+    config: S3GatewayConfig,
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    store: Option<Arc<dyn ObjectStore>>,
+}
+
+impl S3Gateway {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let config = config.s3_gateway.clone();
+        let store: Option<Arc<dyn ObjectStore>> = match &config.bucket {
+            Some(bucket) => {
+                let mut builder = AmazonS3Builder::from_env().with_bucket_name(bucket);
+                if let Some(endpoint) = &config.endpoint {
+                    builder = builder.with_endpoint(endpoint).with_allow_http(endpoint.starts_with("http://"));
+                }
+                let store = builder
+                    .build()
+                    .map_err(|e| SecurityError::ConfigError(format!("S3 gateway store: {}", e)))?;
+                Some(Arc::new(store))
+            }
+            None => None,
+        };
+        Ok(Self { config, storage, clock, store })
+    }
+
+    fn store(&self) -> Result<&Arc<dyn ObjectStore>, SecurityError> {
+        self.store
+            .as_ref()
+            .ok_or_else(|| SecurityError::ConfigError("S3_GATEWAY_BUCKET is not set".to_string()))
+    }
+
+    pub fn max_object_bytes(&self) -> usize {
+        self.config.max_object_bytes
+    }
+
+    pub fn max_clock_skew_secs(&self) -> i64 {
+        self.config.max_clock_skew_secs
+    }
+
+    fn bucket_path(&self, tenant_id: &str, bucket: &str) -> Result<Path, SecurityError> {
+        if !valid_bucket(bucket) {
+            return Err(SecurityError::ValidationError(format!("Invalid bucket name '{}'", bucket)));
+        }
+        let prefix = self.config.prefix.split('/').filter(|part| !part.is_empty());
+        Ok(Path::from_iter(prefix.chain([tenant_id, bucket])))
+    }
+
+    /// Refuse a bucket name S3 would not accept.
+    pub fn check_bucket(&self, caller: &Caller, bucket: &str) -> Result<(), SecurityError> {
+        self.bucket_path(&caller.tenant_id, bucket).map(|_| ())
+    }
+
+    fn object_path(&self, tenant_id: &str, bucket: &str, key: &str) -> Result<Path, SecurityError> {
+        if key.is_empty() || key.len() > MAX_KEY_BYTES {
+            return Err(SecurityError::ValidationError(format!("Keys must be 1-{} bytes", MAX_KEY_BYTES)));
+        }
+        let bucket_path = self.bucket_path(tenant_id, bucket)?;
+        Ok(Path::from_iter(bucket_path.parts().chain(key.split('/').map(Into::into))))
+    }
+
+    // Credentials
+
+    pub async fn create_credential(
+        &self,
+        crypto: &CryptoService,
+        principal: &Principal,
+        request: CreateCredentialRequest,
+    ) -> Result<MintedCredential, SecurityError> {
+        let tenant_id = request.tenant_id.trim();
+        if tenant_id.is_empty() || tenant_id.contains('/') {
+            return Err(SecurityError::ValidationError("tenant_id must be non-empty, without '/'".to_string()));
+        }
+        let description = request.description.trim();
+        if description.is_empty() || description.len() > 200 {
+            return Err(SecurityError::ValidationError("description must be 1-200 characters".to_string()));
+        }
+        let access = request.access.as_deref().unwrap_or("read-write");
+        if !ACCESS_LEVELS.contains(&access) {
+            return Err(SecurityError::ValidationError(format!(
+                "access must be one of: {}",
+                ACCESS_LEVELS.join(", ")
+            )));
+        }
+
+        let access_key_id = format!("CTS3{}", hex::encode_upper(crypto.secure_random(ACCESS_KEY_BYTES).await?));
+        let secret = base64::encode_config(crypto.secure_random(SECRET_BYTES).await?, base64::URL_SAFE_NO_PAD);
+        let sealed = crypto.encrypt_bytes(secret.clone().into_bytes(), None, Some(&secret_context(&access_key_id)))?;
+
+        let credential = sqlx::query_as::<_, Credential>(&format!(
+            "INSERT INTO s3_gateway_credentials (access_key_id, tenant_id, description, access, secret_key_id, \
+             secret_nonce, secret_ciphertext, created_by, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING {}",
+            CREDENTIAL_COLUMNS
+        ))
+        .bind(&access_key_id)
+        .bind(tenant_id)
+        .bind(description)
+        .bind(access)
+        .bind(&sealed.key_id)
+        .bind(&sealed.nonce)
+        .bind(&sealed.ciphertext)
+        .bind(&principal.subject)
+        .bind(self.clock.now())
+        .fetch_one(self.storage.pool())
+        .await?;
+
+        info!("{} issued S3 gateway credential {} for tenant {}", principal.subject, access_key_id, tenant_id);
+        Ok(MintedCredential { credential, secret_access_key: secret })
+    }
+
+    pub async fn list_credentials(&self, filter: &CredentialFilter) -> Result<Vec<Credential>, SecurityError> {
+        Ok(sqlx::query_as::<_, Credential>(&format!(
+            "SELECT {} FROM s3_gateway_credentials WHERE ($1::text IS NULL OR tenant_id = $1) \
+             ORDER BY created_at DESC",
+            CREDENTIAL_COLUMNS
+        ))
+        .bind(&filter.tenant_id)
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    pub async fn revoke_credential(&self, access_key_id: &str) -> Result<Credential, SecurityError> {
+        sqlx::query_as::<_, Credential>(&format!(
+            "UPDATE s3_gateway_credentials SET revoked_at = COALESCE(revoked_at, $2) \
+             WHERE access_key_id = $1 RETURNING {}",
+            CREDENTIAL_COLUMNS
+        ))
+        .bind(access_key_id)
+        .bind(self.clock.now())
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("S3 gateway credential {} not found", access_key_id)))
+    }
+
+    /// The caller and secret behind an unrevoked access key.
+    pub async fn secret(
+        &self,
+        crypto: &CryptoService,
+        access_key_id: &str,
+    ) -> Result<Option<(Caller, String)>, SecurityError> {
+        let stored = sqlx::query_as::<_, StoredSecret>(
+            "SELECT tenant_id, access, secret_key_id, secret_nonce, secret_ciphertext FROM s3_gateway_credentials \
+             WHERE access_key_id = $1 AND revoked_at IS NULL",
+        )
+        .bind(access_key_id)
+        .fetch_optional(self.storage.pool())
+        .await?;
+        let Some(stored) = stored else {
+            return Ok(None);
+        };
+
+        let context_hash = crypto.context_hash(Some(&secret_context(access_key_id)))?;
+        let secret = crypto.decrypt_bytes(
+            &stored.secret_key_id,
+            &stored.secret_nonce,
+            stored.secret_ciphertext,
+            context_hash.as_deref(),
+        )?;
+        let secret = String::from_utf8(secret).map_err(|_| invalid_object())?;
+        let caller = Caller {
+            access_key_id: access_key_id.to_string(),
+            tenant_id: stored.tenant_id,
+            access: stored.access,
+        };
+        Ok(Some((caller, secret)))
+    }
+
+    /// Note a use of the access key, at most once a minute.
+    pub async fn touch(&self, access_key_id: &str) {
+        let touched = sqlx::query(
+            "UPDATE s3_gateway_credentials SET last_used_at = $2 WHERE access_key_id = $1 \
+             AND (last_used_at IS NULL OR last_used_at < $2 - INTERVAL '1 minute')",
+        )
+        .bind(access_key_id)
+        .bind(self.clock.now())
+        .execute(self.storage.pool())
+        .await;
+        if let Err(e) = touched {
+            warn!("Failed to note use of S3 gateway credential {}: {:?}", access_key_id, e);
+        }
+    }
+
+    // Objects
+
+    /// Seal `body` and store it, returning the stored object's ETag.
+    pub async fn put(
+        &self,
+        crypto: &CryptoService,
+        caller: &Caller,
+        bucket: &str,
+        key: &str,
+        content_type: Option<&str>,
+        body: Bytes,
+    ) -> Result<Option<String>, SecurityError> {
+        let path = self.object_path(&caller.tenant_id, bucket, key)?;
+        if content_type.is_some_and(|content_type| content_type.chars().count() > MAX_CONTENT_TYPE_CHARS) {
+            return Err(SecurityError::ValidationError("Content-Type is too long".to_string()));
+        }
+
+        let data_key = crypto.secure_random(DATA_KEY_BYTES).await?;
+        let nonce = crypto.secure_random(NONCE_BYTES).await?;
+        let wrapped = crypto.encrypt_bytes(
+            data_key.clone(),
+            self.config.key_id.clone(),
+            Some(&object_context(&caller.tenant_id, bucket, key)),
+        )?;
+        let envelope = Envelope {
+            key_id: wrapped.key_id,
+            wrapped_key: base64::encode(&wrapped.ciphertext),
+            wrap_nonce: base64::encode(&wrapped.nonce),
+            context_hash: wrapped.context_hash,
+            nonce: base64::encode(&nonce),
+            content_type: content_type.map(str::to_string),
+            size: body.len() as u64,
+        };
+        let header = serde_json::to_vec(&envelope)
+            .map_err(|e| SecurityError::CryptoError(format!("Envelope header: {}", e)))?;
+
+        let mut sealed = body.to_vec();
+        let nonce = Nonce::try_assume_unique_for_key(&nonce)
+            .map_err(|_| SecurityError::CryptoError("Invalid nonce".to_string()))?;
+        data_cipher(&data_key)?
+            .seal_in_place_append_tag(nonce, Aad::from(header.as_slice()), &mut sealed)
+            .map_err(|_| SecurityError::CryptoError("Encryption failed".to_string()))?;
+
+        let mut stored = Vec::with_capacity(MAGIC.len() + 4 + header.len() + sealed.len());
+        stored.extend_from_slice(MAGIC);
+        stored.extend_from_slice(&(header.len() as u32).to_be_bytes());
+        stored.extend_from_slice(&header);
+        stored.extend_from_slice(&sealed);
+
+        let result = self.store()?.put(&path, PutPayload::from(stored)).await.map_err(store_error)?;
+        Ok(result.e_tag)
+    }
+
+    /// The plaintext of an object, with its details.
+    pub async fn get(
+        &self,
+        crypto: &CryptoService,
+        caller: &Caller,
+        bucket: &str,
+        key: &str,
+    ) -> Result<(ObjectInfo, Vec<u8>), SecurityError> {
+        let path = self.object_path(&caller.tenant_id, bucket, key)?;
+        let result = self.store()?.get(&path).await.map_err(store_error)?;
+        let meta = result.meta.clone();
+        let stored = result.bytes().await.map_err(store_error)?;
+
+        let (envelope, header, sealed) = split_stored(&stored)?;
+        let data_key = self.unwrap_key(crypto, caller, bucket, key, &envelope)?;
+        let nonce = base64::decode(&envelope.nonce).map_err(|_| invalid_object())?;
+        let nonce = Nonce::try_assume_unique_for_key(&nonce).map_err(|_| invalid_object())?;
+        let mut plaintext = sealed.to_vec();
+        let len = data_cipher(&data_key)?
+            .open_in_place(nonce, Aad::from(header), &mut plaintext)
+            .map_err(|_| SecurityError::CryptoError("Object failed to decrypt".to_string()))?
+            .len();
+        plaintext.truncate(len);
+        Ok((info(&meta, envelope), plaintext))
+    }
+
+    /// An object's details, from its header alone.
+    pub async fn head(&self, caller: &Caller, bucket: &str, key: &str) -> Result<ObjectInfo, SecurityError> {
+        let path = self.object_path(&caller.tenant_id, bucket, key)?;
+        let store = self.store()?;
+        let meta = store.head(&path).await.map_err(store_error)?;
+        let start = store
+            .get_range(&path, 0..meta.size.min(HEADER_READ_BYTES))
+            .await
+            .map_err(store_error)?;
+        let (envelope, _, _) = split_stored(&start)?;
+        Ok(info(&meta, envelope))
+    }
+
+    pub async fn delete(&self, caller: &Caller, bucket: &str, key: &str) -> Result<(), SecurityError> {
+        let path = self.object_path(&caller.tenant_id, bucket, key)?;
+        match self.store()?.delete(&path).await {
+            // S3 deletes are idempotent
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(store_error(e)),
+        }
+    }
+
+    /// Keys under `prefix` in `bucket`, in order, after `start_after`.
+    pub async fn list(
+        &self,
+        caller: &Caller,
+        bucket: &str,
+        prefix: &str,
+        start_after: Option<&str>,
+        max_keys: usize,
+    ) -> Result<Listing, SecurityError> {
+        let bucket_path = self.bucket_path(&caller.tenant_id, bucket)?;
+        let store = self.store()?;
+        let objects: Vec<ObjectMeta> = match start_after {
+            Some(after) => {
+                let offset = self.object_path(&caller.tenant_id, bucket, after)?;
+                store.list_with_offset(Some(&bucket_path), &offset).try_collect().await
+            }
+            None => store.list(Some(&bucket_path)).try_collect().await,
+        }
+        .map_err(store_error)?;
+
+        let mut entries: Vec<ListEntry> = objects
+            .into_iter()
+            .filter_map(|meta| {
+                let key = meta
+                    .location
+                    .prefix_match(&bucket_path)?
+                    .map(|part| String::from_utf8_lossy(&sigv4::percent_decode(part.as_ref())).into_owned())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                Some(ListEntry {
+                    key,
+                    size: meta.size as u64,
+                    last_modified: meta.last_modified,
+                    etag: meta.e_tag,
+                })
+            })
+            .filter(|entry| entry.key.starts_with(prefix) && start_after.is_none_or(|after| entry.key.as_str() > after))
+            .collect();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        let truncated = entries.len() > max_keys;
+        entries.truncate(max_keys);
+        Ok(Listing { entries, truncated })
+    }
+
+    fn unwrap_key(
+        &self,
+        crypto: &CryptoService,
+        caller: &Caller,
+        bucket: &str,
+        key: &str,
+        envelope: &Envelope,
+    ) -> Result<Vec<u8>, SecurityError> {
+        let context_hash = crypto.context_hash(Some(&object_context(&caller.tenant_id, bucket, key)))?;
+        if envelope.context_hash != context_hash {
+            return Err(SecurityError::CryptoError("Object was not stored under this key".to_string()));
+        }
+        let nonce = base64::decode(&envelope.wrap_nonce).map_err(|_| invalid_object())?;
+        let wrapped = base64::decode(&envelope.wrapped_key).map_err(|_| invalid_object())?;
+        crypto.decrypt_bytes(&envelope.key_id, &nonce, wrapped, context_hash.as_deref())
+    }
+}
+
+fn info(meta: &ObjectMeta, envelope: Envelope) -> ObjectInfo {
+    ObjectInfo {
+        size: envelope.size,
+        content_type: envelope.content_type,
+        last_modified: meta.last_modified,
+        etag: meta.e_tag.clone(),
+    }
+}
+
+// Admin API
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("S3 gateway operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "S3 gateway operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit_credential(state: &AppState, req: &HttpRequest, principal: &Principal, action: &str, credential: &Credential) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: Some(credential.tenant_id.clone()),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(req),
+        action: action.to_string(),
+        resource: format!("s3_gateway_credential:{}", credential.access_key_id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "description": credential.description,
+            "access": credential.access
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} for {}: {:?}", action, credential.access_key_id, e);
+    }
+}
+
+pub async fn list_credentials_handler(
+    req: HttpRequest,
+    filter: web::Query<CredentialFilter>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.s3_gateway.list_credentials(&filter).await {
+        Ok(credentials) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "credentials": credentials
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn create_credential_handler(
+    req: HttpRequest,
+    request: web::Json<CreateCredentialRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.s3_gateway.create_credential(&state.crypto_service, &principal, request.into_inner()).await {
+        Ok(minted) => {
+            audit_credential(&state, &req, &principal, "s3_gateway.credential.create", &minted.credential).await;
+            Ok(HttpResponse::Created().json(minted))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn revoke_credential_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.s3_gateway.revoke_credential(&path.into_inner()).await {
+        Ok(credential) => {
+            info!("{} revoked S3 gateway credential {}", principal.subject, credential.access_key_id);
+            audit_credential(&state, &req, &principal, "s3_gateway.credential.revoke", &credential).await;
+            Ok(HttpResponse::Ok().json(credential))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/s3-gateway")
+            .route("/credentials", web::get().to(list_credentials_handler))
+            .route("/credentials", web::post().to(create_credential_handler))
+            .route("/credentials/{access_key_id}", web::delete().to(revoke_credential_handler)),
+    );
+}
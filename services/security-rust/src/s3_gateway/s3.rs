@@ -0,0 +1,453 @@
+/*!
+S3 API
+The operations the gateway listener serves, path-style
+
+| request                  | operation                       | access |
+|--------------------------|---------------------------------|--------|
+| `PUT /{bucket}`          | CreateBucket (nothing to do)    | write  |
+| `HEAD /{bucket}`         | HeadBucket                      | any    |
+| `GET /{bucket}`          | ListObjects, ListObjectsV2      | read   |
+| `PUT /{bucket}/{key}`    | PutObject                       | write  |
+| `GET /{bucket}/{key}`    | GetObject                       | read   |
+| `HEAD /{bucket}/{key}`   | HeadObject                      | read   |
+| `DELETE /{bucket}/{key}` | DeleteObject                    | write  |
+
+Anything else, multipart uploads and sub-resources (`?acl`, `?tagging`,
+...) included, gets `NotImplemented`. Objects are written and read whole,
+up to `S3_GATEWAY_MAX_OBJECT_BYTES`; a `Range` header is ignored and the
+whole object returned. ETags are the backing store's, of the stored
+ciphertext, and listings give stored sizes. Errors are S3 XML errors.
+
+Each request is audited once it names a bucket: `s3.bucket.<create|head|list>`
+or `s3.object.<put|get|head|delete>`, by `s3:<access key>`, with the
+outcome (`denied` for signature and permission failures).
+*/
+
+use actix_web::http::{header, StatusCode};
+use actix_web::web::{self, Bytes};
+use actix_web::{HttpRequest, HttpResponse};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::HashMap;
+use tracing::{error, warn};
+
+use super::sigv4::{self, Authorization, Rejection};
+use super::{Caller, Listing};
+use crate::audit::NewAuditEvent;
+use crate::auth::client_ip;
+use crate::errors::SecurityError;
+use crate::AppState;
+
+const XMLNS: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+const DEFAULT_MAX_KEYS: usize = 1000;
+
+/// Query parameters that carry no sub-resource.
+const PLAIN_PARAMS: &[&str] = &["x-id"];
+const LIST_PARAMS: &[&str] = &[
+    "x-id", "list-type", "prefix", "delimiter", "max-keys", "start-after", "continuation-token", "marker",
+    "encoding-type", "fetch-owner",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operation {
+    CreateBucket,
+    HeadBucket,
+    ListObjects,
+    PutObject,
+    GetObject,
+    HeadObject,
+    DeleteObject,
+}
+
+impl Operation {
+    fn action(&self) -> &'static str {
+        match self {
+            Operation::CreateBucket => "s3.bucket.create",
+            Operation::HeadBucket => "s3.bucket.head",
+            Operation::ListObjects => "s3.bucket.list",
+            Operation::PutObject => "s3.object.put",
+            Operation::GetObject => "s3.object.get",
+            Operation::HeadObject => "s3.object.head",
+            Operation::DeleteObject => "s3.object.delete",
+        }
+    }
+
+    fn allowed(&self, caller: &Caller) -> bool {
+        match self {
+            Operation::HeadBucket => true,
+            Operation::ListObjects | Operation::GetObject | Operation::HeadObject => caller.can_read(),
+            Operation::CreateBucket | Operation::PutObject | Operation::DeleteObject => caller.can_write(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct S3Error {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl S3Error {
+    fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self { status, code, message: message.into() }
+    }
+
+    fn denied(&self) -> bool {
+        self.status == StatusCode::FORBIDDEN
+    }
+
+    fn response(&self, resource: &str) -> HttpResponse {
+        HttpResponse::build(self.status).content_type("application/xml").body(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Error><Code>{}</Code><Message>{}</Message>\
+             <Resource>{}</Resource></Error>",
+            self.code,
+            escape(&self.message),
+            escape(resource)
+        ))
+    }
+}
+
+impl From<SecurityError> for S3Error {
+    fn from(e: SecurityError) -> Self {
+        match e {
+            SecurityError::NotFound(msg) => S3Error::new(StatusCode::NOT_FOUND, "NoSuchKey", msg),
+            SecurityError::ValidationError(msg) => S3Error::new(StatusCode::BAD_REQUEST, "InvalidArgument", msg),
+            SecurityError::AccessDenied(msg) | SecurityError::AuthError(msg) => {
+                S3Error::new(StatusCode::FORBIDDEN, "AccessDenied", msg)
+            }
+            SecurityError::ConfigError(msg) => {
+                error!("S3 gateway unavailable: {}", msg);
+                S3Error::new(StatusCode::SERVICE_UNAVAILABLE, "ServiceUnavailable", "The gateway has no backing store")
+            }
+            e => {
+                error!("S3 gateway request failed: {:?}", e);
+                S3Error::new(StatusCode::INTERNAL_SERVER_ERROR, "InternalError", "We encountered an internal error")
+            }
+        }
+    }
+}
+
+fn not_implemented() -> S3Error {
+    S3Error::new(StatusCode::NOT_IMPLEMENTED, "NotImplemented", "The gateway does not implement this operation")
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+fn http_date(at: DateTime<Utc>) -> String {
+    at.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn query(req: &HttpRequest) -> HashMap<String, String> {
+    web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .map(|query| query.into_inner())
+        .unwrap_or_default()
+}
+
+/// The caller of a signed request. The access key, when there was one, is
+/// returned with a failure so it can be audited.
+async fn authenticate(req: &HttpRequest, body: &[u8], state: &AppState) -> Result<Caller, (Option<String>, S3Error)> {
+    let rejected = |rejection: Rejection| match rejection {
+        Rejection::Malformed(msg) => S3Error::new(StatusCode::BAD_REQUEST, "AuthorizationHeaderMalformed", msg),
+        Rejection::Skewed => S3Error::new(
+            StatusCode::FORBIDDEN,
+            "RequestTimeTooSkewed",
+            "The difference between the request time and the current time is too large",
+        ),
+        Rejection::Mismatch => S3Error::new(
+            StatusCode::FORBIDDEN,
+            "SignatureDoesNotMatch",
+            "The request signature we calculated does not match the signature you provided",
+        ),
+    };
+    let authorization = Authorization::parse(req).map_err(|rejection| (None, rejected(rejection)))?;
+    let access_key_id = Some(authorization.access_key_id.clone());
+
+    let (caller, secret) = match state.s3_gateway.secret(&state.crypto_service, &authorization.access_key_id).await {
+        Ok(Some(found)) => found,
+        Ok(None) => {
+            return Err((
+                access_key_id,
+                S3Error::new(StatusCode::FORBIDDEN, "InvalidAccessKeyId", "The access key does not exist"),
+            ))
+        }
+        Err(e) => return Err((access_key_id, e.into())),
+    };
+    authorization
+        .verify(req, &secret, state.clock.now(), state.s3_gateway.max_clock_skew_secs())
+        .map_err(|rejection| (access_key_id.clone(), rejected(rejection)))?;
+
+    match sigv4::payload_hash(req) {
+        Some(hash) if hash.starts_with(sigv4::STREAMING_PREFIX) => return Err((access_key_id, not_implemented())),
+        Some(sigv4::UNSIGNED_PAYLOAD) => {}
+        Some(hash) if hash.eq_ignore_ascii_case(&sigv4::sha256_hex(body)) => {}
+        _ => {
+            return Err((
+                access_key_id,
+                S3Error::new(
+                    StatusCode::BAD_REQUEST,
+                    "XAmzContentSHA256Mismatch",
+                    "The provided x-amz-content-sha256 does not match what was computed",
+                ),
+            ))
+        }
+    }
+
+    state.s3_gateway.touch(&caller.access_key_id).await;
+    Ok(caller)
+}
+
+async fn run(
+    state: &AppState,
+    req: &HttpRequest,
+    caller: &Caller,
+    operation: Operation,
+    bucket: &str,
+    key: &str,
+    body: Bytes,
+) -> Result<HttpResponse, S3Error> {
+    if !operation.allowed(caller) {
+        return Err(S3Error::new(StatusCode::FORBIDDEN, "AccessDenied", "The access key may not do this"));
+    }
+    let gateway = &state.s3_gateway;
+    match operation {
+        Operation::CreateBucket | Operation::HeadBucket => {
+            // Buckets are namespaces within the tenant's prefix
+            gateway.check_bucket(caller, bucket)?;
+            Ok(HttpResponse::Ok().insert_header((header::LOCATION, format!("/{}", bucket))).finish())
+        }
+        Operation::ListObjects => list_objects(state, req, caller, bucket).await,
+        Operation::PutObject => {
+            let content_type = req.headers().get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+            let etag = gateway.put(&state.crypto_service, caller, bucket, key, content_type, body).await?;
+            let mut response = HttpResponse::Ok();
+            if let Some(etag) = etag {
+                response.insert_header((header::ETAG, etag));
+            }
+            Ok(response.finish())
+        }
+        Operation::GetObject => {
+            let (info, plaintext) = gateway.get(&state.crypto_service, caller, bucket, key).await?;
+            let mut response = HttpResponse::Ok();
+            response
+                .content_type(info.content_type.as_deref().unwrap_or("application/octet-stream"))
+                .insert_header((header::LAST_MODIFIED, http_date(info.last_modified)));
+            if let Some(etag) = info.etag {
+                response.insert_header((header::ETAG, etag));
+            }
+            Ok(response.body(plaintext))
+        }
+        Operation::HeadObject => {
+            let info = gateway.head(caller, bucket, key).await?;
+            let mut response = HttpResponse::Ok();
+            response
+                .content_type(info.content_type.as_deref().unwrap_or("application/octet-stream"))
+                .insert_header((header::CONTENT_LENGTH, info.size))
+                .insert_header((header::LAST_MODIFIED, http_date(info.last_modified)));
+            if let Some(etag) = info.etag {
+                response.insert_header((header::ETAG, etag));
+            }
+            Ok(response.finish())
+        }
+        Operation::DeleteObject => {
+            gateway.delete(caller, bucket, key).await?;
+            Ok(HttpResponse::NoContent().finish())
+        }
+    }
+}
+
+/// ListObjects, or ListObjectsV2 with `list-type=2`.
+async fn list_objects(state: &AppState, req: &HttpRequest, caller: &Caller, bucket: &str) -> Result<HttpResponse, S3Error> {
+    let query = query(req);
+    let param = |name: &str| query.get(name).map(String::as_str);
+    let v2 = param("list-type") == Some("2");
+    let prefix = param("prefix").unwrap_or_default();
+    let delimiter = param("delimiter").filter(|delimiter| !delimiter.is_empty());
+    let max_keys = match param("max-keys") {
+        Some(max_keys) => max_keys
+            .parse::<usize>()
+            .map_err(|_| S3Error::new(StatusCode::BAD_REQUEST, "InvalidArgument", "max-keys must be a number"))?
+            .min(DEFAULT_MAX_KEYS),
+        None => DEFAULT_MAX_KEYS,
+    };
+    let token = param("continuation-token")
+        .map(|token| {
+            base64::decode_config(token, base64::URL_SAFE_NO_PAD)
+                .ok()
+                .and_then(|key| String::from_utf8(key).ok())
+                .ok_or_else(|| S3Error::new(StatusCode::BAD_REQUEST, "InvalidArgument", "Invalid continuation token"))
+        })
+        .transpose()?;
+    let start_after = match v2 {
+        true => token.as_deref().or(param("start-after")),
+        false => param("marker"),
+    };
+
+    let Listing { entries, truncated } = state
+        .s3_gateway
+        .list(caller, bucket, prefix, start_after.filter(|after| !after.is_empty()), max_keys)
+        .await?;
+
+    let mut contents = String::new();
+    let mut common_prefixes: Vec<String> = Vec::new();
+    for entry in &entries {
+        let rest = &entry.key[prefix.len()..];
+        if let Some(at) = delimiter.and_then(|delimiter| rest.find(delimiter).map(|at| at + delimiter.len())) {
+            let common = format!("{}{}", prefix, &rest[..at]);
+            if common_prefixes.last() != Some(&common) {
+                common_prefixes.push(common);
+            }
+            continue;
+        }
+        contents.push_str(&format!(
+            "<Contents><Key>{}</Key><LastModified>{}</LastModified>{}<Size>{}</Size>\
+             <StorageClass>STANDARD</StorageClass></Contents>",
+            escape(&entry.key),
+            entry.last_modified.to_rfc3339_opts(SecondsFormat::Millis, true),
+            entry.etag.as_deref().map(|etag| format!("<ETag>{}</ETag>", escape(etag))).unwrap_or_default(),
+            entry.size
+        ));
+    }
+    let prefixes: String = common_prefixes
+        .iter()
+        .map(|common| format!("<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>", escape(common)))
+        .collect();
+
+    let last_key = entries.last().map(|entry| entry.key.as_str()).filter(|_| truncated);
+    let paging = match (v2, last_key) {
+        (true, Some(last)) => format!(
+            "<NextContinuationToken>{}</NextContinuationToken>",
+            base64::encode_config(last, base64::URL_SAFE_NO_PAD)
+        ),
+        (false, Some(last)) => format!("<NextMarker>{}</NextMarker>", escape(last)),
+        (_, None) => String::new(),
+    };
+    let key_count = match v2 {
+        true => format!("<KeyCount>{}</KeyCount>", entries.len()),
+        false => String::new(),
+    };
+
+    Ok(HttpResponse::Ok().content_type("application/xml").body(format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<ListBucketResult xmlns=\"{}\"><Name>{}</Name>\
+         <Prefix>{}</Prefix>{}<MaxKeys>{}</MaxKeys>{}<IsTruncated>{}</IsTruncated>{}{}{}</ListBucketResult>",
+        XMLNS,
+        escape(bucket),
+        escape(prefix),
+        key_count,
+        max_keys,
+        delimiter.map(|delimiter| format!("<Delimiter>{}</Delimiter>", escape(delimiter))).unwrap_or_default(),
+        truncated,
+        paging,
+        contents,
+        prefixes
+    )))
+}
+
+async fn audit(
+    state: &AppState,
+    req: &HttpRequest,
+    access: (Option<&Caller>, Option<&str>),
+    operation: Operation,
+    resource: &str,
+    error: Option<&S3Error>,
+) {
+    let (caller, access_key_id) = access;
+    let outcome = match error {
+        None => "success",
+        Some(error) if error.denied() => "denied",
+        Some(_) => "failure",
+    };
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: caller.map(|caller| caller.tenant_id.clone()),
+        actor: format!("s3:{}", access_key_id.unwrap_or("anonymous")),
+        actor_ip: client_ip(req),
+        action: operation.action().to_string(),
+        resource: format!("s3:/{}", resource),
+        outcome: outcome.to_string(),
+        payload: serde_json::json!({
+            "access_key_id": access_key_id,
+            "error": error.map(|error| error.code)
+        }),
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} of {}: {:?}", operation.action(), resource, e);
+    }
+}
+
+async fn serve(state: &AppState, req: &HttpRequest, operation: Operation, bucket: &str, key: &str, body: Bytes) -> HttpResponse {
+    let resource = match key {
+        "" => format!("/{}", bucket),
+        key => format!("/{}/{}", bucket, key),
+    };
+    let (caller, access_key_id, result) = match authenticate(req, &body, state).await {
+        Ok(caller) => {
+            let result = run(state, req, &caller, operation, bucket, key, body).await;
+            let access_key_id = Some(caller.access_key_id.clone());
+            (Some(caller), access_key_id, result)
+        }
+        Err((access_key_id, e)) => (None, access_key_id, Err(e)),
+    };
+    audit(
+        state,
+        req,
+        (caller.as_ref(), access_key_id.as_deref()),
+        operation,
+        &resource,
+        result.as_ref().err(),
+    )
+    .await;
+    result.unwrap_or_else(|e| e.response(&resource))
+}
+
+pub async fn bucket_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: Bytes,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let bucket = path.into_inner();
+    let query = query(&req);
+    let operation = match req.method().as_str() {
+        "GET" if query.keys().all(|name| LIST_PARAMS.contains(&name.as_str())) => Operation::ListObjects,
+        "PUT" if query.keys().all(|name| PLAIN_PARAMS.contains(&name.as_str())) => Operation::CreateBucket,
+        "HEAD" => Operation::HeadBucket,
+        _ => return not_implemented().response(&format!("/{}", bucket)),
+    };
+    serve(&state, &req, operation, &bucket, "", body).await
+}
+
+pub async fn object_handler(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    body: Bytes,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let (bucket, key) = path.into_inner();
+    if !query(&req).keys().all(|name| PLAIN_PARAMS.contains(&name.as_str())) {
+        return not_implemented().response(&format!("/{}/{}", bucket, key));
+    }
+    let operation = match req.method().as_str() {
+        "PUT" if req.headers().contains_key("x-amz-copy-source") => {
+            return not_implemented().response(&format!("/{}/{}", bucket, key));
+        }
+        "PUT" => Operation::PutObject,
+        "GET" => Operation::GetObject,
+        "HEAD" => Operation::HeadObject,
+        "DELETE" => Operation::DeleteObject,
+        _ => return not_implemented().response(&format!("/{}/{}", bucket, key)),
+    };
+    serve(&state, &req, operation, &bucket, &key, body).await
+}
+
+async fn fallback_handler(req: HttpRequest) -> HttpResponse {
+    not_implemented().response(req.path())
+}
+
+/// Routes of the gateway listener.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/{bucket}", web::route().to(bucket_handler))
+        .route("/{bucket}/", web::route().to(bucket_handler))
+        .route("/{bucket}/{key:.+}", web::route().to(object_handler))
+        .default_service(web::to(fallback_handler));
+}
@@ -0,0 +1,220 @@
+/*!
+SigV4 Verification
+Checks the signature S3 clients put in the `Authorization` header
+
+Only header signatures are accepted: presigned URLs and streaming
+(`aws-chunked`) payload signatures are not. The canonical request is
+rebuilt from what arrived, with the path and query re-encoded the way SigV4
+specifies, so clients differing in what they escape still verify. The
+payload hash the client signed is checked against the body by the caller,
+once the body has been read.
+*/
+
+use actix_web::HttpRequest;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use ring::{digest, hmac};
+
+pub const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+pub const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+pub const STREAMING_PREFIX: &str = "STREAMING-";
+const SERVICE: &str = "s3";
+const TERMINATOR: &str = "aws4_request";
+
+/// Why a signature was not accepted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rejection {
+    /// The `Authorization` header is missing or malformed.
+    Malformed(String),
+    /// `x-amz-date` is too far from now.
+    Skewed,
+    /// The signature does not match.
+    Mismatch,
+}
+
+/// The parts of a signed request's `Authorization` header.
+#[derive(Debug, Clone)]
+pub struct Authorization {
+    pub access_key_id: String,
+    date: String,
+    region: String,
+    signed_headers: Vec<String>,
+    signature: Vec<u8>,
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|value| value.to_str().ok())
+}
+
+/// The `x-amz-content-sha256` the client signed.
+pub fn payload_hash(req: &HttpRequest) -> Option<&str> {
+    header(req, "x-amz-content-sha256")
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(digest::digest(&digest::SHA256, data))
+}
+
+fn sign(key: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data).as_ref().to_vec()
+}
+
+/// `%XX` escapes undone; anything malformed is kept as is.
+pub fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(byte) = s.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    out
+}
+
+/// SigV4's URI encoding: everything but unreserved characters escaped,
+/// uppercase hex.
+fn uri_encode(bytes: &[u8], keep_slash: bool) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for &b in bytes {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') || (keep_slash && b == b'/') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+fn canonical_uri(path: &str) -> String {
+    match path {
+        "" => "/".to_string(),
+        path => uri_encode(&percent_decode(path), true),
+    }
+}
+
+fn canonical_query(query: &str) -> String {
+    let mut pairs: Vec<(String, String)> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (uri_encode(&percent_decode(name), false), uri_encode(&percent_decode(value), false))
+        })
+        .collect();
+    pairs.sort();
+    pairs.iter().map(|(name, value)| format!("{}={}", name, value)).collect::<Vec<_>>().join("&")
+}
+
+/// A header's values as signed: trimmed, inner whitespace collapsed, joined
+/// by commas.
+fn canonical_header(req: &HttpRequest, name: &str) -> String {
+    let values: Vec<String> = req
+        .headers()
+        .get_all(name)
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect();
+    if values.is_empty() && name == "host" {
+        // HTTP/2 carries it as :authority
+        return req.connection_info().host().to_string();
+    }
+    values.join(",")
+}
+
+impl Authorization {
+    pub fn parse(req: &HttpRequest) -> Result<Self, Rejection> {
+        let malformed = |msg: &str| Rejection::Malformed(msg.to_string());
+        let value = header(req, "authorization").ok_or_else(|| malformed("Missing Authorization header"))?;
+        let fields = value
+            .strip_prefix(ALGORITHM)
+            .ok_or_else(|| malformed("Only AWS4-HMAC-SHA256 header signatures are supported"))?;
+
+        let (mut credential, mut signed_headers, mut signature) = (None, None, None);
+        for field in fields.split(',') {
+            match field.trim().split_once('=') {
+                Some(("Credential", value)) => credential = Some(value),
+                Some(("SignedHeaders", value)) => signed_headers = Some(value),
+                Some(("Signature", value)) => signature = Some(value),
+                _ => {}
+            }
+        }
+
+        let scope: Vec<&str> = credential.ok_or_else(|| malformed("Missing Credential"))?.split('/').collect();
+        let [access_key_id, date, region, service, terminator] = scope.as_slice() else {
+            return Err(malformed("Credential must be key/date/region/service/aws4_request"));
+        };
+        if *service != SERVICE || *terminator != TERMINATOR {
+            return Err(malformed("Credential scope must be for s3"));
+        }
+        let signed_headers: Vec<String> = signed_headers
+            .ok_or_else(|| malformed("Missing SignedHeaders"))?
+            .split(';')
+            .map(str::to_lowercase)
+            .collect();
+        if !signed_headers.iter().any(|name| name == "host") {
+            return Err(malformed("The host header must be signed"));
+        }
+        let signature = hex::decode(signature.ok_or_else(|| malformed("Missing Signature"))?)
+            .map_err(|_| malformed("Signature must be hex"))?;
+
+        Ok(Self {
+            access_key_id: access_key_id.to_string(),
+            date: date.to_string(),
+            region: region.to_string(),
+            signed_headers,
+            signature,
+        })
+    }
+
+    /// Check the signature under `secret`.
+    pub fn verify(&self, req: &HttpRequest, secret: &str, now: DateTime<Utc>, max_skew_secs: i64) -> Result<(), Rejection> {
+        let amz_date = header(req, "x-amz-date")
+            .ok_or_else(|| Rejection::Malformed("Missing x-amz-date".to_string()))?;
+        let signed_at = NaiveDateTime::parse_from_str(amz_date, "%Y%m%dT%H%M%SZ")
+            .map_err(|_| Rejection::Malformed("Invalid x-amz-date".to_string()))?
+            .and_utc();
+        if (now - signed_at).num_seconds().abs() > max_skew_secs {
+            return Err(Rejection::Skewed);
+        }
+        if !amz_date.starts_with(&self.date) {
+            return Err(Rejection::Malformed("Credential date does not match x-amz-date".to_string()));
+        }
+        let payload_hash = payload_hash(req)
+            .ok_or_else(|| Rejection::Malformed("Missing x-amz-content-sha256".to_string()))?;
+
+        let canonical_headers: String = self
+            .signed_headers
+            .iter()
+            .map(|name| format!("{}:{}\n", name, canonical_header(req, name)))
+            .collect();
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            req.method().as_str(),
+            canonical_uri(req.uri().path()),
+            canonical_query(req.query_string()),
+            canonical_headers,
+            self.signed_headers.join(";"),
+            payload_hash
+        );
+        let scope = format!("{}/{}/{}/{}", self.date, self.region, SERVICE, TERMINATOR);
+        let string_to_sign = format!(
+            "{}\n{}\n{}\n{}",
+            ALGORITHM,
+            amz_date,
+            scope,
+            sha256_hex(canonical_request.as_bytes())
+        );
+
+        let key = [self.date.as_str(), self.region.as_str(), SERVICE, TERMINATOR].iter().fold(
+            format!("AWS4{}", secret).into_bytes(),
+            |key, part| sign(&key, part.as_bytes()),
+        );
+        hmac::verify(&hmac::Key::new(hmac::HMAC_SHA256, &key), string_to_sign.as_bytes(), &self.signature)
+            .map_err(|_| Rejection::Mismatch)
+    }
+}
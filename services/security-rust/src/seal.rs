@@ -0,0 +1,981 @@
+/*!
+Seal Module
+Institutional PAdES seal on outgoing official documents
+
+Award decisions and other official PDFs leave the platform sealed with the
+institution's certificate. The seal key lives in an HSM behind the PAdES
+signer at `SEAL_SIGNER_URL` (authenticated with the `seal_signer` outbound
+credential when one is stored, see `credentials`); this service never holds
+it. It queues documents, hands them to the signer, checks what comes back
+and keeps the verification record.
+
+1. `POST /seals?reference=..&label=..` with the PDF as the body (needs
+   `SEAL_SUBMIT_SCOPE`) stores the original under `SEAL_PREFIX` in
+   `SEAL_BUCKET` and answers `202` with the job. Submitting the same
+   document again returns its job unless that one failed.
+2. The queue sends the document to the signer with the key label
+   `SEAL_KEY_LABEL`, asking for a PAdES signature at `SEAL_LEVEL`
+   timestamped by `SEAL_TSA_URL` (the signer's own authority when unset).
+   The result must be an incremental update of the original, so every byte
+   that was submitted is still there, and must carry a signature timestamp.
+3. The sealed file is stored next to the original, its SHA-256 is
+   registered with the notary (see `notary`) and `seal.completed` is
+   published on the event bus. After `SEAL_MAX_ATTEMPTS` failures the job
+   fails and `seal.failed` is published.
+
+`GET /seals/{id}` returns the job with its verification record and
+`GET /seals/{id}/document` the sealed PDF, both to holders of the submit
+scope. `POST /seals/verify` with `{sha256}` tells anyone holding a copy
+whether it is a document this service sealed, with the notary proof.
+
+Procurement records must stay verifiable long after the seal certificate
+and its CA have expired or gone offline. At `B-LT` the signer embeds the
+OCSP responses, CRLs and certificates that validate the seal in the
+document security store, and at `B-LTA` it adds a document timestamp
+protecting them; the seal is refused when the material is missing. The
+SHA-256 of every embedded item is kept with the job.
+
+`POST /seals/{id}/revalidate` checks that an archived seal still holds: the
+stored file still has the recorded hash, the notary still proves it, and
+the validation service at `SEAL_VALIDATOR_URL` (with the `seal_validator`
+credential) validates the signature from the embedded material alone,
+returning an ETSI EN 319 102-1 indication. Each result is kept, and
+`GET /seals/{id}/validations` lists them, so a record can show it was
+verifiable at every point it was checked. `valid_until` tells when the
+newest timestamp stops protecting the document; it must be re-timestamped
+before then.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use futures::StreamExt;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{FromRow, Postgres, Transaction};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::receipts::{self, RECEIPT_HEADER};
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, SealConfig};
+use crate::credentials::CredentialCache;
+use crate::errors::SecurityError;
+use crate::events::{self, DomainEvent};
+use crate::notary::{self, Lookup};
+use crate::residency;
+use crate::storage::Storage;
+use crate::AppState;
+
+const CREDENTIAL: &str = "seal_signer";
+
+const VALIDATOR_CREDENTIAL: &str = "seal_validator";
+
+const SYSTEM_ACTOR: &str = "system:seal";
+
+/// PAdES baseline levels the signer may be asked for.
+pub const LEVELS: &[&str] = &["B-T", "B-LT", "B-LTA"];
+
+/// ETSI EN 319 102-1 indication of a signature that validated.
+const TOTAL_PASSED: &str = "TOTAL_PASSED";
+
+const MAX_BACKOFF_SECS: i64 = 3600;
+
+const JOB_COLUMNS: &str = "id, tenant_id, reference, label, submitted_by, original_sha256, original_bytes, \
+    original_key, status, attempts, next_attempt_at, last_error, sealed_sha256, sealed_bytes, sealed_key, \
+    certificate_sha256, certificate_subject, signing_time, timestamp_time, timestamp_authority, \
+    notary_entry_id, created_at, sealed_at, level, validation_material, archive_timestamp_time, \
+    archive_timestamp_authority, region";
+
+const VALIDATION_COLUMNS: &str = "id, job_id, validated_at, validated_by, document_intact, notarized, indication, \
+    sub_indication, best_signature_time, valid_until, verifiable, report";
+
+/// SHA-256 of each item embedded for long-term validation.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ValidationMaterial {
+    pub ocsp: Vec<String>,
+    pub crls: Vec<String>,
+    pub certificates: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct SealJob {
+    pub id: Uuid,
+    pub tenant_id: Option<String>,
+    pub reference: String,
+    pub label: Option<String>,
+    pub submitted_by: String,
+    pub original_sha256: String,
+    pub original_bytes: i64,
+    #[serde(skip)]
+    pub original_key: String,
+    /// `pending`, `sealed` or `failed`.
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub sealed_sha256: Option<String>,
+    pub sealed_bytes: Option<i64>,
+    #[serde(skip)]
+    pub sealed_key: Option<String>,
+    /// Hex SHA-256 of the DER seal certificate.
+    pub certificate_sha256: Option<String>,
+    pub certificate_subject: Option<String>,
+    /// Signing time claimed in the signature.
+    pub signing_time: Option<DateTime<Utc>>,
+    /// Time asserted by the signature timestamp.
+    pub timestamp_time: Option<DateTime<Utc>>,
+    pub timestamp_authority: Option<String>,
+    pub notary_entry_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub sealed_at: Option<DateTime<Utc>>,
+    /// PAdES level the seal was made at.
+    pub level: Option<String>,
+    pub validation_material: Option<Json<ValidationMaterial>>,
+    /// Time asserted by the `B-LTA` document timestamp.
+    pub archive_timestamp_time: Option<DateTime<Utc>>,
+    pub archive_timestamp_authority: Option<String>,
+    /// Region of the bucket holding the documents; `None` is the home region.
+    pub region: Option<String>,
+}
+
+/// One check that an archived seal still holds.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct SealValidation {
+    pub id: Uuid,
+    pub job_id: Uuid,
+    pub validated_at: DateTime<Utc>,
+    pub validated_by: String,
+    pub document_intact: bool,
+    pub notarized: bool,
+    /// `TOTAL_PASSED`, `INDETERMINATE` or `TOTAL_FAILED`; `None` when the
+    /// document was not sent for validation.
+    pub indication: Option<String>,
+    pub sub_indication: Option<String>,
+    pub best_signature_time: Option<DateTime<Utc>>,
+    pub valid_until: Option<DateTime<Utc>>,
+    /// Every check above passed.
+    pub verifiable: bool,
+    /// The validation service's full report.
+    pub report: serde_json::Value,
+}
+
+/// What a copy's holder learns about a sealed document.
+#[derive(Debug, Serialize)]
+pub struct SealRecord {
+    pub id: Uuid,
+    pub reference: String,
+    pub label: Option<String>,
+    pub original_sha256: String,
+    pub certificate_sha256: Option<String>,
+    pub certificate_subject: Option<String>,
+    pub signing_time: Option<DateTime<Utc>>,
+    pub timestamp_time: Option<DateTime<Utc>>,
+    pub timestamp_authority: Option<String>,
+    pub sealed_at: Option<DateTime<Utc>>,
+    pub level: Option<String>,
+    pub archive_timestamp_time: Option<DateTime<Utc>>,
+}
+
+impl From<SealJob> for SealRecord {
+    fn from(job: SealJob) -> Self {
+        Self {
+            id: job.id,
+            reference: job.reference,
+            label: job.label,
+            original_sha256: job.original_sha256,
+            certificate_sha256: job.certificate_sha256,
+            certificate_subject: job.certificate_subject,
+            signing_time: job.signing_time,
+            timestamp_time: job.timestamp_time,
+            timestamp_authority: job.timestamp_authority,
+            sealed_at: job.sealed_at,
+            level: job.level,
+            archive_timestamp_time: job.archive_timestamp_time,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubmitParams {
+    pub reference: String,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyRequest {
+    pub sha256: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Verification {
+    pub document_sha256: String,
+    pub sealed: bool,
+    pub seals: Vec<SealRecord>,
+    pub notary: Lookup,
+}
+
+#[derive(Serialize)]
+struct SignerRequest<'a> {
+    /// Base64 PDF.
+    document: String,
+    key_label: &'a str,
+    level: &'a str,
+    reason: &'a str,
+    location: &'a str,
+    tsa_url: Option<&'a str>,
+    reference: &'a str,
+}
+
+#[derive(Deserialize)]
+struct SignerTimestamp {
+    time: DateTime<Utc>,
+    authority: String,
+}
+
+/// Base64 DER items the signer embedded in the document security store.
+#[derive(Deserialize)]
+struct SignerValidation {
+    #[serde(default)]
+    ocsp: Vec<String>,
+    #[serde(default)]
+    crls: Vec<String>,
+    #[serde(default)]
+    certificates: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct SignerResponse {
+    /// Base64 sealed PDF.
+    document: String,
+    /// Base64 DER seal certificate.
+    certificate: String,
+    subject: String,
+    signing_time: DateTime<Utc>,
+    timestamp: Option<SignerTimestamp>,
+    validation: Option<SignerValidation>,
+    archive_timestamp: Option<SignerTimestamp>,
+}
+
+/// A document the signer sealed, checked against the original.
+struct Sealed {
+    document: Vec<u8>,
+    sha256: String,
+    certificate_sha256: String,
+    subject: String,
+    signing_time: DateTime<Utc>,
+    timestamp: SignerTimestamp,
+    material: Option<ValidationMaterial>,
+    archive_timestamp: Option<SignerTimestamp>,
+}
+
+#[derive(Serialize)]
+struct ValidatorRequest<'a> {
+    /// Base64 PDF.
+    document: String,
+    level: &'a str,
+    reference: &'a str,
+}
+
+/// The parts of the validation report kept in columns.
+#[derive(Deserialize)]
+struct ValidatorReport {
+    indication: String,
+    sub_indication: Option<String>,
+    best_signature_time: Option<DateTime<Utc>>,
+    valid_until: Option<DateTime<Utc>>,
+}
+
+fn sha256(data: &[u8]) -> String {
+    hex::encode(digest(&SHA256, data))
+}
+
+fn store_error(e: impl std::fmt::Display) -> SecurityError {
+    SecurityError::StorageError(format!("Seal store: {}", e))
+}
+
+fn signer_error(e: impl std::fmt::Display) -> SecurityError {
+    SecurityError::DeliveryError(format!("Seal signer: {}", e))
+}
+
+fn validator_error(e: impl std::fmt::Display) -> SecurityError {
+    SecurityError::DeliveryError(format!("Seal validator: {}", e))
+}
+
+/// Hash each base64 DER item, refusing any that does not decode.
+fn hash_items(items: &[String], kind: &str) -> Result<Vec<String>, SecurityError> {
+    items
+        .iter()
+        .map(|item| {
+            base64::decode(item)
+                .map(|der| sha256(&der))
+                .map_err(|_| signer_error(format!("{} is not base64", kind)))
+        })
+        .collect()
+}
+
+pub struct SealService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: SealConfig,
+    store: Option<Arc<dyn ObjectStore>>,
+    regional_stores: HashMap<String, Arc<dyn ObjectStore>>,
+    client: reqwest::Client,
+    credentials: Arc<CredentialCache>,
+}
+
+impl SealService {
+    pub async fn new(
+        config: &Config,
+        storage: Storage,
+        clock: Arc<dyn Clock>,
+        credentials: Arc<CredentialCache>,
+    ) -> Result<Self, SecurityError> {
+        let store: Option<Arc<dyn ObjectStore>> = match &config.seal.bucket {
+            Some(bucket) => Some(Arc::new(
+                AmazonS3Builder::from_env()
+                    .with_bucket_name(bucket)
+                    .build()
+                    .map_err(|e| SecurityError::ConfigError(format!("Seal store: {}", e)))?,
+            )),
+            None => None,
+        };
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(config.seal.timeout_secs))
+            .build()
+            .map_err(|e| SecurityError::ConfigError(format!("Seal signer client: {}", e)))?;
+        let regional_stores = residency::build_stores(config)?;
+
+        info!("Seal service initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            config: config.seal.clone(),
+            store,
+            regional_stores,
+            client,
+            credentials,
+        })
+    }
+
+    /// The store, when sealing is configured at all.
+    fn store(&self) -> Result<&Arc<dyn ObjectStore>, SecurityError> {
+        match (&self.config.signer_url, &self.store) {
+            (Some(_), Some(store)) => Ok(store),
+            _ => Err(SecurityError::ValidationError("Document sealing is not configured".to_string())),
+        }
+    }
+
+    /// The store of a region's documents; `None` is the home region.
+    fn store_in(&self, region: Option<&str>) -> Result<&Arc<dyn ObjectStore>, SecurityError> {
+        let home = self.store()?;
+        residency::store_in(&self.regional_stores, Some(home), region)
+    }
+
+    async fn find_open(&self, tenant_id: Option<&str>, original_sha256: &str) -> Result<Option<SealJob>, SecurityError> {
+        Ok(sqlx::query_as::<_, SealJob>(&format!(
+            "SELECT {} FROM seal_jobs WHERE COALESCE(tenant_id, '') = COALESCE($1, '') \
+             AND original_sha256 = $2 AND status <> 'failed'",
+            JOB_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(original_sha256)
+        .fetch_optional(self.storage.pool())
+        .await?)
+    }
+
+    /// Queue a PDF for sealing. The flag says whether the job is new.
+    pub async fn submit(
+        &self,
+        params: &SubmitParams,
+        document: Vec<u8>,
+        submitted_by: &str,
+        tenant_id: Option<&str>,
+        region: Option<&str>,
+    ) -> Result<(SealJob, bool), SecurityError> {
+        let store = self.store_in(region)?;
+        let reference = params.reference.trim();
+        if reference.is_empty() {
+            return Err(SecurityError::ValidationError("reference is required".to_string()));
+        }
+        if !document.starts_with(b"%PDF-") {
+            return Err(SecurityError::ValidationError("The body must be a PDF document".to_string()));
+        }
+
+        let original_sha256 = sha256(&document);
+        if let Some(job) = self.find_open(tenant_id, &original_sha256).await? {
+            return Ok((job, false));
+        }
+
+        let id = Uuid::new_v4();
+        let key = Path::from(format!("{}/{}/original.pdf", self.config.prefix, id));
+        let size = document.len() as i64;
+        store.put(&key, document.into()).await.map_err(store_error)?;
+
+        let inserted = sqlx::query_as::<_, SealJob>(&format!(
+            "INSERT INTO seal_jobs (id, tenant_id, reference, label, submitted_by, original_sha256, original_bytes, \
+             original_key, next_attempt_at, created_at, region) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $9, $10) \
+             ON CONFLICT ((COALESCE(tenant_id, '')), original_sha256) WHERE status <> 'failed' DO NOTHING \
+             RETURNING {}",
+            JOB_COLUMNS
+        ))
+        .bind(id)
+        .bind(tenant_id)
+        .bind(reference)
+        .bind(&params.label)
+        .bind(submitted_by)
+        .bind(&original_sha256)
+        .bind(size)
+        .bind(key.as_ref())
+        .bind(self.clock.now())
+        .bind(region)
+        .fetch_optional(self.storage.pool())
+        .await?;
+
+        match inserted {
+            Some(job) => Ok((job, true)),
+            None => {
+                // A concurrent submission of the same document won
+                if let Err(e) = store.delete(&key).await {
+                    warn!("Failed to remove duplicate seal upload {}: {}", key, e);
+                }
+                let job = self.find_open(tenant_id, &original_sha256).await?
+                    .ok_or_else(|| SecurityError::Conflict("The document was resubmitted concurrently".to_string()))?;
+                Ok((job, false))
+            }
+        }
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<SealJob, SecurityError> {
+        sqlx::query_as::<_, SealJob>(&format!("SELECT {} FROM seal_jobs WHERE id = $1", JOB_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .ok_or_else(|| SecurityError::NotFound(format!("No seal job {}", id)))
+    }
+
+    /// The sealed PDF of a job.
+    pub async fn document(&self, job: &SealJob) -> Result<Vec<u8>, SecurityError> {
+        let key = job.sealed_key.as_deref()
+            .ok_or_else(|| SecurityError::Conflict(format!("Seal job {} is {}", job.id, job.status)))?;
+        let store = self.store_in(job.region.as_deref())?;
+        let object = store.get(&Path::from(key)).await.map_err(store_error)?;
+        Ok(object.bytes().await.map_err(store_error)?.to_vec())
+    }
+
+    /// Seals whose result has the given hash, with its notary registrations.
+    pub async fn verify(&self, state: &AppState, sha256: &str) -> Result<Verification, SecurityError> {
+        let notary = state.notary.lookup(&state.crypto_service, sha256).await?;
+        let seals = sqlx::query_as::<_, SealJob>(&format!(
+            "SELECT {} FROM seal_jobs WHERE sealed_sha256 = $1 ORDER BY sealed_at",
+            JOB_COLUMNS
+        ))
+        .bind(&notary.document_sha256)
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        Ok(Verification {
+            document_sha256: notary.document_sha256.clone(),
+            sealed: !seals.is_empty(),
+            seals: seals.into_iter().map(SealRecord::from).collect(),
+            notary,
+        })
+    }
+
+    /// Have the signer seal a document and check the result.
+    async fn apply_seal(&self, job: &SealJob, original: &[u8]) -> Result<Sealed, SecurityError> {
+        let url = self.config.signer_url.as_deref()
+            .ok_or_else(|| signer_error("SEAL_SIGNER_URL is not set"))?;
+        let request = SignerRequest {
+            document: base64::encode(original),
+            key_label: &self.config.key_label,
+            level: &self.config.level,
+            reason: &self.config.reason,
+            location: &self.config.location,
+            tsa_url: self.config.tsa_url.as_deref(),
+            reference: &job.reference,
+        };
+        let response = self.credentials.apply(CREDENTIAL, self.client.post(url))
+            .header("X-Cotai-Seal-Job", job.id.to_string())
+            .json(&request)
+            .send()
+            .await
+            .map_err(signer_error)?;
+        if !response.status().is_success() {
+            return Err(signer_error(format!("returned {}", response.status())));
+        }
+        let answer: SignerResponse = response.json().await.map_err(signer_error)?;
+
+        let document = base64::decode(&answer.document).map_err(|_| signer_error("document is not base64"))?;
+        let certificate = base64::decode(&answer.certificate).map_err(|_| signer_error("certificate is not base64"))?;
+        // An incremental update leaves every submitted byte in place
+        if document.len() <= original.len() || !document.starts_with(original) {
+            return Err(signer_error("sealed document is not an incremental update of the original"));
+        }
+        let timestamp = answer.timestamp.ok_or_else(|| signer_error("sealed document carries no timestamp"))?;
+
+        let long_term = self.config.level != "B-T";
+        let material = match answer.validation {
+            Some(validation) if long_term => {
+                if validation.ocsp.is_empty() && validation.crls.is_empty() {
+                    return Err(signer_error("sealed document embeds no revocation data"));
+                }
+                Some(ValidationMaterial {
+                    ocsp: hash_items(&validation.ocsp, "OCSP response")?,
+                    crls: hash_items(&validation.crls, "CRL")?,
+                    certificates: hash_items(&validation.certificates, "certificate")?,
+                })
+            }
+            None if long_term => return Err(signer_error("sealed document embeds no validation material")),
+            _ => None,
+        };
+        if self.config.level == "B-LTA" && answer.archive_timestamp.is_none() {
+            return Err(signer_error("sealed document carries no archive timestamp"));
+        }
+
+        Ok(Sealed {
+            sha256: sha256(&document),
+            document,
+            certificate_sha256: sha256(&certificate),
+            subject: answer.subject,
+            signing_time: answer.signing_time,
+            timestamp,
+            material,
+            archive_timestamp: answer.archive_timestamp,
+        })
+    }
+
+    /// Seal, store and register one job's document.
+    async fn process(&self, state: &AppState, tx: &mut Transaction<'_, Postgres>, job: &SealJob) -> Result<(), SecurityError> {
+        let store = self.store_in(job.region.as_deref())?;
+        let original = store.get(&Path::from(job.original_key.as_str())).await.map_err(store_error)?
+            .bytes().await.map_err(store_error)?;
+        let sealed = self.apply_seal(job, &original).await?;
+
+        let key = Path::from(format!("{}/{}/sealed.pdf", self.config.prefix, job.id));
+        let size = sealed.document.len() as i64;
+        store.put(&key, sealed.document.into()).await.map_err(store_error)?;
+
+        let proof = state.notary.register(
+            &state.crypto_service,
+            &notary::RegisterRequest {
+                sha256: sealed.sha256.clone(),
+                label: Some(format!("seal:{}", job.reference)),
+            },
+            SYSTEM_ACTOR,
+            job.tenant_id.as_deref(),
+        ).await?;
+
+        let now = self.clock.now();
+        sqlx::query(
+            "UPDATE seal_jobs SET status = 'sealed', attempts = attempts + 1, last_error = NULL, \
+             sealed_sha256 = $2, sealed_bytes = $3, sealed_key = $4, certificate_sha256 = $5, \
+             certificate_subject = $6, signing_time = $7, timestamp_time = $8, timestamp_authority = $9, \
+             notary_entry_id = $10, sealed_at = $11, level = $12, validation_material = $13, \
+             archive_timestamp_time = $14, archive_timestamp_authority = $15 WHERE id = $1",
+        )
+        .bind(job.id)
+        .bind(&sealed.sha256)
+        .bind(size)
+        .bind(key.as_ref())
+        .bind(&sealed.certificate_sha256)
+        .bind(&sealed.subject)
+        .bind(sealed.signing_time)
+        .bind(sealed.timestamp.time)
+        .bind(&sealed.timestamp.authority)
+        .bind(proof.entry_id)
+        .bind(now)
+        .bind(&self.config.level)
+        .bind(sealed.material.map(Json))
+        .bind(sealed.archive_timestamp.as_ref().map(|t| t.time))
+        .bind(sealed.archive_timestamp.as_ref().map(|t| t.authority.clone()))
+        .execute(&mut **tx)
+        .await?;
+
+        let event = DomainEvent::new(
+            "seal.completed",
+            "seal_job",
+            job.id,
+            job.tenant_id.clone(),
+            serde_json::json!({
+                "reference": job.reference,
+                "original_sha256": job.original_sha256,
+                "sealed_sha256": sealed.sha256,
+                "level": self.config.level,
+                "notary_entry_id": proof.entry_id
+            }),
+            self.clock.now(),
+        );
+        events::enqueue(tx, &event).await
+    }
+
+    /// Seal the jobs that are due. Returns how many were attempted.
+    pub async fn process_due(&self, state: &AppState) -> Result<usize, SecurityError> {
+        if self.store().is_err() {
+            return Ok(0);
+        }
+
+        let mut tx = self.storage.begin().await?;
+        let due = sqlx::query_as::<_, SealJob>(&format!(
+            "SELECT {} FROM seal_jobs WHERE status = 'pending' AND next_attempt_at <= $1 \
+             ORDER BY next_attempt_at LIMIT $2 FOR UPDATE SKIP LOCKED",
+            JOB_COLUMNS
+        ))
+        .bind(self.clock.now())
+        .bind(self.config.batch_size)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for job in &due {
+            let e = match self.process(state, &mut tx, job).await {
+                Ok(()) => {
+                    audit_seal(state, job, "success", serde_json::json!({ "reference": job.reference })).await;
+                    continue;
+                }
+                Err(e) => e,
+            };
+
+            let attempts = job.attempts + 1;
+            let status = if attempts >= self.config.max_attempts { "failed" } else { "pending" };
+            let backoff = Duration::seconds((1i64 << attempts.min(12)).min(MAX_BACKOFF_SECS));
+            sqlx::query(
+                "UPDATE seal_jobs SET status = $2, attempts = $3, next_attempt_at = $4, last_error = $5 WHERE id = $1",
+            )
+            .bind(job.id)
+            .bind(status)
+            .bind(attempts)
+            .bind(self.clock.now() + backoff)
+            .bind(e.to_string())
+            .execute(&mut *tx)
+            .await?;
+
+            if status == "failed" {
+                error!("Seal job {} ({}) failed for good: {}", job.id, job.reference, e);
+                let event = DomainEvent::new(
+                    "seal.failed",
+                    "seal_job",
+                    job.id,
+                    job.tenant_id.clone(),
+                    serde_json::json!({ "reference": job.reference, "error": e.to_string() }),
+                    self.clock.now(),
+                );
+                events::enqueue(&mut tx, &event).await?;
+                audit_seal(state, job, "failure", serde_json::json!({
+                    "reference": job.reference,
+                    "error": e.to_string()
+                })).await;
+            } else {
+                warn!("Seal job {} attempt {} failed: {}", job.id, attempts, e);
+            }
+        }
+        tx.commit().await?;
+
+        Ok(due.len())
+    }
+
+    /// Have the validation service validate a sealed document from its
+    /// embedded material. Returns the report and its kept parts.
+    async fn validate(&self, job: &SealJob, document: &[u8]) -> Result<(serde_json::Value, ValidatorReport), SecurityError> {
+        let url = self.config.validator_url.as_deref()
+            .ok_or_else(|| SecurityError::ValidationError("Seal revalidation is not configured".to_string()))?;
+        let request = ValidatorRequest {
+            document: base64::encode(document),
+            level: job.level.as_deref().unwrap_or("B-T"),
+            reference: &job.reference,
+        };
+        let response = self.credentials.apply(VALIDATOR_CREDENTIAL, self.client.post(url))
+            .header("X-Cotai-Seal-Job", job.id.to_string())
+            .json(&request)
+            .send()
+            .await
+            .map_err(validator_error)?;
+        if !response.status().is_success() {
+            return Err(validator_error(format!("returned {}", response.status())));
+        }
+        let report: serde_json::Value = response.json().await.map_err(validator_error)?;
+        let parsed = serde_json::from_value(report.clone()).map_err(validator_error)?;
+        Ok((report, parsed))
+    }
+
+    /// Check that a sealed document is still intact, notarized and valid,
+    /// and keep the result.
+    pub async fn revalidate(&self, state: &AppState, job: &SealJob, actor: &str) -> Result<SealValidation, SecurityError> {
+        let sealed_sha256 = job.sealed_sha256.as_deref()
+            .ok_or_else(|| SecurityError::Conflict(format!("Seal job {} is {}", job.id, job.status)))?;
+        if self.config.validator_url.is_none() {
+            return Err(SecurityError::ValidationError("Seal revalidation is not configured".to_string()));
+        }
+
+        let document = self.document(job).await?;
+        let document_intact = sha256(&document) == sealed_sha256;
+        let notarized = state.notary.lookup(&state.crypto_service, sealed_sha256).await?
+            .proofs
+            .iter()
+            .any(|p| Some(p.proof.entry_id) == job.notary_entry_id && p.verification.valid);
+        // A file that changed in storage is not the one that was sealed
+        let (report, parsed) = if document_intact {
+            let (report, parsed) = self.validate(job, &document).await?;
+            (report, Some(parsed))
+        } else {
+            (serde_json::json!({}), None)
+        };
+        let verifiable = document_intact
+            && notarized
+            && parsed.as_ref().is_some_and(|p| p.indication == TOTAL_PASSED);
+
+        let validation = sqlx::query_as::<_, SealValidation>(&format!(
+            "INSERT INTO seal_validations (id, job_id, validated_at, validated_by, document_intact, notarized, \
+             indication, sub_indication, best_signature_time, valid_until, verifiable, report) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) RETURNING {}",
+            VALIDATION_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(job.id)
+        .bind(self.clock.now())
+        .bind(actor)
+        .bind(document_intact)
+        .bind(notarized)
+        .bind(parsed.as_ref().map(|p| p.indication.clone()))
+        .bind(parsed.as_ref().and_then(|p| p.sub_indication.clone()))
+        .bind(parsed.as_ref().and_then(|p| p.best_signature_time))
+        .bind(parsed.as_ref().and_then(|p| p.valid_until))
+        .bind(verifiable)
+        .bind(&report)
+        .fetch_one(self.storage.pool())
+        .await?;
+
+        if !verifiable {
+            warn!(
+                "Seal job {} ({}) no longer verifiable: intact={} notarized={} indication={:?}",
+                job.id, job.reference, document_intact, notarized, validation.indication
+            );
+        }
+        Ok(validation)
+    }
+
+    /// A job's revalidations, oldest first.
+    pub async fn validations(&self, job_id: Uuid) -> Result<Vec<SealValidation>, SecurityError> {
+        Ok(sqlx::query_as::<_, SealValidation>(&format!(
+            "SELECT {} FROM seal_validations WHERE job_id = $1 ORDER BY validated_at",
+            VALIDATION_COLUMNS
+        ))
+        .bind(job_id)
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+}
+
+async fn audit_seal(state: &AppState, job: &SealJob, outcome: &str, payload: serde_json::Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: job.tenant_id.clone(),
+        actor: SYSTEM_ACTOR.to_string(),
+        actor_ip: None,
+        action: "seal.apply".to_string(),
+        resource: format!("seal_job:{}", job.id),
+        outcome: outcome.to_string(),
+        payload,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit seal job {}: {:?}", job.id, e);
+    }
+}
+
+/// Background loop working through the seal queue.
+pub async fn run_queue(state: web::Data<AppState>) {
+    let interval_ms = state.config.seal.interval_ms;
+    let mut interval = tokio::time::interval(std::time::Duration::from_millis(interval_ms));
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = state.seal.process_due(&state).await {
+            error!("Seal queue failed: {:?}", e);
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Seal operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Seal operation failed"
+            }))
+        }
+    }
+}
+
+/// A job, if the caller may see it: tenant-bound callers only their tenant's.
+async fn authorized_job(state: &AppState, req: &HttpRequest, id: Uuid) -> Result<(Principal, SealJob), HttpResponse> {
+    let principal = authorize_scope(state, req, &state.config.seal.submit_scope)
+        .map_err(|e| auth_error_response(&e))?;
+    let job = state.seal.get(id).await.map_err(error_response)?;
+    if principal.tenant_id.is_some() && principal.tenant_id != job.tenant_id {
+        return Err(error_response(SecurityError::NotFound(format!("No seal job {}", id))));
+    }
+    Ok((principal, job))
+}
+
+pub async fn submit_handler(
+    req: HttpRequest,
+    params: web::Query<SubmitParams>,
+    mut body: web::Payload,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.seal.submit_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let mut document = Vec::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        if document.len() + chunk.len() > state.config.seal.max_bytes {
+            return Ok(HttpResponse::PayloadTooLarge().json(serde_json::json!({
+                "error": format!("Documents are limited to {} bytes", state.config.seal.max_bytes)
+            })));
+        }
+        document.extend_from_slice(&chunk);
+    }
+
+    let tenant_id = principal.tenant_id.as_deref();
+    let submitted = async {
+        let region = state.residency.placement(tenant_id).await?;
+        state.seal.submit(&params, document, &principal.subject, tenant_id, region.as_deref()).await
+    }
+    .await;
+    let (job, created) = match submitted {
+        Ok(result) => result,
+        Err(e) => return Ok(error_response(e)),
+    };
+    if !created {
+        return Ok(HttpResponse::Ok().json(job));
+    }
+    state.residency.record(tenant_id, "seal", &format!("seal_job:{}", job.id), job.region.as_deref()).await;
+
+    let receipt = receipts::record_or_warn(&state, NewAuditEvent {
+        tenant_id: principal.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(&req),
+        action: "seal.submit".to_string(),
+        resource: format!("seal_job:{}", job.id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "reference": job.reference,
+            "original_sha256": job.original_sha256,
+            "original_bytes": job.original_bytes
+        }),
+    }).await;
+
+    let mut response = HttpResponse::Accepted();
+    response.insert_header(("Location", format!("/api/v1/seals/{}", job.id)));
+    if let Some(receipt) = receipt {
+        response.insert_header((RECEIPT_HEADER, receipt));
+    }
+    Ok(response.json(job))
+}
+
+pub async fn get_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    match authorized_job(&state, &req, path.into_inner()).await {
+        Ok((_, job)) => Ok(HttpResponse::Ok().json(job)),
+        Err(response) => Ok(response),
+    }
+}
+
+pub async fn document_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let (_, job) = match authorized_job(&state, &req, path.into_inner()).await {
+        Ok(authorized) => authorized,
+        Err(response) => return Ok(response),
+    };
+    match state.seal.document(&job).await {
+        Ok(document) => Ok(HttpResponse::Ok()
+            .content_type("application/pdf")
+            .insert_header(("Content-Disposition", format!("attachment; filename=\"{}-sealed.pdf\"", job.id)))
+            .body(document)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn revalidate_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let (principal, job) = match authorized_job(&state, &req, path.into_inner()).await {
+        Ok(authorized) => authorized,
+        Err(response) => return Ok(response),
+    };
+
+    let validation = match state.seal.revalidate(&state, &job, &principal.subject).await {
+        Ok(validation) => validation,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let receipt = receipts::record_or_warn(&state, NewAuditEvent {
+        tenant_id: job.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(&req),
+        action: "seal.revalidate".to_string(),
+        resource: format!("seal_job:{}", job.id),
+        outcome: if validation.verifiable { "success" } else { "failure" }.to_string(),
+        payload: serde_json::json!({
+            "reference": job.reference,
+            "validation_id": validation.id,
+            "document_intact": validation.document_intact,
+            "notarized": validation.notarized,
+            "indication": validation.indication,
+            "sub_indication": validation.sub_indication
+        }),
+    }).await;
+
+    let mut response = HttpResponse::Ok();
+    if let Some(receipt) = receipt {
+        response.insert_header((RECEIPT_HEADER, receipt));
+    }
+    Ok(response.json(validation))
+}
+
+pub async fn validations_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let (_, job) = match authorized_job(&state, &req, path.into_inner()).await {
+        Ok(authorized) => authorized,
+        Err(response) => return Ok(response),
+    };
+    match state.seal.validations(job.id).await {
+        Ok(validations) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "validations": validations
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn verify_handler(request: web::Json<VerifyRequest>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    match state.seal.verify(&state, &request.sha256).await {
+        Ok(verification) => Ok(HttpResponse::Ok().json(verification)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/seals")
+            .route("", web::post().to(submit_handler))
+            .route("/verify", web::post().to(verify_handler))
+            .route("/{id}", web::get().to(get_handler))
+            .route("/{id}/document", web::get().to(document_handler))
+            .route("/{id}/revalidate", web::post().to(revalidate_handler))
+            .route("/{id}/validations", web::get().to(validations_handler)),
+    );
+}
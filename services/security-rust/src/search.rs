@@ -0,0 +1,318 @@
+/*!
+Admin Search
+One search box over the administrative objects
+
+`GET /admin/search?q=<text>` finds users (org chart members), API keys,
+service accounts, OAuth clients, policies, incidents and crypto keys whose
+names start with `q` or, unless `mode=prefix`, resemble it (trigram
+similarity of at least `SEARCH_FUZZY_THRESHOLD`) or, for descriptions,
+contain its words. `types` narrows the search to a comma-separated list of
+kinds and `tenant_id` to one tenant; admins bound to a tenant only ever see
+theirs, and no tenant-less objects. Hits come best first, prefix matches
+ahead of fuzzy ones, at most `limit` (up to `SEARCH_MAX_RESULTS`) of them.
+
+Every searched column has a trigram index and every description a full-text
+one, so searches stay quick as the tables grow.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use tracing::{error, info};
+
+use crate::auth::auth_error_response;
+use crate::config::{Config, SearchConfig};
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+use crate::AppState;
+
+/// A kind of object and where to look for it.
+struct Source {
+    kind: &'static str,
+    table: &'static str,
+    id: &'static str,
+    title: &'static str,
+    detail: &'static str,
+    /// Matched by prefix and similarity, lowercased.
+    names: &'static [&'static str],
+    /// Matched by full-text search.
+    description: Option<&'static str>,
+    tenant: Option<&'static str>,
+    filter: Option<&'static str>,
+}
+
+const SOURCES: &[Source] = &[
+    Source {
+        kind: "user",
+        table: "org_members",
+        id: "subject",
+        title: "subject",
+        detail: "unit",
+        names: &["subject"],
+        description: None,
+        tenant: Some("tenant_id"),
+        filter: None,
+    },
+    Source {
+        kind: "api_key",
+        table: "api_keys",
+        id: "id::text",
+        title: "name",
+        detail: "status",
+        names: &["name"],
+        description: None,
+        tenant: Some("tenant_id"),
+        filter: None,
+    },
+    Source {
+        kind: "service_account",
+        table: "service_accounts",
+        id: "id::text",
+        title: "name",
+        detail: "status",
+        names: &["name"],
+        description: Some("description"),
+        tenant: Some("tenant_id"),
+        filter: None,
+    },
+    Source {
+        kind: "oauth_client",
+        table: "oauth_clients",
+        id: "id::text",
+        title: "name",
+        detail: "status",
+        names: &["name"],
+        description: Some("description"),
+        tenant: None,
+        filter: None,
+    },
+    Source {
+        kind: "policy",
+        table: "policies",
+        id: "id::text",
+        title: "name",
+        detail: "COALESCE(description, '')",
+        names: &["name"],
+        description: Some("COALESCE(description, '')"),
+        tenant: Some("tenant_id"),
+        filter: Some("deleted_at IS NULL"),
+    },
+    Source {
+        kind: "incident",
+        table: "security_incidents",
+        id: "id::text",
+        title: "rule",
+        detail: "entity || ' (' || severity || ', ' || status || ')'",
+        names: &["rule", "entity"],
+        description: None,
+        tenant: Some("tenant_id"),
+        filter: None,
+    },
+    Source {
+        kind: "crypto_key",
+        table: "crypto_keys",
+        id: "key_id",
+        title: "key_id",
+        detail: "state",
+        names: &["key_id"],
+        description: None,
+        tenant: None,
+        filter: None,
+    },
+];
+
+/// Kinds that can be searched for, in `types`.
+pub fn kinds() -> Vec<&'static str> {
+    SOURCES.iter().map(|s| s.kind).collect()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    /// Comma-separated kinds; all when absent.
+    pub types: Option<String>,
+    pub tenant_id: Option<String>,
+    /// `prefix`, or `fuzzy` (the default) to also match similar names.
+    pub mode: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Hit {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub id: String,
+    pub title: String,
+    pub detail: Option<String>,
+    pub tenant_id: Option<String>,
+    pub score: f64,
+}
+
+pub struct SearchService {
+    storage: Storage,
+    config: SearchConfig,
+}
+
+impl SearchService {
+    pub async fn new(config: &Config, storage: Storage) -> Result<Self, SecurityError> {
+        info!("Admin search initialized ({})", kinds().join(", "));
+        Ok(Self {
+            storage,
+            config: config.search.clone(),
+        })
+    }
+
+    /// Search for `query`. `bound_tenant` is the tenant an admin is bound
+    /// to, if any.
+    pub async fn search(&self, query: &SearchQuery, bound_tenant: Option<&str>) -> Result<Vec<Hit>, SecurityError> {
+        let text = query.q.trim().to_lowercase();
+        if text.chars().count() < self.config.min_query_len {
+            return Err(SecurityError::ValidationError(format!(
+                "q must be at least {} characters",
+                self.config.min_query_len
+            )));
+        }
+        let fuzzy = match query.mode.as_deref() {
+            None | Some("fuzzy") => true,
+            Some("prefix") => false,
+            Some(other) => return Err(SecurityError::ValidationError(format!("Unknown mode: {}", other))),
+        };
+        let tenant_id = match (bound_tenant, query.tenant_id.as_deref()) {
+            (Some(own), Some(requested)) if own != requested => {
+                return Err(SecurityError::AccessDenied(format!("Cannot search tenant {}", requested)));
+            }
+            (Some(own), _) => Some(own),
+            (None, requested) => requested,
+        };
+        let sources = match &query.types {
+            Some(types) => {
+                let mut sources = Vec::new();
+                for kind in types.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+                    match SOURCES.iter().find(|s| s.kind == kind) {
+                        Some(source) => sources.push(source),
+                        None => return Err(SecurityError::ValidationError(format!("Unknown type: {}", kind))),
+                    }
+                }
+                sources
+            }
+            None => SOURCES.iter().collect(),
+        };
+        // Tenant-less objects are platform-wide and not for tenant admins.
+        let sources: Vec<&Source> = sources.into_iter()
+            .filter(|s| s.tenant.is_some() || bound_tenant.is_none())
+            .collect();
+        if sources.is_empty() {
+            return Ok(Vec::new());
+        }
+        let limit = query.limit.unwrap_or(self.config.max_results).clamp(1, self.config.max_results);
+        let prefix = format!("{}%", text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+
+        let mut qb: QueryBuilder<Postgres> = QueryBuilder::new("SELECT kind, id, title, detail, tenant_id, score FROM (");
+        for (i, source) in sources.iter().enumerate() {
+            if i > 0 {
+                qb.push(" UNION ALL ");
+            }
+            push_source(&mut qb, source, &text, &prefix, fuzzy, tenant_id);
+        }
+        qb.push(") hits ORDER BY score DESC, title LIMIT ");
+        qb.push_bind(limit as i64);
+
+        let mut tx = self.storage.begin().await?;
+        sqlx::query("SELECT set_config('pg_trgm.similarity_threshold', $1, true)")
+            .bind(self.config.fuzzy_threshold.to_string())
+            .execute(&mut *tx)
+            .await?;
+        let hits = qb.build_query_as::<Hit>().fetch_all(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(hits)
+    }
+}
+
+/// One branch of the union: the rows of `source` matching, scored 1 for a
+/// prefix match and by similarity otherwise.
+fn push_source(qb: &mut QueryBuilder<'_, Postgres>, source: &Source, text: &str, prefix: &str, fuzzy: bool, tenant_id: Option<&str>) {
+    qb.push(format!("SELECT '{}' AS kind, {} AS id, {} AS title, {} AS detail, ", source.kind, source.id, source.title, source.detail));
+    match source.tenant {
+        Some(column) => qb.push(format!("{} AS tenant_id, ", column)),
+        None => qb.push("NULL::text AS tenant_id, "),
+    };
+
+    qb.push("GREATEST(");
+    for (i, name) in source.names.iter().enumerate() {
+        if i > 0 {
+            qb.push(", ");
+        }
+        qb.push(format!("CASE WHEN lower({}) LIKE ", name));
+        qb.push_bind(prefix.to_string());
+        qb.push(" THEN 1.0::float8 ELSE 0.0::float8 END");
+        if fuzzy {
+            qb.push(format!(", similarity(lower({}), ", name));
+            qb.push_bind(text.to_string());
+            qb.push(")::float8");
+        }
+    }
+    qb.push(format!(") AS score FROM {} WHERE (", source.table));
+
+    for (i, name) in source.names.iter().enumerate() {
+        if i > 0 {
+            qb.push(" OR ");
+        }
+        qb.push(format!("lower({}) LIKE ", name));
+        qb.push_bind(prefix.to_string());
+        if fuzzy {
+            qb.push(format!(" OR lower({}) % ", name));
+            qb.push_bind(text.to_string());
+        }
+    }
+    if let (true, Some(description)) = (fuzzy, source.description) {
+        qb.push(format!(" OR to_tsvector('simple', {}) @@ plainto_tsquery('simple', ", description));
+        qb.push_bind(text.to_string());
+        qb.push(")");
+    }
+    qb.push(")");
+
+    if let Some(filter) = source.filter {
+        qb.push(format!(" AND {}", filter));
+    }
+    if let (Some(column), Some(tenant_id)) = (source.tenant, tenant_id) {
+        qb.push(format!(" AND {} = ", column));
+        qb.push_bind(tenant_id.to_string());
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::AccessDenied(msg) => HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Admin search failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Search failed"
+            }))
+        }
+    }
+}
+
+pub async fn search_handler(req: HttpRequest, query: web::Query<SearchQuery>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.search.search(&query, principal.tenant_id.as_deref()).await {
+        Ok(hits) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "hits": hits
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/admin/search").route(web::get().to(search_handler)));
+}
@@ -0,0 +1,190 @@
+/*!
+KMS Resolver
+Decrypts secrets with AWS KMS
+
+Credentials come from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and, for
+temporary credentials, `AWS_SESSION_TOKEN`, the same variables the audit
+export reads. Requests are signed with SigV4 directly, which keeps the AWS
+SDK out of the dependency tree for the few calls the service makes.
+`AWS_KMS_ENDPOINT` points them at a KMS-compatible service instead, such
+as the local KMS the integration tests run.
+*/
+
+use futures::future::BoxFuture;
+use ring::{digest, hmac};
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::SecretResolver;
+use crate::clock::Clock;
+use crate::config::SecretsConfig;
+use crate::errors::SecurityError;
+
+const SERVICE: &str = "kms";
+const CONTENT_TYPE: &str = "application/x-amz-json-1.1";
+
+/// Minimal signed client for the KMS JSON API, shared with the `aws-kms`
+/// key provider.
+pub struct KmsClient {
+    client: reqwest::Client,
+    region: String,
+    endpoint: Option<reqwest::Url>,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    /// Requests are signed for this clock's time.
+    clock: Arc<dyn Clock>,
+}
+
+impl KmsClient {
+    pub fn new(config: &SecretsConfig, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let region = config.kms_region.clone()
+            .ok_or_else(|| SecurityError::ConfigError("AWS_REGION is not set".to_string()))?;
+        let credential = |name: &str| {
+            env::var(name).map_err(|_| SecurityError::ConfigError(format!("{} is not set", name)))
+        };
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .map_err(|e| SecurityError::ConfigError(format!("Failed to build KMS client: {}", e)))?;
+        let endpoint = config.kms_endpoint.as_deref()
+            .map(reqwest::Url::parse)
+            .transpose()
+            .map_err(|e| SecurityError::ConfigError(format!("AWS_KMS_ENDPOINT is not a valid URL: {}", e)))?;
+
+        Ok(Self {
+            client,
+            region,
+            endpoint,
+            access_key_id: credential("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: credential("AWS_SECRET_ACCESS_KEY")?,
+            session_token: env::var("AWS_SESSION_TOKEN").ok(),
+            clock,
+        })
+    }
+
+    /// Call a KMS action such as `Decrypt`, returning the response body or a
+    /// description of what went wrong.
+    pub async fn call(&self, action: &str, body: serde_json::Value) -> Result<serde_json::Value, String> {
+        let (url, host) = match &self.endpoint {
+            Some(endpoint) => {
+                let port = endpoint.port().map(|port| format!(":{}", port)).unwrap_or_default();
+                (endpoint.to_string(), format!("{}{}", endpoint.host_str().unwrap_or_default(), port))
+            }
+            None => {
+                let host = format!("kms.{}.amazonaws.com", self.region);
+                (format!("https://{}/", host), host)
+            }
+        };
+        let target = format!("TrentService.{}", action);
+        let body = body.to_string();
+        let now = self.clock.now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+
+        let mut request = self.client
+            .post(url)
+            .header("Content-Type", CONTENT_TYPE)
+            .header("X-Amz-Date", &amz_date)
+            .header("X-Amz-Target", &target)
+            .header("Authorization", self.authorization(&host, &amz_date, &target, &body));
+        if let Some(token) = &self.session_token {
+            request = request.header("X-Amz-Security-Token", token);
+        }
+
+        let response = request
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| format!("KMS request failed: {}", e))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let detail = response.text().await.unwrap_or_default();
+            return Err(format!("KMS returned {}: {}", status, detail));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| format!("KMS response is not JSON: {}", e))
+    }
+
+    /// SigV4 `Authorization` header for a KMS call.
+    fn authorization(&self, host: &str, amz_date: &str, target: &str, body: &str) -> String {
+        let date = &amz_date[..8];
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, SERVICE);
+
+        let mut headers = vec![
+            ("content-type", CONTENT_TYPE),
+            ("host", host),
+            ("x-amz-date", amz_date),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token));
+        }
+        headers.push(("x-amz-target", target));
+
+        let canonical_headers: String = headers.iter().map(|(k, v)| format!("{}:{}\n", k, v)).collect();
+        let signed_headers = headers.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(";");
+        let canonical_request = format!(
+            "POST\n/\n\n{}\n{}\n{}",
+            canonical_headers,
+            signed_headers,
+            sha256_hex(body.as_bytes())
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            sha256_hex(canonical_request.as_bytes())
+        );
+
+        let key = [date, self.region.as_str(), SERVICE, "aws4_request"].iter().fold(
+            format!("AWS4{}", self.secret_access_key).into_bytes(),
+            |key, part| sign(&key, part.as_bytes()),
+        );
+        let signature = hex::encode(sign(&key, string_to_sign.as_bytes()));
+
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id, scope, signed_headers, signature
+        )
+    }
+}
+
+fn sign(key: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data).as_ref().to_vec()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(digest::digest(&digest::SHA256, data))
+}
+
+pub struct KmsResolver {
+    kms: KmsClient,
+}
+
+impl KmsResolver {
+    pub fn new(config: &SecretsConfig, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        Ok(Self { kms: KmsClient::new(config, clock)? })
+    }
+
+    async fn decrypt(&self, ciphertext: &str) -> Result<String, SecurityError> {
+        let body = self.kms
+            .call("Decrypt", serde_json::json!({ "CiphertextBlob": ciphertext }))
+            .await
+            .map_err(SecurityError::ConfigError)?;
+        let plaintext = body["Plaintext"]
+            .as_str()
+            .and_then(|encoded| base64::decode(encoded).ok())
+            .ok_or_else(|| SecurityError::ConfigError("KMS response has no plaintext".to_string()))?;
+        String::from_utf8(plaintext)
+            .map_err(|_| SecurityError::ConfigError("KMS plaintext is not UTF-8".to_string()))
+    }
+}
+
+impl SecretResolver for KmsResolver {
+    fn resolve<'a>(&'a self, reference: &'a str) -> BoxFuture<'a, Result<String, SecurityError>> {
+        Box::pin(self.decrypt(reference))
+    }
+}
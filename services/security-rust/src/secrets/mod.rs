@@ -0,0 +1,139 @@
+/*!
+Secrets Module
+Resolution of `kms://` and `vault://` references in secret config fields
+
+Runs once at startup, before any component reads a secret, so config can
+carry references instead of plaintext. `*_FILE` variables are already
+handled by `config`. References that cannot be resolved are reported
+together and stop startup.
+
+- `vault://<path>#<field>`: field of a KV version 2 secret under
+  `VAULT_KV_MOUNT`; the field defaults to `value`.
+- `kms://<ciphertext>`: base64 ciphertext decrypted with AWS KMS.
+
+Hosts can add schemes with `SecurityServiceBuilder::secret_resolver`.
+*/
+
+pub mod kms;
+pub mod vault;
+
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::clock::Clock;
+use crate::config::{Config, SecretsConfig};
+use crate::errors::SecurityError;
+
+pub use kms::KmsResolver;
+pub use vault::VaultResolver;
+
+/// Built-in schemes; a reference to one that is not configured is an error
+/// rather than a literal value.
+const BUILT_IN: &[&str] = &["kms", "vault"];
+
+pub trait SecretResolver: Send + Sync {
+    /// Resolve the part of a reference after `scheme://`.
+    fn resolve<'a>(&'a self, reference: &'a str) -> BoxFuture<'a, Result<String, SecurityError>>;
+}
+
+#[derive(Default, Clone)]
+pub struct SecretResolvers {
+    by_scheme: HashMap<String, Arc<dyn SecretResolver>>,
+}
+
+impl SecretResolvers {
+    /// Vault and KMS resolvers, for whichever backends are configured.
+    pub fn from_config(config: &SecretsConfig, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let mut resolvers = Self::default();
+        if config.vault_addr.is_some() {
+            resolvers.register("vault", Arc::new(VaultResolver::new(config)?));
+        }
+        if config.kms_region.is_some() {
+            resolvers.register("kms", Arc::new(KmsResolver::new(config, clock)?));
+        }
+        Ok(resolvers)
+    }
+
+    /// Add or replace the resolver for `scheme`.
+    pub fn register(&mut self, scheme: &str, resolver: Arc<dyn SecretResolver>) -> &mut Self {
+        self.by_scheme.insert(scheme.to_string(), resolver);
+        self
+    }
+
+    /// Add `other`'s resolvers, which win on scheme clashes.
+    pub fn extend(&mut self, other: SecretResolvers) {
+        self.by_scheme.extend(other.by_scheme);
+    }
+
+    /// Replace every reference in the secret fields of `config` with the
+    /// value it points at.
+    pub async fn resolve_config(&self, config: &mut Config) -> Result<(), SecurityError> {
+        let mut problems = Vec::new();
+        let mut resolved = Vec::new();
+
+        for (name, value) in secret_fields(config) {
+            // DATABASE_URL and REDIS_URL carry schemes of their own
+            let Some((scheme, reference)) = value.split_once("://") else {
+                continue;
+            };
+            let resolver = match self.by_scheme.get(scheme) {
+                Some(resolver) => resolver,
+                None if BUILT_IN.contains(&scheme) => {
+                    problems.push(format!("{} uses {}:// but no {} backend is configured", name, scheme, scheme));
+                    continue;
+                }
+                None => continue,
+            };
+            match resolver.resolve(reference).await {
+                Ok(secret) => {
+                    // Long references are ciphertext, not worth quoting in errors
+                    let source = if reference.len() <= 80 {
+                        format!("{}://{}", scheme, reference)
+                    } else {
+                        format!("a {}:// reference", scheme)
+                    };
+                    *value = secret;
+                    resolved.push((name, source));
+                }
+                Err(e) => problems.push(format!("{}: {}", name, e)),
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(SecurityError::ConfigError(format!(
+                "Secret references could not be resolved: {}",
+                problems.join("; ")
+            )));
+        }
+        if !resolved.is_empty() {
+            info!("Resolved {} secret reference(s)", resolved.len());
+        }
+        for (name, source) in resolved {
+            config.sources.record(name, source);
+        }
+        Ok(())
+    }
+}
+
+/// Config fields that may hold a reference, with the variable that set them.
+fn secret_fields(config: &mut Config) -> Vec<(&'static str, &mut String)> {
+    let mut fields = vec![
+        ("SECURITY_MASTER_KEY", &mut config.crypto.master_key),
+        ("DATABASE_URL", &mut config.database_url),
+        ("REDIS_URL", &mut config.redis_url),
+        ("SECRET_KEY", &mut config.auth.jwt_secret),
+        ("AUDIT_PSEUDONYMIZATION_KEY", &mut config.audit.pseudonymization_key),
+    ];
+    if let Some(password) = config.database_password.as_mut() {
+        fields.push(("DATABASE_PASSWORD", password));
+    }
+    if let Some(secret) = config.captcha.secret.as_mut() {
+        fields.push(("CAPTCHA_SECRET", secret));
+    }
+    if let Some(key) = config.tls.key_pem.as_mut() {
+        fields.push(("TLS_KEY_PEM", key));
+    }
+    fields
+}
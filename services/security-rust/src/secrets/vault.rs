@@ -0,0 +1,76 @@
+/*!
+Vault Resolver
+Reads secrets from a HashiCorp Vault KV version 2 engine
+*/
+
+use futures::future::BoxFuture;
+use std::time::Duration;
+
+use super::SecretResolver;
+use crate::config::SecretsConfig;
+use crate::errors::SecurityError;
+
+pub struct VaultResolver {
+    client: reqwest::Client,
+    addr: String,
+    token: String,
+    namespace: Option<String>,
+    mount: String,
+}
+
+impl VaultResolver {
+    pub fn new(config: &SecretsConfig) -> Result<Self, SecurityError> {
+        let addr = config.vault_addr.clone()
+            .ok_or_else(|| SecurityError::ConfigError("VAULT_ADDR is not set".to_string()))?;
+        let token = config.vault_token.clone()
+            .ok_or_else(|| SecurityError::ConfigError("VAULT_TOKEN (or VAULT_TOKEN_FILE) is not set".to_string()))?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .map_err(|e| SecurityError::ConfigError(format!("Failed to build Vault client: {}", e)))?;
+
+        Ok(Self {
+            client,
+            addr: addr.trim_end_matches('/').to_string(),
+            token,
+            namespace: config.vault_namespace.clone(),
+            mount: config.vault_kv_mount.trim_matches('/').to_string(),
+        })
+    }
+
+    async fn read(&self, reference: &str) -> Result<String, SecurityError> {
+        let (path, field) = reference.split_once('#').unwrap_or((reference, "value"));
+        let url = format!("{}/v1/{}/data/{}", self.addr, self.mount, path.trim_matches('/'));
+
+        let mut request = self.client.get(&url).header("X-Vault-Token", &self.token);
+        if let Some(namespace) = &self.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| SecurityError::ConfigError(format!("Vault request for '{}' failed: {}", path, e)))?;
+        if !response.status().is_success() {
+            return Err(SecurityError::ConfigError(format!(
+                "Vault returned {} for '{}'",
+                response.status(),
+                path
+            )));
+        }
+
+        let body: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| SecurityError::ConfigError(format!("Vault response for '{}' is not JSON: {}", path, e)))?;
+        body["data"]["data"][field]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| SecurityError::ConfigError(format!("Vault secret '{}' has no string field '{}'", path, field)))
+    }
+}
+
+impl SecretResolver for VaultResolver {
+    fn resolve<'a>(&'a self, reference: &'a str) -> BoxFuture<'a, Result<String, SecurityError>> {
+        Box::pin(self.read(reference))
+    }
+}
@@ -0,0 +1,580 @@
+/*!
+Signing Module
+Asymmetric signing keys (Ed25519 and ECDSA P-256) published as a JWKS
+
+HMAC signatures verify only with the master key, so anyone able to verify
+one can also forge one. The keys here sign with a private half that never
+leaves the service. The public halves are served at
+`/.well-known/jwks.json`, so other services verify offline with
+`cotai-verify`'s `JwksCache`.
+
+Private keys are persisted wrapped by the configured key provider, like
+data keys. One key per algorithm is generated on first start; every
+replica loads the same keys and picks up keys created elsewhere on the
+key maintenance loop.
+
+Keys are replaced by a rollover, one per algorithm at a time:
+
+1. `publishing`: the new key is generated `pending`, so it appears in the
+   JWKS but does not sign, for `SIGNING_ROLLOVER_PROPAGATION_SECS`.
+2. `retiring`: the new key signs and the old one becomes `verify_only`,
+   still published, for `SIGNING_ROLLOVER_RETIRE_AFTER_SECS`.
+3. `completed`: the old key is `retired` and leaves the JWKS.
+
+Phases advance on the key maintenance loop. Each step is a conditional
+update in storage, so exactly one replica makes it.
+
+The same keys sign access tokens and audit receipts (`sign_jwt`) and
+everything else: caller data from `/crypto/sign` and internal proofs.
+Everything else is signed as `cotai-signature:<domain>\0` followed by the
+data, with the domain naming the use. A JWS signing input is base64url
+and a period, never a NUL, so no such signature is a valid token, and a
+signature made for one domain never verifies for another. Verifiers
+holding only the JWKS prepend the same tag.
+
+ring only generates ECDSA keys and nonces from its own system RNG, so
+P-256 keys do not follow the injected `RandomSource`. Ed25519 keys do.
+*/
+
+use chrono::{DateTime, Duration, Utc};
+use ring::rand::SystemRandom;
+use ring::signature::{
+    EcdsaKeyPair, Ed25519KeyPair, KeyPair, UnparsedPublicKey, ECDSA_P256_SHA256_FIXED,
+    ECDSA_P256_SHA256_FIXED_SIGNING, ED25519,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::errors::SecurityError;
+use crate::key_provider::{local, KeyProvider, LocalKeyProvider};
+use crate::random::{self, RandomSource};
+use crate::storage::{SigningKeyRecord, Storage};
+
+/// JOSE names of the supported signature algorithms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Algorithm {
+    /// HMAC-SHA256 under the master key; symmetric.
+    #[serde(rename = "HS256")]
+    Hs256,
+    #[serde(rename = "EdDSA")]
+    EdDsa,
+    #[serde(rename = "ES256")]
+    Es256,
+}
+
+impl Algorithm {
+    pub const ASYMMETRIC: [Algorithm; 2] = [Algorithm::EdDsa, Algorithm::Es256];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Algorithm::Hs256 => "HS256",
+            Algorithm::EdDsa => "EdDSA",
+            Algorithm::Es256 => "ES256",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "HS256" => Some(Algorithm::Hs256),
+            "EdDSA" => Some(Algorithm::EdDsa),
+            "ES256" => Some(Algorithm::Es256),
+            _ => None,
+        }
+    }
+}
+
+/// Domain of data callers send to `/crypto/sign` and gRPC `Sign`.
+pub const CALLER_DOMAIN: &str = "caller";
+pub const AUDIT_CHECKPOINT_DOMAIN: &str = "audit-checkpoint";
+pub const NOTARY_HEAD_DOMAIN: &str = "notary-head";
+pub const SUBMISSION_RECEIPT_DOMAIN: &str = "submission-receipt";
+pub const QUORUM_ATTESTATION_DOMAIN: &str = "quorum-attestation";
+pub const MANIFEST_DOMAIN: &str = "manifest";
+pub const CUSTODY_REPORT_DOMAIN: &str = "custody-report";
+pub const DOSSIER_INDEX_DOMAIN: &str = "dossier-index";
+pub const DOSSIER_ARCHIVE_DOMAIN: &str = "dossier-archive";
+
+const TAG_PREFIX: &[u8] = b"cotai-signature:";
+
+/// `data` as signed for `domain`.
+pub fn tagged(domain: &str, data: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(TAG_PREFIX.len() + domain.len() + 1 + data.len());
+    message.extend_from_slice(TAG_PREFIX);
+    message.extend_from_slice(domain.as_bytes());
+    message.push(0);
+    message.extend_from_slice(data);
+    message
+}
+
+enum Pair {
+    Ed25519(Ed25519KeyPair),
+    Ecdsa(EcdsaKeyPair),
+}
+
+struct SigningKey {
+    algorithm: Algorithm,
+    pair: Pair,
+    public_key: Vec<u8>,
+    created_at: DateTime<Utc>,
+    state: String,
+}
+
+const ROLLOVER_COLUMNS: &str = "id, algorithm, old_key_id, new_key_id, phase, started_by, started_at, \
+    activate_at, activated_at, retire_at, completed_at";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Rollover {
+    pub id: Uuid,
+    pub algorithm: String,
+    pub old_key_id: Option<String>,
+    pub new_key_id: String,
+    /// `publishing`, `retiring` or `completed`.
+    pub phase: String,
+    pub started_by: String,
+    pub started_at: DateTime<Utc>,
+    /// When the new key starts signing.
+    pub activate_at: DateTime<Utc>,
+    pub activated_at: Option<DateTime<Utc>>,
+    /// When the old key leaves the JWKS.
+    pub retire_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SigningKeyMetadata {
+    pub key_id: String,
+    pub algorithm: Algorithm,
+    pub created_at: DateTime<Utc>,
+    pub state: String,
+}
+
+pub struct SigningKeys {
+    key_provider: Arc<dyn KeyProvider>,
+    local_provider: LocalKeyProvider,
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    rng: Arc<dyn RandomSource>,
+    system_rng: SystemRandom,
+    keys: RwLock<HashMap<String, SigningKey>>,
+    propagation: Duration,
+    retire_after: Duration,
+}
+
+impl SigningKeys {
+    pub async fn new(
+        config: &Config,
+        key_provider: Arc<dyn KeyProvider>,
+        storage: Storage,
+        clock: Arc<dyn Clock>,
+        rng: Arc<dyn RandomSource>,
+    ) -> Result<Self, SecurityError> {
+        let signing = Self::empty(config, key_provider, storage, clock, rng)?;
+        signing.refresh().await?;
+        for algorithm in Algorithm::ASYMMETRIC {
+            if signing.current(algorithm).is_none() {
+                signing.generate(algorithm, "active").await?;
+            }
+        }
+        Ok(signing)
+    }
+
+    /// Holding no keys yet; `new` loads or generates them.
+    pub(crate) fn empty(
+        config: &Config,
+        key_provider: Arc<dyn KeyProvider>,
+        storage: Storage,
+        clock: Arc<dyn Clock>,
+        rng: Arc<dyn RandomSource>,
+    ) -> Result<Self, SecurityError> {
+        Ok(Self {
+            local_provider: LocalKeyProvider::new(config.crypto.master_key.as_bytes(), rng.clone())?,
+            key_provider,
+            storage,
+            clock,
+            rng,
+            system_rng: SystemRandom::new(),
+            keys: RwLock::new(HashMap::new()),
+            propagation: Duration::seconds(config.crypto.signing_propagation_secs),
+            retire_after: Duration::seconds(config.crypto.signing_retire_after_secs),
+        })
+    }
+
+    /// Load keys not yet known here and pick up state changes.
+    pub async fn refresh(&self) -> Result<(), SecurityError> {
+        for record in self.storage.load_signing_keys().await? {
+            if let Some(key) = self.keys.write().unwrap().get_mut(&record.key_id) {
+                key.state = record.state;
+                continue;
+            }
+            match self.open(&record).await {
+                Ok(key) => {
+                    self.keys.write().unwrap().insert(record.key_id, key);
+                }
+                Err(e) => warn!("Skipping signing key {}: {:?}", record.key_id, e),
+            }
+        }
+        Ok(())
+    }
+
+    async fn open(&self, record: &SigningKeyRecord) -> Result<SigningKey, SecurityError> {
+        let algorithm = Algorithm::parse(&record.algorithm)
+            .filter(|a| *a != Algorithm::Hs256)
+            .ok_or_else(|| SecurityError::CryptoError(format!("Unknown signing algorithm '{}'", record.algorithm)))?;
+        let private = if record.provider == self.key_provider.name() {
+            self.key_provider.unwrap(&record.key_id, &record.wrapped_key).await?
+        } else if record.provider == local::NAME {
+            self.local_provider.unwrap(&record.key_id, &record.wrapped_key).await?
+        } else {
+            return Err(SecurityError::CryptoError(format!(
+                "Signing key {} was wrapped by {}, not the configured {}",
+                record.key_id, record.provider, self.key_provider.name()
+            )));
+        };
+
+        let invalid = || SecurityError::CryptoError(format!("Signing key {} is malformed", record.key_id));
+        let pair = match algorithm {
+            Algorithm::EdDsa => Pair::Ed25519(Ed25519KeyPair::from_seed_unchecked(&private).map_err(|_| invalid())?),
+            _ => Pair::Ecdsa(
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &private, &self.system_rng)
+                    .map_err(|_| invalid())?,
+            ),
+        };
+        let public_key = match &pair {
+            Pair::Ed25519(pair) => pair.public_key().as_ref().to_vec(),
+            Pair::Ecdsa(pair) => pair.public_key().as_ref().to_vec(),
+        };
+
+        Ok(SigningKey { algorithm, pair, public_key, created_at: record.created_at, state: record.state.clone() })
+    }
+
+    /// The public key `record` opens to, base64url as stored, without
+    /// loading it.
+    pub async fn restored_public_key(&self, record: &SigningKeyRecord) -> Result<String, SecurityError> {
+        let key = self.open(record).await?;
+        Ok(base64::encode_config(&key.public_key, base64::URL_SAFE_NO_PAD))
+    }
+
+    /// Generate and persist a key for `algorithm` in `state`. Returns its id.
+    async fn generate(&self, algorithm: Algorithm, state: &str) -> Result<String, SecurityError> {
+        let key_id = random::uuid_v4(self.rng.as_ref())?.to_string();
+        let private = match algorithm {
+            Algorithm::EdDsa => {
+                let mut seed = [0u8; 32];
+                self.rng.fill(&mut seed)?;
+                seed.to_vec()
+            }
+            Algorithm::Es256 => EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &self.system_rng)
+                .map_err(|_| SecurityError::CryptoError("Failed to generate ECDSA key".to_string()))?
+                .as_ref()
+                .to_vec(),
+            Algorithm::Hs256 => {
+                return Err(SecurityError::ValidationError("HS256 uses the master key".to_string()))
+            }
+        };
+
+        let mut record = SigningKeyRecord {
+            key_id: key_id.clone(),
+            algorithm: algorithm.as_str().to_string(),
+            provider: self.key_provider.name().to_string(),
+            wrapped_key: self.key_provider.wrap(&key_id, &private).await?,
+            public_key: String::new(),
+            state: state.to_string(),
+            created_at: self.clock.now(),
+        };
+        let key = self.open(&record).await?;
+        record.public_key = base64::encode_config(&key.public_key, base64::URL_SAFE_NO_PAD);
+        self.storage.save_signing_key(&record).await?;
+
+        self.keys.write().unwrap().insert(key_id.clone(), key);
+        info!("Generated {} signing key {}", algorithm.as_str(), key_id);
+        Ok(key_id)
+    }
+
+    /// The newest active key for `algorithm`.
+    fn current(&self, algorithm: Algorithm) -> Option<String> {
+        self.keys.read().unwrap().iter()
+            .filter(|(_, key)| key.algorithm == algorithm && key.state == "active")
+            .max_by_key(|(_, key)| key.created_at)
+            .map(|(key_id, _)| key_id.clone())
+    }
+
+    /// Sign `data` for `domain` with `key_id`, or the current key for
+    /// `algorithm`. Returns the key id used and the signature.
+    pub fn sign(
+        &self,
+        algorithm: Algorithm,
+        key_id: Option<&str>,
+        domain: &str,
+        data: &[u8],
+    ) -> Result<(String, Vec<u8>), SecurityError> {
+        self.sign_message(algorithm, key_id, &tagged(domain, data))
+    }
+
+    /// Sign `message` as is. Only JWS signing inputs may come here.
+    fn sign_message(&self, algorithm: Algorithm, key_id: Option<&str>, message: &[u8]) -> Result<(String, Vec<u8>), SecurityError> {
+        let key_id = match key_id {
+            Some(key_id) => key_id.to_string(),
+            None => self.current(algorithm)
+                .ok_or_else(|| SecurityError::CryptoError(format!("No active {} key", algorithm.as_str())))?,
+        };
+
+        let keys = self.keys.read().unwrap();
+        let key = keys.get(&key_id)
+            .filter(|key| key.algorithm == algorithm)
+            .ok_or_else(|| SecurityError::NotFound(format!("No {} key {}", algorithm.as_str(), key_id)))?;
+        if key.state != "active" {
+            return Err(SecurityError::Conflict(format!("Signing key {} is {}", key_id, key.state)));
+        }
+
+        let signature = match &key.pair {
+            Pair::Ed25519(pair) => pair.sign(message).as_ref().to_vec(),
+            Pair::Ecdsa(pair) => pair.sign(&self.system_rng, message)
+                .map_err(|_| SecurityError::CryptoError("Signing failed".to_string()))?
+                .as_ref()
+                .to_vec(),
+        };
+        Ok((key_id, signature))
+    }
+
+    /// Sign `claims` as a compact JWT with the current `algorithm` key,
+    /// named by `kid` so `JwksCache` can find it.
+    pub fn sign_jwt<T: Serialize>(&self, algorithm: Algorithm, claims: &T) -> Result<String, SecurityError> {
+        let key_id = self.current(algorithm)
+            .ok_or_else(|| SecurityError::CryptoError(format!("No active {} key", algorithm.as_str())))?;
+        let encode = |value: serde_json::Value| base64::encode_config(value.to_string(), base64::URL_SAFE_NO_PAD);
+        let header = encode(serde_json::json!({ "alg": algorithm.as_str(), "typ": "JWT", "kid": key_id }));
+        let payload = encode(serde_json::to_value(claims)
+            .map_err(|e| SecurityError::CryptoError(format!("Failed to encode claims: {}", e)))?);
+
+        let signing_input = format!("{}.{}", header, payload);
+        let (_, signature) = self.sign_message(algorithm, Some(&key_id), signing_input.as_bytes())?;
+        Ok(format!("{}.{}", signing_input, base64::encode_config(signature, base64::URL_SAFE_NO_PAD)))
+    }
+
+    /// Check a signature `sign` made over `data` for `domain`.
+    pub fn verify(
+        &self,
+        algorithm: Algorithm,
+        key_id: &str,
+        domain: &str,
+        data: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, SecurityError> {
+        self.verify_message(algorithm, key_id, &tagged(domain, data), signature)
+    }
+
+    /// Check the signature of a JWT from `sign_jwt` over its signing input.
+    pub fn verify_jwt_signature(
+        &self,
+        algorithm: Algorithm,
+        key_id: &str,
+        signing_input: &str,
+        signature: &[u8],
+    ) -> Result<bool, SecurityError> {
+        self.verify_message(algorithm, key_id, signing_input.as_bytes(), signature)
+    }
+
+    fn verify_message(&self, algorithm: Algorithm, key_id: &str, message: &[u8], signature: &[u8]) -> Result<bool, SecurityError> {
+        let keys = self.keys.read().unwrap();
+        let key = keys.get(key_id)
+            .filter(|key| key.algorithm == algorithm)
+            .ok_or_else(|| SecurityError::NotFound(format!("No {} key {}", algorithm.as_str(), key_id)))?;
+        if key.state == "retired" {
+            return Ok(false);
+        }
+
+        let verified = match algorithm {
+            Algorithm::EdDsa => UnparsedPublicKey::new(&ED25519, &key.public_key).verify(message, signature),
+            _ => UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, &key.public_key).verify(message, signature),
+        };
+        Ok(verified.is_ok())
+    }
+
+    /// Start replacing the current `algorithm` key: publish a pending key
+    /// now and switch to it after the propagation window.
+    pub async fn start_rollover(&self, algorithm: Algorithm, actor: &str) -> Result<Rollover, SecurityError> {
+        if algorithm == Algorithm::Hs256 {
+            return Err(SecurityError::ValidationError("HS256 uses the master key".to_string()));
+        }
+        let in_progress: Option<Uuid> = sqlx::query_scalar(
+            "SELECT id FROM signing_rollovers WHERE algorithm = $1 AND phase <> 'completed'",
+        )
+        .bind(algorithm.as_str())
+        .fetch_optional(self.storage.pool())
+        .await?;
+        if let Some(id) = in_progress {
+            return Err(SecurityError::Conflict(format!("Rollover {} of {} is in progress", id, algorithm.as_str())));
+        }
+
+        let old_key_id = self.current(algorithm);
+        let new_key_id = self.generate(algorithm, "pending").await?;
+        let now = self.clock.now();
+        // The partial unique index turns a concurrent start into a conflict
+        let rollover = sqlx::query_as::<_, Rollover>(&format!(
+            "INSERT INTO signing_rollovers (id, algorithm, old_key_id, new_key_id, started_by, started_at, activate_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING {}",
+            ROLLOVER_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(algorithm.as_str())
+        .bind(&old_key_id)
+        .bind(&new_key_id)
+        .bind(actor)
+        .bind(now)
+        .bind(now + self.propagation)
+        .fetch_one(self.storage.pool())
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                SecurityError::Conflict(format!("A rollover of {} is in progress", algorithm.as_str()))
+            }
+            e => e.into(),
+        })?;
+
+        info!("{} started {} signing key rollover {} to {}", actor, algorithm.as_str(), rollover.id, new_key_id);
+        Ok(rollover)
+    }
+
+    /// Move due rollovers to their next phase. Returns the rollovers that
+    /// moved, in their new phase.
+    pub async fn advance_rollovers(&self) -> Result<Vec<Rollover>, SecurityError> {
+        let now = self.clock.now();
+        let mut tx = self.storage.begin().await?;
+        let due = sqlx::query_as::<_, Rollover>(&format!(
+            "SELECT {} FROM signing_rollovers \
+             WHERE (phase = 'publishing' AND activate_at <= $1) OR (phase = 'retiring' AND retire_at <= $1) \
+             FOR UPDATE SKIP LOCKED",
+            ROLLOVER_COLUMNS
+        ))
+        .bind(now)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut advanced = Vec::new();
+        for rollover in due {
+            let rollover = if rollover.phase == "publishing" {
+                // Every other key of the algorithm stops signing, not just
+                // the one current at the start
+                sqlx::query(
+                    "UPDATE signing_keys SET state = 'verify_only' \
+                     WHERE algorithm = $1 AND state = 'active' AND key_id <> $2",
+                )
+                .bind(&rollover.algorithm)
+                .bind(&rollover.new_key_id)
+                .execute(&mut *tx)
+                .await?;
+                sqlx::query("UPDATE signing_keys SET state = 'active' WHERE key_id = $1 AND state = 'pending'")
+                    .bind(&rollover.new_key_id)
+                    .execute(&mut *tx)
+                    .await?;
+                sqlx::query_as::<_, Rollover>(&format!(
+                    "UPDATE signing_rollovers SET phase = 'retiring', activated_at = $2, retire_at = $3 \
+                     WHERE id = $1 RETURNING {}",
+                    ROLLOVER_COLUMNS
+                ))
+                .bind(rollover.id)
+                .bind(now)
+                .bind(now + self.retire_after)
+                .fetch_one(&mut *tx)
+                .await?
+            } else {
+                sqlx::query(
+                    "UPDATE signing_keys SET state = 'retired' \
+                     WHERE algorithm = $1 AND state = 'verify_only' AND created_at < \
+                     (SELECT created_at FROM signing_keys WHERE key_id = $2)",
+                )
+                .bind(&rollover.algorithm)
+                .bind(&rollover.new_key_id)
+                .execute(&mut *tx)
+                .await?;
+                sqlx::query_as::<_, Rollover>(&format!(
+                    "UPDATE signing_rollovers SET phase = 'completed', completed_at = $2 WHERE id = $1 RETURNING {}",
+                    ROLLOVER_COLUMNS
+                ))
+                .bind(rollover.id)
+                .bind(now)
+                .fetch_one(&mut *tx)
+                .await?
+            };
+            info!("Signing key rollover {} of {} is now {}", rollover.id, rollover.algorithm, rollover.phase);
+            advanced.push(rollover);
+        }
+        tx.commit().await?;
+
+        if !advanced.is_empty() {
+            self.refresh().await?;
+        }
+        Ok(advanced)
+    }
+
+    /// Rollovers, newest first, for operators following each phase.
+    pub async fn rollovers(&self, limit: i64) -> Result<Vec<Rollover>, SecurityError> {
+        let rollovers = sqlx::query_as::<_, Rollover>(&format!(
+            "SELECT {} FROM signing_rollovers ORDER BY started_at DESC LIMIT $1",
+            ROLLOVER_COLUMNS
+        ))
+        .bind(limit)
+        .fetch_all(self.storage.pool())
+        .await?;
+        Ok(rollovers)
+    }
+
+    pub fn metadata(&self) -> Vec<SigningKeyMetadata> {
+        let mut keys: Vec<_> = self.keys.read().unwrap().iter()
+            .map(|(key_id, key)| SigningKeyMetadata {
+                key_id: key_id.clone(),
+                algorithm: key.algorithm,
+                created_at: key.created_at,
+                state: key.state.clone(),
+            })
+            .collect();
+        keys.sort_by_key(|key| std::cmp::Reverse(key.created_at));
+        keys
+    }
+
+    /// When the newest key in the JWKS was created.
+    pub fn published_at(&self) -> Option<DateTime<Utc>> {
+        self.keys.read().unwrap().values()
+            .filter(|key| key.state != "retired")
+            .map(|key| key.created_at)
+            .max()
+    }
+
+    /// Public keys of every loaded key that can still verify, as a JWK Set,
+    /// ordered by key id so equal sets serialize the same.
+    pub fn jwks(&self) -> serde_json::Value {
+        let encode = |bytes: &[u8]| base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+        let loaded = self.keys.read().unwrap();
+        let mut published: Vec<_> = loaded.iter().filter(|(_, key)| key.state != "retired").collect();
+        published.sort_by_key(|(key_id, _)| key_id.as_str());
+        let keys: Vec<_> = published.into_iter()
+            .map(|(key_id, key)| match key.algorithm {
+                Algorithm::EdDsa => serde_json::json!({
+                    "kty": "OKP",
+                    "crv": "Ed25519",
+                    "x": encode(&key.public_key),
+                    "kid": key_id,
+                    "alg": "EdDSA",
+                    "use": "sig"
+                }),
+                // Uncompressed point: 0x04 || X || Y
+                _ => serde_json::json!({
+                    "kty": "EC",
+                    "crv": "P-256",
+                    "x": encode(&key.public_key[1..33]),
+                    "y": encode(&key.public_key[33..]),
+                    "kid": key_id,
+                    "alg": "ES256",
+                    "use": "sig"
+                }),
+            })
+            .collect();
+        serde_json::json!({ "keys": keys })
+    }
+}
@@ -0,0 +1,840 @@
+/*!
+SOAR Module
+Two-way incident sync with external SOAR platforms
+
+Each integration in `SOAR_ENDPOINTS` receives `incident.opened` and
+`incident.updated` deliveries, queued in the transaction that changed the
+incident and sent with retries. It posts back to
+`/soar/{integration}/callback` to change status, attach enrichment or
+record its own case id. Both directions carry
+`X-Cotai-Signature: t=<unix seconds>,v1=<hex HMAC-SHA256 of "t.body">`
+keyed with the integration's secret: its `soar.<name>` outbound credential
+(see `credentials`), or else its entry in `SOAR_SECRETS`.
+
+A field mapping translates between schemas. `outbound` maps dotted paths
+in their payload to our incident fields (plus `event`); `inbound` says
+where `incident_id`, `status`, `enrichment` and `external_id` sit in their
+callbacks; `statuses` maps their status values to ours. Without a mapping
+both sides use our field names. A callback's update is pushed to every
+other integration but not echoed back to its sender.
+
+Each delivery carries its id in `X-Cotai-Delivery`, the same on every
+attempt. `POST /admin/soar/deliveries/{id}/replay` sends one again right
+away, whatever its status, marked `X-Cotai-Replay: 1`, and answers with
+the outcome; the queue is left alone, so this is for debugging a
+receiver, not for redelivery (see `/retry`).
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::types::Json;
+use sqlx::{FromRow, Postgres, QueryBuilder, Transaction};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, Principal};
+use crate::changes::{self, NewChange};
+use crate::clock::Clock;
+use crate::config::{Config, SoarConfig};
+use crate::credentials::CredentialCache;
+use crate::crypto::webhook;
+use crate::deadline;
+use crate::detection::{self, Incident, IncidentUpdate};
+use crate::errors::SecurityError;
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::Storage;
+
+pub const SIGNATURE_HEADER: &str = "x-cotai-signature";
+
+pub const DELIVERY_HEADER: &str = "x-cotai-delivery";
+
+pub const REPLAY_HEADER: &str = "x-cotai-replay";
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "created_at", column: "created_at", kind: KeyKind::Timestamp },
+];
+
+const MAPPING_COLUMNS: &str = "integration, definition, version, updated_by, updated_at";
+
+const DELIVERY_COLUMNS: &str = "id, integration, incident_id, event, status, attempts, next_attempt_at, \
+    last_error, created_at, delivered_at";
+
+/// Fields an outbound mapping can pick from.
+const OUTBOUND_FIELDS: &[&str] = &[
+    "event", "id", "rule", "severity", "tenant_id", "entity_type", "entity", "event_ids",
+    "first_seen", "last_seen", "status", "enrichment", "external_refs", "created_at", "updated_at",
+];
+
+/// Fields a callback can carry.
+const INBOUND_FIELDS: &[&str] = &["incident_id", "status", "enrichment", "external_id"];
+
+/// Longest wait between delivery attempts.
+const MAX_BACKOFF_SECS: i64 = 3600;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldMapping {
+    /// Dotted path in their payload -> our incident field.
+    #[serde(default)]
+    pub outbound: BTreeMap<String, String>,
+    /// Our callback field -> dotted path in their payload.
+    #[serde(default)]
+    pub inbound: BTreeMap<String, String>,
+    /// Their status value -> ours.
+    #[serde(default)]
+    pub statuses: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct SoarMapping {
+    pub integration: String,
+    pub definition: Json<FieldMapping>,
+    pub version: i64,
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Delivery {
+    pub id: Uuid,
+    pub integration: String,
+    pub incident_id: Uuid,
+    pub event: String,
+    /// `pending`, `delivered` or `failed` once attempts run out.
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DeliveryFilter {
+    pub status: Option<String>,
+    pub integration: Option<String>,
+}
+
+/// Queue a delivery of `event` to every integration but `except`, inside
+/// the transaction that changed the incident.
+pub async fn queue(
+    tx: &mut Transaction<'_, Postgres>,
+    config: &SoarConfig,
+    incident_id: Uuid,
+    event: &str,
+    except: Option<&str>,
+) -> Result<(), SecurityError> {
+    for (integration, _) in config.endpoints.iter().filter(|(name, _)| Some(name.as_str()) != except) {
+        sqlx::query(
+            "INSERT INTO soar_deliveries (id, integration, incident_id, event) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(Uuid::new_v4())
+        .bind(integration)
+        .bind(incident_id)
+        .bind(event)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+pub(crate) fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    webhook::sign(&[secret], timestamp, body)
+}
+
+pub(crate) fn signature_valid(secret: &str, header: &str, body: &[u8], now: DateTime<Utc>, tolerance_secs: i64) -> bool {
+    webhook::verify(&[secret], header, body, now, tolerance_secs).is_ok()
+}
+
+fn get_path<'a>(source: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(source, |value, key| value.get(key))
+}
+
+fn set_path(target: &mut Map<String, Value>, path: &str, value: Value) {
+    match path.split_once('.') {
+        None => {
+            target.insert(path.to_string(), value);
+        }
+        Some((head, rest)) => {
+            let child = target.entry(head.to_string()).or_insert_with(|| Value::Object(Map::new()));
+            if !child.is_object() {
+                *child = Value::Object(Map::new());
+            }
+            if let Value::Object(child) = child {
+                set_path(child, rest, value);
+            }
+        }
+    }
+}
+
+/// Our view of the incident, reshaped by the outbound mapping.
+fn outbound_payload(incident: &Incident, event: &str, mapping: Option<&FieldMapping>) -> Value {
+    let mut ours = serde_json::to_value(incident).unwrap_or_default();
+    if let Value::Object(fields) = &mut ours {
+        fields.insert("event".to_string(), Value::String(event.to_string()));
+    }
+
+    match mapping.filter(|m| !m.outbound.is_empty()) {
+        None => ours,
+        Some(mapping) => {
+            let mut theirs = Map::new();
+            for (path, field) in &mapping.outbound {
+                if let Some(value) = ours.get(field) {
+                    set_path(&mut theirs, path, value.clone());
+                }
+            }
+            Value::Object(theirs)
+        }
+    }
+}
+
+/// Read a callback through the inbound mapping.
+fn inbound_update(integration: &str, body: &Value, mapping: Option<&FieldMapping>) -> Result<(Uuid, IncidentUpdate), SecurityError> {
+    let field = |name: &str| {
+        let path = mapping.and_then(|m| m.inbound.get(name)).map(String::as_str).unwrap_or(name);
+        get_path(body, path)
+    };
+
+    let incident_id = field("incident_id")
+        .and_then(Value::as_str)
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| SecurityError::ValidationError("Callback has no valid incident_id".to_string()))?;
+
+    let status = field("status").and_then(Value::as_str).map(|status| {
+        mapping
+            .and_then(|m| m.statuses.get(status))
+            .cloned()
+            .unwrap_or_else(|| status.to_string())
+    });
+
+    let enrichment = match field("enrichment") {
+        None | Some(Value::Null) => None,
+        Some(Value::Object(map)) => Some(map.clone()),
+        Some(_) => return Err(SecurityError::ValidationError("enrichment must be an object".to_string())),
+    };
+
+    let external_id = match field("external_id") {
+        Some(Value::String(id)) => Some(id.clone()),
+        Some(Value::Number(id)) => Some(id.to_string()),
+        _ => None,
+    };
+
+    Ok((incident_id, IncidentUpdate {
+        status,
+        enrichment,
+        external_ref: external_id.map(|id| (integration.to_string(), id)),
+    }))
+}
+
+fn validate(mapping: &FieldMapping) -> Result<(), SecurityError> {
+    let mut problems = Vec::new();
+
+    for (path, field) in &mapping.outbound {
+        if path.is_empty() || path.split('.').any(str::is_empty) {
+            problems.push(format!("outbound path '{}' is not a dotted path", path));
+        }
+        if !OUTBOUND_FIELDS.contains(&field.as_str()) {
+            problems.push(format!("outbound '{}' maps unknown incident field '{}'", path, field));
+        }
+    }
+    for field in mapping.inbound.keys() {
+        if !INBOUND_FIELDS.contains(&field.as_str()) {
+            problems.push(format!("inbound field '{}' must be one of {}", field, INBOUND_FIELDS.join(", ")));
+        }
+    }
+    for (theirs, ours) in &mapping.statuses {
+        if !detection::STATUSES.contains(&ours.as_str()) {
+            problems.push(format!("status '{}' maps to unknown status '{}'", theirs, ours));
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(SecurityError::ValidationError(problems.join("; ")));
+    }
+    Ok(())
+}
+
+pub struct SoarService {
+    storage: Storage,
+    config: SoarConfig,
+    endpoints: HashMap<String, String>,
+    secrets: HashMap<String, String>,
+    credentials: Arc<CredentialCache>,
+    client: reqwest::Client,
+    clock: Arc<dyn Clock>,
+}
+
+impl SoarService {
+    pub async fn new(
+        config: &Config,
+        storage: Storage,
+        clock: Arc<dyn Clock>,
+        credentials: Arc<CredentialCache>,
+    ) -> Result<Self, SecurityError> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(config.soar.timeout_secs))
+            .build()
+            .map_err(|e| SecurityError::ConfigError(format!("SOAR client: {}", e)))?;
+
+        info!("SOAR service initialized with {} integrations", config.soar.endpoints.len());
+        Ok(Self {
+            storage,
+            config: config.soar.clone(),
+            endpoints: config.soar.endpoints.iter().cloned().collect(),
+            secrets: config.soar.secrets.iter().cloned().collect(),
+            credentials,
+            client,
+            clock,
+        })
+    }
+
+    /// Signing secret of a configured integration, the stored credential
+    /// taking precedence over config.
+    fn secret(&self, integration: &str) -> Option<String> {
+        if !self.endpoints.contains_key(integration) {
+            return None;
+        }
+        self.credentials.hmac_key(&format!("soar.{}", integration))
+            .or_else(|| self.secrets.get(integration).cloned())
+    }
+
+    fn ensure_known(&self, integration: &str) -> Result<(), SecurityError> {
+        if !self.endpoints.contains_key(integration) {
+            return Err(SecurityError::NotFound(format!("Unknown integration '{}'", integration)));
+        }
+        Ok(())
+    }
+
+    pub async fn mapping(&self, integration: &str) -> Result<Option<SoarMapping>, SecurityError> {
+        let mapping = sqlx::query_as::<_, SoarMapping>(&format!(
+            "SELECT {} FROM soar_mappings WHERE integration = $1",
+            MAPPING_COLUMNS
+        ))
+        .bind(integration)
+        .fetch_optional(self.storage.pool())
+        .await?;
+        Ok(mapping)
+    }
+
+    /// Configured integrations with their mappings.
+    pub async fn integrations(&self) -> Result<Vec<Value>, SecurityError> {
+        let mappings: HashMap<String, SoarMapping> = sqlx::query_as::<_, SoarMapping>(&format!(
+            "SELECT {} FROM soar_mappings",
+            MAPPING_COLUMNS
+        ))
+        .fetch_all(self.storage.pool())
+        .await?
+        .into_iter()
+        .map(|m| (m.integration.clone(), m))
+        .collect();
+
+        Ok(self.config.endpoints.iter().map(|(name, url)| serde_json::json!({
+            "name": name,
+            "url": url,
+            "mapping": mappings.get(name)
+        })).collect())
+    }
+
+    pub async fn put_mapping(&self, actor: &Principal, integration: &str, mapping: FieldMapping) -> Result<SoarMapping, SecurityError> {
+        self.ensure_known(integration)?;
+        validate(&mapping)?;
+
+        let mut tx = self.storage.begin().await?;
+        let current = sqlx::query_as::<_, SoarMapping>(&format!(
+            "SELECT {} FROM soar_mappings WHERE integration = $1 FOR UPDATE",
+            MAPPING_COLUMNS
+        ))
+        .bind(integration)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let stored = sqlx::query_as::<_, SoarMapping>(&format!(
+            "INSERT INTO soar_mappings (integration, definition, updated_by) VALUES ($1, $2, $3) \
+             ON CONFLICT (integration) DO UPDATE SET definition = $2, updated_by = $3, updated_at = NOW(), \
+             version = soar_mappings.version + 1 \
+             RETURNING {}",
+            MAPPING_COLUMNS
+        ))
+        .bind(integration)
+        .bind(Json(&mapping))
+        .bind(&actor.subject)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        changes::record(&mut tx, NewChange {
+            resource_type: "soar_mapping",
+            resource_id: integration.to_string(),
+            version: Some(stored.version),
+            action: if current.is_some() { "update" } else { "create" },
+            author: &actor.subject,
+            tenant_id: None,
+            before: current.as_ref().and_then(|m| serde_json::to_value(&m.definition.0).ok()),
+            after: serde_json::to_value(&stored.definition.0).ok(),
+        }).await?;
+        tx.commit().await?;
+
+        Ok(stored)
+    }
+
+    /// Drop the mapping; the integration falls back to our field names.
+    pub async fn delete_mapping(&self, actor: &Principal, integration: &str) -> Result<(), SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let deleted = sqlx::query_as::<_, SoarMapping>(&format!(
+            "DELETE FROM soar_mappings WHERE integration = $1 RETURNING {}",
+            MAPPING_COLUMNS
+        ))
+        .bind(integration)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("No mapping stored for '{}'", integration)))?;
+
+        changes::record(&mut tx, NewChange {
+            resource_type: "soar_mapping",
+            resource_id: integration.to_string(),
+            version: Some(deleted.version),
+            action: "delete",
+            author: &actor.subject,
+            tenant_id: None,
+            before: serde_json::to_value(&deleted.definition.0).ok(),
+            after: None,
+        }).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn deliveries(&self, filter: &DeliveryFilter, page: &PageRequest) -> Result<Page<Delivery>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT {} FROM soar_deliveries WHERE 1 = 1",
+            DELIVERY_COLUMNS
+        ));
+        if let Some(status) = &filter.status {
+            builder.push(" AND status = ").push_bind(status.clone());
+        }
+        if let Some(integration) = &filter.integration {
+            builder.push(" AND integration = ").push_bind(integration.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let deliveries = builder
+            .build_query_as::<Delivery>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(deliveries, |delivery, _| (SortKey::Timestamp(delivery.created_at), delivery.id)))
+    }
+
+    /// Put a failed delivery back in the queue with fresh attempts.
+    pub async fn retry(&self, id: Uuid) -> Result<Delivery, SecurityError> {
+        sqlx::query_as::<_, Delivery>(&format!(
+            "UPDATE soar_deliveries SET status = 'pending', attempts = 0, next_attempt_at = NOW() \
+             WHERE id = $1 AND status = 'failed' RETURNING {}",
+            DELIVERY_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("No failed delivery {}", id)))
+    }
+
+    /// Send a delivery again now, leaving its queue state alone. Returns
+    /// the delivery and the error the receiver's answer gave, if any.
+    pub async fn replay(&self, id: Uuid) -> Result<(Delivery, Option<String>), SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let delivery = sqlx::query_as::<_, Delivery>(&format!(
+            "SELECT {} FROM soar_deliveries WHERE id = $1",
+            DELIVERY_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("No delivery {}", id)))?;
+        let mapping = sqlx::query_as::<_, SoarMapping>(&format!(
+            "SELECT {} FROM soar_mappings WHERE integration = $1",
+            MAPPING_COLUMNS
+        ))
+        .bind(&delivery.integration)
+        .fetch_optional(&mut *tx)
+        .await?
+        .map(|m| m.definition.0);
+
+        let outcome = match self.send(&mut tx, &delivery, mapping.as_ref(), true).await {
+            Ok(()) => None,
+            Err(SecurityError::DeliveryError(e)) => Some(e),
+            Err(e) => return Err(e),
+        };
+        tx.commit().await?;
+        Ok((delivery, outcome))
+    }
+
+    /// Send the deliveries that are due. Returns how many were attempted.
+    pub async fn deliver_due(&self) -> Result<usize, SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let due = sqlx::query_as::<_, Delivery>(&format!(
+            "SELECT {} FROM soar_deliveries WHERE status = 'pending' AND next_attempt_at <= NOW() \
+             ORDER BY next_attempt_at LIMIT $1 FOR UPDATE SKIP LOCKED",
+            DELIVERY_COLUMNS
+        ))
+        .bind(self.config.delivery_batch_size)
+        .fetch_all(&mut *tx)
+        .await?;
+        if due.is_empty() {
+            return Ok(0);
+        }
+
+        let mappings: HashMap<String, FieldMapping> = sqlx::query_as::<_, SoarMapping>(&format!(
+            "SELECT {} FROM soar_mappings",
+            MAPPING_COLUMNS
+        ))
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|m| (m.integration, m.definition.0))
+        .collect();
+
+        for delivery in &due {
+            let result = self.send(&mut tx, delivery, mappings.get(&delivery.integration), false).await;
+            let attempts = delivery.attempts + 1;
+            match result {
+                Ok(()) => {
+                    sqlx::query(
+                        "UPDATE soar_deliveries SET status = 'delivered', attempts = $2, delivered_at = NOW(), \
+                         last_error = NULL WHERE id = $1",
+                    )
+                    .bind(delivery.id)
+                    .bind(attempts)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                Err(e) => {
+                    let status = if attempts >= self.config.max_attempts { "failed" } else { "pending" };
+                    if status == "failed" {
+                        error!("SOAR delivery {} to {} failed for good: {}", delivery.id, delivery.integration, e);
+                    }
+                    let backoff = Duration::seconds((1i64 << attempts.min(12)).min(MAX_BACKOFF_SECS));
+                    sqlx::query(
+                        "UPDATE soar_deliveries SET status = $2, attempts = $3, next_attempt_at = $4, \
+                         last_error = $5 WHERE id = $1",
+                    )
+                    .bind(delivery.id)
+                    .bind(status)
+                    .bind(attempts)
+                    .bind(self.clock.now() + backoff)
+                    .bind(e.to_string())
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
+        }
+        tx.commit().await?;
+
+        Ok(due.len())
+    }
+
+    async fn send(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        delivery: &Delivery,
+        mapping: Option<&FieldMapping>,
+        replay: bool,
+    ) -> Result<(), SecurityError> {
+        let (Some(url), Some(secret)) = (self.endpoints.get(&delivery.integration), self.secret(&delivery.integration)) else {
+            return Err(SecurityError::DeliveryError("Integration is no longer configured".to_string()));
+        };
+
+        // Send the incident as it is now, so retries carry the latest state
+        let incident = sqlx::query_as::<_, Incident>(&format!(
+            "SELECT {} FROM security_incidents WHERE id = $1",
+            detection::SELECT_COLUMNS
+        ))
+        .bind(delivery.incident_id)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        let body = serde_json::to_vec(&outbound_payload(&incident, &delivery.event, mapping))
+            .map_err(|e| SecurityError::DeliveryError(e.to_string()))?;
+        let mut request = deadline::outbound(self.client.post(url))
+            .header("Content-Type", "application/json")
+            .header(SIGNATURE_HEADER, signature(&secret, self.clock.now().timestamp(), &body))
+            .header(DELIVERY_HEADER, delivery.id.to_string());
+        if replay {
+            request = request.header(REPLAY_HEADER, "1");
+        }
+        let response = request
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| SecurityError::DeliveryError(e.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(SecurityError::DeliveryError(format!("Integration returned {}", response.status())));
+        }
+        Ok(())
+    }
+}
+
+/// Background loop sending queued incident deliveries.
+pub async fn run_delivery(state: web::Data<crate::AppState>) {
+    let interval_ms = state.config.soar.delivery_interval_ms;
+    let mut interval = tokio::time::interval(std::time::Duration::from_millis(interval_ms));
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = state.soar.deliver_due().await {
+            error!("SOAR delivery failed: {:?}", e);
+        }
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("SOAR operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "SOAR operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit_soar(state: &crate::AppState, actor: String, tenant_id: Option<String>, action: &str, resource: String, detail: Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id,
+        actor,
+        actor_ip: None,
+        action: action.to_string(),
+        resource: resource.clone(),
+        outcome: "success".to_string(),
+        payload: detail,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit {} on {}: {:?}", action, resource, e);
+    }
+}
+
+/// Status, enrichment and case ids posted back by a SOAR platform.
+pub async fn callback_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Bytes,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let integration = path.into_inner();
+    let Some(secret) = state.soar.secret(&integration) else {
+        return Ok(error_response(SecurityError::NotFound(format!("Unknown integration '{}'", integration))));
+    };
+
+    let header = req.headers().get(SIGNATURE_HEADER).and_then(|h| h.to_str().ok()).unwrap_or_default();
+    let tolerance = state.config.soar.callback_tolerance_secs;
+    if !signature_valid(&secret, header, &body, state.clock.now(), tolerance) {
+        warn!("Rejected SOAR callback from {} with a bad signature", integration);
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Invalid signature"
+        })));
+    }
+
+    let payload: Value = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(e) => return Ok(error_response(SecurityError::ValidationError(format!("Invalid JSON: {}", e)))),
+    };
+    let mapping = match state.soar.mapping(&integration).await {
+        Ok(mapping) => mapping.map(|m| m.definition.0),
+        Err(e) => return Ok(error_response(e)),
+    };
+    let (id, update) = match inbound_update(&integration, &payload, mapping.as_ref()) {
+        Ok(parsed) => parsed,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    let detail = serde_json::json!({
+        "status": update.status,
+        "enrichment": update.enrichment,
+        "external_ref": update.external_ref.as_ref().map(|(_, id)| id)
+    });
+    match state.incidents.update(id, update, Some(&integration)).await {
+        Ok(incident) => {
+            audit_soar(&state, format!("soar:{}", integration), incident.tenant_id.clone(), "incident.soar_update", format!("incident:{}", id), detail).await;
+            Ok(HttpResponse::Ok().json(incident))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_integrations_handler(
+    req: HttpRequest,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    match state.soar.integrations().await {
+        Ok(integrations) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "integrations": integrations
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_mapping_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let integration = path.into_inner();
+    if let Err(e) = state.soar.ensure_known(&integration) {
+        return Ok(error_response(e));
+    }
+    match state.soar.mapping(&integration).await {
+        Ok(Some(mapping)) => Ok(HttpResponse::Ok().json(mapping)),
+        Ok(None) => Ok(error_response(SecurityError::NotFound(format!("No mapping stored for '{}'", integration)))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn put_mapping_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    mapping: web::Json<FieldMapping>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let integration = path.into_inner();
+    let mapping = mapping.into_inner();
+    match state.soar.put_mapping(&principal, &integration, mapping.clone()).await {
+        Ok(stored) => {
+            audit_soar(&state, principal.subject.clone(), principal.tenant_id.clone(), "soar_mapping.put", format!("soar_mapping:{}", integration), serde_json::to_value(&mapping).unwrap_or_default()).await;
+            Ok(HttpResponse::Ok().json(stored))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn delete_mapping_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let integration = path.into_inner();
+    match state.soar.delete_mapping(&principal, &integration).await {
+        Ok(()) => {
+            audit_soar(&state, principal.subject.clone(), principal.tenant_id.clone(), "soar_mapping.delete", format!("soar_mapping:{}", integration), serde_json::json!({})).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_deliveries_handler(
+    req: HttpRequest,
+    filter: web::Query<DeliveryFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.soar.deliveries(&filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "deliveries": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn retry_delivery_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let id = path.into_inner();
+    match state.soar.retry(id).await {
+        Ok(delivery) => {
+            info!("{} requeued SOAR delivery {}", principal.subject, id);
+            Ok(HttpResponse::Ok().json(delivery))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn replay_delivery_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let id = path.into_inner();
+    match state.soar.replay(id).await {
+        Ok((delivery, outcome)) => {
+            let detail = serde_json::json!({
+                "integration": delivery.integration,
+                "delivered": outcome.is_none(),
+                "error": outcome
+            });
+            audit_soar(&state, principal.subject.clone(), principal.tenant_id.clone(), "soar_delivery.replay", format!("soar_delivery:{}", id), detail).await;
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "delivery": delivery,
+                "delivered": outcome.is_none(),
+                "error": outcome
+            })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/soar/{integration}/callback", web::post().to(callback_handler))
+        .service(
+            web::scope("/admin/soar")
+                .route("/integrations", web::get().to(list_integrations_handler))
+                .route("/integrations/{integration}/mapping", web::get().to(get_mapping_handler))
+                .route("/integrations/{integration}/mapping", web::put().to(put_mapping_handler))
+                .route("/integrations/{integration}/mapping", web::delete().to(delete_mapping_handler))
+                .route("/deliveries", web::get().to(list_deliveries_handler))
+                .route("/deliveries/{id}/retry", web::post().to(retry_delivery_handler))
+                .route("/deliveries/{id}/replay", web::post().to(replay_delivery_handler))
+        );
+}
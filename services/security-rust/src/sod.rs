@@ -0,0 +1,447 @@
+/*!
+Separation of Duties
+Role combinations and pairs of actions one person may not hold or perform
+
+Rules come from `SOD_POLICY_FILE`, for every tenant, and from `sod` policy
+documents (see `policies`), global or for the subject's tenant:
+
+```json
+{"rules": [{"id": "custodian-not-auditor", "roles": ["key_custodian", "auditor"]},
+           {"id": "award-maker-checker",
+            "actions": ["tender:award:create", "tender:award:approve"],
+            "resources": ["tender:*"]}]}
+```
+
+A `roles` rule forbids holding more than `max` (default 1) of its roles at
+once. It is enforced where roles are handed out here, when tokens are
+minted with roles (see `auth::tokens`) and when elevations are requested
+or approved (see `auth::elevation`), and at `/authz/check`, which denies
+a subject holding a forbidden combination whatever the other rules say.
+
+An `actions` rule forbids one subject from performing more than one of its
+actions on the same resource (patterns as in `authz`, `resources`
+defaulting to every resource). Services report what was done by passing
+`"record": true` to `/authz/check` for an action they are about to
+perform; once recorded, a check for a conflicting action by the same
+subject on that resource is denied. Denials name the rule, with source
+`sod:config` or `sod:policy:<id>`, and the reason `separation_of_duties`.
+
+`GET /admin/sod/violations` reports what already breaks the rules: role
+combinations held through live refresh tokens and active elevations, and
+conflicting actions recorded before a rule existed.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::auth::auth_error_response;
+use crate::authz::glob;
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+use crate::AppState;
+
+/// Name of the policy documents holding SoD rules.
+pub const POLICY_NAME: &str = "sod";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SodPolicy {
+    pub rules: Vec<SodRule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SodRule {
+    pub id: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// Roles of `roles` one subject may hold together.
+    #[serde(default = "one")]
+    pub max: usize,
+    #[serde(default)]
+    pub actions: Vec<String>,
+    #[serde(default)]
+    pub resources: Vec<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+fn one() -> usize {
+    1
+}
+
+impl SodRule {
+    /// The rule's roles among `roles`, if more than it allows.
+    fn excess<'a>(&self, roles: impl Iterator<Item = &'a String>) -> Option<Vec<String>> {
+        let held: BTreeSet<&String> = roles.filter(|role| self.roles.contains(role)).collect();
+        (held.len() > self.max).then(|| held.into_iter().cloned().collect())
+    }
+
+    fn covers(&self, resource: &str) -> bool {
+        self.resources.is_empty() || self.resources.iter().any(|p| glob(p, resource))
+    }
+
+    /// Which of the rule's actions `action` is, if any.
+    fn duty(&self, action: &str) -> Option<usize> {
+        self.actions.iter().position(|p| glob(p, action))
+    }
+}
+
+/// A rule and where it came from, `config` or `policy:<id>`.
+pub type SourcedRule = (String, SodRule);
+
+/// Roles a subject holds and where it holds them.
+type Holdings = (BTreeSet<String>, BTreeSet<String>);
+
+/// A broken rule.
+#[derive(Debug, Clone, Serialize)]
+pub struct Conflict {
+    pub rule: String,
+    pub source: String,
+    /// The roles held together, or the actions performed and asked for.
+    pub conflicting: Vec<String>,
+}
+
+impl Conflict {
+    fn new(source: &str, rule: &SodRule, conflicting: Vec<String>) -> Self {
+        Self { rule: rule.id.clone(), source: format!("sod:{}", source), conflicting }
+    }
+
+    pub fn error(&self, subject: &str) -> SecurityError {
+        SecurityError::AccessDenied(format!(
+            "{} would break separation of duties rule {} ({})",
+            subject,
+            self.rule,
+            self.conflicting.join(", ")
+        ))
+    }
+}
+
+/// Something already breaking a rule.
+#[derive(Debug, Serialize)]
+pub struct Violation {
+    pub kind: &'static str,
+    pub subject: String,
+    pub tenant_id: Option<String>,
+    /// The resource both actions were performed on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource: Option<String>,
+    #[serde(flatten)]
+    pub conflict: Conflict,
+    /// Where the roles are held: `refresh_token` or `elevation`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+}
+
+/// Check an `sod` policy document before it is stored.
+pub fn validate_policy(document: &serde_json::Value) -> Result<(), SecurityError> {
+    let policy: SodPolicy = serde_json::from_value(document.clone())
+        .map_err(|e| SecurityError::ValidationError(format!("Invalid sod policy: {}", e)))?;
+    let problems = rule_problems(&policy.rules);
+    if !problems.is_empty() {
+        return Err(SecurityError::ValidationError(problems.join("; ")));
+    }
+    Ok(())
+}
+
+fn rule_problems(rules: &[SodRule]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for rule in rules {
+        let name = if rule.id.trim().is_empty() { "(unnamed)" } else { rule.id.as_str() };
+        if rule.id.trim().is_empty() {
+            problems.push("every rule needs an id".to_string());
+        }
+        match (rule.roles.is_empty(), rule.actions.is_empty()) {
+            (false, true) => {
+                if rule.roles.len() < 2 || rule.max == 0 || rule.max >= rule.roles.len() {
+                    problems.push(format!("rule {}: needs at least two roles and max below their number", name));
+                }
+            }
+            (true, false) => {
+                if rule.actions.len() < 2 {
+                    problems.push(format!("rule {}: needs at least two actions", name));
+                }
+            }
+            _ => problems.push(format!("rule {}: needs exactly one of roles or actions", name)),
+        }
+        if rule.roles.is_empty() && rule.max != 1 {
+            problems.push(format!("rule {}: max applies to roles only", name));
+        }
+        if !rule.roles.is_empty() && !rule.resources.is_empty() {
+            problems.push(format!("rule {}: resources apply to actions only", name));
+        }
+        let mut patterns = rule.roles.iter().chain(&rule.actions).chain(&rule.resources);
+        if patterns.any(|p| p.trim().is_empty()) {
+            problems.push(format!("rule {}: empty role, action or resource", name));
+        }
+    }
+    problems
+}
+
+/// The first `roles` rule `roles` break.
+pub fn role_conflict(rules: &[SourcedRule], roles: &[String]) -> Option<Conflict> {
+    rules.iter().find_map(|(source, rule)| {
+        rule.excess(roles.iter()).map(|held| Conflict::new(source, rule, held))
+    })
+}
+
+#[derive(sqlx::FromRow)]
+struct HeldRoles {
+    subject: String,
+    tenant_id: Option<String>,
+    roles: Vec<String>,
+    source: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct Duties {
+    subject: String,
+    tenant_id: Option<String>,
+    resource: String,
+    actions: Vec<String>,
+}
+
+pub struct SodService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    /// Rules from `SOD_POLICY_FILE`.
+    configured: Vec<SodRule>,
+}
+
+impl SodService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let configured = match &config.authz.sod_policy_file {
+            Some(path) => {
+                let text = tokio::fs::read_to_string(path).await
+                    .map_err(|e| SecurityError::ConfigError(format!("SOD_POLICY_FILE {}: {}", path, e)))?;
+                let policy: SodPolicy = serde_json::from_str(&text)
+                    .map_err(|e| SecurityError::ConfigError(format!("SOD_POLICY_FILE {}: {}", path, e)))?;
+                let problems = rule_problems(&policy.rules);
+                if !problems.is_empty() {
+                    return Err(SecurityError::ConfigError(format!("SOD_POLICY_FILE {}: {}", path, problems.join("; "))));
+                }
+                policy.rules
+            }
+            None => Vec::new(),
+        };
+
+        info!("SoD service initialized with {} configured rules", configured.len());
+        Ok(Self { storage, clock, configured })
+    }
+
+    /// Configured rules, then those of the stored policies for `tenant_id`.
+    pub async fn rules(&self, state: &AppState, tenant_id: Option<&str>) -> Result<Vec<SourcedRule>, SecurityError> {
+        let mut rules: Vec<SourcedRule> = self.configured.iter().map(|r| ("config".to_string(), r.clone())).collect();
+        for policy in state.policy_service.manifest(tenant_id).await? {
+            if policy.name != POLICY_NAME {
+                continue;
+            }
+            match serde_json::from_value::<SodPolicy>(policy.document) {
+                Ok(document) => {
+                    let source = format!("policy:{}", policy.id);
+                    rules.extend(document.rules.into_iter().map(|rule| (source.clone(), rule)));
+                }
+                Err(e) => warn!("Skipping unreadable sod policy {}: {}", policy.id, e),
+            }
+        }
+        Ok(rules)
+    }
+
+    /// Refuse handing out roles that would leave `subject` holding a
+    /// forbidden combination with `held`.
+    pub async fn check_assignment(
+        &self,
+        state: &AppState,
+        tenant_id: Option<&str>,
+        subject: &str,
+        held: &[String],
+        adding: &[String],
+    ) -> Result<(), SecurityError> {
+        let rules = self.rules(state, tenant_id).await?;
+        let roles: Vec<String> = held.iter().chain(adding).cloned().collect();
+        match role_conflict(&rules, &roles) {
+            Some(conflict) => Err(conflict.error(subject)),
+            None => Ok(()),
+        }
+    }
+
+    /// The first `actions` rule that `action` on `resource` would break,
+    /// given what `subject` has already done to it.
+    pub async fn duty_conflict(
+        &self,
+        rules: &[SourcedRule],
+        subject: &str,
+        action: &str,
+        resource: &str,
+    ) -> Result<Option<Conflict>, SecurityError> {
+        let relevant: Vec<&SourcedRule> = rules.iter()
+            .filter(|(_, rule)| rule.covers(resource) && rule.duty(action).is_some())
+            .collect();
+        if relevant.is_empty() {
+            return Ok(None);
+        }
+        let performed: Vec<String> = sqlx::query_scalar(
+            "SELECT DISTINCT action FROM sod_duties WHERE subject = $1 AND resource = $2",
+        )
+        .bind(subject)
+        .bind(resource)
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        for (source, rule) in relevant {
+            let asked = rule.duty(action);
+            let done = performed.iter().find(|p| rule.duty(p).is_some_and(|duty| Some(duty) != asked));
+            if let Some(done) = done {
+                return Ok(Some(Conflict::new(source, rule, vec![done.clone(), action.to_string()])));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Note that `subject` performed `action` on `resource`.
+    pub async fn record(&self, subject: &str, tenant_id: Option<&str>, action: &str, resource: &str) -> Result<(), SecurityError> {
+        sqlx::query(
+            "INSERT INTO sod_duties (id, subject, tenant_id, action, resource, performed_at) \
+             VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (subject, action, resource) DO NOTHING",
+        )
+        .bind(Uuid::new_v4())
+        .bind(subject)
+        .bind(tenant_id)
+        .bind(action)
+        .bind(resource)
+        .bind(self.clock.now())
+        .execute(self.storage.pool())
+        .await?;
+        Ok(())
+    }
+
+    /// Existing role combinations and recorded actions that break the
+    /// rules, for one tenant or all.
+    pub async fn violations(&self, state: &AppState, tenant_id: Option<&str>) -> Result<Vec<Violation>, SecurityError> {
+        let now: DateTime<Utc> = self.clock.now();
+        let held = sqlx::query_as::<_, HeldRoles>(
+            "SELECT subject, tenant_id, roles, 'refresh_token' AS source FROM refresh_tokens \
+             WHERE used_at IS NULL AND revoked_at IS NULL AND expires_at > $1 AND cardinality(roles) > 0 \
+             AND ($2::TEXT IS NULL OR tenant_id = $2) \
+             UNION ALL \
+             SELECT subject, tenant_id, ARRAY[role], 'elevation' FROM elevations \
+             WHERE status = 'active' AND expires_at > $1 AND ($2::TEXT IS NULL OR tenant_id = $2)",
+        )
+        .bind(now)
+        .bind(tenant_id)
+        .fetch_all(self.storage.pool())
+        .await?;
+        let duties = sqlx::query_as::<_, Duties>(
+            "SELECT subject, tenant_id, resource, ARRAY_AGG(DISTINCT action) AS actions FROM sod_duties \
+             WHERE ($1::TEXT IS NULL OR tenant_id = $1) \
+             GROUP BY subject, tenant_id, resource HAVING COUNT(DISTINCT action) > 1",
+        )
+        .bind(tenant_id)
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        // Everything a subject holds at once, with where it holds it
+        let mut subjects: BTreeMap<(String, Option<String>), Holdings> = BTreeMap::new();
+        for row in held {
+            let entry = subjects.entry((row.subject, row.tenant_id)).or_default();
+            entry.0.extend(row.roles);
+            entry.1.insert(row.source);
+        }
+
+        let mut rules_by_tenant: Vec<(Option<String>, Vec<SourcedRule>)> = Vec::new();
+        let mut violations = Vec::new();
+        for ((subject, tenant), (roles, sources)) in subjects {
+            let rules = cached_rules(self, state, &mut rules_by_tenant, &tenant).await?;
+            for (source, rule) in rules {
+                if let Some(held) = rule.excess(roles.iter()) {
+                    violations.push(Violation {
+                        kind: "roles",
+                        subject: subject.clone(),
+                        tenant_id: tenant.clone(),
+                        resource: None,
+                        conflict: Conflict::new(source, rule, held),
+                        sources: sources.iter().cloned().collect(),
+                    });
+                }
+            }
+        }
+        for row in duties {
+            let rules = cached_rules(self, state, &mut rules_by_tenant, &row.tenant_id).await?;
+            for (source, rule) in rules.iter().filter(|(_, rule)| rule.covers(&row.resource)) {
+                let duties: BTreeSet<usize> = row.actions.iter().filter_map(|a| rule.duty(a)).collect();
+                if duties.len() > 1 {
+                    let conflicting = row.actions.iter().filter(|a| rule.duty(a).is_some()).cloned().collect();
+                    violations.push(Violation {
+                        kind: "actions",
+                        subject: row.subject.clone(),
+                        tenant_id: row.tenant_id.clone(),
+                        resource: Some(row.resource.clone()),
+                        conflict: Conflict::new(source, rule, conflicting),
+                        sources: Vec::new(),
+                    });
+                }
+            }
+        }
+        Ok(violations)
+    }
+}
+
+/// Rules for `tenant`, loaded once per report.
+async fn cached_rules<'a>(
+    sod: &SodService,
+    state: &AppState,
+    loaded: &'a mut Vec<(Option<String>, Vec<SourcedRule>)>,
+    tenant: &Option<String>,
+) -> Result<&'a [SourcedRule], SecurityError> {
+    let index = match loaded.iter().position(|(t, _)| t == tenant) {
+        Some(index) => index,
+        None => {
+            let rules = sod.rules(state, tenant.as_deref()).await?;
+            loaded.push((tenant.clone(), rules));
+            loaded.len() - 1
+        }
+    };
+    Ok(&loaded[index].1)
+}
+
+// HTTP handlers
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ViolationQuery {
+    pub tenant_id: Option<String>,
+}
+
+pub async fn violations_handler(
+    req: HttpRequest,
+    query: web::Query<ViolationQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+    match state.sod.violations(&state, query.tenant_id.as_deref()).await {
+        Ok(violations) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "violations": violations
+        }))),
+        Err(e) => {
+            error!("SoD violation report failed: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "SoD violation report failed"
+            })))
+        }
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/sod")
+            .route("/violations", web::get().to(violations_handler)),
+    );
+}
@@ -0,0 +1,38 @@
+/*!
+Soft Delete Module
+Restore window and background hard-purge for soft-deleted admin resources
+*/
+
+use actix_web::web;
+use chrono::{DateTime, Duration, Utc};
+use tracing::{error, info};
+
+use crate::config::Config;
+
+/// Items deleted at or after this instant can still be restored at `now`.
+pub fn restorable_since(config: &Config, now: DateTime<Utc>) -> DateTime<Utc> {
+    now - Duration::hours(config.soft_delete.retention_hours)
+}
+
+/// Background loop hard-deleting items whose restore window has passed.
+pub async fn run_purge(state: web::Data<crate::AppState>) {
+    let interval_secs = state.config.soft_delete.purge_interval_secs;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+
+    loop {
+        interval.tick().await;
+        let cutoff = restorable_since(&state.config, state.clock.now());
+
+        match state.audit_service.purge_deleted_saved_searches(cutoff).await {
+            Ok(0) => {}
+            Ok(purged) => info!("Purged {} deleted saved searches", purged),
+            Err(e) => error!("Saved search purge failed: {:?}", e),
+        }
+
+        match state.policy_service.purge_deleted(cutoff).await {
+            Ok(0) => {}
+            Ok(purged) => info!("Purged {} deleted policies", purged),
+            Err(e) => error!("Policy purge failed: {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+/*!
+Startup Module
+Dependency initialization with retry and backoff, and per-component init status
+*/
+
+use serde::Serialize;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::config::StartupConfig;
+use crate::errors::SecurityError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InitState {
+    Ready,
+    /// Serving, but without data a background job is still trying to load.
+    Degraded,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentStatus {
+    pub component: &'static str,
+    pub state: InitState,
+    pub attempts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Default)]
+pub struct StartupReport {
+    components: Mutex<Vec<ComponentStatus>>,
+}
+
+impl StartupReport {
+    fn set(&self, status: ComponentStatus) {
+        let mut components = self.components.lock().unwrap();
+        match components.iter_mut().find(|c| c.component == status.component) {
+            Some(existing) => *existing = status,
+            None => components.push(status),
+        }
+    }
+
+    pub fn components(&self) -> Vec<ComponentStatus> {
+        self.components.lock().unwrap().clone()
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.components.lock().unwrap().iter().any(|c| c.state == InitState::Degraded)
+    }
+
+    /// Called by background loops once a degraded component has caught up.
+    pub fn recovered(&self, component: &'static str) {
+        let mut components = self.components.lock().unwrap();
+        if let Some(status) = components.iter_mut().find(|c| c.component == component && c.state == InitState::Degraded) {
+            info!("{} recovered from degraded startup", component);
+            status.state = InitState::Ready;
+            status.detail = None;
+        }
+    }
+}
+
+/// Errors worth waiting out: the dependency may simply not be up yet.
+/// Configuration and key errors will not fix themselves and fail at once.
+fn is_transient(e: &SecurityError) -> bool {
+    matches!(e, SecurityError::StorageError(_) | SecurityError::DeliveryError(_))
+}
+
+/// Run a component's constructor, retrying transient failures with exponential backoff.
+pub async fn init<T, F, Fut>(
+    config: &StartupConfig,
+    report: &StartupReport,
+    component: &'static str,
+    mut init: F,
+) -> Result<T, SecurityError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, SecurityError>>,
+{
+    let max_backoff = Duration::from_millis(config.max_backoff_ms);
+    let mut backoff = Duration::from_millis(config.initial_backoff_ms);
+    let mut attempts = 0;
+
+    loop {
+        attempts += 1;
+        match init().await {
+            Ok(value) => {
+                report.set(ComponentStatus { component, state: InitState::Ready, attempts, detail: None });
+                return Ok(value);
+            }
+            Err(e) if is_transient(&e) && attempts < config.max_attempts => {
+                warn!(
+                    "{} initialization failed (attempt {}/{}), retrying in {:?}: {}",
+                    component, attempts, config.max_attempts, backoff, e
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(max_backoff);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Best-effort initial load for components that can serve without it,
+/// such as caches a background refresh fills in later.
+pub async fn warm<Fut>(report: &StartupReport, component: &'static str, load: Fut)
+where
+    Fut: Future<Output = Result<(), SecurityError>>,
+{
+    if let Err(e) = load.await {
+        warn!("{} starting degraded: {}", component, e);
+        report.set(ComponentStatus {
+            component,
+            state: InitState::Degraded,
+            attempts: 1,
+            detail: Some("Initial load failed; background refresh will retry".to_string()),
+        });
+    }
+}
@@ -0,0 +1,328 @@
+/*!
+Status Page
+Public component health, incidents and maintenance for the ops status page
+
+`GET /status` answers anyone, without credentials:
+
+```json
+{"status": "operational", "updated_at": "...",
+ "components": [{"name": "database", "status": "operational"}, ...],
+ "incidents": [{"reference": "5f2c9a1e", "title": "Security incident", "severity": "critical",
+                "status": "investigating", "started_at": "...", "updated_at": "..."}],
+ "maintenance": [{"reason": "...", "starts_at": "...", "ends_at": "...", "in_progress": false}]}
+```
+
+Components are the readiness checks (see `health`): `operational`, or when
+failing `outage` if critical and `degraded` otherwise. The overall status
+is `maintenance` during a window, else the worst component's. Only
+unresolved security incidents of at least `STATUS_INCIDENT_SEVERITY` are
+listed, and only by severity, progress and times: never their rule,
+entity, tenant or evidence. Maintenance windows show their reason.
+
+`GET /status/feed.json` has the incidents and windows as a JSON Feed
+(jsonfeed.org, version 1.1) for status page tooling and feed readers.
+
+A snapshot is kept for `STATUS_CACHE_SECS` per replica, so checks run no
+more often however busy the page, and each client address may ask
+`STATUS_RATE_LIMIT_RPM` times a minute.
+*/
+
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::auth::client_ip;
+use crate::clock::Clock;
+use crate::config::{Config, StatusPageConfig};
+use crate::errors::SecurityError;
+use crate::health::Criticality;
+use crate::storage::Storage;
+use crate::AppState;
+
+/// Severities from least to most severe; see `alerting::Severity`.
+const SEVERITIES: &[&str] = &["info", "low", "medium", "high", "critical"];
+
+/// Incidents listed at most.
+const MAX_INCIDENTS: i64 = 20;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Component {
+    pub name: &'static str,
+    pub status: &'static str,
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct IncidentRow {
+    id: Uuid,
+    severity: String,
+    status: String,
+    first_seen: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PublicIncident {
+    /// The start of the incident's id, for quoting to support.
+    pub reference: String,
+    pub title: &'static str,
+    pub severity: String,
+    /// `investigating` until acknowledged, then `identified`.
+    pub status: &'static str,
+    pub started_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PublicMaintenance {
+    pub id: Uuid,
+    pub reason: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub in_progress: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    pub status: &'static str,
+    pub updated_at: DateTime<Utc>,
+    pub components: Vec<Component>,
+    pub incidents: Vec<PublicIncident>,
+    pub maintenance: Vec<PublicMaintenance>,
+}
+
+pub struct StatusPage {
+    storage: Storage,
+    config: StatusPageConfig,
+    clock: Arc<dyn Clock>,
+    cache: RwLock<Option<Snapshot>>,
+}
+
+impl StatusPage {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        info!(
+            "Status page initialized (incidents from {}, cached {}s)",
+            config.status_page.incident_severity, config.status_page.cache_secs
+        );
+        Ok(Self {
+            storage,
+            config: config.status_page.clone(),
+            clock,
+            cache: RwLock::new(None),
+        })
+    }
+
+    /// Severities published, least severe first.
+    fn severities(&self) -> Vec<String> {
+        SEVERITIES
+            .iter()
+            .skip_while(|s| **s != self.config.incident_severity)
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    async fn incidents(&self) -> Result<Vec<PublicIncident>, SecurityError> {
+        let severities = self.severities();
+        if severities.is_empty() {
+            return Ok(Vec::new());
+        }
+        let rows = sqlx::query_as::<_, IncidentRow>(
+            "SELECT id, severity, status, first_seen, updated_at FROM security_incidents \
+             WHERE status <> 'resolved' AND severity = ANY($1) ORDER BY first_seen DESC LIMIT $2",
+        )
+        .bind(&severities)
+        .bind(MAX_INCIDENTS)
+        .fetch_all(self.storage.pool())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| PublicIncident {
+                reference: row.id.simple().to_string()[..8].to_string(),
+                title: "Security incident",
+                severity: row.severity,
+                status: if row.status == "open" { "investigating" } else { "identified" },
+                started_at: row.first_seen,
+                updated_at: row.updated_at,
+            })
+            .collect())
+    }
+
+    async fn build(&self, state: &AppState) -> Result<Snapshot, SecurityError> {
+        let now = self.clock.now();
+        let timeout = std::time::Duration::from_millis(state.config.health.check_timeout_ms);
+        let report = state.health.run(state, timeout).await;
+        let components: Vec<Component> = report
+            .checks
+            .iter()
+            .map(|check| Component {
+                name: check.name,
+                status: match (check.passed, check.criticality) {
+                    (true, _) => "operational",
+                    (false, Criticality::Critical) => "outage",
+                    (false, Criticality::NonCritical) => "degraded",
+                },
+            })
+            .collect();
+
+        let maintenance: Vec<PublicMaintenance> = state
+            .maintenance
+            .upcoming()
+            .await?
+            .into_iter()
+            .map(|window| PublicMaintenance {
+                in_progress: window.covers(now),
+                id: window.id,
+                reason: window.reason,
+                starts_at: window.starts_at,
+                ends_at: window.ends_at,
+            })
+            .collect();
+
+        let status = if maintenance.iter().any(|w| w.in_progress) {
+            "maintenance"
+        } else if components.iter().any(|c| c.status == "outage") {
+            "outage"
+        } else if components.iter().any(|c| c.status == "degraded") {
+            "degraded"
+        } else {
+            "operational"
+        };
+
+        Ok(Snapshot {
+            status,
+            updated_at: now,
+            components,
+            incidents: self.incidents().await?,
+            maintenance,
+        })
+    }
+
+    /// The current snapshot, rebuilt when older than `STATUS_CACHE_SECS`.
+    pub async fn snapshot(&self, state: &AppState) -> Result<Snapshot, SecurityError> {
+        let max_age = Duration::seconds(self.config.cache_secs as i64);
+        if let Some(snapshot) = self.cache.read().unwrap().as_ref() {
+            if self.clock.now() - snapshot.updated_at < max_age {
+                return Ok(snapshot.clone());
+            }
+        }
+        let snapshot = self.build(state).await?;
+        *self.cache.write().unwrap() = Some(snapshot.clone());
+        Ok(snapshot)
+    }
+
+    /// `snapshot` as a JSON Feed.
+    pub fn feed(&self, snapshot: &Snapshot) -> serde_json::Value {
+        let mut items: Vec<serde_json::Value> = snapshot
+            .incidents
+            .iter()
+            .map(|incident| {
+                serde_json::json!({
+                    "id": format!("incident:{}", incident.reference),
+                    "title": format!("{} ({}): {}", incident.title, incident.severity, incident.status),
+                    "content_text": format!(
+                        "A {} security incident is {} since {}. Reference {}.",
+                        incident.severity, incident.status, incident.started_at.to_rfc3339(), incident.reference
+                    ),
+                    "date_published": incident.started_at,
+                    "date_modified": incident.updated_at,
+                    "tags": ["incident", incident.severity]
+                })
+            })
+            .collect();
+        items.extend(snapshot.maintenance.iter().map(|window| {
+            serde_json::json!({
+                "id": format!("maintenance:{}", window.id),
+                "title": if window.in_progress { "Maintenance in progress" } else { "Scheduled maintenance" },
+                "content_text": format!(
+                    "{} From {} to {}; changes are refused meanwhile.",
+                    window.reason, window.starts_at.to_rfc3339(), window.ends_at.to_rfc3339()
+                ),
+                "date_published": window.starts_at,
+                "tags": ["maintenance"]
+            })
+        }));
+
+        let mut feed = serde_json::json!({
+            "version": "https://jsonfeed.org/version/1.1",
+            "title": self.config.title,
+            "items": items,
+            "_cotai": {
+                "status": snapshot.status,
+                "updated_at": snapshot.updated_at
+            }
+        });
+        if let Some(url) = &self.config.public_url {
+            feed["home_page_url"] = serde_json::json!(url);
+        }
+        feed
+    }
+}
+
+// HTTP handlers
+
+/// The 429 for a client over `STATUS_RATE_LIMIT_RPM`.
+async fn rejection(state: &AppState, req: &HttpRequest) -> Option<HttpResponse> {
+    let ip = client_ip(req).and_then(|ip| ip.parse::<IpAddr>().ok())?;
+    let limiter = &state.rate_limiter;
+    let rule = limiter.minute_rule("status", state.config.status_page.rate_limit_rpm);
+    let key = limiter.address(&rule, ip).key;
+    let decision = limiter.acquire(&rule, &key).await;
+    if decision.allowed {
+        return None;
+    }
+    let retry_after = decision.retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    Some(
+        HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+            .json(serde_json::json!({
+                "error": "Too many requests",
+                "code": "rate_limited",
+                "retry_after_secs": retry_after
+            })),
+    )
+}
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    error!("Status page failed: {:?}", e);
+    HttpResponse::ServiceUnavailable().json(serde_json::json!({
+        "error": "Status unavailable"
+    }))
+}
+
+fn cache_control(state: &AppState) -> (header::HeaderName, String) {
+    (header::CACHE_CONTROL, format!("public, max-age={}", state.config.status_page.cache_secs))
+}
+
+pub async fn status_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Some(response) = rejection(&state, &req).await {
+        return Ok(response);
+    }
+    match state.status_page.snapshot(&state).await {
+        Ok(snapshot) => Ok(HttpResponse::Ok().insert_header(cache_control(&state)).json(snapshot)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn feed_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    if let Some(response) = rejection(&state, &req).await {
+        return Ok(response);
+    }
+    match state.status_page.snapshot(&state).await {
+        Ok(snapshot) => Ok(HttpResponse::Ok()
+            .insert_header(cache_control(&state))
+            .content_type("application/feed+json")
+            .body(state.status_page.feed(&snapshot).to_string())),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/status", web::get().to(status_handler))
+        .route("/status/feed.json", web::get().to(feed_handler));
+}
@@ -0,0 +1,308 @@
+/*!
+Storage Module
+PostgreSQL connection management, schema migrations and key persistence
+*/
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::postgres::{PgConnectOptions, PgPool, PgPoolOptions};
+use sqlx::{FromRow, Postgres, Transaction};
+use std::str::FromStr;
+use tracing::info;
+
+use crate::config::Config;
+use crate::deadline;
+use crate::errors::SecurityError;
+use crate::health::{CheckFuture, Criticality, HealthRegistry};
+
+/// A persisted data key; see `migrations/0019_crypto_keys.sql` and
+/// `0020_key_providers.sql` for the schema. Wrapping happens in the crypto
+/// service, so storage only ever holds wrapped key material.
+#[derive(Debug, Clone, FromRow)]
+pub struct KeyRecord {
+    pub key_id: String,
+    pub algorithm: String,
+    /// Key provider that wrapped it, see `key_provider`.
+    pub provider: String,
+    /// The provider's wrapped form of the key.
+    pub wrapped_key: String,
+    /// `generated` or `imported`.
+    pub source: String,
+    /// `active`, `decrypt_only`, `compromised`, `retired` or `archived`.
+    pub state: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A persisted asymmetric signing key, see `migrations/0024_signing_keys.sql`.
+#[derive(Debug, Clone, FromRow)]
+pub struct SigningKeyRecord {
+    pub key_id: String,
+    /// `EdDSA` or `ES256`.
+    pub algorithm: String,
+    pub provider: String,
+    pub wrapped_key: String,
+    pub public_key: String,
+    pub state: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// How often a key was used for one operation on one day.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct KeyUsage {
+    pub key_id: String,
+    pub day: NaiveDate,
+    /// `encrypt`, `decrypt` or `rewrap`.
+    pub operation: String,
+    pub count: i64,
+    pub first_at: DateTime<Utc>,
+    pub last_at: DateTime<Utc>,
+}
+
+#[derive(Clone)]
+pub struct Storage {
+    pool: PgPool,
+}
+
+impl Storage {
+    pub async fn new(config: &Config) -> Result<Self, SecurityError> {
+        let mut options = PgConnectOptions::from_str(&config.database_url)?;
+        if let Some(password) = &config.database_password {
+            options = options.password(password);
+        }
+        let pool = PgPoolOptions::new()
+            .max_connections(20)
+            .connect_with(options)
+            .await?;
+
+        sqlx::migrate!("./migrations")
+            .run(&pool)
+            .await
+            .map_err(|e| SecurityError::StorageError(format!("Migration failed: {}", e)))?;
+
+        info!("Storage initialized successfully");
+        Ok(Self { pool })
+    }
+
+    /// A pool whose every query fails, for unit tests of what happens when
+    /// storage does.
+    #[cfg(test)]
+    pub(crate) fn unreachable() -> Self {
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(std::time::Duration::from_millis(200))
+            .connect_lazy("postgres://cotai@127.0.0.1:1/cotai")
+            .expect("valid URL");
+        Self { pool }
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// Begin a transaction. Inside a request with a deadline, its statements
+    /// are cancelled server-side once the budget runs out.
+    pub async fn begin(&self) -> Result<Transaction<'static, Postgres>, SecurityError> {
+        let mut tx = self.pool.begin().await?;
+        if let Some(left) = deadline::remaining() {
+            sqlx::query(&format!("SET LOCAL statement_timeout = {}", left.as_millis().max(1)))
+                .execute(&mut *tx)
+                .await?;
+        }
+        Ok(tx)
+    }
+
+    pub async fn is_ready(&self) -> bool {
+        sqlx::query("SELECT 1").execute(&self.pool).await.is_ok()
+    }
+
+    /// Every persisted data key, oldest first, but those moved to the cold
+    /// tier (see `key_archive`).
+    pub async fn load_keys(&self) -> Result<Vec<KeyRecord>, SecurityError> {
+        let keys = sqlx::query_as::<_, KeyRecord>(
+            "SELECT key_id, algorithm, provider, wrapped_key, source, state, created_at FROM crypto_keys \
+             WHERE state <> 'archived' ORDER BY created_at",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(keys)
+    }
+
+    /// One data key, archived or not.
+    pub async fn load_key(&self, key_id: &str) -> Result<Option<KeyRecord>, SecurityError> {
+        let key = sqlx::query_as::<_, KeyRecord>(
+            "SELECT key_id, algorithm, provider, wrapped_key, source, state, created_at FROM crypto_keys WHERE key_id = $1",
+        )
+        .bind(key_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(key)
+    }
+
+    /// Persist a data key. Ids are never reused, so an existing record wins
+    /// and `false` is returned.
+    pub async fn save_key(&self, record: &KeyRecord) -> Result<bool, SecurityError> {
+        let inserted = sqlx::query(
+            "INSERT INTO crypto_keys (key_id, algorithm, provider, wrapped_key, source, state, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT (key_id) DO NOTHING",
+        )
+        .bind(&record.key_id)
+        .bind(&record.algorithm)
+        .bind(&record.provider)
+        .bind(&record.wrapped_key)
+        .bind(&record.source)
+        .bind(&record.state)
+        .bind(record.created_at)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(inserted == 1)
+    }
+
+    /// Every persisted signing key, oldest first.
+    pub async fn load_signing_keys(&self) -> Result<Vec<SigningKeyRecord>, SecurityError> {
+        let keys = sqlx::query_as::<_, SigningKeyRecord>(
+            "SELECT key_id, algorithm, provider, wrapped_key, public_key, state, created_at \
+             FROM signing_keys ORDER BY created_at",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(keys)
+    }
+
+    pub async fn save_signing_key(&self, record: &SigningKeyRecord) -> Result<bool, SecurityError> {
+        let inserted = sqlx::query(
+            "INSERT INTO signing_keys (key_id, algorithm, provider, wrapped_key, public_key, state, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT (key_id) DO NOTHING",
+        )
+        .bind(&record.key_id)
+        .bind(&record.algorithm)
+        .bind(&record.provider)
+        .bind(&record.wrapped_key)
+        .bind(&record.public_key)
+        .bind(&record.state)
+        .bind(record.created_at)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(inserted == 1)
+    }
+
+    /// Replace a key's wrapping after moving it to another provider.
+    pub async fn rewrap_key(&self, key_id: &str, provider: &str, wrapped_key: &str) -> Result<(), SecurityError> {
+        sqlx::query("UPDATE crypto_keys SET provider = $2, wrapped_key = $3 WHERE key_id = $1")
+            .bind(key_id)
+            .bind(provider)
+            .bind(wrapped_key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Move a key to `state` if it is currently in `from`. Returns whether it moved.
+    pub async fn set_key_state(&self, key_id: &str, from: &str, state: &str) -> Result<bool, SecurityError> {
+        let updated = sqlx::query(
+            "UPDATE crypto_keys SET state = $3, state_changed_at = NOW() WHERE key_id = $1 AND state = $2",
+        )
+        .bind(key_id)
+        .bind(from)
+        .bind(state)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(updated == 1)
+    }
+
+    /// Drop a retired key's wrapped material once it is in the cold tier,
+    /// leaving the row so the id is never reused. Returns whether it moved.
+    pub async fn archive_key(&self, key_id: &str) -> Result<bool, SecurityError> {
+        let updated = sqlx::query(
+            "UPDATE crypto_keys SET state = 'archived', wrapped_key = '', state_changed_at = NOW() \
+             WHERE key_id = $1 AND state = 'retired'",
+        )
+        .bind(key_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(updated == 1)
+    }
+
+    /// Put an archived key's wrapped material back, in `state`. Returns
+    /// whether it was still archived.
+    pub async fn restore_key(&self, key_id: &str, provider: &str, wrapped_key: &str, state: &str) -> Result<bool, SecurityError> {
+        let updated = sqlx::query(
+            "UPDATE crypto_keys SET provider = $2, wrapped_key = $3, state = $4, state_changed_at = NOW() \
+             WHERE key_id = $1 AND state = 'archived'",
+        )
+        .bind(key_id)
+        .bind(provider)
+        .bind(wrapped_key)
+        .bind(state)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(updated == 1)
+    }
+
+    /// Make every active key but `key_id` decrypt-only.
+    pub async fn demote_active_keys(&self, key_id: &str) -> Result<u64, SecurityError> {
+        let demoted = sqlx::query(
+            "UPDATE crypto_keys SET state = 'decrypt_only', state_changed_at = NOW() \
+             WHERE state = 'active' AND key_id <> $1",
+        )
+        .bind(key_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(demoted)
+    }
+
+    /// Add usage counted in memory to the per-day totals.
+    pub async fn record_key_usage(&self, usage: &[KeyUsage]) -> Result<(), SecurityError> {
+        let mut tx = self.pool.begin().await?;
+        for row in usage {
+            sqlx::query(
+                "INSERT INTO crypto_key_usage (key_id, day, operation, count, first_at, last_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6) \
+                 ON CONFLICT (key_id, day, operation) DO UPDATE SET \
+                 count = crypto_key_usage.count + EXCLUDED.count, \
+                 first_at = LEAST(crypto_key_usage.first_at, EXCLUDED.first_at), \
+                 last_at = GREATEST(crypto_key_usage.last_at, EXCLUDED.last_at)",
+            )
+            .bind(&row.key_id)
+            .bind(row.day)
+            .bind(&row.operation)
+            .bind(row.count)
+            .bind(row.first_at)
+            .bind(row.last_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn key_usage(&self, key_id: &str) -> Result<Vec<KeyUsage>, SecurityError> {
+        let usage = sqlx::query_as::<_, KeyUsage>(
+            "SELECT key_id, day, operation, count, first_at, last_at FROM crypto_key_usage \
+             WHERE key_id = $1 ORDER BY day, operation",
+        )
+        .bind(key_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(usage)
+    }
+}
+
+fn database_reachable(state: &crate::AppState) -> CheckFuture<'_> {
+    Box::pin(async move {
+        if state.storage.is_ready().await {
+            Ok(())
+        } else {
+            Err("PostgreSQL is unreachable".to_string())
+        }
+    })
+}
+
+/// Non-critical: caches and the audit buffer keep the service answering.
+pub fn register_health_checks(registry: &mut HealthRegistry) {
+    registry.register("storage", Criticality::NonCritical, database_reachable);
+}
@@ -0,0 +1,495 @@
+/*!
+Submissions Module
+Deadline-enforced sealing of bid packages, with signed receipts either way
+
+A call for bids is opened with its deadline at `POST /submissions/calls`
+(needs `SUBMISSION_MANAGE_SCOPE`). Bidders then submit their package's
+SHA-256 at `POST /submissions/calls/{id}/submissions` (needs
+`SUBMISSION_SUBMIT_SCOPE`); the package itself never reaches this service.
+
+Every submission gets a signed receipt, whichever side of the deadline it
+lands on: `accepted` (201) before it, `refused` (403) at or after it. The
+receipt signs, with the `SUBMISSION_ALGORITHM` key,
+
+```text
+cotai-submission:<call id>:<sequence>:<outcome>:<package sha256>:<submitted by>:
+<received_at>:<deadline>:<previous receipt sha256>
+```
+
+so a bidder can prove their package was in on time, and the agency that a
+late one was not. Sequence numbers run per call across both outcomes and
+each receipt names its predecessor's hash, so the call's receipts form a
+chain in which a dropped or reordered receipt shows.
+
+`received_at` is the database's clock, which every replica shares, never
+the replica's own. A replica whose clock is more than
+`SUBMISSION_MAX_CLOCK_SKEW_MS` off the database's refuses to issue any
+receipt (503) rather than judge a deadline on a time it cannot trust.
+
+`POST /submissions/verify` checks a receipt's signature and that the
+ledger holds it unchanged; `GET /submissions/calls/{id}/receipts` lists a
+call's receipts in sequence.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::sync::Arc;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::audit::receipts::{self, RECEIPT_HEADER};
+use crate::audit::NewAuditEvent;
+use crate::auth::tokens::authorize_scope;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, SubmissionConfig};
+use crate::crypto::{CryptoService, VerifyRequest as SignatureCheck};
+use crate::errors::SecurityError;
+use crate::notary;
+use crate::signing::{Algorithm, SUBMISSION_RECEIPT_DOMAIN};
+use crate::storage::Storage;
+use crate::AppState;
+
+pub(crate) const CALL_COLUMNS: &str = "id, tenant_id, reference, deadline, created_by, created_at, receipts";
+
+pub(crate) const RECEIPT_COLUMNS: &str = "id, call_id, tenant_id, sequence, outcome, package_sha256, label, submitted_by, \
+    received_at, deadline, previous_sha256, receipt_sha256, algorithm, key_id, signature";
+
+/// What the chain's first receipt names as its predecessor.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+fn sha256(data: &[u8]) -> String {
+    hex::encode(digest(&SHA256, data))
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Call {
+    pub id: Uuid,
+    pub tenant_id: Option<String>,
+    /// What bids are called for, e.g. `tender:7`.
+    pub reference: String,
+    pub deadline: DateTime<Utc>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    /// Receipts issued so far, which is also the last sequence number.
+    pub receipts: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Receipt {
+    pub id: Uuid,
+    pub call_id: Uuid,
+    pub tenant_id: Option<String>,
+    pub sequence: i64,
+    /// `accepted` or `refused`.
+    pub outcome: String,
+    pub package_sha256: String,
+    pub label: Option<String>,
+    pub submitted_by: String,
+    pub received_at: DateTime<Utc>,
+    pub deadline: DateTime<Utc>,
+    pub previous_sha256: String,
+    pub receipt_sha256: String,
+    pub algorithm: String,
+    pub key_id: String,
+    pub signature: String,
+}
+
+impl Receipt {
+    /// What the signature covers.
+    pub fn message(&self) -> String {
+        format!(
+            "cotai-submission:{}:{}:{}:{}:{}:{}:{}:{}",
+            self.call_id,
+            self.sequence,
+            self.outcome,
+            self.package_sha256,
+            self.submitted_by,
+            self.received_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+            self.deadline.to_rfc3339_opts(SecondsFormat::Micros, true),
+            self.previous_sha256
+        )
+    }
+
+    pub fn accepted(&self) -> bool {
+        self.outcome == "accepted"
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCallRequest {
+    pub reference: String,
+    pub deadline: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubmitRequest {
+    pub package_sha256: String,
+    /// What the package is, e.g. the lot it bids for.
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReceiptVerification {
+    pub signature_valid: bool,
+    /// The ledger holds this receipt, unchanged.
+    pub recorded: bool,
+    pub valid: bool,
+}
+
+pub struct SubmissionService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: SubmissionConfig,
+}
+
+impl SubmissionService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        info!("Submission service initialized successfully");
+        Ok(Self {
+            storage,
+            clock,
+            config: config.submissions.clone(),
+        })
+    }
+
+    fn algorithm(&self) -> Result<Algorithm, SecurityError> {
+        Algorithm::parse(&self.config.algorithm)
+            .ok_or_else(|| SecurityError::ConfigError("Invalid SUBMISSION_ALGORITHM".to_string()))
+    }
+
+    /// The database's time, provided this replica's clock agrees with it.
+    async fn trusted_now<'c>(&self, executor: impl sqlx::PgExecutor<'c>) -> Result<DateTime<Utc>, SecurityError> {
+        let (now,): (DateTime<Utc>,) = sqlx::query_as("SELECT clock_timestamp()").fetch_one(executor).await?;
+        let skew = (self.clock.now() - now).num_milliseconds().unsigned_abs();
+        if self.config.max_clock_skew_ms > 0 && skew > self.config.max_clock_skew_ms {
+            error!("Clock is {}ms off the database's; refusing to judge submission deadlines", skew);
+            return Err(SecurityError::ConfigError(
+                "The service clock disagrees with the time source; try again shortly".to_string(),
+            ));
+        }
+        Ok(now)
+    }
+
+    pub async fn create_call(&self, principal: &Principal, request: &CreateCallRequest) -> Result<Call, SecurityError> {
+        let reference = request.reference.trim();
+        if reference.is_empty() || reference.len() > 200 {
+            return Err(SecurityError::ValidationError("reference must be 1-200 characters".to_string()));
+        }
+        let now = self.trusted_now(self.storage.pool()).await?;
+        if request.deadline <= now {
+            return Err(SecurityError::ValidationError("deadline must be in the future".to_string()));
+        }
+        let call = sqlx::query_as::<_, Call>(&format!(
+            "INSERT INTO submission_calls (id, tenant_id, reference, deadline, created_by, created_at, receipts) \
+             VALUES ($1, $2, $3, $4, $5, $6, 0) RETURNING {}",
+            CALL_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(&principal.tenant_id)
+        .bind(reference)
+        .bind(request.deadline)
+        .bind(&principal.subject)
+        .bind(now)
+        .fetch_one(self.storage.pool())
+        .await?;
+        info!("Submission call {} for {} opened until {}", call.id, call.reference, call.deadline);
+        Ok(call)
+    }
+
+    pub async fn call(&self, tenant_id: Option<&str>, id: Uuid) -> Result<Call, SecurityError> {
+        sqlx::query_as::<_, Call>(&format!("SELECT {} FROM submission_calls WHERE id = $1", CALL_COLUMNS))
+            .bind(id)
+            .fetch_optional(self.storage.pool())
+            .await?
+            .filter(|call| tenant_id.is_none() || call.tenant_id.as_deref() == tenant_id)
+            .ok_or_else(|| SecurityError::NotFound(format!("No submission call {}", id)))
+    }
+
+    /// Judge a submission against the call's deadline and issue its
+    /// receipt, accepted or refused.
+    pub async fn submit(
+        &self,
+        crypto: &CryptoService,
+        principal: &Principal,
+        call_id: Uuid,
+        request: &SubmitRequest,
+    ) -> Result<Receipt, SecurityError> {
+        let package_sha256 = notary::normalize_sha256(&request.package_sha256)?;
+        let algorithm = self.algorithm()?;
+
+        let mut tx = self.storage.begin().await?;
+        // Locking the call serializes its sequence numbers
+        let call = sqlx::query_as::<_, Call>(&format!(
+            "SELECT {} FROM submission_calls WHERE id = $1 FOR UPDATE",
+            CALL_COLUMNS
+        ))
+        .bind(call_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .filter(|call| principal.tenant_id.is_none() || call.tenant_id == principal.tenant_id)
+        .ok_or_else(|| SecurityError::NotFound(format!("No submission call {}", call_id)))?;
+        // Read after the lock, so a receipt never predates the one before it
+        let received_at = self.trusted_now(&mut *tx).await?;
+        let previous: Option<(String,)> = sqlx::query_as(
+            "SELECT receipt_sha256 FROM submission_receipts WHERE call_id = $1 AND sequence = $2",
+        )
+        .bind(call_id)
+        .bind(call.receipts)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let mut receipt = Receipt {
+            id: Uuid::new_v4(),
+            call_id,
+            tenant_id: call.tenant_id.clone(),
+            sequence: call.receipts + 1,
+            outcome: if received_at < call.deadline { "accepted" } else { "refused" }.to_string(),
+            package_sha256,
+            label: request.label.clone(),
+            submitted_by: principal.subject.clone(),
+            received_at,
+            deadline: call.deadline,
+            previous_sha256: previous.map(|(hash,)| hash).unwrap_or_else(|| GENESIS.to_string()),
+            receipt_sha256: String::new(),
+            algorithm: algorithm.as_str().to_string(),
+            key_id: String::new(),
+            signature: String::new(),
+        };
+        let message = receipt.message();
+        receipt.receipt_sha256 = sha256(message.as_bytes());
+        let signed = crypto.generate_signature(SUBMISSION_RECEIPT_DOMAIN, &message, None, algorithm)?;
+        receipt.key_id = signed.key_id;
+        receipt.signature = signed.signature;
+
+        sqlx::query(&format!(
+            "INSERT INTO submission_receipts ({}) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)",
+            RECEIPT_COLUMNS
+        ))
+        .bind(receipt.id)
+        .bind(receipt.call_id)
+        .bind(&receipt.tenant_id)
+        .bind(receipt.sequence)
+        .bind(&receipt.outcome)
+        .bind(&receipt.package_sha256)
+        .bind(&receipt.label)
+        .bind(&receipt.submitted_by)
+        .bind(receipt.received_at)
+        .bind(receipt.deadline)
+        .bind(&receipt.previous_sha256)
+        .bind(&receipt.receipt_sha256)
+        .bind(&receipt.algorithm)
+        .bind(&receipt.key_id)
+        .bind(&receipt.signature)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE submission_calls SET receipts = $2 WHERE id = $1")
+            .bind(call_id)
+            .bind(receipt.sequence)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        info!("Submission {} to call {} {} as #{}", receipt.id, call_id, receipt.outcome, receipt.sequence);
+        Ok(receipt)
+    }
+
+    pub async fn receipts(&self, call_id: Uuid) -> Result<Vec<Receipt>, SecurityError> {
+        Ok(sqlx::query_as::<_, Receipt>(&format!(
+            "SELECT {} FROM submission_receipts WHERE call_id = $1 ORDER BY sequence",
+            RECEIPT_COLUMNS
+        ))
+        .bind(call_id)
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    /// Check a receipt's signature, and that the ledger holds it unchanged.
+    pub async fn verify(&self, crypto: &CryptoService, receipt: &Receipt) -> Result<ReceiptVerification, SecurityError> {
+        let message = receipt.message();
+        let signature_valid = match Algorithm::parse(&receipt.algorithm) {
+            Some(algorithm) if sha256(message.as_bytes()) == receipt.receipt_sha256 => crypto.verify(SUBMISSION_RECEIPT_DOMAIN, &SignatureCheck {
+                data: message,
+                signature: receipt.signature.clone(),
+                algorithm: Some(algorithm),
+                key_id: Some(receipt.key_id.clone()),
+                timestamp: None,
+            })?,
+            _ => false,
+        };
+        let stored = sqlx::query_as::<_, Receipt>(&format!(
+            "SELECT {} FROM submission_receipts WHERE id = $1",
+            RECEIPT_COLUMNS
+        ))
+        .bind(receipt.id)
+        .fetch_optional(self.storage.pool())
+        .await?;
+        let recorded = stored.is_some_and(|stored| {
+            stored.message() == receipt.message()
+                && stored.signature == receipt.signature
+                && stored.key_id == receipt.key_id
+        });
+        Ok(ReceiptVerification { signature_valid, recorded, valid: signature_valid && recorded })
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ConfigError(msg) => HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Submission operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Submission operation failed"
+            }))
+        }
+    }
+}
+
+pub async fn create_call_handler(
+    req: HttpRequest,
+    request: web::Json<CreateCallRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.submissions.manage_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let call = match state.submissions.create_call(&principal, &request).await {
+        Ok(call) => call,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    let receipt = receipts::record_or_warn(&state, NewAuditEvent {
+        tenant_id: call.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(&req),
+        action: "submission.call.create".to_string(),
+        resource: format!("submission_call:{}", call.id),
+        outcome: "success".to_string(),
+        payload: serde_json::json!({
+            "reference": call.reference,
+            "deadline": call.deadline
+        }),
+    }).await;
+
+    let mut response = HttpResponse::Created();
+    if let Some(receipt) = receipt {
+        response.insert_header((RECEIPT_HEADER, receipt));
+    }
+    Ok(response.json(call))
+}
+
+pub async fn get_call_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.submissions.submit_scope)
+        .or_else(|_| authorize_scope(&state, &req, &state.config.submissions.manage_scope))
+    {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.submissions.call(principal.tenant_id.as_deref(), path.into_inner()).await {
+        Ok(call) => Ok(HttpResponse::Ok().json(call)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn submit_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<SubmitRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.submissions.submit_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let receipt = match state.submissions.submit(&state.crypto_service, &principal, path.into_inner(), &request).await {
+        Ok(receipt) => receipt,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    let audit_receipt = receipts::record_or_warn(&state, NewAuditEvent {
+        tenant_id: receipt.tenant_id.clone(),
+        actor: principal.subject.clone(),
+        actor_ip: client_ip(&req),
+        action: "submission.submit".to_string(),
+        resource: format!("submission_receipt:{}", receipt.id),
+        outcome: if receipt.accepted() { "success" } else { "failure" }.to_string(),
+        payload: serde_json::json!({
+            "call_id": receipt.call_id,
+            "sequence": receipt.sequence,
+            "outcome": receipt.outcome,
+            "package_sha256": receipt.package_sha256,
+            "received_at": receipt.received_at,
+            "deadline": receipt.deadline
+        }),
+    }).await;
+
+    let mut response = if receipt.accepted() { HttpResponse::Created() } else { HttpResponse::Forbidden() };
+    if let Some(audit_receipt) = audit_receipt {
+        response.insert_header((RECEIPT_HEADER, audit_receipt));
+    }
+    Ok(response.json(receipt))
+}
+
+pub async fn receipts_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let principal = match authorize_scope(&state, &req, &state.config.submissions.manage_scope) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let call = match state.submissions.call(principal.tenant_id.as_deref(), path.into_inner()).await {
+        Ok(call) => call,
+        Err(e) => return Ok(error_response(e)),
+    };
+    match state.submissions.receipts(call.id).await {
+        Ok(receipts) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "call": call,
+            "receipts": receipts
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+/// Anyone holding a receipt may check it, as with notary proofs.
+pub async fn verify_handler(request: web::Json<Receipt>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    match state.submissions.verify(&state.crypto_service, &request).await {
+        Ok(verification) => Ok(HttpResponse::Ok().json(verification)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/submissions")
+            .route("/calls", web::post().to(create_call_handler))
+            .route("/calls/{id}", web::get().to(get_call_handler))
+            .route("/calls/{id}/submissions", web::post().to(submit_handler))
+            .route("/calls/{id}/receipts", web::get().to(receipts_handler))
+            .route("/verify", web::post().to(verify_handler)),
+    );
+}
@@ -0,0 +1,536 @@
+/*!
+Tenant Settings Module
+Per-tenant overrides of selected settings, resolved per request
+
+Tenants may override the rate limit, MFA requirement, access token TTL,
+allowed origins, default locale (see `i18n`), time zone and business hours
+(see `timezone`), and whether decryptions must declare a purpose of use and
+which purposes they may declare (see `crypto::purpose`). Global config
+supplies the defaults and the bounds: overrides outside them are rejected
+on write, and clamped again on resolve in case the bounds were tightened
+after the override was stored. MFA and purpose requirements are floors
+only; a tenant can require them but never opt out of a global requirement.
+Likewise a tenant's `algorithms` can only deprecate or forbid algorithms
+for its own calls (see `crypto::algorithms`).
+
+While `DOMAIN_VERIFICATION_REQUIRED` is on, allowed origins must be in a
+domain the tenant has verified (see `domains`): others are rejected on
+write, and dropped on resolve once their domain lapses.
+
+Resolved overrides are cached per instance for `TENANT_SETTINGS_CACHE_TTL_SECS`;
+writes invalidate the local entry, other instances pick them up on expiry.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::FromRow;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use tracing::{error, info, warn};
+
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, Principal};
+use crate::changes::{self, NewChange};
+use crate::clock::Clock;
+use crate::config::{Config, TenantSettingsConfig};
+use crate::crypto::algorithms::{self, AlgorithmStatus};
+use crate::domains;
+use crate::errors::SecurityError;
+use crate::storage::Storage;
+use crate::timezone::{self, BusinessHours};
+
+const SELECT_COLUMNS: &str = "tenant_id, overrides, version, updated_by, updated_at";
+
+/// Settings a tenant may override; `None` falls back to the global value.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TenantOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit_rpm: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mfa_required: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_token_ttl_secs: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_origins: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purpose_required: Option<bool>,
+    /// A subset of `TENANT_PURPOSES`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_purposes: Option<Vec<String>>,
+    /// One of `TENANT_LOCALES`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// IANA name, e.g. `America/Manaus`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_zone: Option<String>,
+    /// E.g. `mon-fri 08:00-18:00`, in `time_zone`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub business_hours: Option<String>,
+    /// Algorithms `deprecated` or `forbidden` for the tenant.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub algorithms: Option<BTreeMap<String, AlgorithmStatus>>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct TenantSettings {
+    pub tenant_id: String,
+    pub overrides: Json<TenantOverrides>,
+    pub version: i64,
+    pub updated_by: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// What a request from the tenant actually runs with.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EffectiveSettings {
+    pub tenant_id: Option<String>,
+    pub rate_limit_rpm: u32,
+    pub mfa_required: bool,
+    pub access_token_ttl_secs: i64,
+    pub allowed_origins: Vec<String>,
+    pub purpose_required: bool,
+    pub allowed_purposes: Vec<String>,
+    pub locale: String,
+    pub time_zone: String,
+    pub business_hours: String,
+    pub algorithms: BTreeMap<String, AlgorithmStatus>,
+}
+
+impl EffectiveSettings {
+    /// The tenant's time zone, parsed.
+    pub fn zone(&self) -> Tz {
+        timezone::parse_zone(&self.time_zone).unwrap_or(Tz::America__Sao_Paulo)
+    }
+
+    pub fn hours(&self) -> Option<BusinessHours> {
+        self.business_hours.parse().ok()
+    }
+}
+
+fn origin_allowed(bounds: &TenantSettingsConfig, origin: &str) -> bool {
+    origin.starts_with("https://")
+        && bounds.allowed_origin_suffixes.iter().any(|suffix| origin.ends_with(suffix.as_str()))
+}
+
+/// Check overrides against the global bounds, reporting every violation.
+fn validate(bounds: &TenantSettingsConfig, overrides: &TenantOverrides) -> Result<(), SecurityError> {
+    let mut problems = Vec::new();
+
+    if let Some(rpm) = overrides.rate_limit_rpm {
+        if rpm == 0 || rpm > bounds.rate_limit_max_rpm {
+            problems.push(format!("rate_limit_rpm must be 1-{}", bounds.rate_limit_max_rpm));
+        }
+    }
+    if overrides.mfa_required == Some(false) && bounds.mfa_required {
+        problems.push("mfa_required cannot be turned off while it is required globally".to_string());
+    }
+    if let Some(ttl) = overrides.access_token_ttl_secs {
+        if ttl < bounds.access_token_min_ttl_secs || ttl > bounds.access_token_max_ttl_secs {
+            problems.push(format!(
+                "access_token_ttl_secs must be {}-{}",
+                bounds.access_token_min_ttl_secs, bounds.access_token_max_ttl_secs
+            ));
+        }
+    }
+    for origin in overrides.allowed_origins.iter().flatten() {
+        if !origin_allowed(bounds, origin) {
+            problems.push(format!("origin '{}' is not permitted", origin));
+        }
+    }
+    if overrides.purpose_required == Some(false) && bounds.purpose_required {
+        problems.push("purpose_required cannot be turned off while it is required globally".to_string());
+    }
+    if let Some(purposes) = &overrides.allowed_purposes {
+        if purposes.is_empty() {
+            problems.push("allowed_purposes must not be empty".to_string());
+        }
+        for purpose in purposes.iter().filter(|p| !bounds.purposes.contains(p)) {
+            problems.push(format!("purpose '{}' is not in the catalog", purpose));
+        }
+    }
+
+    if let Some(locale) = overrides.locale.as_ref().filter(|l| !bounds.locales.contains(l)) {
+        problems.push(format!("locale '{}' is not supported", locale));
+    }
+
+    if let Some(zone) = &overrides.time_zone {
+        if timezone::parse_zone(zone).is_err() {
+            problems.push(format!("time_zone '{}' is not an IANA time zone", zone));
+        }
+    }
+    if let Some(hours) = &overrides.business_hours {
+        if let Err(e) = hours.parse::<BusinessHours>() {
+            problems.push(e);
+        }
+    }
+
+    for (algorithm, status) in overrides.algorithms.iter().flatten() {
+        if !algorithms::catalogued(algorithm) {
+            problems.push(format!("algorithm '{}' is not in the catalog", algorithm));
+        } else if *status < AlgorithmStatus::Deprecated {
+            problems.push(format!("algorithm '{}' can only be deprecated or forbidden", algorithm));
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(SecurityError::ValidationError(problems.join("; ")));
+    }
+    Ok(())
+}
+
+/// Apply overrides on top of the global values, clamped to the bounds.
+pub fn resolve(bounds: &TenantSettingsConfig, tenant_id: Option<&str>, overrides: &TenantOverrides) -> EffectiveSettings {
+    EffectiveSettings {
+        tenant_id: tenant_id.map(str::to_string),
+        rate_limit_rpm: overrides
+            .rate_limit_rpm
+            .unwrap_or(bounds.rate_limit_rpm)
+            .clamp(1, bounds.rate_limit_max_rpm.max(1)),
+        mfa_required: bounds.mfa_required || overrides.mfa_required.unwrap_or(false),
+        access_token_ttl_secs: overrides
+            .access_token_ttl_secs
+            .unwrap_or(bounds.access_token_ttl_secs)
+            .clamp(bounds.access_token_min_ttl_secs, bounds.access_token_max_ttl_secs.max(bounds.access_token_min_ttl_secs)),
+        allowed_origins: overrides
+            .allowed_origins
+            .iter()
+            .flatten()
+            .filter(|origin| origin_allowed(bounds, origin))
+            .cloned()
+            .collect(),
+        purpose_required: bounds.purpose_required || overrides.purpose_required.unwrap_or(false),
+        allowed_purposes: match &overrides.allowed_purposes {
+            Some(allowed) if allowed.iter().any(|p| bounds.purposes.contains(p)) => bounds
+                .purposes
+                .iter()
+                .filter(|purpose| allowed.contains(purpose))
+                .cloned()
+                .collect(),
+            _ => bounds.purposes.clone(),
+        },
+        locale: overrides
+            .locale
+            .clone()
+            .filter(|locale| bounds.locales.contains(locale))
+            .unwrap_or_else(|| bounds.locale.clone()),
+        time_zone: overrides
+            .time_zone
+            .clone()
+            .filter(|zone| timezone::parse_zone(zone).is_ok())
+            .unwrap_or_else(|| bounds.time_zone.clone()),
+        business_hours: overrides
+            .business_hours
+            .clone()
+            .filter(|hours| hours.parse::<BusinessHours>().is_ok())
+            .unwrap_or_else(|| bounds.business_hours.clone()),
+        algorithms: overrides
+            .algorithms
+            .iter()
+            .flatten()
+            .filter(|(algorithm, status)| algorithms::catalogued(algorithm) && **status >= AlgorithmStatus::Deprecated)
+            .map(|(algorithm, status)| (algorithm.clone(), *status))
+            .collect(),
+    }
+}
+
+fn in_verified_domain(verified: &[String], origin: &str) -> bool {
+    domains::host_of(origin).is_some_and(|host| verified.iter().any(|domain| domains::covers(domain, &host)))
+}
+
+pub struct TenantSettingsService {
+    storage: Storage,
+    bounds: TenantSettingsConfig,
+    clock: Arc<dyn Clock>,
+    /// Whether origins must be in a verified domain.
+    domains_required: bool,
+    cache: RwLock<HashMap<String, (DateTime<Utc>, TenantOverrides)>>,
+}
+
+impl TenantSettingsService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+
+        info!("Tenant settings initialized successfully");
+        Ok(Self {
+            storage,
+            bounds: config.tenant_settings.clone(),
+            clock,
+            domains_required: config.domain_verification.required,
+            cache: RwLock::new(HashMap::new()),
+        })
+    }
+
+    pub async fn get(&self, tenant_id: &str) -> Result<TenantSettings, SecurityError> {
+        sqlx::query_as::<_, TenantSettings>(&format!(
+            "SELECT {} FROM tenant_settings WHERE tenant_id = $1",
+            SELECT_COLUMNS
+        ))
+        .bind(tenant_id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("No settings stored for tenant '{}'", tenant_id)))
+    }
+
+    /// Effective settings for a request. Requests without a tenant, and
+    /// tenants whose overrides cannot be loaded, get the global values.
+    pub async fn effective(&self, tenant_id: Option<&str>) -> EffectiveSettings {
+        let Some(tenant_id) = tenant_id else {
+            return resolve(&self.bounds, None, &TenantOverrides::default());
+        };
+        let overrides = match self.overrides(tenant_id).await {
+            Ok(overrides) => overrides,
+            Err(e) => {
+                warn!("Using global settings for tenant {}: {:?}", tenant_id, e);
+                TenantOverrides::default()
+            }
+        };
+        resolve(&self.bounds, Some(tenant_id), &overrides)
+    }
+
+    async fn overrides(&self, tenant_id: &str) -> Result<TenantOverrides, SecurityError> {
+        let now = self.clock.now();
+        if let Some((expires_at, overrides)) = self.cache.read().unwrap_or_else(|e| e.into_inner()).get(tenant_id) {
+            if *expires_at > now {
+                return Ok(overrides.clone());
+            }
+        }
+
+        let mut overrides = match self.get(tenant_id).await {
+            Ok(settings) => settings.overrides.0,
+            Err(SecurityError::NotFound(_)) => TenantOverrides::default(),
+            Err(e) => return Err(e),
+        };
+        if self.domains_required {
+            if let Some(origins) = overrides.allowed_origins.as_mut() {
+                let verified = domains::verified_domains(&self.storage, tenant_id).await?;
+                origins.retain(|origin| in_verified_domain(&verified, origin));
+            }
+        }
+        let expires_at = now + Duration::seconds(self.bounds.cache_ttl_secs as i64);
+        self.cache
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(tenant_id.to_string(), (expires_at, overrides.clone()));
+        Ok(overrides)
+    }
+
+    fn invalidate(&self, tenant_id: &str) {
+        self.cache.write().unwrap_or_else(|e| e.into_inner()).remove(tenant_id);
+    }
+
+    pub async fn put(
+        &self,
+        actor: &Principal,
+        tenant_id: &str,
+        overrides: TenantOverrides,
+    ) -> Result<TenantSettings, SecurityError> {
+        validate(&self.bounds, &overrides)?;
+        if self.domains_required && overrides.allowed_origins.as_ref().is_some_and(|origins| !origins.is_empty()) {
+            let verified = domains::verified_domains(&self.storage, tenant_id).await?;
+            let unverified: Vec<&str> = overrides
+                .allowed_origins
+                .iter()
+                .flatten()
+                .filter(|origin| !in_verified_domain(&verified, origin))
+                .map(String::as_str)
+                .collect();
+            if !unverified.is_empty() {
+                return Err(SecurityError::ValidationError(format!(
+                    "allowed_origins must be in a domain the tenant has verified: {}",
+                    unverified.join(", ")
+                )));
+            }
+        }
+
+        let mut tx = self.storage.begin().await?;
+        let current = sqlx::query_as::<_, TenantSettings>(&format!(
+            "SELECT {} FROM tenant_settings WHERE tenant_id = $1 FOR UPDATE",
+            SELECT_COLUMNS
+        ))
+        .bind(tenant_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let settings = sqlx::query_as::<_, TenantSettings>(&format!(
+            "INSERT INTO tenant_settings (tenant_id, overrides, updated_by) VALUES ($1, $2, $3) \
+             ON CONFLICT (tenant_id) DO UPDATE SET overrides = $2, updated_by = $3, updated_at = NOW(), \
+             version = tenant_settings.version + 1 \
+             RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(Json(&overrides))
+        .bind(&actor.subject)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        changes::record(&mut tx, NewChange {
+            resource_type: "tenant_settings",
+            resource_id: tenant_id.to_string(),
+            version: Some(settings.version),
+            action: if current.is_some() { "update" } else { "create" },
+            author: &actor.subject,
+            tenant_id: Some(tenant_id.to_string()),
+            before: current.as_ref().and_then(|s| serde_json::to_value(&s.overrides.0).ok()),
+            after: serde_json::to_value(&settings.overrides.0).ok(),
+        }).await?;
+        tx.commit().await?;
+
+        self.invalidate(tenant_id);
+        Ok(settings)
+    }
+
+    /// Drop every override, returning the tenant to the global values.
+    pub async fn delete(&self, actor: &Principal, tenant_id: &str) -> Result<(), SecurityError> {
+        let mut tx = self.storage.begin().await?;
+        let deleted = sqlx::query_as::<_, TenantSettings>(&format!(
+            "DELETE FROM tenant_settings WHERE tenant_id = $1 RETURNING {}",
+            SELECT_COLUMNS
+        ))
+        .bind(tenant_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("No settings stored for tenant '{}'", tenant_id)))?;
+
+        changes::record(&mut tx, NewChange {
+            resource_type: "tenant_settings",
+            resource_id: tenant_id.to_string(),
+            version: Some(deleted.version),
+            action: "delete",
+            author: &actor.subject,
+            tenant_id: Some(tenant_id.to_string()),
+            before: serde_json::to_value(&deleted.overrides.0).ok(),
+            after: None,
+        }).await?;
+        tx.commit().await?;
+
+        self.invalidate(tenant_id);
+        Ok(())
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Tenant settings operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Tenant settings operation failed"
+            }))
+        }
+    }
+}
+
+async fn audit_settings_change(state: &crate::AppState, actor: &Principal, action: &str, tenant_id: &str, detail: serde_json::Value) {
+    let recorded = state.audit_service.record(NewAuditEvent {
+        tenant_id: Some(tenant_id.to_string()),
+        actor: actor.subject.clone(),
+        actor_ip: None,
+        action: action.to_string(),
+        resource: format!("tenant_settings:{}", tenant_id),
+        outcome: "success".to_string(),
+        payload: detail,
+    }).await;
+    if let Err(e) = recorded {
+        warn!("Failed to audit settings change for tenant {}: {:?}", tenant_id, e);
+    }
+}
+
+/// The caller's own effective settings.
+pub async fn effective_settings_handler(
+    req: HttpRequest,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authenticate(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let settings = state.tenant_settings.effective(principal.tenant_id.as_deref()).await;
+    Ok(HttpResponse::Ok().json(settings))
+}
+
+pub async fn get_settings_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    if let Err(e) = state.auth_service.authorize_admin(&req) {
+        return Ok(auth_error_response(&e));
+    }
+
+    let tenant_id = path.into_inner();
+    let stored = match state.tenant_settings.get(&tenant_id).await {
+        Ok(settings) => Some(settings),
+        Err(SecurityError::NotFound(_)) => None,
+        Err(e) => return Ok(error_response(e)),
+    };
+    let effective = state.tenant_settings.effective(Some(&tenant_id)).await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "stored": stored,
+        "effective": effective
+    })))
+}
+
+pub async fn put_settings_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    overrides: web::Json<TenantOverrides>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let tenant_id = path.into_inner();
+    let overrides = overrides.into_inner();
+    match state.tenant_settings.put(&principal, &tenant_id, overrides.clone()).await {
+        Ok(settings) => {
+            audit_settings_change(&state, &principal, "tenant_settings.update", &tenant_id, serde_json::to_value(&overrides).unwrap_or_default()).await;
+            Ok(HttpResponse::Ok().json(settings))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn delete_settings_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<crate::AppState>,
+) -> Result<HttpResponse> {
+    let principal = match state.auth_service.authorize_admin(&req) {
+        Ok(principal) => principal,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    let tenant_id = path.into_inner();
+    match state.tenant_settings.delete(&principal, &tenant_id).await {
+        Ok(()) => {
+            audit_settings_change(&state, &principal, "tenant_settings.clear", &tenant_id, serde_json::json!({})).await;
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/tenant/settings", web::get().to(effective_settings_handler))
+        .service(
+            web::scope("/admin/tenants/{tenant_id}/settings")
+                .route("", web::get().to(get_settings_handler))
+                .route("", web::put().to(put_settings_handler))
+                .route("", web::delete().to(delete_settings_handler))
+        );
+}
@@ -0,0 +1,141 @@
+/*!
+Time Zones
+Local calendar boundaries and business hours, safe across DST changes
+
+Tenants live by their own clock: each has a `time_zone` setting (see
+`tenant_settings`, `TENANT_TIME_ZONE` by default) and `business_hours`,
+written `mon-fri 08:00-18:00`. Days, weeks (from Monday) and months start
+at local midnight, and scheduled jobs run at a local wall time.
+
+Clocks changing make some wall times happen twice and others never. A
+repeated time is taken at its first occurrence; a skipped one, such as the
+midnight Brazil used to skip when summer time began, becomes the first
+instant after the gap. Days are therefore not always 24 hours long, and
+nothing here assumes they are.
+*/
+
+use chrono::{DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::errors::SecurityError;
+
+pub fn parse_zone(name: &str) -> Result<Tz, SecurityError> {
+    name.parse::<Tz>()
+        .map_err(|_| SecurityError::ValidationError(format!("Unknown time zone '{}'", name)))
+}
+
+/// When the local clock in `tz` reads `at` on `date`; see the module docs
+/// for times that happen twice or never.
+pub fn at_local(tz: Tz, date: NaiveDate, at: NaiveTime) -> DateTime<Utc> {
+    let local = date.and_time(at);
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(t) => t.with_timezone(&Utc),
+        LocalResult::Ambiguous(earliest, _) => earliest.with_timezone(&Utc),
+        // Gaps are whole minutes and at most a few hours long
+        LocalResult::None => (1..=24 * 60)
+            .find_map(|m| tz.from_local_datetime(&(local + Duration::minutes(m))).earliest())
+            .map(|t| t.with_timezone(&Utc))
+            .unwrap_or_else(|| Utc.from_utc_datetime(&local)),
+    }
+}
+
+/// The start of `date` in `tz`.
+pub fn day_start(tz: Tz, date: NaiveDate) -> DateTime<Utc> {
+    at_local(tz, date, NaiveTime::MIN)
+}
+
+/// The local date of `instant` in `tz`.
+pub fn local_date(tz: Tz, instant: DateTime<Utc>) -> NaiveDate {
+    instant.with_timezone(&tz).date_naive()
+}
+
+/// The first instant after `after` at which the local clock in `tz` reads
+/// `at`.
+pub fn next_at(tz: Tz, after: DateTime<Utc>, at: NaiveTime) -> DateTime<Utc> {
+    let today = local_date(tz, after);
+    (0..=2)
+        .map(|days| at_local(tz, today + Duration::days(days), at))
+        .find(|t| *t > after)
+        .unwrap_or(after + Duration::days(1))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+/// `[start, end)` of the local day, week or month holding `date`.
+pub fn period_bounds(tz: Tz, period: Period, date: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+    let (first, next) = match period {
+        Period::Day => (date, date + Duration::days(1)),
+        Period::Week => {
+            let first = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+            (first, first + Duration::days(7))
+        }
+        Period::Month => {
+            let first = date.with_day(1).unwrap_or(date);
+            let next = if date.month() == 12 {
+                NaiveDate::from_ymd_opt(date.year() + 1, 1, 1)
+            } else {
+                NaiveDate::from_ymd_opt(date.year(), date.month() + 1, 1)
+            };
+            (first, next.unwrap_or(first + Duration::days(31)))
+        }
+    };
+    (day_start(tz, first), day_start(tz, next))
+}
+
+/// Weekdays and a local time range, e.g. `mon-fri 08:00-18:00` or
+/// `mon,wed,fri 09:00-12:30`. The range may not cross midnight.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BusinessHours {
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl BusinessHours {
+    pub fn contains(&self, tz: Tz, instant: DateTime<Utc>) -> bool {
+        let local = instant.with_timezone(&tz);
+        let time = local.time();
+        self.days.contains(&local.weekday()) && time >= self.start && time < self.end
+    }
+}
+
+impl FromStr for BusinessHours {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid business hours '{}', expected e.g. 'mon-fri 08:00-18:00'", s);
+        let (days_part, hours_part) = s.trim().split_once(' ').ok_or_else(invalid)?;
+        let weekday = |name: &str| name.trim().parse::<Weekday>().map_err(|_| invalid());
+
+        let mut days = Vec::new();
+        for part in days_part.split(',') {
+            match part.split_once('-') {
+                Some((from, to)) => {
+                    let (mut day, last) = (weekday(from)?, weekday(to)?);
+                    days.push(day);
+                    while day != last {
+                        day = day.succ();
+                        days.push(day);
+                    }
+                }
+                None => days.push(weekday(part)?),
+            }
+        }
+
+        let (start, end) = hours_part.trim().split_once('-').ok_or_else(invalid)?;
+        let time = |text: &str| NaiveTime::parse_from_str(text.trim(), "%H:%M").map_err(|_| invalid());
+        let (start, end) = (time(start)?, time(end)?);
+        if start >= end {
+            return Err(format!("Business hours '{}' must end after they start", s));
+        }
+        Ok(Self { days, start, end })
+    }
+}
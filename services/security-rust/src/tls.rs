@@ -0,0 +1,198 @@
+/*!
+TLS Module
+Native TLS on the public listener, client certificates and certificate reload
+
+Setting `TLS_CERT_PATH` and `TLS_KEY_PATH` (or `TLS_CERT_PEM` and
+`TLS_KEY_PEM`) makes the public listener speak TLS 1.2 and 1.3 only, with
+HTTP/2 offered through ALPN. Certificate files are re-read every
+`TLS_RELOAD_INTERVAL_SECS` and swapped in for new handshakes when their
+contents change, so renewals by cert-manager or an ACME client need no
+restart; established connections keep the certificate they started with.
+A file that fails to parse is logged and the current certificate stays.
+
+`TLS_CLIENT_CA_PATH` turns on mutual TLS. Clients may present a certificate
+chaining to one of those CAs, and one that does not fails the handshake;
+clients presenting none still connect, and the `client_cert` pipeline stage
+rejects them under `/api/v1`, so probes and health checks outside it keep
+working. The CA bundle is read at startup only.
+*/
+
+use actix_web::dev::{Extensions, ServiceRequest};
+use actix_web::rt::net::TcpStream;
+use actix_web::HttpResponse;
+use actix_tls::accept::rustls_0_21::TlsStream;
+use ring::digest::{digest, SHA256};
+use rustls::server::{AllowAnyAnonymousOrAuthenticatedClient, ClientHello, ResolvesServerCert};
+use rustls::sign::{self, CertifiedKey};
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use rustls_pemfile::Item;
+use std::any::Any;
+use std::fs;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::config::TlsConfig;
+use crate::errors::SecurityError;
+use crate::proxy_protocol::Proxied;
+
+/// A verified client certificate, in the extensions of every request on
+/// the connection that presented it.
+#[derive(Debug, Clone)]
+pub struct ClientCertificate {
+    /// Hex SHA-256 of the leaf certificate's DER.
+    pub fingerprint: String,
+}
+
+/// Hands out the current certificate, which `run_reload` replaces.
+pub struct CertResolver {
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertResolver {
+    fn replace(&self, key: CertifiedKey) {
+        *self.current.write().unwrap() = Arc::new(key);
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+fn fingerprint(der: &[u8]) -> String {
+    hex::encode(digest(&SHA256, der))
+}
+
+fn certified_key(cert_pem: &[u8], key_pem: &[u8]) -> Result<CertifiedKey, SecurityError> {
+    let chain = rustls_pemfile::certs(&mut &cert_pem[..])
+        .map_err(|e| SecurityError::ConfigError(format!("Invalid certificate PEM: {}", e)))?;
+    if chain.is_empty() {
+        return Err(SecurityError::ConfigError("No certificate found in PEM".to_string()));
+    }
+
+    let items = rustls_pemfile::read_all(&mut &key_pem[..])
+        .map_err(|e| SecurityError::ConfigError(format!("Invalid private key PEM: {}", e)))?;
+    let key = items
+        .into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| SecurityError::ConfigError("No private key found in PEM".to_string()))?;
+    let signing_key = sign::any_supported_type(&key)
+        .map_err(|_| SecurityError::ConfigError("Unsupported private key type".to_string()))?;
+
+    Ok(CertifiedKey::new(chain.into_iter().map(Certificate).collect(), signing_key))
+}
+
+/// Certificate and key as configured, from files or inline.
+fn read_pem(config: &TlsConfig) -> Result<(Vec<u8>, Vec<u8>), SecurityError> {
+    let read = |path: &str| {
+        fs::read(path).map_err(|e| SecurityError::ConfigError(format!("Cannot read {}: {}", path, e)))
+    };
+    let cert = match (&config.cert_path, &config.cert_pem) {
+        (Some(path), _) => read(path)?,
+        (None, Some(pem)) => pem.clone().into_bytes(),
+        (None, None) => return Err(SecurityError::ConfigError("No TLS certificate configured".to_string())),
+    };
+    let key = match (&config.key_path, &config.key_pem) {
+        (Some(path), _) => read(path)?,
+        (None, Some(pem)) => pem.clone().into_bytes(),
+        (None, None) => return Err(SecurityError::ConfigError("No TLS private key configured".to_string())),
+    };
+    Ok((cert, key))
+}
+
+fn client_roots(path: &str) -> Result<RootCertStore, SecurityError> {
+    let pem = fs::read(path).map_err(|e| SecurityError::ConfigError(format!("Cannot read {}: {}", path, e)))?;
+    let certs = rustls_pemfile::certs(&mut &pem[..])
+        .map_err(|e| SecurityError::ConfigError(format!("Invalid CA PEM in {}: {}", path, e)))?;
+    let mut roots = RootCertStore::empty();
+    for der in certs {
+        roots
+            .add(&Certificate(der))
+            .map_err(|e| SecurityError::ConfigError(format!("Invalid CA certificate in {}: {}", path, e)))?;
+    }
+    if roots.is_empty() {
+        return Err(SecurityError::ConfigError(format!("No CA certificate found in {}", path)));
+    }
+    Ok(roots)
+}
+
+/// The listener's rustls config and the resolver `run_reload` updates.
+pub fn server_config(config: &TlsConfig) -> Result<(ServerConfig, Arc<CertResolver>), SecurityError> {
+    let (cert, key) = read_pem(config)?;
+    let key = certified_key(&cert, &key)?;
+    info!("TLS certificate {} loaded", fingerprint(&key.cert[0].0));
+    let resolver = Arc::new(CertResolver { current: RwLock::new(Arc::new(key)) });
+
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match &config.client_ca_path {
+        Some(path) => {
+            let roots = client_roots(path)?;
+            info!("Mutual TLS on; {} client CA(s) from {}", roots.len(), path);
+            builder.with_client_cert_verifier(AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed())
+        }
+        None => builder.with_no_client_auth(),
+    };
+    Ok((builder.with_cert_resolver(resolver.clone()), resolver))
+}
+
+/// Swap in the certificate files whenever their contents change.
+pub async fn run_reload(resolver: Arc<CertResolver>, config: TlsConfig) {
+    if config.cert_path.is_none() || config.reload_interval_secs == 0 {
+        return;
+    }
+    let mut loaded = read_pem(&config).ok();
+    let mut interval = tokio::time::interval(Duration::from_secs(config.reload_interval_secs));
+    loop {
+        interval.tick().await;
+        let pem = match read_pem(&config) {
+            Ok(pem) => pem,
+            Err(e) => {
+                warn!("TLS certificate not reloaded: {}", e);
+                continue;
+            }
+        };
+        if loaded.as_ref() == Some(&pem) {
+            continue;
+        }
+        match certified_key(&pem.0, &pem.1) {
+            Ok(key) => {
+                info!("TLS certificate {} reloaded", fingerprint(&key.cert[0].0));
+                resolver.replace(key);
+                loaded = Some(pem);
+            }
+            // Often a renewal caught halfway; retried on the next tick
+            Err(e) => warn!("TLS certificate not reloaded: {}", e),
+        }
+    }
+}
+
+/// `HttpServer::on_connect` hook recording the client certificate, if any.
+pub fn on_connect(connection: &dyn Any, extensions: &mut Extensions) {
+    let session = if let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() {
+        stream.get_ref().1
+    } else if let Some(stream) = connection.downcast_ref::<TlsStream<Proxied>>() {
+        stream.get_ref().1
+    } else {
+        return;
+    };
+    if let Some(leaf) = session.peer_certificates().and_then(|chain| chain.first()) {
+        extensions.insert(ClientCertificate { fingerprint: fingerprint(&leaf.0) });
+    }
+}
+
+/// Pipeline check: with mutual TLS on, requests need a client certificate.
+pub fn rejection(state: &crate::AppState, req: &ServiceRequest) -> Option<HttpResponse> {
+    if state.config.tls.client_ca_path.is_none() || req.conn_data::<ClientCertificate>().is_some() {
+        return None;
+    }
+    state.metrics_service.increment("cotai_client_cert_rejections_total", &[]);
+    Some(HttpResponse::Unauthorized().json(serde_json::json!({
+        "error": "A client certificate is required",
+        "code": "client_certificate_required"
+    })))
+}
@@ -0,0 +1,275 @@
+/*!
+Validation Module
+Field validation rules and PII detection, shared with the gateway and frontend through cotai-validation
+
+Besides single values, callers can validate a set of named fields, or a
+whole payload against a JSON schema, and have every string screened for
+SQL injection, XSS and path traversal. All three return the same report,
+`{ "valid": bool, "errors": [{ "field", "code", "message" }] }`, which
+the other COTAI services pass through to their forms. A failed check is
+still a 200; 400 means the request itself was malformed.
+
+PII detection redacts every finding unless the request gives a `mask`
+expression (see `expr`), in which case only findings it holds for are
+redacted, e.g. `finding.rule != "email" || context.channel == "public"`.
+A finding the expression fails on is redacted anyway.
+
+A field's rule may be `plugin:<name>`, running a validation plugin (see
+`plugins`) in place of a built-in rule.
+
+Messages are in the request's locale (see `i18n`); codes are not.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::errors::SecurityError;
+use crate::expr::{Binding, Environment, Object, Program, Type};
+use crate::i18n;
+use crate::AppState;
+
+pub use cotai_validation::schema::Schema;
+pub use cotai_validation::{injection, pii, validate, FieldError, Rule, RULES};
+
+/// Fields accepted by one `/validate/fields` request.
+const MAX_FIELDS: usize = 500;
+
+static FINDING: Object = Object {
+    name: "finding",
+    open: false,
+    fields: &[
+        Binding { name: "rule", ty: Type::String, doc: "e.g. cpf, email" },
+        Binding { name: "start", ty: Type::Int, doc: "Byte offset" },
+        Binding { name: "end", ty: Type::Int, doc: "Byte offset, exclusive" },
+        Binding { name: "value", ty: Type::String, doc: "The matched text" },
+    ],
+};
+
+/// What PII `mask` expressions see, once per finding.
+pub static MASKING: Environment = Environment {
+    name: "pii_mask",
+    description: "mask expressions of PII detection",
+    bindings: &[
+        Binding { name: "finding", ty: Type::Object(&FINDING), doc: "" },
+        Binding { name: "context", ty: Type::Map(&Type::Dyn), doc: "The request's context" },
+    ],
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ValidateRequest {
+    pub rule: String,
+    pub value: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PiiRequest {
+    pub text: String,
+    /// Expression over `MASKING`; omit to redact every finding.
+    pub mask: Option<String>,
+    #[serde(default)]
+    pub context: Map<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldRequest {
+    /// Path reported back in errors, e.g. `$.buyer.cnpj`.
+    pub field: String,
+    /// A built-in rule or `plugin:<name>`; omit to only screen the value
+    /// for injection.
+    pub rule: Option<String>,
+    pub value: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldsRequest {
+    pub fields: Vec<FieldRequest>,
+    #[serde(default = "default_scan")]
+    pub scan_injection: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SchemaRequest {
+    pub schema: serde_json::Value,
+    pub data: serde_json::Value,
+    #[serde(default = "default_scan")]
+    pub scan_injection: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InjectionRequest {
+    pub data: serde_json::Value,
+}
+
+fn default_scan() -> bool {
+    true
+}
+
+async fn report(state: &AppState, req: &HttpRequest, mut errors: Vec<FieldError>) -> HttpResponse {
+    let locale = state.i18n.locale(state, req, None).await;
+    state.i18n.localize_errors(&locale, &mut errors);
+    i18n::content_language(HttpResponse::Ok().json(serde_json::json!({
+        "valid": errors.is_empty(),
+        "errors": errors
+    })), &locale)
+}
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "error": message
+    }))
+}
+
+// HTTP handlers
+
+pub async fn validate_handler(
+    req: HttpRequest,
+    request: web::Json<ValidateRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let rule: Rule = match request.rule.parse() {
+        Ok(rule) => rule,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e
+        }))),
+    };
+
+    match validate(rule, &request.value) {
+        Ok(()) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "valid": true
+        }))),
+        Err(issue) => {
+            let locale = state.i18n.locale(&state, &req, None).await;
+            let message = state.i18n
+                .translation(&locale, &format!("validation.{}", rule.as_str()))
+                .unwrap_or_else(|| issue.message.to_string());
+            Ok(i18n::content_language(HttpResponse::Ok().json(serde_json::json!({
+                "valid": false,
+                "code": rule.as_str(),
+                "message": message
+            })), &locale))
+        }
+    }
+}
+
+/// Same detector the Python bindings expose; offsets are byte offsets.
+pub async fn detect_pii_handler(request: web::Json<PiiRequest>) -> Result<HttpResponse> {
+    let findings = pii::detect(&request.text);
+    let redacted = match &request.mask {
+        None => pii::redact_findings(&request.text, &findings),
+        Some(mask) => {
+            let mask = match Program::condition(mask, &MASKING) {
+                Ok(mask) => mask,
+                Err(e) => return Ok(bad_request(format!("mask: {}", e))),
+            };
+            let masked: Vec<pii::Finding> = findings
+                .iter()
+                .filter(|finding| mask.holds(&finding_bindings(&request, finding)).unwrap_or(true))
+                .cloned()
+                .collect();
+            pii::redact_findings(&request.text, &masked)
+        }
+    };
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "findings": findings,
+        "redacted": redacted
+    })))
+}
+
+fn finding_bindings(request: &PiiRequest, finding: &pii::Finding) -> Map<String, Value> {
+    let mut bindings = Map::new();
+    bindings.insert("finding".to_string(), serde_json::json!({
+        "rule": finding.rule.as_str(),
+        "start": finding.start,
+        "end": finding.end,
+        "value": &request.text[finding.start..finding.end]
+    }));
+    bindings.insert("context".to_string(), Value::Object(request.context.clone()));
+    bindings
+}
+
+pub async fn rules_handler() -> Result<HttpResponse> {
+    let rules: Vec<&str> = RULES.iter().map(Rule::as_str).collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "rules": rules
+    })))
+}
+
+/// Each field under its rule, plus the injection screen unless turned off.
+pub async fn validate_fields_handler(
+    req: HttpRequest,
+    request: web::Json<FieldsRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    if request.fields.len() > MAX_FIELDS {
+        return Ok(bad_request(format!("At most {} fields per request", MAX_FIELDS)));
+    }
+
+    let mut errors = Vec::new();
+    for field in &request.fields {
+        if let Some(plugin) = field.rule.as_deref().and_then(|rule| rule.strip_prefix("plugin:")) {
+            match state.plugins.validate(plugin, &field.field, Value::String(field.value.clone())) {
+                Ok(found) => errors.extend(found),
+                Err(SecurityError::NotFound(msg)) | Err(SecurityError::ValidationError(msg)) => {
+                    return Ok(bad_request(format!("{}: {}", field.field, msg)))
+                }
+                Err(e) => {
+                    return Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                        "error": format!("{}: {}", field.field, e)
+                    })))
+                }
+            }
+        } else if let Some(rule) = &field.rule {
+            let rule: Rule = match rule.parse() {
+                Ok(rule) => rule,
+                Err(e) => return Ok(bad_request(format!("{}: {}", field.field, e))),
+            };
+            if let Err(issue) = validate(rule, &field.value) {
+                errors.push(FieldError::from_issue(&field.field, issue));
+            }
+        }
+        if request.scan_injection {
+            errors.extend(injection::check(&field.field, &field.value));
+        }
+    }
+    Ok(report(&state, &req, errors).await)
+}
+
+pub async fn validate_schema_handler(
+    req: HttpRequest,
+    request: web::Json<SchemaRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let schema = match Schema::compile(&request.schema) {
+        Ok(schema) => schema,
+        Err(e) => return Ok(bad_request(format!("Invalid schema: {}", e))),
+    };
+
+    let mut errors = schema.validate(&request.data);
+    if request.scan_injection {
+        errors.extend(injection::scan(&request.data));
+    }
+    Ok(report(&state, &req, errors).await)
+}
+
+/// Screen every string in `data`, which may be a bare string.
+pub async fn detect_injection_handler(
+    req: HttpRequest,
+    request: web::Json<InjectionRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    Ok(report(&state, &req, injection::scan(&request.data)).await)
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/validate", web::post().to(validate_handler))
+        .route("/validate/rules", web::get().to(rules_handler))
+        .route("/validate/fields", web::post().to(validate_fields_handler))
+        .route("/validate/schema", web::post().to(validate_schema_handler))
+        .route("/validate/injection", web::post().to(detect_injection_handler))
+        .route("/validate/pii", web::post().to(detect_pii_handler));
+}
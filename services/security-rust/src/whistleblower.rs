@@ -0,0 +1,798 @@
+/*!
+Whistleblower Module
+Anonymous reporting channel with follow-up
+
+Reporters fetch the channel's public key from `GET /whistleblower/key`
+(read from `WHISTLEBLOWER_PUBLIC_KEY_FILE`) and encrypt their report with
+it before sending; this service stores the ciphertext and cannot read it.
+Submitting needs no credentials. Nothing about the sender is kept: no
+subject, no address, no audit event, and the channel's paths are left out
+of the request log and of forensic captures (see `forensics`). Report and message times are truncated to
+`WHISTLEBLOWER_RECEIVED_PRECISION`, and reporter metadata not listed in
+`WHISTLEBLOWER_METADATA_FIELDS` is dropped.
+
+A submission returns a follow-up token, shown once and stored only as a
+keyed hash. With it in the `X-Followup-Token` header the reporter reads the
+report's status and replies and sends further messages. A reporter who
+included a `reply_key` can expect replies encrypted to it.
+
+Only holders of `WHISTLEBLOWER_ROLE` handle reports; admin roles do not
+imply it. Handlers see report metadata, but reading a report, replying or
+changing its status needs an access request approved by another handler,
+valid for `WHISTLEBLOWER_ACCESS_TTL_SECS`. Handler actions are audited.
+*/
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::sync::Arc;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::audit::receipts::{self, RECEIPT_HEADER};
+use crate::audit::NewAuditEvent;
+use crate::auth::{auth_error_response, client_ip, Principal};
+use crate::clock::Clock;
+use crate::config::{Config, WhistleblowerConfig};
+use crate::crypto::CryptoService;
+use crate::errors::SecurityError;
+use crate::pagination::{KeyKind, Page, PageParams, PageRequest, SortField, SortKey, SortOrder};
+use crate::storage::Storage;
+use crate::AppState;
+
+pub const FOLLOWUP_HEADER: &str = "X-Followup-Token";
+
+/// Requests under this path are kept out of the request log and forensic captures.
+pub const PATH_PREFIX: &str = "/api/v1/whistleblower/";
+
+const TOKEN_PREFIX: &str = "wbf_";
+
+const TOKEN_BYTES: usize = 32;
+
+/// Blind index domain of follow-up tokens.
+const TOKEN_DOMAIN: &str = "whistleblower_followup";
+
+const STATUSES: &[&str] = &["received", "in_review", "closed"];
+
+const SORT_FIELDS: &[SortField] = &[
+    SortField { name: "received_at", column: "received_at", kind: KeyKind::Timestamp },
+];
+
+const SUMMARY_COLUMNS: &str = "r.id, r.tenant_id, r.metadata, r.status, r.received_at, \
+    (SELECT COUNT(*) FROM whistleblower_messages m WHERE m.report_id = r.id) AS message_count";
+
+const REPORT_COLUMNS: &str = "id, tenant_id, key_fingerprint, ciphertext, metadata, reply_key, status, received_at";
+
+const MESSAGE_COLUMNS: &str = "id, author, handler, ciphertext, sent_at";
+
+const ACCESS_COLUMNS: &str = "id, report_id, requested_by, reason, status, created_at, decided_by, decided_at, \
+    expires_at";
+
+/// What handlers see of a report without access.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ReportSummary {
+    pub id: Uuid,
+    pub tenant_id: Option<String>,
+    pub metadata: Json<serde_json::Map<String, serde_json::Value>>,
+    pub status: String,
+    pub received_at: DateTime<Utc>,
+    pub message_count: i64,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Report {
+    pub id: Uuid,
+    pub tenant_id: Option<String>,
+    pub key_fingerprint: String,
+    pub ciphertext: String,
+    pub metadata: Json<serde_json::Map<String, serde_json::Value>>,
+    pub reply_key: Option<String>,
+    /// `received`, `in_review` or `closed`.
+    pub status: String,
+    pub received_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Message {
+    pub id: Uuid,
+    /// `reporter` or `handler`.
+    pub author: String,
+    pub handler: Option<String>,
+    pub ciphertext: String,
+    pub sent_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct AccessRequest {
+    pub id: Uuid,
+    pub report_id: Uuid,
+    pub requested_by: String,
+    pub reason: String,
+    /// `pending_approval`, `approved` or `rejected`.
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SubmitRequest {
+    pub tenant_id: Option<String>,
+    /// Fingerprint from `GET /whistleblower/key`, so a report encrypted to
+    /// a replaced key is refused rather than stored unreadable.
+    pub key_fingerprint: String,
+    /// Base64.
+    pub ciphertext: String,
+    #[serde(default)]
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+    pub reply_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MessageRequest {
+    /// Base64.
+    pub ciphertext: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccessRequestBody {
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatusRequest {
+    pub status: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportFilter {
+    pub status: Option<String>,
+}
+
+/// What the reporter sees through the follow-up token.
+#[derive(Debug, Serialize)]
+pub struct FollowUp {
+    pub status: String,
+    pub received_at: DateTime<Utc>,
+    pub messages: Vec<Message>,
+}
+
+pub struct WhistleblowerService {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    config: WhistleblowerConfig,
+    /// The public key and its fingerprint, when the channel is open.
+    key: Option<(String, String)>,
+}
+
+impl WhistleblowerService {
+    pub async fn new(config: &Config, storage: Storage, clock: Arc<dyn Clock>) -> Result<Self, SecurityError> {
+        let key = match &config.whistleblower.public_key_file {
+            Some(path) => {
+                let text = tokio::fs::read_to_string(path).await
+                    .map_err(|e| SecurityError::ConfigError(format!("WHISTLEBLOWER_PUBLIC_KEY_FILE {}: {}", path, e)))?;
+                let key = text.trim().to_string();
+                if key.is_empty() {
+                    return Err(SecurityError::ConfigError(format!("WHISTLEBLOWER_PUBLIC_KEY_FILE {}: empty", path)));
+                }
+                let fingerprint = hex::encode(digest(&SHA256, key.as_bytes()));
+                Some((key, fingerprint))
+            }
+            None => None,
+        };
+
+        info!("Whistleblower service initialized successfully (channel {})", if key.is_some() { "open" } else { "closed" });
+        Ok(Self { storage, clock, config: config.whistleblower.clone(), key })
+    }
+
+    pub fn public_key(&self) -> Result<(&str, &str), SecurityError> {
+        self.key
+            .as_ref()
+            .map(|(key, fingerprint)| (key.as_str(), fingerprint.as_str()))
+            .ok_or_else(|| SecurityError::NotFound("Whistleblower channel is not open".to_string()))
+    }
+
+    pub fn metadata_fields(&self) -> &[String] {
+        &self.config.metadata_fields
+    }
+
+    /// Now, at the configured precision.
+    fn coarse_now(&self) -> DateTime<Utc> {
+        let now = self.clock.now();
+        let unit = match self.config.received_precision.as_str() {
+            "hour" => Duration::hours(1),
+            "day" => Duration::days(1),
+            _ => return now,
+        };
+        now.duration_trunc(unit).unwrap_or(now)
+    }
+
+    fn check_ciphertext(&self, ciphertext: &str) -> Result<(), SecurityError> {
+        let decoded = base64::decode(ciphertext)
+            .map_err(|_| SecurityError::ValidationError("ciphertext must be base64".to_string()))?;
+        if decoded.is_empty() || decoded.len() > self.config.max_bytes {
+            return Err(SecurityError::ValidationError(format!(
+                "ciphertext must be between 1 and {} bytes",
+                self.config.max_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    /// Store a report. Returns the follow-up token.
+    pub async fn submit(&self, crypto: &CryptoService, request: SubmitRequest) -> Result<String, SecurityError> {
+        let (_, fingerprint) = self.public_key()?;
+        if request.key_fingerprint != fingerprint {
+            return Err(SecurityError::ValidationError(
+                "Report is not encrypted to the current key; fetch it again".to_string(),
+            ));
+        }
+        self.check_ciphertext(&request.ciphertext)?;
+        if request.tenant_id.as_deref().is_some_and(|t| t.trim().is_empty()) {
+            return Err(SecurityError::ValidationError("tenant_id must not be empty".to_string()));
+        }
+        let metadata: serde_json::Map<String, serde_json::Value> = request
+            .metadata
+            .into_iter()
+            .filter(|(field, _)| self.config.metadata_fields.contains(field))
+            .collect();
+
+        let token = format!(
+            "{}{}",
+            TOKEN_PREFIX,
+            base64::encode_config(crypto.secure_random(TOKEN_BYTES).await?, base64::URL_SAFE_NO_PAD)
+        );
+        sqlx::query(
+            "INSERT INTO whistleblower_reports (id, tenant_id, key_fingerprint, ciphertext, metadata, reply_key, \
+             followup_hash, received_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(Uuid::new_v4())
+        .bind(&request.tenant_id)
+        .bind(fingerprint)
+        .bind(&request.ciphertext)
+        .bind(Json(metadata))
+        .bind(&request.reply_key)
+        .bind(crypto.blind_index(TOKEN_DOMAIN, &token))
+        .bind(self.coarse_now())
+        .execute(self.storage.pool())
+        .await?;
+        Ok(token)
+    }
+
+    async fn report_for_token(&self, crypto: &CryptoService, token: &str) -> Result<Report, SecurityError> {
+        let report = sqlx::query_as::<_, Report>(&format!(
+            "SELECT {} FROM whistleblower_reports WHERE followup_hash = $1",
+            REPORT_COLUMNS
+        ))
+        .bind(crypto.blind_index(TOKEN_DOMAIN, token))
+        .fetch_optional(self.storage.pool())
+        .await?;
+        report.ok_or_else(|| SecurityError::NotFound("No report for this follow-up token".to_string()))
+    }
+
+    pub async fn follow_up(&self, crypto: &CryptoService, token: &str) -> Result<FollowUp, SecurityError> {
+        let report = self.report_for_token(crypto, token).await?;
+        Ok(FollowUp {
+            status: report.status,
+            received_at: report.received_at,
+            messages: self.messages(report.id).await?,
+        })
+    }
+
+    pub async fn reporter_message(
+        &self,
+        crypto: &CryptoService,
+        token: &str,
+        request: MessageRequest,
+    ) -> Result<Message, SecurityError> {
+        let report = self.report_for_token(crypto, token).await?;
+        if report.status == "closed" {
+            return Err(SecurityError::Conflict("The report is closed".to_string()));
+        }
+        self.add_message(report.id, None, &request.ciphertext).await
+    }
+
+    async fn add_message(&self, report_id: Uuid, handler: Option<&str>, ciphertext: &str) -> Result<Message, SecurityError> {
+        self.check_ciphertext(ciphertext)?;
+        Ok(sqlx::query_as::<_, Message>(&format!(
+            "INSERT INTO whistleblower_messages (id, report_id, author, handler, ciphertext, sent_at) \
+             VALUES ($1, $2, $3, $4, $5, $6) RETURNING {}",
+            MESSAGE_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(report_id)
+        .bind(if handler.is_some() { "handler" } else { "reporter" })
+        .bind(handler)
+        .bind(ciphertext)
+        .bind(self.coarse_now())
+        .fetch_one(self.storage.pool())
+        .await?)
+    }
+
+    async fn messages(&self, report_id: Uuid) -> Result<Vec<Message>, SecurityError> {
+        Ok(sqlx::query_as::<_, Message>(&format!(
+            "SELECT {} FROM whistleblower_messages WHERE report_id = $1 ORDER BY sent_at, id",
+            MESSAGE_COLUMNS
+        ))
+        .bind(report_id)
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    pub async fn list(
+        &self,
+        tenant_id: Option<&str>,
+        filter: &ReportFilter,
+        page: &PageRequest,
+    ) -> Result<Page<ReportSummary>, SecurityError> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT * FROM (SELECT {} FROM whistleblower_reports r) reports WHERE 1 = 1",
+            SUMMARY_COLUMNS
+        ));
+        if let Some(tenant_id) = tenant_id {
+            builder.push(" AND tenant_id = ").push_bind(tenant_id.to_string());
+        }
+        if let Some(status) = &filter.status {
+            builder.push(" AND status = ").push_bind(status.clone());
+        }
+        page.push_after(&mut builder);
+        page.push_order_limit(&mut builder);
+
+        let reports = builder
+            .build_query_as::<ReportSummary>()
+            .fetch_all(self.storage.pool())
+            .await?;
+
+        Ok(page.page(reports, |report, _| (SortKey::Timestamp(report.received_at), report.id)))
+    }
+
+    /// A report, if the handler may see that it exists: tenant-bound
+    /// handlers only their tenant's.
+    async fn visible(&self, handler: &Principal, id: Uuid) -> Result<Report, SecurityError> {
+        let report = sqlx::query_as::<_, Report>(&format!(
+            "SELECT {} FROM whistleblower_reports WHERE id = $1",
+            REPORT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.storage.pool())
+        .await?;
+        match report {
+            Some(report) if handler.tenant_id.is_none() || handler.tenant_id == report.tenant_id => Ok(report),
+            _ => Err(SecurityError::NotFound(format!("No report {}", id))),
+        }
+    }
+
+    /// A report the handler holds approved, unexpired access to.
+    async fn accessible(&self, handler: &Principal, id: Uuid) -> Result<Report, SecurityError> {
+        let report = self.visible(handler, id).await?;
+        let (granted,): (bool,) = sqlx::query_as(
+            "SELECT EXISTS (SELECT 1 FROM whistleblower_access WHERE report_id = $1 AND requested_by = $2 \
+             AND status = 'approved' AND expires_at > $3)",
+        )
+        .bind(id)
+        .bind(&handler.subject)
+        .bind(self.clock.now())
+        .fetch_one(self.storage.pool())
+        .await?;
+        if !granted {
+            return Err(SecurityError::AccessDenied(format!(
+                "No approved access to report {}; request it first",
+                id
+            )));
+        }
+        Ok(report)
+    }
+
+    pub async fn request_access(
+        &self,
+        handler: &Principal,
+        id: Uuid,
+        request: AccessRequestBody,
+    ) -> Result<(Report, AccessRequest), SecurityError> {
+        if request.reason.trim().is_empty() {
+            return Err(SecurityError::ValidationError("reason is required".to_string()));
+        }
+        let report = self.visible(handler, id).await?;
+
+        // One open request per handler and report
+        let access = sqlx::query_as::<_, AccessRequest>(&format!(
+            "INSERT INTO whistleblower_access (id, report_id, requested_by, reason, created_at) \
+             SELECT $1, $2, $3, $4, $5 WHERE NOT EXISTS (SELECT 1 FROM whistleblower_access \
+             WHERE report_id = $2 AND requested_by = $3 AND status = 'pending_approval') RETURNING {}",
+            ACCESS_COLUMNS
+        ))
+        .bind(Uuid::new_v4())
+        .bind(id)
+        .bind(&handler.subject)
+        .bind(request.reason.trim())
+        .bind(self.clock.now())
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::Conflict(format!("An access request for report {} is already pending", id)))?;
+        Ok((report, access))
+    }
+
+    /// Pending requests on reports the handler may see, other than their own.
+    pub async fn pending_access(&self, handler: &Principal) -> Result<Vec<AccessRequest>, SecurityError> {
+        Ok(sqlx::query_as::<_, AccessRequest>(&format!(
+            "SELECT {} FROM whistleblower_access a WHERE status = 'pending_approval' AND requested_by <> $1 \
+             AND ($2::TEXT IS NULL OR EXISTS (SELECT 1 FROM whistleblower_reports r \
+             WHERE r.id = a.report_id AND r.tenant_id = $2)) ORDER BY created_at",
+            ACCESS_COLUMNS
+        ))
+        .bind(&handler.subject)
+        .bind(&handler.tenant_id)
+        .fetch_all(self.storage.pool())
+        .await?)
+    }
+
+    /// Approve or reject another handler's pending request.
+    pub async fn decide(
+        &self,
+        handler: &Principal,
+        access_id: Uuid,
+        approve: bool,
+    ) -> Result<(Report, AccessRequest), SecurityError> {
+        let pending = sqlx::query_as::<_, AccessRequest>(&format!(
+            "SELECT {} FROM whistleblower_access WHERE id = $1 AND status = 'pending_approval'",
+            ACCESS_COLUMNS
+        ))
+        .bind(access_id)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::NotFound(format!("No pending access request {}", access_id)))?;
+        let report = self.visible(handler, pending.report_id).await
+            .map_err(|_| SecurityError::NotFound(format!("No pending access request {}", access_id)))?;
+        if pending.requested_by == handler.subject {
+            return Err(SecurityError::AccessDenied("Access must be approved by another handler".to_string()));
+        }
+
+        let now = self.clock.now();
+        let (status, expires_at) = if approve {
+            ("approved", Some(now + Duration::seconds(self.config.access_ttl_secs)))
+        } else {
+            ("rejected", None)
+        };
+        let access = sqlx::query_as::<_, AccessRequest>(&format!(
+            "UPDATE whistleblower_access SET status = $2, decided_by = $3, decided_at = $4, expires_at = $5 \
+             WHERE id = $1 AND status = 'pending_approval' RETURNING {}",
+            ACCESS_COLUMNS
+        ))
+        .bind(access_id)
+        .bind(status)
+        .bind(&handler.subject)
+        .bind(now)
+        .bind(expires_at)
+        .fetch_optional(self.storage.pool())
+        .await?
+        .ok_or_else(|| SecurityError::Conflict(format!("Access request {} was already decided", access_id)))?;
+        Ok((report, access))
+    }
+
+    pub async fn read(&self, handler: &Principal, id: Uuid) -> Result<(Report, Vec<Message>), SecurityError> {
+        let report = self.accessible(handler, id).await?;
+        let messages = self.messages(id).await?;
+        Ok((report, messages))
+    }
+
+    pub async fn reply(&self, handler: &Principal, id: Uuid, request: MessageRequest) -> Result<(Report, Message), SecurityError> {
+        let report = self.accessible(handler, id).await?;
+        let message = self.add_message(id, Some(&handler.subject), &request.ciphertext).await?;
+        Ok((report, message))
+    }
+
+    pub async fn set_status(&self, handler: &Principal, id: Uuid, request: StatusRequest) -> Result<Report, SecurityError> {
+        if !STATUSES.contains(&request.status.as_str()) {
+            return Err(SecurityError::ValidationError(format!(
+                "status must be one of {}",
+                STATUSES.join(", ")
+            )));
+        }
+        self.accessible(handler, id).await?;
+        Ok(sqlx::query_as::<_, Report>(&format!(
+            "UPDATE whistleblower_reports SET status = $2 WHERE id = $1 RETURNING {}",
+            REPORT_COLUMNS
+        ))
+        .bind(id)
+        .bind(&request.status)
+        .fetch_one(self.storage.pool())
+        .await?)
+    }
+}
+
+// HTTP handlers
+
+fn error_response(e: SecurityError) -> HttpResponse {
+    match e {
+        SecurityError::ValidationError(msg) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::NotFound(msg) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::AccessDenied(msg) => HttpResponse::Forbidden().json(serde_json::json!({
+            "error": msg
+        })),
+        SecurityError::Conflict(msg) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": msg
+        })),
+        e => {
+            error!("Whistleblower operation failed: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Whistleblower operation failed"
+            }))
+        }
+    }
+}
+
+fn authorize_handler(state: &AppState, req: &HttpRequest) -> Result<Principal, SecurityError> {
+    state.auth_service.authorize_roles(req, std::slice::from_ref(&state.config.whistleblower.role))
+}
+
+/// The follow-up token; a missing one answers like an unknown one.
+fn followup_token(req: &HttpRequest) -> Result<&str, SecurityError> {
+    req.headers()
+        .get(FOLLOWUP_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .filter(|token| token.starts_with(TOKEN_PREFIX))
+        .ok_or_else(|| SecurityError::NotFound("No report for this follow-up token".to_string()))
+}
+
+async fn audit(
+    state: &AppState,
+    req: &HttpRequest,
+    handler: &Principal,
+    verb: &str,
+    report: &Report,
+    payload: serde_json::Value,
+) -> Option<String> {
+    receipts::record_or_warn(state, NewAuditEvent {
+        tenant_id: report.tenant_id.clone(),
+        actor: handler.subject.clone(),
+        actor_ip: client_ip(req),
+        action: format!("whistleblower.{}", verb),
+        resource: format!("whistleblower_report:{}", report.id),
+        outcome: "success".to_string(),
+        payload,
+    }).await
+}
+
+fn with_receipt(mut response: actix_web::HttpResponseBuilder, receipt: Option<String>) -> actix_web::HttpResponseBuilder {
+    if let Some(receipt) = receipt {
+        response.insert_header((RECEIPT_HEADER, receipt));
+    }
+    response
+}
+
+pub async fn key_handler(state: web::Data<AppState>) -> Result<HttpResponse> {
+    match state.whistleblower.public_key() {
+        Ok((public_key, fingerprint)) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "public_key": public_key,
+            "fingerprint": fingerprint,
+            "metadata_fields": state.whistleblower.metadata_fields()
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn submit_handler(request: web::Json<SubmitRequest>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    match state.whistleblower.submit(&state.crypto_service, request.into_inner()).await {
+        Ok(token) => Ok(HttpResponse::Created()
+            .insert_header(("Cache-Control", "no-store"))
+            .json(serde_json::json!({
+                "followup_token": token,
+                "status": "received"
+            }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn followup_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let token = match followup_token(&req) {
+        Ok(token) => token,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.whistleblower.follow_up(&state.crypto_service, token).await {
+        Ok(follow_up) => Ok(HttpResponse::Ok().insert_header(("Cache-Control", "no-store")).json(follow_up)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn followup_message_handler(
+    req: HttpRequest,
+    request: web::Json<MessageRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let token = match followup_token(&req) {
+        Ok(token) => token,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.whistleblower.reporter_message(&state.crypto_service, token, request.into_inner()).await {
+        Ok(message) => Ok(HttpResponse::Created().json(message)),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn list_handler(
+    req: HttpRequest,
+    filter: web::Query<ReportFilter>,
+    page: web::Query<PageParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let handler = match authorize_handler(&state, &req) {
+        Ok(handler) => handler,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+    let page = match page.resolve(SORT_FIELDS, SortOrder::Desc) {
+        Ok(page) => page,
+        Err(e) => return Ok(error_response(e)),
+    };
+
+    match state.whistleblower.list(handler.tenant_id.as_deref(), &filter, &page).await {
+        Ok(page) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "reports": page.items,
+            "page": page.info
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn get_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let handler = match authorize_handler(&state, &req) {
+        Ok(handler) => handler,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.whistleblower.read(&handler, path.into_inner()).await {
+        Ok((report, messages)) => {
+            let receipt = audit(&state, &req, &handler, "report.read", &report, serde_json::Value::Null).await;
+            Ok(with_receipt(HttpResponse::Ok(), receipt)
+                .insert_header(("Cache-Control", "no-store"))
+                .json(serde_json::json!({
+                    "report": report,
+                    "messages": messages
+                })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn reply_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<MessageRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let handler = match authorize_handler(&state, &req) {
+        Ok(handler) => handler,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.whistleblower.reply(&handler, path.into_inner(), request.into_inner()).await {
+        Ok((report, message)) => {
+            let receipt = audit(&state, &req, &handler, "report.reply", &report, serde_json::json!({
+                "message_id": message.id
+            })).await;
+            Ok(with_receipt(HttpResponse::Created(), receipt).json(message))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn status_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<StatusRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let handler = match authorize_handler(&state, &req) {
+        Ok(handler) => handler,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.whistleblower.set_status(&handler, path.into_inner(), request.into_inner()).await {
+        Ok(report) => {
+            let receipt = audit(&state, &req, &handler, "report.status", &report, serde_json::json!({
+                "status": report.status
+            })).await;
+            Ok(with_receipt(HttpResponse::Ok(), receipt).json(serde_json::json!({
+                "id": report.id,
+                "status": report.status
+            })))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn request_access_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    request: web::Json<AccessRequestBody>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let handler = match authorize_handler(&state, &req) {
+        Ok(handler) => handler,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.whistleblower.request_access(&handler, path.into_inner(), request.into_inner()).await {
+        Ok((report, access)) => {
+            let receipt = audit(&state, &req, &handler, "access.request", &report, serde_json::json!({
+                "access_id": access.id,
+                "reason": access.reason
+            })).await;
+            Ok(with_receipt(HttpResponse::Created(), receipt).json(access))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn pending_access_handler(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse> {
+    let handler = match authorize_handler(&state, &req) {
+        Ok(handler) => handler,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.whistleblower.pending_access(&handler).await {
+        Ok(requests) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "requests": requests
+        }))),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+async fn decide(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>, approve: bool) -> Result<HttpResponse> {
+    let handler = match authorize_handler(&state, &req) {
+        Ok(handler) => handler,
+        Err(e) => return Ok(auth_error_response(&e)),
+    };
+
+    match state.whistleblower.decide(&handler, path.into_inner(), approve).await {
+        Ok((report, access)) => {
+            let verb = if approve { "access.approve" } else { "access.reject" };
+            let receipt = audit(&state, &req, &handler, verb, &report, serde_json::json!({
+                "access_id": access.id,
+                "requested_by": access.requested_by,
+                "expires_at": access.expires_at
+            })).await;
+            Ok(with_receipt(HttpResponse::Ok(), receipt).json(access))
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+pub async fn approve_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    decide(req, path, state, true).await
+}
+
+pub async fn reject_handler(req: HttpRequest, path: web::Path<Uuid>, state: web::Data<AppState>) -> Result<HttpResponse> {
+    decide(req, path, state, false).await
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/whistleblower")
+            .route("/key", web::get().to(key_handler))
+            .route("/reports", web::post().to(submit_handler))
+            .route("/reports", web::get().to(list_handler))
+            .route("/reports/{id}", web::get().to(get_handler))
+            .route("/reports/{id}/messages", web::post().to(reply_handler))
+            .route("/reports/{id}/status", web::post().to(status_handler))
+            .route("/reports/{id}/access", web::post().to(request_access_handler))
+            .route("/followup", web::get().to(followup_handler))
+            .route("/followup", web::post().to(followup_message_handler))
+            .route("/access", web::get().to(pending_access_handler))
+            .route("/access/{id}/approve", web::post().to(approve_handler))
+            .route("/access/{id}/reject", web::post().to(reject_handler)),
+    );
+}
@@ -0,0 +1,61 @@
+use reqwest::StatusCode;
+use serde_json::json;
+
+use crate::harness::{eventually, Harness};
+use crate::mocks::chain_index;
+
+const ROTATIONS: i64 = 2;
+
+#[actix_web::test]
+async fn audit_chain_verifies_streams_and_detects_tampering() {
+    let harness = Harness::start().await;
+    let admin = harness.admin_token().await;
+
+    for _ in 0..ROTATIONS {
+        let (status, rotated) = harness.post(&admin, "/crypto/keys/rotate", json!({})).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", rotated);
+    }
+
+    let pool = harness.state.storage.pool();
+    eventually("the rotations to be linked into the chain", move || async move {
+        let linked: i64 = sqlx::query_scalar(
+            "SELECT count(*) FROM audit_events WHERE action = 'crypto.key.rotate' AND chain_index IS NOT NULL",
+        )
+        .fetch_one(pool)
+        .await
+        .ok()?;
+        (linked == ROTATIONS).then_some(())
+    })
+    .await;
+
+    let (status, report) = harness.get(&admin, "/audit/verify").await;
+    assert_eq!(status, StatusCode::OK, "{}", report);
+    assert_eq!(report["intact"], true, "{}", report);
+
+    // The SIEM gets the events in chain order
+    let siem = &harness.siem;
+    let messages = eventually("the rotations to reach the SIEM", move || async move {
+        let messages = siem.messages();
+        let rotations = messages.iter().filter(|message| message.contains("act=crypto.key.rotate")).count();
+        (rotations as i64 == ROTATIONS).then_some(messages)
+    })
+    .await;
+    let indexes: Vec<i64> = messages.iter().filter_map(|message| chain_index(message)).collect();
+    assert_eq!(indexes.len(), messages.len(), "every message carries its chain index");
+    assert!(indexes.windows(2).all(|pair| pair[0] < pair[1]), "out of order: {:?}", indexes);
+
+    sqlx::query(
+        "UPDATE audit_events SET outcome = 'failure' WHERE id = \
+         (SELECT id FROM audit_events WHERE action = 'crypto.key.rotate' ORDER BY chain_index LIMIT 1)",
+    )
+    .execute(pool)
+    .await
+    .expect("tamper with an audit event");
+
+    let (status, report) = harness.get(&admin, "/audit/verify").await;
+    assert_eq!(status, StatusCode::OK, "{}", report);
+    assert_eq!(report["intact"], false, "{}", report);
+    assert_eq!(report["first_tampered"]["reason"], "modified");
+
+    harness.stop().await;
+}
@@ -0,0 +1,70 @@
+use reqwest::StatusCode;
+use serde_json::json;
+
+use crate::harness::{eventually, Harness};
+
+const DESTINATION: &str = "+15555550100";
+
+/// The run of `length` digits in a one-time code message.
+fn code_in(body: &str, length: usize) -> String {
+    body.split(|c: char| !c.is_ascii_digit())
+        .find(|run| run.len() == length)
+        .unwrap_or_else(|| panic!("no code in {:?}", body))
+        .to_string()
+}
+
+#[actix_web::test]
+async fn login_with_mfa() {
+    let harness = Harness::start().await;
+    let login = harness.login_flow_token().await;
+
+    let (status, challenge) = harness
+        .post(&login, "/auth/otp", json!({
+            "subject": "alice",
+            "tenant_id": "acme",
+            "channel": "sms",
+            "destination": DESTINATION
+        }))
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", challenge);
+    let verify_path = format!("/auth/otp/{}/verify", challenge["id"].as_str().expect("challenge id"));
+
+    let sms = &harness.sms;
+    let message = eventually("the code to reach the SMS gateway", move || async move {
+        sms.requests().into_iter().find(|request| request.json()["to"] == DESTINATION)
+    })
+    .await;
+    assert_eq!(message.path, "/messages");
+    let length = harness.state.config.auth.otp_length as usize;
+    let code = code_in(message.json()["body"].as_str().unwrap_or_default(), length);
+
+    let wrong: String = code.chars().map(|c| if c == '9' { '0' } else { (c as u8 + 1) as char }).collect();
+    let (status, rejected) = harness.post(&login, &verify_path, json!({ "code": wrong })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", rejected);
+    assert_eq!(rejected["verified"], false);
+
+    let (status, verified) = harness.post(&login, &verify_path, json!({ "code": code })).await;
+    assert_eq!(status, StatusCode::OK, "{}", verified);
+    assert_eq!(verified["verified"], true);
+
+    // Only once the code checks out does the login flow mint the user's tokens
+    let (status, tokens) = harness
+        .post(&login, "/auth/tokens", json!({ "subject": "alice", "tenant_id": "acme", "refresh": true }))
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", tokens);
+    let access_token = tokens["access_token"].as_str().expect("access token");
+    let refresh_token = tokens["refresh_token"].as_str().expect("refresh token");
+
+    let (status, principal) = harness.get(access_token, "/auth/verify").await;
+    assert_eq!(status, StatusCode::OK, "{}", principal);
+    assert_eq!(principal["subject"], "alice");
+    assert_eq!(principal["tenant_id"], "acme");
+
+    let (status, refreshed) = harness
+        .post_form("/auth/token", &[("grant_type", "refresh_token"), ("refresh_token", refresh_token)])
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", refreshed);
+    assert_ne!(refreshed["refresh_token"].as_str(), Some(refresh_token));
+
+    harness.stop().await;
+}
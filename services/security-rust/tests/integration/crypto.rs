@@ -0,0 +1,51 @@
+use reqwest::StatusCode;
+use serde_json::json;
+
+use crate::harness::Harness;
+
+const PLAINTEXT: &str = "Proposal for tender 2024/117: R$ 1.250.000,00";
+
+#[actix_web::test]
+async fn encrypt_rotate_decrypt() {
+    let harness = Harness::start().await;
+    let admin = harness.admin_token().await;
+
+    let (status, sealed) = harness
+        .post(&admin, "/crypto/encrypt", json!({ "data": PLAINTEXT, "context": { "tenant_id": "acme" } }))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", sealed);
+    let old_key = sealed["key_id"].as_str().expect("key id").to_string();
+
+    let (status, rotated) = harness.post(&admin, "/crypto/keys/rotate", json!({})).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", rotated);
+    let new_key = rotated["key_id"].as_str().expect("new key id").to_string();
+    assert_ne!(new_key, old_key);
+
+    // Both keys are wrapped by the KMS, not the master key
+    let providers: Vec<String> = sqlx::query_scalar("SELECT provider FROM crypto_keys WHERE key_id = ANY($1)")
+        .bind(vec![old_key.clone(), new_key.clone()])
+        .fetch_all(harness.state.storage.pool())
+        .await
+        .expect("read crypto keys");
+    assert_eq!(providers, vec!["aws-kms", "aws-kms"]);
+
+    // Data sealed before the rotation still opens
+    let (status, opened) = harness.post(&admin, "/crypto/decrypt", sealed.clone()).await;
+    assert_eq!(status, StatusCode::OK, "{}", opened);
+    assert_eq!(opened["data"], PLAINTEXT);
+
+    let (status, resealed) = harness.post(&admin, "/crypto/encrypt", json!({ "data": PLAINTEXT })).await;
+    assert_eq!(status, StatusCode::OK, "{}", resealed);
+    assert_eq!(resealed["key_id"], new_key.as_str());
+    let (status, opened) = harness.post(&admin, "/crypto/decrypt", resealed).await;
+    assert_eq!(status, StatusCode::OK, "{}", opened);
+    assert_eq!(opened["data"], PLAINTEXT);
+
+    // The context is bound to the ciphertext
+    let mut moved = sealed;
+    moved["context_hash"] = json!("0".repeat(64));
+    let (status, refused) = harness.post(&admin, "/crypto/decrypt", moved).await;
+    assert!(!status.is_success(), "{}", refused);
+
+    harness.stop().await;
+}
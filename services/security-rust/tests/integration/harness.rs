@@ -0,0 +1,213 @@
+//! One security service per test, on its own containers and mocks.
+
+use actix_web::dev::ServerHandle;
+use actix_web::{web, HttpServer};
+use cotai_security::auth::tokens::IssueRequest;
+use cotai_security::clock::SystemClock;
+use cotai_security::config::Config;
+use cotai_security::secrets::kms::KmsClient;
+use cotai_security::{AppState, SecurityServiceBuilder};
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Once};
+use std::time::{Duration, Instant};
+use testcontainers::core::{IntoContainerPort, WaitFor};
+use testcontainers::runners::AsyncRunner;
+use testcontainers::{ContainerAsync, GenericImage, ImageExt};
+use testcontainers_modules::postgres::Postgres;
+use testcontainers_modules::redis::Redis;
+
+use crate::mocks::{MockHttp, MockSiem};
+
+/// Secret SOAR deliveries are signed with.
+pub const SOAR_SECRET: &str = "integration-soar-secret-of-at-least-32-bytes";
+
+const KMS_IMAGE: (&str, &str) = ("nsmithuk/local-kms", "3.12.0");
+const KMS_PORT: u16 = 8080;
+const REGION: &str = "eu-west-2";
+
+/// How long `eventually` waits, covering the background jobs' intervals.
+const EVENTUALLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+static PROCESS_ENV: Once = Once::new();
+
+/// Variables read from the environment rather than `Config`, the same for
+/// every test.
+fn process_env() {
+    PROCESS_ENV.call_once(|| {
+        // The local KMS takes any credentials
+        std::env::set_var("AWS_ACCESS_KEY_ID", "integration");
+        std::env::set_var("AWS_SECRET_ACCESS_KEY", "integration");
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+            .with_test_writer()
+            .try_init();
+    });
+}
+
+pub struct Harness {
+    pub state: web::Data<AppState>,
+    pub client: reqwest::Client,
+    /// SMS gateway, the only one-time code provider.
+    pub sms: MockHttp,
+    /// SOAR platform receiving incidents.
+    pub soar: MockHttp,
+    pub siem: MockSiem,
+    base_url: String,
+    server: ServerHandle,
+    _postgres: ContainerAsync<Postgres>,
+    _redis: ContainerAsync<Redis>,
+    _kms: ContainerAsync<GenericImage>,
+}
+
+impl Harness {
+    /// Start PostgreSQL, Redis and a local KMS, the mocks, and the service
+    /// with its background jobs, listening on a free port.
+    pub async fn start() -> Self {
+        process_env();
+
+        let postgres = Postgres::default().start().await.expect("start PostgreSQL");
+        let redis = Redis::default().start().await.expect("start Redis");
+        let kms = GenericImage::new(KMS_IMAGE.0, KMS_IMAGE.1)
+            .with_exposed_port(KMS_PORT.tcp())
+            .with_wait_for(WaitFor::message_on_stderr("Local KMS started"))
+            .with_env_var("KMS_REGION", REGION)
+            .start()
+            .await
+            .expect("start local KMS");
+        let sms = MockHttp::start().await;
+        let soar = MockHttp::start().await;
+        let siem = MockSiem::start().await;
+
+        let host = postgres.get_host().await.expect("container host").to_string();
+        let postgres_port = postgres.get_host_port_ipv4(5432).await.expect("PostgreSQL port");
+        let redis_port = redis.get_host_port_ipv4(6379).await.expect("Redis port");
+        let kms_port = kms.get_host_port_ipv4(KMS_PORT).await.expect("KMS port");
+
+        let vars: HashMap<String, String> = [
+            ("DATABASE_URL", format!("postgres://postgres:postgres@{}:{}/postgres", host, postgres_port)),
+            ("REDIS_URL", format!("redis://{}:{}", host, redis_port)),
+            ("SECURITY_MASTER_KEY", "integration-master-key-32-bytes!".to_string()),
+            ("SECRET_KEY", "integration-token-secret-of-at-least-32-bytes".to_string()),
+            ("CRYPTO_KEY_PROVIDER", "aws-kms".to_string()),
+            ("AWS_REGION", REGION.to_string()),
+            ("AWS_KMS_ENDPOINT", format!("http://{}:{}/", host, kms_port)),
+            ("DELIVERY_PROVIDERS", format!("sms=sms+{}/messages", sms.url)),
+            ("SOAR_ENDPOINTS", format!("mock={}/incidents", soar.url)),
+            ("SOAR_SECRETS", format!("mock={}", SOAR_SECRET)),
+            ("SOAR_DELIVERY_INTERVAL_MS", "200".to_string()),
+            ("AUDIT_SIEM_SINKS", format!("mock=syslog+tcp://{}", siem.addr)),
+            ("AUDIT_SIEM_INTERVAL_MS", "200".to_string()),
+            ("AUDIT_CHAIN_INTERVAL_SECS", "1".to_string()),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+        let mut config = Config::from_vars(vars).expect("load configuration");
+
+        // Data keys are wrapped under a key made for this run
+        let kms_client = KmsClient::new(&config.secrets, Arc::new(SystemClock)).expect("KMS client");
+        let created = kms_client
+            .call("CreateKey", serde_json::json!({ "Description": "cotai integration tests" }))
+            .await
+            .expect("create KMS key");
+        config.crypto.kms_key_id = created["KeyMetadata"]["KeyId"].as_str().map(String::from);
+
+        let service = SecurityServiceBuilder::new(config).build().await.expect("start security service");
+        let state = service.state();
+        let server = HttpServer::new(move || service.build_app())
+            .workers(1)
+            .disable_signals()
+            .bind(("127.0.0.1", 0))
+            .expect("bind security service");
+        let base_url = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        tokio::spawn(server);
+
+        Self {
+            state,
+            client: reqwest::Client::new(),
+            sms,
+            soar,
+            siem,
+            base_url,
+            server: handle,
+            _postgres: postgres,
+            _redis: redis,
+            _kms: kms,
+        }
+    }
+
+    pub async fn stop(self) {
+        self.server.stop(false).await;
+        self.sms.stop().await;
+        self.soar.stop().await;
+        self.siem.stop();
+    }
+
+    /// An access token minted directly, as the platform's own services
+    /// hold them.
+    pub async fn token(&self, subject: &str, roles: &[&str], scopes: &[&str]) -> String {
+        let request = IssueRequest {
+            subject: subject.to_string(),
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+            ..Default::default()
+        };
+        self.state.tokens.issue(&self.state, &request).await.expect("issue token").access_token
+    }
+
+    pub async fn admin_token(&self) -> String {
+        let role = self.state.config.auth.admin_roles[0].clone();
+        self.token("integration-admin", &[&role], &[]).await
+    }
+
+    /// A token for the platform's login flow, which drives one-time codes
+    /// and mints user tokens.
+    pub async fn login_flow_token(&self) -> String {
+        let scope = self.state.config.auth.issue_scope.clone();
+        self.token("login-flow", &[], &[&scope]).await
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/api/v1{}", self.base_url, path)
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> (StatusCode, Value) {
+        let response = request.send().await.expect("request the security service");
+        let status = response.status();
+        (status, response.json().await.unwrap_or_default())
+    }
+
+    pub async fn get(&self, token: &str, path: &str) -> (StatusCode, Value) {
+        self.send(self.client.get(self.url(path)).bearer_auth(token)).await
+    }
+
+    pub async fn post(&self, token: &str, path: &str, body: Value) -> (StatusCode, Value) {
+        self.send(self.client.post(self.url(path)).bearer_auth(token).json(&body)).await
+    }
+
+    pub async fn post_form(&self, path: &str, form: &[(&str, &str)]) -> (StatusCode, Value) {
+        self.send(self.client.post(self.url(path)).form(form)).await
+    }
+}
+
+/// Poll `check` until it yields a value, failing the test after
+/// `EVENTUALLY_TIMEOUT`.
+pub async fn eventually<T, F, Fut>(what: &str, mut check: F) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Option<T>>,
+{
+    let deadline = Instant::now() + EVENTUALLY_TIMEOUT;
+    loop {
+        if let Some(value) = check().await {
+            return value;
+        }
+        assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
@@ -0,0 +1,14 @@
+//! End-to-end flows against PostgreSQL, Redis and a local KMS in Docker,
+//! with mock SMS, SOAR and SIEM receivers. Needs a Docker daemon:
+//!
+//! ```text
+//! cargo test --features integration
+//! ```
+
+mod harness;
+mod mocks;
+
+mod audit;
+mod auth;
+mod crypto;
+mod webhooks;
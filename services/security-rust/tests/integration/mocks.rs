@@ -0,0 +1,139 @@
+//! Stand-ins for the services the security service calls out to.
+
+use actix_web::dev::ServerHandle;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+/// A request received by a `MockHttp`.
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Recorded {
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or_default()
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_ascii_lowercase()).map(String::as_str)
+    }
+}
+
+/// HTTP receiver that records every request and accepts it with a fresh
+/// `id`, the way SMS gateways and SOAR platforms answer.
+pub struct MockHttp {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Recorded>>>,
+    handle: ServerHandle,
+}
+
+impl MockHttp {
+    pub async fn start() -> Self {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let server = HttpServer::new(move || {
+            let recorded = recorded.clone();
+            App::new().default_service(web::to(move |req: HttpRequest, body: web::Bytes| {
+                let recorded = recorded.clone();
+                async move {
+                    let headers = req
+                        .headers()
+                        .iter()
+                        .filter_map(|(name, value)| Some((name.as_str().to_string(), value.to_str().ok()?.to_string())))
+                        .collect();
+                    recorded.lock().unwrap().push(Recorded {
+                        path: req.path().to_string(),
+                        headers,
+                        body: body.to_vec(),
+                    });
+                    HttpResponse::Ok().json(serde_json::json!({ "id": uuid::Uuid::new_v4() }))
+                }
+            }))
+        })
+        .workers(1)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .expect("bind mock HTTP receiver");
+        let url = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        tokio::spawn(server);
+
+        Self { url, requests, handle }
+    }
+
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub async fn stop(&self) {
+        self.handle.stop(false).await;
+    }
+}
+
+/// Syslog receiver over TCP, as `syslog+tcp://` SIEM sinks send to:
+/// octet-counted RFC 5424 messages carrying CEF.
+pub struct MockSiem {
+    pub addr: String,
+    messages: Arc<Mutex<Vec<String>>>,
+    task: JoinHandle<()>,
+}
+
+impl MockSiem {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind mock SIEM");
+        let addr = listener.local_addr().expect("mock SIEM address").to_string();
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let received = messages.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(read_frames(BufReader::new(stream), received.clone()));
+            }
+        });
+
+        Self { addr, messages, task }
+    }
+
+    pub fn messages(&self) -> Vec<String> {
+        self.messages.lock().unwrap().clone()
+    }
+
+    pub fn stop(&self) {
+        self.task.abort();
+    }
+}
+
+/// `<length> <message>` frames until the sender hangs up.
+async fn read_frames(mut stream: BufReader<tokio::net::TcpStream>, messages: Arc<Mutex<Vec<String>>>) {
+    loop {
+        let mut length = Vec::new();
+        match stream.read_until(b' ', &mut length).await {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+        let Some(length) = std::str::from_utf8(&length).ok().and_then(|length| length.trim().parse::<usize>().ok()) else {
+            return;
+        };
+        let mut message = vec![0; length];
+        if stream.read_exact(&mut message).await.is_err() {
+            return;
+        }
+        messages.lock().unwrap().push(String::from_utf8_lossy(&message).into_owned());
+    }
+}
+
+/// The `chainIndex` a SIEM message carries.
+pub fn chain_index(message: &str) -> Option<i64> {
+    message
+        .split(' ')
+        .find_map(|field| field.strip_prefix("cn1="))
+        .and_then(|index| index.parse().ok())
+}
@@ -0,0 +1,44 @@
+use chrono::Utc;
+use cotai_security::crypto::webhook;
+use cotai_security::soar::SIGNATURE_HEADER;
+use reqwest::StatusCode;
+use serde_json::json;
+
+use crate::harness::{eventually, Harness, SOAR_SECRET};
+
+#[actix_web::test]
+async fn refresh_token_reuse_is_pushed_to_soar() {
+    let harness = Harness::start().await;
+    let login = harness.login_flow_token().await;
+
+    let (status, tokens) = harness
+        .post(&login, "/auth/tokens", json!({ "subject": "bob", "tenant_id": "acme", "refresh": true }))
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", tokens);
+    let refresh_token = tokens["refresh_token"].as_str().expect("refresh token");
+    let refresh = [("grant_type", "refresh_token"), ("refresh_token", refresh_token)];
+
+    let (status, refreshed) = harness.post_form("/auth/token", &refresh).await;
+    assert_eq!(status, StatusCode::OK, "{}", refreshed);
+    // Presented again, the consumed token gives the leak away
+    let (status, refused) = harness.post_form("/auth/token", &refresh).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", refused);
+
+    let soar = &harness.soar;
+    let delivery = eventually("the incident to reach the SOAR platform", move || async move {
+        soar.requests().into_iter().find(|request| request.json()["rule"] == "refresh_token_reuse")
+    })
+    .await;
+    assert_eq!(delivery.path, "/incidents");
+    let incident = delivery.json();
+    assert_eq!(incident["event"], "incident.opened");
+    assert_eq!(incident["entity"], "bob");
+
+    let signature = delivery.header(SIGNATURE_HEADER).expect("signature header");
+    webhook::verify(&[SOAR_SECRET], signature, &delivery.body, Utc::now(), 300).expect("signature verifies");
+    let mut altered = delivery.body.clone();
+    altered.extend_from_slice(b" ");
+    assert!(webhook::verify(&[SOAR_SECRET], signature, &altered, Utc::now(), 300).is_err());
+
+    harness.stop().await;
+}